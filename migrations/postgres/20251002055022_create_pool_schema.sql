@@ -0,0 +1,35 @@
+-- A table to cache ERC20 token metadata.
+CREATE TABLE tokens (
+    address TEXT PRIMARY KEY NOT NULL,
+    symbol TEXT NOT NULL,
+    decimals BIGINT NOT NULL
+);
+
+-- A table for the bot's operational state.
+CREATE TABLE bot_state (
+    key TEXT PRIMARY KEY NOT NULL,
+    value TEXT NOT NULL
+);
+
+-- Insert the initial starting block for scanning.
+INSERT INTO bot_state (key, value) VALUES ('last_seen_block', '15000000');
+
+CREATE TABLE pools (
+    id BIGINT GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+    address TEXT NOT NULL UNIQUE,
+    chain_id BIGINT NOT NULL,
+    dex TEXT NOT NULL,
+    -- V3-specific data, nullable for other types
+    fee BIGINT,
+    tick_spacing BIGINT
+);
+
+-- The junction table to link pools and tokens (many-to-many).
+CREATE TABLE pool_tokens (
+    pool_id BIGINT NOT NULL REFERENCES pools (id),
+    token_address TEXT NOT NULL REFERENCES tokens (address),
+    UNIQUE (pool_id, token_address)
+);
+
+CREATE INDEX idx_pools_address ON pools (address);
+CREATE INDEX idx_pool_tokens_pool_id ON pool_tokens (pool_id);
@@ -0,0 +1,5 @@
+-- Postgres databases are created after addresses were normalized to lowercase hex,
+-- so only the token order column and its lookup index are needed here.
+ALTER TABLE pool_tokens ADD COLUMN position BIGINT NOT NULL DEFAULT 0;
+
+CREATE INDEX idx_pool_tokens_token_address ON pool_tokens (token_address);
@@ -0,0 +1,10 @@
+-- Uniswap V3 liquidity maps, one row per tick bitmap word, as read at block_number. The
+-- bitmap word is hex and ticks_json holds the word's initialized ticks.
+CREATE TABLE v3_tick_words (
+    pool_address TEXT NOT NULL,
+    word_position BIGINT NOT NULL,
+    block_number BIGINT NOT NULL,
+    bitmap TEXT NOT NULL,
+    ticks_json TEXT NOT NULL,
+    PRIMARY KEY (pool_address, word_position)
+);
@@ -0,0 +1,3 @@
+-- Fee-on-transfer tax measured by simulating a transfer, in basis points. NULL until the
+-- token has been analysed.
+ALTER TABLE tokens ADD COLUMN transfer_tax_bps BIGINT;
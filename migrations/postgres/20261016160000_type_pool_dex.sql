@@ -0,0 +1,16 @@
+-- Pool kinds are stored as `PoolKind`'s display strings. Kinds it has no variant for are
+-- left as they are.
+UPDATE pools SET dex = 'uniswap v2' WHERE lower(dex) IN ('uniswap v2', 'uniswapv2');
+UPDATE pools SET dex = 'uniswap v3' WHERE lower(dex) IN ('uniswap v3', 'uniswapv3');
+UPDATE pools SET dex = 'pancakeswap v3' WHERE lower(dex) IN ('pancakeswap v3', 'pancakeswapv3');
+
+-- Curve pools were all stored as 'curve'; their attributes tell the cryptoswap pools apart.
+-- Both kinds hydrate the same way, so one without attributes is fine as stable.
+UPDATE pools SET dex = 'curve crypto' WHERE lower(dex) = 'curve' AND (
+    attributes_json LIKE '%"swap_strategy":"Tricrypto"%'
+    OR attributes_json LIKE '%"swap_strategy":"CryptoSwap"%'
+);
+UPDATE pools SET dex = 'curve stable' WHERE lower(dex) = 'curve';
+
+-- Only weighted Balancer pools have been discovered so far.
+UPDATE pools SET dex = 'balancer weighted' WHERE lower(dex) = 'balancer';
@@ -0,0 +1,7 @@
+-- Pools failing evaluation in a row, so quarantine survives restarts. `retry_in` counts the
+-- evaluations left before a quarantined pool is tried again.
+CREATE TABLE pool_health (
+    address TEXT PRIMARY KEY NOT NULL,
+    consecutive_failures BIGINT NOT NULL,
+    retry_in BIGINT
+);
@@ -0,0 +1 @@
+ALTER TABLE pools ADD COLUMN attributes_json TEXT;
\ No newline at end of file
@@ -0,0 +1,25 @@
+-- Addresses are stored as lowercase 0x-prefixed hex across all dialects.
+-- Parent and child keys are rewritten together, so check foreign keys at commit.
+PRAGMA defer_foreign_keys = ON;
+
+-- Also keeps the foreign key checks on the token rewrite below cheap.
+CREATE INDEX idx_pool_tokens_token_address ON pool_tokens (token_address);
+
+-- Some tokens were cached under both checksummed and lowercase spellings; keep one of each.
+DELETE FROM tokens WHERE rowid NOT IN (SELECT MIN(rowid) FROM tokens GROUP BY lower(address));
+DELETE FROM pool_tokens WHERE rowid NOT IN (
+    SELECT MIN(rowid) FROM pool_tokens GROUP BY pool_id, lower(token_address)
+);
+
+UPDATE tokens SET address = lower(address);
+UPDATE pools SET address = lower(address);
+UPDATE pool_tokens SET token_address = lower(token_address);
+
+-- Token order within a pool matters (token0/token1, Curve coin indices), so record it
+-- explicitly instead of relying on insertion order.
+ALTER TABLE pool_tokens ADD COLUMN position INTEGER NOT NULL DEFAULT 0;
+
+UPDATE pool_tokens SET position = (
+    SELECT COUNT(*) FROM pool_tokens AS earlier
+    WHERE earlier.pool_id = pool_tokens.pool_id AND earlier.rowid < pool_tokens.rowid
+);
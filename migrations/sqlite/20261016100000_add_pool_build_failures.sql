@@ -0,0 +1,6 @@
+-- Pools that were enumerated but could not be built, so they are not retried every run.
+CREATE TABLE pool_build_failures (
+    address TEXT PRIMARY KEY NOT NULL,
+    source TEXT NOT NULL,
+    error TEXT NOT NULL
+);
@@ -0,0 +1,9 @@
+-- Prediction error estimates per pool type, so calibration survives restarts.
+CREATE TABLE calibration_buckets (
+    pool_type TEXT NOT NULL,
+    variant TEXT NOT NULL,
+    samples BIGINT NOT NULL,
+    mean_bps REAL NOT NULL,
+    variance_bps REAL NOT NULL,
+    PRIMARY KEY (pool_type, variant)
+);
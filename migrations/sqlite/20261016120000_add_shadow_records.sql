@@ -0,0 +1,16 @@
+-- Solutions the runner would have executed, re-quoted one block later. Amounts are hex,
+-- and realized_out is NULL when the re-quoted trade would have reverted.
+CREATE TABLE shadow_records (
+    block BIGINT NOT NULL,
+    realized_block BIGINT NOT NULL,
+    day BIGINT NOT NULL,
+    pools TEXT NOT NULL,
+    path_type TEXT NOT NULL,
+    profit_token TEXT NOT NULL,
+    input TEXT NOT NULL,
+    expected_out TEXT NOT NULL,
+    realized_out TEXT,
+    flashloan_fee TEXT NOT NULL,
+    gas_cost TEXT NOT NULL,
+    PRIMARY KEY (block, profit_token)
+);
@@ -0,0 +1,17 @@
+-- Every solution the engine found, one row per block and path. Amounts are hex, in the
+-- profit token except for net_profit_weth.
+CREATE TABLE opportunities (
+    block_number BIGINT NOT NULL,
+    path_hash TEXT NOT NULL,
+    pools TEXT NOT NULL,
+    profit_token TEXT NOT NULL,
+    optimal_input TEXT NOT NULL,
+    gross_profit TEXT NOT NULL,
+    net_profit TEXT NOT NULL,
+    net_profit_weth TEXT NOT NULL,
+    gas_price TEXT NOT NULL,
+    recorded_at BIGINT NOT NULL,
+    PRIMARY KEY (block_number, path_hash)
+);
+
+CREATE INDEX opportunities_path_hash ON opportunities (path_hash);
@@ -0,0 +1,6 @@
+-- Immutable parameters read when a pool was first built, so hydrating it skips them. A
+-- Balancer pool's kind, weights included, is stored in attributes_json.
+ALTER TABLE pools ADD COLUMN balancer_pool_id TEXT;
+ALTER TABLE pools ADD COLUMN balancer_vault TEXT;
+-- LP token of a Curve pool.
+ALTER TABLE pools ADD COLUMN lp_token TEXT;
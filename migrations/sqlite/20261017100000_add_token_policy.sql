@@ -0,0 +1,6 @@
+-- Tokens the token policy allows or denies. The policy's mode is kept in bot_state.
+CREATE TABLE token_policy (
+    address TEXT NOT NULL,
+    list TEXT NOT NULL,
+    PRIMARY KEY (address, list)
+);
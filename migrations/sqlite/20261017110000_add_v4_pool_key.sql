@@ -0,0 +1,6 @@
+-- A Uniswap V4 pool's pool manager and the key components besides its fee and tick
+-- spacing, which have columns already. currency0 is the zero address for native ether.
+ALTER TABLE pools ADD COLUMN v4_pool_manager TEXT;
+ALTER TABLE pools ADD COLUMN v4_currency0 TEXT;
+ALTER TABLE pools ADD COLUMN v4_currency1 TEXT;
+ALTER TABLE pools ADD COLUMN v4_hooks TEXT;
@@ -0,0 +1,8 @@
+-- Tokens whose metadata failed to resolve, refused without calling them again until the
+-- retry block.
+CREATE TABLE token_failures (
+    address TEXT PRIMARY KEY NOT NULL,
+    error_class TEXT NOT NULL,
+    error TEXT NOT NULL,
+    retry_after_block BIGINT NOT NULL
+);
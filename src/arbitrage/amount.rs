@@ -0,0 +1,171 @@
+use crate::arbitrage::optimizer::{token_units_to_wei, wei_to_token_units};
+use crate::core::token::{Token, TokenLike};
+use alloy_primitives::{Address, U256};
+use alloy_provider::Provider;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt::{self, Debug, Display};
+use std::sync::Arc;
+
+/// A raw amount of a token, which formats and converts with the token's decimals.
+///
+/// Amounts only compare with amounts of the same token; `partial_cmp` is `None` across
+/// tokens. [`Self::cmp_weth`] compares any two through their value in WETH.
+pub struct TokenAmount<P: ?Sized> {
+    pub token: Arc<Token<P>>,
+    pub raw: U256,
+}
+
+impl<P: Provider + Send + Sync + 'static + ?Sized> TokenAmount<P> {
+    pub fn new(token: Arc<Token<P>>, raw: U256) -> Self {
+        Self { token, raw }
+    }
+
+    pub fn zero(token: Arc<Token<P>>) -> Self {
+        Self::new(token, U256::ZERO)
+    }
+
+    /// Parses a decimal amount in whole tokens, e.g. `"0.05"`. `None` when it has more
+    /// fractional digits than the token or doesn't fit.
+    pub fn parse(token: Arc<Token<P>>, amount: &str) -> Option<Self> {
+        let raw = parse_units(amount, token.decimals())?;
+        Some(Self::new(token, raw))
+    }
+
+    /// `amount_wei` of WETH in `token`, at the price of 1 ETH in `token` from `conversion_rates`.
+    /// `None` when the token has no rate.
+    pub fn from_weth(
+        token: Arc<Token<P>>,
+        amount_wei: U256,
+        conversion_rates: &HashMap<Address, U256>,
+    ) -> Option<Self> {
+        let rate = conversion_rates.get(&token.address())?;
+        let raw = wei_to_token_units(amount_wei, *rate, token.decimals());
+        Some(Self::new(token, raw))
+    }
+
+    /// This amount in wei, at the price of 1 ETH in its token from `conversion_rates`.
+    /// `None` when the token has no rate.
+    pub fn to_weth(&self, conversion_rates: &HashMap<Address, U256>) -> Option<U256> {
+        let rate = conversion_rates.get(&self.token.address())?;
+        Some(token_units_to_wei(self.raw, *rate, self.token.decimals()))
+    }
+
+    pub fn same_token(&self, other: &Self) -> bool {
+        self.token.address() == other.token.address()
+    }
+
+    /// Orders amounts of any tokens by their value in WETH. `None` when either token has
+    /// no rate.
+    pub fn cmp_weth(&self, other: &Self, conversion_rates: &HashMap<Address, U256>) -> Option<Ordering> {
+        if self.same_token(other) {
+            return Some(self.raw.cmp(&other.raw));
+        }
+        Some(self.to_weth(conversion_rates)?.cmp(&other.to_weth(conversion_rates)?))
+    }
+
+    /// Whether this amount is worth at least `threshold_wei`. `false` when the token has
+    /// no rate, as its value can't be established.
+    pub fn is_at_least_weth(&self, threshold_wei: U256, conversion_rates: &HashMap<Address, U256>) -> bool {
+        self.to_weth(conversion_rates).is_some_and(|value| value >= threshold_wei)
+    }
+
+    /// The amount in whole tokens, lossy for amounts beyond `f64`'s precision.
+    pub fn to_f64(&self) -> f64 {
+        format_units(self.raw, self.token.decimals()).parse().unwrap_or(f64::MAX)
+    }
+}
+
+impl<P: ?Sized> Clone for TokenAmount<P> {
+    fn clone(&self) -> Self {
+        Self {
+            token: self.token.clone(),
+            raw: self.raw,
+        }
+    }
+}
+
+impl<P: Provider + Send + Sync + 'static + ?Sized> PartialEq for TokenAmount<P> {
+    fn eq(&self, other: &Self) -> bool {
+        self.same_token(other) && self.raw == other.raw
+    }
+}
+
+impl<P: Provider + Send + Sync + 'static + ?Sized> PartialOrd for TokenAmount<P> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        self.same_token(other).then(|| self.raw.cmp(&other.raw))
+    }
+}
+
+impl<P: Provider + Send + Sync + 'static + ?Sized> Debug for TokenAmount<P> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TokenAmount")
+            .field("token", &self.token.address())
+            .field("raw", &self.raw)
+            .finish()
+    }
+}
+
+/// Whole tokens and the symbol, e.g. `1.5 WETH`. A precision truncates the fraction to
+/// that many digits, so `{:.2}` formats 1999999 raw USDC as `1.99 USDC`.
+impl<P: Provider + Send + Sync + 'static + ?Sized> Display for TokenAmount<P> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let decimals = self.token.decimals();
+        let amount = match f.precision() {
+            Some(precision) => {
+                let (whole, fraction) = split_units(self.raw, decimals);
+                let mut fraction = fraction;
+                fraction.truncate(precision);
+                if precision == 0 {
+                    whole
+                } else {
+                    format!("{whole}.{fraction:0<precision$}")
+                }
+            }
+            None => format_units(self.raw, decimals),
+        };
+        write!(f, "{} {}", amount, self.token.symbol())
+    }
+}
+
+/// Formats a raw amount in whole units of a token with `decimals`, without trailing zeros,
+/// e.g. `1.5` for 1500000 at 6 decimals.
+pub fn format_units(amount: U256, decimals: u8) -> String {
+    let (whole, fraction) = split_units(amount, decimals);
+    let fraction = fraction.trim_end_matches('0');
+    if fraction.is_empty() {
+        whole
+    } else {
+        format!("{whole}.{fraction}")
+    }
+}
+
+/// Inverse of [`format_units`]. `None` for malformed input, more fractional digits than
+/// `decimals`, or amounts that overflow.
+pub fn parse_units(amount: &str, decimals: u8) -> Option<U256> {
+    let (whole, fraction) = amount.trim().split_once('.').unwrap_or((amount.trim(), ""));
+    if (whole.is_empty() && fraction.is_empty()) || fraction.len() > decimals as usize {
+        return None;
+    }
+    let digits = |part: &str| part.chars().all(|c| c.is_ascii_digit());
+    if !digits(whole) || !digits(fraction) {
+        return None;
+    }
+    let scale = U256::from(10).pow(U256::from(decimals));
+    let whole = if whole.is_empty() { U256::ZERO } else { whole.parse::<U256>().ok()? };
+    let fraction = if fraction.is_empty() {
+        U256::ZERO
+    } else {
+        fraction.parse::<U256>().ok()? * U256::from(10).pow(U256::from(decimals as usize - fraction.len()))
+    };
+    whole.checked_mul(scale)?.checked_add(fraction)
+}
+
+/// The whole part and the fraction zero-padded to `decimals` digits.
+fn split_units(amount: U256, decimals: u8) -> (String, String) {
+    if decimals == 0 {
+        return (amount.to_string(), String::new());
+    }
+    let (whole, fraction) = amount.div_rem(U256::from(10).pow(U256::from(decimals)));
+    (whole.to_string(), format!("{fraction:0>width$}", width = decimals as usize))
+}
@@ -0,0 +1,183 @@
+use crate::arbitrage::types::{SwapAction, SwapKind};
+use crate::core::token::TokenLike;
+use crate::errors::ArbRsError;
+use alloy_primitives::{Address, U256};
+use alloy_rpc_types::{Filter, Log};
+use alloy_sol_types::{SolEvent, sol};
+use futures::future::join_all;
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+
+/// Gas an ERC20 `approve` from the executor costs, added once per token that needs one.
+pub const APPROVAL_GAS_UNITS: u64 = 45_000;
+
+sol! {
+    interface IERC20 {
+        event Approval(address indexed owner, address indexed spender, uint256 value);
+    }
+}
+
+/// An `approve(spender, amount)` the executor has to send on `token` before the swaps.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ApproveAction {
+    pub token: Address,
+    pub spender: Address,
+    pub amount: U256,
+}
+
+/// Allowances the executor `owner` has given its tracked spenders. Each (token, spender)
+/// pair is fetched once, then kept current from the `Approval` logs fed to
+/// [`ApprovalTracker::record_approval_log`] rather than refetched every block.
+#[derive(Debug)]
+pub struct ApprovalTracker {
+    owner: Address,
+    spenders: HashSet<Address>,
+    allowances: Mutex<HashMap<(Address, Address), U256>>,
+}
+
+impl ApprovalTracker {
+    pub fn new(owner: Address, spenders: impl IntoIterator<Item = Address>) -> Self {
+        Self {
+            owner,
+            spenders: spenders.into_iter().collect(),
+            allowances: Mutex::default(),
+        }
+    }
+
+    pub fn owner(&self) -> Address {
+        self.owner
+    }
+
+    pub fn is_tracked(&self, spender: Address) -> bool {
+        self.spenders.contains(&spender)
+    }
+
+    /// The owner's allowance to `spender`, fetched at `block_number` the first time the
+    /// pair is seen.
+    pub async fn allowance(
+        &self,
+        token: &(impl TokenLike + ?Sized),
+        spender: Address,
+        block_number: Option<u64>,
+    ) -> Result<U256, ArbRsError> {
+        let key = (token.address(), spender);
+        if let Some(allowance) = self.cached(key) {
+            return Ok(allowance);
+        }
+        let allowance = token
+            .get_allowance(self.owner, spender, block_number)
+            .await?;
+        if let Ok(mut allowances) = self.allowances.lock() {
+            allowances.entry(key).or_insert(allowance);
+        }
+        Ok(allowance)
+    }
+
+    /// Whether swapping `amount` of `token` through `spender` needs an approval first.
+    /// Spenders that aren't tracked never do.
+    pub async fn requires_approval(
+        &self,
+        token: &(impl TokenLike + ?Sized),
+        spender: Address,
+        amount: U256,
+        block_number: Option<u64>,
+    ) -> Result<bool, ArbRsError> {
+        if !self.is_tracked(spender) {
+            return Ok(false);
+        }
+        Ok(self.allowance(token, spender, block_number).await? < amount)
+    }
+
+    /// Allowances of each token to `spender`. Tokens whose allowance can't be fetched are
+    /// left out.
+    pub async fn allowances<T: TokenLike + ?Sized>(
+        &self,
+        tokens: &[&T],
+        spender: Address,
+        block_number: Option<u64>,
+    ) -> HashMap<Address, U256> {
+        let allowance_futs = tokens.iter().map(|token| async move {
+            (
+                token.address(),
+                self.allowance(*token, spender, block_number).await,
+            )
+        });
+
+        let mut allowances = HashMap::new();
+        for (token, result) in join_all(allowance_futs).await {
+            match result {
+                Ok(allowance) => {
+                    allowances.insert(token, allowance);
+                }
+                Err(e) => tracing::warn!(?token, ?spender, "Failed to fetch allowance: {:?}", e),
+            }
+        }
+        allowances
+    }
+
+    /// Updates the cached allowance from an `Approval` log of the owner to a tracked spender.
+    /// Returns whether the log was one.
+    pub fn record_approval_log(&self, log: &Log) -> bool {
+        let Ok(approval) = IERC20::Approval::decode_log_data(log.data()) else {
+            return false;
+        };
+        if approval.owner != self.owner || !self.is_tracked(approval.spender) {
+            return false;
+        }
+        match self.allowances.lock() {
+            Ok(mut allowances) => {
+                allowances.insert((log.address(), approval.spender), approval.value);
+                true
+            }
+            Err(_) => false,
+        }
+    }
+
+    /// Matches every `Approval` log emitted for the owner.
+    pub fn approval_filter(&self) -> Filter {
+        Filter::new()
+            .event_signature(IERC20::Approval::SIGNATURE_HASH)
+            .topic1(self.owner.into_word())
+    }
+
+    fn cached(&self, key: (Address, Address)) -> Option<U256> {
+        self.allowances.lock().ok()?.get(&key).copied()
+    }
+}
+
+/// Approvals `swap_actions` need before they can run through `spender`, one per input token
+/// whose allowance is below what the path spends of it. Tokens missing from `allowances`
+/// are assumed approved. Wrapping and unwrapping need none.
+pub fn required_approvals(
+    swap_actions: &[SwapAction],
+    spender: Address,
+    allowances: &HashMap<Address, U256>,
+) -> Vec<ApproveAction> {
+    let mut spent: Vec<(Address, U256)> = Vec::new();
+    for action in swap_actions {
+        if matches!(action.kind, SwapKind::Wrap | SwapKind::Unwrap) {
+            continue;
+        }
+        let token = action.token_in.address;
+        match spent
+            .iter_mut()
+            .find(|(spent_token, _)| *spent_token == token)
+        {
+            Some((_, amount)) => *amount = amount.saturating_add(action.amount_in),
+            None => spent.push((token, action.amount_in)),
+        }
+    }
+    spent
+        .into_iter()
+        .filter(|(token, amount)| {
+            allowances
+                .get(token)
+                .is_some_and(|allowance| allowance < amount)
+        })
+        .map(|(token, _)| ApproveAction {
+            token,
+            spender,
+            amount: U256::MAX,
+        })
+        .collect()
+}
@@ -0,0 +1,258 @@
+use crate::arbitrage::engine::ArbitrageEngine;
+use crate::arbitrage::export::decimal;
+#[cfg(feature = "db")]
+use crate::arbitrage::finder::{MinLiquidityFilter, collect_pools};
+use crate::arbitrage::gas::{Eip1559Estimator, GasEstimator};
+use crate::arbitrage::types::{ArbitrageSolution, CycleId};
+use crate::core::token::TokenLike;
+use crate::errors::ArbRsError;
+#[cfg(feature = "db")]
+use crate::manager::{
+    balancer_pool_manager::BalancerPoolManager, curve_pool_manager::CurvePoolManager,
+    uniswap_v2_pool_manager::UniswapV2PoolManager, uniswap_v3_pool_manager::UniswapV3PoolManager,
+};
+use alloy_primitives::{Address, U256};
+use alloy_provider::Provider;
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+/// Paths listed in a report when `Backtester::with_top_paths` isn't called.
+pub const DEFAULT_TOP_PATHS: usize = 20;
+
+/// What the engine found in one replayed block.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlockBacktest {
+    pub block: u64,
+    pub opportunities: usize,
+    #[serde(with = "decimal")]
+    pub net_profit_weth: U256,
+    /// Pools whose snapshot at the block couldn't be fetched, e.g. because they were
+    /// deployed later.
+    pub failed_snapshots: usize,
+}
+
+/// The opportunities in one profit token over the range. Amounts are in its raw units.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TokenBacktest {
+    pub opportunities: u64,
+    #[serde(with = "decimal")]
+    pub net_profit: U256,
+    #[serde(with = "decimal")]
+    pub net_profit_weth: U256,
+    /// Mean of the optimal inputs the optimizer found.
+    #[serde(with = "decimal")]
+    pub average_input: U256,
+}
+
+/// The opportunities of one cycle over the range, in raw units of its profit token.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PathBacktest {
+    pub cycle_id: CycleId,
+    pub pools: Vec<Address>,
+    pub profit_token: Address,
+    pub opportunities: u64,
+    #[serde(with = "decimal")]
+    pub net_profit: U256,
+    #[serde(with = "decimal")]
+    pub net_profit_weth: U256,
+    pub first_block: u64,
+    pub last_block: u64,
+}
+
+/// Everything a backtest found, without timings, so replaying the same blocks with the same
+/// pools and configuration always produces byte-identical JSON.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BacktestReport {
+    pub from_block: u64,
+    pub to_block: u64,
+    /// One entry per block of the range, in order.
+    pub blocks: Vec<BlockBacktest>,
+    pub tokens: BTreeMap<Address, TokenBacktest>,
+    /// The paths with the most net profit in wei over the range, most profitable first.
+    pub top_paths: Vec<PathBacktest>,
+}
+
+impl BacktestReport {
+    pub fn opportunities(&self) -> usize {
+        self.blocks.iter().map(|block| block.opportunities).sum()
+    }
+
+    pub fn to_json_string(&self) -> Result<String, ArbRsError> {
+        serde_json::to_string_pretty(self).map_err(|e| ArbRsError::ExportError(e.to_string()))
+    }
+
+    pub fn from_json_str(json: &str) -> Result<Self, ArbRsError> {
+        serde_json::from_str(json).map_err(|e| ArbRsError::ExportError(e.to_string()))
+    }
+}
+
+/// Sums of one token's or path's opportunities, before averaging and ranking.
+#[derive(Default)]
+struct Totals {
+    opportunities: u64,
+    net_profit: U256,
+    net_profit_weth: U256,
+    input: U256,
+}
+
+impl Totals {
+    fn add<P: Provider + Send + Sync + 'static + ?Sized>(
+        &mut self,
+        solution: &ArbitrageSolution<P>,
+    ) {
+        self.opportunities += 1;
+        self.net_profit = self.net_profit.saturating_add(solution.net_profit.raw);
+        self.net_profit_weth = self
+            .net_profit_weth
+            .saturating_add(solution.net_profit_weth);
+        self.input = self.input.saturating_add(solution.optimal_input.raw);
+    }
+}
+
+/// Replays a range of past blocks through an engine, each evaluated with every pool
+/// snapshotted at that block and gas costed at that block's base and priority fees.
+///
+/// Evaluations carry state from one block to the next, such as how long each cycle has been
+/// profitable and which pools are quarantined, so reports are only reproducible from a
+/// fresh engine.
+pub struct Backtester<P: Provider + Send + Sync + 'static + ?Sized> {
+    pub engine: ArbitrageEngine<P>,
+    top_paths: usize,
+}
+
+impl<P: Provider + Send + Sync + 'static + ?Sized> Backtester<P> {
+    /// Switches `engine` to the historical gas price, read by an [`Eip1559Estimator`] at
+    /// each replayed block. Its state updater is dropped, since nothing feeds it the
+    /// replayed blocks' logs.
+    pub fn new(mut engine: ArbitrageEngine<P>) -> Self {
+        engine.gas_estimator = Arc::new(Eip1559Estimator::new(engine.provider.clone()));
+        engine.state_updater = None;
+        Self {
+            engine,
+            top_paths: DEFAULT_TOP_PATHS,
+        }
+    }
+
+    /// Replaces the engine's paths with the WETH cycles of up to `max_hops` pools over every
+    /// pool the managers hold.
+    #[cfg(feature = "db")]
+    pub async fn from_managers(
+        engine: ArbitrageEngine<P>,
+        v2_manager: &UniswapV2PoolManager<P>,
+        v3_manager: &UniswapV3PoolManager<P>,
+        curve_manager: &CurvePoolManager<P>,
+        balancer_manager: &BalancerPoolManager<P>,
+        max_hops: usize,
+    ) -> Self {
+        let pools = collect_pools(v2_manager, v3_manager, curve_manager, balancer_manager);
+        engine
+            .cache
+            .rebuild_with_filter(
+                pools,
+                &engine.token_manager,
+                max_hops,
+                &MinLiquidityFilter::default(),
+            )
+            .await;
+        Self::new(engine)
+    }
+
+    /// Costs gas with `gas_estimator` instead, e.g. a `FixedGas` of recorded prices.
+    pub fn with_gas_estimator(mut self, gas_estimator: Box<dyn GasEstimator>) -> Self {
+        self.engine.gas_estimator = gas_estimator.into();
+        self
+    }
+
+    pub fn with_top_paths(mut self, top_paths: usize) -> Self {
+        self.top_paths = top_paths;
+        self
+    }
+
+    /// Evaluates every block of `from..=to` in order. Pools that fail to snapshot at a block
+    /// only leave their paths out of that block, and are counted in its `failed_snapshots`.
+    pub async fn run(&self, from: u64, to: u64) -> Result<BacktestReport, ArbRsError> {
+        if from > to {
+            return Err(ArbRsError::CalculationError(format!(
+                "Empty block range {from}..={to}"
+            )));
+        }
+        let mut blocks = Vec::new();
+        let mut tokens: BTreeMap<Address, Totals> = BTreeMap::new();
+        let mut paths: BTreeMap<CycleId, (PathBacktest, Totals)> = BTreeMap::new();
+        for block in from..=to {
+            let solutions = self.engine.find_opportunities(Some(block)).await;
+            let stats = self.engine.last_stats();
+            let mut evaluated = BlockBacktest {
+                block,
+                opportunities: solutions.len(),
+                failed_snapshots: stats.failed_snapshots,
+                ..Default::default()
+            };
+            for solution in &solutions {
+                let profit_token = solution.net_profit.token.address();
+                evaluated.net_profit_weth = evaluated
+                    .net_profit_weth
+                    .saturating_add(solution.net_profit_weth);
+                tokens.entry(profit_token).or_default().add(solution);
+                let (path, totals) = paths.entry(solution.cycle_id.clone()).or_insert_with(|| {
+                    let path = PathBacktest {
+                        cycle_id: solution.cycle_id.clone(),
+                        pools: solution
+                            .swap_actions
+                            .iter()
+                            .map(|action| action.pool_address)
+                            .collect(),
+                        profit_token,
+                        opportunities: 0,
+                        net_profit: U256::ZERO,
+                        net_profit_weth: U256::ZERO,
+                        first_block: block,
+                        last_block: block,
+                    };
+                    (path, Totals::default())
+                });
+                path.last_block = block;
+                totals.add(solution);
+            }
+            blocks.push(evaluated);
+            if (block - from + 1).is_multiple_of(100) {
+                tracing::info!(block, to, "Backtest progress.");
+            }
+        }
+
+        let tokens = tokens
+            .into_iter()
+            .map(|(token, totals)| {
+                let backtest = TokenBacktest {
+                    opportunities: totals.opportunities,
+                    net_profit: totals.net_profit,
+                    net_profit_weth: totals.net_profit_weth,
+                    average_input: totals.input / U256::from(totals.opportunities),
+                };
+                (token, backtest)
+            })
+            .collect();
+        let mut top_paths: Vec<PathBacktest> = paths
+            .into_values()
+            .map(|(path, totals)| PathBacktest {
+                opportunities: totals.opportunities,
+                net_profit: totals.net_profit,
+                net_profit_weth: totals.net_profit_weth,
+                ..path
+            })
+            .collect();
+        // Stable, and the paths come out of the map by cycle, so ties keep a fixed order.
+        top_paths.sort_by_key(|path| Reverse(path.net_profit_weth));
+        top_paths.truncate(self.top_paths);
+
+        Ok(BacktestReport {
+            from_block: from,
+            to_block: to,
+            blocks,
+            tokens,
+            top_paths,
+        })
+    }
+}
@@ -1,12 +1,23 @@
-use crate::arbitrage::types::Arbitrage;
+use crate::arbitrage::finder::{
+    FinderReport, MinLiquidityFilter, PathFinder, find_multi_hop_cycles_in_pools,
+};
+use crate::arbitrage::types::{Arbitrage, PathKey};
+use crate::core::token_policy::TokenPolicy;
+use crate::manager::token_manager::TokenManager;
+use crate::pool::LiquidityPool;
+use alloy_primitives::Address;
 use alloy_provider::Provider;
+use std::collections::HashSet;
 use std::fmt::{self, Debug};
 use std::sync::Arc;
 use tokio::sync::RwLock;
 
-/// An in-memory, thread-safe cache to store discovered arbitrage paths.
+/// An in-memory, thread-safe cache to store discovered arbitrage paths. Holds one path per
+/// [`PathKey`], so rotations of a cycle are only evaluated once.
 pub struct ArbitrageCache<P: Provider + Send + Sync + 'static + ?Sized> {
     pub paths: Arc<RwLock<Vec<Arc<dyn Arbitrage<P>>>>>,
+    /// Keys of `paths`. Only locked while `paths` is locked for writing.
+    keys: RwLock<HashSet<PathKey>>,
 }
 
 impl<P: Provider + Send + Sync + 'static + ?Sized> Debug for ArbitrageCache<P> {
@@ -22,12 +33,141 @@ impl<P: Provider + Send + Sync + 'static + ?Sized> ArbitrageCache<P> {
     pub fn new() -> Self {
         Self {
             paths: Arc::new(RwLock::new(Vec::new())),
+            keys: RwLock::new(HashSet::new()),
         }
     }
 
-    pub async fn add_path(&self, path: Arc<dyn Arbitrage<P>>) {
+    /// The cached paths that trade through none of `pools`.
+    pub async fn paths_excluding(&self, pools: &HashSet<Address>) -> Vec<Arc<dyn Arbitrage<P>>> {
+        let paths = self.paths.read().await;
+        if pools.is_empty() {
+            return paths.clone();
+        }
+        paths
+            .iter()
+            .filter(|path| {
+                !path
+                    .get_involved_pools()
+                    .iter()
+                    .any(|pool| pools.contains(pool))
+            })
+            .cloned()
+            .collect()
+    }
+
+    /// Adds `path` unless a path with its key is cached. Returns whether it was added.
+    pub async fn add_path(&self, path: Arc<dyn Arbitrage<P>>) -> bool {
+        self.merge_paths(vec![path]).await == 1
+    }
+
+    /// Adds the paths [`PathFinder::extend`] found through newly discovered pools, as
+    /// [`Self::merge_paths`] does. Returns how many were added.
+    pub async fn add_paths(&self, new_paths: Vec<Arc<dyn Arbitrage<P>>>) -> usize {
+        self.merge_paths(new_paths).await
+    }
+
+    /// Adds the paths whose keys aren't cached yet, leaving the cached ones in place so
+    /// references to them stay valid. Returns how many were added.
+    pub async fn merge_paths(&self, new_paths: Vec<Arc<dyn Arbitrage<P>>>) -> usize {
+        let mut paths = self.paths.write().await;
+        let mut keys = self.keys.write().await;
+        let previous = paths.len();
+        for path in new_paths {
+            if keys.insert(path.path_key()) {
+                paths.push(path);
+            }
+        }
+        paths.len() - previous
+    }
+
+    /// Makes `new_paths` the cached set. Paths already cached are kept as they are, those
+    /// missing from `new_paths` are dropped and the rest are added.
+    async fn replace_paths(&self, new_paths: &[Arc<dyn Arbitrage<P>>]) -> (usize, usize) {
+        let mut paths = self.paths.write().await;
+        let mut keys = self.keys.write().await;
+        let incoming: HashSet<PathKey> = new_paths.iter().map(|path| path.path_key()).collect();
+        let previous = paths.len();
+        paths.retain(|path| incoming.contains(&path.path_key()));
+        let kept = paths.len();
+        *keys = paths.iter().map(|path| path.path_key()).collect();
+        for path in new_paths {
+            if keys.insert(path.path_key()) {
+                paths.push(path.clone());
+            }
+        }
+        (previous - kept, paths.len() - kept)
+    }
+
+    /// Drops the paths through a pool holding a token `policy` refuses, e.g. after it was
+    /// changed at runtime. Returns the keys of the dropped paths.
+    pub async fn prune_refused(&self, policy: &TokenPolicy) -> Vec<PathKey> {
         let mut paths = self.paths.write().await;
-        paths.push(path);
+        let mut keys = self.keys.write().await;
+        let refused: HashSet<Address> = paths
+            .iter()
+            .flat_map(|path| policy.refused_pools(path.get_pools()))
+            .collect();
+        let mut dropped = Vec::new();
+        paths.retain(|path| {
+            let keep = !path
+                .get_involved_pools()
+                .iter()
+                .any(|pool| refused.contains(pool));
+            if !keep {
+                let key = path.path_key();
+                keys.remove(&key);
+                dropped.push(key);
+            }
+            keep
+        });
+        if !dropped.is_empty() {
+            tracing::info!(
+                dropped = dropped.len(),
+                current = paths.len(),
+                "Dropped paths refused by the token policy."
+            );
+        }
+        dropped
+    }
+
+    /// Re-runs path discovery over `pools` with `filter` and swaps the cached paths for the
+    /// ones found, e.g. to change the liquidity floor without restarting. Paths found again
+    /// keep their cached instance.
+    pub async fn rebuild_with_filter(
+        &self,
+        pools: Vec<Arc<dyn LiquidityPool<P>>>,
+        token_manager: &TokenManager<P>,
+        max_hops: usize,
+        filter: &MinLiquidityFilter,
+    ) -> FinderReport<P> {
+        let report = find_multi_hop_cycles_in_pools(pools, token_manager, max_hops, filter).await;
+        let (removed, added) = self.replace_paths(&report.paths).await;
+        tracing::info!(
+            removed,
+            added,
+            current = self.paths.read().await.len(),
+            "Rebuilt the arbitrage path cache."
+        );
+        report
+    }
+
+    /// Like [`Self::rebuild_with_filter`] with `finder`'s hops and filter, keeping the graph
+    /// in `finder` so pools discovered later only need [`PathFinder::extend`].
+    pub async fn rebuild_with_finder(
+        &self,
+        finder: &PathFinder<P>,
+        pools: Vec<Arc<dyn LiquidityPool<P>>>,
+        token_manager: &TokenManager<P>,
+    ) -> FinderReport<P> {
+        let report = finder.rebuild(pools, token_manager).await;
+        let (removed, added) = self.replace_paths(&report.paths).await;
+        tracing::info!(
+            removed,
+            added,
+            current = self.paths.read().await.len(),
+            "Rebuilt the arbitrage path cache."
+        );
+        report
     }
 }
 
@@ -0,0 +1,196 @@
+#[cfg(feature = "db")]
+use crate::db::DbManager;
+use crate::math::utils::u256_to_f64;
+pub use crate::pool::CalibrationBucket;
+use crate::pool::LiquidityPool;
+use alloy_primitives::U256;
+use alloy_provider::Provider;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, RwLock};
+
+const BPS_DENOMINATOR: u64 = 10_000;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CalibrationConfig {
+    /// Weight of each new sample in the moving averages.
+    pub ewma_alpha: f64,
+    /// Buckets with fewer samples apply no haircut.
+    pub min_samples: u64,
+    /// The haircut is `bias_multiple * (bias + sigma_k * sigma)`, in bps of the expected output.
+    pub bias_multiple: f64,
+    pub sigma_k: f64,
+}
+
+impl Default for CalibrationConfig {
+    fn default() -> Self {
+        Self {
+            ewma_alpha: 0.05,
+            min_samples: 50,
+            bias_multiple: 1.0,
+            sigma_k: 1.0,
+        }
+    }
+}
+
+/// Moving estimate of a bucket's prediction error, in bps of the predicted output.
+/// Positive values mean predictions were higher than what was realized.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct CalibrationStats {
+    pub samples: u64,
+    pub mean_bps: f64,
+    pub variance_bps: f64,
+}
+
+impl CalibrationStats {
+    pub fn sigma_bps(&self) -> f64 {
+        self.variance_bps.max(0.0).sqrt()
+    }
+
+    /// Early samples are weighted `1 / n` so the estimate isn't dragged towards zero while
+    /// the average warms up.
+    fn update(&mut self, error_bps: f64, alpha: f64) {
+        self.samples += 1;
+        let weight = alpha.max(1.0 / self.samples as f64);
+        let diff = error_bps - self.mean_bps;
+        self.mean_bps += weight * diff;
+        self.variance_bps = (1.0 - weight) * (self.variance_bps + weight * diff * diff);
+    }
+}
+
+/// One line of the calibration table.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CalibrationRow {
+    pub bucket: CalibrationBucket,
+    pub stats: CalibrationStats,
+    pub haircut_bps: u64,
+}
+
+/// Accumulates predicted vs realized hop outputs per bucket and turns systematic
+/// optimism into a haircut on expected outputs.
+#[derive(Debug, Default)]
+pub struct CalibrationTracker {
+    pub config: CalibrationConfig,
+    buckets: RwLock<HashMap<CalibrationBucket, CalibrationStats>>,
+}
+
+impl CalibrationTracker {
+    pub fn new(config: CalibrationConfig) -> Self {
+        Self {
+            config,
+            buckets: RwLock::default(),
+        }
+    }
+
+    /// Records one hop's predicted and realized output. Zero predictions are ignored.
+    pub fn record(&self, bucket: CalibrationBucket, predicted: U256, realized: U256) {
+        if predicted.is_zero() {
+            return;
+        }
+        let predicted_f64 = u256_to_f64(predicted);
+        let error_bps =
+            (predicted_f64 - u256_to_f64(realized)) / predicted_f64 * BPS_DENOMINATOR as f64;
+        if let Ok(mut buckets) = self.buckets.write() {
+            buckets
+                .entry(bucket)
+                .or_default()
+                .update(error_bps, self.config.ewma_alpha);
+        }
+    }
+
+    pub fn stats(&self, bucket: &CalibrationBucket) -> Option<CalibrationStats> {
+        self.buckets.read().ok()?.get(bucket).copied()
+    }
+
+    /// The bucket's mean prediction error, once it has `min_samples` samples.
+    pub fn bias_bps(&self, bucket: &CalibrationBucket) -> Option<f64> {
+        self.stats(bucket)
+            .filter(|stats| stats.samples >= self.config.min_samples)
+            .map(|stats| stats.mean_bps)
+    }
+
+    /// Haircut applied to a hop's expected output. Zero for buckets with too few samples
+    /// and for buckets whose predictions are not optimistic.
+    pub fn haircut_bps(&self, bucket: &CalibrationBucket) -> u64 {
+        self.stats(bucket)
+            .map(|stats| self.haircut_for(&stats))
+            .unwrap_or(0)
+    }
+
+    fn haircut_for(&self, stats: &CalibrationStats) -> u64 {
+        if stats.samples < self.config.min_samples {
+            return 0;
+        }
+        let haircut =
+            self.config.bias_multiple * (stats.mean_bps + self.config.sigma_k * stats.sigma_bps());
+        haircut.clamp(0.0, BPS_DENOMINATOR as f64).round() as u64
+    }
+
+    /// Haircuts of every bucket that currently has one.
+    pub fn haircuts(&self) -> HashMap<CalibrationBucket, u64> {
+        self.table()
+            .into_iter()
+            .filter(|row| row.haircut_bps > 0)
+            .map(|row| (row.bucket, row.haircut_bps))
+            .collect()
+    }
+
+    /// Every bucket with its current estimate, sorted by bucket.
+    pub fn table(&self) -> Vec<CalibrationRow> {
+        let Ok(buckets) = self.buckets.read() else {
+            return Vec::new();
+        };
+        buckets
+            .iter()
+            .collect::<BTreeMap<_, _>>()
+            .into_iter()
+            .map(|(bucket, stats)| CalibrationRow {
+                bucket: bucket.clone(),
+                stats: *stats,
+                haircut_bps: self.haircut_for(stats),
+            })
+            .collect()
+    }
+
+    /// Replaces the in-memory estimates with those saved in the database.
+    #[cfg(feature = "db")]
+    pub async fn load(&self, db_manager: &DbManager) -> Result<(), sqlx::Error> {
+        let saved = db_manager.load_calibration().await?;
+        if let Ok(mut buckets) = self.buckets.write() {
+            *buckets = saved.into_iter().collect();
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "db")]
+    pub async fn save(&self, db_manager: &DbManager) -> Result<(), sqlx::Error> {
+        for row in self.table() {
+            db_manager.save_calibration(&row.bucket, &row.stats).await?;
+        }
+        Ok(())
+    }
+}
+
+/// Per-hop haircuts for `pools` looked up in a table from [`CalibrationTracker::haircuts`].
+pub fn hop_haircuts<P: Provider + Send + Sync + 'static + ?Sized>(
+    pools: &[Arc<dyn LiquidityPool<P>>],
+    haircuts: &HashMap<CalibrationBucket, u64>,
+) -> Vec<u64> {
+    pools
+        .iter()
+        .map(|pool| {
+            pool.calibration_bucket()
+                .and_then(|bucket| haircuts.get(&bucket).copied())
+                .unwrap_or(0)
+        })
+        .collect()
+}
+
+/// `amount` reduced by `haircut_bps`, rounded down.
+pub fn apply_haircut(amount: U256, haircut_bps: u64) -> U256 {
+    if haircut_bps == 0 {
+        return amount;
+    }
+    let denominator = U256::from(BPS_DENOMINATOR);
+    amount.saturating_mul(denominator.saturating_sub(U256::from(haircut_bps))) / denominator
+}
@@ -1,19 +1,26 @@
 use crate::{
-    arbitrage::types::{Arbitrage, ArbitragePath},
+    arbitrage::{
+        calibration::apply_haircut,
+        quote_path::QuotePath,
+        types::{Arbitrage, ArbitragePath, CycleId, PathKey, TokenRef, rotate_to_smallest},
+    },
     balancer::pool::BalancerPool,
-    core::token::TokenLike,
+    core::token::{TRANSFER_TAX_BPS_DENOMINATOR, Token, TokenLike},
     curve::{
         constants::FEE_DENOMINATOR, pool::CurveStableswapPool, pool_attributes::SwapStrategyType,
     },
     errors::ArbRsError,
     math::{utils::u256_to_f64, v3::constants::Q96},
-    pool::{LiquidityPool, PoolSnapshot, uniswap_v3::UniswapV3Pool},
+    pool::{
+        LiquidityPool, PoolSnapshot, SwapGasCosts, uniswap_v3::UniswapV3Pool,
+        uniswap_v4::UniswapV4Pool,
+    },
 };
 use alloy_primitives::{Address, U256};
 use alloy_provider::Provider;
 use std::{
     any::Any,
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     fmt::{self, Debug, Formatter},
     sync::Arc,
 };
@@ -30,21 +37,98 @@ impl<P: Provider + Send + Sync + 'static + ?Sized> ArbitrageCycle<P> {
             path: Arc::new(path),
         }
     }
-}
 
-impl<P: Provider + Send + Sync + 'static + ?Sized> Arbitrage<P> for ArbitrageCycle<P> {
-    fn get_involved_pools(&self) -> Vec<Address> {
-        self.path.pools.iter().map(|p| p.address()).collect()
+    /// The id shared by every rotation of this cycle.
+    pub fn cycle_id(&self) -> CycleId {
+        let hops: Vec<(Address, Address)> = self
+            .path
+            .pools
+            .iter()
+            .zip(&self.path.path)
+            .map(|(pool, token_in)| (pool.address(), token_in.address()))
+            .collect();
+        CycleId(rotate_to_smallest(&hops))
     }
 
-    fn get_pools(&self) -> &Vec<Arc<dyn LiquidityPool<P>>> {
-        &self.path.pools
+    /// The same cycle entered at `path[start]`, which becomes the profit token.
+    pub fn rotated(&self, start: usize) -> Self {
+        let n = self.path.pools.len();
+        let start = start % n;
+        let pools = [&self.path.pools[start..], &self.path.pools[..start]].concat();
+        let mut path = [&self.path.path[start..n], &self.path.path[..start]].concat();
+        path.push(self.path.path[start].clone());
+
+        Self::new(ArbitragePath {
+            pools,
+            profit_token: path[0].clone(),
+            path,
+        })
     }
 
-    fn calculate_out_amount(
+    /// Rotations of this cycle entered at each of its tokens found in `entry_tokens`.
+    pub fn entry_rotations(&self, entry_tokens: &HashSet<Address>) -> Vec<Self> {
+        (0..self.path.pools.len())
+            .filter(|&i| entry_tokens.contains(&self.path.path[i].address()))
+            .map(|i| self.rotated(i))
+            .collect()
+    }
+
+    /// Pools whose last observed trade diverges from their snapshot by more than
+    /// `threshold_bps`. Pools without trade data or a snapshot are not reported.
+    pub fn divergent_pools(
+        &self,
+        snapshots: &HashMap<Address, PoolSnapshot>,
+        threshold_bps: u64,
+    ) -> Vec<Address> {
+        self.path
+            .pools
+            .iter()
+            .filter(|pool| {
+                snapshots
+                    .get(&pool.address())
+                    .and_then(|snapshot| pool.spot_vs_last_trade_divergence_bps(snapshot))
+                    .is_some_and(|bps| bps > threshold_bps)
+            })
+            .map(|pool| pool.address())
+            .collect()
+    }
+
+    /// Fails with [`ArbRsError::StaleOracle`] on the first pool whose oracle, by its
+    /// snapshot, last updated more than `max_age` seconds before the snapshot's block.
+    /// Pools without an oracle timestamp or a snapshot pass.
+    pub fn check_oracle_ages(
+        &self,
+        snapshots: &HashMap<Address, PoolSnapshot>,
+        max_age: u64,
+    ) -> Result<(), ArbRsError> {
+        for pool in &self.path.pools {
+            let Some(PoolSnapshot::Curve(snapshot)) = snapshots.get(&pool.address()) else {
+                continue;
+            };
+            match (snapshot.oracle_updated_at, snapshot.oracle_age()) {
+                (Some(updated_at), Some(age)) if age > max_age => {
+                    return Err(ArbRsError::StaleOracle {
+                        pool: pool.address(),
+                        updated_at,
+                        block_timestamp: snapshot.block_timestamp,
+                    });
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Like `calculate_out_amount`, with each hop's output reduced by the matching entry of
+    /// `haircuts_bps`. Missing entries apply no haircut.
+    ///
+    /// Each transfer is net of its token's transfer tax: a hop is quoted on what its pool
+    /// receives, and the cycle's output is what reaches the caller.
+    pub fn calculate_out_amount_with_haircuts(
         &self,
         start_amount: U256,
         snapshots: &HashMap<Address, PoolSnapshot>,
+        haircuts_bps: &[u64],
     ) -> Result<U256, ArbRsError> {
         if start_amount.is_zero() {
             return Ok(U256::ZERO);
@@ -60,16 +144,146 @@ impl<P: Provider + Send + Sync + 'static + ?Sized> Arbitrage<P> for ArbitrageCyc
             let token_in = &self.path.path[i];
             let token_out = &self.path.path[i + 1];
 
-            current_amount =
-                pool.calculate_tokens_out(token_in, token_out, current_amount, snapshot)?;
+            let amount_in = token_in.received_amount(current_amount);
+            current_amount = apply_haircut(
+                pool.calculate_tokens_out(token_in, token_out, amount_in, snapshot)?,
+                haircuts_bps.get(i).copied().unwrap_or(0),
+            );
 
             if current_amount.is_zero() {
                 break;
             }
         }
+        Ok(self.path.path[self.path.pools.len()].received_amount(current_amount))
+    }
+
+    /// The cycle's pools as quoters over `snapshots`, for quoting without the pools.
+    pub fn quote_path(
+        &self,
+        snapshots: &HashMap<Address, PoolSnapshot>,
+    ) -> Result<QuotePath, ArbRsError> {
+        let pools = self
+            .path
+            .pools
+            .iter()
+            .map(|pool| {
+                let snapshot = snapshots
+                    .get(&pool.address())
+                    .ok_or(ArbRsError::NoPoolStateAvailable(0))?;
+                pool.to_quoter(snapshot)
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(QuotePath {
+            pools,
+            tokens: self
+                .path
+                .path
+                .iter()
+                .map(|token| TokenRef::from(token.as_ref()))
+                .collect(),
+            transfer_tax_bps: self
+                .path
+                .path
+                .iter()
+                .map(|token| token.transfer_tax_bps())
+                .collect(),
+        })
+    }
+
+    /// Gas of the swaps along the cycle for an input of `start_amount`, each hop priced by
+    /// its pool from `costs`. Hops whose snapshot is missing are priced as `costs.other`.
+    pub fn swap_gas_estimate(
+        &self,
+        start_amount: U256,
+        snapshots: &HashMap<Address, PoolSnapshot>,
+        costs: &SwapGasCosts,
+    ) -> u64 {
+        let mut current_amount = start_amount;
+        let mut gas = 0;
+        for (i, pool) in self.path.pools.iter().enumerate() {
+            let Some(snapshot) = snapshots.get(&pool.address()) else {
+                gas += costs.other;
+                continue;
+            };
+            let (token_in, token_out) = (&self.path.path[i], &self.path.path[i + 1]);
+            gas += pool.gas_estimate(token_in, token_out, current_amount, snapshot, costs);
+            current_amount = pool
+                .calculate_tokens_out(token_in, token_out, current_amount, snapshot)
+                .unwrap_or_default();
+        }
+        gas
+    }
+}
+
+impl<P: Provider + Send + Sync + 'static + ?Sized> Arbitrage<P> for ArbitrageCycle<P> {
+    fn get_involved_pools(&self) -> Vec<Address> {
+        self.path.pools.iter().map(|p| p.address()).collect()
+    }
+
+    fn get_pools(&self) -> &Vec<Arc<dyn LiquidityPool<P>>> {
+        &self.path.pools
+    }
+
+    fn calculate_out_amount(
+        &self,
+        start_amount: U256,
+        snapshots: &HashMap<Address, PoolSnapshot>,
+    ) -> Result<U256, ArbRsError> {
+        self.calculate_out_amount_with_haircuts(start_amount, snapshots, &[])
+    }
+
+    /// Each hop is asked for what its pool must receive, and the transfer taxes are added
+    /// back on top of that, so the result is what the caller sends.
+    fn calculate_in_amount(
+        &self,
+        target_out: U256,
+        snapshots: &HashMap<Address, PoolSnapshot>,
+    ) -> Result<U256, ArbRsError> {
+        if target_out.is_zero() {
+            return Ok(U256::ZERO);
+        }
+        let untransferable = |token: &Token<P>| {
+            ArbRsError::CalculationError(format!(
+                "No transfer of {} gets through its transfer tax",
+                token.address()
+            ))
+        };
+        let last_token = &self.path.path[self.path.pools.len()];
+        let mut current_amount = last_token
+            .sent_amount_for(target_out)
+            .ok_or_else(|| untransferable(last_token))?;
+
+        for i in (0..self.path.pools.len()).rev() {
+            let pool = &self.path.pools[i];
+            let snapshot = snapshots
+                .get(&pool.address())
+                .ok_or(ArbRsError::NoPoolStateAvailable(0))?;
+
+            let token_in = &self.path.path[i];
+            let token_out = &self.path.path[i + 1];
+
+            let amount_in = pool
+                .calculate_tokens_in(token_in, token_out, current_amount, snapshot)
+                .map_err(|e| {
+                    ArbRsError::CalculationError(format!(
+                        "Hop {} through pool {} can't quote an exact output of {}: {}",
+                        i,
+                        pool.address(),
+                        current_amount,
+                        e
+                    ))
+                })?;
+            current_amount = token_in
+                .sent_amount_for(amount_in)
+                .ok_or_else(|| untransferable(token_in))?;
+        }
         Ok(current_amount)
     }
 
+    fn path_key(&self) -> PathKey {
+        PathKey::new(&self.path.pools, &self.path.path)
+    }
+
     fn check_viability(
         &self,
         snapshots: &HashMap<Address, PoolSnapshot>,
@@ -109,51 +323,79 @@ impl<P: Provider + Send + Sync + 'static + ?Sized> Arbitrage<P> for ArbitrageCyc
                         1.0 / price_of_token0_in_token1
                     };
 
-                    let fee = pool_arc
+                    let v3_pool = pool_arc
                         .as_any()
                         .downcast_ref::<UniswapV3Pool<P>>()
-                        .unwrap()
-                        .fee();
-                    (price, 1.0 - (fee as f64 / 1_000_000.0))
+                        .unwrap();
+                    // An empty pool prices at its last tick, but can't be swapped through.
+                    let zero_for_one = *pool_arc.get_all_tokens()[0] == **token_in;
+                    if let Err(ArbRsError::NoLiquidity { .. }) =
+                        v3_pool.check_liquidity(zero_for_one, s)
+                    {
+                        return Ok(false);
+                    }
+                    (price, 1.0 - (v3_pool.fee() as f64 / 1_000_000.0))
                 }
-                PoolSnapshot::Curve(s) => {
-                    let curve_pool = pool_arc
+                PoolSnapshot::UniswapV4(s) => {
+                    if s.state.sqrt_price_x96.is_zero() {
+                        return Ok(false);
+                    }
+                    let ratio = u256_to_f64(s.state.sqrt_price_x96) / u256_to_f64(Q96);
+                    let price_of_token0_in_token1 = ratio.powi(2);
+                    let zero_for_one = *pool_arc.get_all_tokens()[0] == **token_in;
+                    let price = if zero_for_one {
+                        price_of_token0_in_token1
+                    } else {
+                        1.0 / price_of_token0_in_token1
+                    };
+
+                    let v4_pool = pool_arc
                         .as_any()
-                        .downcast_ref::<CurveStableswapPool<P>>()
+                        .downcast_ref::<UniswapV4Pool<P>>()
                         .unwrap();
+                    if v4_pool.check_liquidity(zero_for_one, s).is_err() {
+                        return Ok(false);
+                    }
+                    (price, 1.0 - (s.swap_fee(zero_for_one) as f64 / 1_000_000.0))
+                }
+                PoolSnapshot::Curve(s) => {
                     let fee_factor = 1.0 - (u256_to_f64(s.fee) / u256_to_f64(FEE_DENOMINATOR));
+                    let peg = 10f64.powi(token_in.decimals() as i32 - token_out.decimals() as i32);
 
-                    let price = match curve_pool.attributes.swap_strategy {
-                        SwapStrategyType::Default
-                        | SwapStrategyType::Metapool
-                        | SwapStrategyType::Lending => {
-                            10f64.powi(token_in.decimals() as i32 - token_out.decimals() as i32)
-                        }
-                        _ => {
-                            let i = curve_pool
-                                .tokens
-                                .iter()
-                                .position(|t| t == token_in)
-                                .unwrap();
-                            let j = curve_pool
-                                .tokens
-                                .iter()
-                                .position(|t| t == token_out)
-                                .unwrap();
-                            if s.balances.is_empty() || s.balances[i].is_zero() {
-                                return Ok(false);
+                    let curve_pool = pool_arc.as_any().downcast_ref::<CurveStableswapPool<P>>();
+                    let price = match curve_pool {
+                        // Underlying swaps through a metapool are priced at the peg, like the
+                        // metapool's own.
+                        None => peg,
+                        Some(curve_pool) => match curve_pool.attributes.swap_strategy {
+                            SwapStrategyType::Default
+                            | SwapStrategyType::Metapool
+                            | SwapStrategyType::Lending => peg,
+                            _ => {
+                                let (Some(i), Some(j)) = (
+                                    curve_pool.coin_index(token_in),
+                                    curve_pool.coin_index(token_out),
+                                ) else {
+                                    return Ok(false);
+                                };
+                                if s.balances.is_empty() || s.balances[i].is_zero() {
+                                    return Ok(false);
+                                }
+                                let reserve_in = u256_to_f64(s.balances[i])
+                                    / 10f64.powi(token_in.decimals() as i32);
+                                let reserve_out = u256_to_f64(s.balances[j])
+                                    / 10f64.powi(token_out.decimals() as i32);
+                                reserve_out / reserve_in
                             }
-                            let reserve_in =
-                                u256_to_f64(s.balances[i]) / 10f64.powi(token_in.decimals() as i32);
-                            let reserve_out = u256_to_f64(s.balances[j])
-                                / 10f64.powi(token_out.decimals() as i32);
-                            reserve_out / reserve_in
-                        }
+                        },
                     };
                     (price, fee_factor)
                 }
 
                 PoolSnapshot::Balancer(s) => {
+                    if s.is_paused {
+                        return Err(ArbRsError::PoolPaused(pool_arc.address()));
+                    }
                     let balancer_pool =
                         pool_arc.as_any().downcast_ref::<BalancerPool<P>>().unwrap();
                     let fee_factor = 1.0 - (u256_to_f64(balancer_pool.fee()) / 1e18);
@@ -162,24 +404,18 @@ impl<P: Provider + Send + Sync + 'static + ?Sized> Arbitrage<P> for ArbitrageCyc
                     let i = tokens.iter().position(|t| **t == **token_in).unwrap();
                     let j = tokens.iter().position(|t| **t == **token_out).unwrap();
 
-                    let balance_in = u256_to_f64(s.balances[i]);
-                    let weight_in = u256_to_f64(balancer_pool.weights()[i]);
-
-                    let balance_out = u256_to_f64(s.balances[j]);
-                    let weight_out = u256_to_f64(balancer_pool.weights()[j]);
-
-                    if balance_in == 0.0 || weight_in == 0.0 {
+                    let Some(price) = balancer_pool.spot_price(s, i, j) else {
                         return Ok(false);
-                    }
-
-                    let price = (balance_out / weight_out) / (balance_in / weight_in);
+                    };
 
                     (price, fee_factor)
                 }
+                PoolSnapshot::WrappedNative => (1.0, 1.0),
             };
 
-            profit_factor *= price * fee_factor;
+            profit_factor *= price * fee_factor * transfer_tax_factor(token_in);
         }
+        profit_factor *= transfer_tax_factor(&self.path.path[self.path.pools.len()]);
 
         Ok(profit_factor > 1.0)
     }
@@ -189,6 +425,11 @@ impl<P: Provider + Send + Sync + 'static + ?Sized> Arbitrage<P> for ArbitrageCyc
     }
 }
 
+/// Share of a transfer of `token` its recipient receives.
+fn transfer_tax_factor<P: Provider + Send + Sync + 'static + ?Sized>(token: &Token<P>) -> f64 {
+    1.0 - token.transfer_tax_bps() as f64 / TRANSFER_TAX_BPS_DENOMINATOR as f64
+}
+
 impl<P: Provider + Send + Sync + 'static + ?Sized> Debug for ArbitrageCycle<P> {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         f.debug_struct("ArbitrageCycle")
@@ -0,0 +1,146 @@
+use crate::arbitrage::amount::{TokenAmount, format_units};
+use crate::arbitrage::cycle::ArbitrageCycle;
+use crate::arbitrage::types::{ArbitragePath, HopMetrics};
+use crate::core::token::{Token, TokenLike};
+use crate::errors::ArbRsError;
+use crate::pool::LiquidityPool;
+use alloy_primitives::{Address, I256, U256};
+use alloy_provider::Provider;
+use std::fmt::{self, Display};
+use std::sync::Arc;
+
+/// One hop of a [`PathEvaluation`].
+#[derive(Debug)]
+pub struct HopEvaluation<P: Provider + Send + Sync + 'static + ?Sized> {
+    pub pool: Address,
+    /// What the pool receives, net of the token's transfer tax.
+    pub amount_in: TokenAmount<P>,
+    pub amount_out: TokenAmount<P>,
+    pub metrics: Option<HopMetrics>,
+}
+
+/// A single path traded at a given input and block, as reported by
+/// [`ArbitrageEngine::evaluate_path_at_block`](crate::arbitrage::engine::ArbitrageEngine::evaluate_path_at_block).
+/// Profits are signed, as a path picked by hand is often a losing one.
+#[derive(Debug)]
+pub struct PathEvaluation<P: Provider + Send + Sync + 'static + ?Sized> {
+    pub block: u64,
+    pub cycle: ArbitrageCycle<P>,
+    pub amount_in: TokenAmount<P>,
+    pub hops: Vec<HopEvaluation<P>>,
+    /// What reaches the caller after the last hop.
+    pub amount_out: TokenAmount<P>,
+    /// `amount_out - amount_in`, in raw units of the profit token.
+    pub gross_profit: I256,
+    pub flashloan_fee: TokenAmount<P>,
+    pub gas_units: u64,
+    pub gas_cost_wei: U256,
+    /// `None` when the profit token has no rate to WETH through the path's own pools, which
+    /// leaves the net profit unknown too.
+    pub gas_cost: Option<TokenAmount<P>>,
+    /// Gross profit less the flashloan fee and gas, in raw units of the profit token.
+    pub net_profit: Option<I256>,
+    /// The input the optimizer settles on for this path and its gross profit, or `None`
+    /// when the search failed.
+    pub optimal_input: Option<(TokenAmount<P>, TokenAmount<P>)>,
+}
+
+/// Chains `pools` into a cycle from `token_in` back to it. Each hop sells the previous hop's
+/// output for a token the next pool also holds, and the last hop buys `token_in` back.
+pub fn cycle_path<P: Provider + Send + Sync + 'static + ?Sized>(
+    pools: Vec<Arc<dyn LiquidityPool<P>>>,
+    token_in: Address,
+) -> Result<ArbitragePath<P>, ArbRsError> {
+    let first = pools.first().ok_or_else(|| {
+        ArbRsError::CalculationError("A path needs at least one pool".to_string())
+    })?;
+    let profit_token = first
+        .get_all_tokens()
+        .into_iter()
+        .find(|token| token.address() == token_in)
+        .ok_or_else(|| ArbRsError::InvalidPool(first.address(), format!("does not hold {token_in}")))?;
+
+    let mut path = vec![profit_token.clone()];
+    for (i, pool) in pools.iter().enumerate() {
+        let current = path[i].address();
+        let tokens = pool.get_all_tokens();
+        if !tokens.iter().any(|token| token.address() == current) {
+            return Err(ArbRsError::InvalidPool(pool.address(), format!("does not hold {current}")));
+        }
+        let next = match pools.get(i + 1) {
+            Some(next_pool) => {
+                let next_tokens = next_pool.get_all_tokens();
+                tokens.into_iter().find(|token| {
+                    token.address() != current
+                        && next_tokens.iter().any(|next| next.address() == token.address())
+                })
+            }
+            None => tokens
+                .into_iter()
+                .find(|token| token.address() == token_in && token.address() != current),
+        };
+        let next = next.ok_or_else(|| {
+            let reason = match pools.get(i + 1) {
+                Some(next_pool) => format!("shares no other token with {}", next_pool.address()),
+                None => format!("does not trade back to {token_in}"),
+            };
+            ArbRsError::InvalidPool(pool.address(), reason)
+        })?;
+        path.push(next);
+    }
+
+    Ok(ArbitragePath {
+        pools,
+        path,
+        profit_token,
+    })
+}
+
+impl<P: Provider + Send + Sync + 'static + ?Sized> Display for PathEvaluation<P> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let token = &self.amount_in.token;
+        writeln!(
+            f,
+            "Path of {} hops at block {}, from {}",
+            self.hops.len(),
+            self.block,
+            self.amount_in
+        )?;
+        for (i, hop) in self.hops.iter().enumerate() {
+            write!(f, "  hop {} via {}: {} -> {}", i + 1, hop.pool, hop.amount_in, hop.amount_out)?;
+            match hop.metrics {
+                Some(metrics) => writeln!(f, " (impact {:.2} bps)", metrics.price_impact_bps)?,
+                None => writeln!(f)?,
+            }
+        }
+        writeln!(f, "  output:        {}", self.amount_out)?;
+        writeln!(f, "  gross profit:  {}", format_signed(self.gross_profit, token))?;
+        writeln!(f, "  flashloan fee: {}", self.flashloan_fee)?;
+        match &self.gas_cost {
+            Some(gas_cost) => writeln!(f, "  gas:           {} ({} gas)", gas_cost, self.gas_units)?,
+            None => writeln!(
+                f,
+                "  gas:           {} wei ({} gas, no WETH rate for {})",
+                self.gas_cost_wei,
+                self.gas_units,
+                token.symbol()
+            )?,
+        }
+        match self.net_profit {
+            Some(net_profit) => writeln!(f, "  net profit:    {}", format_signed(net_profit, token))?,
+            None => writeln!(f, "  net profit:    unknown")?,
+        }
+        match &self.optimal_input {
+            Some((input, gross_profit)) => {
+                write!(f, "  optimizer:     {} input for {} gross", input, gross_profit)
+            }
+            None => write!(f, "  optimizer:     failed"),
+        }
+    }
+}
+
+/// A signed raw amount in whole tokens with the token's symbol, e.g. `-0.25 WETH`.
+fn format_signed<P: Provider + Send + Sync + 'static + ?Sized>(amount: I256, token: &Token<P>) -> String {
+    let sign = if amount.is_negative() { "-" } else { "" };
+    format!("{}{} {}", sign, format_units(amount.unsigned_abs(), token.decimals()), token.symbol())
+}
@@ -1,22 +1,217 @@
 use crate::{arbitrage::{
-    cache::ArbitrageCache, cycle::ArbitrageCycle, optimizer, types::{Arbitrage, ArbitrageSolution, SwapAction},
-}, pool::{LiquidityPool, PoolSnapshot}, ArbRsError, Token, TokenLike, TokenManager};
-use alloy_primitives::{address, Address, U256};
+    approvals::{required_approvals, ApprovalTracker, APPROVAL_GAS_UNITS}, cache::ArbitrageCache, calibration::{apply_haircut, hop_haircuts, CalibrationTracker}, amount::TokenAmount, cycle::ArbitrageCycle, gas::{estimated_tx_size, GasEstimator, LegacyGasPrice}, dry_run::{cycle_path, HopEvaluation, PathEvaluation}, finder::MinLiquidityFilter, health::PoolHealth, impact::hop_metrics, export::{BlockEvaluationExport, BlockExporter, EvaluationStats, ExportConfig, SolutionExport}, optimizer::{self, OptimizerConfig}, persistence::{PersistencePolicy, PersistenceTracker}, priority::PathPriority, quote_path::QuotePath, snapshot_store::SnapshotStore, tvl::TvlEstimator, types::{Arbitrage, ArbitrageSolution, CycleId, HopMetrics, InputBound, PathKey, ScenarioResult, SwapAction, SwapKind}, usd::{UsdPriceFeed, UsdValues}, verification::{SolutionVerifier, VerificationPolicy, VerifiedSolution},
+}, chain::{ChainConfig, BALANCER_V2_VAULT}, core::{multicall::MulticallBatcher, token::{received_amount, WETH_ADDRESS}}, curve::pool::{batch_snapshots, CurveStableswapPool}, pool::{cache::DEFAULT_CACHE_MAX_BLOCKS, quoter::{QuotePool, Quoter}, state_updater::StateUpdater, wrapped_native::WrappedNativePool, DexKind, LiquidityPool, PoolSnapshot, SwapGasCosts}, ArbRsError, Token, TokenLike, TokenManager};
+#[cfg(feature = "db")]
+use crate::{arbitrage::recorder::{OpportunityRecord, OpportunityRecorder, DEFAULT_RECORDER_CAPACITY}, db::DbManager};
+use alloy_primitives::{Address, I256, U256};
 use alloy_provider::Provider;
+use alloy_rpc_types::Header;
 use futures::{future::join_all, StreamExt};
 use std::{
     collections::{HashMap, HashSet},
     fmt::{self, Debug},
-    sync::Arc,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
 };
+#[cfg(feature = "db")]
+use std::time::{SystemTime, UNIX_EPOCH};
 
-const WETH_ADDRESS: Address = address!("C02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2");
+const FLASHLOAN_FEE_BPS: U256 = U256::from_limbs([9, 0, 0, 0]);
+/// Longest cycle searched for by [`ArbitrageEngine::quote_only`].
+pub const QUOTE_ONLY_MAX_HOPS: usize = 3;
+/// Default [`EngineConfig::max_oracle_age_secs`]: twice the longest heartbeat of the
+/// Chainlink feeds Curve pools price with.
+pub const DEFAULT_MAX_ORACLE_AGE_SECS: u64 = 2 * 24 * 60 * 60;
+
+/// Compares each hop's last observed trade with its snapshot before optimizing a path.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TradeDivergenceCheck {
+    pub threshold_bps: u64,
+    /// Skip divergent paths entirely instead of only flagging them on the solution.
+    pub reject: bool,
+}
+
+/// Drops what pools cached for old blocks every few evaluated blocks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CachePruning {
+    /// Blocks between two prunes.
+    pub every_blocks: u64,
+    /// Blocks before the evaluated one whose cached states are kept.
+    pub horizon_blocks: u64,
+}
+
+impl Default for CachePruning {
+    fn default() -> Self {
+        Self {
+            every_blocks: 100,
+            horizon_blocks: DEFAULT_CACHE_MAX_BLOCKS as u64,
+        }
+    }
+}
+
+/// Gas price a scenario is evaluated at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScenarioGasPrice {
+    /// The gas price fetched for the evaluated block.
+    Live,
+    /// The live gas price scaled by `bps / 10_000`, e.g. `12_500` for +25%.
+    LiveScaled { bps: u64 },
+    Fixed(U256),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GasScenario {
+    pub label: String,
+    pub gas_price: ScenarioGasPrice,
+}
+
+impl GasScenario {
+    pub fn new(label: impl Into<String>, gas_price: ScenarioGasPrice) -> Self {
+        Self {
+            label: label.into(),
+            gas_price,
+        }
+    }
+
+    pub fn resolve(&self, live_gas_price: U256) -> U256 {
+        match self.gas_price {
+            ScenarioGasPrice::Live => live_gas_price,
+            ScenarioGasPrice::LiveScaled { bps } => {
+                live_gas_price.saturating_mul(U256::from(bps)) / optimizer::BPS_DENOMINATOR
+            }
+            ScenarioGasPrice::Fixed(gas_price) => gas_price,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EngineConfig {
+    /// Minimum net profit, in wei, after flashloan fee and gas. Converted to each path's
+    /// profit token before comparing.
+    pub min_net_profit_wei: U256,
+    /// Tokens a cycle may be entered at, i.e. those we can source a flashloan for.
+    /// When empty, each cycle is only evaluated from its own profit token.
+    pub entry_tokens: HashSet<Address>,
+    pub divergence_check: Option<TradeDivergenceCheck>,
+    /// Gas prices every solution is costed at. The first is the base scenario, which decides
+    /// whether a path is reported and ranks the solutions.
+    pub gas_scenarios: Vec<GasScenario>,
+    /// Contract whose balance of a token bounds how much of it can be flash-borrowed, per
+    /// token. Profit tokens without a source are only bounded by `max_input_wei`.
+    pub flashloan_sources: HashMap<Address, Address>,
+    /// Largest input searched, in wei. Converted to each path's profit token, like
+    /// `min_net_profit_wei`.
+    pub max_input_wei: U256,
+    /// Search range and tolerance of the optimal input, per profit token.
+    pub optimizer: OptimizerConfig,
+    pub persistence_policy: PersistencePolicy,
+    /// Contract the executor approves to pull each hop's input. Approval gas is only costed
+    /// when this is set and the engine has an approval tracker.
+    pub approval_spender: Option<Address>,
+    /// Fill `approve_actions` on solutions that need approvals.
+    pub emit_approve_actions: bool,
+    /// Dexes paths may trade through. Paths with a pool of any other dex are skipped, and
+    /// their pools only snapshotted if an enabled path needs them.
+    pub enabled_dexes: HashSet<DexKind>,
+    /// Gas of each hop, by pool type. A path is charged the sum of its hops' gas plus
+    /// `gas_overhead_units`.
+    pub swap_gas_costs: SwapGasCosts,
+    pub gas_overhead_units: u64,
+    /// Replays every solution with `eth_simulateV1` before reporting it, and what to do with
+    /// those that fail. Only evaluations of a given block are verified.
+    pub verification: Option<VerificationPolicy>,
+    /// Largest price impact, in bps, any hop of a reported solution may have. Hops that move
+    /// their pool this much rarely survive competition for the trade.
+    pub max_hop_impact_bps: Option<u64>,
+    /// Wall-clock time an evaluation may take, counted from its start. Paths are evaluated
+    /// in [`PathPriority`] order until it runs out, and the rest skipped. At least one path
+    /// is always evaluated.
+    pub evaluation_budget: Option<Duration>,
+    /// Oldest a pool's external price oracle may be, in seconds before the snapshot's
+    /// block, for paths through it to be evaluated. Pools without an oracle timestamp are
+    /// never rejected.
+    pub max_oracle_age_secs: Option<u64>,
+    /// Discards the cached states of the evaluated pools older than its horizon, checked
+    /// after each evaluation of a given block.
+    pub cache_pruning: Option<CachePruning>,
+}
+
+impl Default for EngineConfig {
+    fn default() -> Self {
+        Self {
+            min_net_profit_wei: optimizer::MIN_NET_PROFIT_THRESHOLD,
+            entry_tokens: HashSet::new(),
+            divergence_check: None,
+            gas_scenarios: vec![GasScenario::new("base", ScenarioGasPrice::Live)],
+            flashloan_sources: HashMap::from([(WETH_ADDRESS, BALANCER_V2_VAULT)]),
+            max_input_wei: U256::from(50) * optimizer::ETHER_SCALE,
+            optimizer: OptimizerConfig::default(),
+            persistence_policy: PersistencePolicy::default(),
+            approval_spender: None,
+            emit_approve_actions: false,
+            enabled_dexes: DexKind::ALL.into_iter().collect(),
+            swap_gas_costs: SwapGasCosts::default(),
+            gas_overhead_units: optimizer::GAS_OVERHEAD_UNITS,
+            verification: None,
+            max_hop_impact_bps: None,
+            evaluation_budget: None,
+            max_oracle_age_secs: Some(DEFAULT_MAX_ORACLE_AGE_SECS),
+            cache_pruning: Some(CachePruning::default()),
+        }
+    }
+}
+
+impl EngineConfig {
+    /// The defaults on `chain`: its wrapped native token is flash-borrowed from its Balancer
+    /// Vault, if it has one.
+    pub fn for_chain(chain: &ChainConfig) -> Self {
+        Self {
+            flashloan_sources: chain
+                .balancer_vault
+                .map(|vault| (chain.wrapped_native, vault))
+                .into_iter()
+                .collect(),
+            ..Self::default()
+        }
+    }
+}
 
 /// The main engine responsible for evaluating arbitrage opportunities.
 pub struct ArbitrageEngine<P: Provider + Send + Sync + 'static + ?Sized> {
     pub cache: Arc<ArbitrageCache<P>>,
     pub token_manager: Arc<TokenManager<P>>,
     pub provider: Arc<P>,
+    /// Receives each block's evaluation when exporting is enabled.
+    pub exporter: Option<BlockExporter>,
+    /// Receives each block's solutions when recording opportunities is enabled.
+    #[cfg(feature = "db")]
+    pub recorder: Option<OpportunityRecorder>,
+    /// Haircuts expected hop outputs by the prediction bias measured for their pool type.
+    pub calibration: Option<Arc<CalibrationTracker>>,
+    /// Allowances of the executor, to cost the approvals a path needs first.
+    pub approvals: Option<Arc<ApprovalTracker>>,
+    /// Values solutions in USD when set.
+    pub usd_price_feed: Option<Arc<dyn UsdPriceFeed>>,
+    /// Prices the gas of `ScenarioGasPrice::Live`, `eth_gasPrice` unless replaced.
+    pub gas_estimator: Arc<dyn GasEstimator>,
+    /// Batches the Curve pools' snapshot calls when set and Multicall3 is deployed.
+    pub multicall: Option<Arc<MulticallBatcher<P>>>,
+    /// Limits re-snapshotting to the pools it marked dirty when set.
+    pub state_updater: Option<Arc<StateUpdater>>,
+    pub config: EngineConfig,
+    /// How long each cycle has been profitable for, updated by every evaluation.
+    pub persistence: Arc<PersistenceTracker>,
+    /// Consecutive failures of each pool, and the pools quarantined for failing too often.
+    pub pool_health: Arc<PoolHealth>,
+    /// Snapshots of every evaluated block, shared by its paths and later evaluations of it.
+    pub snapshot_store: Arc<SnapshotStore>,
+    /// Order paths are evaluated in, from their recent profits and which pools changed.
+    pub path_priority: Arc<PathPriority>,
+    verifier: Arc<SolutionVerifier>,
+    last_stats: Arc<Mutex<EvaluationStats>>,
+    last_snapshots: Arc<Mutex<Arc<HashMap<Address, PoolSnapshot>>>>,
+    /// Block the pools' caches were last pruned at.
+    last_cache_prune: Arc<Mutex<Option<u64>>>,
 }
 
 impl<P: Provider + Send + Sync + 'static + ?Sized> ArbitrageEngine<P> {
@@ -25,78 +220,630 @@ impl<P: Provider + Send + Sync + 'static + ?Sized> ArbitrageEngine<P> {
         token_manager: Arc<TokenManager<P>>,
         provider: Arc<P>,
     ) -> Self {
-        Self { cache, token_manager, provider }
+        Self {
+            gas_estimator: Arc::new(LegacyGasPrice::new(provider.clone())),
+            config: EngineConfig::for_chain(token_manager.chain()),
+            cache,
+            token_manager,
+            provider,
+            exporter: None,
+            #[cfg(feature = "db")]
+            recorder: None,
+            calibration: None,
+            approvals: None,
+            usd_price_feed: None,
+            multicall: None,
+            state_updater: None,
+            persistence: Arc::default(),
+            pool_health: Arc::default(),
+            snapshot_store: Arc::default(),
+            path_priority: Arc::default(),
+            verifier: Arc::default(),
+            last_stats: Arc::default(),
+            last_snapshots: Arc::default(),
+            last_cache_prune: Arc::default(),
+        }
     }
 
-    async fn get_all_profit_token_conversion_rates(
+    /// An engine over a fixed pool list with no database or discovery: the pools' tokens are
+    /// cached in memory and every WETH cycle of up to `QUOTE_ONLY_MAX_HOPS` pools is cached.
+    pub async fn quote_only(
+        provider: Arc<P>,
+        pools: Vec<Arc<dyn LiquidityPool<P>>>,
+    ) -> Result<Self, ArbRsError> {
+        let chain_id = provider
+            .get_chain_id()
+            .await
+            .map_err(|e| ArbRsError::ProviderError(e.to_string()))?;
+        let token_manager = Arc::new(TokenManager::in_memory(provider.clone(), chain_id));
+        for token in pools.iter().flat_map(|pool| pool.get_all_tokens()) {
+            token_manager.insert_token(token);
+        }
+        let cache = Arc::new(ArbitrageCache::new());
+        cache
+            .rebuild_with_filter(
+                pools,
+                &token_manager,
+                QUOTE_ONLY_MAX_HOPS,
+                &MinLiquidityFilter::default(),
+            )
+            .await;
+        Ok(Self::new(cache, token_manager, provider))
+    }
+
+    /// Evaluates every cycle from each of its tokens in `entry_tokens`, keeping the most
+    /// profitable entry per cycle.
+    pub fn with_entry_tokens(mut self, entry_tokens: impl IntoIterator<Item = Address>) -> Self {
+        self.config.entry_tokens = entry_tokens.into_iter().collect();
+        self
+    }
+
+    /// Overrides the minimum net profit, in wei. Gas used to be undercounted by a factor of
+    /// 1e18, so the old behaviour roughly corresponds to `0.05 ETH` minus the typical gas cost.
+    pub fn with_min_net_profit(mut self, min_net_profit_wei: U256) -> Self {
+        self.config.min_net_profit_wei = min_net_profit_wei;
+        self
+    }
+
+    pub fn with_trade_divergence_check(mut self, check: TradeDivergenceCheck) -> Self {
+        self.config.divergence_check = Some(check);
+        self
+    }
+
+    /// Replaces the whole configuration, including anything set by the setters above.
+    pub fn with_config(mut self, config: EngineConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    pub fn with_optimizer_config(mut self, optimizer: OptimizerConfig) -> Self {
+        self.config.optimizer = optimizer;
+        self
+    }
+
+    pub fn with_calibration(mut self, calibration: Arc<CalibrationTracker>) -> Self {
+        self.calibration = Some(calibration);
+        self
+    }
+
+    /// Takes effect from the next evaluation, without touching the path cache.
+    pub fn set_enabled_dexes(&mut self, enabled_dexes: impl IntoIterator<Item = DexKind>) {
+        self.config.enabled_dexes = enabled_dexes.into_iter().collect();
+    }
+
+    pub fn with_approvals(mut self, approvals: Arc<ApprovalTracker>) -> Self {
+        self.approvals = Some(approvals);
+        self
+    }
+
+    pub fn with_usd_price_feed(mut self, usd_price_feed: Arc<dyn UsdPriceFeed>) -> Self {
+        self.usd_price_feed = Some(usd_price_feed);
+        self
+    }
+
+    pub fn with_gas_estimator(mut self, gas_estimator: Box<dyn GasEstimator>) -> Self {
+        self.gas_estimator = gas_estimator.into();
+        self
+    }
+
+    pub fn with_multicall(mut self, multicall: Arc<MulticallBatcher<P>>) -> Self {
+        self.multicall = Some(multicall);
+        self
+    }
+
+    /// Reuses the previous block's snapshots of the pools `state_updater` hasn't marked dirty.
+    /// It must be updated with each block before that block is evaluated.
+    pub fn with_state_updater(mut self, state_updater: Arc<StateUpdater>) -> Self {
+        self.state_updater = Some(state_updater);
+        self
+    }
+
+    pub fn with_pool_health(mut self, pool_health: Arc<PoolHealth>) -> Self {
+        self.pool_health = pool_health;
+        self
+    }
+
+    pub fn with_snapshot_store(mut self, snapshot_store: Arc<SnapshotStore>) -> Self {
+        self.snapshot_store = snapshot_store;
+        self
+    }
+
+    /// Pools whose paths are skipped for failing in too many evaluations in a row.
+    pub fn quarantined_pools(&self) -> HashSet<Address> {
+        self.pool_health.quarantined()
+    }
+
+    pub fn with_verification(mut self, policy: VerificationPolicy) -> Self {
+        self.config.verification = Some(policy);
+        self
+    }
+
+    /// Drops solutions with a hop whose price impact exceeds `max_hop_impact_bps`. See
+    /// [`DEFAULT_MAX_HOP_IMPACT_BPS`](crate::arbitrage::impact::DEFAULT_MAX_HOP_IMPACT_BPS).
+    pub fn with_max_hop_impact(mut self, max_hop_impact_bps: u64) -> Self {
+        self.config.max_hop_impact_bps = Some(max_hop_impact_bps);
+        self
+    }
+
+    /// Stops evaluating paths once an evaluation has run for `budget`, leaving the lowest
+    /// priority ones out. [`last_stats`](Self::last_stats) tells whether it did.
+    pub fn with_evaluation_budget(mut self, budget: Duration) -> Self {
+        self.config.evaluation_budget = Some(budget);
+        self
+    }
+
+    /// Skips paths through a pool whose oracle last updated more than `max_age_secs` before
+    /// the evaluated block, replacing [`DEFAULT_MAX_ORACLE_AGE_SECS`].
+    pub fn with_max_oracle_age(mut self, max_age_secs: u64) -> Self {
+        self.config.max_oracle_age_secs = Some(max_age_secs);
+        self
+    }
+
+    /// Prunes the pools' caches as `pruning` says instead of the default, or never if `None`.
+    pub fn with_cache_pruning(mut self, pruning: Option<CachePruning>) -> Self {
+        self.config.cache_pruning = pruning;
+        self
+    }
+
+    /// Writes the block exports and opportunity records still queued, then stops their
+    /// background tasks. Call once no more blocks will be evaluated.
+    pub async fn shutdown(&self) {
+        if let Some(exporter) = &self.exporter {
+            exporter.shutdown().await;
+        }
+        #[cfg(feature = "db")]
+        if let Some(recorder) = &self.recorder {
+            recorder.shutdown().await;
+        }
+    }
+
+    /// Drops what the pools of the cached paths, `state_updater` and the snapshot store hold
+    /// for `block_number` and later, once a reorg replaced those blocks, so the next evaluation reads them again.
+    pub async fn invalidate_from(&self, block_number: u64) {
+        let mut pools = HashMap::new();
+        for path in self.cache.paths.read().await.iter() {
+            for pool in path.get_pools() {
+                pools.insert(pool.address(), pool.clone());
+            }
+        }
+        join_all(pools.values().map(|pool| pool.invalidate_from(block_number))).await;
+        if let Some(updater) = &self.state_updater {
+            updater.invalidate_from(block_number);
+        }
+        self.snapshot_store.clear();
+        if let Ok(mut last_snapshots) = self.last_snapshots.lock() {
+            *last_snapshots = Arc::default();
+        }
+        tracing::info!(block_number, pools = pools.len(), "Invalidated state from reorged blocks.");
+    }
+
+    /// Evaluates one path at `block`, outside the cached paths and the block loop, for
+    /// debugging a path by hand. The pools at `pool_addrs` are looked up in `known_pools`,
+    /// chained from `token_in` back to it, snapshotted at `block` and traded `amount` of
+    /// `token_in` through. The optimizer then searches the path the way the engine would,
+    /// though flashloan liquidity isn't checked.
+    pub async fn evaluate_path_at_block(
+        &self,
+        pool_addrs: &[Address],
+        known_pools: &HashMap<Address, Arc<dyn LiquidityPool<P>>>,
+        token_in: Address,
+        amount: U256,
+        block: u64,
+    ) -> Result<PathEvaluation<P>, ArbRsError> {
+        let pools = pool_addrs
+            .iter()
+            .map(|address| known_pools.get(address).cloned().ok_or(ArbRsError::UnknownPool(*address)))
+            .collect::<Result<Vec<_>, _>>()?;
+        let cycle = ArbitrageCycle::new(cycle_path(pools, token_in)?);
+
+        let mut snapshots = HashMap::new();
+        for pool in &cycle.path.pools {
+            let snapshot = match self.snapshot_store.get(pool.address(), block) {
+                Some(snapshot) => snapshot,
+                None => {
+                    let snapshot = pool.get_snapshot(Some(block)).await?;
+                    self.snapshot_store.insert(pool.address(), block, snapshot.clone());
+                    snapshot
+                }
+            };
+            snapshots.insert(pool.address(), snapshot);
+        }
+        let profit_token = cycle.path.profit_token.clone();
+        let decimals = profit_token.decimals();
+        let path_pools: HashMap<Address, Arc<dyn LiquidityPool<P>>> = cycle
+            .path
+            .pools
+            .iter()
+            .map(|pool| (pool.address(), pool.clone()))
+            .collect();
+        let conversion_rate = self
+            .get_all_profit_token_conversion_rates(std::slice::from_ref(&profit_token), &path_pools, &snapshots)
+            .get(&profit_token.address())
+            .copied();
+
+        let mut hops = Vec::with_capacity(cycle.path.pools.len());
+        let mut current_amount = amount;
+        for (i, pool) in cycle.path.pools.iter().enumerate() {
+            let (hop_token_in, hop_token_out) = (&cycle.path.path[i], &cycle.path.path[i + 1]);
+            let snapshot = &snapshots[&pool.address()];
+            let amount_in = hop_token_in.received_amount(current_amount);
+            let amount_out = pool.calculate_tokens_out(hop_token_in, hop_token_out, amount_in, snapshot)?;
+            hops.push(HopEvaluation {
+                pool: pool.address(),
+                amount_in: TokenAmount::new(hop_token_in.clone(), amount_in),
+                amount_out: TokenAmount::new(hop_token_out.clone(), amount_out),
+                metrics: hop_metrics(pool.as_ref(), hop_token_in, hop_token_out, amount_in, amount_out, snapshot),
+            });
+            current_amount = amount_out;
+        }
+        let amount_out = cycle.calculate_out_amount(amount, &snapshots)?;
+
+        let gas_units = self.config.gas_overhead_units
+            + cycle.swap_gas_estimate(amount, &snapshots, &self.config.swap_gas_costs);
+        let l1_data_fee = self
+            .gas_estimator
+            .l1_data_fee(Some(block), estimated_tx_size(cycle.path.pools.len()))
+            .await?;
+        let gas_cost_wei = optimizer::gas_cost_wei(U256::from(gas_units), self.gas_price_at(Some(block), None).await?)
+            .saturating_add(l1_data_fee);
+        let gas_cost = conversion_rate.map(|rate| {
+            TokenAmount::new(profit_token.clone(), optimizer::wei_to_token_units(gas_cost_wei, rate, decimals))
+        });
+        let flashloan_fee = flashloan_fee(amount);
+        let signed = |amount: U256| I256::try_from(amount).unwrap_or(I256::MAX);
+        let gross_profit = signed(amount_out) - signed(amount);
+        let net_profit = gas_cost
+            .as_ref()
+            .map(|gas_cost| gross_profit - signed(flashloan_fee) - signed(gas_cost.raw));
+
+        let search = self
+            .config
+            .optimizer
+            .for_token(decimals, profit_token_reserve(&cycle, &snapshots));
+        let search_max = search.max_input.unwrap_or_default();
+        let search_bound = match conversion_rate {
+            Some(rate) => search_max.min(optimizer::wei_to_token_units(self.config.max_input_wei, rate, decimals)),
+            None => search_max,
+        };
+        let optimal_input = match cycle
+            .quote_path(&snapshots)
+            .and_then(|path| optimizer::find_optimal_input(&path, search.min_input.min(search_bound), search_bound, &search))
+        {
+                Ok(optimum) => {
+                    if optimum.multimodal {
+                        tracing::debug!(input = %optimum.input, "Profit has separate peaks over the search range; taking the best probe.");
+                    }
+                    Some((
+                        TokenAmount::new(profit_token.clone(), optimum.input),
+                        TokenAmount::new(profit_token.clone(), optimum.gross_profit),
+                    ))
+                }
+                Err(e) => {
+                    tracing::warn!("Optimizer failed for the evaluated path: {}", e);
+                    None
+                }
+            };
+
+        Ok(PathEvaluation {
+            block,
+            cycle,
+            amount_in: TokenAmount::new(profit_token.clone(), amount),
+            hops,
+            amount_out: TokenAmount::new(profit_token.clone(), amount_out),
+            gross_profit,
+            flashloan_fee: TokenAmount::new(profit_token, flashloan_fee),
+            gas_units,
+            gas_cost_wei,
+            gas_cost,
+            net_profit,
+            optimal_input,
+        })
+    }
+
+    /// Replays `solution` on top of `block`: its input is traded through every hop from a
+    /// funded executor, and the profit token it ends up with is read back.
+    pub async fn verify_solution(
         &self,
-        paths: &Vec<Arc<dyn Arbitrage<P>>>,
+        solution: &ArbitrageSolution<P>,
+        block: u64,
+    ) -> Result<VerifiedSolution, ArbRsError> {
+        let cycle = solution
+            .path
+            .as_any()
+            .downcast_ref::<ArbitrageCycle<P>>()
+            .ok_or_else(|| ArbRsError::CalculationError("Only cycles can be verified".to_string()))?;
+        self.verifier.verify(self.provider.as_ref(), cycle, solution.optimal_input.raw, block).await
+    }
+
+    /// Counters from the most recent evaluation.
+    pub fn last_stats(&self) -> EvaluationStats {
+        self.last_stats.lock().map(|stats| stats.clone()).unwrap_or_default()
+    }
+
+    /// Pool snapshots the most recent evaluation ran against, overrides included.
+    pub fn last_snapshots(&self) -> Arc<HashMap<Address, PoolSnapshot>> {
+        self.last_snapshots.lock().map(|snapshots| snapshots.clone()).unwrap_or_default()
+    }
+
+    /// Writes every evaluated block to `config.directory` from a background task.
+    /// Must be called from within a Tokio runtime.
+    pub fn with_export(mut self, config: ExportConfig) -> Self {
+        self.exporter = BlockExporter::spawn(config);
+        self
+    }
+
+    /// Saves every solution of each evaluated block to `db_manager` from a background task.
+    /// Must be called from within a Tokio runtime.
+    #[cfg(feature = "db")]
+    pub fn with_opportunity_recording(mut self, db_manager: Arc<DbManager>) -> Self {
+        self.recorder = Some(OpportunityRecorder::spawn(db_manager, DEFAULT_RECORDER_CAPACITY));
+        self
+    }
+
+    /// The price of 1 ETH in each profit token, in whole tokens scaled by 1e18, which gas
+    /// costs and thresholds in wei are converted with. Quoted from `snapshots` by
+    /// [`TvlEstimator::from_snapshots`], through the deepest pool of each token with the
+    /// chain's wrapped native token or via its stable reference token, so no extra calls are
+    /// made. Tokens neither route prices are left out.
+    pub fn get_all_profit_token_conversion_rates(
+        &self,
+        unique_profit_tokens: &[Arc<Token<P>>],
         all_pools: &HashMap<Address, Arc<dyn LiquidityPool<P>>>,
+        snapshots: &HashMap<Address, PoolSnapshot>,
     ) -> HashMap<Address, U256> {
-        let token_manager = self.token_manager.clone(); 
+        let estimator =
+            TvlEstimator::from_snapshots(self.token_manager.chain(), unique_profit_tokens, all_pools, snapshots);
+        unique_profit_tokens
+            .iter()
+            .filter_map(|token| Some((token.address(), estimator.rate(token.address())?)))
+            .collect()
+    }
 
-        let weth_token = match token_manager.get_token(WETH_ADDRESS).await {
-            Ok(t) => t,
-            Err(_) => return HashMap::new(),
-        };
+    /// Every token a path may be entered at, and so borrowed in: each cycle's profit token
+    /// plus its tokens in `entry_tokens`.
+    fn get_unique_profit_tokens(&self, paths: &[Arc<dyn Arbitrage<P>>]) -> Vec<Arc<Token<P>>> {
+        let mut seen = HashSet::new();
+        paths.iter()
+            .filter_map(|path| path.as_any().downcast_ref::<ArbitrageCycle<P>>())
+            .flat_map(|cycle| {
+                std::iter::once(cycle.path.profit_token.clone()).chain(
+                    cycle.path.path.iter()
+                        .filter(|token| self.config.entry_tokens.contains(&token.address()))
+                        .cloned(),
+                )
+            })
+            .filter(|token| seen.insert(token.address()))
+            .collect()
+    }
+
+    /// Balance of each profit token held by its flashloan source at `block_number`. Tokens
+    /// without a source, or whose balance can't be fetched, are left out and so uncapped.
+    async fn get_flashloan_liquidity(
+        &self,
+        unique_profit_tokens: &[Arc<Token<P>>],
+        block_number: Option<u64>,
+    ) -> HashMap<Address, U256> {
+        let balance_futs = unique_profit_tokens.iter().filter_map(|token| {
+            let source = *self.config.flashloan_sources.get(&token.address())?;
+            Some(async move { (token.address(), token.get_balance(source, block_number).await) })
+        });
 
-        let unique_profit_tokens: HashSet<Arc<Token<P>>> = paths.iter()
+        let mut liquidity = HashMap::new();
+        for (token, result) in join_all(balance_futs).await {
+            match result {
+                Ok(balance) => {
+                    liquidity.insert(token, balance);
+                }
+                Err(e) => tracing::warn!(?token, "Failed to fetch flashloan liquidity: {:?}", e),
+            }
+        }
+        liquidity
+    }
+
+    /// The executor's allowance to the configured spender of every token a path spends, or
+    /// `None` when approvals aren't costed.
+    async fn get_allowances(
+        &self,
+        paths: &[Arc<dyn Arbitrage<P>>],
+        block_number: Option<u64>,
+    ) -> Option<(Address, HashMap<Address, U256>)> {
+        let (approvals, spender) = (self.approvals.as_ref()?, self.config.approval_spender?);
+        let mut seen = HashSet::new();
+        let tokens: Vec<&Token<P>> = paths
+            .iter()
             .filter_map(|path| path.as_any().downcast_ref::<ArbitrageCycle<P>>())
-            .map(|cycle| cycle.path.profit_token.clone())
+            .flat_map(|cycle| cycle.path.path.iter())
+            .filter(|token| seen.insert(token.address()))
+            .map(|token| token.as_ref())
             .collect();
-        
-        let mut rate_map: HashMap<Address, U256> = HashMap::new();
+        Some((spender, approvals.allowances(&tokens, spender, block_number).await))
+    }
 
-        let rate_futs = unique_profit_tokens.into_iter().map(|profit_token| {
-            let pools_ref = all_pools.clone();
-            let weth_token_clone = weth_token.clone();
-            
-            async move {
-                if profit_token.address() == WETH_ADDRESS {
-                    return (profit_token.address(), Ok(U256::from_limbs([1_000_000_000_000_000_000, 0, 0, 0])));
-                }
+    /// The ETH/USD price at `block_number`, or `None` without a feed or when it fails.
+    async fn get_eth_usd_price(&self, block_number: Option<u64>) -> Option<U256> {
+        let feed = self.usd_price_feed.as_ref()?;
+        feed.eth_usd_price(block_number)
+            .await
+            .inspect_err(|e| tracing::warn!("Failed to fetch the ETH/USD price: {:?}", e))
+            .ok()
+    }
 
-                if let Some((_, pool)) = pools_ref.iter().find(|(_, p)| {
-                    let tokens: Vec<Address> = p.get_all_tokens().iter().map(|t| t.address()).collect();
-                    tokens.contains(&WETH_ADDRESS) && tokens.contains(&profit_token.address())
-                }) {
-                    let price_f64 = pool.nominal_price(&weth_token_clone, &profit_token).await.unwrap_or(0.0);
+    /// Snapshots the Curve pools that support it through the multicall batcher. Pools left
+    /// out, or all of them if the batch fails, are for `get_snapshot` to fetch.
+    async fn get_batched_snapshots(
+        &self,
+        pools: &HashMap<Address, Arc<dyn LiquidityPool<P>>>,
+        block_number: Option<u64>,
+    ) -> HashMap<Address, PoolSnapshot> {
+        let Some(multicall) = &self.multicall else {
+            return HashMap::new();
+        };
+        match multicall.is_deployed().await {
+            Ok(true) => {}
+            Ok(false) => return HashMap::new(),
+            Err(e) => {
+                tracing::warn!("Failed to check for Multicall3: {:?}", e);
+                return HashMap::new();
+            }
+        }
 
-                    let price_u256_scaled = U256::from((price_f64 * 1e18).round() as u128);
-                    
-                    return (profit_token.address(), Ok(price_u256_scaled));
-                } else {
-                    return (profit_token.address(), Err(ArbRsError::CalculationError("No liquid WETH pool found for conversion".to_string())));
-                }
+        let curve_pools: Vec<&CurveStableswapPool<P>> = pools
+            .values()
+            .filter_map(|pool| pool.as_any().downcast_ref::<CurveStableswapPool<P>>())
+            .collect();
+        match batch_snapshots(multicall, &curve_pools, block_number).await {
+            Ok(results) => results
+                .into_iter()
+                .filter_map(|(address, result)| match result {
+                    Ok(snapshot) => Some((address, snapshot)),
+                    Err(e) => {
+                        tracing::debug!(?address, "Batched snapshot failed, fetching it alone: {}", e);
+                        None
+                    }
+                })
+                .collect(),
+            Err(e) => {
+                tracing::warn!("Multicall snapshot batch failed: {:?}", e);
+                HashMap::new()
             }
-        });
+        }
+    }
 
-        for (token_addr, result) in join_all(rate_futs).await {
-            if let Ok(rate_u256) = result {
-                rate_map.insert(token_addr, rate_u256);
+    /// Discards what `pools` cached before `pruning.horizon_blocks` ahead of `block`, once
+    /// `pruning.every_blocks` have passed since the last prune, and records what they still
+    /// hold by pool kind.
+    async fn prune_pool_caches(
+        &self,
+        block: u64,
+        pruning: CachePruning,
+        pools: &HashMap<Address, Arc<dyn LiquidityPool<P>>>,
+    ) {
+        {
+            let Ok(mut last_prune) = self.last_cache_prune.lock() else {
+                return;
+            };
+            if last_prune.is_some_and(|last| block < last.saturating_add(pruning.every_blocks)) {
+                return;
             }
+            *last_prune = Some(block);
+        }
+        let oldest_kept = block.saturating_sub(pruning.horizon_blocks);
+        join_all(pools.values().map(|pool| pool.discard_states_before_block(oldest_kept))).await;
+
+        let stats = join_all(pools.values().map(|pool| async move { (pool.dex_kind(), pool.cache_stats().await) })).await;
+        let mut entries_by_kind: HashMap<Option<DexKind>, usize> = HashMap::new();
+        for (dex, stats) in stats {
+            *entries_by_kind.entry(dex).or_default() += stats.entries;
         }
-        rate_map
+        for (dex, entries) in entries_by_kind {
+            crate::metrics::record_cache_entries(dex, entries);
+        }
+        tracing::debug!(block, oldest_kept, "Pruned the caches of {} pools.", pools.len());
+    }
+
+    async fn get_block_timestamp(&self, block_number: u64) -> Result<u64, ArbRsError> {
+        let block = self
+            .provider
+            .get_block_by_number(block_number.into())
+            .await?
+            .ok_or_else(|| ArbRsError::ProviderError("Block not found".to_string()))?;
+        Ok(block.header.timestamp)
     }
 
-    async fn get_live_gas_price(&self) -> Result<U256, ArbRsError> {
-        let gas_price_raw = self.provider.get_gas_price().await?;
-        let gas_price_u256: U256 = U256::from(gas_price_raw); 
+    /// The L1 data fee of executing each of `paths` at `block_number`, by its number of hops.
+    /// Failed quotes are logged and leave that length out, so its paths pay none.
+    async fn l1_data_fees(
+        &self,
+        paths: &[Arc<dyn Arbitrage<P>>],
+        block_number: Option<u64>,
+    ) -> HashMap<usize, U256> {
+        let hop_counts: HashSet<usize> = paths.iter().map(|path| path.get_pools().len()).collect();
+        let fees = join_all(hop_counts.into_iter().map(|hops| async move {
+            let fee = self.gas_estimator.l1_data_fee(block_number, estimated_tx_size(hops)).await;
+            (hops, fee)
+        }))
+        .await;
+        fees.into_iter()
+            .filter_map(|(hops, fee)| match fee {
+                Ok(fee) => Some((hops, fee)),
+                Err(e) => {
+                    tracing::warn!(hops, "Failed to quote the L1 data fee: {:?}", e);
+                    None
+                }
+            })
+            .collect()
+    }
 
-        Ok(gas_price_u256)
+    /// The gas price an evaluation of `block_number` is costed at, per `gas_estimator`.
+    pub async fn gas_price_at(
+        &self,
+        block_number: Option<u64>,
+        header: Option<&Header>,
+    ) -> Result<U256, ArbRsError> {
+        self.gas_estimator.gas_price(block_number, header).await
     }
 
     pub async fn find_opportunities(
         &self,
         block_number: Option<u64>,
     ) -> Vec<ArbitrageSolution<P>> {
-        let paths_read_guard = self.cache.paths.read().await;
-        let paths: Arc<Vec<Arc<dyn Arbitrage<P>>>> = Arc::new(paths_read_guard.clone());
+        self.find_opportunities_with_overrides(block_number, HashMap::new()).await
+    }
+
+    /// Evaluates the block of `header`, whose base fee the gas estimator can use without
+    /// fetching the block again.
+    pub async fn find_opportunities_at_header(&self, header: &Header) -> Vec<ArbitrageSolution<P>> {
+        self.evaluate(Some(header.number), Some(header), HashMap::new()).await
+    }
+
+    /// Evaluates all paths with the given pool snapshots substituted for the fetched ones.
+    /// Used for what-if evaluation, e.g. against the predicted effect of pending transactions.
+    pub async fn find_opportunities_with_overrides(
+        &self,
+        block_number: Option<u64>,
+        overrides: HashMap<Address, PoolSnapshot>,
+    ) -> Vec<ArbitrageSolution<P>> {
+        self.evaluate(block_number, None, overrides).await
+    }
+
+    async fn evaluate(
+        &self,
+        block_number: Option<u64>,
+        header: Option<&Header>,
+        overrides: HashMap<Address, PoolSnapshot>,
+    ) -> Vec<ArbitrageSolution<P>> {
+        let started = Instant::now();
+        let cached_paths = self.cache.paths.read().await.len();
         
-        if paths.is_empty() {
+        if cached_paths == 0 {
             return Vec::new();
         }
 
+        // A pool whose snapshot is given is evaluated whatever its health.
+        let overridden: HashSet<Address> = overrides.keys().copied().collect();
+        let quarantined: HashSet<Address> = self
+            .pool_health
+            .start_evaluation()
+            .into_iter()
+            .filter(|pool| !overridden.contains(pool))
+            .collect();
+        let healthy_paths = self.cache.paths_excluding(&quarantined).await;
+        let quarantine_skips = cached_paths.saturating_sub(healthy_paths.len());
+
+        let enabled_dexes = &self.config.enabled_dexes;
+        let paths: Arc<Vec<Arc<dyn Arbitrage<P>>>> = Arc::new(
+            healthy_paths
+                .iter()
+                .filter(|path| {
+                    path.get_pools()
+                        .iter()
+                        .all(|pool| pool.dex_kind().is_none_or(|dex| enabled_dexes.contains(&dex)))
+                })
+                .cloned()
+                .collect(),
+        );
+        let dex_skips = healthy_paths.len() - paths.len();
+
         let mut unique_pools = HashMap::new();
         for path in paths.iter() {
             for pool in path.get_pools() {
@@ -106,97 +853,206 @@ impl<P: Provider + Send + Sync + 'static + ?Sized> ArbitrageEngine<P> {
 
         tracing::debug!("Found {} unique pools to snapshot.", unique_pools.len());
 
-        let snapshot_futs = unique_pools
+        // Snapshots already taken at this block come from the store, then those the updater
+        // knows still hold from the last one. Both are shared with later evaluations.
+        let mut snapshots = match block_number {
+            Some(block) => self.snapshot_store.snapshots_at(block, unique_pools.keys()),
+            None => HashMap::new(),
+        };
+        let mut reused_snapshots = 0;
+        if let (Some(updater), Some(block)) = (&self.state_updater, block_number) {
+            let reused = updater.reusable_snapshots(
+                block,
+                unique_pools.keys().filter(|address| !snapshots.contains_key(*address)),
+            );
+            for (address, snapshot) in &reused {
+                self.snapshot_store.alias(*address, block, snapshot.clone());
+            }
+            reused_snapshots = reused.len();
+            snapshots.extend(reused);
+        }
+        let stale_pools: HashMap<Address, Arc<dyn LiquidityPool<P>>> = unique_pools
+            .iter()
+            .filter(|(address, _)| !snapshots.contains_key(*address))
+            .map(|(address, pool)| (*address, pool.clone()))
+            .collect();
+
+        let mut fetched = self.get_batched_snapshots(&stale_pools, block_number).await;
+        let snapshot_futs = stale_pools
             .values()
+            .filter(|pool| !fetched.contains_key(&pool.address()))
             .map(|pool| async { (pool.address(), pool.get_snapshot(block_number).await) });
 
         let snapshot_results = join_all(snapshot_futs).await;
 
-        let mut snapshots = HashMap::new();
         for (address, result) in snapshot_results {
             match result {
                 Ok(snapshot) => {
-                    snapshots.insert(address, snapshot);
+                    fetched.insert(address, snapshot);
                 }
-                Err(e) => tracing::warn!(?address, "Failed to get pool snapshot: {:?}", e),
+                Err(e) => tracing::warn!(?address, "Failed to get pool snapshot: {}", e),
             }
         }
+        if let Some(block) = block_number {
+            for (address, snapshot) in &fetched {
+                self.snapshot_store.insert(*address, block, snapshot.clone());
+            }
+        }
+        snapshots.extend(fetched);
+        let failed_snapshots = unique_pools.len() - snapshots.len();
+        if let (Some(updater), Some(block)) = (&self.state_updater, block_number) {
+            updater.store_snapshots(block, &snapshots);
+        }
+        let snapshotted: HashSet<Address> = snapshots.keys().copied().collect();
+        snapshots.extend(overrides);
 
-        let live_gas_price = self.get_live_gas_price().await.unwrap_or_else(|e| {
-            tracing::warn!("Failed to fetch live gas price: {:?}", e);
+        // Pools not served from this block's store or the updater's cache have changed, as
+        // far as the engine knows, and so have those given in place of their state.
+        let changed_pools: HashSet<Address> = stale_pools.keys().chain(&overridden).copied().collect();
+        let order = self.path_priority.order(&paths, &changed_pools);
+        let evaluation_budget = self.config.evaluation_budget;
+        let deadline = evaluation_budget.map(|budget| started + budget);
+
+        let live_gas_price = self.gas_price_at(block_number, header).await.unwrap_or_else(|e| {
+            tracing::warn!("Failed to fetch the gas price: {:?}", e);
             U256::from_limbs([20_000_000_000, 0, 0, 0])
         });
 
-        let path_conversion_rates_map = self.get_all_profit_token_conversion_rates(&paths, &unique_pools).await;
+        let l1_data_fees = self.l1_data_fees(&paths, block_number).await;
+        let unique_profit_tokens = self.get_unique_profit_tokens(&paths);
+        let path_conversion_rates_map =
+            self.get_all_profit_token_conversion_rates(&unique_profit_tokens, &unique_pools, &snapshots);
+        let flashloan_liquidity = self.get_flashloan_liquidity(&unique_profit_tokens, block_number).await;
+        let approval_allowances = self.get_allowances(&paths, block_number).await;
+        let emit_approve_actions = self.config.emit_approve_actions;
+
+        let usd_conversion_rates = path_conversion_rates_map.clone();
 
         let paths_clone = paths.clone();
         let snapshots_clone = snapshots;
         let path_conversion_rates_clone = path_conversion_rates_map;
+        let entry_tokens = self.config.entry_tokens.clone();
+        let min_net_profit_wei = self.config.min_net_profit_wei;
+        let divergence_check = self.config.divergence_check;
+        let max_input_wei = self.config.max_input_wei;
+        let optimizer_config = self.config.optimizer;
+        let swap_gas_costs = self.config.swap_gas_costs;
+        let gas_overhead_units = self.config.gas_overhead_units;
+        let max_hop_impact_bps = self.config.max_hop_impact_bps;
+        let max_oracle_age_secs = self.config.max_oracle_age_secs;
+        let gas_scenarios = if self.config.gas_scenarios.is_empty() {
+            EngineConfig::default().gas_scenarios
+        } else {
+            self.config.gas_scenarios.clone()
+        };
+        let calibration_haircuts = self
+            .calibration
+            .as_ref()
+            .map(|calibration| calibration.haircuts())
+            .unwrap_or_default();
 
+        let evaluation_order = order.clone();
         let task = tokio::task::spawn_blocking(move || {
-            let mut opportunities = Vec::new();
+            // Rotations of one cycle can all be profitable; only the best entry is reported.
+            let mut best_per_cycle: HashMap<CycleId, ArbitrageSolution<P>> = HashMap::new();
 
-            fn build_swap_actions<P>(
-                path: &Arc<dyn Arbitrage<P>>,
+            /// The swaps trading `start_amount` along `path`, with each hop's exact output.
+            fn build_swap_actions(
+                path: &QuotePath,
                 start_amount: U256,
-                snapshots: &HashMap<Address, PoolSnapshot>,
-            ) -> Result<Vec<SwapAction<P>>, ArbRsError>
-            where
-                P: Provider + Send + Sync + 'static + ?Sized,
-            {
-                let cycle = path.as_any().downcast_ref::<ArbitrageCycle<P>>().unwrap();
+                haircuts_bps: &[u64],
+            ) -> Result<(Vec<SwapAction>, Vec<U256>), ArbRsError> {
                 let mut current_amount = start_amount;
-                let mut swap_actions: Vec<SwapAction<P>> = Vec::with_capacity(cycle.path.pools.len());
+                let mut swap_actions: Vec<SwapAction> = Vec::with_capacity(path.pools.len());
+                let mut exact_amounts_out = Vec::with_capacity(path.pools.len());
 
                 const SLIPPAGE_BPS: U256 = U256::from_limbs([5, 0, 0, 0]); 
                 const BPS_DENOMINATOR: U256 = U256::from_limbs([10_000, 0, 0, 0]);
 
-                for i in 0..cycle.path.pools.len() {
-                    let pool = &cycle.path.pools[i];
-                    let token_in = &cycle.path.path[i];
-                    let token_out = &cycle.path.path[i + 1];
+                for (i, pool) in path.pools.iter().enumerate() {
+                    let token_in = &path.tokens[i];
+                    let token_out = &path.tokens[i + 1];
 
-                    let amount_in_for_hop = current_amount;
+                    // The pool receives the amount net of the token's transfer tax.
+                    let amount_in_for_hop = received_amount(current_amount, path.transfer_tax_bps[i]);
 
-                    let exact_amount_out = pool.calculate_tokens_out(
-                        token_in, 
-                        token_out, 
-                        amount_in_for_hop, 
-                        snapshots.get(&pool.address()).unwrap()
-                    )?;
+                    let exact_amount_out =
+                        pool.calculate_out(token_in.address, token_out.address, amount_in_for_hop)?;
 
                     if exact_amount_out.is_zero() {
                         return Err(ArbRsError::CalculationError("Zero output encountered in hop".to_string()));
                     }
 
-                    let min_amount_out = exact_amount_out
+                    let expected_amount_out =
+                        apply_haircut(exact_amount_out, haircuts_bps.get(i).copied().unwrap_or(0));
+                    let min_amount_out = expected_amount_out
                         .checked_mul(BPS_DENOMINATOR.saturating_sub(SLIPPAGE_BPS))
                         .unwrap_or_default()
                         .checked_div(BPS_DENOMINATOR)
                         .unwrap_or_default();
 
+                    let kind = match pool {
+                        QuotePool::WrappedNative(wrapper) if token_in.address == wrapper.native => SwapKind::Wrap,
+                        QuotePool::WrappedNative(_) => SwapKind::Unwrap,
+                        QuotePool::CurveUnderlying(_) => SwapKind::SwapUnderlying,
+                        _ => SwapKind::Swap,
+                    };
                     swap_actions.push(SwapAction {
                         pool_address: pool.address(),
                         token_in: token_in.clone(),
                         token_out: token_out.clone(),
                         amount_in: amount_in_for_hop,
                         min_amount_out,
+                        kind,
                     });
+                    exact_amounts_out.push(exact_amount_out);
 
-                    current_amount = exact_amount_out;
+                    current_amount = expected_amount_out;
                 }
 
-                Ok(swap_actions)
+                Ok((swap_actions, exact_amounts_out))
             }
 
-            const WETH_ADDRESS: Address = address!("C02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2"); 
-            const ETHER_SCALE: U256 = U256::from_limbs([1_000_000_000_000_000_000, 0, 0, 0]);
-            const BPS_DENOMINATOR: U256 = U256::from_limbs([10_000, 0, 0, 0]);
-            const FLASHLOAN_FEE_BPS: U256 = U256::from_limbs([9, 0, 0, 0]); 
-            const ESTIMATED_GAS_UNITS: U256 = U256::from_limbs([700_000, 0, 0, 0]);
-            const MIN_NET_PROFIT_THRESHOLD: U256 = U256::from_limbs([50_000_000_000_000_000, 0, 0, 0]);
+            // In wei, converted to each path's profit token along with the input cap.
+            const DUST_INPUT_WEI: U256 = U256::from_limbs([1_000_000_000_000_000, 0, 0, 0]);
+
+            let scenario_gas_prices: Vec<(String, U256)> = gas_scenarios
+                .iter()
+                .map(|scenario| (scenario.label.clone(), scenario.resolve(live_gas_price)))
+                .collect();
+            // The L1 data fee is paid on top of execution gas, whatever the scenario's price.
+            let scenario_gas_costs_wei = |gas_units: U256, l1_data_fee: U256| -> Vec<(String, U256)> {
+                scenario_gas_prices
+                    .iter()
+                    .map(|(label, gas_price)| {
+                        (label.clone(), optimizer::gas_cost_wei(gas_units, *gas_price).saturating_add(l1_data_fee))
+                    })
+                    .collect()
+            };
+
+            let mut paused_pool_skips = 0;
+            let mut paused_pools = HashSet::new();
+            let mut divergence_rejects = 0;
+            let mut stale_oracle_rejects = 0;
+            let mut liquidity_skips = 0;
+            let mut unpriced_skips = 0;
+            let mut impact_rejects = 0;
+            let mut evaluated_paths = 0;
+            let mut budget_skips = 0;
+            let candidates = evaluation_order.iter().enumerate().flat_map(|(rank, &i)| {
+                entry_candidates(&paths_clone[i], &entry_tokens)
+                    .into_iter()
+                    .map(move |candidate| (rank, i, candidate))
+            });
 
-            for (i, path) in paths_clone.iter().enumerate() {
+            for (rank, i, path) in candidates {
+                if rank >= evaluated_paths {
+                    if rank > 0 && deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                        budget_skips = evaluation_order.len() - rank;
+                        break;
+                    }
+                    evaluated_paths = rank + 1;
+                }
                 if !path
                     .get_involved_pools()
                     .iter()
@@ -211,134 +1067,508 @@ impl<P: Provider + Send + Sync + 'static + ?Sized> ArbitrageEngine<P> {
                         tracing::trace!("Path #{} failed viability check.", i);
                         continue;
                     }
+                    Err(ArbRsError::PoolPaused(pool)) => {
+                        tracing::trace!(?pool, "Path #{} skipped, pool is paused.", i);
+                        paused_pool_skips += 1;
+                        paused_pools.insert(pool);
+                        continue;
+                    }
                     Err(e) => {
-                        tracing::warn!("Viability check failed for path #{}: {:?}", i, e);
+                        tracing::warn!("Viability check failed for path #{}: {}", i, e);
                         continue;
                     }
                 }
             
                 let cycle = path.as_any().downcast_ref::<ArbitrageCycle<P>>().unwrap();
+                let divergent_pools = match divergence_check {
+                    Some(check) => cycle.divergent_pools(&snapshots_clone, check.threshold_bps),
+                    None => Vec::new(),
+                };
+                if divergence_check.is_some_and(|check| check.reject) && !divergent_pools.is_empty() {
+                    tracing::trace!(pools = ?divergent_pools, "Path #{} skipped, last trades diverge from state.", i);
+                    divergence_rejects += 1;
+                    continue;
+                }
+                if let Some(Err(e)) = max_oracle_age_secs.map(|max_age| cycle.check_oracle_ages(&snapshots_clone, max_age)) {
+                    tracing::debug!("Path #{} skipped: {}", i, e);
+                    stale_oracle_rejects += 1;
+                    continue;
+                }
                 let profit_token_address = cycle.path.profit_token.address();
+                let profit_token_decimals = cycle.path.profit_token.decimals();
+                // Gas and the profit floor are in wei, so the profit token needs a rate to WETH.
+                // WETH itself is always in the map at exactly 1e18.
+                let Some(conversion_rate_scaled) = path_conversion_rates_clone.get(&profit_token_address).copied() else {
+                    tracing::trace!(token = ?profit_token_address, "Path #{} skipped, its profit token has no WETH rate.", i);
+                    unpriced_skips += 1;
+                    continue;
+                };
 
-                let gas_cost_weth = ESTIMATED_GAS_UNITS 
-                    .checked_mul(live_gas_price)
-                    .unwrap_or_default()
-                    .checked_div(ETHER_SCALE) 
-                    .unwrap_or_default();
+                let min_net_profit = optimizer::wei_to_token_units(
+                    min_net_profit_wei,
+                    conversion_rate_scaled,
+                    profit_token_decimals,
+                );
+                let [max_input, dust_input] = [max_input_wei, DUST_INPUT_WEI].map(|amount_wei| {
+                    optimizer::wei_to_token_units(amount_wei, conversion_rate_scaled, profit_token_decimals)
+                });
+                let search = optimizer_config
+                    .for_token(profit_token_decimals, profit_token_reserve(cycle, &snapshots_clone));
 
-                let gas_cost_in_profit_token = if profit_token_address == WETH_ADDRESS {
-                    gas_cost_weth
-                } else {
-                    let conversion_rate_scaled = path_conversion_rates_clone
-                        .get(&profit_token_address)
-                        .copied()
-                        .unwrap_or(ETHER_SCALE);
-
-                    gas_cost_weth
-                        .widening_mul(conversion_rate_scaled)
-                        .checked_div(ETHER_SCALE.into())
-                        .unwrap_or_default().to()
-                };
+                let liquidity = flashloan_liquidity.get(&profit_token_address).copied();
+                let input_bound = liquidity.map_or(max_input, |liquidity| liquidity.min(max_input));
+                if input_bound < dust_input {
+                    tracing::trace!(?liquidity, "Path #{} skipped, input bound is below the dust floor.", i);
+                    liquidity_skips += 1;
+                    continue;
+                }
 
-                let optimal_result_input = match optimizer::find_optimal_input(
-                    &path,
-                    U256::from(10).pow(U256::from(17)), 
-                    U256::from(50) * ETHER_SCALE,      
-                    &snapshots_clone,
+                let quote_path = match cycle.quote_path(&snapshots_clone) {
+                    Ok(quote_path) => quote_path,
+                    Err(e) => {
+                        tracing::warn!("No quoters for path #{}: {}", i, e);
+                        continue;
+                    }
+                };
+                let search_bound = search.max_input.map_or(input_bound, |max| max.min(input_bound));
+                let (optimal_result_input, optimal_gross_profit) = match optimizer::find_optimal_input(
+                    &quote_path,
+                    search.min_input.min(search_bound),
+                    search_bound,
+                    &search,
                 ) {
-                    Ok((opt_input, _)) => opt_input,
+                    Ok(optimum) => {
+                        if optimum.multimodal {
+                            tracing::debug!(input = %optimum.input, "Path #{} has separate profit peaks over the search range; taking the best probe.", i);
+                        }
+                        (optimum.input, optimum.gross_profit)
+                    }
+                    Err(ArbRsError::NoLiquidity { pool }) => {
+                        tracing::trace!(?pool, "Path #{} skipped, a pool has no liquidity.", i);
+                        continue;
+                    }
                     Err(e) => {
-                        tracing::warn!("Optimizer failed for path #{}: {:?}", i, e);
+                        tracing::warn!("Optimizer failed for path #{}: {}", i, e);
                         continue;
                     }
                 };
 
+                let l1_data_fee = l1_data_fees.get(&cycle.path.pools.len()).copied().unwrap_or_default();
+                // V3 hops cost more the more ticks they cross, so gas follows the input.
+                let gas_units_for = |amount_in: U256| {
+                    U256::from(gas_overhead_units + cycle.swap_gas_estimate(amount_in, &snapshots_clone, &swap_gas_costs))
+                };
+                let gas_cost_in_profit_token = optimizer::wei_to_token_units(
+                    scenario_gas_costs_wei(gas_units_for(optimal_result_input), l1_data_fee)[0].1,
+                    conversion_rate_scaled,
+                    profit_token_decimals,
+                );
+
                 let max_capacity_input = match optimizer::find_max_capacity(
-                    &path,
+                    &quote_path,
                     optimal_result_input, 
-                    U256::from(50) * ETHER_SCALE,
-                    &snapshots_clone,
-                    MIN_NET_PROFIT_THRESHOLD,
+                    search_bound,
+                    min_net_profit,
                     gas_cost_in_profit_token,
                 ) {
                     Ok(cap_input) => cap_input,
                     Err(e) => {
-                        tracing::warn!("Capacity search failed for path #{}: {:?}", i, e);
+                        tracing::warn!("Capacity search failed for path #{}: {}", i, e);
                         continue;
                     }
                 };
                 
-                if max_capacity_input.is_zero() || max_capacity_input < U256::from(10).pow(U256::from(15)) {
+                if max_capacity_input.is_zero() || max_capacity_input < dust_input {
                     continue;
                 }
 
                 let final_optimal_input = max_capacity_input;
+                let bound_by = if final_optimal_input < input_bound {
+                    InputBound::PoolDepth
+                } else if liquidity.is_some_and(|liquidity| liquidity <= max_input) {
+                    InputBound::Liquidity
+                } else {
+                    InputBound::ConfigCap
+                };
 
-                let gross_profit = path
-                    .calculate_out_amount(final_optimal_input, &snapshots_clone)
-                    .unwrap_or_default()
-                    .saturating_sub(final_optimal_input);
+                let haircuts_bps = hop_haircuts(&cycle.path.pools, &calibration_haircuts);
+                // The optimizer's profit holds when the input stayed put and nothing is haircut.
+                let gross_profit = if final_optimal_input == optimal_result_input
+                    && haircuts_bps.iter().all(|haircut| *haircut == 0)
+                {
+                    optimal_gross_profit
+                } else {
+                    quote_path
+                        .calculate_out_amount_with_haircuts(final_optimal_input, &haircuts_bps)
+                        .unwrap_or_default()
+                        .saturating_sub(final_optimal_input)
+                };
 
-                let flashloan_fee = final_optimal_input 
-                    .checked_mul(FLASHLOAN_FEE_BPS)
-                    .unwrap_or_default()
-                    .checked_div(BPS_DENOMINATOR)
-                    .unwrap_or_default();
-                
-                let total_cost = flashloan_fee.saturating_add(gas_cost_in_profit_token);
-                let net_profit = gross_profit.saturating_sub(total_cost);
-
-                if net_profit >= MIN_NET_PROFIT_THRESHOLD { 
-                    let swap_actions = match build_swap_actions(
-                        &path,
-                        final_optimal_input,
-                        &snapshots_clone,
-                    ) {
-                        Ok(actions) => actions,
-                        Err(e) => {
-                            tracing::warn!("Failed to finalize swap actions for path #{}: {:?}", i, e);
+                let flashloan_fee = flashloan_fee(final_optimal_input);
+
+                // The optimal input barely depends on gas, so only the costs are redone per scenario.
+                let scenario_outcomes = |gas_costs_wei: &[(String, U256)]| -> Vec<ScenarioResult> {
+                    let scenario_gas_costs: Vec<(String, U256)> = gas_costs_wei
+                        .iter()
+                        .map(|(label, cost_wei)| {
+                            let cost = optimizer::wei_to_token_units(*cost_wei, conversion_rate_scaled, profit_token_decimals);
+                            (label.clone(), cost)
+                        })
+                        .collect();
+                    optimizer::scenario_results(gross_profit, flashloan_fee, min_net_profit, &scenario_gas_costs)
+                };
+                let path_gas_units = gas_units_for(final_optimal_input);
+                let scenario_results = scenario_outcomes(&scenario_gas_costs_wei(path_gas_units, l1_data_fee));
+
+                if scenario_results[0].passes {
+                    let (swap_actions, exact_amounts_out) =
+                        match build_swap_actions(&quote_path, final_optimal_input, &haircuts_bps) {
+                            Ok(actions) => actions,
+                            Err(e) => {
+                                tracing::warn!("Failed to finalize swap actions for path #{}: {}", i, e);
+                                continue;
+                            }
+                        };
+                    let hop_metrics: Vec<Option<HopMetrics>> = cycle
+                        .path
+                        .pools
+                        .iter()
+                        .zip(cycle.path.path.windows(2))
+                        .zip(swap_actions.iter().zip(&exact_amounts_out))
+                        .map(|((pool, tokens), (action, amount_out))| {
+                            hop_metrics(
+                                pool.as_ref(),
+                                &tokens[0],
+                                &tokens[1],
+                                action.amount_in,
+                                *amount_out,
+                                &snapshots_clone[&pool.address()],
+                            )
+                        })
+                        .collect();
+                    let worst_hop_impact_bps = hop_metrics
+                        .iter()
+                        .flatten()
+                        .map(|metrics| metrics.price_impact_bps)
+                        .fold(0.0, f64::max);
+                    if max_hop_impact_bps.is_some_and(|cap| worst_hop_impact_bps > cap as f64) {
+                        tracing::trace!(worst_hop_impact_bps, "Path #{} skipped, a hop moves its pool too far.", i);
+                        impact_rejects += 1;
+                        continue;
+                    }
+
+                    let approve_actions = approval_allowances
+                        .as_ref()
+                        .map(|(spender, allowances)| required_approvals(&swap_actions, *spender, allowances))
+                        .unwrap_or_default();
+                    let scenario_results = if approve_actions.is_empty() {
+                        scenario_results
+                    } else {
+                        let gas_units = path_gas_units
+                            + U256::from(APPROVAL_GAS_UNITS) * U256::from(approve_actions.len());
+                        let results = scenario_outcomes(&scenario_gas_costs_wei(gas_units, l1_data_fee));
+                        if !results[0].passes {
+                            tracing::trace!("Path #{} skipped, unprofitable after approval gas.", i);
                             continue;
                         }
+                        results
                     };
+                    let (net_profit, gas_cost) = (scenario_results[0].net_profit, scenario_results[0].gas_cost);
+                    let approve_actions = if emit_approve_actions { approve_actions } else { Vec::new() };
 
-                    opportunities.push(ArbitrageSolution {
-                        path: path.clone(),
-                        optimal_input: final_optimal_input, 
-                        gross_profit,
-                        net_profit, 
-                        swap_actions, 
-                    });
+                    let net_profit_weth = optimizer::token_units_to_wei(
+                        net_profit,
+                        conversion_rate_scaled,
+                        profit_token_decimals,
+                    );
+                    let cycle_id = cycle.cycle_id();
 
-                    if let Some(cycle) = path.as_any().downcast_ref::<ArbitrageCycle<P>>() {
-                        println!("Profitable path details: {:?}", cycle.path);
+                    if best_per_cycle
+                        .get(&cycle_id)
+                        .is_some_and(|best| best.net_profit_weth >= net_profit_weth)
+                    {
+                        continue;
                     }
 
+                    let profit_token = &cycle.path.profit_token;
+                    let [optimal_input, gross_profit, net_profit] = [final_optimal_input, gross_profit, net_profit]
+                        .map(|raw| TokenAmount::new(profit_token.clone(), raw));
+
+                    println!("Profitable path details: {:?}", cycle.path);
                     println!(
                         "Found profitable opportunity! path_index: {}, NET profit: {}, input: {}",
-                        i, net_profit, final_optimal_input
+                        i, net_profit, optimal_input
+                    );
+
+                    best_per_cycle.insert(
+                        cycle_id.clone(),
+                        ArbitrageSolution {
+                            path: path.clone(),
+                            cycle_id,
+                            optimal_input,
+                            gross_profit,
+                            net_profit,
+                            net_profit_weth,
+                            flashloan_fee,
+                            gas_cost,
+                            divergent_pools,
+                            scenario_results,
+                            bound_by,
+                            persistence_blocks: 1,
+                            dexes_involved: dexes_involved(&path),
+                            swap_actions,
+                            hop_metrics,
+                            approve_actions,
+                            usd: None,
+                            verification: None,
+                        },
                     );
                 }
             }
-            opportunities
+            let solutions = best_per_cycle.into_values().collect::<Vec<_>>();
+            let evaluated_paths = if budget_skips > 0 { evaluated_paths } else { evaluation_order.len() };
+            (solutions, snapshots_clone, paused_pool_skips, paused_pools, divergence_rejects, stale_oracle_rejects, liquidity_skips, unpriced_skips, impact_rejects, evaluated_paths, budget_skips)
+        });
+
+        let (mut opportunities, snapshots, paused_pool_skips, paused_pools, divergence_rejects, stale_oracle_rejects, liquidity_skips, unpriced_skips, impact_rejects, evaluated_paths, budget_skips) =
+            match task.await {
+                Ok(result) => result,
+                Err(e) => {
+                    tracing::warn!("Path evaluation task failed: {:?}", e);
+                    (Vec::new(), HashMap::new(), 0, HashSet::new(), 0, 0, 0, 0, 0, 0, 0)
+                }
+            };
+        if budget_skips > 0 {
+            tracing::warn!(
+                evaluated_paths,
+                budget_skips,
+                "Evaluation budget of {:?} ran out, skipped the lowest-priority paths.",
+                evaluation_budget.unwrap_or_default()
+            );
+        }
+        let mut best_profits: HashMap<PathKey, U256> = HashMap::new();
+        for opp in &opportunities {
+            let best = best_profits.entry(opp.path.path_key()).or_default();
+            *best = (*best).max(opp.net_profit_weth);
+        }
+        self.path_priority.record(order[..evaluated_paths].iter().map(|&i| {
+            let key = paths[i].path_key();
+            let net_profit_wei = best_profits.get(&key).copied().unwrap_or_default();
+            (key, net_profit_wei)
+        }));
+        for pool in unique_pools.keys().filter(|pool| !overridden.contains(*pool)) {
+            if snapshotted.contains(pool) && !paused_pools.contains(pool) {
+                self.pool_health.record_success(*pool);
+            } else {
+                self.pool_health.record_failure(*pool);
+            }
+        }
+        if !opportunities.is_empty()
+            && let Some(eth_usd_price) = self.get_eth_usd_price(block_number).await
+        {
+            for opp in opportunities.iter_mut() {
+                opp.usd = usd_values(opp, &usd_conversion_rates, eth_usd_price);
+            }
+        }
+        opportunities.sort_by(|a, b| {
+            b.net_profit_weth.cmp(&a.net_profit_weth).then_with(|| a.cycle_id.cmp(&b.cycle_id))
         });
 
-        let mut opportunities = task.await.unwrap_or_default();
-        opportunities.sort_by(|a, b| b.net_profit.cmp(&a.net_profit));
+        #[cfg(feature = "db")]
+        if let (Some(recorder), Some(block)) = (&self.recorder, block_number) {
+            let recorded_at = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_secs());
+            recorder.submit(
+                opportunities
+                    .iter()
+                    .map(|opp| OpportunityRecord::from_solution(opp, block, live_gas_price, recorded_at))
+                    .collect(),
+            );
+        }
+
+        let profitable: Vec<(CycleId, U256)> = opportunities
+            .iter()
+            .map(|opp| (opp.cycle_id.clone(), opp.net_profit.raw))
+            .collect();
+        let streaks = self.persistence.update(block_number, &profitable);
+        for opp in opportunities.iter_mut() {
+            opp.persistence_blocks = streaks.get(&opp.cycle_id).copied().unwrap_or(1);
+        }
+        let policy = self.config.persistence_policy;
+        let profitable_count = opportunities.len();
+        match policy {
+            PersistencePolicy::ReportAll => {}
+            PersistencePolicy::Suppress { .. } => {
+                opportunities.retain(|opp| policy.is_established(opp.persistence_blocks));
+            }
+            // Stable, so each group keeps its profit order.
+            PersistencePolicy::DownRank { .. } => {
+                opportunities.sort_by_key(|opp| !policy.is_established(opp.persistence_blocks));
+            }
+        }
+        let persistence_suppressed = profitable_count - opportunities.len();
+
+        let mut verification_rejects = 0;
+        if let (Some(policy), Some(block)) = (self.config.verification, block_number) {
+            let results = join_all(opportunities.iter().map(|opp| self.verify_solution(opp, block))).await;
+            for (opp, result) in opportunities.iter_mut().zip(results) {
+                match result {
+                    Ok(verified) => opp.verification = Some(verified),
+                    Err(e) => tracing::debug!(cycle = ?opp.cycle_id, "Solution failed verification: {:?}", e),
+                }
+            }
+            verification_rejects = opportunities.iter().filter(|opp| !opp.is_verified()).count();
+            match policy {
+                VerificationPolicy::Drop => opportunities.retain(|opp| opp.is_verified()),
+                // Stable, like the persistence down-ranking.
+                VerificationPolicy::Downgrade => opportunities.sort_by_key(|opp| !opp.is_verified()),
+                VerificationPolicy::PassThrough => {}
+            }
+        }
 
         for (i, opp) in opportunities.iter().enumerate() {
             tracing::info!(
                 path_index = i,
-                net_profit = ?opp.net_profit,
-                input = ?opp.optimal_input,
+                net_profit = %opp.net_profit,
+                input = %opp.optimal_input,
                 "Found profitable opportunity! (Actions: {})",
                 opp.swap_actions.len()
             );
         }
 
+        let mut sorted_dexes: Vec<DexKind> = enabled_dexes.iter().copied().collect();
+        sorted_dexes.sort();
+        let stats = EvaluationStats {
+            paths: cached_paths,
+            pools: unique_pools.len(),
+            failed_snapshots,
+            reused_snapshots,
+            paused_pool_skips,
+            divergence_rejects,
+            stale_oracle_rejects,
+            liquidity_skips,
+            unpriced_skips,
+            impact_rejects,
+            persistence_suppressed,
+            dex_skips,
+            quarantine_skips,
+            enabled_dexes: sorted_dexes,
+            verification_rejects,
+            budget_skips,
+            truncated: budget_skips > 0,
+            solutions: opportunities.len(),
+            elapsed_ms: started.elapsed().as_millis() as u64,
+        };
+        crate::metrics::record_evaluation(cached_paths, opportunities.iter().map(|opp| opp.net_profit_weth));
+        if let (Some(pruning), Some(block)) = (self.config.cache_pruning, block_number) {
+            self.prune_pool_caches(block, pruning, &unique_pools).await;
+        }
+        if let Ok(mut last_stats) = self.last_stats.lock() {
+            *last_stats = stats.clone();
+        }
+        let snapshots = Arc::new(snapshots);
+        if let Ok(mut last_snapshots) = self.last_snapshots.lock() {
+            *last_snapshots = snapshots.clone();
+        }
+
+        if let (Some(exporter), Some(block)) = (&self.exporter, block_number) {
+            match self.get_block_timestamp(block).await {
+                Ok(timestamp) => exporter.submit(BlockEvaluationExport {
+                    block,
+                    timestamp,
+                    gas_price: live_gas_price,
+                    snapshots: snapshots
+                        .iter()
+                        .map(|(address, snapshot)| (*address, snapshot.clone()))
+                        .collect(),
+                    solutions: opportunities.iter().map(SolutionExport::from_solution).collect(),
+                    stats,
+                }),
+                Err(e) => tracing::warn!(block, "Not exporting, failed to fetch the block timestamp: {:?}", e),
+            }
+        }
+
         opportunities
     }
 }
 
+/// Fee of flash-borrowing `amount`.
+fn flashloan_fee(amount: U256) -> U256 {
+    amount
+        .checked_mul(FLASHLOAN_FEE_BPS)
+        .unwrap_or_default()
+        .checked_div(optimizer::BPS_DENOMINATOR)
+        .unwrap_or_default()
+}
+
+/// The first pool's reserve of `cycle`'s profit token, which the optimizer's default bound
+/// follows. The path starts at the profit token, so its first pool holds some; an unwrap
+/// ahead of it has no reserve to speak of.
+fn profit_token_reserve<P: Provider + Send + Sync + 'static + ?Sized>(
+    cycle: &ArbitrageCycle<P>,
+    snapshots: &HashMap<Address, PoolSnapshot>,
+) -> U256 {
+    let first_pool = cycle
+        .path
+        .pools
+        .iter()
+        .find(|pool| !pool.as_any().is::<WrappedNativePool<P>>())
+        .unwrap_or(&cycle.path.pools[0]);
+    snapshots
+        .get(&first_pool.address())
+        .and_then(|snapshot| {
+            first_pool
+                .reserves_summary(snapshot)
+                .into_iter()
+                .find(|(token, _)| token.address() == cycle.path.profit_token.address())
+        })
+        .map(|(_, reserve)| reserve)
+        .unwrap_or_default()
+}
+
+/// `solution`'s profit and costs in USD. `None` when its profit token has no rate to WETH.
+fn usd_values<P: Provider + Send + Sync + 'static + ?Sized>(
+    solution: &ArbitrageSolution<P>,
+    conversion_rates: &HashMap<Address, U256>,
+    eth_usd_price: U256,
+) -> Option<UsdValues> {
+    let profit_token = &solution.net_profit.token;
+    let conversion_rate = conversion_rates.get(&profit_token.address())?;
+    Some(UsdValues::from_token_amounts(
+        solution.net_profit.raw,
+        solution.flashloan_fee,
+        solution.gas_cost,
+        *conversion_rate,
+        profit_token.decimals(),
+        eth_usd_price,
+    ))
+}
+
+/// Dexes of the pools `path` trades through, deduplicated and in `DexKind` order.
+fn dexes_involved<P: Provider + Send + Sync + 'static + ?Sized>(
+    path: &Arc<dyn Arbitrage<P>>,
+) -> Vec<DexKind> {
+    let mut dexes: Vec<DexKind> = path.get_pools().iter().filter_map(|pool| pool.dex_kind()).collect();
+    dexes.sort();
+    dexes.dedup();
+    dexes
+}
+
+/// The variants of `path` to evaluate: its rotations entered at each allowed entry token,
+/// or the path itself when no entry tokens are configured.
+fn entry_candidates<P: Provider + Send + Sync + 'static + ?Sized>(
+    path: &Arc<dyn Arbitrage<P>>,
+    entry_tokens: &HashSet<Address>,
+) -> Vec<Arc<dyn Arbitrage<P>>> {
+    match path.as_any().downcast_ref::<ArbitrageCycle<P>>() {
+        Some(cycle) if !entry_tokens.is_empty() => cycle
+            .entry_rotations(entry_tokens)
+            .into_iter()
+            .map(|rotation| Arc::new(rotation) as Arc<dyn Arbitrage<P>>)
+            .collect(),
+        _ => vec![path.clone()],
+    }
+}
+
 impl<P: Provider + Send + Sync + 'static + ?Sized> Debug for ArbitrageEngine<P> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("ArbitrageEngine")
@@ -353,6 +1583,24 @@ impl<P: Provider + Send + Sync + 'static + ?Sized> Clone for ArbitrageEngine<P>
             cache: self.cache.clone(),
             token_manager: self.token_manager.clone(),
             provider: self.provider.clone(),
+            exporter: self.exporter.clone(),
+            #[cfg(feature = "db")]
+            recorder: self.recorder.clone(),
+            calibration: self.calibration.clone(),
+            approvals: self.approvals.clone(),
+            usd_price_feed: self.usd_price_feed.clone(),
+            gas_estimator: self.gas_estimator.clone(),
+            multicall: self.multicall.clone(),
+            state_updater: self.state_updater.clone(),
+            config: self.config.clone(),
+            persistence: self.persistence.clone(),
+            pool_health: self.pool_health.clone(),
+            snapshot_store: self.snapshot_store.clone(),
+            path_priority: self.path_priority.clone(),
+            verifier: self.verifier.clone(),
+            last_stats: self.last_stats.clone(),
+            last_snapshots: self.last_snapshots.clone(),
+            last_cache_prune: self.last_cache_prune.clone(),
         }
     }
 }
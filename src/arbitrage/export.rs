@@ -0,0 +1,365 @@
+use crate::arbitrage::cycle::ArbitrageCycle;
+use crate::arbitrage::types::{ArbitrageSolution, CycleId, InputBound, SwapKind};
+use crate::arbitrage::writer::BackgroundWriter;
+pub(crate) use crate::core::decimal;
+use crate::core::token::{Token, TokenLike};
+use crate::errors::ArbRsError;
+use crate::pool::{DexKind, PoolSnapshot};
+use alloy_primitives::{Address, U256};
+use alloy_provider::Provider;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use tokio::sync::mpsc;
+
+const FILE_PREFIX: &str = "block_";
+const FILE_SUFFIX: &str = ".json";
+
+#[derive(Debug, Clone)]
+pub struct ExportConfig {
+    /// Block evaluations are only written when enabled.
+    pub enabled: bool,
+    /// Directory receiving one `block_<number>.json` file per evaluated block.
+    pub directory: PathBuf,
+    /// Number of most recent block files kept; older ones are deleted. `0` keeps all.
+    pub keep_last: usize,
+    /// Evaluations queued beyond this are dropped rather than slowing down the engine.
+    pub channel_capacity: usize,
+}
+
+impl Default for ExportConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            directory: PathBuf::from("exports"),
+            keep_last: 1_000,
+            channel_capacity: 64,
+        }
+    }
+}
+
+/// Everything the engine saw and produced for one block.
+///
+/// Maps are `BTreeMap`s and struct fields serialize in declaration order, so the same
+/// evaluation always produces byte-identical JSON.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BlockEvaluationExport {
+    pub block: u64,
+    /// Unix time in seconds of the evaluated block, from its header.
+    pub timestamp: u64,
+    #[serde(with = "decimal")]
+    pub gas_price: U256,
+    pub snapshots: BTreeMap<Address, PoolSnapshot>,
+    pub solutions: Vec<SolutionExport>,
+    pub stats: EvaluationStats,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EvaluationStats {
+    pub paths: usize,
+    pub pools: usize,
+    /// Pools whose snapshot could not be fetched and whose paths were skipped.
+    pub failed_snapshots: usize,
+    /// Pools whose previous snapshot was reused because nothing changed them since.
+    #[serde(default)]
+    pub reused_snapshots: usize,
+    /// Candidate paths skipped because one of their pools is paused.
+    pub paused_pool_skips: usize,
+    /// Candidate paths rejected because a pool's last trade diverged from its snapshot.
+    #[serde(default)]
+    pub divergence_rejects: usize,
+    /// Candidate paths rejected because a pool's price oracle is older than allowed.
+    #[serde(default)]
+    pub stale_oracle_rejects: usize,
+    /// Candidate paths skipped because their input bound, usually the flashloan liquidity
+    /// of their profit token, is below the dust floor.
+    #[serde(default)]
+    pub liquidity_skips: usize,
+    /// Candidate paths skipped because their profit token has no rate to WETH, so their gas
+    /// cost and profit can't be valued.
+    #[serde(default)]
+    pub unpriced_skips: usize,
+    /// Profitable paths dropped because a hop's price impact exceeds the engine's cap.
+    #[serde(default)]
+    pub impact_rejects: usize,
+    /// Profitable paths withheld because they haven't been profitable for long enough.
+    #[serde(default)]
+    pub persistence_suppressed: usize,
+    /// Paths skipped because they trade through a disabled dex.
+    #[serde(default)]
+    pub dex_skips: usize,
+    /// Paths skipped because they trade through a quarantined pool.
+    #[serde(default)]
+    pub quarantine_skips: usize,
+    /// Dexes enabled for the evaluation.
+    #[serde(default)]
+    pub enabled_dexes: Vec<DexKind>,
+    /// Solutions that failed verification, whether dropped or not.
+    #[serde(default)]
+    pub verification_rejects: usize,
+    /// Paths left unevaluated because the evaluation budget ran out.
+    #[serde(default)]
+    pub budget_skips: usize,
+    /// Whether the evaluation budget ran out, so not every path was evaluated.
+    #[serde(default)]
+    pub truncated: bool,
+    pub solutions: usize,
+    pub elapsed_ms: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TokenExport {
+    pub address: Address,
+    pub symbol: String,
+    pub decimals: u8,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SwapExport {
+    pub pool: Address,
+    pub token_in: Address,
+    pub token_out: Address,
+    #[serde(with = "decimal")]
+    pub amount_in: U256,
+    #[serde(with = "decimal")]
+    pub min_amount_out: U256,
+    #[serde(default)]
+    pub kind: SwapKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CostsExport {
+    #[serde(with = "decimal")]
+    pub flashloan_fee: U256,
+    #[serde(with = "decimal")]
+    pub gas_cost: U256,
+    #[serde(with = "decimal")]
+    pub total: U256,
+}
+
+/// Amounts in USD cents.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UsdExport {
+    #[serde(with = "decimal")]
+    pub net_profit: U256,
+    #[serde(with = "decimal")]
+    pub flashloan_fee: U256,
+    #[serde(with = "decimal")]
+    pub gas_cost: U256,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScenarioExport {
+    pub label: String,
+    #[serde(with = "decimal")]
+    pub gas_cost: U256,
+    #[serde(with = "decimal")]
+    pub net_profit: U256,
+    pub passes: bool,
+}
+
+/// A solution without any provider or pool objects. Amounts are decimal strings in the
+/// raw units of `profit_token`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SolutionExport {
+    pub cycle_id: CycleId,
+    pub pools: Vec<Address>,
+    pub tokens: Vec<TokenExport>,
+    pub profit_token: TokenExport,
+    #[serde(with = "decimal")]
+    pub optimal_input: U256,
+    #[serde(with = "decimal")]
+    pub gross_profit: U256,
+    #[serde(with = "decimal")]
+    pub net_profit: U256,
+    pub costs: CostsExport,
+    pub swaps: Vec<SwapExport>,
+    #[serde(default)]
+    pub divergent_pools: Vec<Address>,
+    #[serde(default)]
+    pub scenarios: Vec<ScenarioExport>,
+    #[serde(default)]
+    pub bound_by: InputBound,
+    #[serde(default)]
+    pub persistence_blocks: u64,
+    #[serde(default)]
+    pub dexes_involved: Vec<DexKind>,
+    #[serde(default)]
+    pub usd: Option<UsdExport>,
+}
+
+impl TokenExport {
+    pub fn from_token<P: Provider + Send + Sync + 'static + ?Sized>(token: &Token<P>) -> Self {
+        Self {
+            address: token.address(),
+            symbol: token.symbol().to_string(),
+            decimals: token.decimals(),
+        }
+    }
+}
+
+impl SolutionExport {
+    pub fn from_solution<P: Provider + Send + Sync + 'static + ?Sized>(
+        solution: &ArbitrageSolution<P>,
+    ) -> Self {
+        let cycle = solution.path.as_any().downcast_ref::<ArbitrageCycle<P>>();
+        let tokens = cycle
+            .map(|cycle| {
+                cycle
+                    .path
+                    .path
+                    .iter()
+                    .map(|token| TokenExport::from_token(token))
+                    .collect()
+            })
+            .unwrap_or_default();
+        let profit_token = TokenExport::from_token(&solution.net_profit.token);
+
+        Self {
+            cycle_id: solution.cycle_id.clone(),
+            pools: solution.path.get_involved_pools(),
+            tokens,
+            profit_token,
+            optimal_input: solution.optimal_input.raw,
+            gross_profit: solution.gross_profit.raw,
+            net_profit: solution.net_profit.raw,
+            costs: CostsExport {
+                flashloan_fee: solution.flashloan_fee,
+                gas_cost: solution.gas_cost,
+                total: solution.flashloan_fee.saturating_add(solution.gas_cost),
+            },
+            swaps: solution
+                .swap_actions
+                .iter()
+                .map(|action| SwapExport {
+                    pool: action.pool_address,
+                    token_in: action.token_in.address,
+                    token_out: action.token_out.address,
+                    amount_in: action.amount_in,
+                    min_amount_out: action.min_amount_out,
+                    kind: action.kind,
+                })
+                .collect(),
+            divergent_pools: solution.divergent_pools.clone(),
+            scenarios: solution
+                .scenario_results
+                .iter()
+                .map(|result| ScenarioExport {
+                    label: result.label.clone(),
+                    gas_cost: result.gas_cost,
+                    net_profit: result.net_profit,
+                    passes: result.passes,
+                })
+                .collect(),
+            bound_by: solution.bound_by,
+            persistence_blocks: solution.persistence_blocks,
+            dexes_involved: solution.dexes_involved.clone(),
+            usd: solution.usd.map(|usd| UsdExport {
+                net_profit: usd.net_profit,
+                flashloan_fee: usd.flashloan_fee,
+                gas_cost: usd.gas_cost,
+            }),
+        }
+    }
+}
+
+impl BlockEvaluationExport {
+    pub fn to_json_string(&self) -> Result<String, ArbRsError> {
+        serde_json::to_string_pretty(self).map_err(|e| ArbRsError::ExportError(e.to_string()))
+    }
+
+    pub fn from_json_str(json: &str) -> Result<Self, ArbRsError> {
+        serde_json::from_str(json).map_err(|e| ArbRsError::ExportError(e.to_string()))
+    }
+
+    /// Writes the export to `path` via a temporary file, so readers never see a partial file.
+    pub fn write_json(&self, path: &Path) -> Result<(), ArbRsError> {
+        let json = self.to_json_string()?;
+        let tmp_path = path.with_extension("json.tmp");
+        std::fs::write(&tmp_path, json)
+            .and_then(|_| std::fs::rename(&tmp_path, path))
+            .map_err(|e| ArbRsError::ExportError(format!("{}: {}", path.display(), e)))
+    }
+
+    pub fn file_name(&self) -> String {
+        // Zero-padded so lexicographic order matches block order.
+        format!("{FILE_PREFIX}{:012}{FILE_SUFFIX}", self.block)
+    }
+}
+
+/// Handle for queueing block evaluations to a background writer task.
+#[derive(Debug, Clone)]
+pub struct BlockExporter {
+    sender: mpsc::Sender<BlockEvaluationExport>,
+    writer: BackgroundWriter,
+}
+
+impl BlockExporter {
+    /// Starts the writer task. Returns `None` if exporting is disabled in `config`.
+    /// Must be called from within a Tokio runtime.
+    pub fn spawn(config: ExportConfig) -> Option<Self> {
+        if !config.enabled {
+            return None;
+        }
+
+        let (sender, receiver) =
+            mpsc::channel::<BlockEvaluationExport>(config.channel_capacity.max(1));
+        let writer = BackgroundWriter::spawn(receiver, move |export: BlockEvaluationExport| {
+            let directory = config.directory.clone();
+            let keep_last = config.keep_last;
+            async move {
+                let result = tokio::task::spawn_blocking(move || {
+                    std::fs::create_dir_all(&directory)
+                        .map_err(|e| ArbRsError::ExportError(e.to_string()))?;
+                    export.write_json(&directory.join(export.file_name()))?;
+                    prune_exports(&directory, keep_last)
+                })
+                .await;
+
+                match result {
+                    Ok(Ok(())) => {}
+                    Ok(Err(e)) => tracing::warn!("Failed to export block evaluation: {:?}", e),
+                    Err(e) => tracing::warn!("Export task panicked: {:?}", e),
+                }
+            }
+        });
+
+        Some(Self { sender, writer })
+    }
+
+    /// Writes the exports still queued and stops the writer task.
+    pub async fn shutdown(&self) {
+        self.writer.shutdown().await;
+    }
+
+    /// Queues an export without waiting. Drops it if the writer is falling behind.
+    pub fn submit(&self, export: BlockEvaluationExport) {
+        if let Err(e) = self.sender.try_send(export) {
+            tracing::warn!("Dropping block evaluation export: {}", e);
+        }
+    }
+}
+
+/// Deletes all but the `keep_last` most recent block files in `directory`.
+pub fn prune_exports(directory: &Path, keep_last: usize) -> Result<(), ArbRsError> {
+    if keep_last == 0 {
+        return Ok(());
+    }
+
+    let mut files: Vec<PathBuf> = std::fs::read_dir(directory)
+        .map_err(|e| ArbRsError::ExportError(e.to_string()))?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.starts_with(FILE_PREFIX) && name.ends_with(FILE_SUFFIX))
+        })
+        .collect();
+    files.sort();
+
+    let excess = files.len().saturating_sub(keep_last);
+    for path in &files[..excess] {
+        std::fs::remove_file(path).map_err(|e| ArbRsError::ExportError(e.to_string()))?;
+    }
+    Ok(())
+}
@@ -2,23 +2,164 @@ use crate::{
     TokenLike, TokenManager,
     arbitrage::{
         cycle::ArbitrageCycle,
+        tvl::TvlEstimator,
         types::{Arbitrage, ArbitragePath},
     },
-    core::token::Token,
-    manager::{
-        balancer_pool_manager::BalancerPoolManager, curve_pool_manager::CurvePoolManager,
-        uniswap_v2_pool_manager::UniswapV2PoolManager,
-        uniswap_v3_pool_manager::UniswapV3PoolManager,
-    },
-    pool::LiquidityPool,
+    chain::ChainConfig,
+    core::token::{NATIVE_ETH_ADDRESS, Token, WETH_ADDRESS},
+    core::token_policy::TokenPolicy,
+    errors::ArbRsError,
+    pool::{LiquidityPool, PoolSnapshot, wrapped_native::WrappedNativePool},
+};
+#[cfg(feature = "db")]
+use crate::manager::{
+    balancer_pool_manager::BalancerPoolManager, curve_pool_manager::CurvePoolManager,
+    uniswap_v2_pool_manager::UniswapV2PoolManager,
+    uniswap_v3_pool_manager::UniswapV3PoolManager,
 };
-use alloy_primitives::{address, Address};
+use alloy_primitives::{Address, U256};
 use alloy_provider::Provider;
+use futures::future::join_all;
 use itertools::Itertools;
 use std::{
-    collections::{HashMap, HashSet, VecDeque},
+    collections::{HashMap, HashSet, VecDeque, hash_map::Entry},
     sync::Arc,
 };
+use tokio::sync::RwLock;
+
+/// A pool and its tokens as resolved by the token manager.
+type ResolvedPool<P> = (Arc<dyn LiquidityPool<P>>, Vec<Arc<Token<P>>>);
+
+/// Paths found over a pool set, alongside the pools left out of the graph because one of
+/// their tokens couldn't be resolved.
+pub struct FinderReport<P: Provider + Send + Sync + 'static + ?Sized> {
+    pub paths: Vec<Arc<dyn Arbitrage<P>>>,
+    pub excluded_pools: Vec<(Address, ArbRsError)>,
+}
+
+/// Pools paired with their tokens as resolved by the token manager, ready to be enumerated
+/// without touching the provider.
+pub struct ResolvedPools<P: Provider + Send + Sync + 'static + ?Sized> {
+    pools: Vec<ResolvedPool<P>>,
+    pub excluded_pools: Vec<(Address, ArbRsError)>,
+    /// Native ether, resolved when some pool trades it in place of WETH.
+    native_token: Option<Arc<Token<P>>>,
+    /// The chain of the token manager, whose wrapped native token cycles start from.
+    chain: Arc<ChainConfig>,
+}
+
+/// Liquidity a pool must hold to be part of the graph, so paths through dust pools are never
+/// enumerated. The default lets every pool through.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MinLiquidityFilter {
+    /// Minimum WETH reserve of pools holding WETH.
+    pub min_weth_reserve: U256,
+    /// Token `min_weth_reserve` applies to: the chain's wrapped native token. Mainnet WETH
+    /// when unset, unless the filter is applied by [`filter_by_liquidity`], which sets it
+    /// from the token manager's chain.
+    pub wrapped_native: Option<Address>,
+    /// Minimum reserve of pools without WETH, in whole tokens: of each token for V2 and V3,
+    /// of the balances summed at 18 decimals for Curve and Balancer.
+    pub min_token_reserve: U256,
+    /// Minimum value of a pool's reserves in wei, as a [`TvlEstimator`] over the filtered
+    /// pools' snapshots prices them. Tokens it can't price count as nothing.
+    pub min_tvl_wei: U256,
+    /// Also drops the pools holding a token with a measured transfer tax.
+    pub exclude_taxed_tokens: bool,
+}
+
+impl MinLiquidityFilter {
+    /// A floor of `min_weth_reserve` WETH, in wei, leaving pools without WETH unfiltered.
+    pub fn weth(min_weth_reserve: U256) -> Self {
+        Self {
+            min_weth_reserve,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_wrapped_native(mut self, wrapped_native: Address) -> Self {
+        self.wrapped_native = Some(wrapped_native);
+        self
+    }
+
+    pub fn with_min_token_reserve(mut self, min_token_reserve: U256) -> Self {
+        self.min_token_reserve = min_token_reserve;
+        self
+    }
+
+    pub fn with_min_tvl(mut self, min_tvl_wei: U256) -> Self {
+        self.min_tvl_wei = min_tvl_wei;
+        self
+    }
+
+    pub fn with_taxed_tokens_excluded(mut self) -> Self {
+        self.exclude_taxed_tokens = true;
+        self
+    }
+
+    /// Whether no liquidity floor is set.
+    pub fn is_disabled(&self) -> bool {
+        self.min_weth_reserve.is_zero()
+            && self.min_token_reserve.is_zero()
+            && self.min_tvl_wei.is_zero()
+    }
+
+    /// Whether a pool with the reserves `summary` at `snapshot`, as its
+    /// [`reserves_summary`](LiquidityPool::reserves_summary) reports them, clears the reserve
+    /// floors. V3 and V4 pools count their virtual reserves at the current price, and need
+    /// some in-range liquidity.
+    pub fn accepts<P>(&self, summary: &[(Arc<Token<P>>, U256)], snapshot: &PoolSnapshot) -> bool
+    where
+        P: Provider + Send + Sync + 'static + ?Sized,
+    {
+        if let PoolSnapshot::UniswapV3(v3) = snapshot
+            && v3.liquidity == 0
+        {
+            return false;
+        }
+        if let PoolSnapshot::UniswapV4(v4) = snapshot
+            && v4.state.liquidity == 0
+        {
+            return false;
+        }
+        let wrapped_native = self.wrapped_native.unwrap_or(WETH_ADDRESS);
+        if let Some((_, weth_reserve)) = summary
+            .iter()
+            .find(|(token, _)| token.address() == wrapped_native)
+        {
+            return *weth_reserve >= self.min_weth_reserve;
+        }
+
+        let whole =
+            |reserve: U256, decimals: u8| reserve / U256::from(10).pow(U256::from(decimals));
+        match snapshot {
+            PoolSnapshot::UniswapV2(_)
+            | PoolSnapshot::UniswapV3(_)
+            | PoolSnapshot::UniswapV4(_) => {
+                summary.iter().all(|(token, reserve)| {
+                    whole(*reserve, token.decimals()) >= self.min_token_reserve
+                })
+            }
+            PoolSnapshot::Curve(_) | PoolSnapshot::Balancer(_) => {
+                let total = summary
+                    .iter()
+                    .fold(U256::ZERO, |total, (token, reserve)| {
+                        total.saturating_add(scale_to_18_decimals(*reserve, token.decimals()))
+                    });
+                whole(total, 18) >= self.min_token_reserve
+            }
+            PoolSnapshot::WrappedNative => true,
+        }
+    }
+}
+
+fn scale_to_18_decimals(amount: U256, decimals: u8) -> U256 {
+    if decimals <= 18 {
+        amount.saturating_mul(U256::from(10).pow(U256::from(18 - decimals)))
+    } else {
+        amount / U256::from(10).pow(U256::from(decimals - 18))
+    }
+}
 
 #[derive(Debug, Clone)]
 struct PathInSearch<P: Provider + Send + Sync + 'static + ?Sized> {
@@ -33,33 +174,320 @@ pub struct PoolNeighbor<P: Provider + Send + Sync + 'static + ?Sized> {
     pub token: Arc<Token<P>>,
 }
 type AdjacencyList<P> = HashMap<Arc<Token<P>>, Vec<PoolNeighbor<P>>>;
-fn build_graph<P>(all_pools: Vec<Arc<dyn LiquidityPool<P>>>) -> AdjacencyList<P>
+
+/// The market graph cycles are searched over: each token's neighbours, through the pools
+/// trading it. It outlives a search, so a discovered pool only adds its own edges.
+pub struct PoolGraph<P: Provider + Send + Sync + 'static + ?Sized> {
+    adjacency: AdjacencyList<P>,
+    /// The graph's pools by address. A metapool and its underlying coins share one.
+    pools: HashMap<Address, Vec<Arc<dyn LiquidityPool<P>>>>,
+    /// Native ether, resolved when some pool trades it in place of WETH.
+    native_token: Option<Arc<Token<P>>>,
+    /// The chain whose wrapped native token cycles start from.
+    chain: Arc<ChainConfig>,
+}
+
+impl<P: Provider + Send + Sync + 'static + ?Sized> PoolGraph<P> {
+    /// The graph over `resolved`'s pools. Its excluded pools are left out.
+    pub fn new(resolved: ResolvedPools<P>) -> Self {
+        tracing::info!("Building market graph from {} pools...", resolved.pools.len());
+        let mut graph = Self {
+            adjacency: HashMap::new(),
+            pools: HashMap::new(),
+            native_token: None,
+            chain: resolved.chain.clone(),
+        };
+        graph.add_pools(resolved);
+        tracing::info!("Graph built with {} unique tokens (nodes).", graph.adjacency.len());
+        graph
+    }
+
+    /// Adds the edges of `resolved`'s pools that aren't in the graph yet, and returns those
+    /// pools.
+    pub fn add_pools(&mut self, resolved: ResolvedPools<P>) -> Vec<Arc<dyn LiquidityPool<P>>> {
+        if self.native_token.is_none() {
+            self.native_token = resolved.native_token;
+        }
+        let mut added = Vec::new();
+        for (pool, tokens) in resolved.pools {
+            if self.contains(&pool) {
+                continue;
+            }
+            for token_pair in tokens.into_iter().combinations(2) {
+                let token0 = token_pair[0].clone();
+                let token1 = token_pair[1].clone();
+                if !pool.trades_pair(token0.address(), token1.address()) {
+                    continue;
+                }
+
+                self.adjacency.entry(token0.clone()).or_default().push(PoolNeighbor {
+                    pool: pool.clone(),
+                    token: token1.clone(),
+                });
+
+                self.adjacency.entry(token1).or_default().push(PoolNeighbor {
+                    pool: pool.clone(),
+                    token: token0,
+                });
+            }
+            self.pools.entry(pool.address()).or_default().push(pool.clone());
+            added.push(pool);
+        }
+        added
+    }
+
+    /// Whether `pool` itself, not just some pool at its address, is in the graph.
+    pub fn contains(&self, pool: &Arc<dyn LiquidityPool<P>>) -> bool {
+        self.pools
+            .get(&pool.address())
+            .is_some_and(|pools| pools.iter().any(|known| Arc::ptr_eq(known, pool)))
+    }
+
+    pub fn pool_count(&self) -> usize {
+        self.pools.values().map(Vec::len).sum()
+    }
+
+    pub fn token_count(&self) -> usize {
+        self.adjacency.len()
+    }
+
+    /// The wrapped native token, where cycles start, if some pool trades it.
+    fn start_token(&self) -> Option<Arc<Token<P>>> {
+        self.adjacency
+            .keys()
+            .find(|token| token.address() == self.chain.wrapped_native)
+            .cloned()
+    }
+
+    fn wrapper(&self, start_token: &Arc<Token<P>>) -> Option<Arc<WrappedNativePool<P>>> {
+        self.native_token
+            .clone()
+            .map(|native| Arc::new(WrappedNativePool::new(start_token.clone(), native)))
+    }
+
+    /// Fewest hops from any of `sources` to each token within `max_hops` of them.
+    fn distances(
+        &self,
+        sources: impl IntoIterator<Item = Address>,
+        max_hops: usize,
+    ) -> HashMap<Address, usize> {
+        let mut distances: HashMap<Address, usize> = HashMap::new();
+        let mut frontier: Vec<Address> = Vec::new();
+        for source in sources {
+            if distances.insert(source, 0).is_none() {
+                frontier.push(source);
+            }
+        }
+        let tokens: HashMap<Address, &Arc<Token<P>>> = self
+            .adjacency
+            .keys()
+            .map(|token| (token.address(), token))
+            .collect();
+        for hops in 1..=max_hops {
+            let mut next = Vec::new();
+            for address in frontier {
+                let Some(neighbors) = tokens.get(&address).and_then(|token| self.adjacency.get(*token)) else {
+                    continue;
+                };
+                for neighbor in neighbors {
+                    if let Entry::Vacant(entry) = distances.entry(neighbor.token.address()) {
+                        entry.insert(hops);
+                        next.push(neighbor.token.address());
+                    }
+                }
+            }
+            frontier = next;
+        }
+        distances
+    }
+
+    /// Every cycle of up to `max_hops` pools from the wrapped native token. Wrapping and
+    /// unwrapping ether are hops of their own, not counted against `max_hops`.
+    pub fn find_cycles(&self, max_hops: usize) -> Vec<Arc<dyn Arbitrage<P>>> {
+        let mut arbitrage_paths: Vec<Arc<dyn Arbitrage<P>>> = Vec::new();
+
+        let mut canonical_cycles: HashSet<Vec<Address>> = HashSet::new();
+
+        let Some(start_token) = self.start_token() else {
+            return arbitrage_paths;
+        };
+
+        let wrapper = self.wrapper(&start_token);
+
+        let mut queue: VecDeque<PathInSearch<P>> = VecDeque::new();
+
+        if let Some(neighbors) = self.adjacency.get(&start_token) {
+            for neighbor in neighbors {
+                let path = PathInSearch {
+                    pools: vec![neighbor.pool.clone()],
+                    tokens: vec![start_token.clone(), neighbor.token.clone()],
+                    current_token: neighbor.token.clone(),
+                };
+                queue.push_back(path);
+            }
+        }
+
+        while let Some(current_path) = queue.pop_front() {
+            let current_hop = current_path.pools.len();
+
+            if current_hop >= max_hops {
+                continue;
+            }
+
+            if let Some(neighbors) = self.adjacency.get(&current_path.current_token) {
+                for neighbor in neighbors {
+                    let next_token = &neighbor.token;
+                    let next_pool = &neighbor.pool;
+
+                    if next_token.address() == start_token.address() {
+                        push_cycle(
+                            &mut arbitrage_paths,
+                            &mut canonical_cycles,
+                            &current_path,
+                            next_pool,
+                            &start_token,
+                            wrapper.as_ref(),
+                        );
+                    } else {
+                        let previous_token = &current_path.tokens[current_path.tokens.len() - 2];
+                        if next_token.address() != previous_token.address() {
+                            queue.push_back(current_path.extended(next_pool, next_token));
+                        }
+                    }
+                }
+            }
+        }
+        arbitrage_paths
+    }
+}
+
+impl<P: Provider + Send + Sync + 'static + ?Sized> PathInSearch<P> {
+    fn extended(&self, pool: &Arc<dyn LiquidityPool<P>>, token: &Arc<Token<P>>) -> Self {
+        Self {
+            pools: [self.pools.clone(), vec![pool.clone()]].concat(),
+            tokens: [self.tokens.clone(), vec![token.clone()]].concat(),
+            current_token: token.clone(),
+        }
+    }
+}
+
+/// Closes `path` back to `start_token` through `closing_pool`, unless the cycle is a single
+/// pool or a rotation or reversal of a cycle found already.
+fn push_cycle<P>(
+    arbitrage_paths: &mut Vec<Arc<dyn Arbitrage<P>>>,
+    canonical_cycles: &mut HashSet<Vec<Address>>,
+    path: &PathInSearch<P>,
+    closing_pool: &Arc<dyn LiquidityPool<P>>,
+    start_token: &Arc<Token<P>>,
+    wrapper: Option<&Arc<WrappedNativePool<P>>>,
+) where
+    P: Provider + Send + Sync + 'static + ?Sized,
+{
+    let new_pools = [path.pools.clone(), vec![closing_pool.clone()]].concat();
+    if new_pools.len() < 2 {
+        return;
+    }
+    let new_tokens = [path.tokens.clone(), vec![start_token.clone()]].concat();
+    if !canonical_cycles.insert(get_canonical_cycle_path(&new_pools)) {
+        return;
+    }
+
+    let mut arbitrage_path = ArbitragePath {
+        pools: new_pools,
+        path: new_tokens,
+        profit_token: start_token.clone(),
+    };
+    if let Some(wrapper) = wrapper {
+        arbitrage_path = insert_wrap_hops(arbitrage_path, wrapper);
+    }
+    arbitrage_paths.push(Arc::new(ArbitrageCycle::new(arbitrage_path)));
+}
+
+/// The cycles of up to `max_hops` pools through `new_pool`, which must have been added to
+/// `graph` already: those [`PoolGraph::find_cycles`] finds through it. Walks are only
+/// followed while they can still take `new_pool` and get back to the wrapped native token
+/// within `max_hops`, so the search stays around the new pool however large the graph is.
+pub fn find_cycles_through_pool<P>(
+    new_pool: &Arc<dyn LiquidityPool<P>>,
+    graph: &PoolGraph<P>,
+    max_hops: usize,
+) -> Vec<Arc<dyn Arbitrage<P>>>
 where
     P: Provider + Send + Sync + 'static + ?Sized,
 {
-    let mut graph: AdjacencyList<P> = HashMap::new();
-    tracing::info!("Building market graph from {} pools...", all_pools.len());
-
-    for pool in all_pools {
-        let tokens = pool.get_all_tokens();
-        for token_pair in tokens.into_iter().combinations(2) {
-            let token0 = token_pair[0].clone();
-            let token1 = token_pair[1].clone();
-
-            graph.entry(token0.clone()).or_default().push(PoolNeighbor {
-                pool: pool.clone(),
-                token: token1.clone(),
-            });
-
-            graph.entry(token1).or_default().push(PoolNeighbor {
-                pool: pool.clone(),
-                token: token0,
-            });
+    let mut arbitrage_paths: Vec<Arc<dyn Arbitrage<P>>> = Vec::new();
+    let Some(start_token) = graph.start_token() else {
+        return arbitrage_paths;
+    };
+    if !graph.contains(new_pool) {
+        return arbitrage_paths;
+    }
+
+    // Lower bounds on the hops left: back to the start token once the walk took the new
+    // pool, and to one of its tokens, through it and out again before.
+    let pool_tokens: Vec<Address> = new_pool.get_all_tokens().iter().map(|token| token.address()).collect();
+    let to_start = graph.distances([start_token.address()], max_hops);
+    let to_pool = graph.distances(pool_tokens.iter().copied(), max_hops);
+    let Some(exit) = pool_tokens.iter().filter_map(|token| to_start.get(token)).min().copied() else {
+        return arbitrage_paths;
+    };
+    let can_close = |token: &Arc<Token<P>>, hops: usize, through: bool| {
+        if through {
+            to_start.get(&token.address()).is_some_and(|left| hops + left <= max_hops)
+        } else {
+            to_pool.get(&token.address()).is_some_and(|left| hops + left + 1 + exit <= max_hops)
+        }
+    };
+
+    let wrapper = graph.wrapper(&start_token);
+    let mut canonical_cycles: HashSet<Vec<Address>> = HashSet::new();
+    let mut stack: Vec<(PathInSearch<P>, bool)> = Vec::new();
+    if let Some(neighbors) = graph.adjacency.get(&start_token) {
+        for neighbor in neighbors {
+            let through = Arc::ptr_eq(&neighbor.pool, new_pool);
+            if can_close(&neighbor.token, 1, through) {
+                let path = PathInSearch {
+                    pools: vec![neighbor.pool.clone()],
+                    tokens: vec![start_token.clone(), neighbor.token.clone()],
+                    current_token: neighbor.token.clone(),
+                };
+                stack.push((path, through));
+            }
         }
     }
 
-    tracing::info!("Graph built with {} unique tokens (nodes).", graph.len());
-    graph
+    while let Some((current_path, through)) = stack.pop() {
+        let current_hop = current_path.pools.len();
+        if current_hop >= max_hops {
+            continue;
+        }
+        let Some(neighbors) = graph.adjacency.get(&current_path.current_token) else {
+            continue;
+        };
+        for neighbor in neighbors {
+            let next_through = through || Arc::ptr_eq(&neighbor.pool, new_pool);
+            if neighbor.token.address() == start_token.address() {
+                if next_through {
+                    push_cycle(
+                        &mut arbitrage_paths,
+                        &mut canonical_cycles,
+                        &current_path,
+                        &neighbor.pool,
+                        &start_token,
+                        wrapper.as_ref(),
+                    );
+                }
+                continue;
+            }
+            let previous_token = &current_path.tokens[current_path.tokens.len() - 2];
+            if neighbor.token.address() != previous_token.address()
+                && can_close(&neighbor.token, current_hop + 1, next_through)
+            {
+                stack.push((current_path.extended(&neighbor.pool, &neighbor.token), next_through));
+            }
+        }
+    }
+    arbitrage_paths
 }
 
 fn get_canonical_cycle_path<P>(pools: &[Arc<dyn LiquidityPool<P>>]) -> Vec<Address>
@@ -99,13 +527,14 @@ where
     canonical
 }
 
+#[cfg(feature = "db")]
 pub async fn find_three_pool_cycles<P>(
     v2_manager: &UniswapV2PoolManager<P>,
     v3_manager: &UniswapV3PoolManager<P>,
     curve_manager: &CurvePoolManager<P>,
     balancer_manager: &BalancerPoolManager<P>,
     token_manager: &TokenManager<P>,
-) -> Vec<Arc<dyn Arbitrage<P>>>
+) -> FinderReport<P>
 where
     P: Provider + Send + Sync + 'static + ?Sized,
 {
@@ -116,18 +545,20 @@ where
         balancer_manager,
         token_manager,
         3,
+        &MinLiquidityFilter::default(),
     )
     .await
 }
 
-pub async fn find_multi_hop_cycles<P>(
+/// Every pool known to the managers, with each metapool's underlying coins as a pool of
+/// their own.
+#[cfg(feature = "db")]
+pub fn collect_pools<P>(
     v2_manager: &UniswapV2PoolManager<P>,
     v3_manager: &UniswapV3PoolManager<P>,
     curve_manager: &CurvePoolManager<P>,
     balancer_manager: &BalancerPoolManager<P>,
-    token_manager: &TokenManager<P>,
-    max_hops: usize,
-) -> Vec<Arc<dyn Arbitrage<P>>>
+) -> Vec<Arc<dyn LiquidityPool<P>>>
 where
     P: Provider + Send + Sync + 'static + ?Sized,
 {
@@ -135,106 +566,451 @@ where
     all_pools.extend(v2_manager.get_all_pools());
     all_pools.extend(v3_manager.get_all_pools());
     all_pools.extend(curve_manager.get_all_pools());
+    all_pools.extend(curve_manager.get_underlying_pools());
     all_pools.extend(balancer_manager.get_all_pools());
+    all_pools
+}
 
-    if all_pools.is_empty() {
-        return Vec::new();
+#[cfg(feature = "db")]
+pub async fn find_multi_hop_cycles<P>(
+    v2_manager: &UniswapV2PoolManager<P>,
+    v3_manager: &UniswapV3PoolManager<P>,
+    curve_manager: &CurvePoolManager<P>,
+    balancer_manager: &BalancerPoolManager<P>,
+    token_manager: &TokenManager<P>,
+    max_hops: usize,
+    liquidity_filter: &MinLiquidityFilter,
+) -> FinderReport<P>
+where
+    P: Provider + Send + Sync + 'static + ?Sized,
+{
+    let all_pools = collect_pools(v2_manager, v3_manager, curve_manager, balancer_manager);
+    find_multi_hop_cycles_in_pools(all_pools, token_manager, max_hops, liquidity_filter).await
+}
+
+/// Finds WETH cycles of up to `max_hops` pools over an explicit pool list, leaving out the
+/// pools holding a token the token manager's policy refuses, the pools below
+/// `liquidity_filter`'s floors at the latest block and, if it says so, the pools holding a
+/// fee-on-transfer token.
+pub async fn find_multi_hop_cycles_in_pools<P>(
+    all_pools: Vec<Arc<dyn LiquidityPool<P>>>,
+    token_manager: &TokenManager<P>,
+    max_hops: usize,
+    liquidity_filter: &MinLiquidityFilter,
+) -> FinderReport<P>
+where
+    P: Provider + Send + Sync + 'static + ?Sized,
+{
+    let resolved = prepare_pools(all_pools, token_manager, liquidity_filter).await;
+    enumerate_multi_hop_cycles(resolved, max_hops)
+}
+
+/// Resolves the tokens of `all_pools` and leaves out the pools
+/// [`find_multi_hop_cycles_in_pools`] does, ready to be added to a [`PoolGraph`].
+pub async fn prepare_pools<P>(
+    all_pools: Vec<Arc<dyn LiquidityPool<P>>>,
+    token_manager: &TokenManager<P>,
+    liquidity_filter: &MinLiquidityFilter,
+) -> ResolvedPools<P>
+where
+    P: Provider + Send + Sync + 'static + ?Sized,
+{
+    let mut resolved = resolve_pool_tokens(all_pools, token_manager).await;
+    resolved = exclude_refused_tokens(resolved, token_manager.token_policy());
+    if liquidity_filter.exclude_taxed_tokens {
+        resolved = exclude_taxed_tokens(resolved);
     }
+    filter_by_liquidity(resolved, liquidity_filter, None).await
+}
 
-    let graph = build_graph(all_pools);
-    let mut arbitrage_paths: Vec<Arc<dyn Arbitrage<P>>> = Vec::new();
+/// Keeps the market graph between searches. [`Self::rebuild`] finds every cycle, e.g. at
+/// startup, and [`Self::extend`] only the cycles through newly discovered pools, so the
+/// paths found before stay as they are.
+pub struct PathFinder<P: Provider + Send + Sync + 'static + ?Sized> {
+    graph: RwLock<Option<PoolGraph<P>>>,
+    max_hops: usize,
+    liquidity_filter: MinLiquidityFilter,
+}
 
-    let mut canonical_cycles: HashSet<Vec<Address>> = HashSet::new(); 
+impl<P: Provider + Send + Sync + 'static + ?Sized> PathFinder<P> {
+    pub fn new(max_hops: usize, liquidity_filter: MinLiquidityFilter) -> Self {
+        Self {
+            graph: RwLock::new(None),
+            max_hops,
+            liquidity_filter,
+        }
+    }
 
-    let start_token = match token_manager
-        .get_token(address!("C02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2"))
-        .await
-    {
-        Ok(token) => token,
-        Err(_) => return Vec::new(),
-    };
+    pub fn max_hops(&self) -> usize {
+        self.max_hops
+    }
 
-    let mut queue: VecDeque<PathInSearch<P>> = VecDeque::new();
+    /// Whether [`Self::extend`] keeps the pools a rebuild would. A TVL floor prices each
+    /// pool against the whole pool set, so it needs a rebuild.
+    pub fn can_extend(&self) -> bool {
+        self.liquidity_filter.min_tvl_wei.is_zero()
+    }
 
-    if let Some(neighbors) = graph.get(&start_token) {
-        for neighbor in neighbors {
-            let path = PathInSearch {
-                pools: vec![neighbor.pool.clone()],
-                tokens: vec![start_token.clone(), neighbor.token.clone()],
-                current_token: neighbor.token.clone(),
-            };
-            queue.push_back(path);
+    /// Replaces the graph with one over `pools`, filtered like
+    /// [`find_multi_hop_cycles_in_pools`] does, and finds every cycle in it.
+    pub async fn rebuild(
+        &self,
+        pools: Vec<Arc<dyn LiquidityPool<P>>>,
+        token_manager: &TokenManager<P>,
+    ) -> FinderReport<P> {
+        let mut resolved = prepare_pools(pools, token_manager, &self.liquidity_filter).await;
+        let excluded_pools = std::mem::take(&mut resolved.excluded_pools);
+        let graph = PoolGraph::new(resolved);
+        let paths = graph.find_cycles(self.max_hops);
+        *self.graph.write().await = Some(graph);
+        tracing::info!(
+            "Found {} unique multi-hop arbitrage paths (up to {} hops).",
+            paths.len(),
+            self.max_hops
+        );
+        FinderReport {
+            paths,
+            excluded_pools,
         }
     }
 
-    while let Some(current_path) = queue.pop_front() {
-        let current_hop = current_path.pools.len();
+    /// Adds the pools of `new_pools` the graph doesn't hold yet, filtered the same way, and
+    /// finds only the cycles through them.
+    pub async fn extend(
+        &self,
+        new_pools: Vec<Arc<dyn LiquidityPool<P>>>,
+        token_manager: &TokenManager<P>,
+    ) -> FinderReport<P> {
+        let mut resolved = prepare_pools(new_pools, token_manager, &self.liquidity_filter).await;
+        let excluded_pools = std::mem::take(&mut resolved.excluded_pools);
+        let mut graph = self.graph.write().await;
+        let graph = match graph.as_mut() {
+            Some(graph) => graph,
+            None => graph.insert(PoolGraph::new(ResolvedPools {
+                pools: Vec::new(),
+                excluded_pools: Vec::new(),
+                native_token: None,
+                chain: resolved.chain.clone(),
+            })),
+        };
+        let added = graph.add_pools(resolved);
 
-        if current_hop >= max_hops { 
-            continue;
+        // A cycle through several new pools is found from each of them.
+        let mut keys = HashSet::new();
+        let paths: Vec<Arc<dyn Arbitrage<P>>> = added
+            .iter()
+            .flat_map(|pool| find_cycles_through_pool(pool, graph, self.max_hops))
+            .filter(|path| keys.insert(path.path_key()))
+            .collect();
+        tracing::info!(
+            pools = added.len(),
+            paths = paths.len(),
+            "Extended the market graph."
+        );
+        FinderReport {
+            paths,
+            excluded_pools,
         }
+    }
 
-        if let Some(neighbors) = graph.get(&current_path.current_token) {
-            for neighbor in neighbors {
-                let next_token = &neighbor.token;
-                let next_pool = &neighbor.pool;
-
-                if next_token.address() == start_token.address() {
-                    let new_pools = [current_path.pools.clone(), vec![next_pool.clone()]].concat();
-                    let new_tokens = [current_path.tokens.clone(), vec![start_token.clone()]].concat();
-
-                    if new_pools.len() >= 2 {
-                        let canonical = get_canonical_cycle_path(&new_pools);
-                        
-                        if !canonical_cycles.contains(&canonical) {
-                            canonical_cycles.insert(canonical);
-
-                            let arbitrage_path = ArbitragePath {
-                                pools: new_pools,
-                                path: new_tokens,
-                                profit_token: start_token.clone(),
-                            };
-                            
-                            arbitrage_paths.push(Arc::new(ArbitrageCycle::new(arbitrage_path)));
-                        }
-                    }
-                }
-                else {
-                    let previous_token = &current_path.tokens[current_path.tokens.len() - 2];
-                    if next_token.address() != previous_token.address() {
-                        let next_path = PathInSearch {
-                            pools: [current_path.pools.clone(), vec![next_pool.clone()]].concat(),
-                            tokens: [current_path.tokens.clone(), vec![next_token.clone()]].concat(),
-                            current_token: next_token.clone(),
-                        };
-                        queue.push_back(next_path);
-                    }
-                }
-            }
+    /// Pools and tokens in the graph, once it's built.
+    pub async fn graph_size(&self) -> Option<(usize, usize)> {
+        self.graph
+            .read()
+            .await
+            .as_ref()
+            .map(|graph| (graph.pool_count(), graph.token_count()))
+    }
+}
+
+/// Resolves the tokens of every pool in one bulk lookup. A pool with any token that can't
+/// be resolved is excluded, with the reason.
+pub async fn resolve_pool_tokens<P>(
+    all_pools: Vec<Arc<dyn LiquidityPool<P>>>,
+    token_manager: &TokenManager<P>,
+) -> ResolvedPools<P>
+where
+    P: Provider + Send + Sync + 'static + ?Sized,
+{
+    let pool_tokens: Vec<(Arc<dyn LiquidityPool<P>>, Vec<Address>)> = all_pools
+        .into_iter()
+        .map(|pool| {
+            let tokens = pool.get_all_tokens().iter().map(|token| token.address()).collect();
+            (pool, tokens)
+        })
+        .collect();
+    let chain = token_manager.chain().clone();
+    let trades_native = pool_tokens
+        .iter()
+        .any(|(pool, _)| pool.is_native(chain.wrapped_native));
+    let resolved_tokens = token_manager
+        .get_tokens(
+            pool_tokens
+                .iter()
+                .flat_map(|(_, tokens)| tokens.iter().copied())
+                .chain(trades_native.then_some(NATIVE_ETH_ADDRESS)),
+        )
+        .await;
+
+    let mut pools = Vec::with_capacity(pool_tokens.len());
+    let mut excluded_pools = Vec::new();
+    for (pool, addresses) in pool_tokens {
+        let tokens: Result<Vec<Arc<Token<P>>>, ArbRsError> = addresses
+            .iter()
+            .map(|address| match &resolved_tokens[address] {
+                Ok(token) => Ok(token.clone()),
+                Err(e) => Err(ArbRsError::UnresolvedToken(*address, e.to_string())),
+            })
+            .collect();
+        match tokens {
+            Ok(tokens) => pools.push((pool, tokens)),
+            Err(e) => excluded_pools.push((pool.address(), e)),
+        }
+    }
+
+    let native_token = resolved_tokens
+        .get(&NATIVE_ETH_ADDRESS)
+        .and_then(|token| token.as_ref().ok())
+        .cloned();
+    ResolvedPools {
+        pools,
+        excluded_pools,
+        native_token,
+        chain,
+    }
+}
+
+/// Drops the pools below `filter`'s floors at `block_number`, snapshotting each pool once. A
+/// pool whose snapshot fails is excluded, with the reason.
+pub async fn filter_by_liquidity<P>(
+    resolved: ResolvedPools<P>,
+    filter: &MinLiquidityFilter,
+    block_number: Option<u64>,
+) -> ResolvedPools<P>
+where
+    P: Provider + Send + Sync + 'static + ?Sized,
+{
+    if filter.is_disabled() {
+        return resolved;
+    }
+    let ResolvedPools {
+        pools,
+        mut excluded_pools,
+        native_token,
+        chain,
+    } = resolved;
+    let filter = MinLiquidityFilter {
+        wrapped_native: filter.wrapped_native.or(Some(chain.wrapped_native)),
+        ..*filter
+    };
+    let pools_before = pools.len();
+    let snapshots = join_all(pools.iter().map(|(pool, _)| pool.get_snapshot(block_number))).await;
+
+    let mut snapshotted = Vec::with_capacity(pools_before);
+    for ((pool, tokens), snapshot) in pools.into_iter().zip(snapshots) {
+        match snapshot {
+            Ok(snapshot) => snapshotted.push((pool, tokens, snapshot)),
+            Err(e) => excluded_pools.push((pool.address(), e)),
+        }
+    }
+    // Priced off the same snapshots, each token through its deepest pool with the wrapped
+    // native or stable reference token.
+    let estimator = (!filter.min_tvl_wei.is_zero()).then(|| {
+        let tokens: Vec<Arc<Token<P>>> = snapshotted
+            .iter()
+            .flat_map(|(_, tokens, _)| tokens.iter().cloned())
+            .unique_by(|token| token.address())
+            .collect();
+        let pools: HashMap<Address, Arc<dyn LiquidityPool<P>>> = snapshotted
+            .iter()
+            .map(|(pool, _, _)| (pool.address(), pool.clone()))
+            .collect();
+        let snapshots: HashMap<Address, PoolSnapshot> = snapshotted
+            .iter()
+            .map(|(pool, _, snapshot)| (pool.address(), snapshot.clone()))
+            .collect();
+        TvlEstimator::from_snapshots(&chain, &tokens, &pools, &snapshots)
+    });
+
+    let mut kept = Vec::with_capacity(snapshotted.len());
+    for (pool, tokens, snapshot) in snapshotted {
+        let summary = pool.reserves_summary(&snapshot);
+        let clears_tvl = estimator
+            .as_ref()
+            .is_none_or(|estimator| estimator.estimate(&summary) >= filter.min_tvl_wei);
+        if clears_tvl && filter.accepts(&summary, &snapshot) {
+            kept.push((pool, tokens));
         }
     }
-    
+    tracing::info!(
+        pools_before,
+        pools_after = kept.len(),
+        min_weth_reserve = %filter.min_weth_reserve,
+        min_token_reserve = %filter.min_token_reserve,
+        min_tvl_wei = %filter.min_tvl_wei,
+        "Filtered pools by liquidity."
+    );
+
+    ResolvedPools {
+        pools: kept,
+        excluded_pools,
+        native_token,
+        chain,
+    }
+}
+
+/// Drops the pools holding a token with a measured transfer tax.
+pub fn exclude_taxed_tokens<P>(resolved: ResolvedPools<P>) -> ResolvedPools<P>
+where
+    P: Provider + Send + Sync + 'static + ?Sized,
+{
+    let ResolvedPools {
+        pools,
+        excluded_pools,
+        native_token,
+        chain,
+    } = resolved;
+    let pools_before = pools.len();
+    let pools: Vec<ResolvedPool<P>> = pools
+        .into_iter()
+        .filter(|(_, tokens)| tokens.iter().all(|token| token.transfer_tax_bps() == 0))
+        .collect();
+    tracing::info!(
+        pools_before,
+        pools_after = pools.len(),
+        "Excluded pools holding taxed tokens."
+    );
+    ResolvedPools {
+        pools,
+        excluded_pools,
+        native_token,
+        chain,
+    }
+}
+
+/// Drops the pools holding a token `policy` refuses.
+pub fn exclude_refused_tokens<P>(
+    resolved: ResolvedPools<P>,
+    policy: &TokenPolicy,
+) -> ResolvedPools<P>
+where
+    P: Provider + Send + Sync + 'static + ?Sized,
+{
+    let ResolvedPools {
+        pools,
+        excluded_pools,
+        native_token,
+        chain,
+    } = resolved;
+    let pools_before = pools.len();
+    let pools: Vec<ResolvedPool<P>> = pools
+        .into_iter()
+        .filter(|(_, tokens)| {
+            policy
+                .refused_token(tokens.iter().map(|token| token.address()))
+                .is_none()
+        })
+        .collect();
+    if pools.len() < pools_before {
+        tracing::info!(
+            pools_before,
+            pools_after = pools.len(),
+            "Excluded pools holding tokens the token policy refuses."
+        );
+    }
+    ResolvedPools {
+        pools,
+        excluded_pools,
+        native_token,
+        chain,
+    }
+}
+
+/// Finds WETH cycles of up to `max_hops` pools over already resolved pools. Works only on
+/// the resolved data, so it makes no provider calls. Wrapping and unwrapping ether are hops
+/// of their own, not counted against `max_hops`.
+pub fn enumerate_multi_hop_cycles<P>(mut resolved: ResolvedPools<P>, max_hops: usize) -> FinderReport<P>
+where
+    P: Provider + Send + Sync + 'static + ?Sized,
+{
+    let excluded_pools = std::mem::take(&mut resolved.excluded_pools);
+    let arbitrage_paths = PoolGraph::new(resolved).find_cycles(max_hops);
     tracing::info!(
         "Found {} unique multi-hop arbitrage paths (up to {} hops).",
         arbitrage_paths.len(), max_hops
     );
-    arbitrage_paths
+    FinderReport {
+        paths: arbitrage_paths,
+        excluded_pools,
+    }
+}
+
+/// Puts a wrap or unwrap hop wherever the pools on either side of WETH disagree on whether
+/// it's paid in native ether, so the path starts and ends with WETH.
+fn insert_wrap_hops<P>(
+    path: ArbitragePath<P>,
+    wrapper: &Arc<WrappedNativePool<P>>,
+) -> ArbitragePath<P>
+where
+    P: Provider + Send + Sync + 'static + ?Sized,
+{
+    let wrapper_pool: Arc<dyn LiquidityPool<P>> = wrapper.clone();
+    let held = |native: bool| Arc::clone(if native { wrapper.native() } else { wrapper.weth() });
+    let wrapped_native = wrapper.weth().address();
+
+    let mut pools = Vec::with_capacity(path.pools.len() + 2);
+    let mut tokens = vec![path.path[0].clone()];
+    let mut holding_native = false;
+    for (i, pool) in path.pools.iter().enumerate() {
+        if path.path[i].address() == wrapped_native {
+            let wants_native = pool.is_native(wrapped_native);
+            if wants_native != holding_native {
+                pools.push(wrapper_pool.clone());
+                tokens.push(held(wants_native));
+                holding_native = wants_native;
+            }
+        }
+        pools.push(pool.clone());
+        let token_out = &path.path[i + 1];
+        if token_out.address() == wrapped_native {
+            holding_native = pool.is_native(wrapped_native);
+            tokens.push(held(holding_native));
+        } else {
+            tokens.push(token_out.clone());
+        }
+    }
+    if holding_native {
+        pools.push(wrapper_pool);
+        tokens.push(held(false));
+    }
+
+    ArbitragePath {
+        pools,
+        path: tokens,
+        profit_token: path.profit_token,
+    }
 }
 
 /// Finds all 2-pool arbitrage cycles given a set of pool managers.
+#[cfg(feature = "db")]
 pub fn find_two_pool_cycles<P: Provider + Send + Sync + 'static + ?Sized>(
     v2_manager: &UniswapV2PoolManager<P>,
     v3_manager: &UniswapV3PoolManager<P>,
     curve_manager: &CurvePoolManager<P>,
     balancer_manager: &BalancerPoolManager<P>,
 ) -> Vec<Arc<dyn Arbitrage<P>>> {
-    let mut all_pools: Vec<Arc<dyn LiquidityPool<P>>> = Vec::new();
-
-    all_pools.extend(v2_manager.get_all_pools());
-    all_pools.extend(v3_manager.get_all_pools());
-    all_pools.extend(curve_manager.get_all_pools());
-    all_pools.extend(balancer_manager.get_all_pools());
+    find_two_pool_cycles_in_pools(collect_pools(
+        v2_manager,
+        v3_manager,
+        curve_manager,
+        balancer_manager,
+    ))
+}
 
+/// Finds all 2-pool arbitrage cycles over an explicit pool list.
+pub fn find_two_pool_cycles_in_pools<P: Provider + Send + Sync + 'static + ?Sized>(
+    all_pools: Vec<Arc<dyn LiquidityPool<P>>>,
+) -> Vec<Arc<dyn Arbitrage<P>>> {
     tracing::info!(
         "Finding 2-pool cycles across {} total pools...",
         all_pools.len()
@@ -0,0 +1,286 @@
+use crate::arbitrage::optimizer;
+use crate::errors::ArbRsError;
+use alloy_primitives::{Address, U256};
+use alloy_provider::Provider;
+use alloy_rpc_types::{BlockId, BlockNumberOrTag, Header, TransactionRequest};
+use alloy_sol_types::{SolCall, sol};
+use async_trait::async_trait;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+/// Blocks of `eth_feeHistory` the priority fee is averaged over by default.
+pub const DEFAULT_FEE_HISTORY_BLOCKS: u64 = 10;
+/// Percentile of each block's priority fees paid by default: enough to be included ahead of
+/// most of the block.
+pub const DEFAULT_PRIORITY_FEE_PERCENTILE: f64 = 50.0;
+
+/// Signed size, in bytes, of an arbitrage transaction before its swaps: the signature,
+/// nonce, fees, executor address and the flash loan call.
+pub const TX_BASE_BYTES: u64 = 250;
+/// Calldata each swap adds to the transaction: the pool, tokens, amounts and swap kind.
+pub const TX_BYTES_PER_HOP: u64 = 160;
+
+/// Estimated signed size, in bytes, of a transaction executing a path of `hops` swaps.
+pub fn estimated_tx_size(hops: usize) -> u64 {
+    TX_BASE_BYTES + TX_BYTES_PER_HOP * hops as u64
+}
+
+sol! {
+    function getL1FeeUpperBound(uint256 unsignedTxSize) external view returns (uint256);
+}
+
+/// Prices the gas solutions are costed at.
+#[async_trait]
+pub trait GasEstimator: Send + Sync {
+    /// Price per gas, in wei, of including a transaction at `block_number`, or now when no
+    /// block is given. `header` is that block's header when the caller already has it.
+    async fn gas_price(
+        &self,
+        block_number: Option<u64>,
+        header: Option<&Header>,
+    ) -> Result<U256, ArbRsError>;
+
+    /// Fee, in wei, a rollup charges on top of execution gas for posting a transaction of
+    /// `tx_size` bytes to L1, at `block_number`. Nothing on L1 itself, which is the default.
+    async fn l1_data_fee(
+        &self,
+        _block_number: Option<u64>,
+        _tx_size: u64,
+    ) -> Result<U256, ArbRsError> {
+        Ok(U256::ZERO)
+    }
+}
+
+/// `eth_gasPrice`, what gas costs now whichever block is evaluated.
+pub struct LegacyGasPrice<P: Provider + Send + Sync + 'static + ?Sized> {
+    provider: Arc<P>,
+}
+
+impl<P: Provider + Send + Sync + 'static + ?Sized> LegacyGasPrice<P> {
+    pub fn new(provider: Arc<P>) -> Self {
+        Self { provider }
+    }
+}
+
+#[async_trait]
+impl<P: Provider + Send + Sync + 'static + ?Sized> GasEstimator for LegacyGasPrice<P> {
+    async fn gas_price(
+        &self,
+        _block_number: Option<u64>,
+        _header: Option<&Header>,
+    ) -> Result<U256, ArbRsError> {
+        Ok(U256::from(self.provider.get_gas_price().await?))
+    }
+}
+
+/// The evaluated block's base fee plus a competitive priority fee: the
+/// `priority_fee_percentile` of the priority fees paid in the `fee_history_blocks` blocks up
+/// to it, averaged and scaled by `priority_fee_multiplier_bps / 10_000`.
+///
+/// Both come from the evaluated block rather than the chain head, so replaying past blocks
+/// costs gas at what it cost then.
+pub struct Eip1559Estimator<P: Provider + Send + Sync + 'static + ?Sized> {
+    provider: Arc<P>,
+    pub fee_history_blocks: u64,
+    pub priority_fee_percentile: f64,
+    pub priority_fee_multiplier_bps: u64,
+}
+
+impl<P: Provider + Send + Sync + 'static + ?Sized> Eip1559Estimator<P> {
+    pub fn new(provider: Arc<P>) -> Self {
+        Self {
+            provider,
+            fee_history_blocks: DEFAULT_FEE_HISTORY_BLOCKS,
+            priority_fee_percentile: DEFAULT_PRIORITY_FEE_PERCENTILE,
+            priority_fee_multiplier_bps: optimizer::BPS_DENOMINATOR.to::<u64>(),
+        }
+    }
+
+    pub fn with_fee_history_blocks(mut self, blocks: u64) -> Self {
+        self.fee_history_blocks = blocks.max(1);
+        self
+    }
+
+    pub fn with_priority_fee_percentile(mut self, percentile: f64) -> Self {
+        self.priority_fee_percentile = percentile.clamp(0.0, 100.0);
+        self
+    }
+
+    /// Scales the priority fee by `bps / 10_000`, e.g. `15_000` to outbid it by 50%.
+    pub fn with_priority_fee_multiplier(mut self, bps: u64) -> Self {
+        self.priority_fee_multiplier_bps = bps;
+        self
+    }
+
+    async fn base_fee(
+        &self,
+        block_id: BlockNumberOrTag,
+        header: Option<&Header>,
+    ) -> Result<U256, ArbRsError> {
+        let base_fee = match header {
+            Some(header) => header.base_fee_per_gas,
+            None => {
+                self.provider
+                    .get_block_by_number(block_id)
+                    .await?
+                    .ok_or_else(|| ArbRsError::ProviderError("Block not found".to_string()))?
+                    .header
+                    .base_fee_per_gas
+            }
+        };
+        base_fee
+            .map(U256::from)
+            .ok_or_else(|| ArbRsError::ProviderError(format!("Block {block_id} has no base fee")))
+    }
+
+    async fn priority_fee(&self, block_id: BlockNumberOrTag) -> Result<U256, ArbRsError> {
+        let fee_history = self
+            .provider
+            .get_fee_history(
+                self.fee_history_blocks,
+                block_id,
+                &[self.priority_fee_percentile],
+            )
+            .await?;
+        let rewards: Vec<u128> = fee_history
+            .reward
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|block_rewards| block_rewards.first().copied())
+            .collect();
+        if rewards.is_empty() {
+            return Ok(U256::ZERO);
+        }
+        let average = rewards
+            .iter()
+            .map(|&reward| U256::from(reward))
+            .sum::<U256>()
+            / U256::from(rewards.len());
+        Ok(
+            average.saturating_mul(U256::from(self.priority_fee_multiplier_bps))
+                / optimizer::BPS_DENOMINATOR,
+        )
+    }
+}
+
+#[async_trait]
+impl<P: Provider + Send + Sync + 'static + ?Sized> GasEstimator for Eip1559Estimator<P> {
+    async fn gas_price(
+        &self,
+        block_number: Option<u64>,
+        header: Option<&Header>,
+    ) -> Result<U256, ArbRsError> {
+        let block_id = header
+            .map(|header| header.number)
+            .or(block_number)
+            .map_or(BlockNumberOrTag::Latest, BlockNumberOrTag::Number);
+        let base_fee = self.base_fee(block_id, header).await?;
+        let priority_fee = self.priority_fee(block_id).await?;
+        Ok(base_fee.saturating_add(priority_fee))
+    }
+}
+
+/// Execution gas priced by `inner`, plus the L1 data fee the `GasPriceOracle` predeploy of
+/// an OP-stack chain (Optimism, Base) quotes for a transaction's size.
+///
+/// The fee comes from `getL1FeeUpperBound`, which the oracle has offered since the Fjord
+/// upgrade, and is read at the evaluated block.
+pub struct OpStackGasEstimator<P: Provider + Send + Sync + 'static + ?Sized> {
+    provider: Arc<P>,
+    oracle: Address,
+    inner: Arc<dyn GasEstimator>,
+}
+
+impl<P: Provider + Send + Sync + 'static + ?Sized> OpStackGasEstimator<P> {
+    pub fn new(provider: Arc<P>, oracle: Address, inner: Arc<dyn GasEstimator>) -> Self {
+        Self {
+            provider,
+            oracle,
+            inner,
+        }
+    }
+}
+
+#[async_trait]
+impl<P: Provider + Send + Sync + 'static + ?Sized> GasEstimator for OpStackGasEstimator<P> {
+    async fn gas_price(
+        &self,
+        block_number: Option<u64>,
+        header: Option<&Header>,
+    ) -> Result<U256, ArbRsError> {
+        self.inner.gas_price(block_number, header).await
+    }
+
+    async fn l1_data_fee(
+        &self,
+        block_number: Option<u64>,
+        tx_size: u64,
+    ) -> Result<U256, ArbRsError> {
+        let request = TransactionRequest::default().to(self.oracle).input(
+            getL1FeeUpperBoundCall {
+                unsignedTxSize: U256::from(tx_size),
+            }
+            .abi_encode()
+            .into(),
+        );
+        let result_bytes = self
+            .provider
+            .call(request)
+            .block(block_number.map(BlockId::from).unwrap_or(BlockId::latest()))
+            .await?;
+        Ok(getL1FeeUpperBoundCall::abi_decode_returns(&result_bytes)?)
+    }
+}
+
+/// Answers a set price per block, and `default` for the blocks without one, so tests and
+/// backtests can cost gas without a node or at recorded historical prices. Transactions
+/// pay `l1_fee_per_byte` of their size as L1 data fee, nothing unless set.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FixedGas {
+    pub default: U256,
+    pub by_block: BTreeMap<u64, U256>,
+    pub l1_fee_per_byte: U256,
+}
+
+impl FixedGas {
+    pub fn new(gas_price: U256) -> Self {
+        Self {
+            default: gas_price,
+            by_block: BTreeMap::new(),
+            l1_fee_per_byte: U256::ZERO,
+        }
+    }
+
+    pub fn with_block_prices(mut self, prices: impl IntoIterator<Item = (u64, U256)>) -> Self {
+        self.by_block.extend(prices);
+        self
+    }
+
+    pub fn with_l1_fee_per_byte(mut self, l1_fee_per_byte: U256) -> Self {
+        self.l1_fee_per_byte = l1_fee_per_byte;
+        self
+    }
+}
+
+#[async_trait]
+impl GasEstimator for FixedGas {
+    async fn gas_price(
+        &self,
+        block_number: Option<u64>,
+        header: Option<&Header>,
+    ) -> Result<U256, ArbRsError> {
+        let block_number = header.map(|header| header.number).or(block_number);
+        Ok(block_number
+            .and_then(|block| self.by_block.get(&block))
+            .copied()
+            .unwrap_or(self.default))
+    }
+
+    async fn l1_data_fee(
+        &self,
+        _block_number: Option<u64>,
+        tx_size: u64,
+    ) -> Result<U256, ArbRsError> {
+        Ok(self.l1_fee_per_byte.saturating_mul(U256::from(tx_size)))
+    }
+}
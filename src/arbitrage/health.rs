@@ -0,0 +1,181 @@
+#[cfg(feature = "db")]
+use crate::db::DbManager;
+use alloy_primitives::Address;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Mutex, MutexGuard};
+
+/// How [`PoolHealth`] decides when to give up on a pool and when to try it again.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PoolHealthConfig {
+    /// Failed evaluations in a row after which a pool is quarantined.
+    pub failure_threshold: u32,
+    /// Evaluations a newly quarantined pool sits out before it's tried again, doubled after
+    /// each failed retry.
+    pub initial_backoff: u64,
+    pub max_backoff: u64,
+}
+
+impl Default for PoolHealthConfig {
+    fn default() -> Self {
+        Self {
+            failure_threshold: 5,
+            initial_backoff: 10,
+            max_backoff: 1_000,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PoolHealthRecord {
+    /// Evaluations in a row the pool failed in.
+    pub consecutive_failures: u32,
+    /// Evaluation the pool is next tried in, while it's quarantined.
+    pub retry_at: Option<u64>,
+}
+
+impl PoolHealthRecord {
+    pub fn is_quarantined(&self) -> bool {
+        self.retry_at.is_some()
+    }
+}
+
+#[derive(Debug, Default)]
+struct HealthState {
+    evaluation: u64,
+    records: HashMap<Address, PoolHealthRecord>,
+}
+
+/// Counts the evaluations each pool failed in a row, because its snapshot couldn't be
+/// fetched or it was paused, and quarantines pools that keep failing. Paths through a
+/// quarantined pool are skipped until it's retried, with exponential backoff, and succeeds.
+#[derive(Debug, Default)]
+pub struct PoolHealth {
+    config: PoolHealthConfig,
+    state: Mutex<HealthState>,
+}
+
+impl PoolHealth {
+    pub fn new(config: PoolHealthConfig) -> Self {
+        Self {
+            config,
+            state: Mutex::default(),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HealthState> {
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Starts an evaluation, returning the quarantined pools it skips. Pools whose retry is
+    /// due aren't among them.
+    pub fn start_evaluation(&self) -> HashSet<Address> {
+        let mut state = self.lock();
+        state.evaluation += 1;
+        let evaluation = state.evaluation;
+        state
+            .records
+            .iter()
+            .filter(|(_, record)| {
+                record
+                    .retry_at
+                    .is_some_and(|retry_at| retry_at > evaluation)
+            })
+            .map(|(pool, _)| *pool)
+            .collect()
+    }
+
+    /// Clears the pool's failures, releasing it from quarantine.
+    pub fn record_success(&self, pool: Address) {
+        if let Some(record) = self.lock().records.remove(&pool)
+            && record.is_quarantined()
+        {
+            tracing::info!(?pool, "Pool recovered, released from quarantine.");
+        }
+    }
+
+    /// Counts a failed evaluation against the pool, quarantining it at the threshold and
+    /// pushing its next retry back after every failure from then on.
+    pub fn record_failure(&self, pool: Address) {
+        let mut state = self.lock();
+        let evaluation = state.evaluation;
+        let record = state.records.entry(pool).or_default();
+        record.consecutive_failures += 1;
+        let Some(retries) = record
+            .consecutive_failures
+            .checked_sub(self.config.failure_threshold)
+        else {
+            return;
+        };
+        let backoff = self
+            .config
+            .initial_backoff
+            .saturating_mul(1u64.checked_shl(retries).unwrap_or(u64::MAX))
+            .min(self.config.max_backoff);
+        if !record.is_quarantined() {
+            tracing::warn!(
+                ?pool,
+                failures = record.consecutive_failures,
+                backoff,
+                "Quarantining a pool that keeps failing."
+            );
+        }
+        record.retry_at = Some(evaluation + backoff.max(1));
+    }
+
+    pub fn record(&self, pool: &Address) -> Option<PoolHealthRecord> {
+        self.lock().records.get(pool).copied()
+    }
+
+    /// Every quarantined pool, including those due for a retry.
+    pub fn quarantined(&self) -> HashSet<Address> {
+        self.lock()
+            .records
+            .iter()
+            .filter(|(_, record)| record.is_quarantined())
+            .map(|(pool, _)| *pool)
+            .collect()
+    }
+
+    /// Replaces the in-memory records with those saved in the database. Saved retries are
+    /// counted from the next evaluation, so a quarantined pool sits out what it had left.
+    #[cfg(feature = "db")]
+    pub async fn load(&self, db_manager: &DbManager) -> Result<(), sqlx::Error> {
+        let saved = db_manager.load_pool_health().await?;
+        let mut state = self.lock();
+        let evaluation = state.evaluation;
+        state.records = saved
+            .into_iter()
+            .map(|(pool, mut record)| {
+                record.retry_at = record.retry_at.map(|retry_in| evaluation + retry_in);
+                (pool, record)
+            })
+            .collect();
+        Ok(())
+    }
+
+    #[cfg(feature = "db")]
+    pub async fn save(&self, db_manager: &DbManager) -> Result<(), sqlx::Error> {
+        let records: Vec<_> = {
+            let state = self.lock();
+            state
+                .records
+                .iter()
+                .map(|(pool, record)| {
+                    let retry_at = record
+                        .retry_at
+                        .map(|retry_at| retry_at.saturating_sub(state.evaluation));
+                    (
+                        *pool,
+                        PoolHealthRecord {
+                            retry_at,
+                            ..*record
+                        },
+                    )
+                })
+                .collect()
+        };
+        db_manager.save_pool_health(&records).await
+    }
+}
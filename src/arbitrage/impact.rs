@@ -0,0 +1,132 @@
+use crate::arbitrage::types::HopMetrics;
+use crate::balancer::pool::BalancerPool;
+use crate::core::token::{Token, TokenLike};
+use crate::curve::pool::CurveStableswapPool;
+use crate::math::utils::u256_to_f64;
+use crate::pool::{
+    LiquidityPool, PoolSnapshot, uniswap_v3::UniswapV3Pool, uniswap_v4::UniswapV4Pool,
+};
+use alloy_primitives::U256;
+use alloy_provider::Provider;
+
+/// Engine default for the worst hop impact a solution may have, once a cap is enabled.
+pub const DEFAULT_MAX_HOP_IMPACT_BPS: u64 = 500;
+
+/// Share of a hop's input the Curve and Balancer marginal prices are probed with.
+const PROBE_DIVISOR: u64 = 10_000;
+
+/// Marginal price of the hop `amount_in -> amount_out` through `pool` before and after it.
+/// Prices are raw units of `token_out` per raw unit of `token_in`. `None` when the hop can't
+/// be replayed on the snapshot, e.g. a metapool swap of underlying coins.
+pub fn hop_metrics<P: Provider + Send + Sync + 'static + ?Sized>(
+    pool: &dyn LiquidityPool<P>,
+    token_in: &Token<P>,
+    token_out: &Token<P>,
+    amount_in: U256,
+    amount_out: U256,
+    snapshot: &PoolSnapshot,
+) -> Option<HopMetrics> {
+    let (pre_trade_price, post_trade_price) = match snapshot {
+        PoolSnapshot::UniswapV2(state) => {
+            let (reserve_in, reserve_out) =
+                if token_in.address() == pool.get_all_tokens()[0].address() {
+                    (state.reserve0, state.reserve1)
+                } else {
+                    (state.reserve1, state.reserve0)
+                };
+            // The whole input, fee included, stays in the pair.
+            (
+                price(reserve_out, reserve_in)?,
+                price(reserve_out.checked_sub(amount_out)?, reserve_in.checked_add(amount_in)?)?,
+            )
+        }
+        PoolSnapshot::UniswapV3(v3_snapshot) => {
+            let v3 = pool.as_any().downcast_ref::<UniswapV3Pool<P>>()?;
+            let result = v3
+                .simulate_exact_input_swap(token_in, token_out, amount_in, v3_snapshot)
+                .ok()?;
+            let zero_for_one = token_in.address() == pool.get_all_tokens()[0].address();
+            (
+                sqrt_price_to_price(v3_snapshot.sqrt_price_x96, zero_for_one)?,
+                sqrt_price_to_price(result.final_state.sqrt_price_x96, zero_for_one)?,
+            )
+        }
+        PoolSnapshot::UniswapV4(v4_snapshot) => {
+            let v4 = pool.as_any().downcast_ref::<UniswapV4Pool<P>>()?;
+            let (_, final_snapshot) = v4
+                .simulate_exact_input_swap(token_in, token_out, amount_in, v4_snapshot)
+                .ok()?;
+            let zero_for_one = token_in.address() == pool.get_all_tokens()[0].address();
+            (
+                sqrt_price_to_price(v4_snapshot.state.sqrt_price_x96, zero_for_one)?,
+                sqrt_price_to_price(final_snapshot.state.sqrt_price_x96, zero_for_one)?,
+            )
+        }
+        PoolSnapshot::Curve(curve_snapshot) => {
+            let curve = pool.as_any().downcast_ref::<CurveStableswapPool<P>>()?;
+            let final_snapshot = match curve.simulate_exchange(token_in, token_out, amount_in, curve_snapshot) {
+                Ok(result) => result.final_snapshot,
+                // Cryptoswap pools aren't simulated, so only their balances are moved.
+                Err(_) => {
+                    let (i, j) = (curve.coin_index(token_in)?, curve.coin_index(token_out)?);
+                    let mut final_snapshot = curve_snapshot.clone();
+                    final_snapshot.balances[i] = final_snapshot.balances[i].checked_add(amount_in)?;
+                    final_snapshot.balances[j] = final_snapshot.balances[j].checked_sub(amount_out)?;
+                    final_snapshot
+                }
+            };
+            probed_prices(pool, token_in, token_out, amount_in, snapshot, &PoolSnapshot::Curve(final_snapshot))?
+        }
+        PoolSnapshot::Balancer(balancer_snapshot) => {
+            let balancer = pool.as_any().downcast_ref::<BalancerPool<P>>()?;
+            let result = balancer
+                .simulate_swap(token_in, token_out, amount_in, balancer_snapshot)
+                .ok()?;
+            probed_prices(
+                pool,
+                token_in,
+                token_out,
+                amount_in,
+                snapshot,
+                &PoolSnapshot::Balancer(result.final_snapshot),
+            )?
+        }
+        PoolSnapshot::WrappedNative => (1.0, 1.0),
+    };
+
+    (pre_trade_price > 0.0).then(|| HopMetrics {
+        pre_trade_price,
+        post_trade_price,
+        price_impact_bps: (1.0 - post_trade_price / pre_trade_price) * 10_000.0,
+    })
+}
+
+fn price(amount_out: U256, amount_in: U256) -> Option<f64> {
+    (!amount_in.is_zero()).then(|| u256_to_f64(amount_out) / u256_to_f64(amount_in))
+}
+
+/// Price of token1 in token0, or its inverse when trading token1 for token0.
+fn sqrt_price_to_price(sqrt_price_x96: U256, zero_for_one: bool) -> Option<f64> {
+    let sqrt_price = u256_to_f64(sqrt_price_x96) / 2f64.powi(96);
+    let price = sqrt_price * sqrt_price;
+    if price == 0.0 {
+        return None;
+    }
+    Some(if zero_for_one { price } else { 1.0 / price })
+}
+
+/// Output per unit of a small probe on `before` and `after`. Both include the pool fee,
+/// which cancels out of the impact.
+fn probed_prices<P: Provider + Send + Sync + 'static + ?Sized>(
+    pool: &dyn LiquidityPool<P>,
+    token_in: &Token<P>,
+    token_out: &Token<P>,
+    amount_in: U256,
+    before: &PoolSnapshot,
+    after: &PoolSnapshot,
+) -> Option<(f64, f64)> {
+    let probe = (amount_in / U256::from(PROBE_DIVISOR)).max(U256::from(1));
+    let pre_trade = pool.calculate_tokens_out(token_in, token_out, probe, before).ok()?;
+    let post_trade = pool.calculate_tokens_out(token_in, token_out, probe, after).unwrap_or_default();
+    Some((price(pre_trade, probe)?, price(post_trade, probe)?))
+}
@@ -1,6 +1,26 @@
+pub mod amount;
+pub mod approvals;
+pub mod backtest;
 pub mod cache;
+pub mod calibration;
 pub mod cycle;
+pub mod dry_run;
 pub mod engine;
+pub mod export;
 pub mod finder;
+pub mod gas;
+pub mod health;
+pub mod impact;
 pub mod optimizer;
+pub mod persistence;
+pub mod priority;
+pub mod quote_path;
+pub mod recorder;
+pub mod serializable;
+pub mod shadow;
+pub mod snapshot_store;
+pub mod tvl;
 pub mod types;
+pub mod usd;
+pub mod verification;
+pub mod writer;
@@ -1,69 +1,366 @@
-use crate::{arbitrage::types::Arbitrage, errors::ArbRsError, pool::PoolSnapshot};
+use crate::{
+    arbitrage::{quote_path::QuotePath, types::ScenarioResult}, errors::ArbRsError,
+    math::{utils::u256_to_f64, v3::full_math::mul_div},
+};
 use alloy_primitives::{Address, U256};
-use alloy_provider::Provider;
-use std::{collections::HashMap, sync::Arc};
+use std::cmp::Ordering;
 
 const INV_PHI_SCALED: U256 = U256::from_limbs([618_034, 0, 0, 0]);
 const SCALE: U256 = U256::from_limbs([1_000_000, 0, 0, 0]);
+/// Log-spaced inputs probed across the whole range before the golden-section search.
+pub const GRID_PROBES: usize = 16;
+/// How far, in basis points of the best probe's profit, the profit must dip between two
+/// higher probes for the path to count as having separate peaks.
+pub const PEAK_DIP_BPS: U256 = U256::from_limbs([100, 0, 0, 0]);
 pub const FLASHLOAN_FEE_BPS: U256 = U256::from_limbs([9, 0, 0, 0]);
 pub const BPS_DENOMINATOR: U256 = U256::from_limbs([10_000, 0, 0, 0]);
-pub const ESTIMATED_GAS_UNITS: U256 = U256::from_limbs([700_000, 0, 0, 0]); 
+/// Gas of a transaction besides its swaps: the flashloan, contract dispatch and the base cost.
+pub const GAS_OVERHEAD_UNITS: u64 = 100_000;
 pub const ETHER_SCALE: U256 = U256::from_limbs([1_000_000_000_000_000_000, 0, 0, 0]);
-pub const MIN_NET_PROFIT_THRESHOLD: U256 = U256::from_limbs([50_000_000_000_000_000, 0, 0, 0]);
+/// Default minimum net profit in wei (0.01 ETH). Gas is deducted before this is applied, so
+/// it only needs to cover execution risk, not the transaction cost.
+pub const MIN_NET_PROFIT_THRESHOLD: U256 = U256::from_limbs([10_000_000_000_000_000, 0, 0, 0]);
+/// Share of the first pool's reserve of the profit token searched up to when
+/// [`OptimizerConfig::max_input`] is unset.
+pub const DEFAULT_RESERVE_SHARE_BPS: U256 = U256::from_limbs([3_000, 0, 0, 0]);
 
-/// Finds the optimal input amount for a given arbitrage path using Golden-section search.
-pub fn find_optimal_input<P>(
-    path: &Arc<dyn Arbitrage<P>>,
-    mut a: U256,
-    mut b: U256,
-    snapshots: &HashMap<Address, PoolSnapshot>,
-) -> Result<(U256, U256), ArbRsError>
-where
-    P: Provider + Send + Sync + 'static + ?Sized,
-{
-    let tolerance = U256::from(10).pow(U256::from(15));
-
-    let mut c = b - (b - a) * INV_PHI_SCALED / SCALE;
-    let mut d = a + (b - a) * INV_PHI_SCALED / SCALE;
-
-    while (b - a) > tolerance {
-        let profit_c = path.calculate_out_amount(c, snapshots)?.saturating_sub(c);
-        let profit_d = path.calculate_out_amount(d, snapshots)?.saturating_sub(d);
-
-        if profit_c > profit_d {
-            b = d;
-        } else {
-            a = c;
+/// Search range and stopping rule of [`find_optimal_input`]. Amounts are in whole profit
+/// tokens scaled by 1e18; [`Self::for_token`] rescales them to a token's decimals.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OptimizerConfig {
+    pub min_input: U256,
+    /// When unset, `DEFAULT_RESERVE_SHARE_BPS` of the first pool's reserve of the profit
+    /// token, so the bound follows the pool rather than a fixed amount.
+    pub max_input: Option<U256>,
+    /// The search stops once its bracket is this narrow.
+    pub tolerance: U256,
+    pub max_iterations: u32,
+}
+
+impl Default for OptimizerConfig {
+    fn default() -> Self {
+        Self {
+            min_input: ETHER_SCALE / U256::from(10),
+            max_input: None,
+            tolerance: U256::from(10).pow(U256::from(15)),
+            max_iterations: 128,
+        }
+    }
+}
+
+impl OptimizerConfig {
+    /// This config in raw units of a token with `decimals`, `reserve` being the first pool's
+    /// reserve of it. The max is always set, and the tolerance at least one unit.
+    pub fn for_token(&self, decimals: u8, reserve: U256) -> Self {
+        let max_input = match self.max_input {
+            Some(max_input) => rescale_decimals(max_input, 18, decimals),
+            None => mul_div(reserve, DEFAULT_RESERVE_SHARE_BPS, BPS_DENOMINATOR).unwrap_or(reserve),
+        };
+        Self {
+            min_input: rescale_decimals(self.min_input, 18, decimals),
+            max_input: Some(max_input),
+            tolerance: rescale_decimals(self.tolerance, 18, decimals).max(U256::ONE),
+            max_iterations: self.max_iterations,
         }
+    }
+}
+
+/// Gas cost in wei. Both inputs are already in native units, so no scaling is applied.
+pub fn gas_cost_wei(gas_units: U256, gas_price_wei: U256) -> U256 {
+    gas_units.saturating_mul(gas_price_wei)
+}
+
+/// Converts a wei amount into raw units of a token, given the price of 1 ETH in whole
+/// tokens scaled by 1e18 (e.g. `2000e18` for USDC).
+pub fn wei_to_token_units(amount_wei: U256, weth_price_scaled: U256, token_decimals: u8) -> U256 {
+    let amount_18 = mul_div(amount_wei, weth_price_scaled, ETHER_SCALE).unwrap_or(U256::MAX);
+    rescale_decimals(amount_18, 18, token_decimals)
+}
+
+/// Inverse of [`wei_to_token_units`]. Returns zero for a zero price.
+pub fn token_units_to_wei(amount: U256, weth_price_scaled: U256, token_decimals: u8) -> U256 {
+    if weth_price_scaled.is_zero() {
+        return U256::ZERO;
+    }
+    let amount_18 = rescale_decimals(amount, token_decimals, 18);
+    mul_div(amount_18, ETHER_SCALE, weth_price_scaled).unwrap_or(U256::MAX)
+}
+
+/// The price of 1 ETH in whole tokens scaled by 1e18, as taken by [`wei_to_token_units`],
+/// that `amount_wei` buying `amount_out` raw units of a token implies. Returns zero for a
+/// zero `amount_wei`.
+pub fn weth_price_scaled(amount_wei: U256, amount_out: U256, token_decimals: u8) -> U256 {
+    if amount_wei.is_zero() {
+        return U256::ZERO;
+    }
+    let amount_18 = rescale_decimals(amount_out, token_decimals, 18);
+    mul_div(amount_18, ETHER_SCALE, amount_wei).unwrap_or(U256::MAX)
+}
+
+fn rescale_decimals(amount: U256, from: u8, to: u8) -> U256 {
+    match from.cmp(&to) {
+        Ordering::Equal => amount,
+        Ordering::Greater => amount / U256::from(10).pow(U256::from(from - to)),
+        Ordering::Less => amount.saturating_mul(U256::from(10).pow(U256::from(to - from))),
+    }
+}
+
+/// Applies each scenario's gas cost to a gross profit computed once at the optimal input.
+/// `gas_costs` are `(label, cost)` pairs in the profit token.
+pub fn scenario_results(
+    gross_profit: U256,
+    flashloan_fee: U256,
+    min_net_profit: U256,
+    gas_costs: &[(String, U256)],
+) -> Vec<ScenarioResult> {
+    gas_costs
+        .iter()
+        .map(|(label, gas_cost)| {
+            let total_cost = flashloan_fee.saturating_add(*gas_cost);
+            let net_profit = gross_profit.saturating_sub(total_cost);
+            ScenarioResult {
+                label: label.clone(),
+                gas_cost: *gas_cost,
+                net_profit,
+                passes: gross_profit >= total_cost && net_profit >= min_net_profit,
+            }
+        })
+        .collect()
+}
+
+/// What [`find_optimal_input`] searches over: a path's output for an input, quoted
+/// synchronously over fixed pool state. [`QuotePath`] is the one the engine searches.
+pub trait ProfitCurve {
+    fn calculate_out_amount(&self, amount_in: U256) -> Result<U256, ArbRsError>;
+
+    /// The smallest input that gets `target_out` back, to cross-check the optimum with.
+    /// Curves without an exact-output quote aren't cross-checked.
+    fn calculate_in_amount(&self, _target_out: U256) -> Result<U256, ArbRsError> {
+        Err(ArbRsError::CalculationError("No exact-output quote".to_string()))
+    }
+
+    fn pool_addresses(&self) -> Vec<Address> {
+        Vec::new()
+    }
+}
+
+impl ProfitCurve for QuotePath {
+    fn calculate_out_amount(&self, amount_in: U256) -> Result<U256, ArbRsError> {
+        QuotePath::calculate_out_amount(self, amount_in)
+    }
 
-        c = b - (b - a) * INV_PHI_SCALED / SCALE;
-        d = a + (b - a) * INV_PHI_SCALED / SCALE;
+    fn calculate_in_amount(&self, target_out: U256) -> Result<U256, ArbRsError> {
+        QuotePath::calculate_in_amount(self, target_out)
     }
 
-    let optimal_input = (a + b) / U256::from(2);
-    let max_profit = path
-        .calculate_out_amount(optimal_input, snapshots)?
-        .saturating_sub(optimal_input);
+    fn pool_addresses(&self) -> Vec<Address> {
+        QuotePath::pool_addresses(self)
+    }
+}
 
-    Ok((optimal_input, max_profit))
+/// The input [`find_optimal_input`] settled on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OptimalInput {
+    pub input: U256,
+    pub gross_profit: U256,
+    /// The grid probes found separate profit peaks, so `input` is the best probe rather
+    /// than a refined optimum: a bracket around one peak could miss a higher one.
+    pub multimodal: bool,
 }
 
-pub fn find_max_capacity<P>(
-    path: &Arc<dyn Arbitrage<P>>,
+/// Evaluates the gross profit for an input, returning `None` when a pool along the path
+/// cannot fill the amount. Partial fills mark an upper bound for the search rather than
+/// a failure of the whole path.
+fn gross_profit_or_partial<C: ProfitCurve + ?Sized>(
+    path: &C,
+    amount_in: U256,
+) -> Result<Option<U256>, ArbRsError> {
+    match path.calculate_out_amount(amount_in) {
+        Ok(amount_out) => Ok(Some(amount_out.saturating_sub(amount_in))),
+        Err(ArbRsError::PartialFill { .. }) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// `probes` inputs from `a` to `b`, both included, spaced evenly in log scale from
+/// `max(a, 1)`. Fewer if the range is too narrow to hold that many distinct inputs.
+pub fn log_grid(a: U256, b: U256, probes: usize) -> Vec<U256> {
+    let low = a.max(U256::ONE);
+    let mut grid = vec![a];
+    if b > low && probes > 2 {
+        // Each probe is `low * 2^exponent`, its fractional power of two scaled by `SCALE`.
+        let octaves = u256_to_f64(b).log2() - u256_to_f64(low).log2();
+        for i in 1..probes - 1 {
+            let exponent = octaves * i as f64 / (probes - 1) as f64;
+            let whole = exponent.floor();
+            let fraction = U256::from(((exponent - whole).exp2() * 1e6) as u64);
+            let probe = low
+                .checked_shl(whole as usize)
+                .and_then(|shifted| mul_div(shifted, fraction, SCALE))
+                .unwrap_or(b);
+            grid.push(probe.clamp(a, b));
+        }
+    }
+    grid.push(b);
+    grid.dedup();
+    grid
+}
+
+/// Whether the profit dips by more than `PEAK_DIP_BPS` of the best profit between two
+/// higher probes, i.e. the probes see more than one peak.
+fn has_separate_peaks(profits: &[U256]) -> bool {
+    let Some(best) = profits.iter().max() else {
+        return false;
+    };
+    let threshold = mul_div(*best, PEAK_DIP_BPS, BPS_DENOMINATOR).unwrap_or_default();
+    let mut right_max = vec![U256::ZERO; profits.len()];
+    for i in (0..profits.len().saturating_sub(1)).rev() {
+        right_max[i] = right_max[i + 1].max(profits[i + 1]);
+    }
+    let mut left_max = U256::ZERO;
+    for (i, profit) in profits.iter().enumerate() {
+        let rim = left_max.min(right_max[i]);
+        if rim.saturating_sub(*profit) > threshold {
+            return true;
+        }
+        left_max = left_max.max(*profit);
+    }
+    false
+}
+
+/// Finds the optimal input amount for a given arbitrage path over `[a, b]`. `config` gives
+/// the stopping rule, in raw units of the profit token.
+///
+/// [`GRID_PROBES`] log-spaced probes bracket the best region first, and a golden-section
+/// search refines the best probe within its neighbours. When the probes see separate peaks
+/// the best probe is returned as it is, flagged as [`OptimalInput::multimodal`], since the
+/// refinement assumes one peak.
+pub fn find_optimal_input<C: ProfitCurve + ?Sized>(
+    path: &C,
+    a: U256,
+    b: U256,
+    config: &OptimizerConfig,
+) -> Result<OptimalInput, ArbRsError> {
+    let tolerance = config.tolerance.max(U256::ONE);
+
+    // Probes stop at the first input the path can't fill, which bounds the search.
+    let mut probes: Vec<(U256, U256)> = Vec::with_capacity(GRID_PROBES);
+    let mut bound = b;
+    for input in log_grid(a, b, GRID_PROBES) {
+        match gross_profit_or_partial(path, input)? {
+            Some(profit) => probes.push((input, profit)),
+            None => {
+                bound = input;
+                break;
+            }
+        }
+    }
+    let Some(best) = (0..probes.len()).reduce(|best, i| if probes[i].1 > probes[best].1 { i } else { best }) else {
+        crate::metrics::record_optimizer_iterations(0);
+        return Ok(OptimalInput { input: a, gross_profit: U256::ZERO, multimodal: false });
+    };
+    let profits: Vec<U256> = probes.iter().map(|(_, profit)| *profit).collect();
+    if has_separate_peaks(&profits) {
+        crate::metrics::record_optimizer_iterations(0);
+        let (input, gross_profit) = probes[best];
+        return Ok(OptimalInput { input, gross_profit, multimodal: true });
+    }
+
+    // The peak lies between the best probe's neighbours.
+    let mut a = if best == 0 { a } else { probes[best - 1].0 };
+    let mut b = probes.get(best + 1).map_or(bound, |(input, _)| *input);
+    let (mut best_input, mut best_profit) = probes[best];
+    let mut consider = |input: U256, profit: Option<U256>| {
+        if let Some(profit) = profit
+            && profit > best_profit
+        {
+            (best_input, best_profit) = (input, profit);
+        }
+    };
+
+    // Scaled with `mul_div`, so bounds near `U256::MAX` don't overflow.
+    let step = |a: U256, b: U256| mul_div(b - a, INV_PHI_SCALED, SCALE).unwrap_or_default();
+
+    let mut c = b - step(a, b);
+    let mut d = a + step(a, b);
+
+    let mut iterations = 0;
+    while iterations < config.max_iterations {
+        if (b - a) <= tolerance {
+            break;
+        }
+        iterations += 1;
+        let profit_c = gross_profit_or_partial(path, c)?;
+        let profit_d = gross_profit_or_partial(path, d)?;
+        consider(c, profit_c);
+        consider(d, profit_d);
+
+        match (profit_c, profit_d) {
+            // `c` can't be filled either, so everything above it is out of range
+            (None, _) => b = c,
+            (Some(_), None) => b = d,
+            // Ties go left: past the profitable range both sides are zero, and moving right
+            // would walk the search out along that plateau.
+            (Some(profit_c), Some(profit_d)) => {
+                if profit_c >= profit_d {
+                    b = d;
+                } else {
+                    a = c;
+                }
+            }
+        }
+
+        c = b - step(a, b);
+        d = a + step(a, b);
+    }
+
+    crate::metrics::record_optimizer_iterations(iterations);
+
+    if iterations > 0 {
+        let midpoint = a + (b - a) / U256::from(2);
+        consider(midpoint, gross_profit_or_partial(path, midpoint)?);
+    }
+    if !best_profit.is_zero() {
+        check_reverse_quote(path, best_input, best_input + best_profit, tolerance);
+    }
+
+    Ok(OptimalInput { input: best_input, gross_profit: best_profit, multimodal: false })
+}
+
+/// Cross-checks an optimum against the path's exact-output quote: the input it takes to get
+/// `gross_output` back can't exceed `optimal_input`, and shouldn't fall short of it by more
+/// than `tolerance`. Disagreement usually means a pool type's forward and reverse math don't
+/// match. Paths with a hop that can't quote an exact output aren't checked.
+fn check_reverse_quote<C: ProfitCurve + ?Sized>(path: &C, optimal_input: U256, gross_output: U256, tolerance: U256) {
+    match path.calculate_in_amount(gross_output) {
+        Ok(implied_input) if implied_input > optimal_input || optimal_input - implied_input > tolerance => {
+            tracing::warn!(
+                pools = ?path.pool_addresses(),
+                %optimal_input,
+                %implied_input,
+                "The input implied by the optimum's output disagrees with the optimal input; a pool's exact-output math may be off."
+            );
+        }
+        Ok(_) => {}
+        Err(e) => tracing::debug!(pools = ?path.pool_addresses(), "Optimal input not cross-checked: {:?}", e),
+    }
+}
+
+pub fn find_max_capacity<C: ProfitCurve + ?Sized>(
+    path: &C,
     mut a: U256,
     mut b: U256,
-    snapshots: &HashMap<Address, PoolSnapshot>,
     min_net_profit: U256,
     gas_cost_in_profit_token: U256,
-) -> Result<U256, ArbRsError>
-where
-    P: Provider + Send + Sync + 'static + ?Sized,
-{
-    let calculate_net_profit = |x: U256| -> Result<U256, ArbRsError> {
-        if x.is_zero() { return Ok(U256::ZERO); }
+) -> Result<U256, ArbRsError> {
+    // A partially filled input counts as unprofitable, which pulls the upper bound below it.
+    // So does one whose costs exceed its gross profit, even when the minimum is zero.
+    let calculate_net_profit = |x: U256| -> Result<Option<U256>, ArbRsError> {
+        if x.is_zero() { return Ok(None); }
 
-        let gross_out = path.calculate_out_amount(x, snapshots)?;
-        let gross_profit = gross_out.saturating_sub(x);
+        let Some(gross_profit) = gross_profit_or_partial(path, x)? else {
+            return Ok(None);
+        };
 
         let flashloan_fee = x
             .checked_mul(FLASHLOAN_FEE_BPS)
@@ -73,13 +370,18 @@ where
             
         let total_cost = gas_cost_in_profit_token.saturating_add(flashloan_fee);
         
-        Ok(gross_profit.saturating_sub(total_cost))
+        Ok(gross_profit.checked_sub(total_cost))
     };
-    if calculate_net_profit(b)? < min_net_profit {
-        let gross_a = path.calculate_out_amount(a, snapshots)?.saturating_sub(a);
-        if gross_a.saturating_sub(calculate_net_profit(a)?) < min_net_profit {
-             return Ok(U256::ZERO);
-        }
+    let clears_minimum = |x: U256| -> Result<bool, ArbRsError> {
+        Ok(calculate_net_profit(x)?.is_some_and(|net_profit| net_profit >= min_net_profit))
+    };
+    // The whole range is profitable, so the bound itself is the capacity.
+    if clears_minimum(b)? {
+        return Ok(b);
+    }
+    let gross_a = gross_profit_or_partial(path, a)?.unwrap_or_default();
+    if gross_a.saturating_sub(calculate_net_profit(a)?.unwrap_or_default()) < min_net_profit {
+         return Ok(U256::ZERO);
     }
 
     let tolerance = U256::from_limbs([10_000_000_000_000_000, 0, 0, 0]);
@@ -96,9 +398,7 @@ where
         let mid = (high.saturating_add(low)) / U256::from(2);
         if mid.is_zero() { break; }
 
-        let net_profit_mid = calculate_net_profit(mid)?;
-
-        if net_profit_mid >= min_net_profit {
+        if clears_minimum(mid)? {
             max_capacity = mid; 
             low = mid;
         } else {
@@ -108,3 +408,305 @@ where
 
     Ok(max_capacity) 
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GWEI: u64 = 1_000_000_000;
+
+    fn usdc_rate() -> U256 {
+        // 2000 USDC per ETH, scaled by 1e18.
+        U256::from(2_000) * ETHER_SCALE
+    }
+
+    #[test]
+    fn test_gas_cost_is_not_rescaled() {
+        let cost = gas_cost_wei(U256::from(700_000), U256::from(30 * GWEI));
+        assert_eq!(cost, U256::from(21_000_000_000_000_000u64)); // 0.021 ETH
+    }
+
+    #[test]
+    fn test_wei_to_token_units() {
+        let cost = U256::from(21_000_000_000_000_000u64);
+        // 0.021 ETH at 2000 USDC/ETH is 42 USDC.
+        assert_eq!(wei_to_token_units(cost, usdc_rate(), 6), U256::from(42_000_000u64));
+        // WETH converts at exactly 1e18.
+        assert_eq!(wei_to_token_units(cost, ETHER_SCALE, 18), cost);
+        // 0.021 ETH at 0.05 WBTC/ETH is 0.00105 WBTC.
+        let wbtc_rate = ETHER_SCALE / U256::from(20);
+        assert_eq!(wei_to_token_units(cost, wbtc_rate, 8), U256::from(105_000u64));
+    }
+
+    #[test]
+    fn test_weth_price_scaled_from_a_quote() {
+        // 0.01 ETH buying 20 USDC, or 0.0005 WBTC.
+        let probe = ETHER_SCALE / U256::from(100);
+        assert_eq!(weth_price_scaled(probe, U256::from(20_000_000u64), 6), usdc_rate());
+        assert_eq!(weth_price_scaled(probe, U256::from(50_000u64), 8), ETHER_SCALE / U256::from(20));
+        assert_eq!(weth_price_scaled(U256::ZERO, U256::from(1), 6), U256::ZERO);
+    }
+
+    #[test]
+    fn test_scenario_results() {
+        // 0.021 ETH of gas at 30 gwei; the path clears it 1.5 times over after the fee.
+        let base_gas = gas_cost_wei(U256::from(700_000), U256::from(30 * GWEI));
+        let flashloan_fee = U256::from(900_000_000_000_000u64);
+        let gross_profit = flashloan_fee + base_gas * U256::from(3) / U256::from(2);
+        let gas_costs = [
+            ("base".to_string(), base_gas),
+            ("+25%".to_string(), base_gas * U256::from(5) / U256::from(4)),
+            ("+100%".to_string(), base_gas * U256::from(2)),
+        ];
+
+        let results = scenario_results(gross_profit, flashloan_fee, U256::ZERO, &gas_costs);
+        let summary: Vec<_> = results
+            .iter()
+            .map(|result| (result.label.as_str(), result.net_profit, result.passes))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                ("base", U256::from(10_500_000_000_000_000u64), true),
+                ("+25%", U256::from(5_250_000_000_000_000u64), true),
+                ("+100%", U256::ZERO, false),
+            ]
+        );
+
+        // The minimum net profit applies to every scenario.
+        let min_net_profit = U256::from(6_000_000_000_000_000u64);
+        let results = scenario_results(gross_profit, flashloan_fee, min_net_profit, &gas_costs);
+        assert_eq!(
+            results.iter().map(|result| result.passes).collect::<Vec<_>>(),
+            [true, false, false]
+        );
+    }
+
+    use crate::arbitrage::{cycle::ArbitrageCycle, types::Arbitrage};
+    use crate::pool::PoolSnapshot;
+    use alloy_primitives::Address;
+    use alloy_provider::Provider;
+    use std::{collections::HashMap, sync::Arc};
+
+    type DynProvider = dyn Provider + Send + Sync;
+
+    /// A WETH -> TKN -> WETH cycle buying TKN where it's 5% cheaper, with its optimal input
+    /// from the closed form for two constant-product pools.
+    fn two_pool_cycle() -> (ArbitrageCycle<DynProvider>, HashMap<Address, PoolSnapshot>, f64) {
+        use crate::arbitrage::types::ArbitragePath;
+        use crate::core::token::{Erc20Data, Token};
+        use crate::pool::{
+            LiquidityPool, strategy::StandardV2Logic, uniswap_v2::UniswapV2Pool,
+            uniswap_v2::UniswapV2PoolState,
+        };
+        use alloy::transports::mock::Asserter;
+        use alloy_provider::ProviderBuilder;
+
+        let provider: Arc<DynProvider> =
+            Arc::new(ProviderBuilder::new().connect_mocked_client(Asserter::new()));
+        let token = |byte: u8| {
+            Arc::new(Token::Erc20(Arc::new(Erc20Data::new(
+                Address::repeat_byte(byte),
+                "TKN".to_string(),
+                "TKN".to_string(),
+                18,
+                provider.clone(),
+            ))))
+        };
+        let (weth, tkn) = (token(0x0a), token(0x0b));
+        let pool = |byte: u8| -> Arc<dyn LiquidityPool<DynProvider>> {
+            Arc::new(UniswapV2Pool::new(
+                Address::repeat_byte(byte),
+                weth.clone(),
+                tkn.clone(),
+                provider.clone(),
+                StandardV2Logic,
+            ))
+        };
+        let path = ArbitrageCycle::new(ArbitragePath {
+            pools: vec![pool(0x01), pool(0x02)],
+            path: vec![weth.clone(), tkn.clone(), weth.clone()],
+            profit_token: weth.clone(),
+        });
+
+        let (a1, b1, a2, b2) = (1_000.0, 2_100_000.0, 1_000.0, 2_000_000.0);
+        let reserves = |weth: f64, tkn: f64| {
+            PoolSnapshot::UniswapV2(UniswapV2PoolState {
+                reserve0: U256::from(weth as u64) * ETHER_SCALE,
+                reserve1: U256::from(tkn as u64) * ETHER_SCALE,
+                block_number: 1,
+            })
+        };
+        let snapshots = HashMap::from([
+            (Address::repeat_byte(0x01), reserves(a1, b1)),
+            (Address::repeat_byte(0x02), reserves(a2, b2)),
+        ]);
+
+        // The cycle quotes like one pool, `out = k * x / (m + n * x)`, whose profit peaks
+        // where `k * m = (m + n * x)^2`.
+        let f = 0.997;
+        let (k, m, n) = (f * f * b1 * a2, a1 * b2, f * (b2 + f * b1));
+        let optimum = ((k * m).sqrt() - m) / n * 1e18;
+        (path, snapshots, optimum)
+    }
+
+    #[test]
+    fn test_optimal_input_matches_the_analytic_optimum() {
+        let (path, snapshots, optimum) = two_pool_cycle();
+        let config = OptimizerConfig::default().for_token(18, U256::from(1_000) * ETHER_SCALE);
+        assert_eq!(config.max_input, Some(U256::from(300) * ETHER_SCALE));
+
+        let OptimalInput { input, gross_profit: profit, multimodal } = find_optimal_input(
+            &path.quote_path(&snapshots).unwrap(),
+            config.min_input,
+            config.max_input.unwrap(),
+            &config,
+        )
+        .unwrap();
+        assert!(!multimodal);
+        let error = (f64::from(input) - optimum).abs();
+        assert!(error <= f64::from(config.tolerance), "input {input}, optimum {optimum}");
+        assert_eq!(
+            profit,
+            path.calculate_out_amount(input, &snapshots).unwrap() - input
+        );
+
+        // Neighbouring inputs a tolerance away do no better.
+        for neighbour in [input - config.tolerance, input + config.tolerance] {
+            let out = path.calculate_out_amount(neighbour, &snapshots).unwrap();
+            assert!(out.saturating_sub(neighbour) <= profit);
+        }
+    }
+
+    #[test]
+    fn test_reverse_quote_agrees_with_the_forward_one() {
+        let (path, snapshots, _) = two_pool_cycle();
+        let quotes = path.quote_path(&snapshots).unwrap();
+        for input in [1, 1_000, 10u64.pow(15), 10u64.pow(18), 5 * 10u64.pow(18)].map(U256::from) {
+            let out = path.calculate_out_amount(input, &snapshots).unwrap();
+            let implied_input = path.calculate_in_amount(out, &snapshots).unwrap();
+            assert!(implied_input <= input, "{implied_input} > {input}");
+            assert_eq!(path.calculate_out_amount(implied_input, &snapshots).unwrap(), out);
+            // The quoters quote exactly as the pools do.
+            assert_eq!(quotes.calculate_out_amount(input).unwrap(), out);
+            assert_eq!(quotes.calculate_in_amount(out).unwrap(), implied_input);
+        }
+
+        // The last pool holds 1000 WETH, so it can't pay out more.
+        let error = path
+            .calculate_in_amount(U256::from(1_000) * ETHER_SCALE, &snapshots)
+            .unwrap_err();
+        assert!(
+            matches!(&error, ArbRsError::CalculationError(message) if message.starts_with("Hop 1 ")),
+            "{error:?}"
+        );
+    }
+
+    #[test]
+    fn test_search_stops_after_max_iterations() {
+        let (path, snapshots, _) = two_pool_cycle();
+        let config = OptimizerConfig {
+            max_iterations: 0,
+            ..Default::default()
+        };
+        let path = path.quote_path(&snapshots).unwrap();
+        let optimum = find_optimal_input(&path, U256::ZERO, ETHER_SCALE * U256::from(2), &config).unwrap();
+
+        // Without a golden-section step, the best grid probe is returned unrefined.
+        let best_probe = log_grid(U256::ZERO, ETHER_SCALE * U256::from(2), GRID_PROBES)
+            .into_iter()
+            .max_by_key(|input| path.calculate_out_amount(*input).unwrap().saturating_sub(*input))
+            .unwrap();
+        assert_eq!(optimum.input, best_probe);
+        assert!(!optimum.multimodal);
+    }
+
+    #[test]
+    fn test_log_grid_spans_the_range() {
+        let grid = log_grid(ETHER_SCALE / U256::from(10), U256::from(100) * ETHER_SCALE, GRID_PROBES);
+        assert_eq!(grid.len(), GRID_PROBES);
+        assert_eq!(grid[0], ETHER_SCALE / U256::from(10));
+        assert_eq!(grid[GRID_PROBES - 1], U256::from(100) * ETHER_SCALE);
+        // A thousandfold range in 15 steps, each about 1.585 times the last.
+        for pair in grid.windows(2) {
+            let ratio = u256_to_f64(pair[1]) / u256_to_f64(pair[0]);
+            assert!((ratio - 1000f64.powf(1.0 / 15.0)).abs() < 1e-3, "{ratio}");
+        }
+
+        // A range holding fewer inputs than probes yields each once.
+        assert_eq!(log_grid(U256::from(3), U256::from(5), GRID_PROBES), [3, 4, 5].map(U256::from));
+        assert_eq!(log_grid(U256::ZERO, U256::ZERO, GRID_PROBES), [U256::ZERO]);
+    }
+
+    /// Profit made of triangular peaks, each `(center, half_width, height)` in whole tokens
+    /// scaled by 1e18, zero between them.
+    struct Peaks(Vec<(f64, f64, f64)>);
+
+    impl ProfitCurve for Peaks {
+        fn calculate_out_amount(&self, amount_in: U256) -> Result<U256, ArbRsError> {
+            let x = u256_to_f64(amount_in) / 1e18;
+            let profit = self
+                .0
+                .iter()
+                .map(|(center, half_width, height)| height * (1.0 - (x - center).abs() / half_width).max(0.0))
+                .fold(0.0, f64::max);
+            Ok(amount_in + U256::from((profit * 1e18) as u128))
+        }
+    }
+
+    #[test]
+    fn test_separate_peaks_return_the_higher_one() {
+        let config = OptimizerConfig::default();
+        let (min, max) = (ETHER_SCALE / U256::from(10), U256::from(100) * ETHER_SCALE);
+
+        // A narrow peak at 1 token and a higher, wider one at 40, with nothing between.
+        let curve = Peaks(vec![(1.0, 0.5, 0.01), (40.0, 20.0, 0.03)]);
+        let optimum = find_optimal_input(&curve, min, max, &config).unwrap();
+        assert!(optimum.multimodal);
+        assert!(optimum.input > U256::from(20) * ETHER_SCALE && optimum.input < U256::from(60) * ETHER_SCALE);
+        assert_eq!(optimum.gross_profit, curve.calculate_out_amount(optimum.input).unwrap() - optimum.input);
+        assert!(optimum.gross_profit > ETHER_SCALE / U256::from(100));
+
+        // The same with the peaks' heights swapped.
+        let curve = Peaks(vec![(1.0, 0.5, 0.03), (40.0, 20.0, 0.01)]);
+        let optimum = find_optimal_input(&curve, min, max, &config).unwrap();
+        assert!(optimum.multimodal);
+        assert!(optimum.input < ETHER_SCALE * U256::from(3) / U256::from(2));
+
+        // One peak is refined to the tolerance.
+        let curve = Peaks(vec![(40.0, 20.0, 0.03)]);
+        let optimum = find_optimal_input(&curve, min, max, &config).unwrap();
+        assert!(!optimum.multimodal);
+        let distance = optimum.input.abs_diff(U256::from(40) * ETHER_SCALE);
+        assert!(distance <= config.tolerance, "{}", optimum.input);
+    }
+
+    #[test]
+    fn test_config_is_scaled_to_the_token() {
+        let config = OptimizerConfig::default();
+        let usdc = config.for_token(6, U256::from(2_000_000_000_000u64));
+        assert_eq!(usdc.min_input, U256::from(100_000));
+        assert_eq!(usdc.tolerance, U256::from(1_000));
+        // 30% of 2M USDC.
+        assert_eq!(usdc.max_input, Some(U256::from(600_000_000_000u64)));
+
+        // An explicit max wins over the reserve, and the tolerance never rounds to zero.
+        let wbtc = OptimizerConfig {
+            max_input: Some(U256::from(5) * ETHER_SCALE),
+            tolerance: U256::ONE,
+            ..config
+        }
+        .for_token(8, U256::MAX);
+        assert_eq!(wbtc.max_input, Some(U256::from(500_000_000u64)));
+        assert_eq!(wbtc.tolerance, U256::ONE);
+    }
+
+    #[test]
+    fn test_token_units_to_wei() {
+        assert_eq!(
+            token_units_to_wei(U256::from(42_000_000u64), usdc_rate(), 6),
+            U256::from(21_000_000_000_000_000u64)
+        );
+        assert_eq!(token_units_to_wei(U256::from(1), U256::ZERO, 18), U256::ZERO);
+    }
+}
@@ -0,0 +1,136 @@
+use crate::arbitrage::types::CycleId;
+use alloy_primitives::U256;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// Cycles tracked at most by a default [`PersistenceTracker`].
+pub const DEFAULT_MAX_TRACKED: usize = 10_000;
+
+/// How the engine treats cycles that have only just turned profitable. One-block
+/// opportunities are usually a single imbalanced trade that is gone before anything lands.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PersistencePolicy {
+    #[default]
+    ReportAll,
+    /// Only report cycles profitable for at least `min_blocks` consecutive evaluations.
+    Suppress { min_blocks: u64 },
+    /// Report every cycle, but rank those below `min_blocks` after the established ones.
+    DownRank { min_blocks: u64 },
+}
+
+impl PersistencePolicy {
+    /// Whether a cycle profitable for `persistence_blocks` evaluations is ranked normally.
+    pub fn is_established(&self, persistence_blocks: u64) -> bool {
+        match *self {
+            PersistencePolicy::ReportAll => true,
+            PersistencePolicy::Suppress { min_blocks }
+            | PersistencePolicy::DownRank { min_blocks } => persistence_blocks >= min_blocks,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PersistenceRecord {
+    /// Block the current streak started at, when evaluations are pinned to a block.
+    pub first_seen_block: Option<u64>,
+    /// Consecutive evaluations the cycle has been profitable in, including the latest.
+    pub consecutive_blocks: u64,
+    /// Highest net profit seen during the streak, in the profit token of the reported entry.
+    pub peak_net_profit: U256,
+}
+
+/// Profitable streaks of the cycles that were profitable in the latest evaluation.
+#[derive(Debug)]
+pub struct PersistenceTracker {
+    max_tracked: usize,
+    records: Mutex<HashMap<CycleId, PersistenceRecord>>,
+}
+
+impl Default for PersistenceTracker {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_TRACKED)
+    }
+}
+
+impl PersistenceTracker {
+    pub fn new(max_tracked: usize) -> Self {
+        Self {
+            max_tracked,
+            records: Mutex::default(),
+        }
+    }
+
+    /// Records one evaluation's profitable cycles and their net profit, returning each
+    /// cycle's streak. Every other cycle loses its streak, including those that have left
+    /// the cache. Beyond `max_tracked` cycles, the longest streaks are kept.
+    pub fn update(
+        &self,
+        block_number: Option<u64>,
+        profitable: &[(CycleId, U256)],
+    ) -> HashMap<CycleId, u64> {
+        let Ok(mut records) = self.records.lock() else {
+            return profitable
+                .iter()
+                .map(|(cycle_id, _)| (cycle_id.clone(), 1))
+                .collect();
+        };
+
+        let mut next: HashMap<CycleId, PersistenceRecord> = profitable
+            .iter()
+            .map(|(cycle_id, net_profit)| {
+                let record = match records.get(cycle_id) {
+                    Some(record) => PersistenceRecord {
+                        consecutive_blocks: record.consecutive_blocks + 1,
+                        peak_net_profit: record.peak_net_profit.max(*net_profit),
+                        ..*record
+                    },
+                    None => PersistenceRecord {
+                        first_seen_block: block_number,
+                        consecutive_blocks: 1,
+                        peak_net_profit: *net_profit,
+                    },
+                };
+                (cycle_id.clone(), record)
+            })
+            .collect();
+        let streaks = next
+            .iter()
+            .map(|(cycle_id, record)| (cycle_id.clone(), record.consecutive_blocks))
+            .collect();
+
+        if next.len() > self.max_tracked {
+            let mut longest: Vec<_> = next.into_iter().collect();
+            longest.sort_by(|a, b| {
+                b.1.consecutive_blocks
+                    .cmp(&a.1.consecutive_blocks)
+                    .then_with(|| a.0.cmp(&b.0))
+            });
+            longest.truncate(self.max_tracked);
+            next = longest.into_iter().collect();
+        }
+        *records = next;
+        streaks
+    }
+
+    pub fn record(&self, cycle_id: &CycleId) -> Option<PersistenceRecord> {
+        self.records.lock().ok()?.get(cycle_id).copied()
+    }
+
+    /// Up to `limit` currently profitable cycles, longest streak first.
+    pub fn longest(&self, limit: usize) -> Vec<(CycleId, PersistenceRecord)> {
+        let Ok(records) = self.records.lock() else {
+            return Vec::new();
+        };
+        let mut longest: Vec<_> = records
+            .iter()
+            .map(|(cycle_id, record)| (cycle_id.clone(), *record))
+            .collect();
+        longest.sort_by(|a, b| {
+            b.1.consecutive_blocks
+                .cmp(&a.1.consecutive_blocks)
+                .then_with(|| a.0.cmp(&b.0))
+        });
+        longest.truncate(limit);
+        longest
+    }
+}
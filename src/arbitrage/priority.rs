@@ -0,0 +1,107 @@
+use crate::arbitrage::types::{Arbitrage, PathKey};
+use crate::math::utils::u256_to_f64;
+use alloy_primitives::{Address, U256};
+use alloy_provider::Provider;
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// Weight of the latest evaluation in a path's running profit average by default.
+pub const DEFAULT_PROFIT_SMOOTHING: f64 = 0.3;
+/// Averages below one wei are forgotten, so paths that left the cache aren't kept forever.
+const MIN_TRACKED_SCORE: f64 = 1.0;
+
+/// Orders the paths of an evaluation so that, when its time budget runs out, the paths
+/// left unevaluated are the least promising ones.
+///
+/// Paths through a pool that changed since the last evaluation come first, then paths by
+/// an exponentially weighted moving average of the net profit, in wei, they were last
+/// seen with. Ties keep the cache's order.
+#[derive(Debug)]
+pub struct PathPriority {
+    /// Weight of the latest evaluation in the average, between 0 and 1.
+    smoothing: f64,
+    scores: Mutex<HashMap<PathKey, f64>>,
+}
+
+impl Default for PathPriority {
+    fn default() -> Self {
+        Self::new(DEFAULT_PROFIT_SMOOTHING)
+    }
+}
+
+impl PathPriority {
+    pub fn new(smoothing: f64) -> Self {
+        Self {
+            smoothing: smoothing.clamp(0.0, 1.0),
+            scores: Mutex::default(),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<PathKey, f64>> {
+        self.scores
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// The path's average net profit, in wei, or zero if it hasn't been profitable lately.
+    pub fn score(&self, key: &PathKey) -> f64 {
+        self.lock().get(key).copied().unwrap_or(0.0)
+    }
+
+    /// Indices of `paths` in the order to evaluate them in: through a pool of
+    /// `changed_pools` first, then by score.
+    pub fn order<P: Provider + Send + Sync + 'static + ?Sized>(
+        &self,
+        paths: &[Arc<dyn Arbitrage<P>>],
+        changed_pools: &HashSet<Address>,
+    ) -> Vec<usize> {
+        let scores = self.lock();
+        let ranks: Vec<(bool, f64)> = paths
+            .iter()
+            .map(|path| {
+                let changed = path
+                    .get_involved_pools()
+                    .iter()
+                    .any(|pool| changed_pools.contains(pool));
+                let score = scores.get(&path.path_key()).copied().unwrap_or(0.0);
+                (changed, score)
+            })
+            .collect();
+        drop(scores);
+
+        let mut order: Vec<usize> = (0..paths.len()).collect();
+        order.sort_by(|&a, &b| {
+            let ((a_changed, a_score), (b_changed, b_score)) = (ranks[a], ranks[b]);
+            b_changed
+                .cmp(&a_changed)
+                .then_with(|| b_score.partial_cmp(&a_score).unwrap_or(Ordering::Equal))
+        });
+        order
+    }
+
+    /// Folds one evaluation into the averages: each evaluated path with the net profit, in
+    /// wei, of its best solution, zero if it had none. Paths left unevaluated keep theirs.
+    pub fn record(&self, evaluated: impl IntoIterator<Item = (PathKey, U256)>) {
+        let mut scores = self.lock();
+        for (key, net_profit_wei) in evaluated {
+            let previous = scores.get(&key).copied().unwrap_or(0.0);
+            let score =
+                self.smoothing * u256_to_f64(net_profit_wei) + (1.0 - self.smoothing) * previous;
+            if score < MIN_TRACKED_SCORE {
+                scores.remove(&key);
+            } else {
+                scores.insert(key, score);
+            }
+        }
+    }
+
+    /// Paths with a non-zero average.
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lock().is_empty()
+    }
+}
@@ -0,0 +1,98 @@
+use crate::{
+    arbitrage::{calibration::apply_haircut, types::TokenRef},
+    core::token::{received_amount, sent_amount_for},
+    errors::ArbRsError,
+    pool::quoter::{QuotePool, Quoter},
+};
+use alloy_primitives::{Address, U256};
+
+/// A cycle's pools as quoters over one set of snapshots, with its tokens detached from the
+/// provider. What the optimizer and the engine's blocking evaluation quote through, built
+/// by [`ArbitrageCycle::quote_path`](crate::arbitrage::cycle::ArbitrageCycle::quote_path).
+#[derive(Debug, Clone)]
+pub struct QuotePath {
+    pub pools: Vec<QuotePool>,
+    /// The tokens traded, the profit token first and last, one more than `pools`.
+    pub tokens: Vec<TokenRef>,
+    /// Transfer tax of each of `tokens`, in basis points, read when the path was built.
+    pub transfer_tax_bps: Vec<u32>,
+}
+
+impl QuotePath {
+    pub fn pool_addresses(&self) -> Vec<Address> {
+        self.pools.iter().map(QuotePool::address).collect()
+    }
+
+    /// The output of trading `start_amount` through every hop, net of transfer taxes.
+    pub fn calculate_out_amount(&self, start_amount: U256) -> Result<U256, ArbRsError> {
+        self.calculate_out_amount_with_haircuts(start_amount, &[])
+    }
+
+    /// Like `calculate_out_amount`, with each hop's output reduced by the matching entry of
+    /// `haircuts_bps`. Missing entries apply no haircut.
+    pub fn calculate_out_amount_with_haircuts(
+        &self,
+        start_amount: U256,
+        haircuts_bps: &[u64],
+    ) -> Result<U256, ArbRsError> {
+        if start_amount.is_zero() {
+            return Ok(U256::ZERO);
+        }
+        let mut current_amount = start_amount;
+        for (i, pool) in self.pools.iter().enumerate() {
+            let amount_in = received_amount(current_amount, self.transfer_tax_bps[i]);
+            current_amount = apply_haircut(
+                pool.calculate_out(
+                    self.tokens[i].address,
+                    self.tokens[i + 1].address,
+                    amount_in,
+                )?,
+                haircuts_bps.get(i).copied().unwrap_or(0),
+            );
+            if current_amount.is_zero() {
+                break;
+            }
+        }
+        Ok(received_amount(
+            current_amount,
+            self.transfer_tax_bps[self.pools.len()],
+        ))
+    }
+
+    /// The smallest input the path turns into at least `target_out`, walked backwards from
+    /// the last hop. Fails on the first hop, counted from the end, whose pool can't quote an
+    /// exact output.
+    pub fn calculate_in_amount(&self, target_out: U256) -> Result<U256, ArbRsError> {
+        if target_out.is_zero() {
+            return Ok(U256::ZERO);
+        }
+        let sent_amount_for = |amount: U256, i: usize| {
+            sent_amount_for(amount, self.transfer_tax_bps[i]).ok_or_else(|| {
+                ArbRsError::CalculationError(format!(
+                    "No transfer of {} gets through its transfer tax",
+                    self.tokens[i].address
+                ))
+            })
+        };
+        let mut current_amount = sent_amount_for(target_out, self.pools.len())?;
+        for (i, pool) in self.pools.iter().enumerate().rev() {
+            let amount_in = pool
+                .calculate_in(
+                    self.tokens[i].address,
+                    self.tokens[i + 1].address,
+                    current_amount,
+                )
+                .map_err(|e| {
+                    ArbRsError::CalculationError(format!(
+                        "Hop {} through pool {} can't quote an exact output of {}: {}",
+                        i,
+                        pool.address(),
+                        current_amount,
+                        e
+                    ))
+                })?;
+            current_amount = sent_amount_for(amount_in, i)?;
+        }
+        Ok(current_amount)
+    }
+}
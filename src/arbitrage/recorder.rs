@@ -0,0 +1,160 @@
+use crate::arbitrage::types::ArbitrageSolution;
+#[cfg(feature = "db")]
+use crate::arbitrage::writer::BackgroundWriter;
+#[cfg(feature = "db")]
+use crate::db::DbManager;
+use alloy_primitives::{Address, B256, U256};
+use alloy_provider::Provider;
+use std::collections::HashMap;
+#[cfg(feature = "db")]
+use std::sync::Arc;
+#[cfg(feature = "db")]
+use tokio::sync::mpsc;
+
+/// Blocks of records the writer task can fall behind by before new ones are dropped.
+pub const DEFAULT_RECORDER_CAPACITY: usize = 64;
+
+/// A solution the engine found, as stored for later analysis. Amounts are in raw units of
+/// `profit_token`, except `net_profit_weth`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OpportunityRecord {
+    pub block_number: u64,
+    /// [`CycleId::path_hash`](crate::arbitrage::types::CycleId::path_hash) of the cycle.
+    pub path_hash: B256,
+    pub pools: Vec<Address>,
+    pub profit_token: Address,
+    pub optimal_input: U256,
+    pub gross_profit: U256,
+    pub net_profit: U256,
+    pub net_profit_weth: U256,
+    /// Live gas price the solution was costed at, in wei.
+    pub gas_price: U256,
+    /// Unix time the solution was found at.
+    pub recorded_at: u64,
+}
+
+impl OpportunityRecord {
+    pub fn from_solution<P: Provider + Send + Sync + 'static + ?Sized>(
+        solution: &ArbitrageSolution<P>,
+        block_number: u64,
+        gas_price: U256,
+        recorded_at: u64,
+    ) -> Self {
+        Self {
+            block_number,
+            path_hash: solution.cycle_id.path_hash(),
+            pools: solution
+                .swap_actions
+                .iter()
+                .map(|action| action.pool_address)
+                .collect(),
+            profit_token: solution
+                .swap_actions
+                .first()
+                .map(|action| action.token_in.address)
+                .unwrap_or_default(),
+            optimal_input: solution.optimal_input.raw,
+            gross_profit: solution.gross_profit.raw,
+            net_profit: solution.net_profit.raw,
+            net_profit_weth: solution.net_profit_weth,
+            gas_price,
+            recorded_at,
+        }
+    }
+}
+
+/// Everything recorded for one path and profit token.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PathProfit {
+    pub path_hash: B256,
+    pub pools: Vec<Address>,
+    pub profit_token: Address,
+    /// Blocks the path was profitable in.
+    pub opportunities: u64,
+    pub cumulative_net_profit: U256,
+    pub cumulative_net_profit_weth: U256,
+    pub first_block: u64,
+    pub last_block: u64,
+}
+
+/// Totals `records` by path and profit token, most profitable in wei first.
+pub fn rank_paths(records: &[OpportunityRecord]) -> Vec<PathProfit> {
+    let mut paths: HashMap<(B256, Address), PathProfit> = HashMap::new();
+    for record in records {
+        let path = paths
+            .entry((record.path_hash, record.profit_token))
+            .or_insert_with(|| PathProfit {
+                path_hash: record.path_hash,
+                pools: record.pools.clone(),
+                profit_token: record.profit_token,
+                opportunities: 0,
+                cumulative_net_profit: U256::ZERO,
+                cumulative_net_profit_weth: U256::ZERO,
+                first_block: record.block_number,
+                last_block: record.block_number,
+            });
+        path.opportunities += 1;
+        path.cumulative_net_profit = path.cumulative_net_profit.saturating_add(record.net_profit);
+        path.cumulative_net_profit_weth = path
+            .cumulative_net_profit_weth
+            .saturating_add(record.net_profit_weth);
+        path.first_block = path.first_block.min(record.block_number);
+        path.last_block = path.last_block.max(record.block_number);
+    }
+
+    let mut ranked: Vec<PathProfit> = paths.into_values().collect();
+    ranked.sort_by(|a, b| {
+        b.cumulative_net_profit_weth
+            .cmp(&a.cumulative_net_profit_weth)
+            .then_with(|| a.path_hash.cmp(&b.path_hash))
+            .then_with(|| a.profit_token.cmp(&b.profit_token))
+    });
+    ranked
+}
+
+/// Handle for queueing each block's opportunities to a background task that writes them
+/// to the database.
+#[cfg(feature = "db")]
+#[derive(Debug, Clone)]
+pub struct OpportunityRecorder {
+    sender: mpsc::Sender<Vec<OpportunityRecord>>,
+    writer: BackgroundWriter,
+}
+
+#[cfg(feature = "db")]
+impl OpportunityRecorder {
+    /// Starts the writer task, which saves each block's records in one transaction.
+    /// Must be called from within a Tokio runtime.
+    pub fn spawn(db_manager: Arc<DbManager>, channel_capacity: usize) -> Self {
+        let (sender, receiver) = mpsc::channel::<Vec<OpportunityRecord>>(channel_capacity.max(1));
+        let writer = BackgroundWriter::spawn(receiver, move |records: Vec<OpportunityRecord>| {
+            let db_manager = db_manager.clone();
+            async move {
+                if let Err(e) = db_manager.record_opportunities(&records).await {
+                    tracing::warn!(
+                        records = records.len(),
+                        "Failed to record opportunities: {:?}",
+                        e
+                    );
+                }
+            }
+        });
+        Self { sender, writer }
+    }
+
+    /// Saves the records still queued and stops the writer task. Records submitted
+    /// afterwards are dropped.
+    pub async fn shutdown(&self) {
+        self.writer.shutdown().await;
+    }
+
+    /// Queues a block's records without waiting. Drops them if the writer is falling behind.
+    pub fn submit(&self, records: Vec<OpportunityRecord>) {
+        if records.is_empty() {
+            return;
+        }
+        if let Err(e) = self.sender.try_send(records) {
+            tracing::warn!("Dropping opportunity records: {}", e);
+        }
+    }
+}
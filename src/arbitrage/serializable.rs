@@ -0,0 +1,291 @@
+//! Solution types for consumers outside the process, e.g. an execution service fed over a
+//! message queue. Tokens are carried as their address, symbol and decimals, and amounts as
+//! decimal strings. Swap actions convert back as they are; a solution needs the path it
+//! was found on and a [`TokenManager`] to re-resolve its profit token.
+
+use crate::arbitrage::amount::TokenAmount;
+use crate::arbitrage::approvals::ApproveAction;
+use crate::arbitrage::cycle::ArbitrageCycle;
+use crate::arbitrage::export::{ScenarioExport, UsdExport};
+use crate::arbitrage::types::{
+    Arbitrage, ArbitrageSolution, CycleId, HopMetrics, InputBound, ScenarioResult, SwapAction,
+    SwapKind, TokenRef,
+};
+use crate::arbitrage::usd::UsdValues;
+use crate::arbitrage::verification::VerifiedSolution;
+use crate::core::decimal;
+use crate::errors::ArbRsError;
+use crate::manager::token_manager::TokenManager;
+use crate::pool::DexKind;
+use alloy_primitives::{Address, U256};
+use alloy_provider::Provider;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SerializableSwapAction {
+    pub pool: Address,
+    pub token_in: TokenRef,
+    pub token_out: TokenRef,
+    #[serde(with = "decimal")]
+    pub amount_in: U256,
+    #[serde(with = "decimal")]
+    pub min_amount_out: U256,
+    pub kind: SwapKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SerializableApproval {
+    pub token: Address,
+    pub spender: Address,
+    #[serde(with = "decimal")]
+    pub amount: U256,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct SerializableHopMetrics {
+    pub pre_trade_price: f64,
+    pub post_trade_price: f64,
+    pub price_impact_bps: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SerializableVerification {
+    #[serde(with = "decimal")]
+    pub input: U256,
+    #[serde(with = "decimal")]
+    pub realized_output: U256,
+    pub gas_used: u64,
+}
+
+/// An [`ArbitrageSolution`] without its pool objects. Amounts are in raw units of
+/// `profit_token`, `net_profit_weth` in wei.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SerializableSolution {
+    pub cycle_id: CycleId,
+    pub pools: Vec<Address>,
+    /// The tokens along the path, the profit token first and last. Empty for paths that
+    /// aren't cycles.
+    pub path: Vec<TokenRef>,
+    pub profit_token: TokenRef,
+    #[serde(with = "decimal")]
+    pub optimal_input: U256,
+    #[serde(with = "decimal")]
+    pub gross_profit: U256,
+    #[serde(with = "decimal")]
+    pub net_profit: U256,
+    #[serde(with = "decimal")]
+    pub net_profit_weth: U256,
+    #[serde(with = "decimal")]
+    pub flashloan_fee: U256,
+    #[serde(with = "decimal")]
+    pub gas_cost: U256,
+    pub divergent_pools: Vec<Address>,
+    pub scenarios: Vec<ScenarioExport>,
+    pub bound_by: InputBound,
+    pub persistence_blocks: u64,
+    pub dexes_involved: Vec<DexKind>,
+    pub swap_actions: Vec<SerializableSwapAction>,
+    pub hop_metrics: Vec<Option<SerializableHopMetrics>>,
+    pub approve_actions: Vec<SerializableApproval>,
+    pub usd: Option<UsdExport>,
+    pub verification: Option<SerializableVerification>,
+}
+
+impl From<&SwapAction> for SerializableSwapAction {
+    fn from(action: &SwapAction) -> Self {
+        Self {
+            pool: action.pool_address,
+            token_in: action.token_in.clone(),
+            token_out: action.token_out.clone(),
+            amount_in: action.amount_in,
+            min_amount_out: action.min_amount_out,
+            kind: action.kind,
+        }
+    }
+}
+
+impl From<SerializableSwapAction> for SwapAction {
+    fn from(action: SerializableSwapAction) -> Self {
+        Self {
+            pool_address: action.pool,
+            token_in: action.token_in,
+            token_out: action.token_out,
+            amount_in: action.amount_in,
+            min_amount_out: action.min_amount_out,
+            kind: action.kind,
+        }
+    }
+}
+
+impl From<&ApproveAction> for SerializableApproval {
+    fn from(action: &ApproveAction) -> Self {
+        Self {
+            token: action.token,
+            spender: action.spender,
+            amount: action.amount,
+        }
+    }
+}
+
+impl From<SerializableApproval> for ApproveAction {
+    fn from(action: SerializableApproval) -> Self {
+        Self {
+            token: action.token,
+            spender: action.spender,
+            amount: action.amount,
+        }
+    }
+}
+
+impl From<&HopMetrics> for SerializableHopMetrics {
+    fn from(metrics: &HopMetrics) -> Self {
+        Self {
+            pre_trade_price: metrics.pre_trade_price,
+            post_trade_price: metrics.post_trade_price,
+            price_impact_bps: metrics.price_impact_bps,
+        }
+    }
+}
+
+impl From<SerializableHopMetrics> for HopMetrics {
+    fn from(metrics: SerializableHopMetrics) -> Self {
+        Self {
+            pre_trade_price: metrics.pre_trade_price,
+            post_trade_price: metrics.post_trade_price,
+            price_impact_bps: metrics.price_impact_bps,
+        }
+    }
+}
+
+impl From<&VerifiedSolution> for SerializableVerification {
+    fn from(verified: &VerifiedSolution) -> Self {
+        Self {
+            input: verified.input,
+            realized_output: verified.realized_output,
+            gas_used: verified.gas_used,
+        }
+    }
+}
+
+impl From<SerializableVerification> for VerifiedSolution {
+    fn from(verified: SerializableVerification) -> Self {
+        Self {
+            input: verified.input,
+            realized_output: verified.realized_output,
+            gas_used: verified.gas_used,
+        }
+    }
+}
+
+impl<P: Provider + Send + Sync + 'static + ?Sized> From<&ArbitrageSolution<P>>
+    for SerializableSolution
+{
+    fn from(solution: &ArbitrageSolution<P>) -> Self {
+        let path = solution
+            .path
+            .as_any()
+            .downcast_ref::<ArbitrageCycle<P>>()
+            .map(|cycle| {
+                cycle
+                    .path
+                    .path
+                    .iter()
+                    .map(|token| TokenRef::from(token.as_ref()))
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Self {
+            cycle_id: solution.cycle_id.clone(),
+            pools: solution.path.get_involved_pools(),
+            path,
+            profit_token: TokenRef::from(solution.net_profit.token.as_ref()),
+            optimal_input: solution.optimal_input.raw,
+            gross_profit: solution.gross_profit.raw,
+            net_profit: solution.net_profit.raw,
+            net_profit_weth: solution.net_profit_weth,
+            flashloan_fee: solution.flashloan_fee,
+            gas_cost: solution.gas_cost,
+            divergent_pools: solution.divergent_pools.clone(),
+            scenarios: solution
+                .scenario_results
+                .iter()
+                .map(|result| ScenarioExport {
+                    label: result.label.clone(),
+                    gas_cost: result.gas_cost,
+                    net_profit: result.net_profit,
+                    passes: result.passes,
+                })
+                .collect(),
+            bound_by: solution.bound_by,
+            persistence_blocks: solution.persistence_blocks,
+            dexes_involved: solution.dexes_involved.clone(),
+            swap_actions: solution.swap_actions.iter().map(Into::into).collect(),
+            hop_metrics: solution
+                .hop_metrics
+                .iter()
+                .map(|metrics| metrics.as_ref().map(Into::into))
+                .collect(),
+            approve_actions: solution.approve_actions.iter().map(Into::into).collect(),
+            usd: solution.usd.map(|usd| UsdExport {
+                net_profit: usd.net_profit,
+                flashloan_fee: usd.flashloan_fee,
+                gas_cost: usd.gas_cost,
+            }),
+            verification: solution.verification.as_ref().map(Into::into),
+        }
+    }
+}
+
+impl SerializableSolution {
+    /// The solution again, on `path`, which must be the path it was found on, e.g. rebuilt
+    /// from `pools` on the receiving side. The profit token is resolved through
+    /// `token_manager`.
+    pub async fn into_solution<P: Provider + Send + Sync + 'static + ?Sized>(
+        self,
+        path: Arc<dyn Arbitrage<P>>,
+        token_manager: &TokenManager<P>,
+    ) -> Result<ArbitrageSolution<P>, ArbRsError> {
+        let profit_token = token_manager.get_token(self.profit_token.address).await?;
+        let amount = |raw: U256| TokenAmount::new(profit_token.clone(), raw);
+
+        Ok(ArbitrageSolution {
+            path,
+            cycle_id: self.cycle_id,
+            optimal_input: amount(self.optimal_input),
+            gross_profit: amount(self.gross_profit),
+            net_profit: amount(self.net_profit),
+            net_profit_weth: self.net_profit_weth,
+            flashloan_fee: self.flashloan_fee,
+            gas_cost: self.gas_cost,
+            divergent_pools: self.divergent_pools,
+            scenario_results: self
+                .scenarios
+                .into_iter()
+                .map(|scenario| ScenarioResult {
+                    label: scenario.label,
+                    gas_cost: scenario.gas_cost,
+                    net_profit: scenario.net_profit,
+                    passes: scenario.passes,
+                })
+                .collect(),
+            bound_by: self.bound_by,
+            persistence_blocks: self.persistence_blocks,
+            dexes_involved: self.dexes_involved,
+            swap_actions: self.swap_actions.into_iter().map(Into::into).collect(),
+            hop_metrics: self
+                .hop_metrics
+                .into_iter()
+                .map(|metrics| metrics.map(Into::into))
+                .collect(),
+            approve_actions: self.approve_actions.into_iter().map(Into::into).collect(),
+            usd: self.usd.map(|usd| UsdValues {
+                net_profit: usd.net_profit,
+                flashloan_fee: usd.flashloan_fee,
+                gas_cost: usd.gas_cost,
+            }),
+            verification: self.verification.map(Into::into),
+        })
+    }
+}
@@ -0,0 +1,233 @@
+use crate::arbitrage::types::{ArbitrageSolution, SwapAction, TokenRef};
+use crate::core::token::TokenLike;
+#[cfg(feature = "db")]
+use crate::db::DbManager;
+use crate::pool::{LiquidityPool, PoolSnapshot};
+use alloy_primitives::{Address, I256, U256};
+use alloy_provider::Provider;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+
+const SECONDS_PER_DAY: u64 = 86_400;
+
+/// A solution the engine would have executed, paired with what its action chain would have
+/// returned one block later. Amounts are in raw units of `profit_token`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShadowRecord {
+    /// Block the solution was found at.
+    pub block: u64,
+    /// Block whose state the chain was re-quoted against.
+    pub realized_block: u64,
+    /// Days since the Unix epoch, from the timestamp of `block`.
+    pub day: u64,
+    pub pools: Vec<Address>,
+    /// Pool types along the path joined by `+`, e.g. `uniswap_v2+curve`.
+    pub path_type: String,
+    pub profit_token: Address,
+    pub input: U256,
+    /// Output of the chain against the state it was found in.
+    pub expected_out: U256,
+    /// Output against the next block's state. `None` when a hop would have fallen below its
+    /// minimum output, reverting the trade.
+    pub realized_out: Option<U256>,
+    pub flashloan_fee: U256,
+    pub gas_cost: U256,
+}
+
+impl ShadowRecord {
+    pub fn expected_profit(&self) -> I256 {
+        self.profit(self.expected_out)
+    }
+
+    /// A reverted trade only loses its gas.
+    pub fn realized_profit(&self) -> I256 {
+        match self.realized_out {
+            Some(amount_out) => self.profit(amount_out),
+            None => -I256::from_raw(self.gas_cost),
+        }
+    }
+
+    fn profit(&self, amount_out: U256) -> I256 {
+        let costs = self.input + self.flashloan_fee + self.gas_cost;
+        I256::from_raw(amount_out) - I256::from_raw(costs)
+    }
+}
+
+/// Shadow PnL of one day, path type and profit token.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShadowPnlRow {
+    pub day: u64,
+    pub path_type: String,
+    pub profit_token: Address,
+    pub trades: usize,
+    pub reverts: usize,
+    pub expected_profit: I256,
+    pub realized_profit: I256,
+}
+
+/// Aggregates records by day, path type and profit token, in that order.
+pub fn summarize(records: &[ShadowRecord]) -> Vec<ShadowPnlRow> {
+    let mut rows: BTreeMap<(u64, String, Address), ShadowPnlRow> = BTreeMap::new();
+    for record in records {
+        let key = (record.day, record.path_type.clone(), record.profit_token);
+        let row = rows.entry(key).or_insert_with(|| ShadowPnlRow {
+            day: record.day,
+            path_type: record.path_type.clone(),
+            profit_token: record.profit_token,
+            trades: 0,
+            reverts: 0,
+            expected_profit: I256::ZERO,
+            realized_profit: I256::ZERO,
+        });
+        row.trades += 1;
+        row.reverts += usize::from(record.realized_out.is_none());
+        row.expected_profit += record.expected_profit();
+        row.realized_profit += record.realized_profit();
+    }
+    rows.into_values().collect()
+}
+
+/// A chosen solution waiting for the next block.
+struct PendingTrade<P: Provider + Send + Sync + 'static + ?Sized> {
+    record: ShadowRecord,
+    pools: Vec<Arc<dyn LiquidityPool<P>>>,
+    actions: Vec<SwapAction>,
+}
+
+/// Follows the engine block by block without trading. Each block, the best solution per
+/// profit token is held back and its action chain re-quoted against the next block's
+/// snapshots, estimating what executing it would actually have returned.
+pub struct ShadowMode<P: Provider + Send + Sync + 'static + ?Sized> {
+    pending: Mutex<Vec<PendingTrade<P>>>,
+}
+
+impl<P: Provider + Send + Sync + 'static + ?Sized> Default for ShadowMode<P> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<P: Provider + Send + Sync + 'static + ?Sized> ShadowMode<P> {
+    pub fn new() -> Self {
+        Self {
+            pending: Mutex::default(),
+        }
+    }
+
+    /// Resolves the trades chosen at the previous observed block against `snapshots`, then
+    /// holds back this block's. `solutions` are expected best first, as the engine returns
+    /// them, and `snapshots` are the ones they were found with.
+    pub fn observe(
+        &self,
+        block: u64,
+        timestamp: u64,
+        solutions: &[ArbitrageSolution<P>],
+        snapshots: &HashMap<Address, PoolSnapshot>,
+    ) -> Vec<ShadowRecord> {
+        let mut seen_tokens = HashSet::new();
+        let chosen: Vec<PendingTrade<P>> = solutions
+            .iter()
+            .filter_map(|solution| {
+                let profit_token = solution.swap_actions.first()?.token_in.address;
+                if !seen_tokens.insert(profit_token) {
+                    return None;
+                }
+                let pools = solution.path.get_pools().clone();
+                let expected_out = quote_chain(
+                    &pools,
+                    &solution.swap_actions,
+                    solution.optimal_input.raw,
+                    snapshots,
+                )?;
+                let path_type = pools
+                    .iter()
+                    .map(|pool| {
+                        pool.calibration_bucket()
+                            .map_or_else(|| "unknown".to_string(), |bucket| bucket.pool_type)
+                    })
+                    .collect::<Vec<_>>()
+                    .join("+");
+                Some(PendingTrade {
+                    record: ShadowRecord {
+                        block,
+                        realized_block: block,
+                        day: timestamp / SECONDS_PER_DAY,
+                        pools: pools.iter().map(|pool| pool.address()).collect(),
+                        path_type,
+                        profit_token,
+                        input: solution.optimal_input.raw,
+                        expected_out,
+                        realized_out: None,
+                        flashloan_fee: solution.flashloan_fee,
+                        gas_cost: solution.gas_cost,
+                    },
+                    pools,
+                    actions: solution.swap_actions.clone(),
+                })
+            })
+            .collect();
+
+        let Ok(mut pending) = self.pending.lock() else {
+            return Vec::new();
+        };
+        std::mem::replace(&mut *pending, chosen)
+            .into_iter()
+            .map(|trade| ShadowRecord {
+                realized_block: block,
+                realized_out: quote_chain(
+                    &trade.pools,
+                    &trade.actions,
+                    trade.record.input,
+                    snapshots,
+                ),
+                ..trade.record
+            })
+            .collect()
+    }
+
+    /// `observe`, saving the resolved records.
+    #[cfg(feature = "db")]
+    pub async fn observe_and_save(
+        &self,
+        db_manager: &DbManager,
+        block: u64,
+        timestamp: u64,
+        solutions: &[ArbitrageSolution<P>],
+        snapshots: &HashMap<Address, PoolSnapshot>,
+    ) -> Result<Vec<ShadowRecord>, sqlx::Error> {
+        let records = self.observe(block, timestamp, solutions, snapshots);
+        for record in &records {
+            db_manager.save_shadow_record(record).await?;
+        }
+        Ok(records)
+    }
+}
+
+/// Output of `actions` starting from `input`, quoted hop by hop against `snapshots`. `None`
+/// if a hop can't be quoted or returns less than its `min_amount_out`.
+fn quote_chain<P: Provider + Send + Sync + 'static + ?Sized>(
+    pools: &[Arc<dyn LiquidityPool<P>>],
+    actions: &[SwapAction],
+    input: U256,
+    snapshots: &HashMap<Address, PoolSnapshot>,
+) -> Option<U256> {
+    pools
+        .iter()
+        .zip(actions)
+        .try_fold(input, |amount_in, (pool, action)| {
+            let snapshot = snapshots.get(&pool.address())?;
+            let pool_token = |token: &TokenRef| {
+                pool.get_all_tokens()
+                    .into_iter()
+                    .find(|pool_token| pool_token.address() == token.address)
+            };
+            let (token_in, token_out) = (
+                pool_token(&action.token_in)?,
+                pool_token(&action.token_out)?,
+            );
+            let amount_out = pool
+                .calculate_tokens_out(&token_in, &token_out, amount_in, snapshot)
+                .ok()?;
+            (amount_out >= action.min_amount_out).then_some(amount_out)
+        })
+}
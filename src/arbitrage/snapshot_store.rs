@@ -0,0 +1,185 @@
+use crate::balancer::pool::BalancerPoolSnapshot;
+use crate::curve::types::CurvePoolSnapshot;
+use crate::pool::PoolSnapshot;
+use alloy_primitives::Address;
+use lru::LruCache;
+use std::collections::HashMap;
+use std::num::NonZeroUsize;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// Snapshots a store keeps when built with `SnapshotStore::default()`.
+pub const DEFAULT_SNAPSHOT_STORE_CAPACITY: usize = 50_000;
+
+/// How a [`SnapshotStore`]'s lookups went since it was built or last cleared.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SnapshotStoreStats {
+    /// Lookups served from a snapshot stored for the block.
+    pub hits: u64,
+    /// Lookups for a block the pool had no snapshot stored for.
+    pub misses: u64,
+    /// Snapshots stored for a block as the pool's snapshot of an earlier block, because
+    /// nothing changed the pool since.
+    pub aliased: u64,
+    /// Snapshots stored right now.
+    pub entries: usize,
+}
+
+#[derive(Debug)]
+struct StoreState {
+    snapshots: LruCache<(Address, u64), Arc<PoolSnapshot>>,
+    stats: SnapshotStoreStats,
+}
+
+/// Pool snapshots keyed by pool and block, shared by every path of an evaluation and by
+/// later evaluations of the same block, least recently used first out.
+///
+/// A pool unchanged between blocks shares one snapshot across them, so quiet pools cost
+/// neither a fetch nor memory per block. The store doesn't notice reorgs itself: the
+/// engine clears it from `invalidate_from`.
+#[derive(Debug)]
+pub struct SnapshotStore {
+    state: Mutex<StoreState>,
+}
+
+impl StoreState {
+    /// The snapshot stored for the block before `block_number`, if the pool's state is the
+    /// same in `snapshot`.
+    fn previous_if_unchanged(
+        &self,
+        pool: Address,
+        block_number: u64,
+        snapshot: &PoolSnapshot,
+    ) -> Option<Arc<PoolSnapshot>> {
+        let previous = self.snapshots.peek(&(pool, block_number.checked_sub(1)?))?;
+        same_state(previous, snapshot).then(|| previous.clone())
+    }
+}
+
+impl Default for SnapshotStore {
+    fn default() -> Self {
+        Self::new(NonZeroUsize::new(DEFAULT_SNAPSHOT_STORE_CAPACITY).unwrap())
+    }
+}
+
+impl SnapshotStore {
+    pub fn new(capacity: NonZeroUsize) -> Self {
+        Self {
+            state: Mutex::new(StoreState {
+                snapshots: LruCache::new(capacity),
+                stats: SnapshotStoreStats::default(),
+            }),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, StoreState> {
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    pub fn get(&self, pool: Address, block_number: u64) -> Option<PoolSnapshot> {
+        self.snapshots_at(block_number, [&pool]).remove(&pool)
+    }
+
+    /// The snapshots stored for `block_number` of the pools among `pools` that have one.
+    pub fn snapshots_at<'a>(
+        &self,
+        block_number: u64,
+        pools: impl IntoIterator<Item = &'a Address>,
+    ) -> HashMap<Address, PoolSnapshot> {
+        let mut state = self.lock();
+        let mut found = HashMap::new();
+        for pool in pools {
+            match state.snapshots.get(&(*pool, block_number)) {
+                Some(snapshot) => {
+                    found.insert(*pool, snapshot.as_ref().clone());
+                    state.stats.hits += 1;
+                }
+                None => state.stats.misses += 1,
+            }
+        }
+        found
+    }
+
+    /// Stores `snapshot` of `pool` taken at `block_number`. If the pool's state is the
+    /// same as in the snapshot stored for the block before, that one is shared instead.
+    pub fn insert(&self, pool: Address, block_number: u64, snapshot: PoolSnapshot) {
+        let mut state = self.lock();
+        let snapshot = match state.previous_if_unchanged(pool, block_number, &snapshot) {
+            Some(previous) => {
+                state.stats.aliased += 1;
+                previous
+            }
+            None => Arc::new(snapshot),
+        };
+        state.snapshots.put((pool, block_number), snapshot);
+    }
+
+    /// Stores a snapshot of `pool` taken at an earlier block for `block_number` too, the
+    /// caller knowing nothing changed the pool since, e.g. from the event stream.
+    pub fn alias(&self, pool: Address, block_number: u64, snapshot: PoolSnapshot) {
+        let mut state = self.lock();
+        state.stats.aliased += 1;
+        let snapshot = state
+            .previous_if_unchanged(pool, block_number, &snapshot)
+            .unwrap_or_else(|| Arc::new(snapshot));
+        state.snapshots.put((pool, block_number), snapshot);
+    }
+
+    /// Drops every snapshot, e.g. once a reorg replaced the blocks they were taken at.
+    /// The counters are kept.
+    pub fn clear(&self) {
+        self.lock().snapshots.clear();
+    }
+
+    pub fn stats(&self) -> SnapshotStoreStats {
+        let state = self.lock();
+        SnapshotStoreStats {
+            entries: state.snapshots.len(),
+            ..state.stats
+        }
+    }
+}
+
+/// Whether two snapshots of a pool quote the same: V2 reserves, V3 price, tick, liquidity
+/// and ticks, and everything else of Curve and Balancer pools but the time they were
+/// taken at.
+fn same_state(a: &PoolSnapshot, b: &PoolSnapshot) -> bool {
+    match (a, b) {
+        (PoolSnapshot::UniswapV2(a), PoolSnapshot::UniswapV2(b)) => {
+            a.reserve0 == b.reserve0 && a.reserve1 == b.reserve1
+        }
+        (PoolSnapshot::UniswapV3(a), PoolSnapshot::UniswapV3(b)) => {
+            a.sqrt_price_x96 == b.sqrt_price_x96
+                && a.tick == b.tick
+                && a.liquidity == b.liquidity
+                && a.tick_bitmap == b.tick_bitmap
+                && a.tick_data == b.tick_data
+        }
+        (PoolSnapshot::UniswapV4(a), PoolSnapshot::UniswapV4(b)) => {
+            a.state.sqrt_price_x96 == b.state.sqrt_price_x96
+                && a.state.tick == b.state.tick
+                && a.state.liquidity == b.state.liquidity
+                && a.state.tick_bitmap == b.state.tick_bitmap
+                && a.state.tick_data == b.state.tick_data
+                && a.protocol_fee == b.protocol_fee
+                && a.lp_fee == b.lp_fee
+        }
+        (PoolSnapshot::Curve(a), PoolSnapshot::Curve(b)) => {
+            let at_any_time = |snapshot: &CurvePoolSnapshot| CurvePoolSnapshot {
+                block_timestamp: 0,
+                ..snapshot.clone()
+            };
+            at_any_time(a) == at_any_time(b)
+        }
+        (PoolSnapshot::Balancer(a), PoolSnapshot::Balancer(b)) => {
+            let at_any_block = |snapshot: &BalancerPoolSnapshot| BalancerPoolSnapshot {
+                block_number: None,
+                ..snapshot.clone()
+            };
+            at_any_block(a) == at_any_block(b)
+        }
+        (PoolSnapshot::WrappedNative, PoolSnapshot::WrappedNative) => true,
+        _ => false,
+    }
+}
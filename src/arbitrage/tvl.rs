@@ -0,0 +1,199 @@
+use crate::arbitrage::optimizer::{self, ETHER_SCALE, token_units_to_wei};
+use crate::chain::ChainConfig;
+use crate::core::token::{NATIVE_ETH_ADDRESS, Token, TokenLike, WETH_ADDRESS};
+use crate::pool::{LiquidityPool, PoolSnapshot};
+use alloy_primitives::{Address, U256};
+use alloy_provider::Provider;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// WETH sold to quote a token's conversion rate: little enough to barely move the deepest
+/// pool, enough to keep rounding out of the quote.
+pub const CONVERSION_PROBE_WEI: U256 = U256::from_limbs([10_000_000_000_000_000, 0, 0, 0]);
+
+/// Values pools in WETH from their [`reserves_summary`](LiquidityPool::reserves_summary),
+/// at the price of 1 ETH in each token, in whole tokens scaled by 1e18, as the engine's
+/// conversion rates are. The chain's wrapped native token and native ether are always priced.
+///
+/// Estimates are floors: a token without a rate adds nothing to a pool's value rather than
+/// failing the estimate.
+#[derive(Debug, Clone, Default)]
+pub struct TvlEstimator {
+    rates: HashMap<Address, U256>,
+}
+
+impl TvlEstimator {
+    /// An estimator on mainnet, valuing pools in WETH.
+    pub fn new(conversion_rates: HashMap<Address, U256>) -> Self {
+        Self::with_wrapped_native(WETH_ADDRESS, conversion_rates)
+    }
+
+    /// An estimator valuing pools in the wrapped native token of `chain`.
+    pub fn for_chain(chain: &ChainConfig, conversion_rates: HashMap<Address, U256>) -> Self {
+        Self::with_wrapped_native(chain.wrapped_native, conversion_rates)
+    }
+
+    fn with_wrapped_native(
+        wrapped_native: Address,
+        conversion_rates: HashMap<Address, U256>,
+    ) -> Self {
+        let mut rates = conversion_rates;
+        rates.insert(wrapped_native, ETHER_SCALE);
+        rates.insert(NATIVE_ETH_ADDRESS, ETHER_SCALE);
+        Self { rates }
+    }
+
+    /// Rates for `tokens` quoted from `snapshots`, without any calls: selling
+    /// `CONVERSION_PROBE_WEI` of the chain's wrapped native token through its deepest pool
+    /// with each token, or through the deepest pools with the chain's stable reference token
+    /// when it has none. Tokens neither route prices are left without a rate.
+    pub fn from_snapshots<P>(
+        chain: &ChainConfig,
+        tokens: &[Arc<Token<P>>],
+        pools: &HashMap<Address, Arc<dyn LiquidityPool<P>>>,
+        snapshots: &HashMap<Address, PoolSnapshot>,
+    ) -> Self
+    where
+        P: Provider + Send + Sync + 'static + ?Sized,
+    {
+        let (wrapped_native, stable) = (chain.wrapped_native, chain.stable_reference_token);
+        let depths = PoolDepths::new(pools, snapshots);
+        let mut rates = HashMap::new();
+        for token in tokens {
+            if token.address() == wrapped_native || rates.contains_key(&token.address()) {
+                continue;
+            }
+            let amount_out = depths
+                .quote(wrapped_native, token.address(), CONVERSION_PROBE_WEI)
+                .or_else(|| {
+                    let stable_out = depths.quote(wrapped_native, stable, CONVERSION_PROBE_WEI)?;
+                    depths.quote(stable, token.address(), stable_out)
+                });
+            match amount_out {
+                Some(amount_out) => {
+                    let rate = optimizer::weth_price_scaled(
+                        CONVERSION_PROBE_WEI,
+                        amount_out,
+                        token.decimals(),
+                    );
+                    rates.insert(token.address(), rate);
+                }
+                None => tracing::debug!(
+                    token = ?token.address(),
+                    "No wrapped native or stable pool snapshot to price the token with."
+                ),
+            }
+        }
+        Self::for_chain(chain, rates)
+    }
+
+    /// The price of 1 ETH in `token`, if known.
+    pub fn rate(&self, token: Address) -> Option<U256> {
+        self.rates.get(&token).copied()
+    }
+
+    pub fn rates(&self) -> &HashMap<Address, U256> {
+        &self.rates
+    }
+
+    pub fn into_rates(self) -> HashMap<Address, U256> {
+        self.rates
+    }
+
+    /// `amount` raw units of `token` in wei. `None` when the token has no rate.
+    pub fn to_weth<P>(&self, token: &Token<P>, amount: U256) -> Option<U256>
+    where
+        P: Provider + Send + Sync + 'static + ?Sized,
+    {
+        let rate = self.rate(token.address())?;
+        Some(token_units_to_wei(amount, rate, token.decimals()))
+    }
+
+    /// The value of `summary` in wei, counting tokens without a rate as zero.
+    pub fn estimate<P>(&self, summary: &[(Arc<Token<P>>, U256)]) -> U256
+    where
+        P: Provider + Send + Sync + 'static + ?Sized,
+    {
+        summary.iter().fold(U256::ZERO, |total, (token, reserve)| {
+            total.saturating_add(self.to_weth(token, *reserve).unwrap_or_default())
+        })
+    }
+
+    /// The value of `pool`'s reserves at `snapshot` in wei.
+    pub fn pool_tvl<P>(&self, pool: &dyn LiquidityPool<P>, snapshot: &PoolSnapshot) -> U256
+    where
+        P: Provider + Send + Sync + 'static + ?Sized,
+    {
+        self.estimate(&pool.reserves_summary(snapshot))
+    }
+}
+
+/// A pool with a snapshot, and its reserves there.
+struct PoolDepth<'a, P: Provider + Send + Sync + 'static + ?Sized> {
+    pool: &'a Arc<dyn LiquidityPool<P>>,
+    snapshot: &'a PoolSnapshot,
+    reserves: Vec<(Arc<Token<P>>, U256)>,
+}
+
+impl<P: Provider + Send + Sync + 'static + ?Sized> PoolDepth<'_, P> {
+    fn token(&self, address: Address) -> Option<&(Arc<Token<P>>, U256)> {
+        self.reserves
+            .iter()
+            .find(|(token, _)| token.address() == address)
+    }
+}
+
+/// The pools with a snapshot, indexed by the tokens they hold.
+struct PoolDepths<'a, P: Provider + Send + Sync + 'static + ?Sized> {
+    pools: Vec<PoolDepth<'a, P>>,
+    by_token: HashMap<Address, Vec<usize>>,
+}
+
+impl<'a, P: Provider + Send + Sync + 'static + ?Sized> PoolDepths<'a, P> {
+    fn new(
+        pools: &'a HashMap<Address, Arc<dyn LiquidityPool<P>>>,
+        snapshots: &'a HashMap<Address, PoolSnapshot>,
+    ) -> Self {
+        let pools: Vec<PoolDepth<'a, P>> = pools
+            .values()
+            .filter_map(|pool| {
+                let snapshot = snapshots.get(&pool.address())?;
+                Some(PoolDepth {
+                    pool,
+                    snapshot,
+                    reserves: pool.reserves_summary(snapshot),
+                })
+            })
+            .collect();
+        let mut by_token: HashMap<Address, Vec<usize>> = HashMap::new();
+        for (index, depth) in pools.iter().enumerate() {
+            for (token, _) in &depth.reserves {
+                by_token.entry(token.address()).or_default().push(index);
+            }
+        }
+        Self { pools, by_token }
+    }
+
+    /// Sells `amount_in` of `token_in` for `token_out` through the pool holding both with
+    /// the most `token_in`. `None` when there is no such pool or it quotes nothing.
+    fn quote(&self, token_in: Address, token_out: Address, amount_in: U256) -> Option<U256> {
+        let (_, depth) = self
+            .by_token
+            .get(&token_in)?
+            .iter()
+            .map(|index| &self.pools[*index])
+            .filter(|depth| depth.token(token_out).is_some())
+            .filter_map(|depth| {
+                let (_, reserve) = depth.token(token_in)?;
+                Some(((*reserve, depth.pool.address()), depth))
+            })
+            .max_by_key(|(key, _)| *key)?;
+        let (token_in, _) = depth.token(token_in)?;
+        let (token_out, _) = depth.token(token_out)?;
+        depth
+            .pool
+            .calculate_tokens_out(token_in, token_out, amount_in, depth.snapshot)
+            .ok()
+            .filter(|amount_out| !amount_out.is_zero())
+    }
+}
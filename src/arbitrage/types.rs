@@ -1,31 +1,207 @@
-use crate::core::token::Token;
+use crate::arbitrage::amount::TokenAmount;
+use crate::arbitrage::approvals::ApproveAction;
+use crate::arbitrage::usd::UsdValues;
+use crate::arbitrage::verification::VerifiedSolution;
+use crate::core::token::{Token, TokenLike};
 use crate::errors::ArbRsError;
-use crate::pool::{LiquidityPool, PoolSnapshot};
-use alloy_primitives::{Address, U256};
+use crate::pool::{DexKind, LiquidityPool, PoolSnapshot};
+use alloy_primitives::{Address, B256, U256, keccak256};
 use alloy_provider::Provider;
+use serde::{Deserialize, Serialize};
 use std::any::Any;
 use std::collections::HashMap;
 use std::fmt::{self, Debug};
 use std::sync::Arc;
 
-#[derive(Debug, Clone)]
-pub struct SwapAction<P: Provider + Send + Sync + 'static + ?Sized> {
+/// Address and metadata of a token, detached from its provider.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct TokenRef {
+    pub address: Address,
+    pub symbol: String,
+    pub decimals: u8,
+}
+
+impl<P: Provider + Send + Sync + 'static + ?Sized> From<&Token<P>> for TokenRef {
+    fn from(token: &Token<P>) -> Self {
+        Self {
+            address: token.address(),
+            symbol: token.symbol().to_string(),
+            decimals: token.decimals(),
+        }
+    }
+}
+
+/// One swap of a solution. Holds no provider, so actions can be sent across threads or
+/// serialized without the pools they were quoted on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SwapAction {
     pub pool_address: Address,
-    pub token_in: Arc<Token<P>>,
-    pub token_out: Arc<Token<P>>,
+    pub token_in: TokenRef,
+    pub token_out: TokenRef,
     pub amount_in: U256,
     pub min_amount_out: U256,
+    pub kind: SwapKind,
+}
+
+/// How far a hop moves its pool. Prices are raw units of the hop's output token per raw
+/// unit of its input, at the margin, before and after the hop is applied to the snapshot.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HopMetrics {
+    pub pre_trade_price: f64,
+    pub post_trade_price: f64,
+    /// Drop of the marginal price over the hop, in bps of the pre-trade price.
+    pub price_impact_bps: f64,
+}
+
+/// What a hop does, for the calldata an executor builds for it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum SwapKind {
+    /// A swap through a pool.
+    #[default]
+    Swap,
+    /// `deposit` of ether into WETH.
+    Wrap,
+    /// `withdraw` of WETH into ether.
+    Unwrap,
+    /// `exchange_underlying` on a Curve metapool, trading its base pool's coins.
+    SwapUnderlying,
+}
+
+/// Identifies a physical cycle regardless of which token it is entered at.
+/// Holds the `(pool, token_in)` hops, rotated to start at the smallest hop.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct CycleId(pub Vec<(Address, Address)>);
+
+impl CycleId {
+    /// Keccak-256 of the hops' pool and token addresses, a fixed size key for the cycle.
+    pub fn path_hash(&self) -> B256 {
+        let bytes: Vec<u8> = self
+            .0
+            .iter()
+            .flat_map(|(pool, token_in)| [pool.as_slice(), token_in.as_slice()].concat())
+            .collect();
+        keccak256(bytes)
+    }
+}
+
+/// The key [`ArbitrageCache`](crate::arbitrage::cache::ArbitrageCache) deduplicates paths by.
+/// Holds the `(pool, token_in)` hops rotated to start at the smallest pool, so every
+/// rotation of a cycle shares it. The reversed cycle shares it too when none of its pools
+/// is [direction sensitive](crate::pool::LiquidityPool::is_direction_sensitive).
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct PathKey(pub Vec<(Address, Address)>);
+
+impl PathKey {
+    /// Keys the cycle through `pools`, where `tokens` are the tokens in, in order, followed
+    /// by the token the cycle returns to.
+    pub fn new<P: Provider + Send + Sync + 'static + ?Sized>(
+        pools: &[Arc<dyn LiquidityPool<P>>],
+        tokens: &[Arc<Token<P>>],
+    ) -> Self {
+        let hops: Vec<(Address, Address)> = pools
+            .iter()
+            .zip(tokens)
+            .map(|(pool, token_in)| (pool.address(), token_in.address()))
+            .collect();
+        let forward = rotate_to_smallest(&hops);
+        if pools.iter().any(|pool| pool.is_direction_sensitive()) {
+            return Self(forward);
+        }
+        // Reversed, each pool is entered with the token it used to return.
+        let reversed: Vec<(Address, Address)> = pools
+            .iter()
+            .zip(tokens.iter().skip(1))
+            .rev()
+            .map(|(pool, token_in)| (pool.address(), token_in.address()))
+            .collect();
+        Self(forward.min(rotate_to_smallest(&reversed)))
+    }
+}
+
+/// `hops` rotated to start at the smallest one.
+pub(crate) fn rotate_to_smallest(hops: &[(Address, Address)]) -> Vec<(Address, Address)> {
+    let start = (0..hops.len()).min_by_key(|&i| hops[i]).unwrap_or(0);
+    hops[start..].iter().chain(&hops[..start]).copied().collect()
+}
+
+/// A solution's outcome under one gas price scenario, in the profit token.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScenarioResult {
+    pub label: String,
+    pub gas_cost: U256,
+    pub net_profit: U256,
+    /// Whether the gross profit covers every cost and leaves at least the minimum net profit.
+    pub passes: bool,
+}
+
+/// What limited a solution's input amount.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum InputBound {
+    /// The flashloan source couldn't lend more of the profit token.
+    Liquidity,
+    /// Larger inputs stop being profitable.
+    #[default]
+    PoolDepth,
+    /// The engine's configured maximum input.
+    ConfigCap,
 }
 
 /// The final, actionable result of the arbitrage calculation.
 #[derive(Debug)]
 pub struct ArbitrageSolution<P: Provider + Send + Sync + 'static + ?Sized> {
     pub path: Arc<dyn Arbitrage<P>>,
-    pub optimal_input: U256,
-    pub gross_profit: U256,
-    pub net_profit: U256,
+    pub cycle_id: CycleId,
+    /// The input and profits are in the profit token.
+    pub optimal_input: TokenAmount<P>,
+    pub gross_profit: TokenAmount<P>,
+    pub net_profit: TokenAmount<P>,
+    /// `net_profit` converted to wei, which solutions in different profit tokens are ranked by.
+    pub net_profit_weth: U256,
+    /// Costs deducted from `gross_profit`, in the profit token.
+    pub flashloan_fee: U256,
+    pub gas_cost: U256,
+    /// Pools whose last observed trade diverged from their snapshot beyond the engine's
+    /// threshold. Only filled when divergence checks run in flagging mode.
+    pub divergent_pools: Vec<Address>,
+    /// One entry per configured gas scenario. The first is the base scenario, which
+    /// `net_profit` and `gas_cost` are taken from.
+    pub scenario_results: Vec<ScenarioResult>,
+    pub bound_by: InputBound,
+    /// Consecutive evaluations, including this one, in which the cycle has been profitable.
+    pub persistence_blocks: u64,
+    /// Dexes of the path's pools, in `DexKind` order.
+    pub dexes_involved: Vec<DexKind>,
     // <<< NEW FIELD for the canonical execution sequence >>>
-    pub swap_actions: Vec<SwapAction<P>>, 
+    pub swap_actions: Vec<SwapAction>,
+    /// Price impact of each of `swap_actions`, `None` for hops it couldn't be measured on.
+    pub hop_metrics: Vec<Option<HopMetrics>>,
+    /// Approvals to send ahead of `swap_actions`. Only filled when the engine is configured
+    /// to emit them; their gas is in `gas_cost` either way.
+    pub approve_actions: Vec<ApproveAction>,
+    /// Profit and costs in USD, when the engine has a price feed and could value the
+    /// profit token.
+    pub usd: Option<UsdValues>,
+    /// The solution replayed on top of its block, when the engine verifies solutions and the
+    /// replay went through.
+    pub verification: Option<VerifiedSolution>,
+}
+
+impl<P: Provider + Send + Sync + 'static + ?Sized> ArbitrageSolution<P> {
+    /// Whether the replay realized enough profit to cover the flashloan fee and gas.
+    pub fn is_verified(&self) -> bool {
+        self.verification.is_some_and(|verified| {
+            verified.realized_profit() >= self.flashloan_fee.saturating_add(self.gas_cost)
+        })
+    }
+
+    /// Largest price impact of any measured hop, 0 when none was measured.
+    pub fn max_hop_impact_bps(&self) -> f64 {
+        self.hop_metrics
+            .iter()
+            .flatten()
+            .map(|metrics| metrics.price_impact_bps)
+            .fold(0.0, f64::max)
+    }
 }
 
 /// Represents a potential arbitrage opportunity, defining the sequence of pools
@@ -53,6 +229,18 @@ pub trait Arbitrage<P: Provider + Send + Sync + 'static + ?Sized>: Debug + Send
         snapshots: &HashMap<Address, PoolSnapshot>,
     ) -> Result<U256, ArbRsError>;
 
+    /// The smallest input the path turns into at least `target_out`, walked backwards from
+    /// the last hop with each pool's exact-output quote. Fails on the first hop, counted
+    /// from the end, whose pool can't quote an exact output.
+    fn calculate_in_amount(
+        &self,
+        target_out: U256,
+        snapshots: &HashMap<Address, PoolSnapshot>,
+    ) -> Result<U256, ArbRsError>;
+
+    /// The key the path is deduplicated by, shared with its rotations.
+    fn path_key(&self) -> PathKey;
+
     /// Quickly checks if a path is potentially profitable.
     fn check_viability(
         &self,
@@ -0,0 +1,136 @@
+use crate::arbitrage::optimizer;
+use crate::errors::ArbRsError;
+use crate::math::v3::full_math::mul_div;
+use alloy_primitives::{Address, U256, address};
+use alloy_provider::Provider;
+use alloy_rpc_types::{BlockId, TransactionRequest};
+use alloy_sol_types::{SolCall, sol};
+use async_trait::async_trait;
+use std::sync::Arc;
+
+/// Chainlink's ETH/USD aggregator on mainnet.
+pub const CHAINLINK_ETH_USD: Address = address!("5f4eC3Df9cbd43714FE2740f5E3616155c5b8419");
+/// Decimals of the ETH/USD price feeds return, as Chainlink reports it.
+pub const USD_PRICE_DECIMALS: u8 = 8;
+/// USD amounts on solutions are in cents.
+pub const USD_DECIMALS: u8 = 2;
+
+sol! {
+    function latestRoundData() external view returns (
+        uint80 roundId,
+        int256 answer,
+        uint256 startedAt,
+        uint256 updatedAt,
+        uint80 answeredInRound
+    );
+}
+
+/// Source of the ETH/USD price solutions are valued at.
+#[async_trait]
+pub trait UsdPriceFeed: Send + Sync {
+    /// Price of 1 ETH in USD at `block_number`, with `USD_PRICE_DECIMALS` decimals.
+    async fn eth_usd_price(&self, block_number: Option<u64>) -> Result<U256, ArbRsError>;
+}
+
+/// Reads the latest answer of a Chainlink ETH/USD aggregator.
+pub struct ChainlinkUsdPriceFeed<P: Provider + Send + Sync + 'static + ?Sized> {
+    provider: Arc<P>,
+    aggregator: Address,
+}
+
+impl<P: Provider + Send + Sync + 'static + ?Sized> ChainlinkUsdPriceFeed<P> {
+    /// `aggregator` must answer with `USD_PRICE_DECIMALS` decimals, as the ETH/USD ones do.
+    pub fn new(provider: Arc<P>, aggregator: Address) -> Self {
+        Self {
+            provider,
+            aggregator,
+        }
+    }
+}
+
+#[async_trait]
+impl<P: Provider + Send + Sync + 'static + ?Sized> UsdPriceFeed for ChainlinkUsdPriceFeed<P> {
+    async fn eth_usd_price(&self, block_number: Option<u64>) -> Result<U256, ArbRsError> {
+        let request = TransactionRequest::default()
+            .to(self.aggregator)
+            .input(latestRoundDataCall {}.abi_encode().into());
+        let result_bytes = self
+            .provider
+            .call(request)
+            .block(block_number.map(BlockId::from).unwrap_or(BlockId::latest()))
+            .await?;
+        let answer = latestRoundDataCall::abi_decode_returns(&result_bytes)?.answer;
+        if !answer.is_positive() {
+            return Err(ArbRsError::CalculationError(format!(
+                "Aggregator {} answered a non-positive price: {}",
+                self.aggregator, answer
+            )));
+        }
+        Ok(answer.into_raw())
+    }
+}
+
+/// Answers the same price at every block.
+#[derive(Debug, Clone, Copy)]
+pub struct StaticUsdPriceFeed {
+    price: U256,
+}
+
+impl StaticUsdPriceFeed {
+    /// `price` has `USD_PRICE_DECIMALS` decimals, e.g. `2000e8` for $2000.
+    pub fn new(price: U256) -> Self {
+        Self { price }
+    }
+}
+
+#[async_trait]
+impl UsdPriceFeed for StaticUsdPriceFeed {
+    async fn eth_usd_price(&self, _block_number: Option<u64>) -> Result<U256, ArbRsError> {
+        Ok(self.price)
+    }
+}
+
+/// A solution's profit and costs in USD cents.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UsdValues {
+    pub net_profit: U256,
+    pub flashloan_fee: U256,
+    pub gas_cost: U256,
+}
+
+impl UsdValues {
+    /// Values raw profit token amounts, given the price of 1 ETH in whole profit tokens
+    /// scaled by 1e18 and the ETH/USD price.
+    pub fn from_token_amounts(
+        net_profit: U256,
+        flashloan_fee: U256,
+        gas_cost: U256,
+        weth_price_scaled: U256,
+        token_decimals: u8,
+        eth_usd_price: U256,
+    ) -> Self {
+        let to_usd = |amount| {
+            wei_to_usd(
+                optimizer::token_units_to_wei(amount, weth_price_scaled, token_decimals),
+                eth_usd_price,
+            )
+        };
+        Self {
+            net_profit: to_usd(net_profit),
+            flashloan_fee: to_usd(flashloan_fee),
+            gas_cost: to_usd(gas_cost),
+        }
+    }
+}
+
+/// Converts wei to USD cents at `eth_usd_price`, rounding down.
+pub fn wei_to_usd(amount_wei: U256, eth_usd_price: U256) -> U256 {
+    let scale = U256::from(10).pow(U256::from(18 + USD_PRICE_DECIMALS - USD_DECIMALS));
+    mul_div(amount_wei, eth_usd_price, scale).unwrap_or(U256::MAX)
+}
+
+/// Formats USD cents as dollars, e.g. `1234.05`.
+pub fn format_usd(cents: U256) -> String {
+    let (dollars, cents) = cents.div_rem(U256::from(100));
+    format!("{dollars}.{cents:0>2}")
+}
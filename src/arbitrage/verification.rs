@@ -0,0 +1,493 @@
+use crate::arbitrage::cycle::ArbitrageCycle;
+use crate::balancer::pool::BalancerPool;
+use crate::core::token::{Token, TokenLike};
+use crate::curve::pool::CurveStableswapPool;
+use crate::curve::underlying::UnderlyingCurvePool;
+use crate::errors::ArbRsError;
+use crate::pool::{DexKind, LiquidityPool};
+use alloy_primitives::{Address, B256, Bytes, I256, U160, U256, hex, keccak256};
+use alloy_provider::Provider;
+use alloy_rpc_types::simulate::{SimBlock, SimulatePayload};
+use alloy_rpc_types::state::StateOverridesBuilder;
+use alloy_rpc_types::{BlockId, TransactionRequest};
+use alloy_sol_types::{SolCall, SolValue, sol};
+use dashmap::DashMap;
+use futures::future::join_all;
+use std::sync::Arc;
+
+sol! {
+    function balanceOf(address owner) external view returns (uint256);
+    function transfer(address to, uint256 amount) external returns (bool);
+    function approve(address spender, uint256 amount) external returns (bool);
+
+    interface IUniswapV2Pair {
+        function swap(uint256 amount0Out, uint256 amount1Out, address to, bytes data) external;
+    }
+    interface IUniswapV3Pool {
+        function swap(address recipient, bool zeroForOne, int256 amountSpecified, uint160 sqrtPriceLimitX96, bytes data) external returns (int256 amount0, int256 amount1);
+    }
+    interface ICurvePool {
+        function exchange(int128 i, int128 j, uint256 dx, uint256 min_dy) external;
+        function exchange_underlying(int128 i, int128 j, uint256 dx, uint256 min_dy) external;
+    }
+    interface ICryptoPool {
+        function exchange(uint256 i, uint256 j, uint256 dx, uint256 min_dy) external;
+    }
+    interface IBalancerVault {
+        struct SingleSwap {
+            bytes32 poolId;
+            uint8 kind;
+            address assetIn;
+            address assetOut;
+            uint256 amount;
+            bytes userData;
+        }
+        struct FundManagement {
+            address sender;
+            bool fromInternalBalance;
+            address recipient;
+            bool toInternalBalance;
+        }
+        function swap(SingleSwap singleSwap, FundManagement funds, uint256 limit, uint256 deadline) external returns (uint256);
+    }
+}
+
+/// Runtime code the swaps are replayed from. Called with `(target, token, offset) ++ data`,
+/// it writes its balance of `token` over the word at `offset` in `data` unless `token` is
+/// zero, calls `target` with `data` and bubbles up the result. As a Uniswap or PancakeSwap V3
+/// swap callback, it pays the pool the owed amount of the token `data` encodes.
+const EXECUTOR_CODE: [u8; 187] = hex!(
+    "60003560e01c8063fa461e331461007b576323a69e751461007b57"
+    "606036038060606040376020358015610057"
+    "576370a0823160e01b600052306004526020600060246000845afa15610071"
+    "57600051604035604001525b50"
+    "6000600082604060006000355af13d600060003e610076575b3d6000fd5b3d6000f3"
+    "5b6004356000811361008b57506024355b6044356024013563a9059cbb60e01b600052"
+    "336004528160245260206000604460006000855af1610076573d6000fd"
+);
+
+/// Address the executor code is placed at for the simulation.
+const EXECUTOR: Address = Address::repeat_byte(0xe7);
+
+/// Sends the simulated calls to the executor.
+const SIMULATION_SENDER: Address = Address::repeat_byte(0xe8);
+
+/// Stands in for a hop's input amount in its calldata, where the executor writes its balance.
+const AMOUNT_PLACEHOLDER: U256 = U256::from_limbs([0x5a5a_5a5a_5a5a_5a5a; 4]);
+
+/// Storage slots searched for a token's balance mapping.
+const BALANCE_SLOT_CANDIDATES: u64 = 32;
+
+/// Added to a candidate's index to mark its balance, far above any real balance.
+const BALANCE_SLOT_MARKER: U256 = U256::from_limbs([0, 0, 0, 1 << 32]);
+
+const MIN_SQRT_RATIO_PLUS_ONE: U160 = U160::from_limbs([4_295_128_740, 0, 0]);
+const MAX_SQRT_RATIO_MINUS_ONE: U160 =
+    U160::from_limbs([0x5d95_1d52_6398_8d25, 0xefd1_fc6a_5064_8849, 0xfffd_8963]);
+
+/// What replaying a solution on top of its block produced.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VerifiedSolution {
+    pub input: U256,
+    /// Profit token balance left once every hop ran.
+    pub realized_output: U256,
+    /// Gas of the simulated swap calls, approvals included.
+    pub gas_used: u64,
+}
+
+impl VerifiedSolution {
+    pub fn realized_profit(&self) -> U256 {
+        self.realized_output.saturating_sub(self.input)
+    }
+}
+
+/// What the engine does with a solution that fails verification.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VerificationPolicy {
+    Drop,
+    /// Report it after every verified solution.
+    Downgrade,
+    /// Report it as is, with whatever the simulation found attached.
+    PassThrough,
+}
+
+/// Where a token keeps its `balanceOf` mapping.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BalanceSlot {
+    /// Declared at this slot by Solidity, which hashes the holder first.
+    Solidity(u64),
+    /// Declared at this slot by Vyper, which hashes the slot first.
+    Vyper(u64),
+}
+
+impl BalanceSlot {
+    /// Storage key of `holder`'s balance.
+    pub fn key(&self, holder: Address) -> B256 {
+        match *self {
+            BalanceSlot::Solidity(slot) => keccak256((holder, U256::from(slot)).abi_encode()),
+            BalanceSlot::Vyper(slot) => keccak256((U256::from(slot), holder).abi_encode()),
+        }
+    }
+}
+
+/// One call the executor makes, optionally with its balance of a token patched in.
+struct ExecutorCall {
+    target: Address,
+    data: Vec<u8>,
+    balance_patch: Option<(Address, usize)>,
+}
+
+impl ExecutorCall {
+    fn new(target: Address, data: Vec<u8>) -> Self {
+        Self {
+            target,
+            data,
+            balance_patch: None,
+        }
+    }
+
+    /// Spends the executor's whole balance of `token` in place of `AMOUNT_PLACEHOLDER`.
+    fn spending(target: Address, data: Vec<u8>, token: Address) -> Result<Self, ArbRsError> {
+        let placeholder = AMOUNT_PLACEHOLDER.to_be_bytes::<32>();
+        let offset = data
+            .windows(32)
+            .position(|word| word == placeholder)
+            .ok_or_else(|| {
+                ArbRsError::CalculationError("Swap calldata has no amount to patch".to_string())
+            })?;
+        Ok(Self {
+            target,
+            data,
+            balance_patch: Some((token, offset)),
+        })
+    }
+
+    fn request(&self) -> TransactionRequest {
+        let (token, offset) = self.balance_patch.unwrap_or_default();
+        let mut input = (self.target, token, U256::from(offset)).abi_encode_params();
+        input.extend_from_slice(&self.data);
+        TransactionRequest::default()
+            .from(SIMULATION_SENDER)
+            .to(EXECUTOR)
+            .input(input.into())
+    }
+}
+
+/// Replays arbitrage solutions with `eth_simulateV1`, from an executor funded with the input
+/// through a storage override.
+#[derive(Debug, Default)]
+pub struct SolutionVerifier {
+    balance_slots: DashMap<Address, BalanceSlot>,
+}
+
+impl SolutionVerifier {
+    /// Trades `input` of the profit token through `cycle` on top of `block`. Each hop spends
+    /// everything the previous one returned. Fails if any hop reverts.
+    pub async fn verify<P: Provider + Send + Sync + 'static + ?Sized>(
+        &self,
+        provider: &P,
+        cycle: &ArbitrageCycle<P>,
+        input: U256,
+        block: u64,
+    ) -> Result<VerifiedSolution, ArbRsError> {
+        let path = &cycle.path;
+        let profit_token = path.profit_token.address();
+        let balance_slot = self.balance_slot(provider, profit_token, block).await?;
+
+        // V2 pairs are told their output, so it's quoted from the pools' state at `block`.
+        let snapshots =
+            join_all(path.pools.iter().map(|pool| pool.get_snapshot(Some(block)))).await;
+        let mut hops = Vec::with_capacity(path.pools.len());
+        let mut amount = input;
+        for (i, (pool, snapshot)) in path.pools.iter().zip(snapshots).enumerate() {
+            let (token_in, token_out) = (&path.path[i], &path.path[i + 1]);
+            let amount_out = pool.calculate_tokens_out(
+                token_in,
+                token_out,
+                token_in.received_amount(amount),
+                &snapshot?,
+            )?;
+            hops.push(hop_calls(pool, token_in, token_out, amount_out)?);
+            amount = amount_out;
+        }
+
+        let mut calls: Vec<TransactionRequest> =
+            hops.iter().flatten().map(ExecutorCall::request).collect();
+        calls.push(
+            TransactionRequest::default()
+                .from(SIMULATION_SENDER)
+                .to(profit_token)
+                .input(balanceOfCall { owner: EXECUTOR }.abi_encode().into()),
+        );
+        let payload = SimulatePayload {
+            block_state_calls: vec![SimBlock {
+                block_overrides: None,
+                state_overrides: Some(
+                    StateOverridesBuilder::default()
+                        .with_code(EXECUTOR, Bytes::from_static(&EXECUTOR_CODE))
+                        .with_state_diff(
+                            profit_token,
+                            [(balance_slot.key(EXECUTOR), B256::from(input))],
+                        )
+                        .build(),
+                ),
+                calls,
+            }],
+            trace_transfers: false,
+            validation: false,
+            return_full_transactions: false,
+        };
+        let blocks = provider
+            .simulate(&payload)
+            .block_id(BlockId::from(block))
+            .await
+            .map_err(|e| ArbRsError::ProviderError(e.to_string()))?;
+        let results = blocks
+            .first()
+            .map(|block| &block.calls)
+            .ok_or_else(|| ArbRsError::ProviderError("Empty simulation result".to_string()))?;
+
+        let mut results = results.iter();
+        let mut gas_used = 0;
+        for (i, hop) in hops.iter().enumerate() {
+            for _ in hop {
+                let result = results.next().ok_or_else(|| {
+                    ArbRsError::ProviderError("Missing simulated call result".to_string())
+                })?;
+                if !result.status {
+                    return Err(ArbRsError::ContractError(format!(
+                        "Hop {} through {} reverted in simulation: {}",
+                        i,
+                        path.pools[i].address(),
+                        result.return_data
+                    )));
+                }
+                gas_used += result.gas_used;
+            }
+        }
+        let balance = results.next().ok_or_else(|| {
+            ArbRsError::ProviderError("Missing simulated call result".to_string())
+        })?;
+        let realized_output = balanceOfCall::abi_decode_returns(&balance.return_data)
+            .map_err(|e| ArbRsError::AbiDecodeError(e.to_string()))?;
+        Ok(VerifiedSolution {
+            input,
+            realized_output,
+            gas_used,
+        })
+    }
+
+    /// `token`'s balance slot, found once and then cached.
+    pub async fn balance_slot<P: Provider + Send + Sync + 'static + ?Sized>(
+        &self,
+        provider: &P,
+        token: Address,
+        block: u64,
+    ) -> Result<BalanceSlot, ArbRsError> {
+        if let Some(slot) = self.balance_slots.get(&token) {
+            return Ok(*slot);
+        }
+        let slot = find_balance_slot(provider, token, block).await?;
+        self.balance_slots.insert(token, slot);
+        Ok(slot)
+    }
+}
+
+/// Finds `token`'s balance mapping in a single `eth_call`: every candidate slot of both
+/// layouts is given a distinct balance, and `balanceOf` tells which one it read.
+pub async fn find_balance_slot<P: Provider + Send + Sync + 'static + ?Sized>(
+    provider: &P,
+    token: Address,
+    block: u64,
+) -> Result<BalanceSlot, ArbRsError> {
+    let candidates: Vec<BalanceSlot> = (0..BALANCE_SLOT_CANDIDATES)
+        .flat_map(|slot| [BalanceSlot::Solidity(slot), BalanceSlot::Vyper(slot)])
+        .collect();
+    let overrides = StateOverridesBuilder::default()
+        .with_state_diff(
+            token,
+            candidates.iter().enumerate().map(|(i, candidate)| {
+                (
+                    candidate.key(EXECUTOR),
+                    B256::from(BALANCE_SLOT_MARKER + U256::from(i)),
+                )
+            }),
+        )
+        .build();
+    let request = TransactionRequest::default()
+        .to(token)
+        .input(balanceOfCall { owner: EXECUTOR }.abi_encode().into());
+    let result = provider
+        .call(request)
+        .overrides(overrides)
+        .block(BlockId::from(block))
+        .await
+        .map_err(|e| ArbRsError::ProviderError(e.to_string()))?;
+    let balance = balanceOfCall::abi_decode_returns(&result)
+        .map_err(|e| ArbRsError::AbiDecodeError(e.to_string()))?;
+
+    balance
+        .checked_sub(BALANCE_SLOT_MARKER)
+        .and_then(|index| candidates.get(usize::try_from(index).ok()?).copied())
+        .ok_or_else(|| {
+            ArbRsError::TokenStandardError(token, "Balance mapping not found".to_string())
+        })
+}
+
+/// The executor calls swapping `token_in` for `amount_out` of `token_out` through `pool`,
+/// spending its whole balance of `token_in`.
+fn hop_calls<P: Provider + Send + Sync + 'static + ?Sized>(
+    pool: &Arc<dyn LiquidityPool<P>>,
+    token_in: &Token<P>,
+    token_out: &Token<P>,
+    amount_out: U256,
+) -> Result<Vec<ExecutorCall>, ArbRsError> {
+    let address = pool.address();
+    let zero_for_one = pool
+        .get_all_tokens()
+        .first()
+        .is_some_and(|token0| **token0 == *token_in);
+    match pool.dex_kind() {
+        Some(DexKind::UniswapV2) => {
+            let (amount0_out, amount1_out) = if zero_for_one {
+                (U256::ZERO, amount_out)
+            } else {
+                (amount_out, U256::ZERO)
+            };
+            let transfer = transferCall {
+                to: address,
+                amount: AMOUNT_PLACEHOLDER,
+            };
+            let swap = IUniswapV2Pair::swapCall {
+                amount0Out: amount0_out,
+                amount1Out: amount1_out,
+                to: EXECUTOR,
+                data: Bytes::new(),
+            };
+            Ok(vec![
+                ExecutorCall::spending(
+                    token_in.address(),
+                    transfer.abi_encode(),
+                    token_in.address(),
+                )?,
+                ExecutorCall::new(address, swap.abi_encode()),
+            ])
+        }
+        Some(DexKind::UniswapV3) => {
+            let swap = IUniswapV3Pool::swapCall {
+                recipient: EXECUTOR,
+                zeroForOne: zero_for_one,
+                amountSpecified: I256::from_raw(AMOUNT_PLACEHOLDER),
+                sqrtPriceLimitX96: if zero_for_one {
+                    MIN_SQRT_RATIO_PLUS_ONE
+                } else {
+                    MAX_SQRT_RATIO_MINUS_ONE
+                },
+                // The callback pays the pool in the token encoded here.
+                data: token_in.address().abi_encode().into(),
+            };
+            Ok(vec![ExecutorCall::spending(
+                address,
+                swap.abi_encode(),
+                token_in.address(),
+            )?])
+        }
+        Some(DexKind::Curve) => {
+            if let Some(underlying) = pool.as_any().downcast_ref::<UnderlyingCurvePool<P>>() {
+                let index = |token: &Token<P>| {
+                    underlying.underlying_index(token).ok_or_else(|| {
+                        ArbRsError::CalculationError("Underlying token not found".to_string())
+                    })
+                };
+                let exchange = ICurvePool::exchange_underlyingCall {
+                    i: index(token_in)? as i128,
+                    j: index(token_out)? as i128,
+                    dx: AMOUNT_PLACEHOLDER,
+                    min_dy: U256::ZERO,
+                };
+                return Ok(vec![
+                    approval(token_in, address),
+                    ExecutorCall::spending(address, exchange.abi_encode(), token_in.address())?,
+                ]);
+            }
+            let curve_pool = pool
+                .as_any()
+                .downcast_ref::<CurveStableswapPool<P>>()
+                .ok_or_else(|| ArbRsError::InvalidPool(address, "Not a Curve pool".to_string()))?;
+            let index = |token: &Token<P>| {
+                curve_pool
+                    .coin_index(token)
+                    .ok_or_else(|| ArbRsError::CalculationError("Token not found".to_string()))
+            };
+            let (i, j) = (index(token_in)?, index(token_out)?);
+            let exchange = if curve_pool.attributes.swap_strategy.is_cryptoswap() {
+                ICryptoPool::exchangeCall {
+                    i: U256::from(i),
+                    j: U256::from(j),
+                    dx: AMOUNT_PLACEHOLDER,
+                    min_dy: U256::ZERO,
+                }
+                .abi_encode()
+            } else {
+                ICurvePool::exchangeCall {
+                    i: i as i128,
+                    j: j as i128,
+                    dx: AMOUNT_PLACEHOLDER,
+                    min_dy: U256::ZERO,
+                }
+                .abi_encode()
+            };
+            Ok(vec![
+                approval(token_in, address),
+                ExecutorCall::spending(address, exchange, token_in.address())?,
+            ])
+        }
+        Some(DexKind::Balancer) => {
+            let balancer_pool =
+                pool.as_any()
+                    .downcast_ref::<BalancerPool<P>>()
+                    .ok_or_else(|| {
+                        ArbRsError::InvalidPool(address, "Not a Balancer pool".to_string())
+                    })?;
+            let vault = balancer_pool.vault();
+            let swap = IBalancerVault::swapCall {
+                singleSwap: IBalancerVault::SingleSwap {
+                    poolId: balancer_pool.pool_id.into(),
+                    kind: 0,
+                    assetIn: token_in.address(),
+                    assetOut: token_out.address(),
+                    amount: AMOUNT_PLACEHOLDER,
+                    userData: Bytes::new(),
+                },
+                funds: IBalancerVault::FundManagement {
+                    sender: EXECUTOR,
+                    fromInternalBalance: false,
+                    recipient: EXECUTOR,
+                    toInternalBalance: false,
+                },
+                limit: U256::ZERO,
+                deadline: U256::MAX,
+            };
+            Ok(vec![
+                approval(token_in, vault),
+                ExecutorCall::spending(vault, swap.abi_encode(), token_in.address())?,
+            ])
+        }
+        // V4 swaps settle through the pool manager's unlock callback, which the executor
+        // doesn't implement.
+        Some(DexKind::UniswapV4) | None => Err(ArbRsError::CalculationError(format!(
+            "Cannot replay a swap through pool {address}"
+        ))),
+    }
+}
+
+fn approval<P: Provider + Send + Sync + 'static + ?Sized>(
+    token: &Token<P>,
+    spender: Address,
+) -> ExecutorCall {
+    let approve = approveCall {
+        spender,
+        amount: U256::MAX,
+    };
+    ExecutorCall::new(token.address(), approve.abi_encode())
+}
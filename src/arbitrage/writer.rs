@@ -0,0 +1,60 @@
+use std::future::Future;
+use std::sync::Arc;
+use tokio::sync::{Mutex, mpsc};
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+
+/// Handle to a background task writing the items of a channel one at a time, shared by the
+/// clones of the handle its owner hands out.
+#[derive(Debug, Clone)]
+pub struct BackgroundWriter {
+    /// Cancelled by [`Self::shutdown`] to have the task write what's queued and stop.
+    cancel: CancellationToken,
+    task: Arc<Mutex<Option<JoinHandle<()>>>>,
+}
+
+impl BackgroundWriter {
+    /// Starts a task passing every item `receiver` yields to `write`, until all senders are
+    /// gone or the writer is shut down. Must be called from within a Tokio runtime.
+    pub fn spawn<T, W, F>(mut receiver: mpsc::Receiver<T>, mut write: W) -> Self
+    where
+        T: Send + 'static,
+        W: FnMut(T) -> F + Send + 'static,
+        F: Future<Output = ()> + Send,
+    {
+        let cancel = CancellationToken::new();
+        let task_cancel = cancel.clone();
+        let task = tokio::spawn(async move {
+            loop {
+                let item = tokio::select! {
+                    item = receiver.recv() => item,
+                    _ = task_cancel.cancelled() => {
+                        // Refuses new items; those already queued are still received.
+                        receiver.close();
+                        receiver.recv().await
+                    }
+                };
+                let Some(item) = item else {
+                    break;
+                };
+                write(item).await;
+            }
+        });
+        Self {
+            cancel,
+            task: Arc::new(Mutex::new(Some(task))),
+        }
+    }
+
+    /// Writes the items still queued and waits for the task to stop. Items sent afterwards
+    /// are refused.
+    pub async fn shutdown(&self) {
+        self.cancel.cancel();
+        let Some(task) = self.task.lock().await.take() else {
+            return;
+        };
+        if let Err(e) = task.await {
+            tracing::warn!("Background writer failed: {:?}", e);
+        }
+    }
+}
@@ -1,3 +1,4 @@
 pub mod pool;
 pub mod scaling_helper;
+pub mod stable_math;
 pub mod weighted_math;
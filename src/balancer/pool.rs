@@ -1,44 +1,154 @@
 use crate::{
     TokenLike,
-    math::balancer::fixed_point as fp,
-    core::token::Token,
-    db::DbManager,
+    balancer::{
+        scaling_helper::{compute_scaling_factor, downscale_down, downscale_up, upscale},
+        stable_math::{self, AMP_PRECISION},
+        weighted_math,
+    },
+    math::{balancer::fixed_point as fp, utils::u256_to_f64},
+    dex::PoolKind,
+    core::{
+        messaging::{Publisher, PublisherMessage, Subscriber, SubscriberList},
+        token::Token,
+    },
     errors::ArbRsError,
     manager::token_manager::TokenManager,
-    pool::{LiquidityPool, PoolSnapshot},
+    pool::{
+        CalibrationBucket, DexKind, LiquidityPool, PoolSnapshot, PriceMatrix, StateUpdate,
+        SwapGasCosts,
+        last_trade::{LastTrade, LastTradeTracker},
+        quoter::{QuotePool, Quoter},
+    },
 };
+#[cfg(feature = "db")]
+use crate::db::PoolRecord;
 use alloy_primitives::{Address, U256};
 use alloy_provider::Provider;
-use alloy_rpc_types::{BlockId, TransactionRequest};
+use alloy_rpc_types::{BlockId, Log, TransactionRequest};
 use alloy_sol_types::{SolCall, sol};
 use async_trait::async_trait;
-use balancer_maths_rust::common::maths::{div_down_fixed, div_up_fixed, mul_down_fixed};
-use balancer_maths_rust::common::maths::pow_up_fixed;
-use balancer_maths_rust::common::maths::complement_fixed;
-use num_bigint::BigInt;
-use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
 use std::fmt::{Formatter, Result as FmtResult};
-use std::{any::Any, fmt::Debug, sync::Arc};
-
-lazy_static! {
-    pub static ref WAD: BigInt = BigInt::from(10).pow(18);
-}
+use std::{any::Any, fmt::Debug, sync::{Arc, Weak}};
+use tokio::sync::Mutex;
 
 sol! {
     contract IVault {
         function getPoolTokens(bytes32 poolId) external view returns (address[] tokens, uint256[] balances, uint256 lastChangeBlock);
+        function getPausedState() external view returns (bool paused, uint256 pauseWindowEndTime, uint256 bufferPeriodEndTime);
     }
     contract IWeightedPool {
         function getPoolId() external view returns (bytes32);
         function getVault() external view returns (address);
         function getSwapFeePercentage() external view returns (uint256);
         function getNormalizedWeights() external view returns (uint256[]);
+        function getPausedState() external view returns (bool paused, uint256 pauseWindowEndTime, uint256 bufferPeriodEndTime);
+        function inRecoveryMode() external view returns (bool);
+    }
+    contract IStablePool {
+        function getAmplificationParameter() external view returns (uint256 value, bool isUpdating, uint256 precision);
+        function getScalingFactors() external view returns (uint256[]);
+        function getRateProviders() external view returns (address[]);
+        function getBptIndex() external view returns (uint256);
+        function getPriceRateCache(address token) external view returns (uint256 rate, uint256 duration, uint256 expires);
+    }
+}
+
+/// The invariant a Balancer pool trades on, told apart by the functions the pool implements.
+/// Stored as a pool record's `attributes_json`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BalancerPoolKind {
+    Weighted { weights: Vec<U256> },
+    /// Stable and meta-stable pools. Meta-stable pools price their tokens through rate
+    /// providers, whose cached rates `getScalingFactors` folds into the scaling factors.
+    Stable { rate_providers: Vec<Address> },
+    /// Composable stable pools, which register their own pre-minted BPT with the vault at
+    /// `bpt_index`. The BPT isn't tradable, so it is left out of the pool's tokens and of its
+    /// snapshots.
+    ComposableStable { bpt_index: usize, rate_providers: Vec<Address> },
+}
+
+impl Default for BalancerPoolKind {
+    fn default() -> Self {
+        BalancerPoolKind::Weighted { weights: Vec::new() }
     }
 }
 
-#[derive(Clone, Debug, Default)]
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
 pub struct BalancerPoolSnapshot {
     pub balances: Vec<U256>,
+    /// Set when the pool or its vault is paused, or the pool is in recovery mode.
+    /// Swaps revert in that state, so quoting against it fails with `PoolPaused`.
+    #[serde(default)]
+    pub is_paused: bool,
+    /// Block the snapshot was read at, `None` when read at the latest block.
+    #[serde(default)]
+    pub block_number: Option<u64>,
+    /// Swap fee at that block. Quotes fall back to the fee read at construction without it.
+    #[serde(default)]
+    pub swap_fee: Option<U256>,
+    /// Amplification parameter of a stable pool at that block, scaled by `AMP_PRECISION`.
+    #[serde(default)]
+    pub amplification: Option<U256>,
+    /// Scaling factors of a stable pool's tokens at that block, rates included.
+    #[serde(default)]
+    pub scaling_factors: Option<Vec<U256>>,
+}
+
+/// The result of a simulated swap on a Balancer pool.
+#[derive(Debug, Clone)]
+pub struct BalancerPoolSimulationResult {
+    pub amount_in: U256,
+    pub amount_out: U256,
+    pub initial_snapshot: BalancerPoolSnapshot,
+    pub final_snapshot: BalancerPoolSnapshot,
+}
+
+/// The emergency pause of a vault, which halts every pool it holds. Shared by those pools
+/// so it is fetched once per block rather than once per pool.
+#[derive(Debug, Default)]
+pub struct VaultPauseState {
+    vault: Address,
+    checked: Mutex<Option<(u64, bool)>>,
+}
+
+impl VaultPauseState {
+    pub fn new(vault: Address) -> Self {
+        Self { vault, checked: Mutex::new(None) }
+    }
+
+    pub fn vault(&self) -> Address { self.vault }
+
+    /// Whether the vault is paused at `block_number`. Only results for an explicit block are cached.
+    pub async fn is_paused<P: Provider + Send + Sync + 'static + ?Sized>(
+        &self,
+        provider: &P,
+        block_number: Option<u64>,
+    ) -> Result<bool, ArbRsError> {
+        let mut checked = self.checked.lock().await;
+        if let (Some(block), Some((checked_block, paused))) = (block_number, *checked)
+            && block == checked_block
+        {
+            return Ok(paused);
+        }
+
+        let request = TransactionRequest::default().to(self.vault).input(IVault::getPausedStateCall {}.abi_encode().into());
+        let result_bytes = provider.call(request).block(block_number.map(BlockId::from).unwrap_or(BlockId::latest())).await?;
+        let paused = IVault::getPausedStateCall::abi_decode_returns(&result_bytes)?.paused;
+
+        if let Some(block) = block_number {
+            *checked = Some((block, paused));
+        }
+        Ok(paused)
+    }
+
+    /// Forgets the cached result if it was read at `block_number` or later.
+    pub async fn invalidate_from(&self, block_number: u64) {
+        let mut checked = self.checked.lock().await;
+        if checked.is_some_and(|(block, _)| block >= block_number) {
+            *checked = None;
+        }
+    }
 }
 
 #[derive(Default)]
@@ -46,73 +156,326 @@ pub struct BalancerPool<P: Provider + Send + Sync + 'static + ?Sized> {
     pub address: Address,
     provider: Arc<P>,
     tokens: Vec<Arc<Token<P>>>,
-    weights: Vec<U256>,
+    kind: BalancerPoolKind,
     fee: U256,
     vault_address: Address,
     pub pool_id: [u8; 32],
+    vault_pause: Arc<VaultPauseState>,
+    last_trades: LastTradeTracker,
+    /// State seen by the last `update_state`, to tell whether the next one changed anything.
+    live_state: Mutex<Option<BalancerPoolSnapshot>>,
+    subscribers: SubscriberList<P>,
+}
+
+#[async_trait]
+impl<P: Provider + Send + Sync + 'static + ?Sized> Publisher<P> for BalancerPool<P> {
+    async fn subscribe(&self, subscriber: Weak<dyn Subscriber<P>>) {
+        self.subscribers.subscribe(subscriber).await;
+    }
+
+    async fn unsubscribe(&self, subscriber_id: usize) {
+        self.subscribers.unsubscribe(subscriber_id).await;
+    }
+
+    async fn notify_subscribers(&self, message: PublisherMessage) {
+        self.subscribers.notify(message).await;
+    }
 }
 
 impl<P: Provider + Send + Sync + 'static + ?Sized> BalancerPool<P> {
+    /// Reads the pool's parameters and detects its kind: pools with `getNormalizedWeights`
+    /// are weighted, pools with `getAmplificationParameter` stable, and stable pools with
+    /// `getBptIndex` composable. A pool implementing neither, such as a linear pool, or a
+    /// weighted pool holding its own BPT, such as a managed pool, is an `InvalidPool` error.
     pub async fn new(
         address: Address,
         provider: Arc<P>,
         token_manager: Arc<TokenManager<P>>,
-        _db_manager: Arc<DbManager>,
     ) -> Result<Self, ArbRsError> {
-        let (pool_id_res, vault_res, fee_res, weights_res) = tokio::join!(
+        let (pool_id_res, vault_res, fee_res, weights_res, amp_res, bpt_index_res, rate_providers_res) = tokio::join!(
             provider.call(TransactionRequest::default().to(address).input(IWeightedPool::getPoolIdCall {}.abi_encode().into())),
             provider.call(TransactionRequest::default().to(address).input(IWeightedPool::getVaultCall {}.abi_encode().into())),
             provider.call(TransactionRequest::default().to(address).input(IWeightedPool::getSwapFeePercentageCall {}.abi_encode().into())),
             provider.call(TransactionRequest::default().to(address).input(IWeightedPool::getNormalizedWeightsCall {}.abi_encode().into())),
+            provider.call(TransactionRequest::default().to(address).input(IStablePool::getAmplificationParameterCall {}.abi_encode().into())),
+            provider.call(TransactionRequest::default().to(address).input(IStablePool::getBptIndexCall {}.abi_encode().into())),
+            provider.call(TransactionRequest::default().to(address).input(IStablePool::getRateProvidersCall {}.abi_encode().into())),
         );
 
         let pool_id = IWeightedPool::getPoolIdCall::abi_decode_returns(&pool_id_res?)?;
         let vault_address = IWeightedPool::getVaultCall::abi_decode_returns(&vault_res?)?;
         let fee = IWeightedPool::getSwapFeePercentageCall::abi_decode_returns(&fee_res?)?;
-        let weights = IWeightedPool::getNormalizedWeightsCall::abi_decode_returns(&weights_res?)?;
 
         let pool_tokens_bytes = provider.call(TransactionRequest::default().to(vault_address).input(IVault::getPoolTokensCall { poolId: pool_id }.abi_encode().into())).await?;
         let pool_tokens_res = IVault::getPoolTokensCall::abi_decode_returns(&pool_tokens_bytes)?;
-        let token_addresses = pool_tokens_res.tokens;
+        let mut token_addresses = pool_tokens_res.tokens;
+
+        // The probes revert on pools that don't implement them.
+        let weights = weights_res.ok().and_then(|bytes| IWeightedPool::getNormalizedWeightsCall::abi_decode_returns(&bytes).ok());
+        let is_stable = amp_res.ok().is_some_and(|bytes| IStablePool::getAmplificationParameterCall::abi_decode_returns(&bytes).is_ok());
+        let bpt_index = bpt_index_res.ok().and_then(|bytes| IStablePool::getBptIndexCall::abi_decode_returns(&bytes).ok());
+        let mut rate_providers = rate_providers_res
+            .ok()
+            .and_then(|bytes| IStablePool::getRateProvidersCall::abi_decode_returns(&bytes).ok())
+            .unwrap_or_default();
 
-        let token_futs = token_addresses.into_iter().map(|addr| token_manager.get_token(addr));
-        let tokens: Vec<_> = futures::future::join_all(token_futs).await.into_iter().collect::<Result<_, _>>()?;
+        let kind = match (weights, is_stable, bpt_index) {
+            // Managed pools report weights too, but register their own BPT as a token.
+            (Some(_), _, _) if token_addresses.contains(&address) => {
+                return Err(ArbRsError::InvalidPool(address, "registers its own BPT, as managed pools do".into()));
+            }
+            (Some(weights), _, _) if weights.len() != token_addresses.len() => {
+                return Err(ArbRsError::InvalidPool(address, format!("has {} weights for {} tokens", weights.len(), token_addresses.len())));
+            }
+            (Some(weights), _, _) => BalancerPoolKind::Weighted { weights },
+            (None, true, Some(bpt_index)) => {
+                let bpt_index = bpt_index.saturating_to::<usize>();
+                if token_addresses.get(bpt_index) != Some(&address) {
+                    return Err(ArbRsError::InvalidPool(address, format!("has no BPT at index {bpt_index}")));
+                }
+                token_addresses.remove(bpt_index);
+                if rate_providers.len() > token_addresses.len() {
+                    rate_providers.remove(bpt_index);
+                }
+                BalancerPoolKind::ComposableStable { bpt_index, rate_providers }
+            }
+            (None, true, None) => BalancerPoolKind::Stable { rate_providers },
+            (None, false, _) => {
+                return Err(ArbRsError::InvalidPool(address, "implements neither weighted nor stable math".into()));
+            }
+        };
+
+        let tokens = token_manager.get_token_list(&token_addresses).await?;
 
         Ok(Self {
             address,
             provider,
             tokens,
-            weights,
+            kind,
             fee,
             vault_address,
             pool_id: pool_id.0,
+            vault_pause: Arc::new(VaultPauseState::new(vault_address)),
+            last_trades: LastTradeTracker::default(),
+            live_state: Mutex::new(None),
+            subscribers: SubscriberList::default(),
         })
     }
-    
+
+    /// Rebuilds a pool from its stored record, reading only the swap fee: the pool id, vault
+    /// and kind never change. A record stored without them is an `InvalidPool` error.
+    #[cfg(feature = "db")]
+    pub async fn from_record(
+        record: &PoolRecord,
+        provider: Arc<P>,
+        token_manager: Arc<TokenManager<P>>,
+    ) -> Result<Self, ArbRsError> {
+        let (Some(pool_id), Some(vault_address), Some(kind_json)) = (
+            record.balancer_pool_id,
+            record.balancer_vault,
+            record.attributes_json.as_deref(),
+        ) else {
+            return Err(ArbRsError::InvalidPool(record.address, "missing Balancer metadata".into()));
+        };
+        let kind: BalancerPoolKind = serde_json::from_str(kind_json)
+            .map_err(|e| ArbRsError::InvalidPool(record.address, format!("invalid stored kind: {e}")))?;
+
+        let fee_bytes = provider.call(TransactionRequest::default().to(record.address).input(IWeightedPool::getSwapFeePercentageCall {}.abi_encode().into())).await?;
+        let fee = IWeightedPool::getSwapFeePercentageCall::abi_decode_returns(&fee_bytes)?;
+        let tokens = token_manager.get_token_list(&record.tokens).await?;
+
+        Ok(Self::from_parts(record.address, provider, tokens, Vec::new(), fee, vault_address, pool_id.0).with_kind(kind))
+    }
+
+    /// The kind as stored in a pool record's `attributes_json`.
+    pub fn kind_json(&self) -> String {
+        serde_json::to_string(&self.kind).expect("pool kinds serialize")
+    }
+
+    /// Builds a weighted pool from already known parameters, without any network calls.
+    pub fn from_parts(
+        address: Address,
+        provider: Arc<P>,
+        tokens: Vec<Arc<Token<P>>>,
+        weights: Vec<U256>,
+        fee: U256,
+        vault_address: Address,
+        pool_id: [u8; 32],
+    ) -> Self {
+        Self {
+            address,
+            provider,
+            tokens,
+            kind: BalancerPoolKind::Weighted { weights },
+            fee,
+            vault_address,
+            pool_id,
+            vault_pause: Arc::new(VaultPauseState::new(vault_address)),
+            last_trades: LastTradeTracker::default(),
+            live_state: Mutex::new(None),
+            subscribers: SubscriberList::default(),
+        }
+    }
+
+    /// Replaces the kind the pool was built with, e.g. to build a stable pool from parts.
+    /// `tokens` must leave out a composable stable pool's BPT.
+    pub fn with_kind(mut self, kind: BalancerPoolKind) -> Self {
+        self.kind = kind;
+        self
+    }
+
+    /// Shares the vault pause check with the other pools of the same vault.
+    pub fn with_vault_pause(mut self, vault_pause: Arc<VaultPauseState>) -> Self {
+        self.vault_pause = vault_pause;
+        self
+    }
+
+    /// Simulates a `GIVEN_IN` swap, returning the output and the pool's vault balances after
+    /// it. The vault credits the whole input, swap fee included, to the pool. Weighted and
+    /// stable pools collect protocol fees as BPT on joins and exits, so a swap moves no other
+    /// balance.
+    pub fn simulate_swap(
+        &self,
+        token_in: &Token<P>,
+        token_out: &Token<P>,
+        amount_in: U256,
+        snapshot: &BalancerPoolSnapshot,
+    ) -> Result<BalancerPoolSimulationResult, ArbRsError> {
+        let wrapped = PoolSnapshot::Balancer(snapshot.clone());
+        let (_, i, j) = self.swap_context(token_in, token_out, &wrapped)?;
+        let amount_out = self.calculate_tokens_out(token_in, token_out, amount_in, &wrapped)?;
+
+        let mut final_snapshot = snapshot.clone();
+        final_snapshot.balances[i] += amount_in;
+        final_snapshot.balances[j] = snapshot.balances[j]
+            .checked_sub(amount_out)
+            .ok_or(ArbRsError::InsufficientLiquidity)?;
+
+        Ok(BalancerPoolSimulationResult {
+            amount_in,
+            amount_out,
+            initial_snapshot: snapshot.clone(),
+            final_snapshot,
+        })
+    }
+
     pub fn fee(&self) -> U256 { self.fee }
-    pub fn weights(&self) -> &Vec<U256> { &self.weights }
+    pub fn vault(&self) -> Address { self.vault_address }
+    pub fn kind(&self) -> &BalancerPoolKind { &self.kind }
+
+    /// The normalized weights of a weighted pool, empty for stable pools.
+    pub fn weights(&self) -> &[U256] {
+        match &self.kind {
+            BalancerPoolKind::Weighted { weights } => weights,
+            _ => &[],
+        }
+    }
+
+    /// The kind the pool is stored as.
+    pub fn pool_kind(&self) -> PoolKind {
+        match self.kind {
+            BalancerPoolKind::Weighted { .. } => PoolKind::BalancerWeighted,
+            _ => PoolKind::BalancerStable,
+        }
+    }
 }
 
 #[async_trait]
 impl<P: Provider + Send + Sync + 'static + ?Sized> LiquidityPool<P> for BalancerPool<P> {
     fn address(&self) -> Address { self.address }
     fn get_all_tokens(&self) -> Vec<Arc<Token<P>>> { self.tokens.clone() }
+    /// The Vault balances, without a composable stable pool's BPT.
+    fn reserves_summary(&self, snapshot: &PoolSnapshot) -> Vec<(Arc<Token<P>>, U256)> {
+        let PoolSnapshot::Balancer(state) = snapshot else { return Vec::new() };
+        self.tokens.iter().cloned().zip(state.balances.iter().copied()).collect()
+    }
     fn as_any(&self) -> &dyn Any { self }
+
+    fn last_trade_tracker(&self) -> Option<&LastTradeTracker> {
+        Some(&self.last_trades)
+    }
+
+    fn dex_kind(&self) -> Option<DexKind> {
+        Some(DexKind::Balancer)
+    }
+
+    fn gas_estimate(
+        &self,
+        _token_in: &Token<P>,
+        _token_out: &Token<P>,
+        _amount_in: U256,
+        _snapshot: &PoolSnapshot,
+        costs: &SwapGasCosts,
+    ) -> u64 {
+        costs.balancer
+    }
+
+    fn calibration_bucket(&self) -> Option<CalibrationBucket> {
+        match self.kind {
+            BalancerPoolKind::Weighted { .. } => Some(CalibrationBucket::new("balancer", "weighted")),
+            _ => Some(CalibrationBucket::new("balancer", "stable")),
+        }
+    }
+
+    /// Swaps are emitted by the vault, tagged with the pool id.
+    fn record_swap_log(&self, log: &Log) -> bool {
+        if log.address() != self.vault_address {
+            return false;
+        }
+        match LastTrade::from_balancer_swap(log, self.pool_id) {
+            Some(trade) => {
+                self.last_trades.record(trade);
+                true
+            }
+            None => false,
+        }
+    }
     
-    async fn update_state(&self) -> Result<(), ArbRsError> {
-        Ok(())
+    async fn update_state(&self) -> Result<StateUpdate, ArbRsError> {
+        let block_number = self.provider.get_block_number().await?;
+        let snapshot = self.fetch_snapshot(Some(block_number)).await?;
+        let mut live_state = self.live_state.lock().await;
+        let unchanged = live_state.as_ref().is_some_and(|live| {
+            live.balances == snapshot.balances
+                && live.is_paused == snapshot.is_paused
+                && live.swap_fee == snapshot.swap_fee
+                && live.amplification == snapshot.amplification
+                && live.scaling_factors == snapshot.scaling_factors
+        });
+        if unchanged {
+            return Ok(StateUpdate::Unchanged);
+        }
+        let message = PublisherMessage::BalancerStateUpdate {
+            pool: self.address,
+            block_number,
+            balances: snapshot.balances.clone(),
+            swap_fee: snapshot.swap_fee.unwrap_or(self.fee),
+            is_paused: snapshot.is_paused,
+        };
+        *live_state = Some(snapshot);
+        drop(live_state);
+
+        self.notify_subscribers(message).await;
+        Ok(StateUpdate::Updated { block: block_number })
     }
 
-    async fn get_snapshot(&self, block_number: Option<u64>) -> Result<PoolSnapshot, ArbRsError> {
-        let call = IVault::getPoolTokensCall { poolId: self.pool_id.into() };
-        let request = TransactionRequest::default().to(self.vault_address).input(call.abi_encode().into());
-        let result_bytes = self.provider.call(request).block(block_number.map(BlockId::from).unwrap_or(BlockId::latest())).await?;
-        let pool_tokens_res = IVault::getPoolTokensCall::abi_decode_returns(&result_bytes)?;
+    /// The live state doesn't record its block, so it's dropped whatever the block and the
+    /// next `update_state` reports a change.
+    async fn invalidate_from(&self, block_number: u64) {
+        *self.live_state.lock().await = None;
+        self.vault_pause.invalidate_from(block_number).await;
+    }
 
-        let snapshot = BalancerPoolSnapshot { balances: pool_tokens_res.balances };
-        Ok(PoolSnapshot::Balancer(snapshot))
+    async fn get_snapshot(&self, block_number: Option<u64>) -> Result<PoolSnapshot, ArbRsError> {
+        crate::metrics::observe_snapshot(Some(DexKind::Balancer), async {
+            Ok(PoolSnapshot::Balancer(self.fetch_snapshot(block_number).await?))
+        })
+        .await
     }
 
+    /// Follows the vault's `BaseMinimalSwapInfoPool` and `BaseGeneralPool`: the fee is taken
+    /// from the raw input before upscaling, and the output is downscaled rounding down.
+    /// Stable pools recompute the invariant from the snapshot's balances on every swap.
     fn calculate_tokens_out(
         &self,
         token_in: &Token<P>,
@@ -120,76 +483,376 @@ impl<P: Provider + Send + Sync + 'static + ?Sized> LiquidityPool<P> for Balancer
         amount_in: U256,
         snapshot: &PoolSnapshot,
     ) -> Result<U256, ArbRsError> {
-        let balancer_snapshot = match snapshot {
-            PoolSnapshot::Balancer(s) => s,
-            _ => return Err(ArbRsError::CalculationError("Invalid snapshot for Balancer pool".into())),
+        self.swap_math(token_in, token_out, snapshot)?.amount_out(amount_in)
+    }
+
+    /// The input is downscaled rounding up, then grossed up by the fee. Weighted pools reject
+    /// outputs past 30% of the pool's balance, as the vault does.
+    fn calculate_tokens_in(&self, token_in: &Token<P>, token_out: &Token<P>, amount_out: U256, snapshot: &PoolSnapshot) -> Result<U256, ArbRsError> {
+        self.swap_math(token_in, token_out, snapshot)?.amount_in(amount_out)
+    }
+
+    fn to_quoter(&self, snapshot: &PoolSnapshot) -> Result<QuotePool, ArbRsError> {
+        let PoolSnapshot::Balancer(balancer_snapshot) = snapshot else {
+            return Err(ArbRsError::CalculationError("Invalid snapshot for Balancer pool".into()));
         };
+        Ok(QuotePool::Balancer(BalancerQuoter {
+            address: self.address,
+            tokens: self.tokens.iter().map(|token| token.address()).collect(),
+            decimal_scaling_factors: self.tokens.iter().map(|token| compute_scaling_factor(token)).collect(),
+            kind: self.kind.clone(),
+            fee: self.fee,
+            snapshot: Arc::new(balancer_snapshot.clone()),
+        }))
+    }
 
-        let token_in_index = self.tokens.iter().position(|t| t.address() == token_in.address()).unwrap();
-        let token_out_index = self.tokens.iter().position(|t| t.address() == token_out.address()).unwrap();
+    async fn nominal_price(&self, token_in: &Token<P>, token_out: &Token<P>) -> Result<f64, ArbRsError> {
+        let price = self.absolute_price(token_in, token_out).await?;
+        Ok(price * 10f64.powi(token_in.decimals() as i32 - token_out.decimals() as i32))
+    }
 
-        let balance_in = fp::to_bigint(balancer_snapshot.balances[token_in_index]);
-        let balance_out = fp::to_bigint(balancer_snapshot.balances[token_out_index]);
-        let weight_in = fp::to_bigint(self.weights[token_in_index]);
-        let weight_out = fp::to_bigint(self.weights[token_out_index]);
-        let amount_in = fp::to_bigint(amount_in);
-        let fee = fp::to_bigint(self.fee);
+    async fn absolute_price(&self, token_in: &Token<P>, token_out: &Token<P>) -> Result<f64, ArbRsError> {
+        let token_index = |token: &Token<P>| self.tokens.iter().position(|t| t.address() == token.address());
+        let (Some(i), Some(j)) = (token_index(token_in), token_index(token_out)) else {
+            return Err(ArbRsError::CalculationError("Token not in Balancer pool".into()));
+        };
+        let PoolSnapshot::Balancer(snapshot) = self.get_snapshot(None).await? else {
+            return Err(ArbRsError::CalculationError("Invalid snapshot for Balancer pool".into()));
+        };
+        self.spot_price(&snapshot, i, j)
+            .ok_or_else(|| ArbRsError::CalculationError("Cannot calculate price: input balance is zero".into()))
+    }
 
-        let scaling_factor_in = BigInt::from(10).pow(18 - self.tokens[token_in_index].decimals() as u32);
-        let scaling_factor_out = BigInt::from(10).pow(18 - self.tokens[token_out_index].decimals() as u32);
+    async fn absolute_exchange_rate(&self, token_in: &Token<P>, token_out: &Token<P>) -> Result<f64, ArbRsError> {
+        let price = self.absolute_price(token_in, token_out).await?;
+        Ok(if price == 0.0 { f64::INFINITY } else { 1.0 / price })
+    }
 
-        let scaled_balance_in = balance_in * &scaling_factor_in;
-        let scaled_balance_out = balance_out * &scaling_factor_out;
-        let scaled_amount_in = amount_in * &scaling_factor_in;
+    fn prices_matrix(&self, snapshot: &PoolSnapshot) -> PriceMatrix {
+        let PoolSnapshot::Balancer(snapshot) = snapshot else {
+            return PriceMatrix::new();
+        };
+        let mut prices = PriceMatrix::new();
+        for (i, token_in) in self.tokens.iter().enumerate() {
+            for (j, token_out) in self.tokens.iter().enumerate().filter(|(j, _)| *j != i) {
+                if let Some(price) = self.spot_price(snapshot, i, j) {
+                    prices.insert((token_in.address(), token_out.address()), price);
+                }
+            }
+        }
+        prices
+    }
+}
 
-        // let fee_amount = mul_up_fixed(&scaled_amount_in, &fee)?;
-        // let amount_in_after_fee = &scaled_amount_in - fee_amount;
-        let amount_in_after_fee = mul_down_fixed(&scaled_amount_in, &(&*WAD - fee))?;
+impl<P: Provider + Send + Sync + 'static + ?Sized> BalancerPool<P> {
+    /// Balances, swap fee and pause state of the pool at `block_number`, and for stable pools
+    /// the amplification and scaling factors. Fails with `NoPoolStateAvailable` if the vault
+    /// doesn't know the pool at that block yet.
+    async fn fetch_snapshot(&self, block_number: Option<u64>) -> Result<BalancerPoolSnapshot, ArbRsError> {
+        let block_id = block_number.map(BlockId::from).unwrap_or(BlockId::latest());
+        let vault_paused = self.vault_pause.is_paused(self.provider.as_ref(), block_number).await?;
 
-        let denominator = &scaled_balance_in + &amount_in_after_fee;
-        let base = div_up_fixed(&scaled_balance_in, &denominator)?;
-        let exponent = div_down_fixed(&weight_in, &weight_out)?;
-        let power = pow_up_fixed(&base, &exponent)?;
+        let tokens_call = IVault::getPoolTokensCall { poolId: self.pool_id.into() };
+        let (tokens_res, paused_res, recovery_res, fee_res) = tokio::join!(
+            self.provider.call(TransactionRequest::default().to(self.vault_address).input(tokens_call.abi_encode().into())).block(block_id),
+            self.provider.call(TransactionRequest::default().to(self.address).input(IWeightedPool::getPausedStateCall {}.abi_encode().into())).block(block_id),
+            self.provider.call(TransactionRequest::default().to(self.address).input(IWeightedPool::inRecoveryModeCall {}.abi_encode().into())).block(block_id),
+            self.provider.call(TransactionRequest::default().to(self.address).input(IWeightedPool::getSwapFeePercentageCall {}.abi_encode().into())).block(block_id),
+        );
+        // The vault reverts with `INVALID_POOL_ID` for a pool registered after `block_id`.
+        let tokens_bytes = match tokens_res {
+            Err(e) if e.as_error_resp().is_some() => {
+                return Err(ArbRsError::NoPoolStateAvailable(block_number.unwrap_or_default()));
+            }
+            res => res?,
+        };
+        let pool_tokens_res = IVault::getPoolTokensCall::abi_decode_returns(&tokens_bytes)?;
+        if pool_tokens_res.tokens.is_empty() {
+            return Err(ArbRsError::NoPoolStateAvailable(block_number.unwrap_or_default()));
+        }
+        let pool_paused = IWeightedPool::getPausedStateCall::abi_decode_returns(&paused_res?)?.paused;
+        // Pools deployed before recovery mode was introduced don't implement `inRecoveryMode`.
+        let in_recovery = recovery_res
+            .ok()
+            .and_then(|bytes| IWeightedPool::inRecoveryModeCall::abi_decode_returns(&bytes).ok())
+            .unwrap_or(false);
+        let swap_fee = IWeightedPool::getSwapFeePercentageCall::abi_decode_returns(&fee_res?)?;
 
-        let scaled_amount_out = mul_down_fixed(&scaled_balance_out, &complement_fixed(&power)?)?;
+        let mut balances = pool_tokens_res.balances;
+        let (amplification, scaling_factors) = match self.kind {
+            BalancerPoolKind::Weighted { .. } => (None, None),
+            BalancerPoolKind::Stable { .. } | BalancerPoolKind::ComposableStable { .. } => {
+                let (amp_res, scaling_factors_res) = tokio::join!(
+                    self.provider.call(TransactionRequest::default().to(self.address).input(IStablePool::getAmplificationParameterCall {}.abi_encode().into())).block(block_id),
+                    self.provider.call(TransactionRequest::default().to(self.address).input(IStablePool::getScalingFactorsCall {}.abi_encode().into())).block(block_id),
+                );
+                let amplification = IStablePool::getAmplificationParameterCall::abi_decode_returns(&amp_res?)?.value;
+                let mut scaling_factors = match scaling_factors_res
+                    .ok()
+                    .and_then(|bytes| IStablePool::getScalingFactorsCall::abi_decode_returns(&bytes).ok())
+                {
+                    Some(scaling_factors) => scaling_factors,
+                    None => self.cached_rate_scaling_factors(block_id).await?,
+                };
+                if let BalancerPoolKind::ComposableStable { bpt_index, .. } = self.kind {
+                    balances.remove(bpt_index);
+                    if scaling_factors.len() > self.tokens.len() {
+                        scaling_factors.remove(bpt_index);
+                    }
+                }
+                (Some(amplification), Some(scaling_factors))
+            }
+        };
 
-        fp::to_u256(scaled_amount_out / scaling_factor_out)
+        Ok(BalancerPoolSnapshot {
+            balances,
+            is_paused: vault_paused || pool_paused || in_recovery,
+            block_number,
+            swap_fee: Some(swap_fee),
+            amplification,
+            scaling_factors,
+        })
     }
 
-    fn calculate_tokens_in(&self, token_in: &Token<P>, token_out: &Token<P>, amount_out: U256, snapshot: &PoolSnapshot) -> Result<U256, ArbRsError> {
-        let balancer_snapshot = match snapshot {
-            PoolSnapshot::Balancer(s) => s,
-            _ => return Err(ArbRsError::CalculationError("Invalid snapshot for Balancer pool".into())),
-        };
-
-        let token_in_index = self.tokens.iter().position(|t| t.address() == token_in.address()).unwrap();
-        let token_out_index = self.tokens.iter().position(|t| t.address() == token_out.address()).unwrap();
-
-        let scaling_factor_in = BigInt::from(10).pow(18 - self.tokens[token_in_index].decimals() as u32);
-        let scaling_factor_out = BigInt::from(10).pow(18 - self.tokens[token_out_index].decimals() as u32);
-        
-        let scaled_balance_in = fp::to_bigint(balancer_snapshot.balances[token_in_index]) * &scaling_factor_in;
-        let scaled_balance_out = fp::to_bigint(balancer_snapshot.balances[token_out_index]) * &scaling_factor_out;
-        let scaled_amount_out = fp::to_bigint(amount_out) * &scaling_factor_out;
-
-        let scaled_amount_in_before_fee = balancer_maths_rust::pools::weighted::compute_in_given_exact_out(
-            &scaled_balance_in,
-            &fp::to_bigint(self.weights[token_in_index]),
-            &scaled_balance_out,
-            &fp::to_bigint(self.weights[token_out_index]),
-            &scaled_amount_out,
+    /// Scaling factors of pools predating `getScalingFactors`: the decimals factor times the
+    /// rate a meta-stable pool caches for each token with a rate provider.
+    async fn cached_rate_scaling_factors(&self, block_id: BlockId) -> Result<Vec<U256>, ArbRsError> {
+        let no_rate_providers = Vec::new();
+        let rate_providers = match &self.kind {
+            BalancerPoolKind::Stable { rate_providers } => rate_providers,
+            _ => &no_rate_providers,
+        };
+        let mut scaling_factors = Vec::with_capacity(self.tokens.len());
+        for (i, token) in self.tokens.iter().enumerate() {
+            let scaling_factor = compute_scaling_factor(token);
+            if rate_providers.get(i).is_none_or(|rate_provider| rate_provider.is_zero()) {
+                scaling_factors.push(scaling_factor);
+                continue;
+            }
+            let call = IStablePool::getPriceRateCacheCall { token: token.address() };
+            let bytes = self.provider.call(TransactionRequest::default().to(self.address).input(call.abi_encode().into())).block(block_id).await?;
+            let rate = IStablePool::getPriceRateCacheCall::abi_decode_returns(&bytes)?.rate;
+            scaling_factors.push(fp::mul_down(scaling_factor, rate)?);
+        }
+        Ok(scaling_factors)
+    }
+
+    /// The Balancer snapshot to swap against and the indices of the two tokens.
+    fn swap_context<'a>(
+        &self,
+        token_in: &Token<P>,
+        token_out: &Token<P>,
+        snapshot: &'a PoolSnapshot,
+    ) -> Result<(&'a BalancerPoolSnapshot, usize, usize), ArbRsError> {
+        let PoolSnapshot::Balancer(balancer_snapshot) = snapshot else {
+            return Err(ArbRsError::CalculationError("Invalid snapshot for Balancer pool".into()));
+        };
+        if balancer_snapshot.is_paused {
+            return Err(ArbRsError::PoolPaused(self.address));
+        }
+        let token_index = |token: &Token<P>| self.tokens.iter().position(|t| t.address() == token.address());
+        let (Some(i), Some(j)) = (token_index(token_in), token_index(token_out)) else {
+            return Err(ArbRsError::CalculationError("Token not in Balancer pool".into()));
+        };
+        Ok((balancer_snapshot, i, j))
+    }
+
+    fn swap_math<'a>(
+        &'a self,
+        token_in: &Token<P>,
+        token_out: &Token<P>,
+        snapshot: &'a PoolSnapshot,
+    ) -> Result<SwapMath<'a>, ArbRsError> {
+        let (snapshot, i, j) = self.swap_context(token_in, token_out, snapshot)?;
+        Ok(SwapMath {
+            kind: &self.kind,
+            fee: snapshot.swap_fee.unwrap_or(self.fee),
+            snapshot,
+            i,
+            j,
+            scaling_factor_in: compute_scaling_factor(&self.tokens[i]),
+            scaling_factor_out: compute_scaling_factor(&self.tokens[j]),
+        })
+    }
+
+    /// Fee-less spot price of token `i` in token `j`, in raw units. Weighted pools price at
+    /// `(B_j / W_j) / (B_i / W_i)`; stable pools at the ratio of the invariant's partial
+    /// derivatives, taken over the upscaled balances.
+    pub fn spot_price(&self, snapshot: &BalancerPoolSnapshot, i: usize, j: usize) -> Option<f64> {
+        let BalancerPoolKind::Weighted { weights } = &self.kind else {
+            return self.stable_spot_price(snapshot, i, j);
+        };
+        let balance_in = u256_to_f64(*snapshot.balances.get(i)?);
+        let balance_out = u256_to_f64(*snapshot.balances.get(j)?);
+        let weight_in = u256_to_f64(*weights.get(i)?);
+        let weight_out = u256_to_f64(*weights.get(j)?);
+        if balance_in == 0.0 || weight_in == 0.0 || weight_out == 0.0 {
+            return None;
+        }
+        Some((balance_out / weight_out) / (balance_in / weight_in))
+    }
+
+    /// With `An^n` the amplification times the number of tokens and `K = D^(n+1) / (n^n * P)`,
+    /// the invariant's derivative in `x_k` is `An^n + K / x_k`.
+    fn stable_spot_price(&self, snapshot: &BalancerPoolSnapshot, i: usize, j: usize) -> Option<f64> {
+        let (amplification, scaling_factors) = stable_parameters(snapshot).ok()?;
+        let balances = upscaled_balances(&snapshot.balances, scaling_factors).ok()?;
+        if i >= balances.len() || j >= balances.len() || balances.iter().any(|balance| balance.is_zero()) {
+            return None;
+        }
+        let invariant = u256_to_f64(stable_math::calculate_invariant(amplification, &balances).ok()?);
+        let balances: Vec<f64> = balances.iter().map(|balance| u256_to_f64(*balance)).collect();
+        let n = balances.len() as f64;
+        let amp_times_total = u256_to_f64(amplification) / u256_to_f64(AMP_PRECISION) * n;
+        let k = balances.iter().fold(invariant, |k, balance| k * invariant / (n * balance));
+
+        let price = (amp_times_total + k / balances[i]) / (amp_times_total + k / balances[j]);
+        Some(price * u256_to_f64(scaling_factors[i]) / u256_to_f64(scaling_factors[j]))
+    }
+}
+
+/// The amplification and scaling factors a stable pool quotes `snapshot` with.
+fn stable_parameters(snapshot: &BalancerPoolSnapshot) -> Result<(U256, &[U256]), ArbRsError> {
+    match (snapshot.amplification, snapshot.scaling_factors.as_deref()) {
+        (Some(amplification), Some(scaling_factors)) if scaling_factors.len() == snapshot.balances.len() => {
+            Ok((amplification, scaling_factors))
+        }
+        _ => Err(ArbRsError::CalculationError("Stable Balancer snapshot without amplification or scaling factors".into())),
+    }
+}
+
+/// A swap of token `i` for token `j` against one snapshot. `scaling_factor_in` and
+/// `scaling_factor_out` are the tokens' decimals factors, which weighted pools scale by.
+struct SwapMath<'a> {
+    kind: &'a BalancerPoolKind,
+    fee: U256,
+    snapshot: &'a BalancerPoolSnapshot,
+    i: usize,
+    j: usize,
+    scaling_factor_in: U256,
+    scaling_factor_out: U256,
+}
+
+impl SwapMath<'_> {
+    fn amount_out(&self, amount_in: U256) -> Result<U256, ArbRsError> {
+        let (i, j, snapshot) = (self.i, self.j, self.snapshot);
+        let amount_in = weighted_math::subtract_swap_fee_amount(amount_in, self.fee)?;
+
+        let BalancerPoolKind::Weighted { weights } = self.kind else {
+            let (amplification, scaling_factors) = stable_parameters(snapshot)?;
+            let balances = upscaled_balances(&snapshot.balances, scaling_factors)?;
+            let invariant = stable_math::calculate_invariant(amplification, &balances)?;
+            let amount_out = stable_math::calc_out_given_in(
+                amplification,
+                &balances,
+                i,
+                j,
+                upscale(amount_in, scaling_factors[i])?,
+                invariant,
+            )?;
+            return downscale_down(amount_out, scaling_factors[j]);
+        };
+
+        let amount_out = weighted_math::calc_out_given_in(
+            upscale(snapshot.balances[i], self.scaling_factor_in)?,
+            weights[i],
+            upscale(snapshot.balances[j], self.scaling_factor_out)?,
+            weights[j],
+            upscale(amount_in, self.scaling_factor_in)?,
         )?;
+        downscale_down(amount_out, self.scaling_factor_out)
+    }
 
-        let fee_bigint = fp::to_bigint(self.fee);
-        let one_wad = BigInt::from(10).pow(18);
-        let amount_in_with_fee = (&scaled_amount_in_before_fee * &one_wad) / (&one_wad - fee_bigint);
+    fn amount_in(&self, amount_out: U256) -> Result<U256, ArbRsError> {
+        let (i, j, snapshot) = (self.i, self.j, self.snapshot);
+        if amount_out >= snapshot.balances[j] {
+            return Err(ArbRsError::CalculationError(format!(
+                "Requested output {} exceeds the pool balance {}",
+                amount_out, snapshot.balances[j]
+            )));
+        }
 
-        fp::to_u256((amount_in_with_fee + BigInt::from(1)) / scaling_factor_in)
+        let amount_in = match self.kind {
+            BalancerPoolKind::Weighted { weights } => {
+                let amount_in = weighted_math::calc_in_given_out(
+                    upscale(snapshot.balances[i], self.scaling_factor_in)?,
+                    weights[i],
+                    upscale(snapshot.balances[j], self.scaling_factor_out)?,
+                    weights[j],
+                    upscale(amount_out, self.scaling_factor_out)?,
+                )?;
+                downscale_up(amount_in, self.scaling_factor_in)?
+            }
+            _ => {
+                let (amplification, scaling_factors) = stable_parameters(snapshot)?;
+                let balances = upscaled_balances(&snapshot.balances, scaling_factors)?;
+                let invariant = stable_math::calculate_invariant(amplification, &balances)?;
+                let amount_in = stable_math::calc_in_given_out(
+                    amplification,
+                    &balances,
+                    i,
+                    j,
+                    upscale(amount_out, scaling_factors[j])?,
+                    invariant,
+                )?;
+                downscale_up(amount_in, scaling_factors[i])?
+            }
+        };
+        weighted_math::add_swap_fee_amount(amount_in, self.fee)
     }
+}
+
+/// A Balancer pool's parameters and one snapshot, quoting without the pool.
+#[derive(Debug, Clone)]
+pub struct BalancerQuoter {
+    pub address: Address,
+    pub tokens: Vec<Address>,
+    /// Each token's decimals factor, see [`compute_scaling_factor`].
+    pub decimal_scaling_factors: Vec<U256>,
+    pub kind: BalancerPoolKind,
+    /// The fee read at construction, quoted with when the snapshot has none.
+    pub fee: U256,
+    pub snapshot: Arc<BalancerPoolSnapshot>,
+}
+
+impl BalancerQuoter {
+    fn swap_math(&self, token_in: Address, token_out: Address) -> Result<SwapMath<'_>, ArbRsError> {
+        if self.snapshot.is_paused {
+            return Err(ArbRsError::PoolPaused(self.address));
+        }
+        let token_index = |token: Address| self.tokens.iter().position(|t| *t == token);
+        let (Some(i), Some(j)) = (token_index(token_in), token_index(token_out)) else {
+            return Err(ArbRsError::CalculationError("Token not in Balancer pool".into()));
+        };
+        Ok(SwapMath {
+            kind: &self.kind,
+            fee: self.snapshot.swap_fee.unwrap_or(self.fee),
+            snapshot: &self.snapshot,
+            i,
+            j,
+            scaling_factor_in: self.decimal_scaling_factors[i],
+            scaling_factor_out: self.decimal_scaling_factors[j],
+        })
+    }
+}
+
+impl Quoter for BalancerQuoter {
+    fn calculate_out(&self, token_in: Address, token_out: Address, amount_in: U256) -> Result<U256, ArbRsError> {
+        self.swap_math(token_in, token_out)?.amount_out(amount_in)
+    }
+
+    fn calculate_in(&self, token_in: Address, token_out: Address, amount_out: U256) -> Result<U256, ArbRsError> {
+        self.swap_math(token_in, token_out)?.amount_in(amount_out)
+    }
+}
 
-    async fn nominal_price(&self, _t_in: &Token<P>, _t_out: &Token<P>) -> Result<f64, ArbRsError> { unimplemented!() }
-    async fn absolute_price(&self, _t_in: &Token<P>, _t_out: &Token<P>) -> Result<f64, ArbRsError> { unimplemented!() }
-    async fn absolute_exchange_rate(&self, _t_in: &Token<P>, _t_out: &Token<P>) -> Result<f64, ArbRsError> { unimplemented!() }
+/// `balances` brought to 18 decimals with their rates applied, as `_upscaleArray` does.
+fn upscaled_balances(balances: &[U256], scaling_factors: &[U256]) -> Result<Vec<U256>, ArbRsError> {
+    balances
+        .iter()
+        .zip(scaling_factors)
+        .map(|(balance, scaling_factor)| upscale(*balance, *scaling_factor))
+        .collect()
 }
 
 impl<P: Provider + Send + Sync + 'static + ?Sized> Debug for BalancerPool<P> {
@@ -1,14 +1,19 @@
-use crate::{TokenLike, core::token::Token, errors::ArbRsError, math::balancer::fixed_point as fp};
+use crate::{
+    TokenLike,
+    core::token::Token,
+    errors::ArbRsError,
+    math::balancer::{constants::ONE, fixed_point as fp},
+};
 use alloy_primitives::U256;
 use alloy_provider::Provider;
-use std::sync::Arc;
 
-pub fn compute_scaling_factor<P>(token: &Arc<Token<P>>) -> U256
+/// Fixed point factor bringing `token` amounts to 18 decimals, as the vault's pools use it.
+pub fn compute_scaling_factor<P>(token: &Token<P>) -> U256
 where
     P: Provider + Send + Sync + 'static + ?Sized,
 {
     let decimals_diff = 18 - token.decimals();
-    U256::from(10).pow(U256::from(decimals_diff))
+    ONE * U256::from(10).pow(U256::from(decimals_diff))
 }
 
 pub fn upscale(amount: U256, scaling_factor: U256) -> Result<U256, ArbRsError> {
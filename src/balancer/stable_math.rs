@@ -0,0 +1,150 @@
+use crate::errors::ArbRsError;
+use alloy_primitives::U256;
+
+/// Precision the amplification parameter is stored with, as returned by
+/// `getAmplificationParameter`.
+pub const AMP_PRECISION: U256 = U256::from_limbs([1_000, 0, 0, 0]);
+
+// Newton iterations the vault allows before reverting with `STABLE_*_DIDNT_CONVERGE`.
+const MAX_ITERATIONS: usize = 255;
+
+/// Computes the StableSwap invariant `D` of upscaled `balances`, as the vault's
+/// `StableMath._calculateInvariant` does: `A * n^n * S + D = A * D * n^n + D^(n+1) / (n^n * P)`.
+/// `amplification` carries `AMP_PRECISION`.
+pub fn calculate_invariant(amplification: U256, balances: &[U256]) -> Result<U256, ArbRsError> {
+    let sum = balances.iter().try_fold(U256::ZERO, |sum, balance| add(sum, *balance))?;
+    if sum.is_zero() {
+        return Ok(U256::ZERO);
+    }
+    let num_tokens = U256::from(balances.len());
+    let amp_times_total = mul(amplification, num_tokens)?;
+
+    let mut invariant = sum;
+    for _ in 0..MAX_ITERATIONS {
+        let mut d_p = invariant;
+        for balance in balances {
+            d_p = div_down(mul(d_p, invariant)?, mul(*balance, num_tokens)?)?;
+        }
+        let previous = invariant;
+        let numerator = mul(
+            add(div_down(mul(amp_times_total, sum)?, AMP_PRECISION)?, mul(d_p, num_tokens)?)?,
+            invariant,
+        )?;
+        let denominator = add(
+            div_down(mul(sub(amp_times_total, AMP_PRECISION)?, invariant)?, AMP_PRECISION)?,
+            mul(num_tokens + U256::ONE, d_p)?,
+        )?;
+        invariant = div_down(numerator, denominator)?;
+        if invariant.abs_diff(previous) <= U256::ONE {
+            return Ok(invariant);
+        }
+    }
+    Err(ArbRsError::CalculationError("STABLE_INVARIANT_DIDNT_CONVERGE".into()))
+}
+
+/// Computes how many upscaled tokens `j` can be taken out of a pool if `amount_in` of `i`
+/// are sent, rounding down.
+pub fn calc_out_given_in(
+    amplification: U256,
+    balances: &[U256],
+    i: usize,
+    j: usize,
+    amount_in: U256,
+    invariant: U256,
+) -> Result<U256, ArbRsError> {
+    let mut balances = balances.to_vec();
+    balances[i] = add(balances[i], amount_in)?;
+    let final_balance_out = get_token_balance_given_invariant_and_all_other_balances(
+        amplification,
+        &balances,
+        invariant,
+        j,
+    )?;
+    sub(sub(balances[j], final_balance_out)?, U256::ONE)
+}
+
+/// Computes how many upscaled tokens `i` must be sent to a pool to take `amount_out` of `j`,
+/// rounding up.
+pub fn calc_in_given_out(
+    amplification: U256,
+    balances: &[U256],
+    i: usize,
+    j: usize,
+    amount_out: U256,
+    invariant: U256,
+) -> Result<U256, ArbRsError> {
+    let mut balances = balances.to_vec();
+    balances[j] = sub(balances[j], amount_out)?;
+    let final_balance_in = get_token_balance_given_invariant_and_all_other_balances(
+        amplification,
+        &balances,
+        invariant,
+        i,
+    )?;
+    add(sub(final_balance_in, balances[i])?, U256::ONE)
+}
+
+/// Solves the invariant for the balance of `token_index`, rounding up, given all the others.
+pub fn get_token_balance_given_invariant_and_all_other_balances(
+    amplification: U256,
+    balances: &[U256],
+    invariant: U256,
+    token_index: usize,
+) -> Result<U256, ArbRsError> {
+    let num_tokens = U256::from(balances.len());
+    let amp_times_total = mul(amplification, num_tokens)?;
+
+    let mut sum = balances[0];
+    let mut p_d = mul(balances[0], num_tokens)?;
+    for balance in &balances[1..] {
+        p_d = div_down(mul(mul(p_d, *balance)?, num_tokens)?, invariant)?;
+        sum = add(sum, *balance)?;
+    }
+    sum = sub(sum, balances[token_index])?;
+
+    let inv2 = mul(invariant, invariant)?;
+    // We remove the balance from c by multiplying it.
+    let c = mul(
+        mul(div_up(inv2, mul(amp_times_total, p_d)?)?, AMP_PRECISION)?,
+        balances[token_index],
+    )?;
+    let b = add(sum, mul(div_down(invariant, amp_times_total)?, AMP_PRECISION)?)?;
+
+    let mut token_balance = div_up(add(inv2, c)?, add(invariant, b)?)?;
+    for _ in 0..MAX_ITERATIONS {
+        let previous = token_balance;
+        token_balance = div_up(
+            add(mul(token_balance, token_balance)?, c)?,
+            sub(add(mul(token_balance, U256::from(2))?, b)?, invariant)?,
+        )?;
+        if token_balance.abs_diff(previous) <= U256::ONE {
+            return Ok(token_balance);
+        }
+    }
+    Err(ArbRsError::CalculationError("STABLE_GET_BALANCE_DIDNT_CONVERGE".into()))
+}
+
+// The vault's checked `Math` helpers, which revert on overflow and division by zero.
+
+fn add(a: U256, b: U256) -> Result<U256, ArbRsError> {
+    a.checked_add(b).ok_or_else(|| ArbRsError::CalculationError("ADD_OVERFLOW".into()))
+}
+
+fn sub(a: U256, b: U256) -> Result<U256, ArbRsError> {
+    a.checked_sub(b).ok_or_else(|| ArbRsError::CalculationError("SUB_OVERFLOW".into()))
+}
+
+fn mul(a: U256, b: U256) -> Result<U256, ArbRsError> {
+    a.checked_mul(b).ok_or_else(|| ArbRsError::CalculationError("MUL_OVERFLOW".into()))
+}
+
+fn div_down(a: U256, b: U256) -> Result<U256, ArbRsError> {
+    a.checked_div(b).ok_or_else(|| ArbRsError::CalculationError("ZERO_DIVISION".into()))
+}
+
+fn div_up(a: U256, b: U256) -> Result<U256, ArbRsError> {
+    if b.is_zero() {
+        return Err(ArbRsError::CalculationError("ZERO_DIVISION".into()));
+    }
+    Ok(if a.is_zero() { U256::ZERO } else { U256::ONE + (a - U256::ONE) / b })
+}
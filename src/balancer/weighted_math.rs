@@ -72,3 +72,8 @@ pub fn subtract_swap_fee_amount(amount: U256, fee_percentage: U256) -> Result<U2
     let fee_amount = fp::mul_up(amount, fee_percentage)?;
     Ok(amount.saturating_sub(fee_amount))
 }
+
+/// Grosses an amount up by the swap fee, so that the fee on the result is `amount`'s complement.
+pub fn add_swap_fee_amount(amount: U256, fee_percentage: U256) -> Result<U256, ArbRsError> {
+    fp::div_up(amount, fp::complement(fee_percentage))
+}
@@ -0,0 +1,361 @@
+use crate::arbitrage::usd::CHAINLINK_ETH_USD;
+use crate::core::multicall::MULTICALL3_ADDRESS;
+use crate::core::token::{USDC_ADDRESS, WETH_ADDRESS};
+use crate::curve::registry::{
+    CURVE_CRYPTO_FACTORY, CURVE_META_REGISTRY, CURVE_STABLE_FACTORY, RegistrySource,
+};
+use crate::dex::{DexDetails, DexVariant, build_mainnet_dex_registry};
+use crate::errors::ArbRsError;
+use crate::manager::uniswap_v3_pool_manager::{FeeTierTable, UNISWAP_V3_FACTORY, V3FactoryConfig};
+use crate::pool::address::{UNISWAP_V2_INIT_CODE_HASH, UNISWAP_V3_INIT_CODE_HASH};
+use crate::pool::tick_lens::UNISWAP_V3_TICK_LENS;
+use crate::pool::uniswap_v4::{UNISWAP_V4_POOL_MANAGER, UNISWAP_V4_STATE_VIEW};
+use alloy_primitives::{Address, B256, address};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::time::Duration;
+
+pub const MAINNET_CHAIN_ID: u64 = 1;
+pub const OPTIMISM_CHAIN_ID: u64 = 10;
+pub const BASE_CHAIN_ID: u64 = 8453;
+pub const ARBITRUM_CHAIN_ID: u64 = 42161;
+
+/// Legacy main Curve registry on mainnet, whose `PoolAdded` events discovery follows.
+pub const CURVE_MAINNET_REGISTRY: Address = address!("90E00ACe148ca3b23Ac1bC8C240C2a7Dd9c2d7f5");
+/// The Balancer V2 Vault, at the same address on every chain it's deployed on.
+pub const BALANCER_V2_VAULT: Address = address!("BA12222222228d8Ba445958a75a0704d566BF2C8");
+/// OP-stack predeploy pricing the L1 data fee of a transaction.
+pub const OP_STACK_GAS_PRICE_ORACLE: Address = address!("420000000000000000000000000000000000000F");
+/// Wrapped ether predeploy on OP-stack chains.
+pub const OP_STACK_WETH: Address = address!("4200000000000000000000000000000000000006");
+
+const OPTIMISM_USDC: Address = address!("0b2C639c533813f4Aa9D7837CAf62653d097Ff85");
+const OPTIMISM_UNISWAP_V2_FACTORY: Address = address!("0c3c1c532F1e39EdF36BE9Fe0bE1410313E074Bf");
+const OPTIMISM_CHAINLINK_ETH_USD: Address = address!("13e3Ee699D1909E989722E753853AE30b17e08c5");
+
+const BASE_USDC: Address = address!("833589fCD6eDb6E08f4c7C32D4f71b54bdA02913");
+const BASE_UNISWAP_V2_FACTORY: Address = address!("8909Dc15e40173Ff4699343b6eB8132c65e18eC6");
+const BASE_UNISWAP_V3_FACTORY: Address = address!("33128a8fC17869897dcE68Ed026d694621f6FDfD");
+const BASE_UNISWAP_V3_TICK_LENS: Address = address!("0CdeE061c75D43c82520eD998C23ac2991c9ac6d");
+const BASE_CHAINLINK_ETH_USD: Address = address!("71041dddad3595F9CEd3DcCFBe3D1F4b0a16Bb70");
+
+const ARBITRUM_WETH: Address = address!("82aF49447D8a07e3bd95BD0d56f35241523fBab1");
+const ARBITRUM_USDC: Address = address!("af88d065e77c8cC2239327C5EDb3A432268e5831");
+const ARBITRUM_UNISWAP_V2_FACTORY: Address = address!("f1D7CC64Fb4452F05c498126312eBE29f30Fbcf9");
+const ARBITRUM_CHAINLINK_ETH_USD: Address = address!("639Fe6ab55C921f74e7fac1ee960C0B6293ba612");
+
+/// Where a chain's contracts live and how its blocks and gas behave, for the managers and
+/// [`ArbitrageEngine`](crate::arbitrage::engine::ArbitrageEngine) to read in place of
+/// mainnet constants.
+///
+/// Presets cover mainnet, Optimism, Base and Arbitrum; [`Self::from_json`] loads another
+/// chain, or overrides a preset's addresses, without recompiling.
+#[derive(Debug, Clone)]
+pub struct ChainConfig {
+    pub name: String,
+    pub chain_id: u64,
+    /// The wrapped native token: cycles start and end in it, and profits are valued in it.
+    pub wrapped_native: Address,
+    /// Stablecoin tokens without a pool against `wrapped_native` are priced through.
+    pub stable_reference_token: Address,
+    /// Uniswap V2 style factories, the first being the one discovery follows.
+    pub v2_factories: Vec<(Address, DexDetails)>,
+    /// Uniswap V3 style deployments, the first being the one discovery follows.
+    pub v3_factories: Vec<V3FactoryConfig>,
+    /// Curve registry whose `PoolAdded` events discovery follows. `None` where Curve isn't
+    /// supported, which leaves Curve discovery off.
+    pub curve_registry: Option<Address>,
+    /// Contracts consulted, in order, for a Curve pool's LP token and base pool.
+    pub curve_registry_sources: Vec<(RegistrySource, Address)>,
+    /// `None` leaves Balancer discovery and flash loans off.
+    pub balancer_vault: Option<Address>,
+    pub multicall3: Address,
+    pub v3_tick_lens: Option<Address>,
+    /// Uniswap V4 pool manager whose `Initialize` events discovery follows. `None` leaves
+    /// V4 discovery off.
+    pub v4_pool_manager: Option<Address>,
+    /// `StateView` reading V4 pools' state. V4 discovery needs it as well.
+    pub v4_state_view: Option<Address>,
+    /// Chainlink ETH/USD aggregator solutions are valued in USD with.
+    pub eth_usd_feed: Option<Address>,
+    pub block_time: Duration,
+    /// `GasPriceOracle` charging an L1 data fee on top of execution gas, on OP-stack chains.
+    pub l1_gas_price_oracle: Option<Address>,
+}
+
+impl Default for ChainConfig {
+    fn default() -> Self {
+        Self::mainnet()
+    }
+}
+
+impl ChainConfig {
+    /// A chain with only its wrapped native and reference tokens known: no dex is
+    /// discovered until its factories are added.
+    pub fn new(
+        name: impl Into<String>,
+        chain_id: u64,
+        wrapped_native: Address,
+        stable_reference_token: Address,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            chain_id,
+            wrapped_native,
+            stable_reference_token,
+            v2_factories: Vec::new(),
+            v3_factories: Vec::new(),
+            curve_registry: None,
+            curve_registry_sources: Vec::new(),
+            balancer_vault: None,
+            multicall3: MULTICALL3_ADDRESS,
+            v3_tick_lens: None,
+            v4_pool_manager: None,
+            v4_state_view: None,
+            eth_usd_feed: None,
+            block_time: Duration::from_secs(12),
+            l1_gas_price_oracle: None,
+        }
+    }
+
+    pub fn mainnet() -> Self {
+        let mut v2_factories: Vec<(Address, DexDetails)> =
+            build_mainnet_dex_registry().into_iter().collect();
+        // Uniswap first, for discovery; the rest in a fixed order.
+        v2_factories.sort_by_key(|(factory, details)| {
+            (details.dex_type != DexVariant::UniswapV2, *factory)
+        });
+        Self {
+            v2_factories,
+            v3_factories: vec![V3FactoryConfig::uniswap(), V3FactoryConfig::pancakeswap()],
+            curve_registry: Some(CURVE_MAINNET_REGISTRY),
+            curve_registry_sources: vec![
+                (RegistrySource::MetaRegistry, CURVE_META_REGISTRY),
+                (RegistrySource::StableFactory, CURVE_STABLE_FACTORY),
+                (RegistrySource::CryptoFactory, CURVE_CRYPTO_FACTORY),
+            ],
+            balancer_vault: Some(BALANCER_V2_VAULT),
+            v3_tick_lens: Some(UNISWAP_V3_TICK_LENS),
+            v4_pool_manager: Some(UNISWAP_V4_POOL_MANAGER),
+            v4_state_view: Some(UNISWAP_V4_STATE_VIEW),
+            eth_usd_feed: Some(CHAINLINK_ETH_USD),
+            ..Self::new("mainnet", MAINNET_CHAIN_ID, WETH_ADDRESS, USDC_ADDRESS)
+        }
+    }
+
+    pub fn optimism() -> Self {
+        Self {
+            v2_factories: vec![uniswap_v2(OPTIMISM_UNISWAP_V2_FACTORY)],
+            v3_factories: vec![uniswap_v3(UNISWAP_V3_FACTORY)],
+            balancer_vault: Some(BALANCER_V2_VAULT),
+            v3_tick_lens: Some(UNISWAP_V3_TICK_LENS),
+            eth_usd_feed: Some(OPTIMISM_CHAINLINK_ETH_USD),
+            block_time: Duration::from_secs(2),
+            l1_gas_price_oracle: Some(OP_STACK_GAS_PRICE_ORACLE),
+            ..Self::new("optimism", OPTIMISM_CHAIN_ID, OP_STACK_WETH, OPTIMISM_USDC)
+        }
+    }
+
+    pub fn base() -> Self {
+        Self {
+            v2_factories: vec![uniswap_v2(BASE_UNISWAP_V2_FACTORY)],
+            v3_factories: vec![uniswap_v3(BASE_UNISWAP_V3_FACTORY)],
+            balancer_vault: Some(BALANCER_V2_VAULT),
+            v3_tick_lens: Some(BASE_UNISWAP_V3_TICK_LENS),
+            eth_usd_feed: Some(BASE_CHAINLINK_ETH_USD),
+            block_time: Duration::from_secs(2),
+            l1_gas_price_oracle: Some(OP_STACK_GAS_PRICE_ORACLE),
+            ..Self::new("base", BASE_CHAIN_ID, OP_STACK_WETH, BASE_USDC)
+        }
+    }
+
+    /// Arbitrum charges for L1 data in L2 gas, so the gas estimate already covers it.
+    pub fn arbitrum() -> Self {
+        Self {
+            v2_factories: vec![uniswap_v2(ARBITRUM_UNISWAP_V2_FACTORY)],
+            v3_factories: vec![uniswap_v3(UNISWAP_V3_FACTORY)],
+            balancer_vault: Some(BALANCER_V2_VAULT),
+            v3_tick_lens: Some(UNISWAP_V3_TICK_LENS),
+            eth_usd_feed: Some(ARBITRUM_CHAINLINK_ETH_USD),
+            block_time: Duration::from_millis(250),
+            ..Self::new("arbitrum", ARBITRUM_CHAIN_ID, ARBITRUM_WETH, ARBITRUM_USDC)
+        }
+    }
+
+    /// The preset for `chain_id`, if there is one.
+    pub fn for_chain_id(chain_id: u64) -> Option<Self> {
+        match chain_id {
+            MAINNET_CHAIN_ID => Some(Self::mainnet()),
+            OPTIMISM_CHAIN_ID => Some(Self::optimism()),
+            BASE_CHAIN_ID => Some(Self::base()),
+            ARBITRUM_CHAIN_ID => Some(Self::arbitrum()),
+            _ => None,
+        }
+    }
+
+    /// Reads a chain from JSON. Fields left out keep the preset of its `chain_id`; a chain
+    /// without a preset must give at least `wrapped_native` and `stable_reference_token`.
+    ///
+    /// ```json
+    /// { "chain_id": 8453, "v2_factories": [{ "factory": "0x…", "fee_bps": 30 }] }
+    /// ```
+    pub fn from_json(json: &str) -> Result<Self, ArbRsError> {
+        let file: ChainConfigFile = serde_json::from_str(json)
+            .map_err(|e| ArbRsError::ConfigError(format!("Invalid chain config: {e}")))?;
+        let mut config = match Self::for_chain_id(file.chain_id) {
+            Some(preset) => preset,
+            None => {
+                let (Some(wrapped_native), Some(stable_reference_token)) =
+                    (file.wrapped_native, file.stable_reference_token)
+                else {
+                    return Err(ArbRsError::ConfigError(format!(
+                        "Chain {} has no preset: wrapped_native and stable_reference_token are required",
+                        file.chain_id
+                    )));
+                };
+                Self::new(
+                    format!("chain {}", file.chain_id),
+                    file.chain_id,
+                    wrapped_native,
+                    stable_reference_token,
+                )
+            }
+        };
+
+        if let Some(name) = file.name {
+            config.name = name;
+        }
+        if let Some(wrapped_native) = file.wrapped_native {
+            config.wrapped_native = wrapped_native;
+        }
+        if let Some(stable_reference_token) = file.stable_reference_token {
+            config.stable_reference_token = stable_reference_token;
+        }
+        if let Some(factories) = file.v2_factories {
+            config.v2_factories = factories
+                .into_iter()
+                .map(V2FactoryFile::into_details)
+                .collect();
+        }
+        if let Some(factories) = file.v3_factories {
+            config.v3_factories = factories
+                .into_iter()
+                .map(V3FactoryFile::into_config)
+                .collect();
+        }
+        if let Some(registry) = file.curve_registry {
+            config.curve_registry = Some(registry);
+        }
+        if let Some(sources) = file.curve_meta_registry {
+            config.curve_registry_sources = vec![(RegistrySource::MetaRegistry, sources)];
+        }
+        config.balancer_vault = file.balancer_vault.or(config.balancer_vault);
+        config.multicall3 = file.multicall3.unwrap_or(config.multicall3);
+        config.v3_tick_lens = file.v3_tick_lens.or(config.v3_tick_lens);
+        config.v4_pool_manager = file.v4_pool_manager.or(config.v4_pool_manager);
+        config.v4_state_view = file.v4_state_view.or(config.v4_state_view);
+        config.eth_usd_feed = file.eth_usd_feed.or(config.eth_usd_feed);
+        if let Some(block_time_ms) = file.block_time_ms {
+            config.block_time = Duration::from_millis(block_time_ms);
+        }
+        config.l1_gas_price_oracle = file.l1_gas_price_oracle.or(config.l1_gas_price_oracle);
+        Ok(config)
+    }
+
+    /// The V2 factory discovery follows.
+    pub fn v2_factory(&self) -> Option<Address> {
+        self.v2_factories.first().map(|(factory, _)| *factory)
+    }
+
+    /// The V3 factory discovery follows.
+    pub fn v3_factory(&self) -> Option<Address> {
+        self.v3_factories.first().map(|config| config.factory)
+    }
+
+    /// The V2 factories by address, as the V2 manager's dex registry.
+    pub fn v2_dex_registry(&self) -> HashMap<Address, DexDetails> {
+        self.v2_factories.iter().cloned().collect()
+    }
+
+    /// Whether the chain charges an L1 data fee on top of execution gas.
+    pub fn has_l1_data_fee(&self) -> bool {
+        self.l1_gas_price_oracle.is_some()
+    }
+}
+
+fn uniswap_v2(factory: Address) -> (Address, DexDetails) {
+    (
+        factory,
+        DexDetails::new(DexVariant::UniswapV2, 30, 10_000, UNISWAP_V2_INIT_CODE_HASH),
+    )
+}
+
+fn uniswap_v3(factory: Address) -> V3FactoryConfig {
+    V3FactoryConfig::new(factory, FeeTierTable::uniswap())
+        .with_pool_init_code(factory, UNISWAP_V3_INIT_CODE_HASH)
+}
+
+/// [`ChainConfig`] as [`ChainConfig::from_json`] reads it.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ChainConfigFile {
+    chain_id: u64,
+    name: Option<String>,
+    wrapped_native: Option<Address>,
+    stable_reference_token: Option<Address>,
+    v2_factories: Option<Vec<V2FactoryFile>>,
+    v3_factories: Option<Vec<V3FactoryFile>>,
+    curve_registry: Option<Address>,
+    curve_meta_registry: Option<Address>,
+    balancer_vault: Option<Address>,
+    multicall3: Option<Address>,
+    v3_tick_lens: Option<Address>,
+    v4_pool_manager: Option<Address>,
+    v4_state_view: Option<Address>,
+    eth_usd_feed: Option<Address>,
+    block_time_ms: Option<u64>,
+    l1_gas_price_oracle: Option<Address>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct V2FactoryFile {
+    factory: Address,
+    /// Fee on the input, in basis points. 30 unless given.
+    fee_bps: Option<u32>,
+    /// Uniswap's unless given.
+    init_code_hash: Option<B256>,
+}
+
+impl V2FactoryFile {
+    fn into_details(self) -> (Address, DexDetails) {
+        (
+            self.factory,
+            DexDetails::new(
+                DexVariant::Custom,
+                self.fee_bps.unwrap_or(30),
+                10_000,
+                self.init_code_hash.unwrap_or(UNISWAP_V2_INIT_CODE_HASH),
+            ),
+        )
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct V3FactoryFile {
+    factory: Address,
+    /// Uniswap's unless given.
+    init_code_hash: Option<B256>,
+    /// The factory itself unless given.
+    deployer: Option<Address>,
+}
+
+impl V3FactoryFile {
+    fn into_config(self) -> V3FactoryConfig {
+        V3FactoryConfig::new(self.factory, FeeTierTable::uniswap()).with_pool_init_code(
+            self.deployer.unwrap_or(self.factory),
+            self.init_code_hash.unwrap_or(UNISWAP_V3_INIT_CODE_HASH),
+        )
+    }
+}
@@ -0,0 +1,200 @@
+use crate::errors::ArbRsError;
+use alloy_primitives::B256;
+use alloy_provider::{Provider, ProviderBuilder};
+use alloy_rpc_types::Header;
+use alloy_transport_ws::WsConnect;
+use futures::future::BoxFuture;
+use futures::stream::{self, BoxStream, Stream, StreamExt};
+use std::collections::VecDeque;
+use std::time::Duration;
+
+/// Opens a new block header subscription.
+pub type BlockSubscriber = Box<
+    dyn Fn() -> BoxFuture<'static, Result<BoxStream<'static, Header>, ArbRsError>> + Send + Sync,
+>;
+
+/// What [`ResilientBlockStream`] yields.
+#[derive(Debug, Clone, PartialEq)]
+pub enum BlockStreamEvent {
+    Block(Box<Header>),
+    /// Blocks `from..=to` went by while the subscription was down. Pools created and state
+    /// changed in them were never seen.
+    MissedBlocks {
+        from: u64,
+        to: u64,
+    },
+}
+
+/// How [`ResilientBlockStream`] detects a dead subscription and reconnects.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockStreamConfig {
+    /// A subscription without a header for this long is taken as dead.
+    pub idle_timeout: Duration,
+    /// Wait before the first reconnect, doubled after each failed one.
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    /// Failed reconnects in a row before the stream ends. `None` retries forever.
+    pub max_reconnects: Option<u32>,
+}
+
+impl Default for BlockStreamConfig {
+    fn default() -> Self {
+        Self {
+            idle_timeout: Duration::from_secs(60),
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(30),
+            max_reconnects: None,
+        }
+    }
+}
+
+/// A block header subscription that outlives its connection: when the stream ends or goes
+/// quiet it reconnects with exponential backoff, resubscribes, and reports the blocks it
+/// missed in between.
+pub struct ResilientBlockStream {
+    subscribe: BlockSubscriber,
+    config: BlockStreamConfig,
+    headers: Option<BoxStream<'static, Header>>,
+    last_block: Option<u64>,
+    last_hash: B256,
+    pending: VecDeque<BlockStreamEvent>,
+}
+
+impl ResilientBlockStream {
+    /// Subscribes to new heads over a websocket opened with `ws`, on the first header asked for.
+    pub fn new(ws: WsConnect) -> Self {
+        Self::with_subscriber(Box::new(move || {
+            let ws = ws.clone();
+            Box::pin(async move {
+                let provider = ProviderBuilder::new()
+                    .connect_ws(ws)
+                    .await
+                    .map_err(|e| ArbRsError::ProviderError(e.to_string()))?;
+                let subscription = provider
+                    .subscribe_blocks()
+                    .await
+                    .map_err(|e| ArbRsError::ProviderError(e.to_string()))?;
+                // The provider owns the connection, so it lives as long as the stream.
+                let headers = subscription.into_stream().map(move |header| {
+                    let _ = &provider;
+                    header
+                });
+                Ok(headers.boxed())
+            })
+        }))
+    }
+
+    /// Takes headers from `subscribe`, called again on every reconnect.
+    pub fn with_subscriber(subscribe: BlockSubscriber) -> Self {
+        Self {
+            subscribe,
+            config: BlockStreamConfig::default(),
+            headers: None,
+            last_block: None,
+            last_hash: B256::ZERO,
+            pending: VecDeque::new(),
+        }
+    }
+
+    pub fn with_config(mut self, config: BlockStreamConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Number of the last header yielded.
+    pub fn last_block(&self) -> Option<u64> {
+        self.last_block
+    }
+
+    /// The next header, preceded by a [`BlockStreamEvent::MissedBlocks`] when it skips ahead
+    /// of the last one. `None` only once `max_reconnects` reconnects in a row have failed.
+    pub async fn next_event(&mut self) -> Option<BlockStreamEvent> {
+        if let Some(event) = self.pending.pop_front() {
+            return Some(event);
+        }
+        loop {
+            let Some(headers) = self.headers.as_mut() else {
+                self.headers = Some(self.reconnect().await?);
+                continue;
+            };
+            match tokio::time::timeout(self.config.idle_timeout, headers.next()).await {
+                Ok(Some(header)) => {
+                    // A resubscription may replay the head it was last at.
+                    if self.last_block == Some(header.number) && self.last_hash == header.hash {
+                        continue;
+                    }
+                    self.on_header(header);
+                    return self.pending.pop_front();
+                }
+                Ok(None) => tracing::warn!("Block subscription ended, reconnecting."),
+                Err(_) => tracing::warn!(
+                    timeout = ?self.config.idle_timeout,
+                    "No block header received, reconnecting."
+                ),
+            }
+            self.headers = None;
+        }
+    }
+
+    /// Yields every event of [`next_event`](Self::next_event) as a stream.
+    pub fn into_stream(self) -> impl Stream<Item = BlockStreamEvent> + Send {
+        stream::unfold(self, |mut blocks| async move {
+            let event = blocks.next_event().await?;
+            Some((event, blocks))
+        })
+    }
+
+    fn on_header(&mut self, header: Header) {
+        if let Some(last) = self.last_block
+            && header.number > last + 1
+        {
+            tracing::warn!(
+                from = last + 1,
+                to = header.number - 1,
+                "Missed blocks while the subscription was down."
+            );
+            self.pending.push_back(BlockStreamEvent::MissedBlocks {
+                from: last + 1,
+                to: header.number - 1,
+            });
+        }
+        self.last_block = Some(header.number);
+        self.last_hash = header.hash;
+        self.pending
+            .push_back(BlockStreamEvent::Block(Box::new(header)));
+    }
+
+    async fn reconnect(&mut self) -> Option<BoxStream<'static, Header>> {
+        let mut backoff = self.config.initial_backoff;
+        let mut failures = 0;
+        loop {
+            match (self.subscribe)().await {
+                Ok(headers) => {
+                    if failures > 0 || self.last_block.is_some() {
+                        tracing::info!(failures, "Block subscription re-established.");
+                    }
+                    return Some(headers);
+                }
+                Err(e) => {
+                    failures += 1;
+                    if self
+                        .config
+                        .max_reconnects
+                        .is_some_and(|max| failures >= max)
+                    {
+                        tracing::error!(failures, "Giving up on the block subscription: {}", e);
+                        return None;
+                    }
+                    tracing::warn!(
+                        failures,
+                        ?backoff,
+                        "Block subscription failed, retrying: {}",
+                        e
+                    );
+                    tokio::time::sleep(backoff).await;
+                    backoff = (backoff * 2).min(self.config.max_backoff);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,74 @@
+use alloy_primitives::B256;
+use alloy_rpc_types::Header;
+use std::collections::BTreeMap;
+
+/// Blocks a [`ChainTracker`] remembers the hash of, well past any reorg seen on mainnet.
+pub const DEFAULT_TRACKED_BLOCKS: u64 = 128;
+
+/// Remembers the hash of each recent block seen on the header stream, to tell when the chain
+/// reorganised under the state cached for those blocks.
+#[derive(Debug, Clone)]
+pub struct ChainTracker {
+    hashes: BTreeMap<u64, B256>,
+    depth: u64,
+}
+
+impl Default for ChainTracker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ChainTracker {
+    pub fn new() -> Self {
+        Self::with_depth(DEFAULT_TRACKED_BLOCKS)
+    }
+
+    /// Remembers the last `depth` blocks. Reorgs deeper than that go unnoticed.
+    pub fn with_depth(depth: u64) -> Self {
+        Self {
+            hashes: BTreeMap::new(),
+            depth: depth.max(1),
+        }
+    }
+
+    /// The hash recorded for block `number`, if it's still remembered.
+    pub fn hash_of(&self, number: u64) -> Option<B256> {
+        self.hashes.get(&number).copied()
+    }
+
+    /// Records `header` through [`observe`](Self::observe).
+    pub fn observe_header(&mut self, header: &Header) -> Option<u64> {
+        self.observe(header.number, header.hash, header.parent_hash)
+    }
+
+    /// Records block `number` and returns the first block whose hash changed, if any: `number`
+    /// itself when it was seen before with another hash, or its parent when `parent_hash`
+    /// isn't the hash recorded for it. Everything recorded from that block on is forgotten,
+    /// being part of the abandoned branch.
+    ///
+    /// Nodes announce each block of the new branch, so the block a reorg starts at is usually
+    /// reported by its own header. Only the parent is checked, so if headers were missed the
+    /// true fork point may lie deeper than the block returned.
+    pub fn observe(&mut self, number: u64, hash: B256, parent_hash: B256) -> Option<u64> {
+        let mut reorg_from = None;
+        if number > 0
+            && self
+                .hash_of(number - 1)
+                .is_some_and(|parent| parent != parent_hash)
+        {
+            reorg_from = Some(number - 1);
+        } else if self.hash_of(number).is_some_and(|known| known != hash) {
+            reorg_from = Some(number);
+        }
+        if let Some(from) = reorg_from {
+            self.hashes.split_off(&from);
+        }
+
+        self.hashes.insert(number, hash);
+        if let Some(&head) = self.hashes.keys().next_back() {
+            self.hashes = self.hashes.split_off(&head.saturating_sub(self.depth - 1));
+        }
+        reorg_from
+    }
+}
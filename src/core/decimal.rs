@@ -0,0 +1,102 @@
+//! Serde for integers as decimal strings, e.g. `#[serde(with = "decimal")]` on a `U256`.
+//! JSON readers that parse numbers as doubles would round anything past 2^53, and alloy's
+//! own encoding of `U256` is hex.
+
+use serde::{Deserialize, Deserializer, Serializer, de::Error};
+use std::fmt::Display;
+use std::str::FromStr;
+
+pub fn serialize<S: Serializer, T: Display>(value: &T, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(value)
+}
+
+pub fn deserialize<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr,
+    T::Err: Display,
+{
+    let s = String::deserialize(deserializer)?;
+    T::from_str(&s).map_err(D::Error::custom)
+}
+
+/// An optional integer as a decimal string or `null`.
+pub mod option {
+    use super::*;
+
+    pub fn serialize<S: Serializer, T: Display>(
+        value: &Option<T>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match value {
+            Some(value) => serializer.collect_str(value),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+    where
+        D: Deserializer<'de>,
+        T: FromStr,
+        T::Err: Display,
+    {
+        Option::<String>::deserialize(deserializer)?
+            .map(|s| T::from_str(&s).map_err(D::Error::custom))
+            .transpose()
+    }
+}
+
+/// Integers as an array of decimal strings.
+pub mod vec {
+    use super::*;
+
+    pub fn serialize<S: Serializer, T: Display>(
+        values: &[T],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(values.iter().map(ToString::to_string))
+    }
+
+    pub fn deserialize<'de, D, T>(deserializer: D) -> Result<Vec<T>, D::Error>
+    where
+        D: Deserializer<'de>,
+        T: FromStr,
+        T::Err: Display,
+    {
+        Vec::<String>::deserialize(deserializer)?
+            .iter()
+            .map(|s| T::from_str(s).map_err(D::Error::custom))
+            .collect()
+    }
+}
+
+/// An optional array of integers, as decimal strings or `null`.
+pub mod option_vec {
+    use super::*;
+
+    pub fn serialize<S: Serializer, T: Display>(
+        values: &Option<Vec<T>>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match values {
+            Some(values) => super::vec::serialize(values, serializer),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D, T>(deserializer: D) -> Result<Option<Vec<T>>, D::Error>
+    where
+        D: Deserializer<'de>,
+        T: FromStr,
+        T::Err: Display,
+    {
+        Option::<Vec<String>>::deserialize(deserializer)?
+            .map(|values| {
+                values
+                    .iter()
+                    .map(|s| T::from_str(s).map_err(D::Error::custom))
+                    .collect()
+            })
+            .transpose()
+    }
+}
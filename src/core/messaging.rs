@@ -1,13 +1,35 @@
 use crate::pool::uniswap_v2::UniswapV2PoolState;
+use alloy_primitives::{Address, U256};
 use alloy_provider::Provider;
 use async_trait::async_trait;
-use std::sync::Weak;
+use std::sync::{Arc, Weak};
+use tokio::sync::RwLock;
 
 /// A message sent by a `Publisher` to a `Subscriber`.
 #[derive(Debug, Clone)]
 pub enum PublisherMessage {
     PoolStateUpdate(UniswapV2PoolState),
-    // You can add other message types here later
+    UniswapV3StateUpdate {
+        pool: Address,
+        block_number: u64,
+        sqrt_price_x96: U256,
+        tick: i32,
+        liquidity: u128,
+    },
+    CurveStateUpdate {
+        pool: Address,
+        block_number: u64,
+        balances: Vec<U256>,
+        a: U256,
+        fee: U256,
+    },
+    BalancerStateUpdate {
+        pool: Address,
+        block_number: u64,
+        balances: Vec<U256>,
+        swap_fee: U256,
+        is_paused: bool,
+    },
 }
 
 /// A trait for objects that can be subscribed to.
@@ -24,3 +46,61 @@ pub trait Subscriber<P: Provider + Send + Sync + 'static + ?Sized>: Send + Sync
     fn id(&self) -> usize;
     async fn notify(&self, message: PublisherMessage);
 }
+
+/// The subscribers of a `Publisher`, held weakly so a subscriber going away never keeps it
+/// registered.
+pub struct SubscriberList<P: ?Sized> {
+    subscribers: RwLock<Vec<Weak<dyn Subscriber<P>>>>,
+}
+
+impl<P: ?Sized> Default for SubscriberList<P> {
+    fn default() -> Self {
+        Self {
+            subscribers: RwLock::new(Vec::new()),
+        }
+    }
+}
+
+impl<P: Provider + Send + Sync + 'static + ?Sized> SubscriberList<P> {
+    pub async fn subscribe(&self, subscriber: Weak<dyn Subscriber<P>>) {
+        self.subscribers.write().await.push(subscriber);
+    }
+
+    pub async fn unsubscribe(&self, subscriber_id: usize) {
+        self.subscribers.write().await.retain(|weak_sub| {
+            weak_sub
+                .upgrade()
+                .is_some_and(|sub| sub.id() != subscriber_id)
+        });
+    }
+
+    /// Subscribers still alive. Dropped ones are pruned.
+    pub async fn len(&self) -> usize {
+        self.live().await.len()
+    }
+
+    pub async fn is_empty(&self) -> bool {
+        self.len().await == 0
+    }
+
+    /// Sends `message` to every live subscriber, pruning the dropped ones. The list isn't
+    /// locked while they're notified, so a subscriber may subscribe or unsubscribe from it.
+    pub async fn notify(&self, message: PublisherMessage) {
+        for sub in self.live().await {
+            sub.notify(message.clone()).await;
+        }
+    }
+
+    async fn live(&self) -> Vec<Arc<dyn Subscriber<P>>> {
+        let mut subscribers = self.subscribers.write().await;
+        let mut live = Vec::with_capacity(subscribers.len());
+        subscribers.retain(|weak_sub| match weak_sub.upgrade() {
+            Some(sub) => {
+                live.push(sub);
+                true
+            }
+            None => false,
+        });
+        live
+    }
+}
@@ -1,3 +1,10 @@
+pub mod block_stream;
+pub mod chain_tracker;
+pub mod decimal;
 pub mod messaging;
+pub mod multicall;
+pub mod rpc_client;
 pub mod token;
+pub mod token_behavior;
 pub mod token_fetcher;
+pub mod token_policy;
@@ -0,0 +1,161 @@
+use crate::errors::ArbRsError;
+use alloy_primitives::{Address, Bytes, address};
+use alloy_provider::Provider;
+use alloy_rpc_types::{BlockId, TransactionRequest};
+use alloy_sol_types::{SolCall, sol};
+use futures::future::join_all;
+use std::sync::{Arc, OnceLock};
+
+/// Multicall3's address, the same on every chain it is deployed to.
+pub const MULTICALL3_ADDRESS: Address = address!("cA11bde05977b3631167028862bE2a173976CA11");
+/// Calls sent per `aggregate3`, keeping each round trip well under node gas caps.
+pub const DEFAULT_MAX_CALLS_PER_BATCH: usize = 1_000;
+
+sol! {
+    struct Call3 {
+        address target;
+        bool allowFailure;
+        bytes callData;
+    }
+
+    struct Result3 {
+        bool success;
+        bytes returnData;
+    }
+
+    function aggregate3(Call3[] calldata calls) external payable returns (Result3[] memory returnData);
+    function getCurrentBlockTimestamp() external view returns (uint256 timestamp);
+}
+
+/// One call of a batch. Calls are allowed to fail, so one reverting contract only costs its
+/// own result rather than the whole batch.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BatchCall {
+    pub target: Address,
+    pub call_data: Bytes,
+}
+
+impl BatchCall {
+    pub fn new<C: SolCall>(target: Address, call: C) -> Self {
+        Self {
+            target,
+            call_data: call.abi_encode().into(),
+        }
+    }
+}
+
+/// Sends many `eth_call`s as a few `aggregate3` calls to a Multicall3 deployment.
+pub struct MulticallBatcher<P: Provider + Send + Sync + 'static + ?Sized> {
+    provider: Arc<P>,
+    address: Address,
+    max_calls_per_batch: usize,
+    deployed: OnceLock<bool>,
+}
+
+impl<P: Provider + Send + Sync + 'static + ?Sized> MulticallBatcher<P> {
+    pub fn new(provider: Arc<P>) -> Self {
+        Self {
+            provider,
+            address: MULTICALL3_ADDRESS,
+            max_calls_per_batch: DEFAULT_MAX_CALLS_PER_BATCH,
+            deployed: OnceLock::new(),
+        }
+    }
+
+    /// For chains where Multicall3 lives somewhere else.
+    pub fn with_address(mut self, address: Address) -> Self {
+        self.address = address;
+        self
+    }
+
+    pub fn with_max_calls_per_batch(mut self, max_calls_per_batch: usize) -> Self {
+        self.max_calls_per_batch = max_calls_per_batch.max(1);
+        self
+    }
+
+    pub fn address(&self) -> Address {
+        self.address
+    }
+
+    /// Whether the Multicall3 contract has code. Checked once, then cached.
+    pub async fn is_deployed(&self) -> Result<bool, ArbRsError> {
+        if let Some(deployed) = self.deployed.get() {
+            return Ok(*deployed);
+        }
+        let code = self.provider.get_code_at(self.address).await?;
+        Ok(*self.deployed.get_or_init(|| !code.is_empty()))
+    }
+
+    /// Reads the block timestamp within the batch, so results decoded against it see the
+    /// same block as the other calls.
+    pub fn timestamp_call(&self) -> BatchCall {
+        BatchCall::new(self.address, getCurrentBlockTimestampCall {})
+    }
+
+    /// Runs `calls` at `block_number`, in chunks of `max_calls_per_batch` sent concurrently.
+    /// Returns each call's return data in order, or `None` where it reverted.
+    pub async fn aggregate(
+        &self,
+        calls: &[BatchCall],
+        block_number: Option<u64>,
+    ) -> Result<Vec<Option<Bytes>>, ArbRsError> {
+        let block_id = block_number.map(BlockId::from).unwrap_or(BlockId::latest());
+        let chunk_futs = calls
+            .chunks(self.max_calls_per_batch)
+            .map(|chunk| async move {
+                let call = aggregate3Call {
+                    calls: chunk
+                        .iter()
+                        .map(|call| Call3 {
+                            target: call.target,
+                            allowFailure: true,
+                            callData: call.call_data.clone(),
+                        })
+                        .collect(),
+                };
+                let request = TransactionRequest::default()
+                    .to(self.address)
+                    .input(call.abi_encode().into());
+                let result_bytes = self.provider.call(request).block(block_id).await?;
+                let results = aggregate3Call::abi_decode_returns(&result_bytes)?;
+                if results.len() != chunk.len() {
+                    return Err(ArbRsError::CalculationError(format!(
+                        "aggregate3 returned {} results for {} calls",
+                        results.len(),
+                        chunk.len()
+                    )));
+                }
+                Ok(results
+                    .into_iter()
+                    .map(|result| result.success.then_some(result.returnData))
+                    .collect::<Vec<_>>())
+            });
+
+        let mut results = Vec::with_capacity(calls.len());
+        for chunk_results in join_all(chunk_futs).await {
+            results.extend(chunk_results?);
+        }
+        Ok(results)
+    }
+}
+
+/// Decodes the result of `MulticallBatcher::timestamp_call`.
+pub fn decode_timestamp(result: &Option<Bytes>) -> Result<u64, ArbRsError> {
+    let timestamp = decode_result::<getCurrentBlockTimestampCall>(result)?;
+    u64::try_from(timestamp).map_err(|_| {
+        ArbRsError::CalculationError(format!("Block timestamp {timestamp} out of range"))
+    })
+}
+
+/// Decodes a batched call's result, failing if the call reverted.
+pub fn decode_result<C: SolCall>(result: &Option<Bytes>) -> Result<C::Return, ArbRsError> {
+    let bytes = result.as_ref().ok_or_else(|| {
+        ArbRsError::CalculationError(format!("Batched call {} reverted", C::SIGNATURE))
+    })?;
+    Ok(C::abi_decode_returns(bytes)?)
+}
+
+/// Like `decode_result`, but `None` if the call reverted or returned something else.
+pub fn try_decode_result<C: SolCall>(result: &Option<Bytes>) -> Option<C::Return> {
+    C::abi_decode_returns(result.as_ref()?).ok()
+}
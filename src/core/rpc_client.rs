@@ -0,0 +1,319 @@
+use alloy::transports::{RpcError, TransportErrorKind, TransportResult};
+use alloy_json_rpc::RpcRecv;
+use alloy_network::Ethereum;
+use alloy_primitives::{BlockNumber, Bytes, ChainId, U64, U128};
+use alloy_provider::{
+    Caller, EthCall, EthCallManyParams, EthCallParams, Provider, ProviderCall, RootProvider,
+};
+use alloy_rpc_client::NoParams;
+use alloy_rpc_types::{BlockNumberOrTag, Filter, Log, TransactionRequest};
+use async_trait::async_trait;
+use std::fmt;
+use std::future::{Future, IntoFuture};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// Time a single RPC call may take before it's abandoned, unless configured otherwise.
+pub const DEFAULT_RPC_TIMEOUT: Duration = Duration::from_secs(2);
+/// Retries of an idempotent call that timed out or was rate limited.
+pub const DEFAULT_RPC_MAX_RETRIES: u32 = 2;
+/// Backoff before the first retry, doubled for each one after it.
+pub const DEFAULT_RPC_BACKOFF: Duration = Duration::from_millis(100);
+
+/// The error a call that ran past its deadline fails with, carried as a custom transport
+/// error and turned into `ArbRsError::RpcTimeout`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RpcTimeoutError {
+    pub method: &'static str,
+    pub timeout: Duration,
+}
+
+impl fmt::Display for RpcTimeoutError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} timed out after {:?}", self.method, self.timeout)
+    }
+}
+
+impl std::error::Error for RpcTimeoutError {}
+
+/// Whether `error` is a call given up on by [`RpcClient`] for taking too long.
+pub fn is_timeout(error: &RpcError<TransportErrorKind>) -> bool {
+    timeout_of(error).is_some()
+}
+
+pub(crate) fn timeout_of(error: &RpcError<TransportErrorKind>) -> Option<&RpcTimeoutError> {
+    match error {
+        RpcError::Transport(TransportErrorKind::Custom(e)) => e.downcast_ref::<RpcTimeoutError>(),
+        _ => None,
+    }
+}
+
+/// Whether the node turned the call away for exceeding its request rate.
+pub fn is_rate_limited(error: &RpcError<TransportErrorKind>) -> bool {
+    match error {
+        RpcError::Transport(TransportErrorKind::HttpError(e)) => e.is_rate_limit_err(),
+        RpcError::Transport(kind) => kind.to_string().contains("429"),
+        // Covers the codes and messages nodes and providers rate limit with.
+        RpcError::ErrorResp(payload) => payload.is_retry_err(),
+        _ => false,
+    }
+}
+
+/// Whether retrying the call may succeed. Reverts and malformed requests never will.
+pub fn is_retryable(error: &RpcError<TransportErrorKind>) -> bool {
+    match error {
+        RpcError::Transport(kind) => is_timeout(error) || kind.is_retry_err(),
+        RpcError::ErrorResp(_) => is_rate_limited(error),
+        _ => false,
+    }
+}
+
+/// Counters of what the [`RpcClient`] ran into, for logging.
+#[derive(Debug, Default)]
+pub struct RpcMetrics {
+    calls: AtomicU64,
+    timeouts: AtomicU64,
+    rate_limited: AtomicU64,
+    retries: AtomicU64,
+    failures: AtomicU64,
+}
+
+/// The [`RpcMetrics`] counters at one point in time.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RpcMetricsSnapshot {
+    pub calls: u64,
+    pub timeouts: u64,
+    pub rate_limited: u64,
+    pub retries: u64,
+    /// Calls that failed after their last attempt.
+    pub failures: u64,
+}
+
+impl RpcMetrics {
+    pub fn snapshot(&self) -> RpcMetricsSnapshot {
+        RpcMetricsSnapshot {
+            calls: self.calls.load(Ordering::Relaxed),
+            timeouts: self.timeouts.load(Ordering::Relaxed),
+            rate_limited: self.rate_limited.load(Ordering::Relaxed),
+            retries: self.retries.load(Ordering::Relaxed),
+            failures: self.failures.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct RpcPolicy {
+    timeout: Duration,
+    max_retries: u32,
+    backoff: Duration,
+}
+
+impl RpcPolicy {
+    /// Runs `attempt` under the timeout, retrying retryable failures with jittered backoff.
+    async fn run<T, F, Fut>(
+        self,
+        metrics: &RpcMetrics,
+        method: &'static str,
+        attempt: F,
+    ) -> TransportResult<T>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = TransportResult<T>>,
+    {
+        metrics.calls.fetch_add(1, Ordering::Relaxed);
+        crate::metrics::record_rpc_call(method);
+        let mut retries = 0;
+        loop {
+            let result = match tokio::time::timeout(self.timeout, attempt()).await {
+                Ok(result) => result,
+                Err(_) => {
+                    metrics.timeouts.fetch_add(1, Ordering::Relaxed);
+                    Err(TransportErrorKind::custom(RpcTimeoutError {
+                        method,
+                        timeout: self.timeout,
+                    }))
+                }
+            };
+            let error = match result {
+                Ok(value) => return Ok(value),
+                Err(error) => error,
+            };
+            if is_rate_limited(&error) {
+                metrics.rate_limited.fetch_add(1, Ordering::Relaxed);
+            }
+            if retries >= self.max_retries || !is_retryable(&error) {
+                metrics.failures.fetch_add(1, Ordering::Relaxed);
+                return Err(error);
+            }
+            retries += 1;
+            metrics.retries.fetch_add(1, Ordering::Relaxed);
+            tracing::debug!(method, retries, "Retrying RPC call: {}", error);
+            tokio::time::sleep(self.backoff_for(retries)).await;
+        }
+    }
+
+    /// Exponential backoff plus up to half of it again at random, so calls that failed
+    /// together don't retry together.
+    fn backoff_for(&self, retry: u32) -> Duration {
+        let backoff = self.backoff.saturating_mul(1 << (retry - 1).min(16));
+        let jitter_ms = backoff.as_millis() as u64 / 2;
+        backoff + Duration::from_millis(rand::random_range(0..=jitter_ms))
+    }
+}
+
+/// A provider that puts a deadline on every `eth_call`, block number, gas price, chain id
+/// and log query it forwards to `inner`, and retries them when they time out or are rate
+/// limited. As a `Provider` itself it's handed to pools and managers in place of the raw
+/// provider, so one hung call fails on its own instead of stalling everything waiting on it.
+pub struct RpcClient<P: ?Sized> {
+    inner: Arc<P>,
+    policy: RpcPolicy,
+    metrics: Arc<RpcMetrics>,
+}
+
+impl<P: Provider + Send + Sync + 'static + ?Sized> RpcClient<P> {
+    pub fn new(inner: Arc<P>) -> Self {
+        Self {
+            inner,
+            policy: RpcPolicy {
+                timeout: DEFAULT_RPC_TIMEOUT,
+                max_retries: DEFAULT_RPC_MAX_RETRIES,
+                backoff: DEFAULT_RPC_BACKOFF,
+            },
+            metrics: Arc::new(RpcMetrics::default()),
+        }
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.policy.timeout = timeout;
+        self
+    }
+
+    pub fn with_max_retries(mut self, max_retries: u32) -> Self {
+        self.policy.max_retries = max_retries;
+        self
+    }
+
+    pub fn with_backoff(mut self, backoff: Duration) -> Self {
+        self.policy.backoff = backoff;
+        self
+    }
+
+    /// The client's counters, to be logged from wherever it was handed to.
+    pub fn metrics(&self) -> Arc<RpcMetrics> {
+        self.metrics.clone()
+    }
+
+    pub fn inner(&self) -> &Arc<P> {
+        &self.inner
+    }
+
+    /// A call with no parameters, made by `attempt` on the inner provider.
+    fn run<Resp, T, F, Fut>(
+        &self,
+        method: &'static str,
+        attempt: F,
+    ) -> ProviderCall<NoParams, Resp, T>
+    where
+        Resp: RpcRecv,
+        T: Send + 'static,
+        F: Fn(Arc<P>) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = TransportResult<T>> + Send + 'static,
+    {
+        let (inner, policy, metrics) = (self.inner.clone(), self.policy, self.metrics.clone());
+        ProviderCall::BoxedFuture(Box::pin(async move {
+            policy
+                .run(&metrics, method, || attempt(inner.clone()))
+                .await
+        }))
+    }
+}
+
+#[async_trait]
+impl<P: Provider + Send + Sync + 'static + ?Sized> Provider for RpcClient<P> {
+    fn root(&self) -> &RootProvider {
+        self.inner.root()
+    }
+
+    fn call(&self, tx: TransactionRequest) -> EthCall<Ethereum, Bytes> {
+        EthCall::call(
+            RetryingCaller {
+                inner: self.inner.clone(),
+                policy: self.policy,
+                metrics: self.metrics.clone(),
+            },
+            tx,
+        )
+        .block(BlockNumberOrTag::Pending.into())
+    }
+
+    fn get_block_number(&self) -> ProviderCall<NoParams, U64, BlockNumber> {
+        self.run("eth_blockNumber", |inner| async move {
+            inner.get_block_number().await
+        })
+    }
+
+    fn get_chain_id(&self) -> ProviderCall<NoParams, U64, ChainId> {
+        self.run(
+            "eth_chainId",
+            |inner| async move { inner.get_chain_id().await },
+        )
+    }
+
+    fn get_gas_price(&self) -> ProviderCall<NoParams, U128, u128> {
+        self.run("eth_gasPrice", |inner| async move {
+            inner.get_gas_price().await
+        })
+    }
+
+    async fn get_logs(&self, filter: &Filter) -> TransportResult<Vec<Log>> {
+        self.policy
+            .run(&self.metrics, "eth_getLogs", || self.inner.get_logs(filter))
+            .await
+    }
+}
+
+/// Sends each `eth_call` through `inner`, under the client's policy.
+struct RetryingCaller<P: ?Sized> {
+    inner: Arc<P>,
+    policy: RpcPolicy,
+    metrics: Arc<RpcMetrics>,
+}
+
+impl<P: Provider + Send + Sync + 'static + ?Sized> Caller<Ethereum, Bytes> for RetryingCaller<P> {
+    fn call(
+        &self,
+        params: EthCallParams<Ethereum>,
+    ) -> TransportResult<ProviderCall<EthCallParams<Ethereum>, Bytes>> {
+        let (inner, policy, metrics) = (self.inner.clone(), self.policy, self.metrics.clone());
+        Ok(ProviderCall::BoxedFuture(Box::pin(async move {
+            policy
+                .run(&metrics, "eth_call", || {
+                    let mut call = inner
+                        .call(params.data().clone())
+                        .overrides_opt(params.overrides().cloned())
+                        .with_block_overrides_opt(params.block_overrides().cloned());
+                    if let Some(block) = params.block() {
+                        call = call.block(block);
+                    }
+                    call.into_future()
+                })
+                .await
+        })))
+    }
+
+    fn estimate_gas(
+        &self,
+        params: EthCallParams<Ethereum>,
+    ) -> TransportResult<ProviderCall<EthCallParams<Ethereum>, Bytes>> {
+        Caller::<Ethereum, Bytes>::estimate_gas(&self.inner.weak_client(), params)
+    }
+
+    fn call_many(
+        &self,
+        params: EthCallManyParams<'_>,
+    ) -> TransportResult<ProviderCall<EthCallManyParams<'static>, Bytes>> {
+        Caller::<Ethereum, Bytes>::call_many(&self.inner.weak_client(), params)
+    }
+}
@@ -1,5 +1,6 @@
 use crate::errors::ArbRsError;
-use alloy_primitives::{Address, Bytes, TxKind, U256};
+use crate::math::v3::full_math::mul_div_rounding_up;
+use alloy_primitives::{Address, Bytes, TxKind, U256, address};
 use alloy_provider::Provider;
 use alloy_rpc_types::{BlockId, BlockNumberOrTag, TransactionRequest};
 use alloy_sol_types::{SolCall, sol};
@@ -11,6 +12,7 @@ use std::fmt::{Debug, Formatter, Result as FmtResult};
 use std::hash::{Hash, Hasher};
 use std::num::NonZeroUsize;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, Ordering as AtomicOrdering};
 use tokio::sync::Mutex;
 
 sol!(
@@ -19,8 +21,18 @@ sol!(
     function balanceOf(address owner) external view returns (uint256 balance);
 );
 
+/// Wrapped ether on mainnet, the token profits and gas costs are valued in.
+pub const WETH_ADDRESS: Address = address!("C02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2");
+/// USD Coin on mainnet, which tokens without a WETH pool are valued through.
+pub const USDC_ADDRESS: Address = address!("A0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48");
+/// Address Curve and the token manager stand native ether in with.
+pub const NATIVE_ETH_ADDRESS: Address = address!("EeeeeEeeeEeEeeEeEeEeeEEEeeeeEeeeeeeeEEeE");
+
 const BALANCE_CACHE_SIZE: usize = 256;
 
+/// Basis points in a whole amount, the unit of transfer taxes.
+pub const TRANSFER_TAX_BPS_DENOMINATOR: u32 = 10_000;
+
 #[async_trait]
 pub trait TokenLike: Send + Sync {
     fn address(&self) -> Address;
@@ -142,6 +154,9 @@ pub struct Erc20Data<P: ?Sized> {
     pub total_supply_cache: Arc<Mutex<LruCache<u64, U256>>>,
     pub allowance_cache:
         Arc<Mutex<HashMap<Address, HashMap<Address, Arc<Mutex<LruCache<u64, U256>>>>>>>,
+    /// Share of every transfer the recipient doesn't receive, in basis points. Shared by the
+    /// clones of this token, so pools built before the tax was measured see it.
+    pub transfer_tax_bps: Arc<AtomicU32>,
 }
 
 impl<P: ?Sized> Debug for Erc20Data<P> {
@@ -174,8 +189,18 @@ impl<P: Provider + Send + Sync + ?Sized> Erc20Data<P> {
                 NonZeroUsize::new(BALANCE_CACHE_SIZE).unwrap(),
             ))),
             allowance_cache: Arc::new(Mutex::new(HashMap::new())),
+            transfer_tax_bps: Arc::new(AtomicU32::new(0)),
         }
     }
+
+    pub fn transfer_tax_bps(&self) -> u32 {
+        self.transfer_tax_bps.load(AtomicOrdering::Relaxed)
+    }
+
+    pub fn set_transfer_tax_bps(&self, transfer_tax_bps: u32) {
+        self.transfer_tax_bps
+            .store(transfer_tax_bps, AtomicOrdering::Relaxed);
+    }
 }
 
 #[async_trait]
@@ -360,6 +385,55 @@ pub enum Token<P: ?Sized> {
     Native(Arc<NativeTokenData<P>>),
 }
 
+impl<P: Provider + Send + Sync + ?Sized> Token<P> {
+    /// The fee-on-transfer tax, in basis points. Zero for ether and untaxed tokens.
+    pub fn transfer_tax_bps(&self) -> u32 {
+        match self {
+            Token::Erc20(token) => token.transfer_tax_bps(),
+            Token::Native(_) => 0,
+        }
+    }
+
+    /// What the recipient of a transfer of `amount` receives, net of the transfer tax.
+    pub fn received_amount(&self, amount: U256) -> U256 {
+        received_amount(amount, self.transfer_tax_bps())
+    }
+
+    /// The smallest transfer whose recipient receives at least `amount`, the inverse of
+    /// [`received_amount`](Self::received_amount). `None` if the tax takes everything.
+    pub fn sent_amount_for(&self, amount: U256) -> Option<U256> {
+        sent_amount_for(amount, self.transfer_tax_bps())
+    }
+}
+
+/// What the recipient of a transfer of `amount` receives under a tax of `transfer_tax_bps`.
+pub fn received_amount(amount: U256, transfer_tax_bps: u32) -> U256 {
+    match transfer_tax_bps {
+        0 => amount,
+        bps => {
+            let kept = U256::from(TRANSFER_TAX_BPS_DENOMINATOR.saturating_sub(bps));
+            let denominator = U256::from(TRANSFER_TAX_BPS_DENOMINATOR);
+            amount / denominator * kept + amount % denominator * kept / denominator
+        }
+    }
+}
+
+/// The smallest transfer whose recipient receives at least `amount` under a tax of
+/// `transfer_tax_bps`. `None` if the tax takes everything.
+pub fn sent_amount_for(amount: U256, transfer_tax_bps: u32) -> Option<U256> {
+    match transfer_tax_bps {
+        0 => Some(amount),
+        bps => {
+            let kept = U256::from(TRANSFER_TAX_BPS_DENOMINATOR.saturating_sub(bps));
+            if kept.is_zero() {
+                return None;
+            }
+            let denominator = U256::from(TRANSFER_TAX_BPS_DENOMINATOR);
+            mul_div_rounding_up(amount, denominator, kept)
+        }
+    }
+}
+
 #[async_trait]
 impl<P: Provider + Send + Sync + 'static + ?Sized> TokenLike for Token<P> {
     fn address(&self) -> Address {
@@ -0,0 +1,80 @@
+use crate::core::token::TRANSFER_TAX_BPS_DENOMINATOR;
+use crate::errors::ArbRsError;
+use alloy_primitives::{Address, Bytes, U256, hex};
+use alloy_provider::Provider;
+use alloy_rpc_types::state::StateOverridesBuilder;
+use alloy_rpc_types::{BlockId, TransactionRequest};
+use alloy_sol_types::SolValue;
+
+/// Runtime code run in place of the sender's for the simulated transfer. Called with
+/// `(token, recipient, amount)`, it reads the recipient's balance, transfers `amount` to it,
+/// reads the balance again and returns `(all calls succeeded, before, after)`.
+const TRANSFER_PROBE_CODE: [u8; 108] = hex!(
+    "6370a0823160e01b60005260203560045260206080602460006000355afa"
+    "63a9059cbb60e01b600052602035600452604035602452600060006044600060006000355af1"
+    "6370a0823160e01b600052602035600452602060a0602460006000355afa"
+    "161660605260606060f3"
+);
+
+/// Receives the simulated transfer. Not a pool or a known exempt address, so the transfer is
+/// taxed as a buy from the sending pool would be.
+const PROBE_RECIPIENT: Address = Address::repeat_byte(0x5c);
+
+/// How a token behaves on transfer, as measured by [`simulate_transfer`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TokenBehavior {
+    /// Share of a transfer the recipient doesn't receive, in basis points, rounded up.
+    pub transfer_tax_bps: u32,
+}
+
+impl TokenBehavior {
+    pub fn is_taxed(&self) -> bool {
+        self.transfer_tax_bps > 0
+    }
+}
+
+/// Simulates `holder` sending `amount` of `token` with `eth_call`, the holder's code
+/// overridden by a probe that measures what the recipient receives. `holder` must hold at
+/// least `amount`, e.g. a pool trading the token.
+pub async fn simulate_transfer<P: Provider + Send + Sync + 'static + ?Sized>(
+    provider: &P,
+    token: Address,
+    holder: Address,
+    amount: U256,
+    block_number: Option<u64>,
+) -> Result<TokenBehavior, ArbRsError> {
+    if amount.is_zero() {
+        return Err(ArbRsError::CalculationError(
+            "Cannot simulate a transfer of nothing".to_string(),
+        ));
+    }
+    let overrides = StateOverridesBuilder::default()
+        .with_code(holder, Bytes::from_static(&TRANSFER_PROBE_CODE))
+        .build();
+    let request = TransactionRequest::default()
+        .to(holder)
+        .input((token, PROBE_RECIPIENT, amount).abi_encode_params().into());
+    let result = provider
+        .call(request)
+        .overrides(overrides)
+        .block(block_number.map(BlockId::from).unwrap_or(BlockId::latest()))
+        .await
+        .map_err(|e| ArbRsError::ProviderError(e.to_string()))?;
+    let (succeeded, before, after) = <(bool, U256, U256)>::abi_decode_params(&result)
+        .map_err(|e| ArbRsError::AbiDecodeError(e.to_string()))?;
+
+    let received = after.saturating_sub(before);
+    if !succeeded || received.is_zero() {
+        return Err(ArbRsError::TokenStandardError(
+            token,
+            "Simulated transfer failed".to_string(),
+        ));
+    }
+    let taxed = amount.saturating_sub(received);
+    let transfer_tax_bps = taxed
+        .saturating_mul(U256::from(TRANSFER_TAX_BPS_DENOMINATOR))
+        .div_ceil(amount);
+    Ok(TokenBehavior {
+        transfer_tax_bps: transfer_tax_bps.to(),
+    })
+}
@@ -1,157 +1,182 @@
+use crate::core::multicall::{BatchCall, MULTICALL3_ADDRESS, MulticallBatcher};
 use crate::core::token::Erc20Data;
 use crate::errors::ArbRsError;
 use alloy_primitives::{Address, B256, Bytes, TxKind};
 use alloy_provider::Provider;
 use alloy_rpc_types::TransactionRequest;
 use alloy_sol_types::{SolCall, sol};
+use futures::future::join_all;
 use std::sync::Arc;
 
+/// Decimals assumed for a token whose `decimals()` reverts, unless configured otherwise.
+pub const DEFAULT_TOKEN_DECIMALS: u8 = 18;
+
 // ABI defs
 sol!(
     function symbol() external view returns (string memory);
     function symbol_bytes32() external view returns (bytes32);
     function decimals() external view returns (uint8);
     function name() external view returns (string memory);
-    function name_bytes32() external view returns (bytes32);
 );
 
 pub struct TokenFetcher<P: ?Sized> {
     provider: Arc<P>,
+    default_decimals: Option<u8>,
+    multicall: Address,
 }
 
 impl<P: Provider + Send + Sync + 'static + ?Sized> TokenFetcher<P> {
     pub fn new(provider: Arc<P>) -> Self {
-        Self { provider }
+        Self {
+            provider,
+            default_decimals: Some(DEFAULT_TOKEN_DECIMALS),
+            multicall: MULTICALL3_ADDRESS,
+        }
+    }
+
+    /// Decimals of tokens whose `decimals()` reverts or returns something else. With `None`,
+    /// fetching such a token fails instead.
+    pub fn with_default_decimals(mut self, default_decimals: Option<u8>) -> Self {
+        self.default_decimals = default_decimals;
+        self
+    }
+
+    /// Batches calls through the Multicall3 deployed at `multicall`.
+    pub fn with_multicall(mut self, multicall: Address) -> Self {
+        self.multicall = multicall;
+        self
     }
 
     pub async fn fetch_erc20_data(&self, address: Address) -> Result<Erc20Data<P>, ArbRsError> {
         let (decimals_res, symbol_res, name_res) = tokio::join!(
-            self.fetch_decimals(address),
-            self.fetch_symbol(address),
-            self.fetch_name(address)
+            self.call(address, decimalsCall {}.abi_encode()),
+            self.call(address, symbolCall {}.abi_encode()),
+            self.call(address, nameCall {}.abi_encode())
         );
-
-        let decimals = match decimals_res {
-            Ok(d) => d,
-            Err(_) => {
-                return Err(ArbRsError::TokenStandardError(
-                    address,
-                    "Failed to fetch decimals".to_string(),
-                ));
-            }
-        };
-
-        let symbol =
-            symbol_res.unwrap_or_else(|| format!("UNKNOWN@{}", address_to_short_string(address)));
-        let name = name_res.unwrap_or_else(|| "Unknown Token".to_string());
-
-        Ok(Erc20Data::new(
+        self.build(
             address,
-            symbol,
-            name,
-            decimals,
-            Arc::clone(&self.provider),
-        ))
+            decimals_res?.as_ref(),
+            symbol_res.ok().flatten().as_ref(),
+            name_res.ok().flatten().as_ref(),
+        )
     }
 
-    async fn fetch_decimals(&self, address: Address) -> Result<u8, ArbRsError> {
-        let call = decimalsCall {};
-        let request = TransactionRequest {
-            to: Some(TxKind::Call(address)),
-            input: Some(Bytes::from(call.abi_encode())).into(),
-            ..Default::default()
-        };
-
-        let result_bytes = self
-            .provider
-            .call(request)
-            .await
-            .map_err(|e| ArbRsError::ProviderError(e.to_string()))?;
-
-        decimalsCall::abi_decode_returns(&result_bytes)
-            .map_err(|e| ArbRsError::AbiDecodeError(e.to_string()))
+    /// Metadata of every token in `addresses`, in order, from a single `aggregate3` batch.
+    /// Falls back to fetching each token on its own if the batch fails, e.g. on a chain
+    /// without Multicall3.
+    pub async fn fetch_erc20_data_batch(
+        &self,
+        addresses: &[Address],
+    ) -> Vec<Result<Erc20Data<P>, ArbRsError>> {
+        let calls: Vec<BatchCall> = addresses
+            .iter()
+            .flat_map(|&address| {
+                [
+                    BatchCall::new(address, decimalsCall {}),
+                    BatchCall::new(address, symbolCall {}),
+                    BatchCall::new(address, nameCall {}),
+                ]
+            })
+            .collect();
+        let batcher = MulticallBatcher::new(self.provider.clone()).with_address(self.multicall);
+        match batcher.aggregate(&calls, None).await {
+            Ok(results) => addresses
+                .iter()
+                .zip(results.chunks(3))
+                .map(|(address, results)| {
+                    self.build(
+                        *address,
+                        results[0].as_ref(),
+                        results[1].as_ref(),
+                        results[2].as_ref(),
+                    )
+                })
+                .collect(),
+            Err(e) => {
+                tracing::debug!("Token metadata batch failed, fetching one by one: {:?}", e);
+                join_all(
+                    addresses
+                        .iter()
+                        .map(|address| self.fetch_erc20_data(*address)),
+                )
+                .await
+            }
+        }
     }
 
-    /// Fetches symbol using a multi-step fallback process.
-    async fn fetch_symbol(&self, address: Address) -> Option<String> {
-        println!("[{address}] Fetching symbol...");
-        let calldata = symbolCall {}.abi_encode();
+    /// The call's return data, or `None` if the token reverted it.
+    async fn call(&self, address: Address, calldata: Vec<u8>) -> Result<Option<Bytes>, ArbRsError> {
         let request = TransactionRequest {
             to: Some(TxKind::Call(address)),
-            input: Some(calldata.into()).into(),
+            input: Some(Bytes::from(calldata)).into(),
             ..Default::default()
         };
-
         match self.provider.call(request).await {
-            Ok(result_bytes) => {
-                println!("[{address}] Call successful. Trying decoders...");
-                if let Ok(decoded_string) = symbolCall::abi_decode_returns(&result_bytes) {
-                    let symbol = decoded_string.trim().to_string();
-                    if !symbol.is_empty() && symbol.chars().any(|c| c.is_alphanumeric()) {
-                        println!("[{address}] Decoded as string: \"{symbol}\"");
-                        return Some(symbol);
-                    }
-                }
-
-                if let Ok(decoded_bytes) = symbol_bytes32Call::abi_decode_returns(&result_bytes) {
-                    let symbol = bytes32_to_string(&decoded_bytes);
-                    if !symbol.is_empty() {
-                        println!("[{address}] Decoded as bytes32: \"{symbol}\"");
-                        return Some(symbol);
-                    }
-                }
-                println!("[{address}] Decoding failed for both string and bytes32.");
-                None
-            }
-            Err(e) => {
-                println!("[{address}] Call reverted or failed: {e}");
-                None
-            }
+            Ok(result_bytes) => Ok(Some(result_bytes)),
+            Err(e) if e.as_error_resp().is_some() => Ok(None),
+            Err(e) => Err(ArbRsError::ProviderError(e.to_string())),
         }
     }
 
-    /// Fetches name using a multi-step fallback process.
-    async fn fetch_name(&self, address: Address) -> Option<String> {
-        println!("[{address}] Fetching name...");
-        let calldata = nameCall {}.abi_encode();
-        let request = TransactionRequest {
-            to: Some(TxKind::Call(address)),
-            input: Some(calldata.into()).into(),
-            ..Default::default()
+    /// Decodes the results of `decimals()`, `symbol()` and `name()`, `None` where reverted.
+    fn build(
+        &self,
+        address: Address,
+        decimals: Option<&Bytes>,
+        symbol: Option<&Bytes>,
+        name: Option<&Bytes>,
+    ) -> Result<Erc20Data<P>, ArbRsError> {
+        let decimals = match decimals.and_then(|bytes| decimalsCall::abi_decode_returns(bytes).ok())
+        {
+            Some(decimals) => decimals,
+            None => {
+                let decimals = self.default_decimals.ok_or_else(|| {
+                    ArbRsError::TokenStandardError(address, "Failed to fetch decimals".to_string())
+                })?;
+                tracing::warn!(
+                    ?address,
+                    decimals,
+                    "Token has no decimals(), assuming the default."
+                );
+                decimals
+            }
         };
+        let symbol = symbol
+            .and_then(|bytes| decode_text(bytes))
+            .unwrap_or_else(|| format!("UNKNOWN@{}", address_to_short_string(address)));
+        let name = name
+            .and_then(|bytes| decode_text(bytes))
+            .unwrap_or_else(|| "Unknown Token".to_string());
 
-        match self.provider.call(request).await {
-            Ok(result_bytes) => {
-                println!("[{address}] Call successful. Trying decoders...");
-                if let Ok(decoded_string) = nameCall::abi_decode_returns(&result_bytes) {
-                    let name = decoded_string.trim().to_string();
-                    if !name.is_empty() && name.chars().any(|c| c.is_alphanumeric()) {
-                        println!("[{address}] Decoded as string: \"{name}\"");
-                        return Some(name);
-                    }
-                }
+        Ok(Erc20Data::new(
+            address,
+            symbol,
+            name,
+            decimals,
+            Arc::clone(&self.provider),
+        ))
+    }
+}
 
-                if let Ok(decoded_bytes) = name_bytes32Call::abi_decode_returns(&result_bytes) {
-                    let name = bytes32_to_string(&decoded_bytes);
-                    if !name.is_empty() {
-                        println!("[{address}] Decoded as bytes32: \"{name}\"");
-                        return Some(name);
-                    }
-                }
-                println!("[{address}] Decoding failed for both string and bytes32.");
-                None
-            }
-            Err(e) => {
-                println!("[{address}] Call reverted or failed: {e}");
-                None
-            }
+/// A `symbol()` or `name()` result, returned as a `string` or, by legacy tokens like MKR, as
+/// a zero padded `bytes32`.
+pub fn decode_text(bytes: &[u8]) -> Option<String> {
+    if let Ok(decoded_string) = symbolCall::abi_decode_returns(bytes) {
+        let text = decoded_string.trim().to_string();
+        if !text.is_empty() && text.chars().any(|c| c.is_alphanumeric()) {
+            return Some(text);
         }
     }
+    let decoded_bytes = symbol_bytes32Call::abi_decode_returns(bytes).ok()?;
+    let text = bytes32_to_string(&decoded_bytes);
+    (!text.is_empty()).then_some(text)
 }
 
 fn bytes32_to_string(bytes: &B256) -> String {
     let first_null = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
-    String::from_utf8_lossy(&bytes[..first_null]).to_string()
+    String::from_utf8_lossy(&bytes[..first_null])
+        .trim()
+        .to_string()
 }
 
 fn address_to_short_string(address: Address) -> String {
@@ -0,0 +1,178 @@
+use crate::TokenLike;
+use crate::errors::ArbRsError;
+use crate::pool::LiquidityPool;
+use alloy_primitives::Address;
+use alloy_provider::Provider;
+use std::collections::HashSet;
+use std::str::FromStr;
+use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+/// Which tokens a [`TokenPolicy`] lets into the pool graph besides the denied ones.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TokenPolicyMode {
+    /// Every token that isn't denied.
+    #[default]
+    Open,
+    /// Only the allowed tokens that aren't denied.
+    Whitelist,
+}
+
+impl TokenPolicyMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TokenPolicyMode::Open => "open",
+            TokenPolicyMode::Whitelist => "whitelist",
+        }
+    }
+}
+
+impl FromStr for TokenPolicyMode {
+    type Err = String;
+
+    fn from_str(mode: &str) -> Result<Self, Self::Err> {
+        match mode {
+            "open" => Ok(TokenPolicyMode::Open),
+            "whitelist" => Ok(TokenPolicyMode::Whitelist),
+            other => Err(format!("unknown token policy mode {other}")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct PolicyState {
+    mode: TokenPolicyMode,
+    allowed: HashSet<Address>,
+    denied: HashSet<Address>,
+}
+
+/// Keeps tokens out of the pool graph: the pool managers don't register a discovered pool
+/// holding a refused token, and path finding leaves such pools out.
+///
+/// A token is refused if it's denied or, in [`TokenPolicyMode::Whitelist`], not allowed. In
+/// whitelist mode WETH has to be allowed for any path to be found. The policy can change
+/// at runtime; [`refused_pools`](Self::refused_pools) and
+/// [`ArbitrageCache::prune_refused`](crate::arbitrage::cache::ArbitrageCache::prune_refused)
+/// then tell which pools and paths it invalidated.
+#[derive(Debug, Default)]
+pub struct TokenPolicy {
+    state: RwLock<PolicyState>,
+}
+
+impl TokenPolicy {
+    /// An open policy refusing no token.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A whitelist policy letting only `allowed` in.
+    pub fn whitelist(allowed: impl IntoIterator<Item = Address>) -> Self {
+        Self::new()
+            .with_mode(TokenPolicyMode::Whitelist)
+            .with_allowed(allowed)
+    }
+
+    pub fn with_mode(self, mode: TokenPolicyMode) -> Self {
+        self.set_mode(mode);
+        self
+    }
+
+    pub fn with_allowed(self, tokens: impl IntoIterator<Item = Address>) -> Self {
+        self.allow(tokens);
+        self
+    }
+
+    pub fn with_denied(self, tokens: impl IntoIterator<Item = Address>) -> Self {
+        self.deny(tokens);
+        self
+    }
+
+    fn read(&self) -> RwLockReadGuard<'_, PolicyState> {
+        self.state
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn write(&self) -> RwLockWriteGuard<'_, PolicyState> {
+        self.state
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    pub fn mode(&self) -> TokenPolicyMode {
+        self.read().mode
+    }
+
+    pub fn set_mode(&self, mode: TokenPolicyMode) {
+        self.write().mode = mode;
+    }
+
+    /// Adds `tokens` to the allow list.
+    pub fn allow(&self, tokens: impl IntoIterator<Item = Address>) {
+        self.write().allowed.extend(tokens);
+    }
+
+    /// Adds `tokens` to the deny list.
+    pub fn deny(&self, tokens: impl IntoIterator<Item = Address>) {
+        self.write().denied.extend(tokens);
+    }
+
+    /// Removes `token` from both lists.
+    pub fn forget(&self, token: Address) {
+        let mut state = self.write();
+        state.allowed.remove(&token);
+        state.denied.remove(&token);
+    }
+
+    /// The allow list, sorted.
+    pub fn allowed(&self) -> Vec<Address> {
+        let mut allowed: Vec<Address> = self.read().allowed.iter().copied().collect();
+        allowed.sort();
+        allowed
+    }
+
+    /// The deny list, sorted.
+    pub fn denied(&self) -> Vec<Address> {
+        let mut denied: Vec<Address> = self.read().denied.iter().copied().collect();
+        denied.sort();
+        denied
+    }
+
+    pub fn allows(&self, token: Address) -> bool {
+        let state = self.read();
+        !state.denied.contains(&token)
+            && (state.mode == TokenPolicyMode::Open || state.allowed.contains(&token))
+    }
+
+    /// The first of `tokens` the policy refuses, if any.
+    pub fn refused_token(&self, tokens: impl IntoIterator<Item = Address>) -> Option<Address> {
+        tokens.into_iter().find(|token| !self.allows(*token))
+    }
+
+    /// Fails with `TokenNotAllowed` if the pool at `pool` holds a refused token.
+    pub fn check_pool(
+        &self,
+        pool: Address,
+        tokens: impl IntoIterator<Item = Address>,
+    ) -> Result<(), ArbRsError> {
+        match self.refused_token(tokens) {
+            Some(token) => Err(ArbRsError::TokenNotAllowed { pool, token }),
+            None => Ok(()),
+        }
+    }
+
+    /// Addresses of the `pools` holding a token the policy refuses, e.g. to drop them after
+    /// the policy changed.
+    pub fn refused_pools<P: Provider + Send + Sync + 'static + ?Sized>(
+        &self,
+        pools: &[Arc<dyn LiquidityPool<P>>],
+    ) -> Vec<Address> {
+        pools
+            .iter()
+            .filter(|pool| {
+                self.refused_token(pool.get_all_tokens().iter().map(|token| token.address()))
+                    .is_some()
+            })
+            .map(|pool| pool.address())
+            .collect()
+    }
+}
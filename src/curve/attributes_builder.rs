@@ -1,10 +1,12 @@
 use crate::TokenLike;
 use crate::core::token::Token;
+use crate::curve::constants::A_PRECISION;
+use crate::curve::parameter_fetcher;
 use crate::curve::pool_attributes::{
-    CalculationStrategy, PoolAttributes, PoolVariant, SwapStrategyType,
+    CalculationStrategy, ParameterFetcherType, PoolAttributes, PoolVariant, SwapStrategyType,
 };
 use crate::curve::pool_overrides::{self, DVariant};
-use crate::curve::registry::CurveRegistry;
+use crate::curve::registry::{CURVE_STABLE_FACTORY, CURVE_STABLESWAP_NG_FACTORY, CurveRegistry};
 use crate::errors::ArbRsError;
 use crate::manager::token_manager::TokenManager;
 use alloy_primitives::{Address, U256, address};
@@ -72,6 +74,9 @@ const ADMIN_FEE_POOLS: &[Address] = &[
 
 const ORACLE_POOLS: &[Address] = &[RAI_METAPOOL, T_METAPOOL];
 
+// Factories asked for `get_fees(pool)` when the pool doesn't expose its own `factory()`.
+const KNOWN_FACTORIES: &[Address] = &[CURVE_STABLE_FACTORY];
+
 pub async fn build_attributes<P: Provider + Send + Sync + 'static + ?Sized>(
     address: Address,
     tokens: &[Arc<Token<P>>],
@@ -93,7 +98,20 @@ pub async fn build_attributes<P: Provider + Send + Sync + 'static + ?Sized>(
     let base_pool_address = registry.get_base_pool(address).await?;
     let is_metapool = base_pool_address.is_some();
 
-    let swap_strategy = determine_swap_strategy(address, is_metapool);
+    let (parameter_fetcher, factory_address) =
+        parameter_fetcher::detect_fetcher(provider.as_ref(), address, KNOWN_FACTORIES).await;
+    // NG pools are only deployed by the NG factory, which `detect_fetcher` has already
+    // found through the pool's `factory()`.
+    let is_ng = factory_address == Some(CURVE_STABLESWAP_NG_FACTORY);
+    let swap_strategy =
+        determine_swap_strategy(address, is_metapool, is_ng, parameter_fetcher, n_coins)?;
+    let a_precision_multiplier = if parameter_fetcher == ParameterFetcherType::Crypto
+        || swap_strategy == SwapStrategyType::StableSwapNG
+    {
+        A_PRECISION
+    } else {
+        parameter_fetcher::detect_a_precision_multiplier(provider.as_ref(), address).await
+    };
 
     let mut attributes = PoolAttributes {
         pool_variant: if is_metapool {
@@ -115,11 +133,30 @@ pub async fn build_attributes<P: Provider + Send + Sync + 'static + ?Sized>(
         offpeg_fee_multiplier: None,
         base_pool_address,
         oracle_method: None,
+        parameter_fetcher,
+        factory_address,
+        a_precision_multiplier,
+        is_native: Vec::new(),
+        raw_coin_addresses: Vec::new(),
+        weth_coin: None,
     };
 
     if ADMIN_FEE_POOLS.contains(&address) || DYNAMIC_FEE_POOLS.contains(&address) {
         attributes.d_variant = DVariant::Legacy;
     }
+    if swap_strategy == SwapStrategyType::StableSwapNG {
+        attributes.d_variant = DVariant::StableSwapNG;
+        let call = offpeg_fee_multiplierCall {};
+        let res_bytes = provider
+            .call(
+                TransactionRequest::default()
+                    .to(address)
+                    .input(call.abi_encode().into()),
+            )
+            .await?;
+        attributes.offpeg_fee_multiplier =
+            Some(offpeg_fee_multiplierCall::abi_decode_returns(&res_bytes)?);
+    }
 
     println!(
         "[Attributes Builder] Applying specific overrides for {}",
@@ -231,9 +268,26 @@ pub async fn build_attributes<P: Provider + Send + Sync + 'static + ?Sized>(
 }
 
 /// Determines which swap strategy to use based on the pool's address and type.
-fn determine_swap_strategy(address: Address, is_metapool: bool) -> SwapStrategyType {
-    if address == TRICRYPTO2_POOL {
-        SwapStrategyType::Tricrypto
+fn determine_swap_strategy(
+    address: Address,
+    is_metapool: bool,
+    is_ng: bool,
+    parameter_fetcher: ParameterFetcherType,
+    n_coins: usize,
+) -> Result<SwapStrategyType, ArbRsError> {
+    let strategy = if parameter_fetcher == ParameterFetcherType::Crypto {
+        match n_coins {
+            2 => SwapStrategyType::CryptoSwap,
+            3 => SwapStrategyType::Tricrypto,
+            _ => {
+                return Err(ArbRsError::InvalidPool(
+                    address,
+                    format!("No cryptoswap strategy for a {}-coin pool", n_coins),
+                ));
+            }
+        }
+    } else if is_ng {
+        SwapStrategyType::StableSwapNG
     } else if DYNAMIC_FEE_POOLS.contains(&address) {
         SwapStrategyType::DynamicFee
     } else if ORACLE_POOLS.contains(&address) {
@@ -248,5 +302,6 @@ fn determine_swap_strategy(address: Address, is_metapool: bool) -> SwapStrategyT
         SwapStrategyType::Unscaled
     } else {
         SwapStrategyType::Default
-    }
+    };
+    Ok(strategy)
 }
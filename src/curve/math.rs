@@ -1,6 +1,8 @@
 use crate::curve::constants::{A_PRECISION, FEE_DENOMINATOR, PRECISION};
 use crate::curve::pool_overrides::DVariant;
+use crate::curve::types::CurvePoolSnapshot;
 use crate::errors::ArbRsError;
+use crate::math::utils::u256_to_f64;
 use alloy_primitives::U256;
 
 /// Calculates the "virtual balances" (`xp`) used in the core invariant math.
@@ -141,6 +143,30 @@ pub(super) fn calc_dp_gamma(d: U256, xp: &[U256], n_coins: U256) -> Result<U256,
         ))
 }
 
+pub(super) fn calc_dp_ng(d: U256, xp: &[U256], n_coins: U256) -> Result<U256, ArbRsError> {
+    let mut d_p = d;
+    for &x in xp {
+        if x.is_zero() {
+            return Err(ArbRsError::CalculationError(
+                "Cannot calculate with zero balance".to_string(),
+            ));
+        }
+        d_p = d_p
+            .checked_mul(d)
+            .ok_or(ArbRsError::CalculationError("dp_ng mul overflow".to_string()))?
+            / x;
+    }
+    let n_coins_pow_n = n_coins
+        .checked_pow(n_coins)
+        .ok_or(ArbRsError::CalculationError(
+            "n_coins^n_coins overflow".to_string(),
+        ))?;
+    d_p.checked_div(n_coins_pow_n)
+        .ok_or(ArbRsError::CalculationError(
+            "dp_ng div underflow".to_string(),
+        ))
+}
+
 pub(super) fn calc_d_default(
     ann: U256,
     s: U256,
@@ -296,6 +322,7 @@ pub fn get_d(
             DVariant::Group1 | DVariant::Group3 => calc_dp_alpha(d, xp, n_coins)?,
             DVariant::Group2 => calc_dp_beta(d, xp, n_coins)?,
             DVariant::Group4 => calc_dp_gamma(d, xp, n_coins)?,
+            DVariant::StableSwapNG => calc_dp_ng(d, xp, n_coins)?,
             _ => calc_dp_default(d, xp, n_coins)?,
         };
 
@@ -319,6 +346,65 @@ pub fn get_d(
     ))
 }
 
+/// The LP token's virtual price as the pool's `get_virtual_price()` computes it, from the
+/// pool's snapshot and LP supply.
+///
+/// Formula
+/// `virtual_price = D * 10^18 / lp_total_supply`
+pub fn virtual_price_from_snapshot(
+    snapshot: &CurvePoolSnapshot,
+    lp_total_supply: U256,
+    d_variant: DVariant,
+) -> Result<U256, ArbRsError> {
+    if lp_total_supply.is_zero() {
+        return Err(ArbRsError::CalculationError(
+            "Virtual price of a pool without LP supply".to_string(),
+        ));
+    }
+    let xp = xp(&snapshot.rates, &snapshot.balances)?;
+    let d = get_d(&xp, snapshot.a, snapshot.balances.len(), d_variant)?;
+    d.checked_mul(PRECISION)
+        .map(|scaled| scaled / lp_total_supply)
+        .ok_or_else(|| ArbRsError::CalculationError("virtual price mul overflow".to_string()))
+}
+
+/// Marginal weights of the invariant at `xp`, with `D` computed once: for a vanishing
+/// trade before fees, `weights[i] / weights[j]` is the `xp_j` paid out per `xp_i` paid in.
+/// `amp` is scaled the way `get_y` scales it.
+///
+/// Formula
+/// `weight_k = Ann + D^(n+1) / (n^n * prod(xp) * xp_k)`
+pub fn marginal_weights(
+    xp: &[U256],
+    amp: U256,
+    n_coins: usize,
+    d_variant: DVariant,
+    is_y_variant_group0: bool,
+    is_y_variant_group1: bool,
+) -> Result<Vec<f64>, ArbRsError> {
+    if xp.iter().any(|x| x.is_zero()) {
+        return Err(ArbRsError::CalculationError(
+            "Cannot price a pool with a zero balance".to_string(),
+        ));
+    }
+    let effective_amp = if is_y_variant_group0 {
+        amp / A_PRECISION
+    } else {
+        amp
+    };
+    let d = u256_to_f64(get_d(xp, effective_amp, n_coins, d_variant)?);
+
+    let n = n_coins as f64;
+    let ann = if is_y_variant_group1 {
+        u256_to_f64(effective_amp) * n
+    } else {
+        u256_to_f64(effective_amp) * n / u256_to_f64(A_PRECISION)
+    };
+    let c = xp.iter().fold(d, |c, x| c * d / (n * u256_to_f64(*x)));
+
+    Ok(xp.iter().map(|x| ann + c / u256_to_f64(*x)).collect())
+}
+
 /// Calculates the output balance `y` for a swap.
 /// It determines the invariant `D` internally.
 pub fn get_y(
@@ -518,7 +604,8 @@ pub fn get_y_d(
 /// Calculates the adjusted fee rate for pools with dynamic fees.
 ///
 /// Formula
-/// `fee_gamma / (fee_gamma + (1 - K))` where `K = prod(x) / (sum(x)/N)**N`
+/// `feemul * fee / ((feemul - 1) * 4 * xpi * xpj / (xpi + xpj)**2 + 1)`, scaled by
+/// `FEE_DENOMINATOR`, which is `fee` at peg and rises towards `feemul * fee` off it.
 pub fn dynamic_fee(xpi: U256, xpj: U256, fee: U256, feemul: U256) -> Result<U256, ArbRsError> {
     if feemul <= FEE_DENOMINATOR {
         return Ok(fee);
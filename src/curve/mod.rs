@@ -1,6 +1,7 @@
 pub mod attributes_builder;
 pub mod constants;
 pub mod math;
+pub mod parameter_fetcher;
 pub mod pool;
 pub mod pool_attributes;
 pub mod pool_overrides;
@@ -8,3 +9,4 @@ pub mod registry;
 pub mod strategies;
 pub mod tricrypto_math;
 pub mod types;
+pub mod underlying;
@@ -0,0 +1,209 @@
+use crate::curve::constants::A_PRECISION;
+use crate::curve::pool_attributes::ParameterFetcherType;
+use crate::curve::pool_overrides::LEGACY_A_POOLS;
+use crate::errors::ArbRsError;
+use alloy_primitives::{Address, U256};
+use alloy_provider::Provider;
+use alloy_rpc_types::TransactionRequest;
+use alloy_sol_types::{SolCall, sol};
+use async_trait::async_trait;
+
+sol! {
+    function A() external view returns (uint256);
+    function A_precise() external view returns (uint256);
+    function fee() external view returns (uint256);
+    function gamma() external view returns (uint256);
+    function mid_fee() external view returns (uint256);
+    function out_fee() external view returns (uint256);
+    function fee_gamma() external view returns (uint256);
+    function factory() external view returns (address);
+
+    // Curve pool factories
+    function get_fees(address pool) external view returns (uint256, uint256);
+}
+
+/// Fee parameters of a cryptoswap pool. The effective fee moves between `mid_fee`
+/// and `out_fee` depending on how balanced the pool is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CryptoParameters {
+    pub gamma: U256,
+    pub mid_fee: U256,
+    pub out_fee: U256,
+    pub fee_gamma: U256,
+}
+
+/// The amplification and fee parameters of a pool at a given block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PoolParameters {
+    /// The raw `A()` value as returned by the pool.
+    pub a: U256,
+    pub fee: U256,
+    pub crypto: Option<CryptoParameters>,
+}
+
+/// Fetches a pool's amplification and fee parameters.
+/// Which implementation a pool uses is recorded in its `PoolAttributes`.
+#[async_trait]
+pub trait ParameterFetcher<P: Provider + Send + Sync + 'static + ?Sized>: Send + Sync {
+    /// Fetches the parameters at `block_number`, or at the latest block if `None`.
+    async fn fetch(
+        &self,
+        provider: &P,
+        pool: Address,
+        block_number: Option<u64>,
+    ) -> Result<PoolParameters, ArbRsError>;
+}
+
+/// Reads the bare `A()` and `fee()` getters exposed by most stableswap pools.
+#[derive(Debug, Default)]
+pub struct StandardFetcher;
+
+#[async_trait]
+impl<P: Provider + Send + Sync + 'static + ?Sized> ParameterFetcher<P> for StandardFetcher {
+    async fn fetch(
+        &self,
+        provider: &P,
+        pool: Address,
+        block_number: Option<u64>,
+    ) -> Result<PoolParameters, ArbRsError> {
+        let (a, fee) = tokio::join!(
+            call(provider, pool, ACall {}, block_number),
+            call(provider, pool, feeCall {}, block_number)
+        );
+        Ok(PoolParameters {
+            a: a?,
+            fee: fee?,
+            crypto: None,
+        })
+    }
+}
+
+/// Reads the cryptoswap parameters (tricrypto, the two-coin crypto pools and crypto-ng pools).
+#[derive(Debug, Default)]
+pub struct CryptoFetcher;
+
+#[async_trait]
+impl<P: Provider + Send + Sync + 'static + ?Sized> ParameterFetcher<P> for CryptoFetcher {
+    async fn fetch(
+        &self,
+        provider: &P,
+        pool: Address,
+        block_number: Option<u64>,
+    ) -> Result<PoolParameters, ArbRsError> {
+        let (a, gamma, mid_fee, out_fee, fee_gamma, fee) = tokio::join!(
+            call(provider, pool, ACall {}, block_number),
+            call(provider, pool, gammaCall {}, block_number),
+            call(provider, pool, mid_feeCall {}, block_number),
+            call(provider, pool, out_feeCall {}, block_number),
+            call(provider, pool, fee_gammaCall {}, block_number),
+            call(provider, pool, feeCall {}, block_number)
+        );
+        let mid_fee = mid_fee?;
+
+        Ok(PoolParameters {
+            a: a?,
+            // `fee()` is the current dynamic fee; fall back to its lower bound if the pool doesn't expose it.
+            fee: fee.unwrap_or(mid_fee),
+            crypto: Some(CryptoParameters {
+                gamma: gamma?,
+                mid_fee,
+                out_fee: out_fee?,
+                fee_gamma: fee_gamma?,
+            }),
+        })
+    }
+}
+
+/// Reads `A()` from the pool and the fee from its factory's `get_fees(pool)`.
+#[derive(Debug)]
+pub struct FactoryFetcher {
+    pub factory: Address,
+}
+
+#[async_trait]
+impl<P: Provider + Send + Sync + 'static + ?Sized> ParameterFetcher<P> for FactoryFetcher {
+    async fn fetch(
+        &self,
+        provider: &P,
+        pool: Address,
+        block_number: Option<u64>,
+    ) -> Result<PoolParameters, ArbRsError> {
+        let (a, fees) = tokio::join!(
+            call(provider, pool, ACall {}, block_number),
+            call(provider, self.factory, get_feesCall { pool }, block_number)
+        );
+        Ok(PoolParameters {
+            a: a?,
+            fee: fees?._0,
+            crypto: None,
+        })
+    }
+}
+
+/// Picks a fetcher by probing the pool. Cryptoswap pools answer `gamma()`; factory
+/// pools are recognised by their own `factory()` getter or by one of `known_factories`
+/// reporting a fee for them. Anything else falls back to the standard fetcher.
+pub async fn detect_fetcher<P: Provider + Send + Sync + 'static + ?Sized>(
+    provider: &P,
+    pool: Address,
+    known_factories: &[Address],
+) -> (ParameterFetcherType, Option<Address>) {
+    if call(provider, pool, gammaCall {}, None).await.is_ok() {
+        return (ParameterFetcherType::Crypto, None);
+    }
+
+    let own_factory = call(provider, pool, factoryCall {}, None).await.ok();
+    for factory in own_factory
+        .into_iter()
+        .chain(known_factories.iter().copied())
+    {
+        match call(provider, factory, get_feesCall { pool }, None).await {
+            Ok(fees) if !fees._0.is_zero() => {
+                return (ParameterFetcherType::Factory, Some(factory));
+            }
+            _ => {}
+        }
+    }
+
+    (ParameterFetcherType::Standard, None)
+}
+
+/// What `A()` is multiplied by for the `A_PRECISION` scaled amplification the math works
+/// with. Pools exposing `A_precise()` give it as the ratio of the two getters; the legacy
+/// pools in `LEGACY_A_POOLS` take `A()` as is. Any other pool is assumed to scale by
+/// `A_PRECISION`.
+pub async fn detect_a_precision_multiplier<P: Provider + Send + Sync + 'static + ?Sized>(
+    provider: &P,
+    pool: Address,
+) -> U256 {
+    if LEGACY_A_POOLS.contains(&pool) {
+        return U256::ONE;
+    }
+    let (a, a_precise) = tokio::join!(
+        call(provider, pool, ACall {}, None),
+        call(provider, pool, A_preciseCall {}, None)
+    );
+    match (a, a_precise) {
+        (Ok(a), Ok(a_precise)) if !a.is_zero() && !a_precise.is_zero() => {
+            // `A()` rounds `A_precise()` down, so the ratio is rounded to the nearest integer.
+            ((a_precise + a / U256::from(2)) / a).max(U256::ONE)
+        }
+        _ => A_PRECISION,
+    }
+}
+
+async fn call<P: Provider + Send + Sync + 'static + ?Sized, C: SolCall + Send>(
+    provider: &P,
+    to: Address,
+    call: C,
+    block_number: Option<u64>,
+) -> Result<C::Return, ArbRsError> {
+    let request = TransactionRequest::default()
+        .to(to)
+        .input(call.abi_encode().into());
+    let bytes = match block_number {
+        Some(bn) => provider.call(request).block(bn.into()).await?,
+        None => provider.call(request).await?,
+    };
+    Ok(C::abi_decode_returns(&bytes)?)
+}
@@ -1,33 +1,48 @@
 use crate::TokenLike;
-use crate::core::token::Token;
+use crate::core::messaging::{Publisher, PublisherMessage, Subscriber, SubscriberList};
+use crate::core::multicall::{
+    BatchCall, MulticallBatcher, decode_result, decode_timestamp, try_decode_result,
+};
+use crate::core::token::{Token, WETH_ADDRESS};
 use crate::curve::attributes_builder;
 use crate::curve::constants::{BROKEN_POOLS, FEE_DENOMINATOR, PRECISION};
 use crate::curve::math;
-use crate::curve::pool_attributes::{PoolAttributes, SwapStrategyType};
-use crate::curve::pool_overrides::Y_D_VARIANT_GROUP_0;
+use crate::curve::parameter_fetcher::{
+    CryptoFetcher, FactoryFetcher, ParameterFetcher, PoolParameters, StandardFetcher,
+};
+use crate::curve::pool_attributes::{ParameterFetcherType, PoolAttributes, SwapStrategyType};
+use crate::curve::pool_overrides::{Y_D_VARIANT_GROUP_0, Y_VARIANT_GROUP_0, Y_VARIANT_GROUP_1};
 use crate::curve::registry::CurveRegistry;
 use crate::curve::strategies::{
-    AdminFeeStrategy, DefaultStrategy, DynamicFeeStrategy, LendingStrategy, MetapoolStrategy,
-    OracleStrategy, SwapParams, SwapStrategy, TricryptoStrategy, UnscaledStrategy,
+    self, AdminFeeStrategy, DefaultStrategy, DynamicFeeStrategy, StableSwapNgStrategy,
+    SwapParams, SwapStrategy, dynamic_fee_exchange, stableswap_exchange, stableswap_ng_exchange,
 };
-use crate::curve::types::CurvePoolSnapshot;
-use crate::errors::ArbRsError;
+use crate::curve::tricrypto_math;
+use crate::curve::types::{CurvePoolSnapshot, CurveStableswapPoolSimulationResult};
+use crate::curve::underlying::UnderlyingCurveQuoter;
+use crate::errors::{ArbRsError, PoolContext, WithContext};
 use crate::manager::token_manager::TokenManager;
 use crate::math::utils::u256_to_f64;
-use crate::pool::{LiquidityPool, PoolSnapshot};
-use alloy_primitives::{Address, U256, address};
+use crate::pool::cache::{CacheConfig, CacheStats};
+use crate::pool::last_trade::{LastTrade, LastTradeTracker};
+use crate::pool::quoter::{QuotePool, Quoter};
+use crate::pool::{
+    CalibrationBucket, DexKind, LiquidityPool, PoolSnapshot, PriceMatrix, StateUpdate,
+    SwapGasCosts, price_probe_amount, quote_prices_matrix,
+};
+use alloy::transports::RpcError;
+use alloy_primitives::{Address, Bytes, U256, address};
 use alloy_provider::Provider;
-use alloy_rpc_types::{BlockId, TransactionRequest};
+use alloy_rpc_types::{BlockId, Log, TransactionRequest};
 use alloy_sol_types::{SolCall, sol};
 use async_recursion::async_recursion;
 use async_trait::async_trait;
 use futures::future::join_all;
 use std::any::Any;
 use std::collections::HashMap;
-use std::sync::Arc;
+use std::sync::{Arc, Weak};
 use tokio::sync::RwLock;
 
-const WETH_ADDRESS: Address = address!("C02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2");
 const NATIVE_PLACEHOLDERS: &[Address] = &[
     Address::ZERO,
     address!("eeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeee"),
@@ -54,17 +69,30 @@ sol! {
     function future_A_time() external view returns (uint256);
     function redemption_price_snap() external view returns (address);
     function snappedRedemptionPrice() external view returns (uint256);
+    function oracleRelayer() external view returns (address);
+    function redemptionPriceUpdateTime() external view returns (uint256);
+    function latestRoundData() external view returns (uint80 roundId, int256 answer, uint256 startedAt, uint256 updatedAt, uint80 answeredInRound);
     function admin_balances(uint256 i) external view returns (uint256);
     function admin_balances(int128 i) external view returns (uint256);
+    function admin_fee() external view returns (uint256);
     function D() external view returns (uint256);
     function gamma() external view returns (uint256);
     function price_scale(uint256 i) external view returns (uint256);
+    function price_scale() external view returns (uint256);
     function oracle_method() external view returns (uint256);
     function price_oracle(uint256 i) external view returns (uint256);
+    function stored_rates() external view returns (uint256[]);
     function supplyRatePerBlock() external view returns (uint256);
     function accrualBlockNumber() external view returns (uint256);
     function ratio() external view returns (uint256);
     function getExchangeRate() external view returns (uint256);
+    function totalSupply() external view returns (uint256);
+
+    // NG metapools return their two rates as a fixed array, the second being the base
+    // pool's virtual price.
+    interface IStableSwapMetaNG {
+        function stored_rates() external view returns (uint256[2]);
+    }
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -94,6 +122,39 @@ pub struct CurveStableswapPool<P: Provider + Send + Sync + 'static + ?Sized> {
     cached_tricrypto_gamma: RwLock<HashMap<u64, U256>>,
     cached_tricrypto_price_scale: RwLock<HashMap<u64, Vec<U256>>>,
     pub cached_oracle_rates: RwLock<HashMap<u64, Vec<U256>>>,
+    cached_oracle_updated_at: RwLock<HashMap<u64, Option<u64>>>,
+    cached_admin_balances: RwLock<HashMap<u64, Vec<U256>>>,
+    cached_stored_rates: RwLock<HashMap<u64, Vec<U256>>>,
+    /// Blocks each of the caches above holds at most.
+    cache_config: CacheConfig,
+    /// Whether `balances` takes an `int128` index, as the oldest pools' does, rather than a
+    /// `uint256` one. Probed on first use.
+    int128_balances: RwLock<Option<bool>>,
+    /// The same for `admin_balances`.
+    int128_admin_balances: RwLock<Option<bool>>,
+    last_trades: LastTradeTracker,
+    /// Reads a metapool's base pool virtual price with `get_virtual_price()` rather than
+    /// deriving it from the base pool's snapshot.
+    onchain_virtual_price: bool,
+    /// Also reads a cryptoswap pool's stored `D()` each snapshot and warns when the locally
+    /// computed invariant strays from it.
+    onchain_d_check: bool,
+    subscribers: SubscriberList<P>,
+}
+
+#[async_trait]
+impl<P: Provider + Send + Sync + 'static + ?Sized> Publisher<P> for CurveStableswapPool<P> {
+    async fn subscribe(&self, subscriber: Weak<dyn Subscriber<P>>) {
+        self.subscribers.subscribe(subscriber).await;
+    }
+
+    async fn unsubscribe(&self, subscriber_id: usize) {
+        self.subscribers.unsubscribe(subscriber_id).await;
+    }
+
+    async fn notify_subscribers(&self, message: PublisherMessage) {
+        self.subscribers.notify(message).await;
+    }
 }
 
 #[async_trait]
@@ -104,24 +165,83 @@ impl<P: Provider + Send + Sync + 'static + ?Sized> LiquidityPool<P> for CurveSta
     fn get_all_tokens(&self) -> Vec<Arc<Token<P>>> {
         self.tokens.clone()
     }
+    /// The coin balances, a metapool's base pool LP token among them, net of admin fees.
+    fn reserves_summary(&self, snapshot: &PoolSnapshot) -> Vec<(Arc<Token<P>>, U256)> {
+        let PoolSnapshot::Curve(state) = snapshot else {
+            return Vec::new();
+        };
+        self.tokens
+            .iter()
+            .cloned()
+            .zip(state.balances.iter().copied())
+            .collect()
+    }
     fn as_any(&self) -> &dyn Any {
         self
     }
 
-    async fn update_state(&self) -> Result<(), ArbRsError> {
-        let (a_res, fee_res, balances_res, vp_res) = tokio::join!(
-            self.provider.call(
-                TransactionRequest::default()
-                    .to(self.address)
-                    .input(ACall {}.abi_encode().into())
-            ),
-            self.provider.call(
-                TransactionRequest::default()
-                    .to(self.address)
-                    .input(feeCall {}.abi_encode().into())
-            ),
-            self.fetch_balances(),
-            async {
+    fn last_trade_tracker(&self) -> Option<&LastTradeTracker> {
+        Some(&self.last_trades)
+    }
+
+    fn dex_kind(&self) -> Option<DexKind> {
+        Some(DexKind::Curve)
+    }
+
+    fn is_native(&self, token: Address) -> bool {
+        if token == WETH_ADDRESS
+            && let Some((native, _)) = self.attributes.native_and_weth_coins()
+        {
+            return self.attributes.weth_coin == Some(native);
+        }
+        self.tokens
+            .iter()
+            .position(|t| t.address() == token)
+            .is_some_and(|index| self.attributes.is_native_coin(index))
+    }
+
+    /// Swaps between a metapool's coin and a base pool coin go through the base pool too.
+    fn gas_estimate(
+        &self,
+        token_in: &Token<P>,
+        token_out: &Token<P>,
+        _amount_in: U256,
+        _snapshot: &PoolSnapshot,
+        costs: &SwapGasCosts,
+    ) -> u64 {
+        let is_coin = |token: &Token<P>| self.tokens.iter().any(|t| **t == *token);
+        if is_coin(token_in) && is_coin(token_out) {
+            costs.curve
+        } else {
+            costs.curve_underlying
+        }
+    }
+
+    fn calibration_bucket(&self) -> Option<CalibrationBucket> {
+        Some(CalibrationBucket::new(
+            "curve",
+            format!("{:?}", self.attributes.swap_strategy),
+        ))
+    }
+
+    fn record_swap_log(&self, log: &Log) -> bool {
+        if log.address() != self.address {
+            return false;
+        }
+        let coins: Vec<Address> = self.tokens.iter().map(|t| t.address()).collect();
+        match LastTrade::from_curve_exchange(log, &coins) {
+            Some(trade) => {
+                self.last_trades.record(trade);
+                true
+            }
+            None => false,
+        }
+    }
+
+    async fn update_state(&self) -> Result<StateUpdate, ArbRsError> {
+        let block_number = self.provider.get_block_number().await?;
+        let (params_res, balances_res, vp_res) =
+            tokio::join!(self.fetch_parameters(None), self.fetch_balances(), async {
                 if let Some(base_pool) = &self.base_pool {
                     let vp_call = get_virtual_priceCall {};
                     let request = TransactionRequest::default()
@@ -131,15 +251,13 @@ impl<P: Provider + Send + Sync + 'static + ?Sized> LiquidityPool<P> for CurveSta
                 } else {
                     None
                 }
-            }
-        );
+            });
 
-        *self.a.write().await = ACall::abi_decode_returns(&a_res?)?;
-        *self.fee.write().await = feeCall::abi_decode_returns(&fee_res?)?;
-
-        let live_balances = balances_res?;
+        let context = || self.context(Some(block_number));
+        let params = params_res.with_ctx(context)?;
+        let live_balances = balances_res.with_ctx(context)?;
         let final_balances = if self.attributes.swap_strategy == SwapStrategyType::AdminFee {
-            let admin_balances = self.get_admin_balances().await?;
+            let admin_balances = self.get_admin_balances().await.with_ctx(context)?;
             live_balances
                 .iter()
                 .zip(admin_balances.iter())
@@ -148,155 +266,300 @@ impl<P: Provider + Send + Sync + 'static + ?Sized> LiquidityPool<P> for CurveSta
         } else {
             live_balances
         };
-        *self.balances.write().await = final_balances;
+        let virtual_price = match vp_res {
+            Some(res) => Some(get_virtual_priceCall::abi_decode_returns(&res?)?),
+            None => None,
+        };
+
+        // Only a fetched virtual price replaces the cached one.
+        let changed = *self.a.read().await != params.a
+            || *self.fee.read().await != params.fee
+            || *self.balances.read().await != final_balances
+            || (virtual_price.is_some()
+                && *self.cached_virtual_price.read().await != virtual_price);
+        if !changed {
+            return Ok(StateUpdate::Unchanged);
+        }
 
-        if let Some(res) = vp_res {
-            *self.cached_virtual_price.write().await =
-                Some(get_virtual_priceCall::abi_decode_returns(&res?)?);
+        *self.a.write().await = params.a;
+        *self.fee.write().await = params.fee;
+        *self.balances.write().await = final_balances.clone();
+        if virtual_price.is_some() {
+            *self.cached_virtual_price.write().await = virtual_price;
         }
-        Ok(())
+        self.notify_subscribers(PublisherMessage::CurveStateUpdate {
+            pool: self.address,
+            block_number,
+            balances: final_balances,
+            a: params.a,
+            fee: params.fee,
+        })
+        .await;
+        Ok(StateUpdate::Updated {
+            block: block_number,
+        })
     }
 
-    async fn get_snapshot(&self, block_number: Option<u64>) -> Result<PoolSnapshot, ArbRsError> {
-        let block_num = if let Some(bn) = block_number {
-            bn
-        } else {
-            self.provider.get_block_number().await?
-        };
+    /// Drops the block-keyed caches, here and in the base pool. The live balances are read
+    /// again on every `update_state`, so they're left alone.
+    async fn invalidate_from(&self, block_number: u64) {
+        let kept = |block: &u64| *block < block_number;
+        self.cached_scaled_redemption_price
+            .write()
+            .await
+            .retain(|block, _| kept(block));
+        self.cached_tricrypto_d
+            .write()
+            .await
+            .retain(|block, _| kept(block));
+        self.cached_tricrypto_gamma
+            .write()
+            .await
+            .retain(|block, _| kept(block));
+        self.cached_tricrypto_price_scale
+            .write()
+            .await
+            .retain(|block, _| kept(block));
+        self.cached_oracle_rates
+            .write()
+            .await
+            .retain(|block, _| kept(block));
+        self.cached_oracle_updated_at
+            .write()
+            .await
+            .retain(|block, _| kept(block));
+        self.cached_admin_balances
+            .write()
+            .await
+            .retain(|block, _| kept(block));
+        self.cached_stored_rates
+            .write()
+            .await
+            .retain(|block, _| kept(block));
+        if let Some(base_pool) = &self.base_pool {
+            base_pool.invalidate_from(block_number).await;
+        }
+    }
 
-        let block_header = self
-            .provider
-            .get_block_by_number(block_num.into())
-            .await?
-            .ok_or_else(|| ArbRsError::ProviderError("Block not found".to_string()))?
-            .header;
-
-        let (
-            a_res,
-            fee_res,
-            balances_res,
-            vp_res,
-            rates_res,
-            tricrypto_res,
-            admin_balances_res,
-            scaled_redemption_price_res,
-            base_lp_supply_res,
-        ) = tokio::join!(
-            self.a_precise(block_header.timestamp),
-            self.provider
-                .call(
-                    TransactionRequest::default()
-                        .to(self.address)
-                        .input(feeCall {}.abi_encode().into())
-                )
-                .block(block_num.into()),
-            async {
-                if self.attributes.swap_strategy == SwapStrategyType::AdminFee {
-                    self.fetch_balances_by_balance_of(Some(block_num)).await
-                } else {
-                    self.fetch_balances_for_block(Some(block_num)).await
-                }
-            },
-            async {
-                if let Some(base_pool) = &self.base_pool {
-                    let request = TransactionRequest::default()
-                        .to(base_pool.address)
-                        .input(get_virtual_priceCall {}.abi_encode().into());
-                    Some(self.provider.call(request).block(block_num.into()).await)
-                } else {
-                    None
-                }
-            },
-            self.get_rates_for_block(block_num),
-            async {
-                if self.attributes.swap_strategy == SwapStrategyType::Tricrypto {
-                    Some(tokio::join!(
-                        self.get_tricrypto_d(block_num),
-                        self.get_tricrypto_gamma(block_num),
-                        self.get_tricrypto_price_scale(block_num)
-                    ))
-                } else {
-                    None
-                }
-            },
-            async {
-                if self.attributes.swap_strategy == SwapStrategyType::AdminFee {
-                    Some(self.get_admin_balances().await)
-                } else {
-                    None
-                }
-            },
-            async {
-                if self.address == RETH_ETH_METAPOOL {
-                    Some(self.get_scaled_redemption_price(block_num).await)
-                } else {
-                    None
-                }
-            },
-            async {
-                if let Some(base_pool) = &self.base_pool {
-                    Some(base_pool.lp_token.get_total_supply(Some(block_num)).await)
-                } else {
-                    None
-                }
-            }
-        );
+    /// Drops the block-keyed caches before `block`, here and in the base pool.
+    async fn discard_states_before_block(&self, block: u64) {
+        let kept = |cached: &u64| *cached >= block;
+        self.cached_scaled_redemption_price
+            .write()
+            .await
+            .retain(|cached, _| kept(cached));
+        self.cached_tricrypto_d
+            .write()
+            .await
+            .retain(|cached, _| kept(cached));
+        self.cached_tricrypto_gamma
+            .write()
+            .await
+            .retain(|cached, _| kept(cached));
+        self.cached_tricrypto_price_scale
+            .write()
+            .await
+            .retain(|cached, _| kept(cached));
+        self.cached_oracle_rates
+            .write()
+            .await
+            .retain(|cached, _| kept(cached));
+        self.cached_oracle_updated_at
+            .write()
+            .await
+            .retain(|cached, _| kept(cached));
+        self.cached_admin_balances
+            .write()
+            .await
+            .retain(|cached, _| kept(cached));
+        self.cached_stored_rates
+            .write()
+            .await
+            .retain(|cached, _| kept(cached));
+        if let Some(base_pool) = &self.base_pool {
+            base_pool.discard_states_before_block(block).await;
+        }
+    }
 
-        let balances = balances_res?;
+    /// Entries of the block-keyed caches, the base pool's included.
+    async fn cache_stats(&self) -> CacheStats {
+        let mut stats = CacheStats::of(self.cached_scaled_redemption_price.read().await.keys())
+            .merge(CacheStats::of(self.cached_tricrypto_d.read().await.keys()))
+            .merge(CacheStats::of(self.cached_tricrypto_gamma.read().await.keys()))
+            .merge(CacheStats::of(self.cached_tricrypto_price_scale.read().await.keys()))
+            .merge(CacheStats::of(self.cached_oracle_rates.read().await.keys()))
+            .merge(CacheStats::of(self.cached_oracle_updated_at.read().await.keys()))
+            .merge(CacheStats::of(self.cached_admin_balances.read().await.keys()))
+            .merge(CacheStats::of(self.cached_stored_rates.read().await.keys()));
+        if let Some(base_pool) = &self.base_pool {
+            stats = stats.merge(base_pool.cache_stats().await);
+        }
+        stats
+    }
 
-        let admin_balances = match admin_balances_res {
-            Some(Ok(bals)) => Some(bals),
-            Some(Err(e)) => return Err(e),
-            None => None,
-        };
+    async fn get_snapshot(&self, block_number: Option<u64>) -> Result<PoolSnapshot, ArbRsError> {
+        crate::metrics::observe_snapshot(Some(DexKind::Curve), async {
+            let block_num = if let Some(bn) = block_number {
+                bn
+            } else {
+                self.provider.get_block_number().await?
+            };
 
-        let final_balances = if let Some(admin_bals) = &admin_balances {
-            balances
-                .iter()
-                .zip(admin_bals.iter())
-                .map(|(l, a)| l.saturating_sub(*a))
-                .collect()
-        } else {
-            balances
-        };
+            let block_header = self
+                .provider
+                .get_block_by_number(block_num.into())
+                .await?
+                .ok_or_else(|| ArbRsError::ProviderError("Block not found".to_string()))?
+                .header;
+
+            let (
+                a_res,
+                params_res,
+                balances_res,
+                base_pool_res,
+                rates_res,
+                tricrypto_res,
+                admin_balances_res,
+                admin_fee_res,
+                scaled_redemption_price_res,
+                oracle_updated_at_res,
+            ) = tokio::join!(
+                self.a_precise(block_header.timestamp),
+                self.fetch_parameters(Some(block_num)),
+                async {
+                    if self.attributes.swap_strategy == SwapStrategyType::AdminFee {
+                        self.fetch_balances_by_balance_of(Some(block_num)).await
+                    } else {
+                        self.fetch_balances_for_block(Some(block_num)).await
+                    }
+                },
+                async {
+                    match &self.base_pool {
+                        Some(base_pool) => Some(self.base_pool_state(base_pool, block_num).await),
+                        None => None,
+                    }
+                },
+                self.get_rates_for_block(block_num),
+                async {
+                    if self.attributes.swap_strategy.is_cryptoswap() {
+                        Some(tokio::join!(
+                            self.get_tricrypto_price_scale(block_num),
+                            async {
+                                if self.onchain_d_check {
+                                    Some(self.get_tricrypto_d(block_num).await)
+                                } else {
+                                    None
+                                }
+                            }
+                        ))
+                    } else {
+                        None
+                    }
+                },
+                async {
+                    if self.attributes.swap_strategy == SwapStrategyType::AdminFee {
+                        Some(self.get_admin_balances_at(block_num).await)
+                    } else {
+                        None
+                    }
+                },
+                self.get_admin_fee(block_num),
+                async {
+                    if self.address == RETH_ETH_METAPOOL {
+                        Some(self.get_scaled_redemption_price(block_num).await)
+                    } else {
+                        None
+                    }
+                },
+                self.get_oracle_updated_at(block_num)
+            );
+
+            let balances = balances_res?;
+
+            let admin_balances = match admin_balances_res {
+                Some(Ok(bals)) => Some(bals),
+                Some(Err(e)) => return Err(e),
+                None => None,
+            };
 
-        let (tricrypto_d, tricrypto_gamma, tricrypto_price_scale) =
-            if let Some(results) = tricrypto_res {
-                (Some(results.0?), Some(results.1?), Some(results.2?))
+            let final_balances = if let Some(admin_bals) = &admin_balances {
+                balances
+                    .iter()
+                    .zip(admin_bals.iter())
+                    .map(|(l, a)| l.saturating_sub(*a))
+                    .collect()
             } else {
-                (None, None, None)
+                balances
             };
 
-        let scaled_redemption_price = match scaled_redemption_price_res {
-            Some(Ok(price)) => Some(price),
-            Some(Err(e)) => return Err(e),
-            None => None,
-        };
+            let params = params_res?;
+            let (tricrypto_price_scale, onchain_d) = match tricrypto_res {
+                Some((price_scale, onchain_d)) => (Some(price_scale?), onchain_d.transpose()?),
+                None => (None, None),
+            };
+            let tricrypto_gamma = match params.crypto {
+                Some(crypto) => Some(crypto.gamma),
+                None if tricrypto_price_scale.is_some() => {
+                    Some(self.get_tricrypto_gamma(block_num).await?)
+                }
+                None => None,
+            };
+            let tricrypto_d = match (&tricrypto_price_scale, tricrypto_gamma) {
+                (Some(price_scale), Some(gamma)) => Some(
+                    self.crypto_d(
+                        block_num,
+                        params.a,
+                        gamma,
+                        &final_balances,
+                        price_scale,
+                        onchain_d,
+                    )
+                    .await?,
+                ),
+                _ => None,
+            };
 
-        let snapshot = CurvePoolSnapshot {
-            balances: final_balances,
-            a: a_res?,
-            fee: feeCall::abi_decode_returns(&fee_res?)?,
-            block_timestamp: block_header.timestamp,
-            base_pool_virtual_price: if let Some(res) = vp_res {
-                Some(get_virtual_priceCall::abi_decode_returns(&res?)?)
-            } else {
-                None
-            },
-            base_pool_lp_total_supply: if let Some(res) = base_lp_supply_res {
-                Some(res?)
-            } else {
-                None
-            },
-            rates: rates_res?,
-            admin_balances,
-            tricrypto_d,
-            tricrypto_gamma,
-            tricrypto_price_scale,
-            scaled_redemption_price,
-        };
+            let scaled_redemption_price = match scaled_redemption_price_res {
+                Some(Ok(price)) => Some(price),
+                Some(Err(e)) => return Err(e),
+                None => None,
+            };
+
+            let base_pool_state = base_pool_res.transpose()?;
+
+            let snapshot = CurvePoolSnapshot {
+                balances: final_balances,
+                // Cryptoswap A is already scaled and never ramps through `initial_A`/`future_A`.
+                a: if params.crypto.is_some() {
+                    params.a
+                } else {
+                    a_res?
+                },
+                fee: params.fee,
+                admin_fee: admin_fee_res?,
+                block_timestamp: block_header.timestamp,
+                base_pool_virtual_price: base_pool_state
+                    .as_ref()
+                    .map(|(virtual_price, _, _)| *virtual_price),
+                base_pool_lp_total_supply: base_pool_state.as_ref().map(|(_, supply, _)| *supply),
+                rates: rates_res?,
+                admin_balances,
+                tricrypto_d,
+                tricrypto_gamma,
+                tricrypto_price_scale,
+                mid_fee: params.crypto.map(|c| c.mid_fee),
+                out_fee: params.crypto.map(|c| c.out_fee),
+                fee_gamma: params.crypto.map(|c| c.fee_gamma),
+                scaled_redemption_price,
+                oracle_updated_at: oracle_updated_at_res?,
+                base_pool_snapshot: base_pool_state
+                    .map(|(_, _, base_snapshot)| Box::new(base_snapshot)),
+            };
 
-        Ok(PoolSnapshot::Curve(snapshot))
+            Ok(PoolSnapshot::Curve(snapshot))
+        })
+        .await
+        .with_ctx(|| self.context(block_number))
     }
 
     fn calculate_tokens_out(
@@ -315,35 +578,8 @@ impl<P: Provider + Send + Sync + 'static + ?Sized> LiquidityPool<P> for CurveSta
             }
         };
 
-        let i = self
-            .tokens
-            .iter()
-            .position(|t| **t == *token_in)
-            .ok_or_else(|| ArbRsError::CalculationError("Token In not found".to_string()))?;
-        let j = self
-            .tokens
-            .iter()
-            .position(|t| **t == *token_out)
-            .ok_or_else(|| ArbRsError::CalculationError("Token Out not found".to_string()))?;
-
-        let params = SwapParams {
-            i,
-            j,
-            dx: amount_in,
-            pool: self,
-            snapshot: curve_snapshot,
-        };
-
-        match self.attributes.swap_strategy {
-            SwapStrategyType::Default => DefaultStrategy::default().calculate_dy(&params),
-            SwapStrategyType::Metapool => MetapoolStrategy::default().calculate_dy(&params),
-            SwapStrategyType::Lending => LendingStrategy::default().calculate_dy(&params),
-            SwapStrategyType::Unscaled => UnscaledStrategy::default().calculate_dy(&params),
-            SwapStrategyType::DynamicFee => DynamicFeeStrategy::default().calculate_dy(&params),
-            SwapStrategyType::Tricrypto => TricryptoStrategy::default().calculate_dy(&params),
-            SwapStrategyType::Oracle => OracleStrategy::default().calculate_dy(&params),
-            SwapStrategyType::AdminFee => AdminFeeStrategy::default().calculate_dy(&params),
-        }
+        let params = self.swap_params(token_in, token_out, amount_in, curve_snapshot)?;
+        strategies::calculate_dy(&params)
     }
 
     fn calculate_tokens_in(
@@ -362,28 +598,17 @@ impl<P: Provider + Send + Sync + 'static + ?Sized> LiquidityPool<P> for CurveSta
             }
         };
 
-        let i = self
-            .tokens
-            .iter()
-            .position(|t| **t == *token_in)
-            .ok_or_else(|| ArbRsError::CalculationError("Token In not found".to_string()))?;
-        let j = self
-            .tokens
-            .iter()
-            .position(|t| **t == *token_out)
-            .ok_or_else(|| ArbRsError::CalculationError("Token Out not found".to_string()))?;
+        let params = self.swap_params(token_in, token_out, U256::ZERO, curve_snapshot)?;
+        strategies::calculate_dx(&params, amount_out)
+    }
 
-        let params = SwapParams {
-            i,
-            j,
-            dx: U256::ZERO,
-            pool: self,
-            snapshot: curve_snapshot,
+    fn to_quoter(&self, snapshot: &PoolSnapshot) -> Result<QuotePool, ArbRsError> {
+        let PoolSnapshot::Curve(curve_snapshot) = snapshot else {
+            return Err(ArbRsError::CalculationError(
+                "Invalid snapshot type for Curve pool".to_string(),
+            ));
         };
-
-        match self.attributes.swap_strategy {
-            _ => DefaultStrategy::default().calculate_dx(&params, amount_out),
-        }
+        Ok(QuotePool::Curve(self.quoter(curve_snapshot)))
     }
 
     async fn nominal_price(
@@ -402,7 +627,7 @@ impl<P: Provider + Send + Sync + 'static + ?Sized> LiquidityPool<P> for CurveSta
         token_out: &Token<P>,
     ) -> Result<f64, ArbRsError> {
         let snapshot = self.get_snapshot(None).await?;
-        let amount_in = U256::from(1000);
+        let amount_in = price_probe_amount(token_in);
         let amount_out = self.calculate_tokens_out(token_in, token_out, amount_in, &snapshot)?;
 
         if amount_in.is_zero() || amount_out.is_zero() {
@@ -421,6 +646,54 @@ impl<P: Provider + Send + Sync + 'static + ?Sized> LiquidityPool<P> for CurveSta
     ) -> Result<f64, ArbRsError> {
         self.absolute_price(token_in, token_out).await
     }
+
+    /// Plain stableswap pools are priced from the invariant's marginal rate, before fees, with
+    /// one `xp`/`D` pass for every pair. Other strategies are quoted through their swap math.
+    fn prices_matrix(&self, snapshot: &PoolSnapshot) -> PriceMatrix {
+        let PoolSnapshot::Curve(curve_snapshot) = snapshot else {
+            return PriceMatrix::new();
+        };
+        let d_variant = match self.attributes.swap_strategy {
+            SwapStrategyType::Default | SwapStrategyType::DynamicFee | SwapStrategyType::Oracle => {
+                SwapStrategy::d_variant_for_math(&DefaultStrategy, &self.attributes)
+            }
+            SwapStrategyType::AdminFee => {
+                SwapStrategy::d_variant_for_math(&AdminFeeStrategy, &self.attributes)
+            }
+            SwapStrategyType::StableSwapNG => {
+                SwapStrategy::d_variant_for_math(&StableSwapNgStrategy, &self.attributes)
+            }
+            _ => return quote_prices_matrix(self, snapshot),
+        };
+
+        let rates = &curve_snapshot.rates;
+        let weights = match math::xp(rates, &curve_snapshot.balances).and_then(|xp| {
+            math::marginal_weights(
+                &xp,
+                curve_snapshot.a,
+                self.attributes.n_coins,
+                d_variant,
+                Y_VARIANT_GROUP_0.contains(&self.address),
+                Y_VARIANT_GROUP_1.contains(&self.address),
+            )
+        }) {
+            Ok(weights) if weights.len() == self.tokens.len() => weights,
+            _ => return PriceMatrix::new(),
+        };
+
+        let mut prices = PriceMatrix::new();
+        for (i, token_in) in self.tokens.iter().enumerate() {
+            for (j, token_out) in self.tokens.iter().enumerate() {
+                if i == j || rates[j].is_zero() {
+                    continue;
+                }
+                // xp_k is balance_k * rate_k / 1e18, so raw units convert through the rates.
+                let price = weights[i] / weights[j] * u256_to_f64(rates[i]) / u256_to_f64(rates[j]);
+                prices.insert((token_in.address(), token_out.address()), price);
+            }
+        }
+        prices
+    }
 }
 
 impl<P: Provider + Send + Sync + 'static + ?Sized> CurveStableswapPool<P> {
@@ -430,16 +703,32 @@ impl<P: Provider + Send + Sync + 'static + ?Sized> CurveStableswapPool<P> {
         provider: Arc<P>,
         token_manager: Arc<TokenManager<P>>,
         registry: &CurveRegistry<P>,
-        attributes: PoolAttributes,
+        mut attributes: PoolAttributes,
     ) -> Result<Self, ArbRsError> {
         if BROKEN_POOLS.contains(&address) {
             return Err(ArbRsError::BrokenPool);
         }
 
-        let tokens = Self::fetch_coins(&address, provider.clone(), &token_manager).await?;
-        let lp_token = token_manager
-            .get_token(registry.get_lp_token(address).await?)
-            .await?;
+        let swap_strategy = attributes.swap_strategy;
+        let context = move || strategies::pool_context(address, swap_strategy);
+        let coins = Self::fetch_coin_addresses(&address, provider.as_ref())
+            .await
+            .with_ctx(context)?;
+        attributes.is_native = coins
+            .iter()
+            .map(|coin| NATIVE_PLACEHOLDERS.contains(coin))
+            .collect();
+        attributes.raw_coin_addresses = coins.clone();
+        if let Some((native, weth)) = attributes.native_and_weth_coins()
+            && attributes.weth_coin.is_none()
+        {
+            tracing::warn!(
+                "Curve pool {address} lists native ether (coin {native}) and WETH (coin {weth}); WETH swaps through it fail until one is picked with with_weth_coin"
+            );
+        }
+        let tokens = token_manager.get_token_list(&wrap_native(coins)).await?;
+        let lp_token_address = registry.get_lp_token(address).await?;
+        let lp_token = token_manager.get_token(lp_token_address).await?;
 
         let mut base_pool = None;
         if let Some(base_pool_address) = attributes.base_pool_address {
@@ -465,7 +754,9 @@ impl<P: Provider + Send + Sync + 'static + ?Sized> CurveStableswapPool<P> {
             base_pool = Some(Arc::new(bp_instance));
         }
 
-        let a_ramping_state = Self::fetch_a_ramping_state(address, provider.clone()).await?;
+        let a_ramping_state = Self::fetch_a_ramping_state(address, provider.clone())
+            .await
+            .with_ctx(context)?;
 
         let underlying_tokens = if let Some(bp) = &base_pool {
             let mut underlying = vec![tokens[0].clone()];
@@ -494,17 +785,397 @@ impl<P: Provider + Send + Sync + 'static + ?Sized> CurveStableswapPool<P> {
             cached_tricrypto_gamma: RwLock::new(HashMap::new()),
             cached_tricrypto_price_scale: RwLock::new(HashMap::new()),
             cached_oracle_rates: RwLock::new(HashMap::new()),
+            cached_oracle_updated_at: RwLock::new(HashMap::new()),
+            cached_admin_balances: RwLock::new(HashMap::new()),
+            cached_stored_rates: RwLock::new(HashMap::new()),
+            cache_config: CacheConfig::default(),
+            int128_balances: RwLock::new(None),
+            int128_admin_balances: RwLock::new(None),
+            last_trades: LastTradeTracker::default(),
+            onchain_virtual_price: false,
+            onchain_d_check: false,
+            subscribers: SubscriberList::default(),
         };
         pool.update_state().await?;
         Ok(pool)
     }
 
+    /// Builds a plain pool from already known coins and attributes, without any network
+    /// calls. `A`, the fee and balances stay zero until the first `update_state`.
+    pub fn from_parts(
+        address: Address,
+        lp_token: Arc<Token<P>>,
+        tokens: Vec<Arc<Token<P>>>,
+        provider: Arc<P>,
+        token_manager: Arc<TokenManager<P>>,
+        attributes: PoolAttributes,
+    ) -> Self {
+        Self {
+            address,
+            lp_token,
+            underlying_tokens: tokens.clone(),
+            tokens,
+            provider,
+            token_manager,
+            attributes,
+            base_pool: None,
+            a_ramping_state: None,
+            a: RwLock::new(U256::ZERO),
+            fee: RwLock::new(U256::ZERO),
+            balances: RwLock::new(Vec::new()),
+            cached_virtual_price: RwLock::new(None),
+            cached_scaled_redemption_price: RwLock::new(HashMap::new()),
+            cached_tricrypto_d: RwLock::new(HashMap::new()),
+            cached_tricrypto_gamma: RwLock::new(HashMap::new()),
+            cached_tricrypto_price_scale: RwLock::new(HashMap::new()),
+            cached_oracle_rates: RwLock::new(HashMap::new()),
+            cached_oracle_updated_at: RwLock::new(HashMap::new()),
+            cached_admin_balances: RwLock::new(HashMap::new()),
+            cached_stored_rates: RwLock::new(HashMap::new()),
+            cache_config: CacheConfig::default(),
+            int128_balances: RwLock::new(None),
+            int128_admin_balances: RwLock::new(None),
+            last_trades: LastTradeTracker::default(),
+            onchain_virtual_price: false,
+            onchain_d_check: false,
+            subscribers: SubscriberList::default(),
+        }
+    }
+
+    /// Rebuilds a pool stored with its LP token and the attributes `new` completed, the raw
+    /// coins among them. Only `A` and its ramp are read, in one multicall; the fee and
+    /// balances stay zero until the first `update_state`. `base_pool` is a metapool's base
+    /// pool, rebuilt the same way.
+    pub async fn from_record(
+        address: Address,
+        lp_token: Address,
+        base_pool: Option<Arc<Self>>,
+        provider: Arc<P>,
+        token_manager: Arc<TokenManager<P>>,
+        attributes: PoolAttributes,
+    ) -> Result<Self, ArbRsError> {
+        if BROKEN_POOLS.contains(&address) {
+            return Err(ArbRsError::BrokenPool);
+        }
+        if attributes.raw_coin_addresses.is_empty() {
+            return Err(ArbRsError::InvalidPool(
+                address,
+                "attributes stored without coins".to_string(),
+            ));
+        }
+        if attributes.base_pool_address != base_pool.as_ref().map(|bp| bp.address) {
+            return Err(ArbRsError::InvalidPool(
+                address,
+                "base pool doesn't match the stored attributes".to_string(),
+            ));
+        }
+
+        let tokens = token_manager
+            .get_token_list(&wrap_native(attributes.raw_coin_addresses.clone()))
+            .await?;
+        let lp_token = token_manager.get_token(lp_token).await?;
+        let (a, a_ramping_state) = Self::fetch_a_state(address, provider.clone()).await?;
+
+        let mut pool = Self::from_parts(
+            address,
+            lp_token,
+            tokens,
+            provider,
+            token_manager,
+            attributes,
+        );
+        if let Some(bp) = &base_pool {
+            pool.underlying_tokens = vec![pool.tokens[0].clone()];
+            pool.underlying_tokens.extend(bp.tokens.clone());
+        }
+        pool.base_pool = base_pool;
+        pool.a_ramping_state = a_ramping_state;
+        pool.a = RwLock::new(a);
+        Ok(pool)
+    }
+
+    /// Swaps WETH as coin `index` of a pool listing both native ether and WETH: the native
+    /// coin, unwrapping ahead of the pool, or the WETH one.
+    pub fn with_weth_coin(mut self, index: usize) -> Self {
+        self.attributes.weth_coin = Some(index);
+        self
+    }
+
+    /// Reads the base pool's virtual price from the chain instead of deriving it, for base
+    /// pools whose `get_virtual_price()` strays from their own balances.
+    pub fn with_onchain_virtual_price(mut self, onchain: bool) -> Self {
+        self.onchain_virtual_price = onchain;
+        self
+    }
+
+    /// Bounds each per-block cache to `cache_config.max_blocks` blocks instead of the
+    /// default. A metapool's base pool keeps its own.
+    pub fn with_cache_config(mut self, cache_config: CacheConfig) -> Self {
+        self.cache_config = cache_config;
+        self
+    }
+
+    /// Cross-checks a cryptoswap pool's locally computed `D` against its stored `D()` each
+    /// snapshot, logging any mismatch. Costs a call per snapshot; meant for debugging.
+    pub fn with_onchain_d_check(mut self, check: bool) -> Self {
+        self.onchain_d_check = check;
+        self
+    }
+
+    /// The base pool's virtual price, LP supply and snapshot at `block_number`. The virtual
+    /// price is computed from the snapshot, saving a `get_virtual_price()` call per block,
+    /// unless the on-chain one is asked for.
+    async fn base_pool_state(
+        &self,
+        base_pool: &CurveStableswapPool<P>,
+        block_number: u64,
+    ) -> Result<(U256, U256, CurvePoolSnapshot), ArbRsError> {
+        let (snapshot, supply, onchain_virtual_price) = tokio::join!(
+            base_pool.get_snapshot(Some(block_number)),
+            base_pool.lp_token.get_total_supply(Some(block_number)),
+            async {
+                if !self.onchain_virtual_price {
+                    return None;
+                }
+                let request = TransactionRequest::default()
+                    .to(base_pool.address)
+                    .input(get_virtual_priceCall {}.abi_encode().into());
+                Some(self.provider.call(request).block(block_number.into()).await)
+            }
+        );
+        let PoolSnapshot::Curve(snapshot) = snapshot? else {
+            return Err(ArbRsError::CalculationError(
+                "Expected Curve snapshot for base pool".into(),
+            ));
+        };
+        let supply = supply?;
+        let virtual_price = match onchain_virtual_price {
+            Some(result) => get_virtual_priceCall::abi_decode_returns(&result?)?,
+            None => math::virtual_price_from_snapshot(
+                &snapshot,
+                supply,
+                base_pool.attributes.d_variant,
+            )?,
+        };
+        Ok((virtual_price, supply, snapshot))
+    }
+
+    /// Calls `decode_snapshot` needs, for pools whose snapshot can be read in one batch.
+    /// `None` for pools needing dependent or pool-specific calls (lending and oracle rates,
+    /// cryptoswap parameters, admin balances), which keep using `get_snapshot`, and for
+    /// metapools over such a base pool. A metapool's calls end with its base pool's, whose
+    /// virtual price is derived from them unless the on-chain one is asked for.
+    pub fn snapshot_calls(&self) -> Option<Vec<BatchCall>> {
+        let batchable = matches!(
+            self.attributes.swap_strategy,
+            SwapStrategyType::Default
+                | SwapStrategyType::Metapool
+                | SwapStrategyType::Unscaled
+                | SwapStrategyType::DynamicFee
+        ) && self.attributes.parameter_fetcher == ParameterFetcherType::Standard
+            && self.address != RETH_ETH_METAPOOL;
+        if !batchable {
+            return None;
+        }
+
+        let mut calls = vec![BatchCall::new(self.address, feeCall {})];
+        // Both index types are asked for, since pools implement one or the other.
+        for i in 0..self.attributes.n_coins {
+            calls.push(BatchCall::new(
+                self.address,
+                balances_1Call { i: i as i128 },
+            ));
+            calls.push(BatchCall::new(
+                self.address,
+                balances_0Call { i: U256::from(i) },
+            ));
+        }
+        calls.push(BatchCall::new(self.address, admin_feeCall {}));
+        if let Some(base_pool) = &self.base_pool {
+            calls.push(BatchCall::new(
+                base_pool.lp_token.address(),
+                totalSupplyCall {},
+            ));
+            if self.onchain_virtual_price {
+                calls.push(BatchCall::new(base_pool.address, get_virtual_priceCall {}));
+            }
+            calls.extend(base_pool.snapshot_calls()?);
+        }
+        Some(calls)
+    }
+
+    /// How many calls `snapshot_calls` makes, for a pool it batches.
+    fn snapshot_call_count(&self) -> usize {
+        let base_calls = self.base_pool.as_ref().map_or(0, |base_pool| {
+            1 + usize::from(self.onchain_virtual_price) + base_pool.snapshot_call_count()
+        });
+        2 + 2 * self.attributes.n_coins + base_calls
+    }
+
+    /// Builds the snapshot `get_snapshot` would return from the results of `snapshot_calls`,
+    /// in order, read at a block with `block_timestamp`.
+    pub async fn decode_snapshot(
+        &self,
+        results: &[Option<Bytes>],
+        block_timestamp: u64,
+    ) -> Result<CurvePoolSnapshot, ArbRsError> {
+        let n_coins = self.attributes.n_coins;
+        if results.len() != self.snapshot_call_count() {
+            return Err(ArbRsError::CalculationError(format!(
+                "Expected {} batched results for Curve pool {}, got {}",
+                self.snapshot_call_count(),
+                self.address,
+                results.len()
+            )));
+        }
+
+        let fee = decode_result::<feeCall>(&results[0])?;
+        let balances = results[1..1 + 2 * n_coins]
+            .chunks(2)
+            .map(|pair| {
+                try_decode_result::<balances_1Call>(&pair[0])
+                    .or_else(|| try_decode_result::<balances_0Call>(&pair[1]))
+                    .ok_or_else(|| {
+                        ArbRsError::CalculationError(format!(
+                            "Balances of Curve pool {} could not be read",
+                            self.address
+                        ))
+                    })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let admin_fee = try_decode_result::<admin_feeCall>(&results[1 + 2 * n_coins]);
+        let (base_pool_virtual_price, base_pool_lp_total_supply, base_pool_snapshot) =
+            match &self.base_pool {
+                Some(base_pool) => {
+                    let base_results = &results[2 + 2 * n_coins..];
+                    let (virtual_price, supply, base_snapshot) = self
+                        .decode_base_pool_state(base_pool, base_results, block_timestamp)
+                        .await?;
+                    (
+                        Some(virtual_price),
+                        Some(supply),
+                        Some(Box::new(base_snapshot)),
+                    )
+                }
+                None => (None, None, None),
+            };
+
+        Ok(CurvePoolSnapshot {
+            balances,
+            a: self.a_precise(block_timestamp).await?,
+            fee,
+            admin_fee,
+            block_timestamp,
+            base_pool_virtual_price,
+            base_pool_lp_total_supply,
+            rates: self.attributes.rates.clone(),
+            admin_balances: None,
+            tricrypto_d: None,
+            tricrypto_gamma: None,
+            tricrypto_price_scale: None,
+            mid_fee: None,
+            out_fee: None,
+            fee_gamma: None,
+            scaled_redemption_price: None,
+            oracle_updated_at: None,
+            base_pool_snapshot,
+        })
+    }
+
+    /// `base_pool_state` from the base pool's share of `snapshot_calls` results: its LP
+    /// supply, the on-chain virtual price if asked for, then its own snapshot calls.
+    async fn decode_base_pool_state(
+        &self,
+        base_pool: &CurveStableswapPool<P>,
+        results: &[Option<Bytes>],
+        block_timestamp: u64,
+    ) -> Result<(U256, U256, CurvePoolSnapshot), ArbRsError> {
+        let supply = decode_result::<totalSupplyCall>(&results[0])?;
+        let snapshot_results = &results[1 + usize::from(self.onchain_virtual_price)..];
+        let snapshot =
+            Box::pin(base_pool.decode_snapshot(snapshot_results, block_timestamp)).await?;
+        let virtual_price = if self.onchain_virtual_price {
+            decode_result::<get_virtual_priceCall>(&results[1])?
+        } else {
+            math::virtual_price_from_snapshot(&snapshot, supply, base_pool.attributes.d_variant)?
+        };
+        Ok((virtual_price, supply, snapshot))
+    }
+
+    /// The pool's swap math on `snapshot`, detached from the pool.
+    pub(crate) fn quoter(&self, snapshot: &CurvePoolSnapshot) -> CurveQuoter {
+        CurveQuoter {
+            address: self.address,
+            attributes: Arc::new(self.attributes.clone()),
+            coins: self.tokens.iter().map(|token| token.address()).collect(),
+            snapshot: Arc::new(snapshot.clone()),
+        }
+    }
+
+    /// Index of `token` among the pool's coins, `None` where [`swap_index`](Self::swap_index)
+    /// finds none or fails.
+    pub fn coin_index(&self, token: &Token<P>) -> Option<usize> {
+        self.swap_index(token).ok().flatten()
+    }
+
+    /// Index of the coin `token` swaps as. Native ether is the coin the pool pays in ether,
+    /// and WETH the pool's WETH coin or else its native one, read from the raw `coins` when
+    /// known. A pool listing both takes WETH as its `weth_coin` and errors without one,
+    /// rather than guess which the caller meant.
+    pub fn swap_index(&self, token: &Token<P>) -> Result<Option<usize>, ArbRsError> {
+        coin_index(
+            self.address,
+            &self.attributes,
+            self.tokens.iter().map(|t| t.address()),
+            token.address(),
+        )
+    }
+
+    /// The pool, named by its swap strategy, at `block` for errors to carry.
+    pub fn context(&self, block: Option<u64>) -> PoolContext {
+        strategies::pool_context(self.address, self.attributes.swap_strategy).with_block(block)
+    }
+
+    /// Parameters of a swap of `dx` from `token_in` to `token_out` on `snapshot`.
+    fn swap_params<'a>(
+        &'a self,
+        token_in: &Token<P>,
+        token_out: &Token<P>,
+        dx: U256,
+        snapshot: &'a CurvePoolSnapshot,
+    ) -> Result<SwapParams<'a>, ArbRsError> {
+        let i = self
+            .swap_index(token_in)?
+            .ok_or_else(|| ArbRsError::CalculationError("Token In not found".to_string()))?;
+        let j = self
+            .swap_index(token_out)?
+            .ok_or_else(|| ArbRsError::CalculationError("Token Out not found".to_string()))?;
+        Ok(SwapParams {
+            i,
+            j,
+            dx,
+            address: self.address,
+            attributes: &self.attributes,
+            snapshot,
+        })
+    }
+
+    /// The pool's coins, with native ether listed as WETH.
     pub async fn fetch_coins(
         address: &Address,
         provider: Arc<P>,
         token_manager: &TokenManager<P>,
     ) -> Result<Vec<Arc<Token<P>>>, ArbRsError> {
-        let mut tokens = Vec::new();
+        let coins = Self::fetch_coin_addresses(address, provider.as_ref()).await?;
+        token_manager.get_token_list(&wrap_native(coins)).await
+    }
+
+    /// The pool's `coins`, as the contract returns them.
+    pub async fn fetch_coin_addresses(
+        address: &Address,
+        provider: &P,
+    ) -> Result<Vec<Address>, ArbRsError> {
+        let mut token_addresses = Vec::new();
         let mut use_int128 = true;
         let test_call_int = coins_1Call { i: 0 };
         if provider
@@ -542,7 +1213,7 @@ impl<P: Provider + Send + Sync + 'static + ?Sized> CurveStableswapPool<P> {
 
             match result_bytes {
                 Ok(bytes) => {
-                    let mut token_address = if use_int128 {
+                    let token_address = if use_int128 {
                         coins_1Call::abi_decode_returns(&bytes)?
                     } else {
                         coins_0Call::abi_decode_returns(&bytes)?
@@ -550,24 +1221,114 @@ impl<P: Provider + Send + Sync + 'static + ?Sized> CurveStableswapPool<P> {
                     if token_address.is_zero() {
                         break;
                     }
-                    if NATIVE_PLACEHOLDERS.contains(&token_address) {
-                        token_address = WETH_ADDRESS;
-                    }
-                    tokens.push(token_manager.get_token(token_address).await?);
+                    token_addresses.push(token_address);
                 }
                 Err(_) => break,
             }
         }
-        if tokens.is_empty() {
+        if token_addresses.is_empty() {
             return Err(ArbRsError::DataFetchError(*address));
         }
-        Ok(tokens)
+        Ok(token_addresses)
+    }
+
+    /// The pool's `i`th coin, with native ether listed as WETH, e.g. to check a stored
+    /// record against the contract.
+    pub async fn fetch_coin(
+        address: Address,
+        i: usize,
+        provider: &P,
+    ) -> Result<Address, ArbRsError> {
+        let int128_call = coins_1Call { i: i as i128 };
+        let coin = match provider
+            .call(
+                TransactionRequest::default()
+                    .to(address)
+                    .input(int128_call.abi_encode().into()),
+            )
+            .await
+        {
+            Ok(bytes) => coins_1Call::abi_decode_returns(&bytes)?,
+            Err(_) => {
+                let call = coins_0Call { i: U256::from(i) };
+                let bytes = provider
+                    .call(
+                        TransactionRequest::default()
+                            .to(address)
+                            .input(call.abi_encode().into()),
+                    )
+                    .await
+                    .map_err(|_| ArbRsError::DataFetchError(address))?;
+                coins_0Call::abi_decode_returns(&bytes)?
+            }
+        };
+        Ok(wrap_native(vec![coin])[0])
     }
 
     pub async fn get_fee(&self) -> Result<U256, ArbRsError> {
         Ok(*self.fee.read().await)
     }
 
+    /// Fetches A and fee parameters with the fetcher recorded in the pool's attributes.
+    pub async fn fetch_parameters(
+        &self,
+        block_number: Option<u64>,
+    ) -> Result<PoolParameters, ArbRsError> {
+        let provider = self.provider.as_ref();
+        match self.attributes.parameter_fetcher {
+            ParameterFetcherType::Standard => {
+                StandardFetcher
+                    .fetch(provider, self.address, block_number)
+                    .await
+            }
+            ParameterFetcherType::Crypto => {
+                CryptoFetcher
+                    .fetch(provider, self.address, block_number)
+                    .await
+            }
+            ParameterFetcherType::Factory => {
+                let factory = self.attributes.factory_address.ok_or_else(|| {
+                    ArbRsError::CalculationError(format!(
+                        "Factory fetcher configured without a factory for pool {}",
+                        self.address
+                    ))
+                })?;
+                FactoryFetcher { factory }
+                    .fetch(provider, self.address, block_number)
+                    .await
+            }
+        }
+    }
+
+    /// `A()` and the ramp `fetch_a_ramping_state` reads, batched into one call.
+    async fn fetch_a_state(
+        address: Address,
+        provider: Arc<P>,
+    ) -> Result<(U256, Option<ARampingState>), ArbRsError> {
+        let calls = [
+            BatchCall::new(address, ACall {}),
+            BatchCall::new(address, initial_ACall {}),
+            BatchCall::new(address, initial_A_timeCall {}),
+            BatchCall::new(address, future_ACall {}),
+            BatchCall::new(address, future_A_timeCall {}),
+        ];
+        let results = MulticallBatcher::new(provider)
+            .aggregate(&calls, None)
+            .await?;
+        let a = decode_result::<ACall>(&results[0])?;
+        // Pools without `initial_A` don't ramp.
+        let a_ramping_state = match try_decode_result::<initial_ACall>(&results[1]) {
+            Some(initial_a) => Some(ARampingState {
+                initial_a,
+                initial_a_time: decode_result::<initial_A_timeCall>(&results[2])?,
+                future_a: decode_result::<future_ACall>(&results[3])?,
+                future_a_time: decode_result::<future_A_timeCall>(&results[4])?,
+            }),
+            None => None,
+        };
+        Ok((a, a_ramping_state))
+    }
+
     async fn fetch_a_ramping_state(
         address: Address,
         provider: Arc<P>,
@@ -624,112 +1385,14 @@ impl<P: Provider + Send + Sync + 'static + ?Sized> CurveStableswapPool<P> {
         }))
     }
 
-    async fn update_state(&self) -> Result<(), ArbRsError> {
-        let _block_number = self.provider.get_block_number().await?;
-
-        let a_call = ACall {};
-        let a_bytes = self
-            .provider
-            .call(
-                TransactionRequest::default()
-                    .to(self.address)
-                    .input(a_call.abi_encode().into()),
-            )
-            .await?;
-        *self.a.write().await = ACall::abi_decode_returns(&a_bytes)?;
-
-        let fee_call = feeCall {};
-        let fee_bytes = self
-            .provider
-            .call(
-                TransactionRequest::default()
-                    .to(self.address)
-                    .input(fee_call.abi_encode().into()),
-            )
-            .await?;
-        *self.fee.write().await = feeCall::abi_decode_returns(&fee_bytes)?;
-
-        let live_balances = self.fetch_balances().await?;
-
-        let final_balances = match self.attributes.swap_strategy {
-            SwapStrategyType::AdminFee => {
-                let admin_balances = self.get_admin_balances().await?;
-                live_balances
-                    .iter()
-                    .zip(admin_balances.iter())
-                    .map(|(live, admin)| live.saturating_sub(*admin))
-                    .collect()
-            }
-            _ => live_balances,
-        };
-        *self.balances.write().await = final_balances;
-
-        if let Some(base_pool) = &self.base_pool {
-            let vp_call = get_virtual_priceCall {};
-            let vp_bytes = self
-                .provider
-                .call(
-                    TransactionRequest::default()
-                        .to(base_pool.address)
-                        .input(vp_call.abi_encode().into()),
-                )
-                .await?;
-            *self.cached_virtual_price.write().await =
-                Some(get_virtual_priceCall::abi_decode_returns(&vp_bytes)?);
-        }
-
-        Ok(())
-    }
-
     pub async fn fetch_balances(&self) -> Result<Vec<U256>, ArbRsError> {
         println!(
             "[fetch_balances] Fetching live balances for pool {}",
             self.address
         );
-        let mut use_int128 = true;
-        let test_call = balances_1Call { i: 0 };
-        if self
-            .provider
-            .call(
-                TransactionRequest::default()
-                    .to(self.address)
-                    .input(test_call.abi_encode().into()),
-            )
-            .await
-            .is_err()
-        {
-            use_int128 = false;
-        }
-
-        let mut balances = Vec::with_capacity(self.attributes.n_coins);
-        for i in 0..self.attributes.n_coins {
-            let result_bytes = if use_int128 {
-                let call = balances_1Call { i: i as i128 };
-                self.provider
-                    .call(
-                        TransactionRequest::default()
-                            .to(self.address)
-                            .input(call.abi_encode().into()),
-                    )
-                    .await?
-            } else {
-                let call = balances_0Call { i: U256::from(i) };
-                self.provider
-                    .call(
-                        TransactionRequest::default()
-                            .to(self.address)
-                            .input(call.abi_encode().into()),
-                    )
-                    .await?
-            };
-            let balance = if use_int128 {
-                balances_1Call::abi_decode_returns(&result_bytes)?
-            } else {
-                balances_0Call::abi_decode_returns(&result_bytes)?
-            };
-
+        let balances = self.fetch_balances_at(BlockId::latest()).await?;
+        for (i, balance) in balances.iter().enumerate() {
             println!("[fetch_balances] balance[{}]: {}", i, balance);
-            balances.push(balance);
         }
         Ok(balances)
     }
@@ -743,58 +1406,80 @@ impl<P: Provider + Send + Sync + 'static + ?Sized> CurveStableswapPool<P> {
             block = ?block_number.unwrap_or(0),
             "Fetching Curve balances"
         );
-        let block_id = block_number.map(BlockId::from).unwrap_or(BlockId::latest());
+        self.fetch_balances_at(block_number.map(BlockId::from).unwrap_or(BlockId::latest()))
+            .await
+    }
 
-        let mut use_int128 = true;
-        let test_call_int = coins_1Call { i: 0 };
-        if self
-            .provider
-            .call(
-                TransactionRequest::default()
-                    .to(self.address)
-                    .input(test_call_int.abi_encode().into()),
+    async fn fetch_balances_at(&self, block_id: BlockId) -> Result<Vec<U256>, ArbRsError> {
+        let use_int128 = self
+            .takes_int128_index(
+                &self.int128_balances,
+                balances_1Call { i: 0 }.abi_encode(),
+                block_id,
             )
-            .block(block_id)
-            .await
-            .is_err()
-        {
-            use_int128 = false;
-        }
+            .await;
 
         let mut balances = Vec::with_capacity(self.attributes.n_coins);
         for i in 0..self.attributes.n_coins {
-            let result_bytes = if use_int128 {
-                let call = balances_1Call { i: i as i128 };
-                self.provider
-                    .call(
-                        TransactionRequest::default()
-                            .to(self.address)
-                            .input(call.abi_encode().into()),
-                    )
-                    .block(block_id)
-                    .await?
+            let input = if use_int128 {
+                balances_1Call { i: i as i128 }.abi_encode()
             } else {
-                let call = balances_0Call { i: U256::from(i) };
-                self.provider
-                    .call(
-                        TransactionRequest::default()
-                            .to(self.address)
-                            .input(call.abi_encode().into()),
-                    )
-                    .block(block_id)
-                    .await?
+                balances_0Call { i: U256::from(i) }.abi_encode()
             };
+            let result_bytes = self
+                .provider
+                .call(
+                    TransactionRequest::default()
+                        .to(self.address)
+                        .input(input.into()),
+                )
+                .block(block_id)
+                .await?;
             let balance = if use_int128 {
                 balances_1Call::abi_decode_returns(&result_bytes)?
             } else {
                 balances_0Call::abi_decode_returns(&result_bytes)?
             };
-
             balances.push(balance);
         }
         Ok(balances)
     }
 
+    /// Whether the pool answers `probe`, a call with the `int128` index 0, probing once and
+    /// keeping the answer in `flavor`. A revert means the getter takes a `uint256` index; a
+    /// node failing to answer leaves the flavor to be probed again.
+    async fn takes_int128_index(
+        &self,
+        flavor: &RwLock<Option<bool>>,
+        probe: Vec<u8>,
+        block_id: BlockId,
+    ) -> bool {
+        if let Some(int128) = *flavor.read().await {
+            return int128;
+        }
+        let result = self
+            .provider
+            .call(
+                TransactionRequest::default()
+                    .to(self.address)
+                    .input(probe.into()),
+            )
+            .block(block_id)
+            .await;
+        let int128 = match result {
+            Ok(_) => true,
+            Err(RpcError::ErrorResp(_)) => false,
+            Err(_) => return false,
+        };
+        *flavor.write().await = Some(int128);
+        int128
+    }
+
+    /// The A ramp read when the pool was built. `None` for pools that don't ramp.
+    pub fn a_ramping_state(&self) -> Option<ARampingState> {
+        self.a_ramping_state
+    }
+
     /// Calculates the precise A value, handling the ramping logic if applicable.
     pub async fn a_precise(&self, timestamp: u64) -> Result<U256, ArbRsError> {
         if let Some(ramping) = self.a_ramping_state {
@@ -843,10 +1528,9 @@ impl<P: Provider + Send + Sync + 'static + ?Sized> CurveStableswapPool<P> {
             }
         } else {
             let a = *self.a.read().await;
-            Ok(a.checked_mul(U256::from(100))
-                .ok_or(ArbRsError::CalculationError(
-                    "A_PRECISION mul overflow".to_string(),
-                ))?)
+            a.checked_mul(self.attributes.a_precision_multiplier).ok_or(
+                ArbRsError::CalculationError("A_PRECISION mul overflow".to_string()),
+            )
         }
     }
 
@@ -861,44 +1545,13 @@ impl<P: Provider + Send + Sync + 'static + ?Sized> CurveStableswapPool<P> {
         snapshot: &CurvePoolSnapshot,
         lp_total_supply: U256,
     ) -> Result<U256, ArbRsError> {
-        let xp0 = math::xp(&snapshot.rates, &snapshot.balances)?;
-        let d0 = math::get_d(
-            &xp0,
-            snapshot.a,
-            self.attributes.n_coins,
-            self.attributes.d_variant,
-        )?;
-        if d0.is_zero() {
-            return Ok(U256::ZERO);
-        }
-
-        let mut balances1 = snapshot.balances.clone();
-        for i in 0..self.attributes.n_coins {
-            if is_deposit {
-                balances1[i] = balances1[i].saturating_add(amounts[i]);
-            } else {
-                balances1[i] = balances1[i]
-                    .checked_sub(amounts[i])
-                    .ok_or(ArbRsError::CalculationError("Withdrawal > balance".into()))?;
-            }
-        }
-
-        let xp1 = math::xp(&snapshot.rates, &balances1)?;
-        let d1 = math::get_d(
-            &xp1,
-            snapshot.a,
-            self.attributes.n_coins,
-            self.attributes.d_variant,
-        )?;
-
-        let diff = if is_deposit {
-            d1.saturating_sub(d0)
-        } else {
-            d0.saturating_sub(d1)
-        };
-        Ok((diff * lp_total_supply)
-            .checked_div(d0)
-            .ok_or(ArbRsError::CalculationError("LP amount div zero".into()))?)
+        calc_token_amount(
+            &self.attributes,
+            amounts,
+            is_deposit,
+            snapshot,
+            lp_total_supply,
+        )
     }
 
     /// Calculates the amount of a single token received upon withdrawing a
@@ -915,72 +1568,14 @@ impl<P: Provider + Send + Sync + 'static + ?Sized> CurveStableswapPool<P> {
             _ => return Err(ArbRsError::CalculationError("Invalid snapshot type".into())),
         };
 
-        if lp_total_supply.is_zero() {
-            return Err(ArbRsError::CalculationError(
-                "LP token supply is zero".into(),
-            ));
-        }
-
-        let xp = math::xp(&curve_snapshot.rates, &curve_snapshot.balances)?;
-        let d0 = math::get_d(
-            &xp,
-            curve_snapshot.a,
-            self.attributes.n_coins,
-            self.attributes.d_variant,
-        )?;
-        let d1 = d0.saturating_sub(
-            (token_amount * d0)
-                .checked_div(lp_total_supply)
-                .unwrap_or(U256::ZERO),
-        );
-
-        let yd_variant = Y_D_VARIANT_GROUP_0.contains(&self.address);
-        let new_y = math::get_y_d(
-            curve_snapshot.a,
-            i,
-            &xp,
-            d1,
-            self.attributes.n_coins,
-            yd_variant,
-        )?;
-        let dy_0 = xp[i]
-            .saturating_sub(new_y)
-            .checked_div(self.attributes.precision_multipliers[i])
-            .unwrap_or(U256::ZERO);
-
-        let mut xp_reduced = xp;
-        let fee_rate = (curve_snapshot.fee * U256::from(self.attributes.n_coins))
-            / U256::from(4 * (self.attributes.n_coins - 1));
-
-        for j in 0..self.attributes.n_coins {
-            let ideal_balance = (xp_reduced[j] * d1).checked_div(d0).unwrap_or(U256::ZERO);
-            let difference = if j == i {
-                ideal_balance.saturating_sub(new_y)
-            } else {
-                xp_reduced[j].saturating_sub(ideal_balance)
-            };
-            let fee_amount = (fee_rate * difference)
-                .checked_div(FEE_DENOMINATOR)
-                .unwrap_or(U256::ZERO);
-            xp_reduced[j] = xp_reduced[j].saturating_sub(fee_amount);
-        }
-
-        let y_after_fee = math::get_y_d(
-            curve_snapshot.a,
+        calc_withdraw_one_coin(
+            self.address,
+            &self.attributes,
+            token_amount,
             i,
-            &xp_reduced,
-            d1,
-            self.attributes.n_coins,
-            yd_variant,
-        )?;
-        let dy = xp_reduced[i]
-            .saturating_sub(y_after_fee)
-            .saturating_sub(U256::from(1))
-            .checked_div(self.attributes.precision_multipliers[i])
-            .unwrap_or(U256::ZERO);
-        let final_fee = dy_0.saturating_sub(dy);
-
-        Ok((dy, final_fee))
+            curve_snapshot,
+            lp_total_supply,
+        )
     }
 
     /// Calculates the output amount for a swap between the underlying tokens of a metapool.
@@ -993,87 +1588,20 @@ impl<P: Provider + Send + Sync + 'static + ?Sized> CurveStableswapPool<P> {
         self_snapshot: &CurvePoolSnapshot,
         base_snapshot: &PoolSnapshot,
     ) -> Result<U256, ArbRsError> {
-        let base_pool = self
-            .base_pool
-            .as_ref()
-            .ok_or_else(|| ArbRsError::CalculationError("Not a metapool".to_string()))?;
-        let i = self
-            .underlying_tokens
-            .iter()
-            .position(|t| **t == *token_in)
-            .ok_or_else(|| ArbRsError::CalculationError("Underlying In not found".to_string()))?;
-        let j = self
-            .underlying_tokens
-            .iter()
-            .position(|t| **t == *token_out)
-            .ok_or_else(|| ArbRsError::CalculationError("Underlying Out not found".to_string()))?;
-
-        if i > 0 && j > 0 {
-            base_pool.calculate_tokens_out(
-                &base_pool.tokens[i - 1],
-                &base_pool.tokens[j - 1],
-                dx,
-                base_snapshot,
-            )
-        } else if i > 0 && j == 0 {
-            let base_curve_snapshot = match base_snapshot {
-                PoolSnapshot::Curve(s) => s,
-                _ => {
-                    return Err(ArbRsError::CalculationError(
-                        "Expected Curve snapshot for base pool".into(),
-                    ));
-                }
-            };
-            let base_pool_lp_supply = self_snapshot.base_pool_lp_total_supply.ok_or_else(|| {
-                ArbRsError::CalculationError("Missing base pool LP supply".into())
-            })?;
-
-            let mut amounts = vec![U256::ZERO; base_pool.attributes.n_coins];
-            amounts[i - 1] = dx;
-            let mut lp_token_amount = base_pool.calc_token_amount_from_snapshot(
-                &amounts,
-                true,
-                base_curve_snapshot,
-                base_pool_lp_supply,
-            )?;
-
-            let fee_amount = (lp_token_amount * base_curve_snapshot.fee)
-                .checked_div(FEE_DENOMINATOR * U256::from(2))
-                .ok_or_else(|| {
-                    ArbRsError::CalculationError("Underlying->Meta fee calc failed".into())
-                })?;
-            lp_token_amount = lp_token_amount.saturating_sub(fee_amount);
-
-            let lp_token = base_pool.lp_token.as_ref();
-            self.calculate_tokens_out(
-                lp_token,
-                token_out,
-                lp_token_amount,
-                &PoolSnapshot::Curve(self_snapshot.clone()),
-            )
-        } else if i == 0 && j > 0 {
-            let lp_token = base_pool.lp_token.as_ref();
-            let lp_token_amount = self.calculate_tokens_out(
-                token_in,
-                lp_token,
-                dx,
-                &PoolSnapshot::Curve(self_snapshot.clone()),
-            )?;
-            let base_lp_supply = self_snapshot.base_pool_lp_total_supply.ok_or_else(|| {
-                ArbRsError::CalculationError("Missing base pool LP supply".into())
-            })?;
-            let (dy, _fee) = base_pool.calc_withdraw_one_coin_from_snapshot(
-                lp_token_amount,
-                j - 1,
-                base_snapshot,
-                base_lp_supply,
-            )?;
-            Ok(dy)
-        } else {
-            Err(ArbRsError::CalculationError(
-                "Cannot swap a token for itself.".to_string(),
-            ))
-        }
+        let PoolSnapshot::Curve(base_snapshot) = base_snapshot else {
+            return Err(ArbRsError::CalculationError(
+                "Expected Curve snapshot for base pool".into(),
+            ));
+        };
+        let snapshot = CurvePoolSnapshot {
+            base_pool_snapshot: Some(Box::new(base_snapshot.clone())),
+            ..self_snapshot.clone()
+        };
+        UnderlyingCurveQuoter::new(self, &snapshot)?.calculate_out(
+            token_in.address(),
+            token_out.address(),
+            dx,
+        )
     }
 
     pub async fn get_scaled_redemption_price(&self, block_number: u64) -> Result<U256, ArbRsError> {
@@ -1115,69 +1643,227 @@ impl<P: Provider + Send + Sync + 'static + ?Sized> CurveStableswapPool<P> {
             .checked_div(U256::from(REDEMPTION_PRICE_SCALE))
             .unwrap_or_default();
 
-        self.cached_scaled_redemption_price
-            .write()
-            .await
-            .insert(block_number, result);
+        self.cache_config.insert_hashed(
+            &mut *self.cached_scaled_redemption_price.write().await,
+            block_number,
+            result,
+        );
 
         Ok(result)
     }
 
+    /// When the oracle the pool prices with last updated, as of `block_number`: the
+    /// redemption price's update time in the RAI metapool's oracle relayer, or `updatedAt`
+    /// of an oracle-rate pool's oracle if it's a Chainlink-style feed. `None` for other pools
+    /// and for oracles that don't expose a timestamp.
+    pub async fn get_oracle_updated_at(
+        &self,
+        block_number: u64,
+    ) -> Result<Option<u64>, ArbRsError> {
+        if let Some(updated_at) = self
+            .cached_oracle_updated_at
+            .read()
+            .await
+            .get(&block_number)
+        {
+            return Ok(*updated_at);
+        }
+
+        let updated_at = if self.address == RETH_ETH_METAPOOL {
+            // The snap keeps no timestamp of its own, it copies the relayer's price.
+            let Some(snap) = self
+                .call_if_exposed(self.address, redemption_price_snapCall {}, block_number)
+                .await?
+            else {
+                return Ok(None);
+            };
+            let Some(relayer) = self
+                .call_if_exposed(snap, oracleRelayerCall {}, block_number)
+                .await?
+            else {
+                return Ok(None);
+            };
+            self.call_if_exposed(relayer, redemptionPriceUpdateTimeCall {}, block_number)
+                .await?
+        } else if self.attributes.swap_strategy == SwapStrategyType::Oracle {
+            match self
+                .call_if_exposed(self.address, oracle_methodCall {}, block_number)
+                .await?
+            {
+                Some(oracle_method) if !oracle_method.is_zero() => {
+                    let oracle = Address::from_slice(&oracle_method.to_be_bytes::<32>()[12..]);
+                    self.call_if_exposed(oracle, latestRoundDataCall {}, block_number)
+                        .await?
+                        .map(|round| round.updatedAt)
+                }
+                _ => None,
+            }
+        } else {
+            return Ok(None);
+        };
+        let updated_at = updated_at.map(|timestamp| timestamp.saturating_to::<u64>());
+
+        self.cache_config.insert_hashed(
+            &mut *self.cached_oracle_updated_at.write().await,
+            block_number,
+            updated_at,
+        );
+        Ok(updated_at)
+    }
+
+    /// `call` on `to` at `block_number`, `None` if it reverts or returns something else.
+    async fn call_if_exposed<C: SolCall>(
+        &self,
+        to: Address,
+        call: C,
+        block_number: u64,
+    ) -> Result<Option<C::Return>, ArbRsError> {
+        let request = TransactionRequest::default()
+            .to(to)
+            .input(call.abi_encode().into());
+        match self.provider.call(request).block(block_number.into()).await {
+            Ok(bytes) => Ok(C::abi_decode_returns(&bytes).ok()),
+            Err(RpcError::ErrorResp(_)) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
     /// Fetches the admin balances for each coin in the pool.
     pub async fn get_admin_balances(&self) -> Result<Vec<U256>, ArbRsError> {
         println!(
             "[get_admin_balances] Fetching admin balances for pool {}",
             self.address
         );
-        let mut use_int128 = true;
-        let test_call = admin_balances_1Call { i: 0 };
-        if self
-            .provider
-            .call(
-                TransactionRequest::default()
-                    .to(self.address)
-                    .input(test_call.abi_encode().into()),
-            )
-            .await
-            .is_err()
-        {
-            use_int128 = false;
+        let admin_balances = self.fetch_admin_balances(BlockId::latest()).await?;
+        for (i, balance) in admin_balances.iter().enumerate() {
+            println!("[get_admin_balances] admin_balance[{}]: {}", i, balance);
+        }
+        Ok(admin_balances)
+    }
+
+    /// The admin balances at `block_number`, to net out of the coin balances read at the
+    /// same block.
+    pub async fn get_admin_balances_at(&self, block_number: u64) -> Result<Vec<U256>, ArbRsError> {
+        if let Some(admin_balances) = self.cached_admin_balances.read().await.get(&block_number) {
+            return Ok(admin_balances.clone());
         }
+        let admin_balances = self
+            .fetch_admin_balances(BlockId::from(block_number))
+            .await?;
+        self.cache_config.insert_hashed(
+            &mut *self.cached_admin_balances.write().await,
+            block_number,
+            admin_balances.clone(),
+        );
+        Ok(admin_balances)
+    }
+
+    async fn fetch_admin_balances(&self, block_id: BlockId) -> Result<Vec<U256>, ArbRsError> {
+        let use_int128 = self
+            .takes_int128_index(
+                &self.int128_admin_balances,
+                admin_balances_1Call { i: 0 }.abi_encode(),
+                block_id,
+            )
+            .await;
 
         let mut admin_balances = Vec::with_capacity(self.attributes.n_coins);
         for i in 0..self.attributes.n_coins {
-            let result_bytes = if use_int128 {
-                let call = admin_balances_1Call { i: i as i128 };
-                self.provider
-                    .call(
-                        TransactionRequest::default()
-                            .to(self.address)
-                            .input(call.abi_encode().into()),
-                    )
-                    .await?
+            let input = if use_int128 {
+                admin_balances_1Call { i: i as i128 }.abi_encode()
             } else {
-                let call = admin_balances_0Call { i: U256::from(i) };
-                self.provider
-                    .call(
-                        TransactionRequest::default()
-                            .to(self.address)
-                            .input(call.abi_encode().into()),
-                    )
-                    .await?
+                admin_balances_0Call { i: U256::from(i) }.abi_encode()
             };
-
+            let result_bytes = self
+                .provider
+                .call(
+                    TransactionRequest::default()
+                        .to(self.address)
+                        .input(input.into()),
+                )
+                .block(block_id)
+                .await?;
             let balance = if use_int128 {
                 admin_balances_1Call::abi_decode_returns(&result_bytes)?
             } else {
                 admin_balances_0Call::abi_decode_returns(&result_bytes)?
             };
-
-            println!("[get_admin_balances] admin_balance[{}]: {}", i, balance);
             admin_balances.push(balance);
         }
         Ok(admin_balances)
     }
 
+    /// The pool's `admin_fee()` at `block_number`. Pools without the getter revert, which
+    /// gives `None`; failing to reach the node is still an error.
+    pub async fn get_admin_fee(&self, block_number: u64) -> Result<Option<U256>, ArbRsError> {
+        let request = TransactionRequest::default()
+            .to(self.address)
+            .input(admin_feeCall {}.abi_encode().into());
+        match self.provider.call(request).block(block_number.into()).await {
+            Ok(bytes) => Ok(admin_feeCall::abi_decode_returns(&bytes).ok()),
+            Err(RpcError::ErrorResp(_)) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Simulates `exchange` on a plain stableswap pool, returning the output and the pool
+    /// as the contract leaves it. Of the fee withheld from the output, the `admin_fee` share
+    /// leaves the working balances while the rest stays in the pool.
+    pub fn simulate_exchange(
+        &self,
+        token_in: &Token<P>,
+        token_out: &Token<P>,
+        amount_in: U256,
+        snapshot: &CurvePoolSnapshot,
+    ) -> Result<CurveStableswapPoolSimulationResult, ArbRsError> {
+        let params = self.swap_params(token_in, token_out, amount_in, snapshot)?;
+        let (i, j) = (params.i, params.j);
+        let exchange = match self.attributes.swap_strategy {
+            SwapStrategyType::Default => stableswap_exchange(
+                &params,
+                SwapStrategy::d_variant_for_math(&DefaultStrategy, &self.attributes),
+            )?,
+            SwapStrategyType::DynamicFee => dynamic_fee_exchange(
+                &params,
+                SwapStrategy::d_variant_for_math(&DynamicFeeStrategy, &self.attributes),
+            )?,
+            SwapStrategyType::AdminFee => stableswap_exchange(
+                &params,
+                SwapStrategy::d_variant_for_math(&AdminFeeStrategy, &self.attributes),
+            )?,
+            SwapStrategyType::StableSwapNG => stableswap_ng_exchange(&params)?,
+            other => {
+                return Err(ArbRsError::CalculationError(format!(
+                    "Swap simulation is not supported for {:?} pools",
+                    other
+                )));
+            }
+        };
+
+        let admin_fee_xp =
+            exchange.fee_xp * snapshot.admin_fee.unwrap_or_default() / FEE_DENOMINATOR;
+        let admin_fee_amount = (admin_fee_xp * PRECISION)
+            .checked_div(snapshot.rates[j])
+            .ok_or_else(|| ArbRsError::CalculationError("Rate is zero".into()))?;
+
+        let mut final_snapshot = snapshot.clone();
+        final_snapshot.balances[i] += amount_in;
+        final_snapshot.balances[j] = snapshot.balances[j]
+            .checked_sub(exchange.dy + admin_fee_amount)
+            .ok_or(ArbRsError::InsufficientLiquidity)?;
+        if let Some(admin_balances) = &mut final_snapshot.admin_balances {
+            admin_balances[j] += admin_fee_amount;
+        }
+
+        Ok(CurveStableswapPoolSimulationResult {
+            amount_in,
+            amount_out: exchange.dy,
+            admin_fee_amount,
+            initial_snapshot: snapshot.clone(),
+            final_snapshot,
+        })
+    }
+
     pub async fn fetch_balances_by_balance_of(
         &self,
         block_number: Option<u64>,
@@ -1192,6 +1878,54 @@ impl<P: Provider + Send + Sync + 'static + ?Sized> CurveStableswapPool<P> {
         results.into_iter().collect()
     }
 
+    /// A cryptoswap pool's invariant at `block_number`, computed from its balances, price
+    /// scale, `A` and gamma rather than read from `D()`. During an `A`/gamma ramp the stored
+    /// `D()` lags the current parameters, while the pool's own quotes recompute it as this
+    /// does. Falls back to `D()` when the balances are outside what the math accepts.
+    async fn crypto_d(
+        &self,
+        block_number: u64,
+        ann: U256,
+        gamma: U256,
+        balances: &[U256],
+        price_scale: &[U256],
+        onchain_d: Option<U256>,
+    ) -> Result<U256, ArbRsError> {
+        let computed = tricrypto_math::scaled_balances(
+            balances,
+            &self.attributes.precision_multipliers,
+            price_scale,
+        )
+        .and_then(|xp| tricrypto_math::newton_d(ann, gamma, &xp));
+        match (computed, onchain_d) {
+            (Ok(d), Some(onchain_d)) => {
+                if d.abs_diff(onchain_d) * U256::from(10).pow(U256::from(14)) > onchain_d {
+                    tracing::warn!(
+                        pool = ?self.address,
+                        block_number,
+                        %d,
+                        %onchain_d,
+                        "Computed cryptoswap D differs from the stored D()."
+                    );
+                }
+                Ok(d)
+            }
+            (Ok(d), None) => Ok(d),
+            (Err(e), _) => {
+                tracing::debug!(
+                    pool = ?self.address,
+                    block_number,
+                    "Falling back to the stored D(): {}",
+                    e
+                );
+                match onchain_d {
+                    Some(d) => Ok(d),
+                    None => self.get_tricrypto_d(block_number).await,
+                }
+            }
+        }
+    }
+
     pub async fn get_tricrypto_d(&self, block_number: u64) -> Result<U256, ArbRsError> {
         if let Some(d) = self.cached_tricrypto_d.read().await.get(&block_number) {
             return Ok(*d);
@@ -1204,12 +1938,14 @@ impl<P: Provider + Send + Sync + 'static + ?Sized> CurveStableswapPool<P> {
                     .to(self.address)
                     .input(call.abi_encode().into()),
             )
+            .block(block_number.into())
             .await?;
         let d = DCall::abi_decode_returns(&bytes)?;
-        self.cached_tricrypto_d
-            .write()
-            .await
-            .insert(block_number, d);
+        self.cache_config.insert_hashed(
+            &mut *self.cached_tricrypto_d.write().await,
+            block_number,
+            d,
+        );
         Ok(d)
     }
 
@@ -1227,10 +1963,11 @@ impl<P: Provider + Send + Sync + 'static + ?Sized> CurveStableswapPool<P> {
             )
             .await?;
         let gamma = gammaCall::abi_decode_returns(&bytes)?;
-        self.cached_tricrypto_gamma
-            .write()
-            .await
-            .insert(block_number, gamma);
+        self.cache_config.insert_hashed(
+            &mut *self.cached_tricrypto_gamma.write().await,
+            block_number,
+            gamma,
+        );
         Ok(gamma)
     }
 
@@ -1246,24 +1983,34 @@ impl<P: Provider + Send + Sync + 'static + ?Sized> CurveStableswapPool<P> {
         {
             return Ok(ps.clone());
         }
-        let mut price_scale = Vec::with_capacity(self.attributes.n_coins - 1);
-        for i in 0..(self.attributes.n_coins - 1) {
-            let call = price_scaleCall { i: U256::from(i) };
-            let bytes = self
-                .provider
+        let call_at_block = |input: Vec<u8>| {
+            self.provider
                 .call(
                     TransactionRequest::default()
                         .to(self.address)
-                        .input(call.abi_encode().into()),
+                        .input(input.into()),
                 )
-                .await?;
-            let p = price_scaleCall::abi_decode_returns(&bytes)?;
+                .block(block_number.into())
+        };
+        let mut price_scale = Vec::with_capacity(self.attributes.n_coins - 1);
+        for i in 0..(self.attributes.n_coins - 1) {
+            let indexed = call_at_block(price_scale_0Call { i: U256::from(i) }.abi_encode()).await;
+            let p = match indexed {
+                Ok(bytes) => price_scale_0Call::abi_decode_returns(&bytes)?,
+                // The two-coin pools only have the one price, without an index.
+                Err(e) if self.attributes.n_coins == 2 && e.as_error_resp().is_some() => {
+                    let bytes = call_at_block(price_scale_1Call {}.abi_encode()).await?;
+                    price_scale_1Call::abi_decode_returns(&bytes)?
+                }
+                Err(e) => return Err(e.into()),
+            };
             price_scale.push(p);
         }
-        self.cached_tricrypto_price_scale
-            .write()
-            .await
-            .insert(block_number, price_scale.clone());
+        self.cache_config.insert_hashed(
+            &mut *self.cached_tricrypto_price_scale.write().await,
+            block_number,
+            price_scale.clone(),
+        );
         Ok(price_scale)
     }
 
@@ -1332,10 +2079,47 @@ impl<P: Provider + Send + Sync + 'static + ?Sized> CurveStableswapPool<P> {
             ]
         };
 
-        self.cached_oracle_rates
-            .write()
-            .await
-            .insert(block_number, rates.clone());
+        self.cache_config.insert_hashed(
+            &mut *self.cached_oracle_rates.write().await,
+            block_number,
+            rates.clone(),
+        );
+        Ok(rates)
+    }
+
+    /// A StableSwap-NG pool's `stored_rates()` at `block_number`: each coin's precision
+    /// scaled by its oracle or ERC-4626 rate, as the pool prices it. A metapool's second
+    /// rate is its base pool's virtual price.
+    pub async fn get_stored_rates(&self, block_number: u64) -> Result<Vec<U256>, ArbRsError> {
+        if let Some(rates) = self.cached_stored_rates.read().await.get(&block_number) {
+            return Ok(rates.clone());
+        }
+        let bytes = self
+            .provider
+            .call(
+                TransactionRequest::default()
+                    .to(self.address)
+                    .input(stored_ratesCall {}.abi_encode().into()),
+            )
+            .block(BlockId::from(block_number))
+            .await?;
+        let rates = if self.attributes.base_pool_address.is_some() {
+            IStableSwapMetaNG::stored_ratesCall::abi_decode_returns(&bytes)?.to_vec()
+        } else {
+            stored_ratesCall::abi_decode_returns(&bytes)?
+        };
+        if rates.len() != self.attributes.n_coins {
+            return Err(ArbRsError::CalculationError(format!(
+                "stored_rates() returned {} rates for a {}-coin pool",
+                rates.len(),
+                self.attributes.n_coins
+            )));
+        }
+        self.cache_config.insert_hashed(
+            &mut *self.cached_stored_rates.write().await,
+            block_number,
+            rates.clone(),
+        );
         Ok(rates)
     }
 
@@ -1449,11 +2233,268 @@ impl<P: Provider + Send + Sync + 'static + ?Sized> CurveStableswapPool<P> {
                     .collect()
             }
             SwapStrategyType::Oracle => self.get_oracle_rates(block_number).await,
+            SwapStrategyType::StableSwapNG => self.get_stored_rates(block_number).await,
             _ => Ok(self.attributes.rates.clone()),
         }
     }
 }
 
+/// Snapshots `pools` through `batcher`, in as few `aggregate3` calls as it allows. Pools
+/// without `snapshot_calls` are left out. Fails only if the batch itself does; each pool's
+/// decoding result is returned separately so a failed one can be fetched on its own.
+pub async fn batch_snapshots<P: Provider + Send + Sync + 'static + ?Sized>(
+    batcher: &MulticallBatcher<P>,
+    pools: &[&CurveStableswapPool<P>],
+    block_number: Option<u64>,
+) -> Result<Vec<(Address, Result<PoolSnapshot, ArbRsError>)>, ArbRsError> {
+    let pool_calls: Vec<(&CurveStableswapPool<P>, Vec<BatchCall>)> = pools
+        .iter()
+        .filter_map(|pool| Some((*pool, pool.snapshot_calls()?)))
+        .collect();
+    if pool_calls.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let mut calls = vec![batcher.timestamp_call()];
+    calls.extend(
+        pool_calls
+            .iter()
+            .flat_map(|(_, calls)| calls.iter().cloned()),
+    );
+    let results = batcher.aggregate(&calls, block_number).await?;
+    let block_timestamp = decode_timestamp(&results[0])?;
+
+    let mut offset = 1;
+    let decode_futs = pool_calls.iter().map(|(pool, calls)| {
+        let pool_results = &results[offset..offset + calls.len()];
+        offset += calls.len();
+        async move {
+            let snapshot = pool.decode_snapshot(pool_results, block_timestamp).await;
+            (pool.address, snapshot.map(PoolSnapshot::Curve))
+        }
+    });
+    Ok(join_all(decode_futs).await)
+}
+
+/// [`CurveStableswapPool::calc_token_amount_from_snapshot`] for a pool with `attributes`.
+pub(crate) fn calc_token_amount(
+    attributes: &PoolAttributes,
+    amounts: &[U256],
+    is_deposit: bool,
+    snapshot: &CurvePoolSnapshot,
+    lp_total_supply: U256,
+) -> Result<U256, ArbRsError> {
+    let xp0 = math::xp(&snapshot.rates, &snapshot.balances)?;
+    let d0 = math::get_d(&xp0, snapshot.a, attributes.n_coins, attributes.d_variant)?;
+    if d0.is_zero() {
+        return Ok(U256::ZERO);
+    }
+
+    let mut balances1 = snapshot.balances.clone();
+    for i in 0..attributes.n_coins {
+        if is_deposit {
+            balances1[i] = balances1[i].saturating_add(amounts[i]);
+        } else {
+            balances1[i] = balances1[i]
+                .checked_sub(amounts[i])
+                .ok_or(ArbRsError::CalculationError("Withdrawal > balance".into()))?;
+        }
+    }
+
+    let xp1 = math::xp(&snapshot.rates, &balances1)?;
+    let d1 = math::get_d(&xp1, snapshot.a, attributes.n_coins, attributes.d_variant)?;
+
+    let diff = if is_deposit {
+        d1.saturating_sub(d0)
+    } else {
+        d0.saturating_sub(d1)
+    };
+    Ok((diff * lp_total_supply)
+        .checked_div(d0)
+        .ok_or(ArbRsError::CalculationError("LP amount div zero".into()))?)
+}
+
+/// [`CurveStableswapPool::calc_withdraw_one_coin_from_snapshot`] for the pool at `address`
+/// with `attributes`.
+pub(crate) fn calc_withdraw_one_coin(
+    address: Address,
+    attributes: &PoolAttributes,
+    token_amount: U256,
+    i: usize,
+    curve_snapshot: &CurvePoolSnapshot,
+    lp_total_supply: U256,
+) -> Result<(U256, U256), ArbRsError> {
+    if lp_total_supply.is_zero() {
+        return Err(ArbRsError::CalculationError(
+            "LP token supply is zero".into(),
+        ));
+    }
+
+    let xp = math::xp(&curve_snapshot.rates, &curve_snapshot.balances)?;
+    let d0 = math::get_d(
+        &xp,
+        curve_snapshot.a,
+        attributes.n_coins,
+        attributes.d_variant,
+    )?;
+    let d1 = d0.saturating_sub(
+        (token_amount * d0)
+            .checked_div(lp_total_supply)
+            .unwrap_or(U256::ZERO),
+    );
+
+    let yd_variant = Y_D_VARIANT_GROUP_0.contains(&address);
+    let new_y = math::get_y_d(curve_snapshot.a, i, &xp, d1, attributes.n_coins, yd_variant)?;
+    let dy_0 = xp[i]
+        .saturating_sub(new_y)
+        .checked_div(attributes.precision_multipliers[i])
+        .unwrap_or(U256::ZERO);
+
+    let mut xp_reduced = xp;
+    let fee_rate = (curve_snapshot.fee * U256::from(attributes.n_coins))
+        / U256::from(4 * (attributes.n_coins - 1));
+
+    for j in 0..attributes.n_coins {
+        let ideal_balance = (xp_reduced[j] * d1).checked_div(d0).unwrap_or(U256::ZERO);
+        let difference = if j == i {
+            ideal_balance.saturating_sub(new_y)
+        } else {
+            xp_reduced[j].saturating_sub(ideal_balance)
+        };
+        let fee_amount = (fee_rate * difference)
+            .checked_div(FEE_DENOMINATOR)
+            .unwrap_or(U256::ZERO);
+        xp_reduced[j] = xp_reduced[j].saturating_sub(fee_amount);
+    }
+
+    let y_after_fee = math::get_y_d(
+        curve_snapshot.a,
+        i,
+        &xp_reduced,
+        d1,
+        attributes.n_coins,
+        yd_variant,
+    )?;
+    let dy = xp_reduced[i]
+        .saturating_sub(y_after_fee)
+        .saturating_sub(U256::from(1))
+        .checked_div(attributes.precision_multipliers[i])
+        .unwrap_or(U256::ZERO);
+    let final_fee = dy_0.saturating_sub(dy);
+
+    Ok((dy, final_fee))
+}
+
+/// Index of the coin `token` swaps as in the pool at `pool`, see
+/// [`CurveStableswapPool::swap_index`]. `coins` are the pool's coins, native ether listed as
+/// WETH.
+fn coin_index(
+    pool: Address,
+    attributes: &PoolAttributes,
+    mut coins: impl Iterator<Item = Address>,
+    token: Address,
+) -> Result<Option<usize>, ArbRsError> {
+    if NATIVE_PLACEHOLDERS.contains(&token) {
+        return Ok((0..attributes.is_native.len()).find(|&index| attributes.is_native_coin(index)));
+    }
+    let raw = &attributes.raw_coin_addresses;
+    if token != WETH_ADDRESS || raw.is_empty() {
+        return Ok(coins.position(|coin| coin == token));
+    }
+    match attributes.native_and_weth_coins() {
+        Some((native, weth)) => match attributes.weth_coin {
+            Some(index) if index == native || index == weth => Ok(Some(index)),
+            _ => Err(ArbRsError::InvalidPool(
+                pool,
+                format!(
+                    "lists native ether (coin {native}) and WETH (coin {weth}); pick the coin WETH swaps as with with_weth_coin"
+                ),
+            )),
+        },
+        None => Ok(raw
+            .iter()
+            .position(|coin| *coin == WETH_ADDRESS || NATIVE_PLACEHOLDERS.contains(coin))),
+    }
+}
+
+/// A Curve pool's parameters and one snapshot, quoting without the pool. Only direct
+/// swaps between the pool's own coins are quoted.
+#[derive(Debug, Clone)]
+pub struct CurveQuoter {
+    pub address: Address,
+    pub attributes: Arc<PoolAttributes>,
+    /// The pool's coins, native ether listed as WETH.
+    pub coins: Vec<Address>,
+    pub snapshot: Arc<CurvePoolSnapshot>,
+}
+
+impl CurveQuoter {
+    fn swap_params(
+        &self,
+        token_in: Address,
+        token_out: Address,
+        dx: U256,
+    ) -> Result<SwapParams<'_>, ArbRsError> {
+        let index = |token| {
+            coin_index(
+                self.address,
+                &self.attributes,
+                self.coins.iter().copied(),
+                token,
+            )
+        };
+        let i = index(token_in)?
+            .ok_or_else(|| ArbRsError::CalculationError("Token In not found".to_string()))?;
+        let j = index(token_out)?
+            .ok_or_else(|| ArbRsError::CalculationError("Token Out not found".to_string()))?;
+        Ok(SwapParams {
+            i,
+            j,
+            dx,
+            address: self.address,
+            attributes: &self.attributes,
+            snapshot: &self.snapshot,
+        })
+    }
+}
+
+impl Quoter for CurveQuoter {
+    fn calculate_out(
+        &self,
+        token_in: Address,
+        token_out: Address,
+        amount_in: U256,
+    ) -> Result<U256, ArbRsError> {
+        strategies::calculate_dy(&self.swap_params(token_in, token_out, amount_in)?)
+    }
+
+    fn calculate_in(
+        &self,
+        token_in: Address,
+        token_out: Address,
+        amount_out: U256,
+    ) -> Result<U256, ArbRsError> {
+        strategies::calculate_dx(
+            &self.swap_params(token_in, token_out, U256::ZERO)?,
+            amount_out,
+        )
+    }
+}
+
+/// Lists native ether as WETH.
+fn wrap_native(coins: Vec<Address>) -> Vec<Address> {
+    coins
+        .into_iter()
+        .map(|coin| {
+            if NATIVE_PLACEHOLDERS.contains(&coin) {
+                WETH_ADDRESS
+            } else {
+                coin
+            }
+        })
+        .collect()
+}
+
 impl<P: ?Sized + Provider> std::fmt::Debug for CurveStableswapPool<P> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("CurveStableswapPool")
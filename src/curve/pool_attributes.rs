@@ -1,3 +1,5 @@
+use crate::core::token::WETH_ADDRESS;
+use crate::curve::constants::A_PRECISION;
 use crate::curve::pool_overrides::{DVariant, YVariant};
 use alloy_primitives::{Address, U256};
 use serde::{Deserialize, Serialize};
@@ -21,7 +23,7 @@ pub enum CalculationStrategy {
 /// A comprehensive struct holding all static and semi-static configuration
 /// for a Curve Stableswap pool. This separates the pool's configuration
 /// from its dynamic state (like balances).
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PoolAttributes {
     pub pool_variant: PoolVariant,
     pub strategy: CalculationStrategy,
@@ -38,6 +40,49 @@ pub struct PoolAttributes {
     pub offpeg_fee_multiplier: Option<U256>,
     pub base_pool_address: Option<Address>,
     pub oracle_method: Option<u8>,
+    #[serde(default)]
+    pub parameter_fetcher: ParameterFetcherType,
+    /// The factory queried for fees when `parameter_fetcher` is `Factory`.
+    #[serde(default)]
+    pub factory_address: Option<Address>,
+    /// What `A()` is multiplied by for the amplification the math works with, unless the
+    /// pool is ramping `A`.
+    #[serde(default = "default_a_precision_multiplier")]
+    pub a_precision_multiplier: U256,
+    /// Which coins the pool takes and pays in native ether, though they're listed as WETH.
+    /// Read from the pool's `coins` when the pool is built.
+    #[serde(default)]
+    pub is_native: Vec<bool>,
+    /// The pool's `coins` as the contract lists them, native ether placeholders included.
+    /// Read when the pool is built, like `is_native`.
+    #[serde(default)]
+    pub raw_coin_addresses: Vec<Address>,
+    /// The coin WETH is swapped as in a pool listing both native ether and WETH. Unset,
+    /// swaps of WETH through such a pool are refused rather than guessed.
+    #[serde(default)]
+    pub weth_coin: Option<usize>,
+}
+
+impl PoolAttributes {
+    pub fn is_native_coin(&self, index: usize) -> bool {
+        self.is_native.get(index).copied().unwrap_or(false)
+    }
+
+    /// The indices of native ether and of WETH among `raw_coin_addresses`, for a pool that
+    /// lists both.
+    pub fn native_and_weth_coins(&self) -> Option<(usize, usize)> {
+        let native =
+            (0..self.raw_coin_addresses.len()).find(|&index| self.is_native_coin(index))?;
+        let weth = self
+            .raw_coin_addresses
+            .iter()
+            .position(|coin| *coin == WETH_ADDRESS)?;
+        Some((native, weth))
+    }
+}
+
+fn default_a_precision_multiplier() -> U256 {
+    A_PRECISION
 }
 
 /// An enum to represent the different swap calculation strategies.
@@ -48,7 +93,33 @@ pub enum SwapStrategyType {
     Lending,
     Unscaled,
     DynamicFee,
+    /// The three-coin tricrypto pools.
     Tricrypto,
+    /// Two-coin cryptoswap pools, e.g. the factory deployed CRV/ETH and CVX/ETH.
+    CryptoSwap,
     AdminFee,
     Oracle,
+    /// StableSwap-NG factory pools, plain and meta: rates from `stored_rates()`, balances
+    /// net of admin fees as the pool tracks them, and a fee rising off peg by
+    /// `offpeg_fee_multiplier`.
+    StableSwapNG,
+}
+
+impl SwapStrategyType {
+    /// Whether the pool uses the cryptoswap invariant, with `D`, `gamma` and `price_scale`.
+    pub fn is_cryptoswap(self) -> bool {
+        matches!(self, Self::Tricrypto | Self::CryptoSwap)
+    }
+}
+
+/// How a pool's amplification and fee parameters are fetched.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ParameterFetcherType {
+    /// Bare `A()` and `fee()` getters.
+    #[default]
+    Standard,
+    /// Cryptoswap `A()`, `gamma()`, `mid_fee()`, `out_fee()` and `fee_gamma()`.
+    Crypto,
+    /// `A()` from the pool, fee from the factory's `get_fees(pool)`.
+    Factory,
 }
@@ -12,6 +12,9 @@ pub enum DVariant {
     Group3,
     Group4,
     Legacy,
+    /// StableSwap-NG pools, whose `D_P` multiplies by `D / x` for each coin and divides by
+    /// `n^n` once at the end.
+    StableSwapNG,
 }
 
 static D_VARIANT_GROUP_0: Lazy<HashSet<Address>> = Lazy::new(|| {
@@ -113,11 +116,8 @@ pub enum YVariant {
 
 pub static Y_VARIANT_GROUP_0: Lazy<HashSet<Address>> = Lazy::new(|| {
     [
-        address!("45F783CCE6B7FF23B2ab2D70e416cdb7D6055f51"),
         address!("52EA46506B9CC5Ef470C5bf89f17Dc28bB35D85C"),
-        address!("79a8C46DeA5aDa233ABaFFD40F3A0A2B1e5A4F27"),
         address!("A2B47E3D5c44877cca798226B7B8118F9BFb7A56"),
-        address!("A5407eAE9Ba41422680e2e00537571bcC53efBfD"),
     ]
     .into_iter()
     .collect()
@@ -140,6 +140,18 @@ pub static Y_VARIANT_GROUP_1: Lazy<HashSet<Address>> = Lazy::new(|| {
     .collect()
 });
 
+/// Legacy pools, y, BUSD and sUSD, predating `A_PRECISION`. Their `A()` is the
+/// amplification their math uses as is, and they don't ramp it.
+pub static LEGACY_A_POOLS: Lazy<HashSet<Address>> = Lazy::new(|| {
+    [
+        address!("45F783CCE6B7FF23B2ab2D70e416cdb7D6055f51"),
+        address!("79a8C46DeA5aDa233ABaFFD40F3A0A2B1e5A4F27"),
+        address!("A5407eAE9Ba41422680e2e00537571bcC53efBfD"),
+    ]
+    .into_iter()
+    .collect()
+});
+
 pub fn get_y_variant(pool_address: &Address) -> YVariant {
     if Y_VARIANT_GROUP_0.contains(pool_address) {
         YVariant::Group0
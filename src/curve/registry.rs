@@ -1,46 +1,151 @@
 use crate::errors::ArbRsError;
-use alloy_primitives::{Address, U256};
+use alloy::transports::RpcError;
+use alloy_primitives::{Address, address};
 use alloy_provider::Provider;
 use alloy_rpc_types::TransactionRequest;
 use alloy_sol_types::{SolCall, sol};
+use dashmap::DashMap;
 use std::sync::Arc;
 
+/// Mainnet Curve MetaRegistry, which aggregates the factory registries.
+pub const CURVE_META_REGISTRY: Address = address!("F98B45FA17DE75FB1aD0e7aFD971b0ca00e379fC");
+/// Mainnet factory of plain and meta stableswap pools.
+pub const CURVE_STABLE_FACTORY: Address = address!("B9fC157394Af804a3578134A6585C0dc9cc990d4");
+/// Mainnet factory of StableSwap-NG pools.
+pub const CURVE_STABLESWAP_NG_FACTORY: Address =
+    address!("6A8cbed756804B16E05E741eDaBd5cB544AE21bf");
+/// Mainnet factory of two-coin cryptoswap pools.
+pub const CURVE_CRYPTO_FACTORY: Address = address!("F18056Bbd320E96A48e3Fbf8bC061322531aac99");
+
 sol! {
     // Interface for the main Curve registry
     interface ICurveRegistry {
-        function get_lp_token(address pool) external view returns (address);
-        function get_pool_from_lp_token(address lp_token) external view returns (address);
         function get_underlying_coins(address pool) external view returns (address[8]);
     }
 
-    // Interface for a generic Curve pool to get its coins
-    interface ICurvePool {
-        function coins(uint256 i) external view returns (address);
+    // Reverts for pools none of its registries know.
+    interface ICurveMetaRegistry {
+        function get_lp_token(address pool) external view returns (address);
+        function get_base_pool(address pool) external view returns (address);
+    }
+
+    // Stable factory pools are their own LP token.
+    interface ICurveStableFactory {
+        function get_coins(address pool) external view returns (address[4]);
+        function get_base_pool(address pool) external view returns (address);
+    }
+
+    interface ICurveCryptoFactory {
+        function get_token(address pool) external view returns (address);
     }
 }
 
+/// Where a pool's metadata came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RegistrySource {
+    MetaRegistry,
+    StableFactory,
+    CryptoFactory,
+    /// Registered with [`CurveRegistry::register_pool_metadata`].
+    Manual,
+}
+
+/// A pool's LP token and, for metapools, its base pool.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PoolMetadata {
+    pub lp_token: Address,
+    pub base_pool: Option<Address>,
+    pub source: RegistrySource,
+}
+
+/// Looks pools up in the MetaRegistry, then the stable and crypto factories, then the
+/// hand-registered pools, and caches the first answer. Clones share the cache.
 pub struct CurveRegistry<P: Provider + Send + Sync + 'static + ?Sized> {
+    /// The legacy main registry, whose `PoolAdded` events discovery follows.
     pub address: Address,
     provider: Arc<P>,
+    sources: Vec<(RegistrySource, Address)>,
+    manual: Arc<DashMap<Address, PoolMetadata>>,
+    cache: Arc<DashMap<Address, PoolMetadata>>,
 }
 
 impl<P: Provider + Send + Sync + 'static + ?Sized> CurveRegistry<P> {
     pub fn new(address: Address, provider: Arc<P>) -> Self {
-        Self { address, provider }
+        Self {
+            address,
+            provider,
+            sources: vec![
+                (RegistrySource::MetaRegistry, CURVE_META_REGISTRY),
+                (RegistrySource::StableFactory, CURVE_STABLE_FACTORY),
+                (RegistrySource::CryptoFactory, CURVE_CRYPTO_FACTORY),
+            ],
+            manual: Arc::new(DashMap::new()),
+            cache: Arc::new(DashMap::new()),
+        }
+    }
+
+    /// Replaces the contracts consulted, in order, before the hand-registered pools.
+    pub fn with_sources(mut self, sources: impl IntoIterator<Item = (RegistrySource, Address)>) -> Self {
+        self.sources = sources.into_iter().collect();
+        self.cache.clear();
+        self
+    }
+
+    /// The contract consulted as `source`, if any.
+    pub fn source_address(&self, source: RegistrySource) -> Option<Address> {
+        self.sources
+            .iter()
+            .find(|(kind, _)| *kind == source)
+            .map(|(_, address)| *address)
+    }
+
+    /// Registers a pool no registry knows. Registries that do know it take precedence.
+    pub fn register_pool_metadata(&self, pool: Address, lp_token: Address, base_pool: Option<Address>) {
+        self.manual.insert(
+            pool,
+            PoolMetadata {
+                lp_token,
+                base_pool,
+                source: RegistrySource::Manual,
+            },
+        );
+    }
+
+    /// The source `pool`'s metadata was found in, if it has been looked up.
+    pub fn source_of(&self, pool: Address) -> Option<RegistrySource> {
+        self.cache.get(&pool).map(|metadata| metadata.source)
+    }
+
+    /// `pool`'s metadata from the first source that knows it. A pool no source knows is a
+    /// `DataFetchError`.
+    pub async fn pool_metadata(&self, pool: Address) -> Result<PoolMetadata, ArbRsError> {
+        if let Some(metadata) = self.cache.get(&pool) {
+            return Ok(*metadata);
+        }
+
+        let mut found = None;
+        for (source, registry) in &self.sources {
+            found = match source {
+                RegistrySource::MetaRegistry => self.meta_registry_metadata(*registry, pool).await?,
+                RegistrySource::StableFactory => self.stable_factory_metadata(*registry, pool).await?,
+                RegistrySource::CryptoFactory => self.crypto_factory_metadata(*registry, pool).await?,
+                RegistrySource::Manual => None,
+            };
+            if found.is_some() {
+                break;
+            }
+        }
+        let metadata = found
+            .or_else(|| self.manual.get(&pool).map(|metadata| *metadata))
+            .ok_or(ArbRsError::DataFetchError(pool))?;
+
+        tracing::debug!(?pool, source = ?metadata.source, "Resolved Curve pool metadata");
+        self.cache.insert(pool, metadata);
+        Ok(metadata)
     }
 
     pub async fn get_lp_token(&self, pool_address: Address) -> Result<Address, ArbRsError> {
-        let call = ICurveRegistry::get_lp_tokenCall { pool: pool_address };
-        let request = TransactionRequest::default()
-            .to(self.address)
-            .input(call.abi_encode().into());
-        let result_bytes = self
-            .provider
-            .call(request)
-            .await
-            .map_err(|e| ArbRsError::ProviderError(e.to_string()))?;
-        let decoded = ICurveRegistry::get_lp_tokenCall::abi_decode_returns(&result_bytes)?;
-        Ok(decoded)
+        Ok(self.pool_metadata(pool_address).await?.lp_token)
     }
 
     pub async fn get_underlying_coins(
@@ -68,49 +173,80 @@ impl<P: Provider + Send + Sync + 'static + ?Sized> CurveRegistry<P> {
         &self,
         metapool_address: Address,
     ) -> Result<Option<Address>, ArbRsError> {
-        println!(
-            "[get_base_pool] Checking for base pool for {}",
-            metapool_address
-        );
-        let get_coin_call = ICurvePool::coinsCall { i: U256::from(1) };
-        let request = TransactionRequest::default()
-            .to(metapool_address)
-            .input(get_coin_call.abi_encode().into());
-
-        let base_lp_token = match self.provider.call(request).await {
-            Ok(bytes) => ICurvePool::coinsCall::abi_decode_returns(&bytes)?,
-            Err(e) => {
-                println!(
-                    "[get_base_pool] Call to coins(1) failed: {}. Assuming not a metapool.",
-                    e
-                );
-                return Ok(None);
-            }
+        Ok(self.pool_metadata(metapool_address).await?.base_pool)
+    }
+
+    async fn meta_registry_metadata(
+        &self,
+        registry: Address,
+        pool: Address,
+    ) -> Result<Option<PoolMetadata>, ArbRsError> {
+        let Some(lp_token) = self
+            .call(registry, ICurveMetaRegistry::get_lp_tokenCall { pool })
+            .await?
+            .filter(|lp_token| !lp_token.is_zero())
+        else {
+            return Ok(None);
         };
+        let base_pool = self
+            .call(registry, ICurveMetaRegistry::get_base_poolCall { pool })
+            .await?
+            .filter(|base_pool| !base_pool.is_zero());
+        Ok(Some(PoolMetadata {
+            lp_token,
+            base_pool,
+            source: RegistrySource::MetaRegistry,
+        }))
+    }
 
-        if base_lp_token.is_zero() {
+    async fn stable_factory_metadata(
+        &self,
+        factory: Address,
+        pool: Address,
+    ) -> Result<Option<PoolMetadata>, ArbRsError> {
+        let coins = self
+            .call(factory, ICurveStableFactory::get_coinsCall { pool })
+            .await?
+            .unwrap_or_default();
+        if coins[0].is_zero() {
             return Ok(None);
         }
+        let base_pool = self
+            .call(factory, ICurveStableFactory::get_base_poolCall { pool })
+            .await?
+            .filter(|base_pool| !base_pool.is_zero());
+        Ok(Some(PoolMetadata {
+            lp_token: pool,
+            base_pool,
+            source: RegistrySource::StableFactory,
+        }))
+    }
 
-        let get_pool_call = ICurveRegistry::get_pool_from_lp_tokenCall {
-            lp_token: base_lp_token,
-        };
-        let request = TransactionRequest::default()
-            .to(self.address)
-            .input(get_pool_call.abi_encode().into());
+    async fn crypto_factory_metadata(
+        &self,
+        factory: Address,
+        pool: Address,
+    ) -> Result<Option<PoolMetadata>, ArbRsError> {
+        Ok(self
+            .call(factory, ICurveCryptoFactory::get_tokenCall { pool })
+            .await?
+            .filter(|lp_token| !lp_token.is_zero())
+            .map(|lp_token| PoolMetadata {
+                lp_token,
+                base_pool: None,
+                source: RegistrySource::CryptoFactory,
+            }))
+    }
 
+    /// `None` when the call reverts, which registries do for pools they don't know.
+    async fn call<C: SolCall>(&self, to: Address, call: C) -> Result<Option<C::Return>, ArbRsError> {
+        let request = TransactionRequest::default()
+            .to(to)
+            .input(call.abi_encode().into());
         match self.provider.call(request).await {
-            Ok(result_bytes) => {
-                let base_pool_address =
-                    ICurveRegistry::get_pool_from_lp_tokenCall::abi_decode_returns(&result_bytes)?;
-
-                if base_pool_address.is_zero() {
-                    Ok(None)
-                } else {
-                    Ok(Some(base_pool_address))
-                }
-            }
-            Err(_) => Ok(None),
+            Ok(bytes) => Ok(C::abi_decode_returns(&bytes).ok()),
+            Err(RpcError::ErrorResp(_)) => Ok(None),
+            Err(e) => Err(e.into()),
         }
     }
 }
@@ -120,6 +256,9 @@ impl<P: Provider + Send + Sync + 'static + ?Sized> Clone for CurveRegistry<P> {
         Self {
             address: self.address,
             provider: self.provider.clone(),
+            sources: self.sources.clone(),
+            manual: self.manual.clone(),
+            cache: self.cache.clone(),
         }
     }
 }
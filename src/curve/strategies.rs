@@ -1,12 +1,11 @@
 use crate::curve::constants::{FEE_DENOMINATOR, PRECISION};
-use crate::curve::pool::CurveStableswapPool;
+use crate::curve::pool_attributes::{PoolAttributes, SwapStrategyType};
 use crate::curve::pool_overrides::{DVariant, Y_VARIANT_GROUP_0, Y_VARIANT_GROUP_1};
 use crate::curve::tricrypto_math::TEN_POW_18;
 use crate::curve::types::CurvePoolSnapshot;
 use crate::curve::{math, tricrypto_math};
-use crate::errors::ArbRsError;
+use crate::errors::{ArbRsError, PoolContext, WithContext};
 use alloy_primitives::{Address, U256, address};
-use alloy_provider::Provider;
 
 const STETH_USDC_METAPOOL: Address = address!("C61557C5d177bd7DC889A3b621eEC333e168f68A");
 const RETH_ETH_METAPOOL: Address = address!("618788357D0EBd8A37e763ADab3bc575D54c2C7d");
@@ -29,138 +28,229 @@ const LENDING_GROUP_B: &[Address] = &[
 ];
 
 /// A synchronous parameter struct that holds a snapshot of the pool state.
-pub struct SwapParams<'a, P: Provider + Send + Sync + 'static + ?Sized> {
+pub struct SwapParams<'a> {
     pub i: usize,
     pub j: usize,
     pub dx: U256,
-    pub pool: &'a CurveStableswapPool<P>,
+    /// The pool's address, which a few pools' math is keyed on.
+    pub address: Address,
+    pub attributes: &'a PoolAttributes,
     pub snapshot: &'a CurvePoolSnapshot,
 }
 
-/// The synchronous trait for all swap calculation strategies.
-pub trait SwapStrategy<P: Provider + Send + Sync + 'static + ?Sized> {
-    fn calculate_dy(&self, params: &SwapParams<P>) -> Result<U256, ArbRsError>;
-    fn calculate_dx(&self, params: &SwapParams<P>, dy: U256) -> Result<U256, ArbRsError>;
+impl SwapParams<'_> {
+    /// The pool quoted on, named by its swap strategy.
+    pub fn context(&self) -> PoolContext {
+        pool_context(self.address, self.attributes.swap_strategy)
+    }
 }
 
-/// Strategy for standard Curve V1 pools.
-/// Logic: xp -> x -> y -> dy -> fee -> unscale by rate
-#[derive(Debug, Default)]
-pub struct DefaultStrategy;
-impl<P: Provider + Send + Sync + 'static + ?Sized> SwapStrategy<P> for DefaultStrategy {
-    fn calculate_dy(&self, params: &SwapParams<P>) -> Result<U256, ArbRsError> {
-        let (i, j, dx) = (params.i, params.j, params.dx);
-        let attributes = &params.pool.attributes;
-
-        let balances = &params.snapshot.balances;
-        let fee = params.snapshot.fee;
-        let amp = params.snapshot.a;
-        let rates = &params.snapshot.rates;
-
-        let xp = math::xp(rates, balances)?;
-
-        let dx_scaled = (dx * rates[i])
-            .checked_div(PRECISION)
-            .ok_or_else(|| ArbRsError::CalculationError("dx_scaled division failed".to_string()))?;
-
-        let x = xp[i]
-            .checked_add(dx_scaled)
-            .ok_or_else(|| ArbRsError::CalculationError("x addition failed".to_string()))?;
-
-        let is_y0 = Y_VARIANT_GROUP_0.contains(&params.pool.address);
-        let is_y1 = Y_VARIANT_GROUP_1.contains(&params.pool.address);
-        let y = math::get_y(
-            i,
-            j,
-            x,
-            &xp,
-            amp,
-            attributes.n_coins,
-            attributes.d_variant,
-            is_y0,
-            is_y1,
-        )?;
-
-        let dy = xp[j].saturating_sub(y).saturating_sub(U256::from(1));
+/// Context naming a Curve pool by its swap strategy, e.g. `Curve AdminFee`.
+pub fn pool_context(address: Address, swap_strategy: SwapStrategyType) -> PoolContext {
+    PoolContext::new(address).with_kind(format!("Curve {swap_strategy:?}"))
+}
 
-        let fee_amount = (dy * fee).checked_div(FEE_DENOMINATOR).ok_or_else(|| {
-            ArbRsError::CalculationError("fee_amount division failed".to_string())
-        })?;
+/// The synchronous trait for all swap calculation strategies.
+pub trait SwapStrategy {
+    fn calculate_dy(&self, params: &SwapParams) -> Result<U256, ArbRsError>;
+    fn calculate_dx(&self, params: &SwapParams, dy: U256) -> Result<U256, ArbRsError>;
+
+    /// The `D` variant used by both `calculate_dy` and `calculate_dx`, so the two stay
+    /// inverses of each other. Defaults to the pool's own.
+    fn d_variant_for_math(&self, attributes: &PoolAttributes) -> DVariant {
+        attributes.d_variant
+    }
+}
 
-        let dy_after_fee = dy.saturating_sub(fee_amount);
+/// Output of swapping `params.dx`, by the pool's swap strategy.
+pub fn calculate_dy(params: &SwapParams) -> Result<U256, ArbRsError> {
+    let result = match params.attributes.swap_strategy {
+        SwapStrategyType::Default => DefaultStrategy.calculate_dy(params),
+        SwapStrategyType::Metapool => MetapoolStrategy.calculate_dy(params),
+        SwapStrategyType::Lending => LendingStrategy.calculate_dy(params),
+        SwapStrategyType::Unscaled => UnscaledStrategy.calculate_dy(params),
+        SwapStrategyType::DynamicFee => DynamicFeeStrategy.calculate_dy(params),
+        SwapStrategyType::Tricrypto | SwapStrategyType::CryptoSwap => {
+            CryptoSwapStrategy.calculate_dy(params)
+        }
+        SwapStrategyType::Oracle => OracleStrategy.calculate_dy(params),
+        SwapStrategyType::AdminFee => AdminFeeStrategy.calculate_dy(params),
+        SwapStrategyType::StableSwapNG => StableSwapNgStrategy.calculate_dy(params),
+    };
+    result.with_ctx(|| params.context())
+}
 
-        let rate_j = rates[j];
-        if rate_j.is_zero() {
-            return Err(ArbRsError::CalculationError("Rate is zero".into()));
+/// Input the output `dy` takes, by the pool's swap strategy. `params.dx` is ignored.
+pub fn calculate_dx(params: &SwapParams, dy: U256) -> Result<U256, ArbRsError> {
+    let result = match params.attributes.swap_strategy {
+        SwapStrategyType::Default => DefaultStrategy.calculate_dx(params, dy),
+        SwapStrategyType::Metapool => MetapoolStrategy.calculate_dx(params, dy),
+        SwapStrategyType::Lending => LendingStrategy.calculate_dx(params, dy),
+        SwapStrategyType::Unscaled => UnscaledStrategy.calculate_dx(params, dy),
+        SwapStrategyType::DynamicFee => DynamicFeeStrategy.calculate_dx(params, dy),
+        SwapStrategyType::Tricrypto | SwapStrategyType::CryptoSwap => {
+            CryptoSwapStrategy.calculate_dx(params, dy)
         }
+        SwapStrategyType::Oracle => OracleStrategy.calculate_dx(params, dy),
+        SwapStrategyType::AdminFee => AdminFeeStrategy.calculate_dx(params, dy),
+        SwapStrategyType::StableSwapNG => StableSwapNgStrategy.calculate_dx(params, dy),
+    };
+    result.with_ctx(|| params.context())
+}
 
-        (dy_after_fee * PRECISION)
-            .checked_div(rate_j)
-            .ok_or_else(|| ArbRsError::CalculationError("final dy division failed".to_string()))
+/// Strategy for standard Curve V1 pools.
+/// Logic: xp -> x -> y -> dy -> fee -> unscale by rate
+#[derive(Debug, Default)]
+pub struct DefaultStrategy;
+impl SwapStrategy for DefaultStrategy {
+    fn calculate_dy(&self, params: &SwapParams) -> Result<U256, ArbRsError> {
+        stableswap_exchange(
+            params,
+            SwapStrategy::d_variant_for_math(self, params.attributes),
+        )
+        .map(|exchange| exchange.dy)
     }
 
-    fn calculate_dx(&self, params: &SwapParams<P>, dy: U256) -> Result<U256, ArbRsError> {
-        let (i, j) = (params.i, params.j);
-        let attributes = &params.pool.attributes;
-
-        let balances = &params.snapshot.balances;
-        let fee = params.snapshot.fee;
-        let amp = params.snapshot.a;
-        let rates = &params.snapshot.rates;
-
-        let xp = math::xp(rates, balances)?;
-
-        let dy_plus_fee = (dy * FEE_DENOMINATOR)
-            .checked_div(FEE_DENOMINATOR.saturating_sub(fee))
-            .ok_or_else(|| {
-                ArbRsError::CalculationError("dy_plus_fee division failed".to_string())
-            })?;
-
-        let dy_scaled = (dy_plus_fee * rates[j])
-            .checked_div(PRECISION)
-            .ok_or_else(|| ArbRsError::CalculationError("dy_scaled division failed".to_string()))?;
+    fn calculate_dx(&self, params: &SwapParams, dy: U256) -> Result<U256, ArbRsError> {
+        stableswap_dx(
+            params,
+            dy,
+            SwapStrategy::d_variant_for_math(self, params.attributes),
+        )
+    }
+}
 
-        let y = xp[j]
-            .checked_sub(dy_scaled)
-            .ok_or_else(|| ArbRsError::CalculationError("y subtraction failed".to_string()))?;
+/// Output of a plain stableswap exchange, split the way the pool's `exchange` books it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StableswapExchange {
+    /// Amount sent to the trader, in the output coin.
+    pub dy: U256,
+    /// Swap fee withheld from the output, in rate-scaled (`xp`) units.
+    pub fee_xp: U256,
+}
 
-        let is_y0 = Y_VARIANT_GROUP_0.contains(&params.pool.address);
-        let is_y1 = Y_VARIANT_GROUP_1.contains(&params.pool.address);
-        let x = math::get_y(
-            j,
-            i,
-            y,
-            &xp,
-            amp,
-            attributes.n_coins,
-            attributes.d_variant,
-            is_y0,
-            is_y1,
-        )?;
+/// Exchanges `params.dx` through a plain stableswap pool, computed with `d_variant`.
+pub fn stableswap_exchange(
+    params: &SwapParams,
+    d_variant: DVariant,
+) -> Result<StableswapExchange, ArbRsError> {
+    let (i, j, dx) = (params.i, params.j, params.dx);
+    let attributes = &params.attributes;
+
+    let balances = &params.snapshot.balances;
+    let fee = params.snapshot.fee;
+    let amp = params.snapshot.a;
+    let rates = &params.snapshot.rates;
+
+    let xp = math::xp(rates, balances)?;
+
+    let dx_scaled = (dx * rates[i])
+        .checked_div(PRECISION)
+        .ok_or_else(|| ArbRsError::CalculationError("dx_scaled division failed".to_string()))?;
+
+    let x = xp[i]
+        .checked_add(dx_scaled)
+        .ok_or_else(|| ArbRsError::CalculationError("x addition failed".to_string()))?;
+
+    let is_y0 = Y_VARIANT_GROUP_0.contains(&params.address);
+    let is_y1 = Y_VARIANT_GROUP_1.contains(&params.address);
+    let y = math::get_y(
+        i,
+        j,
+        x,
+        &xp,
+        amp,
+        attributes.n_coins,
+        d_variant,
+        is_y0,
+        is_y1,
+    )?;
+
+    let dy = xp[j].saturating_sub(y).saturating_sub(U256::from(1));
+
+    let fee_amount = (dy * fee)
+        .checked_div(FEE_DENOMINATOR)
+        .ok_or_else(|| ArbRsError::CalculationError("fee_amount division failed".to_string()))?;
+
+    let dy_after_fee = dy.saturating_sub(fee_amount);
+
+    let rate_j = rates[j];
+    if rate_j.is_zero() {
+        return Err(ArbRsError::CalculationError("Rate is zero".into()));
+    }
 
-        let dx_scaled = x.checked_sub(xp[i]).ok_or_else(|| {
-            ArbRsError::CalculationError("dx_scaled subtraction failed".to_string())
-        })?;
+    Ok(StableswapExchange {
+        dy: (dy_after_fee * PRECISION)
+            .checked_div(rate_j)
+            .ok_or_else(|| ArbRsError::CalculationError("final dy division failed".to_string()))?,
+        fee_xp: fee_amount,
+    })
+}
 
-        let rate_i = rates[i];
-        if rate_i.is_zero() {
-            return Err(ArbRsError::CalculationError("Rate is zero".into()));
-        }
+/// Input needed for `dy` out of a plain stableswap pool, computed with `d_variant`.
+fn stableswap_dx(
+    params: &SwapParams,
+    dy: U256,
+    d_variant: DVariant,
+) -> Result<U256, ArbRsError> {
+    let (i, j) = (params.i, params.j);
+    let attributes = &params.attributes;
+
+    let balances = &params.snapshot.balances;
+    let fee = params.snapshot.fee;
+    let amp = params.snapshot.a;
+    let rates = &params.snapshot.rates;
+
+    let xp = math::xp(rates, balances)?;
+
+    let dy_plus_fee = (dy * FEE_DENOMINATOR)
+        .checked_div(FEE_DENOMINATOR.saturating_sub(fee))
+        .ok_or_else(|| ArbRsError::CalculationError("dy_plus_fee division failed".to_string()))?;
+
+    let dy_scaled = (dy_plus_fee * rates[j])
+        .checked_div(PRECISION)
+        .ok_or_else(|| ArbRsError::CalculationError("dy_scaled division failed".to_string()))?;
+
+    let y = xp[j]
+        .checked_sub(dy_scaled)
+        .ok_or_else(|| ArbRsError::CalculationError("y subtraction failed".to_string()))?;
+
+    let is_y0 = Y_VARIANT_GROUP_0.contains(&params.address);
+    let is_y1 = Y_VARIANT_GROUP_1.contains(&params.address);
+    let x = math::get_y(
+        j,
+        i,
+        y,
+        &xp,
+        amp,
+        attributes.n_coins,
+        d_variant,
+        is_y0,
+        is_y1,
+    )?;
+
+    let dx_scaled = x
+        .checked_sub(xp[i])
+        .ok_or_else(|| ArbRsError::CalculationError("dx_scaled subtraction failed".to_string()))?;
+
+    let rate_i = rates[i];
+    if rate_i.is_zero() {
+        return Err(ArbRsError::CalculationError("Rate is zero".into()));
+    }
 
-        let final_dx = (dx_scaled * PRECISION)
-            .checked_div(rate_i)
-            .ok_or_else(|| ArbRsError::CalculationError("final_dx division failed".to_string()))?;
+    let final_dx = (dx_scaled * PRECISION)
+        .checked_div(rate_i)
+        .ok_or_else(|| ArbRsError::CalculationError("final_dx division failed".to_string()))?;
 
-        Ok(final_dx.saturating_add(U256::from(1)))
-    }
+    Ok(final_dx.saturating_add(U256::from(1)))
 }
 
 #[derive(Debug, Default)]
 pub struct MetapoolStrategy;
-impl<P: Provider + Send + Sync + 'static + ?Sized> SwapStrategy<P> for MetapoolStrategy {
-    fn calculate_dy(&self, params: &SwapParams<P>) -> Result<U256, ArbRsError> {
+impl SwapStrategy for MetapoolStrategy {
+    fn calculate_dy(&self, params: &SwapParams) -> Result<U256, ArbRsError> {
         let (i, j, dx) = (params.i, params.j, params.dx);
-        let attributes = &params.pool.attributes;
+        let attributes = &params.attributes;
 
         let balances = &params.snapshot.balances;
         let fee = params.snapshot.fee;
@@ -169,7 +259,7 @@ impl<P: Provider + Send + Sync + 'static + ?Sized> SwapStrategy<P> for MetapoolS
             ArbRsError::CalculationError("Metapool virtual price not in snapshot".to_string())
         })?;
 
-        let rates = match params.pool.address {
+        let rates = match params.address {
             STETH_USDC_METAPOOL => vec![PRECISION, virtual_price],
             RETH_ETH_METAPOOL => vec![
                 params.snapshot.scaled_redemption_price.ok_or_else(|| {
@@ -188,8 +278,8 @@ impl<P: Provider + Send + Sync + 'static + ?Sized> SwapStrategy<P> for MetapoolS
             .checked_add(dx_scaled)
             .ok_or_else(|| ArbRsError::CalculationError("Metapool dy: x addition failed".into()))?;
 
-        let is_y0 = Y_VARIANT_GROUP_0.contains(&params.pool.address);
-        let is_y1 = Y_VARIANT_GROUP_1.contains(&params.pool.address);
+        let is_y0 = Y_VARIANT_GROUP_0.contains(&params.address);
+        let is_y1 = Y_VARIANT_GROUP_1.contains(&params.address);
         let y = math::get_y(
             i,
             j,
@@ -197,7 +287,7 @@ impl<P: Provider + Send + Sync + 'static + ?Sized> SwapStrategy<P> for MetapoolS
             &xp,
             amp,
             attributes.n_coins,
-            attributes.d_variant,
+            SwapStrategy::d_variant_for_math(self, attributes),
             is_y0,
             is_y1,
         )?;
@@ -220,9 +310,9 @@ impl<P: Provider + Send + Sync + 'static + ?Sized> SwapStrategy<P> for MetapoolS
             })
     }
 
-    fn calculate_dx(&self, params: &SwapParams<P>, dy: U256) -> Result<U256, ArbRsError> {
+    fn calculate_dx(&self, params: &SwapParams, dy: U256) -> Result<U256, ArbRsError> {
         let (i, j) = (params.i, params.j);
-        let attributes = &params.pool.attributes;
+        let attributes = &params.attributes;
 
         let balances = &params.snapshot.balances;
         let fee = params.snapshot.fee;
@@ -231,7 +321,7 @@ impl<P: Provider + Send + Sync + 'static + ?Sized> SwapStrategy<P> for MetapoolS
             ArbRsError::CalculationError("Metapool virtual price not in snapshot".to_string())
         })?;
 
-        let rates = match params.pool.address {
+        let rates = match params.address {
             STETH_USDC_METAPOOL => vec![PRECISION, virtual_price],
             RETH_ETH_METAPOOL => vec![
                 params.snapshot.scaled_redemption_price.ok_or_else(|| {
@@ -256,8 +346,8 @@ impl<P: Provider + Send + Sync + 'static + ?Sized> SwapStrategy<P> for MetapoolS
             ArbRsError::CalculationError("Metapool dx: y subtraction failed".into())
         })?;
 
-        let is_y0 = Y_VARIANT_GROUP_0.contains(&params.pool.address);
-        let is_y1 = Y_VARIANT_GROUP_1.contains(&params.pool.address);
+        let is_y0 = Y_VARIANT_GROUP_0.contains(&params.address);
+        let is_y1 = Y_VARIANT_GROUP_1.contains(&params.address);
         let x = math::get_y(
             j,
             i,
@@ -265,7 +355,7 @@ impl<P: Provider + Send + Sync + 'static + ?Sized> SwapStrategy<P> for MetapoolS
             &xp,
             amp,
             attributes.n_coins,
-            attributes.d_variant,
+            SwapStrategy::d_variant_for_math(self, attributes),
             is_y0,
             is_y1,
         )?;
@@ -287,8 +377,8 @@ impl<P: Provider + Send + Sync + 'static + ?Sized> SwapStrategy<P> for MetapoolS
 
 #[derive(Debug, Default)]
 pub struct LendingStrategy;
-impl<P: Provider + Send + Sync + 'static + ?Sized> SwapStrategy<P> for LendingStrategy {
-    fn calculate_dy(&self, params: &SwapParams<P>) -> Result<U256, ArbRsError> {
+impl SwapStrategy for LendingStrategy {
+    fn calculate_dy(&self, params: &SwapParams) -> Result<U256, ArbRsError> {
         let (i, j, dx) = (params.i, params.j, params.dx);
 
         let balances = &params.snapshot.balances;
@@ -304,23 +394,23 @@ impl<P: Provider + Send + Sync + 'static + ?Sized> SwapStrategy<P> for LendingSt
             .checked_add(dx_scaled)
             .ok_or_else(|| ArbRsError::CalculationError("Lending dy: x addition failed".into()))?;
 
-        let is_y0 = Y_VARIANT_GROUP_0.contains(&params.pool.address);
-        let is_y1 = Y_VARIANT_GROUP_1.contains(&params.pool.address);
+        let is_y0 = Y_VARIANT_GROUP_0.contains(&params.address);
+        let is_y1 = Y_VARIANT_GROUP_1.contains(&params.address);
         let y = math::get_y(
             i,
             j,
             x,
             &xp,
             amp,
-            params.pool.attributes.n_coins,
-            params.pool.attributes.d_variant,
+            params.attributes.n_coins,
+            SwapStrategy::d_variant_for_math(self, params.attributes),
             is_y0,
             is_y1,
         )?;
 
         let dy_raw = xp[j].saturating_sub(y);
 
-        if LENDING_GROUP_A.contains(&params.pool.address) {
+        if LENDING_GROUP_A.contains(&params.address) {
             let fee_amount = (dy_raw * fee).checked_div(FEE_DENOMINATOR).ok_or_else(|| {
                 ArbRsError::CalculationError("Lending dy: fee_amount A failed".into())
             })?;
@@ -331,7 +421,7 @@ impl<P: Provider + Send + Sync + 'static + ?Sized> SwapStrategy<P> for LendingSt
             (dy_after_fee * PRECISION)
                 .checked_div(rates[j])
                 .ok_or_else(|| ArbRsError::CalculationError("Lending dy: final dy A failed".into()))
-        } else if LENDING_GROUP_B.contains(&params.pool.address) {
+        } else if LENDING_GROUP_B.contains(&params.address) {
             let fee_amount = (dy_raw * fee).checked_div(FEE_DENOMINATOR).ok_or_else(|| {
                 ArbRsError::CalculationError("Lending dy: fee_amount B failed".into())
             })?;
@@ -355,7 +445,7 @@ impl<P: Provider + Send + Sync + 'static + ?Sized> SwapStrategy<P> for LendingSt
         }
     }
 
-    fn calculate_dx(&self, params: &SwapParams<P>, dy: U256) -> Result<U256, ArbRsError> {
+    fn calculate_dx(&self, params: &SwapParams, dy: U256) -> Result<U256, ArbRsError> {
         let (i, j) = (params.i, params.j);
 
         let balances = &params.snapshot.balances;
@@ -368,23 +458,36 @@ impl<P: Provider + Send + Sync + 'static + ?Sized> SwapStrategy<P> for LendingSt
         let dy_plus_fee = (dy * FEE_DENOMINATOR)
             .checked_div(FEE_DENOMINATOR.saturating_sub(fee))
             .ok_or_else(|| ArbRsError::CalculationError("Lending dx: dy_plus_fee failed".into()))?;
-        let dy_scaled = (dy_plus_fee * rates[j])
-            .checked_div(PRECISION)
-            .ok_or_else(|| ArbRsError::CalculationError("Lending dx: dy_scaled failed".into()))?;
+        // Undo each group's `calculate_dy` ending: group B pays out in `xp` units, and the
+        // rest hold back a wei before unscaling.
+        let dy_scaled = if LENDING_GROUP_A.contains(&params.address) {
+            (dy_plus_fee * rates[j])
+                .checked_div(PRECISION)
+                .ok_or_else(|| ArbRsError::CalculationError("Lending dx: dy_scaled A failed".into()))?
+        } else if LENDING_GROUP_B.contains(&params.address) {
+            dy_plus_fee
+        } else {
+            (dy_plus_fee * rates[j])
+                .checked_div(PRECISION)
+                .ok_or_else(|| {
+                    ArbRsError::CalculationError("Lending dx: dy_scaled else failed".into())
+                })?
+                .saturating_add(U256::from(1))
+        };
         let y = xp[j].checked_sub(dy_scaled).ok_or_else(|| {
             ArbRsError::CalculationError("Lending dx: y subtraction failed".into())
         })?;
 
-        let is_y0 = Y_VARIANT_GROUP_0.contains(&params.pool.address);
-        let is_y1 = Y_VARIANT_GROUP_1.contains(&params.pool.address);
+        let is_y0 = Y_VARIANT_GROUP_0.contains(&params.address);
+        let is_y1 = Y_VARIANT_GROUP_1.contains(&params.address);
         let x = math::get_y(
             j,
             i,
             y,
             &xp,
             amp,
-            params.pool.attributes.n_coins,
-            params.pool.attributes.d_variant,
+            params.attributes.n_coins,
+            SwapStrategy::d_variant_for_math(self, params.attributes),
             is_y0,
             is_y1,
         )?;
@@ -406,10 +509,10 @@ impl<P: Provider + Send + Sync + 'static + ?Sized> SwapStrategy<P> for LendingSt
 
 #[derive(Debug, Default)]
 pub struct UnscaledStrategy;
-impl<P: Provider + Send + Sync + 'static + ?Sized> SwapStrategy<P> for UnscaledStrategy {
-    fn calculate_dy(&self, params: &SwapParams<P>) -> Result<U256, ArbRsError> {
+impl SwapStrategy for UnscaledStrategy {
+    fn calculate_dy(&self, params: &SwapParams) -> Result<U256, ArbRsError> {
         let (i, j, dx) = (params.i, params.j, params.dx);
-        let attributes = &params.pool.attributes;
+        let attributes = &params.attributes;
 
         let balances = &params.snapshot.balances;
         let fee = params.snapshot.fee;
@@ -421,8 +524,8 @@ impl<P: Provider + Send + Sync + 'static + ?Sized> SwapStrategy<P> for UnscaledS
             .checked_add(dx)
             .ok_or_else(|| ArbRsError::CalculationError("x add overflow".to_string()))?;
 
-        let is_y0 = Y_VARIANT_GROUP_0.contains(&params.pool.address);
-        let is_y1 = Y_VARIANT_GROUP_1.contains(&params.pool.address);
+        let is_y0 = Y_VARIANT_GROUP_0.contains(&params.address);
+        let is_y1 = Y_VARIANT_GROUP_1.contains(&params.address);
         let y = math::get_y(
             i,
             j,
@@ -430,7 +533,7 @@ impl<P: Provider + Send + Sync + 'static + ?Sized> SwapStrategy<P> for UnscaledS
             &xp,
             amp,
             attributes.n_coins,
-            attributes.d_variant,
+            SwapStrategy::d_variant_for_math(self, attributes),
             is_y0,
             is_y1,
         )?;
@@ -446,7 +549,7 @@ impl<P: Provider + Send + Sync + 'static + ?Sized> SwapStrategy<P> for UnscaledS
         Ok(final_dy)
     }
 
-    fn calculate_dx(&self, params: &SwapParams<P>, dy: U256) -> Result<U256, ArbRsError> {
+    fn calculate_dx(&self, params: &SwapParams, dy: U256) -> Result<U256, ArbRsError> {
         let balances = &params.snapshot.balances;
         let fee = params.snapshot.fee;
         let amp = params.snapshot.a;
@@ -463,16 +566,16 @@ impl<P: Provider + Send + Sync + 'static + ?Sized> SwapStrategy<P> for UnscaledS
             .checked_sub(dy_plus_fee)
             .ok_or_else(|| ArbRsError::CalculationError("y subtraction failed".to_string()))?;
 
-        let is_y0 = Y_VARIANT_GROUP_0.contains(&params.pool.address);
-        let is_y1 = Y_VARIANT_GROUP_1.contains(&params.pool.address);
+        let is_y0 = Y_VARIANT_GROUP_0.contains(&params.address);
+        let is_y1 = Y_VARIANT_GROUP_1.contains(&params.address);
         let x = math::get_y(
             params.j,
             params.i,
             y,
             &xp,
             amp,
-            params.pool.attributes.n_coins,
-            params.pool.attributes.d_variant,
+            params.attributes.n_coins,
+            SwapStrategy::d_variant_for_math(self, params.attributes),
             is_y0,
             is_y1,
         )?;
@@ -483,28 +586,130 @@ impl<P: Provider + Send + Sync + 'static + ?Sized> SwapStrategy<P> for UnscaledS
     }
 }
 
-// Quick Note on Dynamic Fee Logic
-// Your original implementation for this strategy followed the same calculation path as DefaultStrategy. A true dynamic fee calculation (like for stETH) would use the offpeg_fee_multiplier from PoolAttributes and the dynamic_fee function from your curve/math.rs file to adjust the fee based on how far the pool is from its peg.
-
-// The code I provided above faithfully refactors your current logic. After we finish this big refactor, we can easily circle back and enhance this strategy to implement the true dynamic fee math.
+/// Strategy for pools whose fee rises as they move off peg. With an `offpeg_fee_multiplier`
+/// (saave), each swap is charged `dynamic_fee` of the average of the pair's `xp` before and
+/// after it, as `StableSwapSAAVE.vy` does; without one (stETH) the fee is flat.
 #[derive(Debug, Default)]
 pub struct DynamicFeeStrategy;
-impl<P: Provider + Send + Sync + 'static + ?Sized> SwapStrategy<P> for DynamicFeeStrategy {
-    fn calculate_dy(&self, params: &SwapParams<P>) -> Result<U256, ArbRsError> {
-        DefaultStrategy::default().calculate_dy(params)
+impl SwapStrategy for DynamicFeeStrategy {
+    fn calculate_dy(&self, params: &SwapParams) -> Result<U256, ArbRsError> {
+        dynamic_fee_exchange(
+            params,
+            SwapStrategy::d_variant_for_math(self, params.attributes),
+        )
+        .map(|exchange| exchange.dy)
     }
 
-    fn calculate_dx(&self, params: &SwapParams<P>, dy: U256) -> Result<U256, ArbRsError> {
-        DefaultStrategy::default().calculate_dx(params, dy)
+    /// The fee depends on where the swap leaves the pool, so this searches for the smallest
+    /// `dx` whose `calculate_dy` covers `dy` rather than inverting the invariant.
+    fn calculate_dx(&self, params: &SwapParams, dy: U256) -> Result<U256, ArbRsError> {
+        if params.attributes.offpeg_fee_multiplier.is_none() {
+            return stableswap_dx(
+                params,
+                dy,
+                SwapStrategy::d_variant_for_math(self, params.attributes),
+            );
+        }
+        search_dx(params, dy, |dx| {
+            self.calculate_dy(&SwapParams { dx, ..*params })
+        })
     }
 }
 
+/// Exchanges `params.dx` through a dynamic fee pool, computed with `d_variant`. Pools
+/// without an `offpeg_fee_multiplier` exchange like plain stableswap pools.
+pub fn dynamic_fee_exchange(
+    params: &SwapParams,
+    d_variant: DVariant,
+) -> Result<StableswapExchange, ArbRsError> {
+    let Some(offpeg_fee_multiplier) = params.attributes.offpeg_fee_multiplier else {
+        return stableswap_exchange(params, d_variant);
+    };
+    let (i, j, dx) = (params.i, params.j, params.dx);
+    let rates = &params.snapshot.rates;
+    if rates[i].is_zero() || rates[j].is_zero() {
+        return Err(ArbRsError::CalculationError("Rate is zero".into()));
+    }
+
+    let xp = math::xp(rates, &params.snapshot.balances)?;
+    let x = xp[i]
+        .checked_add(dx * rates[i] / PRECISION)
+        .ok_or_else(|| ArbRsError::CalculationError("x addition failed".to_string()))?;
+    let y = math::get_y(
+        i,
+        j,
+        x,
+        &xp,
+        params.snapshot.a,
+        params.attributes.n_coins,
+        d_variant,
+        Y_VARIANT_GROUP_0.contains(&params.address),
+        Y_VARIANT_GROUP_1.contains(&params.address),
+    )?;
+
+    // Unlike the plain pools, no wei is held back from `dy`.
+    let dy = xp[j].saturating_sub(y) * PRECISION / rates[j];
+    let fee = math::dynamic_fee(
+        (xp[i] + x) / U256::from(2),
+        (xp[j] + y) / U256::from(2),
+        params.snapshot.fee,
+        offpeg_fee_multiplier,
+    )?;
+    let fee_amount = fee * dy / FEE_DENOMINATOR;
+
+    Ok(StableswapExchange {
+        dy: dy - fee_amount,
+        fee_xp: fee_amount * rates[j] / PRECISION,
+    })
+}
+
+/// The smallest `dx` whose output, as computed by `dy_for`, covers `dy`. For pools whose fee
+/// depends on the balances after the swap, where the invariant can't simply be inverted.
+fn search_dx(
+    params: &SwapParams,
+    dy: U256,
+    dy_for: impl Fn(U256) -> Result<U256, ArbRsError>,
+) -> Result<U256, ArbRsError> {
+    if dy.is_zero() {
+        return Ok(U256::ZERO);
+    }
+    if dy >= params.snapshot.balances[params.j] {
+        return Err(ArbRsError::CalculationError(
+            "Requested output exceeds pool balance".to_string(),
+        ));
+    }
+
+    // Double until the output is covered; past 2^128 the pool can't fill the order.
+    let (mut lo, mut hi) = (U256::ZERO, U256::ONE);
+    while dy_for(hi)? < dy {
+        if hi.bit_len() > 128 {
+            return Err(ArbRsError::CalculationError(
+                "Requested output exceeds pool liquidity".to_string(),
+            ));
+        }
+        lo = hi;
+        hi <<= 1;
+    }
+
+    while hi - lo > U256::ONE {
+        let mid = lo + ((hi - lo) >> 1);
+        if dy_for(mid)? >= dy {
+            hi = mid;
+        } else {
+            lo = mid;
+        }
+    }
+    Ok(hi)
+}
+
+/// Cryptoswap pools: tricrypto and the two-coin factory pools. Balances are priced into
+/// coin 0 with `price_scale` before solving the invariant.
 #[derive(Debug, Default)]
-pub struct TricryptoStrategy;
-impl<P: Provider + Send + Sync + 'static + ?Sized> SwapStrategy<P> for TricryptoStrategy {
-    fn calculate_dy(&self, params: &SwapParams<P>) -> Result<U256, ArbRsError> {
+pub struct CryptoSwapStrategy;
+impl SwapStrategy for CryptoSwapStrategy {
+    fn calculate_dy(&self, params: &SwapParams) -> Result<U256, ArbRsError> {
         let (i, j, dx) = (params.i, params.j, params.dx);
-        let attributes = &params.pool.attributes;
+        let attributes = &params.attributes;
         let snapshot = params.snapshot;
 
         let balances = &snapshot.balances;
@@ -519,21 +724,22 @@ impl<P: Provider + Send + Sync + 'static + ?Sized> SwapStrategy<P> for Tricrypto
             ArbRsError::CalculationError("Missing tricrypto D in snapshot".to_string())
         })?;
 
-        let precisions = [
-            U256::from(10).pow(U256::from(12)),
-            U256::from(10).pow(U256::from(10)),
-            U256::from(1),
-        ];
-
-        let mut xp = balances.clone();
-        xp[i] += dx;
-
-        xp[0] *= precisions[0];
-        for k in 0..(attributes.n_coins - 1) {
-            xp[k + 1] = (xp[k + 1] * price_scale[k] * precisions[k + 1])
-                .checked_div(PRECISION)
-                .ok_or_else(|| ArbRsError::CalculationError("xp div underflow".to_string()))?;
+        let n_coins = attributes.n_coins;
+        if !(2..=3).contains(&n_coins)
+            || attributes.precision_multipliers.len() != n_coins
+            || price_scale.len() != n_coins - 1
+        {
+            return Err(ArbRsError::CalculationError(format!(
+                "Cryptoswap strategy requires a 2 or 3-coin pool, got {} coins",
+                n_coins
+            )));
         }
+        // 10^(18 - decimals) per coin, derived from the coin decimals when the attributes were built.
+        let precisions = &attributes.precision_multipliers;
+
+        let mut balances = balances.clone();
+        balances[i] += dx;
+        let xp = tricrypto_math::scaled_balances(&balances, precisions, price_scale)?;
 
         let y = tricrypto_math::newton_y(amp, gamma, &xp, d, j)?;
         let mut dy = xp[j].saturating_sub(y).saturating_sub(U256::from(1));
@@ -547,9 +753,12 @@ impl<P: Provider + Send + Sync + 'static + ?Sized> SwapStrategy<P> for Tricrypto
 
         let mut xp_post_swap = xp;
         xp_post_swap[j] = y;
-        let fee_gamma = attributes.fee_gamma.unwrap_or_default();
-        let mid_fee = attributes.mid_fee.unwrap_or_default();
-        let out_fee = attributes.out_fee.unwrap_or_default();
+        let fee_gamma = snapshot
+            .fee_gamma
+            .or(attributes.fee_gamma)
+            .unwrap_or_default();
+        let mid_fee = snapshot.mid_fee.or(attributes.mid_fee).unwrap_or_default();
+        let out_fee = snapshot.out_fee.or(attributes.out_fee).unwrap_or_default();
 
         let f = tricrypto_math::reduction_coefficient(&xp_post_swap, fee_gamma)?;
         let fee_calc = (mid_fee * f + out_fee * (TEN_POW_18 - f))
@@ -563,75 +772,120 @@ impl<P: Provider + Send + Sync + 'static + ?Sized> SwapStrategy<P> for Tricrypto
         Ok(dy.saturating_sub(fee_amount))
     }
 
-    fn calculate_dx(&self, _params: &SwapParams<P>, _dy: U256) -> Result<U256, ArbRsError> {
-        unimplemented!("Inverse Tricrypto calculation is not yet implemented.")
+    /// The cryptoswap fee depends on the balances after the swap, so rather than invert
+    /// `newton_y` this searches for the smallest `dx` whose `calculate_dy` covers `dy`.
+    fn calculate_dx(&self, params: &SwapParams, dy: U256) -> Result<U256, ArbRsError> {
+        search_dx(params, dy, |dx| {
+            self.calculate_dy(&SwapParams { dx, ..*params })
+        })
     }
 }
 
+/// Pools that price a coin through an on-chain oracle. The snapshot's `rates` already carry
+/// the oracle rate, so both directions are plain stableswap math.
 #[derive(Debug, Default)]
 pub struct OracleStrategy;
-impl<P: Provider + Send + Sync + 'static + ?Sized> SwapStrategy<P> for OracleStrategy {
-    fn calculate_dy(&self, params: &SwapParams<P>) -> Result<U256, ArbRsError> {
+impl SwapStrategy for OracleStrategy {
+    fn calculate_dy(&self, params: &SwapParams) -> Result<U256, ArbRsError> {
         DefaultStrategy::default().calculate_dy(params)
     }
 
-    fn calculate_dx(&self, params: &SwapParams<P>, dy: U256) -> Result<U256, ArbRsError> {
+    fn calculate_dx(&self, params: &SwapParams, dy: U256) -> Result<U256, ArbRsError> {
         DefaultStrategy::default().calculate_dx(params, dy)
     }
 }
 
+/// Strategy for pools that hold admin fees in their coin balances. Snapshot `balances` are
+/// `balanceOf` net of `admin_balances`, which is what the pool swaps against. Each swap
+/// moves `admin_fee` of its fee into `admin_balances`, so that share leaves the working
+/// balances rather than staying in the pool; see `CurveStableswapPool::simulate_exchange`.
 #[derive(Debug, Default)]
 pub struct AdminFeeStrategy;
-impl<P: Provider + Send + Sync + 'static + ?Sized> SwapStrategy<P> for AdminFeeStrategy {
-    fn calculate_dy(&self, params: &SwapParams<P>) -> Result<U256, ArbRsError> {
-        let (i, j, dx) = (params.i, params.j, params.dx);
-        let attributes = &params.pool.attributes;
-
-        let net_balances = &params.snapshot.balances;
-        let fee = params.snapshot.fee;
-        let amp = params.snapshot.a;
-        let rates = &params.snapshot.rates;
-
-        let xp = math::xp(rates, net_balances)?;
-        let dx_scaled = (dx * rates[i])
-            .checked_div(PRECISION)
-            .ok_or_else(|| ArbRsError::CalculationError("dx_scaled failed".into()))?;
-        let x = xp[i]
-            .checked_add(dx_scaled)
-            .ok_or_else(|| ArbRsError::CalculationError("x addition failed".into()))?;
+impl SwapStrategy for AdminFeeStrategy {
+    fn calculate_dy(&self, params: &SwapParams) -> Result<U256, ArbRsError> {
+        stableswap_exchange(
+            params,
+            SwapStrategy::d_variant_for_math(self, params.attributes),
+        )
+        .map(|exchange| exchange.dy)
+    }
 
-        let is_y0 = Y_VARIANT_GROUP_0.contains(&params.pool.address);
-        let is_y1 = Y_VARIANT_GROUP_1.contains(&params.pool.address);
+    fn calculate_dx(&self, params: &SwapParams, dy: U256) -> Result<U256, ArbRsError> {
+        stableswap_dx(
+            params,
+            dy,
+            SwapStrategy::d_variant_for_math(self, params.attributes),
+        )
+    }
 
-        let y = math::get_y(
-            i,
-            j,
-            x,
-            &xp,
-            amp,
-            attributes.n_coins,
-            DVariant::Legacy,
-            is_y0,
-            is_y1,
-        )?;
+    /// These pools compute `D` the legacy way, whatever their attributes say.
+    fn d_variant_for_math(&self, _attributes: &PoolAttributes) -> DVariant {
+        DVariant::Legacy
+    }
+}
 
-        let dy = xp[j].saturating_sub(y).saturating_sub(U256::from(1));
-        let fee_amount = (dy * fee)
-            .checked_div(FEE_DENOMINATOR)
-            .ok_or_else(|| ArbRsError::CalculationError("fee_amount division failed".into()))?;
-        let dy_after_fee = dy.saturating_sub(fee_amount);
+/// StableSwap-NG pools. Each swap is charged `dynamic_fee` of the average of the pair's `xp`
+/// before and after it, with the pool's `offpeg_fee_multiplier`, after holding back a wei
+/// from `dy` as `CurveStableSwapNG.vy` does. The snapshot's `rates` are the pool's
+/// `stored_rates()`, a metapool's ending in its base pool's virtual price, and its
+/// `balances` already exclude admin fees.
+#[derive(Debug, Default)]
+pub struct StableSwapNgStrategy;
+impl SwapStrategy for StableSwapNgStrategy {
+    fn calculate_dy(&self, params: &SwapParams) -> Result<U256, ArbRsError> {
+        stableswap_ng_exchange(params).map(|exchange| exchange.dy)
+    }
 
-        let rate_j = rates[j];
-        if rate_j.is_zero() {
-            return Err(ArbRsError::CalculationError("Rate is zero".into()));
-        }
+    /// The fee depends on where the swap leaves the pool, so this searches for the smallest
+    /// `dx` whose `calculate_dy` covers `dy` rather than inverting the invariant.
+    fn calculate_dx(&self, params: &SwapParams, dy: U256) -> Result<U256, ArbRsError> {
+        search_dx(params, dy, |dx| {
+            self.calculate_dy(&SwapParams { dx, ..*params })
+        })
+    }
 
-        (dy_after_fee * PRECISION)
-            .checked_div(rate_j)
-            .ok_or_else(|| ArbRsError::CalculationError("final dy division failed".into()))
+    /// NG pools compute `D` their own way, whatever their attributes say.
+    fn d_variant_for_math(&self, _attributes: &PoolAttributes) -> DVariant {
+        DVariant::StableSwapNG
     }
+}
 
-    fn calculate_dx(&self, params: &SwapParams<P>, dy: U256) -> Result<U256, ArbRsError> {
-        DefaultStrategy::default().calculate_dx(params, dy)
+/// Exchanges `params.dx` through a StableSwap-NG pool. Without an `offpeg_fee_multiplier`
+/// the fee is the flat `fee`.
+pub fn stableswap_ng_exchange(params: &SwapParams) -> Result<StableswapExchange, ArbRsError> {
+    let (i, j, dx) = (params.i, params.j, params.dx);
+    let rates = &params.snapshot.rates;
+    if rates[i].is_zero() || rates[j].is_zero() {
+        return Err(ArbRsError::CalculationError("Rate is zero".into()));
     }
+
+    let xp = math::xp(rates, &params.snapshot.balances)?;
+    let x = xp[i]
+        .checked_add(dx * rates[i] / PRECISION)
+        .ok_or_else(|| ArbRsError::CalculationError("x addition failed".to_string()))?;
+    let y = math::get_y(
+        i,
+        j,
+        x,
+        &xp,
+        params.snapshot.a,
+        params.attributes.n_coins,
+        SwapStrategy::d_variant_for_math(&StableSwapNgStrategy, params.attributes),
+        false,
+        false,
+    )?;
+
+    let dy = xp[j].saturating_sub(y).saturating_sub(U256::from(1));
+    let fee = math::dynamic_fee(
+        (xp[i] + x) / U256::from(2),
+        (xp[j] + y) / U256::from(2),
+        params.snapshot.fee,
+        params.attributes.offpeg_fee_multiplier.unwrap_or_default(),
+    )?;
+    let fee_xp = dy * fee / FEE_DENOMINATOR;
+
+    Ok(StableswapExchange {
+        dy: (dy - fee_xp) * PRECISION / rates[j],
+        fee_xp,
+    })
 }
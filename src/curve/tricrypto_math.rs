@@ -39,7 +39,8 @@ pub fn reduction_coefficient(x: &[U256], fee_gamma: U256) -> Result<U256, ArbRsE
     Ok(k)
 }
 
-/// The custom Newton's method solver for the Tricrypto invariant.
+/// The custom Newton's method solver for the cryptoswap invariant, for the two-coin pools
+/// and tricrypto alike. The number of coins is `xp.len()`.
 pub fn newton_y(
     ann: U256,
     gamma: U256,
@@ -47,10 +48,17 @@ pub fn newton_y(
     d: U256,
     token_index: usize,
 ) -> Result<U256, ArbRsError> {
-    const N_COINS: usize = 3;
-    let a_multiplier = U256::from(100);
+    let n_coins = xp.len();
+    if !(2..=3).contains(&n_coins) || token_index >= n_coins {
+        return Err(ArbRsError::CalculationError(format!(
+            "No cryptoswap newton_y for coin {} of a {}-coin pool",
+            token_index, n_coins
+        )));
+    }
+    let n = U256::from(n_coins);
+    // Both pool generations scale `ANN` by 10^4.
+    let a_multiplier = U256::from(10_000);
 
-    let mut y = d / U256::from(N_COINS);
     let mut k0_i = TEN_POW_18;
     let mut s_i = U256::ZERO;
 
@@ -63,25 +71,39 @@ pub fn newton_y(
         .max(d.div_ceil(U256::from(10).pow(U256::from(14))))
         .max(U256::from(100));
 
-    for j in 2..=N_COINS {
-        let _x = x_sorted[N_COINS - j];
-        y = y
-            .checked_mul(d)
+    // The two-coin pools square `D` in one go for their first guess, which rounds
+    // differently from tricrypto's coin by coin division.
+    let mut y = if n_coins == 2 {
+        s_i = x_sorted[0];
+        d.checked_mul(d)
             .ok_or(ArbRsError::CalculationError(
                 "newton_y y mul overflow".to_string(),
             ))?
-            .checked_div(_x.checked_mul(U256::from(N_COINS)).unwrap_or_default())
-            .unwrap_or_default();
-        s_i += _x;
-    }
+            .checked_div(x_sorted[0] * n * n)
+            .unwrap_or_default()
+    } else {
+        let mut y = d / n;
+        for j in 2..=n_coins {
+            let _x = x_sorted[n_coins - j];
+            y = y
+                .checked_mul(d)
+                .ok_or(ArbRsError::CalculationError(
+                    "newton_y y mul overflow".to_string(),
+                ))?
+                .checked_div(_x.checked_mul(n).unwrap_or_default())
+                .unwrap_or_default();
+            s_i += _x;
+        }
+        y
+    };
 
-    for k in 0..(N_COINS - 1) {
+    for x in &x_sorted[..n_coins - 1] {
         k0_i = k0_i
-            .checked_mul(x_sorted[k])
+            .checked_mul(*x)
             .ok_or(ArbRsError::CalculationError(
                 "newton_y k0i mul1 overflow".to_string(),
             ))?
-            .checked_mul(U256::from(N_COINS))
+            .checked_mul(n)
             .ok_or(ArbRsError::CalculationError(
                 "newton_y k0i mul2 overflow".to_string(),
             ))?
@@ -99,7 +121,7 @@ pub fn newton_y(
             .ok_or(ArbRsError::CalculationError(
                 "newton_y k0 mul overflow".to_string(),
             ))?
-            .checked_mul(U256::from(N_COINS))
+            .checked_mul(n)
             .ok_or(ArbRsError::CalculationError(
                 "newton_y k0 mul2 overflow".to_string(),
             ))?
@@ -109,7 +131,7 @@ pub fn newton_y(
             ))?;
         let s = s_i + y;
 
-        let g1k0 = (gamma + TEN_POW_18).saturating_sub(k0) + U256::from(1);
+        let g1k0 = (gamma + TEN_POW_18).abs_diff(k0) + U256::from(1);
 
         let mul1 = TEN_POW_18
             .checked_mul(d)
@@ -169,11 +191,10 @@ pub fn newton_y(
             continue;
         }
 
-        let fprime = (yfprime - dyfprime)
-            .checked_div(y)
-            .ok_or(ArbRsError::CalculationError(
-                "newton_y fprime underflow".to_string(),
-            ))?;
+        let yfprime = yfprime - dyfprime;
+        let fprime = yfprime.checked_div(y).ok_or(ArbRsError::CalculationError(
+            "newton_y fprime underflow".to_string(),
+        ))?;
         let mut y_minus = mul1.checked_div(fprime).unwrap_or_default();
         let y_plus = (yfprime + TEN_POW_18.checked_mul(d).unwrap_or_default())
             .checked_div(fprime)
@@ -197,11 +218,337 @@ pub fn newton_y(
 
         let diff = if y > y_prev { y - y_prev } else { y_prev - y };
         if diff < convergence_limit.max(y / U256::from(10).pow(U256::from(14))) {
+            // The pool rejects results this far from balance.
+            let frac = y * TEN_POW_18 / d;
+            if frac < U256::from(10).pow(U256::from(16))
+                || frac > U256::from(10).pow(U256::from(20))
+            {
+                return Err(ArbRsError::CalculationError(
+                    "Cryptoswap newton_y result out of range".to_string(),
+                ));
+            }
             return Ok(y);
         }
     }
 
     Err(ArbRsError::CalculationError(
-        "Tricrypto newton_y did not converge".to_string(),
+        "Cryptoswap newton_y did not converge".to_string(),
+    ))
+}
+
+/// Balances priced into coin 0 and scaled to 18 decimals: coin 0 by its precision, every
+/// other coin also by its `price_scale`, as the pools' `xp` is.
+pub fn scaled_balances(
+    balances: &[U256],
+    precisions: &[U256],
+    price_scale: &[U256],
+) -> Result<Vec<U256>, ArbRsError> {
+    let n_coins = balances.len();
+    if precisions.len() != n_coins || price_scale.len() + 1 != n_coins {
+        return Err(ArbRsError::CalculationError(format!(
+            "Cannot scale {} balances with {} precisions and {} prices",
+            n_coins,
+            precisions.len(),
+            price_scale.len()
+        )));
+    }
+    let mut xp = balances.to_vec();
+    xp[0] *= precisions[0];
+    for k in 0..(n_coins - 1) {
+        xp[k + 1] = xp[k + 1]
+            .checked_mul(price_scale[k])
+            .and_then(|x| x.checked_mul(precisions[k + 1]))
+            .ok_or(ArbRsError::CalculationError("xp mul overflow".to_string()))?
+            / TEN_POW_18;
+    }
+    Ok(xp)
+}
+
+/// Geometric mean of `x`, sorted high to low, by Newton's method from `x[0]`. The two-coin
+/// pools collapse the product into a single division, which rounds differently.
+fn geometric_mean(x: &[U256]) -> Result<U256, ArbRsError> {
+    let n = U256::from(x.len());
+    let mut d = x[0];
+    for _ in 0..255 {
+        let d_prev = d;
+        d = if x.len() == 2 {
+            (d + x[0] * x[1] / d) / n
+        } else {
+            let mut tmp = TEN_POW_18;
+            for &x_i in x {
+                tmp = tmp.checked_mul(x_i).ok_or(ArbRsError::CalculationError(
+                    "geometric_mean tmp overflow".to_string(),
+                ))? / d;
+            }
+            d * ((n - U256::from(1)) * TEN_POW_18 + tmp) / (n * TEN_POW_18)
+        };
+        let diff = d.abs_diff(d_prev);
+        if diff <= U256::from(1) || diff * TEN_POW_18 < d {
+            return Ok(d);
+        }
+    }
+    Err(ArbRsError::CalculationError(
+        "Cryptoswap geometric_mean did not converge".to_string(),
+    ))
+}
+
+/// The cryptoswap invariant `D` of the scaled balances `x_unsorted`, by the pools' own
+/// Newton iteration, for the two-coin pools and tricrypto alike. Rejects balances the pools
+/// consider unsafe: coin 0 outside 10^9..=10^33, or another coin too small beside it.
+pub fn newton_d(ann: U256, gamma: U256, x_unsorted: &[U256]) -> Result<U256, ArbRsError> {
+    let n_coins = x_unsorted.len();
+    if !(2..=3).contains(&n_coins) {
+        return Err(ArbRsError::CalculationError(format!(
+            "No cryptoswap newton_d for a {}-coin pool",
+            n_coins
+        )));
+    }
+    if ann.is_zero() || gamma.is_zero() {
+        return Err(ArbRsError::CalculationError(
+            "newton_d needs a non-zero A and gamma".to_string(),
+        ));
+    }
+    let n = U256::from(n_coins);
+    let a_multiplier = U256::from(10_000);
+
+    let mut x = x_unsorted.to_vec();
+    x.sort_by(|a, b| b.cmp(a));
+
+    if x[0] < U256::from(10).pow(U256::from(9)) || x[0] > U256::from(10).pow(U256::from(33)) {
+        return Err(ArbRsError::CalculationError(
+            "newton_d balance out of range".to_string(),
+        ));
+    }
+    // The two-coin pools allow a lighter imbalance than tricrypto.
+    let min_frac = if n_coins == 2 {
+        U256::from(10).pow(U256::from(14))
+    } else {
+        U256::from(10).pow(U256::from(11))
+    };
+    for &x_i in &x[1..] {
+        if x_i * TEN_POW_18 / x[0] < min_frac {
+            return Err(ArbRsError::CalculationError(
+                "newton_d balances too imbalanced".to_string(),
+            ));
+        }
+    }
+
+    let mut d = n * geometric_mean(&x)?;
+    let s: U256 = x.iter().sum();
+
+    for _ in 0..255 {
+        let d_prev = d;
+
+        let k0 = if n_coins == 2 {
+            (TEN_POW_18 * n * n)
+                .checked_mul(x[0])
+                .ok_or(ArbRsError::CalculationError(
+                    "newton_d k0 mul overflow".to_string(),
+                ))?
+                / d
+                * x[1]
+                / d
+        } else {
+            let mut k0 = TEN_POW_18;
+            for &x_i in &x {
+                k0 = k0.checked_mul(x_i).and_then(|k0| k0.checked_mul(n)).ok_or(
+                    ArbRsError::CalculationError("newton_d k0 mul overflow".to_string()),
+                )? / d;
+            }
+            k0
+        };
+        if k0.is_zero() {
+            return Err(ArbRsError::CalculationError(
+                "newton_d k0 is zero".to_string(),
+            ));
+        }
+
+        let g1k0 = (gamma + TEN_POW_18).abs_diff(k0) + U256::from(1);
+
+        // D / (A * N**N) * g1k0**2 / gamma**2
+        let mul1 =
+            (TEN_POW_18 * d / gamma)
+                .checked_mul(g1k0)
+                .ok_or(ArbRsError::CalculationError(
+                    "newton_d mul1 overflow".to_string(),
+                ))?
+                / gamma
+                * g1k0
+                * a_multiplier
+                / ann;
+        // 2 * N * K0 / g1k0
+        let mul2 = U256::from(2) * TEN_POW_18 * n * k0 / g1k0;
+
+        let neg_fprime = (s + s * mul2 / TEN_POW_18 + mul1 * n / k0)
+            .checked_sub(mul2 * d / TEN_POW_18)
+            .filter(|neg_fprime| !neg_fprime.is_zero())
+            .ok_or(ArbRsError::CalculationError(
+                "newton_d fprime underflow".to_string(),
+            ))?;
+
+        // D -= f / fprime
+        let d_plus = d * (neg_fprime + s) / neg_fprime;
+        let mut d_minus = d * d / neg_fprime;
+        let correction = d * (mul1 / neg_fprime) / TEN_POW_18 * TEN_POW_18.abs_diff(k0) / k0;
+        if TEN_POW_18 > k0 {
+            d_minus += correction;
+        } else {
+            d_minus = d_minus
+                .checked_sub(correction)
+                .ok_or(ArbRsError::CalculationError(
+                    "newton_d d_minus underflow".to_string(),
+                ))?;
+        }
+
+        d = if d_plus > d_minus {
+            d_plus - d_minus
+        } else {
+            (d_minus - d_plus) / U256::from(2)
+        };
+
+        let diff = d.abs_diff(d_prev);
+        if diff * U256::from(10).pow(U256::from(14)) < d.max(U256::from(10).pow(U256::from(16))) {
+            // The pool rejects an invariant the next newton_y couldn't work with.
+            for &x_i in &x {
+                let frac = x_i * TEN_POW_18 / d;
+                if frac < U256::from(10).pow(U256::from(16))
+                    || frac > U256::from(10).pow(U256::from(20))
+                {
+                    return Err(ArbRsError::CalculationError(
+                        "Cryptoswap newton_d result out of range".to_string(),
+                    ));
+                }
+            }
+            return Ok(d);
+        }
+    }
+
+    Err(ArbRsError::CalculationError(
+        "Cryptoswap newton_d did not converge".to_string(),
     ))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ANN: U256 = U256::from_limbs([1_707_629, 0, 0, 0]);
+    const GAMMA: U256 = U256::from_limbs([11_809_167_828_997, 0, 0, 0]);
+
+    fn million(amount: u64) -> U256 {
+        U256::from(amount) * U256::from(10).pow(U256::from(24))
+    }
+
+    // Expected values come from running the Vyper `newton_y` of tricrypto2's math contract
+    // on the same inputs.
+    #[test]
+    fn test_newton_y_matches_reference() {
+        let d = million(90);
+        let cases = [
+            (
+                [million(30), million(30), million(30)],
+                1,
+                "30000000000000000024191454",
+            ),
+            (
+                [
+                    million(30) + U256::from(1_000) * TEN_POW_18,
+                    million(30),
+                    million(30),
+                ],
+                1,
+                "29999000000575601124743379",
+            ),
+            (
+                [million(31), million(29), million(30)],
+                2,
+                "30027766227963790684757075",
+            ),
+            (
+                [million(10), million(50), million(30)],
+                0,
+                "17977061689422392186404035",
+            ),
+        ];
+        for (xp, i, expected) in cases {
+            assert_eq!(
+                newton_y(ANN, GAMMA, &xp, d, i).unwrap(),
+                U256::from_str_radix(expected, 10).unwrap()
+            );
+        }
+    }
+
+    // Expected values come from the Vyper `newton_y` of the two-coin crypto pools, with the
+    // CRV/ETH pool's `A` and `gamma`.
+    #[test]
+    fn test_two_coin_newton_y_matches_reference() {
+        let ann = U256::from(400_000);
+        let gamma = U256::from(145_000_000_000_000u64);
+        let d = million(20);
+        let cases = [
+            ([million(10), million(10)], 1, "10000000000000000006239738"),
+            (
+                [million(10) + U256::from(1_000) * TEN_POW_18, million(10)],
+                1,
+                "9999000004762477884957533",
+            ),
+            ([million(11), million(9)], 0, "11094573548601470774942351"),
+            ([million(5), million(16)], 0, "6222281032565099001752281"),
+        ];
+        for (xp, i, expected) in cases {
+            assert_eq!(
+                newton_y(ann, gamma, &xp, d, i).unwrap(),
+                U256::from_str_radix(expected, 10).unwrap()
+            );
+        }
+    }
+
+    /// `newton_y` solving for any coin at the `D` of `xp` gives that coin's balance back.
+    fn assert_consistent(ann: U256, gamma: U256, xp: &[U256]) {
+        let d = newton_d(ann, gamma, xp).unwrap();
+        for (i, x_i) in xp.iter().enumerate() {
+            let y = newton_y(ann, gamma, xp, d, i).unwrap();
+            assert!(
+                y.abs_diff(*x_i) * U256::from(10).pow(U256::from(12)) <= *x_i,
+                "coin {i}: {y} for {x_i}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_newton_d_of_balanced_pool_is_the_sum() {
+        let d = newton_d(ANN, GAMMA, &[million(30); 3]).unwrap();
+        assert!(d.abs_diff(million(90)) * U256::from(10).pow(U256::from(14)) <= million(90));
+
+        let d = newton_d(
+            U256::from(400_000),
+            U256::from(145_000_000_000_000u64),
+            &[million(10); 2],
+        )
+        .unwrap();
+        assert!(d.abs_diff(million(20)) * U256::from(10).pow(U256::from(14)) <= million(20));
+    }
+
+    #[test]
+    fn test_newton_d_is_consistent_with_newton_y() {
+        assert_consistent(ANN, GAMMA, &[million(31), million(29), million(30)]);
+        assert_consistent(ANN, GAMMA, &[million(10), million(50), million(30)]);
+        let (ann, gamma) = (U256::from(400_000), U256::from(145_000_000_000_000u64));
+        assert_consistent(ann, gamma, &[million(11), million(9)]);
+        assert_consistent(ann, gamma, &[million(5), million(16)]);
+    }
+
+    #[test]
+    fn test_newton_d_rejects_unsafe_balances() {
+        assert!(newton_d(ANN, GAMMA, &[U256::ZERO; 3]).is_err());
+        let dust = U256::from(10).pow(U256::from(8));
+        assert!(newton_d(ANN, GAMMA, &[million(30), million(30), dust]).is_err());
+    }
+
+    #[test]
+    fn test_newton_y_rejects_unbalanced_result() {
+        // Coin 0 would converge to well under 1% of `D`, which the pool rejects.
+        let xp = [U256::ZERO, million(1_000), million(1_000)];
+        assert!(newton_y(ANN, GAMMA, &xp, million(90), 0).is_err());
+    }
+}
@@ -1,4 +1,5 @@
-use alloy_primitives::{Address, I256, U256};
+use alloy_primitives::{Address, U256};
+use serde::{Deserialize, Serialize};
 
 /// Holds the state of a Curve Stableswap pool at a specific block.
 #[derive(Clone, Debug, Default)]
@@ -13,10 +14,13 @@ pub struct CurveStableswapPoolState {
 /// Represents the result of a simulated swap on a Curve pool.
 #[derive(Debug, Clone)]
 pub struct CurveStableswapPoolSimulationResult {
-    pub amount0_delta: I256,
-    pub amount1_delta: I256,
-    pub initial_state: CurveStableswapPoolState,
-    pub final_state: CurveStableswapPoolState,
+    pub amount_in: U256,
+    pub amount_out: U256,
+    /// Share of the swap fee moved to the admin balance, in the output coin.
+    pub admin_fee_amount: U256,
+    pub initial_snapshot: CurvePoolSnapshot,
+    /// The pool after the swap, with balances booked the way `exchange` books them.
+    pub final_snapshot: CurvePoolSnapshot,
 }
 
 /// Holds the static attributes of a Curve Stableswap pool.
@@ -37,11 +41,14 @@ pub struct CurveStableSwapPoolStateUpdated {
     pub state: CurveStableswapPoolState,
 }
 
-#[derive(Clone, Debug, Default)]
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
 pub struct CurvePoolSnapshot {
     pub balances: Vec<U256>,
     pub a: U256,
     pub fee: U256,
+    /// Share of `fee` retained for the DAO, scaled like `fee`. `None` for pools without an
+    /// `admin_fee()` getter.
+    pub admin_fee: Option<U256>,
     pub block_timestamp: u64,
     pub base_pool_virtual_price: Option<U256>,
     pub base_pool_lp_total_supply: Option<U256>,
@@ -54,7 +61,26 @@ pub struct CurvePoolSnapshot {
     pub tricrypto_d: Option<U256>,
     pub tricrypto_gamma: Option<U256>,
     pub tricrypto_price_scale: Option<Vec<U256>>,
+    pub mid_fee: Option<U256>,
+    pub out_fee: Option<U256>,
+    pub fee_gamma: Option<U256>,
 
     // Metapool-specific data
     pub scaled_redemption_price: Option<U256>,
+    /// When the external oracle behind `rates` or `scaled_redemption_price` last updated,
+    /// for pools whose oracle exposes it.
+    #[serde(default)]
+    pub oracle_updated_at: Option<u64>,
+    /// A metapool's base pool at the same block, for swaps between its underlying coins.
+    #[serde(default)]
+    pub base_pool_snapshot: Option<Box<CurvePoolSnapshot>>,
+}
+
+impl CurvePoolSnapshot {
+    /// Seconds between the oracle's last update and the snapshot's block. `None` for pools
+    /// without an oracle timestamp.
+    pub fn oracle_age(&self) -> Option<u64> {
+        self.oracle_updated_at
+            .map(|updated_at| self.block_timestamp.saturating_sub(updated_at))
+    }
 }
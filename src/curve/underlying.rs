@@ -0,0 +1,348 @@
+//! A metapool's underlying coins as a pool of their own: its first coin and its base pool's
+//! coins, swapped through `exchange_underlying`, which deposits into or withdraws from the
+//! base pool on the way.
+
+use crate::TokenLike;
+use crate::core::token::Token;
+use crate::curve::constants::FEE_DENOMINATOR;
+use crate::curve::pool::{
+    CurveQuoter, CurveStableswapPool, calc_token_amount, calc_withdraw_one_coin,
+};
+use crate::curve::types::CurvePoolSnapshot;
+use crate::errors::ArbRsError;
+use crate::math::utils::u256_to_f64;
+use crate::pool::last_trade::LastTradeTracker;
+use crate::pool::quoter::{QuotePool, Quoter};
+use crate::pool::{
+    CalibrationBucket, DexKind, LiquidityPool, PoolSnapshot, StateUpdate, SwapGasCosts,
+    price_probe_amount,
+};
+use alloy_primitives::{Address, U256};
+use alloy_provider::Provider;
+use alloy_rpc_types::Log;
+use async_trait::async_trait;
+use std::any::Any;
+use std::fmt::{Debug, Formatter, Result as FmtResult};
+use std::sync::Arc;
+
+/// Swaps between a metapool's underlying coins on one snapshot of the metapool and its
+/// base pool, quoted the way `exchange_underlying` routes them.
+#[derive(Debug, Clone)]
+pub struct UnderlyingCurveQuoter {
+    pub metapool: CurveQuoter,
+    pub base_pool: CurveQuoter,
+    /// The base pool's LP token, which the metapool trades against its first coin.
+    pub lp_token: Address,
+    pub base_pool_lp_total_supply: Option<U256>,
+}
+
+impl UnderlyingCurveQuoter {
+    /// Fails unless `metapool` has a base pool and `snapshot` holds the base pool's.
+    pub fn new<P: Provider + Send + Sync + 'static + ?Sized>(
+        metapool: &CurveStableswapPool<P>,
+        snapshot: &CurvePoolSnapshot,
+    ) -> Result<Self, ArbRsError> {
+        let base_pool = metapool
+            .base_pool
+            .as_ref()
+            .ok_or_else(|| ArbRsError::CalculationError("Not a metapool".to_string()))?;
+        let base_snapshot = snapshot.base_pool_snapshot.as_deref().ok_or_else(|| {
+            ArbRsError::CalculationError("Missing base pool snapshot".to_string())
+        })?;
+        Ok(Self {
+            metapool: metapool.quoter(snapshot),
+            base_pool: base_pool.quoter(base_snapshot),
+            lp_token: base_pool.lp_token.address(),
+            base_pool_lp_total_supply: snapshot.base_pool_lp_total_supply,
+        })
+    }
+
+    /// Index of `token` among the underlying coins: 0 for the metapool's first coin, then
+    /// the base pool's coins in order, as `exchange_underlying` numbers them.
+    pub fn underlying_index(&self, token: Address) -> Option<usize> {
+        if self.metapool.coins.first() == Some(&token) {
+            return Some(0);
+        }
+        self.base_pool
+            .coins
+            .iter()
+            .position(|coin| *coin == token)
+            .map(|index| index + 1)
+    }
+
+    fn lp_total_supply(&self) -> Result<U256, ArbRsError> {
+        self.base_pool_lp_total_supply
+            .ok_or_else(|| ArbRsError::CalculationError("Missing base pool LP supply".into()))
+    }
+}
+
+impl Quoter for UnderlyingCurveQuoter {
+    fn calculate_out(
+        &self,
+        token_in: Address,
+        token_out: Address,
+        amount_in: U256,
+    ) -> Result<U256, ArbRsError> {
+        let i = self
+            .underlying_index(token_in)
+            .ok_or_else(|| ArbRsError::CalculationError("Underlying In not found".to_string()))?;
+        let j = self
+            .underlying_index(token_out)
+            .ok_or_else(|| ArbRsError::CalculationError("Underlying Out not found".to_string()))?;
+        let base = &self.base_pool;
+
+        match (i, j) {
+            _ if i == j => Err(ArbRsError::CalculationError(
+                "Cannot swap a token for itself.".to_string(),
+            )),
+            // The metapool's coin for LP tokens, withdrawn from the base pool as one coin.
+            (0, j) => {
+                let lp_amount = self
+                    .metapool
+                    .calculate_out(token_in, self.lp_token, amount_in)?;
+                let (dy, _fee) = calc_withdraw_one_coin(
+                    base.address,
+                    &base.attributes,
+                    lp_amount,
+                    j - 1,
+                    &base.snapshot,
+                    self.lp_total_supply()?,
+                )?;
+                Ok(dy)
+            }
+            // A one-coin deposit into the base pool, then its LP tokens for the metapool's
+            // coin. The deposit pays half the swap fee, the way the base pool charges an
+            // imbalanced `add_liquidity`.
+            (i, 0) => {
+                let mut amounts = vec![U256::ZERO; base.attributes.n_coins];
+                amounts[i - 1] = amount_in;
+                let lp_amount = calc_token_amount(
+                    &base.attributes,
+                    &amounts,
+                    true,
+                    &base.snapshot,
+                    self.lp_total_supply()?,
+                )?;
+                let fee = lp_amount * base.snapshot.fee / (FEE_DENOMINATOR * U256::from(2));
+                self.metapool
+                    .calculate_out(self.lp_token, token_out, lp_amount.saturating_sub(fee))
+            }
+            _ => base.calculate_out(token_in, token_out, amount_in),
+        }
+    }
+
+    fn calculate_in(
+        &self,
+        _token_in: Address,
+        _token_out: Address,
+        _amount_out: U256,
+    ) -> Result<U256, ArbRsError> {
+        Err(ArbRsError::CalculationError(format!(
+            "Metapool {} quotes no exact output between underlying coins",
+            self.metapool.address
+        )))
+    }
+}
+
+/// A metapool as a pool between its underlying coins. Shares the metapool's address,
+/// snapshot and swap logs; swaps against the base pool's LP token stay with the metapool.
+pub struct UnderlyingCurvePool<P: Provider + Send + Sync + 'static + ?Sized> {
+    metapool: Arc<CurveStableswapPool<P>>,
+}
+
+impl<P: Provider + Send + Sync + 'static + ?Sized> UnderlyingCurvePool<P> {
+    /// `None` unless `metapool` has a base pool.
+    pub fn new(metapool: Arc<CurveStableswapPool<P>>) -> Option<Self> {
+        metapool.base_pool.is_some().then_some(Self { metapool })
+    }
+
+    pub fn metapool(&self) -> &Arc<CurveStableswapPool<P>> {
+        &self.metapool
+    }
+
+    /// Index of `token` among the underlying coins, as `exchange_underlying` takes it.
+    pub fn underlying_index(&self, token: &Token<P>) -> Option<usize> {
+        self.metapool
+            .underlying_tokens
+            .iter()
+            .position(|coin| **coin == *token)
+    }
+
+    fn quoter(&self, snapshot: &PoolSnapshot) -> Result<UnderlyingCurveQuoter, ArbRsError> {
+        let PoolSnapshot::Curve(curve_snapshot) = snapshot else {
+            return Err(ArbRsError::CalculationError(
+                "Invalid snapshot type for Curve pool".to_string(),
+            ));
+        };
+        UnderlyingCurveQuoter::new(&self.metapool, curve_snapshot)
+    }
+
+    fn is_base_coin(&self, token: Address) -> bool {
+        self.metapool
+            .base_pool
+            .as_ref()
+            .is_some_and(|base_pool| base_pool.tokens.iter().any(|coin| coin.address() == token))
+    }
+}
+
+#[async_trait]
+impl<P: Provider + Send + Sync + 'static + ?Sized> LiquidityPool<P> for UnderlyingCurvePool<P> {
+    fn address(&self) -> Address {
+        self.metapool.address
+    }
+
+    fn get_all_tokens(&self) -> Vec<Arc<Token<P>>> {
+        self.metapool.underlying_tokens.clone()
+    }
+
+    /// Two base pool coins trade for less gas through the base pool itself.
+    fn trades_pair(&self, token_a: Address, token_b: Address) -> bool {
+        !(self.is_base_coin(token_a) && self.is_base_coin(token_b))
+    }
+
+    /// The metapool's first coin, then the base pool's coins, net of admin fees.
+    fn reserves_summary(&self, snapshot: &PoolSnapshot) -> Vec<(Arc<Token<P>>, U256)> {
+        let PoolSnapshot::Curve(state) = snapshot else {
+            return Vec::new();
+        };
+        let (Some(first), Some(base_state)) =
+            (state.balances.first(), state.base_pool_snapshot.as_ref())
+        else {
+            return Vec::new();
+        };
+        self.metapool
+            .underlying_tokens
+            .iter()
+            .cloned()
+            .zip(std::iter::once(*first).chain(base_state.balances.iter().copied()))
+            .collect()
+    }
+
+    /// Updates the metapool and its base pool, whose balances the swaps go through too.
+    async fn update_state(&self) -> Result<StateUpdate, ArbRsError> {
+        let Some(base_pool) = &self.metapool.base_pool else {
+            return self.metapool.update_state().await;
+        };
+        let updates = tokio::try_join!(self.metapool.update_state(), base_pool.update_state())?;
+        Ok(match updates {
+            (StateUpdate::Updated { block }, _) | (_, StateUpdate::Updated { block }) => {
+                StateUpdate::Updated { block }
+            }
+            _ => StateUpdate::Unchanged,
+        })
+    }
+
+    async fn invalidate_from(&self, block_number: u64) {
+        self.metapool.invalidate_from(block_number).await;
+    }
+
+    /// The metapool's caches are pruned, but counted in its own `cache_stats` only.
+    async fn discard_states_before_block(&self, block: u64) {
+        self.metapool.discard_states_before_block(block).await;
+    }
+
+    async fn get_snapshot(&self, block_number: Option<u64>) -> Result<PoolSnapshot, ArbRsError> {
+        self.metapool.get_snapshot(block_number).await
+    }
+
+    fn calculate_tokens_out(
+        &self,
+        token_in: &Token<P>,
+        token_out: &Token<P>,
+        amount_in: U256,
+        snapshot: &PoolSnapshot,
+    ) -> Result<U256, ArbRsError> {
+        self.quoter(snapshot)?
+            .calculate_out(token_in.address(), token_out.address(), amount_in)
+    }
+
+    fn calculate_tokens_in(
+        &self,
+        token_in: &Token<P>,
+        token_out: &Token<P>,
+        amount_out: U256,
+        snapshot: &PoolSnapshot,
+    ) -> Result<U256, ArbRsError> {
+        self.quoter(snapshot)?
+            .calculate_in(token_in.address(), token_out.address(), amount_out)
+    }
+
+    fn to_quoter(&self, snapshot: &PoolSnapshot) -> Result<QuotePool, ArbRsError> {
+        Ok(QuotePool::CurveUnderlying(self.quoter(snapshot)?))
+    }
+
+    async fn nominal_price(
+        &self,
+        token_in: &Token<P>,
+        token_out: &Token<P>,
+    ) -> Result<f64, ArbRsError> {
+        let price = self.absolute_price(token_in, token_out).await?;
+        let scale_factor = 10f64.powi(token_in.decimals() as i32 - token_out.decimals() as i32);
+        Ok(price * scale_factor)
+    }
+
+    async fn absolute_price(
+        &self,
+        token_in: &Token<P>,
+        token_out: &Token<P>,
+    ) -> Result<f64, ArbRsError> {
+        let snapshot = self.get_snapshot(None).await?;
+        let amount_in = price_probe_amount(token_in);
+        let amount_out = self.calculate_tokens_out(token_in, token_out, amount_in, &snapshot)?;
+
+        if amount_in.is_zero() || amount_out.is_zero() {
+            return Err(ArbRsError::CalculationError(
+                "Cannot calculate price: input reserve is zero".to_string(),
+            ));
+        }
+
+        Ok(u256_to_f64(amount_out) / u256_to_f64(amount_in))
+    }
+
+    async fn absolute_exchange_rate(
+        &self,
+        token_in: &Token<P>,
+        token_out: &Token<P>,
+    ) -> Result<f64, ArbRsError> {
+        self.absolute_price(token_in, token_out).await
+    }
+
+    fn last_trade_tracker(&self) -> Option<&LastTradeTracker> {
+        self.metapool.last_trade_tracker()
+    }
+
+    fn record_swap_log(&self, log: &Log) -> bool {
+        self.metapool.record_swap_log(log)
+    }
+
+    fn calibration_bucket(&self) -> Option<CalibrationBucket> {
+        Some(CalibrationBucket::new("curve", "Underlying"))
+    }
+
+    fn dex_kind(&self) -> Option<DexKind> {
+        Some(DexKind::Curve)
+    }
+
+    fn gas_estimate(
+        &self,
+        _token_in: &Token<P>,
+        _token_out: &Token<P>,
+        _amount_in: U256,
+        _snapshot: &PoolSnapshot,
+        costs: &SwapGasCosts,
+    ) -> u64 {
+        costs.curve_underlying
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+impl<P: Provider + Send + Sync + 'static + ?Sized> Debug for UnderlyingCurvePool<P> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.debug_struct("UnderlyingCurvePool")
+            .field("metapool", &self.metapool.address)
+            .finish_non_exhaustive()
+    }
+}
@@ -1,27 +1,58 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::ops::RangeInclusive;
 use std::str::FromStr;
 use std::sync::Arc;
 
 use crate::TokenLike;
+use crate::arbitrage::calibration::CalibrationStats;
+use crate::arbitrage::health::PoolHealthRecord;
+use crate::arbitrage::recorder::{OpportunityRecord, PathProfit, rank_paths};
+use crate::arbitrage::shadow::{ShadowPnlRow, ShadowRecord, summarize};
 use crate::core::token::Token;
-use alloy_primitives::Address;
+use crate::core::token_policy::{TokenPolicy, TokenPolicyMode};
+use crate::dex::PoolKind;
+use crate::manager::token_manager::TokenFailure;
+use crate::math::v3::tick_bitmap;
+use crate::pool::CalibrationBucket;
+use crate::pool::uniswap_v3::TickInfo;
+use crate::pool::uniswap_v3_snapshot::LiquidityMap;
+use crate::pool::uniswap_v4::PoolKey;
+use alloy_primitives::{Address, B256, U256};
 use alloy_provider::Provider;
-use sqlx::sqlite::{SqlitePool, SqlitePoolOptions};
-use sqlx::{Row, Transaction};
+use sqlx::any::AnyPoolOptions;
+use sqlx::encode::IsNull;
+use sqlx::error::BoxDynError;
+use sqlx::migrate::Migrator;
+use sqlx::{AnyPool, Database, Decode, Encode, Row, Transaction, Type};
+
+static SQLITE_MIGRATOR: Migrator = sqlx::migrate!("migrations/sqlite");
+static POSTGRES_MIGRATOR: Migrator = sqlx::migrate!("migrations/postgres");
 
 /// A struct to represent a pool's data when loaded from the database.
 #[derive(Debug, Clone)]
 pub struct PoolRecord {
     pub address: Address,
-    pub dex: String,
+    pub dex: PoolKind,
     pub tokens: Vec<Address>,
     pub fee: Option<u32>,
     pub tick_spacing: Option<i32>,
     pub attributes_json: Option<String>,
+    /// Pool id and vault of a Balancer pool, stored once the pool has been built.
+    pub balancer_pool_id: Option<B256>,
+    pub balancer_vault: Option<Address>,
+    /// LP token of a Curve pool, stored once the pool has been built.
+    pub lp_token: Option<Address>,
+    /// Pool manager and key of a Uniswap V4 pool.
+    pub v4_pool_manager: Option<Address>,
+    pub v4_pool_key: Option<PoolKey>,
 }
 
 /// Manages all database connections and queries.
+///
+/// Any URL supported by sqlx's `Any` driver is accepted. sqlite is the default; Postgres
+/// requires building with the `postgres` feature.
 pub struct DbManager {
-    pool: SqlitePool,
+    pool: AnyPool,
 }
 
 #[derive(Debug, Clone)]
@@ -29,23 +60,50 @@ pub struct TokenRecord {
     pub address: Address,
     pub symbol: String,
     pub decimals: u8,
+    /// `None` until the token's transfer behavior has been analysed.
+    pub transfer_tax_bps: Option<u32>,
 }
 
 impl DbManager {
+    /// Connects to `db_url` and applies any pending migrations for its dialect.
     pub async fn new(db_url: &str) -> Result<Self, sqlx::Error> {
-        let pool = SqlitePoolOptions::new()
-            .max_connections(5)
-            .connect(db_url)
-            .await?;
+        sqlx::any::install_default_drivers();
+
+        let migrator = if db_url.starts_with("postgres") {
+            &POSTGRES_MIGRATOR
+        } else {
+            &SQLITE_MIGRATOR
+        };
+
+        // Every new connection to `sqlite::memory:` opens a fresh database, so an in-memory
+        // database must live on a single connection that is never recycled.
+        let options = if db_url.contains(":memory:") || db_url.contains("mode=memory") {
+            AnyPoolOptions::new()
+                .max_connections(1)
+                .min_connections(1)
+                .idle_timeout(None)
+                .max_lifetime(None)
+        } else {
+            AnyPoolOptions::new().max_connections(5)
+        };
+        let pool = options.connect(db_url).await?;
+
+        migrator.run(&pool).await?;
         Ok(Self { pool })
     }
 
+    /// Waits for queries in flight and closes every connection, so sqlite checkpoints its
+    /// write-ahead log. Queries made afterwards fail.
+    pub async fn shutdown(&self) {
+        self.pool.close().await;
+    }
+
     pub async fn save_token<P: Provider + Send + Sync + 'static + ?Sized>(
         &self,
         token: &Token<P>,
     ) -> Result<(), sqlx::Error> {
-        sqlx::query("INSERT OR IGNORE INTO tokens (address, symbol, decimals) VALUES (?, ?, ?)")
-            .bind(token.address().to_string())
+        sqlx::query(INSERT_TOKEN)
+            .bind(encode_address(token.address()))
             .bind(token.symbol())
             .bind(token.decimals() as i64)
             .execute(&self.pool)
@@ -56,30 +114,43 @@ impl DbManager {
     pub async fn save_pool(
         &self,
         address: Address,
-        dex: &str,
+        dex: &PoolKind,
         tokens: &[Arc<Token<impl Provider + Send + Sync + 'static + ?Sized>>],
         fee: Option<u32>,
         tick_spacing: Option<i32>,
     ) -> Result<(), sqlx::Error> {
         let mut tx = self.pool.begin().await?;
 
-        let pool_id: i64 = sqlx::query("INSERT OR IGNORE INTO pools (address, chain_id, dex, fee, tick_spacing) VALUES (?, ?, ?, ?, ?); SELECT last_insert_rowid();")
-            .bind(address.to_string())
-            .bind(1) // Assuming chain_id 1
-            .bind(dex)
-            .bind(fee.map(|f| f as i64))
-            .bind(tick_spacing.map(|ts| ts as i64))
+        sqlx::query(
+            "INSERT INTO pools (address, chain_id, dex, fee, tick_spacing) VALUES ($1, $2, $3, $4, $5)
+             ON CONFLICT (address) DO NOTHING",
+        )
+        .bind(encode_address(address))
+        .bind(1i64) // Assuming chain_id 1
+        .bind(dex)
+        .bind(fee.map(|f| f as i64))
+        .bind(tick_spacing.map(|ts| ts as i64))
+        .execute(&mut *tx)
+        .await?;
+
+        // The insert may have been skipped, so look the id up rather than relying on the last rowid.
+        let pool_id: i64 = sqlx::query("SELECT id FROM pools WHERE address = $1")
+            .bind(encode_address(address))
             .fetch_one(&mut *tx)
             .await?
             .get(0);
 
-        for token in tokens {
+        for (position, token) in tokens.iter().enumerate() {
             self.save_token_in_tx(token, &mut tx).await?;
-            sqlx::query("INSERT OR IGNORE INTO pool_tokens (pool_id, token_address) VALUES (?, ?)")
-                .bind(pool_id)
-                .bind(token.address().to_string())
-                .execute(&mut *tx)
-                .await?;
+            sqlx::query(
+                "INSERT INTO pool_tokens (pool_id, token_address, position) VALUES ($1, $2, $3)
+                 ON CONFLICT (pool_id, token_address) DO NOTHING",
+            )
+            .bind(pool_id)
+            .bind(encode_address(token.address()))
+            .bind(position as i64)
+            .execute(&mut *tx)
+            .await?;
         }
 
         tx.commit().await?;
@@ -89,10 +160,10 @@ impl DbManager {
     async fn save_token_in_tx<'a, P: Provider + Send + Sync + 'static + ?Sized>(
         &self,
         token: &Token<P>,
-        tx: &mut Transaction<'a, sqlx::Sqlite>,
+        tx: &mut Transaction<'a, sqlx::Any>,
     ) -> Result<(), sqlx::Error> {
-        sqlx::query("INSERT OR IGNORE INTO tokens (address, symbol, decimals) VALUES (?, ?, ?)")
-            .bind(token.address().to_string())
+        sqlx::query(INSERT_TOKEN)
+            .bind(encode_address(token.address()))
             .bind(token.symbol())
             .bind(token.decimals() as i64)
             .execute(&mut **tx)
@@ -101,37 +172,64 @@ impl DbManager {
     }
 
     pub async fn load_all_pools(&self) -> Result<Vec<PoolRecord>, sqlx::Error> {
-        let rows = sqlx::query(
-            "SELECT p.address, p.dex, p.fee, p.tick_spacing, p.attributes_json, GROUP_CONCAT(pt.token_address) as tokens
-             FROM pools p
-             JOIN pool_tokens pt ON p.id = pt.pool_id
-             GROUP BY p.id",
+        let token_rows = sqlx::query(
+            "SELECT pool_id, token_address FROM pool_tokens ORDER BY pool_id, position",
         )
         .fetch_all(&self.pool)
         .await?;
 
+        let mut pool_tokens: HashMap<i64, Vec<Address>> = HashMap::new();
+        for row in token_rows {
+            pool_tokens
+                .entry(row.get("pool_id"))
+                .or_default()
+                .push(decode_address(&row.get::<String, _>("token_address"))?);
+        }
+
+        let rows = sqlx::query(&format!("SELECT {POOL_COLUMNS} FROM pools ORDER BY id"))
+            .fetch_all(&self.pool)
+            .await?;
+
         let mut records = Vec::new();
         for row in rows {
-            let token_addresses_str: String = row.get("tokens");
-            let tokens = token_addresses_str
-                .split(',')
-                .map(|s| s.parse::<Address>().unwrap())
-                .collect();
-
-            records.push(PoolRecord {
-                address: row.get::<String, _>("address").parse().unwrap(),
-                dex: row.get("dex"),
-                tokens,
-                fee: row.get::<Option<i64>, _>("fee").map(|f| f as u32),
-                tick_spacing: row
-                    .get::<Option<i64>, _>("tick_spacing")
-                    .map(|ts| ts as i32),
-                attributes_json: row.get("attributes_json"), // <-- POPULATE THE NEW FIELD
-            });
+            // Pools without any linked tokens can't be rebuilt, so skip them.
+            let Some(tokens) = pool_tokens.remove(&row.get::<i64, _>("id")) else {
+                continue;
+            };
+            records.push(decode_pool(&row, tokens)?);
         }
         Ok(records)
     }
 
+    /// The stored record of `pool_address`, `None` if it isn't stored or has no tokens.
+    pub async fn load_pool(
+        &self,
+        pool_address: Address,
+    ) -> Result<Option<PoolRecord>, sqlx::Error> {
+        let Some(row) = sqlx::query(&format!(
+            "SELECT {POOL_COLUMNS} FROM pools WHERE address = $1"
+        ))
+        .bind(encode_address(pool_address))
+        .fetch_optional(&self.pool)
+        .await?
+        else {
+            return Ok(None);
+        };
+        let tokens = sqlx::query(
+            "SELECT token_address FROM pool_tokens WHERE pool_id = $1 ORDER BY position",
+        )
+        .bind(row.get::<i64, _>("id"))
+        .fetch_all(&self.pool)
+        .await?
+        .iter()
+        .map(|row| decode_address(&row.get::<String, _>("token_address")))
+        .collect::<Result<Vec<_>, _>>()?;
+        if tokens.is_empty() {
+            return Ok(None);
+        }
+        decode_pool(&row, tokens).map(Some)
+    }
+
     /// Retrieves the last block number the bot successfully scanned.
     pub async fn get_last_seen_block(&self) -> Result<u64, sqlx::Error> {
         let row = sqlx::query("SELECT value FROM bot_state WHERE key = 'last_seen_block'")
@@ -143,7 +241,7 @@ impl DbManager {
 
     /// Updates the last scanned block number in the database.
     pub async fn update_last_seen_block(&self, block_number: u64) -> Result<(), sqlx::Error> {
-        sqlx::query("UPDATE bot_state SET value = ? WHERE key = 'last_seen_block'")
+        sqlx::query("UPDATE bot_state SET value = $1 WHERE key = 'last_seen_block'")
             .bind(block_number.to_string())
             .execute(&self.pool)
             .await?;
@@ -156,28 +254,740 @@ impl DbManager {
         pool_address: Address,
         attributes_json: &str,
     ) -> Result<(), sqlx::Error> {
-        sqlx::query("UPDATE pools SET attributes_json = ? WHERE address = ?")
+        sqlx::query("UPDATE pools SET attributes_json = $1 WHERE address = $2")
             .bind(attributes_json)
-            .bind(pool_address.to_string())
+            .bind(encode_address(pool_address))
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Stores the pool id and vault of a Balancer pool, and its kind as `attributes_json`.
+    pub async fn update_balancer_metadata(
+        &self,
+        pool_address: Address,
+        pool_id: B256,
+        vault: Address,
+        attributes_json: &str,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "UPDATE pools SET balancer_pool_id = $1, balancer_vault = $2, attributes_json = $3
+             WHERE address = $4",
+        )
+        .bind(format!("{:#x}", pool_id))
+        .bind(encode_address(vault))
+        .bind(attributes_json)
+        .bind(encode_address(pool_address))
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Stores the LP token of a Curve pool.
+    pub async fn update_pool_lp_token(
+        &self,
+        pool_address: Address,
+        lp_token: Address,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query("UPDATE pools SET lp_token = $1 WHERE address = $2")
+            .bind(encode_address(lp_token))
+            .bind(encode_address(pool_address))
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Stores the pool manager and key of a Uniswap V4 pool, its fee and tick spacing
+    /// included.
+    pub async fn update_v4_pool_key(
+        &self,
+        pool_address: Address,
+        pool_manager: Address,
+        key: &PoolKey,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "UPDATE pools SET v4_pool_manager = $1, v4_currency0 = $2, v4_currency1 = $3,
+             v4_hooks = $4, fee = $5, tick_spacing = $6 WHERE address = $7",
+        )
+        .bind(encode_address(pool_manager))
+        .bind(encode_address(key.currency0))
+        .bind(encode_address(key.currency1))
+        .bind(encode_address(key.hooks))
+        .bind(key.fee as i64)
+        .bind(key.tick_spacing as i64)
+        .bind(encode_address(pool_address))
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Overwrites a pool's fee and tick spacing, e.g. after correcting them from the chain.
+    pub async fn update_pool_fee_tier(
+        &self,
+        pool_address: Address,
+        fee: u32,
+        tick_spacing: i32,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query("UPDATE pools SET fee = $1, tick_spacing = $2 WHERE address = $3")
+            .bind(fee as i64)
+            .bind(tick_spacing as i64)
+            .bind(encode_address(pool_address))
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Reads a value from the `bot_state` key/value table.
+    pub async fn get_state(&self, key: &str) -> Result<Option<String>, sqlx::Error> {
+        let row = sqlx::query("SELECT value FROM bot_state WHERE key = $1")
+            .bind(key)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(row.map(|row| row.get("value")))
+    }
+
+    /// Inserts or overwrites a value in the `bot_state` key/value table.
+    pub async fn set_state(&self, key: &str, value: &str) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "INSERT INTO bot_state (key, value) VALUES ($1, $2)
+             ON CONFLICT (key) DO UPDATE SET value = excluded.value",
+        )
+        .bind(key)
+        .bind(value)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Addresses of every pool stored in the database.
+    pub async fn load_pool_addresses(&self) -> Result<HashSet<Address>, sqlx::Error> {
+        let rows = sqlx::query("SELECT address FROM pools")
+            .fetch_all(&self.pool)
+            .await?;
+        rows.iter()
+            .map(|row| decode_address(&row.get::<String, _>("address")))
+            .collect()
+    }
+
+    /// Records that building `pool_address` failed, replacing any earlier error.
+    pub async fn record_pool_failure(
+        &self,
+        pool_address: Address,
+        source: &str,
+        error: &str,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "INSERT INTO pool_build_failures (address, source, error) VALUES ($1, $2, $3)
+             ON CONFLICT (address) DO UPDATE SET source = excluded.source, error = excluded.error",
+        )
+        .bind(encode_address(pool_address))
+        .bind(source)
+        .bind(error)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Recorded build failures as `(address, source, error)`.
+    pub async fn load_pool_failures(&self) -> Result<Vec<(Address, String, String)>, sqlx::Error> {
+        let rows =
+            sqlx::query("SELECT address, source, error FROM pool_build_failures ORDER BY address")
+                .fetch_all(&self.pool)
+                .await?;
+        rows.iter()
+            .map(|row| {
+                Ok((
+                    decode_address(&row.get::<String, _>("address"))?,
+                    row.get("source"),
+                    row.get("error"),
+                ))
+            })
+            .collect()
+    }
+
+    pub async fn clear_pool_failure(&self, pool_address: Address) -> Result<(), sqlx::Error> {
+        sqlx::query("DELETE FROM pool_build_failures WHERE address = $1")
+            .bind(encode_address(pool_address))
             .execute(&self.pool)
             .await?;
         Ok(())
     }
 
+    /// Inserts or overwrites a calibration bucket's estimate.
+    pub async fn save_calibration(
+        &self,
+        bucket: &CalibrationBucket,
+        stats: &CalibrationStats,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "INSERT INTO calibration_buckets (pool_type, variant, samples, mean_bps, variance_bps)
+             VALUES ($1, $2, $3, $4, $5)
+             ON CONFLICT (pool_type, variant) DO UPDATE SET samples = excluded.samples,
+             mean_bps = excluded.mean_bps, variance_bps = excluded.variance_bps",
+        )
+        .bind(&bucket.pool_type)
+        .bind(&bucket.variant)
+        .bind(stats.samples as i64)
+        .bind(stats.mean_bps)
+        .bind(stats.variance_bps)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn load_calibration(
+        &self,
+    ) -> Result<Vec<(CalibrationBucket, CalibrationStats)>, sqlx::Error> {
+        let rows = sqlx::query(
+            "SELECT pool_type, variant, samples, mean_bps, variance_bps FROM calibration_buckets",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows
+            .iter()
+            .map(|row| {
+                (
+                    CalibrationBucket::new(
+                        row.get::<String, _>("pool_type"),
+                        row.get::<String, _>("variant"),
+                    ),
+                    CalibrationStats {
+                        samples: row.get::<i64, _>("samples") as u64,
+                        mean_bps: row.get("mean_bps"),
+                        variance_bps: row.get("variance_bps"),
+                    },
+                )
+            })
+            .collect())
+    }
+
+    /// Replaces the stored pool health with `records`, whose `retry_at` counts the evaluations
+    /// left before the pool is retried rather than an evaluation number.
+    pub async fn save_pool_health(
+        &self,
+        records: &[(Address, PoolHealthRecord)],
+    ) -> Result<(), sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("DELETE FROM pool_health")
+            .execute(&mut *tx)
+            .await?;
+        for (pool, record) in records {
+            sqlx::query(
+                "INSERT INTO pool_health (address, consecutive_failures, retry_in) VALUES ($1, $2, $3)",
+            )
+            .bind(encode_address(*pool))
+            .bind(record.consecutive_failures as i64)
+            .bind(record.retry_at.map(|retry_in| retry_in as i64))
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Pool health saved by [`Self::save_pool_health`], `retry_at` still relative.
+    pub async fn load_pool_health(&self) -> Result<Vec<(Address, PoolHealthRecord)>, sqlx::Error> {
+        let rows = sqlx::query("SELECT address, consecutive_failures, retry_in FROM pool_health")
+            .fetch_all(&self.pool)
+            .await?;
+        rows.iter()
+            .map(|row| {
+                Ok((
+                    decode_address(&row.get::<String, _>("address"))?,
+                    PoolHealthRecord {
+                        consecutive_failures: row.get::<i64, _>("consecutive_failures") as u32,
+                        retry_at: row
+                            .get::<Option<i64>, _>("retry_in")
+                            .map(|retry_in| retry_in as u64),
+                    },
+                ))
+            })
+            .collect()
+    }
+
+    pub async fn save_shadow_record(&self, record: &ShadowRecord) -> Result<(), sqlx::Error> {
+        let pools: Vec<String> = record.pools.iter().copied().map(encode_address).collect();
+        sqlx::query(
+            "INSERT INTO shadow_records (block, realized_block, day, pools, path_type, profit_token,
+             input, expected_out, realized_out, flashloan_fee, gas_cost)
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
+             ON CONFLICT (block, profit_token) DO NOTHING",
+        )
+        .bind(record.block as i64)
+        .bind(record.realized_block as i64)
+        .bind(record.day as i64)
+        .bind(pools.join(","))
+        .bind(&record.path_type)
+        .bind(encode_address(record.profit_token))
+        .bind(encode_u256(record.input))
+        .bind(encode_u256(record.expected_out))
+        .bind(record.realized_out.map(encode_u256))
+        .bind(encode_u256(record.flashloan_fee))
+        .bind(encode_u256(record.gas_cost))
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Shadow records of solutions found within `blocks`, oldest first.
+    pub async fn load_shadow_records(
+        &self,
+        blocks: RangeInclusive<u64>,
+    ) -> Result<Vec<ShadowRecord>, sqlx::Error> {
+        let rows = sqlx::query(
+            "SELECT block, realized_block, day, pools, path_type, profit_token, input,
+             expected_out, realized_out, flashloan_fee, gas_cost FROM shadow_records
+             WHERE block >= $1 AND block <= $2 ORDER BY block, profit_token",
+        )
+        .bind(*blocks.start() as i64)
+        .bind(*blocks.end() as i64)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter()
+            .map(|row| {
+                Ok(ShadowRecord {
+                    block: row.get::<i64, _>("block") as u64,
+                    realized_block: row.get::<i64, _>("realized_block") as u64,
+                    day: row.get::<i64, _>("day") as u64,
+                    pools: row
+                        .get::<String, _>("pools")
+                        .split(',')
+                        .filter(|pool| !pool.is_empty())
+                        .map(decode_address)
+                        .collect::<Result<_, _>>()?,
+                    path_type: row.get("path_type"),
+                    profit_token: decode_address(&row.get::<String, _>("profit_token"))?,
+                    input: decode_u256(&row.get::<String, _>("input"))?,
+                    expected_out: decode_u256(&row.get::<String, _>("expected_out"))?,
+                    realized_out: row
+                        .get::<Option<String>, _>("realized_out")
+                        .map(|value| decode_u256(&value))
+                        .transpose()?,
+                    flashloan_fee: decode_u256(&row.get::<String, _>("flashloan_fee"))?,
+                    gas_cost: decode_u256(&row.get::<String, _>("gas_cost"))?,
+                })
+            })
+            .collect()
+    }
+
+    /// Expected against realized shadow PnL of the solutions found within `blocks`, by day,
+    /// path type and profit token.
+    pub async fn shadow_pnl_summary(
+        &self,
+        blocks: RangeInclusive<u64>,
+    ) -> Result<Vec<ShadowPnlRow>, sqlx::Error> {
+        Ok(summarize(&self.load_shadow_records(blocks).await?))
+    }
+
+    pub async fn record_opportunity(&self, record: &OpportunityRecord) -> Result<(), sqlx::Error> {
+        self.record_opportunities(std::slice::from_ref(record))
+            .await
+    }
+
+    /// Saves `records` in a single transaction. A path already recorded at a block is kept.
+    pub async fn record_opportunities(
+        &self,
+        records: &[OpportunityRecord],
+    ) -> Result<(), sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        for record in records {
+            let pools: Vec<String> = record.pools.iter().copied().map(encode_address).collect();
+            sqlx::query(
+                "INSERT INTO opportunities (block_number, path_hash, pools, profit_token,
+                 optimal_input, gross_profit, net_profit, net_profit_weth, gas_price, recorded_at)
+                 VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+                 ON CONFLICT (block_number, path_hash) DO NOTHING",
+            )
+            .bind(record.block_number as i64)
+            .bind(record.path_hash.to_string())
+            .bind(pools.join(","))
+            .bind(encode_address(record.profit_token))
+            .bind(encode_u256(record.optimal_input))
+            .bind(encode_u256(record.gross_profit))
+            .bind(encode_u256(record.net_profit))
+            .bind(encode_u256(record.net_profit_weth))
+            .bind(encode_u256(record.gas_price))
+            .bind(record.recorded_at as i64)
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Paths recorded from `since_block` on, by cumulative net profit in wei, at most `limit`.
+    pub async fn top_paths_by_cumulative_profit(
+        &self,
+        since_block: u64,
+        limit: usize,
+    ) -> Result<Vec<PathProfit>, sqlx::Error> {
+        let rows = sqlx::query(&format!(
+            "{SELECT_OPPORTUNITIES} WHERE block_number >= $1 ORDER BY block_number"
+        ))
+        .bind(since_block as i64)
+        .fetch_all(&self.pool)
+        .await?;
+        let records = rows
+            .iter()
+            .map(decode_opportunity)
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut ranked = rank_paths(&records);
+        ranked.truncate(limit);
+        Ok(ranked)
+    }
+
+    /// Every recorded opportunity of a path, oldest first.
+    pub async fn opportunity_history(
+        &self,
+        path_hash: B256,
+    ) -> Result<Vec<OpportunityRecord>, sqlx::Error> {
+        let rows = sqlx::query(&format!(
+            "{SELECT_OPPORTUNITIES} WHERE path_hash = $1 ORDER BY block_number"
+        ))
+        .bind(path_hash.to_string())
+        .fetch_all(&self.pool)
+        .await?;
+        rows.iter().map(decode_opportunity).collect()
+    }
+
+    /// Replaces a V3 pool's stored liquidity map with `map`, as read at `block_number`.
+    /// Ticks are stored with the bitmap word `tick_spacing` puts them in.
+    pub async fn save_liquidity_map(
+        &self,
+        pool_address: Address,
+        tick_spacing: i32,
+        block_number: u64,
+        map: &LiquidityMap,
+    ) -> Result<(), sqlx::Error> {
+        let mut words: BTreeMap<i16, (U256, Vec<(i32, TickInfo)>)> = map
+            .tick_bitmap
+            .iter()
+            .map(|(&word, &bitmap)| (word, (bitmap, Vec::new())))
+            .collect();
+        for (&tick, info) in &map.tick_data {
+            let (word, _) = tick_bitmap::position(tick / tick_spacing);
+            words.entry(word).or_default().1.push((tick, info.clone()));
+        }
+
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("DELETE FROM v3_tick_words WHERE pool_address = $1")
+            .bind(encode_address(pool_address))
+            .execute(&mut *tx)
+            .await?;
+        for (word, (bitmap, ticks)) in words {
+            let ticks_json =
+                serde_json::to_string(&ticks).map_err(|e| sqlx::Error::Encode(Box::new(e)))?;
+            sqlx::query(
+                "INSERT INTO v3_tick_words (pool_address, word_position, block_number, bitmap, ticks_json)
+                 VALUES ($1, $2, $3, $4, $5)",
+            )
+            .bind(encode_address(pool_address))
+            .bind(word as i64)
+            .bind(block_number as i64)
+            .bind(encode_u256(bitmap))
+            .bind(ticks_json)
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// A V3 pool's stored liquidity map and the block it was read at, if one was saved.
+    pub async fn load_liquidity_map(
+        &self,
+        pool_address: Address,
+    ) -> Result<Option<(u64, LiquidityMap)>, sqlx::Error> {
+        let rows = sqlx::query(
+            "SELECT word_position, block_number, bitmap, ticks_json FROM v3_tick_words
+             WHERE pool_address = $1",
+        )
+        .bind(encode_address(pool_address))
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut block_number = None;
+        let mut map = LiquidityMap::default();
+        for row in &rows {
+            let row_block = row.get::<i64, _>("block_number") as u64;
+            block_number = Some(block_number.map_or(row_block, |block: u64| block.min(row_block)));
+            let bitmap = decode_u256(&row.get::<String, _>("bitmap"))?;
+            if !bitmap.is_zero() {
+                map.tick_bitmap
+                    .insert(row.get::<i64, _>("word_position") as i16, bitmap);
+            }
+            let ticks: Vec<(i32, TickInfo)> =
+                serde_json::from_str(&row.get::<String, _>("ticks_json"))
+                    .map_err(|e| sqlx::Error::Decode(Box::new(e)))?;
+            map.tick_data.extend(ticks);
+        }
+        Ok(block_number.map(|block| (block, map)))
+    }
+
+    /// Replaces the stored token policy with `policy`'s mode and lists.
+    pub async fn save_token_policy(&self, policy: &TokenPolicy) -> Result<(), sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("DELETE FROM token_policy")
+            .execute(&mut *tx)
+            .await?;
+        for (list, tokens) in [("allow", policy.allowed()), ("deny", policy.denied())] {
+            for token in tokens {
+                sqlx::query("INSERT INTO token_policy (address, list) VALUES ($1, $2)")
+                    .bind(encode_address(token))
+                    .bind(list)
+                    .execute(&mut *tx)
+                    .await?;
+            }
+        }
+        sqlx::query(
+            "INSERT INTO bot_state (key, value) VALUES ($1, $2)
+             ON CONFLICT (key) DO UPDATE SET value = excluded.value",
+        )
+        .bind(TOKEN_POLICY_MODE_KEY)
+        .bind(policy.mode().as_str())
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// The stored token policy, open and empty if none was saved.
+    pub async fn load_token_policy(&self) -> Result<TokenPolicy, sqlx::Error> {
+        let mode = match self.get_state(TOKEN_POLICY_MODE_KEY).await? {
+            Some(mode) => {
+                TokenPolicyMode::from_str(&mode).map_err(|e| sqlx::Error::Decode(e.into()))?
+            }
+            None => TokenPolicyMode::default(),
+        };
+        let policy = TokenPolicy::new().with_mode(mode);
+        let rows = sqlx::query("SELECT address, list FROM token_policy")
+            .fetch_all(&self.pool)
+            .await?;
+        for row in &rows {
+            let token = decode_address(&row.get::<String, _>("address"))?;
+            match row.get::<String, _>("list").as_str() {
+                "allow" => policy.allow([token]),
+                _ => policy.deny([token]),
+            }
+        }
+        Ok(policy)
+    }
+
     pub async fn get_token_by_address(
         &self,
         address: Address,
     ) -> Result<Option<TokenRecord>, sqlx::Error> {
-        let result: Option<(String, String, i64)> =
-            sqlx::query_as("SELECT address, symbol, decimals FROM tokens WHERE address = ?")
-                .bind(address.to_string())
-                .fetch_optional(&self.pool)
-                .await?;
+        let result: Option<(String, String, i64, Option<i64>)> = sqlx::query_as(
+            "SELECT address, symbol, decimals, transfer_tax_bps FROM tokens WHERE address = $1",
+        )
+        .bind(encode_address(address))
+        .fetch_optional(&self.pool)
+        .await?;
 
-        Ok(result.map(|(address_str, symbol, decimals)| TokenRecord {
-            address: Address::from_str(&address_str).unwrap(),
-            symbol,
-            decimals: decimals as u8,
-        }))
+        result
+            .map(|(address_str, symbol, decimals, transfer_tax_bps)| {
+                Ok(TokenRecord {
+                    address: decode_address(&address_str)?,
+                    symbol,
+                    decimals: decimals as u8,
+                    transfer_tax_bps: transfer_tax_bps.map(|bps| bps as u32),
+                })
+            })
+            .transpose()
+    }
+
+    /// Records a token's measured transfer tax. The token must already be saved.
+    pub async fn save_token_transfer_tax(
+        &self,
+        address: Address,
+        transfer_tax_bps: u32,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query("UPDATE tokens SET transfer_tax_bps = $1 WHERE address = $2")
+            .bind(transfer_tax_bps as i64)
+            .bind(encode_address(address))
+            .execute(&self.pool)
+            .await?;
+        Ok(())
     }
+
+    /// Inserts or overwrites the failure recorded for the token at `address`.
+    pub async fn save_token_failure(
+        &self,
+        address: Address,
+        failure: &TokenFailure,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "INSERT INTO token_failures (address, error_class, error, retry_after_block)
+             VALUES ($1, $2, $3, $4)
+             ON CONFLICT (address) DO UPDATE SET error_class = excluded.error_class,
+             error = excluded.error, retry_after_block = excluded.retry_after_block",
+        )
+        .bind(encode_address(address))
+        .bind(&failure.error_class)
+        .bind(&failure.error)
+        .bind(failure.retry_after_block as i64)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn load_token_failure(
+        &self,
+        address: Address,
+    ) -> Result<Option<TokenFailure>, sqlx::Error> {
+        let result: Option<(String, String, i64)> = sqlx::query_as(
+            "SELECT error_class, error, retry_after_block FROM token_failures WHERE address = $1",
+        )
+        .bind(encode_address(address))
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(
+            result.map(|(error_class, error, retry_after_block)| TokenFailure {
+                error_class,
+                error,
+                retry_after_block: retry_after_block as u64,
+            }),
+        )
+    }
+
+    pub async fn clear_token_failure(&self, address: Address) -> Result<(), sqlx::Error> {
+        sqlx::query("DELETE FROM token_failures WHERE address = $1")
+            .bind(encode_address(address))
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+}
+
+const TOKEN_POLICY_MODE_KEY: &str = "token_policy_mode";
+
+const INSERT_TOKEN: &str = "INSERT INTO tokens (address, symbol, decimals) VALUES ($1, $2, $3)
+     ON CONFLICT (address) DO NOTHING";
+
+/// Addresses are stored as lowercase 0x-prefixed hex in every dialect.
+const SELECT_OPPORTUNITIES: &str = "SELECT block_number, path_hash, pools, profit_token,
+     optimal_input, gross_profit, net_profit, net_profit_weth, gas_price, recorded_at
+     FROM opportunities";
+
+const POOL_COLUMNS: &str = "id, address, dex, fee, tick_spacing, attributes_json, balancer_pool_id, balancer_vault, lp_token, v4_pool_manager, v4_currency0, v4_currency1, v4_hooks";
+
+fn decode_pool(row: &sqlx::any::AnyRow, tokens: Vec<Address>) -> Result<PoolRecord, sqlx::Error> {
+    let optional_address = |column: &str| {
+        row.get::<Option<String>, _>(column)
+            .map(|value| decode_address(&value))
+            .transpose()
+    };
+    let fee = row.get::<Option<i64>, _>("fee").map(|f| f as u32);
+    let tick_spacing = row
+        .get::<Option<i64>, _>("tick_spacing")
+        .map(|ts| ts as i32);
+    let v4_pool_key = match (
+        optional_address("v4_currency0")?,
+        optional_address("v4_currency1")?,
+        optional_address("v4_hooks")?,
+        fee,
+        tick_spacing,
+    ) {
+        (Some(currency0), Some(currency1), Some(hooks), Some(fee), Some(tick_spacing)) => {
+            Some(PoolKey {
+                currency0,
+                currency1,
+                fee,
+                tick_spacing,
+                hooks,
+            })
+        }
+        _ => None,
+    };
+    Ok(PoolRecord {
+        address: decode_address(&row.get::<String, _>("address"))?,
+        dex: row.get("dex"),
+        tokens,
+        fee,
+        tick_spacing,
+        attributes_json: row.get("attributes_json"),
+        balancer_pool_id: row
+            .get::<Option<String>, _>("balancer_pool_id")
+            .map(|value| B256::from_str(&value).map_err(|e| sqlx::Error::Decode(Box::new(e))))
+            .transpose()?,
+        balancer_vault: optional_address("balancer_vault")?,
+        lp_token: optional_address("lp_token")?,
+        v4_pool_manager: optional_address("v4_pool_manager")?,
+        v4_pool_key,
+    })
+}
+
+fn decode_opportunity(row: &sqlx::any::AnyRow) -> Result<OpportunityRecord, sqlx::Error> {
+    Ok(OpportunityRecord {
+        block_number: row.get::<i64, _>("block_number") as u64,
+        path_hash: B256::from_str(&row.get::<String, _>("path_hash"))
+            .map_err(|e| sqlx::Error::Decode(Box::new(e)))?,
+        pools: row
+            .get::<String, _>("pools")
+            .split(',')
+            .filter(|pool| !pool.is_empty())
+            .map(decode_address)
+            .collect::<Result<_, _>>()?,
+        profit_token: decode_address(&row.get::<String, _>("profit_token"))?,
+        optimal_input: decode_u256(&row.get::<String, _>("optimal_input"))?,
+        gross_profit: decode_u256(&row.get::<String, _>("gross_profit"))?,
+        net_profit: decode_u256(&row.get::<String, _>("net_profit"))?,
+        net_profit_weth: decode_u256(&row.get::<String, _>("net_profit_weth"))?,
+        gas_price: decode_u256(&row.get::<String, _>("gas_price"))?,
+        recorded_at: row.get::<i64, _>("recorded_at") as u64,
+    })
+}
+
+/// Pool kinds are stored as their `Display` string.
+impl<DB: Database> Type<DB> for PoolKind
+where
+    String: Type<DB>,
+{
+    fn type_info() -> DB::TypeInfo {
+        <String as Type<DB>>::type_info()
+    }
+
+    fn compatible(ty: &DB::TypeInfo) -> bool {
+        <String as Type<DB>>::compatible(ty)
+    }
+}
+
+impl<'q, DB: Database> Encode<'q, DB> for PoolKind
+where
+    String: Encode<'q, DB>,
+{
+    fn encode_by_ref(
+        &self,
+        buf: &mut <DB as Database>::ArgumentBuffer<'q>,
+    ) -> Result<IsNull, BoxDynError> {
+        self.to_string().encode(buf)
+    }
+}
+
+impl<'r, DB: Database> Decode<'r, DB> for PoolKind
+where
+    String: Decode<'r, DB>,
+{
+    fn decode(value: <DB as Database>::ValueRef<'r>) -> Result<Self, BoxDynError> {
+        Ok(String::decode(value)?.parse()?)
+    }
+}
+
+pub fn encode_address(address: Address) -> String {
+    format!("{:#x}", address)
+}
+
+pub fn decode_address(value: &str) -> Result<Address, sqlx::Error> {
+    Address::from_str(value).map_err(|e| sqlx::Error::Decode(Box::new(e)))
+}
+
+/// U256 values are stored as lowercase 0x-prefixed hex, since neither sqlite nor the `Any`
+/// driver has a native 256-bit integer type.
+pub fn encode_u256(value: U256) -> String {
+    format!("{:#x}", value)
+}
+
+pub fn decode_u256(value: &str) -> Result<U256, sqlx::Error> {
+    U256::from_str(value).map_err(|e| sqlx::Error::Decode(Box::new(e)))
 }
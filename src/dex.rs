@@ -1,39 +1,215 @@
-use alloy_primitives::{Address, address};
+use crate::pool::address::{
+    PANCAKESWAP_V2_INIT_CODE_HASH, SUSHISWAP_INIT_CODE_HASH, UNISWAP_V2_INIT_CODE_HASH,
+    v2_pair_address,
+};
+use crate::pool::DexKind;
+use alloy_primitives::{Address, B256, address};
 use std::collections::HashMap;
+use std::convert::Infallible;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::str::FromStr;
+
+pub const UNISWAP_V2_FACTORY: Address = address!("5C69bEe701ef814a2B6a3EDD4B1652CB9cc5aA6f");
+pub const SUSHISWAP_FACTORY: Address = address!("C0AEe478e3658e2610c5F7A4A2E1777cE9e4f2Ac");
+pub const PANCAKESWAP_V2_FACTORY: Address = address!("1097053Fd2ea711dad45caCcc45EfF7548fCB362");
+
+/// Denominator of the pool record fee, which is in hundredths of a basis point like V3's.
+pub const FEE_PIPS_DENOMINATOR: u32 = 1_000_000;
+
+/// The kind of pool a stored record holds, which decides how it is rebuilt. Stored as its
+/// `Display` string in the `dex` column.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum PoolKind {
+    UniswapV2,
+    UniswapV3,
+    PancakeSwapV3,
+    /// A hook-less pool of a V4 pool manager, its key stored alongside.
+    UniswapV4,
+    CurveStable,
+    /// Curve tricrypto and two-coin cryptoswap pools.
+    CurveCrypto,
+    BalancerWeighted,
+    /// Balancer stable, meta-stable and composable stable pools.
+    BalancerStable,
+    /// Solidly-style `x³y + xy³` pairs, e.g. Velodrome's stable pools.
+    SolidlyStable,
+    /// Solidly-style constant product pairs.
+    SolidlyVolatile,
+    /// A kind without a variant, e.g. a V3 deployment registered by the caller or one
+    /// written by a newer build. Kept as stored, so saving it again doesn't lose it.
+    Other(String),
+}
+
+impl PoolKind {
+    pub const KNOWN: [PoolKind; 10] = [
+        PoolKind::UniswapV2,
+        PoolKind::UniswapV3,
+        PoolKind::PancakeSwapV3,
+        PoolKind::UniswapV4,
+        PoolKind::CurveStable,
+        PoolKind::CurveCrypto,
+        PoolKind::BalancerWeighted,
+        PoolKind::BalancerStable,
+        PoolKind::SolidlyStable,
+        PoolKind::SolidlyVolatile,
+    ];
+
+    /// The protocol the pool belongs to, unless the kind is unknown.
+    pub fn dex_kind(&self) -> Option<DexKind> {
+        match self {
+            PoolKind::UniswapV2 | PoolKind::SolidlyStable | PoolKind::SolidlyVolatile => {
+                Some(DexKind::UniswapV2)
+            }
+            PoolKind::UniswapV3 | PoolKind::PancakeSwapV3 => Some(DexKind::UniswapV3),
+            PoolKind::UniswapV4 => Some(DexKind::UniswapV4),
+            PoolKind::CurveStable | PoolKind::CurveCrypto => Some(DexKind::Curve),
+            PoolKind::BalancerWeighted | PoolKind::BalancerStable => Some(DexKind::Balancer),
+            PoolKind::Other(_) => None,
+        }
+    }
+}
+
+impl Display for PoolKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.write_str(match self {
+            PoolKind::UniswapV2 => "uniswap v2",
+            PoolKind::UniswapV3 => "uniswap v3",
+            PoolKind::PancakeSwapV3 => "pancakeswap v3",
+            PoolKind::UniswapV4 => "uniswap v4",
+            PoolKind::CurveStable => "curve stable",
+            PoolKind::CurveCrypto => "curve crypto",
+            PoolKind::BalancerWeighted => "balancer weighted",
+            PoolKind::BalancerStable => "balancer stable",
+            PoolKind::SolidlyStable => "solidly stable",
+            PoolKind::SolidlyVolatile => "solidly volatile",
+            PoolKind::Other(kind) => kind,
+        })
+    }
+}
+
+/// Parses the `Display` strings case-insensitively. Anything else is `Other`, verbatim.
+impl FromStr for PoolKind {
+    type Err = Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(PoolKind::KNOWN
+            .into_iter()
+            .find(|kind| kind.to_string().eq_ignore_ascii_case(s))
+            .unwrap_or_else(|| PoolKind::Other(s.to_string())))
+    }
+}
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum DexVariant {
     UniswapV2,
     SushiSwap,
     PancakeSwapV2,
+    /// A fork registered by the caller, quoted with the fee of its `DexDetails`.
+    Custom,
+    /// A Solidly-style factory's stable pairs. Building a pool of either Solidly variant
+    /// uses the first registered factory of either.
+    SolidlyStable,
+    /// A Solidly-style factory's volatile pairs.
+    SolidlyVolatile,
+}
+
+impl DexVariant {
+    /// Whether the variant is one of a Solidly-style factory's, whose pairs are each
+    /// either stable or volatile.
+    pub fn is_solidly(&self) -> bool {
+        matches!(self, DexVariant::SolidlyStable | DexVariant::SolidlyVolatile)
+    }
+
+    /// The Solidly variant of pairs that are `stable` or not.
+    pub fn solidly(stable: bool) -> Self {
+        if stable {
+            DexVariant::SolidlyStable
+        } else {
+            DexVariant::SolidlyVolatile
+        }
+    }
 }
 
-#[derive(Debug, Clone)]
+/// A V2 factory: the fork it belongs to, the fee its pairs charge and the init code hash
+/// they are deployed with.
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct DexDetails {
     pub dex_type: DexVariant,
+    /// Fee charged on the input, as `fee_numerator / fee_denominator`.
+    pub fee_numerator: u32,
+    pub fee_denominator: u32,
+    pub init_code_hash: B256,
+    /// Fee of a Solidly-style factory's stable pairs, as `(numerator, denominator)`, when
+    /// it differs from the volatile pairs' fee above.
+    pub stable_fee: Option<(u32, u32)>,
+}
+
+impl DexDetails {
+    pub fn new(
+        dex_type: DexVariant,
+        fee_numerator: u32,
+        fee_denominator: u32,
+        init_code_hash: B256,
+    ) -> Self {
+        Self {
+            dex_type,
+            fee_numerator,
+            fee_denominator,
+            init_code_hash,
+            stable_fee: None,
+        }
+    }
+
+    /// Sets the fee a Solidly-style factory's stable pairs charge.
+    pub fn with_stable_fee(mut self, fee_numerator: u32, fee_denominator: u32) -> Self {
+        self.stable_fee = Some((fee_numerator, fee_denominator));
+        self
+    }
+
+    /// The fee pairs charge, as `(numerator, denominator)`: the stable pairs' own fee if
+    /// they have one.
+    pub fn fee(&self, stable: bool) -> (u32, u32) {
+        match self.stable_fee {
+            Some(stable_fee) if stable => stable_fee,
+            _ => (self.fee_numerator, self.fee_denominator),
+        }
+    }
+
+    /// The fee in hundredths of a basis point, as pool records store it.
+    pub fn fee_pips(&self) -> u32 {
+        fee_pips(self.fee_numerator, self.fee_denominator)
+    }
+
+    /// Address of the `token_a`/`token_b` pair deployed by `factory`.
+    pub fn pair_address(&self, factory: Address, token_a: Address, token_b: Address) -> Address {
+        v2_pair_address(token_a, token_b, factory, self.init_code_hash)
+    }
+}
+
+/// `fee_numerator / fee_denominator` in hundredths of a basis point, rounded down.
+pub fn fee_pips(fee_numerator: u32, fee_denominator: u32) -> u32 {
+    (fee_numerator as u64 * FEE_PIPS_DENOMINATOR as u64 / fee_denominator as u64) as u32
 }
 
 /// Creates a map of factory addresses to DEX details for mainnet (chain ID 1).
 pub fn build_mainnet_dex_registry() -> HashMap<Address, DexDetails> {
-    let mut registry = HashMap::new();
-
-    // Uniswap V2 Factory
-    registry.insert(
-        address!("5C69bEe701ef814a2B6a3EDD4B1652CB9cc5aA6f"),
-        DexDetails {
-            dex_type: DexVariant::UniswapV2,
-        },
-    );
-
-    // Sushiswap Factory
-    registry.insert(
-        address!("C0AEe478e3658e2610c5F7A4A2E1777cE9e4f2Ac"),
-        DexDetails {
-            dex_type: DexVariant::SushiSwap,
-        },
-    );
-
-    // Can add more forks here later I mean i WILL add more forks here pursuing uniswapv3 rn...
-
-    registry
+    HashMap::from([
+        (
+            UNISWAP_V2_FACTORY,
+            DexDetails::new(DexVariant::UniswapV2, 30, 10_000, UNISWAP_V2_INIT_CODE_HASH),
+        ),
+        (
+            SUSHISWAP_FACTORY,
+            DexDetails::new(DexVariant::SushiSwap, 30, 10_000, SUSHISWAP_INIT_CODE_HASH),
+        ),
+        (
+            PANCAKESWAP_V2_FACTORY,
+            DexDetails::new(
+                DexVariant::PancakeSwapV2,
+                25,
+                10_000,
+                PANCAKESWAP_V2_INIT_CODE_HASH,
+            ),
+        ),
+    ])
 }
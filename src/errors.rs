@@ -1,20 +1,80 @@
+use crate::core::rpc_client;
 use alloy::transports::{RpcError, TransportErrorKind};
 use alloy_contract::Error as ContractError;
-use alloy_primitives::Address;
+use alloy_primitives::{Address, U256};
 use balancer_maths_rust::PoolError;
+use std::fmt;
+use std::time::Duration;
 use thiserror::Error;
 
+/// The pool an error came out of: its address, what kind of pool it is and the block it
+/// was read or quoted at, where known.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PoolContext {
+    pub address: Address,
+    /// The pool's kind, e.g. `Uniswap V3` or `Curve AdminFee` for Curve's swap strategies.
+    pub kind: Option<String>,
+    pub block: Option<u64>,
+}
+
+impl PoolContext {
+    pub fn new(address: Address) -> Self {
+        Self {
+            address,
+            kind: None,
+            block: None,
+        }
+    }
+
+    pub fn with_kind(mut self, kind: impl Into<String>) -> Self {
+        self.kind = Some(kind.into());
+        self
+    }
+
+    pub fn with_block(mut self, block: Option<u64>) -> Self {
+        self.block = block;
+        self
+    }
+}
+
+impl fmt::Display for PoolContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "pool {}", self.address)?;
+        if let Some(kind) = &self.kind {
+            write!(f, " ({kind})")?;
+        }
+        if let Some(block) = self.block {
+            write!(f, " at block {block}")?;
+        }
+        Ok(())
+    }
+}
+
 #[derive(Error, Debug, PartialEq)]
 pub enum ArbRsError {
     #[error("Provider error: {0}")]
     ProviderError(String),
 
+    #[error("RPC call {method} timed out after {timeout:?}")]
+    RpcTimeout { method: String, timeout: Duration },
+
+    #[error("RPC call rate limited: {0}")]
+    RpcRateLimited(String),
+
     #[error("ABI decoding error for contract call: {0}")]
     AbiDecodeError(String),
 
     #[error("Token implementation non-standard at address {0}: {1}")]
     TokenStandardError(Address, String),
 
+    #[error("Token {0} could not be resolved: {1}")]
+    UnresolvedToken(Address, String),
+
+    /// A token whose metadata failed to resolve recently, refused without calling it again
+    /// until its retry block.
+    #[error("Token {0} is unusable: resolving it failed before")]
+    TokenUnusable(Address),
+
     #[error("Could not fetch required data for address: {0}")]
     DataFetchError(Address),
 
@@ -28,13 +88,24 @@ pub enum ArbRsError {
     NoPoolStateAvailable(u64),
 
     #[error(
-        "Update attempted for a block ({attempted_block}) prior to the last recorded update ({latest_block})"
+        "Update of pool {pool} attempted for a block ({attempted_block}) prior to the last recorded update ({latest_block})"
     )]
     LateUpdateError {
+        pool: Address,
         attempted_block: u64,
         latest_block: u64,
     },
 
+    #[error(
+        "Liquidity map of pool {pool} puts {computed} in range at block {block}, but the pool holds {onchain}"
+    )]
+    LiquidityMismatch {
+        pool: Address,
+        block: u64,
+        computed: u128,
+        onchain: u128,
+    },
+
     #[error("ABI Decode Error: {0}")]
     SolAbiError(#[from] alloy_sol_types::Error),
 
@@ -43,10 +114,206 @@ pub enum ArbRsError {
 
     #[error("Contract error: {0}")]
     ContractError(String),
+
+    #[error("Pool {0} is paused")]
+    PoolPaused(Address),
+
+    /// The external oracle `pool` prices a coin with hasn't updated for longer than allowed,
+    /// so quotes against it no longer follow the market.
+    #[error(
+        "Oracle of pool {pool} last updated at {updated_at}, {} seconds before the block",
+        block_timestamp.saturating_sub(*updated_at)
+    )]
+    StaleOracle {
+        pool: Address,
+        updated_at: u64,
+        block_timestamp: u64,
+    },
+
+    #[error("Pool {0} failed validation: {1}")]
+    InvalidPool(Address, String),
+
+    /// A stored pool record that doesn't describe the contract at its address, e.g. a pair
+    /// whose tokens and factory derive another address.
+    #[error("Pool {0} does not match its record: {1}")]
+    AddressMismatch(Address, String),
+
+    #[error("Pool {pool} holds token {token}, which the token policy refuses")]
+    TokenNotAllowed { pool: Address, token: Address },
+
+    #[error("Insufficient input amount")]
+    InsufficientInputAmount,
+
+    #[error("Insufficient output amount")]
+    InsufficientOutputAmount,
+
+    #[error("Insufficient liquidity for the requested swap")]
+    InsufficientLiquidity,
+
+    /// A V3 pool with no liquidity in range and no initialized tick in the swap direction,
+    /// so no amount can be swapped through it that way.
+    #[error("Pool {pool} has no liquidity in the swap direction")]
+    NoLiquidity { pool: Address },
+
+    #[error("Database error: {0}")]
+    DatabaseError(String),
+
+    #[error("Pool {pool} did not exist at block {block}")]
+    PoolNotDeployed { pool: Address, block: u64 },
+
+    #[error("Block {requested} is past the chain head ({latest})")]
+    BlockNotMined { requested: u64, latest: u64 },
+
+    #[error("Pool {0} is not tracked")]
+    UnknownPool(Address),
+
+    #[error("No builder for pool kind {0:?}")]
+    UnknownPoolKind(String),
+
+    #[error("Export error: {0}")]
+    ExportError(String),
+
+    #[error("Configuration error: {0}")]
+    ConfigError(String),
+
+    #[error("Swap only partially filled: {filled} of {requested} requested")]
+    PartialFill { requested: U256, filled: U256 },
+
+    /// A math or data-fetch error and the pool it came out of.
+    #[error("{context}: {source}")]
+    InPool {
+        context: PoolContext,
+        source: Box<ArbRsError>,
+    },
+}
+
+impl ArbRsError {
+    /// Whether the failure was the node's, not the request's, so the same call may succeed
+    /// if tried again.
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self.root(),
+            ArbRsError::RpcTimeout { .. } | ArbRsError::RpcRateLimited(_)
+        )
+    }
+
+    /// The error with any pool context taken off.
+    pub fn root(&self) -> &ArbRsError {
+        match self {
+            ArbRsError::InPool { source, .. } => source.root(),
+            error => error,
+        }
+    }
+
+    /// The pool the error came out of, if known.
+    pub fn pool_context(&self) -> Option<&PoolContext> {
+        match self {
+            ArbRsError::InPool { context, .. } => Some(context),
+            _ => None,
+        }
+    }
+
+    /// Attaches `context` to a math or data-fetch error. An error already attributed to the
+    /// same pool only has its missing kind and block filled in, and one attributed to
+    /// another pool, e.g. a metapool's base pool, keeps its own. The other variants name
+    /// their pool already, or are matched on by callers, and are returned as they are.
+    pub fn in_pool(self, context: PoolContext) -> Self {
+        match self {
+            ArbRsError::InPool {
+                context: mut inner,
+                source,
+            } => {
+                if inner.address == context.address {
+                    inner.kind = inner.kind.or(context.kind);
+                    inner.block = inner.block.or(context.block);
+                }
+                ArbRsError::InPool {
+                    context: inner,
+                    source,
+                }
+            }
+            error @ (ArbRsError::ProviderError(_)
+            | ArbRsError::AbiDecodeError(_)
+            | ArbRsError::SolAbiError(_)
+            | ArbRsError::ContractError(_)
+            | ArbRsError::DataFetchError(_)
+            | ArbRsError::CalculationError(_)
+            | ArbRsError::UniswapV3MathError(_)) => ArbRsError::InPool {
+                context,
+                source: Box::new(error),
+            },
+            error => error,
+        }
+    }
+
+    /// A short, stable name for the kind of failure, e.g. to label metrics with.
+    pub fn class(&self) -> &'static str {
+        match self {
+            ArbRsError::ProviderError(_) => "provider",
+            ArbRsError::RpcTimeout { .. } => "timeout",
+            ArbRsError::RpcRateLimited(_) => "rate_limited",
+            ArbRsError::AbiDecodeError(_) | ArbRsError::SolAbiError(_) => "abi_decode",
+            ArbRsError::ContractError(_) => "contract",
+            ArbRsError::TokenStandardError(..)
+            | ArbRsError::UnresolvedToken(..)
+            | ArbRsError::TokenUnusable(_) => "token",
+            ArbRsError::DataFetchError(_) => "data_fetch",
+            ArbRsError::CalculationError(_) | ArbRsError::UniswapV3MathError(_) => "calculation",
+            ArbRsError::NoPoolStateAvailable(_)
+            | ArbRsError::LateUpdateError { .. }
+            | ArbRsError::LiquidityMismatch { .. } => "state",
+            ArbRsError::BrokenPool
+            | ArbRsError::InvalidPool(..)
+            | ArbRsError::AddressMismatch(..) => "invalid_pool",
+            ArbRsError::TokenNotAllowed { .. } => "policy",
+            ArbRsError::PoolPaused(_) => "paused",
+            ArbRsError::StaleOracle { .. } => "stale_oracle",
+            ArbRsError::InsufficientInputAmount
+            | ArbRsError::InsufficientOutputAmount
+            | ArbRsError::InsufficientLiquidity
+            | ArbRsError::NoLiquidity { .. }
+            | ArbRsError::PartialFill { .. } => "liquidity",
+            ArbRsError::DatabaseError(_) => "database",
+            ArbRsError::PoolNotDeployed { .. } => "not_deployed",
+            ArbRsError::BlockNotMined { .. } => "not_mined",
+            ArbRsError::UnknownPool(_) | ArbRsError::UnknownPoolKind(_) => "unknown",
+            ArbRsError::ExportError(_) => "export",
+            ArbRsError::ConfigError(_) => "config",
+            ArbRsError::InPool { source, .. } => source.class(),
+        }
+    }
+}
+
+/// Attaches the pool a failed call was made on to its error, e.g.
+/// `pool.fetch_balances().await.ctx(address, Some(block))?`.
+pub trait WithContext<T> {
+    fn ctx(self, address: Address, block: Option<u64>) -> Result<T, ArbRsError>;
+
+    /// Attaches the context `context` builds, only built if the call failed.
+    fn with_ctx(self, context: impl FnOnce() -> PoolContext) -> Result<T, ArbRsError>;
+}
+
+impl<T> WithContext<T> for Result<T, ArbRsError> {
+    fn ctx(self, address: Address, block: Option<u64>) -> Result<T, ArbRsError> {
+        self.with_ctx(|| PoolContext::new(address).with_block(block))
+    }
+
+    fn with_ctx(self, context: impl FnOnce() -> PoolContext) -> Result<T, ArbRsError> {
+        self.map_err(|error| error.in_pool(context()))
+    }
 }
 
 impl From<RpcError<TransportErrorKind>> for ArbRsError {
     fn from(error: RpcError<TransportErrorKind>) -> Self {
+        if let Some(timeout) = rpc_client::timeout_of(&error) {
+            return ArbRsError::RpcTimeout {
+                method: timeout.method.to_string(),
+                timeout: timeout.timeout,
+            };
+        }
+        if rpc_client::is_rate_limited(&error) {
+            return ArbRsError::RpcRateLimited(error.to_string());
+        }
         ArbRsError::ProviderError(error.to_string())
     }
 }
@@ -1,12 +1,16 @@
 pub mod arbitrage;
 pub mod balancer;
+pub mod chain;
 pub mod core;
 pub mod curve;
+#[cfg(feature = "db")]
 pub mod db;
 pub mod dex;
 pub mod errors;
 pub mod manager;
 pub mod math;
+pub mod mempool;
+pub mod metrics;
 pub mod pool;
 
 pub use errors::ArbRsError;
@@ -1,254 +1,961 @@
-use alloy_primitives::{Address, address};
+use alloy_primitives::{Address, U256};
 use alloy_provider::{Provider, ProviderBuilder};
+use alloy_rpc_types::Filter;
 use alloy_transport_ws::WsConnect;
 use arbrs::{
     arbitrage::{
+        amount::{format_units, parse_units},
+        approvals::ApprovalTracker,
+        backtest::Backtester,
         cache::ArbitrageCache,
-        engine::ArbitrageEngine,
-        finder::find_multi_hop_cycles,
-    }, db::DbManager, manager::{
-        balancer_pool_manager::BalancerPoolManager, curve_pool_manager::CurvePoolManager,
-        uniswap_v2_pool_manager::UniswapV2PoolManager,
-        uniswap_v3_pool_manager::UniswapV3PoolManager,
-    }, TokenLike, TokenManager
+        calibration::CalibrationTracker,
+        engine::{ArbitrageEngine, CachePruning, EngineConfig, TradeDivergenceCheck},
+        export::ExportConfig,
+        finder::{collect_pools, MinLiquidityFilter, PathFinder},
+        health::PoolHealth,
+        gas::{Eip1559Estimator, GasEstimator, LegacyGasPrice, OpStackGasEstimator},
+        persistence::PersistencePolicy,
+        shadow::ShadowMode,
+        types::Arbitrage,
+        usd::{format_usd, ChainlinkUsdPriceFeed},
+        verification::VerificationPolicy,
+    }, chain::ChainConfig, curve::pool::CurveStableswapPool, core::{block_stream::{BlockStreamEvent, ResilientBlockStream}, chain_tracker::ChainTracker, multicall::MulticallBatcher, rpc_client::RpcClient, token_policy::TokenPolicyMode}, db::DbManager, manager::{
+        balancer_pool_manager::BalancerPoolManager, curve_bootstrap::BootstrapOptions,
+        curve_pool_manager::CurvePoolManager, log_scan::LogScanConfig,
+        pool_factory::PoolFactoryRegistry, uniswap_v2_pool_manager::UniswapV2PoolManager,
+        uniswap_v3_pool_manager::UniswapV3PoolManager, uniswap_v4_pool_manager::UniswapV4PoolManager,
+    }, pool::{cache::CacheConfig, last_trade::{route_swap_log, swap_event_signatures}, reserve_drift::ReserveDriftConfig, state_updater::StateUpdater, LiquidityPool},
+    ArbRsError, TokenLike, TokenManager
 };
 use futures::stream::StreamExt;
+use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
 
 const FORK_RPC_URL: &str = "ws://127.0.0.1:8545";
 const DB_URL: &str = "sqlite:arbrs.db";
-const CHAIN_ID: u64 = 1;
-const V2_FACTORY_ADDRESS: Address = address!("5C69bEe701ef814a2B6a3EDD4B1652CB9cc5aA6f");
-const V3_FACTORY_ADDRESS: Address = address!("1F98431c8aD98523631AE4a59f267346ea31F984");
+#[cfg(feature = "metrics")]
+const METRICS_ADDR: &str = "127.0.0.1:9000";
+const CURVE_BOOTSTRAP_CONCURRENCY: usize = 4;
+const CURVE_BOOTSTRAP_INTERVAL: Duration = Duration::from_millis(50);
+/// V3 liquidity maps refreshed and stored every tenth block, for warm restarts.
+const LIQUIDITY_MAPS_PER_SWEEP: usize = 2;
+/// How long the block being processed gets to finish once shutdown is requested.
+const SHUTDOWN_GRACE: Duration = Duration::from_secs(30);
 
 type DynProvider = dyn Provider + Send + Sync;
+type PoolsByAddress = HashMap<Address, Arc<dyn LiquidityPool<DynProvider>>>;
+
+/// Every pool on a cached path, keyed by address, for routing swap logs.
+fn pools_by_address(paths: &[Arc<dyn Arbitrage<DynProvider>>]) -> PoolsByAddress {
+    paths
+        .iter()
+        .flat_map(|path| path.get_pools().iter())
+        .map(|pool| (pool.address(), pool.clone()))
+        .collect()
+}
+
+/// Pools the finder left out because one of their tokens couldn't be resolved, or their
+/// snapshot failed under a liquidity floor. They're picked up again on the next rebuild.
+fn log_excluded_pools(excluded_pools: &[(Address, ArbRsError)]) {
+    for (pool, e) in excluded_pools {
+        tracing::warn!(?pool, "Excluded pool from path finding: {}", e);
+    }
+    if !excluded_pools.is_empty() {
+        println!(
+            "Excluded {} pools from path finding.",
+            excluded_pools.len()
+        );
+    }
+}
+
+/// The value following `flag` on the command line, e.g. `--block 19000000`.
+fn flag_value<'a>(args: &'a [String], flag: &str) -> Option<&'a str> {
+    args.iter()
+        .position(|arg| arg == flag)
+        .and_then(|i| args.get(i + 1))
+        .map(String::as_str)
+}
+
+/// `arbrs eval-path --pools 0xA,0xB --token-in WETH --amount 1.5 [--block N]` evaluates a
+/// single path at a block, the latest by default, and prints each hop. `--token-in` is an
+/// address or the symbol of one of the first pool's tokens, and `--amount` is in whole tokens.
+async fn eval_path(
+    args: &[String],
+    engine: &ArbitrageEngine<DynProvider>,
+    known_pools: &PoolsByAddress,
+    failed_pools: &HashMap<Address, ArbRsError>,
+    latest_block: u64,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let usage = "usage: arbrs eval-path --pools 0xA,0xB,0xC --token-in WETH --amount 1.5 [--block N]";
+    let pool_addrs = flag_value(args, "--pools")
+        .ok_or(usage)?
+        .split(',')
+        .map(|address| address.trim().parse::<Address>())
+        .collect::<Result<Vec<_>, _>>()?;
+    for address in &pool_addrs {
+        if let Some(e) = failed_pools.get(address) {
+            return Err(format!("Pool {} could not be built: {}", address, e).into());
+        }
+    }
+    let first_pool = pool_addrs
+        .first()
+        .and_then(|address| known_pools.get(address))
+        .ok_or_else(|| ArbRsError::UnknownPool(pool_addrs.first().copied().unwrap_or_default()))?;
+
+    let token_in_arg = flag_value(args, "--token-in").ok_or(usage)?;
+    let token_in = first_pool
+        .get_all_tokens()
+        .into_iter()
+        .find(|token| {
+            token_in_arg.parse::<Address>().is_ok_and(|address| address == token.address())
+                || token.symbol().eq_ignore_ascii_case(token_in_arg)
+        })
+        .ok_or_else(|| format!("{} is not a token of pool {}", token_in_arg, first_pool.address()))?;
+    let amount_arg = flag_value(args, "--amount").ok_or(usage)?;
+    let amount = parse_units(amount_arg, token_in.decimals())
+        .ok_or_else(|| format!("{} is not an amount of {}", amount_arg, token_in.symbol()))?;
+    let block = match flag_value(args, "--block") {
+        Some(block) => block.parse::<u64>()?,
+        None => latest_block,
+    };
+
+    let evaluation = engine
+        .evaluate_path_at_block(&pool_addrs, known_pools, token_in.address(), amount, block)
+        .await?;
+    println!("{}", evaluation);
+    Ok(())
+}
+
+/// `arbrs backtest --from N --to M [--out report.json]` replays blocks `N..=M` through the
+/// engine over the paths found at startup, and writes the report as JSON, to stdout unless
+/// `--out` is given. The node must serve state at those blocks.
+async fn backtest(
+    args: &[String],
+    engine: ArbitrageEngine<DynProvider>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let usage = "usage: arbrs backtest --from 19000000 --to 19000999 [--out report.json]";
+    let from = flag_value(args, "--from").ok_or(usage)?.parse::<u64>()?;
+    let to = flag_value(args, "--to").ok_or(usage)?.parse::<u64>()?;
+
+    let report = Backtester::new(engine).run(from, to).await?;
+    println!("Backtested blocks {}..={}: {} opportunities.", from, to, report.opportunities());
+    let json = report.to_json_string()?;
+    match flag_value(args, "--out") {
+        Some(path) => std::fs::write(path, json)?,
+        None => println!("{}", json),
+    }
+    Ok(())
+}
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     tracing_subscriber::fmt::init();
+    #[cfg(feature = "metrics")]
+    {
+        let address = std::env::var("ARBRS_METRICS_ADDR").unwrap_or_else(|_| METRICS_ADDR.to_string());
+        arbrs::metrics::install_prometheus_exporter(address.parse()?)?;
+        println!("Serving metrics at http://{}/metrics", address);
+    }
 
     tracing::info!("Starting arbrs engine...");
     println!("Starting arbrs engine...");
+    let args: Vec<String> = std::env::args().collect();
+    let bootstrap_curve = args.iter().any(|arg| arg == "--bootstrap-curve");
+    let retry_failed = args.iter().any(|arg| arg == "--retry-failed");
+    let evaluating_path = args.get(1).is_some_and(|command| command == "eval-path");
+    let backtesting = args.get(1).is_some_and(|command| command == "backtest");
+
+    // The first ctrl-c stops discovery between chunks and the block loop after the current
+    // block, then flushes progress and queued writes. A second one exits at once.
+    let shutdown = CancellationToken::new();
+    tokio::spawn({
+        let shutdown = shutdown.clone();
+        async move {
+            if tokio::signal::ctrl_c().await.is_err() {
+                return;
+            }
+            println!("\nShutting down, press ctrl-c again to exit now...");
+            shutdown.cancel();
+            if tokio::signal::ctrl_c().await.is_ok() {
+                std::process::exit(130);
+            }
+        }
+    });
 
-    let db_manager = Arc::new(DbManager::new(DB_URL).await?);
+    let db_url = std::env::var("DATABASE_URL").unwrap_or_else(|_| DB_URL.to_string());
+    let db_manager = Arc::new(DbManager::new(&db_url).await?);
     let known_pools = db_manager.load_all_pools().await?;
     println!("Loaded {} pools from the database.", known_pools.len());
 
-    let ws = WsConnect::new(FORK_RPC_URL);
+    // The RPC connection retries for as long as the node is gone; the block stream opens its
+    // own, resubscribing and reporting the blocks missed when it comes back.
+    let rpc_url = std::env::var("ARBRS_RPC_URL").unwrap_or_else(|_| FORK_RPC_URL.to_string());
+    let ws = WsConnect::new(rpc_url.clone()).with_max_retries(u32::MAX);
     let provider = ProviderBuilder::new().connect_ws(ws).await?;
 
-    let mut stream = provider.subscribe_blocks().await?.into_stream();
-    let provider_arc: Arc<DynProvider> = Arc::new(provider);
-    let token_manager = Arc::new(TokenManager::new(
-        provider_arc.clone(),
-        CHAIN_ID,
-        db_manager.clone(),
-    ));
+    let mut stream = Box::pin(ResilientBlockStream::new(WsConnect::new(rpc_url)).into_stream());
+    // Every call the pools and managers make goes through a deadline, so one hung call
+    // can't hold up a block's evaluation.
+    let mut rpc_client = RpcClient::new(Arc::new(provider));
+    if let Some(timeout_ms) = std::env::var("ARBRS_RPC_TIMEOUT_MS").ok().and_then(|ms| ms.parse::<u64>().ok()) {
+        rpc_client = rpc_client.with_timeout(Duration::from_millis(timeout_ms));
+    }
+    if let Some(max_retries) = std::env::var("ARBRS_RPC_MAX_RETRIES").ok().and_then(|retries| retries.parse::<u32>().ok()) {
+        rpc_client = rpc_client.with_max_retries(max_retries);
+    }
+    let rpc_metrics = rpc_client.metrics();
+    let provider_arc: Arc<DynProvider> = Arc::new(rpc_client);
 
-    let mut last_seen_block = provider_arc.get_block_number().await?;
-    let mut v2_pool_manager = UniswapV2PoolManager::new(
-        token_manager.clone(),
-        provider_arc.clone(),
-        V2_FACTORY_ADDRESS,
-        last_seen_block,
-    );
-    let mut v3_pool_manager = UniswapV3PoolManager::new(
-        token_manager.clone(),
-        provider_arc.clone(),
-        CHAIN_ID,
-        last_seen_block,
-        V3_FACTORY_ADDRESS,
+    // The addresses of the node's chain: its preset, or the JSON file `ARBRS_CHAIN_CONFIG`
+    // names, for a chain without one or to override a preset's.
+    let chain_id = provider_arc.get_chain_id().await?;
+    let chain = match std::env::var("ARBRS_CHAIN_CONFIG") {
+        Ok(path) => ChainConfig::from_json(&std::fs::read_to_string(path)?)?,
+        Err(_) => ChainConfig::for_chain_id(chain_id).ok_or_else(|| {
+            ArbRsError::ConfigError(format!("No preset for chain {chain_id}, set ARBRS_CHAIN_CONFIG"))
+        })?,
+    };
+    if chain.chain_id != chain_id {
+        return Err(ArbRsError::ConfigError(format!(
+            "The chain config is for chain {}, but the node is on chain {}",
+            chain.chain_id, chain_id
+        ))
+        .into());
+    }
+    let chain = Arc::new(chain);
+    tracing::info!(chain = %chain.name, chain_id, "Running on chain");
+    // The stored token policy, with `ARBRS_ALLOW_TOKENS` and `ARBRS_DENY_TOKENS` added to its
+    // lists and `ARBRS_TOKEN_WHITELIST` restricting it to the allowed tokens.
+    let token_policy = Arc::new(db_manager.load_token_policy().await?);
+    let token_list = |name: &str| {
+        std::env::var(name).ok().map(|tokens| {
+            tokens.split(',').filter_map(|address| address.trim().parse::<Address>().ok()).collect::<Vec<_>>()
+        })
+    };
+    let mut policy_changed = false;
+    if let Some(allowed) = token_list("ARBRS_ALLOW_TOKENS") {
+        token_policy.allow(allowed);
+        policy_changed = true;
+    }
+    if let Some(denied) = token_list("ARBRS_DENY_TOKENS") {
+        token_policy.deny(denied);
+        policy_changed = true;
+    }
+    if std::env::var("ARBRS_TOKEN_WHITELIST").is_ok() {
+        token_policy.set_mode(TokenPolicyMode::Whitelist);
+        policy_changed = true;
+    }
+    if policy_changed {
+        db_manager.save_token_policy(&token_policy).await?;
+    }
+    let token_manager = Arc::new(
+        TokenManager::new(provider_arc.clone(), chain_id, db_manager.clone())
+            .with_chain(chain.clone())
+            .with_transfer_tax_detection()
+            .with_token_policy(token_policy),
     );
-    let curve_pool_manager = CurvePoolManager::new(
+
+    let mut last_seen_block = provider_arc.get_block_number().await?;
+    token_manager.observe_block(last_seen_block);
+    // Discovery backfills from this block when set, walking to head in chunks and resuming
+    // from the stored progress after a restart.
+    let discovery_start_block = std::env::var("ARBRS_DISCOVERY_START_BLOCK")
+        .ok()
+        .and_then(|block| block.parse::<u64>().ok())
+        .unwrap_or(last_seen_block);
+    let log_scan = std::env::var("ARBRS_LOG_CHUNK_SIZE")
+        .ok()
+        .and_then(|chunk_size| chunk_size.parse::<u64>().ok())
+        .map(|chunk_size| LogScanConfig::default().with_chunk_size(chunk_size));
+    // Blocks of past state each pool keeps, and the engine's pruning horizon.
+    let cache_config = std::env::var("ARBRS_POOL_CACHE_BLOCKS")
+        .ok()
+        .and_then(|blocks| blocks.parse::<usize>().ok())
+        .map_or_else(CacheConfig::default, |max_blocks| CacheConfig { max_blocks });
+    // A chain without a factory of either kind only serves the pools stored for it.
+    let v2_pool_manager = match chain.v2_factory() {
+        Some(factory) => {
+            UniswapV2PoolManager::new(token_manager.clone(), provider_arc.clone(), factory, discovery_start_block)
+        }
+        None => UniswapV2PoolManager::new_static(token_manager.clone(), provider_arc.clone(), []),
+    };
+    let mut v2_pool_manager = v2_pool_manager
+        .with_chain(&chain)
+        .with_db_manager(db_manager.clone())
+        .with_log_scan(log_scan.unwrap_or_default())
+        .with_cache_config(cache_config)
+        .with_cancellation(shutdown.clone());
+    let v3_pool_manager = match chain.v3_factory() {
+        Some(factory) => UniswapV3PoolManager::new(
+            token_manager.clone(),
+            provider_arc.clone(),
+            chain_id,
+            discovery_start_block,
+            factory,
+        ),
+        None => UniswapV3PoolManager::new_static(token_manager.clone(), provider_arc.clone(), chain_id, []),
+    };
+    let mut v3_pool_manager = v3_pool_manager
+        .with_chain(&chain)
+        .with_db_manager(db_manager.clone())
+        .with_log_scan(log_scan.unwrap_or_default())
+        .with_cache_config(cache_config)
+        .with_cancellation(shutdown.clone());
+    let v4_pool_manager = match chain.v4_pool_manager {
+        Some(_) => UniswapV4PoolManager::new(token_manager.clone(), provider_arc.clone(), discovery_start_block),
+        None => UniswapV4PoolManager::new_static(token_manager.clone(), provider_arc.clone(), []),
+    };
+    let mut v4_pool_manager = v4_pool_manager
+        .with_chain(&chain)
+        .with_db_manager(db_manager.clone())
+        .with_log_scan(log_scan.unwrap_or_default())
+        .with_cache_config(cache_config)
+        .with_cancellation(shutdown.clone());
+    let mut curve_pool_manager = CurvePoolManager::new(
         token_manager.clone(),
         provider_arc.clone(),
-        last_seen_block,
+        discovery_start_block,
         db_manager.clone(),
-    );
+    )
+    .with_chain(&chain)
+    .with_bootstrap_options(BootstrapOptions {
+        retry_failed,
+        low_priority_interval: CURVE_BOOTSTRAP_INTERVAL,
+        ..Default::default()
+    })
+    .with_log_scan(log_scan.unwrap_or_default())
+    .with_cache_config(cache_config)
+    .with_cancellation(shutdown.clone());
     let mut balancer_pool_manager = BalancerPoolManager::new(
         token_manager.clone(),
         provider_arc.clone(),
         db_manager.clone(),
-        last_seen_block,
+        discovery_start_block,
+    )
+    .with_chain(&chain)
+    .with_cancellation(shutdown.clone());
+    if let Some(log_scan) = log_scan {
+        balancer_pool_manager = balancer_pool_manager.with_log_scan(log_scan);
+    }
+    // Restricts Balancer discovery to pools from these factories, e.g. the canonical ones.
+    if let Ok(factories) = std::env::var("ARBRS_BALANCER_FACTORIES") {
+        let factories = factories.split(',').filter_map(|address| address.trim().parse::<Address>().ok());
+        balancer_pool_manager = balancer_pool_manager.with_factories(factories);
+    }
+    let resumed = tokio::join!(
+        v2_pool_manager.resume_discovery(),
+        v3_pool_manager.resume_discovery(),
+        v4_pool_manager.resume_discovery(),
+        curve_pool_manager.resume_discovery(),
+        balancer_pool_manager.resume_discovery()
     );
+    if let Err(e) = resumed.0.and(resumed.1).and(resumed.2).and(resumed.3).and(resumed.4) {
+        tracing::warn!("Failed to load discovery progress: {:?}", e);
+    }
 
     tracing::info!("Hydrating pool managers from database...");
     let mut successful_hydrations = 0;
+    let mut failed_hydrations = HashMap::new();
+    let pool_factories = PoolFactoryRegistry::for_managers(
+        &v2_pool_manager,
+        &v3_pool_manager,
+        &curve_pool_manager,
+        &balancer_pool_manager,
+    )
+    .with_uniswap_v4(&v4_pool_manager);
     for record in &known_pools {
-        tracing::debug!(address = ?record.address, dex = ?record.dex, "Processing record");
-
-        let hydration_result = match record.dex.to_lowercase().as_str() {
-            "uniswap v2" => {
-                v2_pool_manager
-                    .build_v2_pool(
-                        record.address,
-                        record.tokens[0],
-                        record.tokens[1],
-                        arbrs::dex::DexVariant::UniswapV2,
-                    )
-                    .await
-            }
-            "uniswap v3" => {
-                if let (Some(fee), Some(tick_spacing)) = (record.fee, record.tick_spacing) {
-                    v3_pool_manager
-                        .build_pool(
-                            record.address,
-                            record.tokens[0],
-                            record.tokens[1],
-                            fee,
-                            tick_spacing,
-                        )
-                        .await
-                } else {
-                    tracing::warn!(?record.address, "Skipping V3 pool due to missing fee/tick_spacing");
-                    continue;
-                }
-            }
-            "curve" => curve_pool_manager.build_pool_from_record(record).await,
-            "balancer" => balancer_pool_manager.build_pool(record.address).await, // Corrected line
-            unrecognized_dex => {
-                tracing::trace!(dex = unrecognized_dex, "Skipping unrecognized dex type");
-                continue;
-            }
-        };
+        tracing::debug!(address = ?record.address, dex = %record.dex, "Processing record");
 
-        match hydration_result {
+        match pool_factories.build(record).await {
             Ok(_) => {
                 successful_hydrations += 1;
                 tracing::debug!(?record.address, "Successfully hydrated pool.");
             }
+            // The record stays in the database for a build that knows its kind.
+            Err(ArbRsError::UnknownPoolKind(dex)) => {
+                tracing::warn!(?record.address, dex, "Skipping pool of unknown kind");
+                failed_hydrations.insert(record.address, ArbRsError::UnknownPoolKind(dex));
+            }
+            Err(e @ (ArbRsError::TokenNotAllowed { .. } | ArbRsError::TokenUnusable(_))) => {
+                tracing::debug!(?record.address, "Skipping pool: {}", e);
+            }
             Err(e) => {
                 tracing::warn!(?record.address, "Failed to hydrate pool: {:?}", e);
+                failed_hydrations.insert(record.address, e);
             }
         }
     }
+    // It borrows the managers, which discovery needs mutably.
+    drop(pool_factories);
     tracing::info!(
         "Successfully hydrated {} of {} pools.",
         successful_hydrations,
         known_pools.len()
     );
 
+    if bootstrap_curve {
+        match curve_pool_manager
+            .bootstrap_from_registries(CURVE_BOOTSTRAP_CONCURRENCY)
+            .await
+        {
+            Ok(report) => tracing::info!(
+                built = report.built.len(),
+                failed = report.failed.len(),
+                skipped_known = report.skipped_known,
+                skipped_failed = report.skipped_failed,
+                "Curve registry bootstrap finished."
+            ),
+            Err(e) => tracing::warn!("Curve registry bootstrap stopped: {:?}", e),
+        }
+    }
+
+    let calibration = Arc::new(CalibrationTracker::default());
+    if let Err(e) = calibration.load(&db_manager).await {
+        tracing::warn!("Failed to load calibration: {:?}", e);
+    }
+    let pool_health = Arc::new(PoolHealth::default());
+    if let Err(e) = pool_health.load(&db_manager).await {
+        tracing::warn!("Failed to load pool quarantine: {:?}", e);
+    }
+
     let arbitrage_cache = Arc::new(ArbitrageCache::new());
+    let state_updater = Arc::new(StateUpdater::new());
+    // Ramps started before the updater follows the logs are only known from the pools.
+    for pool in curve_pool_manager.get_all_pools() {
+        if let Some(ramp) = pool.as_any().downcast_ref::<CurveStableswapPool<DynProvider>>().and_then(|curve| curve.a_ramping_state()) {
+            state_updater.mark_ramping(pool.address(), ramp.future_a_time.saturating_to());
+        }
+    }
     let arbitrage_engine = ArbitrageEngine::new(
         arbitrage_cache.clone(),
         token_manager.clone(),
         provider_arc.clone(),
-    );
+    )
+    .with_calibration(calibration.clone())
+    .with_pool_health(pool_health.clone())
+    .with_state_updater(state_updater.clone());
+    let arbitrage_engine = match std::env::var("ARBRS_EXPORT_DIR") {
+        Ok(directory) => arbitrage_engine.with_export(ExportConfig {
+            enabled: true,
+            directory: directory.into(),
+            ..Default::default()
+        }),
+        Err(_) => arbitrage_engine,
+    };
+    let arbitrage_engine = match std::env::var("ARBRS_MAX_TRADE_DIVERGENCE_BPS")
+        .ok()
+        .and_then(|bps| bps.parse().ok())
+    {
+        Some(threshold_bps) => arbitrage_engine.with_trade_divergence_check(TradeDivergenceCheck {
+            threshold_bps,
+            reject: false,
+        }),
+        None => arbitrage_engine,
+    };
+    // In ether, e.g. `0.05`, and applied to every profit token at its WETH value.
+    let arbitrage_engine = match std::env::var("ARBRS_MIN_NET_PROFIT_ETH")
+        .ok()
+        .and_then(|amount| parse_units(&amount, 18))
+    {
+        Some(min_net_profit_wei) => arbitrage_engine.with_min_net_profit(min_net_profit_wei),
+        None => arbitrage_engine,
+    };
+    let arbitrage_engine = match std::env::var("ARBRS_MAX_HOP_IMPACT_BPS")
+        .ok()
+        .and_then(|bps| bps.parse().ok())
+    {
+        Some(max_hop_impact_bps) => arbitrage_engine.with_max_hop_impact(max_hop_impact_bps),
+        None => arbitrage_engine,
+    };
+    let arbitrage_engine = match std::env::var("ARBRS_MAX_ORACLE_AGE_SECS")
+        .ok()
+        .and_then(|secs| secs.parse().ok())
+    {
+        Some(max_age_secs) => arbitrage_engine.with_max_oracle_age(max_age_secs),
+        None => arbitrage_engine,
+    };
+    let arbitrage_engine = arbitrage_engine.with_cache_pruning(Some(CachePruning {
+        horizon_blocks: cache_config.max_blocks as u64,
+        ..CachePruning::default()
+    }));
+    let arbitrage_engine = match std::env::var("ARBRS_EVALUATION_BUDGET_MS")
+        .ok()
+        .and_then(|ms| ms.parse().ok())
+    {
+        Some(budget_ms) => arbitrage_engine.with_evaluation_budget(Duration::from_millis(budget_ms)),
+        None => arbitrage_engine,
+    };
+    let arbitrage_engine = match std::env::var("ARBRS_MIN_PERSISTENCE_BLOCKS")
+        .ok()
+        .and_then(|blocks| blocks.parse().ok())
+    {
+        Some(min_blocks) => {
+            let config = EngineConfig {
+                persistence_policy: PersistencePolicy::Suppress { min_blocks },
+                ..arbitrage_engine.config.clone()
+            };
+            arbitrage_engine.with_config(config)
+        }
+        None => arbitrage_engine,
+    };
+
+    let approval_env = |name: &str| std::env::var(name).ok().and_then(|address| address.parse::<Address>().ok());
+    let approvals = match (approval_env("ARBRS_EXECUTOR"), approval_env("ARBRS_APPROVAL_SPENDER")) {
+        (Some(executor), Some(spender)) => Some((Arc::new(ApprovalTracker::new(executor, [spender])), spender)),
+        _ => None,
+    };
+    let arbitrage_engine = match &approvals {
+        Some((tracker, spender)) => {
+            let config = EngineConfig {
+                approval_spender: Some(*spender),
+                ..arbitrage_engine.config.clone()
+            };
+            arbitrage_engine.with_approvals(tracker.clone()).with_config(config)
+        }
+        None => arbitrage_engine,
+    };
+
+    // Costs gas at each block's base fee plus a percentile of recent priority fees, unless
+    // `ARBRS_LEGACY_GAS_PRICE` asks for `eth_gasPrice`.
+    let execution_gas: Box<dyn GasEstimator> = match std::env::var("ARBRS_LEGACY_GAS_PRICE") {
+        Ok(_) => Box::new(LegacyGasPrice::new(provider_arc.clone())),
+        Err(_) => {
+            let mut estimator = Eip1559Estimator::new(provider_arc.clone());
+            if let Some(percentile) = std::env::var("ARBRS_PRIORITY_FEE_PERCENTILE").ok().and_then(|p| p.parse().ok()) {
+                estimator = estimator.with_priority_fee_percentile(percentile);
+            }
+            if let Some(bps) = std::env::var("ARBRS_PRIORITY_FEE_MULTIPLIER_BPS").ok().and_then(|bps| bps.parse().ok()) {
+                estimator = estimator.with_priority_fee_multiplier(bps);
+            }
+            Box::new(estimator)
+        }
+    };
+    // OP-stack chains charge the L1 data fee on top.
+    let arbitrage_engine = match chain.l1_gas_price_oracle {
+        Some(oracle) => arbitrage_engine.with_gas_estimator(Box::new(OpStackGasEstimator::new(
+            provider_arc.clone(),
+            oracle,
+            execution_gas.into(),
+        ))),
+        None => arbitrage_engine.with_gas_estimator(execution_gas),
+    };
+
+    let arbitrage_engine = match std::env::var("ARBRS_USD_PRICES") {
+        Ok(_) => {
+            let aggregator = std::env::var("ARBRS_ETH_USD_AGGREGATOR")
+                .ok()
+                .and_then(|address| address.parse::<Address>().ok())
+                .or(chain.eth_usd_feed);
+            match aggregator {
+                Some(aggregator) => arbitrage_engine
+                    .with_usd_price_feed(Arc::new(ChainlinkUsdPriceFeed::new(provider_arc.clone(), aggregator))),
+                None => {
+                    tracing::warn!(chain = %chain.name, "No ETH/USD feed for the chain, set ARBRS_ETH_USD_AGGREGATOR");
+                    arbitrage_engine
+                }
+            }
+        }
+        Err(_) => arbitrage_engine,
+    };
+
+    // Replays solutions before reporting them: `drop` the ones that fail, `downgrade` them
+    // below the rest, or `pass` them through with the replay attached.
+    let arbitrage_engine = match std::env::var("ARBRS_VERIFY_SOLUTIONS").as_deref() {
+        Ok("drop") => arbitrage_engine.with_verification(VerificationPolicy::Drop),
+        Ok("downgrade") => arbitrage_engine.with_verification(VerificationPolicy::Downgrade),
+        Ok("pass") => arbitrage_engine.with_verification(VerificationPolicy::PassThrough),
+        _ => arbitrage_engine,
+    };
+
+    // Saves every solution to the database, for the path rankings and history.
+    let arbitrage_engine = match std::env::var("ARBRS_RECORD_OPPORTUNITIES") {
+        Ok(_) => arbitrage_engine.with_opportunity_recording(db_manager.clone()),
+        Err(_) => arbitrage_engine,
+    };
+
+    let arbitrage_engine = match std::env::var("ARBRS_DISABLE_MULTICALL") {
+        Ok(_) => arbitrage_engine,
+        Err(_) => arbitrage_engine
+            .with_multicall(Arc::new(MulticallBatcher::new(provider_arc.clone()).with_address(chain.multicall3))),
+    };
+
+    let reserve_drift = std::env::var("ARBRS_RESERVE_DRIFT_BPS")
+        .ok()
+        .and_then(|bps| bps.parse().ok())
+        .map(|threshold_bps| ReserveDriftConfig {
+            threshold_bps,
+            ..Default::default()
+        });
+    let shadow_mode = std::env::var("ARBRS_SHADOW_MODE").is_ok().then(ShadowMode::new);
+
+    if evaluating_path {
+        // A metapool comes before the pool of its underlying coins, which shares its address.
+        let mut known_pools = PoolsByAddress::new();
+        let pools = collect_pools(&v2_pool_manager, &v3_pool_manager, &curve_pool_manager, &balancer_pool_manager);
+        for pool in pools.into_iter().chain(v4_pool_manager.get_all_pools()) {
+            known_pools.entry(pool.address()).or_insert(pool);
+        }
+        return eval_path(&args, &arbitrage_engine, &known_pools, &failed_hydrations, last_seen_block).await;
+    }
 
     println!("Finding initial arbitrage paths...");
 
     let max_hops: usize = 5; 
-    let initial_paths = find_multi_hop_cycles(
-        &v2_pool_manager,
-        &v3_pool_manager,
-        &curve_pool_manager,
-        &balancer_pool_manager,
-        &token_manager,
-        max_hops,
-    )
-    .await;
+    let liquidity_filter = std::env::var("ARBRS_MIN_WETH_LIQUIDITY")
+        .ok()
+        .and_then(|weth| weth.parse::<u64>().ok())
+        .map(|weth| MinLiquidityFilter::weth(U256::from(weth) * U256::from(10).pow(U256::from(18))))
+        .unwrap_or_default();
+    let liquidity_filter = match std::env::var("ARBRS_MIN_POOL_TVL_WETH").ok().and_then(|weth| weth.parse::<u64>().ok()) {
+        Some(weth) => liquidity_filter.with_min_tvl(U256::from(weth) * U256::from(10).pow(U256::from(18))),
+        None => liquidity_filter,
+    };
+    let liquidity_filter = match std::env::var("ARBRS_EXCLUDE_TAXED_TOKENS") {
+        Ok(_) => liquidity_filter.with_taxed_tokens_excluded(),
+        Err(_) => liquidity_filter,
+    };
+    let path_finder = PathFinder::new(max_hops, liquidity_filter);
+    let report = arbitrage_cache
+        .rebuild_with_finder(
+            &path_finder,
+            [
+                collect_pools(&v2_pool_manager, &v3_pool_manager, &curve_pool_manager, &balancer_pool_manager),
+                v4_pool_manager.get_all_pools(),
+            ]
+            .concat(),
+            &token_manager,
+        )
+        .await;
+    log_excluded_pools(&report.excluded_pools);
+    let initial_paths = report.paths;
 
     println!(
         "Found {} potential arbitrage paths (up to {} hops).", 
         initial_paths.len(),
         max_hops
     );
-    for path in initial_paths {
-        arbitrage_cache.add_path(path).await;
+    if backtesting {
+        return backtest(&args, arbitrage_engine).await;
     }
+    let mut traded_pools = pools_by_address(&initial_paths);
+    let swap_filter = Filter::new().event_signature(swap_event_signatures());
 
     println!("Setup complete. Listening for new blocks...");
 
-    while let Some(header) = stream.next().await {
+    // Set when blocks were missed, so discovery catches up on the next block.
+    let mut missed_blocks = false;
+    let mut chain_tracker = ChainTracker::new();
+    while let Some(event) = tokio::select! {
+        biased;
+        _ = shutdown.cancelled() => None,
+        event = stream.next() => event,
+    } {
+        let header = match event {
+            BlockStreamEvent::Block(header) => *header,
+            // The state updater sees the gap itself and re-snapshots what changed in it.
+            BlockStreamEvent::MissedBlocks { from, to } => {
+                println!("\n--- [ Missed Blocks {}..={} while reconnecting ] ---", from, to);
+                missed_blocks = true;
+                continue;
+            }
+        };
         let block_number = header.number;
 
-        println!("\n--- [ New Block Received: {} ] ---", block_number);
-
-        let opportunities = arbitrage_engine
-            .find_opportunities(Some(block_number))
-            .await;
-
-        if opportunities.is_empty() {
-            println!("No profitable opportunities found in this block.");
-        } else {
-            println!(
-                "[!] Found {} profitable opportunities! (Scored by Max Net Profit)",
-                opportunities.len()
-            );
-            if let Some(top_opp) = opportunities.first() {
-                let profit_pool_ref = top_opp.path.get_pools().first().unwrap();
-                let profit_token_arc = profit_pool_ref.get_all_tokens().first().unwrap().clone();
-                let profit_token_symbol = profit_token_arc.symbol(); 
-
-                let net_profit_f64 = top_opp.net_profit.as_limbs()[0] as f64 / 1e18;
-                let input_eth = top_opp.optimal_input.as_limbs()[0] as f64 / 1e18;
+        // Once shutdown is requested, the block gets a grace period to finish rather than
+        // being dropped mid-evaluation.
+        let mut process_block = std::pin::pin!(async {
+            println!("\n--- [ New Block Received: {} ] ---", block_number);
+            token_manager.observe_block(block_number);
+
+            if let Some(reorged_from) = chain_tracker.observe_header(&header) {
+                println!("--- [ Reorg: blocks from {} replaced, invalidating cached state ] ---", reorged_from);
+                arbitrage_engine.invalidate_from(reorged_from).await;
+            }
+
+            let block_swaps = swap_filter.clone().from_block(block_number).to_block(block_number);
+            match provider_arc.get_logs(&block_swaps).await {
+                Ok(logs) => {
+                    let recorded = logs.iter().filter(|log| route_swap_log(&traded_pools, log)).count();
+                    tracing::debug!(recorded, "Recorded last trades from swap logs.");
+                }
+                Err(e) => tracing::warn!("Failed to fetch swap logs: {:?}", e),
+            }
+            if let Err(e) = state_updater.update(provider_arc.as_ref(), block_number).await {
+                tracing::warn!("Failed to fetch pool state logs, re-snapshotting every pool: {:?}", e);
+            }
+            if let Some((tracker, _)) = &approvals {
+                let block_approvals = tracker.approval_filter().from_block(block_number).to_block(block_number);
+                match provider_arc.get_logs(&block_approvals).await {
+                    Ok(logs) => {
+                        let recorded = logs.iter().filter(|log| tracker.record_approval_log(log)).count();
+                        tracing::debug!(recorded, "Updated allowances from approval logs.");
+                    }
+                    Err(e) => tracing::warn!("Failed to fetch approval logs: {:?}", e),
+                }
+            }
+
+            let opportunities = arbitrage_engine
+                .find_opportunities_at_header(&header)
+                .await;
+
+            if let Some(shadow_mode) = &shadow_mode {
+                let snapshots = arbitrage_engine.last_snapshots();
+                match shadow_mode
+                    .observe_and_save(&db_manager, block_number, header.timestamp, &opportunities, &snapshots)
+                    .await
+                {
+                    Ok(records) => {
+                        for record in &records {
+                            println!(
+                                "    => Shadow: block {} {} expected {} realized {}{}",
+                                record.block,
+                                record.path_type,
+                                record.expected_profit(),
+                                record.realized_profit(),
+                                if record.realized_out.is_none() { " (reverted)" } else { "" }
+                            );
+                        }
+                    }
+                    Err(e) => tracing::warn!("Failed to save shadow records: {:?}", e),
+                }
+            }
+
+            if opportunities.is_empty() {
+                println!("No profitable opportunities found in this block.");
+            } else {
                 println!(
-                    "    => Top Opp: NET Profit {:.6} {} from {:.4} {} input",
-                    net_profit_f64, profit_token_symbol, input_eth, profit_token_symbol
+                    "[!] Found {} profitable opportunities! (Scored by Max Net Profit)",
+                    opportunities.len()
                 );
+                if let Some(top_opp) = opportunities.first() {
+                    println!(
+                        "    => Top Opp: NET Profit {:.6} from {:.4} input",
+                        top_opp.net_profit, top_opp.optimal_input
+                    );
+                    if let Some(usd) = top_opp.usd {
+                        println!(
+                            "    => In USD: NET ${} after ${} gas and ${} flashloan fee",
+                            format_usd(usd.net_profit),
+                            format_usd(usd.gas_cost),
+                            format_usd(usd.flashloan_fee)
+                        );
+                    }
+                    println!("    => Profitable for {} consecutive block(s)", top_opp.persistence_blocks);
+
+                    if top_opp.scenario_results.len() > 1 {
+                        let scenarios: Vec<String> = top_opp
+                            .scenario_results
+                            .iter()
+                            .map(|result| {
+                                format!("{} {}", result.label, if result.passes { "pass" } else { "fail" })
+                            })
+                            .collect();
+                        println!("    => Gas scenarios: {}", scenarios.join(", "));
+                    }
 
-                if let (Some(first_action), Some(last_action)) = (top_opp.swap_actions.first(), top_opp.swap_actions.last()) {
-                    let token_in_symbol = first_action.token_in.symbol();
-                    let token_out_symbol = last_action.token_out.symbol();
+                    if let (Some(first_action), Some(last_action)) = (top_opp.swap_actions.first(), top_opp.swap_actions.last()) {
+                        let token_in_symbol = &first_action.token_in.symbol;
+                        let token_out_symbol = &last_action.token_out.symbol;
                     
-                    println!("    => Hop 1: {:.4} {} -> {:.4} {} @ {}", 
-                        first_action.amount_in.as_limbs()[0] as f64 / 1e18, 
-                        token_in_symbol,
-                        first_action.min_amount_out.as_limbs()[0] as f64 / 1e18,
-                        first_action.token_out.symbol(),
-                        first_action.pool_address,
-                    );
-                    println!("    => Final Hop ({}): Output {} {}", 
-                        top_opp.swap_actions.len(),
-                        last_action.min_amount_out.as_limbs()[0] as f64 / 1e18,
-                        token_out_symbol
-                    );
+                        println!("    => Hop 1: {} {} -> {} {} @ {}", 
+                            format_units(first_action.amount_in, first_action.token_in.decimals), 
+                            token_in_symbol,
+                            format_units(first_action.min_amount_out, first_action.token_out.decimals),
+                            first_action.token_out.symbol,
+                            first_action.pool_address,
+                        );
+                        println!("    => Final Hop ({}): Output {} {}", 
+                            top_opp.swap_actions.len(),
+                            format_units(last_action.min_amount_out, last_action.token_out.decimals),
+                            token_out_symbol
+                        );
+                    }
                 }
             }
-        }
 
-        if block_number % 10 == 0 {
-            println!(
-                "\nChecking for new pools since block {}...",
-                last_seen_block
-            );
-            let (v2_discoveries, v3_discoveries, curve_discoveries, balancer_discoveries) = tokio::join!(
-                v2_pool_manager.discover_pools_in_range(block_number),
-                v3_pool_manager.discover_pools_in_range(block_number),
-                curve_pool_manager.discover_pools_in_range(block_number),
-                balancer_pool_manager.discover_pools_in_range(block_number)
-            );
-
-            let new_pools_found = v2_discoveries.is_ok_and(|p| !p.is_empty())
-                || v3_discoveries.is_ok_and(|p| !p.is_empty())
-                || curve_discoveries.is_ok_and(|p| !p.is_empty())
-                || balancer_discoveries.is_ok_and(|p| !p.is_empty());
-
-            if new_pools_found {
-                println!("New pools found! Rebuilding arbitrage paths...");
-                let new_paths = find_multi_hop_cycles(
-                    &v2_pool_manager,
-                    &v3_pool_manager,
-                    &curve_pool_manager,
-                    &balancer_pool_manager,
-                    &token_manager,
-                    max_hops,
-                )
-                .await;
+            if let Some(config) = &reserve_drift {
+                match provider_arc.get_gas_price().await {
+                    Ok(gas_price) => {
+                        let skims = v2_pool_manager
+                            .sweep_reserve_drift(block_number, config, U256::from(gas_price))
+                            .await;
+                        for skim in &skims {
+                            println!(
+                                "    => Skim: {} holds {} / {} over its reserves, worth {} wei against {} wei gas",
+                                skim.pool, skim.amount0, skim.amount1, skim.value_wei, skim.gas_cost_wei
+                            );
+                        }
+                    }
+                    Err(e) => tracing::warn!("Skipping reserve drift sweep, no gas price: {:?}", e),
+                }
+            }
+
+            if block_number % 10 == 0 || missed_blocks {
+                missed_blocks = false;
+                let calibration_table = calibration.table();
+                if !calibration_table.is_empty() {
+                    println!("\nPrediction calibration (error bps, positive = optimistic):");
+                    for row in &calibration_table {
+                        println!(
+                            "    {:<24} samples {:>6}  bias {:>8.2}  sigma {:>8.2}  haircut {:>4}",
+                            row.bucket.to_string(),
+                            row.stats.samples,
+                            row.stats.mean_bps,
+                            row.stats.sigma_bps(),
+                            row.haircut_bps
+                        );
+                    }
+                    if let Err(e) = calibration.save(&db_manager).await {
+                        tracing::warn!("Failed to save calibration: {:?}", e);
+                    }
+                }
+
+                let saved_maps = v3_pool_manager
+                    .save_liquidity_maps(block_number, LIQUIDITY_MAPS_PER_SWEEP)
+                    .await;
+                tracing::debug!(saved_maps, "Stored V3 liquidity maps.");
+                let saved_maps = v4_pool_manager
+                    .save_liquidity_maps(block_number, LIQUIDITY_MAPS_PER_SWEEP)
+                    .await;
+                tracing::debug!(saved_maps, "Stored V4 liquidity maps.");
+
+                let rpc = rpc_metrics.snapshot();
+                tracing::info!(
+                    calls = rpc.calls,
+                    timeouts = rpc.timeouts,
+                    rate_limited = rpc.rate_limited,
+                    retries = rpc.retries,
+                    failures = rpc.failures,
+                    "RPC call counters."
+                );
 
-                arbitrage_cache.paths.write().await.clear();
-                for path in new_paths {
-                    arbitrage_cache.add_path(path).await;
+                let persisting = arbitrage_engine.persistence.longest(5);
+                if !persisting.is_empty() {
+                    println!("\nLongest-persisting profitable paths:");
+                    for (cycle_id, record) in &persisting {
+                        let pools: Vec<String> = cycle_id.0.iter().map(|(pool, _)| pool.to_string()).collect();
+                        println!(
+                            "    {:>4} blocks (since {})  peak net {}  via {}",
+                            record.consecutive_blocks,
+                            record.first_seen_block.unwrap_or_default(),
+                            record.peak_net_profit,
+                            pools.join(" -> ")
+                        );
+                    }
                 }
+
+                if shadow_mode.is_some() {
+                    match db_manager.shadow_pnl_summary(0..=block_number).await {
+                        Ok(rows) if !rows.is_empty() => {
+                            println!("\nShadow PnL (expected vs realized next block):");
+                            for row in &rows {
+                                println!(
+                                    "    day {:>6}  {:<32} {}  trades {:>4}  reverts {:>4}  expected {}  realized {}",
+                                    row.day,
+                                    row.path_type,
+                                    row.profit_token,
+                                    row.trades,
+                                    row.reverts,
+                                    row.expected_profit,
+                                    row.realized_profit
+                                );
+                            }
+                        }
+                        Ok(_) => {}
+                        Err(e) => tracing::warn!("Failed to summarize shadow records: {:?}", e),
+                    }
+                }
+
                 println!(
-                    "Updated to {} potential paths.",
-                    arbitrage_cache.paths.read().await.len()
+                    "\nChecking for new pools since block {}...",
+                    last_seen_block
                 );
-            } else {
-                println!("No new pools found.");
+                let (v2_discoveries, v3_discoveries, v4_discoveries, curve_discoveries, balancer_discoveries) = tokio::join!(
+                    v2_pool_manager.discover_pools_in_range(block_number),
+                    v3_pool_manager.discover_pools_in_range(block_number),
+                    v4_pool_manager.discover_pools_in_range(block_number),
+                    curve_pool_manager.discover_pools_in_range(block_number),
+                    balancer_pool_manager.discover_pools_in_range(block_number)
+                );
+
+                // A new metapool brings the pool of its underlying coins along.
+                let curve_discoveries = curve_discoveries.map(|pools| {
+                    let underlying: Vec<_> = pools
+                        .iter()
+                        .filter_map(|pool| curve_pool_manager.get_underlying_pool(pool.address()))
+                        .collect();
+                    [pools, underlying].concat()
+                });
+                let new_pools: Vec<_> = [v2_discoveries, v3_discoveries, v4_discoveries, curve_discoveries, balancer_discoveries]
+                    .into_iter()
+                    .flat_map(Result::unwrap_or_default)
+                    .collect();
+                let paused_pools_changed = balancer_pool_manager.sweep_paused_pools(block_number).await;
+
+                if paused_pools_changed || (!new_pools.is_empty() && !path_finder.can_extend()) {
+                    println!("Pool set changed! Rebuilding arbitrage paths...");
+                    let report = arbitrage_cache
+                        .rebuild_with_finder(
+                            &path_finder,
+                            [
+                                collect_pools(&v2_pool_manager, &v3_pool_manager, &curve_pool_manager, &balancer_pool_manager),
+                                v4_pool_manager.get_all_pools(),
+                            ]
+                            .concat(),
+                            &token_manager,
+                        )
+                        .await;
+                    log_excluded_pools(&report.excluded_pools);
+                    traded_pools = pools_by_address(&report.paths);
+                    println!("Updated to {} potential paths.", report.paths.len());
+                } else if !new_pools.is_empty() {
+                    // Only the cycles through the new pools are searched; the cached paths
+                    // stay as they are.
+                    println!("Found {} new pools! Extending arbitrage paths...", new_pools.len());
+                    let report = path_finder.extend(new_pools, &token_manager).await;
+                    log_excluded_pools(&report.excluded_pools);
+                    traded_pools.extend(pools_by_address(&report.paths));
+                    let added = arbitrage_cache.add_paths(report.paths).await;
+                    println!(
+                        "Added {} potential paths, {} in total.",
+                        added,
+                        arbitrage_cache.paths.read().await.len()
+                    );
+                } else {
+                    println!("No new pools found.");
+                }
+                last_seen_block = block_number;
             }
-            last_seen_block = block_number;
+        });
+        let finished = tokio::select! {
+            _ = &mut process_block => true,
+            _ = shutdown.cancelled() => false,
+        };
+        if !finished && tokio::time::timeout(SHUTDOWN_GRACE, process_block).await.is_err() {
+            tracing::warn!(block_number, "Block still processing after {:?}, stopping it.", SHUTDOWN_GRACE);
         }
     }
+
+    println!("Flushing state before exit...");
+    arbitrage_engine.shutdown().await;
+    let flushed = tokio::join!(
+        v2_pool_manager.shutdown(),
+        v3_pool_manager.shutdown(),
+        v4_pool_manager.shutdown(),
+        curve_pool_manager.shutdown(),
+        balancer_pool_manager.shutdown()
+    );
+    if let Err(e) = flushed.0.and(flushed.1).and(flushed.2).and(flushed.3).and(flushed.4) {
+        tracing::warn!("Failed to flush discovery progress: {:?}", e);
+    }
+    if let Err(e) = calibration.save(&db_manager).await {
+        tracing::warn!("Failed to save calibration: {:?}", e);
+    }
+    if let Err(e) = pool_health.save(&db_manager).await {
+        tracing::warn!("Failed to save pool quarantine: {:?}", e);
+    }
+    db_manager.shutdown().await;
     Ok(())
 }
\ No newline at end of file
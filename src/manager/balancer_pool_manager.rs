@@ -1,24 +1,47 @@
 use crate::{
-    balancer::pool::BalancerPool, db::DbManager, errors::ArbRsError,
-    manager::token_manager::TokenManager, pool::LiquidityPool,
+    TokenLike,
+    balancer::pool::{BalancerPool, VaultPauseState},
+    db::{DbManager, PoolRecord},
+    chain::{BALANCER_V2_VAULT, ChainConfig},
+    errors::ArbRsError,
+    manager::log_scan::{
+        LogScanConfig, chunked_log_scan, flush_discovery_block, load_discovery_block,
+        save_discovery_block,
+    },
+    manager::token_manager::TokenManager,
+    pool::{DexKind, LiquidityPool, PoolSnapshot},
 };
-use alloy_primitives::{Address, U256, address};
+use alloy_primitives::Address;
 use alloy_provider::Provider;
-use alloy_rpc_types::{Filter, Log};
-use alloy_sol_types::{SolEvent, sol};
-use dashmap::DashMap;
+use alloy_rpc_types::{Filter, TransactionRequest};
+use alloy_sol_types::{SolCall, SolEvent, sol};
+use dashmap::{DashMap, DashSet};
+use futures::future::join_all;
 use futures::stream::{self, StreamExt};
 use std::sync::Arc;
 use tokio::sync::Mutex;
+use tokio_util::sync::CancellationToken;
 
-// The official Balancer V2 Vault address on Mainnet
-const BALANCER_V2_VAULT: Address = address!("BA12222222228d8Ba445958a75a0704d566BF2C8");
+// Balancer events are sparse, so discovery can read larger ranges at once.
+const DISCOVERY_CHUNK_SIZE: u64 = 25_000;
+// Roughly one hour of blocks.
+const DEFAULT_PAUSE_DEACTIVATION_BLOCKS: u64 = 300;
 
 sol! {
-    event PoolRegistered(bytes32 indexed poolId, address indexed poolAddress, uint256 specialization);
+    event PoolRegistered(bytes32 indexed poolId, address indexed poolAddress, uint8 specialization);
+
+    interface IBasePoolFactory {
+        function isPoolFromFactory(address pool) external view returns (bool);
+    }
+
+    interface IBasePool {
+        function getPoolId() external view returns (bytes32);
+    }
 }
 
 type PoolRegistry<P> = DashMap<Address, Arc<dyn LiquidityPool<P>>>;
+type VaultPauseRegistry = DashMap<Address, Arc<VaultPauseState>>;
+type SkippedPools = DashMap<Address, String>;
 
 /// Manages the discovery and lifecycle of Balancer pools.
 pub struct BalancerPoolManager<P: Provider + Send + Sync + 'static + ?Sized> {
@@ -26,7 +49,23 @@ pub struct BalancerPoolManager<P: Provider + Send + Sync + 'static + ?Sized> {
     pool_registry: Arc<PoolRegistry<P>>,
     provider: Arc<P>,
     db_manager: Arc<DbManager>,
+    /// Vault discovery follows. `None` on chains without Balancer, leaving discovery off.
+    vault: Option<Address>,
     last_discovery_block: u64,
+    log_scan: LogScanConfig,
+    vault_pauses: Arc<VaultPauseRegistry>,
+    /// The block at which each currently paused pool was first seen paused.
+    paused_since: DashMap<Address, u64>,
+    inactive_pools: DashSet<Address>,
+    pause_deactivation_blocks: u64,
+    /// Factories discovery is restricted to. Empty accepts every pool the Vault registers.
+    factories: Arc<Vec<Address>>,
+    /// Discovered pools that weren't registered, with the reason.
+    skipped_pools: Arc<SkippedPools>,
+    /// Stops discovery between chunks, with the progress of every scanned chunk recorded.
+    cancel: CancellationToken,
+    /// Whether stored pool ids are checked against the pool's `getPoolId()` on hydration.
+    verify_on_hydrate: bool,
 }
 
 impl<P: Provider + Send + Sync + 'static + ?Sized> BalancerPoolManager<P> {
@@ -42,11 +81,94 @@ impl<P: Provider + Send + Sync + 'static + ?Sized> BalancerPoolManager<P> {
             pool_registry: Arc::new(DashMap::new()),
             provider,
             db_manager,
+            vault: Some(BALANCER_V2_VAULT),
             last_discovery_block: start_block,
+            log_scan: LogScanConfig::default().with_chunk_size(DISCOVERY_CHUNK_SIZE),
+            vault_pauses: Arc::new(DashMap::new()),
+            paused_since: DashMap::new(),
+            inactive_pools: DashSet::new(),
+            pause_deactivation_blocks: DEFAULT_PAUSE_DEACTIVATION_BLOCKS,
+            factories: Arc::new(Vec::new()),
+            skipped_pools: Arc::new(DashMap::new()),
+            cancel: CancellationToken::new(),
+            verify_on_hydrate: true,
         }
     }
 
-    /// Hydrates a pool from a database record.
+    /// Sets how many blocks a pool may stay paused before it is deactivated.
+    pub fn with_pause_deactivation_blocks(mut self, blocks: u64) -> Self {
+        self.pause_deactivation_blocks = blocks;
+        self
+    }
+
+    /// Sets how discovery splits and retries its `getLogs` calls.
+    pub fn with_log_scan(mut self, log_scan: LogScanConfig) -> Self {
+        self.log_scan = log_scan;
+        self
+    }
+
+    /// Stops discovery at the next chunk once `cancel` is cancelled.
+    pub fn with_cancellation(mut self, cancel: CancellationToken) -> Self {
+        self.cancel = cancel;
+        self
+    }
+
+    /// Turns the check of records by `build_pool_from_record` on or off. It's on by default.
+    pub fn with_verify_on_hydrate(mut self, verify_on_hydrate: bool) -> Self {
+        self.verify_on_hydrate = verify_on_hydrate;
+        self
+    }
+
+    /// Follows the Vault of `chain`, or discovers nothing on a chain without one.
+    pub fn with_chain(mut self, chain: &ChainConfig) -> Self {
+        self.vault = chain.balancer_vault;
+        self
+    }
+
+    /// Restricts discovery to pools deployed by `factories`, e.g. the canonical weighted and
+    /// stable pool factories. Pools registered by any other factory are skipped.
+    pub fn with_factories(mut self, factories: impl IntoIterator<Item = Address>) -> Self {
+        self.factories = Arc::new(factories.into_iter().collect());
+        self
+    }
+
+    /// Continues discovery from the last block a previous run stored, if it is further
+    /// along. Returns the block discovery continues after.
+    pub async fn resume_discovery(&mut self) -> Result<u64, ArbRsError> {
+        let Some(vault) = self.vault else {
+            return Ok(self.last_discovery_block);
+        };
+        if let Some(block) = load_discovery_block(&self.db_manager, "balancer", vault).await? {
+            self.last_discovery_block = self.last_discovery_block.max(block);
+        }
+        Ok(self.last_discovery_block)
+    }
+
+    /// Flushes the last block discovery scanned, for the next run to resume after. Call once
+    /// discovery has stopped.
+    pub async fn shutdown(&self) -> Result<(), ArbRsError> {
+        let Some(vault) = self.vault else {
+            return Ok(());
+        };
+        flush_discovery_block(
+            &self.db_manager,
+            "balancer",
+            vault,
+            self.last_discovery_block,
+        )
+        .await
+    }
+
+    /// Registers an already built pool, sharing its vault's pause check with the other pools.
+    pub fn add_pool(&self, pool: BalancerPool<P>) -> Arc<dyn LiquidityPool<P>> {
+        let vault_pause = vault_pause_state(&self.vault_pauses, pool.vault());
+        let pool: Arc<dyn LiquidityPool<P>> = Arc::new(pool.with_vault_pause(vault_pause));
+        self.pool_registry.insert(pool.address(), pool.clone());
+        pool
+    }
+
+    /// Builds the pool at `address` from the chain and stores its pool id, vault and kind,
+    /// so the next start can hydrate it with `build_pool_from_record`.
     pub async fn build_pool(
         &self,
         address: Address,
@@ -55,79 +177,128 @@ impl<P: Provider + Send + Sync + 'static + ?Sized> BalancerPoolManager<P> {
             return Ok(pool.clone());
         }
 
-        tracing::debug!(?address, "Hydrating Balancer pool from DB");
-
-        let pool = Arc::new(
-            BalancerPool::new(
-                address,
-                self.provider.clone(),
-                self.token_manager.clone(),
-                self.db_manager.clone(),
-            )
-            .await?,
-        );
+        tracing::debug!(?address, "Building Balancer pool from chain");
 
-        self.pool_registry.insert(address, pool.clone());
-        tracing::debug!(?address, "Successfully hydrated and cached Balancer pool.");
+        let pool =
+            BalancerPool::new(address, self.provider.clone(), self.token_manager.clone()).await?;
+        save_metadata(&self.db_manager, &pool).await;
+        let pool = self.add_pool(pool);
+        tracing::debug!(?address, "Successfully built and cached Balancer pool.");
 
         Ok(pool)
     }
 
-    /// Discovers new Balancer pools within a specified block range by listening for `PoolRegistered` events.
+    /// Hydrates a pool from a database record. A record with its pool id, vault and kind
+    /// stored only has its swap fee read, after its pool id is checked against the pool's
+    /// own. Any other is built with `build_pool`.
+    pub async fn build_pool_from_record(
+        &self,
+        record: &PoolRecord,
+    ) -> Result<Arc<dyn LiquidityPool<P>>, ArbRsError> {
+        if let Some(pool) = self.pool_registry.get(&record.address) {
+            return Ok(pool.clone());
+        }
+        if record.balancer_pool_id.is_none()
+            || record.balancer_vault.is_none()
+            || record.attributes_json.is_none()
+        {
+            return self.build_pool(record.address).await;
+        }
+        if self.verify_on_hydrate {
+            verify_record(self.provider.as_ref(), record).await?;
+        }
+
+        let pool = BalancerPool::from_record(
+            record,
+            self.provider.clone(),
+            self.token_manager.clone(),
+        )
+        .await?;
+        tracing::debug!(address = ?record.address, "Hydrated Balancer pool from DB");
+        Ok(self.add_pool(pool))
+    }
+
+    /// Discovers new Balancer pools within a specified block range by listening for the
+    /// Vault's `PoolRegistered` events. Each pool is probed for the math it implements, and
+    /// pools that can't be modelled, or don't come from a configured factory, are skipped
+    /// and listed by `skipped_pools`.
     pub async fn discover_pools_in_range(
         &mut self,
         end_block: u64,
     ) -> Result<Vec<Arc<dyn LiquidityPool<P>>>, ArbRsError> {
+        let Some(vault) = self.vault else {
+            return Ok(Vec::new());
+        };
         if end_block <= self.last_discovery_block {
             return Ok(Vec::new());
         }
 
-        const CHUNK_SIZE: u64 = 25000; // Balancer events can be sparse, larger chunk is ok
-        let mut from_block = self.last_discovery_block + 1;
+        let mut scan = chunked_log_scan(
+            Filter::new()
+                .address(vault)
+                .event_signature(PoolRegistered::SIGNATURE_HASH),
+            self.last_discovery_block + 1,
+            end_block,
+            self.log_scan,
+        )
+        .with_cancellation(self.cancel.clone());
         let new_pools = Arc::new(Mutex::new(Vec::new()));
 
-        while from_block <= end_block {
-            let to_block = (from_block + CHUNK_SIZE - 1).min(end_block);
+        while let Some(chunk) = scan.next_chunk(self.provider.as_ref()).await? {
             tracing::info!(
-                "[Balancer Manager] Discovering pools from {} to {}",
-                from_block,
-                to_block
+                from_block = chunk.from_block,
+                to_block = chunk.to_block,
+                pools = chunk.logs.len(),
+                "[Balancer Manager] Discovering pools"
             );
-
-            let event_filter = Filter::new()
-                .address(BALANCER_V2_VAULT)
-                .event_signature(PoolRegistered::SIGNATURE_HASH)
-                .from_block(from_block)
-                .to_block(to_block);
-
-            let logs: Vec<Log> = self.provider.get_logs(&event_filter).await?;
+            crate::metrics::record_discovery_events(Some(DexKind::Balancer), chunk.logs.len());
+            let logs = chunk.logs;
 
             let build_tasks = logs.into_iter().map(|log| {
                 let pool_registry = self.pool_registry.clone();
                 let db_manager = self.db_manager.clone();
                 let token_manager = self.token_manager.clone();
                 let provider = self.provider.clone();
+                let vault_pauses = self.vault_pauses.clone();
+                let factories = self.factories.clone();
+                let skipped_pools = self.skipped_pools.clone();
 
                 async move {
+                    // Any specialization may hold a weighted or stable pool, so the functions
+                    // the pool implements decide whether it is supported.
                     if let Ok(decoded_log) = PoolRegistered::decode_log_data(&log.inner.data) {
-                        // We are only interested in Weighted Pools for now (specialization == 0)
-                        if decoded_log.specialization == U256::ZERO {
-                            match build_new_discovered_pool(
-                                pool_registry,
-                                db_manager,
-                                token_manager,
-                                provider,
-                                decoded_log.poolAddress,
-                            )
-                            .await
-                            {
-                                Ok(pool) => return Some(pool),
-                                Err(e) => tracing::warn!(
-                                    "Failed to build discovered Balancer pool {}: {:?}",
-                                    decoded_log.poolAddress,
-                                    e
-                                ),
+                        match build_new_discovered_pool(
+                            pool_registry,
+                            vault_pauses,
+                            db_manager,
+                            token_manager,
+                            provider,
+                            &factories,
+                            decoded_log.poolAddress,
+                        )
+                        .await
+                        {
+                            Ok(pool) => return Some(pool),
+                            Err(ArbRsError::InvalidPool(address, reason)) => {
+                                tracing::debug!(
+                                    ?address,
+                                    "Skipping unsupported Balancer pool: {}",
+                                    reason
+                                );
+                                skipped_pools.insert(address, reason);
+                            }
+                            Err(
+                                e @ (ArbRsError::TokenNotAllowed { .. }
+                                | ArbRsError::TokenUnusable(_)),
+                            ) => {
+                                tracing::debug!("Skipping Balancer pool: {}", e);
+                                skipped_pools.insert(decoded_log.poolAddress, e.to_string());
                             }
+                            Err(e) => tracing::warn!(
+                                "Failed to build discovered Balancer pool {}: {:?}",
+                                decoded_log.poolAddress,
+                                e
+                            ),
                         }
                     }
                     None
@@ -145,50 +316,170 @@ impl<P: Provider + Send + Sync + 'static + ?Sized> BalancerPoolManager<P> {
                     guard.push(pool);
                 }
             }
+            drop(guard);
 
-            from_block = to_block + 1;
+            self.last_discovery_block = chunk.to_block;
+            if let Err(e) =
+                save_discovery_block(&self.db_manager, "balancer", vault, chunk.to_block).await
+            {
+                tracing::warn!(
+                    block = chunk.to_block,
+                    "Failed to store Balancer discovery progress: {:?}",
+                    e
+                );
+            }
+        }
+
+        if scan.is_cancelled() {
+            tracing::info!(
+                last_block = self.last_discovery_block,
+                "[Balancer Manager] Discovery cancelled"
+            );
         }
 
-        self.last_discovery_block = end_block;
         let final_pools = Arc::try_unwrap(new_pools).unwrap().into_inner();
         Ok(final_pools)
     }
 
-    /// Returns a vector of all pools currently in the manager's registry.
+    /// Pools discovery skipped, with the reason, ordered by address.
+    pub fn skipped_pools(&self) -> Vec<(Address, String)> {
+        let mut skipped: Vec<_> = self
+            .skipped_pools
+            .iter()
+            .map(|entry| (*entry.key(), entry.value().clone()))
+            .collect();
+        skipped.sort();
+        skipped
+    }
+
+    /// Returns a vector of all active pools in the manager's registry.
     pub fn get_all_pools(&self) -> Vec<Arc<dyn LiquidityPool<P>>> {
         self.pool_registry
             .iter()
+            .filter(|entry| !self.inactive_pools.contains(entry.key()))
             .map(|entry| entry.value().clone())
             .collect()
     }
+
+    pub fn is_active(&self, address: Address) -> bool {
+        self.pool_registry.contains_key(&address) && !self.inactive_pools.contains(&address)
+    }
+
+    /// Checks every registered pool's pause state at `block_number`. Pools paused for more
+    /// than `pause_deactivation_blocks` are deactivated, and deactivated pools that are no
+    /// longer paused are reactivated. Returns whether the set of active pools changed.
+    pub async fn sweep_paused_pools(&self, block_number: u64) -> bool {
+        let pools: Vec<_> = self
+            .pool_registry
+            .iter()
+            .map(|entry| entry.value().clone())
+            .collect();
+        let snapshot_futs = pools
+            .iter()
+            .map(|pool| async { (pool.address(), pool.get_snapshot(Some(block_number)).await) });
+
+        let mut changed = false;
+        for (address, result) in join_all(snapshot_futs).await {
+            let is_paused = match result {
+                Ok(PoolSnapshot::Balancer(snapshot)) => snapshot.is_paused,
+                Ok(_) => continue,
+                Err(e) => {
+                    tracing::debug!(
+                        ?address,
+                        "Failed to check Balancer pool pause state: {:?}",
+                        e
+                    );
+                    continue;
+                }
+            };
+
+            if is_paused {
+                let since = *self.paused_since.entry(address).or_insert(block_number);
+                if block_number.saturating_sub(since) > self.pause_deactivation_blocks
+                    && self.inactive_pools.insert(address)
+                {
+                    tracing::info!(?address, since, "Deactivating paused Balancer pool");
+                    changed = true;
+                }
+            } else {
+                self.paused_since.remove(&address);
+                if self.inactive_pools.remove(&address).is_some() {
+                    tracing::info!(?address, "Reactivating unpaused Balancer pool");
+                    changed = true;
+                }
+            }
+        }
+        changed
+    }
+}
+
+fn vault_pause_state(vault_pauses: &VaultPauseRegistry, vault: Address) -> Arc<VaultPauseState> {
+    vault_pauses
+        .entry(vault)
+        .or_insert_with(|| Arc::new(VaultPauseState::new(vault)))
+        .clone()
+}
+
+/// Stores what `BalancerPool::from_record` needs to rebuild `pool` without reading it again.
+async fn save_metadata<P: Provider + Send + Sync + 'static + ?Sized>(
+    db_manager: &DbManager,
+    pool: &BalancerPool<P>,
+) {
+    if let Err(e) = db_manager
+        .update_balancer_metadata(
+            pool.address,
+            pool.pool_id.into(),
+            pool.vault(),
+            &pool.kind_json(),
+        )
+        .await
+    {
+        tracing::warn!(
+            address = ?pool.address,
+            "Failed to store Balancer pool metadata: {:?}",
+            e
+        );
+    }
 }
 
 /// Helper function to build a newly discovered pool, save it to the DB, and register it.
 async fn build_new_discovered_pool<P: Provider + Send + Sync + 'static + ?Sized>(
     pool_registry: Arc<PoolRegistry<P>>,
+    vault_pauses: Arc<VaultPauseRegistry>,
     db_manager: Arc<DbManager>,
     token_manager: Arc<TokenManager<P>>,
     provider: Arc<P>,
+    factories: &[Address],
     pool_address: Address,
 ) -> Result<Arc<dyn LiquidityPool<P>>, ArbRsError> {
     if pool_registry.contains_key(&pool_address) {
         return Err(ArbRsError::DataFetchError(pool_address));
     }
+    if !factories.is_empty()
+        && !is_from_factories(provider.as_ref(), factories, pool_address).await?
+    {
+        return Err(ArbRsError::InvalidPool(
+            pool_address,
+            "not deployed by a configured factory".into(),
+        ));
+    }
 
     tracing::info!("[Balancer Manager] New pool discovered: {}", pool_address);
 
-    let pool = Arc::new(
-        BalancerPool::new(
-            pool_address,
-            provider,
-            token_manager.clone(),
-            db_manager.clone(),
-        )
-        .await?,
-    );
+    let pool = BalancerPool::new(pool_address, provider, token_manager.clone()).await?;
+    token_manager.token_policy().check_pool(
+        pool_address,
+        pool.get_all_tokens().iter().map(|token| token.address()),
+    )?;
 
     db_manager
-        .save_pool(pool_address, "balancer", &pool.get_all_tokens(), None, None)
+        .save_pool(
+            pool_address,
+            &pool.pool_kind(),
+            &pool.get_all_tokens(),
+            None,
+            None,
+        )
         .await
         .unwrap_or_else(|e| {
             tracing::error!(
@@ -197,7 +488,60 @@ async fn build_new_discovered_pool<P: Provider + Send + Sync + 'static + ?Sized>
                 e
             );
         });
+    save_metadata(&db_manager, &pool).await;
 
+    let vault_pause = vault_pause_state(&vault_pauses, pool.vault());
+    let pool: Arc<dyn LiquidityPool<P>> = Arc::new(pool.with_vault_pause(vault_pause));
     pool_registry.insert(pool_address, pool.clone());
     Ok(pool)
 }
+
+/// Refuses a record whose pool id isn't the pool's `getPoolId()`.
+async fn verify_record<P: Provider + Send + Sync + 'static + ?Sized>(
+    provider: &P,
+    record: &PoolRecord,
+) -> Result<(), ArbRsError> {
+    let Some(stored) = record.balancer_pool_id else {
+        return Ok(());
+    };
+    let bytes = provider
+        .call(
+            TransactionRequest::default()
+                .to(record.address)
+                .input(IBasePool::getPoolIdCall {}.abi_encode().into()),
+        )
+        .await?;
+    let pool_id = IBasePool::getPoolIdCall::abi_decode_returns(&bytes)?;
+    if pool_id != stored {
+        return Err(ArbRsError::AddressMismatch(
+            record.address,
+            format!("getPoolId() is {pool_id}, not the stored {stored}"),
+        ));
+    }
+    Ok(())
+}
+
+/// Whether any of `factories` deployed `pool`.
+async fn is_from_factories<P: Provider + Send + Sync + 'static + ?Sized>(
+    provider: &P,
+    factories: &[Address],
+    pool: Address,
+) -> Result<bool, ArbRsError> {
+    let checks = factories.iter().map(|&factory| async move {
+        let call = IBasePoolFactory::isPoolFromFactoryCall { pool };
+        let bytes = provider
+            .call(
+                TransactionRequest::default()
+                    .to(factory)
+                    .input(call.abi_encode().into()),
+            )
+            .await?;
+        Ok::<_, ArbRsError>(IBasePoolFactory::isPoolFromFactoryCall::abi_decode_returns(&bytes)?)
+    });
+    for result in join_all(checks).await {
+        if result? {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
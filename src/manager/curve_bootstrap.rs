@@ -0,0 +1,299 @@
+use crate::db::DbManager;
+use crate::errors::ArbRsError;
+use alloy_primitives::{Address, U256};
+use alloy_provider::Provider;
+use alloy_rpc_types::TransactionRequest;
+use alloy_sol_types::{SolCall, sol};
+use async_trait::async_trait;
+use futures::future::join_all;
+use std::collections::HashSet;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tokio::time::Instant;
+use tokio_util::sync::CancellationToken;
+
+pub use crate::curve::registry::CURVE_META_REGISTRY;
+
+const CHECKPOINT_KEY_PREFIX: &str = "curve_bootstrap:";
+
+sol! {
+    // Shared by the legacy registry and the MetaRegistry.
+    interface IPoolListRegistry {
+        function pool_count() external view returns (uint256);
+        function pool_list(uint256 i) external view returns (address);
+    }
+}
+
+/// A registry whose pools can be listed by index.
+#[async_trait]
+pub trait PoolEnumerator: Send + Sync {
+    /// Stable name used for checkpoints and failure records.
+    fn id(&self) -> String;
+    async fn pool_count(&self) -> Result<u64, ArbRsError>;
+    async fn pool_at(&self, index: u64) -> Result<Address, ArbRsError>;
+}
+
+/// Enumerates a registry through `pool_count()` / `pool_list(i)`.
+pub struct RegistryEnumerator<P: Provider + Send + Sync + 'static + ?Sized> {
+    name: &'static str,
+    address: Address,
+    provider: Arc<P>,
+}
+
+impl<P: Provider + Send + Sync + 'static + ?Sized> RegistryEnumerator<P> {
+    pub fn new(name: &'static str, address: Address, provider: Arc<P>) -> Self {
+        Self {
+            name,
+            address,
+            provider,
+        }
+    }
+
+    async fn call<C: SolCall>(&self, call: C) -> Result<C::Return, ArbRsError> {
+        let request = TransactionRequest::default()
+            .to(self.address)
+            .input(call.abi_encode().into());
+        let result_bytes = self
+            .provider
+            .call(request)
+            .await
+            .map_err(|e| ArbRsError::ProviderError(e.to_string()))?;
+        Ok(C::abi_decode_returns(&result_bytes)?)
+    }
+}
+
+#[async_trait]
+impl<P: Provider + Send + Sync + 'static + ?Sized> PoolEnumerator for RegistryEnumerator<P> {
+    fn id(&self) -> String {
+        format!("{}:{}", self.name, self.address)
+    }
+
+    async fn pool_count(&self) -> Result<u64, ArbRsError> {
+        let count = self.call(IPoolListRegistry::pool_countCall {}).await?;
+        u64::try_from(count)
+            .map_err(|_| ArbRsError::CalculationError("Registry pool count overflow".to_string()))
+    }
+
+    async fn pool_at(&self, index: u64) -> Result<Address, ArbRsError> {
+        self.call(IPoolListRegistry::pool_listCall {
+            i: U256::from(index),
+        })
+        .await
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BootstrapOptions {
+    /// Rebuild pools recorded as failed by earlier runs instead of skipping them.
+    pub retry_failed: bool,
+    /// Stops the run after the pools already being built; progress up to that point is kept.
+    pub cancel: CancellationToken,
+    /// Minimum spacing between registry lookups. Bootstrapping is background work, so it
+    /// paces itself rather than competing with the per-block calls.
+    pub low_priority_interval: Duration,
+}
+
+impl Default for BootstrapOptions {
+    fn default() -> Self {
+        Self {
+            retry_failed: false,
+            cancel: CancellationToken::new(),
+            low_priority_interval: Duration::ZERO,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct BootstrapReport {
+    pub built: Vec<Address>,
+    /// Pools that failed to build in this run, with their error.
+    pub failed: Vec<(Address, String)>,
+    pub skipped_known: usize,
+    pub skipped_failed: usize,
+    /// Enumerators that could not be queried and were skipped.
+    pub unsupported: Vec<String>,
+    /// False if the run was cancelled before every enumerator was exhausted.
+    pub completed: bool,
+}
+
+enum Outcome {
+    Built(Address),
+    Failed(Address, String),
+    Known,
+    PreviouslyFailed,
+}
+
+/// Walks every enumerator from its checkpoint, building each pool not yet in the database.
+///
+/// Pools are built in batches of `concurrency` and results are recorded in index order,
+/// advancing the checkpoint after each one. A cancelled run resumes at the first index
+/// whose result was not recorded; pools built past that point are already persisted and
+/// show up as known on resume.
+pub async fn run_bootstrap<F, Fut>(
+    db_manager: &DbManager,
+    enumerators: &[Arc<dyn PoolEnumerator>],
+    concurrency: usize,
+    options: &BootstrapOptions,
+    build: F,
+) -> Result<BootstrapReport, ArbRsError>
+where
+    F: Fn(Address) -> Fut + Sync,
+    Fut: Future<Output = Result<(), ArbRsError>> + Send,
+{
+    let mut report = BootstrapReport::default();
+    let mut known = db_manager.load_pool_addresses().await.map_err(db_error)?;
+    let mut failed: HashSet<Address> = HashSet::new();
+
+    for (address, source, _) in db_manager.load_pool_failures().await.map_err(db_error)? {
+        if !options.retry_failed {
+            failed.insert(address);
+            continue;
+        }
+        if options.cancel.is_cancelled() {
+            return Ok(report);
+        }
+        match build(address).await {
+            Ok(()) => {
+                db_manager
+                    .clear_pool_failure(address)
+                    .await
+                    .map_err(db_error)?;
+                known.insert(address);
+                report.built.push(address);
+            }
+            Err(e) => {
+                let error = e.to_string();
+                db_manager
+                    .record_pool_failure(address, &source, &error)
+                    .await
+                    .map_err(db_error)?;
+                report.failed.push((address, error));
+            }
+        }
+    }
+
+    let pacer = Pacer::new(options.low_priority_interval);
+    for enumerator in enumerators {
+        let id = enumerator.id();
+        let count = match enumerator.pool_count().await {
+            Ok(count) => count,
+            Err(e) => {
+                tracing::warn!(enumerator = %id, "Skipping registry enumeration: {:?}", e);
+                report.unsupported.push(id);
+                continue;
+            }
+        };
+        let checkpoint_key = format!("{CHECKPOINT_KEY_PREFIX}{id}");
+        let start = db_manager
+            .get_state(&checkpoint_key)
+            .await
+            .map_err(db_error)?
+            .and_then(|value| value.parse::<u64>().ok())
+            .unwrap_or(0);
+        tracing::info!(enumerator = %id, start, count, "Bootstrapping pools from registry");
+
+        let mut exhausted = true;
+        let batch_size = concurrency.max(1) as u64;
+        let mut batch_start = start;
+        'batches: while batch_start < count {
+            let batch_end = (batch_start + batch_size).min(count);
+            // Results are recorded only once the whole batch has finished, so no build is
+            // left suspended mid-write while the checkpoint is saved.
+            let results = join_all((batch_start..batch_end).map(|index| {
+                let (known, failed, pacer, build) = (&known, &failed, &pacer, &build);
+                async move {
+                    if options.cancel.is_cancelled() {
+                        return None;
+                    }
+                    pacer.wait().await;
+                    let outcome = match enumerator.pool_at(index).await {
+                        Ok(address) if known.contains(&address) => Ok(Outcome::Known),
+                        Ok(address) if failed.contains(&address) => Ok(Outcome::PreviouslyFailed),
+                        Ok(address) => {
+                            if options.cancel.is_cancelled() {
+                                return None;
+                            }
+                            Ok(match build(address).await {
+                                Ok(()) => Outcome::Built(address),
+                                Err(e) => Outcome::Failed(address, e.to_string()),
+                            })
+                        }
+                        Err(e) => Err(e),
+                    };
+                    Some((index, outcome))
+                }
+            }))
+            .await;
+
+            for result in results {
+                let Some((index, outcome)) = result else {
+                    exhausted = false;
+                    break 'batches;
+                };
+                match outcome? {
+                    Outcome::Built(address) => report.built.push(address),
+                    Outcome::Failed(address, error) => {
+                        tracing::warn!(?address, "Failed to build registry pool: {}", error);
+                        db_manager
+                            .record_pool_failure(address, &id, &error)
+                            .await
+                            .map_err(db_error)?;
+                        report.failed.push((address, error));
+                    }
+                    Outcome::Known => report.skipped_known += 1,
+                    Outcome::PreviouslyFailed => report.skipped_failed += 1,
+                }
+                db_manager
+                    .set_state(&checkpoint_key, &(index + 1).to_string())
+                    .await
+                    .map_err(db_error)?;
+            }
+            batch_start = batch_end;
+        }
+
+        if !exhausted {
+            tracing::info!(enumerator = %id, "Registry bootstrap cancelled");
+            return Ok(report);
+        }
+        // Another registry may list the same pools.
+        known.extend(report.built.iter().copied());
+        failed.extend(report.failed.iter().map(|(address, _)| *address));
+    }
+
+    report.completed = true;
+    Ok(report)
+}
+
+fn db_error(e: sqlx::Error) -> ArbRsError {
+    ArbRsError::DatabaseError(e.to_string())
+}
+
+/// Spaces out calls by a fixed interval across all concurrent tasks.
+struct Pacer {
+    interval: Duration,
+    next: Mutex<Instant>,
+}
+
+impl Pacer {
+    fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            next: Mutex::new(Instant::now()),
+        }
+    }
+
+    async fn wait(&self) {
+        if self.interval.is_zero() {
+            return;
+        }
+        let at = {
+            let mut next = self.next.lock().await;
+            let at = (*next).max(Instant::now());
+            *next = at + self.interval;
+            at
+        };
+        tokio::time::sleep_until(at).await;
+    }
+}
@@ -1,21 +1,35 @@
 use crate::{
-    curve::{attributes_builder, pool::CurveStableswapPool, registry::CurveRegistry},
+    TokenLike,
+    chain::{CURVE_MAINNET_REGISTRY, ChainConfig},
+    curve::{
+        attributes_builder,
+        pool::CurveStableswapPool,
+        registry::{CurveRegistry, RegistrySource},
+        underlying::UnderlyingCurvePool,
+    },
     db::{DbManager, PoolRecord},
+    dex::PoolKind,
     errors::ArbRsError,
+    manager::curve_bootstrap::{
+        BootstrapOptions, BootstrapReport, PoolEnumerator, RegistryEnumerator, run_bootstrap,
+    },
+    manager::log_scan::{
+        LogScanConfig, chunked_log_scan, flush_discovery_block, load_discovery_block,
+        save_discovery_block,
+    },
     manager::token_manager::TokenManager,
-    pool::LiquidityPool,
+    pool::{DexKind, LiquidityPool, cache::CacheConfig},
 };
-use alloy_primitives::{Address, address};
+use alloy_primitives::Address;
 use alloy_provider::Provider;
-use alloy_rpc_types::{Filter, Log};
+use async_recursion::async_recursion;
+use alloy_rpc_types::Filter;
 use alloy_sol_types::{SolEvent, sol};
 use dashmap::DashMap;
 use futures::stream::{self, StreamExt};
 use std::sync::Arc;
 use tokio::sync::Mutex;
-
-/// Mainnet Curve Registry Address
-const CURVE_MAINNET_REGISTRY: Address = address!("90E00ACe148ca3b23Ac1bC8C240C2a7Dd9c2d7f5");
+use tokio_util::sync::CancellationToken;
 
 sol! {
     event PoolAdded(address indexed pool);
@@ -26,10 +40,20 @@ type PoolRegistry<P> = DashMap<Address, Arc<dyn LiquidityPool<P>>>;
 pub struct CurvePoolManager<P: Provider + Send + Sync + 'static + ?Sized> {
     token_manager: Arc<TokenManager<P>>,
     pool_registry: Arc<PoolRegistry<P>>,
+    /// Each metapool's [`UnderlyingCurvePool`], under the metapool's address.
+    underlying_pools: Arc<PoolRegistry<P>>,
     provider: Arc<P>,
     curve_registry: CurveRegistry<P>,
+    /// Off on chains without a Curve registry, where pools are only built by hand.
+    discovery_enabled: bool,
     pub last_discovery_block: u64,
+    log_scan: LogScanConfig,
     db_manager: Arc<DbManager>,
+    bootstrap_options: BootstrapOptions,
+    /// Whether records are checked against the pool's `coins(0)` before they're built.
+    verify_on_hydrate: bool,
+    /// Retention of the per-block caches of the pools it builds.
+    cache_config: CacheConfig,
 }
 
 impl<P: Provider + Send + Sync + 'static + ?Sized> CurvePoolManager<P> {
@@ -43,67 +67,176 @@ impl<P: Provider + Send + Sync + 'static + ?Sized> CurvePoolManager<P> {
         Self {
             token_manager,
             pool_registry: Arc::new(DashMap::new()),
+            underlying_pools: Arc::new(DashMap::new()),
             provider,
             curve_registry,
+            discovery_enabled: true,
             last_discovery_block: start_block,
+            log_scan: LogScanConfig::default(),
             db_manager,
+            bootstrap_options: BootstrapOptions::default(),
+            verify_on_hydrate: true,
+            cache_config: CacheConfig::default(),
         }
     }
 
-    pub async fn discover_pools_in_range(
+    /// Registers the LP token and base pool of a pool none of the Curve registries know, so
+    /// it can be built.
+    pub fn register_pool_metadata(&self, pool: Address, lp_token: Address, base_pool: Option<Address>) {
+        self.curve_registry.register_pool_metadata(pool, lp_token, base_pool);
+    }
+
+    /// Follows the registries of `chain`. On a chain without a Curve registry, discovery and
+    /// the bootstrap are turned off. Replaces any pool metadata registered so far.
+    pub fn with_chain(mut self, chain: &ChainConfig) -> Self {
+        self.discovery_enabled = chain.curve_registry.is_some();
+        self.curve_registry = CurveRegistry::new(
+            chain.curve_registry.unwrap_or_default(),
+            self.provider.clone(),
+        )
+        .with_sources(chain.curve_registry_sources.iter().copied());
+        self
+    }
+
+    pub fn with_bootstrap_options(mut self, options: BootstrapOptions) -> Self {
+        self.bootstrap_options = options;
+        self
+    }
+
+    /// Stops discovery at the next chunk, and the registry bootstrap at the next pool, once
+    /// `cancel` is cancelled. Replaces the token of the bootstrap options.
+    pub fn with_cancellation(mut self, cancel: CancellationToken) -> Self {
+        self.bootstrap_options.cancel = cancel;
+        self
+    }
+
+    /// Sets how discovery splits and retries its `getLogs` calls.
+    pub fn with_log_scan(mut self, log_scan: LogScanConfig) -> Self {
+        self.log_scan = log_scan;
+        self
+    }
+
+    /// Turns the check of records by `build_pool_from_record` on or off. It's on by default.
+    pub fn with_verify_on_hydrate(mut self, verify_on_hydrate: bool) -> Self {
+        self.verify_on_hydrate = verify_on_hydrate;
+        self
+    }
+
+    /// Bounds the per-block caches of the pools it builds from now on.
+    pub fn with_cache_config(mut self, cache_config: CacheConfig) -> Self {
+        self.cache_config = cache_config;
+        self
+    }
+
+    /// Continues discovery from the last block a previous run stored, if it is further
+    /// along. Returns the block discovery continues after.
+    pub async fn resume_discovery(&mut self) -> Result<u64, ArbRsError> {
+        if !self.discovery_enabled {
+            return Ok(self.last_discovery_block);
+        }
+        if let Some(block) =
+            load_discovery_block(&self.db_manager, "curve", self.curve_registry.address).await?
+        {
+            self.last_discovery_block = self.last_discovery_block.max(block);
+        }
+        Ok(self.last_discovery_block)
+    }
+
+    /// Flushes the last block discovery scanned, for the next run to resume after. Call once
+    /// discovery has stopped.
+    pub async fn shutdown(&self) -> Result<(), ArbRsError> {
+        if !self.discovery_enabled {
+            return Ok(());
+        }
+        flush_discovery_block(
+            &self.db_manager,
+            "curve",
+            self.curve_registry.address,
+            self.last_discovery_block,
+        )
+        .await
+    }
+
+    /// Builds every pool listed by the legacy registry and, where it responds, the
+    /// MetaRegistry. Progress is checkpointed in the database, so an interrupted run picks
+    /// up where it stopped.
+    pub async fn bootstrap_from_registries(
         &self,
+        concurrency: usize,
+    ) -> Result<BootstrapReport, ArbRsError> {
+        if !self.discovery_enabled {
+            return Ok(BootstrapReport::default());
+        }
+        let mut enumerators: Vec<Arc<dyn PoolEnumerator>> = vec![Arc::new(
+            RegistryEnumerator::new("registry", self.curve_registry.address, self.provider.clone()),
+        )];
+        if let Some(meta_registry) = self.curve_registry.source_address(RegistrySource::MetaRegistry)
+        {
+            enumerators.push(Arc::new(RegistryEnumerator::new(
+                "meta_registry",
+                meta_registry,
+                self.provider.clone(),
+            )));
+        }
+
+        let build_context = &self.build_context();
+        run_bootstrap(
+            &self.db_manager,
+            &enumerators,
+            concurrency,
+            &self.bootstrap_options,
+            |pool_address| async move {
+                build_new_discovered_pool(build_context, pool_address)
+                    .await
+                    .map(|_| ())
+            },
+        )
+        .await
+    }
+
+    /// Discovers pools added to the registry up to `end_block`, storing progress after each
+    /// chunk of blocks.
+    pub async fn discover_pools_in_range(
+        &mut self,
         end_block: u64,
     ) -> Result<Vec<Arc<dyn LiquidityPool<P>>>, ArbRsError> {
-        if end_block <= self.last_discovery_block {
+        if !self.discovery_enabled || end_block <= self.last_discovery_block {
             return Ok(Vec::new());
         }
 
-        const CHUNK_SIZE: u64 = 10000;
-        let mut from_block = self.last_discovery_block + 1;
+        let mut scan = chunked_log_scan(
+            Filter::new()
+                .address(self.curve_registry.address)
+                .event_signature(PoolAdded::SIGNATURE_HASH),
+            self.last_discovery_block + 1,
+            end_block,
+            self.log_scan,
+        )
+        .with_cancellation(self.bootstrap_options.cancel.clone());
         let new_pools = Arc::new(Mutex::new(Vec::new()));
 
-        while from_block <= end_block {
-            let to_block = (from_block + CHUNK_SIZE - 1).min(end_block);
-            println!(
-                "[Curve Manager] Discovering pools from {} to {}",
-                from_block, to_block
+        while let Some(chunk) = scan.next_chunk(self.provider.as_ref()).await? {
+            tracing::info!(
+                from_block = chunk.from_block,
+                to_block = chunk.to_block,
+                pools = chunk.logs.len(),
+                "[Curve Manager] Discovering pools"
             );
+            crate::metrics::record_discovery_events(Some(DexKind::Curve), chunk.logs.len());
+            let logs = chunk.logs;
 
-            let event_filter = Filter::new()
-                .address(self.curve_registry.address)
-                .event_signature(PoolAdded::SIGNATURE_HASH)
-                .from_block(from_block)
-                .to_block(to_block);
-
-            let logs: Vec<Log> = self.provider.get_logs(&event_filter).await?;
-
-            let provider = self.provider.clone();
-            let token_manager = self.token_manager.clone();
-            let curve_registry = self.curve_registry.clone();
-            let db_manager = self.db_manager.clone();
-            let pool_registry = self.pool_registry.clone();
+            let build_context = self.build_context();
             let new_pools_clone = new_pools.clone();
 
             stream::iter(logs)
                 .for_each_concurrent(5, move |log| {
-                    let provider = provider.clone();
-                    let token_manager = token_manager.clone();
-                    let curve_registry = curve_registry.clone();
-                    let db_manager = db_manager.clone();
-                    let pool_registry = pool_registry.clone();
+                    let build_context = build_context.clone();
                     let new_pools_clone = new_pools_clone.clone();
 
                     async move {
                         if let Ok(decoded_log) = PoolAdded::decode_log_data(&log.inner.data) {
-                            if let Ok(pool) = build_new_discovered_pool(
-                                pool_registry,
-                                db_manager,
-                                token_manager,
-                                provider,
-                                &curve_registry,
-                                decoded_log.pool,
-                            )
-                            .await
+                            if let Ok(pool) =
+                                build_new_discovered_pool(&build_context, decoded_log.pool).await
                             {
                                 new_pools_clone.lock().await.push(pool);
                             }
@@ -112,13 +245,37 @@ impl<P: Provider + Send + Sync + 'static + ?Sized> CurvePoolManager<P> {
                 })
                 .await;
 
-            from_block = to_block + 1;
+            self.last_discovery_block = chunk.to_block;
+            if let Err(e) = save_discovery_block(
+                &self.db_manager,
+                "curve",
+                self.curve_registry.address,
+                chunk.to_block,
+            )
+            .await
+            {
+                tracing::warn!(
+                    block = chunk.to_block,
+                    "Failed to store Curve discovery progress: {:?}",
+                    e
+                );
+            }
+        }
+
+        if scan.is_cancelled() {
+            tracing::info!(
+                last_block = self.last_discovery_block,
+                "[Curve Manager] Discovery cancelled"
+            );
         }
 
         let final_pools = Arc::try_unwrap(new_pools).unwrap().into_inner();
         Ok(final_pools)
     }
 
+    /// Builds `record`'s pool, after checking that the contract at its address holds the
+    /// record's first coin. Curve pools' addresses don't derive from their coins, so the
+    /// check takes a call.
     pub async fn build_pool_from_record(
         &self,
         record: &PoolRecord,
@@ -126,7 +283,24 @@ impl<P: Provider + Send + Sync + 'static + ?Sized> CurvePoolManager<P> {
         if let Some(pool) = self.pool_registry.get(&record.address) {
             return Ok(pool.clone());
         }
+        if self.verify_on_hydrate {
+            verify_record(self.provider.as_ref(), record).await?;
+        }
 
+        let pool = Arc::new(self.build_curve_pool(record).await?);
+        register_underlying(&self.underlying_pools, &pool);
+        self.pool_registry.insert(record.address, pool.clone());
+        Ok(pool)
+    }
+
+    /// Builds `record`'s pool with `CurveStableswapPool::from_record` when its LP token,
+    /// coins and any base pool's record are stored, and from the chain otherwise, storing
+    /// what the next start needs to skip that.
+    #[async_recursion]
+    async fn build_curve_pool(
+        &self,
+        record: &PoolRecord,
+    ) -> Result<CurveStableswapPool<P>, ArbRsError> {
         let attributes = if let Some(json_attributes) = &record.attributes_json {
             println!(
                 "[CACHE HIT] Loaded Curve attributes for {} from DB.",
@@ -139,15 +313,7 @@ impl<P: Provider + Send + Sync + 'static + ?Sized> CurvePoolManager<P> {
                 "[CACHE MISS] Fetching Curve attributes for {} from on-chain...",
                 record.address
             );
-            let tokens: Vec<_> = futures::future::join_all(
-                record
-                    .tokens
-                    .iter()
-                    .map(|&addr| self.token_manager.get_token(addr)),
-            )
-            .await
-            .into_iter()
-            .collect::<Result<_, _>>()?;
+            let tokens = self.token_manager.get_token_list(&record.tokens).await?;
 
             let fetched_attributes = attributes_builder::build_attributes(
                 record.address,
@@ -170,37 +336,152 @@ impl<P: Provider + Send + Sync + 'static + ?Sized> CurvePoolManager<P> {
             fetched_attributes
         };
 
-        let pool = Arc::new(
-            CurveStableswapPool::new(
-                record.address,
-                self.provider.clone(),
-                self.token_manager.clone(),
-                &self.curve_registry,
-                attributes,
-            )
-            .await?,
-        );
+        if let Some(lp_token) = record.lp_token
+            && !attributes.raw_coin_addresses.is_empty()
+        {
+            // A metapool's base pool is rebuilt from its own record, so without one the
+            // metapool is built from the chain.
+            let base_record = match attributes.base_pool_address {
+                Some(base_pool_address) => self
+                    .db_manager
+                    .load_pool(base_pool_address)
+                    .await
+                    .ok()
+                    .flatten(),
+                None => None,
+            };
+            if attributes.base_pool_address.is_none() || base_record.is_some() {
+                let base_pool = match &base_record {
+                    Some(base_record) => Some(Arc::new(self.build_curve_pool(base_record).await?)),
+                    None => None,
+                };
+                return CurveStableswapPool::from_record(
+                    record.address,
+                    lp_token,
+                    base_pool,
+                    self.provider.clone(),
+                    self.token_manager.clone(),
+                    attributes,
+                )
+                .await
+                .map(|pool| pool.with_cache_config(self.cache_config));
+            }
+        }
 
-        self.pool_registry.insert(record.address, pool.clone());
+        let pool = CurveStableswapPool::new(
+            record.address,
+            self.provider.clone(),
+            self.token_manager.clone(),
+            &self.curve_registry,
+            attributes,
+        )
+        .await?
+        .with_cache_config(self.cache_config);
+        save_metadata(&self.db_manager, &pool).await;
         Ok(pool)
     }
 
+    fn build_context(&self) -> CurveBuildContext<P> {
+        CurveBuildContext {
+            pool_registry: self.pool_registry.clone(),
+            underlying_pools: self.underlying_pools.clone(),
+            db_manager: self.db_manager.clone(),
+            token_manager: self.token_manager.clone(),
+            provider: self.provider.clone(),
+            curve_registry: self.curve_registry.clone(),
+            cache_config: self.cache_config,
+        }
+    }
+
     pub fn get_all_pools(&self) -> Vec<Arc<dyn LiquidityPool<P>>> {
         self.pool_registry
             .iter()
             .map(|entry| entry.value().clone())
             .collect()
     }
+
+    /// The metapools' underlying coins, each as a pool sharing its metapool's address.
+    pub fn get_underlying_pools(&self) -> Vec<Arc<dyn LiquidityPool<P>>> {
+        self.underlying_pools
+            .iter()
+            .map(|entry| entry.value().clone())
+            .collect()
+    }
+
+    /// The underlying coins of the metapool at `address` as a pool, if it's a metapool.
+    pub fn get_underlying_pool(&self, address: Address) -> Option<Arc<dyn LiquidityPool<P>>> {
+        self.underlying_pools
+            .get(&address)
+            .map(|entry| entry.value().clone())
+    }
 }
 
-async fn build_new_discovered_pool<P: Provider + Send + Sync + 'static + ?Sized>(
+/// Refuses a record whose first coin isn't the pool's `coins(0)`.
+async fn verify_record<P: Provider + Send + Sync + 'static + ?Sized>(
+    provider: &P,
+    record: &PoolRecord,
+) -> Result<(), ArbRsError> {
+    let Some(&stored) = record.tokens.first() else {
+        return Err(ArbRsError::InvalidPool(record.address, "stored without coins".to_string()));
+    };
+    let coin = CurveStableswapPool::fetch_coin(record.address, 0, provider).await?;
+    if coin != stored {
+        return Err(ArbRsError::AddressMismatch(
+            record.address,
+            format!("coins(0) is {coin}, not the stored {stored}"),
+        ));
+    }
+    Ok(())
+}
+
+/// Registers `pool`'s underlying coins as a pool of their own, if it's a metapool.
+fn register_underlying<P: Provider + Send + Sync + 'static + ?Sized>(
+    underlying_pools: &PoolRegistry<P>,
+    pool: &Arc<CurveStableswapPool<P>>,
+) {
+    if let Some(underlying) = UnderlyingCurvePool::new(pool.clone()) {
+        underlying_pools.insert(pool.address, Arc::new(underlying));
+    }
+}
+
+/// What building a discovered pool needs from its manager, cloned into each concurrent build.
+struct CurveBuildContext<P: Provider + Send + Sync + 'static + ?Sized> {
     pool_registry: Arc<PoolRegistry<P>>,
+    underlying_pools: Arc<PoolRegistry<P>>,
     db_manager: Arc<DbManager>,
     token_manager: Arc<TokenManager<P>>,
     provider: Arc<P>,
-    curve_registry: &CurveRegistry<P>,
+    curve_registry: CurveRegistry<P>,
+    cache_config: CacheConfig,
+}
+
+impl<P: Provider + Send + Sync + 'static + ?Sized> Clone for CurveBuildContext<P> {
+    fn clone(&self) -> Self {
+        Self {
+            pool_registry: self.pool_registry.clone(),
+            underlying_pools: self.underlying_pools.clone(),
+            db_manager: self.db_manager.clone(),
+            token_manager: self.token_manager.clone(),
+            provider: self.provider.clone(),
+            curve_registry: self.curve_registry.clone(),
+            cache_config: self.cache_config,
+        }
+    }
+}
+
+async fn build_new_discovered_pool<P: Provider + Send + Sync + 'static + ?Sized>(
+    context: &CurveBuildContext<P>,
     pool_address: Address,
 ) -> Result<Arc<dyn LiquidityPool<P>>, ArbRsError> {
+    let CurveBuildContext {
+        pool_registry,
+        underlying_pools,
+        db_manager,
+        token_manager,
+        provider,
+        curve_registry,
+        cache_config,
+    } = context;
     if pool_registry.contains_key(&pool_address) {
         return Err(ArbRsError::DataFetchError(pool_address));
     }
@@ -211,19 +492,27 @@ async fn build_new_discovered_pool<P: Provider + Send + Sync + 'static + ?Sized>
     );
 
     let tokens =
-        CurveStableswapPool::fetch_coins(&pool_address, provider.clone(), &token_manager).await?;
+        CurveStableswapPool::fetch_coins(&pool_address, provider.clone(), token_manager).await?;
+    token_manager
+        .token_policy()
+        .check_pool(pool_address, tokens.iter().map(|token| token.address()))?;
 
     let attributes = attributes_builder::build_attributes(
         pool_address,
         &tokens,
         provider.clone(),
-        &token_manager,
+        token_manager,
         curve_registry,
     )
     .await?;
 
+    let kind = if attributes.swap_strategy.is_cryptoswap() {
+        PoolKind::CurveCrypto
+    } else {
+        PoolKind::CurveStable
+    };
     db_manager
-        .save_pool(pool_address, "curve", &tokens, None, None)
+        .save_pool(pool_address, &kind, &tokens, None, None)
         .await
         .ok();
 
@@ -237,17 +526,40 @@ async fn build_new_discovered_pool<P: Provider + Send + Sync + 'static + ?Sized>
         pool_address
     );
 
-    let pool = Arc::new(
-        CurveStableswapPool::new(
-            pool_address,
-            provider.clone(),
-            token_manager.clone(),
-            curve_registry,
-            attributes,
-        )
-        .await?,
-    );
+    let pool = CurveStableswapPool::new(
+        pool_address,
+        provider.clone(),
+        token_manager.clone(),
+        curve_registry,
+        attributes,
+    )
+    .await?
+    .with_cache_config(*cache_config);
+    save_metadata(db_manager, &pool).await;
 
+    let pool = Arc::new(pool);
+    register_underlying(underlying_pools, &pool);
+    let pool: Arc<dyn LiquidityPool<P>> = pool;
     pool_registry.insert(pool_address, pool.clone());
     Ok(pool)
 }
+
+/// Stores the LP token and the attributes `new` completed, which
+/// `CurveStableswapPool::from_record` rebuilds `pool` from.
+async fn save_metadata<P: Provider + Send + Sync + 'static + ?Sized>(
+    db_manager: &DbManager,
+    pool: &CurveStableswapPool<P>,
+) {
+    let json_attributes = serde_json::to_string(&pool.attributes).unwrap();
+    let saved = tokio::try_join!(
+        db_manager.update_pool_attributes(pool.address, &json_attributes),
+        db_manager.update_pool_lp_token(pool.address, pool.lp_token.address()),
+    );
+    if let Err(e) = saved {
+        tracing::warn!(
+            address = ?pool.address,
+            "Failed to store Curve pool metadata: {:?}",
+            e
+        );
+    }
+}
@@ -0,0 +1,240 @@
+#[cfg(feature = "db")]
+use crate::db::DbManager;
+use crate::errors::ArbRsError;
+#[cfg(feature = "db")]
+use alloy_primitives::Address;
+use alloy_provider::Provider;
+use alloy_rpc_types::{Filter, Log};
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+
+/// Prefix of the `bot_state` keys holding each discovery source's last scanned block.
+#[cfg(feature = "db")]
+const DISCOVERY_KEY_PREFIX: &str = "discovery:";
+
+/// Messages with which nodes refuse a `getLogs` range for returning too much.
+const TOO_MANY_RESULTS: [&str; 6] = [
+    "query returned more than",
+    "response size exceeded",
+    "log response size",
+    "block range is too large",
+    "range too large",
+    "too many results",
+];
+
+/// How [`ChunkedLogScan`] splits and retries a `getLogs` backfill.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LogScanConfig {
+    /// Blocks per `getLogs` call. Halved while the node reports too many results.
+    pub chunk_size: u64,
+    /// Smallest chunk halving goes down to before the error is returned.
+    pub min_chunk_size: u64,
+    /// Retries of a chunk failing for any other reason, with doubling backoff.
+    pub max_retries: u32,
+    pub retry_backoff: Duration,
+}
+
+impl Default for LogScanConfig {
+    fn default() -> Self {
+        Self {
+            chunk_size: 10_000,
+            min_chunk_size: 1,
+            max_retries: 3,
+            retry_backoff: Duration::from_millis(500),
+        }
+    }
+}
+
+impl LogScanConfig {
+    pub fn with_chunk_size(mut self, chunk_size: u64) -> Self {
+        self.chunk_size = chunk_size.max(1);
+        self
+    }
+}
+
+/// The logs of one fully scanned block range.
+#[derive(Debug, Clone)]
+pub struct ScannedChunk {
+    pub from_block: u64,
+    pub to_block: u64,
+    pub logs: Vec<Log>,
+}
+
+/// A `getLogs` backfill over `from_block..=end_block`, read one chunk at a time.
+#[derive(Debug, Clone)]
+pub struct ChunkedLogScan {
+    filter: Filter,
+    next_block: u64,
+    end_block: u64,
+    chunk_size: u64,
+    config: LogScanConfig,
+    cancel: CancellationToken,
+}
+
+/// Starts a scan of `filter`'s logs from `from_block` to `end_block`, both included.
+pub fn chunked_log_scan(
+    filter: Filter,
+    from_block: u64,
+    end_block: u64,
+    config: LogScanConfig,
+) -> ChunkedLogScan {
+    ChunkedLogScan {
+        filter,
+        next_block: from_block,
+        end_block,
+        chunk_size: config.chunk_size.max(1),
+        config,
+        cancel: CancellationToken::new(),
+    }
+}
+
+impl ChunkedLogScan {
+    /// Ends the scan once `cancel` is cancelled: chunks already handed out stay scanned, and
+    /// no further `getLogs` call or retry is made.
+    pub fn with_cancellation(mut self, cancel: CancellationToken) -> Self {
+        self.cancel = cancel;
+        self
+    }
+
+    /// The chunk size the next call starts with.
+    pub fn chunk_size(&self) -> u64 {
+        self.chunk_size
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.next_block > self.end_block
+    }
+
+    /// Whether the scan was cancelled before reaching `end_block`.
+    pub fn is_cancelled(&self) -> bool {
+        !self.is_done() && self.cancel.is_cancelled()
+    }
+
+    /// Reads the next chunk, or `None` once `end_block` is scanned or the scan is cancelled.
+    /// A chunk the node refuses for its size is split in half until it fits; the size then
+    /// grows back after each chunk read.
+    pub async fn next_chunk<P: Provider + Send + Sync + 'static + ?Sized>(
+        &mut self,
+        provider: &P,
+    ) -> Result<Option<ScannedChunk>, ArbRsError> {
+        if self.is_done() || self.cancel.is_cancelled() {
+            return Ok(None);
+        }
+        let from_block = self.next_block;
+        let mut retries = 0;
+        loop {
+            let to_block = from_block
+                .saturating_add(self.chunk_size - 1)
+                .min(self.end_block);
+            let filter = self
+                .filter
+                .clone()
+                .from_block(from_block)
+                .to_block(to_block);
+            match provider.get_logs(&filter).await {
+                Ok(logs) => {
+                    self.next_block = to_block + 1;
+                    let scanned = to_block + 1 - from_block;
+                    tracing::info!(
+                        from_block,
+                        to_block,
+                        logs = logs.len(),
+                        remaining_blocks = self.end_block.saturating_sub(to_block),
+                        "Scanned logs"
+                    );
+                    if scanned == self.chunk_size {
+                        self.chunk_size = self
+                            .chunk_size
+                            .saturating_mul(2)
+                            .min(self.config.chunk_size.max(1));
+                    }
+                    return Ok(Some(ScannedChunk {
+                        from_block,
+                        to_block,
+                        logs,
+                    }));
+                }
+                Err(e) => {
+                    let message = e.to_string();
+                    if is_too_many_results(&message) && self.chunk_size > self.config.min_chunk_size
+                    {
+                        self.chunk_size = (self.chunk_size / 2).max(self.config.min_chunk_size);
+                        tracing::debug!(
+                            from_block,
+                            chunk_size = self.chunk_size,
+                            "Too many logs, halving the chunk"
+                        );
+                        continue;
+                    }
+                    if retries >= self.config.max_retries {
+                        return Err(ArbRsError::ProviderError(message));
+                    }
+                    let backoff = self.config.retry_backoff * 2u32.saturating_pow(retries);
+                    retries += 1;
+                    tracing::warn!(from_block, retries, "getLogs failed, retrying: {}", message);
+                    tokio::select! {
+                        _ = tokio::time::sleep(backoff) => {}
+                        _ = self.cancel.cancelled() => return Ok(None),
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn is_too_many_results(message: &str) -> bool {
+    let message = message.to_lowercase();
+    TOO_MANY_RESULTS
+        .iter()
+        .any(|pattern| message.contains(pattern))
+}
+
+#[cfg(feature = "db")]
+fn discovery_key(source: &str, address: Address) -> String {
+    format!("{DISCOVERY_KEY_PREFIX}{source}:{address:#x}")
+}
+
+/// The last block fully scanned for `source`'s pools at `address`, if any was stored.
+#[cfg(feature = "db")]
+pub async fn load_discovery_block(
+    db_manager: &DbManager,
+    source: &str,
+    address: Address,
+) -> Result<Option<u64>, ArbRsError> {
+    Ok(db_manager
+        .get_state(&discovery_key(source, address))
+        .await
+        .map_err(|e| ArbRsError::DatabaseError(e.to_string()))?
+        .and_then(|value| value.parse().ok()))
+}
+
+/// Stores the last block fully scanned for `source`'s pools at `address`.
+#[cfg(feature = "db")]
+pub async fn save_discovery_block(
+    db_manager: &DbManager,
+    source: &str,
+    address: Address,
+    block: u64,
+) -> Result<(), ArbRsError> {
+    db_manager
+        .set_state(&discovery_key(source, address), &block.to_string())
+        .await
+        .map_err(|e| ArbRsError::DatabaseError(e.to_string()))
+}
+
+/// Stores `block` as the last block fully scanned for `source`'s pools at `address`, unless
+/// a later one is already stored. For flushing progress on shutdown, where the manager may
+/// never have resumed from the stored block.
+#[cfg(feature = "db")]
+pub async fn flush_discovery_block(
+    db_manager: &DbManager,
+    source: &str,
+    address: Address,
+    block: u64,
+) -> Result<(), ArbRsError> {
+    let stored = load_discovery_block(db_manager, source, address).await?;
+    if stored.is_some_and(|stored| stored >= block) {
+        return Ok(());
+    }
+    save_discovery_block(db_manager, source, address, block).await
+}
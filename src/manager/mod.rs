@@ -1,6 +1,14 @@
+#[cfg(feature = "db")]
 pub mod balancer_pool_manager;
+#[cfg(feature = "db")]
+pub mod curve_bootstrap;
+#[cfg(feature = "db")]
 pub mod curve_pool_manager;
+pub mod log_scan;
 pub mod pool_discovery;
+#[cfg(feature = "db")]
+pub mod pool_factory;
 pub mod token_manager;
 pub mod uniswap_v2_pool_manager;
 pub mod uniswap_v3_pool_manager;
+pub mod uniswap_v4_pool_manager;
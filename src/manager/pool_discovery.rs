@@ -1,8 +1,9 @@
 use crate::errors::ArbRsError;
-use alloy_primitives::Address;
+use crate::pool::uniswap_v4::PoolKey;
+use alloy_primitives::{Address, B256};
 use alloy_provider::Provider;
-use alloy_rpc_types::{Filter, Log};
-use alloy_sol_types::{SolEvent, sol};
+use alloy_rpc_types::{Filter, Log, TransactionRequest};
+use alloy_sol_types::{SolCall, SolEvent, sol};
 use std::sync::Arc;
 
 // ABI definition for the Uniswap V2 Factory's `PairCreated` event
@@ -26,6 +27,41 @@ sol! {
     );
 }
 
+// ABI definition for the Uniswap V4 PoolManager's `Initialize` event
+sol! {
+    event Initialize(
+        bytes32 indexed id,
+        address indexed currency0,
+        address indexed currency1,
+        uint24 fee,
+        int24 tickSpacing,
+        address hooks,
+        uint160 sqrtPriceX96,
+        int24 tick
+    );
+}
+
+// Token getters shared by V2 pairs and V3 pools
+sol! {
+    function token0() external view returns (address);
+    function token1() external view returns (address);
+}
+
+// ABI definitions for Solidly-style factories and pairs
+mod solidly {
+    alloy_sol_types::sol! {
+        event PairCreated(
+            address indexed token0,
+            address indexed token1,
+            bool stable,
+            address pair,
+            uint256
+        );
+
+        function stable() external view returns (bool);
+    }
+}
+
 /// Represents the data from a discovered V2 pool
 #[derive(Debug, Clone, Copy)]
 pub struct DiscoveredV2Pool {
@@ -34,6 +70,15 @@ pub struct DiscoveredV2Pool {
     pub pool_address: Address,
 }
 
+/// A pair discovered from a Solidly-style factory, which is either stable or volatile
+#[derive(Debug, Clone, Copy)]
+pub struct DiscoveredSolidlyPair {
+    pub token0: Address,
+    pub token1: Address,
+    pub stable: bool,
+    pub pool_address: Address,
+}
+
 /// Represents the data from a discovered V3 pool
 #[derive(Debug, Clone, Copy)]
 pub struct DiscoveredV3Pool {
@@ -44,23 +89,22 @@ pub struct DiscoveredV3Pool {
     pub pool_address: Address,
 }
 
-pub async fn discover_new_v2_pools<P: Provider + Send + Sync + 'static + ?Sized>(
-    provider: Arc<P>,
-    factory_address: Address,
-    from_block: u64,
-    to_block: u64,
-) -> Result<Vec<DiscoveredV2Pool>, ArbRsError> {
-    let event_filter = Filter::new()
+/// Represents the data from a pool initialized in a V4 pool manager
+#[derive(Debug, Clone, Copy)]
+pub struct DiscoveredV4Pool {
+    pub pool_id: B256,
+    pub key: PoolKey,
+}
+
+/// Filter for the `PairCreated` events of a V2 factory, to be given a block range.
+pub fn v2_pair_created_filter(factory_address: Address) -> Filter {
+    Filter::new()
         .address(factory_address)
         .event_signature(PairCreated::SIGNATURE_HASH)
-        .from_block(from_block)
-        .to_block(to_block);
-
-    let logs: Vec<Log> = provider
-        .get_logs(&event_filter)
-        .await
-        .map_err(|e| ArbRsError::ProviderError(e.to_string()))?;
+}
 
+/// Decodes `PairCreated` logs, skipping any that don't decode.
+pub fn decode_v2_pools(logs: &[Log]) -> Vec<DiscoveredV2Pool> {
     let mut discovered_pools = Vec::new();
 
     for (i, log) in logs.iter().enumerate() {
@@ -82,18 +126,40 @@ pub async fn discover_new_v2_pools<P: Provider + Send + Sync + 'static + ?Sized>
         }
     }
 
-    Ok(discovered_pools)
+    discovered_pools
 }
 
-pub async fn discover_new_v3_pools<P: Provider + Send + Sync + 'static + ?Sized>(
+/// Filter for the `PairCreated(token0, token1, stable, pair, uint)` events of a
+/// Solidly-style factory, to be given a block range.
+pub fn solidly_pair_created_filter(factory_address: Address) -> Filter {
+    Filter::new()
+        .address(factory_address)
+        .event_signature(solidly::PairCreated::SIGNATURE_HASH)
+}
+
+/// Decodes Solidly-style `PairCreated` logs.
+pub fn decode_solidly_pairs(logs: &[Log]) -> Result<Vec<DiscoveredSolidlyPair>, ArbRsError> {
+    let mut discovered_pairs = Vec::new();
+    for log in logs {
+        let decoded_log = solidly::PairCreated::decode_log(&log.inner)
+            .map_err(|e| ArbRsError::AbiDecodeError(e.to_string()))?;
+        discovered_pairs.push(DiscoveredSolidlyPair {
+            token0: decoded_log.token0,
+            token1: decoded_log.token1,
+            stable: decoded_log.stable,
+            pool_address: decoded_log.pair,
+        });
+    }
+    Ok(discovered_pairs)
+}
+
+pub async fn discover_new_v2_pools<P: Provider + Send + Sync + 'static + ?Sized>(
     provider: Arc<P>,
     factory_address: Address,
     from_block: u64,
     to_block: u64,
-) -> Result<Vec<DiscoveredV3Pool>, ArbRsError> {
-    let event_filter = Filter::new()
-        .address(factory_address)
-        .event_signature(PoolCreated::SIGNATURE_HASH)
+) -> Result<Vec<DiscoveredV2Pool>, ArbRsError> {
+    let event_filter = v2_pair_created_filter(factory_address)
         .from_block(from_block)
         .to_block(to_block);
 
@@ -102,6 +168,18 @@ pub async fn discover_new_v3_pools<P: Provider + Send + Sync + 'static + ?Sized>
         .await
         .map_err(|e| ArbRsError::ProviderError(e.to_string()))?;
 
+    Ok(decode_v2_pools(&logs))
+}
+
+/// Filter for the `PoolCreated` events of a V3 factory, to be given a block range.
+pub fn v3_pool_created_filter(factory_address: Address) -> Filter {
+    Filter::new()
+        .address(factory_address)
+        .event_signature(PoolCreated::SIGNATURE_HASH)
+}
+
+/// Decodes `PoolCreated` logs.
+pub fn decode_v3_pools(logs: &[Log]) -> Result<Vec<DiscoveredV3Pool>, ArbRsError> {
     let mut discovered_pools = Vec::new();
     for log in logs {
         let decoded_log = PoolCreated::decode_log(&log.inner)
@@ -116,3 +194,80 @@ pub async fn discover_new_v3_pools<P: Provider + Send + Sync + 'static + ?Sized>
     }
     Ok(discovered_pools)
 }
+
+pub async fn discover_new_v3_pools<P: Provider + Send + Sync + 'static + ?Sized>(
+    provider: Arc<P>,
+    factory_address: Address,
+    from_block: u64,
+    to_block: u64,
+) -> Result<Vec<DiscoveredV3Pool>, ArbRsError> {
+    let event_filter = v3_pool_created_filter(factory_address)
+        .from_block(from_block)
+        .to_block(to_block);
+
+    let logs: Vec<Log> = provider
+        .get_logs(&event_filter)
+        .await
+        .map_err(|e| ArbRsError::ProviderError(e.to_string()))?;
+
+    decode_v3_pools(&logs)
+}
+
+/// Filter for the `Initialize` events of a V4 pool manager, to be given a block range.
+pub fn v4_initialize_filter(pool_manager: Address) -> Filter {
+    Filter::new()
+        .address(pool_manager)
+        .event_signature(Initialize::SIGNATURE_HASH)
+}
+
+/// Decodes `Initialize` logs.
+pub fn decode_v4_pools(logs: &[Log]) -> Result<Vec<DiscoveredV4Pool>, ArbRsError> {
+    let mut discovered_pools = Vec::new();
+    for log in logs {
+        let decoded_log = Initialize::decode_log(&log.inner)
+            .map_err(|e| ArbRsError::AbiDecodeError(e.to_string()))?;
+        discovered_pools.push(DiscoveredV4Pool {
+            pool_id: decoded_log.id,
+            key: PoolKey {
+                currency0: decoded_log.currency0,
+                currency1: decoded_log.currency1,
+                fee: decoded_log.fee.to(),
+                tick_spacing: decoded_log.tickSpacing.as_i32(),
+                hooks: decoded_log.hooks,
+            },
+        });
+    }
+    Ok(discovered_pools)
+}
+
+/// Reads `token0()` and `token1()` from a V2 pair or V3 pool.
+pub async fn fetch_pool_tokens<P: Provider + Send + Sync + 'static + ?Sized>(
+    provider: &P,
+    pool_address: Address,
+) -> Result<(Address, Address), ArbRsError> {
+    let request = |input: Vec<u8>| {
+        TransactionRequest::default()
+            .to(pool_address)
+            .input(input.into())
+    };
+    let (token0_res, token1_res) = tokio::join!(
+        provider.call(request(token0Call {}.abi_encode())),
+        provider.call(request(token1Call {}.abi_encode())),
+    );
+    let token0 = token0Call::abi_decode_returns(&token0_res?)?;
+    let token1 = token1Call::abi_decode_returns(&token1_res?)?;
+    Ok((token0, token1))
+}
+
+/// Reads `stable()` from a Solidly-style pair.
+pub async fn fetch_solidly_stable<P: Provider + Send + Sync + 'static + ?Sized>(
+    provider: &P,
+    pool_address: Address,
+) -> Result<bool, ArbRsError> {
+    let request = TransactionRequest::default()
+        .to(pool_address)
+        .input(solidly::stableCall {}.abi_encode().into());
+    Ok(solidly::stableCall::abi_decode_returns(
+        &provider.call(request).await?,
+    )?)
+}
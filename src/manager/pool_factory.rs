@@ -0,0 +1,191 @@
+use crate::db::PoolRecord;
+use crate::dex::{FEE_PIPS_DENOMINATOR, PoolKind};
+use crate::errors::ArbRsError;
+use crate::manager::{
+    balancer_pool_manager::BalancerPoolManager, curve_pool_manager::CurvePoolManager,
+    uniswap_v2_pool_manager::UniswapV2PoolManager, uniswap_v3_pool_manager::UniswapV3PoolManager,
+    uniswap_v4_pool_manager::UniswapV4PoolManager,
+};
+use crate::pool::LiquidityPool;
+use alloy_primitives::Address;
+use alloy_provider::Provider;
+use futures::future::BoxFuture;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Fee of V2 records stored before fees were kept, which are all Uniswap's 30 bps pairs.
+const LEGACY_V2_FEE_PIPS: u32 = 3_000;
+
+/// Rebuilds a pool from its stored record.
+pub type PoolBuilder<'a, P> = Box<
+    dyn Fn(&'a PoolRecord) -> BoxFuture<'a, Result<Arc<dyn LiquidityPool<P>>, ArbRsError>>
+        + Send
+        + Sync
+        + 'a,
+>;
+
+/// The builder for each kind of stored pool, so hydrating a record is a lookup on its kind.
+pub struct PoolFactoryRegistry<'a, P: Provider + Send + Sync + 'static + ?Sized> {
+    builders: HashMap<PoolKind, PoolBuilder<'a, P>>,
+}
+
+impl<'a, P: Provider + Send + Sync + 'static + ?Sized> Default for PoolFactoryRegistry<'a, P> {
+    fn default() -> Self {
+        Self {
+            builders: HashMap::new(),
+        }
+    }
+}
+
+impl<'a, P: Provider + Send + Sync + 'static + ?Sized> PoolFactoryRegistry<'a, P> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Builders for every kind the managers store: V2 and Solidly pairs, each V3
+    /// deployment the V3 manager knows, Curve and Balancer pools. Each manager checks a
+    /// record against the chain before building its pool, unless told not to with
+    /// `with_verify_on_hydrate(false)`.
+    pub fn for_managers(
+        v2_manager: &'a UniswapV2PoolManager<P>,
+        v3_manager: &'a UniswapV3PoolManager<P>,
+        curve_manager: &'a CurvePoolManager<P>,
+        balancer_manager: &'a BalancerPoolManager<P>,
+    ) -> Self {
+        let mut registry = Self::new().with_builder(PoolKind::UniswapV2, move |record| {
+            Box::pin(async move {
+                let (token_a, token_b) = token_pair(record)?;
+                v2_manager
+                    .verify_record(record.address, token_a, token_b, &record.dex)
+                    .await?;
+                v2_manager
+                    .build_v2_pool_with_fee(
+                        record.address,
+                        token_a,
+                        token_b,
+                        record.fee.unwrap_or(LEGACY_V2_FEE_PIPS),
+                        FEE_PIPS_DENOMINATOR,
+                    )
+                    .await
+            })
+        });
+
+        for (pool_kind, stable) in [
+            (PoolKind::SolidlyStable, true),
+            (PoolKind::SolidlyVolatile, false),
+        ] {
+            registry = registry.with_builder(pool_kind, move |record| {
+                Box::pin(async move {
+                    let (token_a, token_b) = token_pair(record)?;
+                    let fee = record.fee.ok_or_else(|| {
+                        ArbRsError::InvalidPool(record.address, "missing fee".to_string())
+                    })?;
+                    v2_manager
+                        .verify_record(record.address, token_a, token_b, &record.dex)
+                        .await?;
+                    v2_manager
+                        .build_solidly_pool(
+                            record.address,
+                            token_a,
+                            token_b,
+                            stable,
+                            fee,
+                            FEE_PIPS_DENOMINATOR,
+                        )
+                        .await
+                })
+            });
+        }
+
+        // Each deployment's pools are checked against its own init code hash.
+        for pool_kind in v3_manager.pool_kinds() {
+            let Some(factory) = v3_manager.factory_for_kind(&pool_kind) else {
+                continue;
+            };
+            registry = registry.with_builder(pool_kind, move |record| {
+                Box::pin(async move {
+                    let (token_a, token_b) = token_pair(record)?;
+                    let (Some(fee), Some(tick_spacing)) = (record.fee, record.tick_spacing) else {
+                        return Err(ArbRsError::InvalidPool(
+                            record.address,
+                            "missing fee or tick spacing".to_string(),
+                        ));
+                    };
+                    v3_manager
+                        .build_factory_pool(
+                            factory,
+                            record.address,
+                            token_a,
+                            token_b,
+                            fee,
+                            tick_spacing,
+                        )
+                        .await
+                })
+            });
+        }
+
+        // The stored attributes, not the kind, decide a Curve pool's math.
+        for pool_kind in [PoolKind::CurveStable, PoolKind::CurveCrypto] {
+            registry = registry.with_builder(pool_kind, move |record| {
+                Box::pin(curve_manager.build_pool_from_record(record))
+            });
+        }
+
+        // Balancer pools detect their kind from the functions they implement when first
+        // built, and are rebuilt from the stored kind after.
+        for pool_kind in [PoolKind::BalancerWeighted, PoolKind::BalancerStable] {
+            registry = registry.with_builder(pool_kind, move |record| {
+                Box::pin(balancer_manager.build_pool_from_record(record))
+            });
+        }
+        registry
+    }
+
+    /// Adds the builder of V4 pool records, hydrated from their stored key by `v4_manager`.
+    pub fn with_uniswap_v4(self, v4_manager: &'a UniswapV4PoolManager<P>) -> Self {
+        self.with_builder(PoolKind::UniswapV4, move |record| {
+            Box::pin(v4_manager.build_pool_from_record(record))
+        })
+    }
+
+    /// Registers or replaces the builder of `pool_kind`.
+    pub fn with_builder(
+        mut self,
+        pool_kind: PoolKind,
+        builder: impl Fn(&'a PoolRecord) -> BoxFuture<'a, Result<Arc<dyn LiquidityPool<P>>, ArbRsError>>
+        + Send
+        + Sync
+        + 'a,
+    ) -> Self {
+        self.builders.insert(pool_kind, Box::new(builder));
+        self
+    }
+
+    pub fn supports(&self, pool_kind: &PoolKind) -> bool {
+        self.builders.contains_key(pool_kind)
+    }
+
+    /// Builds `record`'s pool with the builder of its kind. A kind without one is an
+    /// `UnknownPoolKind` error carrying the stored string.
+    pub async fn build(
+        &self,
+        record: &'a PoolRecord,
+    ) -> Result<Arc<dyn LiquidityPool<P>>, ArbRsError> {
+        let builder = self
+            .builders
+            .get(&record.dex)
+            .ok_or_else(|| ArbRsError::UnknownPoolKind(record.dex.to_string()))?;
+        builder(record).await
+    }
+}
+
+fn token_pair(record: &PoolRecord) -> Result<(Address, Address), ArbRsError> {
+    match record.tokens[..] {
+        [token_a, token_b] => Ok((token_a, token_b)),
+        _ => Err(ArbRsError::InvalidPool(
+            record.address,
+            format!("expected 2 tokens, found {}", record.tokens.len()),
+        )),
+    }
+}
@@ -1,11 +1,18 @@
-use crate::core::token::{Erc20Data, NativeTokenData, Token};
-use crate::core::token_fetcher::TokenFetcher;
+use crate::chain::ChainConfig;
+use crate::core::token::{Erc20Data, NativeTokenData, Token, TokenLike};
+use crate::core::token_behavior::{TokenBehavior, simulate_transfer};
+use crate::core::token_fetcher::{DEFAULT_TOKEN_DECIMALS, TokenFetcher};
+use crate::core::token_policy::TokenPolicy;
+#[cfg(feature = "db")]
 use crate::db::DbManager;
 use crate::errors::ArbRsError;
-use alloy_primitives::{Address, address};
+use alloy_primitives::{Address, U256, address};
 use alloy_provider::Provider;
 use dashmap::DashMap;
+use futures::future::join_all;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
 
 // Placeholder addresses for native currency
 const NATIVE_PLACEHOLDERS: &[Address] = &[
@@ -13,26 +20,219 @@ const NATIVE_PLACEHOLDERS: &[Address] = &[
     address!("eeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeee"),
 ];
 
+/// Share of the holder's balance sent in a simulated transfer, in basis points.
+const PROBE_TRANSFER_BPS: u64 = 100;
+
+/// Blocks a token that failed to resolve is refused for before it's fetched again, about a
+/// week on mainnet.
+pub const DEFAULT_TOKEN_RETRY_BLOCKS: u64 = 50_400;
+
+/// Why a token failed to resolve, and the block until which it isn't fetched again.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TokenFailure {
+    /// [`ArbRsError::class`] of the failure.
+    pub error_class: String,
+    pub error: String,
+    pub retry_after_block: u64,
+}
+
 pub struct TokenManager<P: ?Sized> {
     chain_id: u64,
+    /// Addresses of the chain's contracts and reference tokens, for the engine and path
+    /// finding to read.
+    chain: Arc<ChainConfig>,
     provider: Arc<P>,
     token_registry: Arc<DashMap<Address, Arc<Token<P>>>>,
-    db_manager: Arc<DbManager>,
+    /// Transfer behavior of the tokens analysed so far.
+    behaviors: Arc<DashMap<Address, TokenBehavior>>,
+    detect_transfer_tax: bool,
+    default_decimals: Option<u8>,
+    /// Tokens the pool managers and path finding keep out of the pool graph.
+    token_policy: Arc<TokenPolicy>,
+    /// Tokens whose metadata failed to resolve, as loaded or recorded so far.
+    failures: Arc<DashMap<Address, TokenFailure>>,
+    failure_retry_blocks: u64,
+    /// Latest block seen, to tell which failures are due a retry. 0 until one is known.
+    latest_block: AtomicU64,
+    #[cfg(feature = "db")]
+    db_manager: Option<Arc<DbManager>>,
 }
 
 impl<P: Provider + Send + Sync + 'static + ?Sized> TokenManager<P> {
+    /// Caches token metadata in memory and persists it to `db_manager`.
+    #[cfg(feature = "db")]
     pub fn new(provider: Arc<P>, chain_id: u64, db_manager: Arc<DbManager>) -> Self {
+        Self {
+            db_manager: Some(db_manager),
+            ..Self::in_memory(provider, chain_id)
+        }
+    }
+
+    /// Caches token metadata in memory only; nothing is read from or written to a database.
+    pub fn in_memory(provider: Arc<P>, chain_id: u64) -> Self {
         Self {
             chain_id,
+            chain: Arc::new(ChainConfig::for_chain_id(chain_id).unwrap_or_default()),
             provider,
             token_registry: Arc::new(DashMap::new()),
-            db_manager,
+            behaviors: Arc::new(DashMap::new()),
+            detect_transfer_tax: false,
+            default_decimals: Some(DEFAULT_TOKEN_DECIMALS),
+            token_policy: Arc::new(TokenPolicy::new()),
+            failures: Arc::new(DashMap::new()),
+            failure_retry_blocks: DEFAULT_TOKEN_RETRY_BLOCKS,
+            latest_block: AtomicU64::new(0),
+            #[cfg(feature = "db")]
+            db_manager: None,
         }
     }
 
+    /// Measures the transfer tax of each new token the pool managers see, through
+    /// [`analyze_pool_tokens`](Self::analyze_pool_tokens).
+    pub fn with_transfer_tax_detection(mut self) -> Self {
+        self.detect_transfer_tax = true;
+        self
+    }
+
+    /// Decimals given to tokens whose `decimals()` reverts. With `None` they fail to resolve.
+    pub fn with_default_decimals(mut self, default_decimals: Option<u8>) -> Self {
+        self.default_decimals = default_decimals;
+        self
+    }
+
+    /// Keeps the tokens `token_policy` refuses out of the pool graph. It may be changed at
+    /// runtime through the shared handle.
+    pub fn with_token_policy(mut self, token_policy: Arc<TokenPolicy>) -> Self {
+        self.token_policy = token_policy;
+        self
+    }
+
+    pub fn token_policy(&self) -> &Arc<TokenPolicy> {
+        &self.token_policy
+    }
+
+    /// Blocks a token that failed to resolve is refused for with `TokenUnusable` before
+    /// it's fetched again.
+    pub fn with_failure_retry_blocks(mut self, failure_retry_blocks: u64) -> Self {
+        self.failure_retry_blocks = failure_retry_blocks;
+        self
+    }
+
+    /// Runs on `chain`, whose id replaces the one the manager was created with. Without it,
+    /// the preset of that id is used, or mainnet's on a chain without one.
+    pub fn with_chain(mut self, chain: Arc<ChainConfig>) -> Self {
+        self.chain_id = chain.chain_id;
+        self.chain = chain;
+        self
+    }
+
+    pub fn chain(&self) -> &Arc<ChainConfig> {
+        &self.chain
+    }
+
+    fn fetcher(&self) -> TokenFetcher<P> {
+        TokenFetcher::new(Arc::clone(&self.provider))
+            .with_default_decimals(self.default_decimals)
+            .with_multicall(self.chain.multicall3)
+    }
+
+    /// The token at `address`, fetched if it isn't cached or stored. A token that failed to
+    /// resolve is refused with `TokenUnusable` until its retry block, without a call.
     pub async fn get_token(&self, address: Address) -> Result<Arc<Token<P>>, ArbRsError> {
+        if let Some(token) = self.lookup(address).await {
+            return Ok(token);
+        }
+        self.check_failure(address).await?;
+        tracing::debug!(?address, "[CACHE MISS] Fetching token from on-chain...");
+        match self.fetcher().fetch_erc20_data(address).await {
+            Ok(erc20_data) => Ok(self.register(erc20_data).await),
+            Err(e) => {
+                self.record_failure(address, &e).await;
+                Err(e)
+            }
+        }
+    }
+
+    /// Resolves each distinct address once. Tokens not cached or stored are fetched together
+    /// in one multicall, in the order first given. One failure doesn't affect the rest.
+    pub async fn get_tokens(
+        &self,
+        addresses: impl IntoIterator<Item = Address>,
+    ) -> HashMap<Address, Result<Arc<Token<P>>, ArbRsError>> {
+        let mut seen = HashSet::new();
+        let addresses: Vec<Address> = addresses
+            .into_iter()
+            .filter(|address| seen.insert(*address))
+            .collect();
+        let lookups = join_all(
+            addresses
+                .into_iter()
+                .map(|address| async move { (address, self.lookup(address).await) }),
+        )
+        .await;
+
+        let mut tokens = HashMap::with_capacity(lookups.len());
+        let mut missing = Vec::new();
+        for (address, token) in lookups {
+            match token {
+                Some(token) => {
+                    tokens.insert(address, Ok(token));
+                }
+                None => match self.check_failure(address).await {
+                    Ok(()) => missing.push(address),
+                    Err(e) => {
+                        tokens.insert(address, Err(e));
+                    }
+                },
+            }
+        }
+        if missing.is_empty() {
+            return tokens;
+        }
+        tracing::debug!(
+            tokens = missing.len(),
+            "[CACHE MISS] Fetching token batch from on-chain..."
+        );
+        let fetched = self.fetcher().fetch_erc20_data_batch(&missing).await;
+        for (address, result) in missing.into_iter().zip(fetched) {
+            let token = match result {
+                Ok(erc20_data) => Ok(self.register(erc20_data).await),
+                Err(e) => {
+                    self.record_failure(address, &e).await;
+                    Err(e)
+                }
+            };
+            tokens.insert(address, token);
+        }
+        tokens
+    }
+
+    /// The tokens at `addresses`, in order, resolved through [`get_tokens`](Self::get_tokens).
+    /// Fails with the first that can't be resolved.
+    pub async fn get_token_list(
+        &self,
+        addresses: &[Address],
+    ) -> Result<Vec<Arc<Token<P>>>, ArbRsError> {
+        let mut tokens = self.get_tokens(addresses.iter().copied()).await;
+        let mut list = Vec::with_capacity(addresses.len());
+        for address in addresses {
+            if let Some(Ok(token)) = tokens.get(address) {
+                list.push(token.clone());
+                continue;
+            }
+            return Err(match tokens.remove(address) {
+                Some(Err(e)) => e,
+                _ => ArbRsError::UnresolvedToken(*address, "Not resolved".to_string()),
+            });
+        }
+        Ok(list)
+    }
+
+    /// The token from the registry, as a native placeholder or from the database; `None` if
+    /// it has to be fetched.
+    async fn lookup(&self, address: Address) -> Option<Arc<Token<P>>> {
         if let Some(token_entry) = self.token_registry.get(&address) {
-            return Ok(token_entry.clone());
+            return Some(token_entry.clone());
         }
 
         if NATIVE_PLACEHOLDERS.contains(&address) {
@@ -42,10 +242,13 @@ impl<P: Provider + Send + Sync + 'static + ?Sized> TokenManager<P> {
                 self.provider.clone(),
             ))));
             self.token_registry.insert(address, native_token.clone());
-            return Ok(native_token);
+            return Some(native_token);
         }
 
-        if let Ok(Some(record)) = self.db_manager.get_token_by_address(address).await {
+        #[cfg(feature = "db")]
+        if let Some(db_manager) = &self.db_manager
+            && let Ok(Some(record)) = db_manager.get_token_by_address(address).await
+        {
             tracing::debug!(
                 ?address,
                 symbol = record.symbol,
@@ -58,26 +261,205 @@ impl<P: Provider + Send + Sync + 'static + ?Sized> TokenManager<P> {
                 record.decimals,
                 self.provider.clone(),
             );
+            if let Some(transfer_tax_bps) = record.transfer_tax_bps {
+                erc20_data.set_transfer_tax_bps(transfer_tax_bps);
+                self.behaviors
+                    .insert(address, TokenBehavior { transfer_tax_bps });
+            }
             let token = Arc::new(Token::Erc20(Arc::new(erc20_data)));
             self.token_registry.insert(address, token.clone());
-            return Ok(token);
+            return Some(token);
         }
+        None
+    }
 
-        tracing::debug!(?address, "[CACHE MISS] Fetching token from on-chain...");
-        let fetcher = TokenFetcher::new(Arc::clone(&self.provider));
-        let erc20_data = fetcher.fetch_erc20_data(address).await?;
+    /// Tells the manager the chain reached `block_number`, so tokens that failed to resolve
+    /// are fetched again once their retry block has passed.
+    pub fn observe_block(&self, block_number: u64) {
+        self.latest_block.fetch_max(block_number, Ordering::Relaxed);
+    }
+
+    /// The failure recorded for `address`, from memory or the database.
+    pub async fn token_failure(&self, address: Address) -> Option<TokenFailure> {
+        if let Some(failure) = self.failures.get(&address) {
+            return Some(failure.clone());
+        }
+        #[cfg(feature = "db")]
+        if let Some(db_manager) = &self.db_manager {
+            match db_manager.load_token_failure(address).await {
+                Ok(Some(failure)) => {
+                    self.failures.insert(address, failure.clone());
+                    return Some(failure);
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    tracing::warn!(?address, "Failed to load token failure from DB: {:?}", e)
+                }
+            }
+        }
+        None
+    }
+
+    /// Forgets the failure recorded for `address`, so the next request fetches it again.
+    pub async fn invalidate(&self, address: Address) {
+        self.failures.remove(&address);
+        #[cfg(feature = "db")]
+        if let Some(db_manager) = &self.db_manager
+            && let Err(e) = db_manager.clear_token_failure(address).await
+        {
+            tracing::warn!(?address, "Failed to clear token failure in DB: {:?}", e);
+        }
+    }
+
+    /// `TokenUnusable` if `address` failed to resolve and isn't due a retry. Until a block
+    /// is known, no failure is.
+    async fn check_failure(&self, address: Address) -> Result<(), ArbRsError> {
+        match self.token_failure(address).await {
+            Some(failure)
+                if failure.retry_after_block > self.latest_block.load(Ordering::Relaxed) =>
+            {
+                tracing::debug!(
+                    ?address,
+                    retry_after_block = failure.retry_after_block,
+                    "Token failed to resolve before, not fetching it."
+                );
+                Err(ArbRsError::TokenUnusable(address))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Records a failure of the token's contract, as opposed to one of the node, refusing
+    /// the token for `failure_retry_blocks` from the current block.
+    async fn record_failure(&self, address: Address, error: &ArbRsError) {
+        if !matches!(
+            error.root(),
+            ArbRsError::TokenStandardError(..) | ArbRsError::UnresolvedToken(..)
+        ) {
+            return;
+        }
+        let block_number = match self.latest_block.load(Ordering::Relaxed) {
+            0 => match self.provider.get_block_number().await {
+                Ok(block_number) => {
+                    self.observe_block(block_number);
+                    block_number
+                }
+                Err(e) => {
+                    tracing::debug!(?address, "Not recording token failure: {:?}", e);
+                    return;
+                }
+            },
+            block_number => block_number,
+        };
+        let failure = TokenFailure {
+            error_class: error.class().to_string(),
+            error: error.to_string(),
+            retry_after_block: block_number + self.failure_retry_blocks,
+        };
+        tracing::info!(
+            ?address,
+            retry_after_block = failure.retry_after_block,
+            "Token failed to resolve: {}",
+            failure.error
+        );
+        #[cfg(feature = "db")]
+        if let Some(db_manager) = &self.db_manager
+            && let Err(e) = db_manager.save_token_failure(address, &failure).await
+        {
+            tracing::warn!(?address, "Failed to save token failure to DB: {:?}", e);
+        }
+        self.failures.insert(address, failure);
+    }
 
-        if let Err(e) = self
-            .db_manager
-            .save_token(&Token::Erc20(Arc::new(erc20_data.clone())))
-            .await
+    /// Caches a freshly fetched token and saves it to the database, clearing any failure
+    /// it was retried after.
+    async fn register(&self, erc20_data: Erc20Data<P>) -> Arc<Token<P>> {
+        if self.failures.contains_key(&erc20_data.address) {
+            self.invalidate(erc20_data.address).await;
+        }
+        #[cfg(feature = "db")]
+        if let Some(db_manager) = &self.db_manager
+            && let Err(e) = db_manager
+                .save_token(&Token::Erc20(Arc::new(erc20_data.clone())))
+                .await
         {
-            tracing::warn!(?address, "Failed to save token to DB: {:?}", e);
+            tracing::warn!(address = ?erc20_data.address, "Failed to save token to DB: {:?}", e);
         }
 
+        let address = erc20_data.address;
         let new_token = Arc::new(Token::Erc20(Arc::new(erc20_data)));
-        self.token_registry.insert(address, new_token.clone());
-        Ok(new_token)
+        self.token_registry
+            .entry(address)
+            .or_insert(new_token)
+            .clone()
+    }
+
+    /// Registers an already built token, e.g. one a static pool was created with, so it's
+    /// never fetched.
+    pub fn insert_token(&self, token: Arc<Token<P>>) {
+        self.token_registry.entry(token.address()).or_insert(token);
+    }
+
+    /// The transfer behavior measured for `address`, if it has been analysed.
+    pub fn token_behavior(&self, address: Address) -> Option<TokenBehavior> {
+        self.behaviors.get(&address).map(|behavior| *behavior)
+    }
+
+    /// Measures `token`'s transfer tax by simulating a transfer of 1% of `holder`'s balance,
+    /// and records it on the token and in the database.
+    pub async fn analyze_token_behavior(
+        &self,
+        token: &Token<P>,
+        holder: Address,
+    ) -> Result<TokenBehavior, ArbRsError> {
+        let Token::Erc20(erc20) = token else {
+            return Ok(TokenBehavior::default());
+        };
+        let balance = token.get_balance(holder, None).await?;
+        let amount = balance * U256::from(PROBE_TRANSFER_BPS) / U256::from(10_000);
+        let behavior =
+            simulate_transfer(self.provider.as_ref(), erc20.address, holder, amount, None).await?;
+
+        erc20.set_transfer_tax_bps(behavior.transfer_tax_bps);
+        self.behaviors.insert(erc20.address, behavior);
+        #[cfg(feature = "db")]
+        if let Some(db_manager) = &self.db_manager
+            && let Err(e) = db_manager
+                .save_token_transfer_tax(erc20.address, behavior.transfer_tax_bps)
+                .await
+        {
+            tracing::warn!(address = ?erc20.address, "Failed to save token transfer tax: {:?}", e);
+        }
+        if behavior.is_taxed() {
+            tracing::info!(
+                address = ?erc20.address,
+                transfer_tax_bps = behavior.transfer_tax_bps,
+                "Detected a fee-on-transfer token."
+            );
+        }
+        Ok(behavior)
+    }
+
+    /// Analyses the tokens of a newly built pool not analysed yet, the pool sending the
+    /// simulated transfers. Does nothing unless transfer tax detection is enabled. A token
+    /// that can't be analysed is treated as untaxed and retried with its next pool.
+    pub async fn analyze_pool_tokens(&self, pool: Address, tokens: &[Arc<Token<P>>]) {
+        if !self.detect_transfer_tax {
+            return;
+        }
+        for token in tokens {
+            if self.behaviors.contains_key(&token.address()) {
+                continue;
+            }
+            if let Err(e) = self.analyze_token_behavior(token, pool).await {
+                tracing::debug!(
+                    token = ?token.address(),
+                    ?pool,
+                    "Failed to analyse token transfer behavior: {:?}",
+                    e
+                );
+            }
+        }
     }
 }
 
@@ -92,6 +474,7 @@ impl<P: ?Sized> Clone for Erc20Data<P> {
             balances: self.balances.clone(),
             total_supply_cache: self.total_supply_cache.clone(),
             allowance_cache: self.allowance_cache.clone(),
+            transfer_tax_bps: self.transfer_tax_bps.clone(),
         }
     }
 }
@@ -1,25 +1,69 @@
-use crate::dex::{DexDetails, DexVariant, build_mainnet_dex_registry};
+use crate::chain::ChainConfig;
+use crate::core::token::Token;
+#[cfg(feature = "db")]
+use crate::db::DbManager;
+#[cfg(feature = "db")]
+use crate::dex::fee_pips;
+use crate::dex::{
+    DexDetails, DexVariant, FEE_PIPS_DENOMINATOR, PoolKind, UNISWAP_V2_FACTORY,
+    build_mainnet_dex_registry,
+};
 use crate::errors::ArbRsError;
-use crate::manager::pool_discovery::discover_new_v2_pools;
+use crate::manager::log_scan::{LogScanConfig, chunked_log_scan};
+#[cfg(feature = "db")]
+use crate::manager::log_scan::{flush_discovery_block, load_discovery_block, save_discovery_block};
+use crate::manager::pool_discovery::{
+    decode_solidly_pairs, decode_v2_pools, fetch_pool_tokens, fetch_solidly_stable,
+    solidly_pair_created_filter, v2_pair_created_filter,
+};
 use crate::manager::token_manager::TokenManager;
-use crate::pool::LiquidityPool;
-use alloy_primitives::Address;
+use crate::pool::cache::CacheConfig;
+use crate::pool::reserve_drift::{ReserveDrift, ReserveDriftConfig, SkimOpportunity};
+use crate::pool::strategy::{
+    ConfigurableFeeStrategy, PancakeV2Logic, SolidlyStableStrategy, SolidlyVolatileStrategy,
+    StandardV2Logic,
+};
+use crate::pool::uniswap_v2::UniswapV2Pool;
+use crate::pool::{DexKind, LiquidityPool};
+use alloy_primitives::{Address, U256};
 use alloy_provider::Provider;
-use dashmap::DashMap;
+use dashmap::{DashMap, DashSet};
+use futures::future::join_all;
 use futures::{StreamExt, stream};
 use std::collections::HashMap;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use tokio::sync::Mutex;
+use tokio_util::sync::CancellationToken;
 
 type PoolRegistry<P> = DashMap<Address, Arc<dyn LiquidityPool<P>>>;
 
 pub struct UniswapV2PoolManager<P: Provider + Send + Sync + 'static + ?Sized> {
     token_manager: Arc<TokenManager<P>>,
-    _dex_registry: HashMap<Address, DexDetails>,
+    /// Known factories, by address. Discovery quotes new pairs with the fee of its factory.
+    dex_registry: HashMap<Address, DexDetails>,
     pool_registry: Arc<PoolRegistry<P>>,
     provider: Arc<P>,
     factory_address: Address,
     pub last_discovery_block: u64,
+    log_scan: LogScanConfig,
+    /// Static managers only serve the pools they were given and never discover.
+    is_static: bool,
+    /// Latest balance/reserve drift measured per pool.
+    reserve_drifts: DashMap<Address, ReserveDrift>,
+    /// Pools whose latest drift exceeded the sweep threshold, pending review.
+    drift_flagged_pools: DashSet<Address>,
+    /// Where the next drift sweep starts in the address-ordered registry.
+    drift_cursor: AtomicUsize,
+    /// Stops discovery between chunks, with the progress of every scanned chunk recorded.
+    cancel: CancellationToken,
+    /// Whether [`Self::verify_record`] checks stored pairs against the chain.
+    verify_on_hydrate: bool,
+    /// Retention of the state caches of the pools it builds.
+    cache_config: CacheConfig,
+    /// Stores discovered pools, with their fee, for hydration on restart.
+    #[cfg(feature = "db")]
+    db_manager: Option<Arc<DbManager>>,
 }
 
 impl<P: Provider + Send + Sync + 'static + ?Sized> UniswapV2PoolManager<P> {
@@ -32,68 +76,254 @@ impl<P: Provider + Send + Sync + 'static + ?Sized> UniswapV2PoolManager<P> {
         Self {
             token_manager,
             pool_registry: Arc::new(DashMap::new()),
-            _dex_registry: build_mainnet_dex_registry(),
+            dex_registry: build_mainnet_dex_registry(),
             provider,
             factory_address,
             last_discovery_block: start_block,
+            log_scan: LogScanConfig::default(),
+            is_static: false,
+            reserve_drifts: DashMap::new(),
+            drift_flagged_pools: DashSet::new(),
+            drift_cursor: AtomicUsize::new(0),
+            cancel: CancellationToken::new(),
+            verify_on_hydrate: true,
+            cache_config: CacheConfig::default(),
+            #[cfg(feature = "db")]
+            db_manager: None,
         }
     }
 
+    /// Replaces the known factories with those of `chain`. The factory discovery follows is
+    /// still the one the manager was created with.
+    pub fn with_chain(mut self, chain: &ChainConfig) -> Self {
+        self.dex_registry = chain.v2_dex_registry();
+        self
+    }
+
+    /// Registers or replaces a factory, e.g. for a fork with its own fee.
+    pub fn with_factory(mut self, factory: Address, details: DexDetails) -> Self {
+        self.dex_registry.insert(factory, details);
+        self
+    }
+
+    /// Stores discovered pools in the database.
+    #[cfg(feature = "db")]
+    pub fn with_db_manager(mut self, db_manager: Arc<DbManager>) -> Self {
+        self.db_manager = Some(db_manager);
+        self
+    }
+
+    /// Sets how discovery splits and retries its `getLogs` calls.
+    pub fn with_log_scan(mut self, log_scan: LogScanConfig) -> Self {
+        self.log_scan = log_scan;
+        self
+    }
+
+    /// Stops discovery at the next chunk once `cancel` is cancelled.
+    pub fn with_cancellation(mut self, cancel: CancellationToken) -> Self {
+        self.cancel = cancel;
+        self
+    }
+
+    /// Turns the check of stored pairs by [`Self::verify_record`] on or off. It's on by
+    /// default.
+    pub fn with_verify_on_hydrate(mut self, verify_on_hydrate: bool) -> Self {
+        self.verify_on_hydrate = verify_on_hydrate;
+        self
+    }
+
+    /// Bounds the state caches of the pools it builds from now on.
+    pub fn with_cache_config(mut self, cache_config: CacheConfig) -> Self {
+        self.cache_config = cache_config;
+        self
+    }
+
+    /// Continues discovery from the last block a previous run stored, if it is further
+    /// along. Returns the block discovery continues after.
+    #[cfg(feature = "db")]
+    pub async fn resume_discovery(&mut self) -> Result<u64, ArbRsError> {
+        if let Some(db_manager) = &self.db_manager
+            && let Some(block) =
+                load_discovery_block(db_manager, "uniswap v2", self.factory_address).await?
+        {
+            self.last_discovery_block = self.last_discovery_block.max(block);
+        }
+        Ok(self.last_discovery_block)
+    }
+
+    /// Records that discovery scanned every block up to `block`.
+    async fn record_discovery_progress(&mut self, block: u64) {
+        self.last_discovery_block = block;
+        #[cfg(feature = "db")]
+        if let Some(db_manager) = &self.db_manager
+            && let Err(e) =
+                save_discovery_block(db_manager, "uniswap v2", self.factory_address, block).await
+        {
+            tracing::warn!(block, "Failed to store V2 discovery progress: {:?}", e);
+        }
+    }
+
+    /// Flushes the last block discovery scanned, for the next run to resume after. Call once
+    /// discovery has stopped.
+    pub async fn shutdown(&self) -> Result<(), ArbRsError> {
+        #[cfg(feature = "db")]
+        if let Some(db_manager) = &self.db_manager
+            && !self.is_static
+        {
+            flush_discovery_block(
+                db_manager,
+                "uniswap v2",
+                self.factory_address,
+                self.last_discovery_block,
+            )
+            .await?;
+        }
+        Ok(())
+    }
+
+    pub fn dex_details(&self, factory: Address) -> Option<&DexDetails> {
+        self.dex_registry.get(&factory)
+    }
+
+    /// Details of the factory this manager discovers from. Unregistered factories are
+    /// assumed to charge Uniswap's fee.
+    fn factory_details(&self) -> DexDetails {
+        self.dex_registry
+            .get(&self.factory_address)
+            .or_else(|| self.dex_registry.get(&UNISWAP_V2_FACTORY))
+            .cloned()
+            .unwrap_or_else(|| build_mainnet_dex_registry()[&UNISWAP_V2_FACTORY].clone())
+    }
+
+    /// A manager over a fixed set of pools, for embedding the quoting core without
+    /// discovery. More pools can still be added with [`Self::add_pool_by_address`].
+    pub fn new_static(
+        token_manager: Arc<TokenManager<P>>,
+        provider: Arc<P>,
+        pools: impl IntoIterator<Item = Arc<dyn LiquidityPool<P>>>,
+    ) -> Self {
+        let manager = Self {
+            is_static: true,
+            ..Self::new(token_manager, provider, Address::ZERO, 0)
+        };
+        for pool in pools {
+            manager.pool_registry.insert(pool.address(), pool);
+        }
+        manager
+    }
+
+    pub fn is_static(&self) -> bool {
+        self.is_static
+    }
+
+    /// Builds a pool from its address alone, reading its tokens from the pair. Solidly
+    /// pairs are also asked whether they're stable, whichever Solidly variant is given.
+    pub async fn add_pool_by_address(
+        &self,
+        pool_address: Address,
+        dex_type: DexVariant,
+    ) -> Result<Arc<dyn LiquidityPool<P>>, ArbRsError> {
+        if let Some(pool) = self.pool_registry.get(&pool_address) {
+            return Ok(pool.clone());
+        }
+        let (token0, token1) = fetch_pool_tokens(self.provider.as_ref(), pool_address).await?;
+        let dex_type = if dex_type.is_solidly() {
+            DexVariant::solidly(fetch_solidly_stable(self.provider.as_ref(), pool_address).await?)
+        } else {
+            dex_type
+        };
+        self.build_v2_pool(pool_address, token0, token1, dex_type)
+            .await
+    }
+
     /// Discovers new pools within a specified block range and adds them to the manager.
     pub async fn discover_pools_in_range(
         &mut self,
         end_block: u64,
     ) -> Result<Vec<Arc<dyn LiquidityPool<P>>>, ArbRsError> {
-        if end_block <= self.last_discovery_block {
+        if self.is_static || end_block <= self.last_discovery_block {
             return Ok(Vec::new());
         }
 
-        const CHUNK_SIZE: u64 = 10000;
-        let mut from_block = self.last_discovery_block + 1;
+        let factory_details = self.factory_details();
+        let is_solidly = factory_details.dex_type.is_solidly();
+        let filter = if is_solidly {
+            solidly_pair_created_filter(self.factory_address)
+        } else {
+            v2_pair_created_filter(self.factory_address)
+        };
+        let mut scan = chunked_log_scan(
+            filter,
+            self.last_discovery_block + 1,
+            end_block,
+            self.log_scan,
+        )
+        .with_cancellation(self.cancel.clone());
         let mut all_new_pools = Vec::new();
 
-        while from_block <= end_block {
-            let to_block = (from_block + CHUNK_SIZE - 1).min(end_block);
-            println!(
-                "[V2 Manager] Discovering pools from block {} to {}",
-                from_block, to_block
+        while let Some(chunk) = scan.next_chunk(self.provider.as_ref()).await? {
+            let discovered_pools_data: Vec<_> = if is_solidly {
+                decode_solidly_pairs(&chunk.logs)?
+                    .into_iter()
+                    .map(|pair| {
+                        let kind = V2PairKind::solidly(&factory_details, pair.stable)?;
+                        Ok((pair.pool_address, pair.token0, pair.token1, kind))
+                    })
+                    .collect::<Result<_, ArbRsError>>()?
+            } else {
+                let kind = V2PairKind::for_fee(factory_details.fee(false))?;
+                decode_v2_pools(&chunk.logs)
+                    .into_iter()
+                    .map(|pool| (pool.pool_address, pool.token0, pool.token1, kind))
+                    .collect()
+            };
+            tracing::info!(
+                from_block = chunk.from_block,
+                to_block = chunk.to_block,
+                pools = discovered_pools_data.len(),
+                "[V2 Manager] Discovering pools"
             );
-
-            let discovered_pools_data = discover_new_v2_pools(
-                self.provider.clone(),
-                self.factory_address,
-                from_block,
-                to_block,
-            )
-            .await?;
+            crate::metrics::record_discovery_events(Some(DexKind::UniswapV2), chunk.logs.len());
 
             const CONCURRENT_BUILDS: usize = 5;
 
             let new_pools_in_chunk = Arc::new(Mutex::new(Vec::new()));
 
-            let token_manager_clone = self.token_manager.clone();
-            let provider_clone = self.provider.clone();
-            let pool_registry_clone = self.pool_registry.clone();
+            let build_context = self.build_context();
+            #[cfg(feature = "db")]
+            let db_manager_clone = self.db_manager.clone();
 
             stream::iter(discovered_pools_data)
-                .for_each_concurrent(CONCURRENT_BUILDS, |pool_data| {
-                    let token_manager = token_manager_clone.clone();
-                    let provider = provider_clone.clone();
-                    let pool_registry = pool_registry_clone.clone();
+                .for_each_concurrent(CONCURRENT_BUILDS, |(pool_address, token0, token1, kind)| {
+                    let build_context = build_context.clone();
                     let new_pools = new_pools_in_chunk.clone();
+                    #[cfg(feature = "db")]
+                    let db_manager = db_manager_clone.clone();
 
                     async move {
                         if let Ok(pool) = build_and_register_v2_pool(
-                            pool_registry,
-                            token_manager,
-                            provider,
-                            pool_data.pool_address,
-                            pool_data.token0,
-                            pool_data.token1,
-                            DexVariant::UniswapV2,
+                            &build_context,
+                            pool_address,
+                            token0,
+                            token1,
+                            kind,
                         )
                         .await
                         {
+                            #[cfg(feature = "db")]
+                            if let Some(db_manager) = &db_manager
+                                && let Err(e) = db_manager
+                                    .save_pool(
+                                        pool.address(),
+                                        &kind.pool_kind(),
+                                        &pool.get_all_tokens(),
+                                        Some(kind.fee_pips()),
+                                        None,
+                                    )
+                                    .await
+                            {
+                                tracing::warn!(pool = ?pool.address(), "Failed to store V2 pool: {:?}", e);
+                            }
                             let mut new_pools_guard = new_pools.lock().await;
                             new_pools_guard.push(pool);
                         }
@@ -104,10 +334,15 @@ impl<P: Provider + Send + Sync + 'static + ?Sized> UniswapV2PoolManager<P> {
             let new_pools = Arc::try_unwrap(new_pools_in_chunk).unwrap().into_inner();
             all_new_pools.extend(new_pools);
 
-            from_block = to_block + 1;
+            self.record_discovery_progress(chunk.to_block).await;
+        }
+        if scan.is_cancelled() {
+            tracing::info!(
+                last_block = self.last_discovery_block,
+                "[V2 Manager] Discovery cancelled"
+            );
         }
 
-        self.last_discovery_block = end_block;
         Ok(all_new_pools)
     }
 
@@ -121,7 +356,62 @@ impl<P: Provider + Send + Sync + 'static + ?Sized> UniswapV2PoolManager<P> {
         self.discover_pools_in_range(latest_block).await
     }
 
-    /// Creates or retrieves a cached V2 liquidity pool instance.
+    /// Checks that a stored pair is the contract its record describes before it's built.
+    /// Uniswap-style pairs must sit at the CREATE2 address a registered factory derives
+    /// from their tokens. Solidly pairs, whose addresses derive differently, must hold the
+    /// stored tokens and be as stable as their kind says. Passes without a check when
+    /// `verify_on_hydrate` is off.
+    pub async fn verify_record(
+        &self,
+        pool_address: Address,
+        token_a: Address,
+        token_b: Address,
+        pool_kind: &PoolKind,
+    ) -> Result<(), ArbRsError> {
+        if !self.verify_on_hydrate {
+            return Ok(());
+        }
+        if matches!(
+            pool_kind,
+            PoolKind::SolidlyStable | PoolKind::SolidlyVolatile
+        ) {
+            let ((token0, token1), stable) = tokio::try_join!(
+                fetch_pool_tokens(self.provider.as_ref(), pool_address),
+                fetch_solidly_stable(self.provider.as_ref(), pool_address),
+            )?;
+            if (token0, token1) != (token_a.min(token_b), token_a.max(token_b)) {
+                return Err(ArbRsError::AddressMismatch(
+                    pool_address,
+                    format!("pair holds {token0} and {token1}, not {token_a} and {token_b}"),
+                ));
+            }
+            if stable != (*pool_kind == PoolKind::SolidlyStable) {
+                return Err(ArbRsError::AddressMismatch(
+                    pool_address,
+                    format!("pair is not {pool_kind}"),
+                ));
+            }
+            return Ok(());
+        }
+        let derived = self
+            .dex_registry
+            .iter()
+            .filter(|(_, details)| !details.dex_type.is_solidly())
+            .any(|(factory, details)| {
+                details.pair_address(*factory, token_a, token_b) == pool_address
+            });
+        if !derived {
+            return Err(ArbRsError::AddressMismatch(
+                pool_address,
+                format!("no registered factory deploys the {token_a}/{token_b} pair there"),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Creates or retrieves a cached V2 liquidity pool instance, quoted with the fee of
+    /// the first registered factory of `dex_type`. Solidly pairs are built stable or
+    /// volatile as `dex_type` says, from the first factory of either Solidly variant.
     pub async fn build_v2_pool(
         &self,
         pool_address: Address,
@@ -129,44 +419,131 @@ impl<P: Provider + Send + Sync + 'static + ?Sized> UniswapV2PoolManager<P> {
         token_b: Address,
         dex_type: DexVariant,
     ) -> Result<Arc<dyn LiquidityPool<P>>, ArbRsError> {
-        if let Some(pool) = self.pool_registry.get(&pool_address) {
-            return Ok(pool.clone());
+        let mut factories: Vec<_> = self
+            .dex_registry
+            .iter()
+            .filter(|(_, details)| {
+                details.dex_type == dex_type
+                    || (dex_type.is_solidly() && details.dex_type.is_solidly())
+            })
+            .collect();
+        factories.sort_by_key(|(factory, _)| **factory);
+        let Some((_, details)) = factories.first() else {
+            return Err(ArbRsError::InvalidPool(
+                pool_address,
+                format!("no factory registered for {:?}", dex_type),
+            ));
+        };
+        if dex_type.is_solidly() {
+            let stable = dex_type == DexVariant::SolidlyStable;
+            let (fee_numerator, fee_denominator) = details.fee(stable);
+            return self
+                .build_solidly_pool(
+                    pool_address,
+                    token_a,
+                    token_b,
+                    stable,
+                    fee_numerator,
+                    fee_denominator,
+                )
+                .await;
         }
+        self.build_v2_pool_with_fee(
+            pool_address,
+            token_a,
+            token_b,
+            details.fee_numerator,
+            details.fee_denominator,
+        )
+        .await
+    }
 
-        let token0 = self
-            .token_manager
-            .get_token(if token_a < token_b { token_a } else { token_b })
-            .await?;
-        let token1 = self
-            .token_manager
-            .get_token(if token_a < token_b { token_b } else { token_a })
-            .await?;
-
-        let pool: Arc<dyn LiquidityPool<P>> = match dex_type {
-            DexVariant::UniswapV2 | DexVariant::SushiSwap => {
-                let strategy = crate::pool::strategy::StandardV2Logic;
-                Arc::new(crate::pool::uniswap_v2::UniswapV2Pool::new(
-                    pool_address,
-                    token0,
-                    token1,
-                    self.provider.clone(),
-                    strategy,
-                ))
-            }
-            DexVariant::PancakeSwapV2 => {
-                let strategy = crate::pool::strategy::PancakeV2Logic;
-                Arc::new(crate::pool::uniswap_v2::UniswapV2Pool::new(
+    /// Like [`Self::build_v2_pool`], with the fee of a registered `factory`. Pairs of a
+    /// Solidly factory are asked whether they're stable.
+    pub async fn build_v2_pool_for_factory(
+        &self,
+        pool_address: Address,
+        token_a: Address,
+        token_b: Address,
+        factory: Address,
+    ) -> Result<Arc<dyn LiquidityPool<P>>, ArbRsError> {
+        let details = self.dex_registry.get(&factory).ok_or_else(|| {
+            ArbRsError::InvalidPool(pool_address, format!("unknown V2 factory {factory}"))
+        })?;
+        if details.dex_type.is_solidly() {
+            let stable = fetch_solidly_stable(self.provider.as_ref(), pool_address).await?;
+            let (fee_numerator, fee_denominator) = details.fee(stable);
+            return self
+                .build_solidly_pool(
                     pool_address,
-                    token0,
-                    token1,
-                    self.provider.clone(),
-                    strategy,
-                ))
-            }
+                    token_a,
+                    token_b,
+                    stable,
+                    fee_numerator,
+                    fee_denominator,
+                )
+                .await;
+        }
+        self.build_v2_pool_with_fee(
+            pool_address,
+            token_a,
+            token_b,
+            details.fee_numerator,
+            details.fee_denominator,
+        )
+        .await
+    }
+
+    /// Creates or retrieves a pool charging `fee_numerator / fee_denominator`, e.g. from a
+    /// stored record whose fee is in [`FEE_PIPS_DENOMINATOR`]ths.
+    pub async fn build_v2_pool_with_fee(
+        &self,
+        pool_address: Address,
+        token_a: Address,
+        token_b: Address,
+        fee_numerator: u32,
+        fee_denominator: u32,
+    ) -> Result<Arc<dyn LiquidityPool<P>>, ArbRsError> {
+        build_and_register_v2_pool(
+            &self.build_context(),
+            pool_address,
+            token_a,
+            token_b,
+            V2PairKind::for_fee((fee_numerator, fee_denominator))?,
+        )
+        .await
+    }
+
+    /// Creates or retrieves a Solidly-style pair, stable or volatile, charging
+    /// `fee_numerator / fee_denominator` of the input.
+    pub async fn build_solidly_pool(
+        &self,
+        pool_address: Address,
+        token_a: Address,
+        token_b: Address,
+        stable: bool,
+        fee_numerator: u32,
+        fee_denominator: u32,
+    ) -> Result<Arc<dyn LiquidityPool<P>>, ArbRsError> {
+        let kind = if stable {
+            V2PairKind::SolidlyStable(SolidlyStableStrategy::new(fee_numerator, fee_denominator)?)
+        } else {
+            V2PairKind::SolidlyVolatile(SolidlyVolatileStrategy::new(
+                fee_numerator,
+                fee_denominator,
+            )?)
         };
+        build_and_register_v2_pool(&self.build_context(), pool_address, token_a, token_b, kind)
+            .await
+    }
 
-        self.pool_registry.insert(pool_address, pool.clone());
-        Ok(pool)
+    fn build_context(&self) -> V2BuildContext<P> {
+        V2BuildContext {
+            pool_registry: self.pool_registry.clone(),
+            token_manager: self.token_manager.clone(),
+            provider: self.provider.clone(),
+            cache_config: self.cache_config,
+        }
     }
 
     /// Retrieves a pool from the registry by its address.
@@ -180,20 +557,142 @@ impl<P: Provider + Send + Sync + 'static + ?Sized> UniswapV2PoolManager<P> {
             .map(|entry| entry.value().clone())
             .collect()
     }
+
+    /// Checks the next `config.pools_per_sweep` pools, in address order, for drift between
+    /// their reserves and token balances at `block_number`. Each pool's latest drift is
+    /// kept, and pools drifting beyond `config.threshold_bps` are flagged for review until
+    /// a later sweep finds them back within it. Returns the skims worth more than
+    /// `config.skim_gas_units` at `gas_price`.
+    pub async fn sweep_reserve_drift(
+        &self,
+        block_number: u64,
+        config: &ReserveDriftConfig,
+        gas_price: U256,
+    ) -> Vec<SkimOpportunity> {
+        let mut pools = self.get_all_pools();
+        if pools.is_empty() {
+            return Vec::new();
+        }
+        pools.sort_by_key(|pool| pool.address());
+        let start = self
+            .drift_cursor
+            .fetch_add(config.pools_per_sweep, Ordering::Relaxed)
+            % pools.len();
+        let drift_futs = pools
+            .iter()
+            .cycle()
+            .skip(start)
+            .take(config.pools_per_sweep.min(pools.len()))
+            .map(|pool| async move {
+                (
+                    pool.address(),
+                    fetch_reserve_drift(pool.as_ref(), block_number).await,
+                )
+            });
+
+        let skim_gas_cost_wei = U256::from(config.skim_gas_units).saturating_mul(gas_price);
+        let mut skims = Vec::new();
+        for (address, result) in join_all(drift_futs).await {
+            let drift = match result {
+                Some(Ok(drift)) => drift,
+                Some(Err(e)) => {
+                    tracing::debug!(?address, "Failed to check reserve drift: {:?}", e);
+                    continue;
+                }
+                None => continue,
+            };
+            if drift.exceeds(config.threshold_bps) {
+                if self.drift_flagged_pools.insert(address) {
+                    tracing::warn!(
+                        ?address,
+                        drift_bps = drift.relative_bps(),
+                        "Flagging V2 pool for review: balances drift from reserves"
+                    );
+                }
+            } else {
+                self.drift_flagged_pools.remove(&address);
+            }
+            if let Some(skim) = drift.skim_opportunity(skim_gas_cost_wei) {
+                tracing::info!(?address, value_wei = ?skim.value_wei, "Skim opportunity");
+                skims.push(skim);
+            }
+            self.reserve_drifts.insert(address, drift);
+        }
+        skims
+    }
+
+    /// The latest drift measured for `address` by a sweep.
+    pub fn reserve_drift(&self, address: Address) -> Option<ReserveDrift> {
+        self.reserve_drifts.get(&address).map(|drift| drift.clone())
+    }
+
+    /// Pools flagged by drift sweeps, in address order.
+    pub fn drift_flagged_pools(&self) -> Vec<Address> {
+        let mut pools: Vec<Address> = self.drift_flagged_pools.iter().map(|pool| *pool).collect();
+        pools.sort();
+        pools
+    }
 }
 
-async fn build_and_register_v2_pool<P: Provider + Send + Sync + 'static + ?Sized>(
+/// `None` for pools that aren't V2 pairs of a known strategy.
+async fn fetch_reserve_drift<P: Provider + Send + Sync + 'static + ?Sized>(
+    pool: &dyn LiquidityPool<P>,
+    block_number: u64,
+) -> Option<Result<ReserveDrift, ArbRsError>> {
+    let any = pool.as_any();
+    if let Some(v2) = any.downcast_ref::<UniswapV2Pool<P, StandardV2Logic>>() {
+        Some(v2.fetch_reserve_drift(block_number).await)
+    } else if let Some(v2) = any.downcast_ref::<UniswapV2Pool<P, PancakeV2Logic>>() {
+        Some(v2.fetch_reserve_drift(block_number).await)
+    } else if let Some(v2) = any.downcast_ref::<UniswapV2Pool<P, ConfigurableFeeStrategy>>() {
+        Some(v2.fetch_reserve_drift(block_number).await)
+    } else if let Some(v2) = any.downcast_ref::<UniswapV2Pool<P, SolidlyStableStrategy>>() {
+        Some(v2.fetch_reserve_drift(block_number).await)
+    } else if let Some(v2) = any.downcast_ref::<UniswapV2Pool<P, SolidlyVolatileStrategy>>() {
+        Some(v2.fetch_reserve_drift(block_number).await)
+    } else {
+        None
+    }
+}
+
+/// What building a pair needs from its manager, cloned into each concurrent build.
+struct V2BuildContext<P: Provider + Send + Sync + 'static + ?Sized> {
     pool_registry: Arc<PoolRegistry<P>>,
     token_manager: Arc<TokenManager<P>>,
     provider: Arc<P>,
+    cache_config: CacheConfig,
+}
+
+impl<P: Provider + Send + Sync + 'static + ?Sized> Clone for V2BuildContext<P> {
+    fn clone(&self) -> Self {
+        Self {
+            pool_registry: self.pool_registry.clone(),
+            token_manager: self.token_manager.clone(),
+            provider: self.provider.clone(),
+            cache_config: self.cache_config,
+        }
+    }
+}
+
+async fn build_and_register_v2_pool<P: Provider + Send + Sync + 'static + ?Sized>(
+    context: &V2BuildContext<P>,
     pool_address: Address,
     token_a: Address,
     token_b: Address,
-    dex_type: DexVariant,
+    kind: V2PairKind,
 ) -> Result<Arc<dyn LiquidityPool<P>>, ArbRsError> {
+    let V2BuildContext {
+        pool_registry,
+        token_manager,
+        provider,
+        cache_config,
+    } = context;
     if let Some(pool) = pool_registry.get(&pool_address) {
         return Ok(pool.clone());
     }
+    token_manager
+        .token_policy()
+        .check_pool(pool_address, [token_a, token_b])?;
 
     let token0 = token_manager
         .get_token(if token_a < token_b { token_a } else { token_b })
@@ -202,25 +701,116 @@ async fn build_and_register_v2_pool<P: Provider + Send + Sync + 'static + ?Sized
         .get_token(if token_a < token_b { token_b } else { token_a })
         .await?;
 
-    let pool: Arc<dyn LiquidityPool<P>> = match dex_type {
-        DexVariant::UniswapV2 | DexVariant::SushiSwap => {
-            Arc::new(crate::pool::uniswap_v2::UniswapV2Pool::new(
-                pool_address,
-                token0,
-                token1,
-                provider,
-                crate::pool::strategy::StandardV2Logic,
-            ))
-        }
-        DexVariant::PancakeSwapV2 => Arc::new(crate::pool::uniswap_v2::UniswapV2Pool::new(
-            pool_address,
-            token0,
-            token1,
-            provider,
-            crate::pool::strategy::PancakeV2Logic,
-        )),
-    };
-
+    token_manager
+        .analyze_pool_tokens(pool_address, &[token0.clone(), token1.clone()])
+        .await;
+    let pool = kind.new_pool(
+        pool_address,
+        token0,
+        token1,
+        provider.clone(),
+        *cache_config,
+    );
     pool_registry.insert(pool_address, pool.clone());
     Ok(pool)
 }
+
+/// The curve and fee a pair is quoted with.
+#[derive(Debug, Clone, Copy)]
+enum V2PairKind {
+    Uniswap(ConfigurableFeeStrategy),
+    SolidlyStable(SolidlyStableStrategy),
+    SolidlyVolatile(SolidlyVolatileStrategy),
+}
+
+impl V2PairKind {
+    fn for_fee((fee_numerator, fee_denominator): (u32, u32)) -> Result<Self, ArbRsError> {
+        Ok(Self::Uniswap(ConfigurableFeeStrategy::new(
+            fee_numerator,
+            fee_denominator,
+        )?))
+    }
+
+    fn solidly(factory: &DexDetails, stable: bool) -> Result<Self, ArbRsError> {
+        let (fee_numerator, fee_denominator) = factory.fee(stable);
+        Ok(if stable {
+            Self::SolidlyStable(SolidlyStableStrategy::new(fee_numerator, fee_denominator)?)
+        } else {
+            Self::SolidlyVolatile(SolidlyVolatileStrategy::new(
+                fee_numerator,
+                fee_denominator,
+            )?)
+        })
+    }
+
+    #[cfg(feature = "db")]
+    fn pool_kind(&self) -> PoolKind {
+        match self {
+            Self::Uniswap(_) => PoolKind::UniswapV2,
+            Self::SolidlyStable(_) => PoolKind::SolidlyStable,
+            Self::SolidlyVolatile(_) => PoolKind::SolidlyVolatile,
+        }
+    }
+
+    #[cfg(feature = "db")]
+    fn fee_pips(&self) -> u32 {
+        match self {
+            Self::Uniswap(fee) => fee_pips(fee.fee_numerator(), fee.fee_denominator()),
+            Self::SolidlyStable(fee) => fee_pips(fee.fee_numerator(), fee.fee_denominator()),
+            Self::SolidlyVolatile(fee) => fee_pips(fee.fee_numerator(), fee.fee_denominator()),
+        }
+    }
+
+    fn new_pool<P: Provider + Send + Sync + 'static + ?Sized>(
+        self,
+        pool_address: Address,
+        token0: Arc<Token<P>>,
+        token1: Arc<Token<P>>,
+        provider: Arc<P>,
+        cache_config: CacheConfig,
+    ) -> Arc<dyn LiquidityPool<P>> {
+        match self {
+            Self::Uniswap(fee) => {
+                new_v2_pool(pool_address, token0, token1, provider, fee, cache_config)
+            }
+            Self::SolidlyStable(strategy) => Arc::new(
+                UniswapV2Pool::new(pool_address, token0, token1, provider, strategy)
+                    .with_cache_config(cache_config),
+            ),
+            Self::SolidlyVolatile(strategy) => Arc::new(
+                UniswapV2Pool::new(pool_address, token0, token1, provider, strategy)
+                    .with_cache_config(cache_config),
+            ),
+        }
+    }
+}
+
+/// Builds a pair quoted with `fee`. The canonical 30 and 25 bps fees keep their dedicated
+/// strategies, which the rest of the crate downcasts to.
+fn new_v2_pool<P: Provider + Send + Sync + 'static + ?Sized>(
+    pool_address: Address,
+    token0: Arc<Token<P>>,
+    token1: Arc<Token<P>>,
+    provider: Arc<P>,
+    fee: ConfigurableFeeStrategy,
+    cache_config: CacheConfig,
+) -> Arc<dyn LiquidityPool<P>> {
+    let fee_pips =
+        fee.fee_numerator() as u64 * FEE_PIPS_DENOMINATOR as u64 / fee.fee_denominator() as u64;
+    let exact = fee_pips * fee.fee_denominator() as u64
+        == fee.fee_numerator() as u64 * FEE_PIPS_DENOMINATOR as u64;
+    match (exact, fee_pips) {
+        (true, 3_000) => Arc::new(
+            UniswapV2Pool::new(pool_address, token0, token1, provider, StandardV2Logic)
+                .with_cache_config(cache_config),
+        ),
+        (true, 2_500) => Arc::new(
+            UniswapV2Pool::new(pool_address, token0, token1, provider, PancakeV2Logic)
+                .with_cache_config(cache_config),
+        ),
+        _ => Arc::new(
+            UniswapV2Pool::new(pool_address, token0, token1, provider, fee)
+                .with_cache_config(cache_config),
+        ),
+    }
+}
@@ -1,25 +1,177 @@
+use crate::chain::ChainConfig;
+#[cfg(feature = "db")]
+use crate::db::DbManager;
+use crate::dex::PoolKind;
 use crate::errors::ArbRsError;
-use crate::manager::pool_discovery::discover_new_v3_pools;
+use crate::manager::log_scan::{LogScanConfig, chunked_log_scan};
+#[cfg(feature = "db")]
+use crate::manager::log_scan::{flush_discovery_block, load_discovery_block, save_discovery_block};
+use crate::manager::pool_discovery::{decode_v3_pools, fetch_pool_tokens, v3_pool_created_filter};
 use crate::manager::token_manager::TokenManager;
+use crate::pool::address::{PANCAKE_V3_INIT_CODE_HASH, UNISWAP_V3_INIT_CODE_HASH, v3_pool_address};
+use crate::pool::cache::CacheConfig;
 use crate::pool::{
-    LiquidityPool, uniswap_v3::UniswapV3Pool, uniswap_v3_snapshot::UniswapV3LiquiditySnapshot,
+    DexKind, LiquidityPool, uniswap_v3::UniswapV3Pool,
+    uniswap_v3_snapshot::UniswapV3LiquiditySnapshot,
 };
-use alloy_primitives::Address;
+use alloy_primitives::{Address, B256, address};
 use alloy_provider::Provider;
+use alloy_rpc_types::TransactionRequest;
+use alloy_sol_types::{SolCall, sol};
 use dashmap::DashMap;
 use futures::{StreamExt, stream};
+use std::collections::HashMap;
 use std::sync::Arc;
+#[cfg(feature = "db")]
+use std::sync::atomic::{AtomicUsize, Ordering};
 use tokio::sync::{Mutex, RwLock};
+use tokio_util::sync::CancellationToken;
 
 type PoolRegistry<P> = DashMap<Address, Arc<dyn LiquidityPool<P>>>;
 
+pub const UNISWAP_V3_FACTORY: Address = address!("1F98431c8aD98523631AE4a59f267346ea31F984");
+pub const PANCAKE_V3_FACTORY: Address = address!("0BFbCF9fa4f9C56B0F40a671Ad40E0805A091865");
+/// Pancake V3's factory has pools created by this separate contract.
+pub const PANCAKE_V3_DEPLOYER: Address = address!("41ff9AA7e16B8B1a8a8dc4f0eFacd93D02d071c9");
+
+sol! {
+    function fee() external view returns (uint24);
+    function tickSpacing() external view returns (int24);
+}
+
+/// The fee tiers a V3 factory deploys, mapping each fee to its tick spacing.
+#[derive(Debug, Clone, Default)]
+pub struct FeeTierTable {
+    tiers: HashMap<u32, i32>,
+}
+
+impl FeeTierTable {
+    pub fn new(tiers: impl IntoIterator<Item = (u32, i32)>) -> Self {
+        Self {
+            tiers: tiers.into_iter().collect(),
+        }
+    }
+
+    pub fn uniswap() -> Self {
+        Self::new([(100, 1), (500, 10), (3_000, 60), (10_000, 200)])
+    }
+
+    pub fn pancakeswap() -> Self {
+        Self::new([(100, 1), (500, 10), (2_500, 50), (10_000, 200)])
+    }
+
+    pub fn tick_spacing(&self, fee: u32) -> Option<i32> {
+        self.tiers.get(&fee).copied()
+    }
+
+    pub fn is_standard(&self, fee: u32, tick_spacing: i32) -> bool {
+        self.tick_spacing(fee) == Some(tick_spacing)
+    }
+}
+
+/// A V3 deployment: the factory emitting `PoolCreated`, how its pool addresses derive, and
+/// the fee tiers it offers.
+#[derive(Debug, Clone)]
+pub struct V3FactoryConfig {
+    pub factory: Address,
+    /// Init code hash of the pool contract. Pool addresses are only checked when it's known.
+    pub pool_init_code_hash: Option<B256>,
+    /// Sender of the pools' CREATE2, which is the factory itself on Uniswap.
+    pub deployer: Address,
+    pub fee_tiers: FeeTierTable,
+    /// Kind stored with the deployment's pool records, so hydration finds this config again.
+    pub pool_kind: PoolKind,
+}
+
+impl V3FactoryConfig {
+    pub fn new(factory: Address, fee_tiers: FeeTierTable) -> Self {
+        Self {
+            factory,
+            pool_init_code_hash: None,
+            deployer: factory,
+            fee_tiers,
+            pool_kind: PoolKind::UniswapV3,
+        }
+    }
+
+    /// Enables checking that pools sit at the CREATE2 address derived from their tokens and fee.
+    pub fn with_pool_init_code(mut self, deployer: Address, init_code_hash: B256) -> Self {
+        self.deployer = deployer;
+        self.pool_init_code_hash = Some(init_code_hash);
+        self
+    }
+
+    pub fn with_pool_kind(mut self, pool_kind: PoolKind) -> Self {
+        self.pool_kind = pool_kind;
+        self
+    }
+
+    pub fn uniswap() -> Self {
+        Self::new(UNISWAP_V3_FACTORY, FeeTierTable::uniswap())
+            .with_pool_init_code(UNISWAP_V3_FACTORY, UNISWAP_V3_INIT_CODE_HASH)
+    }
+
+    pub fn pancakeswap() -> Self {
+        Self::new(PANCAKE_V3_FACTORY, FeeTierTable::pancakeswap())
+            .with_pool_init_code(PANCAKE_V3_DEPLOYER, PANCAKE_V3_INIT_CODE_HASH)
+            .with_pool_kind(PoolKind::PancakeSwapV3)
+    }
+
+    /// Address of the `fee` pool of a token pair, when the init code hash is known.
+    pub fn pool_address(&self, token_a: Address, token_b: Address, fee: u32) -> Option<Address> {
+        self.pool_init_code_hash.map(|init_code_hash| {
+            v3_pool_address(token_a, token_b, fee, self.deployer, init_code_hash)
+        })
+    }
+}
+
+/// A fee tier after validation against the factory's table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct ResolvedTier {
+    fee: u32,
+    tick_spacing: i32,
+    non_standard: bool,
+}
+
+/// How a pool of one deployment is built: its tier and the manager's per-pool settings.
+#[derive(Debug, Clone, Copy)]
+struct V3PoolOptions {
+    tier: ResolvedTier,
+    tick_lens: Option<Address>,
+    max_swap_words: Option<u32>,
+    cache_config: CacheConfig,
+}
+
 pub struct UniswapV3PoolManager<P: Provider + Send + Sync + 'static + ?Sized> {
     token_manager: Arc<TokenManager<P>>,
     pool_registry: Arc<PoolRegistry<P>>,
     provider: Arc<P>,
     liquidity_snapshot: Arc<RwLock<UniswapV3LiquiditySnapshot<P>>>,
     factory_address: Address,
+    /// Known deployments by factory. Discovery uses the one at `factory_address`, and
+    /// hydration the one a record's dex names.
+    factories: HashMap<Address, V3FactoryConfig>,
+    #[cfg(feature = "db")]
+    db_manager: Option<Arc<DbManager>>,
+    /// Where the next liquidity map save continues from.
+    #[cfg(feature = "db")]
+    map_cursor: AtomicUsize,
     pub last_discovery_block: u64,
+    log_scan: LogScanConfig,
+    /// `TickLens` the pools it builds read their liquidity maps through.
+    tick_lens: Option<Address>,
+    /// Bitmap words the pools it builds search per swap, see
+    /// [`UniswapV3Pool::with_max_swap_words`].
+    max_swap_words: Option<u32>,
+    /// Retention of the state caches of the pools it builds.
+    cache_config: CacheConfig,
+    /// Static managers only serve the pools they were given and never discover.
+    is_static: bool,
+    /// Stops discovery between chunks, with the progress of every scanned chunk recorded.
+    cancel: CancellationToken,
+    /// Whether pools built from a record or an address are checked against the CREATE2
+    /// address their deployment derives from their tokens and fee.
+    verify_on_hydrate: bool,
 }
 
 impl<P: Provider + Send + Sync + 'static + ?Sized> UniswapV3PoolManager<P> {
@@ -40,8 +192,323 @@ impl<P: Provider + Send + Sync + 'static + ?Sized> UniswapV3PoolManager<P> {
                 start_block,
             ))),
             factory_address,
+            factories: [V3FactoryConfig::uniswap(), V3FactoryConfig::pancakeswap()]
+                .into_iter()
+                .map(|config| (config.factory, config))
+                .collect(),
+            #[cfg(feature = "db")]
+            db_manager: None,
+            #[cfg(feature = "db")]
+            map_cursor: AtomicUsize::new(0),
             last_discovery_block: start_block,
+            log_scan: LogScanConfig::default(),
+            tick_lens: None,
+            max_swap_words: None,
+            cache_config: CacheConfig::default(),
+            is_static: false,
+            cancel: CancellationToken::new(),
+            verify_on_hydrate: true,
+        }
+    }
+
+    /// A manager over a fixed set of pools, for embedding the quoting core without
+    /// discovery. More pools can still be added with [`Self::add_pool_by_address`].
+    pub fn new_static(
+        token_manager: Arc<TokenManager<P>>,
+        provider: Arc<P>,
+        chain_id: u64,
+        pools: impl IntoIterator<Item = Arc<dyn LiquidityPool<P>>>,
+    ) -> Self {
+        let manager = Self {
+            is_static: true,
+            ..Self::new(token_manager, provider, chain_id, 0, UNISWAP_V3_FACTORY)
+        };
+        for pool in pools {
+            manager.pool_registry.insert(pool.address(), pool);
         }
+        manager
+    }
+
+    pub fn is_static(&self) -> bool {
+        self.is_static
+    }
+
+    /// Builds a pool from its address alone, reading its tokens and fee tier from the pool.
+    /// The tier is checked against the table of the manager's factory.
+    pub async fn add_pool_by_address(
+        &self,
+        pool_address: Address,
+    ) -> Result<Arc<dyn LiquidityPool<P>>, ArbRsError> {
+        if let Some(pool) = self.pool_registry.get(&pool_address) {
+            return Ok(pool.clone());
+        }
+        let ((token0, token1), (fee, tick_spacing)) = tokio::try_join!(
+            fetch_pool_tokens(self.provider.as_ref(), pool_address),
+            fetch_fee_tier(self.provider.as_ref(), pool_address),
+        )?;
+        self.build_pool(pool_address, token0, token1, fee, tick_spacing)
+            .await
+    }
+
+    /// A manager discovering from `config.factory`, e.g. a fork with its own init code hash.
+    pub fn from_config(
+        token_manager: Arc<TokenManager<P>>,
+        provider: Arc<P>,
+        chain_id: u64,
+        start_block: u64,
+        config: V3FactoryConfig,
+    ) -> Self {
+        Self::new(
+            token_manager,
+            provider,
+            chain_id,
+            start_block,
+            config.factory,
+        )
+        .with_factory_config(config)
+    }
+
+    /// Replaces the known deployments with those of `chain` and reads ticks through its
+    /// `TickLens`. The factory discovery follows is still the one the manager was created
+    /// with.
+    pub fn with_chain(mut self, chain: &ChainConfig) -> Self {
+        self.factories = chain
+            .v3_factories
+            .iter()
+            .map(|config| (config.factory, config.clone()))
+            .collect();
+        self.tick_lens = chain.v3_tick_lens;
+        self
+    }
+
+    /// Turns the CREATE2 check of built pools on or off. It's on by default; discovered
+    /// pools are checked either way.
+    pub fn with_verify_on_hydrate(mut self, verify_on_hydrate: bool) -> Self {
+        self.verify_on_hydrate = verify_on_hydrate;
+        self
+    }
+
+    /// Registers or replaces the fee tiers of a factory, e.g. for a fork.
+    pub fn with_fee_tiers(mut self, factory: Address, table: FeeTierTable) -> Self {
+        self.factories
+            .entry(factory)
+            .or_insert_with(|| V3FactoryConfig::new(factory, FeeTierTable::default()))
+            .fee_tiers = table;
+        self
+    }
+
+    /// Registers or replaces a deployment, so its pools can be hydrated and checked.
+    pub fn with_factory_config(mut self, config: V3FactoryConfig) -> Self {
+        self.factories.insert(config.factory, config);
+        self
+    }
+
+    /// The factory of the deployment whose records carry `pool_kind`.
+    pub fn factory_for_kind(&self, pool_kind: &PoolKind) -> Option<Address> {
+        self.factories
+            .values()
+            .find(|config| config.pool_kind == *pool_kind)
+            .map(|config| config.factory)
+    }
+
+    /// Kinds the known deployments store their records under.
+    pub fn pool_kinds(&self) -> Vec<PoolKind> {
+        let mut kinds: Vec<PoolKind> = Vec::new();
+        for config in self.factories.values() {
+            if !kinds.contains(&config.pool_kind) {
+                kinds.push(config.pool_kind.clone());
+            }
+        }
+        kinds
+    }
+
+    /// Lets fee tiers corrected from the chain be written back to the database, and
+    /// liquidity maps be stored and restored.
+    #[cfg(feature = "db")]
+    pub fn with_db_manager(mut self, db_manager: Arc<DbManager>) -> Self {
+        self.db_manager = Some(db_manager);
+        self
+    }
+
+    /// Sets how discovery splits and retries its `getLogs` calls.
+    pub fn with_log_scan(mut self, log_scan: LogScanConfig) -> Self {
+        self.log_scan = log_scan;
+        self
+    }
+
+    /// Stops discovery at the next chunk once `cancel` is cancelled.
+    pub fn with_cancellation(mut self, cancel: CancellationToken) -> Self {
+        self.cancel = cancel;
+        self
+    }
+
+    /// Has the pools it builds from now on read their ticks through the `TickLens` at
+    /// `tick_lens`.
+    pub fn with_tick_lens(mut self, tick_lens: Address) -> Self {
+        self.tick_lens = Some(tick_lens);
+        self
+    }
+
+    /// Has the pools it builds from now on search at most `max_swap_words` bitmap words per
+    /// swap.
+    pub fn with_max_swap_words(mut self, max_swap_words: u32) -> Self {
+        self.max_swap_words = Some(max_swap_words);
+        self
+    }
+
+    /// Bounds the state caches of the pools it builds from now on.
+    pub fn with_cache_config(mut self, cache_config: CacheConfig) -> Self {
+        self.cache_config = cache_config;
+        self
+    }
+
+    /// Continues discovery from the last block a previous run stored, if it is further
+    /// along. Returns the block discovery continues after.
+    #[cfg(feature = "db")]
+    pub async fn resume_discovery(&mut self) -> Result<u64, ArbRsError> {
+        if let Some(db_manager) = &self.db_manager
+            && let Some(block) =
+                load_discovery_block(db_manager, "uniswap v3", self.factory_address).await?
+        {
+            self.last_discovery_block = self.last_discovery_block.max(block);
+        }
+        Ok(self.last_discovery_block)
+    }
+
+    /// Records that discovery scanned every block up to `block`.
+    async fn record_discovery_progress(&mut self, block: u64) {
+        self.last_discovery_block = block;
+        #[cfg(feature = "db")]
+        if let Some(db_manager) = &self.db_manager
+            && let Err(e) =
+                save_discovery_block(db_manager, "uniswap v3", self.factory_address, block).await
+        {
+            tracing::warn!(block, "Failed to store V3 discovery progress: {:?}", e);
+        }
+    }
+
+    /// Flushes the last block discovery scanned, for the next run to resume after. Call once
+    /// discovery has stopped.
+    pub async fn shutdown(&self) -> Result<(), ArbRsError> {
+        #[cfg(feature = "db")]
+        if let Some(db_manager) = &self.db_manager
+            && !self.is_static
+        {
+            flush_discovery_block(
+                db_manager,
+                "uniswap v3",
+                self.factory_address,
+                self.last_discovery_block,
+            )
+            .await?;
+        }
+        Ok(())
+    }
+
+    /// Checks `(fee, tick_spacing)` against the factory's table. A pair the table disagrees
+    /// with is re-read from the pool itself and corrected in the database. Factories without
+    /// a table are trusted, but their pools are flagged as non-standard.
+    async fn resolve_fee_tier(
+        &self,
+        factory: Address,
+        pool_address: Address,
+        fee: u32,
+        tick_spacing: i32,
+    ) -> Result<ResolvedTier, ArbRsError> {
+        let Some(table) = self.factories.get(&factory).map(|config| &config.fee_tiers) else {
+            tracing::debug!(
+                ?pool_address,
+                fee,
+                tick_spacing,
+                "No fee tier table for factory."
+            );
+            return Ok(ResolvedTier {
+                fee,
+                tick_spacing,
+                non_standard: true,
+            });
+        };
+        if table.is_standard(fee, tick_spacing) {
+            return Ok(ResolvedTier {
+                fee,
+                tick_spacing,
+                non_standard: false,
+            });
+        }
+
+        let (onchain_fee, onchain_tick_spacing) =
+            fetch_fee_tier(self.provider.as_ref(), pool_address).await?;
+        if (onchain_fee, onchain_tick_spacing) != (fee, tick_spacing) {
+            tracing::warn!(
+                ?pool_address,
+                fee,
+                tick_spacing,
+                onchain_fee,
+                onchain_tick_spacing,
+                "Correcting V3 pool fee tier from chain."
+            );
+            #[cfg(feature = "db")]
+            if let Some(db_manager) = &self.db_manager
+                && let Err(e) = db_manager
+                    .update_pool_fee_tier(pool_address, onchain_fee, onchain_tick_spacing)
+                    .await
+            {
+                tracing::warn!(?pool_address, "Failed to store corrected fee tier: {:?}", e);
+            }
+        }
+
+        let non_standard = !table.is_standard(onchain_fee, onchain_tick_spacing);
+        if non_standard {
+            tracing::warn!(
+                ?pool_address,
+                onchain_fee,
+                onchain_tick_spacing,
+                "Non-standard V3 fee tier."
+            );
+        }
+        Ok(ResolvedTier {
+            fee: onchain_fee,
+            tick_spacing: onchain_tick_spacing,
+            non_standard,
+        })
+    }
+
+    /// Flags tiers the factory's table doesn't list, trusting the pair as given.
+    fn classify_fee_tier(&self, fee: u32, tick_spacing: i32) -> ResolvedTier {
+        let non_standard = self
+            .factories
+            .get(&self.factory_address)
+            .is_none_or(|config| !config.fee_tiers.is_standard(fee, tick_spacing));
+        ResolvedTier {
+            fee,
+            tick_spacing,
+            non_standard,
+        }
+    }
+
+    /// Checks a standard-tier pool against the address its factory would have deployed it at.
+    fn verify_pool_address(
+        &self,
+        factory: Address,
+        pool_address: Address,
+        token_a: Address,
+        token_b: Address,
+        fee: u32,
+    ) -> Result<(), ArbRsError> {
+        let Some(expected) = self
+            .factories
+            .get(&factory)
+            .and_then(|config| config.pool_address(token_a, token_b, fee))
+        else {
+            return Ok(());
+        };
+        if expected != pool_address {
+            return Err(ArbRsError::AddressMismatch(
+                pool_address,
+                format!("expected address {} for fee {}", expected, fee),
+            ));
+        }
+        Ok(())
     }
 
     pub async fn build_pool(
@@ -51,105 +518,232 @@ impl<P: Provider + Send + Sync + 'static + ?Sized> UniswapV3PoolManager<P> {
         token_b: Address,
         fee: u32,
         tick_spacing: i32,
+    ) -> Result<Arc<dyn LiquidityPool<P>>, ArbRsError> {
+        self.build_factory_pool(
+            self.factory_address,
+            pool_address,
+            token_a,
+            token_b,
+            fee,
+            tick_spacing,
+        )
+        .await
+    }
+
+    /// Like [`Self::build_pool`], checking the pool against `factory`'s deployment instead,
+    /// e.g. to hydrate another deployment's records.
+    pub async fn build_factory_pool(
+        &self,
+        factory: Address,
+        pool_address: Address,
+        token_a: Address,
+        token_b: Address,
+        fee: u32,
+        tick_spacing: i32,
     ) -> Result<Arc<dyn LiquidityPool<P>>, ArbRsError> {
         if let Some(pool) = self.pool_registry.get(&pool_address) {
             return Ok(pool.clone());
         }
 
-        let initial_liquidity_map = {
-            let snapshot = self.liquidity_snapshot.read().await;
-            snapshot.liquidity_snapshot.get(&pool_address).cloned()
-        };
-
-        let token0 = self
-            .token_manager
-            .get_token(if token_a < token_b { token_a } else { token_b })
-            .await?;
-        let token1 = self
-            .token_manager
-            .get_token(if token_a < token_b { token_b } else { token_a })
+        let tier = self
+            .resolve_fee_tier(factory, pool_address, fee, tick_spacing)
             .await?;
+        if self.verify_on_hydrate && !tier.non_standard {
+            self.verify_pool_address(factory, pool_address, token_a, token_b, tier.fee)?;
+        }
 
-        let pool = Arc::new(UniswapV3Pool::new(
+        let pool = build_and_register_v3_pool(
+            &self.build_context(),
             pool_address,
-            token0,
-            token1,
-            fee,
-            tick_spacing,
-            self.provider.clone(),
-            initial_liquidity_map,
-        ));
+            token_a,
+            token_b,
+            self.pool_options(tier),
+        )
+        .await?;
+        #[cfg(feature = "db")]
+        if let Some(v3_pool) = pool.as_any().downcast_ref::<UniswapV3Pool<P>>() {
+            self.hydrate_liquidity_map(v3_pool).await;
+        }
+        Ok(pool)
+    }
 
-        let pending_updates = {
-            let mut snapshot = self.liquidity_snapshot.write().await;
-            snapshot.pending_updates(pool_address)
+    /// Restores a pool's stored liquidity map and refreshes it to the latest block, so its
+    /// ticks don't have to be read again from scratch.
+    #[cfg(feature = "db")]
+    async fn hydrate_liquidity_map(&self, pool: &UniswapV3Pool<P>) {
+        let Some(db_manager) = &self.db_manager else {
+            return;
+        };
+        let pool_address = pool.address();
+        let (map_block, map) = match db_manager.load_liquidity_map(pool_address).await {
+            Ok(Some(stored)) => stored,
+            Ok(None) => return,
+            Err(e) => {
+                tracing::warn!(?pool_address, "Failed to load liquidity map: {:?}", e);
+                return;
+            }
         };
+        pool.set_liquidity_map(map, map_block).await;
 
-        for update in pending_updates {
-            pool.update_liquidity_map(update).await;
+        let refreshed = match self.provider.get_block_number().await {
+            Ok(latest_block) => pool.refresh_liquidity_map(latest_block).await,
+            Err(e) => Err(ArbRsError::ProviderError(e.to_string())),
+        };
+        match refreshed {
+            Ok(words) => {
+                tracing::debug!(?pool_address, map_block, words, "Restored liquidity map.")
+            }
+            Err(e) => tracing::warn!(
+                ?pool_address,
+                map_block,
+                "Failed to refresh restored liquidity map: {:?}",
+                e
+            ),
         }
+    }
 
-        self.pool_registry.insert(pool_address, pool.clone());
-        Ok(pool)
+    /// Refreshes the liquidity maps of the next `pools_per_sweep` pools, in address order, to
+    /// `block_number` and stores them for the next run. Returns the number stored.
+    #[cfg(feature = "db")]
+    pub async fn save_liquidity_maps(&self, block_number: u64, pools_per_sweep: usize) -> usize {
+        let Some(db_manager) = &self.db_manager else {
+            return 0;
+        };
+        let mut pools = self.get_all_pools();
+        if pools.is_empty() {
+            return 0;
+        }
+        pools.sort_by_key(|pool| pool.address());
+        let start = self
+            .map_cursor
+            .fetch_add(pools_per_sweep, Ordering::Relaxed)
+            % pools.len();
+
+        let mut saved = 0;
+        for pool in pools
+            .iter()
+            .cycle()
+            .skip(start)
+            .take(pools_per_sweep.min(pools.len()))
+        {
+            let Some(v3_pool) = pool.as_any().downcast_ref::<UniswapV3Pool<P>>() else {
+                continue;
+            };
+            let pool_address = pool.address();
+            if let Err(e) = v3_pool.refresh_liquidity_map(block_number).await {
+                tracing::debug!(?pool_address, "Failed to refresh liquidity map: {:?}", e);
+                continue;
+            }
+            let (Some(map_block), map) = v3_pool.liquidity_map().await else {
+                continue;
+            };
+            match db_manager
+                .save_liquidity_map(pool_address, v3_pool.tick_spacing(), map_block, &map)
+                .await
+            {
+                Ok(()) => saved += 1,
+                Err(e) => tracing::warn!(?pool_address, "Failed to save liquidity map: {:?}", e),
+            }
+        }
+        saved
     }
 
     pub async fn discover_pools_in_range(
         &mut self,
         end_block: u64,
     ) -> Result<Vec<Arc<dyn LiquidityPool<P>>>, ArbRsError> {
-        if end_block <= self.last_discovery_block {
+        if self.is_static || end_block <= self.last_discovery_block {
             return Ok(Vec::new());
         }
 
-        const CHUNK_SIZE: u64 = 10000;
-        let mut from_block = self.last_discovery_block + 1;
+        let mut scan = chunked_log_scan(
+            v3_pool_created_filter(self.factory_address),
+            self.last_discovery_block + 1,
+            end_block,
+            self.log_scan,
+        )
+        .with_cancellation(self.cancel.clone());
         let mut all_new_pools = Vec::new();
 
-        while from_block <= end_block {
-            let to_block = (from_block + CHUNK_SIZE - 1).min(end_block);
-            println!(
-                "[V3 Manager] Discovering pools from block {} to {}",
-                from_block, to_block
+        while let Some(chunk) = scan.next_chunk(self.provider.as_ref()).await? {
+            let discovered_pools_data = decode_v3_pools(&chunk.logs)?;
+            tracing::info!(
+                from_block = chunk.from_block,
+                to_block = chunk.to_block,
+                pools = discovered_pools_data.len(),
+                "[V3 Manager] Discovering pools"
             );
-
-            let discovered_pools_data = discover_new_v3_pools(
-                self.provider.clone(),
-                self.factory_address,
-                from_block,
-                to_block,
-            )
-            .await?;
+            crate::metrics::record_discovery_events(Some(DexKind::UniswapV3), chunk.logs.len());
 
             const CONCURRENT_BUILDS: usize = 5;
             let new_pools_in_chunk = Arc::new(Mutex::new(Vec::new()));
 
-            let token_manager_clone = self.token_manager.clone();
-            let provider_clone = self.provider.clone();
-            let pool_registry_clone = self.pool_registry.clone();
-            let liquidity_snapshot_clone = self.liquidity_snapshot.clone();
-
-            stream::iter(discovered_pools_data)
-                .for_each_concurrent(CONCURRENT_BUILDS, |pool_data| {
-                    let token_manager = token_manager_clone.clone();
-                    let provider = provider_clone.clone();
-                    let pool_registry = pool_registry_clone.clone();
-                    let liquidity_snapshot = liquidity_snapshot_clone.clone();
+            let build_context = self.build_context();
+
+            #[cfg(feature = "db")]
+            let db_manager_clone = self.db_manager.clone();
+            #[cfg(feature = "db")]
+            let pool_kind = self
+                .factories
+                .get(&self.factory_address)
+                .map_or(&PoolKind::UniswapV3, |config| &config.pool_kind);
+
+            // Standard-tier pools must sit at their CREATE2 address, which rules out events
+            // from a look-alike factory.
+            let classified_pools: Vec<_> = discovered_pools_data
+                .into_iter()
+                .map(|pool_data| {
+                    let options = self.pool_options(
+                        self.classify_fee_tier(pool_data.fee, pool_data.tick_spacing),
+                    );
+                    (pool_data, options)
+                })
+                .filter(|(pool_data, options)| {
+                    options.tier.non_standard
+                        || self
+                            .verify_pool_address(
+                                self.factory_address,
+                                pool_data.pool_address,
+                                pool_data.token0,
+                                pool_data.token1,
+                                options.tier.fee,
+                            )
+                            .inspect_err(|e| tracing::warn!("Skipping discovered V3 pool: {:?}", e))
+                            .is_ok()
+                })
+                .collect();
+
+            stream::iter(classified_pools)
+                .for_each_concurrent(CONCURRENT_BUILDS, |(pool_data, options)| {
+                    let build_context = build_context.clone();
                     let new_pools = new_pools_in_chunk.clone();
+                    #[cfg(feature = "db")]
+                    let db_manager = db_manager_clone.clone();
 
                     async move {
                         if let Ok(pool) = build_and_register_v3_pool(
-                            pool_registry,
-                            token_manager,
-                            provider,
-                            liquidity_snapshot,
+                            &build_context,
                             pool_data.pool_address,
                             pool_data.token0,
                             pool_data.token1,
-                            pool_data.fee,
-                            pool_data.tick_spacing,
+                            options,
                         )
                         .await
                         {
+                            #[cfg(feature = "db")]
+                            if let Some(db_manager) = &db_manager
+                                && let Err(e) = db_manager
+                                    .save_pool(
+                                        pool.address(),
+                                        pool_kind,
+                                        &pool.get_all_tokens(),
+                                        Some(options.tier.fee),
+                                        Some(options.tier.tick_spacing),
+                                    )
+                                    .await
+                            {
+                                tracing::warn!(pool = ?pool.address(), "Failed to store V3 pool: {:?}", e);
+                            }
                             let mut new_pools_guard = new_pools.lock().await;
                             new_pools_guard.push(pool);
                         }
@@ -160,10 +754,15 @@ impl<P: Provider + Send + Sync + 'static + ?Sized> UniswapV3PoolManager<P> {
             let new_pools = Arc::try_unwrap(new_pools_in_chunk).unwrap().into_inner();
             all_new_pools.extend(new_pools);
 
-            from_block = to_block + 1;
+            self.record_discovery_progress(chunk.to_block).await;
+        }
+        if scan.is_cancelled() {
+            tracing::info!(
+                last_block = self.last_discovery_block,
+                "[V3 Manager] Discovery cancelled"
+            );
         }
 
-        self.last_discovery_block = end_block;
         Ok(all_new_pools)
     }
 
@@ -173,22 +772,70 @@ impl<P: Provider + Send + Sync + 'static + ?Sized> UniswapV3PoolManager<P> {
             .map(|entry| entry.value().clone())
             .collect()
     }
+
+    fn build_context(&self) -> V3BuildContext<P> {
+        V3BuildContext {
+            pool_registry: self.pool_registry.clone(),
+            token_manager: self.token_manager.clone(),
+            provider: self.provider.clone(),
+            liquidity_snapshot: self.liquidity_snapshot.clone(),
+        }
+    }
+
+    fn pool_options(&self, tier: ResolvedTier) -> V3PoolOptions {
+        V3PoolOptions {
+            tier,
+            tick_lens: self.tick_lens,
+            max_swap_words: self.max_swap_words,
+            cache_config: self.cache_config,
+        }
+    }
 }
 
-async fn build_and_register_v3_pool<P: Provider + Send + Sync + 'static + ?Sized>(
+/// What building a pool needs from its manager, cloned into each concurrent build.
+struct V3BuildContext<P: Provider + Send + Sync + 'static + ?Sized> {
     pool_registry: Arc<PoolRegistry<P>>,
     token_manager: Arc<TokenManager<P>>,
     provider: Arc<P>,
     liquidity_snapshot: Arc<RwLock<UniswapV3LiquiditySnapshot<P>>>,
+}
+
+impl<P: Provider + Send + Sync + 'static + ?Sized> Clone for V3BuildContext<P> {
+    fn clone(&self) -> Self {
+        Self {
+            pool_registry: self.pool_registry.clone(),
+            token_manager: self.token_manager.clone(),
+            provider: self.provider.clone(),
+            liquidity_snapshot: self.liquidity_snapshot.clone(),
+        }
+    }
+}
+
+async fn build_and_register_v3_pool<P: Provider + Send + Sync + 'static + ?Sized>(
+    context: &V3BuildContext<P>,
     pool_address: Address,
     token_a: Address,
     token_b: Address,
-    fee: u32,
-    tick_spacing: i32,
+    options: V3PoolOptions,
 ) -> Result<Arc<dyn LiquidityPool<P>>, ArbRsError> {
+    let V3BuildContext {
+        pool_registry,
+        token_manager,
+        provider,
+        liquidity_snapshot,
+    } = context;
+    let V3PoolOptions {
+        tier,
+        tick_lens,
+        max_swap_words,
+        cache_config,
+    } = options;
     if let Some(pool) = pool_registry.get(&pool_address) {
         return Ok(pool.clone());
     }
+    token_manager
+        .token_policy()
+        .check_pool(pool_address, [token_a, token_b])?;
 
     let initial_liquidity_map = {
         let snapshot = liquidity_snapshot.read().await;
@@ -201,16 +848,28 @@ async fn build_and_register_v3_pool<P: Provider + Send + Sync + 'static + ?Sized
     let token1 = token_manager
         .get_token(if token_a < token_b { token_b } else { token_a })
         .await?;
+    token_manager
+        .analyze_pool_tokens(pool_address, &[token0.clone(), token1.clone()])
+        .await;
 
-    let pool = Arc::new(UniswapV3Pool::new(
+    let mut pool = UniswapV3Pool::new(
         pool_address,
         token0,
         token1,
-        fee,
-        tick_spacing,
-        provider,
+        tier.fee,
+        tier.tick_spacing,
+        provider.clone(),
         initial_liquidity_map,
-    ));
+    )
+    .with_non_standard_tier(tier.non_standard)
+    .with_cache_config(cache_config);
+    if let Some(tick_lens) = tick_lens {
+        pool = pool.with_tick_lens(tick_lens);
+    }
+    if let Some(max_swap_words) = max_swap_words {
+        pool = pool.with_max_swap_words(max_swap_words);
+    }
+    let pool = Arc::new(pool);
 
     let pending_updates = {
         let mut snapshot = liquidity_snapshot.write().await;
@@ -224,3 +883,22 @@ async fn build_and_register_v3_pool<P: Provider + Send + Sync + 'static + ?Sized
     pool_registry.insert(pool_address, pool.clone());
     Ok(pool)
 }
+
+/// Reads a pool's own fee and tick spacing.
+async fn fetch_fee_tier<P: Provider + Send + Sync + 'static + ?Sized>(
+    provider: &P,
+    pool_address: Address,
+) -> Result<(u32, i32), ArbRsError> {
+    let request = |input: Vec<u8>| {
+        TransactionRequest::default()
+            .to(pool_address)
+            .input(input.into())
+    };
+    let (fee_res, tick_spacing_res) = tokio::join!(
+        provider.call(request(feeCall {}.abi_encode())),
+        provider.call(request(tickSpacingCall {}.abi_encode())),
+    );
+    let fee = feeCall::abi_decode_returns(&fee_res?)?;
+    let tick_spacing = tickSpacingCall::abi_decode_returns(&tick_spacing_res?)?;
+    Ok((fee.to(), tick_spacing.as_i32()))
+}
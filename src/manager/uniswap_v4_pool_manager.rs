@@ -0,0 +1,509 @@
+use crate::chain::ChainConfig;
+use crate::core::token::WETH_ADDRESS;
+#[cfg(feature = "db")]
+use crate::db::{DbManager, PoolRecord};
+#[cfg(feature = "db")]
+use crate::dex::PoolKind;
+use crate::errors::ArbRsError;
+use crate::manager::log_scan::{LogScanConfig, chunked_log_scan};
+#[cfg(feature = "db")]
+use crate::manager::log_scan::{flush_discovery_block, load_discovery_block, save_discovery_block};
+use crate::manager::pool_discovery::{decode_v4_pools, v4_initialize_filter};
+use crate::manager::token_manager::TokenManager;
+use crate::pool::cache::CacheConfig;
+use crate::pool::uniswap_v4::{
+    PoolKey, UNISWAP_V4_POOL_MANAGER, UNISWAP_V4_STATE_VIEW, UniswapV4Pool, v4_pool_address,
+};
+use crate::pool::{DexKind, LiquidityPool};
+use alloy_primitives::Address;
+use alloy_provider::Provider;
+use dashmap::DashMap;
+use futures::{StreamExt, stream};
+use std::sync::Arc;
+#[cfg(feature = "db")]
+use std::sync::atomic::{AtomicUsize, Ordering};
+use tokio_util::sync::CancellationToken;
+
+type PoolRegistry<P> = DashMap<Address, Arc<dyn LiquidityPool<P>>>;
+type SkippedPools = DashMap<Address, String>;
+
+/// Highest LP fee a pool without hooks can be initialized with; anything above is the
+/// dynamic fee flag, which only hooks can act on.
+const MAX_LP_FEE: u32 = 1_000_000;
+
+/// Manages the hook-less pools of a Uniswap V4 pool manager. Pools are known by the address
+/// their id derives, see [`v4_pool_address`].
+pub struct UniswapV4PoolManager<P: Provider + Send + Sync + 'static + ?Sized> {
+    token_manager: Arc<TokenManager<P>>,
+    pool_registry: Arc<PoolRegistry<P>>,
+    provider: Arc<P>,
+    /// Pool manager discovery follows. `None` on chains without V4, leaving discovery off.
+    pool_manager: Option<Address>,
+    state_view: Option<Address>,
+    /// Stands in for native ether, which V4 pools trade as `Address::ZERO`.
+    wrapped_native: Address,
+    #[cfg(feature = "db")]
+    db_manager: Option<Arc<DbManager>>,
+    /// Where the next liquidity map save continues from.
+    #[cfg(feature = "db")]
+    map_cursor: AtomicUsize,
+    pub last_discovery_block: u64,
+    log_scan: LogScanConfig,
+    /// Bitmap words the pools it builds search per swap, see
+    /// [`UniswapV4Pool::with_max_swap_words`].
+    max_swap_words: Option<u32>,
+    /// Retention of the state caches of the pools it builds.
+    cache_config: CacheConfig,
+    /// Static managers only serve the pools they were given and never discover.
+    is_static: bool,
+    /// Stops discovery between chunks, with the progress of every scanned chunk recorded.
+    cancel: CancellationToken,
+    /// Discovered pools that weren't registered, with the reason: pools with hooks, and
+    /// pools that can't be modelled or hold a refused token.
+    skipped_pools: Arc<SkippedPools>,
+}
+
+impl<P: Provider + Send + Sync + 'static + ?Sized> UniswapV4PoolManager<P> {
+    /// A manager following the mainnet pool manager from `start_block`.
+    pub fn new(token_manager: Arc<TokenManager<P>>, provider: Arc<P>, start_block: u64) -> Self {
+        Self {
+            token_manager,
+            pool_registry: Arc::new(DashMap::new()),
+            provider,
+            pool_manager: Some(UNISWAP_V4_POOL_MANAGER),
+            state_view: Some(UNISWAP_V4_STATE_VIEW),
+            wrapped_native: WETH_ADDRESS,
+            #[cfg(feature = "db")]
+            db_manager: None,
+            #[cfg(feature = "db")]
+            map_cursor: AtomicUsize::new(0),
+            last_discovery_block: start_block,
+            log_scan: LogScanConfig::default(),
+            max_swap_words: None,
+            cache_config: CacheConfig::default(),
+            is_static: false,
+            cancel: CancellationToken::new(),
+            skipped_pools: Arc::new(DashMap::new()),
+        }
+    }
+
+    /// A manager over a fixed set of pools, for embedding the quoting core without
+    /// discovery.
+    pub fn new_static(
+        token_manager: Arc<TokenManager<P>>,
+        provider: Arc<P>,
+        pools: impl IntoIterator<Item = Arc<dyn LiquidityPool<P>>>,
+    ) -> Self {
+        let manager = Self {
+            is_static: true,
+            ..Self::new(token_manager, provider, 0)
+        };
+        for pool in pools {
+            manager.pool_registry.insert(pool.address(), pool);
+        }
+        manager
+    }
+
+    pub fn is_static(&self) -> bool {
+        self.is_static
+    }
+
+    /// Follows the pool manager of `chain` and lists its native ether as its wrapped native
+    /// token, or discovers nothing on a chain without V4.
+    pub fn with_chain(mut self, chain: &ChainConfig) -> Self {
+        self.pool_manager = chain.v4_pool_manager;
+        self.state_view = chain.v4_state_view;
+        self.wrapped_native = chain.wrapped_native;
+        self
+    }
+
+    /// Lets pools and their keys be stored, and liquidity maps be stored and restored.
+    #[cfg(feature = "db")]
+    pub fn with_db_manager(mut self, db_manager: Arc<DbManager>) -> Self {
+        self.db_manager = Some(db_manager);
+        self
+    }
+
+    /// Sets how discovery splits and retries its `getLogs` calls.
+    pub fn with_log_scan(mut self, log_scan: LogScanConfig) -> Self {
+        self.log_scan = log_scan;
+        self
+    }
+
+    /// Stops discovery at the next chunk once `cancel` is cancelled.
+    pub fn with_cancellation(mut self, cancel: CancellationToken) -> Self {
+        self.cancel = cancel;
+        self
+    }
+
+    /// Has the pools it builds from now on search at most `max_swap_words` bitmap words per
+    /// swap.
+    pub fn with_max_swap_words(mut self, max_swap_words: u32) -> Self {
+        self.max_swap_words = Some(max_swap_words);
+        self
+    }
+
+    /// Bounds the state caches of the pools it builds from now on.
+    pub fn with_cache_config(mut self, cache_config: CacheConfig) -> Self {
+        self.cache_config = cache_config;
+        self
+    }
+
+    pub fn pool_manager(&self) -> Option<Address> {
+        self.pool_manager
+    }
+
+    /// Continues discovery from the last block a previous run stored, if it is further
+    /// along. Returns the block discovery continues after.
+    #[cfg(feature = "db")]
+    pub async fn resume_discovery(&mut self) -> Result<u64, ArbRsError> {
+        if let Some(db_manager) = &self.db_manager
+            && let Some(pool_manager) = self.pool_manager
+            && let Some(block) =
+                load_discovery_block(db_manager, "uniswap v4", pool_manager).await?
+        {
+            self.last_discovery_block = self.last_discovery_block.max(block);
+        }
+        Ok(self.last_discovery_block)
+    }
+
+    /// Records that discovery scanned every block up to `block`.
+    async fn record_discovery_progress(&mut self, block: u64) {
+        self.last_discovery_block = block;
+        #[cfg(feature = "db")]
+        if let Some(db_manager) = &self.db_manager
+            && let Some(pool_manager) = self.pool_manager
+            && let Err(e) =
+                save_discovery_block(db_manager, "uniswap v4", pool_manager, block).await
+        {
+            tracing::warn!(block, "Failed to store V4 discovery progress: {:?}", e);
+        }
+    }
+
+    /// Flushes the last block discovery scanned, for the next run to resume after. Call once
+    /// discovery has stopped.
+    pub async fn shutdown(&self) -> Result<(), ArbRsError> {
+        #[cfg(feature = "db")]
+        if let Some(db_manager) = &self.db_manager
+            && let Some(pool_manager) = self.pool_manager
+            && !self.is_static
+        {
+            flush_discovery_block(
+                db_manager,
+                "uniswap v4",
+                pool_manager,
+                self.last_discovery_block,
+            )
+            .await?;
+        }
+        Ok(())
+    }
+
+    /// Builds the pool of `key` and reads its liquidity map, restored from the database
+    /// and refreshed if one was stored, in full otherwise. Pools with hooks, whose swaps
+    /// the hooks may change, are refused with `InvalidPool`.
+    pub async fn build_pool(&self, key: PoolKey) -> Result<Arc<dyn LiquidityPool<P>>, ArbRsError> {
+        let address = v4_pool_address(key.pool_id());
+        if let Some(pool) = self.pool_registry.get(&address) {
+            return Ok(pool.clone());
+        }
+        let (Some(pool_manager), Some(state_view)) = (self.pool_manager, self.state_view) else {
+            return Err(ArbRsError::ConfigError(
+                "No Uniswap V4 pool manager and state view configured".to_string(),
+            ));
+        };
+        if key.has_hooks() {
+            return Err(ArbRsError::InvalidPool(
+                address,
+                format!("hooks {}", key.hooks),
+            ));
+        }
+        if key.fee > MAX_LP_FEE {
+            return Err(ArbRsError::InvalidPool(
+                address,
+                format!("unsupported fee {:#x}", key.fee),
+            ));
+        }
+        // The native token is listed as the wrapped one, which can't be both sides.
+        let currency0 = if key.is_native() {
+            self.wrapped_native
+        } else {
+            key.currency0
+        };
+        if currency0 == key.currency1 {
+            return Err(ArbRsError::InvalidPool(
+                address,
+                "trades ether against its wrapped token".to_string(),
+            ));
+        }
+        self.token_manager
+            .token_policy()
+            .check_pool(address, [currency0, key.currency1])?;
+
+        let token0 = self.token_manager.get_token(currency0).await?;
+        let token1 = self.token_manager.get_token(key.currency1).await?;
+        self.token_manager
+            .analyze_pool_tokens(address, &[token0.clone(), token1.clone()])
+            .await;
+
+        let mut pool = UniswapV4Pool::new(
+            key,
+            pool_manager,
+            state_view,
+            token0,
+            token1,
+            self.provider.clone(),
+        )
+        .with_cache_config(self.cache_config);
+        if let Some(max_swap_words) = self.max_swap_words {
+            pool = pool.with_max_swap_words(max_swap_words);
+        }
+        self.load_liquidity_map(&pool).await?;
+
+        let pool: Arc<dyn LiquidityPool<P>> = Arc::new(pool);
+        self.pool_registry.insert(address, pool.clone());
+        Ok(pool)
+    }
+
+    /// Restores a stored liquidity map if there is one, and brings it up to the latest
+    /// block.
+    async fn load_liquidity_map(&self, pool: &UniswapV4Pool<P>) -> Result<(), ArbRsError> {
+        #[cfg(feature = "db")]
+        if let Some(db_manager) = &self.db_manager {
+            match db_manager.load_liquidity_map(pool.address()).await {
+                Ok(Some((map_block, map))) => pool.set_liquidity_map(map, map_block).await,
+                Ok(None) => {}
+                Err(e) => tracing::warn!(
+                    pool = ?pool.address(),
+                    "Failed to load liquidity map: {:?}",
+                    e
+                ),
+            }
+        }
+        let latest_block = self.provider.get_block_number().await?;
+        let words = pool.refresh_liquidity_map(latest_block).await?;
+        tracing::debug!(pool = ?pool.address(), words, "Read V4 liquidity map.");
+        Ok(())
+    }
+
+    /// Hydrates a pool from a record stored by discovery, checking its address against the
+    /// id its key hashes to.
+    #[cfg(feature = "db")]
+    pub async fn build_pool_from_record(
+        &self,
+        record: &PoolRecord,
+    ) -> Result<Arc<dyn LiquidityPool<P>>, ArbRsError> {
+        let Some(key) = record.v4_pool_key else {
+            return Err(ArbRsError::InvalidPool(
+                record.address,
+                "missing pool key".to_string(),
+            ));
+        };
+        if record.v4_pool_manager != self.pool_manager {
+            return Err(ArbRsError::InvalidPool(
+                record.address,
+                format!(
+                    "stored for pool manager {:?}, not {:?}",
+                    record.v4_pool_manager, self.pool_manager
+                ),
+            ));
+        }
+        let derived = v4_pool_address(key.pool_id());
+        if derived != record.address {
+            return Err(ArbRsError::InvalidPool(
+                record.address,
+                format!("pool key hashes to {derived}"),
+            ));
+        }
+        self.build_pool(key).await
+    }
+
+    /// Stores a discovered pool and its key, for the next run to hydrate.
+    #[cfg(feature = "db")]
+    async fn save_pool(&self, pool: &Arc<dyn LiquidityPool<P>>, key: &PoolKey) {
+        let (Some(db_manager), Some(pool_manager)) = (&self.db_manager, self.pool_manager) else {
+            return;
+        };
+        let saved = async {
+            db_manager
+                .save_pool(
+                    pool.address(),
+                    &PoolKind::UniswapV4,
+                    &pool.get_all_tokens(),
+                    Some(key.fee),
+                    Some(key.tick_spacing),
+                )
+                .await?;
+            db_manager
+                .update_v4_pool_key(pool.address(), pool_manager, key)
+                .await
+        };
+        if let Err(e) = saved.await {
+            tracing::warn!(pool = ?pool.address(), "Failed to store V4 pool: {:?}", e);
+        }
+    }
+
+    /// Refreshes the liquidity maps of the next `pools_per_sweep` pools, in address order, to
+    /// `block_number` and stores them for the next run. Returns the number stored.
+    #[cfg(feature = "db")]
+    pub async fn save_liquidity_maps(&self, block_number: u64, pools_per_sweep: usize) -> usize {
+        let Some(db_manager) = &self.db_manager else {
+            return 0;
+        };
+        let mut pools = self.get_all_pools();
+        if pools.is_empty() {
+            return 0;
+        }
+        pools.sort_by_key(|pool| pool.address());
+        let start = self
+            .map_cursor
+            .fetch_add(pools_per_sweep, Ordering::Relaxed)
+            % pools.len();
+
+        let mut saved = 0;
+        for pool in pools
+            .iter()
+            .cycle()
+            .skip(start)
+            .take(pools_per_sweep.min(pools.len()))
+        {
+            let Some(v4_pool) = pool.as_any().downcast_ref::<UniswapV4Pool<P>>() else {
+                continue;
+            };
+            let pool_address = pool.address();
+            if let Err(e) = v4_pool.refresh_liquidity_map(block_number).await {
+                tracing::debug!(?pool_address, "Failed to refresh liquidity map: {:?}", e);
+                continue;
+            }
+            let (Some(map_block), map) = v4_pool.liquidity_map().await else {
+                continue;
+            };
+            match db_manager
+                .save_liquidity_map(pool_address, v4_pool.tick_spacing(), map_block, &map)
+                .await
+            {
+                Ok(()) => saved += 1,
+                Err(e) => tracing::warn!(?pool_address, "Failed to save liquidity map: {:?}", e),
+            }
+        }
+        saved
+    }
+
+    /// Discovers pools from the pool manager's `Initialize` events up to `end_block`. Pools
+    /// with hooks aren't built, and are listed by `skipped_pools` with those that fail to
+    /// build for a reason of their own.
+    pub async fn discover_pools_in_range(
+        &mut self,
+        end_block: u64,
+    ) -> Result<Vec<Arc<dyn LiquidityPool<P>>>, ArbRsError> {
+        let Some(pool_manager) = self.pool_manager else {
+            return Ok(Vec::new());
+        };
+        if self.is_static || end_block <= self.last_discovery_block {
+            return Ok(Vec::new());
+        }
+
+        let mut scan = chunked_log_scan(
+            v4_initialize_filter(pool_manager),
+            self.last_discovery_block + 1,
+            end_block,
+            self.log_scan,
+        )
+        .with_cancellation(self.cancel.clone());
+        let mut all_new_pools = Vec::new();
+
+        while let Some(chunk) = scan.next_chunk(self.provider.as_ref()).await? {
+            let discovered_pools = decode_v4_pools(&chunk.logs)?;
+            tracing::info!(
+                from_block = chunk.from_block,
+                to_block = chunk.to_block,
+                pools = discovered_pools.len(),
+                "[V4 Manager] Discovering pools"
+            );
+            crate::metrics::record_discovery_events(Some(DexKind::UniswapV4), chunk.logs.len());
+
+            const CONCURRENT_BUILDS: usize = 5;
+            let mut hookless = Vec::new();
+            for discovered in discovered_pools {
+                let address = v4_pool_address(discovered.pool_id);
+                if discovered.key.has_hooks() {
+                    tracing::debug!(
+                        ?address,
+                        hooks = ?discovered.key.hooks,
+                        "Skipping V4 pool with hooks"
+                    );
+                    self.skipped_pools
+                        .insert(address, format!("hooks {}", discovered.key.hooks));
+                } else {
+                    hookless.push(discovered.key);
+                }
+            }
+
+            let this = &*self;
+            let new_pools: Vec<_> = stream::iter(hookless)
+                .map(|key| async move {
+                    let address = v4_pool_address(key.pool_id());
+                    match this.build_pool(key).await {
+                        Ok(pool) => {
+                            #[cfg(feature = "db")]
+                            this.save_pool(&pool, &key).await;
+                            Some(pool)
+                        }
+                        Err(ArbRsError::InvalidPool(address, reason)) => {
+                            tracing::debug!(?address, "Skipping V4 pool: {}", reason);
+                            this.skipped_pools.insert(address, reason);
+                            None
+                        }
+                        Err(
+                            e @ (ArbRsError::TokenNotAllowed { .. } | ArbRsError::TokenUnusable(_)),
+                        ) => {
+                            tracing::debug!("Skipping V4 pool: {}", e);
+                            this.skipped_pools.insert(address, e.to_string());
+                            None
+                        }
+                        Err(e) => {
+                            tracing::warn!(
+                                "Failed to build discovered V4 pool {}: {:?}",
+                                address,
+                                e
+                            );
+                            None
+                        }
+                    }
+                })
+                .buffer_unordered(CONCURRENT_BUILDS)
+                .filter_map(|pool| async move { pool })
+                .collect()
+                .await;
+            all_new_pools.extend(new_pools);
+
+            self.record_discovery_progress(chunk.to_block).await;
+        }
+        if scan.is_cancelled() {
+            tracing::info!(
+                last_block = self.last_discovery_block,
+                "[V4 Manager] Discovery cancelled"
+            );
+        }
+
+        Ok(all_new_pools)
+    }
+
+    /// Pools discovery skipped, with the reason, ordered by address.
+    pub fn skipped_pools(&self) -> Vec<(Address, String)> {
+        let mut skipped: Vec<_> = self
+            .skipped_pools
+            .iter()
+            .map(|entry| (*entry.key(), entry.value().clone()))
+            .collect();
+        skipped.sort();
+        skipped
+    }
+
+    pub fn get_all_pools(&self) -> Vec<Arc<dyn LiquidityPool<P>>> {
+        self.pool_registry
+            .iter()
+            .map(|entry| entry.value().clone())
+            .collect()
+    }
+}
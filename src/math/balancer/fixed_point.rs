@@ -91,8 +91,6 @@ pub fn complement(x: U256) -> U256 {
 }
 
 pub fn pow_down(x: U256, y: U256) -> Result<U256, ArbRsError> {
-    if y.is_zero() { return Ok(ONE); }
-    if x == ONE { return Ok(ONE); }
     if y == ONE { return Ok(x); }
     if y == TWO { return mul_down(x, x); }
     if y == FOUR {
@@ -108,8 +106,6 @@ pub fn pow_down(x: U256, y: U256) -> Result<U256, ArbRsError> {
 }
 
 pub fn pow_up(x: U256, y: U256) -> Result<U256, ArbRsError> {
-    if y.is_zero() { return Ok(ONE); }
-    if x == ONE { return Ok(ONE); }
     if y == ONE { return Ok(x); }
     if y == TWO { return mul_up(x, x); }
     if y == FOUR {
@@ -22,13 +22,15 @@ pub struct Tick {
     pub initialized: bool,
 }
 
+/// Returns the lowest tick divisible by `tick_spacing`.
 /// Uses integer arithmetic for ceiling division
-fn get_min_tick(tick_spacing: i32) -> i32 {
+pub fn get_min_tick(tick_spacing: i32) -> i32 {
     (MIN_TICK + tick_spacing - 1).div_euclid(tick_spacing) * tick_spacing
 }
 
+/// Returns the highest tick divisible by `tick_spacing`.
 /// Uses integer arithmetic for floor division
-fn get_max_tick(tick_spacing: i32) -> i32 {
+pub fn get_max_tick(tick_spacing: i32) -> i32 {
     (MAX_TICK).div_euclid(tick_spacing) * tick_spacing
 }
 
@@ -47,6 +49,18 @@ mod tests {
     use super::*;
     use std::str::FromStr;
 
+    #[test]
+    fn test_min_max_tick_for_spacing() {
+        assert_eq!(get_min_tick(1), MIN_TICK);
+        assert_eq!(get_max_tick(1), MAX_TICK);
+        assert_eq!(get_min_tick(10), -887270);
+        assert_eq!(get_max_tick(10), 887270);
+        assert_eq!(get_min_tick(60), -887220);
+        assert_eq!(get_max_tick(60), 887220);
+        assert_eq!(get_min_tick(200), -887200);
+        assert_eq!(get_max_tick(200), 887200);
+    }
+
     #[test]
     fn test_tick_spacing_to_max_liquidity_per_tick() {
         let tick_spacing_low = 10;
@@ -7,13 +7,24 @@ pub fn position(tick: i32) -> (i16, u8) {
     (word_pos, bit_pos)
 }
 
+/// `tick` in units of `tick_spacing`, rounded towards negative infinity as
+/// `TickBitmap.sol` does, so a negative tick between two spacings compresses to the lower.
+pub fn compress(tick: i32, tick_spacing: i32) -> i32 {
+    let compressed = tick / tick_spacing;
+    if tick < 0 && tick % tick_spacing != 0 {
+        compressed - 1
+    } else {
+        compressed
+    }
+}
+
 pub fn next_initialized_tick_within_one_word(
     bitmap: U256,
     tick: i32,
     tick_spacing: i32,
     lte: bool,
 ) -> Option<(i32, bool)> {
-    let compressed = tick / tick_spacing;
+    let compressed = compress(tick, tick_spacing);
 
     if lte {
         let (word_pos, bit_pos) = position(compressed);
@@ -126,4 +137,23 @@ mod tests {
         );
         assert_eq!(result, Some((78, true)));
     }
+
+    #[test]
+    fn test_negative_ticks_compress_downwards() {
+        assert_eq!(compress(-30, 60), -1);
+        assert_eq!(compress(-60, 60), -1);
+        assert_eq!(compress(-61, 60), -2);
+        assert_eq!(compress(30, 60), 0);
+
+        // Tick -30 compresses to the last bit of word -1, where tick -60 is initialized.
+        let mut bitmap = HashMap::new();
+        flip_tick(&mut bitmap, -1);
+        let result = next_initialized_tick_within_one_word(
+            bitmap.get(&-1).copied().unwrap_or_default(),
+            -30,
+            60,
+            true,
+        );
+        assert_eq!(result, Some((-60, true)));
+    }
 }
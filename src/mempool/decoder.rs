@@ -0,0 +1,327 @@
+use crate::errors::ArbRsError;
+use crate::pool::address::{
+    SUSHISWAP_INIT_CODE_HASH, UNISWAP_V2_INIT_CODE_HASH, UNISWAP_V3_INIT_CODE_HASH,
+    v2_pair_address, v3_pool_address,
+};
+use alloy_primitives::{Address, B256, U256, address};
+use alloy_sol_types::{SolCall, sol};
+use std::collections::HashMap;
+use std::fmt::{self, Debug};
+
+sol! {
+    // Uniswap V2 style routers
+    function swapExactTokensForTokens(uint256 amountIn, uint256 amountOutMin, address[] path, address to, uint256 deadline) external returns (uint256[] amounts);
+
+    // Uniswap V3 SwapRouter
+    struct ExactInputSingleParams {
+        address tokenIn;
+        address tokenOut;
+        uint24 fee;
+        address recipient;
+        uint256 deadline;
+        uint256 amountIn;
+        uint256 amountOutMinimum;
+        uint160 sqrtPriceLimitX96;
+    }
+    function exactInputSingle(ExactInputSingleParams params) external payable returns (uint256 amountOut);
+
+    // Curve stableswap pools
+    function exchange(int128 i, int128 j, uint256 dx, uint256 min_dy) external returns (uint256);
+
+    // Balancer V2 Vault
+    struct SingleSwap {
+        bytes32 poolId;
+        uint8 kind;
+        address assetIn;
+        address assetOut;
+        uint256 amount;
+        bytes userData;
+    }
+    struct FundManagement {
+        address sender;
+        bool fromInternalBalance;
+        address recipient;
+        bool toInternalBalance;
+    }
+    function swap(SingleSwap singleSwap, FundManagement funds, uint256 limit, uint256 deadline) external payable returns (uint256);
+}
+
+const UNISWAP_V2_ROUTER: Address = address!("7a250d5630B4cF539739dF2C5dAcb4c659F2488D");
+const UNISWAP_V2_FACTORY: Address = address!("5C69bEe701ef814a2B6a3EDD4B1652CB9cc5aA6f");
+const SUSHISWAP_ROUTER: Address = address!("d9e1cE17f2641f24aE83637ab66a2cca9C378B9F");
+const SUSHISWAP_FACTORY: Address = address!("C0AEe478e3658e2610c5F7A4A2E1777cE9e4f2Ac");
+const UNISWAP_V3_ROUTER: Address = address!("E592427A0AEce92De3Edee1F18E0157C05861564");
+const UNISWAP_V3_FACTORY: Address = address!("1F98431c8aD98523631AE4a59f267346ea31F984");
+const BALANCER_VAULT: Address = address!("BA12222222228d8Ba445958a75a0704d566BF2C8");
+
+/// Balancer's `SwapKind.GIVEN_IN`.
+const BALANCER_GIVEN_IN: u8 = 0;
+
+/// Identifies the tokens traded in a single hop.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SwapLeg {
+    Tokens {
+        token_in: Address,
+        token_out: Address,
+    },
+    /// Coin indices, as used by Curve's `exchange`.
+    Indices { i: usize, j: usize },
+}
+
+/// A single hop of a decoded pending swap.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DecodedHop {
+    pub pool: Address,
+    pub leg: SwapLeg,
+}
+
+/// An exact-input swap decoded from pending calldata.
+/// Hops are executed in order, each consuming the previous hop's output.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecodedSwap {
+    pub amount_in: U256,
+    pub hops: Vec<DecodedHop>,
+}
+
+/// Decodes calldata for one function selector into the swap it would perform.
+pub trait CalldataDecoder: Send + Sync {
+    fn selector(&self) -> [u8; 4];
+
+    /// Returns `Ok(None)` if the call is well formed but not something we can model,
+    /// e.g. it targets an unknown router or is an exact-output swap.
+    fn decode(&self, to: Address, input: &[u8]) -> Result<Option<DecodedSwap>, ArbRsError>;
+}
+
+/// Decodes `swapExactTokensForTokens` on Uniswap V2 style routers.
+#[derive(Debug, Clone, Default)]
+pub struct V2RouterDecoder {
+    /// Router address -> (factory, pair init code hash)
+    routers: HashMap<Address, (Address, B256)>,
+}
+
+impl V2RouterDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_router(mut self, router: Address, factory: Address, init_code_hash: B256) -> Self {
+        self.routers.insert(router, (factory, init_code_hash));
+        self
+    }
+}
+
+impl CalldataDecoder for V2RouterDecoder {
+    fn selector(&self) -> [u8; 4] {
+        swapExactTokensForTokensCall::SELECTOR
+    }
+
+    fn decode(&self, to: Address, input: &[u8]) -> Result<Option<DecodedSwap>, ArbRsError> {
+        let Some((factory, init_code_hash)) = self.routers.get(&to) else {
+            return Ok(None);
+        };
+        let call = swapExactTokensForTokensCall::abi_decode(input)?;
+        if call.path.len() < 2 {
+            return Ok(None);
+        }
+
+        let hops = call
+            .path
+            .windows(2)
+            .map(|pair| DecodedHop {
+                pool: v2_pair_address(pair[0], pair[1], *factory, *init_code_hash),
+                leg: SwapLeg::Tokens {
+                    token_in: pair[0],
+                    token_out: pair[1],
+                },
+            })
+            .collect();
+
+        Ok(Some(DecodedSwap {
+            amount_in: call.amountIn,
+            hops,
+        }))
+    }
+}
+
+/// Decodes `exactInputSingle` on the Uniswap V3 SwapRouter.
+#[derive(Debug, Clone, Default)]
+pub struct V3RouterDecoder {
+    /// Router address -> (factory, pool init code hash)
+    routers: HashMap<Address, (Address, B256)>,
+}
+
+impl V3RouterDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_router(mut self, router: Address, factory: Address, init_code_hash: B256) -> Self {
+        self.routers.insert(router, (factory, init_code_hash));
+        self
+    }
+}
+
+impl CalldataDecoder for V3RouterDecoder {
+    fn selector(&self) -> [u8; 4] {
+        exactInputSingleCall::SELECTOR
+    }
+
+    fn decode(&self, to: Address, input: &[u8]) -> Result<Option<DecodedSwap>, ArbRsError> {
+        let Some((factory, init_code_hash)) = self.routers.get(&to) else {
+            return Ok(None);
+        };
+        let params = exactInputSingleCall::abi_decode(input)?.params;
+
+        Ok(Some(DecodedSwap {
+            amount_in: params.amountIn,
+            hops: vec![DecodedHop {
+                pool: v3_pool_address(
+                    params.tokenIn,
+                    params.tokenOut,
+                    params.fee.to::<u32>(),
+                    *factory,
+                    *init_code_hash,
+                ),
+                leg: SwapLeg::Tokens {
+                    token_in: params.tokenIn,
+                    token_out: params.tokenOut,
+                },
+            }],
+        }))
+    }
+}
+
+/// Decodes `exchange(int128,int128,uint256,uint256)` sent directly to a Curve pool.
+#[derive(Debug, Clone, Default)]
+pub struct CurveExchangeDecoder;
+
+impl CalldataDecoder for CurveExchangeDecoder {
+    fn selector(&self) -> [u8; 4] {
+        exchangeCall::SELECTOR
+    }
+
+    fn decode(&self, to: Address, input: &[u8]) -> Result<Option<DecodedSwap>, ArbRsError> {
+        let call = exchangeCall::abi_decode(input)?;
+        let (Ok(i), Ok(j)) = (usize::try_from(call.i), usize::try_from(call.j)) else {
+            return Ok(None);
+        };
+
+        Ok(Some(DecodedSwap {
+            amount_in: call.dx,
+            hops: vec![DecodedHop {
+                pool: to,
+                leg: SwapLeg::Indices { i, j },
+            }],
+        }))
+    }
+}
+
+/// Decodes single swaps sent to the Balancer V2 Vault.
+#[derive(Debug, Clone)]
+pub struct BalancerVaultDecoder {
+    vault: Address,
+}
+
+impl BalancerVaultDecoder {
+    pub fn new(vault: Address) -> Self {
+        Self { vault }
+    }
+}
+
+impl Default for BalancerVaultDecoder {
+    fn default() -> Self {
+        Self::new(BALANCER_VAULT)
+    }
+}
+
+impl CalldataDecoder for BalancerVaultDecoder {
+    fn selector(&self) -> [u8; 4] {
+        swapCall::SELECTOR
+    }
+
+    fn decode(&self, to: Address, input: &[u8]) -> Result<Option<DecodedSwap>, ArbRsError> {
+        if to != self.vault {
+            return Ok(None);
+        }
+        let single_swap = swapCall::abi_decode(input)?.singleSwap;
+        if single_swap.kind != BALANCER_GIVEN_IN {
+            return Ok(None);
+        }
+
+        // The first 20 bytes of a Balancer pool id are the pool's address.
+        let pool = Address::from_slice(&single_swap.poolId[..20]);
+
+        Ok(Some(DecodedSwap {
+            amount_in: single_swap.amount,
+            hops: vec![DecodedHop {
+                pool,
+                leg: SwapLeg::Tokens {
+                    token_in: single_swap.assetIn,
+                    token_out: single_swap.assetOut,
+                },
+            }],
+        }))
+    }
+}
+
+/// A registry of calldata decoders keyed by function selector.
+#[derive(Default)]
+pub struct DecoderRegistry {
+    decoders: HashMap<[u8; 4], Box<dyn CalldataDecoder>>,
+}
+
+impl DecoderRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A registry with decoders for the common mainnet routers, Curve pools and the Balancer Vault.
+    pub fn mainnet() -> Self {
+        let mut registry = Self::new();
+        registry.register(Box::new(
+            V2RouterDecoder::new()
+                .with_router(
+                    UNISWAP_V2_ROUTER,
+                    UNISWAP_V2_FACTORY,
+                    UNISWAP_V2_INIT_CODE_HASH,
+                )
+                .with_router(
+                    SUSHISWAP_ROUTER,
+                    SUSHISWAP_FACTORY,
+                    SUSHISWAP_INIT_CODE_HASH,
+                ),
+        ));
+        registry.register(Box::new(V3RouterDecoder::new().with_router(
+            UNISWAP_V3_ROUTER,
+            UNISWAP_V3_FACTORY,
+            UNISWAP_V3_INIT_CODE_HASH,
+        )));
+        registry.register(Box::new(CurveExchangeDecoder));
+        registry.register(Box::new(BalancerVaultDecoder::default()));
+        registry
+    }
+
+    /// Registers a decoder, replacing any existing decoder for the same selector.
+    pub fn register(&mut self, decoder: Box<dyn CalldataDecoder>) {
+        self.decoders.insert(decoder.selector(), decoder);
+    }
+
+    /// Decodes a transaction's calldata. Unknown selectors yield `Ok(None)`.
+    pub fn decode(&self, to: Address, input: &[u8]) -> Result<Option<DecodedSwap>, ArbRsError> {
+        let Some(selector) = input.get(..4) else {
+            return Ok(None);
+        };
+        match self.decoders.get(selector) {
+            Some(decoder) => decoder.decode(to, input),
+            None => Ok(None),
+        }
+    }
+}
+
+impl Debug for DecoderRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DecoderRegistry")
+            .field("selectors", &self.decoders.len())
+            .finish()
+    }
+}
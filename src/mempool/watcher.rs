@@ -0,0 +1,381 @@
+use crate::arbitrage::engine::ArbitrageEngine;
+use crate::balancer::pool::BalancerPool;
+use crate::core::token::{Token, TokenLike};
+use crate::curve::pool::CurveStableswapPool;
+use crate::errors::ArbRsError;
+use crate::mempool::decoder::{DecodedSwap, DecoderRegistry, SwapLeg};
+use crate::pool::strategy::{
+    ConfigurableFeeStrategy, PancakeV2Logic, SolidlyStableStrategy, SolidlyVolatileStrategy,
+    StandardV2Logic,
+};
+use crate::pool::uniswap_v2::UniswapV2Pool;
+use crate::pool::uniswap_v3::UniswapV3Pool;
+use crate::pool::uniswap_v4::UniswapV4Pool;
+use crate::pool::{LiquidityPool, PoolSnapshot};
+use alloy_primitives::{Address, U256};
+use alloy_provider::Provider;
+use alloy_rpc_types::TransactionTrait;
+use futures::StreamExt;
+use std::collections::HashMap;
+use std::fmt::{self, Debug};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+/// A predicted post-swap snapshot for a pool touched by a pending transaction.
+pub type SnapshotOverride = (Address, PoolSnapshot);
+
+type TokenPair<P> = (Arc<Token<P>>, Arc<Token<P>>);
+
+#[derive(Debug, Clone)]
+pub struct MempoolConfig {
+    /// The watcher is only started when enabled. Many providers don't serve full pending
+    /// transaction bodies, so this is off by default.
+    pub enabled: bool,
+    /// Pending swaps through managed pools beyond this rate are dropped without being
+    /// simulated. Unrelated transactions don't count towards it.
+    pub max_txs_per_second: u32,
+}
+
+impl Default for MempoolConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_txs_per_second: 50,
+        }
+    }
+}
+
+/// A fixed one-second window rate limiter.
+#[derive(Debug)]
+struct RateLimiter {
+    max_per_window: u32,
+    window_start: Instant,
+    count: u32,
+}
+
+impl RateLimiter {
+    fn new(max_per_window: u32) -> Self {
+        Self {
+            max_per_window,
+            window_start: Instant::now(),
+            count: 0,
+        }
+    }
+
+    fn try_acquire(&mut self) -> bool {
+        if self.window_start.elapsed() >= Duration::from_secs(1) {
+            self.window_start = Instant::now();
+            self.count = 0;
+        }
+        if self.count >= self.max_per_window {
+            return false;
+        }
+        self.count += 1;
+        true
+    }
+}
+
+/// Watches pending transactions and predicts the snapshots of managed pools they will touch.
+pub struct MempoolWatcher<P: Provider + Send + Sync + 'static + ?Sized> {
+    provider: Arc<P>,
+    pools: HashMap<Address, Arc<dyn LiquidityPool<P>>>,
+    decoders: DecoderRegistry,
+    config: MempoolConfig,
+    limiter: Mutex<RateLimiter>,
+}
+
+impl<P: Provider + Send + Sync + 'static + ?Sized> MempoolWatcher<P> {
+    pub fn new(
+        provider: Arc<P>,
+        pools: HashMap<Address, Arc<dyn LiquidityPool<P>>>,
+        decoders: DecoderRegistry,
+        config: MempoolConfig,
+    ) -> Self {
+        let limiter = Mutex::new(RateLimiter::new(config.max_txs_per_second));
+        Self {
+            provider,
+            pools,
+            decoders,
+            config,
+            limiter,
+        }
+    }
+
+    /// Decodes a pending transaction, keeping it only if every hop goes through a managed pool.
+    pub fn match_pending(
+        &self,
+        to: Address,
+        input: &[u8],
+    ) -> Result<Option<DecodedSwap>, ArbRsError> {
+        Ok(self.decoders.decode(to, input)?.filter(|decoded| {
+            decoded
+                .hops
+                .iter()
+                .all(|hop| self.pools.contains_key(&hop.pool))
+        }))
+    }
+
+    /// Decodes a pending transaction and simulates it against the given snapshots.
+    ///
+    /// Transactions with unknown selectors, or that route through a pool we don't manage or
+    /// have no snapshot for, produce no overrides.
+    pub async fn predict_overrides(
+        &self,
+        to: Address,
+        input: &[u8],
+        snapshots: &HashMap<Address, PoolSnapshot>,
+    ) -> Result<Vec<SnapshotOverride>, ArbRsError> {
+        match self.match_pending(to, input)? {
+            Some(decoded) => self.simulate(&decoded, snapshots).await,
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// Like `predict_overrides`, but rate limited. Only transactions that touch managed pools
+    /// count towards `max_txs_per_second`; past it they are dropped without being simulated.
+    pub async fn process_pending(
+        &self,
+        to: Address,
+        input: &[u8],
+        snapshots: &HashMap<Address, PoolSnapshot>,
+    ) -> Result<Vec<SnapshotOverride>, ArbRsError> {
+        let Some(decoded) = self.match_pending(to, input)? else {
+            return Ok(Vec::new());
+        };
+        let acquired = self
+            .limiter
+            .lock()
+            .map(|mut limiter| limiter.try_acquire())
+            .unwrap_or(false);
+        if !acquired {
+            return Ok(Vec::new());
+        }
+        self.simulate(&decoded, snapshots).await
+    }
+
+    async fn simulate(
+        &self,
+        decoded: &DecodedSwap,
+        snapshots: &HashMap<Address, PoolSnapshot>,
+    ) -> Result<Vec<SnapshotOverride>, ArbRsError> {
+        if decoded
+            .hops
+            .iter()
+            .any(|hop| !snapshots.contains_key(&hop.pool))
+        {
+            return Ok(Vec::new());
+        }
+
+        let mut overrides: Vec<SnapshotOverride> = Vec::with_capacity(decoded.hops.len());
+        let mut amount_in = decoded.amount_in;
+
+        for hop in &decoded.hops {
+            let pool = &self.pools[&hop.pool];
+            // A route may pass through the same pool twice; chain on the predicted state.
+            let snapshot = overrides
+                .iter()
+                .rev()
+                .find(|(addr, _)| *addr == hop.pool)
+                .map(|(_, predicted)| predicted)
+                .unwrap_or(&snapshots[&hop.pool]);
+
+            let (token_in, token_out) = resolve_leg(pool.as_ref(), hop.leg)?;
+            let (amount_out, predicted) =
+                simulate_hop(pool.as_ref(), &token_in, &token_out, amount_in, snapshot).await?;
+
+            overrides.push((hop.pool, predicted));
+            amount_in = amount_out;
+        }
+
+        Ok(overrides)
+    }
+
+    /// Starts the watcher in the background, sending predicted overrides into `sender`.
+    /// Pending swaps are simulated against the snapshots of the engine's latest evaluation.
+    /// Returns `None` if the watcher is disabled in its config.
+    pub fn spawn(
+        self: Arc<Self>,
+        engine: Arc<ArbitrageEngine<P>>,
+        sender: mpsc::Sender<SnapshotOverride>,
+    ) -> Option<JoinHandle<()>> {
+        if !self.config.enabled {
+            return None;
+        }
+
+        Some(tokio::spawn(async move {
+            let subscription = match self.provider.subscribe_full_pending_transactions().await {
+                Ok(subscription) => subscription,
+                Err(e) => {
+                    tracing::warn!("Pending transaction subscription unavailable: {:?}", e);
+                    return;
+                }
+            };
+            let mut stream = subscription.into_stream();
+
+            while let Some(tx) = stream.next().await {
+                let Some(to) = tx.to() else {
+                    continue;
+                };
+
+                let snapshots = engine.last_snapshots();
+                match self.process_pending(to, tx.input(), &snapshots).await {
+                    Ok(overrides) => {
+                        for predicted in overrides {
+                            if sender.send(predicted).await.is_err() {
+                                return;
+                            }
+                        }
+                    }
+                    Err(e) => {
+                        tracing::debug!(?to, "Failed to simulate pending transaction: {:?}", e)
+                    }
+                }
+            }
+        }))
+    }
+}
+
+/// Drains all overrides currently queued in `receiver`, keeping the latest prediction per pool.
+pub fn collect_overrides(
+    receiver: &mut mpsc::Receiver<SnapshotOverride>,
+) -> HashMap<Address, PoolSnapshot> {
+    let mut overrides = HashMap::new();
+    while let Ok((pool, snapshot)) = receiver.try_recv() {
+        overrides.insert(pool, snapshot);
+    }
+    overrides
+}
+
+fn resolve_leg<P: Provider + Send + Sync + 'static + ?Sized>(
+    pool: &dyn LiquidityPool<P>,
+    leg: SwapLeg,
+) -> Result<TokenPair<P>, ArbRsError> {
+    let tokens = pool.get_all_tokens();
+    let (token_in, token_out) = match leg {
+        SwapLeg::Tokens {
+            token_in,
+            token_out,
+        } => (
+            tokens.iter().find(|t| t.address() == token_in),
+            tokens.iter().find(|t| t.address() == token_out),
+        ),
+        SwapLeg::Indices { i, j } => (tokens.get(i), tokens.get(j)),
+    };
+    match (token_in, token_out) {
+        (Some(token_in), Some(token_out)) => Ok((token_in.clone(), token_out.clone())),
+        _ => Err(ArbRsError::CalculationError(format!(
+            "Pending swap leg {:?} does not match pool {}",
+            leg,
+            pool.address()
+        ))),
+    }
+}
+
+/// Applies an exact-input swap to a snapshot, returning the output amount and the
+/// predicted snapshot afterwards.
+async fn simulate_hop<P: Provider + Send + Sync + 'static + ?Sized>(
+    pool: &dyn LiquidityPool<P>,
+    token_in: &Token<P>,
+    token_out: &Token<P>,
+    amount_in: U256,
+    snapshot: &PoolSnapshot,
+) -> Result<(U256, PoolSnapshot), ArbRsError> {
+    match snapshot {
+        PoolSnapshot::UniswapV2(state) => {
+            let any = pool.as_any();
+            let result = if let Some(v2) = any.downcast_ref::<UniswapV2Pool<P, StandardV2Logic>>() {
+                v2.simulate_exact_input_swap(token_in, token_out, amount_in, Some(state))
+                    .await?
+            } else if let Some(v2) = any.downcast_ref::<UniswapV2Pool<P, PancakeV2Logic>>() {
+                v2.simulate_exact_input_swap(token_in, token_out, amount_in, Some(state))
+                    .await?
+            } else if let Some(v2) = any.downcast_ref::<UniswapV2Pool<P, ConfigurableFeeStrategy>>()
+            {
+                v2.simulate_exact_input_swap(token_in, token_out, amount_in, Some(state))
+                    .await?
+            } else if let Some(v2) = any.downcast_ref::<UniswapV2Pool<P, SolidlyStableStrategy>>() {
+                v2.simulate_exact_input_swap(token_in, token_out, amount_in, Some(state))
+                    .await?
+            } else if let Some(v2) = any.downcast_ref::<UniswapV2Pool<P, SolidlyVolatileStrategy>>()
+            {
+                v2.simulate_exact_input_swap(token_in, token_out, amount_in, Some(state))
+                    .await?
+            } else {
+                return Err(ArbRsError::CalculationError(
+                    "Unsupported V2 pool strategy for pending swap simulation".into(),
+                ));
+            };
+            let amount_out = if token_in.address() == pool.get_all_tokens()[0].address() {
+                (-result.amount1_delta).into_raw()
+            } else {
+                (-result.amount0_delta).into_raw()
+            };
+            Ok((amount_out, PoolSnapshot::UniswapV2(result.final_state)))
+        }
+        PoolSnapshot::UniswapV3(v3_snapshot) => {
+            let v3 = pool
+                .as_any()
+                .downcast_ref::<UniswapV3Pool<P>>()
+                .ok_or_else(|| ArbRsError::CalculationError("Expected a V3 pool".into()))?;
+            let result =
+                v3.simulate_exact_input_swap(token_in, token_out, amount_in, v3_snapshot)?;
+            let amount_out = if token_in.address() == pool.get_all_tokens()[0].address() {
+                (-result.amount1_delta).into_raw()
+            } else {
+                (-result.amount0_delta).into_raw()
+            };
+            Ok((
+                amount_out,
+                PoolSnapshot::UniswapV3(result.final_state.into()),
+            ))
+        }
+        PoolSnapshot::UniswapV4(v4_snapshot) => {
+            let v4 = pool
+                .as_any()
+                .downcast_ref::<UniswapV4Pool<P>>()
+                .ok_or_else(|| ArbRsError::CalculationError("Expected a V4 pool".into()))?;
+            let (amount_out, final_snapshot) =
+                v4.simulate_exact_input_swap(token_in, token_out, amount_in, v4_snapshot)?;
+            Ok((amount_out, PoolSnapshot::UniswapV4(final_snapshot)))
+        }
+        PoolSnapshot::Curve(curve_snapshot) => {
+            let curve = pool
+                .as_any()
+                .downcast_ref::<CurveStableswapPool<P>>()
+                .ok_or_else(|| ArbRsError::CalculationError("Expected a Curve pool".into()))?;
+            let result = curve.simulate_exchange(token_in, token_out, amount_in, curve_snapshot)?;
+            Ok((
+                result.amount_out,
+                PoolSnapshot::Curve(result.final_snapshot),
+            ))
+        }
+        PoolSnapshot::Balancer(balancer_snapshot) => {
+            let balancer = pool
+                .as_any()
+                .downcast_ref::<BalancerPool<P>>()
+                .ok_or_else(|| ArbRsError::CalculationError("Expected a Balancer pool".into()))?;
+            let result =
+                balancer.simulate_swap(token_in, token_out, amount_in, balancer_snapshot)?;
+            Ok((
+                result.amount_out,
+                PoolSnapshot::Balancer(result.final_snapshot),
+            ))
+        }
+        PoolSnapshot::WrappedNative => Ok((
+            pool.calculate_tokens_out(token_in, token_out, amount_in, snapshot)?,
+            PoolSnapshot::WrappedNative,
+        )),
+    }
+}
+
+impl<P: Provider + Send + Sync + 'static + ?Sized> Debug for MempoolWatcher<P> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MempoolWatcher")
+            .field("pools", &self.pools.len())
+            .field("decoders", &self.decoders)
+            .field("config", &self.config)
+            .finish()
+    }
+}
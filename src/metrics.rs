@@ -0,0 +1,168 @@
+//! Counters, gauges and histograms of the engine, the pool managers and the pools, recorded
+//! through the [`metrics`](https://docs.rs/metrics) facade so whoever runs the engine picks
+//! the exporter. The names below are stable: dashboards and alerts may rely on them.
+//!
+//! Recording needs the `metrics` feature. Without it every function here is empty and
+//! compiles away, and with it but no recorder installed the facade drops each sample at
+//! the cost of a load and a branch.
+#![cfg_attr(not(feature = "metrics"), allow(unused_variables))]
+
+use crate::errors::ArbRsError;
+use crate::pool::{DexKind, PoolSnapshot};
+use alloy_primitives::U256;
+use std::future::Future;
+
+/// Blocks [`find_opportunities`](crate::arbitrage::engine::ArbitrageEngine::find_opportunities)
+/// evaluated. Counter.
+pub const BLOCKS_PROCESSED: &str = "arbrs_blocks_processed_total";
+/// Time a `get_snapshot` took, in seconds, by `pool_kind`. Histogram.
+pub const SNAPSHOT_DURATION_SECONDS: &str = "arbrs_snapshot_duration_seconds";
+/// `get_snapshot` calls that failed, by `pool_kind` and `error_class`. Counter.
+pub const SNAPSHOT_FAILURES: &str = "arbrs_snapshot_failures_total";
+/// Cached paths evaluations started from, skipped ones included. Counter.
+pub const PATHS_EVALUATED: &str = "arbrs_paths_evaluated_total";
+/// Solutions evaluations returned. Counter.
+pub const OPPORTUNITIES_FOUND: &str = "arbrs_opportunities_found_total";
+/// Net profit of each solution returned, in ETH. Histogram.
+pub const OPPORTUNITY_NET_PROFIT_ETH: &str = "arbrs_opportunity_net_profit_eth";
+/// Golden-section rounds each optimal input search took. Histogram.
+pub const OPTIMIZER_ITERATIONS: &str = "arbrs_optimizer_iterations";
+/// Calls made through [`RpcClient`](crate::core::rpc_client::RpcClient), by `method`, retries
+/// not counted. Counter.
+pub const RPC_CALLS: &str = "arbrs_rpc_calls_total";
+/// Pool creation events the managers' discovery found, by `pool_kind`, before any were
+/// rejected. Counter.
+pub const DISCOVERY_EVENTS: &str = "arbrs_discovery_events_total";
+/// Entries the evaluated pools hold in their per-block caches, by `pool_kind`, as of the
+/// engine's last cache prune. Gauge.
+pub const POOL_CACHE_ENTRIES: &str = "arbrs_pool_cache_entries";
+
+/// Label of the protocol: `uniswap_v2`, `uniswap_v3`, `curve`, `balancer` or `other`.
+pub const POOL_KIND_LABEL: &str = "pool_kind";
+/// Label of what failed, see [`ArbRsError::class`].
+pub const ERROR_CLASS_LABEL: &str = "error_class";
+/// Label of the RPC method, e.g. `eth_call`.
+pub const METHOD_LABEL: &str = "method";
+
+#[cfg(feature = "metrics")]
+fn pool_kind(dex: Option<DexKind>) -> &'static str {
+    dex.map_or("other", DexKind::label)
+}
+
+/// Describes every metric to the installed recorder, so exporters can show their units
+/// and help text.
+pub fn describe() {
+    #[cfg(feature = "metrics")]
+    {
+        use ::metrics::{Unit, describe_counter, describe_gauge, describe_histogram};
+        describe_counter!(BLOCKS_PROCESSED, Unit::Count, "Blocks evaluated.");
+        describe_histogram!(
+            SNAPSHOT_DURATION_SECONDS,
+            Unit::Seconds,
+            "Time a pool snapshot took, by pool kind."
+        );
+        describe_counter!(
+            SNAPSHOT_FAILURES,
+            Unit::Count,
+            "Pool snapshots that failed, by pool kind and error class."
+        );
+        describe_counter!(PATHS_EVALUATED, Unit::Count, "Cached paths evaluated.");
+        describe_counter!(OPPORTUNITIES_FOUND, Unit::Count, "Solutions found.");
+        describe_histogram!(
+            OPPORTUNITY_NET_PROFIT_ETH,
+            "Net profit of each solution found, in ETH."
+        );
+        describe_histogram!(
+            OPTIMIZER_ITERATIONS,
+            Unit::Count,
+            "Rounds each optimal input search took."
+        );
+        describe_counter!(RPC_CALLS, Unit::Count, "RPC calls made, by method.");
+        describe_counter!(
+            DISCOVERY_EVENTS,
+            Unit::Count,
+            "Pool creation events found by discovery, by pool kind."
+        );
+        describe_gauge!(
+            POOL_CACHE_ENTRIES,
+            Unit::Count,
+            "Entries in the pools' per-block caches, by pool kind."
+        );
+    }
+}
+
+/// Serves every metric recorded from now on at `http://{address}/metrics` in the Prometheus
+/// text format.
+#[cfg(feature = "metrics")]
+pub fn install_prometheus_exporter(
+    address: std::net::SocketAddr,
+) -> Result<(), ::metrics_exporter_prometheus::BuildError> {
+    metrics_exporter_prometheus::PrometheusBuilder::new()
+        .with_http_listener(address)
+        .install()?;
+    describe();
+    Ok(())
+}
+
+/// Awaits `snapshot`, recording how long it took and, if it failed, why.
+pub async fn observe_snapshot<F>(
+    dex: Option<DexKind>,
+    snapshot: F,
+) -> Result<PoolSnapshot, ArbRsError>
+where
+    F: Future<Output = Result<PoolSnapshot, ArbRsError>>,
+{
+    #[cfg(feature = "metrics")]
+    {
+        let started = std::time::Instant::now();
+        let result = snapshot.await;
+        let kind = pool_kind(dex);
+        ::metrics::histogram!(SNAPSHOT_DURATION_SECONDS, POOL_KIND_LABEL => kind)
+            .record(started.elapsed().as_secs_f64());
+        if let Err(e) = &result {
+            ::metrics::counter!(SNAPSHOT_FAILURES, POOL_KIND_LABEL => kind, ERROR_CLASS_LABEL => e.class())
+                .increment(1);
+        }
+        result
+    }
+    #[cfg(not(feature = "metrics"))]
+    snapshot.await
+}
+
+/// Records one block's evaluation: the paths it started from and the net profit, in wei,
+/// of each solution it returned.
+pub fn record_evaluation(paths: usize, net_profits_wei: impl IntoIterator<Item = U256>) {
+    #[cfg(feature = "metrics")]
+    {
+        ::metrics::counter!(BLOCKS_PROCESSED).increment(1);
+        ::metrics::counter!(PATHS_EVALUATED).increment(paths as u64);
+        let profits = metrics::histogram!(OPPORTUNITY_NET_PROFIT_ETH);
+        let mut found = 0;
+        for net_profit in net_profits_wei {
+            profits.record(crate::math::utils::u256_to_f64(net_profit) / 1e18);
+            found += 1;
+        }
+        ::metrics::counter!(OPPORTUNITIES_FOUND).increment(found);
+    }
+}
+
+pub fn record_optimizer_iterations(iterations: u32) {
+    #[cfg(feature = "metrics")]
+    ::metrics::histogram!(OPTIMIZER_ITERATIONS).record(f64::from(iterations));
+}
+
+pub fn record_rpc_call(method: &'static str) {
+    #[cfg(feature = "metrics")]
+    ::metrics::counter!(RPC_CALLS, METHOD_LABEL => method).increment(1);
+}
+
+pub fn record_discovery_events(dex: Option<DexKind>, events: usize) {
+    #[cfg(feature = "metrics")]
+    ::metrics::counter!(DISCOVERY_EVENTS, POOL_KIND_LABEL => pool_kind(dex))
+        .increment(events as u64);
+}
+
+pub fn record_cache_entries(dex: Option<DexKind>, entries: usize) {
+    #[cfg(feature = "metrics")]
+    ::metrics::gauge!(POOL_CACHE_ENTRIES, POOL_KIND_LABEL => pool_kind(dex)).set(entries as f64);
+}
@@ -0,0 +1,46 @@
+//! CREATE2 addresses of pools deployed by known factories, computed without any calls.
+
+use alloy_primitives::{Address, B256, U256, b256, keccak256};
+use alloy_sol_types::SolValue;
+
+pub(crate) const UNISWAP_V2_INIT_CODE_HASH: B256 =
+    b256!("96e8ac4277198ff8b6f785478aa9a39f403cb768dd02cbee326c3e7da348845f");
+pub(crate) const SUSHISWAP_INIT_CODE_HASH: B256 =
+    b256!("e18a34eb0e04b04f7a0ac29a6e80748dca96319b42c520b22d4b0d2c3d7df8a3");
+pub(crate) const PANCAKESWAP_V2_INIT_CODE_HASH: B256 =
+    b256!("57224589c67f3f30a6b0d7a1b54cf3153ab84563bc609ef41dfb34f8b2974d2d");
+pub(crate) const UNISWAP_V3_INIT_CODE_HASH: B256 =
+    b256!("e34f199b19b2b4f47f68442619d555527d244f78a3297ea89325f843f87b8b54");
+pub(crate) const PANCAKE_V3_INIT_CODE_HASH: B256 =
+    b256!("6ce8eb472fa82df5469c6ab6d485f17c3ad13c8cd7af59b3d4a8026c5ce0f7e2");
+
+fn sort_tokens(token_a: Address, token_b: Address) -> (Address, Address) {
+    if token_a < token_b {
+        (token_a, token_b)
+    } else {
+        (token_b, token_a)
+    }
+}
+
+pub(crate) fn v2_pair_address(
+    token_a: Address,
+    token_b: Address,
+    factory: Address,
+    init_code_hash: B256,
+) -> Address {
+    let (token0, token1) = sort_tokens(token_a, token_b);
+    let salt = keccak256((token0, token1).abi_encode_packed());
+    factory.create2(salt, init_code_hash)
+}
+
+pub(crate) fn v3_pool_address(
+    token_a: Address,
+    token_b: Address,
+    fee: u32,
+    factory: Address,
+    init_code_hash: B256,
+) -> Address {
+    let (token0, token1) = sort_tokens(token_a, token_b);
+    let salt = keccak256((token0, token1, U256::from(fee)).abi_encode());
+    factory.create2(salt, init_code_hash)
+}
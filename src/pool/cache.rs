@@ -0,0 +1,75 @@
+use std::collections::{BTreeMap, HashMap};
+
+/// Blocks a pool keeps in each of its per-block caches by default: somewhat under an hour
+/// of mainnet blocks, enough for reorgs and recent lookups.
+pub const DEFAULT_CACHE_MAX_BLOCKS: usize = 256;
+
+/// Retention of the caches pools key by block, such as their past states.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CacheConfig {
+    /// Blocks each cache holds at most. Inserting past it evicts the oldest ones.
+    pub max_blocks: usize,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            max_blocks: DEFAULT_CACHE_MAX_BLOCKS,
+        }
+    }
+}
+
+impl CacheConfig {
+    /// Inserts `value` at `block`, then drops the oldest blocks beyond `max_blocks`.
+    pub(crate) fn insert<V>(&self, cache: &mut BTreeMap<u64, V>, block: u64, value: V) {
+        cache.insert(block, value);
+        while cache.len() > self.max_blocks.max(1) {
+            cache.pop_first();
+        }
+    }
+
+    /// Inserts `value` at `block`, then keeps only the `max_blocks` blocks up to the newest
+    /// one cached.
+    pub(crate) fn insert_hashed<V>(&self, cache: &mut HashMap<u64, V>, block: u64, value: V) {
+        cache.insert(block, value);
+        if cache.len() > self.max_blocks.max(1) {
+            let newest = cache.keys().copied().max().unwrap_or(block);
+            let oldest_kept = newest.saturating_sub(self.max_blocks.max(1) as u64 - 1);
+            cache.retain(|&cached, _| cached >= oldest_kept);
+        }
+    }
+}
+
+/// What a pool holds in its per-block caches, see `LiquidityPool::cache_stats`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    /// Entries across all of the pool's per-block caches.
+    pub entries: usize,
+    pub oldest_block: Option<u64>,
+    pub newest_block: Option<u64>,
+}
+
+impl CacheStats {
+    /// Stats of one cache holding an entry at each of `blocks`.
+    pub(crate) fn of<'a>(blocks: impl IntoIterator<Item = &'a u64>) -> Self {
+        blocks.into_iter().fold(Self::default(), |stats, &block| {
+            stats.merge(Self {
+                entries: 1,
+                oldest_block: Some(block),
+                newest_block: Some(block),
+            })
+        })
+    }
+
+    /// Stats of both caches together.
+    pub fn merge(self, other: Self) -> Self {
+        Self {
+            entries: self.entries + other.entries,
+            oldest_block: match (self.oldest_block, other.oldest_block) {
+                (Some(a), Some(b)) => Some(a.min(b)),
+                (a, b) => a.or(b),
+            },
+            newest_block: self.newest_block.max(other.newest_block),
+        }
+    }
+}
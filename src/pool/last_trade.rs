@@ -0,0 +1,247 @@
+use crate::math::utils::u256_to_f64;
+use crate::pool::LiquidityPool;
+use alloy_primitives::{Address, B256, I256, U256};
+use alloy_provider::Provider;
+use alloy_rpc_types::Log;
+use alloy_sol_types::{SolEvent, sol};
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+
+const DEFAULT_CAPACITY: usize = 16;
+const BPS_DENOMINATOR: u64 = 10_000;
+
+sol! {
+    interface IUniswapV2Pair {
+        event Swap(address indexed sender, uint256 amount0In, uint256 amount1In, uint256 amount0Out, uint256 amount1Out, address indexed to);
+    }
+    interface IUniswapV3Pool {
+        event Swap(address indexed sender, address indexed recipient, int256 amount0, int256 amount1, uint160 sqrtPriceX96, uint128 liquidity, int24 tick);
+    }
+    interface ICurvePool {
+        event TokenExchange(address indexed buyer, int128 sold_id, uint256 tokens_sold, int128 bought_id, uint256 tokens_bought);
+    }
+    interface ICurveCryptoPool {
+        event TokenExchange(address indexed buyer, uint256 sold_id, uint256 tokens_sold, uint256 bought_id, uint256 tokens_bought);
+    }
+    interface IVault {
+        event Swap(bytes32 indexed poolId, address indexed tokenIn, address indexed tokenOut, uint256 amountIn, uint256 amountOut);
+    }
+}
+
+/// A swap observed on-chain, as reported by the pool's swap event.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LastTrade {
+    pub block: u64,
+    pub token_in: Address,
+    pub token_out: Address,
+    pub amount_in: U256,
+    pub amount_out: U256,
+    /// `amount_out / amount_in` in raw units, without decimal scaling.
+    pub implied_price: f64,
+}
+
+impl LastTrade {
+    pub fn new(
+        block: u64,
+        token_in: Address,
+        token_out: Address,
+        amount_in: U256,
+        amount_out: U256,
+    ) -> Self {
+        let implied_price = if amount_in.is_zero() {
+            0.0
+        } else {
+            u256_to_f64(amount_out) / u256_to_f64(amount_in)
+        };
+        Self {
+            block,
+            token_in,
+            token_out,
+            amount_in,
+            amount_out,
+            implied_price,
+        }
+    }
+
+    /// Decodes a Uniswap V2 `Swap` event. Returns `None` for other logs and for swaps
+    /// without a single clear direction.
+    pub fn from_v2_swap(log: &Log, token0: Address, token1: Address) -> Option<Self> {
+        let swap = IUniswapV2Pair::Swap::decode_log_data(log.data()).ok()?;
+        let block = log.block_number.unwrap_or_default();
+        if !swap.amount0In.is_zero() && !swap.amount1Out.is_zero() {
+            Some(Self::new(
+                block,
+                token0,
+                token1,
+                swap.amount0In,
+                swap.amount1Out,
+            ))
+        } else if !swap.amount1In.is_zero() && !swap.amount0Out.is_zero() {
+            Some(Self::new(
+                block,
+                token1,
+                token0,
+                swap.amount1In,
+                swap.amount0Out,
+            ))
+        } else {
+            None
+        }
+    }
+
+    /// Decodes a Uniswap V3 `Swap` event. Positive amounts flow into the pool.
+    pub fn from_v3_swap(log: &Log, token0: Address, token1: Address) -> Option<Self> {
+        let swap = IUniswapV3Pool::Swap::decode_log_data(log.data()).ok()?;
+        let block = log.block_number.unwrap_or_default();
+        if swap.amount0 > I256::ZERO && swap.amount1 < I256::ZERO {
+            Some(Self::new(
+                block,
+                token0,
+                token1,
+                swap.amount0.unsigned_abs(),
+                swap.amount1.unsigned_abs(),
+            ))
+        } else if swap.amount1 > I256::ZERO && swap.amount0 < I256::ZERO {
+            Some(Self::new(
+                block,
+                token1,
+                token0,
+                swap.amount1.unsigned_abs(),
+                swap.amount0.unsigned_abs(),
+            ))
+        } else {
+            None
+        }
+    }
+
+    /// Decodes a Curve `TokenExchange` event, in either its `int128` or `uint256` index form.
+    /// `coins` are the pool's tokens in index order.
+    pub fn from_curve_exchange(log: &Log, coins: &[Address]) -> Option<Self> {
+        let (sold_id, tokens_sold, bought_id, tokens_bought) =
+            if let Ok(exchange) = ICurvePool::TokenExchange::decode_log_data(log.data()) {
+                (
+                    usize::try_from(exchange.sold_id).ok()?,
+                    exchange.tokens_sold,
+                    usize::try_from(exchange.bought_id).ok()?,
+                    exchange.tokens_bought,
+                )
+            } else {
+                let exchange = ICurveCryptoPool::TokenExchange::decode_log_data(log.data()).ok()?;
+                (
+                    usize::try_from(exchange.sold_id).ok()?,
+                    exchange.tokens_sold,
+                    usize::try_from(exchange.bought_id).ok()?,
+                    exchange.tokens_bought,
+                )
+            };
+        Some(Self::new(
+            log.block_number.unwrap_or_default(),
+            *coins.get(sold_id)?,
+            *coins.get(bought_id)?,
+            tokens_sold,
+            tokens_bought,
+        ))
+    }
+
+    /// Decodes a Balancer vault `Swap` event, if it belongs to `pool_id`.
+    pub fn from_balancer_swap(log: &Log, pool_id: [u8; 32]) -> Option<Self> {
+        let swap = IVault::Swap::decode_log_data(log.data()).ok()?;
+        if swap.poolId != B256::from(pool_id) {
+            return None;
+        }
+        Some(Self::new(
+            log.block_number.unwrap_or_default(),
+            swap.tokenIn,
+            swap.tokenOut,
+            swap.amountIn,
+            swap.amountOut,
+        ))
+    }
+}
+
+/// Keeps the most recent swaps seen on a pool, newest last.
+#[derive(Debug)]
+pub struct LastTradeTracker {
+    trades: Mutex<VecDeque<LastTrade>>,
+    capacity: usize,
+}
+
+impl Default for LastTradeTracker {
+    fn default() -> Self {
+        Self::new(DEFAULT_CAPACITY)
+    }
+}
+
+impl LastTradeTracker {
+    pub fn new(capacity: usize) -> Self {
+        let capacity = capacity.max(1);
+        Self {
+            trades: Mutex::new(VecDeque::with_capacity(capacity)),
+            capacity,
+        }
+    }
+
+    pub fn record(&self, trade: LastTrade) {
+        if let Ok(mut trades) = self.trades.lock() {
+            if trades.len() == self.capacity {
+                trades.pop_front();
+            }
+            trades.push_back(trade);
+        }
+    }
+
+    pub fn last(&self) -> Option<LastTrade> {
+        self.trades.lock().ok()?.back().cloned()
+    }
+
+    /// All retained trades, oldest first.
+    pub fn recent(&self) -> Vec<LastTrade> {
+        self.trades
+            .lock()
+            .map(|trades| trades.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+}
+
+/// Relative difference between a snapshot quote and what a trade actually received, in
+/// basis points of the quote.
+pub fn divergence_bps(quoted_out: U256, traded_out: U256) -> u64 {
+    if quoted_out.is_zero() {
+        return u64::MAX;
+    }
+    let diff = quoted_out.abs_diff(traded_out);
+    let bps = diff.saturating_mul(U256::from(BPS_DENOMINATOR)) / quoted_out;
+    u64::try_from(bps).unwrap_or(u64::MAX)
+}
+
+/// Topics of every swap event understood by [`route_swap_log`], for building log filters.
+pub fn swap_event_signatures() -> Vec<B256> {
+    vec![
+        IUniswapV2Pair::Swap::SIGNATURE_HASH,
+        IUniswapV3Pool::Swap::SIGNATURE_HASH,
+        ICurvePool::TokenExchange::SIGNATURE_HASH,
+        ICurveCryptoPool::TokenExchange::SIGNATURE_HASH,
+        IVault::Swap::SIGNATURE_HASH,
+    ]
+}
+
+/// Hands a swap log to the pool that emitted it. Balancer swaps are emitted by the vault,
+/// so they are routed by the pool address embedded in the first 20 bytes of the pool id.
+/// Returns whether a trade was recorded.
+pub fn route_swap_log<P: Provider + Send + Sync + 'static + ?Sized>(
+    pools: &HashMap<Address, Arc<dyn LiquidityPool<P>>>,
+    log: &Log,
+) -> bool {
+    let topics = log.topics();
+    let target = match topics.first() {
+        Some(topic) if *topic == IVault::Swap::SIGNATURE_HASH => match topics.get(1) {
+            Some(pool_id) => Address::from_slice(&pool_id[..20]),
+            None => return false,
+        },
+        Some(_) => log.address(),
+        None => return false,
+    };
+    pools
+        .get(&target)
+        .is_some_and(|pool| pool.record_swap_log(log))
+}
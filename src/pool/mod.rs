@@ -1,21 +1,169 @@
 use crate::balancer::pool::BalancerPoolSnapshot;
-use crate::core::token::Token;
+use crate::core::token::{Token, TokenLike};
 use crate::curve::types::CurvePoolSnapshot;
 use crate::errors::ArbRsError;
+use crate::pool::cache::CacheStats;
+use crate::math::utils::u256_to_f64;
+use crate::pool::last_trade::{LastTrade, LastTradeTracker, divergence_bps};
+use crate::pool::quoter::QuotePool;
 use crate::pool::uniswap_v2::UniswapV2PoolState;
 use crate::pool::uniswap_v3::UniswapV3PoolSnapshot;
+use crate::pool::uniswap_v4::UniswapV4PoolSnapshot;
 use alloy_primitives::{Address, U256};
 use alloy_provider::Provider;
+use alloy_rpc_types::Log;
 use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
 use std::any::Any;
-use std::fmt::Debug;
+use std::collections::HashMap;
+use std::fmt::{self, Debug};
 use std::sync::Arc;
 
+pub mod address;
+pub mod cache;
+pub mod last_trade;
+pub mod quoter;
+pub mod reserve_drift;
+pub mod serializable;
+pub mod state_updater;
 pub mod strategy;
+pub mod tick_lens;
+pub mod tick_words;
 pub mod uniswap_v2;
 pub mod uniswap_v2_simulation;
 pub mod uniswap_v3;
 pub mod uniswap_v3_snapshot;
+pub mod uniswap_v4;
+pub mod wrapped_native;
+
+/// Amount quoted through swap math to derive a spot price: one whole `token_in`, so the
+/// output doesn't round away when the output token has fewer decimals.
+pub fn price_probe_amount<P: Provider + Send + Sync + 'static + ?Sized>(
+    token_in: &Token<P>,
+) -> U256 {
+    U256::from(10).pow(U256::from(token_in.decimals()))
+}
+
+/// Prices every ordered token pair of `pool` by quoting `price_probe_amount` through its
+/// swap math. Pairs that can't be quoted are left out.
+pub fn quote_prices_matrix<P, L>(pool: &L, snapshot: &PoolSnapshot) -> PriceMatrix
+where
+    P: Provider + Send + Sync + 'static + ?Sized,
+    L: LiquidityPool<P> + ?Sized,
+{
+    let tokens = pool.get_all_tokens();
+    let mut prices = PriceMatrix::new();
+    for token_in in &tokens {
+        let amount_in = price_probe_amount(token_in);
+        for token_out in tokens.iter().filter(|t| t.address() != token_in.address()) {
+            if let Ok(amount_out) =
+                pool.calculate_tokens_out(token_in, token_out, amount_in, snapshot)
+                && !amount_out.is_zero()
+            {
+                prices.insert(
+                    (token_in.address(), token_out.address()),
+                    u256_to_f64(amount_out) / u256_to_f64(amount_in),
+                );
+            }
+        }
+    }
+    prices
+}
+
+/// Spot prices keyed by `(token_in, token_out)`, in raw units of `token_out` per raw unit
+/// of `token_in`, as returned by `absolute_price`.
+pub type PriceMatrix = HashMap<(Address, Address), f64>;
+
+/// The protocol a pool belongs to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DexKind {
+    UniswapV2,
+    UniswapV3,
+    UniswapV4,
+    Curve,
+    Balancer,
+}
+
+impl DexKind {
+    pub const ALL: [DexKind; 5] = [
+        DexKind::UniswapV2,
+        DexKind::UniswapV3,
+        DexKind::UniswapV4,
+        DexKind::Curve,
+        DexKind::Balancer,
+    ];
+
+    /// The kind's name as serialized, e.g. `uniswap_v2`.
+    pub fn label(self) -> &'static str {
+        match self {
+            DexKind::UniswapV2 => "uniswap_v2",
+            DexKind::UniswapV3 => "uniswap_v3",
+            DexKind::UniswapV4 => "uniswap_v4",
+            DexKind::Curve => "curve",
+            DexKind::Balancer => "balancer",
+        }
+    }
+}
+
+/// Gas one swap through a pool is estimated to cost, by pool type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SwapGasCosts {
+    pub uniswap_v2: u64,
+    /// Uniswap V3 swap within a single range of liquidity.
+    pub uniswap_v3: u64,
+    /// Added per initialized tick a Uniswap V3 or V4 swap crosses.
+    pub uniswap_v3_tick_crossing: u64,
+    /// Uniswap V4 swap within a single range of liquidity, settled through the pool manager.
+    pub uniswap_v4: u64,
+    pub curve: u64,
+    /// Curve metapool swap through `exchange_underlying`, which also trades in the base pool.
+    pub curve_underlying: u64,
+    pub balancer: u64,
+    /// WETH `deposit` or `withdraw`.
+    pub wrap: u64,
+    /// Pools outside the known protocols.
+    pub other: u64,
+}
+
+impl Default for SwapGasCosts {
+    fn default() -> Self {
+        Self {
+            uniswap_v2: 120_000,
+            uniswap_v3: 150_000,
+            uniswap_v3_tick_crossing: 25_000,
+            uniswap_v4: 130_000,
+            curve: 250_000,
+            curve_underlying: 450_000,
+            balancer: 180_000,
+            wrap: 30_000,
+            other: 250_000,
+        }
+    }
+}
+
+/// Groups pools whose quotes share a source of error: the pool type plus its fee tier or
+/// swap strategy, e.g. `curve/Lending` or `uniswap_v3/500`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct CalibrationBucket {
+    pub pool_type: String,
+    pub variant: String,
+}
+
+impl CalibrationBucket {
+    pub fn new(pool_type: impl Into<String>, variant: impl Into<String>) -> Self {
+        Self {
+            pool_type: pool_type.into(),
+            variant: variant.into(),
+        }
+    }
+}
+
+impl fmt::Display for CalibrationBucket {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.pool_type, self.variant)
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct UniswapPoolSwapVector<P: Provider + Send + Sync + 'static + ?Sized> {
@@ -24,12 +172,30 @@ pub struct UniswapPoolSwapVector<P: Provider + Send + Sync + 'static + ?Sized> {
     pub zero_for_one: bool,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum PoolSnapshot {
     UniswapV2(UniswapV2PoolState),
     UniswapV3(UniswapV3PoolSnapshot),
+    UniswapV4(UniswapV4PoolSnapshot),
     Curve(CurvePoolSnapshot),
     Balancer(BalancerPoolSnapshot),
+    /// The stateless [`WrappedNativePool`](wrapped_native::WrappedNativePool).
+    WrappedNative,
+}
+
+/// Outcome of `LiquidityPool::update_state`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StateUpdate {
+    /// The fetched state matches the cached one, which was left as is.
+    Unchanged,
+    /// The cached state was replaced with the one fetched at `block`.
+    Updated { block: u64 },
+}
+
+impl StateUpdate {
+    pub fn changed(&self) -> bool {
+        matches!(self, StateUpdate::Updated { .. })
+    }
 }
 
 #[async_trait]
@@ -41,11 +207,44 @@ pub trait LiquidityPool<P: Provider + Send + Sync + 'static + ?Sized>: Debug + S
     fn get_all_tokens(&self) -> Vec<Arc<Token<P>>>;
 
     /// Fetches the latest state from the blockchain and updates the pool's internal cache.
-    async fn update_state(&self) -> Result<(), ArbRsError>;
+    /// Reports whether the fetched state differed from the cached one.
+    async fn update_state(&self) -> Result<StateUpdate, ArbRsError>;
+
+    /// Moves the pool's live state to `block_number`, for backfills, backtests and reorg
+    /// rollbacks. Like `update_state`, going back in time fails with `LateUpdateError` unless
+    /// `allow_rewind` is set, in which case cached states after `block_number` are dropped.
+    async fn update_state_at_block(
+        &self,
+        _block_number: u64,
+        _allow_rewind: bool,
+    ) -> Result<(), ArbRsError> {
+        Err(ArbRsError::CalculationError(format!(
+            "Pool {} does not support block-pinned state updates",
+            self.address()
+        )))
+    }
+
+    /// Forgets everything cached for `block_number` and later, after a reorg replaced those
+    /// blocks. Live state read from them goes back to the last state cached before, or is
+    /// marked stale so the next `update_state` reads it again. Pools caching nothing by
+    /// block do nothing.
+    async fn invalidate_from(&self, _block_number: u64) {}
+
+    /// Drops what the pool cached for blocks before `block`. The live state is kept.
+    async fn discard_states_before_block(&self, _block: u64) {}
+
+    /// Sizes of the pool's per-block caches. Empty for pools caching nothing by block.
+    async fn cache_stats(&self) -> CacheStats {
+        CacheStats::default()
+    }
 
     /// Fetches all dynamic data for a pool at a specific block and returns a snapshot.
     async fn get_snapshot(&self, block_number: Option<u64>) -> Result<PoolSnapshot, ArbRsError>;
 
+    /// Each token's reserve at `snapshot`, in the order of `get_all_tokens`: what a swap
+    /// could drain, for sizing the pool up. Empty for another kind of pool's snapshot.
+    fn reserves_summary(&self, snapshot: &PoolSnapshot) -> Vec<(Arc<Token<P>>, U256)>;
+
     /// Calculates tokens out using a pre-fetched state snapshot. PURE & SYNCHRONOUS.
     fn calculate_tokens_out(
         &self,
@@ -64,6 +263,15 @@ pub trait LiquidityPool<P: Provider + Send + Sync + 'static + ?Sized>: Debug + S
         snapshot: &PoolSnapshot,
     ) -> Result<U256, ArbRsError>;
 
+    /// The pool's swap math on `snapshot`, detached from the pool and its provider. Quotes
+    /// the same as `calculate_tokens_out` and `calculate_tokens_in` on that snapshot.
+    fn to_quoter(&self, _snapshot: &PoolSnapshot) -> Result<QuotePool, ArbRsError> {
+        Err(ArbRsError::CalculationError(format!(
+            "Pool {} has no provider-free quoter",
+            self.address()
+        )))
+    }
+
     /// Calculates the "absolute price" of token0 in terms of token1, without decimal scaling.
     async fn absolute_price(
         &self,
@@ -83,5 +291,89 @@ pub trait LiquidityPool<P: Provider + Send + Sync + 'static + ?Sized>: Debug + S
         token_out: &Token<P>,
     ) -> Result<f64, ArbRsError>;
 
+    /// Spot prices of every ordered token pair, all derived from `snapshot`. The default
+    /// is `quote_prices_matrix`.
+    fn prices_matrix(&self, snapshot: &PoolSnapshot) -> PriceMatrix {
+        quote_prices_matrix(self, snapshot)
+    }
+
+    /// `prices_matrix` at `block_number`, fetching a single snapshot for all pairs.
+    async fn fetch_prices_matrix(
+        &self,
+        block_number: Option<u64>,
+    ) -> Result<PriceMatrix, ArbRsError> {
+        let snapshot = self.get_snapshot(block_number).await?;
+        Ok(self.prices_matrix(&snapshot))
+    }
+
+    /// Recent swaps observed through `record_swap_log`. `None` for pools that don't track them.
+    fn last_trade_tracker(&self) -> Option<&LastTradeTracker> {
+        None
+    }
+
+    /// Records the trade in a swap event emitted for this pool. Returns whether it was recognised.
+    fn record_swap_log(&self, _log: &Log) -> bool {
+        false
+    }
+
+    /// Bucket whose prediction error calibration applies to this pool's quotes.
+    fn calibration_bucket(&self) -> Option<CalibrationBucket> {
+        None
+    }
+
+    /// The protocol this pool belongs to. `None` for pools outside the known protocols,
+    /// which dex toggles don't apply to.
+    fn dex_kind(&self) -> Option<DexKind> {
+        None
+    }
+
+    /// Gas a swap of `amount_in` through this pool costs, priced from `costs`.
+    fn gas_estimate(
+        &self,
+        _token_in: &Token<P>,
+        _token_out: &Token<P>,
+        _amount_in: U256,
+        _snapshot: &PoolSnapshot,
+        costs: &SwapGasCosts,
+    ) -> u64 {
+        costs.other
+    }
+
+    /// Whether the pool takes and pays `token`, which it lists as WETH, in native ether.
+    fn is_native(&self, _token: Address) -> bool {
+        false
+    }
+
+    /// Whether swapping through the pool the other way is a different trade, so a cycle
+    /// and its reverse are kept as separate paths. True of every AMM, whose fees and price
+    /// impact differ by direction.
+    fn is_direction_sensitive(&self) -> bool {
+        true
+    }
+
+    /// Whether the path finder routes swaps between `token_a` and `token_b`, both among
+    /// `get_all_tokens`, through this pool. False for pairs another pool trades for less.
+    fn trades_pair(&self, _token_a: Address, _token_b: Address) -> bool {
+        true
+    }
+
+    fn last_trade(&self) -> Option<LastTrade> {
+        self.last_trade_tracker()?.last()
+    }
+
+    /// Re-quotes the last observed trade against `snapshot` and returns how far the quoted
+    /// output is from what the trade received, in basis points of the quote. Large trades
+    /// diverge from the post-trade state on their own, so thresholds should allow for it.
+    fn spot_vs_last_trade_divergence_bps(&self, snapshot: &PoolSnapshot) -> Option<u64> {
+        let trade = self.last_trade()?;
+        let tokens = self.get_all_tokens();
+        let token_in = tokens.iter().find(|t| t.address() == trade.token_in)?;
+        let token_out = tokens.iter().find(|t| t.address() == trade.token_out)?;
+        let quoted = self
+            .calculate_tokens_out(token_in, token_out, trade.amount_in, snapshot)
+            .ok()?;
+        Some(divergence_bps(quoted, trade.amount_out))
+    }
+
     fn as_any(&self) -> &dyn Any;
 }
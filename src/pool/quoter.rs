@@ -0,0 +1,92 @@
+//! Pools reduced to their swap math and one snapshot, with no provider, locks or async
+//! methods, so quotes can be run anywhere, `spawn_blocking` included.
+
+use crate::balancer::pool::BalancerQuoter;
+use crate::curve::pool::CurveQuoter;
+use crate::curve::underlying::UnderlyingCurveQuoter;
+use crate::errors::ArbRsError;
+use crate::pool::uniswap_v2::UniswapV2Quoter;
+use crate::pool::uniswap_v3::UniswapV3Quoter;
+use crate::pool::uniswap_v4::UniswapV4Quoter;
+use crate::pool::wrapped_native::WrappedNativeQuoter;
+use alloy_primitives::{Address, U256};
+
+/// Quotes swaps on one pool state. Tokens are given by address, native ether by the
+/// address the pool's token stands it in with.
+pub trait Quoter: Send + Sync {
+    fn calculate_out(
+        &self,
+        token_in: Address,
+        token_out: Address,
+        amount_in: U256,
+    ) -> Result<U256, ArbRsError>;
+
+    /// The input `amount_out` of `token_out` takes.
+    fn calculate_in(
+        &self,
+        token_in: Address,
+        token_out: Address,
+        amount_out: U256,
+    ) -> Result<U256, ArbRsError>;
+}
+
+/// A pool and its snapshot, as built by
+/// [`LiquidityPool::to_quoter`](crate::pool::LiquidityPool::to_quoter). Cheap to clone:
+/// tick maps and Curve parameters are shared.
+#[derive(Debug, Clone)]
+pub enum QuotePool {
+    UniswapV2(UniswapV2Quoter),
+    UniswapV3(UniswapV3Quoter),
+    UniswapV4(UniswapV4Quoter),
+    Curve(CurveQuoter),
+    /// Swaps between a metapool's underlying coins.
+    CurveUnderlying(UnderlyingCurveQuoter),
+    Balancer(BalancerQuoter),
+    WrappedNative(WrappedNativeQuoter),
+}
+
+impl QuotePool {
+    pub fn address(&self) -> Address {
+        match self {
+            QuotePool::UniswapV2(quoter) => quoter.address,
+            QuotePool::UniswapV3(quoter) => quoter.address,
+            QuotePool::UniswapV4(quoter) => quoter.address,
+            QuotePool::Curve(quoter) => quoter.address,
+            QuotePool::CurveUnderlying(quoter) => quoter.metapool.address,
+            QuotePool::Balancer(quoter) => quoter.address,
+            QuotePool::WrappedNative(quoter) => quoter.weth,
+        }
+    }
+
+    fn quoter(&self) -> &dyn Quoter {
+        match self {
+            QuotePool::UniswapV2(quoter) => quoter,
+            QuotePool::UniswapV3(quoter) => quoter,
+            QuotePool::UniswapV4(quoter) => quoter,
+            QuotePool::Curve(quoter) => quoter,
+            QuotePool::CurveUnderlying(quoter) => quoter,
+            QuotePool::Balancer(quoter) => quoter,
+            QuotePool::WrappedNative(quoter) => quoter,
+        }
+    }
+}
+
+impl Quoter for QuotePool {
+    fn calculate_out(
+        &self,
+        token_in: Address,
+        token_out: Address,
+        amount_in: U256,
+    ) -> Result<U256, ArbRsError> {
+        self.quoter().calculate_out(token_in, token_out, amount_in)
+    }
+
+    fn calculate_in(
+        &self,
+        token_in: Address,
+        token_out: Address,
+        amount_out: U256,
+    ) -> Result<U256, ArbRsError> {
+        self.quoter().calculate_in(token_in, token_out, amount_out)
+    }
+}
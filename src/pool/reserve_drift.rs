@@ -0,0 +1,127 @@
+use crate::core::token::WETH_ADDRESS;
+use alloy_primitives::{Address, I256, U256};
+
+const BPS: u64 = 10_000;
+
+/// Gas a `skim(to)` on a V2 pair costs: the call plus a transfer of each token.
+pub const SKIM_GAS_UNITS: u64 = 60_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReserveDriftConfig {
+    /// Relative drift of either token above which a pool is flagged for review.
+    pub threshold_bps: u64,
+    /// Pools checked per sweep. Sweeps continue where the previous one stopped, so the whole
+    /// registry is covered over several of them without competing with the block loop.
+    pub pools_per_sweep: usize,
+    /// Gas a skim is costed at, against the gas price of the sweep.
+    pub skim_gas_units: u64,
+}
+
+impl Default for ReserveDriftConfig {
+    fn default() -> Self {
+        Self {
+            threshold_bps: 100,
+            pools_per_sweep: 20,
+            skim_gas_units: SKIM_GAS_UNITS,
+        }
+    }
+}
+
+/// A V2 pair's token balances against its stored reserves at one block.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReserveDrift {
+    pub pool: Address,
+    pub block: u64,
+    pub token0: Address,
+    pub token1: Address,
+    pub reserve0: U256,
+    pub reserve1: U256,
+    /// Balance minus reserve. Positive amounts can be skimmed; negative ones mean the pair
+    /// holds less than it accounts for, as with rebasing or fee-on-transfer tokens.
+    pub drift0: I256,
+    pub drift1: I256,
+}
+
+impl ReserveDrift {
+    /// The larger of the two drifts relative to its reserve. Any drift against an empty
+    /// reserve is `u64::MAX`.
+    pub fn relative_bps(&self) -> u64 {
+        relative_bps(self.drift0, self.reserve0).max(relative_bps(self.drift1, self.reserve1))
+    }
+
+    pub fn exceeds(&self, threshold_bps: u64) -> bool {
+        self.relative_bps() > threshold_bps
+    }
+
+    /// Amounts `skim` would send out.
+    pub fn skim_amounts(&self) -> (U256, U256) {
+        (skimmable(self.drift0), skimmable(self.drift1))
+    }
+
+    /// What the skimmable amounts are worth in wei, pricing the other token at the pair's
+    /// reserves. `None` for pairs without WETH.
+    pub fn skim_value_wei(&self) -> Option<U256> {
+        let (amount0, amount1) = self.skim_amounts();
+        let (weth_amount, weth_reserve, other_amount, other_reserve) =
+            if self.token0 == WETH_ADDRESS {
+                (amount0, self.reserve0, amount1, self.reserve1)
+            } else if self.token1 == WETH_ADDRESS {
+                (amount1, self.reserve1, amount0, self.reserve0)
+            } else {
+                return None;
+            };
+        let other_value = if other_reserve.is_zero() {
+            U256::ZERO
+        } else {
+            other_amount.saturating_mul(weth_reserve) / other_reserve
+        };
+        Some(weth_amount.saturating_add(other_value))
+    }
+
+    /// A skim worth more than `gas_cost_wei`, or `None`.
+    pub fn skim_opportunity(&self, gas_cost_wei: U256) -> Option<SkimOpportunity> {
+        let value_wei = self.skim_value_wei()?;
+        (value_wei > gas_cost_wei).then(|| {
+            let (amount0, amount1) = self.skim_amounts();
+            SkimOpportunity {
+                pool: self.pool,
+                block: self.block,
+                amount0,
+                amount1,
+                value_wei,
+                gas_cost_wei,
+            }
+        })
+    }
+}
+
+/// Excess balances of a pair worth skimming.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SkimOpportunity {
+    pub pool: Address,
+    pub block: u64,
+    pub amount0: U256,
+    pub amount1: U256,
+    pub value_wei: U256,
+    pub gas_cost_wei: U256,
+}
+
+fn relative_bps(drift: I256, reserve: U256) -> u64 {
+    let drift = drift.unsigned_abs();
+    if drift.is_zero() {
+        return 0;
+    }
+    if reserve.is_zero() {
+        return u64::MAX;
+    }
+    let bps = drift.saturating_mul(U256::from(BPS)) / reserve;
+    u64::try_from(bps).unwrap_or(u64::MAX)
+}
+
+fn skimmable(drift: I256) -> U256 {
+    if drift.is_positive() {
+        drift.into_raw()
+    } else {
+        U256::ZERO
+    }
+}
@@ -0,0 +1,327 @@
+//! Snapshot types for consumers outside the process, e.g. archives read back for debugging.
+//! Every integer wider than 53 bits is a decimal string, and tick maps are arrays of
+//! entries rather than maps keyed by number. Each converts to and from the snapshot it
+//! mirrors without loss.
+
+use crate::balancer::pool::BalancerPoolSnapshot;
+use crate::core::decimal;
+use crate::curve::types::CurvePoolSnapshot;
+use crate::pool::PoolSnapshot;
+use crate::pool::uniswap_v2::UniswapV2PoolState;
+use crate::pool::uniswap_v3::{TickInfo, UniswapV3PoolSnapshot};
+use crate::pool::uniswap_v4::UniswapV4PoolSnapshot;
+use alloy_primitives::U256;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SerializableUniswapV2State {
+    #[serde(with = "decimal")]
+    pub reserve0: U256,
+    #[serde(with = "decimal")]
+    pub reserve1: U256,
+    pub block_number: u64,
+}
+
+/// A word of a V3 pool's tick bitmap.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SerializableBitmapWord {
+    pub word: i16,
+    #[serde(with = "decimal")]
+    pub bitmap: U256,
+}
+
+/// An initialized tick of a V3 pool.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SerializableTick {
+    pub tick: i32,
+    #[serde(with = "decimal")]
+    pub liquidity_gross: u128,
+    #[serde(with = "decimal")]
+    pub liquidity_net: i128,
+}
+
+/// Words and ticks are in ascending order.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SerializableUniswapV3Snapshot {
+    #[serde(with = "decimal")]
+    pub sqrt_price_x96: U256,
+    pub tick: i32,
+    #[serde(with = "decimal")]
+    pub liquidity: u128,
+    pub fee_protocol: u8,
+    pub tick_bitmap: Vec<SerializableBitmapWord>,
+    pub ticks: Vec<SerializableTick>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SerializableUniswapV4Snapshot {
+    #[serde(flatten)]
+    pub state: SerializableUniswapV3Snapshot,
+    pub protocol_fee: u32,
+    pub lp_fee: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SerializableCurveSnapshot {
+    #[serde(with = "decimal::vec")]
+    pub balances: Vec<U256>,
+    #[serde(with = "decimal")]
+    pub a: U256,
+    #[serde(with = "decimal")]
+    pub fee: U256,
+    #[serde(with = "decimal::option")]
+    pub admin_fee: Option<U256>,
+    pub block_timestamp: u64,
+    #[serde(with = "decimal::option")]
+    pub base_pool_virtual_price: Option<U256>,
+    #[serde(with = "decimal::option")]
+    pub base_pool_lp_total_supply: Option<U256>,
+    #[serde(with = "decimal::vec")]
+    pub rates: Vec<U256>,
+    #[serde(with = "decimal::option_vec")]
+    pub admin_balances: Option<Vec<U256>>,
+    #[serde(with = "decimal::option")]
+    pub tricrypto_d: Option<U256>,
+    #[serde(with = "decimal::option")]
+    pub tricrypto_gamma: Option<U256>,
+    #[serde(with = "decimal::option_vec")]
+    pub tricrypto_price_scale: Option<Vec<U256>>,
+    #[serde(with = "decimal::option")]
+    pub mid_fee: Option<U256>,
+    #[serde(with = "decimal::option")]
+    pub out_fee: Option<U256>,
+    #[serde(with = "decimal::option")]
+    pub fee_gamma: Option<U256>,
+    #[serde(with = "decimal::option")]
+    pub scaled_redemption_price: Option<U256>,
+    #[serde(default)]
+    pub oracle_updated_at: Option<u64>,
+    pub base_pool_snapshot: Option<Box<SerializableCurveSnapshot>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SerializableBalancerSnapshot {
+    #[serde(with = "decimal::vec")]
+    pub balances: Vec<U256>,
+    pub is_paused: bool,
+    pub block_number: Option<u64>,
+    #[serde(with = "decimal::option")]
+    pub swap_fee: Option<U256>,
+    #[serde(with = "decimal::option")]
+    pub amplification: Option<U256>,
+    #[serde(with = "decimal::option_vec")]
+    pub scaling_factors: Option<Vec<U256>>,
+}
+
+/// A [`PoolSnapshot`], tagged with its pool type under `"type"`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SerializablePoolSnapshot {
+    UniswapV2(SerializableUniswapV2State),
+    UniswapV3(SerializableUniswapV3Snapshot),
+    UniswapV4(SerializableUniswapV4Snapshot),
+    Curve(Box<SerializableCurveSnapshot>),
+    Balancer(SerializableBalancerSnapshot),
+    WrappedNative,
+}
+
+impl From<&UniswapV2PoolState> for SerializableUniswapV2State {
+    fn from(state: &UniswapV2PoolState) -> Self {
+        Self {
+            reserve0: state.reserve0,
+            reserve1: state.reserve1,
+            block_number: state.block_number,
+        }
+    }
+}
+
+impl From<SerializableUniswapV2State> for UniswapV2PoolState {
+    fn from(state: SerializableUniswapV2State) -> Self {
+        Self {
+            reserve0: state.reserve0,
+            reserve1: state.reserve1,
+            block_number: state.block_number,
+        }
+    }
+}
+
+impl From<&UniswapV3PoolSnapshot> for SerializableUniswapV3Snapshot {
+    fn from(snapshot: &UniswapV3PoolSnapshot) -> Self {
+        Self {
+            sqrt_price_x96: snapshot.sqrt_price_x96,
+            tick: snapshot.tick,
+            liquidity: snapshot.liquidity,
+            fee_protocol: snapshot.fee_protocol,
+            tick_bitmap: snapshot
+                .tick_bitmap
+                .iter()
+                .map(|(&word, &bitmap)| SerializableBitmapWord { word, bitmap })
+                .collect(),
+            ticks: snapshot
+                .tick_data
+                .iter()
+                .map(|(&tick, info)| SerializableTick {
+                    tick,
+                    liquidity_gross: info.liquidity_gross,
+                    liquidity_net: info.liquidity_net,
+                })
+                .collect(),
+        }
+    }
+}
+
+impl From<SerializableUniswapV3Snapshot> for UniswapV3PoolSnapshot {
+    fn from(snapshot: SerializableUniswapV3Snapshot) -> Self {
+        Self {
+            sqrt_price_x96: snapshot.sqrt_price_x96,
+            tick: snapshot.tick,
+            liquidity: snapshot.liquidity,
+            tick_bitmap: snapshot
+                .tick_bitmap
+                .into_iter()
+                .map(|word| (word.word, word.bitmap))
+                .collect(),
+            tick_data: snapshot
+                .ticks
+                .into_iter()
+                .map(|tick| {
+                    (
+                        tick.tick,
+                        TickInfo {
+                            liquidity_gross: tick.liquidity_gross,
+                            liquidity_net: tick.liquidity_net,
+                        },
+                    )
+                })
+                .collect(),
+            fee_protocol: snapshot.fee_protocol,
+        }
+    }
+}
+
+impl From<&UniswapV4PoolSnapshot> for SerializableUniswapV4Snapshot {
+    fn from(snapshot: &UniswapV4PoolSnapshot) -> Self {
+        Self {
+            state: (&snapshot.state).into(),
+            protocol_fee: snapshot.protocol_fee,
+            lp_fee: snapshot.lp_fee,
+        }
+    }
+}
+
+impl From<SerializableUniswapV4Snapshot> for UniswapV4PoolSnapshot {
+    fn from(snapshot: SerializableUniswapV4Snapshot) -> Self {
+        Self {
+            state: snapshot.state.into(),
+            protocol_fee: snapshot.protocol_fee,
+            lp_fee: snapshot.lp_fee,
+        }
+    }
+}
+
+impl From<&CurvePoolSnapshot> for SerializableCurveSnapshot {
+    fn from(snapshot: &CurvePoolSnapshot) -> Self {
+        Self {
+            balances: snapshot.balances.clone(),
+            a: snapshot.a,
+            fee: snapshot.fee,
+            admin_fee: snapshot.admin_fee,
+            block_timestamp: snapshot.block_timestamp,
+            base_pool_virtual_price: snapshot.base_pool_virtual_price,
+            base_pool_lp_total_supply: snapshot.base_pool_lp_total_supply,
+            rates: snapshot.rates.clone(),
+            admin_balances: snapshot.admin_balances.clone(),
+            tricrypto_d: snapshot.tricrypto_d,
+            tricrypto_gamma: snapshot.tricrypto_gamma,
+            tricrypto_price_scale: snapshot.tricrypto_price_scale.clone(),
+            mid_fee: snapshot.mid_fee,
+            out_fee: snapshot.out_fee,
+            fee_gamma: snapshot.fee_gamma,
+            scaled_redemption_price: snapshot.scaled_redemption_price,
+            oracle_updated_at: snapshot.oracle_updated_at,
+            base_pool_snapshot: snapshot
+                .base_pool_snapshot
+                .as_deref()
+                .map(|base| Box::new(base.into())),
+        }
+    }
+}
+
+impl From<SerializableCurveSnapshot> for CurvePoolSnapshot {
+    fn from(snapshot: SerializableCurveSnapshot) -> Self {
+        Self {
+            balances: snapshot.balances,
+            a: snapshot.a,
+            fee: snapshot.fee,
+            admin_fee: snapshot.admin_fee,
+            block_timestamp: snapshot.block_timestamp,
+            base_pool_virtual_price: snapshot.base_pool_virtual_price,
+            base_pool_lp_total_supply: snapshot.base_pool_lp_total_supply,
+            rates: snapshot.rates,
+            admin_balances: snapshot.admin_balances,
+            tricrypto_d: snapshot.tricrypto_d,
+            tricrypto_gamma: snapshot.tricrypto_gamma,
+            tricrypto_price_scale: snapshot.tricrypto_price_scale,
+            mid_fee: snapshot.mid_fee,
+            out_fee: snapshot.out_fee,
+            fee_gamma: snapshot.fee_gamma,
+            scaled_redemption_price: snapshot.scaled_redemption_price,
+            oracle_updated_at: snapshot.oracle_updated_at,
+            base_pool_snapshot: snapshot
+                .base_pool_snapshot
+                .map(|base| Box::new((*base).into())),
+        }
+    }
+}
+
+impl From<&BalancerPoolSnapshot> for SerializableBalancerSnapshot {
+    fn from(snapshot: &BalancerPoolSnapshot) -> Self {
+        Self {
+            balances: snapshot.balances.clone(),
+            is_paused: snapshot.is_paused,
+            block_number: snapshot.block_number,
+            swap_fee: snapshot.swap_fee,
+            amplification: snapshot.amplification,
+            scaling_factors: snapshot.scaling_factors.clone(),
+        }
+    }
+}
+
+impl From<SerializableBalancerSnapshot> for BalancerPoolSnapshot {
+    fn from(snapshot: SerializableBalancerSnapshot) -> Self {
+        Self {
+            balances: snapshot.balances,
+            is_paused: snapshot.is_paused,
+            block_number: snapshot.block_number,
+            swap_fee: snapshot.swap_fee,
+            amplification: snapshot.amplification,
+            scaling_factors: snapshot.scaling_factors,
+        }
+    }
+}
+
+impl From<&PoolSnapshot> for SerializablePoolSnapshot {
+    fn from(snapshot: &PoolSnapshot) -> Self {
+        match snapshot {
+            PoolSnapshot::UniswapV2(state) => Self::UniswapV2(state.into()),
+            PoolSnapshot::UniswapV3(snapshot) => Self::UniswapV3(snapshot.into()),
+            PoolSnapshot::UniswapV4(snapshot) => Self::UniswapV4(snapshot.into()),
+            PoolSnapshot::Curve(snapshot) => Self::Curve(Box::new(snapshot.into())),
+            PoolSnapshot::Balancer(snapshot) => Self::Balancer(snapshot.into()),
+            PoolSnapshot::WrappedNative => Self::WrappedNative,
+        }
+    }
+}
+
+impl From<SerializablePoolSnapshot> for PoolSnapshot {
+    fn from(snapshot: SerializablePoolSnapshot) -> Self {
+        match snapshot {
+            SerializablePoolSnapshot::UniswapV2(state) => Self::UniswapV2(state.into()),
+            SerializablePoolSnapshot::UniswapV3(snapshot) => Self::UniswapV3(snapshot.into()),
+            SerializablePoolSnapshot::UniswapV4(snapshot) => Self::UniswapV4(snapshot.into()),
+            SerializablePoolSnapshot::Curve(snapshot) => Self::Curve((*snapshot).into()),
+            SerializablePoolSnapshot::Balancer(snapshot) => Self::Balancer(snapshot.into()),
+            SerializablePoolSnapshot::WrappedNative => Self::WrappedNative,
+        }
+    }
+}
@@ -0,0 +1,306 @@
+use crate::ArbRsError;
+use crate::pool::PoolSnapshot;
+use crate::pool::last_trade::swap_event_signatures;
+use crate::pool::uniswap_v4::v4_pool_address;
+use alloy_primitives::{Address, B256};
+use alloy_provider::Provider;
+use alloy_rpc_types::{Filter, Log};
+use alloy_sol_types::{SolEvent, sol};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Mutex, MutexGuard};
+
+/// Longest run of blocks whose logs are fetched in one update. Longer gaps, e.g. after the
+/// block stream reconnects, mark every pool dirty instead.
+pub const MAX_UPDATE_BLOCKS: u64 = 100;
+
+sol! {
+    interface IUniswapV2Pair {
+        event Sync(uint112 reserve0, uint112 reserve1);
+    }
+    interface IUniswapV3Pool {
+        event Mint(address sender, address indexed owner, int24 indexed tickLower, int24 indexed tickUpper, uint128 amount, uint256 amount0, uint256 amount1);
+        event Burn(address indexed owner, int24 indexed tickLower, int24 indexed tickUpper, uint128 amount, uint256 amount0, uint256 amount1);
+    }
+    interface IVault {
+        event Swap(bytes32 indexed poolId, address indexed tokenIn, address indexed tokenOut, uint256 amountIn, uint256 amountOut);
+        event PoolBalanceChanged(bytes32 indexed poolId, address indexed liquidityProvider, address[] tokens, int256[] deltas, uint256[] protocolFeeAmounts);
+    }
+    interface ICurvePool {
+        event TokenExchangeUnderlying(address indexed buyer, int128 sold_id, uint256 tokens_sold, int128 bought_id, uint256 tokens_bought);
+        event RemoveLiquidityOne(address indexed provider, uint256 token_amount, uint256 coin_amount);
+        event RampA(uint256 old_A, uint256 new_A, uint256 initial_time, uint256 future_time);
+        event StopRampA(uint256 A, uint256 t);
+    }
+    interface ICurveFactoryPool {
+        event RemoveLiquidityOne(address indexed provider, uint256 token_amount, uint256 coin_amount, uint256 token_supply);
+    }
+    interface ICurvePool2 {
+        event AddLiquidity(address indexed provider, uint256[2] token_amounts, uint256[2] fees, uint256 invariant, uint256 token_supply);
+        event RemoveLiquidity(address indexed provider, uint256[2] token_amounts, uint256[2] fees, uint256 token_supply);
+        event RemoveLiquidityImbalance(address indexed provider, uint256[2] token_amounts, uint256[2] fees, uint256 invariant, uint256 token_supply);
+    }
+    interface ICurvePool3 {
+        event AddLiquidity(address indexed provider, uint256[3] token_amounts, uint256[3] fees, uint256 invariant, uint256 token_supply);
+        event RemoveLiquidity(address indexed provider, uint256[3] token_amounts, uint256[3] fees, uint256 token_supply);
+        event RemoveLiquidityImbalance(address indexed provider, uint256[3] token_amounts, uint256[3] fees, uint256 invariant, uint256 token_supply);
+    }
+    interface ICurvePool4 {
+        event AddLiquidity(address indexed provider, uint256[4] token_amounts, uint256[4] fees, uint256 invariant, uint256 token_supply);
+        event RemoveLiquidity(address indexed provider, uint256[4] token_amounts, uint256[4] fees, uint256 token_supply);
+        event RemoveLiquidityImbalance(address indexed provider, uint256[4] token_amounts, uint256[4] fees, uint256 invariant, uint256 token_supply);
+    }
+    interface ICurveStableSwapNG {
+        event AddLiquidity(address indexed provider, uint256[] token_amounts, uint256[] fees, uint256 invariant, uint256 token_supply);
+        event RemoveLiquidity(address indexed provider, uint256[] token_amounts, uint256[] fees, uint256 token_supply);
+        event RemoveLiquidityImbalance(address indexed provider, uint256[] token_amounts, uint256[] fees, uint256 invariant, uint256 token_supply);
+        event RemoveLiquidityOne(address indexed provider, int128 token_id, uint256 token_amount, uint256 coin_amount, uint256 token_supply);
+    }
+    interface IPoolManager {
+        event Swap(bytes32 indexed id, address indexed sender, int128 amount0, int128 amount1, uint160 sqrtPriceX96, uint128 liquidity, int24 tick, uint24 fee);
+        event ModifyLiquidity(bytes32 indexed id, address indexed sender, int24 tickLower, int24 tickUpper, int256 liquidityDelta, bytes32 salt);
+    }
+}
+
+#[derive(Debug, Default)]
+struct UpdaterState {
+    /// Last block whose logs have been applied.
+    synced_block: Option<u64>,
+    /// Pools that emitted a state-changing event since the cached snapshots were taken.
+    dirty: HashSet<Address>,
+    /// Set on reorgs and missed blocks, when the dirty set can't be trusted.
+    all_dirty: bool,
+    cache_block: Option<u64>,
+    snapshots: HashMap<Address, PoolSnapshot>,
+    /// Curve pools whose A is ramping, by the timestamp the ramp ends at. Their A moves
+    /// every block without an event, so they stay dirty until then.
+    ramping: HashMap<Address, u64>,
+}
+
+impl UpdaterState {
+    fn mark_all_dirty(&mut self, block_number: u64) {
+        self.all_dirty = true;
+        self.synced_block = Some(block_number);
+    }
+
+    /// Marks the pool `log` changes dirty, and starts or ends its A ramp. Returns whether
+    /// the log is a state-changing event.
+    fn apply_log(&mut self, log: &Log) -> bool {
+        let Some(pool) = changed_pool(log) else {
+            return false;
+        };
+        self.dirty.insert(pool);
+        match log.topic0() {
+            Some(topic) if *topic == ICurvePool::RampA::SIGNATURE_HASH => {
+                if let Ok(ramp) = ICurvePool::RampA::decode_log_data(log.data()) {
+                    let end = ramp.future_time.try_into().unwrap_or(u64::MAX);
+                    self.ramping.insert(pool, end);
+                }
+            }
+            Some(topic) if *topic == ICurvePool::StopRampA::SIGNATURE_HASH => {
+                self.ramping.remove(&pool);
+            }
+            _ => {}
+        }
+        true
+    }
+}
+
+/// Follows the events that change pool state so that only the pools touched since the last
+/// evaluated block are re-snapshotted, the others reusing that block's snapshots.
+///
+/// Curve pools are followed through their exchanges, liquidity changes and A ramps. A pool
+/// whose A is ramping is dirty on every block until the ramp ends.
+#[derive(Debug, Default)]
+pub struct StateUpdater {
+    state: Mutex<UpdaterState>,
+}
+
+impl StateUpdater {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Topics of V2 `Sync`, V3 `Swap`/`Mint`/`Burn`, Curve `TokenExchange`, the Curve
+    /// stableswap liquidity and A ramp events, the Balancer vault's `Swap`/`PoolBalanceChanged`
+    /// and the V4 pool manager's `Swap`/`ModifyLiquidity` events.
+    pub fn event_signatures() -> Vec<B256> {
+        let mut signatures = swap_event_signatures();
+        signatures.extend([
+            IUniswapV2Pair::Sync::SIGNATURE_HASH,
+            IUniswapV3Pool::Mint::SIGNATURE_HASH,
+            IUniswapV3Pool::Burn::SIGNATURE_HASH,
+            ICurvePool::TokenExchangeUnderlying::SIGNATURE_HASH,
+            ICurvePool::RemoveLiquidityOne::SIGNATURE_HASH,
+            ICurvePool::RampA::SIGNATURE_HASH,
+            ICurvePool::StopRampA::SIGNATURE_HASH,
+            ICurveFactoryPool::RemoveLiquidityOne::SIGNATURE_HASH,
+            ICurvePool2::AddLiquidity::SIGNATURE_HASH,
+            ICurvePool2::RemoveLiquidity::SIGNATURE_HASH,
+            ICurvePool2::RemoveLiquidityImbalance::SIGNATURE_HASH,
+            ICurvePool3::AddLiquidity::SIGNATURE_HASH,
+            ICurvePool3::RemoveLiquidity::SIGNATURE_HASH,
+            ICurvePool3::RemoveLiquidityImbalance::SIGNATURE_HASH,
+            ICurvePool4::AddLiquidity::SIGNATURE_HASH,
+            ICurvePool4::RemoveLiquidity::SIGNATURE_HASH,
+            ICurvePool4::RemoveLiquidityImbalance::SIGNATURE_HASH,
+            ICurveStableSwapNG::AddLiquidity::SIGNATURE_HASH,
+            ICurveStableSwapNG::RemoveLiquidity::SIGNATURE_HASH,
+            ICurveStableSwapNG::RemoveLiquidityImbalance::SIGNATURE_HASH,
+            ICurveStableSwapNG::RemoveLiquidityOne::SIGNATURE_HASH,
+            IVault::PoolBalanceChanged::SIGNATURE_HASH,
+            IPoolManager::Swap::SIGNATURE_HASH,
+            IPoolManager::ModifyLiquidity::SIGNATURE_HASH,
+        ]);
+        signatures
+    }
+
+    /// Filters by topic only: marks left by pools nobody tracks are never looked at.
+    pub fn filter() -> Filter {
+        Filter::new().event_signature(Self::event_signatures())
+    }
+
+    fn lock(&self) -> MutexGuard<'_, UpdaterState> {
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Fetches the logs of every block since the last update up to `block_number` and marks
+    /// their pools dirty. A block number at or below the last one means a reorg, and marks
+    /// every pool dirty, as does a failed fetch.
+    pub async fn update<P: Provider + Send + Sync + 'static + ?Sized>(
+        &self,
+        provider: &P,
+        block_number: u64,
+    ) -> Result<(), ArbRsError> {
+        let synced_block = self.lock().synced_block;
+        let from_block = match synced_block {
+            Some(synced) if block_number > synced && block_number - synced <= MAX_UPDATE_BLOCKS => {
+                synced + 1
+            }
+            Some(synced) => {
+                if block_number <= synced {
+                    tracing::warn!(
+                        block_number,
+                        synced,
+                        "Block number went backwards, marking every pool dirty."
+                    );
+                }
+                self.lock().mark_all_dirty(block_number);
+                return Ok(());
+            }
+            None => block_number,
+        };
+
+        let filter = Self::filter().from_block(from_block).to_block(block_number);
+        let logs = match provider.get_logs(&filter).await {
+            Ok(logs) => logs,
+            Err(e) => {
+                self.lock().mark_all_dirty(block_number);
+                return Err(e.into());
+            }
+        };
+        let mut state = self.lock();
+        for log in &logs {
+            state.apply_log(log);
+        }
+        state.synced_block = Some(block_number);
+        Ok(())
+    }
+
+    /// Marks the pool a log was emitted for dirty, for logs fed from a subscription instead
+    /// of [`update`](Self::update). Returns whether the log is a state-changing event.
+    pub fn record_log(&self, log: &Log) -> bool {
+        self.lock().apply_log(log)
+    }
+
+    /// Keeps `pool` dirty until a snapshot taken at or after `future_a_time` is stored, for
+    /// Curve pools found mid-ramp before their `RampA` log was seen.
+    pub fn mark_ramping(&self, pool: Address, future_a_time: u64) {
+        let mut state = self.lock();
+        state.ramping.insert(pool, future_a_time);
+    }
+
+    /// Forgets the logs and snapshots of `block_number` and later, after a reorg replaced
+    /// those blocks. Every pool is dirty until the next snapshots are stored, and the next
+    /// update fetches logs from `block_number` on.
+    pub fn invalidate_from(&self, block_number: u64) {
+        let mut state = self.lock();
+        let before = block_number.saturating_sub(1);
+        let synced = state.synced_block.map_or(before, |block| block.min(before));
+        state.mark_all_dirty(synced);
+        state.cache_block = None;
+        state.snapshots.clear();
+    }
+
+    pub fn is_dirty(&self, pool: &Address) -> bool {
+        let state = self.lock();
+        state.all_dirty || state.dirty.contains(pool) || state.ramping.contains_key(pool)
+    }
+
+    /// The cached snapshots that still hold at `block_number`, for the pools among `pools`
+    /// that haven't changed since they were taken. Empty unless that block's logs have been
+    /// applied.
+    pub fn reusable_snapshots<'a>(
+        &self,
+        block_number: u64,
+        pools: impl IntoIterator<Item = &'a Address>,
+    ) -> HashMap<Address, PoolSnapshot> {
+        let state = self.lock();
+        let cache_is_current = state.synced_block == Some(block_number)
+            && state
+                .cache_block
+                .is_some_and(|cache_block| cache_block <= block_number);
+        if !cache_is_current || state.all_dirty {
+            return HashMap::new();
+        }
+        pools
+            .into_iter()
+            .filter(|pool| !state.dirty.contains(*pool) && !state.ramping.contains_key(*pool))
+            .filter_map(|pool| Some((*pool, state.snapshots.get(pool)?.clone())))
+            .collect()
+    }
+
+    /// Caches the snapshots taken at `block_number` and clears the dirty marks. They're only
+    /// kept if that block's logs have been applied, otherwise changes could be missed. A
+    /// Curve snapshot taken once its pool's ramp has ended holds the final A, so the pool
+    /// stops being dirty on every block.
+    pub fn store_snapshots(&self, block_number: u64, snapshots: &HashMap<Address, PoolSnapshot>) {
+        let mut state = self.lock();
+        state.ramping.retain(|pool, end| match snapshots.get(pool) {
+            Some(PoolSnapshot::Curve(snapshot)) => snapshot.block_timestamp < *end,
+            _ => true,
+        });
+        if state.synced_block == Some(block_number) {
+            state.cache_block = Some(block_number);
+            state.snapshots = snapshots.clone();
+        } else {
+            state.cache_block = None;
+            state.snapshots.clear();
+        }
+        state.dirty.clear();
+        state.all_dirty = false;
+    }
+}
+
+/// The pool whose state a log changes. Balancer vault events carry it in the first 20 bytes
+/// of the pool id, V4 pool manager events in the id the pool's address derives from.
+fn changed_pool(log: &Log) -> Option<Address> {
+    let topics = log.topics();
+    let signature = topics.first()?;
+    if *signature == IVault::Swap::SIGNATURE_HASH
+        || *signature == IVault::PoolBalanceChanged::SIGNATURE_HASH
+    {
+        return topics
+            .get(1)
+            .map(|pool_id| Address::from_slice(&pool_id[..20]));
+    }
+    if *signature == IPoolManager::Swap::SIGNATURE_HASH
+        || *signature == IPoolManager::ModifyLiquidity::SIGNATURE_HASH
+    {
+        return topics.get(1).map(|pool_id| v4_pool_address(*pool_id));
+    }
+    StateUpdater::event_signatures()
+        .contains(signature)
+        .then(|| log.address())
+}
@@ -1,4 +1,5 @@
 use crate::errors::ArbRsError;
+use crate::math::utils::u256_to_f64;
 use crate::math::v3::full_math;
 use alloy_primitives::U256;
 use std::fmt::Debug;
@@ -7,30 +8,29 @@ use std::fmt::Debug;
 /// This allows for different fee structures or custom math for forks.
 #[async_trait::async_trait]
 pub trait V2CalculationStrategy: Debug + Send + Sync {
-    /// Calculates the output amount for an exact input swap.
+    /// Calculates the output amount for an exact input swap. Matches
+    /// `UniswapV2Library.getAmountOut`: `amount_in * fee * reserve_out /
+    /// (reserve_in * denominator + amount_in * fee)`, rounded down.
     fn calculate_tokens_out(
         &self,
         reserve_in: U256,
         reserve_out: U256,
         amount_in: U256,
     ) -> Result<U256, ArbRsError> {
-        if amount_in == U256::ZERO || reserve_in == U256::ZERO || reserve_out == U256::ZERO {
-            return Err(ArbRsError::CalculationError("Invalid input".into()));
+        if amount_in.is_zero() {
+            return Err(ArbRsError::InsufficientInputAmount);
+        }
+        if reserve_in.is_zero() || reserve_out.is_zero() {
+            return Err(ArbRsError::InsufficientLiquidity);
         }
 
-        let fee_bps = self.get_fee_bps();
-        let fee_denominator = U256::from(10000);
-        let fee_numerator = fee_denominator.saturating_sub(U256::from(fee_bps));
-
+        let (fee_numerator, fee_denominator) = self.fee_fraction();
         let amount_in_with_fee = amount_in.checked_mul(fee_numerator).ok_or_else(|| {
             ArbRsError::CalculationError("Overflow calculating amount with fee".to_string())
         })?;
         let denominator = reserve_in
             .checked_mul(fee_denominator)
-            .ok_or_else(|| {
-                ArbRsError::CalculationError("Overflow calculating denominator".to_string())
-            })?
-            .checked_add(amount_in_with_fee)
+            .and_then(|scaled| scaled.checked_add(amount_in_with_fee))
             .ok_or_else(|| {
                 ArbRsError::CalculationError("Overflow calculating denominator".to_string())
             })?;
@@ -39,39 +39,82 @@ pub trait V2CalculationStrategy: Debug + Send + Sync {
             .ok_or_else(|| ArbRsError::CalculationError("mul_div failed".to_string()))
     }
 
-    /// Calculates the required input amount for an exact output swap.
+    /// Calculates the required input amount for an exact output swap. Matches
+    /// `UniswapV2Library.getAmountIn`: `reserve_in * amount_out * denominator /
+    /// ((reserve_out - amount_out) * fee) + 1`, with a single rounding step.
     fn calculate_tokens_in_from_tokens_out(
         &self,
         reserve_in: U256,
         reserve_out: U256,
         amount_out: U256,
     ) -> Result<U256, ArbRsError> {
-        if amount_out == U256::ZERO || reserve_in == U256::ZERO || reserve_out == U256::ZERO {
-            return Err(ArbRsError::CalculationError("Invalid input".into()));
+        if amount_out.is_zero() {
+            return Err(ArbRsError::InsufficientOutputAmount);
         }
-        if amount_out >= reserve_out {
-            return Err(ArbRsError::CalculationError(
-                "Insufficient liquidity for desired output amount".to_string(),
-            ));
+        if reserve_in.is_zero() || reserve_out.is_zero() || amount_out >= reserve_out {
+            return Err(ArbRsError::InsufficientLiquidity);
         }
 
-        let fee_bps = self.get_fee_bps();
-        let fee_denominator = U256::from(10000);
-        let fee_numerator = fee_denominator.saturating_sub(U256::from(fee_bps));
+        let (fee_numerator, fee_denominator) = self.fee_fraction();
+        let numerator_factor = amount_out.checked_mul(fee_denominator).ok_or_else(|| {
+            ArbRsError::CalculationError("Overflow calculating numerator".to_string())
+        })?;
+        let denominator = (reserve_out - amount_out)
+            .checked_mul(fee_numerator)
+            .ok_or_else(|| {
+                ArbRsError::CalculationError("Overflow calculating denominator".to_string())
+            })?;
 
-        let tmp = full_math::mul_div(
-            reserve_in,
-            amount_out,
-            reserve_out.saturating_sub(amount_out),
-        )
-        .ok_or_else(|| ArbRsError::CalculationError("mul_div for tmp failed".to_string()))?;
+        let amount_in = full_math::mul_div(reserve_in, numerator_factor, denominator)
+            .ok_or_else(|| ArbRsError::CalculationError("mul_div failed".to_string()))?;
+        amount_in.checked_add(U256::from(1)).ok_or_else(|| {
+            ArbRsError::CalculationError("Overflow calculating amount in".to_string())
+        })
+    }
 
-        let amount_in =
-            full_math::mul_div(tmp, fee_denominator, fee_numerator).ok_or_else(|| {
-                ArbRsError::CalculationError("mul_div for final amount failed".to_string())
-            })?;
+    /// Like [`Self::calculate_tokens_out`], for curves that need the tokens' decimals. The
+    /// default ignores them.
+    fn calculate_tokens_out_with_decimals(
+        &self,
+        reserve_in: U256,
+        reserve_out: U256,
+        amount_in: U256,
+        _decimals_in: u8,
+        _decimals_out: u8,
+    ) -> Result<U256, ArbRsError> {
+        self.calculate_tokens_out(reserve_in, reserve_out, amount_in)
+    }
 
-        Ok(amount_in.saturating_add(U256::from(1)))
+    /// Like [`Self::calculate_tokens_in_from_tokens_out`], for curves that need the tokens'
+    /// decimals. The default ignores them.
+    fn calculate_tokens_in_from_tokens_out_with_decimals(
+        &self,
+        reserve_in: U256,
+        reserve_out: U256,
+        amount_out: U256,
+        _decimals_in: u8,
+        _decimals_out: u8,
+    ) -> Result<U256, ArbRsError> {
+        self.calculate_tokens_in_from_tokens_out(reserve_in, reserve_out, amount_out)
+    }
+
+    /// Raw units of the output token one raw unit of the input is worth at the margin,
+    /// before fees. The reserve ratio unless the curve isn't constant product.
+    fn spot_price(
+        &self,
+        reserve_in: U256,
+        reserve_out: U256,
+        _decimals_in: u8,
+        _decimals_out: u8,
+    ) -> f64 {
+        u256_to_f64(reserve_out) / u256_to_f64(reserve_in)
+    }
+
+    /// The fraction of the input that remains after fees, as `(numerator, denominator)`.
+    fn fee_fraction(&self) -> (U256, U256) {
+        let fee_denominator = U256::from(10000);
+        let fee_numerator = fee_denominator.saturating_sub(U256::from(self.get_fee_bps()));
+        (fee_numerator, fee_denominator)
     }
 
     fn get_fee_bps(&self) -> u32;
@@ -96,3 +139,440 @@ impl V2CalculationStrategy for PancakeV2Logic {
         25
     }
 }
+
+/// Strategy for V2 forks charging any fee, e.g. 1 bps stable pairs. The fee is
+/// `fee_numerator / fee_denominator` of the input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConfigurableFeeStrategy {
+    fee_numerator: u32,
+    fee_denominator: u32,
+}
+
+impl ConfigurableFeeStrategy {
+    pub fn new(fee_numerator: u32, fee_denominator: u32) -> Result<Self, ArbRsError> {
+        check_fee(fee_numerator, fee_denominator)?;
+        Ok(Self {
+            fee_numerator,
+            fee_denominator,
+        })
+    }
+
+    pub fn fee_numerator(&self) -> u32 {
+        self.fee_numerator
+    }
+
+    pub fn fee_denominator(&self) -> u32 {
+        self.fee_denominator
+    }
+}
+
+impl V2CalculationStrategy for ConfigurableFeeStrategy {
+    fn fee_fraction(&self) -> (U256, U256) {
+        let fee_denominator = U256::from(self.fee_denominator);
+        (
+            fee_denominator - U256::from(self.fee_numerator),
+            fee_denominator,
+        )
+    }
+
+    /// Rounded down, so fees finer than a basis point report less.
+    fn get_fee_bps(&self) -> u32 {
+        (self.fee_numerator as u64 * 10_000 / self.fee_denominator as u64) as u32
+    }
+}
+
+fn overflow(what: &str) -> ArbRsError {
+    ArbRsError::CalculationError(format!("Overflow calculating {what}"))
+}
+
+/// Checks `fee_numerator / fee_denominator` is a fraction below one.
+fn check_fee(fee_numerator: u32, fee_denominator: u32) -> Result<(), ArbRsError> {
+    if fee_denominator == 0 || fee_numerator >= fee_denominator {
+        return Err(ArbRsError::CalculationError(format!(
+            "V2 fee {fee_numerator}/{fee_denominator} is not a fraction below one"
+        )));
+    }
+    Ok(())
+}
+
+/// The input left after a Solidly pair takes its fee: `amount_in - amount_in * fee`,
+/// with the fee rounded down.
+fn solidly_amount_after_fee(
+    amount_in: U256,
+    fee_numerator: u32,
+    fee_denominator: u32,
+) -> Result<U256, ArbRsError> {
+    let fee = amount_in
+        .checked_mul(U256::from(fee_numerator))
+        .ok_or_else(|| overflow("fee"))?
+        / U256::from(fee_denominator);
+    Ok(amount_in - fee)
+}
+
+/// The smallest input that leaves at least `amount` after a Solidly pair's fee.
+fn solidly_amount_before_fee(
+    amount: U256,
+    fee_numerator: u32,
+    fee_denominator: u32,
+) -> Result<U256, ArbRsError> {
+    if amount.is_zero() {
+        return Ok(U256::ZERO);
+    }
+    // `amount_in - floor(amount_in * fee)` is `ceil(amount_in * (1 - fee))`.
+    let kept = U256::from(fee_denominator - fee_numerator);
+    full_math::mul_div(amount - U256::from(1), U256::from(fee_denominator), kept)
+        .and_then(|amount_in| amount_in.checked_add(U256::from(1)))
+        .ok_or_else(|| overflow("amount in"))
+}
+
+/// Strategy for Solidly-style volatile pairs (Velodrome, Aerodrome and their forks). Same
+/// constant product as Uniswap V2, but the fee is taken off the input before the curve,
+/// with its own rounding. The fee is `fee_numerator / fee_denominator` of the input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SolidlyVolatileStrategy {
+    fee_numerator: u32,
+    fee_denominator: u32,
+}
+
+impl SolidlyVolatileStrategy {
+    pub fn new(fee_numerator: u32, fee_denominator: u32) -> Result<Self, ArbRsError> {
+        check_fee(fee_numerator, fee_denominator)?;
+        Ok(Self {
+            fee_numerator,
+            fee_denominator,
+        })
+    }
+
+    pub fn fee_numerator(&self) -> u32 {
+        self.fee_numerator
+    }
+
+    pub fn fee_denominator(&self) -> u32 {
+        self.fee_denominator
+    }
+}
+
+impl V2CalculationStrategy for SolidlyVolatileStrategy {
+    /// Matches the pair's `getAmountOut`: `a * reserve_out / (reserve_in + a)` of the input
+    /// `a` left after the fee.
+    fn calculate_tokens_out(
+        &self,
+        reserve_in: U256,
+        reserve_out: U256,
+        amount_in: U256,
+    ) -> Result<U256, ArbRsError> {
+        if amount_in.is_zero() {
+            return Err(ArbRsError::InsufficientInputAmount);
+        }
+        if reserve_in.is_zero() || reserve_out.is_zero() {
+            return Err(ArbRsError::InsufficientLiquidity);
+        }
+        let amount_in =
+            solidly_amount_after_fee(amount_in, self.fee_numerator, self.fee_denominator)?;
+        let denominator = reserve_in
+            .checked_add(amount_in)
+            .ok_or_else(|| overflow("denominator"))?;
+        full_math::mul_div(amount_in, reserve_out, denominator)
+            .ok_or_else(|| ArbRsError::CalculationError("mul_div failed".to_string()))
+    }
+
+    /// The smallest input [`Self::calculate_tokens_out`] gives `amount_out` for.
+    fn calculate_tokens_in_from_tokens_out(
+        &self,
+        reserve_in: U256,
+        reserve_out: U256,
+        amount_out: U256,
+    ) -> Result<U256, ArbRsError> {
+        if amount_out.is_zero() {
+            return Err(ArbRsError::InsufficientOutputAmount);
+        }
+        if reserve_in.is_zero() || reserve_out.is_zero() || amount_out >= reserve_out {
+            return Err(ArbRsError::InsufficientLiquidity);
+        }
+        // `a * reserve_out / (reserve_in + a) >= amount_out` rounded down holds from
+        // `a = ceil(amount_out * reserve_in / (reserve_out - amount_out))`.
+        let after_fee =
+            full_math::mul_div_rounding_up(amount_out, reserve_in, reserve_out - amount_out)
+                .ok_or_else(|| ArbRsError::CalculationError("mul_div failed".to_string()))?;
+        solidly_amount_before_fee(after_fee, self.fee_numerator, self.fee_denominator)
+    }
+
+    fn fee_fraction(&self) -> (U256, U256) {
+        let fee_denominator = U256::from(self.fee_denominator);
+        (
+            fee_denominator - U256::from(self.fee_numerator),
+            fee_denominator,
+        )
+    }
+
+    /// Rounded down, so fees finer than a basis point report less.
+    fn get_fee_bps(&self) -> u32 {
+        (self.fee_numerator as u64 * 10_000 / self.fee_denominator as u64) as u32
+    }
+}
+
+const SOLIDLY_PRECISION: U256 = U256::from_limbs([1_000_000_000_000_000_000, 0, 0, 0]);
+
+/// Rounds of Newton's method `_get_y` runs before the pair reverts.
+const SOLIDLY_MAX_ROUNDS: usize = 255;
+
+/// Rounds of stepping up the estimate of an exact output's input before giving up.
+const SOLIDLY_MAX_INPUT_STEPS: usize = 64;
+
+/// `x³y + xy³` of reserves normalized to 18 decimals, rounded as the pair's `_f`.
+fn solidly_f(x0: U256, y: U256) -> Result<U256, ArbRsError> {
+    let mul = |a: U256, b: U256| a.checked_mul(b).ok_or_else(|| overflow("stable invariant"));
+    let a = mul(x0, y)? / SOLIDLY_PRECISION;
+    let b = mul(x0, x0)? / SOLIDLY_PRECISION + mul(y, y)? / SOLIDLY_PRECISION;
+    Ok(mul(a, b)? / SOLIDLY_PRECISION)
+}
+
+/// `∂f/∂y`, rounded as the pair's `_d`.
+fn solidly_d(x0: U256, y: U256) -> Result<U256, ArbRsError> {
+    let mul = |a: U256, b: U256| {
+        a.checked_mul(b)
+            .ok_or_else(|| overflow("stable derivative"))
+    };
+    let y2 = mul(y, y)? / SOLIDLY_PRECISION;
+    let x2 = mul(x0, x0)? / SOLIDLY_PRECISION;
+    Ok(mul(mul(U256::from(3), x0)?, y2)? / SOLIDLY_PRECISION + mul(x2, x0)? / SOLIDLY_PRECISION)
+}
+
+/// The `y` keeping `f(x0, y)` at `xy`, found from the starting `y` with the pair's `_get_y`.
+/// The pair's exit check on `y + 1` normalizes again by both tokens' decimals, which only
+/// differs from this for tokens of other than 18 decimals, and then by at most a unit.
+fn solidly_get_y(x0: U256, xy: U256, mut y: U256) -> Result<U256, ArbRsError> {
+    for _ in 0..SOLIDLY_MAX_ROUNDS {
+        let k = solidly_f(x0, y)?;
+        let d = solidly_d(x0, y)?;
+        if d.is_zero() {
+            return Err(ArbRsError::InsufficientLiquidity);
+        }
+        if k < xy {
+            let mut dy = (xy - k)
+                .checked_mul(SOLIDLY_PRECISION)
+                .ok_or_else(|| overflow("stable step"))?
+                / d;
+            if dy.is_zero() {
+                if k == xy {
+                    return Ok(y);
+                }
+                if solidly_f(x0, y + U256::from(1))? > xy {
+                    return Ok(y + U256::from(1));
+                }
+                dy = U256::from(1);
+            }
+            y += dy;
+        } else {
+            let mut dy = (k - xy)
+                .checked_mul(SOLIDLY_PRECISION)
+                .ok_or_else(|| overflow("stable step"))?
+                / d;
+            if dy.is_zero() {
+                if k == xy || y.is_zero() || solidly_f(x0, y - U256::from(1))? < xy {
+                    return Ok(y);
+                }
+                dy = U256::from(1);
+            }
+            y = y.checked_sub(dy).ok_or(ArbRsError::InsufficientLiquidity)?;
+        }
+    }
+    Err(ArbRsError::CalculationError(
+        "Stable swap did not converge".to_string(),
+    ))
+}
+
+fn decimals_scale(decimals: u8) -> U256 {
+    U256::from(10).pow(U256::from(decimals))
+}
+
+/// Strategy for Solidly-style stable pairs (Velodrome, Aerodrome and their forks), which
+/// trade on `x³y + xy³ = k` over reserves normalized to 18 decimals. Quotes match the
+/// pair's `getAmountOut`, with the fee taken off the input first.
+///
+/// The methods without decimals assume both tokens have 18.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SolidlyStableStrategy {
+    fee_numerator: u32,
+    fee_denominator: u32,
+}
+
+impl SolidlyStableStrategy {
+    pub fn new(fee_numerator: u32, fee_denominator: u32) -> Result<Self, ArbRsError> {
+        check_fee(fee_numerator, fee_denominator)?;
+        Ok(Self {
+            fee_numerator,
+            fee_denominator,
+        })
+    }
+
+    pub fn fee_numerator(&self) -> u32 {
+        self.fee_numerator
+    }
+
+    pub fn fee_denominator(&self) -> u32 {
+        self.fee_denominator
+    }
+
+    /// The output of `amount_in`, already net of the fee.
+    fn amount_out_after_fee(
+        reserve_in: U256,
+        reserve_out: U256,
+        amount_in: U256,
+        decimals_in: u8,
+        decimals_out: u8,
+    ) -> Result<U256, ArbRsError> {
+        let (scale_in, scale_out) = (decimals_scale(decimals_in), decimals_scale(decimals_out));
+        let normalize = |amount: U256, scale: U256| {
+            full_math::mul_div(amount, SOLIDLY_PRECISION, scale)
+                .ok_or_else(|| overflow("normalized amount"))
+        };
+        let reserve_a = normalize(reserve_in, scale_in)?;
+        let reserve_b = normalize(reserve_out, scale_out)?;
+        let amount_in = normalize(amount_in, scale_in)?;
+        let xy = solidly_f(reserve_a, reserve_b)?;
+        let x0 = amount_in
+            .checked_add(reserve_a)
+            .ok_or_else(|| overflow("stable reserve"))?;
+        let y = reserve_b
+            .checked_sub(solidly_get_y(x0, xy, reserve_b)?)
+            .ok_or(ArbRsError::InsufficientLiquidity)?;
+        full_math::mul_div(y, scale_out, SOLIDLY_PRECISION)
+            .ok_or_else(|| ArbRsError::CalculationError("mul_div failed".to_string()))
+    }
+}
+
+impl V2CalculationStrategy for SolidlyStableStrategy {
+    fn calculate_tokens_out(
+        &self,
+        reserve_in: U256,
+        reserve_out: U256,
+        amount_in: U256,
+    ) -> Result<U256, ArbRsError> {
+        self.calculate_tokens_out_with_decimals(reserve_in, reserve_out, amount_in, 18, 18)
+    }
+
+    fn calculate_tokens_in_from_tokens_out(
+        &self,
+        reserve_in: U256,
+        reserve_out: U256,
+        amount_out: U256,
+    ) -> Result<U256, ArbRsError> {
+        self.calculate_tokens_in_from_tokens_out_with_decimals(
+            reserve_in,
+            reserve_out,
+            amount_out,
+            18,
+            18,
+        )
+    }
+
+    fn calculate_tokens_out_with_decimals(
+        &self,
+        reserve_in: U256,
+        reserve_out: U256,
+        amount_in: U256,
+        decimals_in: u8,
+        decimals_out: u8,
+    ) -> Result<U256, ArbRsError> {
+        if amount_in.is_zero() {
+            return Err(ArbRsError::InsufficientInputAmount);
+        }
+        if reserve_in.is_zero() || reserve_out.is_zero() {
+            return Err(ArbRsError::InsufficientLiquidity);
+        }
+        let amount_in =
+            solidly_amount_after_fee(amount_in, self.fee_numerator, self.fee_denominator)?;
+        Self::amount_out_after_fee(
+            reserve_in,
+            reserve_out,
+            amount_in,
+            decimals_in,
+            decimals_out,
+        )
+    }
+
+    /// The smallest input [`Self::calculate_tokens_out_with_decimals`] gives `amount_out`
+    /// for: the curve solved for the input reserve, then stepped up past rounding.
+    fn calculate_tokens_in_from_tokens_out_with_decimals(
+        &self,
+        reserve_in: U256,
+        reserve_out: U256,
+        amount_out: U256,
+        decimals_in: u8,
+        decimals_out: u8,
+    ) -> Result<U256, ArbRsError> {
+        if amount_out.is_zero() {
+            return Err(ArbRsError::InsufficientOutputAmount);
+        }
+        if reserve_in.is_zero() || reserve_out.is_zero() || amount_out >= reserve_out {
+            return Err(ArbRsError::InsufficientLiquidity);
+        }
+        let (scale_in, scale_out) = (decimals_scale(decimals_in), decimals_scale(decimals_out));
+        let normalize = |amount: U256, scale: U256| {
+            full_math::mul_div_rounding_up(amount, SOLIDLY_PRECISION, scale)
+                .ok_or_else(|| overflow("normalized amount"))
+        };
+        let reserve_a = normalize(reserve_in, scale_in)?;
+        let reserve_b = normalize(reserve_out, scale_out)?;
+        let amount_out_normalized = normalize(amount_out, scale_out)?;
+        if amount_out_normalized >= reserve_b {
+            return Err(ArbRsError::InsufficientLiquidity);
+        }
+        // The invariant is symmetric, so `_get_y` also solves for the input reserve.
+        let xy = solidly_f(reserve_a, reserve_b)?;
+        let x = solidly_get_y(reserve_b - amount_out_normalized, xy, reserve_a)?;
+        let after_fee = full_math::mul_div_rounding_up(
+            x.saturating_sub(reserve_a).max(U256::from(1)),
+            scale_in,
+            SOLIDLY_PRECISION,
+        )
+        .ok_or_else(|| overflow("amount in"))?;
+
+        let mut amount_in =
+            solidly_amount_before_fee(after_fee, self.fee_numerator, self.fee_denominator)?;
+        for _ in 0..SOLIDLY_MAX_INPUT_STEPS {
+            let quoted = self.calculate_tokens_out_with_decimals(
+                reserve_in,
+                reserve_out,
+                amount_in,
+                decimals_in,
+                decimals_out,
+            )?;
+            if quoted >= amount_out {
+                return Ok(amount_in);
+            }
+            amount_in += U256::from(1);
+        }
+        Err(ArbRsError::CalculationError(
+            "Stable swap input did not converge".to_string(),
+        ))
+    }
+
+    /// `dy/dx` of the invariant, `(3x²y + y³) / (x³ + 3xy²)`, over the normalized reserves.
+    fn spot_price(
+        &self,
+        reserve_in: U256,
+        reserve_out: U256,
+        decimals_in: u8,
+        decimals_out: u8,
+    ) -> f64 {
+        let x = u256_to_f64(reserve_in) / 10f64.powi(decimals_in as i32);
+        let y = u256_to_f64(reserve_out) / 10f64.powi(decimals_out as i32);
+        let price = (3.0 * x * x * y + y * y * y) / (x * x * x + 3.0 * x * y * y);
+        price * 10f64.powi(decimals_out as i32 - decimals_in as i32)
+    }
+
+    fn fee_fraction(&self) -> (U256, U256) {
+        let fee_denominator = U256::from(self.fee_denominator);
+        (
+            fee_denominator - U256::from(self.fee_numerator),
+            fee_denominator,
+        )
+    }
+
+    /// Rounded down, so fees finer than a basis point report less.
+    fn get_fee_bps(&self) -> u32 {
+        (self.fee_numerator as u64 * 10_000 / self.fee_denominator as u64) as u32
+    }
+}
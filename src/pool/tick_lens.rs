@@ -0,0 +1,72 @@
+//! Reads every initialized tick of a V3 bitmap word in one call, through Uniswap's
+//! `TickLens` periphery contract.
+
+use crate::errors::ArbRsError;
+use crate::pool::uniswap_v3::TickInfo;
+use alloy_primitives::{Address, address};
+use alloy_provider::Provider;
+use alloy_rpc_types::{BlockId, TransactionRequest};
+use alloy_sol_types::{SolCall, sol};
+use std::sync::Arc;
+
+/// `TickLens` on Ethereum mainnet.
+pub const UNISWAP_V3_TICK_LENS: Address = address!("bfd8137f7d1516D3ea5cA83523914859ec47F573");
+
+sol! {
+    struct PopulatedTick {
+        int24 tick;
+        int128 liquidityNet;
+        uint128 liquidityGross;
+    }
+
+    function getPopulatedTicksInWord(address pool, int16 tickBitmapIndex) external view returns (PopulatedTick[] populatedTicks);
+}
+
+pub struct TickLensClient<P: ?Sized> {
+    address: Address,
+    provider: Arc<P>,
+}
+
+impl<P: Provider + Send + Sync + 'static + ?Sized> TickLensClient<P> {
+    pub fn new(address: Address, provider: Arc<P>) -> Self {
+        Self { address, provider }
+    }
+
+    pub fn address(&self) -> Address {
+        self.address
+    }
+
+    /// The liquidity of each initialized tick in `pool`'s bitmap word `word`, lowest tick
+    /// first.
+    pub async fn populated_ticks_in_word(
+        &self,
+        pool: Address,
+        word: i16,
+        block: BlockId,
+    ) -> Result<Vec<(i32, TickInfo)>, ArbRsError> {
+        let call = getPopulatedTicksInWordCall {
+            pool,
+            tickBitmapIndex: word,
+        };
+        let request = TransactionRequest::default()
+            .to(self.address)
+            .input(call.abi_encode().into());
+        let bytes = self.provider.call(request).block(block).await?;
+        let populated = getPopulatedTicksInWordCall::abi_decode_returns(&bytes)?;
+
+        let mut ticks: Vec<(i32, TickInfo)> = populated
+            .into_iter()
+            .map(|populated| {
+                (
+                    populated.tick.as_i32(),
+                    TickInfo {
+                        liquidity_gross: populated.liquidityGross,
+                        liquidity_net: populated.liquidityNet,
+                    },
+                )
+            })
+            .collect();
+        ticks.sort_unstable_by_key(|(tick, _)| *tick);
+        Ok(ticks)
+    }
+}
@@ -0,0 +1,105 @@
+//! Reading a tick liquidity map again by bitmap word, shared by the Uniswap V3 and V4
+//! pools. The pools differ only in where a word's bits and ticks are read from and which
+//! logs tell them a word changed; choosing the words and applying what was read is the
+//! same for both.
+
+use crate::errors::ArbRsError;
+use crate::math::v3::{
+    tick::{get_max_tick, get_min_tick},
+    tick_bitmap,
+};
+use crate::pool::uniswap_v3::{MAX_LIQUIDITY_MAP_AGE_BLOCKS, TickInfo};
+use alloy_primitives::U256;
+use futures::{StreamExt, TryStreamExt, stream};
+use std::collections::{BTreeMap, BTreeSet};
+use std::future::Future;
+use std::ops::RangeInclusive;
+
+/// Bitmap words read concurrently when refreshing a liquidity map.
+pub(crate) const WORD_FETCH_CONCURRENCY: usize = 16;
+
+/// A bitmap word's position, its bits and the liquidity of the ticks they mark initialized.
+pub(crate) type TickWord = (i16, U256, Vec<(i32, TickInfo)>);
+
+/// The bitmap word holding `tick`'s initialized bit.
+pub(crate) fn word_position(tick: i32, tick_spacing: i32) -> i16 {
+    tick_bitmap::position(tick_bitmap::compress(tick, tick_spacing)).0
+}
+
+/// The ticks whose initialized bit lives in bitmap word `word`.
+pub(crate) fn word_tick_range(word: i16, tick_spacing: i32) -> RangeInclusive<i32> {
+    let first = word as i32 * 256;
+    first * tick_spacing..=(first + 255) * tick_spacing
+}
+
+/// The ticks `bitmap`, read from word `word`, marks initialized.
+pub(crate) fn initialized_ticks(
+    word: i16,
+    bitmap: U256,
+    tick_spacing: i32,
+) -> impl Iterator<Item = i32> {
+    (0..256)
+        .filter(move |bit| bitmap.bit(*bit))
+        .map(move |bit| (word as i32 * 256 + bit as i32) * tick_spacing)
+}
+
+/// Reads the words a map last read at `map_block` needs to be brought up to
+/// `block_number`: those `touched_words` finds changed since, unless the map is older than
+/// `MAX_LIQUIDITY_MAP_AGE_BLOCKS` or was never read, in which case every word. Returns the
+/// words read and whether they are all of them, or `None` if the map is already current.
+pub(crate) async fn read_words<T, TFut, F, FFut>(
+    map_block: Option<u64>,
+    block_number: u64,
+    tick_spacing: i32,
+    touched_words: T,
+    fetch_word: F,
+) -> Result<Option<(Vec<TickWord>, bool)>, ArbRsError>
+where
+    T: FnOnce(u64, u64) -> TFut,
+    TFut: Future<Output = Result<BTreeSet<i16>, ArbRsError>>,
+    F: Fn(i16) -> FFut,
+    FFut: Future<Output = Result<TickWord, ArbRsError>>,
+{
+    let (words, full) = match map_block {
+        Some(map_block) if map_block >= block_number => return Ok(None),
+        Some(map_block) if block_number - map_block <= MAX_LIQUIDITY_MAP_AGE_BLOCKS => {
+            (touched_words(map_block + 1, block_number).await?, false)
+        }
+        _ => {
+            let min_word = word_position(get_min_tick(tick_spacing), tick_spacing);
+            let max_word = word_position(get_max_tick(tick_spacing), tick_spacing);
+            ((min_word..=max_word).collect(), true)
+        }
+    };
+
+    let fetched = stream::iter(words)
+        .map(fetch_word)
+        .buffer_unordered(WORD_FETCH_CONCURRENCY)
+        .try_collect()
+        .await?;
+    Ok(Some((fetched, full)))
+}
+
+/// Replaces each fetched word's bits and ticks in the map, after clearing it if `full`.
+pub(crate) fn apply_words(
+    tick_bitmap: &mut BTreeMap<i16, U256>,
+    tick_data: &mut BTreeMap<i32, TickInfo>,
+    fetched: &[TickWord],
+    tick_spacing: i32,
+    full: bool,
+) {
+    if full {
+        tick_bitmap.clear();
+        tick_data.clear();
+    }
+    for (word, bitmap, ticks) in fetched {
+        let word_ticks = word_tick_range(*word, tick_spacing);
+        tick_data.retain(|tick, _| !word_ticks.contains(tick));
+        if bitmap.is_zero() {
+            tick_bitmap.remove(word);
+        } else {
+            tick_bitmap.insert(*word, *bitmap);
+        }
+        tick_data.extend(ticks.iter().cloned());
+    }
+}
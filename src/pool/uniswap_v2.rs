@@ -1,28 +1,51 @@
-use crate::core::messaging::{Publisher, PublisherMessage, Subscriber};
+use crate::core::messaging::{Publisher, PublisherMessage, Subscriber, SubscriberList};
 use crate::core::token::{Token, TokenLike};
 use crate::errors::ArbRsError;
 use crate::math::v3::full_math;
+use crate::pool::cache::{CacheConfig, CacheStats};
+use crate::pool::last_trade::{LastTrade, LastTradeTracker};
+use crate::pool::quoter::{QuotePool, Quoter};
+use crate::pool::reserve_drift::ReserveDrift;
 use crate::pool::strategy::V2CalculationStrategy;
 use crate::pool::uniswap_v2_simulation::UniswapV2PoolSimulationResult;
-use crate::pool::{LiquidityPool, PoolSnapshot};
+use crate::pool::{
+    CalibrationBucket, DexKind, LiquidityPool, PoolSnapshot, PriceMatrix, StateUpdate, SwapGasCosts,
+};
 use alloy_primitives::{Address, B256, Bytes, I256, TxKind, U256, keccak256};
 use alloy_provider::Provider;
-use alloy_rpc_types::{BlockId, BlockNumberOrTag, TransactionRequest};
-use alloy_sol_types::{SolCall, sol};
+use alloy_rpc_types::{BlockId, BlockNumberOrTag, Log, TransactionRequest};
+use alloy_sol_types::{SolCall, SolEvent, sol};
 use async_trait::async_trait;
+use futures::{StreamExt, TryStreamExt, stream};
+use serde::{Deserialize, Serialize};
 use std::any::Any;
 use std::collections::BTreeMap;
 use std::fmt::{Debug, Formatter, Result as FmtResult};
 use std::sync::{Arc, Weak};
 use tokio::sync::RwLock;
 
+/// Blocks whose reserves `get_snapshot_range` fetches at once.
+const RANGE_FETCH_CONCURRENCY: usize = 16;
+
+/// Whether a call failed because the node has no block it was made at, in the wording of
+/// geth, erigon, reth or anvil.
+fn is_unknown_block(error: &str) -> bool {
+    let error = error.to_lowercase();
+    ["header not found", "block not found", "unknown block", "blockoutofrange"]
+        .iter()
+        .any(|wording| error.contains(wording))
+}
+
 // ABI Definition
 sol!(
     function getReserves() external view returns (uint112 reserve0, uint112 reserve1, uint32 blockTimestampLast);
+    interface IUniswapV2Pair {
+        event Sync(uint112 reserve0, uint112 reserve1);
+    }
 );
 
 /// Holds the reserves for a Uniswap V2 pool at a specific block.
-#[derive(Clone, Debug, Default)]
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
 pub struct UniswapV2PoolState {
     pub reserve0: U256,
     pub reserve1: U256,
@@ -35,15 +58,91 @@ pub struct UniswapV2PoolSnapshot {
     pub reserve1: U256,
 }
 
+/// A V2 pair's reserves and fee math, quoting without the pool.
+#[derive(Debug, Clone)]
+pub struct UniswapV2Quoter {
+    pub address: Address,
+    pub token0: Address,
+    pub token1: Address,
+    pub decimals0: u8,
+    pub decimals1: u8,
+    pub reserve0: U256,
+    pub reserve1: U256,
+    strategy: Arc<dyn V2CalculationStrategy>,
+}
+
+impl UniswapV2Quoter {
+    fn check_pair(&self, token_in: Address, token_out: Address) -> Result<(), ArbRsError> {
+        if (token_in, token_out) == (self.token0, self.token1)
+            || (token_in, token_out) == (self.token1, self.token0)
+        {
+            Ok(())
+        } else {
+            Err(ArbRsError::CalculationError(
+                "Token pair does not match pool".into(),
+            ))
+        }
+    }
+
+    /// Reserves and decimals of the tokens in and out of a swap.
+    fn sides(&self, zero_for_one: bool) -> (U256, U256, u8, u8) {
+        if zero_for_one {
+            (self.reserve0, self.reserve1, self.decimals0, self.decimals1)
+        } else {
+            (self.reserve1, self.reserve0, self.decimals1, self.decimals0)
+        }
+    }
+}
+
+impl Quoter for UniswapV2Quoter {
+    fn calculate_out(
+        &self,
+        token_in: Address,
+        token_out: Address,
+        amount_in: U256,
+    ) -> Result<U256, ArbRsError> {
+        self.check_pair(token_in, token_out)?;
+        let (reserve_in, reserve_out, decimals_in, decimals_out) =
+            self.sides(token_in == self.token0);
+        self.strategy.calculate_tokens_out_with_decimals(
+            reserve_in,
+            reserve_out,
+            amount_in,
+            decimals_in,
+            decimals_out,
+        )
+    }
+
+    fn calculate_in(
+        &self,
+        token_in: Address,
+        token_out: Address,
+        amount_out: U256,
+    ) -> Result<U256, ArbRsError> {
+        self.check_pair(token_in, token_out)?;
+        let (reserve_in, reserve_out, decimals_in, decimals_out) =
+            self.sides(token_out == self.token1);
+        self.strategy.calculate_tokens_in_from_tokens_out_with_decimals(
+            reserve_in,
+            reserve_out,
+            amount_out,
+            decimals_in,
+            decimals_out,
+        )
+    }
+}
+
 pub struct UniswapV2Pool<P: ?Sized, S: V2CalculationStrategy> {
     address: Address,
     pub token0: Arc<Token<P>>,
     token1: Arc<Token<P>>,
     state: RwLock<UniswapV2PoolState>,
     pub provider: Arc<P>,
-    strategy: S,
+    strategy: Arc<S>,
     state_cache: RwLock<BTreeMap<u64, UniswapV2PoolState>>,
-    subscribers: RwLock<Vec<Weak<dyn Subscriber<P>>>>,
+    cache_config: CacheConfig,
+    subscribers: SubscriberList<P>,
+    last_trades: LastTradeTracker,
 }
 
 #[async_trait]
@@ -51,28 +150,36 @@ impl<P: Provider + Send + Sync + 'static + ?Sized, S: V2CalculationStrategy + 's
     for UniswapV2Pool<P, S>
 {
     async fn subscribe(&self, subscriber: Weak<dyn Subscriber<P>>) {
-        let mut subscribers = self.subscribers.write().await;
-        subscribers.push(subscriber);
+        self.subscribers.subscribe(subscriber).await;
     }
 
     async fn unsubscribe(&self, subscriber_id: usize) {
-        let mut subscribers = self.subscribers.write().await;
-        subscribers.retain(|weak_sub| {
-            if let Some(sub) = weak_sub.upgrade() {
-                sub.id() != subscriber_id
-            } else {
-                false
-            }
-        });
+        self.subscribers.unsubscribe(subscriber_id).await;
     }
 
     async fn notify_subscribers(&self, message: PublisherMessage) {
-        let subscribers = self.subscribers.read().await;
-        for weak_sub in subscribers.iter() {
-            if let Some(sub) = weak_sub.upgrade() {
-                sub.notify(message.clone()).await;
-            }
-        }
+        self.subscribers.notify(message).await;
+    }
+}
+
+impl<P: Provider + Send + Sync + ?Sized + 'static, S: V2CalculationStrategy + 'static> UniswapV2Pool<P, S> {
+    /// The pool's swap math on `snapshot`.
+    fn quoter(&self, snapshot: &PoolSnapshot) -> Result<UniswapV2Quoter, ArbRsError> {
+        let PoolSnapshot::UniswapV2(state) = snapshot else {
+            return Err(ArbRsError::CalculationError(
+                "Invalid snapshot for V2 pool".into(),
+            ));
+        };
+        Ok(UniswapV2Quoter {
+            address: self.address,
+            token0: self.token0.address(),
+            token1: self.token1.address(),
+            decimals0: self.token0.decimals(),
+            decimals1: self.token1.decimals(),
+            reserve0: state.reserve0,
+            reserve1: state.reserve1,
+            strategy: self.strategy.clone(),
+        })
     }
 }
 
@@ -91,12 +198,20 @@ impl<P: Provider + Send + Sync + ?Sized + 'static, S: V2CalculationStrategy> Uni
             token1,
             state: RwLock::new(UniswapV2PoolState::default()),
             provider,
-            strategy,
+            strategy: Arc::new(strategy),
             state_cache: RwLock::new(BTreeMap::new()),
-            subscribers: RwLock::new(Vec::new()),
+            cache_config: CacheConfig::default(),
+            subscribers: SubscriberList::default(),
+            last_trades: LastTradeTracker::default(),
         }
     }
 
+    /// Bounds the state cache to `cache_config.max_blocks` blocks instead of the default.
+    pub fn with_cache_config(mut self, cache_config: CacheConfig) -> Self {
+        self.cache_config = cache_config;
+        self
+    }
+
     /// Calculates swap output using a provided state object, bypassing the internal cached state.
     pub fn calculate_tokens_out_with_override(
         &self,
@@ -106,13 +221,20 @@ impl<P: Provider + Send + Sync + ?Sized + 'static, S: V2CalculationStrategy> Uni
         override_state: &UniswapV2PoolState,
     ) -> Result<U256, ArbRsError> {
         self.validate_token_in(token_in)?;
-        let (reserve_in, reserve_out) = if token_in.address() == self.token0.address() {
+        let zero_for_one = token_in.address() == self.token0.address();
+        let (reserve_in, reserve_out) = if zero_for_one {
             (override_state.reserve0, override_state.reserve1)
         } else {
             (override_state.reserve1, override_state.reserve0)
         };
-        self.strategy
-            .calculate_tokens_out(reserve_in, reserve_out, amount_in)
+        let (decimals_in, decimals_out) = self.decimals(zero_for_one);
+        self.strategy.calculate_tokens_out_with_decimals(
+            reserve_in,
+            reserve_out,
+            amount_in,
+            decimals_in,
+            decimals_out,
+        )
     }
 
     /// Calculates swap input using a provided state object, bypassing the internal cached state.
@@ -124,13 +246,30 @@ impl<P: Provider + Send + Sync + ?Sized + 'static, S: V2CalculationStrategy> Uni
         override_state: &UniswapV2PoolState,
     ) -> Result<U256, ArbRsError> {
         self.validate_token_out(token_out)?;
-        let (reserve_in, reserve_out) = if token_out.address() == self.token1.address() {
+        let zero_for_one = token_out.address() == self.token1.address();
+        let (reserve_in, reserve_out) = if zero_for_one {
             (override_state.reserve0, override_state.reserve1)
         } else {
             (override_state.reserve1, override_state.reserve0)
         };
-        self.strategy
-            .calculate_tokens_in_from_tokens_out(reserve_in, reserve_out, amount_out)
+        let (decimals_in, decimals_out) = self.decimals(zero_for_one);
+        self.strategy.calculate_tokens_in_from_tokens_out_with_decimals(
+            reserve_in,
+            reserve_out,
+            amount_out,
+            decimals_in,
+            decimals_out,
+        )
+    }
+
+    /// Decimals of the tokens in and out of a swap.
+    fn decimals(&self, zero_for_one: bool) -> (u8, u8) {
+        let (decimals0, decimals1) = (self.token0.decimals(), self.token1.decimals());
+        if zero_for_one {
+            (decimals0, decimals1)
+        } else {
+            (decimals1, decimals0)
+        }
     }
 
     /// Returns a clone of the current cached reserves (reserve0, reserve1).
@@ -235,17 +374,9 @@ impl<P: Provider + Send + Sync + ?Sized + 'static, S: V2CalculationStrategy> Uni
         }
     }
 
-    /// Discard states recorded prior to a target block.
-    pub async fn discard_states_before_block(&self, block: u64) {
-        let mut state_cache = self.state_cache.write().await;
-        let keys_to_remove: Vec<u64> = state_cache
-            .keys()
-            .filter(|&&b| b < block)
-            .cloned()
-            .collect();
-        for key in keys_to_remove {
-            state_cache.remove(&key);
-        }
+    /// Every cached state, keyed by block.
+    pub async fn cached_states(&self) -> BTreeMap<u64, UniswapV2PoolState> {
+        self.state_cache.read().await.clone()
     }
 
     pub async fn calculate_tokens_in_from_ratio_out(
@@ -495,12 +626,32 @@ impl<P: Provider + Send + Sync + ?Sized + 'static, S: V2CalculationStrategy> Uni
             input: Some(Bytes::from(call.abi_encode())).into(),
             ..Default::default()
         };
-        let result_bytes = self
+        let result_bytes = match self
             .provider
             .call(request)
             .block(BlockId::Number(BlockNumberOrTag::Number(block_number)))
             .await
-            .map_err(|e| ArbRsError::ProviderError(e.to_string()))?;
+        {
+            Ok(result_bytes) => result_bytes,
+            Err(e) => {
+                // Nodes word a call at a block they don't have differently, and it may be
+                // pruned rather than not mined yet, so the head tells the two apart.
+                if is_unknown_block(&e.to_string())
+                    && let Err(not_mined @ ArbRsError::BlockNotMined { .. }) =
+                        self.check_mined(block_number).await
+                {
+                    return Err(not_mined);
+                }
+                return Err(e.into());
+            }
+        };
+        // A call to an address without code succeeds with no data.
+        if result_bytes.is_empty() {
+            return Err(ArbRsError::PoolNotDeployed {
+                pool: self.address,
+                block: block_number,
+            });
+        }
         let decoded = getReservesCall::abi_decode_returns(&result_bytes)
             .map_err(|e| ArbRsError::AbiDecodeError(e.to_string()))?;
         Ok(UniswapV2PoolState {
@@ -510,6 +661,95 @@ impl<P: Provider + Send + Sync + ?Sized + 'static, S: V2CalculationStrategy> Uni
         })
     }
 
+    /// Each token's balance in the pair minus its stored reserve at `block_number`.
+    /// Positive drift is what `skim` would send out.
+    pub async fn reserve_drift(&self, block_number: u64) -> Result<(I256, I256), ArbRsError> {
+        let drift = self.fetch_reserve_drift(block_number).await?;
+        Ok((drift.drift0, drift.drift1))
+    }
+
+    /// [`Self::reserve_drift`] along with the reserves it was measured against.
+    pub async fn fetch_reserve_drift(&self, block_number: u64) -> Result<ReserveDrift, ArbRsError> {
+        let (state, balance0, balance1) = tokio::try_join!(
+            self._fetch_state_at_block(block_number),
+            self.token0.get_balance(self.address, Some(block_number)),
+            self.token1.get_balance(self.address, Some(block_number)),
+        )?;
+        let drift = |balance: U256, reserve: U256| {
+            I256::from_raw(balance).saturating_sub(I256::from_raw(reserve))
+        };
+        Ok(ReserveDrift {
+            pool: self.address,
+            block: block_number,
+            token0: self.token0.address(),
+            token1: self.token1.address(),
+            reserve0: state.reserve0,
+            reserve1: state.reserve1,
+            drift0: drift(balance0, state.reserve0),
+            drift1: drift(balance1, state.reserve1),
+        })
+    }
+
+    /// Fails with `BlockNotMined` if `block_number` is past the chain head.
+    async fn check_mined(&self, block_number: u64) -> Result<(), ArbRsError> {
+        let latest = self.provider.get_block_number().await?;
+        if block_number > latest {
+            return Err(ArbRsError::BlockNotMined {
+                requested: block_number,
+                latest,
+            });
+        }
+        Ok(())
+    }
+
+    /// The reserves at `block_number`, from the state cache, or else fetched and cached.
+    pub async fn state_at_block(&self, block_number: u64) -> Result<UniswapV2PoolState, ArbRsError> {
+        if let Some(state) = self.state_cache.read().await.get(&block_number) {
+            return Ok(state.clone());
+        }
+        self.fetch_and_cache_state_at_block(block_number).await
+    }
+
+    /// The reserves at every block of `from..=to`, e.g. to backtest a path over them. Blocks
+    /// missing from the state cache are fetched several at a time and cached.
+    pub async fn get_snapshot_range(
+        &self,
+        from: u64,
+        to: u64,
+    ) -> Result<BTreeMap<u64, UniswapV2PoolState>, ArbRsError> {
+        if from > to {
+            return Err(ArbRsError::CalculationError(format!(
+                "Empty block range {from}..={to}"
+            )));
+        }
+        let mut states: BTreeMap<u64, UniswapV2PoolState> = self
+            .state_cache
+            .read()
+            .await
+            .range(from..=to)
+            .map(|(block, state)| (*block, state.clone()))
+            .collect();
+        let missing: Vec<u64> = (from..=to)
+            .filter(|block| !states.contains_key(block))
+            .collect();
+        if let Some(&last) = missing.last() {
+            self.check_mined(last).await?;
+        }
+
+        let fetched: Vec<UniswapV2PoolState> = stream::iter(missing)
+            .map(|block| self._fetch_state_at_block(block))
+            .buffer_unordered(RANGE_FETCH_CONCURRENCY)
+            .try_collect()
+            .await?;
+        let mut cache = self.state_cache.write().await;
+        for state in fetched {
+            self.cache_config
+                .insert(&mut *cache, state.block_number, state.clone());
+            states.insert(state.block_number, state);
+        }
+        Ok(states)
+    }
+
     /// Fetches state at a specific block and adds it to the cache.
     /// Used for populating historical data for simulations.
     pub async fn fetch_and_cache_state_at_block(
@@ -518,11 +758,55 @@ impl<P: Provider + Send + Sync + ?Sized + 'static, S: V2CalculationStrategy> Uni
     ) -> Result<UniswapV2PoolState, ArbRsError> {
         let new_state = self._fetch_state_at_block(block_number).await?;
         let mut cache = self.state_cache.write().await;
-        cache.insert(block_number, new_state.clone());
+        self.cache_config
+            .insert(&mut *cache, block_number, new_state.clone());
         Ok(new_state)
     }
 }
 
+impl<P: Provider + Send + Sync + ?Sized + 'static, S: V2CalculationStrategy + 'static>
+    UniswapV2Pool<P, S>
+{
+    /// Sets the reserves from a `Sync` event, which carries them exactly as they are after
+    /// the trade, instead of calling `getReserves`. Logs older than the current state are
+    /// ignored; logs of the same block are applied, since a later one supersedes the earlier.
+    pub async fn apply_sync_log(&self, log: &Log) -> Result<(), ArbRsError> {
+        if log.address() != self.address {
+            return Err(ArbRsError::CalculationError(format!(
+                "Sync log from {} does not belong to pool {}",
+                log.address(),
+                self.address
+            )));
+        }
+        let sync = IUniswapV2Pair::Sync::decode_log_data(log.data())?;
+        let block_number = log
+            .block_number
+            .ok_or_else(|| ArbRsError::CalculationError("Sync log has no block number".into()))?;
+
+        let new_state = UniswapV2PoolState {
+            reserve0: U256::from(sync.reserve0),
+            reserve1: U256::from(sync.reserve1),
+            block_number,
+        };
+        {
+            let mut state = self.state.write().await;
+            if block_number < state.block_number {
+                return Ok(());
+            }
+            *state = new_state.clone();
+        }
+        self.cache_config.insert(
+            &mut *self.state_cache.write().await,
+            block_number,
+            new_state.clone(),
+        );
+
+        self.notify_subscribers(PublisherMessage::PoolStateUpdate(new_state))
+            .await;
+        Ok(())
+    }
+}
+
 #[async_trait]
 impl<P: Provider + Send + Sync + ?Sized + 'static, S: V2CalculationStrategy + 'static>
     LiquidityPool<P> for UniswapV2Pool<P, S>
@@ -535,21 +819,84 @@ impl<P: Provider + Send + Sync + ?Sized + 'static, S: V2CalculationStrategy + 's
         vec![self.token0.clone(), self.token1.clone()]
     }
 
+    fn reserves_summary(&self, snapshot: &PoolSnapshot) -> Vec<(Arc<Token<P>>, U256)> {
+        let PoolSnapshot::UniswapV2(state) = snapshot else {
+            return Vec::new();
+        };
+        vec![
+            (self.token0.clone(), state.reserve0),
+            (self.token1.clone(), state.reserve1),
+        ]
+    }
+
     fn as_any(&self) -> &dyn Any {
         self
     }
 
-    async fn update_state(&self) -> Result<(), ArbRsError> {
-        let latest_block = self
-            .provider
-            .get_block_number()
-            .await
-            .map_err(|e| ArbRsError::ProviderError(e.to_string()))?;
+    fn last_trade_tracker(&self) -> Option<&LastTradeTracker> {
+        Some(&self.last_trades)
+    }
+
+    fn prices_matrix(&self, snapshot: &PoolSnapshot) -> PriceMatrix {
+        let PoolSnapshot::UniswapV2(state) = snapshot else {
+            return PriceMatrix::new();
+        };
+        if state.reserve0.is_zero() || state.reserve1.is_zero() {
+            return PriceMatrix::new();
+        }
+        let price = self.strategy.spot_price(
+            state.reserve0,
+            state.reserve1,
+            self.token0.decimals(),
+            self.token1.decimals(),
+        );
+        let (token0, token1) = (self.token0.address(), self.token1.address());
+        PriceMatrix::from([((token0, token1), price), ((token1, token0), 1.0 / price)])
+    }
+
+    fn dex_kind(&self) -> Option<DexKind> {
+        Some(DexKind::UniswapV2)
+    }
+
+    fn gas_estimate(
+        &self,
+        _token_in: &Token<P>,
+        _token_out: &Token<P>,
+        _amount_in: U256,
+        _snapshot: &PoolSnapshot,
+        costs: &SwapGasCosts,
+    ) -> u64 {
+        costs.uniswap_v2
+    }
+
+    fn calibration_bucket(&self) -> Option<CalibrationBucket> {
+        Some(CalibrationBucket::new(
+            "uniswap_v2",
+            format!("{}bps", self.strategy.get_fee_bps()),
+        ))
+    }
+
+    fn record_swap_log(&self, log: &Log) -> bool {
+        if log.address() != self.address {
+            return false;
+        }
+        match LastTrade::from_v2_swap(log, self.token0.address(), self.token1.address()) {
+            Some(trade) => {
+                self.last_trades.record(trade);
+                true
+            }
+            None => false,
+        }
+    }
+
+    async fn update_state(&self) -> Result<StateUpdate, ArbRsError> {
+        let latest_block = self.provider.get_block_number().await?;
 
         let current_block_number = self.state.read().await.block_number;
 
         if latest_block < current_block_number {
             return Err(ArbRsError::LateUpdateError {
+                pool: self.address,
                 attempted_block: latest_block,
                 latest_block: current_block_number,
             });
@@ -562,12 +909,7 @@ impl<P: Provider + Send + Sync + ?Sized + 'static, S: V2CalculationStrategy + 's
             ..Default::default()
         };
 
-        let result_bytes = self
-            .provider
-            .call(request)
-            .block(BlockId::latest())
-            .await
-            .map_err(|e| ArbRsError::ProviderError(e.to_string()))?;
+        let result_bytes = self.provider.call(request).block(BlockId::latest()).await?;
 
         let decoded = getReservesCall::abi_decode_returns(&result_bytes)
             .map_err(|e| ArbRsError::AbiDecodeError(e.to_string()))?;
@@ -587,19 +929,74 @@ impl<P: Provider + Send + Sync + ?Sized + 'static, S: V2CalculationStrategy + 's
         };
 
         if state_updated {
-            let mut state_writer = self.state.write().await;
-            *state_writer = new_state.clone();
-
-            let mut cache = self.state_cache.write().await;
-            cache.insert(latest_block, new_state.clone());
-
+            *self.state.write().await = new_state.clone();
+            self.cache_config.insert(
+                &mut *self.state_cache.write().await,
+                latest_block,
+                new_state.clone(),
+            );
+
+            // Notified with no lock held, so a subscriber can read the pool back.
             self.notify_subscribers(PublisherMessage::PoolStateUpdate(new_state))
                 .await;
+            return Ok(StateUpdate::Updated {
+                block: latest_block,
+            });
         }
 
+        Ok(StateUpdate::Unchanged)
+    }
+
+    async fn update_state_at_block(
+        &self,
+        block_number: u64,
+        allow_rewind: bool,
+    ) -> Result<(), ArbRsError> {
+        let current_block_number = self.state.read().await.block_number;
+        if block_number < current_block_number && !allow_rewind {
+            return Err(ArbRsError::LateUpdateError {
+                pool: self.address,
+                attempted_block: block_number,
+                latest_block: current_block_number,
+            });
+        }
+
+        let new_state = self._fetch_state_at_block(block_number).await?;
+        {
+            let mut cache = self.state_cache.write().await;
+            if allow_rewind {
+                cache.retain(|&block, _| block <= block_number);
+            }
+            self.cache_config
+                .insert(&mut *cache, block_number, new_state.clone());
+        }
+        *self.state.write().await = new_state.clone();
+
+        self.notify_subscribers(PublisherMessage::PoolStateUpdate(new_state))
+            .await;
         Ok(())
     }
 
+    async fn invalidate_from(&self, block_number: u64) {
+        if self.restore_state_before_block(block_number).await.is_err() {
+            // Nothing cached from before the reorg: keep the reserves until they're read
+            // again, but let an update at the replacement block through.
+            let mut state = self.state.write().await;
+            state.block_number = state.block_number.min(block_number.saturating_sub(1));
+        }
+    }
+
+    async fn discard_states_before_block(&self, block: u64) {
+        self.state_cache
+            .write()
+            .await
+            .retain(|&cached, _| cached >= block);
+    }
+
+    async fn cache_stats(&self) -> CacheStats {
+        CacheStats::of(self.state_cache.read().await.keys())
+    }
+
     fn calculate_tokens_out(
         &self,
         token_in: &Token<P>,
@@ -607,24 +1004,8 @@ impl<P: Provider + Send + Sync + ?Sized + 'static, S: V2CalculationStrategy + 's
         amount_in: U256,
         snapshot: &PoolSnapshot,
     ) -> Result<U256, ArbRsError> {
-        self.validate_token_pair(token_in, token_out)?;
-        let v2_snapshot = match snapshot {
-            PoolSnapshot::UniswapV2(s) => s,
-            _ => {
-                return Err(ArbRsError::CalculationError(
-                    "Invalid snapshot for V2 pool".into(),
-                ));
-            }
-        };
-
-        let (reserve_in, reserve_out) = if token_in.address() == self.token0.address() {
-            (v2_snapshot.reserve0, v2_snapshot.reserve1)
-        } else {
-            (v2_snapshot.reserve1, v2_snapshot.reserve0)
-        };
-
-        self.strategy
-            .calculate_tokens_out(reserve_in, reserve_out, amount_in)
+        self.quoter(snapshot)?
+            .calculate_out(token_in.address(), token_out.address(), amount_in)
     }
 
     fn calculate_tokens_in(
@@ -634,24 +1015,12 @@ impl<P: Provider + Send + Sync + ?Sized + 'static, S: V2CalculationStrategy + 's
         amount_out: U256,
         snapshot: &PoolSnapshot,
     ) -> Result<U256, ArbRsError> {
-        self.validate_token_pair(token_in, token_out)?;
-        let v2_snapshot = match snapshot {
-            PoolSnapshot::UniswapV2(s) => s,
-            _ => {
-                return Err(ArbRsError::CalculationError(
-                    "Invalid snapshot for V2 pool".into(),
-                ));
-            }
-        };
-
-        let (reserve_in, reserve_out) = if token_out.address() == self.token1.address() {
-            (v2_snapshot.reserve0, v2_snapshot.reserve1)
-        } else {
-            (v2_snapshot.reserve1, v2_snapshot.reserve0)
-        };
+        self.quoter(snapshot)?
+            .calculate_in(token_in.address(), token_out.address(), amount_out)
+    }
 
-        self.strategy
-            .calculate_tokens_in_from_tokens_out(reserve_in, reserve_out, amount_out)
+    fn to_quoter(&self, snapshot: &PoolSnapshot) -> Result<QuotePool, ArbRsError> {
+        Ok(QuotePool::UniswapV2(self.quoter(snapshot)?))
     }
 
     async fn absolute_price(
@@ -661,7 +1030,8 @@ impl<P: Provider + Send + Sync + ?Sized + 'static, S: V2CalculationStrategy + 's
     ) -> Result<f64, ArbRsError> {
         self.validate_token_pair(token_in, token_out)?;
         let current_state = self.state.read().await;
-        let (reserve_in, reserve_out) = if token_in.address() == self.token0.address() {
+        let zero_for_one = token_in.address() == self.token0.address();
+        let (reserve_in, reserve_out) = if zero_for_one {
             (current_state.reserve0, current_state.reserve1)
         } else {
             (current_state.reserve1, current_state.reserve0)
@@ -672,14 +1042,10 @@ impl<P: Provider + Send + Sync + ?Sized + 'static, S: V2CalculationStrategy + 's
                 "Cannot calculate price: input reserve is zero".into(),
             ));
         }
-        let reserve_in_f64 = reserve_in.to_string().parse::<f64>().unwrap_or(0.0);
-        let reserve_out_f64 = reserve_out.to_string().parse::<f64>().unwrap_or(0.0);
-        if reserve_in_f64 == 0.0 {
-            return Err(ArbRsError::CalculationError(
-                "Cannot calculate price: reserve conversion failed or is zero".into(),
-            ));
-        }
-        Ok(reserve_out_f64 / reserve_in_f64)
+        let (decimals_in, decimals_out) = self.decimals(zero_for_one);
+        Ok(self
+            .strategy
+            .spot_price(reserve_in, reserve_out, decimals_in, decimals_out))
     }
 
     async fn nominal_price(
@@ -705,27 +1071,32 @@ impl<P: Provider + Send + Sync + ?Sized + 'static, S: V2CalculationStrategy + 's
         }
     }
 
+    /// Reads through the state cache when `block_number` is given.
     async fn get_snapshot(&self, block_number: Option<u64>) -> Result<PoolSnapshot, ArbRsError> {
-        let call = getReservesCall {};
-        let request = TransactionRequest::default()
-            .to(self.address)
-            .input(call.abi_encode().into());
+        crate::metrics::observe_snapshot(Some(DexKind::UniswapV2), async {
+            if let Some(block_number) = block_number {
+                return Ok(PoolSnapshot::UniswapV2(
+                    self.state_at_block(block_number).await?,
+                ));
+            }
+            let call = getReservesCall {};
+            let request = TransactionRequest::default()
+                .to(self.address)
+                .input(call.abi_encode().into());
 
-        let result_bytes = self
-            .provider
-            .call(request)
-            .block(block_number.map(BlockId::from).unwrap_or(BlockId::latest()))
-            .await?;
+            let result_bytes = self.provider.call(request).block(BlockId::latest()).await?;
 
-        let reserves = getReservesCall::abi_decode_returns(&result_bytes)?;
+            let reserves = getReservesCall::abi_decode_returns(&result_bytes)?;
 
-        let snapshot = UniswapV2PoolState {
-            reserve0: U256::from(reserves.reserve0),
-            reserve1: U256::from(reserves.reserve1),
-            block_number: block_number.unwrap_or(0),
-        };
+            let snapshot = UniswapV2PoolState {
+                reserve0: U256::from(reserves.reserve0),
+                reserve1: U256::from(reserves.reserve1),
+                block_number: 0,
+            };
 
-        Ok(PoolSnapshot::UniswapV2(snapshot))
+            Ok(PoolSnapshot::UniswapV2(snapshot))
+        })
+        .await
     }
 }
 
@@ -766,12 +1137,17 @@ impl<P: Provider + Send + Sync + ?Sized + 'static> LiquidityPool<P>
         vec![self.token0.clone(), self.token1.clone()]
     }
 
+    /// Nothing is known of an unregistered pool's reserves.
+    fn reserves_summary(&self, _snapshot: &PoolSnapshot) -> Vec<(Arc<Token<P>>, U256)> {
+        Vec::new()
+    }
+
     fn as_any(&self) -> &dyn Any {
         self
     }
 
-    async fn update_state(&self) -> Result<(), ArbRsError> {
-        Ok(())
+    async fn update_state(&self) -> Result<StateUpdate, ArbRsError> {
+        Ok(StateUpdate::Unchanged)
     }
 
     fn calculate_tokens_out(
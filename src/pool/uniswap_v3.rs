@@ -1,23 +1,39 @@
 use crate::TokenLike;
+use crate::core::messaging::{Publisher, PublisherMessage, Subscriber, SubscriberList};
 use crate::core::token::Token;
-use crate::errors::ArbRsError;
-use crate::math::v3::tick_bitmap::position;
+use crate::errors::{ArbRsError, PoolContext, WithContext};
+use crate::math::utils::u256_to_f64;
 use crate::math::v3::{
-    constants::{MAX_SQRT_RATIO, MAX_TICK, MIN_SQRT_RATIO, MIN_TICK},
-    liquidity_math, swap_math, tick_bitmap,
+    constants::{MAX_SQRT_RATIO, MIN_SQRT_RATIO},
+    full_math::mul_div,
+    liquidity_math,
+    sqrt_price_math::Q96,
+    swap_math,
+    tick::{get_max_tick, get_min_tick},
+    tick_bitmap,
     tick_math::{self},
 };
-use crate::pool::uniswap_v3_snapshot::{LiquidityMap, UniswapV3PoolLiquidityMappingUpdate};
-use crate::pool::{LiquidityPool, PoolSnapshot};
-use alloy_primitives::{Address, Bytes, I256, U256};
+use crate::pool::cache::{CacheConfig, CacheStats};
+use crate::pool::last_trade::{LastTrade, LastTradeTracker};
+use crate::pool::quoter::{QuotePool, Quoter};
+use crate::pool::tick_lens::TickLensClient;
+use crate::pool::tick_words::{self, TickWord};
+use crate::pool::uniswap_v3_snapshot::{
+    Burn, LiquidityMap, Mint, UniswapV3PoolLiquidityMappingUpdate, apply_liquidity_update,
+};
+use crate::pool::{
+    CalibrationBucket, DexKind, LiquidityPool, PoolSnapshot, PriceMatrix, StateUpdate, SwapGasCosts,
+};
+use alloy_primitives::{Address, Bytes, I256, U256, aliases::I24};
 use alloy_provider::Provider;
-use alloy_rpc_types::{BlockId, TransactionRequest};
-use alloy_sol_types::{SolCall, sol};
+use alloy_rpc_types::{BlockId, Filter, Log, TransactionRequest};
+use alloy_sol_types::{SolCall, SolEvent, sol};
 use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
 use std::any::Any;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 use std::fmt::{Debug, Formatter, Result as FmtResult};
-use std::sync::Arc;
+use std::sync::{Arc, Weak};
 use tokio::sync::RwLock;
 
 // ABI Definition for slot0 and liquidity
@@ -28,7 +44,11 @@ sol! {
     function ticks(int24 tick) external view returns (uint128 liquidityGross, int128 liquidityNet, uint256 feeGrowthOutside0X128, uint256 feeGrowthOutside1X128, int56 tickCumulativeOutside, uint160 secondsPerLiquidityOutsideX128, uint32 secondsOutside, bool initialized);
 }
 
-#[derive(Clone, Debug, Default, PartialEq)]
+/// Age beyond which a liquidity map is read again in full rather than only the words its
+/// `Mint`/`Burn` logs touched since.
+pub const MAX_LIQUIDITY_MAP_AGE_BLOCKS: u64 = 7_200;
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
 pub struct TickInfo {
     pub liquidity_gross: u128,
     pub liquidity_net: i128,
@@ -39,18 +59,39 @@ pub struct UniswapV3PoolState {
     pub liquidity: u128,
     pub sqrt_price_x96: U256,
     pub tick: i32,
+    /// `feeProtocol` from slot0, see [`UniswapV3PoolSnapshot::fee_protocol`].
+    pub fee_protocol: u8,
     pub block_number: u64,
     pub tick_bitmap: BTreeMap<i16, U256>,
     pub tick_data: BTreeMap<i32, TickInfo>,
 }
 
-#[derive(Clone, Debug, Default)]
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct UniswapV3PoolSnapshot {
     pub sqrt_price_x96: U256,
     pub tick: i32,
     pub liquidity: u128,
     pub tick_bitmap: BTreeMap<i16, U256>,
     pub tick_data: BTreeMap<i32, TickInfo>,
+    /// `feeProtocol` from slot0: the protocol's share of swap fees as `1/n`, for swaps
+    /// selling token0 in the low four bits and token1 in the high four, 0 when off.
+    #[serde(default)]
+    pub fee_protocol: u8,
+}
+
+impl UniswapV3PoolSnapshot {
+    /// The virtual reserves `L / sqrt(P)` of token0 and `L * sqrt(P)` of token1 backing the
+    /// in-range liquidity.
+    pub fn virtual_reserves(&self) -> (U256, U256) {
+        if self.sqrt_price_x96.is_zero() {
+            return (U256::ZERO, U256::ZERO);
+        }
+        let liquidity = U256::from(self.liquidity);
+        (
+            mul_div(liquidity, Q96, self.sqrt_price_x96).unwrap_or(U256::MAX),
+            mul_div(liquidity, self.sqrt_price_x96, Q96).unwrap_or(U256::MAX),
+        )
+    }
 }
 
 /// Represents the state of a swap calculation as it progresses
@@ -69,106 +110,187 @@ pub struct UniswapV3PoolSimulationResult {
     pub amount1_delta: I256,
     pub initial_state: UniswapV3PoolState,
     pub final_state: UniswapV3PoolState,
+    /// False if the pool ran out of liquidity before the specified amount was swapped.
+    /// The deltas then only reflect the filled portion.
+    pub fully_filled: bool,
+    /// Swap fee, in the input token, left to the liquidity providers.
+    pub lp_fee: U256,
+    /// Swap fee, in the input token, set aside for the protocol when `fee_protocol` is on.
+    /// It is split off the fee the swap pays anyway, so the deltas don't depend on it.
+    pub protocol_fee: U256,
 }
 
-pub struct UniswapV3Pool<P: ?Sized> {
-    address: Address,
-    token0: Arc<Token<P>>,
-    token1: Arc<Token<P>>,
+/// The raw output of a swap loop, before it is mapped to token amounts.
+pub(crate) struct SwapOutcome {
+    pub(crate) amount0_delta: I256,
+    pub(crate) amount1_delta: I256,
+    pub(crate) final_state: UniswapV3PoolSnapshot,
+    pub(crate) amount_specified_remaining: I256,
+    pub(crate) initialized_ticks_crossed: u64,
+    lp_fee: U256,
+    protocol_fee: U256,
+}
+
+/// The part of a pool its swaps depend on besides its state. V4 pools run the same loop,
+/// with the fee their swap pays in the direction of the swap.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct SwapMath {
+    pool: Address,
     fee: u32,
     tick_spacing: i32,
-    pub state: RwLock<UniswapV3PoolState>,
-    provider: Arc<P>,
-    state_cache: RwLock<BTreeMap<u64, UniswapV3PoolState>>,
-    _min_word: i16,
-    _max_word: i16,
+    /// Bitmap words a swap may search for initialized ticks, the current one included.
+    max_swap_words: Option<u32>,
 }
 
-impl<P: Provider + Send + Sync + 'static + ?Sized> UniswapV3Pool<P> {
-    pub fn new(
-        address: Address,
-        token0: Arc<Token<P>>,
-        token1: Arc<Token<P>>,
+impl SwapMath {
+    pub(crate) fn new(
+        pool: Address,
         fee: u32,
         tick_spacing: i32,
-        provider: Arc<P>,
-        initial_liquidity_map: Option<LiquidityMap>,
+        max_swap_words: Option<u32>,
     ) -> Self {
-        let (tick_bitmap, tick_data) = match initial_liquidity_map {
-            Some(map) => (map.tick_bitmap, map.tick_data),
-            None => (BTreeMap::new(), BTreeMap::new()),
-        };
-
-        let (min_word, _) = position(MIN_TICK / tick_spacing);
-        let (max_word, _) = position(MAX_TICK / tick_spacing);
-
         Self {
-            address,
-            token0,
-            token1,
+            pool,
             fee,
             tick_spacing,
-            state: RwLock::new(UniswapV3PoolState {
-                tick_bitmap,
-                tick_data,
-                ..Default::default()
-            }),
-            provider,
-            state_cache: RwLock::new(BTreeMap::new()),
-            _min_word: min_word,
-            _max_word: max_word,
+            max_swap_words,
         }
     }
 
-    fn validate_token_pair(
+    /// Tightens a swap's price limit to the price at the pool's usable tick range boundary.
+    /// No position can be minted outside `[get_min_tick, get_max_tick]` for the pool's tick
+    /// spacing, so a swap that reaches that boundary has exhausted all liquidity.
+    /// With `max_swap_words` set, the limit is also kept within the words a swap may search
+    /// from `last_word`, and the swap ends there as if the liquidity ran out.
+    fn bounded_price_limit(
         &self,
-        token_a: &Token<P>,
-        token_b: &Token<P>,
-    ) -> Result<(), ArbRsError> {
-        if !((token_a.address() == self.token0.address()
-            && token_b.address() == self.token1.address())
-            || (token_a.address() == self.token1.address()
-                && token_b.address() == self.token0.address()))
-        {
-            Err(ArbRsError::CalculationError(
-                "Token pair does not match pool".into(),
-            ))
+        zero_for_one: bool,
+        sqrt_price_limit_x96: U256,
+        last_word: i16,
+    ) -> Result<U256, ArbRsError> {
+        let spacing = self.tick_spacing;
+        Ok(if zero_for_one {
+            let boundary = get_min_tick(spacing).max(last_word as i32 * 256 * spacing);
+            sqrt_price_limit_x96.max(tick_math::get_sqrt_ratio_at_tick(boundary)?)
         } else {
-            Ok(())
-        }
+            let boundary = get_max_tick(spacing).min((last_word as i32 + 1) * 256 * spacing);
+            sqrt_price_limit_x96.min(tick_math::get_sqrt_ratio_at_tick(boundary)?)
+        })
     }
 
-    /// Applies an update to the liquidity map.
-    pub async fn update_liquidity_map(&self, update: UniswapV3PoolLiquidityMappingUpdate) {
-        let mut state = self.state.write().await;
+    /// The furthest bitmap word a swap from `tick` searches: the word of the usable tick
+    /// range's end, or the last of `max_swap_words` words when that comes first.
+    pub(crate) fn last_word(&self, zero_for_one: bool, tick: i32) -> i16 {
+        let (current, _) = tick_bitmap::position(tick_bitmap::compress(tick, self.tick_spacing));
+        let end = if zero_for_one {
+            get_min_tick(self.tick_spacing)
+        } else {
+            get_max_tick(self.tick_spacing)
+        };
+        let (end, _) = tick_bitmap::position(tick_bitmap::compress(end, self.tick_spacing));
+        match self.max_swap_words {
+            Some(words) => {
+                let reach = words.saturating_sub(1).min(i16::MAX as u32) as i32;
+                if zero_for_one {
+                    end.max((current as i32 - reach).max(i16::MIN as i32) as i16)
+                } else {
+                    end.min((current as i32 + reach).min(i16::MAX as i32) as i16)
+                }
+            }
+            None => end,
+        }
+    }
 
-        if update.tick_lower <= state.tick && state.tick < update.tick_upper {
-            state.liquidity =
-                liquidity_math::add_delta(state.liquidity, update.liquidity).unwrap_or(0);
+    /// Refuses a swap from `snapshot` that has no liquidity in range and no initialized
+    /// tick up to `last_word` to bring some.
+    pub(crate) fn check_liquidity(
+        &self,
+        zero_for_one: bool,
+        snapshot: &UniswapV3PoolSnapshot,
+        last_word: i16,
+    ) -> Result<(), ArbRsError> {
+        if snapshot.liquidity == 0
+            && self
+                .next_initialized_tick(zero_for_one, snapshot.tick, last_word, snapshot)
+                .is_none()
+        {
+            return Err(ArbRsError::NoLiquidity { pool: self.pool });
         }
+        Ok(())
+    }
 
-        let lower_tick_info = state.tick_data.entry(update.tick_lower).or_default();
-        lower_tick_info.liquidity_gross =
-            (lower_tick_info.liquidity_gross as i128 + update.liquidity) as u128;
-        lower_tick_info.liquidity_net += update.liquidity;
+    /// The next initialized tick from `tick` in the swap direction, searching no further
+    /// than bitmap word `last_word`.
+    fn next_initialized_tick(
+        &self,
+        zero_for_one: bool,
+        tick: i32,
+        last_word: i16,
+        snapshot: &UniswapV3PoolSnapshot,
+    ) -> Option<i32> {
+        // Searching up starts from the next spacing, which may be in the next word.
+        let compressed = tick_bitmap::compress(tick, self.tick_spacing);
+        let (word_pos, _) = tick_bitmap::position(if zero_for_one {
+            compressed
+        } else {
+            compressed + 1
+        });
+        let bitmap = snapshot
+            .tick_bitmap
+            .get(&word_pos)
+            .copied()
+            .unwrap_or_default();
+        if let Some((found_tick, _)) = tick_bitmap::next_initialized_tick_within_one_word(
+            bitmap,
+            tick,
+            self.tick_spacing,
+            zero_for_one,
+        ) {
+            return Some(found_tick);
+        }
 
-        let upper_tick_info = state.tick_data.entry(update.tick_upper).or_default();
-        upper_tick_info.liquidity_gross =
-            (upper_tick_info.liquidity_gross as i128 - update.liquidity) as u128;
-        upper_tick_info.liquidity_net -= update.liquidity;
+        // Simplified loop, just checks the BTreeMaps
+        if zero_for_one {
+            let word_pos = word_pos.checked_sub(1)?;
+            if word_pos < last_word {
+                return None;
+            }
+            snapshot
+                .tick_bitmap
+                .range(last_word..=word_pos)
+                .rev()
+                .find_map(|(&pos, &bmp)| {
+                    (bmp != U256::ZERO).then(|| {
+                        (pos as i32 * 256
+                            + crate::math::v3::bit_math::most_significant_bit(bmp) as i32)
+                            * self.tick_spacing
+                    })
+                })
+        } else {
+            let word_pos = word_pos.checked_add(1)?;
+            if word_pos > last_word {
+                return None;
+            }
+            snapshot
+                .tick_bitmap
+                .range(word_pos..=last_word)
+                .find_map(|(&pos, &bmp)| {
+                    (bmp != U256::ZERO).then(|| {
+                        (pos as i32 * 256
+                            + crate::math::v3::bit_math::least_significant_bit(bmp) as i32)
+                            * self.tick_spacing
+                    })
+                })
+        }
     }
 
-    /// Internal swap calculation logic
-    async fn _calculate_swap(
+    pub(crate) fn swap(
         &self,
         zero_for_one: bool,
         amount_specified: I256,
         sqrt_price_limit_x96: U256,
-        override_state: Option<&UniswapV3PoolState>,
-    ) -> Result<(I256, I256, UniswapV3PoolState), ArbRsError> {
-        let state_guard = self.state.read().await;
-        let initial_state = override_state.unwrap_or(&state_guard);
-
+        snapshot: &UniswapV3PoolSnapshot,
+    ) -> Result<SwapOutcome, ArbRsError> {
         if amount_specified.is_zero() {
             return Err(ArbRsError::CalculationError(
                 "Amount specified cannot be zero".into(),
@@ -176,107 +298,49 @@ impl<P: Provider + Send + Sync + 'static + ?Sized> UniswapV3Pool<P> {
         }
 
         let exact_input = amount_specified.is_positive();
-        let mut current_state = initial_state.clone();
+        let last_word = self.last_word(zero_for_one, snapshot.tick);
+        // Without liquidity in range or a tick to bring some, the swap can't fill anything.
+        self.check_liquidity(zero_for_one, snapshot, last_word)?;
+        let sqrt_price_limit_x96 =
+            self.bounded_price_limit(zero_for_one, sqrt_price_limit_x96, last_word)?;
 
         let mut swap_state = SwapState {
             amount_specified_remaining: amount_specified,
             amount_calculated: I256::ZERO,
-            sqrt_price_x96: current_state.sqrt_price_x96,
-            tick: current_state.tick,
-            liquidity: current_state.liquidity,
+            sqrt_price_x96: snapshot.sqrt_price_x96,
+            tick: snapshot.tick,
+            liquidity: snapshot.liquidity,
+        };
+        let mut initialized_ticks_crossed = 0;
+        let fee_protocol = if zero_for_one {
+            snapshot.fee_protocol % 16
+        } else {
+            snapshot.fee_protocol >> 4
         };
+        let (mut lp_fee, mut protocol_fee) = (U256::ZERO, U256::ZERO);
 
         while !swap_state.amount_specified_remaining.is_zero()
             && swap_state.sqrt_price_x96 != sqrt_price_limit_x96
         {
-            let (mut word_pos, _) = tick_bitmap::position(swap_state.tick / self.tick_spacing);
-
-            let (next_tick, initialized) = {
-                let mut result = None;
-
-                if !current_state.tick_bitmap.contains_key(&word_pos) {
-                    self._fetch_and_populate_initialized_ticks(
-                        word_pos,
-                        &mut current_state.tick_bitmap,
-                        &mut current_state.tick_data,
-                    )
-                    .await?;
-                }
-                let bitmap = current_state
-                    .tick_bitmap
-                    .get(&word_pos)
-                    .copied()
-                    .unwrap_or_default();
-
-                if let Some(found_tick) = tick_bitmap::next_initialized_tick_within_one_word(
-                    bitmap,
-                    swap_state.tick,
-                    self.tick_spacing,
-                    zero_for_one,
-                ) {
-                    result = Some(found_tick);
-                } else {
-                    if zero_for_one {
-                        word_pos -= 1;
-                        while word_pos >= self._min_word {
-                            if !current_state.tick_bitmap.contains_key(&word_pos) {
-                                self._fetch_and_populate_initialized_ticks(
-                                    word_pos,
-                                    &mut current_state.tick_bitmap,
-                                    &mut current_state.tick_data,
-                                )
-                                .await?;
-                            }
-                            let next_bitmap = current_state
-                                .tick_bitmap
-                                .get(&word_pos)
-                                .copied()
-                                .unwrap_or_default();
-                            if next_bitmap != U256::ZERO {
-                                let next_initialized_tick = (word_pos as i32 * 256
-                                    + crate::math::v3::bit_math::most_significant_bit(next_bitmap)
-                                        as i32)
-                                    * self.tick_spacing;
-                                result = Some((next_initialized_tick, true));
-                                break;
-                            }
-                            word_pos -= 1;
-                        }
-                    } else {
-                        word_pos += 1;
-                        while word_pos <= self._max_word {
-                            if !current_state.tick_bitmap.contains_key(&word_pos) {
-                                self._fetch_and_populate_initialized_ticks(
-                                    word_pos,
-                                    &mut current_state.tick_bitmap,
-                                    &mut current_state.tick_data,
-                                )
-                                .await?;
-                            }
-                            let next_bitmap = current_state
-                                .tick_bitmap
-                                .get(&word_pos)
-                                .copied()
-                                .unwrap_or_default();
-                            if next_bitmap != U256::ZERO {
-                                let next_initialized_tick = (word_pos as i32 * 256
-                                    + crate::math::v3::bit_math::least_significant_bit(next_bitmap)
-                                        as i32)
-                                    * self.tick_spacing;
-                                result = Some((next_initialized_tick, true));
-                                break;
-                            }
-                            word_pos += 1;
-                        }
-                    }
-                }
-                result.unwrap_or((if zero_for_one { MIN_TICK } else { MAX_TICK }, false))
-            };
-
-            let next_tick = next_tick.clamp(MIN_TICK, MAX_TICK);
+            let (next_tick, initialized) = self
+                .next_initialized_tick(zero_for_one, swap_state.tick, last_word, snapshot)
+                .map_or(
+                    (
+                        if zero_for_one {
+                            get_min_tick(self.tick_spacing)
+                        } else {
+                            get_max_tick(self.tick_spacing)
+                        },
+                        false,
+                    ),
+                    |tick| (tick, true),
+                );
 
+            let next_tick = next_tick.clamp(
+                get_min_tick(self.tick_spacing),
+                get_max_tick(self.tick_spacing),
+            );
             let sqrt_price_next_tick = tick_math::get_sqrt_ratio_at_tick(next_tick)?;
-
             let sqrt_price_target = if (zero_for_one && sqrt_price_next_tick < sqrt_price_limit_x96)
                 || (!zero_for_one && sqrt_price_next_tick > sqrt_price_limit_x96)
             {
@@ -294,7 +358,15 @@ impl<P: Provider + Send + Sync + 'static + ?Sized> UniswapV3Pool<P> {
             )?;
 
             swap_state.sqrt_price_x96 = step.sqrt_ratio_next_x96;
-
+            // As the pool does: the protocol takes `1/fee_protocol` of the step's fee, and the
+            // rest grows the fees of the liquidity in range.
+            let step_protocol_fee = if fee_protocol > 0 {
+                step.fee_amount / U256::from(fee_protocol)
+            } else {
+                U256::ZERO
+            };
+            protocol_fee += step_protocol_fee;
+            lp_fee += step.fee_amount - step_protocol_fee;
             if exact_input {
                 swap_state.amount_specified_remaining -= I256::from_raw(step.amount_in);
                 swap_state.amount_calculated -= I256::from_raw(step.amount_out);
@@ -305,7 +377,8 @@ impl<P: Provider + Send + Sync + 'static + ?Sized> UniswapV3Pool<P> {
 
             if swap_state.sqrt_price_x96 == sqrt_price_next_tick {
                 if initialized {
-                    let liquidity_net = current_state
+                    initialized_ticks_crossed += 1;
+                    let liquidity_net = snapshot
                         .tick_data
                         .get(&next_tick)
                         .map(|t| t.liquidity_net)
@@ -318,9 +391,7 @@ impl<P: Provider + Send + Sync + 'static + ?Sized> UniswapV3Pool<P> {
                             liquidity_net
                         },
                     )
-                    .ok_or(ArbRsError::CalculationError(
-                        "Liquidity underflow/overflow".into(),
-                    ))?;
+                    .ok_or(ArbRsError::CalculationError("Liquidity math error".into()))?;
                 }
                 swap_state.tick = if zero_for_one {
                     next_tick - 1
@@ -332,7 +403,8 @@ impl<P: Provider + Send + Sync + 'static + ?Sized> UniswapV3Pool<P> {
             }
         }
 
-        let (amount0_delta, amount1_delta) = if zero_for_one {
+        // The specified amount is the input for exact-input swaps and the output otherwise.
+        let (amount0_delta, amount1_delta) = if zero_for_one == exact_input {
             (
                 amount_specified - swap_state.amount_specified_remaining,
                 swap_state.amount_calculated,
@@ -344,169 +416,502 @@ impl<P: Provider + Send + Sync + 'static + ?Sized> UniswapV3Pool<P> {
             )
         };
 
-        let final_state = UniswapV3PoolState {
+        let final_state = UniswapV3PoolSnapshot {
             liquidity: swap_state.liquidity,
             sqrt_price_x96: swap_state.sqrt_price_x96,
             tick: swap_state.tick,
-            ..initial_state.clone()
+            tick_bitmap: snapshot.tick_bitmap.clone(), // This could be optimized
+            tick_data: snapshot.tick_data.clone(),
+            fee_protocol: snapshot.fee_protocol,
         };
 
-        Ok((amount0_delta, amount1_delta, final_state))
+        Ok(SwapOutcome {
+            amount0_delta,
+            amount1_delta,
+            final_state,
+            amount_specified_remaining: swap_state.amount_specified_remaining,
+            initialized_ticks_crossed,
+            lp_fee,
+            protocol_fee,
+        })
     }
 
-    fn _calculate_swap_from_snapshot(
+    /// Output of an exact-input swap of `amount_in`, a `PartialFill` if the liquidity runs out.
+    pub(crate) fn exact_input(
         &self,
         zero_for_one: bool,
-        amount_specified: I256,
-        sqrt_price_limit_x96: U256,
+        amount_in: U256,
         snapshot: &UniswapV3PoolSnapshot,
-    ) -> Result<(I256, I256, UniswapV3PoolSnapshot), ArbRsError> {
-        if amount_specified.is_zero() {
-            return Err(ArbRsError::CalculationError(
-                "Amount specified cannot be zero".into(),
-            ));
-        }
+    ) -> Result<U256, ArbRsError> {
+        let sqrt_price_limit_x96 = if zero_for_one {
+            MIN_SQRT_RATIO + U256::from(1)
+        } else {
+            MAX_SQRT_RATIO - U256::from(1)
+        };
 
-        let exact_input = amount_specified.is_positive();
+        let outcome = self.swap(
+            zero_for_one,
+            I256::from_raw(amount_in),
+            sqrt_price_limit_x96,
+            snapshot,
+        )?;
 
-        let mut swap_state = SwapState {
-            amount_specified_remaining: amount_specified,
-            amount_calculated: I256::ZERO,
-            sqrt_price_x96: snapshot.sqrt_price_x96,
-            tick: snapshot.tick,
-            liquidity: snapshot.liquidity,
-        };
+        if !outcome.amount_specified_remaining.is_zero() {
+            return Err(ArbRsError::PartialFill {
+                requested: amount_in,
+                filled: amount_in - outcome.amount_specified_remaining.into_raw(),
+            });
+        }
 
-        while !swap_state.amount_specified_remaining.is_zero()
-            && swap_state.sqrt_price_x96 != sqrt_price_limit_x96
-        {
-            let (mut word_pos, _) = tick_bitmap::position(swap_state.tick / self.tick_spacing);
-            let bitmap = snapshot
-                .tick_bitmap
-                .get(&word_pos)
-                .copied()
-                .unwrap_or_default();
-
-            let (next_tick, initialized) = if let Some(found_tick) =
-                tick_bitmap::next_initialized_tick_within_one_word(
-                    bitmap,
-                    swap_state.tick,
-                    self.tick_spacing,
-                    zero_for_one,
-                ) {
-                Some(found_tick)
-            } else {
-                // Simplified loop, just checks the BTreeMaps
-                if zero_for_one {
-                    word_pos -= 1;
-                    snapshot
-                        .tick_bitmap
-                        .range(..=word_pos)
-                        .rev()
-                        .find_map(|(&pos, &bmp)| {
-                            if bmp != U256::ZERO {
-                                let next_init_tick = (pos as i32 * 256
-                                    + crate::math::v3::bit_math::most_significant_bit(bmp) as i32)
-                                    * self.tick_spacing;
-                                Some((next_init_tick, true))
-                            } else {
-                                None
-                            }
-                        })
+        Ok(if zero_for_one {
+            (-outcome.amount1_delta).into_raw()
+        } else {
+            (-outcome.amount0_delta).into_raw()
+        })
+    }
+
+    /// Input of an exact-output swap of `amount_out`, see
+    /// [`UniswapV3Pool::calculate_tokens_in_with_price_limit`].
+    pub(crate) fn exact_output(
+        &self,
+        zero_for_one: bool,
+        amount_out: U256,
+        snapshot: &UniswapV3PoolSnapshot,
+        sqrt_price_limit_x96: Option<U256>,
+    ) -> Result<U256, ArbRsError> {
+        let sqrt_price_limit_x96 = match sqrt_price_limit_x96 {
+            Some(limit) => {
+                let valid = if zero_for_one {
+                    limit < snapshot.sqrt_price_x96 && limit > MIN_SQRT_RATIO
                 } else {
-                    word_pos += 1;
-                    snapshot
-                        .tick_bitmap
-                        .range(word_pos..)
-                        .find_map(|(&pos, &bmp)| {
-                            if bmp != U256::ZERO {
-                                let next_init_tick = (pos as i32 * 256
-                                    + crate::math::v3::bit_math::least_significant_bit(bmp) as i32)
-                                    * self.tick_spacing;
-                                Some((next_init_tick, true))
-                            } else {
-                                None
-                            }
-                        })
+                    limit > snapshot.sqrt_price_x96 && limit < MAX_SQRT_RATIO
+                };
+                if !valid {
+                    return Err(ArbRsError::CalculationError(format!(
+                        "Price limit {} is not past the current price {} in the swap direction",
+                        limit, snapshot.sqrt_price_x96
+                    )));
                 }
+                limit
             }
-            .unwrap_or((if zero_for_one { MIN_TICK } else { MAX_TICK }, false));
+            None if zero_for_one => MIN_SQRT_RATIO + U256::from(1),
+            None => MAX_SQRT_RATIO - U256::from(1),
+        };
 
-            let next_tick = next_tick.clamp(MIN_TICK, MAX_TICK);
-            let sqrt_price_next_tick = tick_math::get_sqrt_ratio_at_tick(next_tick)?;
-            let sqrt_price_target = if (zero_for_one && sqrt_price_next_tick < sqrt_price_limit_x96)
-                || (!zero_for_one && sqrt_price_next_tick > sqrt_price_limit_x96)
-            {
-                sqrt_price_limit_x96
-            } else {
-                sqrt_price_next_tick
+        let outcome = self.swap(
+            zero_for_one,
+            -I256::from_raw(amount_out),
+            sqrt_price_limit_x96,
+            snapshot,
+        )?;
+
+        if !outcome.amount_specified_remaining.is_zero() {
+            return Err(ArbRsError::PartialFill {
+                requested: amount_out,
+                filled: amount_out - (-outcome.amount_specified_remaining).into_raw(),
+            });
+        }
+
+        Ok(if zero_for_one {
+            outcome.amount0_delta.into_raw()
+        } else {
+            outcome.amount1_delta.into_raw()
+        })
+    }
+}
+
+/// A V3 pool's swap math and one snapshot, quoting without the pool.
+#[derive(Debug, Clone)]
+pub struct UniswapV3Quoter {
+    pub address: Address,
+    pub token0: Address,
+    pub token1: Address,
+    pub fee: u32,
+    pub tick_spacing: i32,
+    pub snapshot: Arc<UniswapV3PoolSnapshot>,
+    /// Bitmap words a swap may search, see [`UniswapV3Pool::with_max_swap_words`].
+    pub max_swap_words: Option<u32>,
+}
+
+impl UniswapV3Quoter {
+    fn swap_math(&self) -> SwapMath {
+        SwapMath {
+            pool: self.address,
+            fee: self.fee,
+            tick_spacing: self.tick_spacing,
+            max_swap_words: self.max_swap_words,
+        }
+    }
+
+    fn check_pair(&self, token_in: Address, token_out: Address) -> Result<(), ArbRsError> {
+        if (token_in, token_out) == (self.token0, self.token1)
+            || (token_in, token_out) == (self.token1, self.token0)
+        {
+            Ok(())
+        } else {
+            Err(ArbRsError::CalculationError(
+                "Token pair does not match pool".into(),
+            ))
+        }
+    }
+}
+
+impl Quoter for UniswapV3Quoter {
+    fn calculate_out(
+        &self,
+        token_in: Address,
+        token_out: Address,
+        amount_in: U256,
+    ) -> Result<U256, ArbRsError> {
+        self.check_pair(token_in, token_out)?;
+        self.swap_math()
+            .exact_input(token_in == self.token0, amount_in, &self.snapshot)
+            .with_ctx(|| pool_context(self.address))
+    }
+
+    fn calculate_in(
+        &self,
+        token_in: Address,
+        token_out: Address,
+        amount_out: U256,
+    ) -> Result<U256, ArbRsError> {
+        self.check_pair(token_in, token_out)?;
+        self.swap_math()
+            .exact_output(token_out == self.token1, amount_out, &self.snapshot, None)
+            .with_ctx(|| pool_context(self.address))
+    }
+}
+
+/// Context naming a pool as a Uniswap V3 one.
+fn pool_context(address: Address) -> PoolContext {
+    PoolContext::new(address).with_kind("Uniswap V3")
+}
+
+pub struct UniswapV3Pool<P: ?Sized> {
+    address: Address,
+    token0: Arc<Token<P>>,
+    token1: Arc<Token<P>>,
+    fee: u32,
+    tick_spacing: i32,
+    pub state: RwLock<UniswapV3PoolState>,
+    provider: Arc<P>,
+    state_cache: RwLock<BTreeMap<u64, UniswapV3PoolState>>,
+    cache_config: CacheConfig,
+    last_trades: LastTradeTracker,
+    non_standard_tier: bool,
+    /// Block the tick bitmap and tick data were last read from the chain at, if ever.
+    liquidity_map_block: RwLock<Option<u64>>,
+    /// Reads a word's ticks in one call when set, instead of one call per tick.
+    tick_lens: Option<TickLensClient<P>>,
+    /// Bitmap words a swap may search for initialized ticks, unbounded if unset.
+    max_swap_words: Option<u32>,
+    subscribers: SubscriberList<P>,
+}
+
+#[async_trait]
+impl<P: Provider + Send + Sync + 'static + ?Sized> Publisher<P> for UniswapV3Pool<P> {
+    async fn subscribe(&self, subscriber: Weak<dyn Subscriber<P>>) {
+        self.subscribers.subscribe(subscriber).await;
+    }
+
+    async fn unsubscribe(&self, subscriber_id: usize) {
+        self.subscribers.unsubscribe(subscriber_id).await;
+    }
+
+    async fn notify_subscribers(&self, message: PublisherMessage) {
+        self.subscribers.notify(message).await;
+    }
+}
+
+impl<P: Provider + Send + Sync + 'static + ?Sized> UniswapV3Pool<P> {
+    pub fn new(
+        address: Address,
+        token0: Arc<Token<P>>,
+        token1: Arc<Token<P>>,
+        fee: u32,
+        tick_spacing: i32,
+        provider: Arc<P>,
+        initial_liquidity_map: Option<LiquidityMap>,
+    ) -> Self {
+        let (tick_bitmap, tick_data) = match initial_liquidity_map {
+            Some(map) => (map.tick_bitmap, map.tick_data),
+            None => (BTreeMap::new(), BTreeMap::new()),
+        };
+
+        Self {
+            address,
+            token0,
+            token1,
+            fee,
+            tick_spacing,
+            state: RwLock::new(UniswapV3PoolState {
+                tick_bitmap,
+                tick_data,
+                ..Default::default()
+            }),
+            provider,
+            state_cache: RwLock::new(BTreeMap::new()),
+            cache_config: CacheConfig::default(),
+            last_trades: LastTradeTracker::default(),
+            non_standard_tier: false,
+            liquidity_map_block: RwLock::new(None),
+            tick_lens: None,
+            max_swap_words: None,
+            subscribers: SubscriberList::default(),
+        }
+    }
+
+    fn state_update_message(&self, state: &UniswapV3PoolState) -> PublisherMessage {
+        PublisherMessage::UniswapV3StateUpdate {
+            pool: self.address,
+            block_number: state.block_number,
+            sqrt_price_x96: state.sqrt_price_x96,
+            tick: state.tick,
+            liquidity: state.liquidity,
+        }
+    }
+
+    /// Marks a fee/tick spacing pair that no known factory table lists.
+    pub fn with_non_standard_tier(mut self, non_standard_tier: bool) -> Self {
+        self.non_standard_tier = non_standard_tier;
+        self
+    }
+
+    /// Reads the liquidity map through the `TickLens` at `tick_lens`, e.g.
+    /// [`UNISWAP_V3_TICK_LENS`](crate::pool::tick_lens::UNISWAP_V3_TICK_LENS), so each word
+    /// takes at most two calls.
+    pub fn with_tick_lens(mut self, tick_lens: Address) -> Self {
+        self.tick_lens = Some(TickLensClient::new(tick_lens, self.provider.clone()));
+        self
+    }
+
+    /// Bounds the bitmap words a swap searches for initialized ticks to `max_swap_words`,
+    /// the current one included. A swap reaching the end of them stops there as if the
+    /// liquidity ran out, so a sparse or unloaded map can't send it across the whole range.
+    pub fn with_max_swap_words(mut self, max_swap_words: u32) -> Self {
+        self.max_swap_words = Some(max_swap_words.max(1));
+        self
+    }
+
+    /// Bounds the state cache to `cache_config.max_blocks` blocks instead of the default.
+    pub fn with_cache_config(mut self, cache_config: CacheConfig) -> Self {
+        self.cache_config = cache_config;
+        self
+    }
+
+    /// The tick bitmap and tick data, and the block they were read from the chain at.
+    pub async fn liquidity_map(&self) -> (Option<u64>, LiquidityMap) {
+        let state = self.state.read().await;
+        let map = LiquidityMap {
+            tick_bitmap: state.tick_bitmap.clone(),
+            tick_data: state.tick_data.clone(),
+        };
+        (*self.liquidity_map_block.read().await, map)
+    }
+
+    /// Replaces the tick bitmap and tick data with a map read at `block_number`, e.g. one
+    /// stored by a previous run.
+    pub async fn set_liquidity_map(&self, map: LiquidityMap, block_number: u64) {
+        {
+            let mut state = self.state.write().await;
+            state.tick_bitmap = map.tick_bitmap;
+            state.tick_data = map.tick_data;
+        }
+        *self.liquidity_map_block.write().await = Some(block_number);
+    }
+
+    /// Brings the liquidity map up to `block_number`. Only the bitmap words holding a tick
+    /// that a `Mint` or `Burn` touched since the map was read are read again, unless the map
+    /// is older than `MAX_LIQUIDITY_MAP_AGE_BLOCKS` or was never read, in which case every
+    /// word is. Returns the number of words read.
+    pub async fn refresh_liquidity_map(&self, block_number: u64) -> Result<usize, ArbRsError> {
+        let map_block = *self.liquidity_map_block.read().await;
+        let Some((fetched, full)) = tick_words::read_words(
+            map_block,
+            block_number,
+            self.tick_spacing,
+            |from_block, to_block| self.touched_words(from_block, to_block),
+            |word| self.fetch_word(word, block_number),
+        )
+        .await?
+        else {
+            return Ok(0);
+        };
+
+        let mut guard = self.state.write().await;
+        let state = &mut *guard;
+        tick_words::apply_words(
+            &mut state.tick_bitmap,
+            &mut state.tick_data,
+            &fetched,
+            self.tick_spacing,
+            full,
+        );
+        drop(guard);
+        *self.liquidity_map_block.write().await = Some(block_number);
+        Ok(fetched.len())
+    }
+
+    /// Bitmap words holding a tick of a `Mint` or `Burn` between the two blocks.
+    async fn touched_words(
+        &self,
+        from_block: u64,
+        to_block: u64,
+    ) -> Result<BTreeSet<i16>, ArbRsError> {
+        let filter = Filter::new()
+            .address(self.address)
+            .event_signature(vec![Mint::SIGNATURE_HASH, Burn::SIGNATURE_HASH])
+            .from_block(from_block)
+            .to_block(to_block);
+        let logs = self.provider.get_logs(&filter).await?;
+
+        let mut words = BTreeSet::new();
+        for log in &logs {
+            let (tick_lower, tick_upper) = match log.topic0() {
+                Some(topic) if *topic == Mint::SIGNATURE_HASH => {
+                    let mint = Mint::decode_log_data(log.data())?;
+                    (mint.tickLower, mint.tickUpper)
+                }
+                Some(topic) if *topic == Burn::SIGNATURE_HASH => {
+                    let burn = Burn::decode_log_data(log.data())?;
+                    (burn.tickLower, burn.tickUpper)
+                }
+                _ => continue,
             };
+            words.insert(tick_words::word_position(
+                tick_lower.as_i32(),
+                self.tick_spacing,
+            ));
+            words.insert(tick_words::word_position(
+                tick_upper.as_i32(),
+                self.tick_spacing,
+            ));
+        }
+        Ok(words)
+    }
 
-            let step = swap_math::compute_swap_step(
-                swap_state.sqrt_price_x96,
-                sqrt_price_target,
-                swap_state.liquidity,
-                swap_state.amount_specified_remaining,
-                self.fee,
-            )?;
+    /// Reads bitmap word `word` and the liquidity of each tick it marks initialized, through
+    /// the tick lens if the pool has one.
+    async fn fetch_word(&self, word: i16, block_number: u64) -> Result<TickWord, ArbRsError> {
+        let block_id = BlockId::from(block_number);
+        let request = |input: Vec<u8>| {
+            TransactionRequest::default()
+                .to(self.address)
+                .input(input.into())
+        };
+        let bitmap_bytes = self
+            .provider
+            .call(request(tickBitmapCall { wordPosition: word }.abi_encode()))
+            .block(block_id)
+            .await?;
+        let bitmap = tickBitmapCall::abi_decode_returns(&bitmap_bytes)?;
+        if bitmap.is_zero() {
+            return Ok((word, bitmap, Vec::new()));
+        }
+        if let Some(tick_lens) = &self.tick_lens {
+            let ticks = tick_lens
+                .populated_ticks_in_word(self.address, word, block_id)
+                .await?;
+            return Ok((word, bitmap, ticks));
+        }
+
+        let mut ticks = Vec::new();
+        for tick in tick_words::initialized_ticks(word, bitmap, self.tick_spacing) {
+            let tick_arg =
+                I24::try_from(tick).map_err(|e| ArbRsError::CalculationError(e.to_string()))?;
+            let tick_bytes = self
+                .provider
+                .call(request(ticksCall { tick: tick_arg }.abi_encode()))
+                .block(block_id)
+                .await?;
+            let info = ticksCall::abi_decode_returns(&tick_bytes)?;
+            ticks.push((
+                tick,
+                TickInfo {
+                    liquidity_gross: info.liquidityGross,
+                    liquidity_net: info.liquidityNet,
+                },
+            ));
+        }
+        Ok((word, bitmap, ticks))
+    }
+
+    fn validate_token_pair(
+        &self,
+        token_a: &Token<P>,
+        token_b: &Token<P>,
+    ) -> Result<(), ArbRsError> {
+        if !((token_a.address() == self.token0.address()
+            && token_b.address() == self.token1.address())
+            || (token_a.address() == self.token1.address()
+                && token_b.address() == self.token0.address()))
+        {
+            Err(ArbRsError::CalculationError(
+                "Token pair does not match pool".into(),
+            ))
+        } else {
+            Ok(())
+        }
+    }
 
-            swap_state.sqrt_price_x96 = step.sqrt_ratio_next_x96;
-            if exact_input {
-                swap_state.amount_specified_remaining -= I256::from_raw(step.amount_in);
-                swap_state.amount_calculated -= I256::from_raw(step.amount_out);
-            } else {
-                swap_state.amount_specified_remaining += I256::from_raw(step.amount_out);
-                swap_state.amount_calculated += I256::from_raw(step.amount_in);
-            }
+    /// Applies an update to the liquidity map.
+    pub async fn update_liquidity_map(&self, update: UniswapV3PoolLiquidityMappingUpdate) {
+        let mut state = self.state.write().await;
 
-            if swap_state.sqrt_price_x96 == sqrt_price_next_tick {
-                if initialized {
-                    let liquidity_net = snapshot
-                        .tick_data
-                        .get(&next_tick)
-                        .map(|t| t.liquidity_net)
-                        .unwrap_or(0);
-                    swap_state.liquidity = liquidity_math::add_delta(
-                        swap_state.liquidity,
-                        if zero_for_one {
-                            -liquidity_net
-                        } else {
-                            liquidity_net
-                        },
-                    )
-                    .ok_or(ArbRsError::CalculationError("Liquidity math error".into()))?;
-                }
-                swap_state.tick = if zero_for_one {
-                    next_tick - 1
-                } else {
-                    next_tick
-                };
-            } else {
-                swap_state.tick = tick_math::get_tick_at_sqrt_ratio(swap_state.sqrt_price_x96)?;
-            }
+        if update.tick_lower <= state.tick && state.tick < update.tick_upper {
+            state.liquidity =
+                liquidity_math::add_delta(state.liquidity, update.liquidity).unwrap_or(0);
         }
 
-        let (amount0_delta, amount1_delta) = if zero_for_one {
-            (
-                amount_specified - swap_state.amount_specified_remaining,
-                swap_state.amount_calculated,
-            )
-        } else {
-            (
-                swap_state.amount_calculated,
-                amount_specified - swap_state.amount_specified_remaining,
-            )
-        };
+        let state = &mut *state;
+        apply_liquidity_update(
+            &mut state.tick_bitmap,
+            &mut state.tick_data,
+            &update,
+            self.tick_spacing,
+        );
+    }
 
-        let final_state = UniswapV3PoolSnapshot {
-            liquidity: swap_state.liquidity,
-            sqrt_price_x96: swap_state.sqrt_price_x96,
-            tick: swap_state.tick,
-            tick_bitmap: snapshot.tick_bitmap.clone(), // This could be optimized
-            tick_data: snapshot.tick_data.clone(),
-        };
+    fn swap_math(&self) -> SwapMath {
+        SwapMath {
+            pool: self.address,
+            fee: self.fee,
+            tick_spacing: self.tick_spacing,
+            max_swap_words: self.max_swap_words,
+        }
+    }
+
+    /// A [`NoLiquidity`](ArbRsError::NoLiquidity) error if `snapshot` has no liquidity in
+    /// range and no initialized tick to bring some in the swap direction, which a swap
+    /// would otherwise only find out by walking the bitmap.
+    pub fn check_liquidity(
+        &self,
+        zero_for_one: bool,
+        snapshot: &UniswapV3PoolSnapshot,
+    ) -> Result<(), ArbRsError> {
+        let swap_math = self.swap_math();
+        swap_math.check_liquidity(
+            zero_for_one,
+            snapshot,
+            swap_math.last_word(zero_for_one, snapshot.tick),
+        )
+    }
 
-        Ok((amount0_delta, amount1_delta, final_state))
+    fn _calculate_swap_from_snapshot(
+        &self,
+        zero_for_one: bool,
+        amount_specified: I256,
+        sqrt_price_limit_x96: U256,
+        snapshot: &UniswapV3PoolSnapshot,
+    ) -> Result<SwapOutcome, ArbRsError> {
+        self.swap_math()
+            .swap(
+                zero_for_one,
+                amount_specified,
+                sqrt_price_limit_x96,
+                snapshot,
+            )
+            .with_ctx(|| pool_context(self.address))
     }
 
     /// Fetches state at a specific block number without updating the live state.
@@ -535,9 +940,8 @@ impl<P: Provider + Send + Sync + 'static + ?Sized> UniswapV3Pool<P> {
             self.provider.call(liquidity_request).block(block_id)
         );
 
-        let slot0_bytes = slot0_res.map_err(|e| ArbRsError::ProviderError(e.to_string()))?;
-        let liquidity_bytes =
-            liquidity_res.map_err(|e| ArbRsError::ProviderError(e.to_string()))?;
+        let slot0_bytes = slot0_res?;
+        let liquidity_bytes = liquidity_res?;
 
         let slot0_decoded = slot0Call::abi_decode_returns(&slot0_bytes)
             .map_err(|e| ArbRsError::AbiDecodeError(e.to_string()))?;
@@ -547,6 +951,7 @@ impl<P: Provider + Send + Sync + 'static + ?Sized> UniswapV3Pool<P> {
         Ok(UniswapV3PoolState {
             sqrt_price_x96: U256::from(slot0_decoded.sqrtPriceX96),
             tick: slot0_decoded.tick.as_i32(),
+            fee_protocol: slot0_decoded.feeProtocol,
             liquidity: liquidity_decoded,
             block_number,
             tick_bitmap: BTreeMap::new(),
@@ -554,66 +959,32 @@ impl<P: Provider + Send + Sync + 'static + ?Sized> UniswapV3Pool<P> {
         })
     }
 
-    async fn _fetch_and_populate_initialized_ticks(
+    /// The input an exact-output swap of `amount_out` takes, stopping at `sqrt_price_limit_x96`
+    /// if given, as `QuoterV2.quoteExactOutputSingle` does. A limit on the wrong side of the
+    /// current price is an error, and an output that can't be paid out in full before the
+    /// limit or the end of the liquidity is a `PartialFill` reporting the achievable output.
+    pub fn calculate_tokens_in_with_price_limit(
         &self,
-        word_pos: i16,
-        tick_bitmap: &mut BTreeMap<i16, U256>,
-        tick_data: &mut BTreeMap<i32, TickInfo>,
-    ) -> Result<(), ArbRsError> {
-        println!("Fetching on-demand tick data for word_pos: {}", word_pos);
-
-        let bitmap_call = tickBitmapCall {
-            wordPosition: word_pos,
-        };
-        let request = TransactionRequest {
-            to: Some(self.address.into()),
-            input: Some(Bytes::from(bitmap_call.abi_encode())).into(),
-            ..Default::default()
+        token_in: &Token<P>,
+        token_out: &Token<P>,
+        amount_out: U256,
+        snapshot: &PoolSnapshot,
+        sqrt_price_limit_x96: Option<U256>,
+    ) -> Result<U256, ArbRsError> {
+        self.validate_token_pair(token_in, token_out)?;
+        let v3_snapshot = match snapshot {
+            PoolSnapshot::UniswapV3(s) => s,
+            _ => {
+                return Err(ArbRsError::CalculationError(
+                    "Invalid snapshot for V3 pool".into(),
+                ));
+            }
         };
 
-        let bitmap_bytes = self
-            .provider
-            .call(request.clone())
-            .await
-            .map_err(|e| ArbRsError::ProviderError(e.to_string()))?;
-        let bitmap_word = tickBitmapCall::abi_decode_returns(&bitmap_bytes)?;
-
-        tick_bitmap.insert(word_pos, bitmap_word);
-
-        for i in 0..256 {
-            if (bitmap_word >> i) & U256::from(1) != U256::ZERO {
-                let compressed_tick = ((word_pos as i32) << 8) + i;
-
-                let actual_tick = compressed_tick * self.tick_spacing;
-
-                let ticks_call = ticksCall {
-                    tick: actual_tick.try_into().map_err(|_| {
-                        ArbRsError::CalculationError("Tick number out of bounds".to_string())
-                    })?,
-                };
-                let request = TransactionRequest {
-                    to: Some(self.address.into()),
-                    input: Some(Bytes::from(ticks_call.abi_encode())).into(),
-                    ..Default::default()
-                };
-
-                let tick_data_bytes = self
-                    .provider
-                    .call(request)
-                    .await
-                    .map_err(|e| ArbRsError::ProviderError(e.to_string()))?;
-                let tick_decoded = ticksCall::abi_decode_returns(&tick_data_bytes)?;
-
-                tick_data.insert(
-                    actual_tick,
-                    TickInfo {
-                        liquidity_gross: tick_decoded.liquidityGross,
-                        liquidity_net: tick_decoded.liquidityNet,
-                    },
-                );
-            }
-        }
-        Ok(())
+        let zero_for_one = token_out.address() == self.token1.address();
+        self.swap_math()
+            .exact_output(zero_for_one, amount_out, v3_snapshot, sqrt_price_limit_x96)
+            .with_ctx(|| pool_context(self.address))
     }
 
     pub fn simulate_exact_input_swap(
@@ -633,7 +1004,7 @@ impl<P: Provider + Send + Sync + 'static + ?Sized> UniswapV3Pool<P> {
             MAX_SQRT_RATIO - U256::from(1)
         };
 
-        let (amount0_delta, amount1_delta, final_state) = self._calculate_swap_from_snapshot(
+        let outcome = self._calculate_swap_from_snapshot(
             zero_for_one,
             amount_specified,
             sqrt_price_limit_x96,
@@ -641,10 +1012,13 @@ impl<P: Provider + Send + Sync + 'static + ?Sized> UniswapV3Pool<P> {
         )?;
 
         Ok(UniswapV3PoolSimulationResult {
-            amount0_delta,
-            amount1_delta,
+            amount0_delta: outcome.amount0_delta,
+            amount1_delta: outcome.amount1_delta,
             initial_state: snapshot.clone().into(),
-            final_state: final_state.into(),
+            final_state: outcome.final_state.into(),
+            fully_filled: outcome.amount_specified_remaining.is_zero(),
+            lp_fee: outcome.lp_fee,
+            protocol_fee: outcome.protocol_fee,
         })
     }
 
@@ -665,7 +1039,7 @@ impl<P: Provider + Send + Sync + 'static + ?Sized> UniswapV3Pool<P> {
             MAX_SQRT_RATIO - U256::from(1)
         };
 
-        let (amount0_delta, amount1_delta, final_state) = self._calculate_swap_from_snapshot(
+        let outcome = self._calculate_swap_from_snapshot(
             zero_for_one,
             amount_specified,
             sqrt_price_limit_x96,
@@ -673,10 +1047,13 @@ impl<P: Provider + Send + Sync + 'static + ?Sized> UniswapV3Pool<P> {
         )?;
 
         Ok(UniswapV3PoolSimulationResult {
-            amount0_delta,
-            amount1_delta,
+            amount0_delta: outcome.amount0_delta,
+            amount1_delta: outcome.amount1_delta,
             initial_state: snapshot.clone().into(),
-            final_state: final_state.into(),
+            final_state: outcome.final_state.into(),
+            fully_filled: outcome.amount_specified_remaining.is_zero(),
+            lp_fee: outcome.lp_fee,
+            protocol_fee: outcome.protocol_fee,
         })
     }
 
@@ -688,11 +1065,8 @@ impl<P: Provider + Send + Sync + 'static + ?Sized> UniswapV3Pool<P> {
         self.tick_spacing
     }
 
-    pub async fn update_state_at_block(&self, block_number: u64) -> Result<(), ArbRsError> {
-        let fetched_state = self._fetch_state_at_block(block_number).await?;
-        let mut state_writer = self.state.write().await;
-        *state_writer = fetched_state;
-        Ok(())
+    pub fn is_non_standard_tier(&self) -> bool {
+        self.non_standard_tier
     }
 }
 
@@ -706,36 +1080,117 @@ impl<P: Provider + Send + Sync + 'static + ?Sized> LiquidityPool<P> for UniswapV
         vec![self.token0.clone(), self.token1.clone()]
     }
 
+    /// The virtual reserves of the in-range liquidity at the current price.
+    fn reserves_summary(&self, snapshot: &PoolSnapshot) -> Vec<(Arc<Token<P>>, U256)> {
+        let PoolSnapshot::UniswapV3(state) = snapshot else {
+            return Vec::new();
+        };
+        let (reserve0, reserve1) = state.virtual_reserves();
+        vec![
+            (self.token0.clone(), reserve0),
+            (self.token1.clone(), reserve1),
+        ]
+    }
+
     fn as_any(&self) -> &dyn Any {
         self
     }
 
-    async fn update_state(&self) -> Result<(), ArbRsError> {
-        let latest_block = self
-            .provider
-            .get_block_number()
-            .await
-            .map_err(|e| ArbRsError::ProviderError(e.to_string()))?;
+    fn last_trade_tracker(&self) -> Option<&LastTradeTracker> {
+        Some(&self.last_trades)
+    }
+
+    fn prices_matrix(&self, snapshot: &PoolSnapshot) -> PriceMatrix {
+        let PoolSnapshot::UniswapV3(state) = snapshot else {
+            return PriceMatrix::new();
+        };
+        if state.sqrt_price_x96.is_zero() {
+            return PriceMatrix::new();
+        }
+        let ratio = u256_to_f64(state.sqrt_price_x96) / u256_to_f64(U256::from(1) << 96);
+        let price = ratio.powi(2);
+        let (token0, token1) = (self.token0.address(), self.token1.address());
+        PriceMatrix::from([((token0, token1), price), ((token1, token0), 1.0 / price)])
+    }
+
+    fn dex_kind(&self) -> Option<DexKind> {
+        Some(DexKind::UniswapV3)
+    }
+
+    /// The base cost plus the initialized ticks the swap crosses, simulated on `snapshot`.
+    fn gas_estimate(
+        &self,
+        token_in: &Token<P>,
+        _token_out: &Token<P>,
+        amount_in: U256,
+        snapshot: &PoolSnapshot,
+        costs: &SwapGasCosts,
+    ) -> u64 {
+        let ticks_crossed = match snapshot {
+            PoolSnapshot::UniswapV3(v3_snapshot) if !amount_in.is_zero() => {
+                let zero_for_one = token_in.address() == self.token0.address();
+                let sqrt_price_limit_x96 = if zero_for_one {
+                    MIN_SQRT_RATIO + U256::from(1)
+                } else {
+                    MAX_SQRT_RATIO - U256::from(1)
+                };
+                self._calculate_swap_from_snapshot(
+                    zero_for_one,
+                    I256::from_raw(amount_in),
+                    sqrt_price_limit_x96,
+                    v3_snapshot,
+                )
+                .map_or(0, |outcome| outcome.initialized_ticks_crossed)
+            }
+            _ => 0,
+        };
+        costs.uniswap_v3 + costs.uniswap_v3_tick_crossing * ticks_crossed
+    }
+
+    fn calibration_bucket(&self) -> Option<CalibrationBucket> {
+        Some(CalibrationBucket::new("uniswap_v3", self.fee.to_string()))
+    }
+
+    fn record_swap_log(&self, log: &Log) -> bool {
+        if log.address() != self.address {
+            return false;
+        }
+        match LastTrade::from_v3_swap(log, self.token0.address(), self.token1.address()) {
+            Some(trade) => {
+                self.last_trades.record(trade);
+                true
+            }
+            None => false,
+        }
+    }
+
+    async fn update_state(&self) -> Result<StateUpdate, ArbRsError> {
+        let latest_block = self.provider.get_block_number().await?;
 
         let current_block_number = self.state.read().await.block_number;
 
         if latest_block < current_block_number {
             return Err(ArbRsError::LateUpdateError {
+                pool: self.address,
                 attempted_block: latest_block,
                 latest_block: current_block_number,
             });
         }
 
         if latest_block == current_block_number && current_block_number != 0 {
-            return Ok(());
+            return Ok(StateUpdate::Unchanged);
         }
 
-        let fetched_state = self._fetch_state_at_block(latest_block).await?;
+        let fetched_state = self
+            ._fetch_state_at_block(latest_block)
+            .await
+            .with_ctx(|| pool_context(self.address).with_block(Some(latest_block)))?;
 
         let state_updated = {
             let state = self.state.read().await;
             state.sqrt_price_x96 != fetched_state.sqrt_price_x96
                 || state.liquidity != fetched_state.liquidity
+                || state.fee_protocol != fetched_state.fee_protocol
         };
 
         if state_updated {
@@ -746,13 +1201,97 @@ impl<P: Provider + Send + Sync + 'static + ?Sized> LiquidityPool<P> for UniswapV
             state_writer.tick_bitmap = old_tick_bitmap;
             state_writer.tick_data = old_tick_data;
 
+            drop(state_writer);
+
+            let mut cache = self.state_cache.write().await;
+            self.cache_config
+                .insert(&mut *cache, latest_block, fetched_state.clone());
+            drop(cache);
+
+            self.notify_subscribers(self.state_update_message(&fetched_state))
+                .await;
+            return Ok(StateUpdate::Updated {
+                block: latest_block,
+            });
+        }
+
+        Ok(StateUpdate::Unchanged)
+    }
+
+    async fn update_state_at_block(
+        &self,
+        block_number: u64,
+        allow_rewind: bool,
+    ) -> Result<(), ArbRsError> {
+        let current_block_number = self.state.read().await.block_number;
+        if block_number < current_block_number && !allow_rewind {
+            return Err(ArbRsError::LateUpdateError {
+                pool: self.address,
+                attempted_block: block_number,
+                latest_block: current_block_number,
+            });
+        }
+
+        let fetched_state = self
+            ._fetch_state_at_block(block_number)
+            .await
+            .with_ctx(|| pool_context(self.address).with_block(Some(block_number)))?;
+        {
             let mut cache = self.state_cache.write().await;
-            cache.insert(latest_block, fetched_state.clone());
+            if allow_rewind {
+                cache.retain(|&block, _| block <= block_number);
+            }
+            self.cache_config
+                .insert(&mut *cache, block_number, fetched_state.clone());
         }
+        let message = self.state_update_message(&fetched_state);
+        *self.state.write().await = fetched_state;
 
+        self.notify_subscribers(message).await;
         Ok(())
     }
 
+    async fn invalidate_from(&self, block_number: u64) {
+        let restored = {
+            let mut cache = self.state_cache.write().await;
+            cache.retain(|&block, _| block < block_number);
+            cache.values().next_back().cloned()
+        };
+        {
+            let mut state = self.state.write().await;
+            if state.block_number >= block_number {
+                match restored {
+                    Some(restored) => {
+                        state.sqrt_price_x96 = restored.sqrt_price_x96;
+                        state.tick = restored.tick;
+                        state.fee_protocol = restored.fee_protocol;
+                        state.liquidity = restored.liquidity;
+                        state.block_number = restored.block_number;
+                    }
+                    // Block 0 makes the next `update_state` read the slot again.
+                    None => state.block_number = 0,
+                }
+            }
+        }
+        // Ticks a reorged `Mint` or `Burn` touched can't be told apart, so the whole map is
+        // read again on the next refresh.
+        let mut map_block = self.liquidity_map_block.write().await;
+        if map_block.is_some_and(|map_block| map_block >= block_number) {
+            *map_block = None;
+        }
+    }
+
+    async fn discard_states_before_block(&self, block: u64) {
+        self.state_cache
+            .write()
+            .await
+            .retain(|&cached, _| cached >= block);
+    }
+
+    async fn cache_stats(&self) -> CacheStats {
+        CacheStats::of(self.state_cache.read().await.keys())
+    }
+
     fn calculate_tokens_out(
         &self,
         token_in: &Token<P>,
@@ -771,26 +1310,9 @@ impl<P: Provider + Send + Sync + 'static + ?Sized> LiquidityPool<P> for UniswapV
         };
 
         let zero_for_one = token_in.address() == self.token0.address();
-        let amount_specified = I256::from_raw(amount_in);
-
-        let sqrt_price_limit_x96 = if zero_for_one {
-            MIN_SQRT_RATIO + U256::from(1)
-        } else {
-            MAX_SQRT_RATIO - U256::from(1)
-        };
-
-        let (amount0_delta, amount1_delta, _final_state) = self._calculate_swap_from_snapshot(
-            zero_for_one,
-            amount_specified,
-            sqrt_price_limit_x96,
-            v3_snapshot,
-        )?;
-
-        Ok(if zero_for_one {
-            (-amount1_delta).into_raw()
-        } else {
-            (-amount0_delta).into_raw()
-        })
+        self.swap_math()
+            .exact_input(zero_for_one, amount_in, v3_snapshot)
+            .with_ctx(|| pool_context(self.address))
     }
 
     fn calculate_tokens_in(
@@ -800,37 +1322,24 @@ impl<P: Provider + Send + Sync + 'static + ?Sized> LiquidityPool<P> for UniswapV
         amount_out: U256,
         snapshot: &PoolSnapshot,
     ) -> Result<U256, ArbRsError> {
-        self.validate_token_pair(token_in, token_out)?;
-        let v3_snapshot = match snapshot {
-            PoolSnapshot::UniswapV3(s) => s,
-            _ => {
-                return Err(ArbRsError::CalculationError(
-                    "Invalid snapshot for V3 pool".into(),
-                ));
-            }
-        };
-
-        let zero_for_one = token_out.address() == self.token1.address();
-        let amount_specified = -I256::from_raw(amount_out);
+        self.calculate_tokens_in_with_price_limit(token_in, token_out, amount_out, snapshot, None)
+    }
 
-        let sqrt_price_limit_x96 = if zero_for_one {
-            MIN_SQRT_RATIO + U256::from(1)
-        } else {
-            MAX_SQRT_RATIO - U256::from(1)
+    fn to_quoter(&self, snapshot: &PoolSnapshot) -> Result<QuotePool, ArbRsError> {
+        let PoolSnapshot::UniswapV3(v3_snapshot) = snapshot else {
+            return Err(ArbRsError::CalculationError(
+                "Invalid snapshot for V3 pool".into(),
+            ));
         };
-
-        let (amount0_delta, amount1_delta, _final_state) = self._calculate_swap_from_snapshot(
-            zero_for_one,
-            amount_specified,
-            sqrt_price_limit_x96,
-            v3_snapshot,
-        )?;
-
-        Ok(if zero_for_one {
-            amount0_delta.into_raw()
-        } else {
-            amount1_delta.into_raw()
-        })
+        Ok(QuotePool::UniswapV3(UniswapV3Quoter {
+            address: self.address,
+            token0: self.token0.address(),
+            token1: self.token1.address(),
+            fee: self.fee,
+            tick_spacing: self.tick_spacing,
+            snapshot: Arc::new(v3_snapshot.clone()),
+            max_swap_words: self.max_swap_words,
+        }))
     }
 
     async fn nominal_price(
@@ -890,40 +1399,45 @@ impl<P: Provider + Send + Sync + 'static + ?Sized> LiquidityPool<P> for UniswapV
     }
 
     async fn get_snapshot(&self, block_number: Option<u64>) -> Result<PoolSnapshot, ArbRsError> {
-        let block_id = block_number.map(BlockId::from).unwrap_or(BlockId::latest());
-
-        let slot0_call = slot0Call {};
-        let slot0_request = TransactionRequest::default()
-            .to(self.address)
-            .input(slot0_call.abi_encode().into());
-
-        let liquidity_call = liquidityCall {};
-        let liquidity_request = TransactionRequest::default()
-            .to(self.address)
-            .input(liquidity_call.abi_encode().into());
-
-        let (slot0_res, liquidity_res) = tokio::join!(
-            self.provider.call(slot0_request).block(block_id),
-            self.provider.call(liquidity_request).block(block_id)
-        );
-
-        let slot0_bytes = slot0_res?;
-        let slot0_data = slot0Call::abi_decode_returns(&slot0_bytes)?;
-
-        let liquidity_bytes = liquidity_res?;
-        let liquidity_data = liquidityCall::abi_decode_returns(&liquidity_bytes)?;
-
-        let state_guard = self.state.read().await;
-
-        let snapshot = UniswapV3PoolSnapshot {
-            sqrt_price_x96: U256::from(slot0_data.sqrtPriceX96),
-            tick: slot0_data.tick.as_i32(),
-            liquidity: liquidity_data,
-            tick_bitmap: state_guard.tick_bitmap.clone(),
-            tick_data: state_guard.tick_data.clone(),
-        };
+        crate::metrics::observe_snapshot(Some(DexKind::UniswapV3), async {
+            let block_id = block_number.map(BlockId::from).unwrap_or(BlockId::latest());
+
+            let slot0_call = slot0Call {};
+            let slot0_request = TransactionRequest::default()
+                .to(self.address)
+                .input(slot0_call.abi_encode().into());
+
+            let liquidity_call = liquidityCall {};
+            let liquidity_request = TransactionRequest::default()
+                .to(self.address)
+                .input(liquidity_call.abi_encode().into());
+
+            let (slot0_res, liquidity_res) = tokio::join!(
+                self.provider.call(slot0_request).block(block_id),
+                self.provider.call(liquidity_request).block(block_id)
+            );
+
+            let slot0_bytes = slot0_res?;
+            let slot0_data = slot0Call::abi_decode_returns(&slot0_bytes)?;
+
+            let liquidity_bytes = liquidity_res?;
+            let liquidity_data = liquidityCall::abi_decode_returns(&liquidity_bytes)?;
+
+            let state_guard = self.state.read().await;
+
+            let snapshot = UniswapV3PoolSnapshot {
+                sqrt_price_x96: U256::from(slot0_data.sqrtPriceX96),
+                tick: slot0_data.tick.as_i32(),
+                liquidity: liquidity_data,
+                tick_bitmap: state_guard.tick_bitmap.clone(),
+                tick_data: state_guard.tick_data.clone(),
+                fee_protocol: slot0_data.feeProtocol,
+            };
 
-        Ok(PoolSnapshot::UniswapV3(snapshot))
+            Ok(PoolSnapshot::UniswapV3(snapshot))
+        })
+        .await
+        .with_ctx(|| pool_context(self.address).with_block(block_number))
     }
 }
 
@@ -935,6 +1449,7 @@ impl<P: Provider + Send + Sync + 'static + ?Sized> Debug for UniswapV3Pool<P> {
             .field("token1", &self.token1.symbol())
             .field("fee", &self.fee)
             .field("tick_spacing", &self.tick_spacing)
+            .field("non_standard_tier", &self.non_standard_tier)
             .finish_non_exhaustive()
     }
 }
@@ -947,7 +1462,21 @@ impl From<UniswapV3PoolSnapshot> for UniswapV3PoolState {
             liquidity: snapshot.liquidity,
             tick_bitmap: snapshot.tick_bitmap,
             tick_data: snapshot.tick_data,
+            fee_protocol: snapshot.fee_protocol,
             block_number: 0,
         }
     }
 }
+
+impl From<UniswapV3PoolState> for UniswapV3PoolSnapshot {
+    fn from(state: UniswapV3PoolState) -> Self {
+        Self {
+            sqrt_price_x96: state.sqrt_price_x96,
+            tick: state.tick,
+            liquidity: state.liquidity,
+            tick_bitmap: state.tick_bitmap,
+            tick_data: state.tick_data,
+            fee_protocol: state.fee_protocol,
+        }
+    }
+}
@@ -1,8 +1,11 @@
+use crate::manager::log_scan::{LogScanConfig, chunked_log_scan};
+use crate::math::v3::{liquidity_math, tick_bitmap};
+use crate::pool::uniswap_v3::{liquidityCall, slot0Call};
 use crate::{ArbRsError, pool::uniswap_v3::TickInfo};
 use alloy_primitives::{Address, U256};
 use alloy_provider::Provider;
-use alloy_rpc_types::{Filter, Log as RpcLog};
-use alloy_sol_types::{SolEvent, sol};
+use alloy_rpc_types::{BlockId, Filter, Log as RpcLog, TransactionRequest};
+use alloy_sol_types::{SolCall, SolEvent, sol};
 use std::collections::BTreeMap;
 use std::sync::Arc;
 
@@ -31,13 +34,214 @@ pub struct UniswapV3PoolLiquidityMappingUpdate {
     pub tick_upper: i32,
 }
 
+impl From<UniswapV3LiquidityEvent> for UniswapV3PoolLiquidityMappingUpdate {
+    fn from(event: UniswapV3LiquidityEvent) -> Self {
+        Self {
+            block_number: event.block_number,
+            liquidity: event.liquidity,
+            tick_lower: event.tick_lower,
+            tick_upper: event.tick_upper,
+        }
+    }
+}
+
 /// A complete snapshot of a pool's tick-level liquidity.
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Default, PartialEq)]
 pub struct LiquidityMap {
     pub tick_bitmap: BTreeMap<i16, U256>,
     pub tick_data: BTreeMap<i32, TickInfo>,
 }
 
+impl LiquidityMap {
+    /// Applies a `Mint` or `Burn` to the ticks bounding its range.
+    pub fn apply_update(
+        &mut self,
+        update: &UniswapV3PoolLiquidityMappingUpdate,
+        tick_spacing: i32,
+    ) {
+        apply_liquidity_update(
+            &mut self.tick_bitmap,
+            &mut self.tick_data,
+            update,
+            tick_spacing,
+        );
+    }
+
+    /// The liquidity in range while the price is at `tick`: the net liquidity of every
+    /// initialized tick at or below it.
+    pub fn liquidity_at(&self, tick: i32) -> u128 {
+        let net: i128 = self
+            .tick_data
+            .range(..=tick)
+            .map(|(_, info)| info.liquidity_net)
+            .sum();
+        net.max(0) as u128
+    }
+}
+
+/// Adds `update`'s liquidity to both ticks' gross liquidity and crosses it into the range at
+/// the lower tick and out at the upper one, as the pool's `_updatePosition` does. A tick
+/// becoming initialized or cleared flips its bit in the bitmap.
+pub(crate) fn apply_liquidity_update(
+    tick_bitmap: &mut BTreeMap<i16, U256>,
+    tick_data: &mut BTreeMap<i32, TickInfo>,
+    update: &UniswapV3PoolLiquidityMappingUpdate,
+    tick_spacing: i32,
+) {
+    for (tick, net_delta) in [
+        (update.tick_lower, update.liquidity),
+        (update.tick_upper, -update.liquidity),
+    ] {
+        let info = tick_data.entry(tick).or_default();
+        let was_initialized = info.liquidity_gross != 0;
+        info.liquidity_gross =
+            liquidity_math::add_delta(info.liquidity_gross, update.liquidity).unwrap_or(0);
+        info.liquidity_net += net_delta;
+        let initialized = info.liquidity_gross != 0;
+        if !initialized {
+            tick_data.remove(&tick);
+        }
+        if was_initialized != initialized {
+            let (word, bit) = tick_bitmap::position(tick / tick_spacing);
+            let bits = tick_bitmap.entry(word).or_default();
+            *bits ^= U256::ONE << bit;
+            if bits.is_zero() {
+                tick_bitmap.remove(&word);
+            }
+        }
+    }
+}
+
+/// Decodes a pool's `Mint` or `Burn` log into the pool's address and the liquidity change.
+fn decode_liquidity_event(log: &RpcLog) -> Result<(Address, UniswapV3LiquidityEvent), ArbRsError> {
+    let pool_address = log.address();
+    let topics = log.topics();
+
+    let (liquidity, tick_lower, tick_upper) = if topics[0] == Mint::SIGNATURE_HASH {
+        let decoded = Mint::decode_log_data(&log.inner.data)?;
+        (decoded.amount as i128, decoded.tickLower, decoded.tickUpper)
+    } else if topics[0] == Burn::SIGNATURE_HASH {
+        let decoded = Burn::decode_log_data(&log.inner.data)?;
+        (
+            -(decoded.amount as i128),
+            decoded.tickLower,
+            decoded.tickUpper,
+        )
+    } else {
+        return Err(ArbRsError::AbiDecodeError(
+            "Unknown event signature".to_string(),
+        ));
+    };
+
+    Ok((
+        pool_address,
+        UniswapV3LiquidityEvent {
+            block_number: log.block_number.unwrap_or(0),
+            tx_index: log.transaction_index.unwrap_or(0),
+            log_index: log.log_index.unwrap_or(0),
+            liquidity,
+            tick_lower: tick_lower.try_into().unwrap(),
+            tick_upper: tick_upper.try_into().unwrap(),
+        },
+    ))
+}
+
+/// Builds a pool's [`LiquidityMap`] offline from its `Mint` and `Burn` logs over a block range,
+/// for `UniswapV3Pool::new`, instead of reading every bitmap word and tick.
+pub struct LiquidityMapBuilder<P: ?Sized> {
+    provider: Arc<P>,
+    pool: Address,
+    tick_spacing: i32,
+    log_scan: LogScanConfig,
+    initial_map: LiquidityMap,
+}
+
+impl<P: Provider + Send + Sync + 'static + ?Sized> LiquidityMapBuilder<P> {
+    pub fn new(provider: Arc<P>, pool: Address, tick_spacing: i32) -> Self {
+        Self {
+            provider,
+            pool,
+            tick_spacing,
+            log_scan: LogScanConfig::default(),
+            initial_map: LiquidityMap::default(),
+        }
+    }
+
+    pub fn with_log_scan(mut self, log_scan: LogScanConfig) -> Self {
+        self.log_scan = log_scan;
+        self
+    }
+
+    /// Starts from `map`, the pool's map as of the block before the scanned range, rather than
+    /// from an empty one, e.g. to bring a stored map up to date.
+    pub fn with_initial_map(mut self, map: LiquidityMap) -> Self {
+        self.initial_map = map;
+        self
+    }
+
+    /// The pool's map at `to_block`, folding its logs from `from_block` on. Unless the range
+    /// starts at the pool's deployment or at the initial map's block, the map misses liquidity,
+    /// so a map disagreeing with the pool's `liquidity()` at its current tick is a
+    /// `LiquidityMismatch`.
+    pub async fn build(&self, from_block: u64, to_block: u64) -> Result<LiquidityMap, ArbRsError> {
+        let mut map = self.initial_map.clone();
+        for update in self.fetch_updates(from_block, to_block).await? {
+            map.apply_update(&update, self.tick_spacing);
+        }
+
+        let block_id = BlockId::from(to_block);
+        let request = |input: Vec<u8>| {
+            TransactionRequest::default()
+                .to(self.pool)
+                .input(input.into())
+        };
+        let slot0_bytes = self
+            .provider
+            .call(request(slot0Call {}.abi_encode()))
+            .block(block_id)
+            .await?;
+        let tick = slot0Call::abi_decode_returns(&slot0_bytes)?.tick.as_i32();
+        let liquidity_bytes = self
+            .provider
+            .call(request(liquidityCall {}.abi_encode()))
+            .block(block_id)
+            .await?;
+        let onchain = liquidityCall::abi_decode_returns(&liquidity_bytes)?;
+
+        let computed = map.liquidity_at(tick);
+        if computed != onchain {
+            return Err(ArbRsError::LiquidityMismatch {
+                pool: self.pool,
+                block: to_block,
+                computed,
+                onchain,
+            });
+        }
+        Ok(map)
+    }
+
+    /// The pool's liquidity changes from `from_block` to `to_block`, both included, in the
+    /// order they happened.
+    pub async fn fetch_updates(
+        &self,
+        from_block: u64,
+        to_block: u64,
+    ) -> Result<Vec<UniswapV3PoolLiquidityMappingUpdate>, ArbRsError> {
+        let filter = Filter::new()
+            .address(self.pool)
+            .event_signature(vec![Mint::SIGNATURE_HASH, Burn::SIGNATURE_HASH]);
+        let mut scan = chunked_log_scan(filter, from_block, to_block, self.log_scan);
+        let mut events = Vec::new();
+        while let Some(chunk) = scan.next_chunk(self.provider.as_ref()).await? {
+            for log in &chunk.logs {
+                events.push(decode_liquidity_event(log)?.1);
+            }
+        }
+        events.sort_by_key(|e| (e.block_number, e.tx_index, e.log_index));
+        Ok(events.into_iter().map(Into::into).collect())
+    }
+}
+
 pub struct UniswapV3LiquiditySnapshot<P: ?Sized> {
     provider: Arc<P>,
     chain_id: u64,
@@ -83,8 +287,8 @@ impl<P: Provider + Send + Sync + 'static + ?Sized> UniswapV3LiquiditySnapshot<P>
             self.provider.get_logs(&burn_filter)
         );
 
-        let mint_logs = mint_logs_res.map_err(|e| ArbRsError::ProviderError(e.to_string()))?;
-        let burn_logs = burn_logs_res.map_err(|e| ArbRsError::ProviderError(e.to_string()))?;
+        let mint_logs = mint_logs_res?;
+        let burn_logs = burn_logs_res?;
 
         let all_logs = mint_logs.into_iter().chain(burn_logs.into_iter());
 
@@ -101,36 +305,7 @@ impl<P: Provider + Send + Sync + 'static + ?Sized> UniswapV3LiquiditySnapshot<P>
     }
 
     fn process_log(&self, log: &RpcLog) -> Result<(Address, UniswapV3LiquidityEvent), ArbRsError> {
-        let pool_address = log.address();
-        let topics = log.topics();
-
-        let (liquidity, tick_lower, tick_upper) = if topics[0] == Mint::SIGNATURE_HASH {
-            let decoded = Mint::decode_log_data(&log.inner.data)?;
-            (decoded.amount as i128, decoded.tickLower, decoded.tickUpper)
-        } else if topics[0] == Burn::SIGNATURE_HASH {
-            let decoded = Burn::decode_log_data(&log.inner.data)?;
-            (
-                -(decoded.amount as i128),
-                decoded.tickLower,
-                decoded.tickUpper,
-            )
-        } else {
-            return Err(ArbRsError::AbiDecodeError(
-                "Unknown event signature".to_string(),
-            ));
-        };
-
-        Ok((
-            pool_address,
-            UniswapV3LiquidityEvent {
-                block_number: log.block_number.unwrap_or(0),
-                tx_index: log.transaction_index.unwrap_or(0),
-                log_index: log.log_index.unwrap_or(0),
-                liquidity,
-                tick_lower: tick_lower.try_into().unwrap(),
-                tick_upper: tick_upper.try_into().unwrap(),
-            },
-        ))
+        decode_liquidity_event(log)
     }
 
     /// Consumes pending liquidity updates for a pool, sorted chronologically.
@@ -142,15 +317,7 @@ impl<P: Provider + Send + Sync + 'static + ?Sized> UniswapV3LiquiditySnapshot<P>
             let mut sorted_events = events;
             sorted_events.sort_by_key(|e| (e.block_number, e.tx_index, e.log_index));
 
-            sorted_events
-                .into_iter()
-                .map(|event| UniswapV3PoolLiquidityMappingUpdate {
-                    block_number: event.block_number,
-                    liquidity: event.liquidity,
-                    tick_lower: event.tick_lower,
-                    tick_upper: event.tick_upper,
-                })
-                .collect()
+            sorted_events.into_iter().map(Into::into).collect()
         } else {
             Vec::new()
         }
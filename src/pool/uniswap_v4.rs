@@ -0,0 +1,891 @@
+//! Uniswap V4 pools without hooks. Every V4 pool lives in the one `PoolManager` contract,
+//! known by the id hashed from its [`PoolKey`], so a pool here has no contract of its own:
+//! its state is read by id through the `StateView` periphery contract, and its swaps run
+//! the V3 tick math.
+
+use crate::TokenLike;
+use crate::core::token::Token;
+use crate::errors::{ArbRsError, PoolContext, WithContext};
+use crate::math::utils::u256_to_f64;
+use crate::math::v3::constants::{MAX_SQRT_RATIO, MIN_SQRT_RATIO};
+use crate::pool::cache::{CacheConfig, CacheStats};
+use crate::pool::quoter::{QuotePool, Quoter};
+use crate::pool::tick_words::{self, TickWord};
+use crate::pool::uniswap_v3::{SwapMath, TickInfo, UniswapV3PoolSnapshot};
+use crate::pool::uniswap_v3_snapshot::LiquidityMap;
+use crate::pool::{
+    CalibrationBucket, DexKind, LiquidityPool, PoolSnapshot, PriceMatrix, StateUpdate, SwapGasCosts,
+};
+use alloy_primitives::{
+    Address, B256, I256, U256, address,
+    aliases::{I24, U24},
+    keccak256,
+};
+use alloy_provider::Provider;
+use alloy_rpc_types::{BlockId, Filter, TransactionRequest};
+use alloy_sol_types::{SolCall, SolEvent, SolValue, sol};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::any::Any;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::{Debug, Formatter, Result as FmtResult};
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+/// The mainnet `PoolManager`, holding every V4 pool.
+pub const UNISWAP_V4_POOL_MANAGER: Address = address!("000000000004444c5dc75cB358380D2e3dE08A90");
+/// The mainnet `StateView`, reading a pool's slot0, liquidity and ticks by its id.
+pub const UNISWAP_V4_STATE_VIEW: Address = address!("7fFE42C4a5DEeA5b0feC41C94C136Cf115597227");
+
+/// Fees are in hundredths of a basis point, out of a million.
+const PIPS_DENOMINATOR: u32 = 1_000_000;
+
+sol! {
+    function getSlot0(bytes32 poolId) external view returns (uint160 sqrtPriceX96, int24 tick, uint24 protocolFee, uint24 lpFee);
+    function getLiquidity(bytes32 poolId) external view returns (uint128 liquidity);
+    function getTickBitmap(bytes32 poolId, int16 tick) external view returns (uint256 tickBitmap);
+    function getTickLiquidity(bytes32 poolId, int24 tick) external view returns (uint128 liquidityGross, int128 liquidityNet);
+
+    event ModifyLiquidity(bytes32 indexed id, address indexed sender, int24 tickLower, int24 tickUpper, int256 liquidityDelta, bytes32 salt);
+}
+
+/// What a V4 pool is initialized with, and hashed into its id. A `currency0` of
+/// `Address::ZERO` is native ether.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct PoolKey {
+    pub currency0: Address,
+    pub currency1: Address,
+    /// LP fee in hundredths of a basis point, or the dynamic fee flag for a pool whose
+    /// hooks set it.
+    pub fee: u32,
+    pub tick_spacing: i32,
+    pub hooks: Address,
+}
+
+impl PoolKey {
+    /// `keccak256(abi.encode(key))`, the id the pool manager knows the pool by.
+    pub fn pool_id(&self) -> B256 {
+        keccak256(
+            (
+                self.currency0,
+                self.currency1,
+                U24::from(self.fee),
+                I24::try_from(self.tick_spacing).unwrap_or_default(),
+                self.hooks,
+            )
+                .abi_encode(),
+        )
+    }
+
+    pub fn has_hooks(&self) -> bool {
+        !self.hooks.is_zero()
+    }
+
+    /// Whether the pool trades native ether, which sorts first as `Address::ZERO`.
+    pub fn is_native(&self) -> bool {
+        self.currency0.is_zero()
+    }
+}
+
+/// The address a V4 pool is keyed by everywhere a pool is known by address: the last 20
+/// bytes of its id.
+pub fn v4_pool_address(pool_id: B256) -> Address {
+    Address::from_word(pool_id)
+}
+
+/// The fee a swap pays, in hundredths of a basis point, as `ProtocolFeeLibrary` computes it:
+/// the protocol's fee for the swap direction, and the LP fee on what's left of the input.
+/// `protocol_fee` holds the zero-for-one fee in its low 12 bits and the other in the next 12.
+pub fn swap_fee(protocol_fee: u32, lp_fee: u32, zero_for_one: bool) -> u32 {
+    let protocol_fee = if zero_for_one {
+        protocol_fee & 0xfff
+    } else {
+        (protocol_fee >> 12) & 0xfff
+    };
+    let cross = (protocol_fee as u64 * lp_fee as u64 / PIPS_DENOMINATOR as u64) as u32;
+    protocol_fee + lp_fee - cross
+}
+
+/// A V4 pool's state at a block. `state` holds its price, in-range liquidity and ticks as a
+/// V3 snapshot does, with `fee_protocol` left at zero: V4 adds its protocol fee to the LP
+/// fee rather than taking it out of it.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct UniswapV4PoolSnapshot {
+    pub state: UniswapV3PoolSnapshot,
+    /// `protocolFee` from slot0, see [`swap_fee`].
+    pub protocol_fee: u32,
+    /// `lpFee` from slot0.
+    pub lp_fee: u32,
+}
+
+impl UniswapV4PoolSnapshot {
+    pub fn swap_fee(&self, zero_for_one: bool) -> u32 {
+        swap_fee(self.protocol_fee, self.lp_fee, zero_for_one)
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct UniswapV4PoolState {
+    pub sqrt_price_x96: U256,
+    pub tick: i32,
+    pub liquidity: u128,
+    pub protocol_fee: u32,
+    pub lp_fee: u32,
+    pub block_number: u64,
+}
+
+/// The swap math for a swap in one direction. A fee of the whole input would never move
+/// the price, and the pool manager refuses such swaps.
+fn swap_math(
+    pool: Address,
+    tick_spacing: i32,
+    max_swap_words: Option<u32>,
+    snapshot: &UniswapV4PoolSnapshot,
+    zero_for_one: bool,
+) -> Result<SwapMath, ArbRsError> {
+    let fee = snapshot.swap_fee(zero_for_one);
+    if fee >= PIPS_DENOMINATOR {
+        return Err(ArbRsError::CalculationError(format!(
+            "Swap fee {fee} takes the whole input"
+        )));
+    }
+    Ok(SwapMath::new(pool, fee, tick_spacing, max_swap_words))
+}
+
+/// A V4 pool's swap math and one snapshot, quoting without the pool.
+#[derive(Debug, Clone)]
+pub struct UniswapV4Quoter {
+    pub address: Address,
+    /// The pool's first token, WETH when it trades native ether.
+    pub token0: Address,
+    pub token1: Address,
+    pub tick_spacing: i32,
+    pub snapshot: Arc<UniswapV4PoolSnapshot>,
+    /// Bitmap words a swap may search, see [`UniswapV4Pool::with_max_swap_words`].
+    pub max_swap_words: Option<u32>,
+}
+
+impl UniswapV4Quoter {
+    fn check_pair(&self, token_in: Address, token_out: Address) -> Result<(), ArbRsError> {
+        if (token_in, token_out) == (self.token0, self.token1)
+            || (token_in, token_out) == (self.token1, self.token0)
+        {
+            Ok(())
+        } else {
+            Err(ArbRsError::CalculationError(
+                "Token pair does not match pool".into(),
+            ))
+        }
+    }
+}
+
+impl Quoter for UniswapV4Quoter {
+    fn calculate_out(
+        &self,
+        token_in: Address,
+        token_out: Address,
+        amount_in: U256,
+    ) -> Result<U256, ArbRsError> {
+        self.check_pair(token_in, token_out)?;
+        let zero_for_one = token_in == self.token0;
+        swap_math(
+            self.address,
+            self.tick_spacing,
+            self.max_swap_words,
+            &self.snapshot,
+            zero_for_one,
+        )
+        .and_then(|math| math.exact_input(zero_for_one, amount_in, &self.snapshot.state))
+        .with_ctx(|| pool_context(self.address))
+    }
+
+    fn calculate_in(
+        &self,
+        token_in: Address,
+        token_out: Address,
+        amount_out: U256,
+    ) -> Result<U256, ArbRsError> {
+        self.check_pair(token_in, token_out)?;
+        let zero_for_one = token_out == self.token1;
+        swap_math(
+            self.address,
+            self.tick_spacing,
+            self.max_swap_words,
+            &self.snapshot,
+            zero_for_one,
+        )
+        .and_then(|math| math.exact_output(zero_for_one, amount_out, &self.snapshot.state, None))
+        .with_ctx(|| pool_context(self.address))
+    }
+}
+
+/// Context naming a pool as a Uniswap V4 one.
+fn pool_context(address: Address) -> PoolContext {
+    PoolContext::new(address).with_kind("Uniswap V4")
+}
+
+/// A hook-less V4 pool. Native ether is listed as the chain's wrapped native token, as
+/// Curve pools list it, and [`is_native`](LiquidityPool::is_native) tells the executor to
+/// unwrap it.
+pub struct UniswapV4Pool<P: ?Sized> {
+    key: PoolKey,
+    pool_id: B256,
+    address: Address,
+    pool_manager: Address,
+    state_view: Address,
+    token0: Arc<Token<P>>,
+    token1: Arc<Token<P>>,
+    provider: Arc<P>,
+    pub state: RwLock<UniswapV4PoolState>,
+    state_cache: RwLock<BTreeMap<u64, UniswapV4PoolState>>,
+    cache_config: CacheConfig,
+    liquidity_map: RwLock<LiquidityMap>,
+    /// Block the liquidity map was last read from the chain at, if ever.
+    liquidity_map_block: RwLock<Option<u64>>,
+    /// Bitmap words a swap may search for initialized ticks, unbounded if unset.
+    max_swap_words: Option<u32>,
+}
+
+impl<P: Provider + Send + Sync + 'static + ?Sized> UniswapV4Pool<P> {
+    /// The pool of `key` in `pool_manager`, read through `state_view`. `token0` stands in
+    /// for `key.currency0`, the wrapped native token if that's native ether.
+    pub fn new(
+        key: PoolKey,
+        pool_manager: Address,
+        state_view: Address,
+        token0: Arc<Token<P>>,
+        token1: Arc<Token<P>>,
+        provider: Arc<P>,
+    ) -> Self {
+        let pool_id = key.pool_id();
+        Self {
+            key,
+            pool_id,
+            address: v4_pool_address(pool_id),
+            pool_manager,
+            state_view,
+            token0,
+            token1,
+            provider,
+            state: RwLock::new(UniswapV4PoolState::default()),
+            state_cache: RwLock::new(BTreeMap::new()),
+            cache_config: CacheConfig::default(),
+            liquidity_map: RwLock::new(LiquidityMap::default()),
+            liquidity_map_block: RwLock::new(None),
+            max_swap_words: None,
+        }
+    }
+
+    /// Bounds the bitmap words a swap searches for initialized ticks, as
+    /// [`UniswapV3Pool::with_max_swap_words`](crate::pool::uniswap_v3::UniswapV3Pool::with_max_swap_words) does.
+    pub fn with_max_swap_words(mut self, max_swap_words: u32) -> Self {
+        self.max_swap_words = Some(max_swap_words.max(1));
+        self
+    }
+
+    /// Bounds the state cache to `cache_config.max_blocks` blocks instead of the default.
+    pub fn with_cache_config(mut self, cache_config: CacheConfig) -> Self {
+        self.cache_config = cache_config;
+        self
+    }
+
+    pub fn key(&self) -> &PoolKey {
+        &self.key
+    }
+
+    pub fn pool_id(&self) -> B256 {
+        self.pool_id
+    }
+
+    pub fn pool_manager(&self) -> Address {
+        self.pool_manager
+    }
+
+    pub fn fee(&self) -> u32 {
+        self.key.fee
+    }
+
+    pub fn tick_spacing(&self) -> i32 {
+        self.key.tick_spacing
+    }
+
+    /// The tick bitmap and tick data, and the block they were read from the chain at.
+    pub async fn liquidity_map(&self) -> (Option<u64>, LiquidityMap) {
+        (
+            *self.liquidity_map_block.read().await,
+            self.liquidity_map.read().await.clone(),
+        )
+    }
+
+    /// Replaces the liquidity map with one read at `block_number`, e.g. one stored by a
+    /// previous run.
+    pub async fn set_liquidity_map(&self, map: LiquidityMap, block_number: u64) {
+        *self.liquidity_map.write().await = map;
+        *self.liquidity_map_block.write().await = Some(block_number);
+    }
+
+    /// Brings the liquidity map up to `block_number`, as
+    /// [`UniswapV3Pool::refresh_liquidity_map`](crate::pool::uniswap_v3::UniswapV3Pool::refresh_liquidity_map)
+    /// does, with the words to read again found from the pool manager's `ModifyLiquidity`
+    /// logs for this pool. Returns the number of words read.
+    pub async fn refresh_liquidity_map(&self, block_number: u64) -> Result<usize, ArbRsError> {
+        let map_block = *self.liquidity_map_block.read().await;
+        let Some((fetched, full)) = tick_words::read_words(
+            map_block,
+            block_number,
+            self.key.tick_spacing,
+            |from_block, to_block| self.touched_words(from_block, to_block),
+            |word| self.fetch_word(word, block_number),
+        )
+        .await
+        .with_ctx(|| pool_context(self.address).with_block(Some(block_number)))?
+        else {
+            return Ok(0);
+        };
+
+        let mut guard = self.liquidity_map.write().await;
+        let map = &mut *guard;
+        tick_words::apply_words(
+            &mut map.tick_bitmap,
+            &mut map.tick_data,
+            &fetched,
+            self.key.tick_spacing,
+            full,
+        );
+        drop(guard);
+        *self.liquidity_map_block.write().await = Some(block_number);
+        Ok(fetched.len())
+    }
+
+    /// Bitmap words holding a tick of a `ModifyLiquidity` on this pool between the two
+    /// blocks.
+    async fn touched_words(
+        &self,
+        from_block: u64,
+        to_block: u64,
+    ) -> Result<BTreeSet<i16>, ArbRsError> {
+        let filter = Filter::new()
+            .address(self.pool_manager)
+            .event_signature(ModifyLiquidity::SIGNATURE_HASH)
+            .topic1(self.pool_id)
+            .from_block(from_block)
+            .to_block(to_block);
+        let logs = self.provider.get_logs(&filter).await?;
+
+        let mut words = BTreeSet::new();
+        for log in &logs {
+            let modify = ModifyLiquidity::decode_log_data(log.data())?;
+            words.insert(tick_words::word_position(
+                modify.tickLower.as_i32(),
+                self.key.tick_spacing,
+            ));
+            words.insert(tick_words::word_position(
+                modify.tickUpper.as_i32(),
+                self.key.tick_spacing,
+            ));
+        }
+        Ok(words)
+    }
+
+    /// Reads bitmap word `word` and the liquidity of each tick it marks initialized.
+    async fn fetch_word(&self, word: i16, block_number: u64) -> Result<TickWord, ArbRsError> {
+        let block_id = BlockId::from(block_number);
+        let bitmap_bytes = self
+            .provider
+            .call(
+                self.state_view_request(
+                    getTickBitmapCall {
+                        poolId: self.pool_id,
+                        tick: word,
+                    }
+                    .abi_encode(),
+                ),
+            )
+            .block(block_id)
+            .await?;
+        let bitmap = getTickBitmapCall::abi_decode_returns(&bitmap_bytes)?;
+
+        let mut ticks = Vec::new();
+        for tick in tick_words::initialized_ticks(word, bitmap, self.key.tick_spacing) {
+            let tick_arg =
+                I24::try_from(tick).map_err(|e| ArbRsError::CalculationError(e.to_string()))?;
+            let tick_bytes = self
+                .provider
+                .call(
+                    self.state_view_request(
+                        getTickLiquidityCall {
+                            poolId: self.pool_id,
+                            tick: tick_arg,
+                        }
+                        .abi_encode(),
+                    ),
+                )
+                .block(block_id)
+                .await?;
+            let info = getTickLiquidityCall::abi_decode_returns(&tick_bytes)?;
+            ticks.push((
+                tick,
+                TickInfo {
+                    liquidity_gross: info.liquidityGross,
+                    liquidity_net: info.liquidityNet,
+                },
+            ));
+        }
+        Ok((word, bitmap, ticks))
+    }
+
+    fn state_view_request(&self, input: Vec<u8>) -> TransactionRequest {
+        TransactionRequest::default()
+            .to(self.state_view)
+            .input(input.into())
+    }
+
+    /// Reads slot0 and the in-range liquidity at `block_id`.
+    async fn fetch_state(
+        &self,
+        block_id: BlockId,
+        block_number: u64,
+    ) -> Result<UniswapV4PoolState, ArbRsError> {
+        let (slot0_res, liquidity_res) = tokio::join!(
+            self.provider
+                .call(
+                    self.state_view_request(
+                        getSlot0Call {
+                            poolId: self.pool_id
+                        }
+                        .abi_encode()
+                    )
+                )
+                .block(block_id),
+            self.provider
+                .call(
+                    self.state_view_request(
+                        getLiquidityCall {
+                            poolId: self.pool_id
+                        }
+                        .abi_encode()
+                    )
+                )
+                .block(block_id)
+        );
+        let slot0 = getSlot0Call::abi_decode_returns(&slot0_res?)?;
+        let liquidity = getLiquidityCall::abi_decode_returns(&liquidity_res?)?;
+
+        Ok(UniswapV4PoolState {
+            sqrt_price_x96: U256::from(slot0.sqrtPriceX96),
+            tick: slot0.tick.as_i32(),
+            liquidity,
+            protocol_fee: slot0.protocolFee.to::<u32>(),
+            lp_fee: slot0.lpFee.to::<u32>(),
+            block_number,
+        })
+    }
+
+    fn validate_token_pair(
+        &self,
+        token_a: &Token<P>,
+        token_b: &Token<P>,
+    ) -> Result<(), ArbRsError> {
+        if !((token_a.address() == self.token0.address()
+            && token_b.address() == self.token1.address())
+            || (token_a.address() == self.token1.address()
+                && token_b.address() == self.token0.address()))
+        {
+            Err(ArbRsError::CalculationError(
+                "Token pair does not match pool".into(),
+            ))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn swap_math(
+        &self,
+        snapshot: &UniswapV4PoolSnapshot,
+        zero_for_one: bool,
+    ) -> Result<SwapMath, ArbRsError> {
+        swap_math(
+            self.address,
+            self.key.tick_spacing,
+            self.max_swap_words,
+            snapshot,
+            zero_for_one,
+        )
+    }
+
+    /// A [`NoLiquidity`](ArbRsError::NoLiquidity) error if `snapshot` has nothing in range
+    /// and no initialized tick to bring some in the swap direction.
+    pub fn check_liquidity(
+        &self,
+        zero_for_one: bool,
+        snapshot: &UniswapV4PoolSnapshot,
+    ) -> Result<(), ArbRsError> {
+        let swap_math = self.swap_math(snapshot, zero_for_one)?;
+        swap_math.check_liquidity(
+            zero_for_one,
+            &snapshot.state,
+            swap_math.last_word(zero_for_one, snapshot.state.tick),
+        )
+    }
+
+    /// The output of an exact-input swap of `amount_in` and the pool's snapshot after it.
+    /// A swap the liquidity runs out on pays what it reached.
+    pub fn simulate_exact_input_swap(
+        &self,
+        token_in: &Token<P>,
+        token_out: &Token<P>,
+        amount_in: U256,
+        snapshot: &UniswapV4PoolSnapshot,
+    ) -> Result<(U256, UniswapV4PoolSnapshot), ArbRsError> {
+        self.validate_token_pair(token_in, token_out)?;
+        let zero_for_one = token_in.address() == self.token0.address();
+        let sqrt_price_limit_x96 = if zero_for_one {
+            MIN_SQRT_RATIO + U256::from(1)
+        } else {
+            MAX_SQRT_RATIO - U256::from(1)
+        };
+        let outcome = self
+            .swap_math(snapshot, zero_for_one)
+            .and_then(|math| {
+                math.swap(
+                    zero_for_one,
+                    I256::from_raw(amount_in),
+                    sqrt_price_limit_x96,
+                    &snapshot.state,
+                )
+            })
+            .with_ctx(|| pool_context(self.address))?;
+        let amount_out = if zero_for_one {
+            (-outcome.amount1_delta).into_raw()
+        } else {
+            (-outcome.amount0_delta).into_raw()
+        };
+        Ok((
+            amount_out,
+            UniswapV4PoolSnapshot {
+                state: outcome.final_state,
+                ..snapshot.clone()
+            },
+        ))
+    }
+
+    fn v4_snapshot<'a>(
+        &self,
+        snapshot: &'a PoolSnapshot,
+    ) -> Result<&'a UniswapV4PoolSnapshot, ArbRsError> {
+        match snapshot {
+            PoolSnapshot::UniswapV4(s) => Ok(s),
+            _ => Err(ArbRsError::CalculationError(
+                "Invalid snapshot for V4 pool".into(),
+            )),
+        }
+    }
+}
+
+#[async_trait]
+impl<P: Provider + Send + Sync + 'static + ?Sized> LiquidityPool<P> for UniswapV4Pool<P> {
+    fn address(&self) -> Address {
+        self.address
+    }
+
+    fn get_all_tokens(&self) -> Vec<Arc<Token<P>>> {
+        vec![self.token0.clone(), self.token1.clone()]
+    }
+
+    /// The virtual reserves of the in-range liquidity at the current price.
+    fn reserves_summary(&self, snapshot: &PoolSnapshot) -> Vec<(Arc<Token<P>>, U256)> {
+        let PoolSnapshot::UniswapV4(snapshot) = snapshot else {
+            return Vec::new();
+        };
+        let (reserve0, reserve1) = snapshot.state.virtual_reserves();
+        vec![
+            (self.token0.clone(), reserve0),
+            (self.token1.clone(), reserve1),
+        ]
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn prices_matrix(&self, snapshot: &PoolSnapshot) -> PriceMatrix {
+        let PoolSnapshot::UniswapV4(snapshot) = snapshot else {
+            return PriceMatrix::new();
+        };
+        if snapshot.state.sqrt_price_x96.is_zero() {
+            return PriceMatrix::new();
+        }
+        let ratio = u256_to_f64(snapshot.state.sqrt_price_x96) / u256_to_f64(U256::from(1) << 96);
+        let price = ratio.powi(2);
+        let (token0, token1) = (self.token0.address(), self.token1.address());
+        PriceMatrix::from([((token0, token1), price), ((token1, token0), 1.0 / price)])
+    }
+
+    fn dex_kind(&self) -> Option<DexKind> {
+        Some(DexKind::UniswapV4)
+    }
+
+    /// The base cost plus the initialized ticks the swap crosses, simulated on `snapshot`.
+    fn gas_estimate(
+        &self,
+        token_in: &Token<P>,
+        _token_out: &Token<P>,
+        amount_in: U256,
+        snapshot: &PoolSnapshot,
+        costs: &SwapGasCosts,
+    ) -> u64 {
+        let ticks_crossed = match snapshot {
+            PoolSnapshot::UniswapV4(v4_snapshot) if !amount_in.is_zero() => {
+                let zero_for_one = token_in.address() == self.token0.address();
+                let sqrt_price_limit_x96 = if zero_for_one {
+                    MIN_SQRT_RATIO + U256::from(1)
+                } else {
+                    MAX_SQRT_RATIO - U256::from(1)
+                };
+                self.swap_math(v4_snapshot, zero_for_one)
+                    .and_then(|math| {
+                        math.swap(
+                            zero_for_one,
+                            I256::from_raw(amount_in),
+                            sqrt_price_limit_x96,
+                            &v4_snapshot.state,
+                        )
+                    })
+                    .map_or(0, |outcome| outcome.initialized_ticks_crossed)
+            }
+            _ => 0,
+        };
+        costs.uniswap_v4 + costs.uniswap_v3_tick_crossing * ticks_crossed
+    }
+
+    fn calibration_bucket(&self) -> Option<CalibrationBucket> {
+        Some(CalibrationBucket::new(
+            "uniswap_v4",
+            self.key.fee.to_string(),
+        ))
+    }
+
+    fn is_native(&self, token: Address) -> bool {
+        self.key.is_native() && token == self.token0.address()
+    }
+
+    async fn update_state(&self) -> Result<StateUpdate, ArbRsError> {
+        let latest_block = self.provider.get_block_number().await?;
+        let current_block_number = self.state.read().await.block_number;
+
+        if latest_block < current_block_number {
+            return Err(ArbRsError::LateUpdateError {
+                pool: self.address,
+                attempted_block: latest_block,
+                latest_block: current_block_number,
+            });
+        }
+        if latest_block == current_block_number && current_block_number != 0 {
+            return Ok(StateUpdate::Unchanged);
+        }
+
+        let fetched_state = self
+            .fetch_state(BlockId::from(latest_block), latest_block)
+            .await
+            .with_ctx(|| pool_context(self.address).with_block(Some(latest_block)))?;
+
+        let state_updated = {
+            let state = self.state.read().await;
+            state.sqrt_price_x96 != fetched_state.sqrt_price_x96
+                || state.liquidity != fetched_state.liquidity
+                || state.protocol_fee != fetched_state.protocol_fee
+                || state.lp_fee != fetched_state.lp_fee
+        };
+        if !state_updated {
+            return Ok(StateUpdate::Unchanged);
+        }
+
+        *self.state.write().await = fetched_state.clone();
+        self.cache_config.insert(
+            &mut *self.state_cache.write().await,
+            latest_block,
+            fetched_state,
+        );
+        Ok(StateUpdate::Updated {
+            block: latest_block,
+        })
+    }
+
+    async fn update_state_at_block(
+        &self,
+        block_number: u64,
+        allow_rewind: bool,
+    ) -> Result<(), ArbRsError> {
+        let current_block_number = self.state.read().await.block_number;
+        if block_number < current_block_number && !allow_rewind {
+            return Err(ArbRsError::LateUpdateError {
+                pool: self.address,
+                attempted_block: block_number,
+                latest_block: current_block_number,
+            });
+        }
+
+        let fetched_state = self
+            .fetch_state(BlockId::from(block_number), block_number)
+            .await
+            .with_ctx(|| pool_context(self.address).with_block(Some(block_number)))?;
+        {
+            let mut cache = self.state_cache.write().await;
+            if allow_rewind {
+                cache.retain(|&block, _| block <= block_number);
+            }
+            self.cache_config
+                .insert(&mut *cache, block_number, fetched_state.clone());
+        }
+        *self.state.write().await = fetched_state;
+        Ok(())
+    }
+
+    async fn invalidate_from(&self, block_number: u64) {
+        let restored = {
+            let mut cache = self.state_cache.write().await;
+            cache.retain(|&block, _| block < block_number);
+            cache.values().next_back().cloned()
+        };
+        {
+            let mut state = self.state.write().await;
+            if state.block_number >= block_number {
+                // Block 0 makes the next `update_state` read the slot again.
+                *state = restored.unwrap_or_default();
+            }
+        }
+        let mut map_block = self.liquidity_map_block.write().await;
+        if map_block.is_some_and(|map_block| map_block >= block_number) {
+            *map_block = None;
+        }
+    }
+
+    async fn discard_states_before_block(&self, block: u64) {
+        self.state_cache
+            .write()
+            .await
+            .retain(|&cached, _| cached >= block);
+    }
+
+    async fn cache_stats(&self) -> CacheStats {
+        CacheStats::of(self.state_cache.read().await.keys())
+    }
+
+    fn calculate_tokens_out(
+        &self,
+        token_in: &Token<P>,
+        token_out: &Token<P>,
+        amount_in: U256,
+        snapshot: &PoolSnapshot,
+    ) -> Result<U256, ArbRsError> {
+        self.validate_token_pair(token_in, token_out)?;
+        let v4_snapshot = self.v4_snapshot(snapshot)?;
+        let zero_for_one = token_in.address() == self.token0.address();
+        self.swap_math(v4_snapshot, zero_for_one)
+            .and_then(|math| math.exact_input(zero_for_one, amount_in, &v4_snapshot.state))
+            .with_ctx(|| pool_context(self.address))
+    }
+
+    fn calculate_tokens_in(
+        &self,
+        token_in: &Token<P>,
+        token_out: &Token<P>,
+        amount_out: U256,
+        snapshot: &PoolSnapshot,
+    ) -> Result<U256, ArbRsError> {
+        self.validate_token_pair(token_in, token_out)?;
+        let v4_snapshot = self.v4_snapshot(snapshot)?;
+        let zero_for_one = token_out.address() == self.token1.address();
+        self.swap_math(v4_snapshot, zero_for_one)
+            .and_then(|math| math.exact_output(zero_for_one, amount_out, &v4_snapshot.state, None))
+            .with_ctx(|| pool_context(self.address))
+    }
+
+    fn to_quoter(&self, snapshot: &PoolSnapshot) -> Result<QuotePool, ArbRsError> {
+        let v4_snapshot = self.v4_snapshot(snapshot)?;
+        Ok(QuotePool::UniswapV4(UniswapV4Quoter {
+            address: self.address,
+            token0: self.token0.address(),
+            token1: self.token1.address(),
+            tick_spacing: self.key.tick_spacing,
+            snapshot: Arc::new(v4_snapshot.clone()),
+            max_swap_words: self.max_swap_words,
+        }))
+    }
+
+    async fn nominal_price(
+        &self,
+        token_in: &Token<P>,
+        token_out: &Token<P>,
+    ) -> Result<f64, ArbRsError> {
+        let absolute_price = self.absolute_price(token_in, token_out).await?;
+        let scaling_factor = 10_f64.powi(token_in.decimals() as i32 - token_out.decimals() as i32);
+        Ok(absolute_price * scaling_factor)
+    }
+
+    async fn absolute_price(
+        &self,
+        token_in: &Token<P>,
+        token_out: &Token<P>,
+    ) -> Result<f64, ArbRsError> {
+        self.validate_token_pair(token_in, token_out)?;
+        let sqrt_price_x96 = self.state.read().await.sqrt_price_x96;
+        if sqrt_price_x96.is_zero() {
+            return Ok(0.0);
+        }
+        let ratio = u256_to_f64(sqrt_price_x96) / u256_to_f64(U256::from(1) << 96);
+        let price_of_token0_in_token1 = ratio.powi(2);
+
+        if token_in.address() == self.token0.address() {
+            Ok(price_of_token0_in_token1)
+        } else {
+            Ok(1.0 / price_of_token0_in_token1)
+        }
+    }
+
+    async fn absolute_exchange_rate(
+        &self,
+        token_in: &Token<P>,
+        token_out: &Token<P>,
+    ) -> Result<f64, ArbRsError> {
+        self.absolute_price(token_out, token_in).await
+    }
+
+    async fn get_snapshot(&self, block_number: Option<u64>) -> Result<PoolSnapshot, ArbRsError> {
+        crate::metrics::observe_snapshot(Some(DexKind::UniswapV4), async {
+            let block_id = block_number.map(BlockId::from).unwrap_or(BlockId::latest());
+            let state = self
+                .fetch_state(block_id, block_number.unwrap_or_default())
+                .await?;
+            let map = self.liquidity_map.read().await.clone();
+
+            Ok(PoolSnapshot::UniswapV4(UniswapV4PoolSnapshot {
+                state: UniswapV3PoolSnapshot {
+                    sqrt_price_x96: state.sqrt_price_x96,
+                    tick: state.tick,
+                    liquidity: state.liquidity,
+                    tick_bitmap: map.tick_bitmap,
+                    tick_data: map.tick_data,
+                    fee_protocol: 0,
+                },
+                protocol_fee: state.protocol_fee,
+                lp_fee: state.lp_fee,
+            }))
+        })
+        .await
+        .with_ctx(|| pool_context(self.address).with_block(block_number))
+    }
+}
+
+impl<P: Provider + Send + Sync + 'static + ?Sized> Debug for UniswapV4Pool<P> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.debug_struct("UniswapV4Pool")
+            .field("pool_id", &self.pool_id)
+            .field("token0", &self.token0.symbol())
+            .field("token1", &self.token1.symbol())
+            .field("fee", &self.key.fee)
+            .field("tick_spacing", &self.key.tick_spacing)
+            .field("native", &self.key.is_native())
+            .finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,205 @@
+//! Wrapping and unwrapping ether as a hop of its own, for paths through pools that trade
+//! native ether next to pools that trade WETH.
+
+use crate::TokenLike;
+use crate::core::token::Token;
+use crate::errors::ArbRsError;
+use crate::pool::quoter::{QuotePool, Quoter};
+use crate::pool::{DexKind, LiquidityPool, PoolSnapshot, StateUpdate, SwapGasCosts};
+use alloy_primitives::{Address, U256};
+use alloy_provider::Provider;
+use async_trait::async_trait;
+use std::any::Any;
+use std::fmt::{Debug, Formatter, Result as FmtResult};
+use std::sync::Arc;
+
+/// The WETH contract as a pool between native ether and WETH. Converts 1:1 with no fee and
+/// no state, costing only the gas of `deposit` or `withdraw`. Its address is WETH's.
+pub struct WrappedNativePool<P: ?Sized> {
+    weth: Arc<Token<P>>,
+    native: Arc<Token<P>>,
+}
+
+impl<P: Provider + Send + Sync + 'static + ?Sized> WrappedNativePool<P> {
+    pub fn new(weth: Arc<Token<P>>, native: Arc<Token<P>>) -> Self {
+        Self { weth, native }
+    }
+
+    pub fn weth(&self) -> &Arc<Token<P>> {
+        &self.weth
+    }
+
+    pub fn native(&self) -> &Arc<Token<P>> {
+        &self.native
+    }
+
+    fn check_pair(&self, token_in: &Token<P>, token_out: &Token<P>) -> Result<(), ArbRsError> {
+        self.quoter()
+            .check_pair(token_in.address(), token_out.address())
+    }
+
+    fn quoter(&self) -> WrappedNativeQuoter {
+        WrappedNativeQuoter {
+            weth: self.weth.address(),
+            native: self.native.address(),
+        }
+    }
+}
+
+/// Wrapping and unwrapping, quoted without the pool.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WrappedNativeQuoter {
+    pub weth: Address,
+    pub native: Address,
+}
+
+impl WrappedNativeQuoter {
+    fn check_pair(&self, token_in: Address, token_out: Address) -> Result<(), ArbRsError> {
+        let pair = (token_in, token_out);
+        if pair == (self.weth, self.native) || pair == (self.native, self.weth) {
+            return Ok(());
+        }
+        Err(ArbRsError::CalculationError(format!(
+            "{} -> {} is neither a wrap nor an unwrap",
+            pair.0, pair.1
+        )))
+    }
+}
+
+impl Quoter for WrappedNativeQuoter {
+    fn calculate_out(
+        &self,
+        token_in: Address,
+        token_out: Address,
+        amount_in: U256,
+    ) -> Result<U256, ArbRsError> {
+        self.check_pair(token_in, token_out)?;
+        Ok(amount_in)
+    }
+
+    fn calculate_in(
+        &self,
+        token_in: Address,
+        token_out: Address,
+        amount_out: U256,
+    ) -> Result<U256, ArbRsError> {
+        self.check_pair(token_in, token_out)?;
+        Ok(amount_out)
+    }
+}
+
+#[async_trait]
+impl<P: Provider + Send + Sync + 'static + ?Sized> LiquidityPool<P> for WrappedNativePool<P> {
+    fn address(&self) -> Address {
+        self.weth.address()
+    }
+
+    fn get_all_tokens(&self) -> Vec<Arc<Token<P>>> {
+        vec![self.native.clone(), self.weth.clone()]
+    }
+
+    /// Unbounded: ether and WETH convert 1:1 in any amount.
+    fn reserves_summary(&self, snapshot: &PoolSnapshot) -> Vec<(Arc<Token<P>>, U256)> {
+        let PoolSnapshot::WrappedNative = snapshot else {
+            return Vec::new();
+        };
+        vec![
+            (self.native.clone(), U256::MAX),
+            (self.weth.clone(), U256::MAX),
+        ]
+    }
+
+    async fn update_state(&self) -> Result<StateUpdate, ArbRsError> {
+        Ok(StateUpdate::Unchanged)
+    }
+
+    async fn update_state_at_block(
+        &self,
+        _block_number: u64,
+        _allow_rewind: bool,
+    ) -> Result<(), ArbRsError> {
+        Ok(())
+    }
+
+    async fn get_snapshot(&self, _block_number: Option<u64>) -> Result<PoolSnapshot, ArbRsError> {
+        Ok(PoolSnapshot::WrappedNative)
+    }
+
+    fn calculate_tokens_out(
+        &self,
+        token_in: &Token<P>,
+        token_out: &Token<P>,
+        amount_in: U256,
+        _snapshot: &PoolSnapshot,
+    ) -> Result<U256, ArbRsError> {
+        self.check_pair(token_in, token_out)?;
+        Ok(amount_in)
+    }
+
+    fn calculate_tokens_in(
+        &self,
+        token_in: &Token<P>,
+        token_out: &Token<P>,
+        amount_out: U256,
+        _snapshot: &PoolSnapshot,
+    ) -> Result<U256, ArbRsError> {
+        self.check_pair(token_in, token_out)?;
+        Ok(amount_out)
+    }
+
+    fn to_quoter(&self, _snapshot: &PoolSnapshot) -> Result<QuotePool, ArbRsError> {
+        Ok(QuotePool::WrappedNative(self.quoter()))
+    }
+
+    async fn absolute_price(
+        &self,
+        token_in: &Token<P>,
+        token_out: &Token<P>,
+    ) -> Result<f64, ArbRsError> {
+        self.check_pair(token_in, token_out)?;
+        Ok(1.0)
+    }
+
+    async fn nominal_price(
+        &self,
+        token_in: &Token<P>,
+        token_out: &Token<P>,
+    ) -> Result<f64, ArbRsError> {
+        self.check_pair(token_in, token_out)?;
+        Ok(1.0)
+    }
+
+    async fn absolute_exchange_rate(
+        &self,
+        token_in: &Token<P>,
+        token_out: &Token<P>,
+    ) -> Result<f64, ArbRsError> {
+        self.check_pair(token_in, token_out)?;
+        Ok(1.0)
+    }
+
+    fn dex_kind(&self) -> Option<DexKind> {
+        None
+    }
+
+    fn gas_estimate(
+        &self,
+        _token_in: &Token<P>,
+        _token_out: &Token<P>,
+        _amount_in: U256,
+        _snapshot: &PoolSnapshot,
+        costs: &SwapGasCosts,
+    ) -> u64 {
+        costs.wrap
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+impl<P: ?Sized> Debug for WrappedNativePool<P> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.debug_struct("WrappedNativePool").finish_non_exhaustive()
+    }
+}
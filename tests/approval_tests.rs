@@ -0,0 +1,185 @@
+mod common;
+
+use alloy::transports::mock::Asserter;
+use alloy_primitives::{Address, Bytes, U256, address};
+use alloy_provider::{Provider, ProviderBuilder};
+use alloy_rpc_types::Log;
+use alloy_sol_types::SolEvent;
+use arbrs::arbitrage::approvals::{APPROVAL_GAS_UNITS, ApprovalTracker, ApproveAction, IERC20};
+use arbrs::arbitrage::cache::ArbitrageCache;
+use arbrs::arbitrage::cycle::ArbitrageCycle;
+use arbrs::arbitrage::engine::{ArbitrageEngine, EngineConfig};
+use arbrs::arbitrage::types::{ArbitragePath, ArbitrageSolution};
+use arbrs::core::token::TokenLike;
+use arbrs::manager::token_manager::TokenManager;
+use arbrs::pool::strategy::StandardV2Logic;
+use arbrs::pool::uniswap_v2::{UniswapV2Pool, UniswapV2PoolState};
+use arbrs::pool::{LiquidityPool, PoolSnapshot};
+use common::token;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+type DynProvider = dyn Provider + Send + Sync;
+
+const WETH: Address = address!("C02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2");
+const EXECUTOR: Address = Address::repeat_byte(0xe0);
+const ROUTER: Address = Address::repeat_byte(0xf0);
+const BLOCK: u64 = 1;
+// The gas price the engine falls back to when the live one can't be fetched.
+const FALLBACK_GAS_PRICE: u64 = 20_000_000_000;
+
+fn mocked(asserter: &Asserter) -> Arc<DynProvider> {
+    Arc::new(ProviderBuilder::new().connect_mocked_client(asserter.clone()))
+}
+
+/// Queues the `allowance()` result of one call.
+fn push_allowance(asserter: &Asserter, allowance: U256) {
+    asserter.push_success(&Bytes::from(allowance.to_be_bytes::<32>()));
+}
+
+fn reserves(reserve0: u64, reserve1: u64) -> PoolSnapshot {
+    let ether = U256::from(10).pow(U256::from(18));
+    PoolSnapshot::UniswapV2(UniswapV2PoolState {
+        reserve0: U256::from(reserve0) * ether,
+        reserve1: U256::from(reserve1) * ether,
+        block_number: BLOCK,
+    })
+}
+
+fn approval_log(token: Address, owner: Address, spender: Address, value: U256) -> Log {
+    let event = IERC20::Approval {
+        owner,
+        spender,
+        value,
+    };
+    Log {
+        inner: alloy_primitives::Log {
+            address: token,
+            data: event.encode_log_data(),
+        },
+        block_number: Some(BLOCK),
+        ..Default::default()
+    }
+}
+
+/// Evaluates a mispriced two pool cycle whose tokens report `allowance` to the router.
+async fn solve(allowance: U256) -> ArbitrageSolution<DynProvider> {
+    // Pools and gas price calls fail, so the snapshots come from the overrides.
+    let provider = mocked(&Asserter::new());
+    let token_asserter = Asserter::new();
+    let token_provider = mocked(&token_asserter);
+    for _ in 0..2 {
+        push_allowance(&token_asserter, allowance);
+    }
+    let (profit_token, other) = (
+        token(WETH, token_provider.clone()),
+        token(Address::repeat_byte(0xee), token_provider),
+    );
+    let pools: Vec<Arc<dyn LiquidityPool<DynProvider>>> = [0x01, 0x02]
+        .into_iter()
+        .map(|byte| {
+            Arc::new(UniswapV2Pool::new(
+                Address::repeat_byte(byte),
+                profit_token.clone(),
+                other.clone(),
+                provider.clone(),
+                StandardV2Logic,
+            )) as Arc<dyn LiquidityPool<DynProvider>>
+        })
+        .collect();
+    let cache = Arc::new(ArbitrageCache::new());
+    cache
+        .add_path(Arc::new(ArbitrageCycle::new(ArbitragePath {
+            pools,
+            path: vec![profit_token.clone(), other, profit_token.clone()],
+            profit_token,
+        })))
+        .await;
+
+    let engine = ArbitrageEngine::new(
+        cache,
+        Arc::new(TokenManager::in_memory(provider.clone(), 1)),
+        provider,
+    )
+    .with_approvals(Arc::new(ApprovalTracker::new(EXECUTOR, [ROUTER])))
+    .with_config(EngineConfig {
+        min_net_profit_wei: U256::ZERO,
+        approval_spender: Some(ROUTER),
+        emit_approve_actions: true,
+        flashloan_sources: HashMap::new(),
+        ..Default::default()
+    });
+    let overrides = HashMap::from([
+        (Address::repeat_byte(0x01), reserves(1_000, 2_400_000)),
+        (Address::repeat_byte(0x02), reserves(1_000, 2_000_000)),
+    ]);
+    let mut solutions = engine
+        .find_opportunities_with_overrides(Some(BLOCK), overrides)
+        .await;
+    assert!(token_asserter.read_q().is_empty());
+    assert_eq!(solutions.len(), 1);
+    solutions.remove(0)
+}
+
+#[tokio::test]
+async fn test_approval_gas_only_without_allowance() {
+    let approved = solve(U256::MAX).await;
+    let unapproved = solve(U256::ZERO).await;
+
+    assert!(approved.approve_actions.is_empty());
+    assert_eq!(
+        unapproved.approve_actions,
+        [WETH, Address::repeat_byte(0xee)].map(|token| ApproveAction {
+            token,
+            spender: ROUTER,
+            amount: U256::MAX,
+        })
+    );
+
+    // Both tokens need an approval, priced at the fallback gas price.
+    let approval_gas = U256::from(2 * APPROVAL_GAS_UNITS * FALLBACK_GAS_PRICE);
+    assert_eq!(approved.optimal_input.raw, unapproved.optimal_input.raw);
+    assert_eq!(unapproved.gas_cost - approved.gas_cost, approval_gas);
+    assert_eq!(approved.net_profit.raw - unapproved.net_profit.raw, approval_gas);
+    assert_eq!(unapproved.scenario_results[0].gas_cost, unapproved.gas_cost);
+}
+
+#[tokio::test]
+async fn test_approval_log_updates_cache() {
+    let asserter = Asserter::new();
+    let token = token(WETH, mocked(&asserter));
+    let tracker = ApprovalTracker::new(EXECUTOR, [ROUTER]);
+    let amount = U256::from(1_000);
+
+    push_allowance(&asserter, U256::ZERO);
+    assert!(
+        tracker
+            .requires_approval(token.as_ref(), ROUTER, amount, Some(BLOCK))
+            .await
+            .unwrap()
+    );
+    assert!(asserter.read_q().is_empty());
+
+    // Logs for other owners or untracked spenders are ignored.
+    let other_owner = approval_log(token.address(), ROUTER, ROUTER, U256::MAX);
+    let untracked = approval_log(token.address(), EXECUTOR, EXECUTOR, U256::MAX);
+    assert!(!tracker.record_approval_log(&other_owner));
+    assert!(!tracker.record_approval_log(&untracked));
+
+    let approval = approval_log(token.address(), EXECUTOR, ROUTER, U256::MAX);
+    assert!(tracker.record_approval_log(&approval));
+    // Nothing is queued, so any call would fail.
+    assert!(
+        !tracker
+            .requires_approval(token.as_ref(), ROUTER, amount, Some(BLOCK + 1))
+            .await
+            .unwrap()
+    );
+    // Untracked spenders never need an approval.
+    assert!(
+        !tracker
+            .requires_approval(token.as_ref(), EXECUTOR, amount, Some(BLOCK + 1))
+            .await
+            .unwrap()
+    );
+}
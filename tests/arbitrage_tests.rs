@@ -0,0 +1,251 @@
+#![cfg(feature = "db")]
+
+mod common;
+
+use alloy_primitives::{Address, address};
+use alloy_provider::{Provider, ProviderBuilder};
+use arbrs::arbitrage::cache::ArbitrageCache;
+use arbrs::arbitrage::cycle::ArbitrageCycle;
+use arbrs::arbitrage::engine::ArbitrageEngine;
+use arbrs::arbitrage::types::{Arbitrage, ArbitragePath, ArbitrageSolution};
+use arbrs::core::token::{Token, TokenLike};
+use arbrs::db::DbManager;
+use arbrs::manager::token_manager::TokenManager;
+use arbrs::pool::strategy::{PancakeV2Logic, StandardV2Logic};
+use arbrs::pool::uniswap_v2::{UniswapV2Pool, UniswapV2PoolState};
+use arbrs::pool::{LiquidityPool, PoolSnapshot};
+use common::{ether, named_token};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+// Nothing listens here; snapshots come from overrides and the provider calls fail fast.
+const FORK_RPC_URL: &str = "http://127.0.0.1:8545";
+const DB_URL: &str = "sqlite::memory:";
+const WETH: Address = address!("C02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2");
+type DynProvider = dyn Provider + Send + Sync;
+
+struct Triangle {
+    provider: Arc<DynProvider>,
+    token_manager: Arc<TokenManager<DynProvider>>,
+    tokens: [Arc<Token<DynProvider>>; 3],
+    cycle: ArbitrageCycle<DynProvider>,
+    snapshots: HashMap<Address, PoolSnapshot>,
+}
+
+/// A -> B -> C -> A over three V2 pools. A is mispriced against B, and the pools
+/// charge different fees, so each entry token yields a different net profit. A is WETH,
+/// which prices B and C through their pools with it.
+async fn setup() -> Triangle {
+    let provider: Arc<DynProvider> =
+        Arc::new(ProviderBuilder::new().connect_http(FORK_RPC_URL.parse().unwrap()));
+    let db_manager = Arc::new(DbManager::new(DB_URL).await.unwrap());
+    let token_manager = Arc::new(TokenManager::new(provider.clone(), 1, db_manager));
+
+    let a = named_token(WETH, "A", 18, provider.clone());
+    let b = named_token(Address::repeat_byte(0x0b), "B", 18, provider.clone());
+    let c = named_token(Address::repeat_byte(0x0c), "C", 18, provider.clone());
+
+    let ab: Arc<dyn LiquidityPool<DynProvider>> = Arc::new(UniswapV2Pool::new(
+        Address::repeat_byte(0xab),
+        a.clone(),
+        b.clone(),
+        provider.clone(),
+        StandardV2Logic,
+    ));
+    let bc: Arc<dyn LiquidityPool<DynProvider>> = Arc::new(UniswapV2Pool::new(
+        Address::repeat_byte(0xbc),
+        b.clone(),
+        c.clone(),
+        provider.clone(),
+        PancakeV2Logic,
+    ));
+    let ca: Arc<dyn LiquidityPool<DynProvider>> = Arc::new(UniswapV2Pool::new(
+        Address::repeat_byte(0xca),
+        a.clone(),
+        c.clone(),
+        provider.clone(),
+        StandardV2Logic,
+    ));
+
+    let state = |reserve0: u64, reserve1: u64| {
+        PoolSnapshot::UniswapV2(UniswapV2PoolState {
+            reserve0: ether(reserve0),
+            reserve1: ether(reserve1),
+            block_number: 1,
+        })
+    };
+    let snapshots = HashMap::from([
+        (ab.address(), state(1_000, 1_100)),
+        (bc.address(), state(5_000, 5_000)),
+        (ca.address(), state(2_000, 2_000)),
+    ]);
+
+    let cycle = ArbitrageCycle::new(ArbitragePath {
+        pools: vec![ab, bc, ca],
+        path: vec![a.clone(), b.clone(), c.clone(), a.clone()],
+        profit_token: a.clone(),
+    });
+
+    Triangle {
+        provider,
+        token_manager,
+        tokens: [a, b, c],
+        cycle,
+        snapshots,
+    }
+}
+
+async fn run_engine(
+    triangle: &Triangle,
+    paths: Vec<Arc<dyn Arbitrage<DynProvider>>>,
+    entry_tokens: &[Address],
+) -> Vec<ArbitrageSolution<DynProvider>> {
+    let cache = Arc::new(ArbitrageCache::new());
+    for path in paths {
+        cache.add_path(path).await;
+    }
+    let engine = ArbitrageEngine::new(
+        cache,
+        triangle.token_manager.clone(),
+        triangle.provider.clone(),
+    )
+    .with_entry_tokens(entry_tokens.iter().copied());
+
+    engine
+        .find_opportunities_with_overrides(Some(1), triangle.snapshots.clone())
+        .await
+}
+
+fn profit_token_of(solution: &ArbitrageSolution<DynProvider>) -> Address {
+    let cycle = solution
+        .path
+        .as_any()
+        .downcast_ref::<ArbitrageCycle<DynProvider>>()
+        .unwrap();
+    cycle.path.profit_token.address()
+}
+
+#[test]
+fn test_rotations_share_cycle_id() {
+    let provider: Arc<DynProvider> =
+        Arc::new(ProviderBuilder::new().connect_http(FORK_RPC_URL.parse().unwrap()));
+    let a = named_token(Address::repeat_byte(0x0a), "A", 18, provider.clone());
+    let b = named_token(Address::repeat_byte(0x0b), "B", 18, provider.clone());
+    let pool = |byte: u8| -> Arc<dyn LiquidityPool<DynProvider>> {
+        Arc::new(UniswapV2Pool::new(
+            Address::repeat_byte(byte),
+            a.clone(),
+            b.clone(),
+            provider.clone(),
+            StandardV2Logic,
+        ))
+    };
+    let (p1, p2) = (pool(0x01), pool(0x02));
+
+    let forward = ArbitrageCycle::new(ArbitragePath {
+        pools: vec![p1.clone(), p2.clone()],
+        path: vec![a.clone(), b.clone(), a.clone()],
+        profit_token: a.clone(),
+    });
+    let rotated = forward.rotated(1);
+    assert_eq!(rotated.path.profit_token.address(), b.address());
+    assert_eq!(
+        rotated.get_involved_pools(),
+        vec![p2.address(), p1.address()]
+    );
+    assert_eq!(rotated.path.path.first(), rotated.path.path.last());
+    assert_eq!(rotated.cycle_id(), forward.cycle_id());
+
+    // Same pools traversed in the other direction is a different trade.
+    let reverse = ArbitrageCycle::new(ArbitragePath {
+        pools: vec![p1, p2],
+        path: vec![b.clone(), a.clone(), b],
+        profit_token: a,
+    });
+    assert_ne!(reverse.cycle_id(), forward.cycle_id());
+}
+
+#[tokio::test]
+async fn test_engine_picks_most_profitable_entry() {
+    let triangle = setup().await;
+    let path: Arc<dyn Arbitrage<DynProvider>> = Arc::new(triangle.cycle.rotated(0));
+
+    // Evaluate each entry on its own to find the expected winner.
+    let mut single_entry = Vec::new();
+    for token in &triangle.tokens {
+        let solutions = run_engine(&triangle, vec![path.clone()], &[token.address()]).await;
+        assert!(solutions.len() <= 1);
+        if let Some(solution) = solutions.into_iter().next() {
+            assert_eq!(profit_token_of(&solution), token.address());
+            single_entry.push((token.address(), solution.net_profit_weth));
+        }
+    }
+    assert!(
+        single_entry.len() >= 2,
+        "expected several profitable rotations, got {:?}",
+        single_entry
+    );
+    let (best_token, best_profit) = *single_entry.iter().max_by_key(|(_, p)| *p).unwrap();
+    let (worst_token, worst_profit) = *single_entry.iter().min_by_key(|(_, p)| *p).unwrap();
+    assert!(best_profit > worst_profit);
+    assert_ne!(best_token, worst_token);
+
+    // With every entry allowed, one solution is reported for the cycle: the best one.
+    let all_entries: Vec<Address> = triangle.tokens.iter().map(|t| t.address()).collect();
+    let solutions = run_engine(&triangle, vec![path.clone()], &all_entries).await;
+    assert_eq!(solutions.len(), 1);
+    assert_eq!(profit_token_of(&solutions[0]), best_token);
+    assert_eq!(solutions[0].net_profit_weth, best_profit);
+    assert_eq!(solutions[0].cycle_id, triangle.cycle.cycle_id());
+
+    // Rotations added to the cache collapse into a single path, and a single solution.
+    let rotations: Vec<Arc<dyn Arbitrage<DynProvider>>> = (0..3)
+        .map(|i| Arc::new(triangle.cycle.rotated(i)) as Arc<dyn Arbitrage<DynProvider>>)
+        .collect();
+    let solutions = run_engine(&triangle, rotations, &all_entries).await;
+    assert_eq!(solutions.len(), 1);
+    assert_eq!(profit_token_of(&solutions[0]), best_token);
+}
+
+#[tokio::test]
+async fn test_engine_without_entry_tokens_keeps_path_profit_token() {
+    let triangle = setup().await;
+    let path: Arc<dyn Arbitrage<DynProvider>> = Arc::new(triangle.cycle.rotated(0));
+
+    let solutions = run_engine(&triangle, vec![path], &[]).await;
+    assert_eq!(solutions.len(), 1);
+    assert_eq!(profit_token_of(&solutions[0]), triangle.tokens[0].address());
+}
+
+#[tokio::test]
+async fn test_cache_keeps_one_path_per_cycle() {
+    let triangle = setup().await;
+    let cache = ArbitrageCache::<DynProvider>::new();
+    assert!(cache.add_path(Arc::new(triangle.cycle.rotated(0))).await);
+    assert!(!cache.add_path(Arc::new(triangle.cycle.rotated(1))).await);
+    assert_eq!(cache.paths.read().await.len(), 1);
+    assert_eq!(
+        triangle.cycle.rotated(2).path_key(),
+        triangle.cycle.path_key()
+    );
+
+    // A -> C -> B -> A trades every pool the other way, so it's a path of its own.
+    let [a, b, c] = &triangle.tokens;
+    let pools = &triangle.cycle.path.pools;
+    let reversed = ArbitrageCycle::new(ArbitragePath {
+        pools: vec![pools[2].clone(), pools[1].clone(), pools[0].clone()],
+        path: vec![a.clone(), c.clone(), b.clone(), a.clone()],
+        profit_token: a.clone(),
+    });
+    let cached = cache.paths.read().await[0].clone();
+    let added = cache
+        .merge_paths(vec![
+            Arc::new(triangle.cycle.rotated(2)),
+            Arc::new(reversed),
+        ])
+        .await;
+    assert_eq!(added, 1);
+    let paths = cache.paths.read().await;
+    assert_eq!(paths.len(), 2);
+    assert!(Arc::ptr_eq(&paths[0], &cached), "the cached path is kept");
+}
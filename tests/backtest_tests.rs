@@ -0,0 +1,177 @@
+mod common;
+
+use alloy::transports::mock::Asserter;
+use alloy_primitives::{Address, U64, U256, address, aliases::U112};
+use alloy_provider::{Provider, ProviderBuilder};
+use alloy_rpc_types::{Block, FeeHistory};
+use alloy_sol_types::{SolCall, sol};
+use arbrs::arbitrage::backtest::{BacktestReport, Backtester};
+use arbrs::arbitrage::engine::{ArbitrageEngine, EngineConfig};
+use arbrs::arbitrage::gas::FixedGas;
+use arbrs::pool::LiquidityPool;
+use arbrs::pool::strategy::StandardV2Logic;
+use arbrs::pool::uniswap_v2::UniswapV2Pool;
+use common::token;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+type DynProvider = dyn Provider + Send + Sync;
+
+const WETH: Address = address!("C02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2");
+
+sol! {
+    function getReserves() external view returns (uint112 reserve0, uint112 reserve1, uint32 blockTimestampLast);
+}
+
+fn ether(amount: u64) -> U112 {
+    U112::from(amount) * U112::from(10u64.pow(18))
+}
+
+fn mocked(asserter: &Asserter) -> Arc<DynProvider> {
+    Arc::new(ProviderBuilder::new().connect_mocked_client(asserter.clone()))
+}
+
+fn push_reserves(asserter: &Asserter, other: u64) {
+    asserter.push_success(&getReservesCall::abi_encode_returns(&getReservesReturn {
+        reserve0: ether(1_000),
+        reserve1: ether(other),
+        blockTimestampLast: 0,
+    }));
+}
+
+/// Answers the gas estimator: the block's base fee, then a fee history paying no tip.
+fn push_base_fee(asserter: &Asserter, gwei: u64) {
+    let mut block: Block = Block::default();
+    block.header.inner.base_fee_per_gas = Some(gwei * 1_000_000_000);
+    asserter.push_success(&block);
+    asserter.push_success(&FeeHistory {
+        reward: Some(vec![vec![0]]),
+        ..Default::default()
+    });
+}
+
+/// Two WETH pairs on their own mocked providers, whose reserves at blocks 1 to 3 are
+/// queued: pair 0x01 pays 20% more for WETH in blocks 1 and 3, and nothing in block 2.
+async fn backtester(engine_asserter: &Asserter) -> (Backtester<DynProvider>, [Asserter; 2]) {
+    let provider = mocked(engine_asserter);
+    let (weth, other) = (
+        token(WETH, provider.clone()),
+        token(Address::repeat_byte(0xee), provider.clone()),
+    );
+    let asserters = [Asserter::new(), Asserter::new()];
+    let pools: Vec<Arc<dyn LiquidityPool<DynProvider>>> = asserters
+        .iter()
+        .enumerate()
+        .map(|(i, asserter)| {
+            Arc::new(UniswapV2Pool::new(
+                Address::repeat_byte(i as u8 + 1),
+                weth.clone(),
+                other.clone(),
+                mocked(asserter),
+                StandardV2Logic,
+            )) as Arc<dyn LiquidityPool<DynProvider>>
+        })
+        .collect();
+    for other in [2_400_000, 2_000_000, 2_400_000] {
+        push_reserves(&asserters[0], other);
+        push_reserves(&asserters[1], 2_000_000);
+    }
+
+    engine_asserter.push_success(&U64::from(1));
+    let engine = ArbitrageEngine::quote_only(provider.clone(), pools)
+        .await
+        .unwrap()
+        .with_config(EngineConfig {
+            flashloan_sources: HashMap::new(),
+            ..Default::default()
+        });
+    (Backtester::new(engine), asserters)
+}
+
+#[tokio::test]
+async fn test_backtest_replays_each_block_at_its_base_fee() {
+    let asserter = Asserter::new();
+    let (backtester, pool_asserters) = backtester(&asserter).await;
+    for gwei in [10, 10, 500] {
+        push_base_fee(&asserter, gwei);
+    }
+    let report = backtester.run(1, 3).await.unwrap();
+    assert!(asserter.read_q().is_empty());
+    assert!(
+        pool_asserters
+            .iter()
+            .all(|asserter| asserter.read_q().is_empty())
+    );
+
+    let opportunities: Vec<usize> = report
+        .blocks
+        .iter()
+        .map(|block| block.opportunities)
+        .collect();
+    assert_eq!(opportunities, [1, 0, 1]);
+    assert!(
+        report
+            .blocks
+            .iter()
+            .all(|block| block.failed_snapshots == 0)
+    );
+    // Same reserves, but gas cost 50 times as much in block 3.
+    assert!(report.blocks[2].net_profit_weth < report.blocks[0].net_profit_weth);
+
+    let weth = &report.tokens[&WETH];
+    assert_eq!(weth.opportunities, 2);
+    assert_eq!(
+        weth.net_profit_weth,
+        report.blocks[0].net_profit_weth + report.blocks[2].net_profit_weth
+    );
+    assert!(weth.average_input > U256::ZERO);
+    assert_eq!(report.top_paths.len(), 1);
+    let path = &report.top_paths[0];
+    assert_eq!(
+        (path.opportunities, path.first_block, path.last_block),
+        (2, 1, 3)
+    );
+    assert_eq!(path.net_profit_weth, weth.net_profit_weth);
+
+    assert_eq!(
+        BacktestReport::from_json_str(&report.to_json_string().unwrap()).unwrap(),
+        report
+    );
+}
+
+#[tokio::test]
+async fn test_backtest_report_is_deterministic() {
+    let asserter = Asserter::new();
+    let (backtester, _) = backtester(&asserter).await;
+    for gwei in [10, 20, 30] {
+        push_base_fee(&asserter, gwei);
+    }
+    let first = backtester.run(1, 3).await.unwrap();
+
+    // The pools' reserves at those blocks are cached, so only the gas prices are fetched.
+    for gwei in [10, 20, 30] {
+        push_base_fee(&asserter, gwei);
+    }
+    let second = backtester.run(1, 3).await.unwrap();
+    assert!(asserter.read_q().is_empty());
+    assert_eq!(
+        first.to_json_string().unwrap(),
+        second.to_json_string().unwrap()
+    );
+    assert!(backtester.run(3, 1).await.is_err());
+}
+
+#[tokio::test]
+async fn test_backtest_costs_recorded_gas_prices() {
+    let asserter = Asserter::new();
+    let (backtester, _) = backtester(&asserter).await;
+    let gwei = |amount: u64| U256::from(amount * 1_000_000_000);
+    let backtester = backtester.with_gas_estimator(Box::new(
+        FixedGas::new(gwei(10)).with_block_prices([(3, gwei(500))]),
+    ));
+    let report = backtester.run(1, 3).await.unwrap();
+    assert!(asserter.read_q().is_empty());
+
+    // Block 3 is as profitable as block 1 before gas, which it pays 50 times as much for.
+    assert!(report.blocks[2].net_profit_weth < report.blocks[0].net_profit_weth);
+}
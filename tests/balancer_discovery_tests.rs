@@ -0,0 +1,290 @@
+#![cfg(feature = "db")]
+
+mod common;
+
+use alloy::transports::mock::Asserter;
+use alloy_primitives::{Address, B256, Bytes, LogData, U256, address};
+use alloy_provider::{Provider, ProviderBuilder};
+use alloy_rpc_types::Log;
+use alloy_sol_types::{SolCall, sol};
+use arbrs::db::DbManager;
+use arbrs::dex::PoolKind;
+use arbrs::manager::balancer_pool_manager::BalancerPoolManager;
+use arbrs::manager::token_manager::TokenManager;
+use common::named_token;
+use std::sync::Arc;
+
+const FORK_RPC_URL: &str = "http://127.0.0.1:8545";
+const VAULT: Address = address!("BA12222222228d8Ba445958a75a0704d566BF2C8");
+const BAL_WETH_80_20: Address = address!("5c6Ee304399DBdB9C8Ef030aB642B10820DB8F56");
+const FACTORY: Address = Address::repeat_byte(0xfa);
+type DynProvider = dyn Provider + Send + Sync;
+
+sol! {
+    event PoolRegistered(bytes32 indexed poolId, address indexed poolAddress, uint8 specialization);
+
+    function getPoolId() external view returns (bytes32);
+    function getVault() external view returns (address);
+    function getSwapFeePercentage() external view returns (uint256);
+    function getNormalizedWeights() external view returns (uint256[]);
+    function getBptIndex() external view returns (uint256);
+    function getPoolTokens(bytes32 poolId) external view returns (address[] tokens, uint256[] balances, uint256 lastChangeBlock);
+    function isPoolFromFactory(address pool) external view returns (bool);
+}
+
+struct Fixture {
+    asserter: Asserter,
+    db_manager: Arc<DbManager>,
+    manager: BalancerPoolManager<DynProvider>,
+}
+
+async fn setup() -> Fixture {
+    let asserter = Asserter::new();
+    let provider: Arc<DynProvider> =
+        Arc::new(ProviderBuilder::new().connect_mocked_client(asserter.clone()));
+    let db_manager = Arc::new(DbManager::new("sqlite::memory:").await.unwrap());
+    let token_manager = Arc::new(TokenManager::new(provider.clone(), 1, db_manager.clone()));
+    for byte in [0x0a, 0x0b] {
+        token_manager.insert_token(named_token(
+            Address::repeat_byte(byte),
+            &format!("TKN{byte}"),
+            18,
+            provider.clone(),
+        ));
+    }
+    let manager = BalancerPoolManager::new(token_manager, provider, db_manager.clone(), 0);
+    Fixture {
+        asserter,
+        db_manager,
+        manager,
+    }
+}
+
+fn pool_id(pool: Address) -> B256 {
+    let mut id = [0u8; 32];
+    id[..20].copy_from_slice(pool.as_slice());
+    B256::from(id)
+}
+
+fn registered_log(pool: Address, block_number: u64) -> Log {
+    Log {
+        inner: alloy_primitives::Log {
+            address: VAULT,
+            data: LogData::from(&PoolRegistered {
+                poolId: pool_id(pool),
+                poolAddress: pool,
+                specialization: 2,
+            }),
+        },
+        block_number: Some(block_number),
+        ..Default::default()
+    }
+}
+
+/// Answers `BalancerPool::new`'s probes, in order: pool id, vault, fee, weights, amp, BPT
+/// index and rate providers, then the Vault's `getPoolTokens`. `None` probes revert.
+fn push_probes(
+    asserter: &Asserter,
+    pool: Address,
+    weights: Option<Vec<U256>>,
+    bpt_index: Option<u64>,
+    tokens: Vec<Address>,
+) {
+    asserter.push_success(&Bytes::from(getPoolIdCall::abi_encode_returns(&pool_id(
+        pool,
+    ))));
+    asserter.push_success(&Bytes::from(getVaultCall::abi_encode_returns(&VAULT)));
+    asserter.push_success(&Bytes::from(getSwapFeePercentageCall::abi_encode_returns(
+        &U256::from(10).pow(U256::from(16)),
+    )));
+    match weights {
+        Some(weights) => asserter.push_success(&Bytes::from(
+            getNormalizedWeightsCall::abi_encode_returns(&weights),
+        )),
+        None => asserter.push_failure_msg("execution reverted"),
+    }
+    asserter.push_failure_msg("execution reverted");
+    match bpt_index {
+        Some(index) => asserter.push_success(&Bytes::from(getBptIndexCall::abi_encode_returns(
+            &U256::from(index),
+        ))),
+        None => asserter.push_failure_msg("execution reverted"),
+    }
+    asserter.push_failure_msg("execution reverted");
+    asserter.push_success(&Bytes::from(getPoolTokensCall::abi_encode_returns(
+        &getPoolTokensReturn {
+            balances: vec![U256::from(10).pow(U256::from(21)); tokens.len()],
+            tokens,
+            lastChangeBlock: U256::ZERO,
+        },
+    )));
+}
+
+fn half() -> U256 {
+    U256::from(5) * U256::from(10).pow(U256::from(17))
+}
+
+#[tokio::test]
+async fn test_discovery_registers_weighted_pools_and_skips_unsupported_ones() {
+    let mut fixture = setup().await;
+    let (a, b) = (Address::repeat_byte(0x0a), Address::repeat_byte(0x0b));
+
+    // A weighted pool is registered and stored with its kind.
+    let weighted = Address::repeat_byte(0x01);
+    fixture
+        .asserter
+        .push_success(&vec![registered_log(weighted, 5)]);
+    push_probes(
+        &fixture.asserter,
+        weighted,
+        Some(vec![half(), half()]),
+        None,
+        vec![a, b],
+    );
+    let pools = fixture.manager.discover_pools_in_range(10).await.unwrap();
+    assert_eq!(pools.len(), 1);
+    assert_eq!(pools[0].address(), weighted);
+    let record = fixture
+        .db_manager
+        .load_pool(weighted)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(record.dex, PoolKind::BalancerWeighted);
+    assert_eq!(record.balancer_vault, Some(VAULT));
+
+    // A linear pool implements neither weighted nor stable math.
+    let linear = Address::repeat_byte(0x02);
+    fixture
+        .asserter
+        .push_success(&vec![registered_log(linear, 15)]);
+    push_probes(&fixture.asserter, linear, None, Some(1), vec![a, linear, b]);
+    assert!(
+        fixture
+            .manager
+            .discover_pools_in_range(20)
+            .await
+            .unwrap()
+            .is_empty()
+    );
+
+    // A managed pool reports weights but holds its own BPT.
+    let managed = Address::repeat_byte(0x03);
+    fixture
+        .asserter
+        .push_success(&vec![registered_log(managed, 25)]);
+    push_probes(
+        &fixture.asserter,
+        managed,
+        Some(vec![half(), half()]),
+        None,
+        vec![managed, a, b],
+    );
+    assert!(
+        fixture
+            .manager
+            .discover_pools_in_range(30)
+            .await
+            .unwrap()
+            .is_empty()
+    );
+    assert!(fixture.asserter.read_q().is_empty());
+
+    let skipped = fixture.manager.skipped_pools();
+    assert_eq!(
+        skipped
+            .iter()
+            .map(|(address, _)| *address)
+            .collect::<Vec<_>>(),
+        [linear, managed]
+    );
+    assert!(
+        skipped[0].1.contains("neither weighted nor stable"),
+        "{}",
+        skipped[0].1
+    );
+    assert!(skipped[1].1.contains("own BPT"), "{}", skipped[1].1);
+    assert_eq!(fixture.manager.get_all_pools().len(), 1);
+    assert!(
+        fixture
+            .db_manager
+            .load_pool(linear)
+            .await
+            .unwrap()
+            .is_none()
+    );
+}
+
+#[tokio::test]
+async fn test_discovery_can_be_restricted_to_factories() {
+    let fixture = setup().await;
+    let mut manager = fixture.manager.with_factories([FACTORY]);
+    let pool = Address::repeat_byte(0x01);
+
+    // The factory disowns the pool, so it isn't probed.
+    fixture
+        .asserter
+        .push_success(&vec![registered_log(pool, 5)]);
+    fixture
+        .asserter
+        .push_success(&Bytes::from(isPoolFromFactoryCall::abi_encode_returns(
+            &false,
+        )));
+    assert!(
+        manager
+            .discover_pools_in_range(10)
+            .await
+            .unwrap()
+            .is_empty()
+    );
+    assert!(fixture.asserter.read_q().is_empty());
+    assert_eq!(
+        manager.skipped_pools(),
+        [(pool, "not deployed by a configured factory".to_string())]
+    );
+
+    // A pool the factory deployed is built as usual.
+    let other = Address::repeat_byte(0x02);
+    fixture
+        .asserter
+        .push_success(&vec![registered_log(other, 15)]);
+    fixture
+        .asserter
+        .push_success(&Bytes::from(isPoolFromFactoryCall::abi_encode_returns(
+            &true,
+        )));
+    push_probes(
+        &fixture.asserter,
+        other,
+        Some(vec![half(), half()]),
+        None,
+        vec![Address::repeat_byte(0x0a), Address::repeat_byte(0x0b)],
+    );
+    let pools = manager.discover_pools_in_range(20).await.unwrap();
+    assert_eq!(pools.len(), 1);
+    assert_eq!(pools[0].address(), other);
+}
+
+#[tokio::test]
+async fn test_discovery_over_the_vaults_first_blocks_finds_bal_weth() {
+    let provider: Arc<DynProvider> =
+        Arc::new(ProviderBuilder::new().connect_http(FORK_RPC_URL.parse().unwrap()));
+    let db_manager = Arc::new(DbManager::new("sqlite::memory:").await.unwrap());
+    let token_manager = Arc::new(TokenManager::new(provider.clone(), 1, db_manager.clone()));
+
+    // The Vault was deployed at block 12_272_146 and the 80/20 BAL/WETH pool registered
+    // shortly after, alongside the first stable and investment pools.
+    let mut manager =
+        BalancerPoolManager::new(token_manager, provider, db_manager.clone(), 12_272_145);
+    let pools = manager.discover_pools_in_range(12_400_000).await.unwrap();
+
+    assert!(pools.iter().any(|pool| pool.address() == BAL_WETH_80_20));
+    let record = db_manager.load_pool(BAL_WETH_80_20).await.unwrap().unwrap();
+    assert_eq!(record.dex, PoolKind::BalancerWeighted);
+    for (address, reason) in manager.skipped_pools() {
+        assert!(
+            pools.iter().all(|pool| pool.address() != address),
+            "{address}: {reason}"
+        );
+    }
+}
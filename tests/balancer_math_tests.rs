@@ -1,3 +1,5 @@
+#![cfg(feature = "db")]
+
 #[cfg(test)]
 mod balancer_tests {
     mod integration_tests {
@@ -6,8 +8,8 @@ mod balancer_tests {
         use alloy_rpc_types::TransactionRequest;
         use alloy_sol_types::{SolCall, sol};
         use arbrs::{
-            TokenLike, balancer::pool::BalancerPool, db::DbManager,
-            manager::token_manager::TokenManager, pool::LiquidityPool,
+            TokenLike, balancer::pool::{BalancerPool, BalancerPoolKind}, db::DbManager,
+            manager::token_manager::TokenManager, pool::{LiquidityPool, PoolSnapshot},
         };
         use std::sync::Arc;
 
@@ -20,6 +22,10 @@ mod balancer_tests {
         // Balancer V2 80/20 BAL/WETH Pool
         const POOL_ADDRESS: Address = address!("5c6Ee304399DBdB9C8Ef030aB642B10820DB8F56");
         const BALANCER_QUERIES: Address = address!("E39B5e3B6D74016b2F6A9673D7d7493B6DF549d5");
+        const VAULT: Address = address!("BA12222222228d8Ba445958a75a0704d566BF2C8");
+        // wstETH/WETH meta-stable and composable stable pools
+        const WSTETH_WETH_META_STABLE: Address = address!("32296969Ef14EB0c6d29669C550D4a0449130230");
+        const WSTETH_WETH_COMPOSABLE: Address = address!("93d199263632a4EF4Bb438F1feB99e57b4b5f0BD");
 
         sol! {
             struct SingleSwap {
@@ -37,6 +43,30 @@ mod balancer_tests {
                     (address,bool,address,bool) memory funds
                 ) external view returns (uint256);
             }
+
+            struct BatchSwapStep {
+                bytes32 poolId;
+                uint256 assetInIndex;
+                uint256 assetOutIndex;
+                uint256 amount;
+                bytes userData;
+            }
+
+            struct FundManagement {
+                address sender;
+                bool fromInternalBalance;
+                address recipient;
+                bool toInternalBalance;
+            }
+
+            interface IVault {
+                function queryBatchSwap(
+                    uint8 kind,
+                    BatchSwapStep[] memory swaps,
+                    address[] memory assets,
+                    FundManagement memory funds
+                ) external returns (int256[] memory assetDeltas);
+            }
         }
 
         async fn setup() -> (Arc<DynProvider>, Arc<TokenManager<DynProvider>>, Arc<DbManager>) {
@@ -49,8 +79,8 @@ mod balancer_tests {
 
         #[tokio::test]
         async fn test_pool_initialization() {
-            let (provider, token_manager, db_manager) = setup().await;
-            let pool = BalancerPool::new(POOL_ADDRESS, provider, token_manager, db_manager).await.unwrap();
+            let (provider, token_manager, _) = setup().await;
+            let pool = BalancerPool::new(POOL_ADDRESS, provider, token_manager).await.unwrap();
             let bal_token = address!("ba100000625a3754423978a60c9317c58a424e3D");
             let weth_token = address!("C02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2");
             assert_eq!(pool.address(), POOL_ADDRESS);
@@ -61,8 +91,8 @@ mod balancer_tests {
 
         #[tokio::test]
         async fn test_swap_calculation_vs_onchain_quoter() {
-            let (provider, token_manager, db_manager) = setup().await;
-            let pool = BalancerPool::new(POOL_ADDRESS, provider.clone(), token_manager, db_manager).await.unwrap();
+            let (provider, token_manager, _) = setup().await;
+            let pool = BalancerPool::new(POOL_ADDRESS, provider.clone(), token_manager).await.unwrap();
             let snapshot = pool.get_snapshot(Some(TEST_BLOCK)).await.unwrap();
             let bal_token = &pool.get_all_tokens()[0];
             let weth_token = &pool.get_all_tokens()[1];
@@ -91,6 +121,165 @@ mod balancer_tests {
             }
         }
 
+        #[tokio::test]
+        async fn test_exact_output_vs_vault_batch_query() {
+            let (provider, token_manager, _) = setup().await;
+            let pool = BalancerPool::new(POOL_ADDRESS, provider.clone(), token_manager).await.unwrap();
+            let snapshot = pool.get_snapshot(Some(TEST_BLOCK)).await.unwrap();
+            let bal_token = &pool.get_all_tokens()[0];
+            let weth_token = &pool.get_all_tokens()[1];
+
+            let amounts_out = [
+                U256::from(10).pow(U256::from(12)),
+                U256::from(10).pow(U256::from(15)),
+                U256::from(10).pow(U256::from(17)),
+                U256::from(10).pow(U256::from(18)),
+            ];
+            for amount_out in amounts_out {
+                for (token_in, token_out) in [(bal_token, weth_token), (weth_token, bal_token)] {
+                    let local_amount_in = pool.calculate_tokens_in(token_in, token_out, amount_out, &snapshot).unwrap();
+
+                    let query = IVault::queryBatchSwapCall {
+                        kind: 1,
+                        swaps: vec![BatchSwapStep {
+                            poolId: pool.pool_id.into(),
+                            assetInIndex: U256::ZERO,
+                            assetOutIndex: U256::ONE,
+                            amount: amount_out,
+                            userData: Bytes::new(),
+                        }],
+                        assets: vec![token_in.address(), token_out.address()],
+                        funds: FundManagement {
+                            sender: Address::ZERO,
+                            fromInternalBalance: false,
+                            recipient: Address::ZERO,
+                            toInternalBalance: false,
+                        },
+                    };
+                    let request = TransactionRequest::default().to(VAULT).input(query.abi_encode().into());
+                    let result_bytes = provider.call(request).block(TEST_BLOCK.into()).await.unwrap();
+                    let deltas = IVault::queryBatchSwapCall::abi_decode_returns(&result_bytes).unwrap();
+                    let onchain_amount_in = deltas[0].into_raw();
+
+                    let diff = local_amount_in.max(onchain_amount_in) - local_amount_in.min(onchain_amount_in);
+                    assert!(
+                        diff <= U256::from(1_000_000_000),
+                        "Mismatch for amount out {}: got {}, expected {}",
+                        amount_out, local_amount_in, onchain_amount_in
+                    );
+                }
+            }
+
+            // The vault refuses outputs past 30% of the balance.
+            let arbrs::pool::PoolSnapshot::Balancer(balancer_snapshot) = &snapshot else {
+                panic!("expected a Balancer snapshot");
+            };
+            let too_much = balancer_snapshot.balances[1] / U256::from(2);
+            assert!(pool.calculate_tokens_in(bal_token, weth_token, too_much, &snapshot).is_err());
+        }
+
+        #[tokio::test]
+        async fn test_snapshot_at_historical_block() {
+            let (provider, token_manager, _) = setup().await;
+            let pool = BalancerPool::new(POOL_ADDRESS, provider, token_manager).await.unwrap();
+            let bal_token = &pool.get_all_tokens()[0];
+            let weth_token = &pool.get_all_tokens()[1];
+            let earlier_block = TEST_BLOCK - 100_000;
+
+            let snapshot = pool.get_snapshot(Some(TEST_BLOCK)).await.unwrap();
+            let earlier = pool.get_snapshot(Some(earlier_block)).await.unwrap();
+            let (PoolSnapshot::Balancer(current), PoolSnapshot::Balancer(historical)) = (&snapshot, &earlier) else {
+                panic!("expected Balancer snapshots");
+            };
+            assert_eq!(current.block_number, Some(TEST_BLOCK));
+            assert_eq!(historical.block_number, Some(earlier_block));
+            assert!(historical.swap_fee.is_some());
+            assert_ne!(current.balances, historical.balances);
+
+            // Each snapshot quotes against the balances of its own block.
+            let amount_in = U256::from(10).pow(U256::from(18));
+            let out_now = pool.calculate_tokens_out(bal_token, weth_token, amount_in, &snapshot).unwrap();
+            let out_then = pool.calculate_tokens_out(bal_token, weth_token, amount_in, &earlier).unwrap();
+            assert_ne!(out_now, out_then);
+        }
+
+        #[tokio::test]
+        async fn test_stable_pools_vs_vault_batch_query() {
+            let (provider, token_manager, _) = setup().await;
+            for pool_address in [WSTETH_WETH_META_STABLE, WSTETH_WETH_COMPOSABLE] {
+                let pool = BalancerPool::new(pool_address, provider.clone(), token_manager.clone()).await.unwrap();
+                let tokens = pool.get_all_tokens();
+                // The composable pool's BPT isn't tradable.
+                assert_eq!(tokens.len(), 2);
+                assert!(tokens.iter().all(|token| token.address() != pool_address));
+                assert_eq!(
+                    matches!(pool.kind(), BalancerPoolKind::ComposableStable { .. }),
+                    pool_address == WSTETH_WETH_COMPOSABLE
+                );
+
+                let snapshot = pool.get_snapshot(Some(TEST_BLOCK)).await.unwrap();
+                let amounts = [
+                    U256::from(10).pow(U256::from(15)),
+                    U256::from(10).pow(U256::from(18)),
+                    U256::from(100) * U256::from(10).pow(U256::from(18)),
+                ];
+                for amount in amounts {
+                    for (token_in, token_out) in [(&tokens[0], &tokens[1]), (&tokens[1], &tokens[0])] {
+                        let local_out = pool.calculate_tokens_out(token_in, token_out, amount, &snapshot).unwrap();
+                        let onchain_out = query_batch_swap(&provider, &pool, 0, token_in.address(), token_out.address(), amount).await;
+                        let onchain_out = (-onchain_out[1]).into_raw();
+                        let diff = local_out.max(onchain_out) - local_out.min(onchain_out);
+                        assert!(
+                            diff <= U256::from(1_000_000_000),
+                            "GIVEN_IN mismatch for {} on {}: got {}, expected {}",
+                            amount, pool_address, local_out, onchain_out
+                        );
+
+                        let local_in = pool.calculate_tokens_in(token_in, token_out, amount, &snapshot).unwrap();
+                        let onchain_in = query_batch_swap(&provider, &pool, 1, token_in.address(), token_out.address(), amount).await;
+                        let onchain_in = onchain_in[0].into_raw();
+                        let diff = local_in.max(onchain_in) - local_in.min(onchain_in);
+                        assert!(
+                            diff <= U256::from(1_000_000_000),
+                            "GIVEN_OUT mismatch for {} on {}: got {}, expected {}",
+                            amount, pool_address, local_in, onchain_in
+                        );
+                    }
+                }
+            }
+        }
+
+        /// The vault's asset deltas for a single `kind` swap of `amount`, at `TEST_BLOCK`.
+        async fn query_batch_swap<P: Provider + Send + Sync + 'static + ?Sized>(
+            provider: &Arc<P>,
+            pool: &BalancerPool<P>,
+            kind: u8,
+            token_in: Address,
+            token_out: Address,
+            amount: U256,
+        ) -> Vec<alloy_primitives::I256> {
+            let query = IVault::queryBatchSwapCall {
+                kind,
+                swaps: vec![BatchSwapStep {
+                    poolId: pool.pool_id.into(),
+                    assetInIndex: U256::ZERO,
+                    assetOutIndex: U256::ONE,
+                    amount,
+                    userData: Bytes::new(),
+                }],
+                assets: vec![token_in, token_out],
+                funds: FundManagement {
+                    sender: Address::ZERO,
+                    fromInternalBalance: false,
+                    recipient: Address::ZERO,
+                    toInternalBalance: false,
+                },
+            };
+            let request = TransactionRequest::default().to(VAULT).input(query.abi_encode().into());
+            let result_bytes = provider.call(request).block(TEST_BLOCK.into()).await.unwrap();
+            IVault::queryBatchSwapCall::abi_decode_returns(&result_bytes).unwrap()
+        }
+
         // Helper function to run a single swap test
         async fn test_single_swap<P: Provider + Send + Sync + 'static + ?Sized>(
             pool: &BalancerPool<P>,
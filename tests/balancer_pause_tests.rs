@@ -0,0 +1,310 @@
+#![cfg(feature = "db")]
+
+mod common;
+
+use alloy::transports::mock::Asserter;
+use alloy_primitives::{Address, Bytes, U256, address};
+use alloy_provider::{Provider, ProviderBuilder};
+use alloy_sol_types::{SolCall, sol};
+use arbrs::arbitrage::cache::ArbitrageCache;
+use arbrs::arbitrage::cycle::ArbitrageCycle;
+use arbrs::arbitrage::engine::ArbitrageEngine;
+use arbrs::arbitrage::types::{Arbitrage, ArbitragePath};
+use arbrs::balancer::pool::BalancerPool;
+use arbrs::core::token::Token;
+use arbrs::db::DbManager;
+use arbrs::manager::balancer_pool_manager::BalancerPoolManager;
+use arbrs::manager::token_manager::TokenManager;
+use arbrs::pool::strategy::StandardV2Logic;
+use arbrs::pool::uniswap_v2::{UniswapV2Pool, UniswapV2PoolState};
+use arbrs::pool::{LiquidityPool, PoolSnapshot};
+use arbrs::{ArbRsError, TokenLike};
+use common::named_token;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+const DB_URL: &str = "sqlite::memory:";
+const VAULT: Address = address!("BA12222222228d8Ba445958a75a0704d566BF2C8");
+const BALANCER_POOL: Address = address!("5c6Ee304399DBdB9C8Ef030aB642B10820DB8F56");
+const OTHER_BALANCER_POOL: Address = address!("32296969Ef14EB0c6d29669C550D4a0449130230");
+const SWAP_FEE: U256 = U256::from_limbs([3_000_000_000_000_000, 0, 0, 0]);
+const V2_POOL: Address = address!("B4e16d0168e52d35CaCD2c6185b44281Ec28C9Dc");
+type DynProvider = dyn Provider + Send + Sync;
+
+// Return encodings for the calls the mock answers.
+sol! {
+    function getPoolTokens(bytes32 poolId) external view returns (address[] tokens, uint256[] balances, uint256 lastChangeBlock);
+    function getPausedState() external view returns (bool paused, uint256 pauseWindowEndTime, uint256 bufferPeriodEndTime);
+    function inRecoveryMode() external view returns (bool);
+    function getSwapFeePercentage() external view returns (uint256);
+}
+
+struct Fixture {
+    asserter: Asserter,
+    provider: Arc<DynProvider>,
+    token_manager: Arc<TokenManager<DynProvider>>,
+    db_manager: Arc<DbManager>,
+    tokens: [Arc<Token<DynProvider>>; 2],
+}
+
+async fn setup() -> Fixture {
+    let asserter = Asserter::new();
+    let provider: Arc<DynProvider> =
+        Arc::new(ProviderBuilder::new().connect_mocked_client(asserter.clone()));
+    let db_manager = Arc::new(DbManager::new(DB_URL).await.unwrap());
+    let token_manager = Arc::new(TokenManager::new(provider.clone(), 1, db_manager.clone()));
+    let token = |byte: u8, symbol: &str| {
+        named_token(Address::repeat_byte(byte), symbol, 18, provider.clone())
+    };
+    let tokens = [token(0x0a, "AAA"), token(0x0b, "BBB")];
+
+    Fixture {
+        asserter,
+        provider,
+        token_manager,
+        db_manager,
+        tokens,
+    }
+}
+
+fn balancer_pool(fixture: &Fixture, address: Address) -> BalancerPool<DynProvider> {
+    let half = U256::from(500_000_000_000_000_000u64);
+    BalancerPool::from_parts(
+        address,
+        fixture.provider.clone(),
+        fixture.tokens.to_vec(),
+        vec![half, half],
+        SWAP_FEE,
+        VAULT,
+        [0x11; 32],
+    )
+}
+
+fn push_call_result(asserter: &Asserter, encoded: Vec<u8>) {
+    asserter.push_success(&Bytes::from(encoded));
+}
+
+fn push_vault_state(asserter: &Asserter, vault_paused: bool) {
+    push_call_result(
+        asserter,
+        getPausedStateCall::abi_encode_returns(&getPausedStateReturn {
+            paused: vault_paused,
+            pauseWindowEndTime: U256::ZERO,
+            bufferPeriodEndTime: U256::ZERO,
+        }),
+    );
+}
+
+/// Queues the per-pool responses of `get_snapshot`, in the order the calls are issued.
+fn push_pool_state(
+    asserter: &Asserter,
+    tokens: &[Arc<Token<DynProvider>>],
+    paused: bool,
+    recovery: bool,
+) {
+    push_call_result(
+        asserter,
+        getPoolTokensCall::abi_encode_returns(&getPoolTokensReturn {
+            tokens: tokens.iter().map(|t| t.address()).collect(),
+            balances: vec![U256::from(10).pow(U256::from(21)); tokens.len()],
+            lastChangeBlock: U256::ZERO,
+        }),
+    );
+    push_call_result(
+        asserter,
+        getPausedStateCall::abi_encode_returns(&getPausedStateReturn {
+            paused,
+            pauseWindowEndTime: U256::ZERO,
+            bufferPeriodEndTime: U256::ZERO,
+        }),
+    );
+    push_call_result(asserter, inRecoveryModeCall::abi_encode_returns(&recovery));
+    push_call_result(
+        asserter,
+        getSwapFeePercentageCall::abi_encode_returns(&SWAP_FEE),
+    );
+}
+
+async fn is_paused(pool: &dyn LiquidityPool<DynProvider>, block: u64) -> bool {
+    match pool.get_snapshot(Some(block)).await.unwrap() {
+        PoolSnapshot::Balancer(snapshot) => snapshot.is_paused,
+        other => panic!("Unexpected snapshot {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn test_paused_pool_rejects_quotes() {
+    let fixture = setup().await;
+    let pool = balancer_pool(&fixture, BALANCER_POOL);
+    let [a, b] = &fixture.tokens;
+
+    push_vault_state(&fixture.asserter, false);
+    push_pool_state(&fixture.asserter, &fixture.tokens, true, false);
+    let snapshot = pool.get_snapshot(Some(100)).await.unwrap();
+    assert!(fixture.asserter.read_q().is_empty());
+
+    let amount = U256::from(10).pow(U256::from(18));
+    assert_eq!(
+        pool.calculate_tokens_out(a, b, amount, &snapshot),
+        Err(ArbRsError::PoolPaused(BALANCER_POOL))
+    );
+    assert_eq!(
+        pool.calculate_tokens_in(a, b, amount, &snapshot),
+        Err(ArbRsError::PoolPaused(BALANCER_POOL))
+    );
+
+    // Recovery mode is treated like a pause.
+    push_vault_state(&fixture.asserter, false);
+    push_pool_state(&fixture.asserter, &fixture.tokens, false, true);
+    assert!(is_paused(&pool, 101).await);
+
+    push_vault_state(&fixture.asserter, false);
+    push_pool_state(&fixture.asserter, &fixture.tokens, false, false);
+    let snapshot = pool.get_snapshot(Some(102)).await.unwrap();
+    assert!(pool.calculate_tokens_out(a, b, amount, &snapshot).unwrap() > U256::ZERO);
+}
+
+#[tokio::test]
+async fn test_snapshot_is_taken_at_the_requested_block() {
+    let fixture = setup().await;
+    let pool = balancer_pool(&fixture, BALANCER_POOL);
+
+    push_vault_state(&fixture.asserter, false);
+    push_pool_state(&fixture.asserter, &fixture.tokens, false, false);
+    match pool.get_snapshot(Some(150)).await.unwrap() {
+        PoolSnapshot::Balancer(snapshot) => {
+            assert_eq!(snapshot.block_number, Some(150));
+            assert_eq!(snapshot.swap_fee, Some(SWAP_FEE));
+        }
+        other => panic!("Unexpected snapshot {:?}", other),
+    }
+
+    // Before the pool is registered the vault reverts with `BAL#500`, which is an error
+    // rather than an empty snapshot.
+    push_vault_state(&fixture.asserter, false);
+    fixture
+        .asserter
+        .push_failure_msg("execution reverted: BAL#500");
+    push_call_result(&fixture.asserter, Vec::new());
+    push_call_result(&fixture.asserter, Vec::new());
+    push_call_result(&fixture.asserter, Vec::new());
+    assert!(matches!(
+        pool.get_snapshot(Some(149)).await,
+        Err(ArbRsError::NoPoolStateAvailable(149))
+    ));
+}
+
+#[tokio::test]
+async fn test_vault_pause_is_checked_once_per_block() {
+    let fixture = setup().await;
+    let manager = BalancerPoolManager::new(
+        fixture.token_manager.clone(),
+        fixture.provider.clone(),
+        fixture.db_manager.clone(),
+        0,
+    );
+    let first = manager.add_pool(balancer_pool(&fixture, BALANCER_POOL));
+    let second = manager.add_pool(balancer_pool(&fixture, OTHER_BALANCER_POOL));
+
+    // One vault response serves both pools at the same block.
+    push_vault_state(&fixture.asserter, true);
+    push_pool_state(&fixture.asserter, &fixture.tokens, false, false);
+    push_pool_state(&fixture.asserter, &fixture.tokens, false, false);
+    assert!(is_paused(first.as_ref(), 200).await);
+    assert!(is_paused(second.as_ref(), 200).await);
+    assert!(fixture.asserter.read_q().is_empty());
+
+    // A new block fetches it again.
+    push_vault_state(&fixture.asserter, false);
+    push_pool_state(&fixture.asserter, &fixture.tokens, false, false);
+    assert!(!is_paused(second.as_ref(), 201).await);
+}
+
+#[tokio::test]
+async fn test_engine_skips_paths_through_paused_pools() {
+    let fixture = setup().await;
+    let [a, b] = &fixture.tokens;
+    let balancer: Arc<dyn LiquidityPool<DynProvider>> =
+        Arc::new(balancer_pool(&fixture, BALANCER_POOL));
+    let v2: Arc<dyn LiquidityPool<DynProvider>> = Arc::new(UniswapV2Pool::new(
+        V2_POOL,
+        a.clone(),
+        b.clone(),
+        fixture.provider.clone(),
+        StandardV2Logic,
+    ));
+
+    push_vault_state(&fixture.asserter, false);
+    push_pool_state(&fixture.asserter, &fixture.tokens, true, false);
+    let paused_snapshot = balancer.get_snapshot(Some(300)).await.unwrap();
+    let v2_snapshot = PoolSnapshot::UniswapV2(UniswapV2PoolState {
+        reserve0: U256::from(10).pow(U256::from(21)),
+        reserve1: U256::from(12).pow(U256::from(21)),
+        block_number: 300,
+    });
+
+    let cache = Arc::new(ArbitrageCache::new());
+    let path: Arc<dyn Arbitrage<DynProvider>> = Arc::new(ArbitrageCycle::new(ArbitragePath {
+        pools: vec![balancer, v2],
+        path: vec![a.clone(), b.clone(), a.clone()],
+        profit_token: a.clone(),
+    }));
+    cache.add_path(path).await;
+    let engine = ArbitrageEngine::new(
+        cache,
+        fixture.token_manager.clone(),
+        fixture.provider.clone(),
+    );
+
+    // The mock has no responses left, so every snapshot comes from the overrides.
+    let overrides = HashMap::from([(BALANCER_POOL, paused_snapshot), (V2_POOL, v2_snapshot)]);
+    let solutions = engine
+        .find_opportunities_with_overrides(Some(300), overrides)
+        .await;
+    assert!(solutions.is_empty());
+    let stats = engine.last_stats();
+    assert_eq!(stats.paths, 1);
+    assert_eq!(stats.paused_pool_skips, 1);
+}
+
+#[tokio::test]
+async fn test_manager_deactivates_long_paused_pools() {
+    let fixture = setup().await;
+    let manager = BalancerPoolManager::new(
+        fixture.token_manager.clone(),
+        fixture.provider.clone(),
+        fixture.db_manager.clone(),
+        0,
+    )
+    .with_pause_deactivation_blocks(100);
+    manager.add_pool(balancer_pool(&fixture, BALANCER_POOL));
+
+    let sweep = |block: u64, paused: bool| {
+        push_vault_state(&fixture.asserter, false);
+        push_pool_state(&fixture.asserter, &fixture.tokens, paused, false);
+        manager.sweep_paused_pools(block)
+    };
+
+    // Paused, but not yet for long enough.
+    assert!(!sweep(1_000, true).await);
+    assert!(!sweep(1_100, true).await);
+    assert!(manager.is_active(BALANCER_POOL));
+    assert_eq!(manager.get_all_pools().len(), 1);
+
+    assert!(sweep(1_101, true).await);
+    assert!(!manager.is_active(BALANCER_POOL));
+    assert!(manager.get_all_pools().is_empty());
+
+    // Still paused: nothing changes.
+    assert!(!sweep(1_150, true).await);
+
+    assert!(sweep(1_160, false).await);
+    assert!(manager.is_active(BALANCER_POOL));
+    assert_eq!(manager.get_all_pools().len(), 1);
+
+    // A fresh pause starts counting from scratch.
+    assert!(!sweep(1_200, true).await);
+    assert!(!sweep(1_250, true).await);
+    assert!(manager.is_active(BALANCER_POOL));
+    assert!(fixture.asserter.read_q().is_empty());
+}
@@ -0,0 +1,267 @@
+mod common;
+
+use alloy::transports::mock::Asserter;
+use alloy_primitives::{Address, B256, Bytes, U256};
+use alloy_provider::{Provider, ProviderBuilder};
+use alloy_sol_types::{SolCall, sol};
+use arbrs::balancer::pool::{BalancerPool, BalancerPoolKind, BalancerPoolSnapshot};
+use arbrs::balancer::stable_math;
+use arbrs::core::token::Token;
+use arbrs::dex::PoolKind;
+use arbrs::manager::token_manager::TokenManager;
+use arbrs::pool::{CalibrationBucket, LiquidityPool, PoolSnapshot};
+use arbrs::{ArbRsError, TokenLike};
+use balancer_maths_rust::pools::stable::stable_math as reference;
+use num_bigint::BigInt;
+use common::named_token;
+use std::sync::Arc;
+
+type DynProvider = dyn Provider + Send + Sync;
+
+const POOL: Address = Address::repeat_byte(0x01);
+const VAULT: Address = Address::repeat_byte(0x02);
+const SWAP_FEE: U256 = U256::from_limbs([100_000_000_000_000, 0, 0, 0]);
+// A = 200, with the vault's precision of 1000.
+const AMPLIFICATION: U256 = U256::from_limbs([200_000, 0, 0, 0]);
+const ONE: U256 = U256::from_limbs([1_000_000_000_000_000_000, 0, 0, 0]);
+// Wei the V2 stable math may differ from exact rounding by.
+const TOLERANCE: u64 = 10_000;
+
+// Return encodings for the calls the mock answers.
+sol! {
+    function getPoolId() external view returns (bytes32);
+    function getVault() external view returns (address);
+    function getSwapFeePercentage() external view returns (uint256);
+    function getAmplificationParameter() external view returns (uint256 value, bool isUpdating, uint256 precision);
+    function getBptIndex() external view returns (uint256);
+    function getRateProviders() external view returns (address[]);
+    function getScalingFactors() external view returns (uint256[]);
+    function getPoolTokens(bytes32 poolId) external view returns (address[] tokens, uint256[] balances, uint256 lastChangeBlock);
+    function getPausedState() external view returns (bool paused, uint256 pauseWindowEndTime, uint256 bufferPeriodEndTime);
+    function inRecoveryMode() external view returns (bool);
+}
+
+fn ether(amount: u64) -> U256 {
+    U256::from(amount) * ONE
+}
+
+fn push(asserter: &Asserter, encoded: Vec<u8>) {
+    asserter.push_success(&Bytes::from(encoded));
+}
+
+fn to_bigint(value: U256) -> BigInt {
+    BigInt::from_bytes_be(num_bigint::Sign::Plus, &value.to_be_bytes::<32>())
+}
+
+#[test]
+fn test_stable_math_matches_the_reference_implementation() {
+    let cases: [&[U256]; 3] = [
+        &[ether(1_000_000), ether(1_000_000)],
+        &[ether(2_500_000), ether(700_000)],
+        &[ether(30_000_000), ether(25_000_000), ether(41_000_000)],
+    ];
+    for balances in cases {
+        let invariant = stable_math::calculate_invariant(AMPLIFICATION, balances).unwrap();
+        let bigint_balances: Vec<BigInt> = balances.iter().map(|balance| to_bigint(*balance)).collect();
+        let expected = reference::compute_invariant(&to_bigint(AMPLIFICATION), &bigint_balances).unwrap();
+        assert_eq!(to_bigint(invariant), expected);
+
+        for amount in [ether(1), ether(10_000), ether(500_000)] {
+            let out = stable_math::calc_out_given_in(AMPLIFICATION, balances, 0, 1, amount, invariant).unwrap();
+            let expected = reference::compute_out_given_exact_in(
+                &to_bigint(AMPLIFICATION),
+                &bigint_balances,
+                0,
+                1,
+                &to_bigint(amount),
+                &to_bigint(invariant),
+            )
+            .unwrap();
+            // The V2 math rounds `c` up before scaling it by the amp precision where V3 scales
+            // first, which moves the solved balance by up to a few thousand wei.
+            let diff = to_bigint(out) - &expected;
+            assert!(diff.magnitude() <= &TOLERANCE.into(), "out of {amount}: {out} vs {expected}");
+
+            let back = stable_math::calc_in_given_out(AMPLIFICATION, balances, 0, 1, out, invariant).unwrap();
+            assert!(back.abs_diff(amount) <= U256::from(TOLERANCE), "in for {out}: {back}");
+        }
+    }
+
+    // A balanced pool's invariant is the sum of its balances.
+    let invariant = stable_math::calculate_invariant(AMPLIFICATION, &[ether(5), ether(5)]).unwrap();
+    assert!(invariant.abs_diff(ether(10)) <= U256::ONE);
+    assert_eq!(stable_math::calculate_invariant(AMPLIFICATION, &[U256::ZERO, U256::ZERO]), Ok(U256::ZERO));
+}
+
+/// A wstETH/WETH style pool: wstETH is worth 1.15 WETH through its rate provider, and the
+/// pool holds balances of equal value.
+fn rated_pool() -> (BalancerPool<DynProvider>, PoolSnapshot, [Arc<Token<DynProvider>>; 2]) {
+    let provider: Arc<DynProvider> = Arc::new(ProviderBuilder::new().connect_mocked_client(Asserter::new()));
+    let tokens = [
+        named_token(Address::repeat_byte(0x0a), "wstETH", 18, provider.clone()),
+        named_token(Address::repeat_byte(0x0b), "WETH", 18, provider.clone()),
+    ];
+    let pool = BalancerPool::from_parts(POOL, provider, tokens.to_vec(), Vec::new(), SWAP_FEE, VAULT, [0x11; 32])
+        .with_kind(BalancerPoolKind::Stable {
+            rate_providers: vec![Address::repeat_byte(0x0c), Address::ZERO],
+        });
+    let rate = U256::from(1_150_000_000_000_000_000u64);
+    let snapshot = PoolSnapshot::Balancer(BalancerPoolSnapshot {
+        balances: vec![ether(10_000), ether(11_500)],
+        swap_fee: Some(SWAP_FEE),
+        amplification: Some(AMPLIFICATION),
+        scaling_factors: Some(vec![rate, ONE]),
+        ..Default::default()
+    });
+    (pool, snapshot, tokens)
+}
+
+#[test]
+fn test_stable_pool_quotes_with_rates() {
+    let (pool, snapshot, [wsteth, weth]) = rated_pool();
+    assert_eq!(pool.pool_kind(), PoolKind::BalancerStable);
+    assert_eq!(pool.calibration_bucket(), Some(CalibrationBucket::new("balancer", "stable")));
+    assert!(pool.weights().is_empty());
+
+    // Close to the rate, less the 0.01% fee and a little slippage.
+    let out = pool.calculate_tokens_out(&wsteth, &weth, ether(1), &snapshot).unwrap();
+    assert!(out < U256::from(1_150_000_000_000_000_000u64), "{out}");
+    assert!(out > U256::from(1_149_000_000_000_000_000u64), "{out}");
+
+    let amount_in = pool.calculate_tokens_in(&wsteth, &weth, out, &snapshot).unwrap();
+    assert!(amount_in.abs_diff(ether(1)) <= U256::from(TOLERANCE), "{amount_in}");
+
+    let PoolSnapshot::Balancer(balancer_snapshot) = &snapshot else {
+        unreachable!();
+    };
+    let price = pool.spot_price(balancer_snapshot, 0, 1).unwrap();
+    assert!((price - 1.15).abs() < 1e-9, "{price}");
+
+    // Without the stable state the pool can't be quoted.
+    let bare = PoolSnapshot::Balancer(BalancerPoolSnapshot {
+        balances: balancer_snapshot.balances.clone(),
+        ..Default::default()
+    });
+    assert!(matches!(
+        pool.calculate_tokens_out(&wsteth, &weth, ether(1), &bare),
+        Err(ArbRsError::CalculationError(_))
+    ));
+}
+
+#[tokio::test]
+async fn test_composable_stable_pools_leave_out_their_bpt() {
+    let asserter = Asserter::new();
+    let provider: Arc<DynProvider> = Arc::new(ProviderBuilder::new().connect_mocked_client(asserter.clone()));
+    let token_manager = Arc::new(TokenManager::in_memory(provider.clone(), 1));
+    let tokens = [
+        named_token(Address::repeat_byte(0x0a), "USDC", 6, provider.clone()),
+        named_token(Address::repeat_byte(0x0b), "DAI", 18, provider.clone()),
+    ];
+    for token in &tokens {
+        token_manager.insert_token(token.clone());
+    }
+    let registered = vec![POOL, tokens[0].address(), tokens[1].address()];
+
+    push(&asserter, getPoolIdCall::abi_encode_returns(&B256::repeat_byte(0x11)));
+    push(&asserter, getVaultCall::abi_encode_returns(&VAULT));
+    push(&asserter, getSwapFeePercentageCall::abi_encode_returns(&SWAP_FEE));
+    asserter.push_failure_msg("execution reverted");
+    push(
+        &asserter,
+        getAmplificationParameterCall::abi_encode_returns(&getAmplificationParameterReturn {
+            value: AMPLIFICATION,
+            isUpdating: false,
+            precision: U256::from(1_000),
+        }),
+    );
+    push(&asserter, getBptIndexCall::abi_encode_returns(&U256::ZERO));
+    push(&asserter, getRateProvidersCall::abi_encode_returns(&vec![Address::ZERO; 3]));
+    push(
+        &asserter,
+        getPoolTokensCall::abi_encode_returns(&getPoolTokensReturn {
+            tokens: registered.clone(),
+            balances: vec![U256::MAX >> 1, U256::from(10).pow(U256::from(12)), ether(1_000_000)],
+            lastChangeBlock: U256::ZERO,
+        }),
+    );
+
+    let pool = BalancerPool::new(POOL, provider, token_manager).await.unwrap();
+    assert_eq!(
+        *pool.kind(),
+        BalancerPoolKind::ComposableStable {
+            bpt_index: 0,
+            rate_providers: vec![Address::ZERO; 2],
+        }
+    );
+    let pool_tokens = pool.get_all_tokens();
+    let symbols: Vec<&str> = pool_tokens.iter().map(|token| token.symbol()).collect();
+    assert_eq!(symbols, ["USDC", "DAI"]);
+    assert!(asserter.read_q().is_empty());
+
+    // Vault pause, then the pool's state, then its stable parameters.
+    let paused = getPausedStateReturn {
+        paused: false,
+        pauseWindowEndTime: U256::ZERO,
+        bufferPeriodEndTime: U256::ZERO,
+    };
+    push(&asserter, getPausedStateCall::abi_encode_returns(&paused));
+    push(
+        &asserter,
+        getPoolTokensCall::abi_encode_returns(&getPoolTokensReturn {
+            tokens: registered,
+            balances: vec![U256::MAX >> 1, U256::from(10).pow(U256::from(12)), ether(1_000_000)],
+            lastChangeBlock: U256::ZERO,
+        }),
+    );
+    push(&asserter, getPausedStateCall::abi_encode_returns(&paused));
+    push(&asserter, inRecoveryModeCall::abi_encode_returns(&false));
+    push(&asserter, getSwapFeePercentageCall::abi_encode_returns(&SWAP_FEE));
+    push(
+        &asserter,
+        getAmplificationParameterCall::abi_encode_returns(&getAmplificationParameterReturn {
+            value: AMPLIFICATION,
+            isUpdating: false,
+            precision: U256::from(1_000),
+        }),
+    );
+    let usdc_scaling = ONE * U256::from(10).pow(U256::from(12));
+    push(&asserter, getScalingFactorsCall::abi_encode_returns(&vec![ONE, usdc_scaling, ONE]));
+
+    let snapshot = pool.get_snapshot(Some(19_000_000)).await.unwrap();
+    assert!(asserter.read_q().is_empty());
+    let PoolSnapshot::Balancer(balancer_snapshot) = &snapshot else {
+        panic!("expected a Balancer snapshot");
+    };
+    assert_eq!(balancer_snapshot.balances, [U256::from(10).pow(U256::from(12)), ether(1_000_000)]);
+    assert_eq!(balancer_snapshot.scaling_factors.as_deref(), Some(&[usdc_scaling, ONE][..]));
+    assert_eq!(balancer_snapshot.amplification, Some(AMPLIFICATION));
+
+    let [usdc, dai] = &tokens;
+    let out = pool.calculate_tokens_out(usdc, dai, U256::from(1_000_000_000), &snapshot).unwrap();
+    assert!(out > ether(999) && out < ether(1_000), "{out}");
+}
+
+#[tokio::test]
+async fn test_pools_without_known_math_are_invalid() {
+    let asserter = Asserter::new();
+    let provider: Arc<DynProvider> = Arc::new(ProviderBuilder::new().connect_mocked_client(asserter.clone()));
+    let token_manager = Arc::new(TokenManager::in_memory(provider.clone(), 1));
+
+    push(&asserter, getPoolIdCall::abi_encode_returns(&B256::repeat_byte(0x11)));
+    push(&asserter, getVaultCall::abi_encode_returns(&VAULT));
+    push(&asserter, getSwapFeePercentageCall::abi_encode_returns(&SWAP_FEE));
+    for _ in 0..4 {
+        asserter.push_failure_msg("execution reverted");
+    }
+    push(
+        &asserter,
+        getPoolTokensCall::abi_encode_returns(&getPoolTokensReturn {
+            tokens: vec![Address::repeat_byte(0x0a), Address::repeat_byte(0x0b)],
+            balances: vec![ether(1); 2],
+            lastChangeBlock: U256::ZERO,
+        }),
+    );
+
+    let result = BalancerPool::new(POOL, provider, token_manager).await;
+    assert!(matches!(result, Err(ArbRsError::InvalidPool(POOL, _))));
+}
@@ -0,0 +1,397 @@
+mod common;
+
+use alloy_primitives::{Address, U256};
+use alloy_provider::{Provider, ProviderBuilder};
+use arbrs::ArbRsError;
+use arbrs::balancer::pool::{BalancerPool, BalancerPoolSnapshot};
+use arbrs::pool::{LiquidityPool, PoolSnapshot};
+use common::named_token;
+use std::sync::Arc;
+
+// The provider is lazy and never called.
+const FORK_RPC_URL: &str = "http://127.0.0.1:8545";
+type DynProvider = dyn Provider + Send + Sync;
+
+/// One swap against a two token weighted pool. Balances are raw token amounts with their
+/// decimals; weights and the fee are 18 decimal fixed point. `expected` is `None` when the
+/// vault would revert on the max in/out ratio.
+struct Case {
+    label: &'static str,
+    balance_in: (u128, u8),
+    weight_in: u128,
+    balance_out: (u128, u8),
+    weight_out: u128,
+    amount: u128,
+    fee: u128,
+    expected: Option<u128>,
+}
+
+// Expected amounts are exact integer evaluations of the Balancer V2 contracts: the
+// `WeightedMath` formulas over `FixedPoint` and `LogExpMath`, with the fee and scaling
+// handling of `BaseMinimalSwapInfoPool`.
+const GIVEN_IN: &[Case] = &[
+    Case {
+        label: "50/50 0.3% fee",
+        balance_in: (1_000_000_000_000_000_000_000, 18),
+        weight_in: 500_000_000_000_000_000,
+        balance_out: (1_000_000_000_000_000_000_000, 18),
+        weight_out: 500_000_000_000_000_000,
+        amount: 1_000_000_000_000_000_000,
+        fee: 3_000_000_000_000_000,
+        expected: Some(996_006_981_039_903_000),
+    },
+    Case {
+        label: "80/20 1% fee",
+        balance_in: (1_000_000_000_000_000_000_000_000, 18),
+        weight_in: 800_000_000_000_000_000,
+        balance_out: (10_000_000_000_000_000_000_000, 18),
+        weight_out: 200_000_000_000_000_000,
+        amount: 1_000_000_000_000_000_000,
+        fee: 10_000_000_000_000_000,
+        expected: Some(39_599_901_990_130_000),
+    },
+    Case {
+        label: "20/80 1% fee",
+        balance_in: (10_000_000_000_000_000_000_000, 18),
+        weight_in: 200_000_000_000_000_000,
+        balance_out: (1_000_000_000_000_000_000_000_000, 18),
+        weight_out: 800_000_000_000_000_000,
+        amount: 1_000_000_000_000_000_000,
+        fee: 10_000_000_000_000_000,
+        expected: Some(24_748_468_697_447_000_000),
+    },
+    Case {
+        label: "one wei in",
+        balance_in: (1_000_000_000_000_000_000_000, 18),
+        weight_in: 800_000_000_000_000_000,
+        balance_out: (1_000_000_000_000_000_000_000, 18),
+        weight_out: 200_000_000_000_000_000,
+        amount: 1,
+        fee: 10_000_000_000_000_000,
+        expected: Some(0),
+    },
+    Case {
+        label: "dust in",
+        balance_in: (1_000_000_000_000_000_000_000, 18),
+        weight_in: 500_000_000_000_000_000,
+        balance_out: (1_000_000_000_000_000_000_000, 18),
+        weight_out: 500_000_000_000_000_000,
+        amount: 1_000,
+        fee: 0,
+        expected: Some(0),
+    },
+    Case {
+        label: "zero fee",
+        balance_in: (5_000_000_000_000_000_000_000, 18),
+        weight_in: 600_000_000_000_000_000,
+        balance_out: (3_000_000_000_000_000_000_000, 18),
+        weight_out: 400_000_000_000_000_000,
+        amount: 25_000_000_000_000_000_000,
+        fee: 0,
+        expected: Some(22_360_190_723_454_087_000),
+    },
+    Case {
+        label: "max fee",
+        balance_in: (5_000_000_000_000_000_000_000, 18),
+        weight_in: 600_000_000_000_000_000,
+        balance_out: (3_000_000_000_000_000_000_000, 18),
+        weight_out: 400_000_000_000_000_000,
+        amount: 25_000_000_000_000_000_000,
+        fee: 100_000_000_000_000_000,
+        expected: Some(20_136_688_745_281_140_000),
+    },
+    Case {
+        label: "98/2",
+        balance_in: (1_000_000_000_000_000_000_000_000, 18),
+        weight_in: 980_000_000_000_000_000,
+        balance_out: (1_000_000_000_000_000_000_000, 18),
+        weight_out: 20_000_000_000_000_000,
+        amount: 1_000_000_000_000_000_000_000,
+        fee: 3_000_000_000_000_000,
+        expected: Some(47_655_712_422_542_057_000),
+    },
+    Case {
+        label: "2/98",
+        balance_in: (1_000_000_000_000_000_000_000, 18),
+        weight_in: 20_000_000_000_000_000,
+        balance_out: (1_000_000_000_000_000_000_000_000, 18),
+        weight_out: 980_000_000_000_000_000,
+        amount: 1_000_000_000_000_000_000,
+        fee: 3_000_000_000_000_000,
+        expected: Some(20_336_595_761_805_000_000),
+    },
+    Case {
+        label: "at max in ratio",
+        balance_in: (1_000_000_000_000_000_000_000, 18),
+        weight_in: 500_000_000_000_000_000,
+        balance_out: (1_000_000_000_000_000_000_000, 18),
+        weight_out: 500_000_000_000_000_000,
+        amount: 300_000_000_000_000_000_000,
+        fee: 0,
+        expected: Some(230_769_230_769_230_769_000),
+    },
+    Case {
+        label: "past max in ratio before fee",
+        balance_in: (1_000_000_000_000_000_000_000, 18),
+        weight_in: 500_000_000_000_000_000,
+        balance_out: (1_000_000_000_000_000_000_000, 18),
+        weight_out: 500_000_000_000_000_000,
+        amount: 301_000_000_000_000_000_000,
+        fee: 10_000_000_000_000_000,
+        expected: Some(229_578_039_892_449_094_000),
+    },
+    Case {
+        label: "past max in ratio",
+        balance_in: (1_000_000_000_000_000_000_000, 18),
+        weight_in: 500_000_000_000_000_000,
+        balance_out: (1_000_000_000_000_000_000_000, 18),
+        weight_out: 500_000_000_000_000_000,
+        amount: 300_000_000_000_000_000_001,
+        fee: 0,
+        expected: None,
+    },
+    Case {
+        label: "6 decimals in",
+        balance_in: (2_000_000_000_000, 6),
+        weight_in: 500_000_000_000_000_000,
+        balance_out: (1_000_000_000_000_000_000_000, 18),
+        weight_out: 500_000_000_000_000_000,
+        amount: 1_234_567_891,
+        fee: 500_000_000_000_000,
+        expected: Some(616_594_879_686_968_000),
+    },
+    Case {
+        label: "6 decimals out",
+        balance_in: (1_000_000_000_000_000_000_000, 18),
+        weight_in: 800_000_000_000_000_000,
+        balance_out: (2_000_000_000_000, 6),
+        weight_out: 200_000_000_000_000_000,
+        amount: 1_234_500_000_000_000_000,
+        fee: 500_000_000_000_000,
+        expected: Some(9_840_687_647),
+    },
+];
+
+const GIVEN_OUT: &[Case] = &[
+    Case {
+        label: "50/50 0.3% fee",
+        balance_in: (1_000_000_000_000_000_000_000, 18),
+        weight_in: 500_000_000_000_000_000,
+        balance_out: (1_000_000_000_000_000_000_000, 18),
+        weight_out: 500_000_000_000_000_000,
+        amount: 1_000_000_000_000_000_000,
+        fee: 3_000_000_000_000_000,
+        expected: Some(1_004_013_040_121_366_099),
+    },
+    Case {
+        label: "80/20 1% fee",
+        balance_in: (1_000_000_000_000_000_000_000_000, 18),
+        weight_in: 800_000_000_000_000_000,
+        balance_out: (10_000_000_000_000_000_000_000, 18),
+        weight_out: 200_000_000_000_000_000,
+        amount: 1_000_000_000_000_000_000,
+        fee: 10_000_000_000_000_000,
+        expected: Some(25_254_103_663_836_363_637),
+    },
+    Case {
+        label: "20/80 1% fee",
+        balance_in: (10_000_000_000_000_000_000_000, 18),
+        weight_in: 200_000_000_000_000_000,
+        balance_out: (1_000_000_000_000_000_000_000_000, 18),
+        weight_out: 800_000_000_000_000_000,
+        amount: 1_000_000_000_000_000_000,
+        fee: 10_000_000_000_000_000,
+        expected: Some(40_404_141_414_414_142),
+    },
+    Case {
+        label: "one wei out",
+        balance_in: (1_000_000_000_000_000_000_000, 18),
+        weight_in: 800_000_000_000_000_000,
+        balance_out: (1_000_000_000_000_000_000_000, 18),
+        weight_out: 200_000_000_000_000_000,
+        amount: 1,
+        fee: 10_000_000_000_000_000,
+        expected: Some(10_102_021),
+    },
+    Case {
+        label: "zero fee",
+        balance_in: (5_000_000_000_000_000_000_000, 18),
+        weight_in: 600_000_000_000_000_000,
+        balance_out: (3_000_000_000_000_000_000_000, 18),
+        weight_out: 400_000_000_000_000_000,
+        amount: 25_000_000_000_000_000_000,
+        fee: 0,
+        expected: Some(27_972_118_911_192_565_000),
+    },
+    Case {
+        label: "max fee",
+        balance_in: (5_000_000_000_000_000_000_000, 18),
+        weight_in: 600_000_000_000_000_000,
+        balance_out: (3_000_000_000_000_000_000_000, 18),
+        weight_out: 400_000_000_000_000_000,
+        amount: 25_000_000_000_000_000_000,
+        fee: 100_000_000_000_000_000,
+        expected: Some(31_080_132_123_547_294_445),
+    },
+    Case {
+        label: "98/2",
+        balance_in: (1_000_000_000_000_000_000_000_000, 18),
+        weight_in: 980_000_000_000_000_000,
+        balance_out: (1_000_000_000_000_000_000_000, 18),
+        weight_out: 20_000_000_000_000_000,
+        amount: 1_000_000_000_000_000_000,
+        fee: 3_000_000_000_000_000,
+        expected: Some(20_480_022_689_255_767_302),
+    },
+    Case {
+        label: "2/98",
+        balance_in: (1_000_000_000_000_000_000_000, 18),
+        weight_in: 20_000_000_000_000_000,
+        balance_out: (1_000_000_000_000_000_000_000_000, 18),
+        weight_out: 980_000_000_000_000_000,
+        amount: 1_000_000_000_000_000_000_000,
+        fee: 3_000_000_000_000_000,
+        expected: Some(50_397_290_492_203_688_065),
+    },
+    Case {
+        label: "at max out ratio",
+        balance_in: (1_000_000_000_000_000_000_000, 18),
+        weight_in: 500_000_000_000_000_000,
+        balance_out: (1_000_000_000_000_000_000_000, 18),
+        weight_out: 500_000_000_000_000_000,
+        amount: 300_000_000_000_000_000_000,
+        fee: 0,
+        expected: Some(428_571_428_571_428_572_000),
+    },
+    Case {
+        label: "past max out ratio",
+        balance_in: (1_000_000_000_000_000_000_000, 18),
+        weight_in: 500_000_000_000_000_000,
+        balance_out: (1_000_000_000_000_000_000_000, 18),
+        weight_out: 500_000_000_000_000_000,
+        amount: 300_000_000_000_000_000_001,
+        fee: 0,
+        expected: None,
+    },
+    Case {
+        label: "whole balance out",
+        balance_in: (1_000_000_000_000_000_000_000, 18),
+        weight_in: 500_000_000_000_000_000,
+        balance_out: (1_000_000_000_000_000_000_000, 18),
+        weight_out: 500_000_000_000_000_000,
+        amount: 1_000_000_000_000_000_000_000,
+        fee: 0,
+        expected: None,
+    },
+    Case {
+        label: "past the balance out",
+        balance_in: (1_000_000_000_000_000_000_000, 18),
+        weight_in: 500_000_000_000_000_000,
+        balance_out: (1_000_000_000_000_000_000_000, 18),
+        weight_out: 500_000_000_000_000_000,
+        amount: 5_000_000_000_000_000_000_000,
+        fee: 3_000_000_000_000_000,
+        expected: None,
+    },
+    Case {
+        label: "empty balance out",
+        balance_in: (1_000_000_000_000_000_000_000, 18),
+        weight_in: 500_000_000_000_000_000,
+        balance_out: (0, 18),
+        weight_out: 500_000_000_000_000_000,
+        amount: 1,
+        fee: 3_000_000_000_000_000,
+        expected: None,
+    },
+    Case {
+        label: "6 decimals in",
+        balance_in: (2_000_000_000_000, 6),
+        weight_in: 500_000_000_000_000_000,
+        balance_out: (1_000_000_000_000_000_000_000, 18),
+        weight_out: 500_000_000_000_000_000,
+        amount: 1_000_000_000_000_000_000,
+        fee: 500_000_000_000_000,
+        expected: Some(2_003_003_505),
+    },
+    Case {
+        label: "6 decimals out",
+        balance_in: (1_000_000_000_000_000_000_000, 18),
+        weight_in: 800_000_000_000_000_000,
+        balance_out: (2_000_000_000_000, 6),
+        weight_out: 200_000_000_000_000_000,
+        amount: 1_234_567_891,
+        fee: 500_000_000_000_000,
+        expected: Some(154_457_780_269_676_839),
+    },
+];
+
+/// The case's pool, with the input token first, and its snapshot.
+fn pool(case: &Case) -> (BalancerPool<DynProvider>, PoolSnapshot) {
+    let provider: Arc<DynProvider> =
+        Arc::new(ProviderBuilder::new().connect_http(FORK_RPC_URL.parse().unwrap()));
+    let pool = BalancerPool::from_parts(
+        Address::repeat_byte(0x01),
+        provider.clone(),
+        vec![
+            named_token(
+                Address::repeat_byte(0x0a),
+                "TKN",
+                case.balance_in.1,
+                provider.clone(),
+            ),
+            named_token(
+                Address::repeat_byte(0x0b),
+                "TKN",
+                case.balance_out.1,
+                provider,
+            ),
+        ],
+        vec![U256::from(case.weight_in), U256::from(case.weight_out)],
+        U256::from(case.fee),
+        Address::repeat_byte(0xba),
+        [0; 32],
+    );
+    let snapshot = PoolSnapshot::Balancer(BalancerPoolSnapshot {
+        balances: vec![
+            U256::from(case.balance_in.0),
+            U256::from(case.balance_out.0),
+        ],
+        is_paused: false,
+        ..Default::default()
+    });
+    (pool, snapshot)
+}
+
+fn check(case: &Case, result: Result<U256, ArbRsError>) {
+    match case.expected {
+        Some(expected) => assert_eq!(result, Ok(U256::from(expected)), "{}", case.label),
+        None => assert!(
+            matches!(result, Err(ArbRsError::CalculationError(_))),
+            "{}: {:?}",
+            case.label,
+            result
+        ),
+    }
+}
+
+#[test]
+fn test_out_given_in_vectors() {
+    for case in GIVEN_IN {
+        let (pool, snapshot) = pool(case);
+        let tokens = pool.get_all_tokens();
+        let result =
+            pool.calculate_tokens_out(&tokens[0], &tokens[1], U256::from(case.amount), &snapshot);
+        check(case, result);
+    }
+}
+
+#[test]
+fn test_in_given_out_vectors() {
+    for case in GIVEN_OUT {
+        let (pool, snapshot) = pool(case);
+        let tokens = pool.get_all_tokens();
+        let result =
+            pool.calculate_tokens_in(&tokens[0], &tokens[1], U256::from(case.amount), &snapshot);
+        check(case, result);
+    }
+}
@@ -0,0 +1,127 @@
+use alloy::consensus::Header as ConsensusHeader;
+use alloy_rpc_types::Header;
+use arbrs::ArbRsError;
+use arbrs::core::block_stream::{
+    BlockStreamConfig, BlockStreamEvent, BlockSubscriber, ResilientBlockStream,
+};
+use futures::StreamExt;
+use futures::stream;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// One subscription attempt: the headers it yields before closing, a connection that never
+/// yields, or a failed connect.
+enum Connection {
+    Headers(Vec<u64>),
+    Silent,
+    Refused,
+}
+
+fn header(number: u64) -> Header {
+    Header::new(ConsensusHeader {
+        number,
+        ..Default::default()
+    })
+}
+
+/// Serves `connections` in order, refusing every attempt once they run out.
+fn scripted(connections: Vec<Connection>) -> (BlockSubscriber, Arc<Mutex<usize>>) {
+    let connections = Arc::new(Mutex::new(VecDeque::from(connections)));
+    let attempts = Arc::new(Mutex::new(0));
+    let counter = attempts.clone();
+    let subscriber: BlockSubscriber = Box::new(move || {
+        *counter.lock().unwrap() += 1;
+        let connection = connections.lock().unwrap().pop_front();
+        Box::pin(async move {
+            match connection {
+                Some(Connection::Headers(numbers)) => {
+                    Ok(stream::iter(numbers.into_iter().map(header)).boxed())
+                }
+                Some(Connection::Silent) => Ok(stream::pending().boxed()),
+                Some(Connection::Refused) | None => {
+                    Err(ArbRsError::ProviderError("connection refused".into()))
+                }
+            }
+        })
+    });
+    (subscriber, attempts)
+}
+
+fn config() -> BlockStreamConfig {
+    BlockStreamConfig {
+        idle_timeout: Duration::from_millis(50),
+        initial_backoff: Duration::ZERO,
+        max_backoff: Duration::ZERO,
+        max_reconnects: Some(2),
+    }
+}
+
+fn describe(event: &BlockStreamEvent) -> (u64, u64) {
+    match event {
+        BlockStreamEvent::Block(header) => (header.number, header.number),
+        BlockStreamEvent::MissedBlocks { from, to } => (*from, *to),
+    }
+}
+
+#[tokio::test]
+async fn test_reconnects_and_reports_missed_blocks() {
+    let (subscriber, attempts) = scripted(vec![
+        Connection::Headers(vec![10, 11]),
+        Connection::Refused,
+        Connection::Headers(vec![15, 16]),
+    ]);
+    let events: Vec<_> = ResilientBlockStream::with_subscriber(subscriber)
+        .with_config(config())
+        .into_stream()
+        .collect()
+        .await;
+
+    assert_eq!(
+        events.iter().map(describe).collect::<Vec<_>>(),
+        [(10, 10), (11, 11), (12, 14), (15, 15), (16, 16)]
+    );
+    assert!(matches!(
+        events[2],
+        BlockStreamEvent::MissedBlocks { from: 12, to: 14 }
+    ));
+    // The three scripted attempts, then two refusals in a row end the stream.
+    assert_eq!(*attempts.lock().unwrap(), 5);
+}
+
+#[tokio::test]
+async fn test_silent_subscription_is_replaced() {
+    let (subscriber, attempts) = scripted(vec![
+        Connection::Headers(vec![7]),
+        Connection::Silent,
+        Connection::Headers(vec![8]),
+    ]);
+    let mut blocks = ResilientBlockStream::with_subscriber(subscriber).with_config(config());
+
+    assert_eq!(describe(&blocks.next_event().await.unwrap()), (7, 7));
+    // The silent connection times out and the next one picks up without a gap.
+    assert!(matches!(
+        blocks.next_event().await,
+        Some(BlockStreamEvent::Block(header)) if header.number == 8
+    ));
+    assert_eq!(blocks.last_block(), Some(8));
+    assert_eq!(*attempts.lock().unwrap(), 3);
+}
+
+#[tokio::test]
+async fn test_replayed_header_is_skipped() {
+    // A resubscription may replay the head it was last at, which isn't yielded twice.
+    let (subscriber, _) = scripted(vec![
+        Connection::Headers(vec![20]),
+        Connection::Headers(vec![20, 21]),
+    ]);
+    let events: Vec<_> = ResilientBlockStream::with_subscriber(subscriber)
+        .with_config(config())
+        .into_stream()
+        .collect()
+        .await;
+    assert_eq!(
+        events.iter().map(describe).collect::<Vec<_>>(),
+        [(20, 20), (21, 21)]
+    );
+}
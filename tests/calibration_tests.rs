@@ -0,0 +1,306 @@
+mod common;
+
+use alloy::transports::mock::Asserter;
+use alloy_primitives::{Address, U256, address};
+use alloy_provider::{Provider, ProviderBuilder};
+use arbrs::ArbRsError;
+use arbrs::arbitrage::cache::ArbitrageCache;
+use arbrs::arbitrage::calibration::{
+    CalibrationBucket, CalibrationConfig, CalibrationTracker, apply_haircut,
+};
+use arbrs::arbitrage::cycle::ArbitrageCycle;
+use arbrs::arbitrage::engine::ArbitrageEngine;
+use arbrs::arbitrage::types::{ArbitragePath, ArbitrageSolution};
+use arbrs::core::token::{Token, TokenLike};
+use arbrs::manager::token_manager::TokenManager;
+use arbrs::pool::quoter::QuotePool;
+use arbrs::pool::strategy::StandardV2Logic;
+use arbrs::pool::uniswap_v2::{UniswapV2Pool, UniswapV2PoolState};
+use arbrs::pool::{LiquidityPool, PoolSnapshot, StateUpdate};
+use async_trait::async_trait;
+use common::token;
+use std::any::Any;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+type DynProvider = dyn Provider + Send + Sync;
+type V2Pool = UniswapV2Pool<DynProvider, StandardV2Logic>;
+
+const WETH: Address = address!("C02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2");
+const BIAS_BPS: u64 = 20;
+
+fn curve_lending() -> CalibrationBucket {
+    CalibrationBucket::new("curve", "Lending")
+}
+
+fn uniswap_v2() -> CalibrationBucket {
+    CalibrationBucket::new("uniswap_v2", "30bps")
+}
+
+/// Realized outputs `BIAS_BPS` below the prediction, +-5 bps of alternating noise.
+fn feed_biased_samples(tracker: &CalibrationTracker, bucket: &CalibrationBucket, count: u64) {
+    let predicted = U256::from(10).pow(U256::from(18));
+    for i in 0..count {
+        let error_bps = if i % 2 == 0 {
+            BIAS_BPS + 5
+        } else {
+            BIAS_BPS - 5
+        };
+        let realized = predicted * U256::from(10_000 - error_bps) / U256::from(10_000);
+        tracker.record(bucket.clone(), predicted, realized);
+    }
+}
+
+#[test]
+fn test_tracker_converges_to_bias() {
+    let tracker = CalibrationTracker::new(CalibrationConfig {
+        min_samples: 50,
+        ..Default::default()
+    });
+    feed_biased_samples(&tracker, &curve_lending(), 500);
+
+    let bias = tracker.bias_bps(&curve_lending()).unwrap();
+    assert!((bias - BIAS_BPS as f64).abs() < 1.0, "bias {bias}");
+    let sigma = tracker.stats(&curve_lending()).unwrap().sigma_bps();
+    assert!((sigma - 5.0).abs() < 1.0, "sigma {sigma}");
+    // bias + 1 sigma
+    assert_eq!(tracker.haircut_bps(&curve_lending()), 25);
+
+    assert_eq!(tracker.bias_bps(&uniswap_v2()), None);
+    assert_eq!(tracker.haircut_bps(&uniswap_v2()), 0);
+    assert_eq!(tracker.haircuts().len(), 1);
+}
+
+#[test]
+fn test_no_haircut_below_min_samples() {
+    let tracker = CalibrationTracker::new(CalibrationConfig {
+        min_samples: 50,
+        ..Default::default()
+    });
+    feed_biased_samples(&tracker, &curve_lending(), 49);
+    assert_eq!(tracker.bias_bps(&curve_lending()), None);
+    assert_eq!(tracker.haircut_bps(&curve_lending()), 0);
+
+    feed_biased_samples(&tracker, &curve_lending(), 1);
+    assert!(tracker.haircut_bps(&curve_lending()) > 0);
+}
+
+/// A V2 pool reporting itself as a Curve lending pool, so the pipeline can be run offline.
+#[derive(Debug)]
+struct LendingPool(V2Pool);
+
+#[async_trait]
+impl LiquidityPool<DynProvider> for LendingPool {
+    fn address(&self) -> Address {
+        self.0.address()
+    }
+
+    fn get_all_tokens(&self) -> Vec<Arc<Token<DynProvider>>> {
+        self.0.get_all_tokens()
+    }
+
+    fn reserves_summary(&self, snapshot: &PoolSnapshot) -> Vec<(Arc<Token<DynProvider>>, U256)> {
+        self.0.reserves_summary(snapshot)
+    }
+
+    async fn update_state(&self) -> Result<StateUpdate, ArbRsError> {
+        self.0.update_state().await
+    }
+
+    async fn get_snapshot(&self, block_number: Option<u64>) -> Result<PoolSnapshot, ArbRsError> {
+        self.0.get_snapshot(block_number).await
+    }
+
+    fn calculate_tokens_out(
+        &self,
+        token_in: &Token<DynProvider>,
+        token_out: &Token<DynProvider>,
+        amount_in: U256,
+        snapshot: &PoolSnapshot,
+    ) -> Result<U256, ArbRsError> {
+        self.0
+            .calculate_tokens_out(token_in, token_out, amount_in, snapshot)
+    }
+
+    fn calculate_tokens_in(
+        &self,
+        token_in: &Token<DynProvider>,
+        token_out: &Token<DynProvider>,
+        amount_out: U256,
+        snapshot: &PoolSnapshot,
+    ) -> Result<U256, ArbRsError> {
+        self.0
+            .calculate_tokens_in(token_in, token_out, amount_out, snapshot)
+    }
+
+    fn to_quoter(&self, snapshot: &PoolSnapshot) -> Result<QuotePool, ArbRsError> {
+        self.0.to_quoter(snapshot)
+    }
+
+    async fn absolute_price(
+        &self,
+        token_in: &Token<DynProvider>,
+        token_out: &Token<DynProvider>,
+    ) -> Result<f64, ArbRsError> {
+        self.0.absolute_price(token_in, token_out).await
+    }
+
+    async fn nominal_price(
+        &self,
+        token_in: &Token<DynProvider>,
+        token_out: &Token<DynProvider>,
+    ) -> Result<f64, ArbRsError> {
+        self.0.nominal_price(token_in, token_out).await
+    }
+
+    async fn absolute_exchange_rate(
+        &self,
+        token_in: &Token<DynProvider>,
+        token_out: &Token<DynProvider>,
+    ) -> Result<f64, ArbRsError> {
+        self.0.absolute_exchange_rate(token_in, token_out).await
+    }
+
+    fn calibration_bucket(&self) -> Option<CalibrationBucket> {
+        Some(curve_lending())
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+fn reserves(reserve0: u64, reserve1: u64) -> PoolSnapshot {
+    let ether = U256::from(10).pow(U256::from(18));
+    PoolSnapshot::UniswapV2(UniswapV2PoolState {
+        reserve0: U256::from(reserve0) * ether,
+        reserve1: U256::from(reserve1) * ether,
+        block_number: 1,
+    })
+}
+
+/// Buys TKN on a plain V2 pool and sells it on the lending pool.
+async fn evaluate(calibration: Option<Arc<CalibrationTracker>>) -> ArbitrageSolution<DynProvider> {
+    let provider: Arc<DynProvider> =
+        Arc::new(ProviderBuilder::new().connect_mocked_client(Asserter::new()));
+    let (weth, other) = (
+        token(WETH, provider.clone()),
+        token(Address::repeat_byte(0xee), provider.clone()),
+    );
+    let v2_pool = Arc::new(V2Pool::new(
+        Address::repeat_byte(0x01),
+        weth.clone(),
+        other.clone(),
+        provider.clone(),
+        StandardV2Logic,
+    ));
+    let lending_pool = Arc::new(LendingPool(V2Pool::new(
+        Address::repeat_byte(0x02),
+        weth.clone(),
+        other.clone(),
+        provider.clone(),
+        StandardV2Logic,
+    )));
+
+    let cache = Arc::new(ArbitrageCache::new());
+    cache
+        .add_path(Arc::new(ArbitrageCycle::new(ArbitragePath {
+            pools: vec![v2_pool, lending_pool],
+            path: vec![weth.clone(), other, weth.clone()],
+            profit_token: weth,
+        })))
+        .await;
+    let engine = ArbitrageEngine::new(
+        cache,
+        Arc::new(TokenManager::in_memory(provider.clone(), 1)),
+        provider,
+    )
+    .with_min_net_profit(U256::ZERO);
+    let engine = match calibration {
+        Some(calibration) => engine.with_calibration(calibration),
+        None => engine,
+    };
+
+    let overrides = HashMap::from([
+        (Address::repeat_byte(0x01), reserves(1_000, 2_400_000)),
+        (Address::repeat_byte(0x02), reserves(1_000, 2_000_000)),
+    ]);
+    let mut solutions = engine
+        .find_opportunities_with_overrides(Some(1), overrides)
+        .await;
+    assert_eq!(solutions.len(), 1);
+    solutions.remove(0)
+}
+
+#[tokio::test]
+async fn test_haircut_applies_to_lending_hop_only() {
+    let tracker = Arc::new(CalibrationTracker::new(CalibrationConfig {
+        min_samples: 50,
+        sigma_k: 0.0,
+        ..Default::default()
+    }));
+    let predicted = U256::from(1_000_000);
+    for _ in 0..100 {
+        tracker.record(curve_lending(), predicted, U256::from(998_000));
+    }
+    assert_eq!(tracker.haircut_bps(&curve_lending()), BIAS_BPS);
+
+    let plain = evaluate(None).await;
+    let calibrated = evaluate(Some(tracker)).await;
+    assert_eq!(plain.optimal_input.raw, calibrated.optimal_input.raw);
+    assert!(calibrated.gross_profit.raw < plain.gross_profit.raw);
+
+    // The V2 hop is untouched.
+    let (plain_v2, calibrated_v2) = (&plain.swap_actions[0], &calibrated.swap_actions[0]);
+    assert_eq!(plain_v2.amount_in, calibrated_v2.amount_in);
+    assert_eq!(plain_v2.min_amount_out, calibrated_v2.min_amount_out);
+
+    // The lending hop's expected output is cut by the bias before slippage is applied.
+    let lending_hop = &calibrated.swap_actions[1];
+    assert_eq!(lending_hop.amount_in, plain.swap_actions[1].amount_in);
+    let cycle = plain
+        .path
+        .as_any()
+        .downcast_ref::<ArbitrageCycle<DynProvider>>()
+        .unwrap();
+    assert_eq!(cycle.path.path[1].address(), lending_hop.token_in.address);
+    let exact_out = plain.path.get_pools()[1]
+        .calculate_tokens_out(
+            &cycle.path.path[1],
+            &cycle.path.path[2],
+            lending_hop.amount_in,
+            &reserves(1_000, 2_000_000),
+        )
+        .unwrap();
+    let with_slippage = |amount: U256| amount * U256::from(9_995) / U256::from(10_000);
+    assert_eq!(
+        plain.swap_actions[1].min_amount_out,
+        with_slippage(exact_out)
+    );
+    assert_eq!(
+        lending_hop.min_amount_out,
+        with_slippage(apply_haircut(exact_out, BIAS_BPS))
+    );
+    assert_eq!(
+        calibrated.gross_profit.raw,
+        apply_haircut(exact_out, BIAS_BPS) - calibrated.optimal_input.raw
+    );
+}
+
+#[cfg(feature = "db")]
+#[tokio::test]
+async fn test_calibration_persists_across_restarts() {
+    let db_manager = arbrs::db::DbManager::new("sqlite::memory:").await.unwrap();
+    let tracker = CalibrationTracker::default();
+    feed_biased_samples(&tracker, &curve_lending(), 60);
+    feed_biased_samples(&tracker, &uniswap_v2(), 10);
+    tracker.save(&db_manager).await.unwrap();
+
+    let restarted = CalibrationTracker::default();
+    restarted.load(&db_manager).await.unwrap();
+    assert_eq!(restarted.table(), tracker.table());
+    assert_eq!(
+        restarted.haircut_bps(&curve_lending()),
+        tracker.haircut_bps(&curve_lending())
+    );
+}
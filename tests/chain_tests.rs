@@ -0,0 +1,331 @@
+mod common;
+
+use alloy::transports::mock::Asserter;
+use alloy_primitives::{Address, Bytes, U256, address};
+use alloy_provider::{Provider, ProviderBuilder};
+use arbrs::ArbRsError;
+use arbrs::arbitrage::cache::ArbitrageCache;
+use arbrs::arbitrage::cycle::ArbitrageCycle;
+use arbrs::arbitrage::engine::{ArbitrageEngine, EngineConfig};
+use arbrs::arbitrage::finder::{enumerate_multi_hop_cycles, resolve_pool_tokens};
+use arbrs::arbitrage::gas::{FixedGas, GasEstimator, OpStackGasEstimator, estimated_tx_size};
+use arbrs::arbitrage::optimizer::GAS_OVERHEAD_UNITS;
+use arbrs::arbitrage::types::ArbitragePath;
+use arbrs::chain::{
+    ARBITRUM_CHAIN_ID, BALANCER_V2_VAULT, BASE_CHAIN_ID, ChainConfig, MAINNET_CHAIN_ID,
+    OP_STACK_GAS_PRICE_ORACLE, OP_STACK_WETH, OPTIMISM_CHAIN_ID,
+};
+use arbrs::core::multicall::MULTICALL3_ADDRESS;
+use arbrs::core::token::{Token, WETH_ADDRESS};
+use arbrs::dex::DexVariant;
+use arbrs::manager::token_manager::TokenManager;
+use arbrs::pool::strategy::StandardV2Logic;
+use arbrs::pool::uniswap_v2::{UniswapV2Pool, UniswapV2PoolState};
+use arbrs::pool::{LiquidityPool, PoolSnapshot, SwapGasCosts};
+use common::token;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+type DynProvider = dyn Provider + Send + Sync;
+
+const FORK_RPC_URL: &str = "http://127.0.0.1:8545";
+const BASE_USDC: Address = address!("833589fCD6eDb6E08f4c7C32D4f71b54bdA02913");
+const BASE_V2_FACTORY: Address = address!("8909Dc15e40173Ff4699343b6eB8132c65e18eC6");
+const TOKEN_A: Address = Address::repeat_byte(0x0a);
+const GWEI: u64 = 1_000_000_000;
+
+fn v2_pool(
+    byte: u8,
+    token0: &Arc<Token<DynProvider>>,
+    token1: &Arc<Token<DynProvider>>,
+    provider: &Arc<DynProvider>,
+) -> Arc<dyn LiquidityPool<DynProvider>> {
+    Arc::new(UniswapV2Pool::new(
+        Address::repeat_byte(byte),
+        token0.clone(),
+        token1.clone(),
+        provider.clone(),
+        StandardV2Logic,
+    ))
+}
+
+fn reserves(reserve0: u64, reserve1: u64) -> PoolSnapshot {
+    let ether = U256::from(10).pow(U256::from(18));
+    PoolSnapshot::UniswapV2(UniswapV2PoolState {
+        reserve0: U256::from(reserve0) * ether,
+        reserve1: U256::from(reserve1) * ether,
+        block_number: 1,
+    })
+}
+
+#[test]
+fn test_presets_resolve_each_chains_addresses() {
+    assert_eq!(ChainConfig::default().chain_id, MAINNET_CHAIN_ID);
+    assert!(ChainConfig::for_chain_id(56).is_none());
+
+    let mainnet = ChainConfig::for_chain_id(MAINNET_CHAIN_ID).unwrap();
+    assert_eq!(mainnet.wrapped_native, WETH_ADDRESS);
+    assert_eq!(
+        mainnet.v2_factories[0].1.dex_type,
+        DexVariant::UniswapV2,
+        "discovery follows Uniswap's factory"
+    );
+    assert_eq!(mainnet.v2_dex_registry().len(), mainnet.v2_factories.len());
+    assert!(mainnet.curve_registry.is_some());
+    assert!(!mainnet.has_l1_data_fee());
+
+    let base = ChainConfig::for_chain_id(BASE_CHAIN_ID).unwrap();
+    assert_eq!(base.wrapped_native, OP_STACK_WETH);
+    assert_eq!(base.stable_reference_token, BASE_USDC);
+    assert_eq!(base.v2_factory(), Some(BASE_V2_FACTORY));
+    assert_eq!(base.balancer_vault, Some(BALANCER_V2_VAULT));
+    assert_eq!(base.curve_registry, None);
+    assert_eq!(base.l1_gas_price_oracle, Some(OP_STACK_GAS_PRICE_ORACLE));
+    assert_eq!(base.block_time, Duration::from_secs(2));
+
+    let optimism = ChainConfig::for_chain_id(OPTIMISM_CHAIN_ID).unwrap();
+    assert_eq!(optimism.wrapped_native, OP_STACK_WETH);
+    assert!(optimism.has_l1_data_fee());
+
+    // Arbitrum bills L1 data in L2 gas, so there's no fee on top.
+    let arbitrum = ChainConfig::for_chain_id(ARBITRUM_CHAIN_ID).unwrap();
+    assert_ne!(arbitrum.wrapped_native, WETH_ADDRESS);
+    assert!(!arbitrum.has_l1_data_fee());
+    assert!(arbitrum.block_time < Duration::from_secs(1));
+
+    for chain in [&mainnet, &base, &optimism, &arbitrum] {
+        assert_eq!(chain.multicall3, MULTICALL3_ADDRESS);
+        assert!(chain.v2_factory().is_some() && chain.v3_factory().is_some());
+        assert!(chain.eth_usd_feed.is_some());
+    }
+}
+
+#[test]
+fn test_json_overrides_a_presets_addresses() {
+    let custom_factory = Address::repeat_byte(0xfa);
+    let json = format!(
+        r#"{{
+            "chain_id": 8453,
+            "v2_factories": [{{ "factory": "{custom_factory}", "fee_bps": 25 }}],
+            "block_time_ms": 1000,
+            "balancer_vault": null
+        }}"#
+    );
+    let chain = ChainConfig::from_json(&json).unwrap();
+
+    assert_eq!(chain.name, "base");
+    assert_eq!(chain.v2_factory(), Some(custom_factory));
+    let details = &chain.v2_dex_registry()[&custom_factory];
+    assert_eq!(
+        (details.fee_numerator, details.fee_denominator),
+        (25, 10_000)
+    );
+    assert_eq!(chain.block_time, Duration::from_secs(1));
+    // Left out or null, the preset's values stay.
+    assert_eq!(chain.wrapped_native, OP_STACK_WETH);
+    assert_eq!(chain.balancer_vault, Some(BALANCER_V2_VAULT));
+    assert_eq!(
+        chain.v3_factory(),
+        ChainConfig::base().v3_factory(),
+        "V3 factories weren't overridden"
+    );
+}
+
+#[test]
+fn test_chain_without_a_preset_needs_its_reference_tokens() {
+    let error = ChainConfig::from_json(r#"{ "chain_id": 56 }"#).unwrap_err();
+    assert!(matches!(error, ArbRsError::ConfigError(_)));
+    assert_eq!(error.class(), "config");
+
+    let wrapped_native = Address::repeat_byte(0x01);
+    let json = format!(
+        r#"{{
+            "chain_id": 56,
+            "name": "bsc",
+            "wrapped_native": "{wrapped_native}",
+            "stable_reference_token": "{}",
+            "v3_factories": [{{ "factory": "{}" }}]
+        }}"#,
+        Address::repeat_byte(0x02),
+        Address::repeat_byte(0x03),
+    );
+    let chain = ChainConfig::from_json(&json).unwrap();
+    assert_eq!(chain.name, "bsc");
+    assert_eq!(chain.wrapped_native, wrapped_native);
+    assert_eq!(chain.v2_factory(), None);
+    assert_eq!(chain.v3_factory(), Some(Address::repeat_byte(0x03)));
+    assert_eq!(chain.balancer_vault, None);
+    assert!(!chain.has_l1_data_fee());
+
+    // Misspelled fields are refused rather than silently ignored.
+    assert!(ChainConfig::from_json(r#"{ "chain_id": 8453, "wraped_native": "0x00" }"#).is_err());
+}
+
+#[tokio::test]
+async fn test_cycles_start_from_the_chains_wrapped_native() {
+    let provider: Arc<DynProvider> =
+        Arc::new(ProviderBuilder::new().connect_mocked_client(Asserter::new()));
+    let token_manager = TokenManager::in_memory(provider.clone(), BASE_CHAIN_ID);
+    assert_eq!(token_manager.chain().chain_id, BASE_CHAIN_ID);
+    let (weth, mainnet_weth, a) = (
+        token(OP_STACK_WETH, provider.clone()),
+        token(WETH_ADDRESS, provider.clone()),
+        token(TOKEN_A, provider.clone()),
+    );
+    for token in [&weth, &mainnet_weth, &a] {
+        token_manager.insert_token(token.clone());
+    }
+    let pools = vec![
+        v2_pool(0x01, &weth, &a, &provider),
+        v2_pool(0x02, &weth, &a, &provider),
+        // A cycle through mainnet's WETH address means nothing on Base.
+        v2_pool(0x03, &mainnet_weth, &a, &provider),
+        v2_pool(0x04, &mainnet_weth, &a, &provider),
+    ];
+
+    let report = enumerate_multi_hop_cycles(resolve_pool_tokens(pools, &token_manager).await, 3);
+    assert!(!report.paths.is_empty());
+    for path in &report.paths {
+        assert!(
+            path.get_involved_pools()
+                .iter()
+                .all(|pool| [0x01, 0x02].map(Address::repeat_byte).contains(pool))
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_l1_data_fee_is_added_to_the_gas_cost() {
+    let provider: Arc<DynProvider> =
+        Arc::new(ProviderBuilder::new().connect_mocked_client(Asserter::new()));
+    let (weth, a) = (
+        token(OP_STACK_WETH, provider.clone()),
+        token(TOKEN_A, provider.clone()),
+    );
+    let pools = vec![
+        v2_pool(0x01, &weth, &a, &provider),
+        v2_pool(0x02, &weth, &a, &provider),
+    ];
+    let cache = Arc::new(ArbitrageCache::new());
+    cache
+        .add_path(Arc::new(ArbitrageCycle::new(ArbitragePath {
+            pools,
+            path: vec![weth.clone(), a, weth.clone()],
+            profit_token: weth,
+        })))
+        .await;
+
+    let token_manager = Arc::new(
+        TokenManager::in_memory(provider.clone(), 1).with_chain(Arc::new(ChainConfig::base())),
+    );
+    let engine = ArbitrageEngine::new(cache, token_manager, provider);
+    // Flash loans come from the Vault on Base too, in Base's WETH.
+    assert_eq!(
+        engine.config.flashloan_sources,
+        HashMap::from([(OP_STACK_WETH, BALANCER_V2_VAULT)])
+    );
+    let l1_fee_per_byte = U256::from(1_000 * GWEI);
+    let engine = engine
+        .with_config(EngineConfig {
+            min_net_profit_wei: U256::ZERO,
+            flashloan_sources: HashMap::new(),
+            ..Default::default()
+        })
+        .with_gas_estimator(Box::new(
+            FixedGas::new(U256::from(GWEI)).with_l1_fee_per_byte(l1_fee_per_byte),
+        ));
+    let overrides = HashMap::from([
+        (Address::repeat_byte(0x01), reserves(1_000, 2_400_000)),
+        (Address::repeat_byte(0x02), reserves(1_000, 2_000_000)),
+    ]);
+
+    let solutions = engine
+        .find_opportunities_with_overrides(Some(1), overrides)
+        .await;
+    assert_eq!(solutions.len(), 1);
+    let gas_units = U256::from(2 * SwapGasCosts::default().uniswap_v2 + GAS_OVERHEAD_UNITS);
+    let l1_data_fee = l1_fee_per_byte * U256::from(estimated_tx_size(2));
+    assert_eq!(
+        solutions[0].gas_cost,
+        gas_units * U256::from(GWEI) + l1_data_fee
+    );
+}
+
+#[tokio::test]
+async fn test_op_stack_estimator_reads_the_oracle() {
+    let asserter = Asserter::new();
+    let provider: Arc<DynProvider> =
+        Arc::new(ProviderBuilder::new().connect_mocked_client(asserter.clone()));
+    let estimator = OpStackGasEstimator::new(
+        provider,
+        OP_STACK_GAS_PRICE_ORACLE,
+        Arc::new(FixedGas::new(U256::from(GWEI))),
+    );
+
+    // Execution gas is left to the inner estimator, without any call.
+    assert_eq!(
+        estimator.gas_price(Some(1), None).await.unwrap(),
+        U256::from(GWEI)
+    );
+    asserter.push_success(&Bytes::from(
+        U256::from(42_000_000_000_000u64).to_be_bytes::<32>(),
+    ));
+    assert_eq!(
+        estimator
+            .l1_data_fee(Some(1), estimated_tx_size(3))
+            .await
+            .unwrap(),
+        U256::from(42_000_000_000_000u64)
+    );
+    assert!(asserter.read_q().is_empty());
+    assert_eq!(
+        FixedGas::new(U256::from(GWEI))
+            .l1_data_fee(None, 100)
+            .await
+            .unwrap(),
+        U256::ZERO
+    );
+}
+
+/// Needs the node at `FORK_RPC_URL` to fork Base.
+#[tokio::test]
+async fn test_base_preset_matches_a_base_fork() {
+    let provider: Arc<DynProvider> =
+        Arc::new(ProviderBuilder::new().connect_http(FORK_RPC_URL.parse().unwrap()));
+    let chain_id = provider.get_chain_id().await.unwrap();
+    assert_eq!(chain_id, BASE_CHAIN_ID);
+    let chain = ChainConfig::for_chain_id(chain_id).unwrap();
+
+    for contract in [
+        chain.wrapped_native,
+        chain.stable_reference_token,
+        chain.v2_factory().unwrap(),
+        chain.v3_factory().unwrap(),
+        chain.multicall3,
+        chain.balancer_vault.unwrap(),
+    ] {
+        let code = provider.get_code_at(contract).await.unwrap();
+        assert!(!code.is_empty(), "no code at {contract}");
+    }
+    // The WETH/USDC pair sits where Base's factory and init code hash put it.
+    let (factory, details) = &chain.v2_factories[0];
+    let pair = details.pair_address(*factory, chain.wrapped_native, chain.stable_reference_token);
+    assert!(!provider.get_code_at(pair).await.unwrap().is_empty());
+
+    let block = provider.get_block_number().await.unwrap();
+    let estimator = OpStackGasEstimator::new(
+        provider.clone(),
+        chain.l1_gas_price_oracle.unwrap(),
+        Arc::new(FixedGas::new(U256::ZERO)),
+    );
+    let small = estimator
+        .l1_data_fee(Some(block), estimated_tx_size(2))
+        .await
+        .unwrap();
+    let large = estimator
+        .l1_data_fee(Some(block), estimated_tx_size(4))
+        .await
+        .unwrap();
+    assert!(!small.is_zero() && large > small, "{small} {large}");
+}
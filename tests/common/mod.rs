@@ -0,0 +1,68 @@
+//! Fixtures shared by the integration tests. Each test crate uses some of them.
+#![allow(dead_code)]
+
+use alloy_primitives::{Address, U256};
+use alloy_provider::Provider;
+use arbrs::core::token::{Erc20Data, Token};
+use arbrs::curve::constants::A_PRECISION;
+use arbrs::curve::pool_attributes::{
+    CalculationStrategy, ParameterFetcherType, PoolAttributes, PoolVariant, SwapStrategyType,
+};
+use arbrs::curve::pool_overrides::{DVariant, YVariant};
+use std::sync::Arc;
+
+type DynProvider = dyn Provider + Send + Sync;
+
+/// `amount` whole units of an 18 decimal token.
+pub fn ether(amount: u64) -> U256 {
+    U256::from(amount) * U256::from(10).pow(U256::from(18))
+}
+
+/// An 18 decimal token named `TKN`, its metadata known up front so nothing is fetched.
+pub fn token(address: Address, provider: Arc<DynProvider>) -> Arc<Token<DynProvider>> {
+    named_token(address, "TKN", 18, provider)
+}
+
+/// A token like [`token`] with its own symbol and decimals.
+pub fn named_token(
+    address: Address,
+    symbol: &str,
+    decimals: u8,
+    provider: Arc<DynProvider>,
+) -> Arc<Token<DynProvider>> {
+    Arc::new(Token::Erc20(Arc::new(Erc20Data::new(
+        address,
+        symbol.to_string(),
+        symbol.to_string(),
+        decimals,
+        provider,
+    ))))
+}
+
+/// A plain Curve pool of `n_coins` 18 decimal coins quoted with `swap_strategy`, with none
+/// of the optional parameters set. Tests override what they need with struct update syntax.
+pub fn attributes(n_coins: usize, swap_strategy: SwapStrategyType) -> PoolAttributes {
+    PoolAttributes {
+        pool_variant: PoolVariant::Plain,
+        strategy: CalculationStrategy::Modern,
+        swap_strategy,
+        d_variant: DVariant::Default,
+        y_variant: YVariant::Default,
+        n_coins,
+        rates: vec![U256::from(10).pow(U256::from(18)); n_coins],
+        precision_multipliers: vec![U256::ONE; n_coins],
+        use_lending: vec![false; n_coins],
+        fee_gamma: None,
+        mid_fee: None,
+        out_fee: None,
+        offpeg_fee_multiplier: None,
+        base_pool_address: None,
+        oracle_method: None,
+        parameter_fetcher: ParameterFetcherType::Standard,
+        factory_address: None,
+        a_precision_multiplier: A_PRECISION,
+        is_native: Vec::new(),
+        raw_coin_addresses: Vec::new(),
+        weth_coin: None,
+    }
+}
@@ -0,0 +1,162 @@
+mod common;
+
+use alloy::transports::mock::Asserter;
+use alloy_primitives::{Address, U256, address};
+use alloy_provider::{Provider, ProviderBuilder};
+use arbrs::arbitrage::cache::ArbitrageCache;
+use arbrs::arbitrage::engine::ArbitrageEngine;
+use arbrs::arbitrage::optimizer::{ETHER_SCALE, wei_to_token_units};
+use arbrs::core::token::{Token, TokenLike, USDC_ADDRESS, WETH_ADDRESS};
+use arbrs::manager::token_manager::TokenManager;
+use arbrs::pool::strategy::StandardV2Logic;
+use arbrs::pool::uniswap_v2::{UniswapV2Pool, UniswapV2PoolState};
+use arbrs::pool::{LiquidityPool, PoolSnapshot};
+use common::named_token;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+type DynProvider = dyn Provider + Send + Sync;
+
+const WBTC: Address = address!("2260FAC5E5542a773Aa44fBCfeDf7C193bc2C599");
+
+/// Pools and their snapshots, all on a provider that's never called.
+struct Market {
+    provider: Arc<DynProvider>,
+    tokens: HashMap<Address, Arc<Token<DynProvider>>>,
+    pools: HashMap<Address, Arc<dyn LiquidityPool<DynProvider>>>,
+    snapshots: HashMap<Address, PoolSnapshot>,
+}
+
+impl Market {
+    fn new() -> Self {
+        let provider: Arc<DynProvider> =
+            Arc::new(ProviderBuilder::new().connect_mocked_client(Asserter::new()));
+        let tokens = [(WETH_ADDRESS, 18), (USDC_ADDRESS, 6), (WBTC, 8)]
+            .into_iter()
+            .map(|(address, decimals)| {
+                let token = named_token(address, "TKN", decimals, provider.clone());
+                (address, token)
+            })
+            .collect();
+        Self {
+            provider,
+            tokens,
+            pools: HashMap::new(),
+            snapshots: HashMap::new(),
+        }
+    }
+
+    /// Adds a V2 pair holding `amount_a` whole `token_a` and `amount_b` whole `token_b`.
+    fn pair(
+        mut self,
+        id: u8,
+        (token_a, amount_a): (Address, u64),
+        (token_b, amount_b): (Address, u64),
+    ) -> Self {
+        let raw = |token: Address, amount: u64| {
+            U256::from(amount) * U256::from(10).pow(U256::from(self.tokens[&token].decimals()))
+        };
+        let (mut reserve_a, mut reserve_b) = (raw(token_a, amount_a), raw(token_b, amount_b));
+        let (mut token0, mut token1) = (token_a, token_b);
+        if token0 > token1 {
+            (token0, token1) = (token1, token0);
+            (reserve_a, reserve_b) = (reserve_b, reserve_a);
+        }
+        let address = Address::repeat_byte(id);
+        let pool = UniswapV2Pool::new(
+            address,
+            self.tokens[&token0].clone(),
+            self.tokens[&token1].clone(),
+            self.provider.clone(),
+            StandardV2Logic,
+        );
+        self.pools.insert(address, Arc::new(pool));
+        self.snapshots.insert(
+            address,
+            PoolSnapshot::UniswapV2(UniswapV2PoolState {
+                reserve0: reserve_a,
+                reserve1: reserve_b,
+                block_number: 1,
+            }),
+        );
+        self
+    }
+
+    fn rates(&self, tokens: &[Address]) -> HashMap<Address, U256> {
+        let engine = ArbitrageEngine::new(
+            Arc::new(ArbitrageCache::new()),
+            Arc::new(TokenManager::in_memory(self.provider.clone(), 1)),
+            self.provider.clone(),
+        );
+        let tokens: Vec<_> = tokens
+            .iter()
+            .map(|token| self.tokens[token].clone())
+            .collect();
+        engine.get_all_profit_token_conversion_rates(&tokens, &self.pools, &self.snapshots)
+    }
+}
+
+/// Whether `actual` is within `bps` of `expected`.
+fn assert_close(actual: U256, expected: U256, bps: u64) {
+    let tolerance = expected * U256::from(bps) / U256::from(10_000);
+    assert!(
+        actual.abs_diff(expected) <= tolerance,
+        "{actual} is not within {bps} bps of {expected}"
+    );
+}
+
+/// 0.021 ETH, 700k gas at 30 gwei.
+fn gas_cost_wei() -> U256 {
+    U256::from(21_000_000_000_000_000u64)
+}
+
+#[tokio::test]
+async fn test_usdc_rate_comes_from_the_deepest_weth_pool() {
+    // The dust pair prices ETH at 1000 USDC, the deep one at 2000.
+    let market = Market::new()
+        .pair(0x01, (WETH_ADDRESS, 1), (USDC_ADDRESS, 1_000))
+        .pair(0x02, (WETH_ADDRESS, 1_000), (USDC_ADDRESS, 2_000_000));
+    let rates = market.rates(&[WETH_ADDRESS, USDC_ADDRESS]);
+    assert_eq!(rates[&WETH_ADDRESS], ETHER_SCALE);
+    let rate = rates[&USDC_ADDRESS];
+    assert_close(rate, U256::from(2_000) * ETHER_SCALE, 50);
+    // 0.021 ETH is 42 USDC.
+    assert_close(
+        wei_to_token_units(gas_cost_wei(), rate, 6),
+        U256::from(42_000_000u64),
+        50,
+    );
+}
+
+#[tokio::test]
+async fn test_wbtc_rate_from_its_weth_pool() {
+    // 1 WBTC is 20 ETH.
+    let market = Market::new().pair(0x01, (WETH_ADDRESS, 2_000), (WBTC, 100));
+    let rate = market.rates(&[WBTC])[&WBTC];
+    assert_close(rate, ETHER_SCALE / U256::from(20), 50);
+    // 0.021 ETH is 0.00105 WBTC.
+    assert_close(
+        wei_to_token_units(gas_cost_wei(), rate, 8),
+        U256::from(105_000u64),
+        50,
+    );
+}
+
+#[tokio::test]
+async fn test_rate_without_a_weth_pool_goes_through_usdc() {
+    // 1 ETH is 2000 USDC and 1 WBTC 40000 USDC, so 1 ETH is 0.05 WBTC.
+    let market = Market::new()
+        .pair(0x01, (WETH_ADDRESS, 1_000), (USDC_ADDRESS, 2_000_000))
+        .pair(0x02, (WBTC, 50), (USDC_ADDRESS, 2_000_000));
+    let rate = market.rates(&[WBTC])[&WBTC];
+    assert_close(rate, ETHER_SCALE / U256::from(20), 100);
+    assert_close(
+        wei_to_token_units(gas_cost_wei(), rate, 8),
+        U256::from(105_000u64),
+        100,
+    );
+
+    // Without the WETH/USDC leg there's nothing to price it with.
+    let market = Market::new().pair(0x02, (WBTC, 50), (USDC_ADDRESS, 2_000_000));
+    assert!(market.rates(&[WBTC]).is_empty());
+}
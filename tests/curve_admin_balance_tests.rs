@@ -0,0 +1,107 @@
+mod common;
+
+use alloy::transports::mock::Asserter;
+use alloy_primitives::{Address, Bytes, U256};
+use alloy_provider::{Provider, ProviderBuilder};
+use arbrs::TokenLike;
+use arbrs::curve::pool::CurveStableswapPool;
+use arbrs::curve::pool_attributes::{CalculationStrategy, PoolAttributes, SwapStrategyType};
+use arbrs::manager::token_manager::TokenManager;
+use arbrs::pool::LiquidityPool;
+use common::{attributes, named_token};
+use std::sync::Arc;
+
+type DynProvider = dyn Provider + Send + Sync;
+
+const POOL: Address = Address::repeat_byte(0x01);
+
+fn admin_fee_pool(asserter: &Asserter) -> CurveStableswapPool<DynProvider> {
+    let provider: Arc<DynProvider> =
+        Arc::new(ProviderBuilder::new().connect_mocked_client(asserter.clone()));
+    let token = |byte: u8| {
+        named_token(
+            Address::repeat_byte(byte),
+            &format!("TKN{byte}"),
+            18,
+            provider.clone(),
+        )
+    };
+    let coins = vec![token(0x0a), token(0x0b)];
+    let attributes = PoolAttributes {
+        strategy: CalculationStrategy::Legacy,
+        raw_coin_addresses: coins.iter().map(|coin| coin.address()).collect(),
+        ..attributes(2, SwapStrategyType::AdminFee)
+    };
+    CurveStableswapPool::from_parts(
+        POOL,
+        token(0x0c),
+        coins,
+        provider.clone(),
+        Arc::new(TokenManager::in_memory(provider, 1)),
+        attributes,
+    )
+}
+
+fn push_words(asserter: &Asserter, values: &[u64]) {
+    for value in values {
+        asserter.push_success(&Bytes::from(U256::from(*value).to_be_bytes::<32>()));
+    }
+}
+
+#[tokio::test]
+async fn test_admin_balances_are_read_at_each_block_and_cached() {
+    let asserter = Asserter::new();
+    let pool = admin_fee_pool(&asserter);
+
+    // `admin_balances(int128)` reverts, so the pool takes a uint256 index.
+    asserter.push_failure_msg("execution reverted");
+    push_words(&asserter, &[5, 7]);
+    assert_eq!(
+        pool.get_admin_balances_at(10).await.unwrap(),
+        [U256::from(5), U256::from(7)]
+    );
+    assert_eq!(
+        pool.get_admin_balances_at(10).await.unwrap(),
+        [U256::from(5), U256::from(7)]
+    );
+
+    // The index type isn't probed again.
+    push_words(&asserter, &[6, 8]);
+    assert_eq!(
+        pool.get_admin_balances_at(11).await.unwrap(),
+        [U256::from(6), U256::from(8)]
+    );
+    assert!(asserter.read_q().is_empty());
+
+    // A reorg from block 11 drops only the balances read at it.
+    pool.invalidate_from(11).await;
+    push_words(&asserter, &[9, 9]);
+    assert_eq!(
+        pool.get_admin_balances_at(11).await.unwrap(),
+        [U256::from(9), U256::from(9)]
+    );
+    assert_eq!(
+        pool.get_admin_balances_at(10).await.unwrap(),
+        [U256::from(5), U256::from(7)]
+    );
+    assert!(asserter.read_q().is_empty());
+}
+
+#[tokio::test]
+async fn test_balances_index_type_is_probed_once() {
+    let asserter = Asserter::new();
+    let pool = admin_fee_pool(&asserter);
+
+    // `balances(int128)` answers, and is used from then on.
+    push_words(&asserter, &[0, 110, 210]);
+    assert_eq!(
+        pool.fetch_balances_for_block(Some(11)).await.unwrap(),
+        [U256::from(110), U256::from(210)]
+    );
+    push_words(&asserter, &[120, 220]);
+    assert_eq!(
+        pool.fetch_balances_for_block(Some(12)).await.unwrap(),
+        [U256::from(120), U256::from(220)]
+    );
+    assert!(asserter.read_q().is_empty());
+}
@@ -0,0 +1,213 @@
+#![cfg(feature = "db")]
+
+use alloy_primitives::Address;
+use alloy_provider::Provider;
+use arbrs::ArbRsError;
+use arbrs::core::token::Token;
+use arbrs::db::DbManager;
+use arbrs::dex::PoolKind;
+use arbrs::manager::curve_bootstrap::{
+    BootstrapOptions, BootstrapReport, PoolEnumerator, run_bootstrap,
+};
+use async_trait::async_trait;
+use std::sync::{Arc, Mutex};
+use tokio_util::sync::CancellationToken;
+
+const DB_URL: &str = "sqlite::memory:";
+const POOL_COUNT: u64 = 50;
+const FAILING: [u64; 2] = [5, 33];
+type DynProvider = dyn Provider + Send + Sync;
+
+struct MockRegistry {
+    id: &'static str,
+    pools: Vec<Address>,
+}
+
+#[async_trait]
+impl PoolEnumerator for MockRegistry {
+    fn id(&self) -> String {
+        self.id.to_string()
+    }
+
+    async fn pool_count(&self) -> Result<u64, ArbRsError> {
+        Ok(self.pools.len() as u64)
+    }
+
+    async fn pool_at(&self, index: u64) -> Result<Address, ArbRsError> {
+        Ok(self.pools[index as usize])
+    }
+}
+
+struct UnsupportedRegistry;
+
+#[async_trait]
+impl PoolEnumerator for UnsupportedRegistry {
+    fn id(&self) -> String {
+        "unsupported".to_string()
+    }
+
+    async fn pool_count(&self) -> Result<u64, ArbRsError> {
+        Err(ArbRsError::ProviderError("execution reverted".to_string()))
+    }
+
+    async fn pool_at(&self, _index: u64) -> Result<Address, ArbRsError> {
+        unreachable!()
+    }
+}
+
+fn pool_address(index: u64) -> Address {
+    Address::with_last_byte(index as u8 + 1)
+}
+
+fn registry(id: &'static str) -> Arc<dyn PoolEnumerator> {
+    Arc::new(MockRegistry {
+        id,
+        pools: (0..POOL_COUNT).map(pool_address).collect(),
+    })
+}
+
+/// Saves pools like the real builder and fails for the `FAILING` indices. Cancels the
+/// run once `crash_after` builds have been attempted.
+struct FakeBuilder {
+    attempts: Mutex<Vec<Address>>,
+    crash_after: Option<usize>,
+    fail: bool,
+    cancel: CancellationToken,
+}
+
+impl FakeBuilder {
+    fn new(cancel: CancellationToken, crash_after: Option<usize>, fail: bool) -> Self {
+        Self {
+            attempts: Mutex::new(Vec::new()),
+            crash_after,
+            fail,
+            cancel,
+        }
+    }
+
+    async fn build(&self, db_manager: &DbManager, address: Address) -> Result<(), ArbRsError> {
+        let attempts = {
+            let mut attempts = self.attempts.lock().unwrap();
+            attempts.push(address);
+            attempts.len()
+        };
+        if self.crash_after == Some(attempts) {
+            self.cancel.cancel();
+        }
+        if self.fail && FAILING.iter().any(|&index| pool_address(index) == address) {
+            return Err(ArbRsError::BrokenPool);
+        }
+        let tokens: &[Arc<Token<DynProvider>>] = &[];
+        db_manager
+            .save_pool(address, &PoolKind::CurveStable, tokens, None, None)
+            .await
+            .unwrap();
+        Ok(())
+    }
+
+    fn attempts(&self) -> Vec<Address> {
+        self.attempts.lock().unwrap().clone()
+    }
+}
+
+async fn run(
+    db_manager: &DbManager,
+    enumerators: &[Arc<dyn PoolEnumerator>],
+    builder: &FakeBuilder,
+    retry_failed: bool,
+) -> BootstrapReport {
+    let options = BootstrapOptions {
+        retry_failed,
+        cancel: builder.cancel.clone(),
+        ..Default::default()
+    };
+    run_bootstrap(db_manager, enumerators, 4, &options, |address| {
+        builder.build(db_manager, address)
+    })
+    .await
+    .unwrap()
+}
+
+#[tokio::test]
+async fn test_interrupted_bootstrap_resumes() {
+    let db_manager = DbManager::new(DB_URL).await.unwrap();
+    let enumerators = [registry("registry")];
+
+    // Crash after the 20th build.
+    let first = FakeBuilder::new(CancellationToken::new(), Some(20), true);
+    let report = run(&db_manager, &enumerators, &first, false).await;
+    let expected: Vec<Address> = (0..20).map(pool_address).collect();
+    assert_eq!(first.attempts(), expected);
+    assert!(!report.completed);
+    assert_eq!(report.built.len(), 19);
+    assert_eq!(
+        report.failed,
+        vec![(pool_address(5), ArbRsError::BrokenPool.to_string())]
+    );
+    assert_eq!(
+        db_manager
+            .get_state("curve_bootstrap:registry")
+            .await
+            .unwrap(),
+        Some("20".to_string())
+    );
+
+    // The resumed run builds exactly the remaining 30.
+    let second = FakeBuilder::new(CancellationToken::new(), None, true);
+    let report = run(&db_manager, &enumerators, &second, false).await;
+    let expected: Vec<Address> = (20..POOL_COUNT).map(pool_address).collect();
+    assert_eq!(second.attempts(), expected);
+    assert!(report.completed);
+    assert_eq!(report.built.len(), 29);
+    assert_eq!(report.failed.len(), 1);
+
+    let failures = db_manager.load_pool_failures().await.unwrap();
+    assert_eq!(failures.len(), 2);
+    for (address, source, error) in &failures {
+        assert!(FAILING.iter().any(|&index| pool_address(index) == *address));
+        assert_eq!(source, "registry");
+        assert_eq!(error, &ArbRsError::BrokenPool.to_string());
+    }
+    assert_eq!(
+        db_manager.load_pool_addresses().await.unwrap().len(),
+        (POOL_COUNT - 2) as usize
+    );
+}
+
+#[tokio::test]
+async fn test_failures_are_skipped_unless_retried() {
+    let db_manager = DbManager::new(DB_URL).await.unwrap();
+    let builder = FakeBuilder::new(CancellationToken::new(), None, true);
+    run(&db_manager, &[registry("registry")], &builder, false).await;
+    assert_eq!(builder.attempts().len(), POOL_COUNT as usize);
+
+    // A second registry listing the same pools builds nothing new, and an unsupported
+    // one is skipped.
+    let enumerators = [
+        registry("registry"),
+        Arc::new(UnsupportedRegistry) as Arc<dyn PoolEnumerator>,
+        registry("meta_registry"),
+    ];
+    let second = FakeBuilder::new(CancellationToken::new(), None, true);
+    let report = run(&db_manager, &enumerators, &second, false).await;
+    assert!(second.attempts().is_empty());
+    assert!(report.completed);
+    assert_eq!(report.skipped_known, (POOL_COUNT - 2) as usize);
+    assert_eq!(report.skipped_failed, 2);
+    assert_eq!(report.unsupported, vec!["unsupported".to_string()]);
+
+    // Only the recorded failures are retried.
+    let retry = FakeBuilder::new(CancellationToken::new(), None, false);
+    let report = run(&db_manager, &enumerators, &retry, true).await;
+    assert_eq!(
+        retry.attempts(),
+        FAILING
+            .iter()
+            .map(|&index| pool_address(index))
+            .collect::<Vec<_>>()
+    );
+    assert_eq!(report.built.len(), 2);
+    // Both registries are already fully enumerated.
+    assert_eq!(report.skipped_known, 0);
+    assert!(db_manager.load_pool_failures().await.unwrap().is_empty());
+}
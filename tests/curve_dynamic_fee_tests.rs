@@ -0,0 +1,151 @@
+mod common;
+
+use alloy::transports::mock::Asserter;
+use alloy_primitives::{Address, U256};
+use alloy_provider::{Provider, ProviderBuilder};
+use arbrs::curve::constants::FEE_DENOMINATOR;
+use arbrs::curve::math;
+use arbrs::curve::pool::CurveStableswapPool;
+use arbrs::curve::pool_attributes::{CalculationStrategy, PoolAttributes, SwapStrategyType};
+use arbrs::curve::pool_overrides::DVariant;
+use arbrs::curve::types::CurvePoolSnapshot;
+use arbrs::manager::token_manager::TokenManager;
+use arbrs::pool::{LiquidityPool, PoolSnapshot};
+use common::{attributes, ether, token};
+use std::sync::Arc;
+
+type DynProvider = dyn Provider + Send + Sync;
+
+/// A two-coin pool like saave, with an off-peg multiplier of 2 when `offpeg_fee_multiplier`
+/// is set.
+fn pool(offpeg_fee_multiplier: Option<U256>) -> CurveStableswapPool<DynProvider> {
+    let provider: Arc<DynProvider> =
+        Arc::new(ProviderBuilder::new().connect_mocked_client(Asserter::new()));
+    let tokens: Vec<_> = [0x0a, 0x0b]
+        .map(|byte| token(Address::repeat_byte(byte), provider.clone()))
+        .to_vec();
+    let attributes = PoolAttributes {
+        strategy: CalculationStrategy::Legacy,
+        d_variant: DVariant::Legacy,
+        offpeg_fee_multiplier,
+        ..attributes(2, SwapStrategyType::DynamicFee)
+    };
+    CurveStableswapPool::from_parts(
+        Address::repeat_byte(0x01),
+        tokens[0].clone(),
+        tokens,
+        provider.clone(),
+        Arc::new(TokenManager::in_memory(provider, 1)),
+        attributes,
+    )
+}
+
+/// 0.04% fee, with three times as much of the first coin as of the second.
+fn snapshot(fee: u64) -> CurvePoolSnapshot {
+    CurvePoolSnapshot {
+        balances: vec![ether(3_000_000), ether(1_000_000)],
+        a: U256::from(100),
+        fee: U256::from(fee),
+        admin_fee: Some(FEE_DENOMINATOR / U256::from(2)),
+        rates: vec![ether(1); 2],
+        ..Default::default()
+    }
+}
+
+fn multiplier() -> Option<U256> {
+    Some(FEE_DENOMINATOR * U256::from(2))
+}
+
+#[test]
+fn test_offpeg_swaps_pay_the_dynamic_fee_of_the_average_balances() {
+    let dynamic = pool(multiplier());
+    let (token_in, token_out) = (&dynamic.tokens[0], &dynamic.tokens[1]);
+    let dx = ether(10_000);
+
+    // Without a fee the output is the whole invariant move, with no wei held back.
+    let no_fee = PoolSnapshot::Curve(snapshot(0));
+    let dy = dynamic
+        .calculate_tokens_out(token_in, token_out, dx, &no_fee)
+        .unwrap();
+    let balances = snapshot(0).balances;
+    let (x, y) = (balances[0] + dx, balances[1] - dy);
+    let fee = math::dynamic_fee(
+        (balances[0] + x) / U256::from(2),
+        (balances[1] + y) / U256::from(2),
+        U256::from(4_000_000),
+        multiplier().unwrap(),
+    )
+    .unwrap();
+    // Off peg, the fee is above the 0.04% base.
+    assert!(fee > U256::from(4_000_000));
+
+    let with_fee = dynamic
+        .calculate_tokens_out(
+            token_in,
+            token_out,
+            dx,
+            &PoolSnapshot::Curve(snapshot(4_000_000)),
+        )
+        .unwrap();
+    assert_eq!(with_fee, dy - fee * dy / FEE_DENOMINATOR);
+
+    // A pool without a multiplier charges the flat fee.
+    let flat = pool(None)
+        .calculate_tokens_out(
+            token_in,
+            token_out,
+            dx,
+            &PoolSnapshot::Curve(snapshot(4_000_000)),
+        )
+        .unwrap();
+    assert!(flat > with_fee);
+}
+
+#[test]
+fn test_dynamic_fee_tokens_in_is_the_smallest_input_covering_the_output() {
+    let snapshot = PoolSnapshot::Curve(snapshot(4_000_000));
+    for offpeg_fee_multiplier in [multiplier(), None] {
+        let pool = pool(offpeg_fee_multiplier);
+        for (i, j) in [(0, 1), (1, 0)] {
+            let (token_in, token_out) = (&pool.tokens[i], &pool.tokens[j]);
+            let amount_out = ether(50_000);
+            let amount_in = pool
+                .calculate_tokens_in(token_in, token_out, amount_out, &snapshot)
+                .unwrap();
+            let out = |dx| {
+                pool.calculate_tokens_out(token_in, token_out, dx, &snapshot)
+                    .unwrap()
+            };
+            assert!(out(amount_in) >= amount_out, "{i}->{j}: falls short");
+            if offpeg_fee_multiplier.is_some() {
+                assert!(
+                    out(amount_in - U256::ONE) < amount_out,
+                    "{i}->{j}: overpays"
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn test_dynamic_fee_exchange_simulation_matches_the_quote() {
+    let pool = pool(multiplier());
+    let (token_in, token_out) = (&pool.tokens[1], &pool.tokens[0]);
+    let snapshot = snapshot(4_000_000);
+    let dx = ether(10_000);
+
+    let result = pool
+        .simulate_exchange(token_in, token_out, dx, &snapshot)
+        .unwrap();
+    assert_eq!(
+        result.amount_out,
+        pool.calculate_tokens_out(token_in, token_out, dx, &PoolSnapshot::Curve(snapshot))
+            .unwrap()
+    );
+    assert!(!result.admin_fee_amount.is_zero());
+    assert_eq!(result.final_snapshot.balances[1], ether(1_010_000));
+    assert_eq!(
+        result.final_snapshot.balances[0],
+        ether(3_000_000) - result.amount_out - result.admin_fee_amount
+    );
+}
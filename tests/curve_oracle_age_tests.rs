@@ -0,0 +1,161 @@
+mod common;
+
+use alloy::transports::mock::Asserter;
+use alloy_primitives::{Address, Bytes, U256, address, aliases::U80};
+use alloy_provider::{Provider, ProviderBuilder};
+use alloy_sol_types::{SolCall, sol};
+use arbrs::ArbRsError;
+use arbrs::arbitrage::cycle::ArbitrageCycle;
+use arbrs::arbitrage::types::ArbitragePath;
+use arbrs::curve::pool::CurveStableswapPool;
+use arbrs::curve::pool_attributes::{CalculationStrategy, PoolAttributes, SwapStrategyType};
+use arbrs::curve::pool_overrides::DVariant;
+use arbrs::curve::types::CurvePoolSnapshot;
+use arbrs::manager::token_manager::TokenManager;
+use arbrs::pool::{LiquidityPool, PoolSnapshot};
+use common::{attributes, ether, token};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+type DynProvider = dyn Provider + Send + Sync;
+
+const ORACLE_POOL: Address = address!("59Ab5a5b5d617E478a2479B0cAD80DA7e2831492");
+const PLAIN_POOL: Address = Address::repeat_byte(0x01);
+const FEED: Address = Address::repeat_byte(0xfe);
+
+// Return encodings for the calls the mock answers.
+sol! {
+    function oracle_method() external view returns (uint256);
+    function latestRoundData() external view returns (uint80 roundId, int256 answer, uint256 startedAt, uint256 updatedAt, uint80 answeredInRound);
+}
+
+fn pool(
+    provider: &Arc<DynProvider>,
+    address: Address,
+    swap_strategy: SwapStrategyType,
+) -> Arc<CurveStableswapPool<DynProvider>> {
+    let tokens = vec![
+        token(Address::repeat_byte(0x0a), provider.clone()),
+        token(Address::repeat_byte(0x0b), provider.clone()),
+    ];
+    let attributes = PoolAttributes {
+        strategy: CalculationStrategy::Legacy,
+        d_variant: DVariant::Legacy,
+        oracle_method: Some(1),
+        ..attributes(2, swap_strategy)
+    };
+    Arc::new(CurveStableswapPool::from_parts(
+        address,
+        tokens[0].clone(),
+        tokens,
+        provider.clone(),
+        Arc::new(TokenManager::in_memory(provider.clone(), 1)),
+        attributes,
+    ))
+}
+
+fn mocked_provider() -> (Asserter, Arc<DynProvider>) {
+    let asserter = Asserter::new();
+    let provider: Arc<DynProvider> =
+        Arc::new(ProviderBuilder::new().connect_mocked_client(asserter.clone()));
+    (asserter, provider)
+}
+
+/// `oracle_method` pointing at `FEED`, called with `latestAnswer()`'s selector.
+fn push_oracle_method(asserter: &Asserter) {
+    let oracle_method = (U256::from(0x50d25bcdu32) << 224) | U256::from_be_slice(FEED.as_slice());
+    asserter.push_success(&Bytes::from(oracle_methodCall::abi_encode_returns(
+        &oracle_method,
+    )));
+}
+
+#[tokio::test]
+async fn test_oracle_timestamp_is_read_from_a_chainlink_feed() {
+    let (asserter, provider) = mocked_provider();
+    let oracle_pool = pool(&provider, ORACLE_POOL, SwapStrategyType::Oracle);
+
+    push_oracle_method(&asserter);
+    asserter.push_success(&Bytes::from(latestRoundDataCall::abi_encode_returns(
+        &latestRoundDataReturn {
+            roundId: U80::from(7),
+            answer: Default::default(),
+            startedAt: U256::from(1_690_000_000),
+            updatedAt: U256::from(1_700_000_000),
+            answeredInRound: U80::from(7),
+        },
+    )));
+    assert_eq!(
+        oracle_pool.get_oracle_updated_at(100).await.unwrap(),
+        Some(1_700_000_000)
+    );
+    // Cached for the block, the mock has nothing left to answer with.
+    assert_eq!(
+        oracle_pool.get_oracle_updated_at(100).await.unwrap(),
+        Some(1_700_000_000)
+    );
+    assert!(asserter.read_q().is_empty());
+
+    // An oracle without `latestRoundData` has no timestamp.
+    push_oracle_method(&asserter);
+    asserter.push_failure_msg("execution reverted");
+    assert_eq!(oracle_pool.get_oracle_updated_at(101).await.unwrap(), None);
+
+    // Pools without an oracle aren't asked at all.
+    let plain = pool(&provider, PLAIN_POOL, SwapStrategyType::Default);
+    assert_eq!(plain.get_oracle_updated_at(100).await.unwrap(), None);
+    assert!(asserter.read_q().is_empty());
+}
+
+#[test]
+fn test_paths_through_a_stale_oracle_are_rejected() {
+    let (_, provider) = mocked_provider();
+    let oracle_pool = pool(&provider, ORACLE_POOL, SwapStrategyType::Oracle);
+    let plain_pool = pool(&provider, PLAIN_POOL, SwapStrategyType::Default);
+    let cycle = ArbitrageCycle::new(ArbitragePath {
+        pools: vec![
+            oracle_pool.clone() as Arc<dyn LiquidityPool<DynProvider>>,
+            plain_pool.clone(),
+        ],
+        path: vec![
+            oracle_pool.tokens[0].clone(),
+            oracle_pool.tokens[1].clone(),
+            oracle_pool.tokens[0].clone(),
+        ],
+        profit_token: oracle_pool.tokens[0].clone(),
+    });
+    let snapshot = |oracle_updated_at| {
+        PoolSnapshot::Curve(CurvePoolSnapshot {
+            balances: vec![ether(1_000); 2],
+            block_timestamp: 1_700_100_000,
+            oracle_updated_at,
+            ..Default::default()
+        })
+    };
+    let snapshots = |oracle_updated_at| {
+        HashMap::from([
+            (ORACLE_POOL, snapshot(oracle_updated_at)),
+            (PLAIN_POOL, snapshot(None)),
+        ])
+    };
+
+    let stale = snapshots(Some(1_700_000_000));
+    let error = cycle.check_oracle_ages(&stale, 86_400).unwrap_err();
+    assert_eq!(
+        error,
+        ArbRsError::StaleOracle {
+            pool: ORACLE_POOL,
+            updated_at: 1_700_000_000,
+            block_timestamp: 1_700_100_000,
+        }
+    );
+    assert_eq!(error.class(), "stale_oracle");
+    assert!(
+        error
+            .to_string()
+            .contains("100000 seconds before the block")
+    );
+    assert!(cycle.check_oracle_ages(&stale, 100_000).is_ok());
+
+    // Without a timestamp, the pool passes whatever the limit.
+    assert!(cycle.check_oracle_ages(&snapshots(None), 0).is_ok());
+}
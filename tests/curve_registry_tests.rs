@@ -0,0 +1,119 @@
+use alloy::transports::mock::Asserter;
+use alloy_primitives::{Address, Bytes};
+use alloy_provider::{Provider, ProviderBuilder};
+use alloy_sol_types::SolValue;
+use arbrs::ArbRsError;
+use arbrs::curve::registry::{CurveRegistry, PoolMetadata, RegistrySource};
+use std::sync::Arc;
+
+type DynProvider = dyn Provider + Send + Sync;
+
+const LEGACY_REGISTRY: Address = Address::repeat_byte(0x90);
+const POOL: Address = Address::repeat_byte(0x01);
+const LP_TOKEN: Address = Address::repeat_byte(0x02);
+const BASE_POOL: Address = Address::repeat_byte(0x03);
+
+fn registry() -> (CurveRegistry<DynProvider>, Asserter) {
+    let asserter = Asserter::new();
+    let provider: Arc<DynProvider> =
+        Arc::new(ProviderBuilder::new().connect_mocked_client(asserter.clone()));
+    (CurveRegistry::new(LEGACY_REGISTRY, provider), asserter)
+}
+
+fn push_address(asserter: &Asserter, address: Address) {
+    asserter.push_success(&Bytes::from(address.abi_encode()));
+}
+
+fn push_unknown_to_factories(asserter: &Asserter) {
+    asserter.push_failure_msg("execution reverted");
+    asserter.push_success(&Bytes::from([Address::ZERO; 4].abi_encode()));
+    push_address(asserter, Address::ZERO);
+}
+
+#[tokio::test]
+async fn test_meta_registry_answers_first() {
+    let (registry, asserter) = registry();
+    push_address(&asserter, LP_TOKEN);
+    push_address(&asserter, BASE_POOL);
+
+    let metadata = registry.pool_metadata(POOL).await.unwrap();
+    assert_eq!(
+        metadata,
+        PoolMetadata {
+            lp_token: LP_TOKEN,
+            base_pool: Some(BASE_POOL),
+            source: RegistrySource::MetaRegistry,
+        }
+    );
+    assert!(asserter.read_q().is_empty());
+    assert_eq!(registry.source_of(POOL), Some(RegistrySource::MetaRegistry));
+}
+
+#[tokio::test]
+async fn test_stable_factory_pools_are_their_own_lp_token() {
+    let (registry, asserter) = registry();
+    // Unknown to the MetaRegistry, which reverts.
+    asserter.push_failure_msg("execution reverted");
+    let coins = [Address::repeat_byte(0xaa), Address::repeat_byte(0xbb), Address::ZERO, Address::ZERO];
+    asserter.push_success(&Bytes::from(coins.abi_encode()));
+    push_address(&asserter, Address::ZERO);
+
+    assert_eq!(registry.get_lp_token(POOL).await.unwrap(), POOL);
+    assert!(asserter.read_q().is_empty());
+    assert_eq!(registry.source_of(POOL), Some(RegistrySource::StableFactory));
+
+    // Answered from the cache, which clones share.
+    let clone = registry.clone();
+    assert_eq!(clone.get_base_pool(POOL).await.unwrap(), None);
+    assert_eq!(clone.get_lp_token(POOL).await.unwrap(), POOL);
+}
+
+#[tokio::test]
+async fn test_crypto_factory_gives_the_lp_token() {
+    let (registry, asserter) = registry();
+    asserter.push_failure_msg("execution reverted");
+    asserter.push_success(&Bytes::from([Address::ZERO; 4].abi_encode()));
+    push_address(&asserter, LP_TOKEN);
+
+    assert_eq!(registry.get_lp_token(POOL).await.unwrap(), LP_TOKEN);
+    assert_eq!(registry.source_of(POOL), Some(RegistrySource::CryptoFactory));
+}
+
+#[tokio::test]
+async fn test_manual_registration_is_the_last_resort() {
+    let (registry, asserter) = registry();
+    registry.register_pool_metadata(POOL, LP_TOKEN, Some(BASE_POOL));
+    push_unknown_to_factories(&asserter);
+
+    assert_eq!(registry.get_base_pool(POOL).await.unwrap(), Some(BASE_POOL));
+    assert_eq!(registry.get_lp_token(POOL).await.unwrap(), LP_TOKEN);
+    assert_eq!(registry.source_of(POOL), Some(RegistrySource::Manual));
+    assert!(asserter.read_q().is_empty());
+
+    // A registry that knows the pool takes precedence over its registration.
+    let other = Address::repeat_byte(0x04);
+    registry.register_pool_metadata(other, LP_TOKEN, None);
+    push_address(&asserter, other);
+    push_address(&asserter, Address::ZERO);
+    assert_eq!(registry.get_lp_token(other).await.unwrap(), other);
+    assert_eq!(registry.source_of(other), Some(RegistrySource::MetaRegistry));
+}
+
+#[tokio::test]
+async fn test_unknown_pool_is_an_error() {
+    let (registry, asserter) = registry();
+    push_unknown_to_factories(&asserter);
+
+    assert_eq!(registry.get_lp_token(POOL).await, Err(ArbRsError::DataFetchError(POOL)));
+    assert_eq!(registry.source_of(POOL), None);
+}
+
+#[tokio::test]
+async fn test_sources_can_be_replaced() {
+    let (registry, asserter) = registry();
+    let registry = registry.with_sources([(RegistrySource::CryptoFactory, Address::repeat_byte(0xcf))]);
+    push_address(&asserter, LP_TOKEN);
+
+    assert_eq!(registry.get_lp_token(POOL).await.unwrap(), LP_TOKEN);
+    assert_eq!(registry.source_of(POOL), Some(RegistrySource::CryptoFactory));
+}
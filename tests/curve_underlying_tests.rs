@@ -0,0 +1,502 @@
+mod common;
+
+use alloy::transports::mock::Asserter;
+use alloy_primitives::{Address, U256, address};
+use alloy_provider::{Provider, ProviderBuilder};
+use alloy_rpc_types::TransactionRequest;
+use alloy_sol_types::{SolCall, sol};
+use arbrs::arbitrage::approvals::required_approvals;
+use arbrs::arbitrage::cache::ArbitrageCache;
+use arbrs::arbitrage::cycle::ArbitrageCycle;
+use arbrs::arbitrage::engine::{ArbitrageEngine, EngineConfig};
+use arbrs::arbitrage::finder::{enumerate_multi_hop_cycles, resolve_pool_tokens};
+use arbrs::arbitrage::types::{ArbitragePath, SwapKind};
+use arbrs::core::token::{TokenLike, WETH_ADDRESS};
+use arbrs::curve::constants::{A_PRECISION, FEE_DENOMINATOR};
+use arbrs::curve::pool::CurveStableswapPool;
+use arbrs::curve::pool_attributes::{
+    CalculationStrategy, PoolAttributes, PoolVariant, SwapStrategyType,
+};
+use arbrs::curve::pool_overrides::DVariant;
+use arbrs::curve::registry::CurveRegistry;
+use arbrs::curve::types::CurvePoolSnapshot;
+use arbrs::curve::underlying::UnderlyingCurvePool;
+use arbrs::manager::token_manager::TokenManager;
+use arbrs::pool::quoter::{QuotePool, Quoter};
+use arbrs::pool::strategy::StandardV2Logic;
+use arbrs::pool::uniswap_v2::{UniswapV2Pool, UniswapV2PoolState};
+use arbrs::pool::{LiquidityPool, PoolSnapshot};
+use common::{ether, token};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+type DynProvider = dyn Provider + Send + Sync;
+
+const FORK_RPC_URL: &str = "http://127.0.0.1:8545";
+const CURVE_MAINNET_REGISTRY: Address = address!("90E00ACe148ca3b23Ac1bC8C240C2a7Dd9c2d7f5");
+const TEST_BLOCK: u64 = 19000000;
+const RAI3CRV_METAPOOL: Address = address!("618788357D0EBd8A37e763ADab3bc575D54c2C7d");
+const USDC_WETH_V2: Address = address!("B4e16d0168e52d35CaCD2c6185b44281Ec28C9Dc");
+const WETH_USDT_V2: Address = address!("0d4a11d5EEaaC28EC3F61d100daF4d40471f1852");
+const USDC: Address = address!("A0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48");
+const USDT: Address = address!("dAC17F958D2ee523a2206206994597C13D831ec7");
+
+const METAPOOL: Address = Address::repeat_byte(0x01);
+const BASE_POOL: Address = Address::repeat_byte(0x02);
+const RAI: Address = Address::repeat_byte(0x7a);
+const LP_TOKEN: Address = Address::repeat_byte(0x3c);
+const BASE_COINS: [Address; 3] = [
+    Address::repeat_byte(0xda),
+    Address::repeat_byte(0xc0),
+    Address::repeat_byte(0xd7),
+];
+
+sol! {
+    function get_dy_underlying(int128 i, int128 j, uint256 dx) external view returns (uint256);
+}
+
+fn provider() -> Arc<DynProvider> {
+    Arc::new(ProviderBuilder::new().connect_mocked_client(Asserter::new()))
+}
+
+fn attributes(
+    pool_variant: PoolVariant,
+    swap_strategy: SwapStrategyType,
+    n_coins: usize,
+    base_pool_address: Option<Address>,
+) -> PoolAttributes {
+    PoolAttributes {
+        pool_variant,
+        strategy: CalculationStrategy::Legacy,
+        d_variant: DVariant::Legacy,
+        base_pool_address,
+        ..common::attributes(n_coins, swap_strategy)
+    }
+}
+
+/// A RAI metapool over a three coin base pool, all coins with 18 decimals.
+fn metapool(provider: &Arc<DynProvider>) -> Arc<CurveStableswapPool<DynProvider>> {
+    let token_manager = Arc::new(TokenManager::in_memory(provider.clone(), 1));
+    let base_tokens: Vec<_> = BASE_COINS
+        .iter()
+        .map(|coin| token(*coin, provider.clone()))
+        .collect();
+    let base_pool = CurveStableswapPool::from_parts(
+        BASE_POOL,
+        token(LP_TOKEN, provider.clone()),
+        base_tokens.clone(),
+        provider.clone(),
+        token_manager.clone(),
+        attributes(PoolVariant::Plain, SwapStrategyType::Default, 3, None),
+    );
+    let mut metapool = CurveStableswapPool::from_parts(
+        METAPOOL,
+        token(Address::repeat_byte(0x3d), provider.clone()),
+        vec![
+            token(RAI, provider.clone()),
+            token(LP_TOKEN, provider.clone()),
+        ],
+        provider.clone(),
+        token_manager,
+        attributes(
+            PoolVariant::Meta,
+            SwapStrategyType::Metapool,
+            2,
+            Some(BASE_POOL),
+        ),
+    );
+    metapool.underlying_tokens = vec![metapool.tokens[0].clone()];
+    metapool.underlying_tokens.extend(base_tokens);
+    metapool.base_pool = Some(Arc::new(base_pool));
+    Arc::new(metapool)
+}
+
+fn base_snapshot() -> CurvePoolSnapshot {
+    CurvePoolSnapshot {
+        balances: vec![ether(4_000_000), ether(3_000_000), ether(3_500_000)],
+        a: U256::from(2_000) * A_PRECISION,
+        fee: U256::from(1_000_000),
+        rates: vec![ether(1); 3],
+        ..Default::default()
+    }
+}
+
+fn meta_snapshot() -> CurvePoolSnapshot {
+    CurvePoolSnapshot {
+        balances: vec![ether(1_000_000), ether(900_000)],
+        a: U256::from(100) * A_PRECISION,
+        fee: U256::from(4_000_000),
+        base_pool_virtual_price: Some(ether(1) + ether(1) / U256::from(50)),
+        base_pool_lp_total_supply: Some(ether(10_000_000)),
+        rates: vec![ether(1); 2],
+        base_pool_snapshot: Some(Box::new(base_snapshot())),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_underlying_swaps_route_through_the_base_pool() {
+    let provider = provider();
+    let metapool = metapool(&provider);
+    let base_pool = metapool.base_pool.clone().unwrap();
+    let underlying = UnderlyingCurvePool::new(metapool.clone()).unwrap();
+    let snapshot = PoolSnapshot::Curve(meta_snapshot());
+    let base = PoolSnapshot::Curve(base_snapshot());
+    let supply = ether(10_000_000);
+    let (rai, dai, usdc) = (
+        &metapool.underlying_tokens[0],
+        &metapool.underlying_tokens[1],
+        &metapool.underlying_tokens[2],
+    );
+    let dx = ether(10_000);
+
+    assert_eq!(underlying.address(), METAPOOL);
+    assert_eq!(underlying.get_all_tokens().len(), 4);
+    assert!(underlying.trades_pair(RAI, BASE_COINS[1]));
+    assert!(!underlying.trades_pair(BASE_COINS[0], BASE_COINS[1]));
+    assert!(UnderlyingCurvePool::new(base_pool.clone()).is_none());
+
+    // RAI for the base pool's LP token, withdrawn as USDC.
+    let lp_out = metapool
+        .calculate_tokens_out(rai, &metapool.tokens[1], dx, &snapshot)
+        .unwrap();
+    let (withdrawn, _) = base_pool
+        .calc_withdraw_one_coin_from_snapshot(lp_out, 1, &base, supply)
+        .unwrap();
+    let rai_for_usdc = underlying
+        .calculate_tokens_out(rai, usdc, dx, &snapshot)
+        .unwrap();
+    assert_eq!(rai_for_usdc, withdrawn);
+
+    // USDC deposited, paying half the fee, then the LP tokens sold for RAI.
+    let mut amounts = vec![U256::ZERO; 3];
+    amounts[1] = dx;
+    let minted = base_pool
+        .calc_token_amount_from_snapshot(&amounts, true, &base_snapshot(), supply)
+        .unwrap();
+    let minted = minted - minted * base_snapshot().fee / (FEE_DENOMINATOR * U256::from(2));
+    let rai_out = metapool
+        .calculate_tokens_out(&metapool.tokens[1], rai, minted, &snapshot)
+        .unwrap();
+    assert_eq!(
+        underlying
+            .calculate_tokens_out(usdc, rai, dx, &snapshot)
+            .unwrap(),
+        rai_out
+    );
+
+    // Between base coins, the base pool's own swap.
+    assert_eq!(
+        underlying
+            .calculate_tokens_out(dai, usdc, dx, &snapshot)
+            .unwrap(),
+        base_pool
+            .calculate_tokens_out(dai, usdc, dx, &base)
+            .unwrap()
+    );
+    assert_eq!(
+        metapool
+            .calculate_dy_underlying_from_snapshot(rai, usdc, dx, &meta_snapshot(), &base)
+            .unwrap(),
+        rai_for_usdc
+    );
+
+    let quoter = underlying.to_quoter(&snapshot).unwrap();
+    assert!(matches!(quoter, QuotePool::CurveUnderlying(_)));
+    assert_eq!(quoter.address(), METAPOOL);
+    assert_eq!(
+        quoter.calculate_out(RAI, BASE_COINS[1], dx).unwrap(),
+        rai_for_usdc
+    );
+    assert!(quoter.calculate_in(RAI, BASE_COINS[1], dx).is_err());
+
+    // A snapshot taken without the base pool can't quote underlying swaps.
+    let without_base = PoolSnapshot::Curve(CurvePoolSnapshot {
+        base_pool_snapshot: None,
+        ..meta_snapshot()
+    });
+    assert!(
+        underlying
+            .calculate_tokens_out(rai, usdc, dx, &without_base)
+            .is_err()
+    );
+
+    let reserves = underlying.reserves_summary(&snapshot);
+    let reserves: Vec<(Address, U256)> = reserves
+        .iter()
+        .map(|(token, reserve)| (token.address(), *reserve))
+        .collect();
+    assert_eq!(
+        reserves,
+        [
+            (RAI, ether(1_000_000)),
+            (BASE_COINS[0], ether(4_000_000)),
+            (BASE_COINS[1], ether(3_000_000)),
+            (BASE_COINS[2], ether(3_500_000)),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_finder_adds_edges_to_the_underlying_coins() {
+    let provider = provider();
+    let metapool = metapool(&provider);
+    let token_manager = TokenManager::in_memory(provider.clone(), 1);
+    let weth = token(WETH_ADDRESS, provider.clone());
+    token_manager.insert_token(weth.clone());
+    for token in metapool.tokens.iter().chain(&metapool.underlying_tokens) {
+        token_manager.insert_token(token.clone());
+    }
+    let (rai, dai, usdc) = (
+        metapool.underlying_tokens[0].clone(),
+        metapool.underlying_tokens[1].clone(),
+        metapool.underlying_tokens[2].clone(),
+    );
+    let pools: Vec<Arc<dyn LiquidityPool<DynProvider>>> = vec![
+        Arc::new(UniswapV2Pool::new(
+            Address::repeat_byte(0x0a),
+            rai.clone(),
+            weth.clone(),
+            provider.clone(),
+            StandardV2Logic,
+        )),
+        Arc::new(UniswapV2Pool::new(
+            Address::repeat_byte(0x0b),
+            usdc.clone(),
+            weth.clone(),
+            provider.clone(),
+            StandardV2Logic,
+        )),
+        Arc::new(UniswapV2Pool::new(
+            Address::repeat_byte(0x0c),
+            dai.clone(),
+            weth.clone(),
+            provider.clone(),
+            StandardV2Logic,
+        )),
+        metapool.clone(),
+        Arc::new(UnderlyingCurvePool::new(metapool.clone()).unwrap()),
+    ];
+
+    let report = enumerate_multi_hop_cycles(resolve_pool_tokens(pools, &token_manager).await, 3);
+    let hops: Vec<Vec<(Address, Address)>> = report
+        .paths
+        .iter()
+        .map(|path| {
+            let cycle = path
+                .as_any()
+                .downcast_ref::<ArbitrageCycle<DynProvider>>()
+                .unwrap();
+            cycle
+                .path
+                .path
+                .windows(2)
+                .map(|pair| (pair[0].address(), pair[1].address()))
+                .collect()
+        })
+        .collect();
+    // RAI reaches USDC and DAI through the metapool's underlying coins...
+    assert!(hops.iter().any(|hops| hops.contains(&(RAI, BASE_COINS[1]))));
+    assert!(hops.iter().any(|hops| hops.contains(&(BASE_COINS[0], RAI))));
+    // ...but DAI and USDC only trade through the base pool, which isn't listed here.
+    assert!(hops.iter().flatten().all(
+        |hop| *hop != (BASE_COINS[0], BASE_COINS[1]) && *hop != (BASE_COINS[1], BASE_COINS[0])
+    ));
+}
+
+#[tokio::test]
+async fn test_underlying_hops_are_marked_for_exchange_underlying() {
+    let provider = provider();
+    let metapool = metapool(&provider);
+    let weth = token(WETH_ADDRESS, provider.clone());
+    let (rai, usdc) = (
+        metapool.underlying_tokens[0].clone(),
+        metapool.underlying_tokens[2].clone(),
+    );
+    let (rai_pool, usdc_pool) = (Address::repeat_byte(0x0a), Address::repeat_byte(0x0b));
+    let cache = Arc::new(ArbitrageCache::new());
+    cache
+        .add_path(Arc::new(ArbitrageCycle::new(ArbitragePath {
+            pools: vec![
+                Arc::new(UniswapV2Pool::new(
+                    rai_pool,
+                    rai.clone(),
+                    weth.clone(),
+                    provider.clone(),
+                    StandardV2Logic,
+                )),
+                Arc::new(UnderlyingCurvePool::new(metapool).unwrap()),
+                Arc::new(UniswapV2Pool::new(
+                    usdc_pool,
+                    usdc.clone(),
+                    weth.clone(),
+                    provider.clone(),
+                    StandardV2Logic,
+                )),
+            ],
+            path: vec![weth.clone(), rai.clone(), usdc, weth.clone()],
+            profit_token: weth,
+        })))
+        .await;
+    let engine = ArbitrageEngine::new(
+        cache,
+        Arc::new(TokenManager::in_memory(provider.clone(), 1)),
+        provider,
+    )
+    .with_config(EngineConfig {
+        min_net_profit_wei: U256::ZERO,
+        flashloan_sources: HashMap::new(),
+        ..Default::default()
+    });
+    // RAI is cheap against WETH next to USDC.
+    let overrides = HashMap::from([
+        (
+            rai_pool,
+            PoolSnapshot::UniswapV2(UniswapV2PoolState {
+                reserve0: ether(3_000_000),
+                reserve1: ether(1_000),
+                block_number: 1,
+            }),
+        ),
+        (METAPOOL, PoolSnapshot::Curve(meta_snapshot())),
+        (
+            usdc_pool,
+            PoolSnapshot::UniswapV2(UniswapV2PoolState {
+                reserve0: ether(2_000_000),
+                reserve1: ether(1_000),
+                block_number: 1,
+            }),
+        ),
+    ]);
+
+    let solutions = engine
+        .find_opportunities_with_overrides(Some(1), overrides)
+        .await;
+    assert_eq!(solutions.len(), 1);
+    let actions = &solutions[0].swap_actions;
+    let kinds: Vec<SwapKind> = actions.iter().map(|action| action.kind).collect();
+    assert_eq!(
+        kinds,
+        [SwapKind::Swap, SwapKind::SwapUnderlying, SwapKind::Swap]
+    );
+    assert_eq!(actions[1].pool_address, METAPOOL);
+    assert_eq!(
+        (actions[1].token_in.address, actions[1].token_out.address),
+        (RAI, BASE_COINS[1])
+    );
+    // The metapool pulls the RAI it's given, so it needs an allowance like any swap.
+    let allowances = HashMap::from([(RAI, U256::ZERO)]);
+    let approvals = required_approvals(actions, Address::repeat_byte(0xee), &allowances);
+    assert_eq!(approvals.len(), 1);
+    assert_eq!(approvals[0].token, RAI);
+}
+
+/// Generates paths through the RAI metapool's underlying coins on a mainnet fork, and
+/// checks each underlying hop against the metapool's `get_dy_underlying`.
+#[tokio::test]
+async fn test_generated_underlying_hops_match_get_dy_underlying() {
+    let provider: Arc<DynProvider> =
+        Arc::new(ProviderBuilder::new().connect_http(FORK_RPC_URL.parse().unwrap()));
+    let token_manager = Arc::new(TokenManager::in_memory(provider.clone(), 1));
+    let registry = CurveRegistry::new(CURVE_MAINNET_REGISTRY, provider.clone());
+    let tokens =
+        CurveStableswapPool::fetch_coins(&RAI3CRV_METAPOOL, provider.clone(), &token_manager)
+            .await
+            .unwrap();
+    let attributes = arbrs::curve::attributes_builder::build_attributes(
+        RAI3CRV_METAPOOL,
+        &tokens,
+        provider.clone(),
+        &token_manager,
+        &registry,
+    )
+    .await
+    .unwrap();
+    let metapool = Arc::new(
+        CurveStableswapPool::new(
+            RAI3CRV_METAPOOL,
+            provider.clone(),
+            token_manager.clone(),
+            &registry,
+            attributes,
+        )
+        .await
+        .unwrap(),
+    );
+    let (usdc, weth, usdt) = (
+        token_manager.get_token(USDC).await.unwrap(),
+        token_manager.get_token(WETH_ADDRESS).await.unwrap(),
+        token_manager.get_token(USDT).await.unwrap(),
+    );
+    let pools: Vec<Arc<dyn LiquidityPool<DynProvider>>> = vec![
+        Arc::new(UniswapV2Pool::new(
+            USDC_WETH_V2,
+            usdc,
+            weth.clone(),
+            provider.clone(),
+            StandardV2Logic,
+        )),
+        Arc::new(UniswapV2Pool::new(
+            WETH_USDT_V2,
+            weth,
+            usdt,
+            provider.clone(),
+            StandardV2Logic,
+        )),
+        metapool.clone(),
+        Arc::new(UnderlyingCurvePool::new(metapool.clone()).unwrap()),
+    ];
+
+    // WETH -> USDC -> RAI -> USDT -> WETH, through the metapool twice.
+    let report = enumerate_multi_hop_cycles(resolve_pool_tokens(pools, &token_manager).await, 4);
+    let snapshot = metapool.get_snapshot(Some(TEST_BLOCK)).await.unwrap();
+    let mut checked = 0;
+    for path in &report.paths {
+        let cycle = path
+            .as_any()
+            .downcast_ref::<ArbitrageCycle<DynProvider>>()
+            .unwrap();
+        for (hop, pool) in cycle.path.pools.iter().enumerate() {
+            let Some(underlying) = pool
+                .as_any()
+                .downcast_ref::<UnderlyingCurvePool<DynProvider>>()
+            else {
+                continue;
+            };
+            let (token_in, token_out) = (&cycle.path.path[hop], &cycle.path.path[hop + 1]);
+            let dx = U256::from(1_000) * U256::from(10).pow(U256::from(token_in.decimals()));
+            let local = underlying
+                .calculate_tokens_out(token_in, token_out, dx, &snapshot)
+                .unwrap();
+            let call = get_dy_underlyingCall {
+                i: underlying.underlying_index(token_in).unwrap() as i128,
+                j: underlying.underlying_index(token_out).unwrap() as i128,
+                dx,
+            };
+            let request = TransactionRequest::default()
+                .to(RAI3CRV_METAPOOL)
+                .input(call.abi_encode().into());
+            let onchain = get_dy_underlyingCall::abi_decode_returns(
+                &provider
+                    .call(request)
+                    .block(TEST_BLOCK.into())
+                    .await
+                    .unwrap(),
+            )
+            .unwrap();
+            let difference = if local > onchain {
+                local - onchain
+            } else {
+                onchain - local
+            };
+            assert!(
+                difference <= U256::from(100),
+                "{} -> {}: {local} vs {onchain}",
+                token_in.symbol(),
+                token_out.symbol()
+            );
+            checked += 1;
+        }
+    }
+    assert!(checked >= 2, "no underlying route was generated");
+}
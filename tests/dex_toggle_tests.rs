@@ -0,0 +1,134 @@
+mod common;
+
+use alloy::transports::mock::Asserter;
+use alloy_primitives::{Address, U256, address};
+use alloy_provider::{Provider, ProviderBuilder};
+use arbrs::arbitrage::cache::ArbitrageCache;
+use arbrs::arbitrage::cycle::ArbitrageCycle;
+use arbrs::arbitrage::engine::ArbitrageEngine;
+use arbrs::arbitrage::types::{ArbitragePath, ArbitrageSolution};
+use arbrs::manager::token_manager::TokenManager;
+use arbrs::pool::strategy::StandardV2Logic;
+use arbrs::pool::uniswap_v2::{UniswapV2Pool, UniswapV2PoolState};
+use arbrs::pool::uniswap_v3::UniswapV3Pool;
+use arbrs::pool::{DexKind, LiquidityPool, PoolSnapshot};
+use common::token;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+type DynProvider = dyn Provider + Send + Sync;
+
+const WETH: Address = address!("C02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2");
+const V2_POOLS: [u8; 2] = [0x01, 0x02];
+const V3_POOLS: [u8; 2] = [0x11, 0x12];
+
+fn reserves(reserve0: u64, reserve1: u64) -> PoolSnapshot {
+    let ether = U256::from(10).pow(U256::from(18));
+    PoolSnapshot::UniswapV2(UniswapV2PoolState {
+        reserve0: U256::from(reserve0) * ether,
+        reserve1: U256::from(reserve1) * ether,
+        block_number: 1,
+    })
+}
+
+/// An engine caching a V2-only, a V3-only and a mixed path over the same token pair. No
+/// snapshot can be fetched; the V2 pools' come from overrides.
+async fn engine() -> (
+    ArbitrageEngine<DynProvider>,
+    Arc<ArbitrageCache<DynProvider>>,
+) {
+    let provider: Arc<DynProvider> =
+        Arc::new(ProviderBuilder::new().connect_mocked_client(Asserter::new()));
+    let (profit_token, other) = (
+        token(WETH, provider.clone()),
+        token(Address::repeat_byte(0xee), provider.clone()),
+    );
+    let v2 = |byte: u8| {
+        Arc::new(UniswapV2Pool::new(
+            Address::repeat_byte(byte),
+            profit_token.clone(),
+            other.clone(),
+            provider.clone(),
+            StandardV2Logic,
+        )) as Arc<dyn LiquidityPool<DynProvider>>
+    };
+    let v3 = |byte: u8| {
+        Arc::new(UniswapV3Pool::new(
+            Address::repeat_byte(byte),
+            profit_token.clone(),
+            other.clone(),
+            3000,
+            60,
+            provider.clone(),
+            None,
+        )) as Arc<dyn LiquidityPool<DynProvider>>
+    };
+
+    let cache = Arc::new(ArbitrageCache::new());
+    for pools in [
+        V2_POOLS.map(v2).to_vec(),
+        V3_POOLS.map(v3).to_vec(),
+        vec![v2(V2_POOLS[0]), v3(V3_POOLS[0])],
+    ] {
+        cache
+            .add_path(Arc::new(ArbitrageCycle::new(ArbitragePath {
+                pools,
+                path: vec![profit_token.clone(), other.clone(), profit_token.clone()],
+                profit_token: profit_token.clone(),
+            })))
+            .await;
+    }
+    let engine = ArbitrageEngine::new(
+        cache.clone(),
+        Arc::new(TokenManager::in_memory(provider.clone(), 1)),
+        provider,
+    )
+    .with_min_net_profit(U256::ZERO);
+    (engine, cache)
+}
+
+async fn evaluate(engine: &ArbitrageEngine<DynProvider>) -> Vec<ArbitrageSolution<DynProvider>> {
+    let overrides = HashMap::from([
+        (
+            Address::repeat_byte(V2_POOLS[0]),
+            reserves(1_000, 2_400_000),
+        ),
+        (
+            Address::repeat_byte(V2_POOLS[1]),
+            reserves(1_000, 2_000_000),
+        ),
+    ]);
+    engine
+        .find_opportunities_with_overrides(Some(1), overrides)
+        .await
+}
+
+#[tokio::test]
+async fn test_disabled_dex_paths_are_skipped() {
+    let (mut engine, cache) = engine().await;
+
+    engine.set_enabled_dexes([DexKind::UniswapV2, DexKind::Curve, DexKind::Balancer]);
+    let solutions = evaluate(&engine).await;
+    let stats = engine.last_stats();
+    assert_eq!((stats.paths, stats.dex_skips), (3, 2));
+    // The V3 pools aren't on any enabled path, so they aren't fetched.
+    assert_eq!(stats.pools, 2);
+    assert_eq!(
+        stats.enabled_dexes,
+        [DexKind::UniswapV2, DexKind::Curve, DexKind::Balancer]
+    );
+    assert_eq!(solutions.len(), 1);
+    assert_eq!(solutions[0].dexes_involved, [DexKind::UniswapV2]);
+    assert_eq!(
+        solutions[0].path.get_involved_pools(),
+        V2_POOLS.map(Address::repeat_byte)
+    );
+
+    engine.set_enabled_dexes(DexKind::ALL);
+    evaluate(&engine).await;
+    let stats = engine.last_stats();
+    assert_eq!((stats.paths, stats.dex_skips), (3, 0));
+    assert_eq!(stats.pools, 4);
+    assert_eq!(stats.enabled_dexes, DexKind::ALL);
+    assert_eq!(cache.paths.read().await.len(), 3);
+}
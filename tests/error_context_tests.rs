@@ -0,0 +1,152 @@
+mod common;
+
+use alloy::transports::mock::Asserter;
+use alloy_primitives::{Address, U256};
+use alloy_provider::{Provider, ProviderBuilder};
+use arbrs::TokenLike;
+use arbrs::core::token::Token;
+use arbrs::curve::constants::A_PRECISION;
+use arbrs::curve::pool::CurveStableswapPool;
+use arbrs::curve::pool_attributes::{CalculationStrategy, PoolAttributes, SwapStrategyType};
+use arbrs::curve::types::CurvePoolSnapshot;
+use arbrs::errors::{ArbRsError, PoolContext, WithContext};
+use arbrs::manager::token_manager::TokenManager;
+use arbrs::pool::quoter::Quoter;
+use arbrs::pool::{LiquidityPool, PoolSnapshot};
+use common::{attributes, named_token};
+use std::sync::Arc;
+
+type DynProvider = dyn Provider + Send + Sync;
+
+const POOL: Address = Address::repeat_byte(0x01);
+
+fn admin_fee_pool() -> (
+    CurveStableswapPool<DynProvider>,
+    Vec<Arc<Token<DynProvider>>>,
+) {
+    let provider: Arc<DynProvider> =
+        Arc::new(ProviderBuilder::new().connect_mocked_client(Asserter::new()));
+    let token = |byte: u8| {
+        named_token(
+            Address::repeat_byte(byte),
+            &format!("TKN{byte}"),
+            18,
+            provider.clone(),
+        )
+    };
+    let coins = vec![token(0x0a), token(0x0b)];
+    let attributes = PoolAttributes {
+        strategy: CalculationStrategy::Legacy,
+        raw_coin_addresses: coins.iter().map(|coin| coin.address()).collect(),
+        ..attributes(2, SwapStrategyType::AdminFee)
+    };
+    let pool = CurveStableswapPool::from_parts(
+        POOL,
+        token(0x0c),
+        coins.clone(),
+        provider.clone(),
+        Arc::new(TokenManager::in_memory(provider, 1)),
+        attributes,
+    );
+    (pool, coins)
+}
+
+/// A snapshot the swap math can't run on: it lists a balance but no rate for a third coin.
+fn broken_snapshot() -> PoolSnapshot {
+    PoolSnapshot::Curve(CurvePoolSnapshot {
+        balances: vec![U256::from(10).pow(U256::from(21)); 3],
+        a: U256::from(100 * A_PRECISION.to::<u64>()),
+        fee: U256::from(4_000_000),
+        rates: vec![U256::from(10).pow(U256::from(18)); 2],
+        ..Default::default()
+    })
+}
+
+fn assert_names_the_pool(error: &ArbRsError) {
+    let context = error.pool_context().expect("error carries no pool");
+    assert_eq!(context.address, POOL);
+    assert_eq!(context.kind.as_deref(), Some("Curve AdminFee"));
+    assert!(matches!(error.root(), ArbRsError::CalculationError(_)));
+    assert_eq!(error.class(), "calculation");
+
+    let message = error.to_string();
+    assert!(message.contains(&POOL.to_string()), "{message}");
+    assert!(message.contains("Curve AdminFee"), "{message}");
+}
+
+#[test]
+fn test_failing_curve_quote_names_the_pool_and_strategy() {
+    let (pool, coins) = admin_fee_pool();
+    let snapshot = broken_snapshot();
+    let amount = U256::from(10).pow(U256::from(18));
+
+    let out = pool.calculate_tokens_out(&coins[0], &coins[1], amount, &snapshot);
+    assert_names_the_pool(&out.unwrap_err());
+    let input = pool.calculate_tokens_in(&coins[0], &coins[1], amount, &snapshot);
+    assert_names_the_pool(&input.unwrap_err());
+
+    // Quoting through the pool's quoter doesn't attribute the error twice.
+    let quoter = pool.to_quoter(&snapshot).unwrap();
+    let error = quoter
+        .calculate_out(coins[0].address(), coins[1].address(), amount)
+        .unwrap_err();
+    assert_names_the_pool(&error);
+    assert!(matches!(
+        &error,
+        ArbRsError::InPool { source, .. } if !matches!(**source, ArbRsError::InPool { .. })
+    ));
+}
+
+#[test]
+fn test_context_fills_in_and_passes_through() {
+    let failed: Result<(), ArbRsError> = Err(ArbRsError::CalculationError(
+        "get_D did not converge".into(),
+    ));
+
+    // Context given again for the same pool only fills in what was missing.
+    let error = failed
+        .with_ctx(|| PoolContext::new(POOL).with_kind("Curve Default"))
+        .ctx(POOL, Some(7))
+        .ctx(POOL, Some(8))
+        .unwrap_err();
+    assert_eq!(
+        error.pool_context(),
+        Some(
+            &PoolContext::new(POOL)
+                .with_kind("Curve Default")
+                .with_block(Some(7))
+        )
+    );
+    assert_eq!(
+        error.to_string(),
+        format!(
+            "pool {POOL} (Curve Default) at block 7: Pool calculation error: get_D did not converge"
+        )
+    );
+
+    // A metapool passing on its base pool's error leaves it naming the base pool.
+    let metapool = Address::repeat_byte(0x02);
+    let error = Err::<(), _>(error).ctx(metapool, None).unwrap_err();
+    assert_eq!(error.pool_context().unwrap().address, POOL);
+
+    // Errors callers match on are left as they are.
+    let partial_fill = || ArbRsError::PartialFill {
+        requested: U256::from(2),
+        filled: U256::ONE,
+    };
+    assert_eq!(
+        Err::<(), _>(partial_fill()).ctx(POOL, None),
+        Err(partial_fill())
+    );
+    assert_eq!(
+        Err::<(), _>(ArbRsError::PoolPaused(POOL)).ctx(POOL, None),
+        Err(ArbRsError::PoolPaused(POOL))
+    );
+
+    // The class is the source's.
+    let provider_error = Err::<(), _>(ArbRsError::ProviderError("reset".into()))
+        .ctx(POOL, None)
+        .unwrap_err();
+    assert_eq!(provider_error.class(), "provider");
+    assert!(!provider_error.is_retryable());
+}
@@ -0,0 +1,170 @@
+mod common;
+
+use alloy::transports::mock::Asserter;
+use alloy_primitives::{Address, U256, address};
+use alloy_provider::{Provider, ProviderBuilder};
+use arbrs::arbitrage::cache::ArbitrageCache;
+use arbrs::arbitrage::cycle::ArbitrageCycle;
+use arbrs::arbitrage::engine::{ArbitrageEngine, EngineConfig};
+use arbrs::arbitrage::gas::FixedGas;
+use arbrs::arbitrage::types::{Arbitrage, ArbitragePath};
+use arbrs::manager::token_manager::TokenManager;
+use arbrs::pool::strategy::StandardV2Logic;
+use arbrs::pool::uniswap_v2::{UniswapV2Pool, UniswapV2PoolState};
+use arbrs::pool::{LiquidityPool, PoolSnapshot};
+use common::token;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+type DynProvider = dyn Provider + Send + Sync;
+
+const WETH: Address = address!("C02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2");
+const BLOCK: u64 = 5;
+
+fn reserves(reserve0: u64, reserve1: u64) -> PoolSnapshot {
+    let ether = U256::from(10).pow(U256::from(18));
+    PoolSnapshot::UniswapV2(UniswapV2PoolState {
+        reserve0: U256::from(reserve0) * ether,
+        reserve1: U256::from(reserve1) * ether,
+        block_number: BLOCK,
+    })
+}
+
+/// A pool that sells the other token 20% cheaper than [`balanced`], so a cycle through it
+/// and a balanced pool is profitable.
+fn cheap() -> PoolSnapshot {
+    reserves(1_000, 2_400_000)
+}
+
+fn balanced() -> PoolSnapshot {
+    reserves(1_000, 2_000_000)
+}
+
+fn pool_address(cycle: usize, hop: usize) -> Address {
+    let mut bytes = [0u8; 20];
+    bytes[0] = hop as u8 + 1;
+    bytes[16..].copy_from_slice(&(cycle as u32).to_be_bytes());
+    Address::from(bytes)
+}
+
+/// An engine over `cycles` WETH cycles of two pools each, the first of them `cheap` for
+/// the cycles in `profitable`, with every pool's snapshot at `BLOCK` already stored.
+async fn engine(
+    cycles: usize,
+    profitable: &[usize],
+) -> (
+    ArbitrageEngine<DynProvider>,
+    Vec<Arc<ArbitrageCycle<DynProvider>>>,
+) {
+    let provider: Arc<DynProvider> =
+        Arc::new(ProviderBuilder::new().connect_mocked_client(Asserter::new()));
+    let (weth, other) = (
+        token(WETH, provider.clone()),
+        token(Address::repeat_byte(0xee), provider.clone()),
+    );
+
+    let cache = Arc::new(ArbitrageCache::new());
+    let engine = ArbitrageEngine::new(
+        cache.clone(),
+        Arc::new(TokenManager::in_memory(provider.clone(), 1)),
+        provider.clone(),
+    )
+    .with_config(EngineConfig {
+        min_net_profit_wei: U256::ZERO,
+        flashloan_sources: HashMap::new(),
+        ..Default::default()
+    })
+    .with_gas_estimator(Box::new(FixedGas::new(U256::ZERO)));
+
+    let mut paths = Vec::with_capacity(cycles);
+    for cycle in 0..cycles {
+        let pools: Vec<Arc<dyn LiquidityPool<DynProvider>>> = (0..2)
+            .map(|hop| {
+                let address = pool_address(cycle, hop);
+                let snapshot = if hop == 0 && profitable.contains(&cycle) {
+                    cheap()
+                } else {
+                    balanced()
+                };
+                engine.snapshot_store.insert(address, BLOCK, snapshot);
+                Arc::new(UniswapV2Pool::new(
+                    address,
+                    weth.clone(),
+                    other.clone(),
+                    provider.clone(),
+                    StandardV2Logic,
+                )) as Arc<dyn LiquidityPool<DynProvider>>
+            })
+            .collect();
+        let path = Arc::new(ArbitrageCycle::new(ArbitragePath {
+            pools,
+            path: vec![weth.clone(), other.clone(), weth.clone()],
+            profit_token: weth.clone(),
+        }));
+        cache.add_path(path.clone()).await;
+        paths.push(path);
+    }
+    (engine, paths)
+}
+
+#[tokio::test]
+async fn test_paths_through_changed_pools_are_evaluated_first() {
+    let (engine, paths) = engine(200, &[]).await;
+    let engine = engine.with_evaluation_budget(Duration::ZERO);
+
+    // Only cycle 150's first pool changed, and it turned profitable. With no time to spare,
+    // it's the one path evaluated.
+    let overrides = HashMap::from([(pool_address(150, 0), cheap())]);
+    let solutions = engine
+        .find_opportunities_with_overrides(Some(BLOCK), overrides)
+        .await;
+    assert_eq!(solutions.len(), 1);
+    assert_eq!(solutions[0].cycle_id, paths[150].cycle_id());
+
+    let stats = engine.last_stats();
+    assert!(stats.truncated);
+    assert_eq!(stats.budget_skips, 199);
+}
+
+#[tokio::test]
+async fn test_recently_profitable_paths_are_evaluated_first() {
+    let (engine, paths) = engine(100, &[70]).await;
+
+    // Unbudgeted, every path is evaluated and the profitable one remembered.
+    let solutions = engine.find_opportunities(Some(BLOCK)).await;
+    assert_eq!(solutions.len(), 1);
+    let stats = engine.last_stats();
+    assert!(!stats.truncated);
+    assert_eq!(stats.budget_skips, 0);
+    assert!(engine.path_priority.score(&paths[70].path_key()) > 0.0);
+    assert_eq!(engine.path_priority.len(), 1);
+
+    // Re-evaluated from the store, no pool changed, so the remembered path goes first.
+    let engine = engine.with_evaluation_budget(Duration::ZERO);
+    let solutions = engine.find_opportunities(Some(BLOCK)).await;
+    assert_eq!(solutions.len(), 1);
+    assert_eq!(solutions[0].cycle_id, paths[70].cycle_id());
+    assert_eq!(engine.last_stats().budget_skips, 99);
+}
+
+#[tokio::test]
+async fn test_large_path_sets_are_cut_off_at_the_budget() {
+    let cycles = 3_000;
+    let profitable: Vec<usize> = (0..cycles).collect();
+    let (engine, _) = engine(cycles, &profitable).await;
+    let budget = Duration::from_millis(50);
+    let engine = engine.with_evaluation_budget(budget);
+
+    let started = Instant::now();
+    let solutions = engine.find_opportunities(Some(BLOCK)).await;
+    let elapsed = started.elapsed();
+
+    let stats = engine.last_stats();
+    assert!(stats.truncated);
+    assert!(stats.budget_skips > 0);
+    assert_eq!(solutions.len(), cycles - stats.budget_skips);
+    // Evaluation stops at the budget; what's left is finishing the last path and the
+    // bookkeeping after the search.
+    assert!(elapsed < budget * 10, "{elapsed:?}");
+}
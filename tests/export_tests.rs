@@ -0,0 +1,424 @@
+mod common;
+
+use alloy::transports::mock::Asserter;
+use alloy_primitives::{Address, U256};
+use alloy_provider::{Provider, ProviderBuilder};
+use alloy_rpc_types::Block;
+use arbrs::arbitrage::amount::TokenAmount;
+use arbrs::arbitrage::cache::ArbitrageCache;
+use arbrs::arbitrage::cycle::ArbitrageCycle;
+use arbrs::arbitrage::engine::{ArbitrageEngine, EngineConfig};
+use arbrs::arbitrage::export::{
+    BlockEvaluationExport, BlockExporter, EvaluationStats, ExportConfig, SolutionExport,
+};
+use arbrs::arbitrage::types::{
+    ArbitragePath, ArbitrageSolution, InputBound, ScenarioResult, SwapAction, SwapKind, TokenRef,
+};
+use arbrs::arbitrage::usd::UsdValues;
+use arbrs::balancer::pool::BalancerPoolSnapshot;
+use arbrs::curve::types::CurvePoolSnapshot;
+use arbrs::manager::token_manager::TokenManager;
+use arbrs::pool::strategy::StandardV2Logic;
+use arbrs::pool::uniswap_v2::{UniswapV2Pool, UniswapV2PoolState};
+use arbrs::pool::uniswap_v3::{TickInfo, UniswapV3PoolSnapshot};
+use arbrs::pool::{DexKind, LiquidityPool, PoolSnapshot};
+use common::named_token;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+// The provider is lazy and never called.
+const FORK_RPC_URL: &str = "http://127.0.0.1:8545";
+type DynProvider = dyn Provider + Send + Sync;
+
+/// A two-pool cycle with amounts that don't fit in a u64 or an f64 mantissa.
+fn synthetic_evaluation(block: u64) -> BlockEvaluationExport {
+    let provider: Arc<DynProvider> =
+        Arc::new(ProviderBuilder::new().connect_http(FORK_RPC_URL.parse().unwrap()));
+    let a = named_token(Address::repeat_byte(0x0a), "AAA", 18, provider.clone());
+    let b = named_token(Address::repeat_byte(0x0b), "BBB", 6, provider.clone());
+    let pool = |byte: u8| -> Arc<dyn LiquidityPool<DynProvider>> {
+        Arc::new(UniswapV2Pool::new(
+            Address::repeat_byte(byte),
+            a.clone(),
+            b.clone(),
+            provider.clone(),
+            StandardV2Logic,
+        ))
+    };
+    let (p1, p2) = (pool(0x01), pool(0x02));
+
+    let cycle = ArbitrageCycle::new(ArbitragePath {
+        pools: vec![p1.clone(), p2.clone()],
+        path: vec![a.clone(), b.clone(), a.clone()],
+        profit_token: a.clone(),
+    });
+    let optimal_input = U256::from(123_456_789_012_345_678_901_234u128);
+    let solution = ArbitrageSolution {
+        cycle_id: cycle.cycle_id(),
+        path: Arc::new(cycle),
+        optimal_input: TokenAmount::new(a.clone(), optimal_input),
+        gross_profit: TokenAmount::new(a.clone(), U256::from(9_007_199_254_740_993u64)),
+        net_profit: TokenAmount::new(a.clone(), U256::from(9_000_000_000_000_001u64)),
+        net_profit_weth: U256::from(9_000_000_000_000_001u64),
+        flashloan_fee: U256::from(7_000_000_000_000_000u64) / U256::from(1_000),
+        gas_cost: U256::from(199_254_740_992u64),
+        divergent_pools: vec![p2.address()],
+        scenario_results: vec![
+            ScenarioResult {
+                label: "base".to_string(),
+                gas_cost: U256::from(199_254_740_992u64),
+                net_profit: U256::from(9_000_000_000_000_001u64),
+                passes: true,
+            },
+            ScenarioResult {
+                label: "+100%".to_string(),
+                gas_cost: U256::from(398_509_481_984u64),
+                net_profit: U256::from(8_999_800_745_259_009u64),
+                passes: false,
+            },
+        ],
+        bound_by: InputBound::Liquidity,
+        persistence_blocks: 4,
+        dexes_involved: vec![DexKind::UniswapV2],
+        swap_actions: vec![
+            SwapAction {
+                pool_address: p1.address(),
+                token_in: TokenRef::from(a.as_ref()),
+                token_out: TokenRef::from(b.as_ref()),
+                amount_in: optimal_input,
+                min_amount_out: U256::from(250_000_000_123u64),
+                kind: SwapKind::Swap,
+            },
+            SwapAction {
+                pool_address: p2.address(),
+                token_in: TokenRef::from(b.as_ref()),
+                token_out: TokenRef::from(a.as_ref()),
+                amount_in: U256::from(250_000_000_123u64),
+                min_amount_out: U256::MAX,
+                kind: SwapKind::Swap,
+            },
+        ],
+        hop_metrics: vec![None, None],
+        approve_actions: vec![],
+        usd: Some(UsdValues {
+            net_profit: U256::from(1_800_000),
+            flashloan_fee: U256::from(1_400),
+            gas_cost: U256::from(5),
+        }),
+        verification: None,
+    };
+
+    let snapshots = BTreeMap::from([
+        (
+            p2.address(),
+            PoolSnapshot::UniswapV2(UniswapV2PoolState {
+                reserve0: U256::from(10).pow(U256::from(30)),
+                reserve1: U256::from(42),
+                block_number: block,
+            }),
+        ),
+        (
+            p1.address(),
+            PoolSnapshot::UniswapV3(UniswapV3PoolSnapshot {
+                sqrt_price_x96: U256::from(1) << 96,
+                tick: -887_220,
+                liquidity: u128::MAX,
+                tick_bitmap: BTreeMap::from([(-3, U256::from(5)), (2, U256::MAX)]),
+                tick_data: BTreeMap::from([(
+                    -60,
+                    TickInfo {
+                        liquidity_gross: 10,
+                        liquidity_net: i128::MIN,
+                    },
+                )]),
+                fee_protocol: 0x44,
+            }),
+        ),
+        (
+            Address::repeat_byte(0x03),
+            PoolSnapshot::Curve(CurvePoolSnapshot {
+                balances: vec![U256::from(1), U256::from(2)],
+                a: U256::from(2_000),
+                fee: U256::from(4_000_000),
+                tricrypto_gamma: Some(U256::from(11)),
+                ..Default::default()
+            }),
+        ),
+        (
+            Address::repeat_byte(0x04),
+            PoolSnapshot::Balancer(BalancerPoolSnapshot {
+                balances: vec![U256::from(3)],
+                is_paused: false,
+                block_number: Some(block),
+                swap_fee: Some(U256::from(4)),
+                ..Default::default()
+            }),
+        ),
+    ]);
+
+    BlockEvaluationExport {
+        block,
+        timestamp: 1_700_000_000,
+        gas_price: U256::from(30_000_000_000u64),
+        snapshots,
+        solutions: vec![SolutionExport::from_solution(&solution)],
+        stats: EvaluationStats {
+            paths: 1,
+            pools: 4,
+            failed_snapshots: 0,
+            reused_snapshots: 0,
+            paused_pool_skips: 0,
+            divergence_rejects: 0,
+            stale_oracle_rejects: 0,
+            liquidity_skips: 0,
+            unpriced_skips: 0,
+            impact_rejects: 0,
+            persistence_suppressed: 0,
+            dex_skips: 1,
+            quarantine_skips: 0,
+            enabled_dexes: vec![DexKind::UniswapV2, DexKind::Curve],
+            verification_rejects: 0,
+            budget_skips: 0,
+            truncated: false,
+            solutions: 1,
+            elapsed_ms: 3,
+        },
+    }
+}
+
+/// Asserts that `keys`, at the given pretty-printed indentation, appear in this order.
+fn assert_keys_in_order(json: &str, indent: &str, keys: &[&str]) {
+    let positions: Vec<usize> = keys
+        .iter()
+        .map(|key| {
+            json.find(&format!("\n{indent}\"{key}\":"))
+                .unwrap_or_else(|| panic!("key {key} not found"))
+        })
+        .collect();
+    assert!(
+        positions.windows(2).all(|w| w[0] < w[1]),
+        "keys out of order: {:?}",
+        keys
+    );
+}
+
+#[test]
+fn test_export_round_trip() {
+    let export = synthetic_evaluation(19_000_000);
+    let json = export.to_json_string().unwrap();
+    let parsed = BlockEvaluationExport::from_json_str(&json).unwrap();
+
+    assert_eq!(parsed.block, export.block);
+    assert_eq!(parsed.gas_price, export.gas_price);
+    assert_eq!(parsed.solutions, export.solutions);
+    assert_eq!(parsed.stats, export.stats);
+    assert_eq!(
+        parsed.snapshots.keys().collect::<Vec<_>>(),
+        export.snapshots.keys().collect::<Vec<_>>()
+    );
+    assert_eq!(parsed.to_json_string().unwrap(), json);
+
+    let solution = &parsed.solutions[0];
+    assert_eq!(
+        solution.pools,
+        vec![Address::repeat_byte(0x01), Address::repeat_byte(0x02)]
+    );
+    assert_eq!(solution.profit_token.address, Address::repeat_byte(0x0a));
+    assert_eq!(solution.profit_token.symbol, "AAA");
+    assert_eq!(solution.tokens[1].decimals, 6);
+    assert_eq!(solution.swaps[1].min_amount_out, U256::MAX);
+    assert_eq!(
+        solution.costs.total,
+        solution.costs.flashloan_fee + solution.costs.gas_cost
+    );
+
+    // Amounts are exact decimal strings, not numbers a JSON reader would round.
+    let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+    let raw = &value["solutions"][0];
+    assert_eq!(raw["optimal_input"], "123456789012345678901234");
+    assert_eq!(raw["gross_profit"], "9007199254740993");
+    assert_eq!(raw["swaps"][1]["min_amount_out"], U256::MAX.to_string());
+    assert_eq!(value["gas_price"], "30000000000");
+    assert_eq!(raw["scenarios"][1]["label"], "+100%");
+    assert_eq!(raw["scenarios"][1]["gas_cost"], "398509481984");
+    assert_eq!(raw["scenarios"][1]["passes"], false);
+    assert_eq!(raw["bound_by"], "Liquidity");
+    assert_eq!(raw["dexes_involved"][0], "uniswap_v2");
+    assert_eq!(raw["usd"]["net_profit"], "1800000");
+    assert_eq!(raw["usd"]["gas_cost"], "5");
+    assert_eq!(value["stats"]["enabled_dexes"][1], "curve");
+}
+
+#[test]
+fn test_export_serialization_is_stable() {
+    let first = synthetic_evaluation(19_000_000).to_json_string().unwrap();
+    let second = synthetic_evaluation(19_000_000).to_json_string().unwrap();
+    assert_eq!(first, second);
+
+    // Fields come out in declaration order rather than alphabetically.
+    assert_keys_in_order(
+        &first,
+        "  ",
+        &[
+            "block",
+            "timestamp",
+            "gas_price",
+            "snapshots",
+            "solutions",
+            "stats",
+        ],
+    );
+    assert_keys_in_order(
+        &first,
+        "      ",
+        &[
+            "cycle_id",
+            "pools",
+            "tokens",
+            "profit_token",
+            "optimal_input",
+            "gross_profit",
+            "net_profit",
+            "costs",
+            "swaps",
+        ],
+    );
+
+    // Snapshots are keyed by pool address in ascending order, whatever the insertion order.
+    let pools: Vec<String> = (1..=4u8)
+        .map(|byte| Address::repeat_byte(byte).to_string().to_lowercase())
+        .collect();
+    let pool_keys: Vec<&str> = pools.iter().map(String::as_str).collect();
+    assert_keys_in_order(&first.to_lowercase(), "    ", &pool_keys);
+}
+
+#[tokio::test]
+async fn test_exporter_keeps_last_n_blocks() {
+    let directory = std::env::temp_dir().join(format!("arbrs-export-test-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&directory);
+
+    let exporter = BlockExporter::spawn(ExportConfig {
+        enabled: true,
+        directory: directory.clone(),
+        keep_last: 3,
+        ..Default::default()
+    })
+    .unwrap();
+    for block in 100..105 {
+        exporter.submit(synthetic_evaluation(block));
+    }
+    // Shutting down has the writer drain the queue and exit.
+    exporter.shutdown().await;
+
+    let mut files: Vec<String> = std::fs::read_dir(&directory)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .collect();
+    files.sort();
+    assert_eq!(
+        files,
+        [
+            "block_000000000102.json",
+            "block_000000000103.json",
+            "block_000000000104.json"
+        ]
+    );
+
+    let latest = std::fs::read_to_string(directory.join(&files[2])).unwrap();
+    let parsed = BlockEvaluationExport::from_json_str(&latest).unwrap();
+    assert_eq!(parsed.block, 104);
+
+    let _ = std::fs::remove_dir_all(&directory);
+}
+
+#[test]
+fn test_exporter_disabled_by_default() {
+    assert!(BlockExporter::spawn(ExportConfig::default()).is_none());
+}
+
+#[tokio::test]
+async fn test_engine_exports_the_block_timestamp() {
+    let directory = std::env::temp_dir().join(format!(
+        "arbrs-export-timestamp-test-{}",
+        std::process::id()
+    ));
+    let _ = std::fs::remove_dir_all(&directory);
+
+    let asserter = Asserter::new();
+    let provider: Arc<DynProvider> =
+        Arc::new(ProviderBuilder::new().connect_mocked_client(asserter.clone()));
+    let a = named_token(Address::repeat_byte(0x0a), "AAA", 18, provider.clone());
+    let b = named_token(Address::repeat_byte(0x0b), "BBB", 18, provider.clone());
+    let pools: Vec<Arc<dyn LiquidityPool<DynProvider>>> = [0x01, 0x02]
+        .into_iter()
+        .map(|byte| {
+            Arc::new(UniswapV2Pool::new(
+                Address::repeat_byte(byte),
+                a.clone(),
+                b.clone(),
+                provider.clone(),
+                StandardV2Logic,
+            )) as Arc<dyn LiquidityPool<DynProvider>>
+        })
+        .collect();
+    let cache = Arc::new(ArbitrageCache::new());
+    cache
+        .add_path(Arc::new(ArbitrageCycle::new(ArbitragePath {
+            pools,
+            path: vec![a.clone(), b, a.clone()],
+            profit_token: a,
+        })))
+        .await;
+    let engine = ArbitrageEngine::new(
+        cache,
+        Arc::new(TokenManager::in_memory(provider.clone(), 1)),
+        provider,
+    )
+    .with_config(EngineConfig {
+        flashloan_sources: HashMap::new(),
+        ..Default::default()
+    })
+    .with_export(ExportConfig {
+        enabled: true,
+        directory: directory.clone(),
+        ..Default::default()
+    });
+
+    let mut block: Block = Block::default();
+    block.header.inner.number = 100;
+    block.header.inner.timestamp = 1_700_000_123;
+    // Both pools are overridden, so their own snapshot calls may fail.
+    asserter.push_failure_msg("unavailable");
+    asserter.push_failure_msg("unavailable");
+    asserter.push_success(&U256::from(30_000_000_000u64));
+    asserter.push_success(&block);
+    let balanced = PoolSnapshot::UniswapV2(UniswapV2PoolState {
+        reserve0: U256::from(10).pow(U256::from(21)),
+        reserve1: U256::from(10).pow(U256::from(21)),
+        block_number: 100,
+    });
+    engine
+        .find_opportunities_with_overrides(
+            Some(100),
+            HashMap::from([
+                (Address::repeat_byte(0x01), balanced.clone()),
+                (Address::repeat_byte(0x02), balanced),
+            ]),
+        )
+        .await;
+
+    // The writer runs in the background; wait for the file to appear.
+    let path = directory.join("block_000000000100.json");
+    for _ in 0..100 {
+        if path.exists() {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    }
+    let export =
+        BlockEvaluationExport::from_json_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+    assert_eq!(export.block, 100);
+    assert_eq!(export.timestamp, 1_700_000_123);
+
+    let _ = std::fs::remove_dir_all(&directory);
+}
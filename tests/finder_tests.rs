@@ -0,0 +1,456 @@
+mod common;
+
+use alloy::transports::mock::Asserter;
+use alloy_primitives::{Address, Bytes, U256, address};
+use alloy_provider::{Provider, ProviderBuilder};
+use arbrs::ArbRsError;
+use arbrs::arbitrage::cache::ArbitrageCache;
+use arbrs::arbitrage::finder::{
+    MinLiquidityFilter, PathFinder, PoolGraph, enumerate_multi_hop_cycles, filter_by_liquidity,
+    find_cycles_through_pool, resolve_pool_tokens,
+};
+use arbrs::arbitrage::types::{Arbitrage, PathKey};
+use arbrs::curve::types::CurvePoolSnapshot;
+use arbrs::manager::token_manager::TokenManager;
+use arbrs::pool::strategy::StandardV2Logic;
+use arbrs::pool::uniswap_v2::UniswapV2Pool;
+use arbrs::pool::uniswap_v3::UniswapV3PoolSnapshot;
+use arbrs::pool::{LiquidityPool, PoolSnapshot};
+use common::{ether, named_token, token};
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+type DynProvider = dyn Provider + Send + Sync;
+
+const WETH: Address = address!("C02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2");
+const TOKEN_A: Address = Address::repeat_byte(0x0a);
+const TOKEN_B: Address = Address::repeat_byte(0x0b);
+// Unknown to the token manager, and its metadata can't be fetched.
+const BROKEN: Address = Address::repeat_byte(0xbd);
+
+/// Queues a `getReserves()` result.
+fn push_reserves(asserter: &Asserter, reserve0: U256, reserve1: U256) {
+    asserter.push_success(&Bytes::from(
+        [reserve0, reserve1, U256::ZERO]
+            .iter()
+            .flat_map(|word| word.to_be_bytes::<32>())
+            .collect::<Vec<u8>>(),
+    ));
+}
+
+/// The pools of each path, sorted.
+fn cycles(paths: &[Arc<dyn Arbitrage<DynProvider>>]) -> Vec<Vec<Address>> {
+    let mut cycles: Vec<Vec<Address>> =
+        paths.iter().map(|path| path.get_involved_pools()).collect();
+    cycles.sort();
+    cycles
+}
+
+/// The keys of `paths`, which must all differ.
+fn path_keys(paths: &[Arc<dyn Arbitrage<DynProvider>>]) -> HashSet<PathKey> {
+    let keys: HashSet<PathKey> = paths.iter().map(|path| path.path_key()).collect();
+    assert_eq!(keys.len(), paths.len(), "no path is found twice");
+    keys
+}
+
+/// `pool_count` V2 pools over WETH and `token_count` other tokens, all known to a token
+/// manager. The first `weth_pairs` tokens trade against WETH and the other pools pair
+/// tokens at random, parallel pools included.
+fn random_market(
+    provider: Arc<DynProvider>,
+    token_count: u32,
+    weth_pairs: u32,
+    pool_count: u32,
+) -> (
+    Vec<Arc<dyn LiquidityPool<DynProvider>>>,
+    TokenManager<DynProvider>,
+) {
+    let token_manager = TokenManager::in_memory(provider.clone(), 1);
+    let weth = token(WETH, provider.clone());
+    token_manager.insert_token(weth.clone());
+    let tokens: Vec<_> = (1..=token_count)
+        .map(|i| {
+            let token = token(
+                Address::left_padding_from(&i.to_be_bytes()),
+                provider.clone(),
+            );
+            token_manager.insert_token(token.clone());
+            token
+        })
+        .collect();
+
+    let mut seed = 0x2545_f491_4f6c_dd1d_u64;
+    let mut next = |bound: u32| {
+        seed ^= seed << 13;
+        seed ^= seed >> 7;
+        seed ^= seed << 17;
+        (seed % u64::from(bound)) as usize
+    };
+    let mut pairs: Vec<_> = tokens[..weth_pairs as usize]
+        .iter()
+        .map(|token| (weth.clone(), token.clone()))
+        .collect();
+    while pairs.len() < pool_count as usize {
+        let (i, j) = (next(token_count), next(token_count));
+        if i != j {
+            pairs.push((tokens[i].clone(), tokens[j].clone()));
+        }
+    }
+    let pools = pairs
+        .into_iter()
+        .zip(0x1000_0000_u32..)
+        .map(|((token0, token1), id)| {
+            Arc::new(UniswapV2Pool::new(
+                Address::left_padding_from(&id.to_be_bytes()),
+                token0,
+                token1,
+                provider.clone(),
+                StandardV2Logic,
+            )) as Arc<dyn LiquidityPool<DynProvider>>
+        })
+        .collect();
+    (pools, token_manager)
+}
+
+#[tokio::test]
+async fn test_cycles_through_new_pools_complete_the_full_search() {
+    let provider: Arc<DynProvider> =
+        Arc::new(ProviderBuilder::new().connect_mocked_client(Asserter::new()));
+    let (pools, token_manager) = random_market(provider, 30, 12, 70);
+    let (known, new) = pools.split_at(64);
+
+    let before =
+        enumerate_multi_hop_cycles(resolve_pool_tokens(known.to_vec(), &token_manager).await, 4);
+    let after =
+        enumerate_multi_hop_cycles(resolve_pool_tokens(pools.clone(), &token_manager).await, 4);
+
+    let finder = PathFinder::new(4, MinLiquidityFilter::default());
+    let rebuilt = finder.rebuild(known.to_vec(), &token_manager).await;
+    assert_eq!(path_keys(&rebuilt.paths), path_keys(&before.paths));
+    let extended = finder.extend(new.to_vec(), &token_manager).await;
+    assert_eq!(finder.graph_size().await.map(|(pools, _)| pools), Some(70));
+
+    // The new cycles are exactly those the full search gains from the new pools.
+    let new_keys = path_keys(&extended.paths);
+    assert!(!new_keys.is_empty());
+    assert!(new_keys.is_disjoint(&path_keys(&before.paths)));
+    let combined: HashSet<PathKey> = path_keys(&before.paths).union(&new_keys).cloned().collect();
+    assert_eq!(combined, path_keys(&after.paths));
+
+    // Pools the graph holds already add nothing.
+    let again = finder.extend(new.to_vec(), &token_manager).await;
+    assert!(again.paths.is_empty());
+
+    // Each new pool's own cycles are the full search's cycles trading it.
+    let mut graph = PoolGraph::new(resolve_pool_tokens(pools.clone(), &token_manager).await);
+    assert!(
+        graph
+            .add_pools(resolve_pool_tokens(new.to_vec(), &token_manager).await)
+            .is_empty()
+    );
+    for pool in new {
+        let through: HashSet<PathKey> = after
+            .paths
+            .iter()
+            .filter(|path| path.get_involved_pools().contains(&pool.address()))
+            .map(|path| path.path_key())
+            .collect();
+        assert_eq!(
+            path_keys(&find_cycles_through_pool(pool, &graph, 4)),
+            through
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_extending_a_large_graph_leaves_cached_paths_alone() {
+    let provider: Arc<DynProvider> =
+        Arc::new(ProviderBuilder::new().connect_mocked_client(Asserter::new()));
+    let (mut pools, token_manager) = random_market(provider.clone(), 600, 250, 4_000);
+    let new_pool = pools.pop().unwrap();
+
+    let finder = PathFinder::new(3, MinLiquidityFilter::default());
+    let cache = ArbitrageCache::new();
+    let report = cache
+        .rebuild_with_finder(&finder, pools, &token_manager)
+        .await;
+    assert_eq!(
+        finder.graph_size().await.map(|(pools, _)| pools),
+        Some(3_999)
+    );
+    let cached = cache.paths.read().await.clone();
+    assert_eq!(cached.len(), report.paths.len());
+
+    // Both tokens trade against WETH, so this one closes cycles with the pools around it.
+    let bridge = Arc::new(UniswapV2Pool::new(
+        Address::repeat_byte(0xb1),
+        token(
+            Address::left_padding_from(&1_u32.to_be_bytes()),
+            provider.clone(),
+        ),
+        token(
+            Address::left_padding_from(&2_u32.to_be_bytes()),
+            provider.clone(),
+        ),
+        provider,
+        StandardV2Logic,
+    )) as Arc<dyn LiquidityPool<DynProvider>>;
+
+    let started = Instant::now();
+    let extended = finder.extend(vec![new_pool, bridge], &token_manager).await;
+    let elapsed = started.elapsed();
+    assert!(!extended.paths.is_empty());
+    assert!(
+        elapsed < Duration::from_millis(250),
+        "extending took {elapsed:?}"
+    );
+
+    let added = cache.add_paths(extended.paths.clone()).await;
+    assert_eq!(added, extended.paths.len());
+    let paths = cache.paths.read().await;
+    assert_eq!(paths.len(), cached.len() + added);
+    assert!(
+        cached
+            .iter()
+            .zip(paths.iter())
+            .all(|(before, after)| Arc::ptr_eq(before, after)),
+        "cached paths keep their instance"
+    );
+}
+
+#[tokio::test]
+async fn test_unresolved_token_excludes_its_pools_only() {
+    let asserter = Asserter::new();
+    let provider: Arc<DynProvider> =
+        Arc::new(ProviderBuilder::new().connect_mocked_client(asserter.clone()));
+    let token_manager = TokenManager::in_memory(provider.clone(), 1);
+    for address in [WETH, TOKEN_A, TOKEN_B] {
+        token_manager.insert_token(token(address, provider.clone()));
+    }
+
+    let pool = |byte: u8, token0: Address, token1: Address| {
+        Arc::new(UniswapV2Pool::new(
+            Address::repeat_byte(byte),
+            token(token0, provider.clone()),
+            token(token1, provider.clone()),
+            provider.clone(),
+            StandardV2Logic,
+        )) as Arc<dyn LiquidityPool<DynProvider>>
+    };
+    let healthy_pools = vec![
+        pool(0x01, WETH, TOKEN_A),
+        pool(0x02, WETH, TOKEN_A),
+        pool(0x03, TOKEN_A, TOKEN_B),
+        pool(0x04, TOKEN_B, WETH),
+    ];
+    let mut pools = healthy_pools.clone();
+    pools.extend([pool(0x05, WETH, BROKEN), pool(0x06, BROKEN, TOKEN_A)]);
+
+    // Nothing is queued, so fetching the broken token's metadata fails.
+    let resolved = resolve_pool_tokens(pools, &token_manager).await;
+    let mut excluded = resolved
+        .excluded_pools
+        .iter()
+        .map(|(pool, e)| (*pool, e))
+        .collect::<Vec<_>>();
+    excluded.sort_by_key(|(pool, _)| *pool);
+    assert_eq!(excluded.len(), 2);
+    for ((pool, e), expected) in excluded.into_iter().zip([0x05, 0x06]) {
+        assert_eq!(pool, Address::repeat_byte(expected));
+        assert!(matches!(e, ArbRsError::UnresolvedToken(token, _) if *token == BROKEN));
+    }
+
+    // Any call during enumeration would consume one of these.
+    for _ in 0..3 {
+        asserter.push_success(&U256::ZERO);
+    }
+    let report = enumerate_multi_hop_cycles(resolved, 3);
+    assert_eq!(asserter.read_q().len(), 3);
+    assert_eq!(report.excluded_pools.len(), 2);
+
+    // The rest of the graph is enumerated as if the broken pools had never been there.
+    let healthy =
+        enumerate_multi_hop_cycles(resolve_pool_tokens(healthy_pools, &token_manager).await, 3);
+    assert!(healthy.excluded_pools.is_empty());
+    assert_eq!(asserter.read_q().len(), 3);
+    assert_eq!(cycles(&report.paths), cycles(&healthy.paths));
+    let traded: HashSet<Address> = report
+        .paths
+        .iter()
+        .flat_map(|path| path.get_involved_pools())
+        .collect();
+    assert_eq!(
+        traded,
+        HashSet::from([0x01, 0x02, 0x03, 0x04].map(Address::repeat_byte))
+    );
+}
+
+#[tokio::test]
+async fn test_liquidity_filter_drops_dust_pools_from_the_graph() {
+    let asserter = Asserter::new();
+    let provider: Arc<DynProvider> =
+        Arc::new(ProviderBuilder::new().connect_mocked_client(asserter.clone()));
+    let token_manager = TokenManager::in_memory(provider.clone(), 1);
+    for address in [WETH, TOKEN_A, TOKEN_B] {
+        token_manager.insert_token(token(address, provider.clone()));
+    }
+    let pool = |byte: u8, token0: Address, token1: Address| {
+        Arc::new(UniswapV2Pool::new(
+            Address::repeat_byte(byte),
+            token(token0, provider.clone()),
+            token(token1, provider.clone()),
+            provider.clone(),
+            StandardV2Logic,
+        )) as Arc<dyn LiquidityPool<DynProvider>>
+    };
+    let pools = vec![
+        pool(0x01, WETH, TOKEN_A),
+        pool(0x02, WETH, TOKEN_A),
+        pool(0x03, TOKEN_A, TOKEN_B),
+        pool(0x04, TOKEN_B, WETH),
+    ];
+
+    let unfiltered = filter_by_liquidity(
+        resolve_pool_tokens(pools.clone(), &token_manager).await,
+        &MinLiquidityFilter::default(),
+        Some(10),
+    )
+    .await;
+    // Without a floor nothing is snapshotted.
+    assert!(asserter.read_q().is_empty());
+    assert_eq!(unfiltered.excluded_pools.len(), 0);
+    let without_dust: Vec<_> = pools
+        .iter()
+        .filter(|pool| pool.address() != Address::repeat_byte(0x02))
+        .cloned()
+        .collect();
+    let expected =
+        enumerate_multi_hop_cycles(resolve_pool_tokens(without_dust, &token_manager).await, 3);
+    assert!(enumerate_multi_hop_cycles(unfiltered, 3).paths.len() > expected.paths.len());
+
+    // Pool 0x02 holds 1 WETH, under the floor. Pools without WETH aren't held to it.
+    push_reserves(&asserter, ether(10), ether(20_000));
+    push_reserves(&asserter, ether(1), ether(2_000));
+    push_reserves(&asserter, U256::from(1), U256::from(1));
+    push_reserves(&asserter, ether(20_000), ether(10));
+    let filter = MinLiquidityFilter::weth(ether(5));
+    let filtered = filter_by_liquidity(
+        resolve_pool_tokens(pools, &token_manager).await,
+        &filter,
+        Some(10),
+    )
+    .await;
+    assert!(filtered.excluded_pools.is_empty());
+    let report = enumerate_multi_hop_cycles(filtered, 3);
+    assert_eq!(cycles(&report.paths), cycles(&expected.paths));
+}
+
+#[tokio::test]
+async fn test_tvl_floor_drops_pools_worth_too_little() {
+    let asserter = Asserter::new();
+    let provider: Arc<DynProvider> =
+        Arc::new(ProviderBuilder::new().connect_mocked_client(asserter.clone()));
+    let token_manager = TokenManager::in_memory(provider.clone(), 1);
+    for address in [WETH, TOKEN_A, TOKEN_B] {
+        token_manager.insert_token(token(address, provider.clone()));
+    }
+    let pool = |byte: u8, token0: Address, token1: Address| {
+        Arc::new(UniswapV2Pool::new(
+            Address::repeat_byte(byte),
+            token(token0, provider.clone()),
+            token(token1, provider.clone()),
+            provider.clone(),
+            StandardV2Logic,
+        )) as Arc<dyn LiquidityPool<DynProvider>>
+    };
+    let pools = vec![
+        pool(0x01, WETH, TOKEN_A),
+        pool(0x02, WETH, TOKEN_A),
+        pool(0x03, TOKEN_A, TOKEN_B),
+        pool(0x04, TOKEN_B, WETH),
+    ];
+
+    // A and B price at 2,000 per ETH off their deepest WETH pools, which puts 0x01, 0x03
+    // and 0x04 at 20 ETH each and 0x02 at 2 ETH.
+    push_reserves(&asserter, ether(10), ether(20_000));
+    push_reserves(&asserter, ether(1), ether(2_000));
+    push_reserves(&asserter, ether(20_000), ether(20_000));
+    push_reserves(&asserter, ether(20_000), ether(10));
+    let filter = MinLiquidityFilter::default().with_min_tvl(ether(5));
+    assert!(!filter.is_disabled());
+    let filtered = filter_by_liquidity(
+        resolve_pool_tokens(pools.clone(), &token_manager).await,
+        &filter,
+        Some(10),
+    )
+    .await;
+    assert!(asserter.read_q().is_empty());
+    assert!(filtered.excluded_pools.is_empty());
+
+    let without_dust: Vec<_> = pools
+        .into_iter()
+        .filter(|pool| pool.address() != Address::repeat_byte(0x02))
+        .collect();
+    let expected =
+        enumerate_multi_hop_cycles(resolve_pool_tokens(without_dust, &token_manager).await, 3);
+    assert!(!expected.paths.is_empty());
+    assert_eq!(
+        cycles(&enumerate_multi_hop_cycles(filtered, 3).paths),
+        cycles(&expected.paths)
+    );
+}
+
+#[test]
+fn test_liquidity_filter_per_pool_type() {
+    let provider: Arc<DynProvider> =
+        Arc::new(ProviderBuilder::new().connect_mocked_client(Asserter::new()));
+    let (weth, token_a) = (
+        token(WETH, provider.clone()),
+        token(TOKEN_A, provider.clone()),
+    );
+    let filter = MinLiquidityFilter::weth(ether(5)).with_min_token_reserve(U256::from(1_000));
+
+    // At a price of one, a V3 pool's virtual reserves are both its liquidity.
+    let v3 = |liquidity: U256| {
+        let snapshot = UniswapV3PoolSnapshot {
+            sqrt_price_x96: U256::ONE << 96,
+            tick: 0,
+            liquidity: liquidity.to(),
+            tick_bitmap: Default::default(),
+            tick_data: Default::default(),
+            fee_protocol: 0,
+        };
+        let (reserve0, reserve1) = snapshot.virtual_reserves();
+        assert_eq!((reserve0, reserve1), (liquidity, liquidity));
+        (
+            vec![(weth.clone(), reserve0), (token_a.clone(), reserve1)],
+            PoolSnapshot::UniswapV3(snapshot),
+        )
+    };
+    let (summary, snapshot) = v3(ether(10));
+    assert!(filter.accepts(&summary, &snapshot));
+    let (summary, snapshot) = v3(ether(1));
+    assert!(!filter.accepts(&summary, &snapshot));
+    let (summary, snapshot) = v3(U256::ZERO);
+    assert!(!MinLiquidityFilter::default().accepts(&summary, &snapshot));
+
+    // 600 of an 18-decimal token and 500 of a 6-decimal one.
+    let stable_pair = vec![
+        (token_a, ether(600)),
+        (
+            named_token(TOKEN_B, "TKN", 6, provider),
+            U256::from(500_000_000),
+        ),
+    ];
+    let curve = PoolSnapshot::Curve(CurvePoolSnapshot {
+        balances: stable_pair.iter().map(|(_, reserve)| *reserve).collect(),
+        ..Default::default()
+    });
+    assert!(filter.accepts(&stable_pair, &curve));
+    assert!(
+        !filter
+            .with_min_token_reserve(U256::from(1_200))
+            .accepts(&stable_pair, &curve)
+    );
+}
@@ -0,0 +1,178 @@
+mod common;
+
+use alloy::transports::mock::Asserter;
+use alloy_primitives::{Address, Bytes, U256, address};
+use alloy_provider::{Provider, ProviderBuilder};
+use arbrs::arbitrage::cache::ArbitrageCache;
+use arbrs::arbitrage::cycle::ArbitrageCycle;
+use arbrs::arbitrage::engine::{ArbitrageEngine, EngineConfig, GasScenario, ScenarioGasPrice};
+use arbrs::arbitrage::types::{ArbitragePath, ArbitrageSolution, InputBound};
+use arbrs::manager::token_manager::TokenManager;
+use arbrs::pool::strategy::StandardV2Logic;
+use arbrs::pool::uniswap_v2::{UniswapV2Pool, UniswapV2PoolState};
+use arbrs::pool::{LiquidityPool, PoolSnapshot};
+use common::{ether, named_token, token};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+type DynProvider = dyn Provider + Send + Sync;
+
+const WETH: Address = address!("C02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2");
+const LENDER: Address = Address::repeat_byte(0x5e);
+
+fn reserves(reserve0: u64, reserve1: u64) -> PoolSnapshot {
+    PoolSnapshot::UniswapV2(UniswapV2PoolState {
+        reserve0: ether(reserve0),
+        reserve1: ether(reserve1),
+        block_number: 1,
+    })
+}
+
+/// Evaluates a two-pool cycle that stays profitable up to roughly 105 of the profit token,
+/// with `liquidity` of it held by the flashloan source.
+async fn evaluate(liquidity: U256) -> (Vec<ArbitrageSolution<DynProvider>>, usize) {
+    let provider: Arc<DynProvider> =
+        Arc::new(ProviderBuilder::new().connect_mocked_client(Asserter::new()));
+    // Tokens get their own mock, answering only the lender's balance.
+    let balances = Asserter::new();
+    balances.push_success(&Bytes::from(liquidity.to_be_bytes::<32>()));
+    let token_provider: Arc<DynProvider> =
+        Arc::new(ProviderBuilder::new().connect_mocked_client(balances.clone()));
+    let (profit_token, other) = (
+        token(WETH, token_provider.clone()),
+        token(Address::repeat_byte(0xee), token_provider),
+    );
+    let pools: Vec<Arc<dyn LiquidityPool<DynProvider>>> = [0x01, 0x02]
+        .into_iter()
+        .map(|byte| {
+            Arc::new(UniswapV2Pool::new(
+                Address::repeat_byte(byte),
+                profit_token.clone(),
+                other.clone(),
+                provider.clone(),
+                StandardV2Logic,
+            )) as Arc<dyn LiquidityPool<DynProvider>>
+        })
+        .collect();
+
+    let cache = Arc::new(ArbitrageCache::new());
+    cache
+        .add_path(Arc::new(ArbitrageCycle::new(ArbitragePath {
+            pools,
+            path: vec![profit_token.clone(), other, profit_token.clone()],
+            profit_token,
+        })))
+        .await;
+    let engine = ArbitrageEngine::new(
+        cache,
+        Arc::new(TokenManager::in_memory(provider.clone(), 1)),
+        provider,
+    )
+    .with_config(EngineConfig {
+        min_net_profit_wei: U256::ZERO,
+        flashloan_sources: HashMap::from([(WETH, LENDER)]),
+        max_input_wei: ether(1_000),
+        ..Default::default()
+    });
+
+    let overrides = HashMap::from([
+        (Address::repeat_byte(0x01), reserves(1_200, 2_880_000)),
+        (Address::repeat_byte(0x02), reserves(1_200, 2_400_000)),
+    ]);
+    let solutions = engine
+        .find_opportunities_with_overrides(Some(1), overrides)
+        .await;
+    assert!(balances.read_q().is_empty());
+    (solutions, engine.last_stats().liquidity_skips)
+}
+
+#[tokio::test]
+async fn test_input_capped_by_flashloan_liquidity() {
+    let (solutions, _) = evaluate(ether(10)).await;
+    assert_eq!(solutions.len(), 1);
+    assert_eq!(solutions[0].optimal_input.raw, ether(10));
+    assert_eq!(solutions[0].bound_by, InputBound::Liquidity);
+    assert_eq!(solutions[0].swap_actions[0].amount_in, ether(10));
+}
+
+#[tokio::test]
+async fn test_ample_liquidity_is_bound_by_pool_depth() {
+    let (solutions, _) = evaluate(ether(10_000)).await;
+    assert_eq!(solutions.len(), 1);
+    let input = solutions[0].optimal_input.raw;
+    assert!(input > ether(100) && input < ether(110), "input {input}");
+    assert_eq!(solutions[0].bound_by, InputBound::PoolDepth);
+}
+
+#[tokio::test]
+async fn test_dust_liquidity_is_skipped() {
+    let (solutions, liquidity_skips) = evaluate(U256::from(10).pow(U256::from(14))).await;
+    assert!(solutions.is_empty());
+    assert_eq!(liquidity_skips, 1);
+}
+
+#[tokio::test]
+async fn test_input_cap_is_converted_to_the_profit_token() {
+    let provider: Arc<DynProvider> =
+        Arc::new(ProviderBuilder::new().connect_mocked_client(Asserter::new()));
+    let weth = token(WETH, provider.clone());
+    let usdc = named_token(Address::repeat_byte(0xcc), "TKN", 6, provider.clone());
+    let pool = |byte: u8| -> Arc<dyn LiquidityPool<DynProvider>> {
+        Arc::new(UniswapV2Pool::new(
+            Address::repeat_byte(byte),
+            weth.clone(),
+            usdc.clone(),
+            provider.clone(),
+            StandardV2Logic,
+        ))
+    };
+
+    let cache = Arc::new(ArbitrageCache::new());
+    cache
+        .add_path(Arc::new(ArbitrageCycle::new(ArbitragePath {
+            pools: vec![pool(0x03), pool(0x04)],
+            path: vec![usdc.clone(), weth.clone(), usdc.clone()],
+            profit_token: usdc.clone(),
+        })))
+        .await;
+    let engine = ArbitrageEngine::new(
+        cache,
+        Arc::new(TokenManager::in_memory(provider.clone(), 1)),
+        provider.clone(),
+    )
+    .with_config(EngineConfig {
+        min_net_profit_wei: U256::ZERO,
+        gas_scenarios: vec![GasScenario::new(
+            "base",
+            ScenarioGasPrice::Fixed(U256::ZERO),
+        )],
+        flashloan_sources: HashMap::new(),
+        max_input_wei: ether(1),
+        ..Default::default()
+    });
+
+    // A 5% gap between two pools pricing ether at about 2000 USDC, deep enough that the
+    // one ether cap binds.
+    let usdc_reserves = |weth_reserve: u64, usdc_reserve: u64| {
+        PoolSnapshot::UniswapV2(UniswapV2PoolState {
+            reserve0: ether(weth_reserve),
+            reserve1: U256::from(usdc_reserve) * U256::from(1_000_000),
+            block_number: 1,
+        })
+    };
+    let overrides = HashMap::from([
+        (Address::repeat_byte(0x03), usdc_reserves(1_000, 2_000_000)),
+        (Address::repeat_byte(0x04), usdc_reserves(1_000, 2_100_000)),
+    ]);
+    let solutions = engine
+        .find_opportunities_with_overrides(Some(1), overrides)
+        .await;
+    assert_eq!(solutions.len(), 1);
+    let input = solutions[0].optimal_input.raw;
+    let usdc_units = |amount: u64| U256::from(amount) * U256::from(1_000_000);
+    assert!(
+        input > usdc_units(1_900) && input < usdc_units(2_200),
+        "input {input}"
+    );
+    assert_eq!(solutions[0].bound_by, InputBound::ConfigCap);
+}
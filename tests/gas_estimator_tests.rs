@@ -0,0 +1,194 @@
+mod common;
+
+use alloy::consensus::Header as ConsensusHeader;
+use alloy::transports::mock::Asserter;
+use alloy_primitives::{Address, U256, address};
+use alloy_provider::{Provider, ProviderBuilder};
+use alloy_rpc_types::{Block, FeeHistory, Header};
+use arbrs::arbitrage::cache::ArbitrageCache;
+use arbrs::arbitrage::cycle::ArbitrageCycle;
+use arbrs::arbitrage::engine::{ArbitrageEngine, EngineConfig};
+use arbrs::arbitrage::gas::{Eip1559Estimator, FixedGas, GasEstimator, LegacyGasPrice};
+use arbrs::arbitrage::optimizer::GAS_OVERHEAD_UNITS;
+use arbrs::arbitrage::types::ArbitragePath;
+use arbrs::manager::token_manager::TokenManager;
+use arbrs::pool::strategy::StandardV2Logic;
+use arbrs::pool::uniswap_v2::{UniswapV2Pool, UniswapV2PoolState};
+use arbrs::pool::{LiquidityPool, PoolSnapshot, SwapGasCosts};
+use common::token;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+type DynProvider = dyn Provider + Send + Sync;
+
+const WETH: Address = address!("C02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2");
+const GWEI: u64 = 1_000_000_000;
+
+fn mocked(asserter: &Asserter) -> Arc<DynProvider> {
+    Arc::new(ProviderBuilder::new().connect_mocked_client(asserter.clone()))
+}
+
+fn gwei(amount: u64) -> U256 {
+    U256::from(amount * GWEI)
+}
+
+fn header(number: u64, base_fee_gwei: u64) -> Header {
+    Header::new(ConsensusHeader {
+        number,
+        base_fee_per_gas: Some(base_fee_gwei * GWEI),
+        ..Default::default()
+    })
+}
+
+fn push_block(asserter: &Asserter, number: u64, base_fee_gwei: u64) {
+    let block: Block = Block {
+        header: header(number, base_fee_gwei),
+        ..Default::default()
+    };
+    asserter.push_success(&block);
+}
+
+/// A fee history whose blocks paid these priority fees at the requested percentile.
+fn push_fee_history(asserter: &Asserter, rewards_gwei: &[u64]) {
+    asserter.push_success(&FeeHistory {
+        reward: Some(
+            rewards_gwei
+                .iter()
+                .map(|reward| vec![(reward * GWEI) as u128])
+                .collect(),
+        ),
+        ..Default::default()
+    });
+}
+
+#[tokio::test]
+async fn test_eip1559_price_is_base_fee_plus_scaled_priority_fee() {
+    let asserter = Asserter::new();
+    let estimator = Eip1559Estimator::new(mocked(&asserter))
+        .with_fee_history_blocks(2)
+        .with_priority_fee_percentile(90.0)
+        .with_priority_fee_multiplier(15_000);
+
+    // The header's base fee is used as is; only the fee history is read.
+    push_fee_history(&asserter, &[1, 3]);
+    let price = estimator
+        .gas_price(Some(7), Some(&header(7, 30)))
+        .await
+        .unwrap();
+    assert!(asserter.read_q().is_empty());
+    assert_eq!(price, gwei(30) + gwei(3));
+
+    // Without a header the block is fetched first.
+    push_block(&asserter, 8, 40);
+    push_fee_history(&asserter, &[2]);
+    assert_eq!(
+        estimator.gas_price(Some(8), None).await.unwrap(),
+        gwei(40) + gwei(3)
+    );
+    assert!(asserter.read_q().is_empty());
+}
+
+#[tokio::test]
+async fn test_eip1559_price_without_base_fee_is_an_error() {
+    let asserter = Asserter::new();
+    let estimator = Eip1559Estimator::new(mocked(&asserter));
+    let mut legacy_header = header(5, 0);
+    legacy_header.inner.base_fee_per_gas = None;
+    assert!(
+        estimator
+            .gas_price(Some(5), Some(&legacy_header))
+            .await
+            .is_err()
+    );
+}
+
+#[tokio::test]
+async fn test_legacy_and_fixed_gas_prices() {
+    let asserter = Asserter::new();
+    asserter.push_success(&U256::from(25 * GWEI));
+    let legacy = LegacyGasPrice::new(mocked(&asserter));
+    assert_eq!(legacy.gas_price(Some(1), None).await.unwrap(), gwei(25));
+
+    let fixed = FixedGas::new(gwei(20)).with_block_prices([(2, gwei(35))]);
+    assert_eq!(fixed.gas_price(Some(1), None).await.unwrap(), gwei(20));
+    assert_eq!(fixed.gas_price(Some(2), None).await.unwrap(), gwei(35));
+    assert_eq!(
+        fixed.gas_price(None, Some(&header(2, 10))).await.unwrap(),
+        gwei(35)
+    );
+    assert_eq!(fixed.gas_price(None, None).await.unwrap(), gwei(20));
+}
+
+fn reserves(reserve0: u64, reserve1: u64) -> PoolSnapshot {
+    let ether = U256::from(10).pow(U256::from(18));
+    PoolSnapshot::UniswapV2(UniswapV2PoolState {
+        reserve0: U256::from(reserve0) * ether,
+        reserve1: U256::from(reserve1) * ether,
+        block_number: 1,
+    })
+}
+
+#[tokio::test]
+async fn test_engine_gas_cost_tracks_the_base_fee() {
+    let provider = mocked(&Asserter::new());
+    let (weth, other) = (
+        token(WETH, provider.clone()),
+        token(Address::repeat_byte(0xee), provider.clone()),
+    );
+    let pools: Vec<Arc<dyn LiquidityPool<DynProvider>>> = [0x01, 0x02]
+        .into_iter()
+        .map(|byte| {
+            Arc::new(UniswapV2Pool::new(
+                Address::repeat_byte(byte),
+                weth.clone(),
+                other.clone(),
+                provider.clone(),
+                StandardV2Logic,
+            )) as Arc<dyn LiquidityPool<DynProvider>>
+        })
+        .collect();
+    let cache = Arc::new(ArbitrageCache::new());
+    cache
+        .add_path(Arc::new(ArbitrageCycle::new(ArbitragePath {
+            pools,
+            path: vec![weth.clone(), other, weth.clone()],
+            profit_token: weth,
+        })))
+        .await;
+
+    let gas_asserter = Asserter::new();
+    let engine = ArbitrageEngine::new(
+        cache,
+        Arc::new(TokenManager::in_memory(provider.clone(), 1)),
+        provider,
+    )
+    .with_config(EngineConfig {
+        min_net_profit_wei: U256::ZERO,
+        flashloan_sources: HashMap::new(),
+        ..Default::default()
+    })
+    .with_gas_estimator(Box::new(
+        Eip1559Estimator::new(mocked(&gas_asserter)).with_fee_history_blocks(1),
+    ));
+    let overrides = HashMap::from([
+        (Address::repeat_byte(0x01), reserves(1_000, 2_400_000)),
+        (Address::repeat_byte(0x02), reserves(1_000, 2_000_000)),
+    ]);
+    let gas_units = U256::from(2 * SwapGasCosts::default().uniswap_v2 + GAS_OVERHEAD_UNITS);
+
+    let mut gas_costs = Vec::new();
+    for (block, base_fee) in [(1, 10), (2, 50)] {
+        push_block(&gas_asserter, block, base_fee);
+        push_fee_history(&gas_asserter, &[2]);
+        let solutions = engine
+            .find_opportunities_with_overrides(Some(block), overrides.clone())
+            .await;
+        assert_eq!(solutions.len(), 1);
+        gas_costs.push(solutions[0].gas_cost);
+    }
+    assert!(gas_asserter.read_q().is_empty());
+    assert_eq!(
+        gas_costs,
+        [gas_units * gwei(10 + 2), gas_units * gwei(50 + 2)]
+    );
+}
@@ -0,0 +1,221 @@
+mod common;
+
+use alloy::transports::mock::Asserter;
+use alloy_primitives::{Address, U256, address};
+use alloy_provider::{Provider, ProviderBuilder};
+use arbrs::arbitrage::cache::ArbitrageCache;
+use arbrs::arbitrage::cycle::ArbitrageCycle;
+use arbrs::arbitrage::engine::{ArbitrageEngine, EngineConfig, GasScenario, ScenarioGasPrice};
+use arbrs::arbitrage::optimizer::GAS_OVERHEAD_UNITS;
+use arbrs::arbitrage::types::{ArbitragePath, ArbitrageSolution};
+use arbrs::manager::token_manager::TokenManager;
+use arbrs::pool::strategy::StandardV2Logic;
+use arbrs::pool::uniswap_v2::{UniswapV2Pool, UniswapV2PoolState};
+use arbrs::pool::{LiquidityPool, PoolSnapshot, SwapGasCosts};
+use common::token;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+type DynProvider = dyn Provider + Send + Sync;
+
+const WETH: Address = address!("C02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2");
+
+fn reserves(reserve0: u64, reserve1: u64) -> PoolSnapshot {
+    let ether = U256::from(10).pow(U256::from(18));
+    PoolSnapshot::UniswapV2(UniswapV2PoolState {
+        reserve0: U256::from(reserve0) * ether,
+        reserve1: U256::from(reserve1) * ether,
+        block_number: 1,
+    })
+}
+
+/// Gas charged to a cycle through `hops` V2 pools.
+fn v2_cycle_gas_units(hops: u64) -> U256 {
+    U256::from(hops * SwapGasCosts::default().uniswap_v2 + GAS_OVERHEAD_UNITS)
+}
+
+/// Evaluates a two-pool cycle at fixed gas prices. With no minimum net profit the input
+/// search doesn't depend on gas, so every run trades the same amount.
+async fn evaluate(gas_prices: &[(&str, U256)]) -> ArbitrageSolution<DynProvider> {
+    let provider: Arc<DynProvider> =
+        Arc::new(ProviderBuilder::new().connect_mocked_client(Asserter::new()));
+    let (profit_token, other) = (
+        token(WETH, provider.clone()),
+        token(Address::repeat_byte(0xee), provider.clone()),
+    );
+    let pools: Vec<Arc<dyn LiquidityPool<DynProvider>>> = [0x01, 0x02]
+        .into_iter()
+        .map(|byte| {
+            Arc::new(UniswapV2Pool::new(
+                Address::repeat_byte(byte),
+                profit_token.clone(),
+                other.clone(),
+                provider.clone(),
+                StandardV2Logic,
+            )) as Arc<dyn LiquidityPool<DynProvider>>
+        })
+        .collect();
+
+    let cache = Arc::new(ArbitrageCache::new());
+    cache
+        .add_path(Arc::new(ArbitrageCycle::new(ArbitragePath {
+            pools,
+            path: vec![profit_token.clone(), other, profit_token.clone()],
+            profit_token,
+        })))
+        .await;
+    let engine = ArbitrageEngine::new(
+        cache,
+        Arc::new(TokenManager::in_memory(provider.clone(), 1)),
+        provider,
+    )
+    .with_config(EngineConfig {
+        min_net_profit_wei: U256::ZERO,
+        gas_scenarios: gas_prices
+            .iter()
+            .map(|(label, gas_price)| GasScenario::new(*label, ScenarioGasPrice::Fixed(*gas_price)))
+            .collect(),
+        ..Default::default()
+    });
+
+    let overrides = HashMap::from([
+        (Address::repeat_byte(0x01), reserves(1_000, 2_400_000)),
+        (Address::repeat_byte(0x02), reserves(1_000, 2_000_000)),
+    ]);
+    let mut solutions = engine
+        .find_opportunities_with_overrides(Some(1), overrides)
+        .await;
+    assert_eq!(solutions.len(), 1);
+    solutions.remove(0)
+}
+
+#[tokio::test]
+async fn test_gas_scenarios_costed_from_one_optimization() {
+    let free = evaluate(&[("base", U256::ZERO)]).await;
+    let margin = free.gross_profit.raw - free.flashloan_fee;
+
+    // A base gas price at which the margin is 1.5x the gas cost, rounded down to a
+    // multiple of 4 so the +25% price is exact.
+    let four = U256::from(4);
+    let base_price = margin * U256::from(2) / (U256::from(3) * v2_cycle_gas_units(2)) / four * four;
+    let prices = [
+        ("base", base_price),
+        ("+25%", base_price * U256::from(5) / four),
+        ("+100%", base_price * U256::from(2)),
+    ];
+    let solution = evaluate(&prices).await;
+    assert_eq!(solution.optimal_input.raw, free.optimal_input.raw);
+    assert_eq!(solution.gross_profit.raw, free.gross_profit.raw);
+
+    let base_gas = v2_cycle_gas_units(2) * base_price;
+    assert!(margin >= base_gas * U256::from(3) / U256::from(2));
+    assert!(margin < base_gas * U256::from(2));
+
+    let results: Vec<_> = solution
+        .scenario_results
+        .iter()
+        .map(|result| {
+            (
+                result.label.as_str(),
+                result.gas_cost,
+                result.net_profit,
+                result.passes,
+            )
+        })
+        .collect();
+    assert_eq!(
+        results,
+        [
+            ("base", base_gas, margin - base_gas, true),
+            (
+                "+25%",
+                base_gas * U256::from(5) / four,
+                margin - base_gas * U256::from(5) / four,
+                true
+            ),
+            ("+100%", base_gas * U256::from(2), U256::ZERO, false),
+        ]
+    );
+    // The base scenario is the one reported and ranked on.
+    assert_eq!(solution.net_profit.raw, margin - base_gas);
+    assert_eq!(solution.gas_cost, base_gas);
+}
+
+#[test]
+fn test_live_scaled_gas_price() {
+    let live = U256::from(20_000_000_000u64);
+    let spike = GasScenario::new("+25%", ScenarioGasPrice::LiveScaled { bps: 12_500 });
+    assert_eq!(spike.resolve(live), U256::from(25_000_000_000u64));
+    assert_eq!(
+        GasScenario::new("base", ScenarioGasPrice::Live).resolve(live),
+        live
+    );
+    assert_eq!(EngineConfig::default().gas_scenarios.len(), 1);
+}
+
+/// Gas cost, at 1 gwei, of the solution for a WETH cycle through `hops` V2 pools: WETH is
+/// cheap in the first pool and dear in the last, with deep 1:1 pools in between.
+async fn cycle_gas_cost(hops: u8) -> U256 {
+    let provider: Arc<DynProvider> =
+        Arc::new(ProviderBuilder::new().connect_mocked_client(Asserter::new()));
+    let weth = token(WETH, provider.clone());
+    let mut path = vec![weth.clone()];
+    path.extend((1..hops).map(|byte| token(Address::repeat_byte(0xe0 + byte), provider.clone())));
+    path.push(weth.clone());
+
+    let mut pools: Vec<Arc<dyn LiquidityPool<DynProvider>>> = Vec::new();
+    let mut overrides = HashMap::new();
+    for hop in 0..hops as usize {
+        let address = Address::repeat_byte(hop as u8 + 1);
+        pools.push(Arc::new(UniswapV2Pool::new(
+            address,
+            path[hop].clone(),
+            path[hop + 1].clone(),
+            provider.clone(),
+            StandardV2Logic,
+        )));
+        let snapshot = match hop {
+            0 => reserves(1_000, 2_400_000),
+            _ if hop + 1 == hops as usize => reserves(2_000_000, 1_000),
+            _ => reserves(1_000_000_000, 1_000_000_000),
+        };
+        overrides.insert(address, snapshot);
+    }
+
+    let cache = Arc::new(ArbitrageCache::new());
+    cache
+        .add_path(Arc::new(ArbitrageCycle::new(ArbitragePath {
+            pools,
+            path,
+            profit_token: weth,
+        })))
+        .await;
+    let engine = ArbitrageEngine::new(
+        cache,
+        Arc::new(TokenManager::in_memory(provider.clone(), 1)),
+        provider,
+    )
+    .with_config(EngineConfig {
+        min_net_profit_wei: U256::ZERO,
+        gas_scenarios: vec![GasScenario::new(
+            "base",
+            ScenarioGasPrice::Fixed(U256::from(1_000_000_000u64)),
+        )],
+        ..Default::default()
+    });
+    let solutions = engine
+        .find_opportunities_with_overrides(Some(1), overrides)
+        .await;
+    assert_eq!(solutions.len(), 1);
+    solutions[0].gas_cost
+}
+
+#[tokio::test]
+async fn test_gas_is_summed_over_the_hops() {
+    let gwei = U256::from(1_000_000_000u64);
+    let two_hops = cycle_gas_cost(2).await;
+    let five_hops = cycle_gas_cost(5).await;
+    assert_eq!(two_hops, v2_cycle_gas_units(2) * gwei);
+    assert_eq!(five_hops, v2_cycle_gas_units(5) * gwei);
+    assert!(two_hops < five_hops);
+}
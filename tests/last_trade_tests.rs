@@ -0,0 +1,283 @@
+mod common;
+
+use alloy_primitives::{Address, I256, LogData, U256, address};
+use alloy_provider::{Provider, ProviderBuilder};
+use alloy_rpc_types::Log;
+use alloy_sol_types::SolEvent;
+use arbrs::arbitrage::cycle::ArbitrageCycle;
+use arbrs::arbitrage::types::ArbitragePath;
+use arbrs::balancer::pool::BalancerPool;
+use arbrs::pool::last_trade::{
+    ICurveCryptoPool, ICurvePool, IUniswapV2Pair, IUniswapV3Pool, IVault, LastTrade,
+    LastTradeTracker, divergence_bps, route_swap_log,
+};
+use arbrs::pool::strategy::StandardV2Logic;
+use arbrs::pool::uniswap_v2::{UniswapV2Pool, UniswapV2PoolState};
+use arbrs::pool::{LiquidityPool, PoolSnapshot};
+use common::token;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+// The provider is lazy and never called.
+const FORK_RPC_URL: &str = "http://127.0.0.1:8545";
+const VAULT: Address = address!("BA12222222228d8Ba445958a75a0704d566BF2C8");
+const BALANCER_POOL: Address = address!("5c6Ee304399DBdB9C8Ef030aB642B10820DB8F56");
+type DynProvider = dyn Provider + Send + Sync;
+
+const TOKEN_A: Address = Address::repeat_byte(0x0a);
+const TOKEN_B: Address = Address::repeat_byte(0x0b);
+const TOKEN_C: Address = Address::repeat_byte(0x0c);
+
+fn provider() -> Arc<DynProvider> {
+    Arc::new(ProviderBuilder::new().connect_http(FORK_RPC_URL.parse().unwrap()))
+}
+
+fn log(emitter: Address, block: u64, data: LogData) -> Log {
+    Log {
+        inner: alloy_primitives::Log {
+            address: emitter,
+            data,
+        },
+        block_number: Some(block),
+        ..Default::default()
+    }
+}
+
+fn v2_swap(pool: Address, amount0_in: u64, amount1_out: u64) -> Log {
+    let event = IUniswapV2Pair::Swap {
+        sender: Address::ZERO,
+        amount0In: U256::from(amount0_in),
+        amount1In: U256::ZERO,
+        amount0Out: U256::ZERO,
+        amount1Out: U256::from(amount1_out),
+        to: Address::ZERO,
+    };
+    log(pool, 7, event.encode_log_data())
+}
+
+fn v2_pool(address: Address, provider: Arc<DynProvider>) -> Arc<dyn LiquidityPool<DynProvider>> {
+    Arc::new(UniswapV2Pool::new(
+        address,
+        token(TOKEN_A, provider.clone()),
+        token(TOKEN_B, provider.clone()),
+        provider,
+        StandardV2Logic,
+    ))
+}
+
+/// Quotes exactly 997_000_000 of token B for 1_000 of token A:
+/// 1_000 * 997 * 1e12 / (999_003 * 1_000 + 1_000 * 997) = 997e15 / 1e9.
+fn v2_snapshot() -> PoolSnapshot {
+    PoolSnapshot::UniswapV2(UniswapV2PoolState {
+        reserve0: U256::from(999_003u64),
+        reserve1: U256::from(1_000_000_000_000u64),
+        block_number: 7,
+    })
+}
+
+#[test]
+fn test_v2_swap_log_implied_price() {
+    let pool = v2_pool(Address::repeat_byte(0x01), provider());
+    assert!(pool.last_trade().is_none());
+
+    assert!(pool.record_swap_log(&v2_swap(pool.address(), 1_000, 1_046_850_000)));
+    let trade = pool.last_trade().unwrap();
+    assert_eq!(trade.block, 7);
+    assert_eq!((trade.token_in, trade.token_out), (TOKEN_A, TOKEN_B));
+    assert_eq!(trade.amount_in, U256::from(1_000));
+    assert_eq!(trade.amount_out, U256::from(1_046_850_000u64));
+    assert_eq!(trade.implied_price, 1_046_850.0);
+
+    // 49_850_000 * 10_000 / 997_000_000
+    assert_eq!(
+        pool.spot_vs_last_trade_divergence_bps(&v2_snapshot()),
+        Some(500)
+    );
+
+    // Logs from another pool are ignored.
+    assert!(!pool.record_swap_log(&v2_swap(Address::repeat_byte(0x02), 1, 1)));
+}
+
+#[test]
+fn test_v3_swap_log_direction() {
+    let event = IUniswapV3Pool::Swap {
+        sender: Address::ZERO,
+        recipient: Address::ZERO,
+        amount0: I256::try_from(-3_000_000i64).unwrap(),
+        amount1: I256::try_from(2_000_000_000_000_000_000u128).unwrap(),
+        sqrtPriceX96: Default::default(),
+        liquidity: 0,
+        tick: Default::default(),
+    };
+    let trade = LastTrade::from_v3_swap(
+        &log(Address::repeat_byte(0x03), 9, event.encode_log_data()),
+        TOKEN_A,
+        TOKEN_B,
+    )
+    .unwrap();
+
+    assert_eq!((trade.token_in, trade.token_out), (TOKEN_B, TOKEN_A));
+    assert_eq!(trade.amount_in, U256::from(2_000_000_000_000_000_000u128));
+    assert_eq!(trade.amount_out, U256::from(3_000_000));
+    assert_eq!(trade.implied_price, 1.5e-12);
+
+    // A V2 event does not decode as V3.
+    assert!(LastTrade::from_v3_swap(&v2_swap(Address::ZERO, 1, 1), TOKEN_A, TOKEN_B).is_none());
+}
+
+#[test]
+fn test_curve_exchange_logs() {
+    let coins = [TOKEN_A, TOKEN_B, TOKEN_C];
+    let stable = ICurvePool::TokenExchange {
+        buyer: Address::ZERO,
+        sold_id: 1,
+        tokens_sold: U256::from(100_000_000u64),
+        bought_id: 2,
+        tokens_bought: U256::from(99_950_000u64),
+    };
+    let trade =
+        LastTrade::from_curve_exchange(&log(Address::ZERO, 1, stable.encode_log_data()), &coins)
+            .unwrap();
+    assert_eq!((trade.token_in, trade.token_out), (TOKEN_B, TOKEN_C));
+    assert_eq!(trade.implied_price, 0.9995);
+
+    let crypto = ICurveCryptoPool::TokenExchange {
+        buyer: Address::ZERO,
+        sold_id: U256::from(2),
+        tokens_sold: U256::from(4),
+        bought_id: U256::ZERO,
+        tokens_bought: U256::from(10),
+    };
+    let trade =
+        LastTrade::from_curve_exchange(&log(Address::ZERO, 1, crypto.encode_log_data()), &coins)
+            .unwrap();
+    assert_eq!((trade.token_in, trade.token_out), (TOKEN_C, TOKEN_A));
+    assert_eq!(trade.implied_price, 2.5);
+
+    // Indices outside the pool's coins are rejected.
+    let out_of_range = ICurvePool::TokenExchange {
+        sold_id: 3,
+        ..stable
+    };
+    assert!(
+        LastTrade::from_curve_exchange(
+            &log(Address::ZERO, 1, out_of_range.encode_log_data()),
+            &coins
+        )
+        .is_none()
+    );
+}
+
+#[test]
+fn test_balancer_swaps_are_routed_by_pool_id() {
+    let provider = provider();
+    let half = U256::from(500_000_000_000_000_000u64);
+    let mut pool_id = [0u8; 32];
+    pool_id[..20].copy_from_slice(BALANCER_POOL.as_slice());
+    pool_id[31] = 0x12;
+    let pool: Arc<dyn LiquidityPool<DynProvider>> = Arc::new(BalancerPool::from_parts(
+        BALANCER_POOL,
+        provider.clone(),
+        vec![token(TOKEN_A, provider.clone()), token(TOKEN_B, provider)],
+        vec![half, half],
+        U256::from(3_000_000_000_000_000u64),
+        VAULT,
+        pool_id,
+    ));
+    let pools = HashMap::from([(BALANCER_POOL, pool.clone())]);
+
+    let swap = |pool_id: [u8; 32]| IVault::Swap {
+        poolId: pool_id.into(),
+        tokenIn: TOKEN_B,
+        tokenOut: TOKEN_A,
+        amountIn: U256::from(400),
+        amountOut: U256::from(100),
+    };
+    assert!(route_swap_log(
+        &pools,
+        &log(VAULT, 5, swap(pool_id).encode_log_data())
+    ));
+    let trade = pool.last_trade().unwrap();
+    assert_eq!((trade.token_in, trade.token_out), (TOKEN_B, TOKEN_A));
+    assert_eq!(trade.implied_price, 0.25);
+
+    // Same pool address, different pool id.
+    let mut other_id = pool_id;
+    other_id[31] = 0x13;
+    assert!(!route_swap_log(
+        &pools,
+        &log(VAULT, 6, swap(other_id).encode_log_data())
+    ));
+    // Not emitted by the vault.
+    assert!(!route_swap_log(
+        &pools,
+        &log(BALANCER_POOL, 6, swap(pool_id).encode_log_data())
+    ));
+    assert_eq!(pool.last_trade().unwrap().block, 5);
+}
+
+#[test]
+fn test_tracker_keeps_most_recent_trades() {
+    let tracker = LastTradeTracker::new(3);
+    for block in 1..=5 {
+        tracker.record(LastTrade::new(
+            block,
+            TOKEN_A,
+            TOKEN_B,
+            U256::from(1),
+            U256::from(1),
+        ));
+    }
+    let blocks: Vec<u64> = tracker.recent().iter().map(|trade| trade.block).collect();
+    assert_eq!(blocks, [3, 4, 5]);
+    assert_eq!(tracker.last().unwrap().block, 5);
+}
+
+#[test]
+fn test_divergence_bps() {
+    let quoted = U256::from(997_000_000u64);
+    assert_eq!(divergence_bps(quoted, U256::from(1_046_850_000u64)), 500);
+    assert_eq!(divergence_bps(quoted, U256::from(947_150_000u64)), 500);
+    assert_eq!(divergence_bps(quoted, U256::from(997_498_500u64)), 5);
+    assert_eq!(divergence_bps(quoted, quoted), 0);
+}
+
+#[test]
+fn test_divergent_hops_are_flagged() {
+    let provider = provider();
+    let diverged = v2_pool(Address::repeat_byte(0x01), provider.clone());
+    let close = v2_pool(Address::repeat_byte(0x02), provider.clone());
+    let untraded = v2_pool(Address::repeat_byte(0x04), provider.clone());
+    // 500 bps and 5 bps away from the 997_000_000 quote.
+    diverged.record_swap_log(&v2_swap(diverged.address(), 1_000, 1_046_850_000));
+    close.record_swap_log(&v2_swap(close.address(), 1_000, 997_498_500));
+
+    let (a, b) = (token(TOKEN_A, provider.clone()), token(TOKEN_B, provider));
+    let cycle = ArbitrageCycle::new(ArbitragePath {
+        pools: vec![diverged.clone(), close.clone(), untraded.clone()],
+        path: vec![a.clone(), b.clone(), a.clone(), b],
+        profit_token: a,
+    });
+    let snapshots: HashMap<Address, PoolSnapshot> = [&diverged, &close, &untraded]
+        .iter()
+        .map(|pool| (pool.address(), v2_snapshot()))
+        .collect();
+
+    assert_eq!(
+        close.spot_vs_last_trade_divergence_bps(&v2_snapshot()),
+        Some(5)
+    );
+    assert_eq!(
+        untraded.spot_vs_last_trade_divergence_bps(&v2_snapshot()),
+        None
+    );
+    assert_eq!(
+        cycle.divergent_pools(&snapshots, 100),
+        vec![diverged.address()]
+    );
+    assert_eq!(
+        cycle.divergent_pools(&snapshots, 4),
+        vec![diverged.address(), close.address()]
+    );
+    assert!(cycle.divergent_pools(&snapshots, 500).is_empty());
+}
@@ -0,0 +1,223 @@
+use alloy::transports::mock::Asserter;
+use alloy_primitives::Address;
+use alloy_provider::{Provider, ProviderBuilder};
+use alloy_rpc_types::{Filter, Log};
+use arbrs::ArbRsError;
+use arbrs::manager::log_scan::{ChunkedLogScan, LogScanConfig, chunked_log_scan};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+
+type DynProvider = dyn Provider + Send + Sync;
+
+fn config(chunk_size: u64, max_retries: u32) -> LogScanConfig {
+    LogScanConfig {
+        max_retries,
+        retry_backoff: Duration::ZERO,
+        ..LogScanConfig::default().with_chunk_size(chunk_size)
+    }
+}
+
+fn mocked() -> (Asserter, Arc<DynProvider>) {
+    let asserter = Asserter::new();
+    let provider: Arc<DynProvider> =
+        Arc::new(ProviderBuilder::new().connect_mocked_client(asserter.clone()));
+    (asserter, provider)
+}
+
+async fn ranges(scan: &mut ChunkedLogScan, provider: &DynProvider) -> Vec<(u64, u64)> {
+    let mut ranges = Vec::new();
+    while let Some(chunk) = scan.next_chunk(provider).await.unwrap() {
+        ranges.push((chunk.from_block, chunk.to_block));
+    }
+    ranges
+}
+
+#[tokio::test]
+async fn test_chunk_is_halved_on_too_many_results_and_grows_back() {
+    let (asserter, provider) = mocked();
+    let mut scan = chunked_log_scan(Filter::new(), 1, 250, config(100, 0));
+
+    asserter.push_failure_msg("query returned more than 10000 results");
+    asserter.push_failure_msg("query returned more than 10000 results");
+    for _ in 0..4 {
+        asserter.push_success(&Vec::<Log>::new());
+    }
+
+    // 100 and 50 blocks are refused, 25 is read, and the size doubles back up to 100.
+    assert_eq!(
+        ranges(&mut scan, provider.as_ref()).await,
+        [(1, 25), (26, 75), (76, 175), (176, 250)]
+    );
+    assert!(scan.is_done());
+    assert_eq!(scan.chunk_size(), 100);
+}
+
+#[tokio::test]
+async fn test_other_errors_are_retried_then_returned() {
+    let (asserter, provider) = mocked();
+    let mut scan = chunked_log_scan(Filter::new(), 1, 10, config(100, 1));
+
+    asserter.push_failure_msg("rate limited");
+    asserter.push_success(&Vec::<Log>::new());
+    assert_eq!(ranges(&mut scan, provider.as_ref()).await, [(1, 10)]);
+
+    let mut scan = chunked_log_scan(Filter::new(), 1, 10, config(100, 1));
+    asserter.push_failure_msg("rate limited");
+    asserter.push_failure_msg("rate limited");
+    let result = scan.next_chunk(provider.as_ref()).await;
+    assert!(matches!(result, Err(ArbRsError::ProviderError(_))));
+    assert!(!scan.is_done());
+}
+
+#[tokio::test]
+async fn test_empty_range_scans_nothing() {
+    let (asserter, provider) = mocked();
+    let mut scan = chunked_log_scan(Filter::new(), 11, 10, LogScanConfig::default());
+    assert!(scan.next_chunk(provider.as_ref()).await.unwrap().is_none());
+    assert!(asserter.read_q().is_empty());
+}
+
+#[tokio::test]
+async fn test_cancelled_scan_makes_no_further_calls() {
+    let (asserter, provider) = mocked();
+    let cancel = CancellationToken::new();
+    let mut scan =
+        chunked_log_scan(Filter::new(), 1, 250, config(100, 0)).with_cancellation(cancel.clone());
+
+    asserter.push_success(&Vec::<Log>::new());
+    assert_eq!(
+        scan.next_chunk(provider.as_ref())
+            .await
+            .unwrap()
+            .unwrap()
+            .to_block,
+        100
+    );
+    cancel.cancel();
+    assert!(scan.next_chunk(provider.as_ref()).await.unwrap().is_none());
+    assert!(scan.is_cancelled());
+    assert!(!scan.is_done());
+    assert!(asserter.read_q().is_empty());
+}
+
+#[tokio::test]
+async fn test_cancellation_ends_a_retry_backoff() {
+    let (asserter, provider) = mocked();
+    let cancel = CancellationToken::new();
+    let mut scan = chunked_log_scan(
+        Filter::new(),
+        1,
+        10,
+        LogScanConfig {
+            retry_backoff: Duration::from_secs(3_600),
+            ..config(100, 1)
+        },
+    )
+    .with_cancellation(cancel.clone());
+
+    asserter.push_failure_msg("rate limited");
+    let (chunk, _) = tokio::join!(scan.next_chunk(provider.as_ref()), async {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        cancel.cancel();
+    });
+    assert!(chunk.unwrap().is_none());
+    assert!(scan.is_cancelled());
+}
+
+#[cfg(feature = "db")]
+#[tokio::test]
+async fn test_interrupted_discovery_resumes_from_the_stored_block() {
+    use arbrs::db::DbManager;
+    use arbrs::manager::token_manager::TokenManager;
+    use arbrs::manager::uniswap_v2_pool_manager::UniswapV2PoolManager;
+
+    const FACTORY: Address = Address::repeat_byte(0xfa);
+
+    let (asserter, provider) = mocked();
+    let db = Arc::new(DbManager::new("sqlite::memory:").await.unwrap());
+    let manager = || {
+        UniswapV2PoolManager::new(
+            Arc::new(TokenManager::in_memory(provider.clone(), 1)),
+            provider.clone(),
+            FACTORY,
+            0,
+        )
+        .with_db_manager(db.clone())
+        .with_log_scan(config(50, 0))
+    };
+
+    // The first chunk is read, then the node goes away.
+    let mut interrupted = manager();
+    asserter.push_success(&Vec::<Log>::new());
+    asserter.push_failure_msg("connection reset");
+    assert!(interrupted.discover_pools_in_range(200).await.is_err());
+    assert_eq!(interrupted.last_discovery_block, 50);
+
+    let mut resumed = manager();
+    assert_eq!(resumed.resume_discovery().await.unwrap(), 50);
+    for _ in 0..3 {
+        asserter.push_success(&Vec::<Log>::new());
+    }
+    assert!(
+        resumed
+            .discover_pools_in_range(200)
+            .await
+            .unwrap()
+            .is_empty()
+    );
+    assert_eq!(resumed.last_discovery_block, 200);
+    assert!(asserter.read_q().is_empty());
+    assert_eq!(manager().resume_discovery().await.unwrap(), 200);
+}
+
+#[cfg(feature = "db")]
+#[tokio::test]
+async fn test_cancelled_discovery_resumes_from_the_flushed_block() {
+    use arbrs::db::DbManager;
+    use arbrs::manager::token_manager::TokenManager;
+    use arbrs::manager::uniswap_v2_pool_manager::UniswapV2PoolManager;
+
+    const FACTORY: Address = Address::repeat_byte(0xfa);
+
+    let (asserter, provider) = mocked();
+    let db = Arc::new(DbManager::new("sqlite::memory:").await.unwrap());
+    let cancel = CancellationToken::new();
+    let manager = || {
+        UniswapV2PoolManager::new(
+            Arc::new(TokenManager::in_memory(provider.clone(), 1)),
+            provider.clone(),
+            FACTORY,
+            0,
+        )
+        .with_db_manager(db.clone())
+        .with_log_scan(LogScanConfig {
+            retry_backoff: Duration::from_secs(3_600),
+            ..config(50, 1)
+        })
+    };
+
+    // Shutdown is requested while the second chunk waits to be retried.
+    let mut interrupted = manager().with_cancellation(cancel.clone());
+    asserter.push_success(&Vec::<Log>::new());
+    asserter.push_failure_msg("rate limited");
+    let (discovered, _) = tokio::join!(interrupted.discover_pools_in_range(200), async {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        cancel.cancel();
+    });
+    assert!(discovered.unwrap().is_empty());
+    assert_eq!(interrupted.last_discovery_block, 50);
+    interrupted.shutdown().await.unwrap();
+
+    // A manager that never resumed doesn't move the checkpoint back.
+    manager().shutdown().await.unwrap();
+
+    let mut resumed = manager();
+    assert_eq!(resumed.resume_discovery().await.unwrap(), 50);
+    for _ in 0..3 {
+        asserter.push_success(&Vec::<Log>::new());
+    }
+    resumed.discover_pools_in_range(200).await.unwrap();
+    assert_eq!(resumed.last_discovery_block, 200);
+    assert!(asserter.read_q().is_empty());
+}
@@ -0,0 +1,477 @@
+mod common;
+
+use alloy_primitives::aliases::{U24, U160};
+use alloy_primitives::{Address, U256, address};
+use alloy_provider::{Provider, ProviderBuilder};
+use alloy_sol_types::SolCall;
+use arbrs::balancer::pool::{BalancerPool, BalancerPoolSnapshot};
+use arbrs::core::token::Token;
+use arbrs::curve::pool::CurveStableswapPool;
+use arbrs::curve::pool_attributes::{PoolAttributes, SwapStrategyType};
+use arbrs::curve::types::CurvePoolSnapshot;
+use arbrs::manager::token_manager::TokenManager;
+use arbrs::math::v3::{tick_math::get_tick_at_sqrt_ratio, utils::sqrt};
+use arbrs::mempool::decoder::{
+    DecoderRegistry, ExactInputSingleParams, FundManagement, SingleSwap, exactInputSingleCall,
+    exchangeCall, swapCall, swapExactTokensForTokensCall,
+};
+use arbrs::mempool::watcher::{MempoolConfig, MempoolWatcher};
+use arbrs::pool::strategy::StandardV2Logic;
+use arbrs::pool::uniswap_v2::{UniswapV2Pool, UniswapV2PoolState};
+use arbrs::pool::uniswap_v3::{UniswapV3Pool, UniswapV3PoolSnapshot};
+use arbrs::pool::{LiquidityPool, PoolSnapshot};
+use common::{attributes, named_token};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+const FORK_RPC_URL: &str = "http://127.0.0.1:8545";
+const WETH_ADDRESS: Address = address!("C02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2");
+const WBTC_ADDRESS: Address = address!("2260FAC5E5542a773Aa44fBCfeDf7C193bc2C599");
+const WBTC_WETH_V2_POOL: Address = address!("Bb2b8038a1640196FbE3e38816F3e67Cba72D940");
+const WBTC_WETH_V3_POOL: Address = address!("CBCdF9626bC03E24f779434178A73a0B4bad62eD");
+const UNISWAP_V2_ROUTER: Address = address!("7a250d5630B4cF539739dF2C5dAcb4c659F2488D");
+const UNISWAP_V3_ROUTER: Address = address!("E592427A0AEce92De3Edee1F18E0157C05861564");
+const BALANCER_VAULT: Address = address!("BA12222222228d8Ba445958a75a0704d566BF2C8");
+const CURVE_POOL: Address = Address::repeat_byte(0xc0);
+const BALANCER_POOL: Address = Address::repeat_byte(0xba);
+type DynProvider = dyn Provider + Send + Sync;
+
+struct Fixture {
+    provider: Arc<DynProvider>,
+    weth: Arc<Token<DynProvider>>,
+    wbtc: Arc<Token<DynProvider>>,
+    v2_pool: Arc<UniswapV2Pool<DynProvider, StandardV2Logic>>,
+    v3_pool: Arc<UniswapV3Pool<DynProvider>>,
+    watcher: MempoolWatcher<DynProvider>,
+    snapshots: HashMap<Address, PoolSnapshot>,
+}
+
+fn v2_swap_calldata(amount_in: U256) -> Vec<u8> {
+    swapExactTokensForTokensCall {
+        amountIn: amount_in,
+        amountOutMin: U256::ZERO,
+        path: vec![WETH_ADDRESS, WBTC_ADDRESS],
+        to: Address::ZERO,
+        deadline: U256::MAX,
+    }
+    .abi_encode()
+}
+
+// Everything here runs against in-memory snapshots; the provider is never called.
+fn setup() -> Fixture {
+    setup_with_config(MempoolConfig::default())
+}
+
+fn setup_with_config(config: MempoolConfig) -> Fixture {
+    let provider: Arc<DynProvider> =
+        Arc::new(ProviderBuilder::new().connect_http(FORK_RPC_URL.parse().unwrap()));
+    let weth = named_token(WETH_ADDRESS, "WETH", 18, provider.clone());
+    let wbtc = named_token(WBTC_ADDRESS, "WBTC", 8, provider.clone());
+
+    let v2_pool = Arc::new(UniswapV2Pool::new(
+        WBTC_WETH_V2_POOL,
+        wbtc.clone(),
+        weth.clone(),
+        provider.clone(),
+        StandardV2Logic,
+    ));
+    let v3_pool = Arc::new(UniswapV3Pool::new(
+        WBTC_WETH_V3_POOL,
+        wbtc.clone(),
+        weth.clone(),
+        3000,
+        60,
+        provider.clone(),
+        None,
+    ));
+
+    // 1 WBTC = 20 WETH
+    let sqrt_price_x96 = sqrt(U256::from(200_000_000_000u64) << 192);
+    let snapshots = HashMap::from([
+        (
+            WBTC_WETH_V2_POOL,
+            PoolSnapshot::UniswapV2(UniswapV2PoolState {
+                reserve0: U256::from(500) * U256::from(100_000_000u64),
+                reserve1: U256::from(10_000) * U256::from(10).pow(U256::from(18)),
+                block_number: 0,
+            }),
+        ),
+        (
+            WBTC_WETH_V3_POOL,
+            PoolSnapshot::UniswapV3(UniswapV3PoolSnapshot {
+                sqrt_price_x96,
+                tick: get_tick_at_sqrt_ratio(sqrt_price_x96).unwrap(),
+                liquidity: 1_000_000_000_000_000_000,
+                ..Default::default()
+            }),
+        ),
+    ]);
+
+    let pools: HashMap<Address, Arc<dyn LiquidityPool<DynProvider>>> = HashMap::from([
+        (
+            WBTC_WETH_V2_POOL,
+            v2_pool.clone() as Arc<dyn LiquidityPool<DynProvider>>,
+        ),
+        (
+            WBTC_WETH_V3_POOL,
+            v3_pool.clone() as Arc<dyn LiquidityPool<DynProvider>>,
+        ),
+    ]);
+    let watcher = MempoolWatcher::new(provider.clone(), pools, DecoderRegistry::mainnet(), config);
+
+    Fixture {
+        provider,
+        weth,
+        wbtc,
+        v2_pool,
+        v3_pool,
+        watcher,
+        snapshots,
+    }
+}
+
+#[tokio::test]
+async fn test_v2_router_swap_matches_simulation() {
+    let fixture = setup();
+    let amount_in = U256::from(10).pow(U256::from(18));
+    let calldata = swapExactTokensForTokensCall {
+        amountIn: amount_in,
+        amountOutMin: U256::ZERO,
+        path: vec![WETH_ADDRESS, WBTC_ADDRESS],
+        to: Address::ZERO,
+        deadline: U256::MAX,
+    }
+    .abi_encode();
+
+    let overrides = fixture
+        .watcher
+        .predict_overrides(UNISWAP_V2_ROUTER, &calldata, &fixture.snapshots)
+        .await
+        .unwrap();
+    assert_eq!(overrides.len(), 1);
+    assert_eq!(overrides[0].0, WBTC_WETH_V2_POOL);
+
+    let PoolSnapshot::UniswapV2(state) = &fixture.snapshots[&WBTC_WETH_V2_POOL] else {
+        panic!("Wrong snapshot type");
+    };
+    let expected = fixture
+        .v2_pool
+        .simulate_exact_input_swap(&fixture.weth, &fixture.wbtc, amount_in, Some(state))
+        .await
+        .unwrap();
+    match &overrides[0].1 {
+        PoolSnapshot::UniswapV2(predicted) => {
+            assert_eq!(predicted.reserve0, expected.final_state.reserve0);
+            assert_eq!(predicted.reserve1, expected.final_state.reserve1);
+        }
+        other => panic!("Unexpected override {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn test_v3_router_swap_matches_simulation() {
+    let fixture = setup();
+    let amount_in = U256::from(10_000_000u64);
+    let calldata = exactInputSingleCall {
+        params: ExactInputSingleParams {
+            tokenIn: WBTC_ADDRESS,
+            tokenOut: WETH_ADDRESS,
+            fee: U24::from(3000),
+            recipient: Address::ZERO,
+            deadline: U256::MAX,
+            amountIn: amount_in,
+            amountOutMinimum: U256::ZERO,
+            sqrtPriceLimitX96: U160::ZERO,
+        },
+    }
+    .abi_encode();
+
+    let overrides = fixture
+        .watcher
+        .predict_overrides(UNISWAP_V3_ROUTER, &calldata, &fixture.snapshots)
+        .await
+        .unwrap();
+    assert_eq!(overrides.len(), 1);
+    assert_eq!(overrides[0].0, WBTC_WETH_V3_POOL);
+
+    let PoolSnapshot::UniswapV3(snapshot) = &fixture.snapshots[&WBTC_WETH_V3_POOL] else {
+        panic!("Wrong snapshot type");
+    };
+    let expected = fixture
+        .v3_pool
+        .simulate_exact_input_swap(&fixture.wbtc, &fixture.weth, amount_in, snapshot)
+        .unwrap();
+    match &overrides[0].1 {
+        PoolSnapshot::UniswapV3(predicted) => {
+            assert_eq!(
+                predicted.sqrt_price_x96,
+                expected.final_state.sqrt_price_x96
+            );
+            assert_eq!(predicted.liquidity, expected.final_state.liquidity);
+            assert_eq!(predicted.tick, expected.final_state.tick);
+        }
+        other => panic!("Unexpected override {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn test_unknown_calldata_is_skipped() {
+    let fixture = setup();
+
+    let unknown_selector = [0xde, 0xad, 0xbe, 0xef, 0x00, 0x01];
+    let overrides = fixture
+        .watcher
+        .predict_overrides(UNISWAP_V2_ROUTER, &unknown_selector, &fixture.snapshots)
+        .await
+        .unwrap();
+    assert!(overrides.is_empty());
+
+    let too_short = [0x38, 0xed];
+    let overrides = fixture
+        .watcher
+        .predict_overrides(UNISWAP_V2_ROUTER, &too_short, &fixture.snapshots)
+        .await
+        .unwrap();
+    assert!(overrides.is_empty());
+
+    // A known selector sent to a router we have no factory mapping for.
+    let calldata = swapExactTokensForTokensCall {
+        amountIn: U256::from(1),
+        amountOutMin: U256::ZERO,
+        path: vec![WETH_ADDRESS, WBTC_ADDRESS],
+        to: Address::ZERO,
+        deadline: U256::MAX,
+    }
+    .abi_encode();
+    let overrides = fixture
+        .watcher
+        .predict_overrides(Address::repeat_byte(0x11), &calldata, &fixture.snapshots)
+        .await
+        .unwrap();
+    assert!(overrides.is_empty());
+}
+
+#[test]
+fn test_decoder_derives_pool_addresses() {
+    let registry = DecoderRegistry::mainnet();
+
+    let v2_calldata = swapExactTokensForTokensCall {
+        amountIn: U256::from(1),
+        amountOutMin: U256::ZERO,
+        path: vec![WETH_ADDRESS, WBTC_ADDRESS],
+        to: Address::ZERO,
+        deadline: U256::MAX,
+    }
+    .abi_encode();
+    let decoded = registry
+        .decode(UNISWAP_V2_ROUTER, &v2_calldata)
+        .unwrap()
+        .unwrap();
+    assert_eq!(decoded.hops[0].pool, WBTC_WETH_V2_POOL);
+
+    let v3_calldata = exactInputSingleCall {
+        params: ExactInputSingleParams {
+            tokenIn: WETH_ADDRESS,
+            tokenOut: WBTC_ADDRESS,
+            fee: U24::from(3000),
+            recipient: Address::ZERO,
+            deadline: U256::MAX,
+            amountIn: U256::from(1),
+            amountOutMinimum: U256::ZERO,
+            sqrtPriceLimitX96: U160::ZERO,
+        },
+    }
+    .abi_encode();
+    let decoded = registry
+        .decode(UNISWAP_V3_ROUTER, &v3_calldata)
+        .unwrap()
+        .unwrap();
+    assert_eq!(decoded.hops[0].pool, WBTC_WETH_V3_POOL);
+}
+
+#[tokio::test]
+async fn test_rate_limit_only_counts_managed_swaps() {
+    let fixture = setup_with_config(MempoolConfig {
+        enabled: true,
+        max_txs_per_second: 1,
+    });
+    let unknown_selector = [0xde, 0xad, 0xbe, 0xef, 0x00, 0x01];
+    for _ in 0..5 {
+        let overrides = fixture
+            .watcher
+            .process_pending(UNISWAP_V2_ROUTER, &unknown_selector, &fixture.snapshots)
+            .await
+            .unwrap();
+        assert!(overrides.is_empty());
+    }
+
+    // Unrelated traffic left the budget intact for the first managed swap.
+    let calldata = v2_swap_calldata(U256::from(10).pow(U256::from(18)));
+    let overrides = fixture
+        .watcher
+        .process_pending(UNISWAP_V2_ROUTER, &calldata, &fixture.snapshots)
+        .await
+        .unwrap();
+    assert_eq!(overrides.len(), 1);
+    let overrides = fixture
+        .watcher
+        .process_pending(UNISWAP_V2_ROUTER, &calldata, &fixture.snapshots)
+        .await
+        .unwrap();
+    assert!(overrides.is_empty());
+}
+
+#[tokio::test]
+async fn test_pool_without_snapshot_is_skipped() {
+    // The provider has no node behind it, so fetching a snapshot would fail.
+    let fixture = setup();
+    let overrides = fixture
+        .watcher
+        .predict_overrides(
+            UNISWAP_V2_ROUTER,
+            &v2_swap_calldata(U256::from(1_000)),
+            &HashMap::new(),
+        )
+        .await
+        .unwrap();
+    assert!(overrides.is_empty());
+}
+
+#[tokio::test]
+async fn test_curve_and_balancer_swaps_match_simulation() {
+    let fixture = setup();
+    let provider = fixture.provider.clone();
+    let tokens = vec![fixture.wbtc.clone(), fixture.weth.clone()];
+
+    let attributes = PoolAttributes {
+        // WBTC has 8 decimals.
+        rates: vec![
+            U256::from(10).pow(U256::from(28)),
+            U256::from(10).pow(U256::from(18)),
+        ],
+        precision_multipliers: vec![U256::from(10).pow(U256::from(10)), U256::ONE],
+        ..attributes(2, SwapStrategyType::Default)
+    };
+    let curve = Arc::new(CurveStableswapPool::from_parts(
+        CURVE_POOL,
+        tokens[0].clone(),
+        tokens.clone(),
+        provider.clone(),
+        Arc::new(TokenManager::in_memory(provider.clone(), 1)),
+        attributes,
+    ));
+    let curve_snapshot = CurvePoolSnapshot {
+        balances: vec![
+            U256::from(1_000) * U256::from(100_000_000u64),
+            U256::from(10).pow(U256::from(21)),
+        ],
+        a: U256::from(20_000),
+        fee: U256::from(4_000_000),
+        admin_fee: Some(U256::from(5_000_000_000u64)),
+        rates: curve.attributes.rates.clone(),
+        admin_balances: Some(vec![U256::ZERO; 2]),
+        ..Default::default()
+    };
+
+    let mut pool_id = [0u8; 32];
+    pool_id[..20].copy_from_slice(BALANCER_POOL.as_slice());
+    let half = U256::from(10).pow(U256::from(18)) / U256::from(2);
+    let balancer = Arc::new(BalancerPool::from_parts(
+        BALANCER_POOL,
+        provider.clone(),
+        tokens.clone(),
+        vec![half, half],
+        U256::from(3_000_000_000_000_000u64),
+        BALANCER_VAULT,
+        pool_id,
+    ));
+    let balancer_snapshot = BalancerPoolSnapshot {
+        balances: vec![
+            U256::from(500) * U256::from(100_000_000u64),
+            U256::from(10).pow(U256::from(22)),
+        ],
+        is_paused: false,
+        ..Default::default()
+    };
+
+    let watcher = MempoolWatcher::new(
+        provider,
+        HashMap::from([
+            (
+                CURVE_POOL,
+                curve.clone() as Arc<dyn LiquidityPool<DynProvider>>,
+            ),
+            (
+                BALANCER_POOL,
+                balancer.clone() as Arc<dyn LiquidityPool<DynProvider>>,
+            ),
+        ]),
+        DecoderRegistry::mainnet(),
+        MempoolConfig::default(),
+    );
+    let snapshots = HashMap::from([
+        (CURVE_POOL, PoolSnapshot::Curve(curve_snapshot.clone())),
+        (
+            BALANCER_POOL,
+            PoolSnapshot::Balancer(balancer_snapshot.clone()),
+        ),
+    ]);
+
+    let amount_in = U256::from(10).pow(U256::from(19));
+    let calldata = exchangeCall {
+        i: 1,
+        j: 0,
+        dx: amount_in,
+        min_dy: U256::ZERO,
+    }
+    .abi_encode();
+    let overrides = watcher
+        .predict_overrides(CURVE_POOL, &calldata, &snapshots)
+        .await
+        .unwrap();
+    let expected = curve
+        .simulate_exchange(&fixture.weth, &fixture.wbtc, amount_in, &curve_snapshot)
+        .unwrap();
+    assert!(expected.admin_fee_amount > U256::ZERO);
+    let [(pool, PoolSnapshot::Curve(predicted))] = overrides.as_slice() else {
+        panic!("Unexpected overrides {:?}", overrides);
+    };
+    assert_eq!(*pool, CURVE_POOL);
+    assert_eq!(predicted.balances, expected.final_snapshot.balances);
+    assert_eq!(
+        predicted.admin_balances,
+        expected.final_snapshot.admin_balances
+    );
+
+    let calldata = swapCall {
+        singleSwap: SingleSwap {
+            poolId: pool_id.into(),
+            kind: 0,
+            assetIn: WETH_ADDRESS,
+            assetOut: WBTC_ADDRESS,
+            amount: amount_in,
+            userData: Default::default(),
+        },
+        funds: FundManagement {
+            sender: Address::ZERO,
+            fromInternalBalance: false,
+            recipient: Address::ZERO,
+            toInternalBalance: false,
+        },
+        limit: U256::ZERO,
+        deadline: U256::MAX,
+    }
+    .abi_encode();
+    let overrides = watcher
+        .predict_overrides(BALANCER_VAULT, &calldata, &snapshots)
+        .await
+        .unwrap();
+    let expected = balancer
+        .simulate_swap(&fixture.weth, &fixture.wbtc, amount_in, &balancer_snapshot)
+        .unwrap();
+    let [(pool, PoolSnapshot::Balancer(predicted))] = overrides.as_slice() else {
+        panic!("Unexpected overrides {:?}", overrides);
+    };
+    assert_eq!(*pool, BALANCER_POOL);
+    assert_eq!(*predicted, expected.final_snapshot);
+}
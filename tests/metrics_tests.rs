@@ -0,0 +1,41 @@
+use arbrs::ArbRsError;
+use arbrs::metrics;
+use arbrs::pool::{DexKind, PoolSnapshot};
+use std::collections::HashSet;
+
+#[test]
+fn test_metric_names_are_unique_prometheus_names() {
+    let names = [
+        metrics::BLOCKS_PROCESSED,
+        metrics::SNAPSHOT_DURATION_SECONDS,
+        metrics::SNAPSHOT_FAILURES,
+        metrics::PATHS_EVALUATED,
+        metrics::OPPORTUNITIES_FOUND,
+        metrics::OPPORTUNITY_NET_PROFIT_ETH,
+        metrics::OPTIMIZER_ITERATIONS,
+        metrics::RPC_CALLS,
+        metrics::DISCOVERY_EVENTS,
+    ];
+    assert_eq!(names.iter().collect::<HashSet<_>>().len(), names.len());
+    for name in names {
+        assert!(name.starts_with("arbrs_"), "{name}");
+        assert!(
+            name.chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_'),
+            "{name}"
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_observed_snapshots_are_passed_through() {
+    let snapshot = metrics::observe_snapshot(None, async { Ok(PoolSnapshot::WrappedNative) }).await;
+    assert!(matches!(snapshot, Ok(PoolSnapshot::WrappedNative)));
+
+    let failure = metrics::observe_snapshot(Some(DexKind::Curve), async {
+        Err(ArbRsError::RpcRateLimited("429".to_string()))
+    })
+    .await;
+    assert_eq!(failure.unwrap_err().class(), "rate_limited");
+    assert_eq!(DexKind::UniswapV3.label(), "uniswap_v3");
+}
@@ -0,0 +1,326 @@
+mod common;
+
+use alloy::transports::mock::Asserter;
+use alloy_primitives::{Address, Bytes, U256, address};
+use alloy_provider::{Provider, ProviderBuilder};
+use alloy_sol_types::{SolCall, SolValue};
+use arbrs::arbitrage::cache::ArbitrageCache;
+use arbrs::arbitrage::cycle::ArbitrageCycle;
+use arbrs::arbitrage::engine::ArbitrageEngine;
+use arbrs::arbitrage::types::ArbitragePath;
+use arbrs::core::multicall::{
+    BatchCall, MulticallBatcher, Result3, aggregate3Call, getCurrentBlockTimestampCall,
+};
+use arbrs::core::token::{Token, TokenLike};
+use arbrs::curve::math::virtual_price_from_snapshot;
+use arbrs::curve::pool::{CurveStableswapPool, batch_snapshots};
+use arbrs::curve::pool_attributes::SwapStrategyType;
+use arbrs::curve::pool_overrides::DVariant;
+use arbrs::manager::token_manager::TokenManager;
+use arbrs::pool::{LiquidityPool, PoolSnapshot};
+use common::{attributes, token};
+use std::sync::Arc;
+
+type DynProvider = dyn Provider + Send + Sync;
+
+const WETH: Address = address!("C02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2");
+const BLOCK: u64 = 1;
+const TIMESTAMP: u64 = 1_700_000_000;
+const FEE: u64 = 4_000_000;
+
+fn mocked(asserter: &Asserter) -> Arc<DynProvider> {
+    Arc::new(ProviderBuilder::new().connect_mocked_client(asserter.clone()))
+}
+
+fn word(value: U256) -> Bytes {
+    Bytes::from(value.to_be_bytes::<32>())
+}
+
+fn success(return_data: Bytes) -> Result3 {
+    Result3 {
+        success: true,
+        returnData: return_data,
+    }
+}
+
+fn revert() -> Result3 {
+    Result3 {
+        success: false,
+        returnData: Bytes::new(),
+    }
+}
+
+fn push_aggregate(asserter: &Asserter, results: Vec<Result3>) {
+    asserter.push_success(&Bytes::from(aggregate3Call::abi_encode_returns(&results)));
+}
+
+/// A plain two coin pool of WETH and another token, indexing coins with `int128`.
+async fn curve_pool(
+    byte: u8,
+    tokens: &[Arc<Token<DynProvider>>; 2],
+    provider: Arc<DynProvider>,
+) -> CurveStableswapPool<DynProvider> {
+    let attributes = attributes(2, SwapStrategyType::Default);
+    let pool = CurveStableswapPool::from_parts(
+        Address::repeat_byte(byte),
+        tokens[0].clone(),
+        tokens.to_vec(),
+        provider.clone(),
+        Arc::new(TokenManager::in_memory(provider, 1)),
+        attributes,
+    );
+    *pool.a.write().await = U256::from(200);
+    pool
+}
+
+/// Results of one pool's `snapshot_calls`: the fee, each balance by `int128` index (the
+/// `uint256` variant reverts), then the admin fee.
+fn pool_results(balance: U256) -> Vec<Result3> {
+    vec![
+        success(word(U256::from(FEE))),
+        success(word(balance)),
+        revert(),
+        success(word(balance)),
+        revert(),
+        success(word(U256::from(5_000_000_000u64))),
+    ]
+}
+
+fn timestamp_result() -> Result3 {
+    success(word(U256::from(TIMESTAMP)))
+}
+
+#[tokio::test]
+async fn test_batcher_checks_deployment_once() {
+    let asserter = Asserter::new();
+    let batcher = MulticallBatcher::new(mocked(&asserter));
+    asserter.push_success(&Bytes::new());
+    assert!(!batcher.is_deployed().await.unwrap());
+    // Cached, so no second `eth_getCode`.
+    assert!(!batcher.is_deployed().await.unwrap());
+    assert!(asserter.read_q().is_empty());
+
+    let asserter = Asserter::new();
+    let batcher = MulticallBatcher::new(mocked(&asserter));
+    asserter.push_success(&Bytes::from(vec![0x60, 0x80]));
+    assert!(batcher.is_deployed().await.unwrap());
+}
+
+#[tokio::test]
+async fn test_batcher_splits_calls_and_keeps_their_order() {
+    let asserter = Asserter::new();
+    let batcher = MulticallBatcher::new(mocked(&asserter)).with_max_calls_per_batch(2);
+    let calls: Vec<BatchCall> = (0..3)
+        .map(|_| BatchCall::new(Address::repeat_byte(0x01), getCurrentBlockTimestampCall {}))
+        .collect();
+    push_aggregate(&asserter, vec![success(word(U256::from(1))), revert()]);
+    push_aggregate(&asserter, vec![success(word(U256::from(3)))]);
+
+    let results = batcher.aggregate(&calls, Some(BLOCK)).await.unwrap();
+    assert_eq!(
+        results,
+        [Some(word(U256::from(1))), None, Some(word(U256::from(3)))]
+    );
+    assert!(asserter.read_q().is_empty());
+}
+
+#[tokio::test]
+async fn test_engine_snapshots_curve_pools_in_one_aggregate_call() {
+    const POOLS: u8 = 120;
+    let asserter = Asserter::new();
+    let provider = mocked(&asserter);
+    let tokens = [
+        token(WETH, provider.clone()),
+        token(Address::repeat_byte(0xee), provider.clone()),
+    ];
+    let mut pools: Vec<Arc<dyn LiquidityPool<DynProvider>>> = Vec::new();
+    for byte in 1..=POOLS {
+        pools.push(Arc::new(curve_pool(byte, &tokens, provider.clone()).await));
+    }
+
+    let cache = Arc::new(ArbitrageCache::new());
+    for pair in pools.chunks(2) {
+        cache
+            .add_path(Arc::new(ArbitrageCycle::new(ArbitragePath {
+                pools: pair.to_vec(),
+                path: vec![tokens[0].clone(), tokens[1].clone(), tokens[0].clone()],
+                profit_token: tokens[0].clone(),
+            })))
+            .await;
+    }
+
+    // `eth_getCode`, then a single `aggregate3`. Every pool answers the same, so the order
+    // the engine lists them in doesn't matter.
+    asserter.push_success(&Bytes::from(vec![0x60, 0x80]));
+    let balance = U256::from(10).pow(U256::from(24));
+    let mut results = vec![timestamp_result()];
+    for _ in 0..POOLS {
+        results.extend(pool_results(balance));
+    }
+    push_aggregate(&asserter, results);
+
+    let engine = ArbitrageEngine::new(
+        cache,
+        Arc::new(TokenManager::in_memory(provider.clone(), 1)),
+        provider.clone(),
+    )
+    .with_multicall(Arc::new(MulticallBatcher::new(provider)));
+    engine.find_opportunities(Some(BLOCK)).await;
+
+    // Everything else the engine asks for fails against the empty queue.
+    assert!(asserter.read_q().is_empty());
+    let snapshots = engine.last_snapshots();
+    assert_eq!(snapshots.len(), POOLS as usize);
+    for snapshot in snapshots.values() {
+        let PoolSnapshot::Curve(snapshot) = snapshot else {
+            panic!("expected a Curve snapshot");
+        };
+        assert_eq!(snapshot.balances, [balance, balance]);
+        assert_eq!(snapshot.fee, U256::from(FEE));
+        assert_eq!(snapshot.admin_fee, Some(U256::from(5_000_000_000u64)));
+        assert_eq!(snapshot.block_timestamp, TIMESTAMP);
+        // A_PRECISION times the pool's A, as `get_snapshot` reports it.
+        assert_eq!(snapshot.a, U256::from(20_000));
+    }
+    assert_eq!(engine.last_stats().failed_snapshots, 0);
+}
+
+#[tokio::test]
+async fn test_undecodable_pool_is_left_for_the_per_pool_path() {
+    let asserter = Asserter::new();
+    let provider = mocked(&asserter);
+    let tokens = [
+        token(WETH, provider.clone()),
+        token(Address::repeat_byte(0xee), provider.clone()),
+    ];
+    let healthy = curve_pool(0x01, &tokens, provider.clone()).await;
+    let broken = curve_pool(0x02, &tokens, provider.clone()).await;
+    let balance = U256::from(1_000);
+    let mut results = vec![timestamp_result()];
+    results.extend(pool_results(balance));
+    // Neither balance index answers.
+    results.extend([
+        success(word(U256::from(FEE))),
+        revert(),
+        revert(),
+        revert(),
+        revert(),
+        revert(),
+    ]);
+    push_aggregate(&asserter, results);
+
+    let batcher = MulticallBatcher::new(provider);
+    let snapshots = batch_snapshots(&batcher, &[&healthy, &broken], Some(BLOCK))
+        .await
+        .unwrap();
+    assert_eq!(snapshots.len(), 2);
+    let (address, snapshot) = &snapshots[0];
+    assert_eq!(*address, healthy.address);
+    let Ok(PoolSnapshot::Curve(snapshot)) = snapshot else {
+        panic!("expected a Curve snapshot");
+    };
+    assert_eq!(snapshot.balances, [balance, balance]);
+    assert_eq!(snapshots[1].0, broken.address);
+    assert!(snapshots[1].1.is_err());
+}
+
+/// A metapool of WETH and the LP token of a two coin base pool.
+async fn metapool(provider: Arc<DynProvider>) -> CurveStableswapPool<DynProvider> {
+    let base_tokens = [
+        token(Address::repeat_byte(0xdd), provider.clone()),
+        token(Address::repeat_byte(0xee), provider.clone()),
+    ];
+    let mut base_pool = curve_pool(0x02, &base_tokens, provider.clone()).await;
+    base_pool.lp_token = token(Address::repeat_byte(0x3c), provider.clone());
+    let meta_tokens = [token(WETH, provider.clone()), base_pool.lp_token.clone()];
+    let mut metapool = curve_pool(0x01, &meta_tokens, provider).await;
+    metapool.base_pool = Some(Arc::new(base_pool));
+    metapool
+}
+
+#[tokio::test]
+async fn test_metapool_batch_carries_its_base_pool_snapshot() {
+    let asserter = Asserter::new();
+    let provider = mocked(&asserter);
+    let metapool = metapool(provider.clone()).await;
+
+    let supply = U256::from(5_000);
+    let (meta_balance, base_balance) = (U256::from(1_000), U256::from(2_000));
+    let mut results = vec![timestamp_result()];
+    results.extend(pool_results(meta_balance));
+    results.push(success(word(supply)));
+    results.extend(pool_results(base_balance));
+    push_aggregate(&asserter, results);
+
+    let batcher = MulticallBatcher::new(provider);
+    let snapshots = batch_snapshots(&batcher, &[&metapool], Some(BLOCK))
+        .await
+        .unwrap();
+    let Ok(PoolSnapshot::Curve(snapshot)) = &snapshots[0].1 else {
+        panic!("expected a Curve snapshot");
+    };
+    assert_eq!(snapshot.balances, [meta_balance, meta_balance]);
+    assert_eq!(snapshot.base_pool_lp_total_supply, Some(supply));
+    let base_snapshot = snapshot.base_pool_snapshot.as_deref().unwrap();
+    assert_eq!(base_snapshot.balances, [base_balance, base_balance]);
+    assert_eq!(base_snapshot.block_timestamp, TIMESTAMP);
+    let derived = virtual_price_from_snapshot(base_snapshot, supply, DVariant::Default).unwrap();
+    assert_eq!(snapshot.base_pool_virtual_price, Some(derived));
+    assert!(asserter.read_q().is_empty());
+}
+
+#[tokio::test]
+async fn test_metapool_batch_reads_the_onchain_virtual_price_when_asked() {
+    let asserter = Asserter::new();
+    let provider = mocked(&asserter);
+    let metapool = metapool(provider.clone())
+        .await
+        .with_onchain_virtual_price(true);
+
+    let (supply, virtual_price) = (U256::from(5_000), U256::from(1_020_000));
+    let mut results = vec![timestamp_result()];
+    results.extend(pool_results(U256::from(1_000)));
+    results.extend([success(word(supply)), success(word(virtual_price))]);
+    results.extend(pool_results(U256::from(2_000)));
+    push_aggregate(&asserter, results);
+
+    let batcher = MulticallBatcher::new(provider);
+    let snapshots = batch_snapshots(&batcher, &[&metapool], Some(BLOCK))
+        .await
+        .unwrap();
+    let Ok(PoolSnapshot::Curve(snapshot)) = &snapshots[0].1 else {
+        panic!("expected a Curve snapshot");
+    };
+    assert_eq!(snapshot.base_pool_virtual_price, Some(virtual_price));
+    assert_eq!(snapshot.base_pool_lp_total_supply, Some(supply));
+}
+
+#[tokio::test]
+async fn test_token_metadata_is_fetched_in_one_aggregate_call() {
+    let asserter = Asserter::new();
+    let manager = TokenManager::in_memory(mocked(&asserter), 1);
+    let (usdc, mkr) = (Address::repeat_byte(0x0c), Address::repeat_byte(0x0d));
+    let mut mkr_symbol = [0u8; 32];
+    mkr_symbol[..3].copy_from_slice(b"MKR");
+    // Decimals, symbol and name of each token; the second has a `bytes32` symbol and no
+    // `decimals()` or `name()`.
+    push_aggregate(
+        &asserter,
+        vec![
+            success(word(U256::from(6))),
+            success(Bytes::from("USDC".to_string().abi_encode())),
+            success(Bytes::from("USD Coin".to_string().abi_encode())),
+            revert(),
+            success(Bytes::from(mkr_symbol.to_vec())),
+            revert(),
+        ],
+    );
+
+    let tokens = manager.get_token_list(&[usdc, mkr, usdc]).await.unwrap();
+    assert!(asserter.read_q().is_empty());
+    assert_eq!((tokens[0].symbol(), tokens[0].decimals()), ("USDC", 6));
+    assert_eq!((tokens[1].symbol(), tokens[1].decimals()), ("MKR", 18));
+    assert!(Arc::ptr_eq(&tokens[0], &tokens[2]));
+    // Cached, so nothing more is fetched.
+    assert_eq!(manager.get_token(mkr).await.unwrap().symbol(), "MKR");
+}
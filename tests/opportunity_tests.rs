@@ -0,0 +1,199 @@
+#![cfg(feature = "db")]
+
+mod common;
+
+use alloy::transports::mock::Asserter;
+use alloy_primitives::{Address, B256, U64, U256, address};
+use alloy_provider::{Provider, ProviderBuilder};
+use arbrs::arbitrage::engine::ArbitrageEngine;
+use arbrs::arbitrage::recorder::{OpportunityRecord, OpportunityRecorder};
+use arbrs::db::DbManager;
+use arbrs::pool::strategy::StandardV2Logic;
+use arbrs::pool::uniswap_v2::{UniswapV2Pool, UniswapV2PoolState};
+use arbrs::pool::{LiquidityPool, PoolSnapshot};
+use common::{ether, token};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+type DynProvider = dyn Provider + Send + Sync;
+
+const WETH: Address = address!("C02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2");
+const BLOCKS: u64 = 100;
+
+fn record(block_number: u64, path: u8, net_profit: u64) -> OpportunityRecord {
+    OpportunityRecord {
+        block_number,
+        path_hash: B256::repeat_byte(path),
+        pools: vec![Address::repeat_byte(path), Address::repeat_byte(path + 1)],
+        profit_token: WETH,
+        optimal_input: ether(1),
+        gross_profit: U256::from(net_profit * 2),
+        net_profit: U256::from(net_profit),
+        net_profit_weth: U256::from(net_profit),
+        gas_price: U256::from(20_000_000_000u64),
+        recorded_at: 1_700_000_000 + block_number * 12,
+    }
+}
+
+#[tokio::test]
+async fn test_opportunities_are_ranked_by_cumulative_profit() {
+    let db_manager = DbManager::new("sqlite::memory:").await.unwrap();
+    db_manager
+        .record_opportunities(&[record(1, 0x10, 500), record(1, 0x20, 300)])
+        .await
+        .unwrap();
+    db_manager
+        .record_opportunities(&[record(2, 0x20, 300), record(3, 0x20, 300)])
+        .await
+        .unwrap();
+    // Recording a block twice keeps the first row.
+    db_manager
+        .record_opportunity(&record(3, 0x20, 1_000))
+        .await
+        .unwrap();
+
+    let ranked = db_manager
+        .top_paths_by_cumulative_profit(0, 10)
+        .await
+        .unwrap();
+    assert_eq!(ranked.len(), 2);
+    assert_eq!(ranked[0].path_hash, B256::repeat_byte(0x20));
+    assert_eq!(ranked[0].opportunities, 3);
+    assert_eq!(ranked[0].cumulative_net_profit, U256::from(900));
+    assert_eq!((ranked[0].first_block, ranked[0].last_block), (1, 3));
+    assert_eq!(ranked[1].cumulative_net_profit, U256::from(500));
+
+    let since_second = db_manager
+        .top_paths_by_cumulative_profit(2, 1)
+        .await
+        .unwrap();
+    assert_eq!(since_second.len(), 1);
+    assert_eq!(since_second[0].opportunities, 2);
+
+    let history = db_manager
+        .opportunity_history(B256::repeat_byte(0x20))
+        .await
+        .unwrap();
+    assert_eq!(
+        history,
+        vec![
+            record(1, 0x20, 300),
+            record(2, 0x20, 300),
+            record(3, 0x20, 300)
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_engine_records_solutions_of_every_block() {
+    let asserter = Asserter::new();
+    let provider: Arc<DynProvider> =
+        Arc::new(ProviderBuilder::new().connect_mocked_client(asserter.clone()));
+    let (weth, other) = (
+        token(WETH, provider.clone()),
+        token(Address::repeat_byte(0xee), provider.clone()),
+    );
+    let pools: Vec<Arc<dyn LiquidityPool<DynProvider>>> = [0x01, 0x02]
+        .into_iter()
+        .map(|byte| {
+            Arc::new(UniswapV2Pool::new(
+                Address::repeat_byte(byte),
+                weth.clone(),
+                other.clone(),
+                provider.clone(),
+                StandardV2Logic,
+            )) as Arc<dyn LiquidityPool<DynProvider>>
+        })
+        .collect();
+    asserter.push_success(&U64::from(1));
+    let db_manager = Arc::new(DbManager::new("sqlite::memory:").await.unwrap());
+    let engine = ArbitrageEngine::quote_only(provider, pools)
+        .await
+        .unwrap()
+        .with_opportunity_recording(db_manager.clone());
+
+    // Pair 0x01 pays 20% more for WETH, in every block.
+    let reserves = |other: u64| {
+        PoolSnapshot::UniswapV2(UniswapV2PoolState {
+            reserve0: ether(1_000),
+            reserve1: ether(other),
+            block_number: 1,
+        })
+    };
+    let overrides = HashMap::from([
+        (Address::repeat_byte(0x01), reserves(2_400_000)),
+        (Address::repeat_byte(0x02), reserves(2_000_000)),
+    ]);
+    let mut found = Vec::new();
+    for block in 1..=BLOCKS {
+        // Both snapshots, which the overrides stand in for, the gas price and the flashloan
+        // liquidity.
+        for _ in 0..4 {
+            asserter.push_failure_msg("not mocked");
+        }
+        let solutions = engine
+            .find_opportunities_with_overrides(Some(block), overrides.clone())
+            .await;
+        assert_eq!(solutions.len(), 1);
+        found.push(solutions[0].net_profit.raw);
+    }
+
+    // The writes happen in the background.
+    let mut ranked = Vec::new();
+    for _ in 0..100 {
+        ranked = db_manager
+            .top_paths_by_cumulative_profit(0, 10)
+            .await
+            .unwrap();
+        if ranked
+            .first()
+            .is_some_and(|path| path.opportunities == BLOCKS)
+        {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert_eq!(ranked.len(), 1);
+    assert_eq!(ranked[0].opportunities, BLOCKS);
+    assert_eq!(ranked[0].profit_token, WETH);
+    assert_eq!(
+        ranked[0].cumulative_net_profit,
+        found.iter().fold(U256::ZERO, |sum, profit| sum + profit)
+    );
+    assert_eq!((ranked[0].first_block, ranked[0].last_block), (1, BLOCKS));
+
+    let history = db_manager
+        .opportunity_history(ranked[0].path_hash)
+        .await
+        .unwrap();
+    assert_eq!(history.len() as u64, BLOCKS);
+    assert_eq!(history[0].pools.len(), 2, "a round trip through both pairs");
+}
+
+#[tokio::test]
+async fn test_shutdown_saves_the_queued_records() {
+    let db_manager = Arc::new(DbManager::new("sqlite::memory:").await.unwrap());
+    let recorder = OpportunityRecorder::spawn(db_manager.clone(), 16);
+    for block in 1..=10 {
+        recorder.submit(vec![record(block, 0x10, 5), record(block, 0x20, 7)]);
+    }
+
+    // Nothing is waited for before shutting down; the writer drains its queue first.
+    recorder.shutdown().await;
+    let ranked = db_manager
+        .top_paths_by_cumulative_profit(0, 10)
+        .await
+        .unwrap();
+    assert_eq!(ranked.len(), 2);
+    assert!(ranked.iter().all(|path| path.opportunities == 10));
+
+    // Records submitted afterwards are dropped, and a second shutdown is a no-op.
+    recorder.submit(vec![record(11, 0x10, 5)]);
+    recorder.shutdown().await;
+    let history = db_manager
+        .opportunity_history(B256::repeat_byte(0x10))
+        .await
+        .unwrap();
+    assert_eq!(history.len(), 10);
+}
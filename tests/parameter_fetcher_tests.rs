@@ -0,0 +1,229 @@
+mod common;
+
+use alloy::transports::mock::Asserter;
+use alloy_primitives::{Address, Bytes, U256, address};
+use alloy_provider::{Provider, ProviderBuilder};
+use alloy_sol_types::{SolCall, sol};
+use arbrs::curve::attributes_builder::build_attributes;
+use arbrs::curve::constants::A_PRECISION;
+use arbrs::curve::parameter_fetcher::{detect_a_precision_multiplier, detect_fetcher};
+use arbrs::curve::pool_attributes::{ParameterFetcherType, PoolVariant, SwapStrategyType};
+use arbrs::curve::pool_overrides::DVariant;
+use arbrs::curve::registry::{CURVE_STABLESWAP_NG_FACTORY, CurveRegistry};
+use arbrs::manager::token_manager::TokenManager;
+use common::token;
+use std::sync::Arc;
+
+sol! {
+    function gamma() external view returns (uint256);
+    function factory() external view returns (address);
+    function get_fees(address pool) external view returns (uint256, uint256);
+    function A() external view returns (uint256);
+    function offpeg_fee_multiplier() external view returns (uint256);
+}
+
+const POOL: Address = Address::repeat_byte(0x01);
+const KNOWN_FACTORY: Address = Address::repeat_byte(0xfa);
+const OWN_FACTORY: Address = Address::repeat_byte(0xfb);
+const BASE_POOL: Address = Address::repeat_byte(0x02);
+const Y_POOL: Address = address!("45F783CCE6B7FF23B2ab2D70e416cdb7D6055f51");
+
+type DynProvider = dyn Provider + Send + Sync;
+
+fn mocked(asserter: &Asserter) -> Arc<DynProvider> {
+    Arc::new(ProviderBuilder::new().connect_mocked_client(asserter.clone()))
+}
+
+fn push_call_result(asserter: &Asserter, encoded: Vec<u8>) {
+    asserter.push_success(&Bytes::from(encoded));
+}
+
+fn push_revert(asserter: &Asserter) {
+    asserter.push_failure_msg("execution reverted");
+}
+
+fn push_fees(asserter: &Asserter, fee: u64) {
+    push_call_result(
+        asserter,
+        get_feesCall::abi_encode_returns(&get_feesReturn {
+            _0: U256::from(fee),
+            _1: U256::from(5_000_000_000u64),
+        }),
+    );
+}
+
+#[tokio::test]
+async fn test_pool_answering_gamma_uses_crypto_fetcher() {
+    let asserter = Asserter::new();
+    push_call_result(
+        &asserter,
+        gammaCall::abi_encode_returns(&U256::from(11_809_167_828_997u64)),
+    );
+
+    let detected = detect_fetcher(mocked(&asserter).as_ref(), POOL, &[KNOWN_FACTORY]).await;
+    assert_eq!(detected, (ParameterFetcherType::Crypto, None));
+}
+
+#[tokio::test]
+async fn test_pool_factory_getter_takes_precedence() {
+    let asserter = Asserter::new();
+    push_revert(&asserter);
+    push_call_result(&asserter, factoryCall::abi_encode_returns(&OWN_FACTORY));
+    push_fees(&asserter, 4_000_000);
+
+    let detected = detect_fetcher(mocked(&asserter).as_ref(), POOL, &[KNOWN_FACTORY]).await;
+    assert_eq!(detected, (ParameterFetcherType::Factory, Some(OWN_FACTORY)));
+}
+
+#[tokio::test]
+async fn test_known_factory_is_probed_when_the_pool_has_no_getter() {
+    let asserter = Asserter::new();
+    push_revert(&asserter);
+    push_revert(&asserter);
+    push_fees(&asserter, 4_000_000);
+
+    let detected = detect_fetcher(mocked(&asserter).as_ref(), POOL, &[KNOWN_FACTORY]).await;
+    assert_eq!(
+        detected,
+        (ParameterFetcherType::Factory, Some(KNOWN_FACTORY))
+    );
+}
+
+#[tokio::test]
+async fn test_unrecognised_pool_falls_back_to_standard_fetcher() {
+    let asserter = Asserter::new();
+    push_revert(&asserter);
+    push_revert(&asserter);
+    // A factory that never deployed the pool reports a zero fee for it.
+    push_fees(&asserter, 0);
+
+    let detected = detect_fetcher(mocked(&asserter).as_ref(), POOL, &[KNOWN_FACTORY]).await;
+    assert_eq!(detected, (ParameterFetcherType::Standard, None));
+}
+
+fn push_word(asserter: &Asserter, value: u64) {
+    push_call_result(asserter, ACall::abi_encode_returns(&U256::from(value)));
+}
+
+#[tokio::test]
+async fn test_a_precision_is_the_ratio_of_a_precise_to_a() {
+    let asserter = Asserter::new();
+    let provider = mocked(&asserter);
+
+    // `A()`, then `A_precise()`.
+    push_word(&asserter, 2_000);
+    push_word(&asserter, 200_000);
+    assert_eq!(
+        detect_a_precision_multiplier(provider.as_ref(), POOL).await,
+        A_PRECISION
+    );
+
+    // Mid ramp, `A()` is `A_precise()` rounded down.
+    push_word(&asserter, 1_999);
+    push_word(&asserter, 199_950);
+    assert_eq!(
+        detect_a_precision_multiplier(provider.as_ref(), POOL).await,
+        A_PRECISION
+    );
+
+    // A pool whose `A()` is already precise.
+    push_word(&asserter, 200_000);
+    push_word(&asserter, 200_000);
+    assert_eq!(
+        detect_a_precision_multiplier(provider.as_ref(), POOL).await,
+        U256::ONE
+    );
+    assert!(asserter.read_q().is_empty());
+}
+
+#[tokio::test]
+async fn test_a_precision_without_a_precise_getter() {
+    let asserter = Asserter::new();
+    let provider = mocked(&asserter);
+
+    push_word(&asserter, 2_000);
+    push_revert(&asserter);
+    assert_eq!(
+        detect_a_precision_multiplier(provider.as_ref(), POOL).await,
+        A_PRECISION
+    );
+
+    // Legacy pools are known, so nothing is called for them.
+    assert_eq!(
+        detect_a_precision_multiplier(provider.as_ref(), Y_POOL).await,
+        U256::ONE
+    );
+    assert!(asserter.read_q().is_empty());
+}
+
+/// A registry consulting no contracts, knowing `POOL` as a metapool over `base_pool`.
+fn registry(provider: &Arc<DynProvider>, base_pool: Option<Address>) -> CurveRegistry<DynProvider> {
+    let registry = CurveRegistry::new(Address::ZERO, provider.clone()).with_sources([]);
+    registry.register_pool_metadata(POOL, POOL, base_pool);
+    registry
+}
+
+#[tokio::test]
+async fn test_ng_factory_metapool_is_quoted_with_the_ng_math() {
+    let asserter = Asserter::new();
+    let provider = mocked(&asserter);
+    let tokens = [0x0a, 0x0b].map(|byte| token(Address::repeat_byte(byte), provider.clone()));
+    let offpeg_fee_multiplier = U256::from(20_000_000_000u64);
+
+    push_revert(&asserter);
+    push_call_result(
+        &asserter,
+        factoryCall::abi_encode_returns(&CURVE_STABLESWAP_NG_FACTORY),
+    );
+    push_fees(&asserter, 4_000_000);
+    push_call_result(
+        &asserter,
+        offpeg_fee_multiplierCall::abi_encode_returns(&offpeg_fee_multiplier),
+    );
+
+    let attributes = build_attributes(
+        POOL,
+        &tokens,
+        provider.clone(),
+        &TokenManager::in_memory(provider.clone(), 1),
+        &registry(&provider, Some(BASE_POOL)),
+    )
+    .await
+    .unwrap();
+    assert_eq!(attributes.pool_variant, PoolVariant::Meta);
+    assert_eq!(attributes.swap_strategy, SwapStrategyType::StableSwapNG);
+    assert_eq!(attributes.d_variant, DVariant::StableSwapNG);
+    assert_eq!(
+        attributes.offpeg_fee_multiplier,
+        Some(offpeg_fee_multiplier)
+    );
+    assert!(asserter.read_q().is_empty());
+}
+
+#[tokio::test]
+async fn test_pool_outside_any_factory_is_not_probed_for_ng() {
+    let asserter = Asserter::new();
+    let provider = mocked(&asserter);
+    let tokens = [0x0a, 0x0b].map(|byte| token(Address::repeat_byte(byte), provider.clone()));
+
+    // `gamma()`, the pool's `factory()` and the stable factory's `get_fees` all revert, so
+    // the only calls left are `A()` and `A_precise()`.
+    push_revert(&asserter);
+    push_revert(&asserter);
+    push_revert(&asserter);
+    push_word(&asserter, 200_000);
+    push_word(&asserter, 200_000);
+
+    let attributes = build_attributes(
+        POOL,
+        &tokens,
+        provider.clone(),
+        &TokenManager::in_memory(provider.clone(), 1),
+        &registry(&provider, None),
+    )
+    .await
+    .unwrap();
+    assert_eq!(attributes.swap_strategy, SwapStrategyType::Default);
+    assert_eq!(attributes.a_precision_multiplier, U256::ONE);
+    assert!(asserter.read_q().is_empty());
+}
@@ -0,0 +1,176 @@
+mod common;
+
+use alloy::transports::mock::Asserter;
+use alloy_primitives::{Address, Bytes, I256, U256, address};
+use alloy_provider::{Provider, ProviderBuilder};
+use alloy_sol_types::SolValue;
+use arbrs::ArbRsError;
+use arbrs::arbitrage::cache::ArbitrageCache;
+use arbrs::arbitrage::dry_run::cycle_path;
+use arbrs::arbitrage::engine::ArbitrageEngine;
+use arbrs::core::token::{Token, TokenLike};
+use arbrs::manager::token_manager::TokenManager;
+use arbrs::pool::LiquidityPool;
+use arbrs::pool::strategy::StandardV2Logic;
+use arbrs::pool::uniswap_v2::UniswapV2Pool;
+use common::{ether, named_token};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+type DynProvider = dyn Provider + Send + Sync;
+type PoolsByAddress = HashMap<Address, Arc<dyn LiquidityPool<DynProvider>>>;
+
+const WETH: Address = address!("C02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2");
+const GAS_PRICE: u64 = 10_000_000_000;
+
+fn v2_pool(
+    byte: u8,
+    token0: &Arc<Token<DynProvider>>,
+    token1: &Arc<Token<DynProvider>>,
+    provider: Arc<DynProvider>,
+) -> Arc<dyn LiquidityPool<DynProvider>> {
+    Arc::new(UniswapV2Pool::new(
+        Address::repeat_byte(byte),
+        token0.clone(),
+        token1.clone(),
+        provider,
+        StandardV2Logic,
+    ))
+}
+
+fn push_reserves(asserter: &Asserter, reserve0: U256, reserve1: U256) {
+    asserter.push_success(&Bytes::from((reserve0, reserve1, U256::ZERO).abi_encode()));
+}
+
+/// A WETH/USDC pair priced at 2400 and a WETH/USDC pair priced at 2000, both holding
+/// 1000 WETH, with USDC as token0 of the second so the path has to be ordered.
+fn pools(provider: Arc<DynProvider>) -> (PoolsByAddress, Arc<Token<DynProvider>>) {
+    let weth = named_token(WETH, "WETH", 18, provider.clone());
+    let usdc = named_token(Address::repeat_byte(0xee), "USDC", 18, provider.clone());
+    let pools = [
+        v2_pool(0x01, &weth, &usdc, provider.clone()),
+        v2_pool(0x02, &usdc, &weth, provider),
+    ];
+    (pools.into_iter().map(|pool| (pool.address(), pool)).collect(), weth)
+}
+
+#[test]
+fn test_cycle_path_orders_tokens_along_the_pools() {
+    let provider: Arc<DynProvider> =
+        Arc::new(ProviderBuilder::new().connect_mocked_client(Asserter::new()));
+    let weth = named_token(WETH, "WETH", 18, provider.clone());
+    let usdc = named_token(Address::repeat_byte(0xee), "USDC", 18, provider.clone());
+    let dai = named_token(Address::repeat_byte(0xdd), "DAI", 18, provider.clone());
+    let weth_usdc = v2_pool(0x01, &weth, &usdc, provider.clone());
+    let dai_usdc = v2_pool(0x02, &dai, &usdc, provider.clone());
+    let dai_weth = v2_pool(0x03, &dai, &weth, provider.clone());
+
+    let path = cycle_path(vec![weth_usdc.clone(), dai_usdc.clone(), dai_weth.clone()], WETH).unwrap();
+    let symbols: Vec<&str> = path.path.iter().map(|token| token.symbol()).collect();
+    assert_eq!(symbols, ["WETH", "USDC", "DAI", "WETH"]);
+    assert_eq!(path.profit_token.address(), WETH);
+
+    // The first pool doesn't hold the input.
+    assert_eq!(
+        cycle_path(vec![dai_usdc.clone(), dai_weth.clone()], WETH).unwrap_err(),
+        ArbRsError::InvalidPool(dai_usdc.address(), format!("does not hold {WETH}"))
+    );
+    // Neighbours must share a token, and the last hop must buy the input back.
+    assert_eq!(
+        cycle_path(vec![dai_weth.clone(), weth_usdc.clone(), dai_weth.clone()], WETH).unwrap_err(),
+        ArbRsError::InvalidPool(dai_weth.address(), format!("shares no other token with {}", weth_usdc.address()))
+    );
+    assert_eq!(
+        cycle_path(vec![weth_usdc.clone(), dai_usdc.clone()], WETH).unwrap_err(),
+        ArbRsError::InvalidPool(dai_usdc.address(), format!("does not trade back to {WETH}"))
+    );
+}
+
+#[tokio::test]
+async fn test_evaluates_a_single_path_at_a_block() {
+    let asserter = Asserter::new();
+    let provider: Arc<DynProvider> =
+        Arc::new(ProviderBuilder::new().connect_mocked_client(asserter.clone()));
+    let (known_pools, weth) = pools(provider.clone());
+    let engine = ArbitrageEngine::new(
+        Arc::new(ArbitrageCache::new()),
+        Arc::new(TokenManager::in_memory(provider.clone(), 1)),
+        provider,
+    );
+
+    push_reserves(&asserter, ether(1_000), ether(2_400_000));
+    push_reserves(&asserter, ether(2_000_000), ether(1_000));
+    asserter.push_success(&U256::from(GAS_PRICE));
+
+    let pool_addrs = [Address::repeat_byte(0x01), Address::repeat_byte(0x02)];
+    let Ok(evaluation) = engine
+        .evaluate_path_at_block(&pool_addrs, &known_pools, WETH, ether(1), 19_000_000)
+        .await
+    else {
+        panic!("evaluation failed");
+    };
+    assert!(asserter.read_q().is_empty());
+
+    assert_eq!(evaluation.block, 19_000_000);
+    assert_eq!(evaluation.hops.len(), 2);
+    let (first, second) = (&evaluation.hops[0], &evaluation.hops[1]);
+    assert_eq!(first.pool, pool_addrs[0]);
+    assert_eq!(first.amount_in.raw, ether(1));
+    assert_eq!(first.amount_out.token.symbol(), "USDC");
+    assert_eq!(second.amount_in, first.amount_out);
+    assert_eq!(second.amount_out.token.address(), WETH);
+    assert_eq!(evaluation.amount_out, second.amount_out);
+    assert!(first.metrics.is_some() && second.metrics.is_some());
+
+    // Buying at 2000 what sells for 2400 is about a fifth up, less two 30 bps fees.
+    let gross = evaluation.gross_profit;
+    assert_eq!(gross, I256::try_from(evaluation.amount_out.raw).unwrap() - I256::try_from(ether(1)).unwrap());
+    assert!(gross > I256::try_from(ether(1) / U256::from(6)).unwrap());
+
+    let gas_cost = evaluation.gas_cost.as_ref().unwrap();
+    assert_eq!(gas_cost.token.address(), WETH);
+    assert_eq!(gas_cost.raw, evaluation.gas_cost_wei);
+    assert_eq!(evaluation.gas_cost_wei, U256::from(evaluation.gas_units) * U256::from(GAS_PRICE));
+    let costs = I256::try_from(evaluation.flashloan_fee.raw + gas_cost.raw).unwrap();
+    assert_eq!(evaluation.net_profit, Some(gross - costs));
+
+    let (optimal_input, optimal_gross) = evaluation.optimal_input.as_ref().unwrap();
+    assert!(*optimal_input.token == *weth);
+    assert!(optimal_gross.raw > gross.unsigned_abs());
+
+    let report = evaluation.to_string();
+    assert!(report.contains("hop 1 via"), "{report}");
+    assert!(report.contains("1 WETH -> "), "{report}");
+}
+
+#[tokio::test]
+async fn test_untracked_pools_are_an_error() {
+    let asserter = Asserter::new();
+    let provider: Arc<DynProvider> =
+        Arc::new(ProviderBuilder::new().connect_mocked_client(asserter.clone()));
+    let (known_pools, _) = pools(provider.clone());
+    let engine = ArbitrageEngine::new(
+        Arc::new(ArbitrageCache::new()),
+        Arc::new(TokenManager::in_memory(provider.clone(), 1)),
+        provider,
+    );
+
+    let untracked = Address::repeat_byte(0x03);
+    let result = engine
+        .evaluate_path_at_block(&[Address::repeat_byte(0x01), untracked], &known_pools, WETH, ether(1), 1)
+        .await;
+    assert_eq!(result.err(), Some(ArbRsError::UnknownPool(untracked)));
+
+    // A pool whose snapshot can't be taken fails the evaluation too.
+    asserter.push_failure_msg("execution reverted");
+    let result = engine
+        .evaluate_path_at_block(
+            &[Address::repeat_byte(0x01), Address::repeat_byte(0x02)],
+            &known_pools,
+            WETH,
+            ether(1),
+            1,
+        )
+        .await;
+    assert!(matches!(result.err(), Some(ArbRsError::ProviderError(_))));
+}
@@ -0,0 +1,156 @@
+mod common;
+
+use alloy::transports::mock::Asserter;
+use alloy_primitives::{Address, U256, address};
+use alloy_provider::{Provider, ProviderBuilder};
+use arbrs::arbitrage::cache::ArbitrageCache;
+use arbrs::arbitrage::cycle::ArbitrageCycle;
+use arbrs::arbitrage::engine::{ArbitrageEngine, EngineConfig};
+use arbrs::arbitrage::persistence::{PersistencePolicy, PersistenceTracker};
+use arbrs::arbitrage::types::{ArbitragePath, CycleId};
+use arbrs::manager::token_manager::TokenManager;
+use arbrs::pool::strategy::StandardV2Logic;
+use arbrs::pool::uniswap_v2::{UniswapV2Pool, UniswapV2PoolState};
+use arbrs::pool::{LiquidityPool, PoolSnapshot};
+use common::token;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+type DynProvider = dyn Provider + Send + Sync;
+
+// Path A trades through pools 0x01 and 0x02, path B through 0x03 and 0x04.
+const WETH: Address = address!("C02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2");
+const PATH_A: [u8; 2] = [0x01, 0x02];
+const PATH_B: [u8; 2] = [0x03, 0x04];
+
+fn reserves(reserve0: u64, reserve1: u64) -> PoolSnapshot {
+    let ether = U256::from(10).pow(U256::from(18));
+    PoolSnapshot::UniswapV2(UniswapV2PoolState {
+        reserve0: U256::from(reserve0) * ether,
+        reserve1: U256::from(reserve1) * ether,
+        block_number: 1,
+    })
+}
+
+/// Snapshots for both pools of a path, mispriced when `profitable`.
+fn path_snapshots(pools: [u8; 2], profitable: bool) -> [(Address, PoolSnapshot); 2] {
+    let first = if profitable { 2_400_000 } else { 2_000_000 };
+    [
+        (Address::repeat_byte(pools[0]), reserves(1_000, first)),
+        (Address::repeat_byte(pools[1]), reserves(1_000, 2_000_000)),
+    ]
+}
+
+async fn engine(policy: PersistencePolicy) -> (ArbitrageEngine<DynProvider>, [CycleId; 2]) {
+    let provider: Arc<DynProvider> =
+        Arc::new(ProviderBuilder::new().connect_mocked_client(Asserter::new()));
+    let (profit_token, other) = (
+        token(WETH, provider.clone()),
+        token(Address::repeat_byte(0xee), provider.clone()),
+    );
+    let cache = Arc::new(ArbitrageCache::new());
+    let mut cycle_ids = Vec::new();
+    for pools in [PATH_A, PATH_B] {
+        let pools: Vec<Arc<dyn LiquidityPool<DynProvider>>> = pools
+            .into_iter()
+            .map(|byte| {
+                Arc::new(UniswapV2Pool::new(
+                    Address::repeat_byte(byte),
+                    profit_token.clone(),
+                    other.clone(),
+                    provider.clone(),
+                    StandardV2Logic,
+                )) as Arc<dyn LiquidityPool<DynProvider>>
+            })
+            .collect();
+        let cycle = ArbitrageCycle::new(ArbitragePath {
+            pools,
+            path: vec![profit_token.clone(), other.clone(), profit_token.clone()],
+            profit_token: profit_token.clone(),
+        });
+        cycle_ids.push(cycle.cycle_id());
+        cache.add_path(Arc::new(cycle)).await;
+    }
+
+    let engine = ArbitrageEngine::new(
+        cache,
+        Arc::new(TokenManager::in_memory(provider.clone(), 1)),
+        provider,
+    )
+    .with_config(EngineConfig {
+        min_net_profit_wei: U256::ZERO,
+        persistence_policy: policy,
+        ..Default::default()
+    });
+    (engine, cycle_ids.try_into().unwrap())
+}
+
+/// Runs blocks 1 to 3 with A profitable in all of them and B only in block 2, returning
+/// each block's reported cycles with their persistence, and how many were suppressed.
+async fn run_blocks(engine: &ArbitrageEngine<DynProvider>) -> Vec<(Vec<(CycleId, u64)>, usize)> {
+    let mut reported = Vec::new();
+    for block in 1..=3 {
+        let overrides: HashMap<_, _> = path_snapshots(PATH_A, true)
+            .into_iter()
+            .chain(path_snapshots(PATH_B, block == 2))
+            .collect();
+        let solutions = engine
+            .find_opportunities_with_overrides(Some(block), overrides)
+            .await;
+        reported.push((
+            solutions
+                .into_iter()
+                .map(|solution| (solution.cycle_id, solution.persistence_blocks))
+                .collect(),
+            engine.last_stats().persistence_suppressed,
+        ));
+    }
+    reported
+}
+
+#[tokio::test]
+async fn test_persistence_counters() {
+    let (engine, [a, b]) = engine(PersistencePolicy::ReportAll).await;
+    let reported = run_blocks(&engine).await;
+    assert_eq!(reported[0].0, [(a.clone(), 1)]);
+    // Both are equally mispriced in block 2, so the tie is broken by cycle id.
+    assert_eq!(reported[1].0, [(a.clone(), 2), (b.clone(), 1)]);
+    assert_eq!(reported[2].0, [(a.clone(), 3)]);
+
+    let record = engine.persistence.record(&a).unwrap();
+    assert_eq!(record.first_seen_block, Some(1));
+    assert_eq!(record.consecutive_blocks, 3);
+    assert!(!record.peak_net_profit.is_zero());
+    // B's streak ended in block 3.
+    assert_eq!(engine.persistence.record(&b), None);
+    assert_eq!(engine.persistence.longest(5).len(), 1);
+}
+
+#[tokio::test]
+async fn test_new_paths_suppressed() {
+    let (engine, [a, _]) = engine(PersistencePolicy::Suppress { min_blocks: 2 }).await;
+    let reported = run_blocks(&engine).await;
+    assert_eq!(reported[0], (vec![], 1));
+    // B is profitable but new in block 2.
+    assert_eq!(reported[1], (vec![(a.clone(), 2)], 1));
+    assert_eq!(reported[2], (vec![(a, 3)], 0));
+}
+
+#[test]
+fn test_tracker_is_bounded() {
+    let tracker = PersistenceTracker::new(1);
+    let (old, new) = (
+        CycleId(vec![]),
+        CycleId(vec![(Address::ZERO, Address::ZERO)]),
+    );
+    tracker.update(Some(1), &[(old.clone(), U256::from(1))]);
+    let streaks = tracker.update(
+        Some(2),
+        &[(old.clone(), U256::from(1)), (new.clone(), U256::from(5))],
+    );
+    assert_eq!(streaks[&old], 2);
+    assert_eq!(streaks[&new], 1);
+    // Only the longest streak is kept.
+    assert!(tracker.record(&old).is_some());
+    assert_eq!(tracker.record(&new), None);
+}
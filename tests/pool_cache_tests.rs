@@ -0,0 +1,91 @@
+mod common;
+
+use alloy::transports::mock::Asserter;
+use alloy_primitives::{Address, LogData, aliases::U112};
+use alloy_provider::{Provider, ProviderBuilder};
+use alloy_rpc_types::Log;
+use alloy_sol_types::sol;
+use arbrs::pool::LiquidityPool;
+use arbrs::pool::cache::{CacheConfig, CacheStats, DEFAULT_CACHE_MAX_BLOCKS};
+use arbrs::pool::strategy::StandardV2Logic;
+use arbrs::pool::uniswap_v2::UniswapV2Pool;
+use common::token;
+use std::sync::Arc;
+
+sol! {
+    interface IUniswapV2Pair {
+        event Sync(uint112 reserve0, uint112 reserve1);
+    }
+}
+
+type DynProvider = dyn Provider + Send + Sync;
+
+const POOL: Address = Address::repeat_byte(0x01);
+const BLOCKS: u64 = 10_000;
+
+fn pool() -> UniswapV2Pool<DynProvider, StandardV2Logic> {
+    let provider: Arc<DynProvider> =
+        Arc::new(ProviderBuilder::new().connect_mocked_client(Asserter::new()));
+    UniswapV2Pool::new(
+        POOL,
+        token(Address::repeat_byte(0x0a), provider.clone()),
+        token(Address::repeat_byte(0x0b), provider.clone()),
+        provider,
+        StandardV2Logic,
+    )
+}
+
+fn sync_log(block_number: u64) -> Log {
+    let event = IUniswapV2Pair::Sync {
+        reserve0: U112::from(1_000 + block_number),
+        reserve1: U112::from(2_000 + block_number),
+    };
+    Log {
+        inner: alloy_primitives::Log {
+            address: POOL,
+            data: LogData::from(&event),
+        },
+        block_number: Some(block_number),
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn test_state_cache_keeps_the_newest_blocks_up_to_the_cap() {
+    let pool = pool().with_cache_config(CacheConfig { max_blocks: 100 });
+    for block in 1..=BLOCKS {
+        pool.apply_sync_log(&sync_log(block)).await.unwrap();
+    }
+
+    let cached = pool.cached_states().await;
+    assert_eq!(cached.len(), 100);
+    assert_eq!(
+        cached.keys().copied().collect::<Vec<_>>(),
+        (BLOCKS - 99..=BLOCKS).collect::<Vec<_>>()
+    );
+    assert_eq!(cached[&BLOCKS].reserve0.to::<u64>(), 1_000 + BLOCKS);
+    assert_eq!(
+        pool.cache_stats().await,
+        CacheStats {
+            entries: 100,
+            oldest_block: Some(BLOCKS - 99),
+            newest_block: Some(BLOCKS),
+        }
+    );
+
+    pool.discard_states_before_block(BLOCKS - 9).await;
+    assert_eq!(pool.cache_stats().await.entries, 10);
+    assert_eq!(pool.cache_stats().await.oldest_block, Some(BLOCKS - 9));
+}
+
+#[tokio::test]
+async fn test_state_cache_is_bounded_by_default() {
+    let pool = pool();
+    for block in 1..=BLOCKS {
+        pool.apply_sync_log(&sync_log(block)).await.unwrap();
+    }
+
+    let stats = pool.cache_stats().await;
+    assert_eq!(stats.entries, DEFAULT_CACHE_MAX_BLOCKS);
+    assert_eq!(stats.newest_block, Some(BLOCKS));
+}
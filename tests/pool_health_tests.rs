@@ -0,0 +1,188 @@
+mod common;
+
+use alloy::transports::mock::Asserter;
+use alloy_primitives::{Address, address, aliases::U112};
+use alloy_provider::{Provider, ProviderBuilder};
+use alloy_sol_types::{SolCall, sol};
+use arbrs::arbitrage::cache::ArbitrageCache;
+use arbrs::arbitrage::cycle::ArbitrageCycle;
+use arbrs::arbitrage::engine::ArbitrageEngine;
+use arbrs::arbitrage::health::{PoolHealth, PoolHealthConfig};
+use arbrs::arbitrage::types::ArbitragePath;
+use arbrs::manager::token_manager::TokenManager;
+use arbrs::pool::LiquidityPool;
+use arbrs::pool::strategy::StandardV2Logic;
+use arbrs::pool::uniswap_v2::UniswapV2Pool;
+use common::token;
+use std::collections::HashSet;
+use std::sync::Arc;
+
+type DynProvider = dyn Provider + Send + Sync;
+
+const WETH: Address = address!("C02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2");
+const GOOD_POOLS: [Address; 2] = [Address::repeat_byte(0x01), Address::repeat_byte(0x02)];
+const BAD_POOL: Address = Address::repeat_byte(0x0b);
+
+sol! {
+    function getReserves() external view returns (uint112 reserve0, uint112 reserve1, uint32 blockTimestampLast);
+}
+
+fn mocked(asserter: &Asserter) -> Arc<DynProvider> {
+    Arc::new(ProviderBuilder::new().connect_mocked_client(asserter.clone()))
+}
+
+fn push_reserves(asserter: &Asserter) {
+    let reserve = U112::from(1_000_000_000_000_000_000_000u128);
+    asserter.push_success(&getReservesCall::abi_encode_returns(&getReservesReturn {
+        reserve0: reserve,
+        reserve1: reserve,
+        blockTimestampLast: 0,
+    }));
+}
+
+/// Two pools that answer every snapshot call and one that answers none, each on its own
+/// mocked provider, cached as a healthy path and a path through the bad pool.
+struct Setup {
+    engine: ArbitrageEngine<DynProvider>,
+    good: [Asserter; 2],
+    bad: Asserter,
+}
+
+async fn setup() -> Setup {
+    let provider = mocked(&Asserter::new());
+    let (weth, other) = (
+        token(WETH, provider.clone()),
+        token(Address::repeat_byte(0xee), provider.clone()),
+    );
+    let pool = |address: Address, asserter: &Asserter| {
+        Arc::new(UniswapV2Pool::new(
+            address,
+            weth.clone(),
+            other.clone(),
+            mocked(asserter),
+            StandardV2Logic,
+        )) as Arc<dyn LiquidityPool<DynProvider>>
+    };
+    let (good, bad) = ([Asserter::new(), Asserter::new()], Asserter::new());
+    let good_pools = [pool(GOOD_POOLS[0], &good[0]), pool(GOOD_POOLS[1], &good[1])];
+    let bad_pool = pool(BAD_POOL, &bad);
+
+    let cache = Arc::new(ArbitrageCache::new());
+    for pools in [good_pools.to_vec(), vec![good_pools[0].clone(), bad_pool]] {
+        cache
+            .add_path(Arc::new(ArbitrageCycle::new(ArbitragePath {
+                pools,
+                path: vec![weth.clone(), other.clone(), weth.clone()],
+                profit_token: weth.clone(),
+            })))
+            .await;
+    }
+    let engine = ArbitrageEngine::new(
+        cache,
+        Arc::new(TokenManager::in_memory(provider.clone(), 1)),
+        provider,
+    )
+    .with_pool_health(Arc::new(PoolHealth::new(PoolHealthConfig {
+        failure_threshold: 2,
+        initial_backoff: 2,
+        max_backoff: 100,
+    })));
+    Setup { engine, good, bad }
+}
+
+impl Setup {
+    /// Runs one evaluation, the good pools answering their snapshot calls. Returns the
+    /// number of pools snapshotted and of paths skipped for quarantine.
+    async fn evaluate(&self) -> (usize, usize) {
+        self.good.iter().for_each(push_reserves);
+        self.engine.find_opportunities(Some(1)).await;
+        let stats = self.engine.last_stats();
+        (stats.pools, stats.quarantine_skips)
+    }
+}
+
+#[tokio::test]
+async fn test_paths_excluding_skips_paths_through_the_pools() {
+    let setup = setup().await;
+    let cache = &setup.engine.cache;
+    assert_eq!(cache.paths_excluding(&HashSet::new()).await.len(), 2);
+    let healthy = cache.paths_excluding(&HashSet::from([BAD_POOL])).await;
+    assert_eq!(healthy.len(), 1);
+    assert_eq!(healthy[0].get_involved_pools(), GOOD_POOLS);
+    assert!(
+        cache
+            .paths_excluding(&HashSet::from([GOOD_POOLS[0]]))
+            .await
+            .is_empty()
+    );
+}
+
+#[tokio::test]
+async fn test_failing_pool_is_quarantined_and_retried_with_backoff() {
+    let setup = setup().await;
+    let engine = &setup.engine;
+
+    // Below the threshold the bad pool is still snapshotted.
+    assert_eq!(setup.evaluate().await, (3, 0));
+    assert_eq!(engine.last_stats().failed_snapshots, 1);
+    assert!(engine.quarantined_pools().is_empty());
+    assert_eq!(setup.evaluate().await, (3, 0));
+    assert_eq!(engine.quarantined_pools(), HashSet::from([BAD_POOL]));
+
+    // Quarantined: its path is skipped, the healthy one still evaluated.
+    assert_eq!(setup.evaluate().await, (2, 1));
+    assert_eq!(engine.last_stats().failed_snapshots, 0);
+    assert_eq!(engine.last_stats().paths, 2);
+
+    // The retry fails, doubling the wait before the next one.
+    assert_eq!(setup.evaluate().await, (3, 0));
+    for _ in 0..3 {
+        assert_eq!(setup.evaluate().await, (2, 1));
+    }
+    let record = engine.pool_health.record(&BAD_POOL).unwrap();
+    assert_eq!(record.consecutive_failures, 3);
+    assert!(record.is_quarantined());
+
+    // Once it answers again it's released.
+    push_reserves(&setup.bad);
+    assert_eq!(setup.evaluate().await, (3, 0));
+    assert!(engine.quarantined_pools().is_empty());
+    assert_eq!(engine.pool_health.record(&BAD_POOL), None);
+    assert_eq!(setup.evaluate().await, (3, 0));
+    assert!(engine.pool_health.record(&GOOD_POOLS[0]).is_none());
+}
+
+#[cfg(feature = "db")]
+#[tokio::test]
+async fn test_quarantine_persists_across_restarts() {
+    let db_manager = arbrs::db::DbManager::new("sqlite::memory:").await.unwrap();
+    let config = PoolHealthConfig {
+        failure_threshold: 2,
+        initial_backoff: 4,
+        max_backoff: 100,
+    };
+    let health = PoolHealth::new(config);
+    health.start_evaluation();
+    health.record_failure(BAD_POOL);
+    health.record_failure(GOOD_POOLS[0]);
+    health.start_evaluation();
+    health.record_failure(BAD_POOL);
+    assert_eq!(health.start_evaluation(), HashSet::from([BAD_POOL]));
+    health.save(&db_manager).await.unwrap();
+
+    // The restarted tracker's evaluations start over, the retry is still three away.
+    let restarted = PoolHealth::new(config);
+    restarted.load(&db_manager).await.unwrap();
+    assert_eq!(restarted.quarantined(), HashSet::from([BAD_POOL]));
+    assert_eq!(
+        restarted
+            .record(&GOOD_POOLS[0])
+            .unwrap()
+            .consecutive_failures,
+        1
+    );
+    for _ in 0..2 {
+        assert_eq!(restarted.start_evaluation(), HashSet::from([BAD_POOL]));
+    }
+    assert!(restarted.start_evaluation().is_empty());
+}
@@ -0,0 +1,518 @@
+#![cfg(feature = "db")]
+
+mod common;
+
+use alloy::transports::mock::Asserter;
+use alloy_primitives::{Address, B256, Bytes, U256, address};
+use alloy_provider::{Provider, ProviderBuilder};
+use alloy_sol_types::{SolCall, sol};
+use arbrs::ArbRsError;
+use arbrs::TokenLike;
+use arbrs::balancer::pool::{BalancerPool, BalancerPoolKind};
+use arbrs::core::multicall::{Result3, aggregate3Call};
+use arbrs::core::token::Token;
+use arbrs::curve::constants::A_PRECISION;
+use arbrs::curve::pool::CurveStableswapPool;
+use arbrs::curve::pool_attributes::{PoolAttributes, PoolVariant, SwapStrategyType};
+use arbrs::db::DbManager;
+use arbrs::dex::{PoolKind, UNISWAP_V2_FACTORY};
+use arbrs::manager::balancer_pool_manager::BalancerPoolManager;
+use arbrs::manager::curve_pool_manager::CurvePoolManager;
+use arbrs::manager::pool_factory::PoolFactoryRegistry;
+use arbrs::manager::token_manager::TokenManager;
+use arbrs::manager::uniswap_v2_pool_manager::UniswapV2PoolManager;
+use arbrs::manager::uniswap_v3_pool_manager::{UNISWAP_V3_FACTORY, UniswapV3PoolManager};
+use arbrs::pool::LiquidityPool;
+use common::{attributes, named_token};
+use std::sync::Arc;
+
+const VAULT: Address = address!("BA12222222228d8Ba445958a75a0704d566BF2C8");
+const BALANCER_POOL: Address = address!("5c6Ee304399DBdB9C8Ef030aB642B10820DB8F56");
+const SWAP_FEE: U256 = U256::from_limbs([3_000_000_000_000_000, 0, 0, 0]);
+const BASE_POOL: Address = address!("bEbc44782C7dB0a1A60Cb6fe97d0b483032FF1C7");
+const META_POOL: Address = address!("Ed279fDD11cA84bEef15AF5D39BB4d4bEE23F0cA");
+const WETH: Address = address!("C02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2");
+const USDC: Address = address!("A0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48");
+const USDC_WETH_V2: Address = address!("B4e16d0168e52d35CaCD2c6185b44281Ec28C9Dc");
+const WETH_USDT_V2: Address = address!("0d4a11d5EEaaC28EC3F61d100daF4d40471f1852");
+const USDC_WETH_005_V3: Address = address!("88e6A0c2dDD26FEEb64F039a2c41296FcB3f5640");
+const USDC_WETH_030_V3: Address = address!("8ad599c3A0ff1De082011EFDDc58f1908eb6e6D8");
+type DynProvider = dyn Provider + Send + Sync;
+
+// Return encodings for the calls the mock answers.
+sol! {
+    function getSwapFeePercentage() external view returns (uint256);
+    function getPoolId() external view returns (bytes32);
+    function coins(uint256 i) external view returns (address);
+}
+
+struct Fixture {
+    asserter: Asserter,
+    provider: Arc<DynProvider>,
+    token_manager: Arc<TokenManager<DynProvider>>,
+    db_manager: Arc<DbManager>,
+}
+
+async fn setup() -> Fixture {
+    let asserter = Asserter::new();
+    let provider: Arc<DynProvider> =
+        Arc::new(ProviderBuilder::new().connect_mocked_client(asserter.clone()));
+    let db_manager = Arc::new(DbManager::new("sqlite::memory:").await.unwrap());
+    let token_manager = Arc::new(TokenManager::new(provider.clone(), 1, db_manager.clone()));
+    Fixture {
+        asserter,
+        provider,
+        token_manager,
+        db_manager,
+    }
+}
+
+fn token(fixture: &Fixture, byte: u8) -> Arc<Token<DynProvider>> {
+    let address = Address::repeat_byte(byte);
+    named_token(address, &format!("TKN{byte}"), 18, fixture.provider.clone())
+}
+
+fn word(value: U256) -> Result3 {
+    Result3 {
+        success: true,
+        returnData: Bytes::from(value.to_be_bytes::<32>()),
+    }
+}
+
+/// Answers `fetch_a_state`'s multicall: `A()`, then the ramp, which reverts unless given.
+fn push_a_state(asserter: &Asserter, a: u64, ramp: Option<[u64; 4]>) {
+    let mut results = vec![word(U256::from(a))];
+    match ramp {
+        Some(ramp) => results.extend(ramp.map(|value| word(U256::from(value)))),
+        None => results.extend((0..4).map(|_| Result3 {
+            success: false,
+            returnData: Bytes::new(),
+        })),
+    }
+    asserter.push_success(&Bytes::from(aggregate3Call::abi_encode_returns(&results)));
+}
+
+fn curve_attributes(coins: Vec<Address>, base_pool_address: Option<Address>) -> PoolAttributes {
+    let n_coins = coins.len();
+    PoolAttributes {
+        pool_variant: if base_pool_address.is_some() {
+            PoolVariant::Meta
+        } else {
+            PoolVariant::Plain
+        },
+        base_pool_address,
+        raw_coin_addresses: coins,
+        ..attributes(n_coins, SwapStrategyType::Default)
+    }
+}
+
+/// Stores a Curve pool as `new` leaves it: its coins, completed attributes and LP token.
+async fn store_curve_pool(
+    fixture: &Fixture,
+    address: Address,
+    coins: &[Arc<Token<DynProvider>>],
+    lp_token: &Arc<Token<DynProvider>>,
+    base_pool_address: Option<Address>,
+) {
+    let db = &fixture.db_manager;
+    db.save_pool(address, &PoolKind::CurveStable, coins, None, None)
+        .await
+        .unwrap();
+    db.save_token(lp_token).await.unwrap();
+    let attributes = curve_attributes(
+        coins.iter().map(|coin| coin.address()).collect(),
+        base_pool_address,
+    );
+    db.update_pool_attributes(address, &serde_json::to_string(&attributes).unwrap())
+        .await
+        .unwrap();
+    db.update_pool_lp_token(address, lp_token.address())
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn test_stored_balancer_pool_only_reads_its_fee() {
+    let fixture = setup().await;
+    let tokens = vec![token(&fixture, 0x0a), token(&fixture, 0x0b)];
+    let weights = vec![
+        U256::from(800_000_000_000_000_000u64),
+        U256::from(200_000_000_000_000_000u64),
+    ];
+    let pool_id = B256::repeat_byte(0x11);
+    let stored = BalancerPool::from_parts(
+        BALANCER_POOL,
+        fixture.provider.clone(),
+        tokens.clone(),
+        weights.clone(),
+        U256::ZERO,
+        VAULT,
+        pool_id.0,
+    );
+    fixture
+        .db_manager
+        .save_pool(
+            BALANCER_POOL,
+            &PoolKind::BalancerWeighted,
+            &tokens,
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+    fixture
+        .db_manager
+        .update_balancer_metadata(BALANCER_POOL, pool_id, VAULT, &stored.kind_json())
+        .await
+        .unwrap();
+    let record = fixture
+        .db_manager
+        .load_pool(BALANCER_POOL)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(record.balancer_pool_id, Some(pool_id));
+    assert_eq!(record.balancer_vault, Some(VAULT));
+
+    let manager = BalancerPoolManager::new(
+        fixture.token_manager.clone(),
+        fixture.provider.clone(),
+        fixture.db_manager.clone(),
+        0,
+    );
+    // The stored pool id is checked against the pool's before the fee is read.
+    fixture
+        .asserter
+        .push_success(&Bytes::from(getPoolIdCall::abi_encode_returns(&pool_id)));
+    fixture
+        .asserter
+        .push_success(&Bytes::from(getSwapFeePercentageCall::abi_encode_returns(
+            &SWAP_FEE,
+        )));
+    let pool = manager.build_pool_from_record(&record).await.unwrap();
+    assert!(fixture.asserter.read_q().is_empty());
+
+    let pool = pool
+        .as_any()
+        .downcast_ref::<BalancerPool<DynProvider>>()
+        .unwrap();
+    assert_eq!(pool.fee(), SWAP_FEE);
+    assert_eq!(pool.vault(), VAULT);
+    assert_eq!(pool.pool_id, pool_id.0);
+    assert_eq!(pool.kind(), &BalancerPoolKind::Weighted { weights });
+    assert_eq!(
+        pool.get_all_tokens()
+            .iter()
+            .map(|token| token.address())
+            .collect::<Vec<_>>(),
+        vec![Address::repeat_byte(0x0a), Address::repeat_byte(0x0b)]
+    );
+}
+
+#[tokio::test]
+async fn test_balancer_record_without_metadata_is_refused_by_from_record() {
+    let fixture = setup().await;
+    let tokens = vec![token(&fixture, 0x0a), token(&fixture, 0x0b)];
+    fixture
+        .db_manager
+        .save_pool(
+            BALANCER_POOL,
+            &PoolKind::BalancerWeighted,
+            &tokens,
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+    let record = fixture
+        .db_manager
+        .load_pool(BALANCER_POOL)
+        .await
+        .unwrap()
+        .unwrap();
+
+    let result = BalancerPool::from_record(
+        &record,
+        fixture.provider.clone(),
+        fixture.token_manager.clone(),
+    )
+    .await;
+    assert!(
+        matches!(result, Err(arbrs::ArbRsError::InvalidPool(address, _)) if address == BALANCER_POOL)
+    );
+}
+
+#[tokio::test]
+async fn test_stored_curve_metapool_reads_only_a_of_each_pool() {
+    let fixture = setup().await;
+    let base_coins = vec![
+        token(&fixture, 0x01),
+        token(&fixture, 0x02),
+        token(&fixture, 0x03),
+    ];
+    let base_lp = token(&fixture, 0x04);
+    let meta_coins = vec![token(&fixture, 0x05), base_lp.clone()];
+    store_curve_pool(&fixture, BASE_POOL, &base_coins, &base_lp, None).await;
+    store_curve_pool(
+        &fixture,
+        META_POOL,
+        &meta_coins,
+        &token(&fixture, 0x06),
+        Some(BASE_POOL),
+    )
+    .await;
+    let record = fixture
+        .db_manager
+        .load_pool(META_POOL)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(record.lp_token, Some(Address::repeat_byte(0x06)));
+
+    let manager = CurvePoolManager::new(
+        fixture.token_manager.clone(),
+        fixture.provider.clone(),
+        0,
+        fixture.db_manager.clone(),
+    );
+    // The metapool's record is checked against its first coin. Then the base pool is
+    // built, then the metapool, which is ramping `A`.
+    fixture
+        .asserter
+        .push_success(&Bytes::from(coinsCall::abi_encode_returns(
+            &Address::repeat_byte(0x05),
+        )));
+    push_a_state(&fixture.asserter, 2_000, None);
+    push_a_state(
+        &fixture.asserter,
+        100,
+        Some([100, 1_700_000_000, 200, 1_700_086_400]),
+    );
+    let pool = manager.build_pool_from_record(&record).await.unwrap();
+    assert!(fixture.asserter.read_q().is_empty());
+
+    let pool = pool
+        .as_any()
+        .downcast_ref::<CurveStableswapPool<DynProvider>>()
+        .unwrap();
+    assert_eq!(*pool.a.read().await, U256::from(100));
+    assert_eq!(pool.lp_token.address(), Address::repeat_byte(0x06));
+    let base_pool = pool.base_pool.as_ref().unwrap();
+    assert_eq!(base_pool.address, BASE_POOL);
+    assert_eq!(*base_pool.a.read().await, U256::from(2_000));
+    // Halfway through the ramp from 100 to 200.
+    assert_eq!(
+        pool.a_precise(1_700_043_200).await.unwrap(),
+        U256::from(150)
+    );
+    assert_eq!(
+        base_pool.a_precise(1_700_043_200).await.unwrap(),
+        U256::from(2_000) * A_PRECISION
+    );
+    assert_eq!(
+        pool.underlying_tokens
+            .iter()
+            .map(|token| token.address())
+            .collect::<Vec<_>>(),
+        [0x05, 0x01, 0x02, 0x03].map(Address::repeat_byte)
+    );
+}
+
+#[tokio::test]
+async fn test_v2_and_v3_records_must_sit_at_their_create2_address() {
+    let fixture = setup().await;
+    let tokens: Vec<_> = [USDC, WETH]
+        .into_iter()
+        .map(|address| common::token(address, fixture.provider.clone()))
+        .collect();
+    let db = &fixture.db_manager;
+    // The USDC/WETH pair stored under the USDT pair's address, and the 0.05% pool's tier
+    // under the 0.3% pool's.
+    db.save_pool(
+        WETH_USDT_V2,
+        &PoolKind::UniswapV2,
+        &tokens,
+        Some(3_000),
+        None,
+    )
+    .await
+    .unwrap();
+    db.save_pool(
+        USDC_WETH_030_V3,
+        &PoolKind::UniswapV3,
+        &tokens,
+        Some(500),
+        Some(10),
+    )
+    .await
+    .unwrap();
+    let records = db.load_all_pools().await.unwrap();
+    assert_eq!(records.len(), 2);
+
+    let v2_manager = UniswapV2PoolManager::new(
+        fixture.token_manager.clone(),
+        fixture.provider.clone(),
+        UNISWAP_V2_FACTORY,
+        0,
+    );
+    let v3_manager = UniswapV3PoolManager::new(
+        fixture.token_manager.clone(),
+        fixture.provider.clone(),
+        1,
+        0,
+        UNISWAP_V3_FACTORY,
+    );
+    let curve_manager = CurvePoolManager::new(
+        fixture.token_manager.clone(),
+        fixture.provider.clone(),
+        0,
+        fixture.db_manager.clone(),
+    );
+    let balancer_manager = BalancerPoolManager::new(
+        fixture.token_manager.clone(),
+        fixture.provider.clone(),
+        fixture.db_manager.clone(),
+        0,
+    );
+    let registry = PoolFactoryRegistry::for_managers(
+        &v2_manager,
+        &v3_manager,
+        &curve_manager,
+        &balancer_manager,
+    );
+    for record in &records {
+        let result = registry.build(record).await;
+        assert!(
+            matches!(&result, Err(ArbRsError::AddressMismatch(address, _)) if *address == record.address),
+            "{result:?}"
+        );
+    }
+    // Neither check needed a call.
+    assert!(fixture.asserter.read_q().is_empty());
+
+    // The pair's own address derives from Uniswap's factory.
+    v2_manager
+        .verify_record(USDC_WETH_V2, WETH, USDC, &PoolKind::UniswapV2)
+        .await
+        .unwrap();
+    assert_eq!(
+        v3_manager
+            .build_pool(USDC_WETH_005_V3, WETH, USDC, 3_000, 60)
+            .await
+            .err()
+            .map(|e| e.class()),
+        Some("invalid_pool")
+    );
+    let unchecked = UniswapV2PoolManager::new(
+        fixture.token_manager.clone(),
+        fixture.provider.clone(),
+        UNISWAP_V2_FACTORY,
+        0,
+    )
+    .with_verify_on_hydrate(false);
+    unchecked
+        .verify_record(WETH_USDT_V2, WETH, USDC, &PoolKind::UniswapV2)
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn test_curve_record_must_hold_the_pools_first_coin() {
+    let fixture = setup().await;
+    let coins = vec![
+        token(&fixture, 0x01),
+        token(&fixture, 0x02),
+        token(&fixture, 0x03),
+    ];
+    store_curve_pool(&fixture, BASE_POOL, &coins, &token(&fixture, 0x04), None).await;
+    let record = fixture
+        .db_manager
+        .load_pool(BASE_POOL)
+        .await
+        .unwrap()
+        .unwrap();
+    let manager = CurvePoolManager::new(
+        fixture.token_manager.clone(),
+        fixture.provider.clone(),
+        0,
+        fixture.db_manager.clone(),
+    );
+
+    fixture
+        .asserter
+        .push_success(&Bytes::from(coinsCall::abi_encode_returns(
+            &Address::repeat_byte(0x09),
+        )));
+    let result = manager.build_pool_from_record(&record).await;
+    assert!(matches!(
+        result,
+        Err(ArbRsError::AddressMismatch(BASE_POOL, _))
+    ));
+    assert!(manager.get_all_pools().is_empty());
+
+    // Without the check, the record is trusted as it is.
+    let manager = manager.with_verify_on_hydrate(false);
+    push_a_state(&fixture.asserter, 2_000, None);
+    manager.build_pool_from_record(&record).await.unwrap();
+    assert!(fixture.asserter.read_q().is_empty());
+}
+
+#[tokio::test]
+async fn test_balancer_record_must_carry_the_pools_id() {
+    let fixture = setup().await;
+    let tokens = vec![token(&fixture, 0x0a), token(&fixture, 0x0b)];
+    let stored = BalancerPool::from_parts(
+        BALANCER_POOL,
+        fixture.provider.clone(),
+        tokens.clone(),
+        vec![U256::from(500_000_000_000_000_000u64); 2],
+        U256::ZERO,
+        VAULT,
+        [0x11; 32],
+    );
+    fixture
+        .db_manager
+        .save_pool(
+            BALANCER_POOL,
+            &PoolKind::BalancerWeighted,
+            &tokens,
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+    fixture
+        .db_manager
+        .update_balancer_metadata(
+            BALANCER_POOL,
+            B256::repeat_byte(0x11),
+            VAULT,
+            &stored.kind_json(),
+        )
+        .await
+        .unwrap();
+    let record = fixture
+        .db_manager
+        .load_pool(BALANCER_POOL)
+        .await
+        .unwrap()
+        .unwrap();
+    let manager = BalancerPoolManager::new(
+        fixture.token_manager.clone(),
+        fixture.provider.clone(),
+        fixture.db_manager.clone(),
+        0,
+    );
+
+    fixture
+        .asserter
+        .push_success(&Bytes::from(getPoolIdCall::abi_encode_returns(
+            &B256::repeat_byte(0x22),
+        )));
+    let result = manager.build_pool_from_record(&record).await;
+    assert!(matches!(
+        result,
+        Err(ArbRsError::AddressMismatch(BALANCER_POOL, _))
+    ));
+    assert!(fixture.asserter.read_q().is_empty());
+}
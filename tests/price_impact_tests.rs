@@ -0,0 +1,172 @@
+mod common;
+
+use alloy::transports::mock::Asserter;
+use alloy_primitives::{Address, U256, address};
+use alloy_provider::{Provider, ProviderBuilder};
+use arbrs::arbitrage::cache::ArbitrageCache;
+use arbrs::arbitrage::cycle::ArbitrageCycle;
+use arbrs::arbitrage::engine::{ArbitrageEngine, EngineConfig};
+use arbrs::arbitrage::impact::{DEFAULT_MAX_HOP_IMPACT_BPS, hop_metrics};
+use arbrs::arbitrage::types::{ArbitragePath, ArbitrageSolution};
+use arbrs::core::token::Token;
+use arbrs::manager::token_manager::TokenManager;
+use arbrs::pool::strategy::StandardV2Logic;
+use arbrs::pool::uniswap_v2::{UniswapV2Pool, UniswapV2PoolState};
+use arbrs::pool::{LiquidityPool, PoolSnapshot};
+use common::{ether, token};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+type DynProvider = dyn Provider + Send + Sync;
+
+const WETH: Address = address!("C02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2");
+
+fn provider() -> Arc<DynProvider> {
+    Arc::new(ProviderBuilder::new().connect_mocked_client(Asserter::new()))
+}
+
+fn reserves(reserve0: u64, reserve1: u64) -> PoolSnapshot {
+    PoolSnapshot::UniswapV2(UniswapV2PoolState {
+        reserve0: ether(reserve0),
+        reserve1: ether(reserve1),
+        block_number: 1,
+    })
+}
+
+fn v2_pool(
+    byte: u8,
+    token0: &Arc<Token<DynProvider>>,
+    token1: &Arc<Token<DynProvider>>,
+    provider: Arc<DynProvider>,
+) -> Arc<dyn LiquidityPool<DynProvider>> {
+    Arc::new(UniswapV2Pool::new(
+        Address::repeat_byte(byte),
+        token0.clone(),
+        token1.clone(),
+        provider,
+        StandardV2Logic,
+    ))
+}
+
+/// Impact of selling `amount_in` into a 30 bps pair holding `reserve_in`: the marginal
+/// price falls from `r_out / r_in` to `(r_out - out) / (r_in + in)`, where
+/// `r_out - out = r_out * r_in * 1000 / (r_in * 1000 + in * 997)`.
+fn closed_form_impact_bps(reserve_in: f64, amount_in: f64) -> f64 {
+    let ratio = reserve_in * reserve_in * 1000.0
+        / ((reserve_in + amount_in) * (reserve_in * 1000.0 + amount_in * 997.0));
+    (1.0 - ratio) * 10_000.0
+}
+
+#[test]
+fn test_v2_hop_impact_matches_closed_form() {
+    let provider = provider();
+    let (a, b) = (
+        token(Address::repeat_byte(0xaa), provider.clone()),
+        token(Address::repeat_byte(0xbb), provider.clone()),
+    );
+    let pool = v2_pool(0x01, &a, &b, provider);
+    let snapshot = reserves(1_000, 2_000);
+
+    let amount_in = ether(10);
+    let amount_out = pool.calculate_tokens_out(&a, &b, amount_in, &snapshot).unwrap();
+    let metrics = hop_metrics(pool.as_ref(), &a, &b, amount_in, amount_out, &snapshot).unwrap();
+
+    assert!((metrics.pre_trade_price - 2.0).abs() < 1e-12);
+    let expected_bps = closed_form_impact_bps(1_000.0, 10.0);
+    assert!((metrics.price_impact_bps - expected_bps).abs() < 1e-6, "{metrics:?}");
+    assert!((metrics.post_trade_price - 2.0 * (1.0 - expected_bps / 10_000.0)).abs() < 1e-12);
+}
+
+#[test]
+fn test_v2_hop_impact_selling_token1() {
+    let provider = provider();
+    let (a, b) = (
+        token(Address::repeat_byte(0xaa), provider.clone()),
+        token(Address::repeat_byte(0xbb), provider.clone()),
+    );
+    let pool = v2_pool(0x01, &a, &b, provider);
+    let snapshot = reserves(1_000, 2_000);
+
+    let amount_in = ether(500);
+    let amount_out = pool.calculate_tokens_out(&b, &a, amount_in, &snapshot).unwrap();
+    let metrics = hop_metrics(pool.as_ref(), &b, &a, amount_in, amount_out, &snapshot).unwrap();
+
+    assert!((metrics.pre_trade_price - 0.5).abs() < 1e-12);
+    let expected_bps = closed_form_impact_bps(2_000.0, 500.0);
+    assert!((metrics.price_impact_bps - expected_bps).abs() < 1e-6, "{metrics:?}");
+    // A quarter of the pool's side moves the price by well over a third.
+    assert!(metrics.price_impact_bps > 3_500.0);
+}
+
+/// Evaluates a two-pool cycle whose optimal input is close to a tenth of the first pool's
+/// WETH, which moves both pools by well over 5%.
+async fn evaluate(max_hop_impact_bps: Option<u64>) -> (Vec<ArbitrageSolution<DynProvider>>, usize) {
+    let provider = provider();
+    let (weth, other) = (
+        token(WETH, provider.clone()),
+        token(Address::repeat_byte(0xee), provider.clone()),
+    );
+    let pools = vec![
+        v2_pool(0x01, &weth, &other, provider.clone()),
+        v2_pool(0x02, &weth, &other, provider.clone()),
+    ];
+
+    let cache = Arc::new(ArbitrageCache::new());
+    cache
+        .add_path(Arc::new(ArbitrageCycle::new(ArbitragePath {
+            pools,
+            path: vec![weth.clone(), other, weth.clone()],
+            profit_token: weth,
+        })))
+        .await;
+    let engine = ArbitrageEngine::new(
+        cache,
+        Arc::new(TokenManager::in_memory(provider.clone(), 1)),
+        provider,
+    )
+    .with_config(EngineConfig {
+        min_net_profit_wei: U256::ZERO,
+        flashloan_sources: HashMap::new(),
+        max_input_wei: ether(1_000),
+        max_hop_impact_bps,
+        ..Default::default()
+    });
+
+    let overrides = HashMap::from([
+        (Address::repeat_byte(0x01), reserves(1_200, 2_880_000)),
+        (Address::repeat_byte(0x02), reserves(1_200, 2_400_000)),
+    ]);
+    let solutions = engine
+        .find_opportunities_with_overrides(Some(1), overrides)
+        .await;
+    (solutions, engine.last_stats().impact_rejects)
+}
+
+#[tokio::test]
+async fn test_solutions_report_their_worst_hop_impact() {
+    let (solutions, impact_rejects) = evaluate(None).await;
+    assert_eq!(solutions.len(), 1);
+    assert_eq!(impact_rejects, 0);
+
+    let solution = &solutions[0];
+    assert_eq!(solution.hop_metrics.len(), solution.swap_actions.len());
+    let impacts: Vec<f64> = solution
+        .hop_metrics
+        .iter()
+        .map(|metrics| metrics.unwrap().price_impact_bps)
+        .collect();
+    assert!(impacts.iter().all(|impact| *impact > 0.0));
+    assert_eq!(solution.max_hop_impact_bps(), impacts.iter().copied().fold(0.0, f64::max));
+    assert!(solution.max_hop_impact_bps() > DEFAULT_MAX_HOP_IMPACT_BPS as f64);
+}
+
+#[tokio::test]
+async fn test_hop_impact_cap_drops_solutions() {
+    let (solutions, impact_rejects) = evaluate(Some(DEFAULT_MAX_HOP_IMPACT_BPS)).await;
+    assert!(solutions.is_empty());
+    assert_eq!(impact_rejects, 1);
+
+    let (solutions, impact_rejects) = evaluate(Some(5_000)).await;
+    assert_eq!(solutions.len(), 1);
+    assert_eq!(impact_rejects, 0);
+}
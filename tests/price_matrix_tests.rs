@@ -0,0 +1,205 @@
+mod common;
+
+use alloy::transports::mock::Asserter;
+use alloy_primitives::{Address, Bytes, U256, address};
+use alloy_provider::{Provider, ProviderBuilder};
+use alloy_sol_types::{SolCall, sol};
+use arbrs::TokenLike;
+use arbrs::balancer::pool::BalancerPool;
+use arbrs::core::token::Token;
+use arbrs::curve::pool::CurveStableswapPool;
+use arbrs::curve::pool_attributes::{PoolAttributes, SwapStrategyType};
+use arbrs::curve::types::CurvePoolSnapshot;
+use arbrs::manager::token_manager::TokenManager;
+use arbrs::pool::strategy::StandardV2Logic;
+use arbrs::pool::uniswap_v2::{UniswapV2Pool, UniswapV2PoolState};
+use arbrs::pool::{LiquidityPool, PoolSnapshot};
+use common::{attributes, named_token, token};
+use std::sync::Arc;
+
+const VAULT: Address = address!("BA12222222228d8Ba445958a75a0704d566BF2C8");
+const BALANCER_POOL: Address = address!("5c6Ee304399DBdB9C8Ef030aB642B10820DB8F56");
+type DynProvider = dyn Provider + Send + Sync;
+
+// Return encodings for the calls the mock answers.
+sol! {
+    function getPoolTokens(bytes32 poolId) external view returns (address[] tokens, uint256[] balances, uint256 lastChangeBlock);
+    function getPausedState() external view returns (bool paused, uint256 pauseWindowEndTime, uint256 bufferPeriodEndTime);
+    function inRecoveryMode() external view returns (bool);
+    function getSwapFeePercentage() external view returns (uint256);
+}
+
+fn pow10(exp: u8) -> U256 {
+    U256::from(10).pow(U256::from(exp))
+}
+
+fn push_call_result(asserter: &Asserter, encoded: Vec<u8>) {
+    asserter.push_success(&Bytes::from(encoded));
+}
+
+fn paused_state(paused: bool) -> Vec<u8> {
+    getPausedStateCall::abi_encode_returns(&getPausedStateReturn {
+        paused,
+        pauseWindowEndTime: U256::ZERO,
+        bufferPeriodEndTime: U256::ZERO,
+    })
+}
+
+/// Queues the responses of one `get_snapshot`, in the order the calls are issued.
+fn push_snapshot(asserter: &Asserter, tokens: &[Arc<Token<DynProvider>>], balances: &[U256]) {
+    push_call_result(asserter, paused_state(false));
+    push_call_result(
+        asserter,
+        getPoolTokensCall::abi_encode_returns(&getPoolTokensReturn {
+            tokens: tokens.iter().map(|t| t.address()).collect(),
+            balances: balances.to_vec(),
+            lastChangeBlock: U256::ZERO,
+        }),
+    );
+    push_call_result(asserter, paused_state(false));
+    push_call_result(asserter, inRecoveryModeCall::abi_encode_returns(&false));
+    push_call_result(
+        asserter,
+        getSwapFeePercentageCall::abi_encode_returns(&U256::from(3_000_000_000_000_000u64)),
+    );
+}
+
+fn assert_close(actual: f64, expected: f64) {
+    assert!(
+        ((actual - expected) / expected).abs() < 1e-12,
+        "{actual} != {expected}"
+    );
+}
+
+#[tokio::test]
+async fn test_balancer_matrix_from_one_snapshot() {
+    let asserter = Asserter::new();
+    let provider: Arc<DynProvider> =
+        Arc::new(ProviderBuilder::new().connect_mocked_client(asserter.clone()));
+    let tokens: Vec<_> = (1..=8)
+        .map(|byte| token(Address::repeat_byte(byte), provider.clone()))
+        .collect();
+    let percent = U256::from(10_000_000_000_000_000u64);
+    let weights: Vec<U256> = [5, 10, 15, 20, 10, 10, 15, 15]
+        .into_iter()
+        .map(|weight| percent * U256::from(weight))
+        .collect();
+    let balances: Vec<U256> = [1, 3, 7, 20, 150, 900, 4_000, 25_000]
+        .into_iter()
+        .map(|balance| U256::from(balance) * U256::from(10).pow(U256::from(18)))
+        .collect();
+    let pool = BalancerPool::from_parts(
+        BALANCER_POOL,
+        provider,
+        tokens.clone(),
+        weights,
+        U256::from(3_000_000_000_000_000u64),
+        VAULT,
+        [0x11; 32],
+    );
+
+    push_snapshot(&asserter, &tokens, &balances);
+    let matrix = pool.fetch_prices_matrix(Some(1)).await.unwrap();
+    // All 56 ordered pairs come from the single queued snapshot.
+    assert_eq!(matrix.len(), 56);
+    assert!(asserter.read_q().is_empty());
+
+    for token_in in &tokens {
+        for token_out in tokens.iter().filter(|t| t.address() != token_in.address()) {
+            push_snapshot(&asserter, &tokens, &balances);
+            let price = pool.absolute_price(token_in, token_out).await.unwrap();
+            assert_close(matrix[&(token_in.address(), token_out.address())], price);
+        }
+    }
+    assert!(asserter.read_q().is_empty());
+}
+
+#[test]
+fn test_v2_matrix_is_reserve_ratio() {
+    let provider: Arc<DynProvider> =
+        Arc::new(ProviderBuilder::new().connect_mocked_client(Asserter::new()));
+    let (token0, token1) = (
+        token(Address::repeat_byte(0x0a), provider.clone()),
+        token(Address::repeat_byte(0x0b), provider.clone()),
+    );
+    let pool = UniswapV2Pool::new(
+        Address::repeat_byte(0x01),
+        token0.clone(),
+        token1.clone(),
+        provider,
+        StandardV2Logic,
+    );
+    let snapshot = PoolSnapshot::UniswapV2(UniswapV2PoolState {
+        reserve0: U256::from(1_000),
+        reserve1: U256::from(2_400_000),
+        block_number: 1,
+    });
+
+    let matrix = pool.prices_matrix(&snapshot);
+    assert_eq!(matrix.len(), 2);
+    assert_close(matrix[&(token0.address(), token1.address())], 2_400.0);
+    assert_close(matrix[&(token1.address(), token0.address())], 1.0 / 2_400.0);
+}
+
+#[test]
+fn test_curve_matrix_prices_an_18_to_6_decimal_pair() {
+    let provider: Arc<DynProvider> =
+        Arc::new(ProviderBuilder::new().connect_mocked_client(Asserter::new()));
+    let decimals = [18u8, 6];
+    let tokens: Vec<_> = [0x0a, 0x0b]
+        .into_iter()
+        .zip(decimals)
+        .map(|(byte, decimals)| {
+            named_token(
+                Address::repeat_byte(byte),
+                "TKN",
+                decimals,
+                provider.clone(),
+            )
+        })
+        .collect();
+    let rates: Vec<U256> = decimals.iter().map(|d| pow10(36 - d)).collect();
+    let attributes = PoolAttributes {
+        rates: rates.clone(),
+        precision_multipliers: decimals.iter().map(|d| pow10(18 - d)).collect(),
+        ..attributes(2, SwapStrategyType::Default)
+    };
+    let pool = CurveStableswapPool::from_parts(
+        Address::repeat_byte(0x01),
+        tokens[0].clone(),
+        tokens.clone(),
+        provider.clone(),
+        Arc::new(TokenManager::in_memory(provider, 1)),
+        attributes,
+    );
+    // An imbalanced pool with A = 200 and no fee, so quotes track the spot price.
+    let snapshot = PoolSnapshot::Curve(CurvePoolSnapshot {
+        balances: vec![
+            U256::from(3_000_000) * pow10(18),
+            U256::from(1_000_000) * pow10(6),
+        ],
+        a: U256::from(20_000),
+        fee: U256::ZERO,
+        rates,
+        ..Default::default()
+    });
+
+    let matrix = pool.prices_matrix(&snapshot);
+    assert_eq!(matrix.len(), 2);
+    for (token_in, token_out) in [(&tokens[0], &tokens[1]), (&tokens[1], &tokens[0])] {
+        let price = matrix[&(token_in.address(), token_out.address())];
+        assert!(price > 0.0);
+
+        let amount_in = pow10(token_in.decimals());
+        let quoted = pool
+            .calculate_tokens_out(token_in, token_out, amount_in, &snapshot)
+            .unwrap();
+        let quoted = quoted.to::<u128>() as f64 / amount_in.to::<u128>() as f64;
+        assert!(
+            ((price - quoted) / quoted).abs() < 1e-5,
+            "{price} != {quoted}"
+        );
+    }
+    // The abundant coin is the cheaper one.
+    assert!(matrix[&(tokens[0].address(), tokens[1].address())] < 1e-12);
+}
@@ -0,0 +1,132 @@
+//! The quoting core with no database: these tests also run under `--no-default-features`,
+//! where sqlx is not compiled in at all.
+mod common;
+
+use alloy::transports::mock::Asserter;
+use alloy_primitives::{Address, U64, U256, address};
+use alloy_provider::{Provider, ProviderBuilder};
+use arbrs::arbitrage::engine::ArbitrageEngine;
+use arbrs::dex::DexVariant;
+use arbrs::manager::token_manager::TokenManager;
+use arbrs::manager::uniswap_v2_pool_manager::UniswapV2PoolManager;
+use arbrs::manager::uniswap_v3_pool_manager::UniswapV3PoolManager;
+use arbrs::pool::strategy::StandardV2Logic;
+use arbrs::pool::uniswap_v2::{UniswapV2Pool, UniswapV2PoolState};
+use arbrs::pool::{LiquidityPool, PoolSnapshot};
+use common::{named_token, token};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+const FORK_RPC_URL: &str = "http://127.0.0.1:8545";
+const FORK_BLOCK: u64 = 19_000_000;
+const WETH: Address = address!("C02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2");
+const UNISWAP_V2_WETH_USDC: Address = address!("B4e16d0168e52d35CaCD2c6185b44281Ec28C9Dc");
+const SUSHISWAP_WETH_USDC: Address = address!("397FF1542f962076d0BFE58ea045ffa2d3473aee");
+const UNISWAP_V3_WETH_USDC_500: Address = address!("88e6A0c2dDD26FEEb64F039a2c41296FcB3f5640");
+type DynProvider = dyn Provider + Send + Sync;
+
+fn reserves(weth: u64, other: u64) -> PoolSnapshot {
+    let ether = U256::from(10).pow(U256::from(18));
+    PoolSnapshot::UniswapV2(UniswapV2PoolState {
+        reserve0: U256::from(weth) * ether,
+        reserve1: U256::from(other) * ether,
+        block_number: 1,
+    })
+}
+
+#[tokio::test]
+async fn test_quote_only_engine_from_pool_list() {
+    let asserter = Asserter::new();
+    let provider: Arc<DynProvider> =
+        Arc::new(ProviderBuilder::new().connect_mocked_client(asserter.clone()));
+    let (weth, other) = (
+        named_token(WETH, "WETH", 18, provider.clone()),
+        token(Address::repeat_byte(0xee), provider.clone()),
+    );
+    let pools: Vec<Arc<dyn LiquidityPool<DynProvider>>> = [0x01, 0x02]
+        .into_iter()
+        .map(|byte| {
+            Arc::new(UniswapV2Pool::new(
+                Address::repeat_byte(byte),
+                weth.clone(),
+                other.clone(),
+                provider.clone(),
+                StandardV2Logic,
+            )) as Arc<dyn LiquidityPool<DynProvider>>
+        })
+        .collect();
+
+    // Only the chain id: the finder resolves tokens from the ones the pools were built with.
+    asserter.push_success(&U64::from(1));
+    let mut manager = UniswapV2PoolManager::new_static(
+        Arc::new(TokenManager::in_memory(provider.clone(), 1)),
+        provider.clone(),
+        pools.clone(),
+    );
+    assert!(
+        manager
+            .discover_pools_in_range(100)
+            .await
+            .unwrap()
+            .is_empty()
+    );
+    assert_eq!(manager.get_all_pools().len(), 2);
+
+    let engine = ArbitrageEngine::quote_only(provider.clone(), pools)
+        .await
+        .unwrap();
+    assert!(asserter.read_q().is_empty());
+    let paths = engine.cache.paths.read().await.len();
+    assert!(paths > 0);
+
+    // No responses are left, so snapshots come from the overrides and nothing else is read.
+    // The cycle is found as 0x01 -> 0x02, so 0x01 is the pool that pays more for WETH.
+    let overrides = HashMap::from([
+        (Address::repeat_byte(0x01), reserves(1_000, 2_400_000)),
+        (Address::repeat_byte(0x02), reserves(1_000, 2_000_000)),
+    ]);
+    let solutions = engine
+        .find_opportunities_with_overrides(Some(1), overrides)
+        .await;
+    assert_eq!(solutions.len(), 1);
+    assert!(solutions[0].net_profit.raw > U256::ZERO);
+    assert_eq!(engine.last_stats().paths, paths);
+}
+
+#[tokio::test]
+async fn test_quote_only_engine_on_fork() {
+    let provider: Arc<DynProvider> =
+        Arc::new(ProviderBuilder::new().connect_http(FORK_RPC_URL.parse().unwrap()));
+    let token_manager = Arc::new(TokenManager::in_memory(provider.clone(), 1));
+
+    let v2_manager = UniswapV2PoolManager::new_static(token_manager.clone(), provider.clone(), []);
+    let v3_manager =
+        UniswapV3PoolManager::new_static(token_manager.clone(), provider.clone(), 1, []);
+    v2_manager
+        .add_pool_by_address(UNISWAP_V2_WETH_USDC, DexVariant::UniswapV2)
+        .await
+        .unwrap();
+    v2_manager
+        .add_pool_by_address(SUSHISWAP_WETH_USDC, DexVariant::SushiSwap)
+        .await
+        .unwrap();
+    v3_manager
+        .add_pool_by_address(UNISWAP_V3_WETH_USDC_500)
+        .await
+        .unwrap();
+
+    let pools: Vec<_> = v2_manager
+        .get_all_pools()
+        .into_iter()
+        .chain(v3_manager.get_all_pools())
+        .collect();
+    assert_eq!(pools.len(), 3);
+
+    let engine = ArbitrageEngine::quote_only(provider, pools).await.unwrap();
+    assert!(!engine.cache.paths.read().await.is_empty());
+
+    engine.find_opportunities(Some(FORK_BLOCK)).await;
+    let stats = engine.last_stats();
+    assert_eq!(stats.pools, 3);
+    assert_eq!(stats.failed_snapshots, 0);
+}
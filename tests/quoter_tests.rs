@@ -0,0 +1,327 @@
+mod common;
+
+use alloy::transports::mock::Asserter;
+use alloy_primitives::{Address, U256};
+use alloy_provider::{Provider, ProviderBuilder};
+use arbrs::ArbRsError;
+use arbrs::balancer::pool::{BalancerPool, BalancerPoolKind, BalancerPoolSnapshot};
+use arbrs::core::token::{NATIVE_ETH_ADDRESS, NativeTokenData, Token, TokenLike, WETH_ADDRESS};
+use arbrs::curve::constants::FEE_DENOMINATOR;
+use arbrs::curve::pool::CurveStableswapPool;
+use arbrs::curve::pool_attributes::{CalculationStrategy, PoolAttributes, SwapStrategyType};
+use arbrs::curve::pool_overrides::DVariant;
+use arbrs::curve::types::CurvePoolSnapshot;
+use arbrs::manager::token_manager::TokenManager;
+use arbrs::pool::quoter::{QuotePool, Quoter};
+use arbrs::pool::strategy::StandardV2Logic;
+use arbrs::pool::uniswap_v2::{UniswapV2Pool, UniswapV2PoolState};
+use arbrs::pool::uniswap_v3::{UniswapV3Pool, UniswapV3PoolSnapshot};
+use arbrs::pool::wrapped_native::WrappedNativePool;
+use arbrs::pool::{LiquidityPool, PoolSnapshot};
+use common::{attributes, ether, named_token, token};
+use std::sync::Arc;
+
+type DynProvider = dyn Provider + Send + Sync;
+
+const POOL: Address = Address::repeat_byte(0x01);
+
+fn provider() -> Arc<DynProvider> {
+    Arc::new(ProviderBuilder::new().connect_mocked_client(Asserter::new()))
+}
+
+/// Asserts the pool's quoter quotes `amounts` both ways between `a` and `b` exactly as the
+/// pool does, errors included.
+fn assert_quotes_match(
+    pool: &dyn LiquidityPool<DynProvider>,
+    snapshot: &PoolSnapshot,
+    a: &Token<DynProvider>,
+    b: &Token<DynProvider>,
+    amounts: &[U256],
+) {
+    let quoter = pool.to_quoter(snapshot).unwrap();
+    assert_eq!(quoter.address(), pool.address());
+    for (token_in, token_out) in [(a, b), (b, a)] {
+        for &amount in amounts {
+            assert_eq!(
+                quoter.calculate_out(token_in.address(), token_out.address(), amount),
+                pool.calculate_tokens_out(token_in, token_out, amount, snapshot),
+                "{amount} in"
+            );
+            assert_eq!(
+                quoter.calculate_in(token_in.address(), token_out.address(), amount),
+                pool.calculate_tokens_in(token_in, token_out, amount, snapshot),
+                "{amount} out"
+            );
+        }
+    }
+}
+
+#[test]
+fn test_uniswap_v2_quoter_matches_the_pool() {
+    let provider = provider();
+    let (token0, token1) = (
+        token(Address::repeat_byte(0x0a), provider.clone()),
+        named_token(Address::repeat_byte(0x0b), "TKN", 6, provider.clone()),
+    );
+    let pool = UniswapV2Pool::new(
+        POOL,
+        token0.clone(),
+        token1.clone(),
+        provider,
+        StandardV2Logic,
+    );
+    let snapshot = PoolSnapshot::UniswapV2(UniswapV2PoolState {
+        reserve0: ether(1_000),
+        reserve1: U256::from(2_000_000_000_000u64),
+        block_number: 1,
+    });
+    let amounts = [1, 1_000_000, 10u64.pow(18), 2 * 10u64.pow(12), u64::MAX].map(U256::from);
+    assert_quotes_match(&pool, &snapshot, &token0, &token1, &amounts);
+
+    // The quoter holds no provider, so it quotes from any thread.
+    let quoter = pool.to_quoter(&snapshot).unwrap();
+    let (a, b) = (token0.address(), token1.address());
+    let out = std::thread::spawn(move || quoter.calculate_out(a, b, ether(1)))
+        .join()
+        .unwrap();
+    assert_eq!(
+        out,
+        pool.calculate_tokens_out(&token0, &token1, ether(1), &snapshot)
+    );
+}
+
+#[test]
+fn test_uniswap_v3_quoter_matches_the_pool() {
+    let provider = provider();
+    let (token0, token1) = (
+        token(Address::repeat_byte(0x0a), provider.clone()),
+        token(Address::repeat_byte(0x0b), provider.clone()),
+    );
+    let pool = UniswapV3Pool::new(
+        POOL,
+        token0.clone(),
+        token1.clone(),
+        3_000,
+        60,
+        provider,
+        None,
+    );
+    let snapshot = PoolSnapshot::UniswapV3(UniswapV3PoolSnapshot {
+        sqrt_price_x96: U256::ONE << 96,
+        tick: 0,
+        liquidity: 10u128.pow(24),
+        ..Default::default()
+    });
+    // The last amount is more than the pool holds, a partial fill either way.
+    let amounts = [
+        U256::from(1_000),
+        ether(1),
+        ether(10_000),
+        ether(10_000_000),
+    ];
+    assert_quotes_match(&pool, &snapshot, &token0, &token1, &amounts);
+}
+
+fn curve_attributes(
+    swap_strategy: SwapStrategyType,
+    is_native: Vec<bool>,
+    raw_coin_addresses: Vec<Address>,
+) -> PoolAttributes {
+    PoolAttributes {
+        strategy: CalculationStrategy::Legacy,
+        d_variant: DVariant::Legacy,
+        offpeg_fee_multiplier: Some(FEE_DENOMINATOR * U256::from(2)),
+        is_native,
+        raw_coin_addresses,
+        ..attributes(2, swap_strategy)
+    }
+}
+
+fn curve_pool(
+    provider: Arc<DynProvider>,
+    tokens: Vec<Arc<Token<DynProvider>>>,
+    attributes: PoolAttributes,
+) -> CurveStableswapPool<DynProvider> {
+    CurveStableswapPool::from_parts(
+        POOL,
+        tokens[0].clone(),
+        tokens,
+        provider.clone(),
+        Arc::new(TokenManager::in_memory(provider, 1)),
+        attributes,
+    )
+}
+
+fn curve_snapshot() -> PoolSnapshot {
+    PoolSnapshot::Curve(CurvePoolSnapshot {
+        balances: vec![ether(3_000_000), ether(1_000_000)],
+        a: U256::from(100),
+        fee: U256::from(4_000_000),
+        admin_fee: Some(FEE_DENOMINATOR / U256::from(2)),
+        rates: vec![ether(1); 2],
+        ..Default::default()
+    })
+}
+
+#[test]
+fn test_curve_quoter_matches_the_pool() {
+    let provider = provider();
+    let tokens = vec![
+        token(Address::repeat_byte(0x0a), provider.clone()),
+        token(Address::repeat_byte(0x0b), provider.clone()),
+    ];
+    let amounts = [U256::from(1_000), ether(1), ether(10_000), ether(900_000)];
+    for swap_strategy in [
+        SwapStrategyType::Default,
+        SwapStrategyType::DynamicFee,
+        SwapStrategyType::AdminFee,
+    ] {
+        let pool = curve_pool(
+            provider.clone(),
+            tokens.clone(),
+            curve_attributes(swap_strategy, Vec::new(), Vec::new()),
+        );
+        assert_quotes_match(&pool, &curve_snapshot(), &tokens[0], &tokens[1], &amounts);
+    }
+
+    // A token the pool doesn't list fails the same way.
+    let pool = curve_pool(
+        provider.clone(),
+        tokens.clone(),
+        curve_attributes(SwapStrategyType::Default, Vec::new(), Vec::new()),
+    );
+    let stranger = token(Address::repeat_byte(0x0c), provider);
+    assert_quotes_match(&pool, &curve_snapshot(), &tokens[0], &stranger, &[ether(1)]);
+}
+
+#[test]
+fn test_curve_quoter_resolves_native_ether_as_the_pool_does() {
+    let provider = provider();
+    let (weth, steth) = (
+        token(WETH_ADDRESS, provider.clone()),
+        token(Address::repeat_byte(0x0b), provider.clone()),
+    );
+    let native = Token::Native(Arc::new(NativeTokenData::new(
+        1,
+        NATIVE_ETH_ADDRESS,
+        provider.clone(),
+    )));
+    let pool = curve_pool(
+        provider,
+        vec![weth.clone(), steth.clone()],
+        curve_attributes(
+            SwapStrategyType::Default,
+            vec![true, false],
+            vec![NATIVE_ETH_ADDRESS, steth.address()],
+        ),
+    );
+    let amounts = [ether(1), ether(10_000)];
+    assert_quotes_match(&pool, &curve_snapshot(), &native, &steth, &amounts);
+    assert_quotes_match(&pool, &curve_snapshot(), &weth, &steth, &amounts);
+}
+
+fn balancer_tokens(provider: &Arc<DynProvider>) -> Vec<Arc<Token<DynProvider>>> {
+    vec![
+        token(Address::repeat_byte(0x0a), provider.clone()),
+        named_token(Address::repeat_byte(0x0b), "TKN", 6, provider.clone()),
+    ]
+}
+
+#[test]
+fn test_balancer_weighted_quoter_matches_the_pool() {
+    let provider = provider();
+    let tokens = balancer_tokens(&provider);
+    let pool = BalancerPool::from_parts(
+        POOL,
+        provider,
+        tokens.clone(),
+        vec![ether(8) / U256::from(10), ether(2) / U256::from(10)],
+        U256::from(3_000_000_000_000_000u64),
+        Address::repeat_byte(0xba),
+        [0x11; 32],
+    );
+    let mut snapshot = BalancerPoolSnapshot {
+        balances: vec![ether(4_000), U256::from(2_000_000_000_000u64)],
+        ..Default::default()
+    };
+    let amounts = [
+        U256::from(1_000),
+        U256::from(1_000_000),
+        ether(1),
+        ether(1_000),
+    ];
+    assert_quotes_match(
+        &pool,
+        &PoolSnapshot::Balancer(snapshot.clone()),
+        &tokens[0],
+        &tokens[1],
+        &amounts,
+    );
+
+    // The snapshot's fee wins over the pool's, and a paused pool refuses to quote.
+    snapshot.swap_fee = Some(U256::from(10_000_000_000_000_000u64));
+    snapshot.is_paused = true;
+    let snapshot = PoolSnapshot::Balancer(snapshot);
+    assert_quotes_match(&pool, &snapshot, &tokens[0], &tokens[1], &[ether(1)]);
+    assert_eq!(
+        pool.to_quoter(&snapshot).unwrap().calculate_out(
+            tokens[0].address(),
+            tokens[1].address(),
+            ether(1)
+        ),
+        Err(ArbRsError::PoolPaused(POOL))
+    );
+}
+
+#[test]
+fn test_balancer_stable_quoter_matches_the_pool() {
+    let provider = provider();
+    let tokens = balancer_tokens(&provider);
+    let pool = BalancerPool::from_parts(
+        POOL,
+        provider,
+        tokens.clone(),
+        Vec::new(),
+        U256::from(100_000_000_000_000u64),
+        Address::repeat_byte(0xba),
+        [0x11; 32],
+    )
+    .with_kind(BalancerPoolKind::Stable {
+        rate_providers: Vec::new(),
+    });
+    let snapshot = PoolSnapshot::Balancer(BalancerPoolSnapshot {
+        balances: vec![ether(10_000), U256::from(11_000_000_000u64)],
+        amplification: Some(U256::from(200_000)),
+        scaling_factors: Some(vec![ether(1), ether(10u64.pow(12))]),
+        ..Default::default()
+    });
+    let amounts = [
+        U256::from(1_000),
+        U256::from(1_000_000),
+        ether(1),
+        ether(1_000),
+    ];
+    assert_quotes_match(&pool, &snapshot, &tokens[0], &tokens[1], &amounts);
+}
+
+#[test]
+fn test_wrapped_native_quoter_matches_the_pool() {
+    let provider = provider();
+    let weth = token(WETH_ADDRESS, provider.clone());
+    let native = Arc::new(Token::Native(Arc::new(NativeTokenData::new(
+        1,
+        NATIVE_ETH_ADDRESS,
+        provider.clone(),
+    ))));
+    let pool = WrappedNativePool::new(weth.clone(), native.clone());
+    let snapshot = PoolSnapshot::WrappedNative;
+    assert_quotes_match(&pool, &snapshot, &native, &weth, &[U256::ONE, ether(1)]);
+
+    let quoter = pool.to_quoter(&snapshot).unwrap();
+    assert!(matches!(quoter, QuotePool::WrappedNative(_)));
+    let other = Address::repeat_byte(0x0b);
+    assert_eq!(
+        quoter.calculate_out(WETH_ADDRESS, other, ether(1)),
+        pool.calculate_tokens_out(&weth, &token(other, provider), ether(1), &snapshot)
+    );
+}
@@ -0,0 +1,161 @@
+mod common;
+
+use alloy::transports::mock::Asserter;
+use alloy_primitives::{Address, U256, address};
+use alloy_provider::{Provider, ProviderBuilder};
+use arbrs::TokenLike;
+use arbrs::arbitrage::cache::ArbitrageCache;
+use arbrs::arbitrage::cycle::ArbitrageCycle;
+use arbrs::arbitrage::engine::{ArbitrageEngine, EngineConfig, GasScenario, ScenarioGasPrice};
+use arbrs::arbitrage::types::ArbitragePath;
+use arbrs::core::token::Token;
+use arbrs::manager::token_manager::TokenManager;
+use arbrs::pool::strategy::StandardV2Logic;
+use arbrs::pool::uniswap_v2::{UniswapV2Pool, UniswapV2PoolState};
+use arbrs::pool::{LiquidityPool, PoolSnapshot};
+use common::token;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+type DynProvider = dyn Provider + Send + Sync;
+
+const WETH: Address = address!("C02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2");
+const DAI: Address = address!("6B175474E89094C44Da98b954EedeAC495271d0F");
+
+fn pool(
+    byte: u8,
+    token0: &Arc<Token<DynProvider>>,
+    token1: &Arc<Token<DynProvider>>,
+    provider: &Arc<DynProvider>,
+) -> Arc<dyn LiquidityPool<DynProvider>> {
+    Arc::new(UniswapV2Pool::new(
+        Address::repeat_byte(byte),
+        token0.clone(),
+        token1.clone(),
+        provider.clone(),
+        StandardV2Logic,
+    ))
+}
+
+fn reserves(reserve0: u64, reserve1: u64) -> PoolSnapshot {
+    let ether = U256::from(10).pow(U256::from(18));
+    PoolSnapshot::UniswapV2(UniswapV2PoolState {
+        reserve0: U256::from(reserve0) * ether,
+        reserve1: U256::from(reserve1) * ether,
+        block_number: 1,
+    })
+}
+
+#[tokio::test]
+async fn test_solutions_ranked_by_profit_in_weth() {
+    let provider: Arc<DynProvider> =
+        Arc::new(ProviderBuilder::new().connect_mocked_client(Asserter::new()));
+    let (weth, dai) = (token(WETH, provider.clone()), token(DAI, provider.clone()));
+    let other = token(Address::repeat_byte(0xee), provider.clone());
+
+    let cache = Arc::new(ArbitrageCache::new());
+    // A WETH cycle through a 10% gap, and a DAI cycle through a 5% gap between WETH/DAI
+    // pools, which also price DAI at about 2000 per ether. Both trade the 10 ether input cap.
+    cache
+        .add_path(Arc::new(ArbitrageCycle::new(ArbitragePath {
+            pools: vec![
+                pool(0x01, &weth, &other, &provider),
+                pool(0x02, &weth, &other, &provider),
+            ],
+            path: vec![weth.clone(), other.clone(), weth.clone()],
+            profit_token: weth.clone(),
+        })))
+        .await;
+    cache
+        .add_path(Arc::new(ArbitrageCycle::new(ArbitragePath {
+            pools: vec![
+                pool(0x04, &weth, &dai, &provider),
+                pool(0x03, &weth, &dai, &provider),
+            ],
+            path: vec![dai.clone(), weth.clone(), dai.clone()],
+            profit_token: dai.clone(),
+        })))
+        .await;
+
+    let engine = ArbitrageEngine::new(
+        cache,
+        Arc::new(TokenManager::in_memory(provider.clone(), 1)),
+        provider,
+    )
+    .with_config(EngineConfig {
+        min_net_profit_wei: U256::ZERO,
+        gas_scenarios: vec![GasScenario::new(
+            "base",
+            ScenarioGasPrice::Fixed(U256::ZERO),
+        )],
+        flashloan_sources: HashMap::new(),
+        max_input_wei: U256::from(10).pow(U256::from(19)),
+        ..Default::default()
+    });
+
+    let overrides = HashMap::from([
+        (Address::repeat_byte(0x01), reserves(1_000, 2_200_000)),
+        (Address::repeat_byte(0x02), reserves(1_000, 2_000_000)),
+        (Address::repeat_byte(0x03), reserves(1_000, 2_100_000)),
+        (Address::repeat_byte(0x04), reserves(1_000, 2_000_000)),
+    ]);
+    let solutions = engine
+        .find_opportunities_with_overrides(Some(1), overrides)
+        .await;
+    assert_eq!(solutions.len(), 2);
+
+    let profit_token = |i: usize| {
+        solutions[i]
+            .path
+            .as_any()
+            .downcast_ref::<ArbitrageCycle<DynProvider>>()
+            .unwrap()
+            .path
+            .profit_token
+            .address()
+    };
+    // The DAI cycle makes more raw profit but less in WETH, so it ranks second.
+    assert_eq!((profit_token(0), profit_token(1)), (WETH, DAI));
+    assert!(solutions[1].net_profit.raw > solutions[0].net_profit.raw);
+    assert!(solutions[0].net_profit_weth > solutions[1].net_profit_weth);
+    assert_eq!(solutions[0].net_profit_weth, solutions[0].net_profit.raw);
+}
+
+#[tokio::test]
+async fn test_profit_token_without_weth_rate_is_skipped() {
+    let provider: Arc<DynProvider> =
+        Arc::new(ProviderBuilder::new().connect_mocked_client(Asserter::new()));
+    let (profit_token, other) = (
+        token(Address::repeat_byte(0x0e), provider.clone()),
+        token(Address::repeat_byte(0xee), provider.clone()),
+    );
+
+    let cache = Arc::new(ArbitrageCache::new());
+    cache
+        .add_path(Arc::new(ArbitrageCycle::new(ArbitragePath {
+            pools: vec![
+                pool(0x01, &profit_token, &other, &provider),
+                pool(0x02, &profit_token, &other, &provider),
+            ],
+            path: vec![profit_token.clone(), other, profit_token.clone()],
+            profit_token,
+        })))
+        .await;
+    let engine = ArbitrageEngine::new(
+        cache,
+        Arc::new(TokenManager::in_memory(provider.clone(), 1)),
+        provider,
+    )
+    .with_min_net_profit(U256::ZERO);
+
+    // Profitable, but nothing prices the profit token in WETH.
+    let overrides = HashMap::from([
+        (Address::repeat_byte(0x01), reserves(1_000, 2_400_000)),
+        (Address::repeat_byte(0x02), reserves(1_000, 2_000_000)),
+    ]);
+    let solutions = engine
+        .find_opportunities_with_overrides(Some(1), overrides)
+        .await;
+    assert!(solutions.is_empty());
+    assert_eq!(engine.last_stats().unpriced_skips, 1);
+}
@@ -0,0 +1,187 @@
+mod common;
+
+use alloy::transports::mock::Asserter;
+use alloy_primitives::{Address, B256, U256, aliases::U112};
+use alloy_provider::{Provider, ProviderBuilder};
+use alloy_rpc_types::Log;
+use alloy_sol_types::{SolCall, sol};
+use arbrs::core::chain_tracker::ChainTracker;
+use arbrs::core::token::Token;
+use arbrs::curve::pool::CurveStableswapPool;
+use arbrs::curve::pool_attributes::SwapStrategyType;
+use arbrs::manager::token_manager::TokenManager;
+use arbrs::pool::state_updater::StateUpdater;
+use arbrs::pool::strategy::StandardV2Logic;
+use arbrs::pool::uniswap_v2::UniswapV2Pool;
+use arbrs::pool::{LiquidityPool, PoolSnapshot};
+use common::{attributes, token};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+type DynProvider = dyn Provider + Send + Sync;
+
+const POOL: Address = Address::repeat_byte(0x01);
+
+sol! {
+    function getReserves() external view returns (uint112 reserve0, uint112 reserve1, uint32 blockTimestampLast);
+    function D() external view returns (uint256);
+}
+
+fn mocked(asserter: &Asserter) -> Arc<DynProvider> {
+    Arc::new(ProviderBuilder::new().connect_mocked_client(asserter.clone()))
+}
+
+fn tokens(provider: &Arc<DynProvider>) -> [Arc<Token<DynProvider>>; 2] {
+    [0x0a, 0x0b].map(|byte| token(Address::repeat_byte(byte), provider.clone()))
+}
+
+fn push_reserves(asserter: &Asserter, reserve0: u64, reserve1: u64) {
+    asserter.push_success(&getReservesCall::abi_encode_returns(&getReservesReturn {
+        reserve0: U112::from(reserve0),
+        reserve1: U112::from(reserve1),
+        blockTimestampLast: 0,
+    }));
+}
+
+/// Feeds blocks 100 and 101, then a second block 101 on another branch.
+fn reorged_tracker() -> (ChainTracker, Option<u64>) {
+    let mut tracker = ChainTracker::new();
+    assert_eq!(
+        tracker.observe(100, B256::repeat_byte(0x10), B256::ZERO),
+        None
+    );
+    assert_eq!(
+        tracker.observe(101, B256::repeat_byte(0x11), B256::repeat_byte(0x10)),
+        None
+    );
+    let reorged_from = tracker.observe(101, B256::repeat_byte(0x21), B256::repeat_byte(0x10));
+    (tracker, reorged_from)
+}
+
+#[test]
+fn test_chain_tracker_detects_replaced_blocks() {
+    let (mut tracker, reorged_from) = reorged_tracker();
+    assert_eq!(reorged_from, Some(101));
+    assert_eq!(tracker.hash_of(101), Some(B256::repeat_byte(0x21)));
+
+    // A resubscription replaying the head isn't a reorg.
+    assert_eq!(
+        tracker.observe(101, B256::repeat_byte(0x21), B256::repeat_byte(0x10)),
+        None
+    );
+
+    // A head whose parent isn't the recorded block 101 replaced it too, and whatever followed.
+    assert_eq!(
+        tracker.observe(102, B256::repeat_byte(0x12), B256::repeat_byte(0x21)),
+        None
+    );
+    assert_eq!(
+        tracker.observe(102, B256::repeat_byte(0x32), B256::repeat_byte(0x31)),
+        Some(101)
+    );
+    assert_eq!(tracker.hash_of(101), None);
+    assert_eq!(tracker.hash_of(102), Some(B256::repeat_byte(0x32)));
+    assert_eq!(tracker.hash_of(100), Some(B256::repeat_byte(0x10)));
+}
+
+#[test]
+fn test_chain_tracker_forgets_blocks_past_its_depth() {
+    let mut tracker = ChainTracker::with_depth(2);
+    for number in 100..=103u8 {
+        tracker.observe(
+            number as u64,
+            B256::repeat_byte(number),
+            B256::repeat_byte(number - 1),
+        );
+    }
+    assert_eq!(tracker.hash_of(101), None);
+    assert_eq!(tracker.hash_of(102), Some(B256::repeat_byte(102)));
+    // Too deep to tell: block 101 is no longer known.
+    assert_eq!(
+        tracker.observe(101, B256::repeat_byte(0xff), B256::repeat_byte(100)),
+        None
+    );
+}
+
+#[tokio::test]
+async fn test_v2_pool_restores_state_from_before_reorg() {
+    let asserter = Asserter::new();
+    let provider = mocked(&asserter);
+    let [token0, token1] = tokens(&provider);
+    let pool = UniswapV2Pool::new(POOL, token0, token1, provider, StandardV2Logic);
+
+    push_reserves(&asserter, 1_000, 2_000);
+    pool.update_state_at_block(100, false).await.unwrap();
+    push_reserves(&asserter, 1_100, 1_900);
+    pool.update_state_at_block(101, false).await.unwrap();
+
+    let (_, reorged_from) = reorged_tracker();
+    pool.invalidate_from(reorged_from.unwrap()).await;
+    let state = pool.get_cached_reserves().await;
+    assert_eq!(
+        (state.reserve0, state.reserve1, state.block_number),
+        (U256::from(1_000), U256::from(2_000), 100)
+    );
+    assert_eq!(
+        pool.cached_states()
+            .await
+            .keys()
+            .copied()
+            .collect::<Vec<_>>(),
+        [100]
+    );
+
+    // The replacement block is read again rather than rejected as late.
+    push_reserves(&asserter, 1_200, 1_800);
+    pool.update_state_at_block(101, false).await.unwrap();
+    let state = pool.get_cached_reserves().await;
+    assert_eq!(
+        (state.reserve0, state.block_number),
+        (U256::from(1_200), 101)
+    );
+}
+
+#[tokio::test]
+async fn test_curve_block_caches_refetch_after_reorg() {
+    let asserter = Asserter::new();
+    let provider = mocked(&asserter);
+    let tokens = tokens(&provider);
+    let attributes = attributes(2, SwapStrategyType::Default);
+    let pool = CurveStableswapPool::from_parts(
+        POOL,
+        tokens[0].clone(),
+        tokens.to_vec(),
+        provider.clone(),
+        Arc::new(TokenManager::in_memory(provider, 1)),
+        attributes,
+    );
+
+    asserter.push_success(&DCall::abi_encode_returns(&U256::from(1_000)));
+    asserter.push_success(&DCall::abi_encode_returns(&U256::from(2_000)));
+    assert_eq!(pool.get_tricrypto_d(100).await.unwrap(), U256::from(1_000));
+    assert_eq!(pool.get_tricrypto_d(101).await.unwrap(), U256::from(2_000));
+    // Cached: no response is queued for a second read.
+    assert_eq!(pool.get_tricrypto_d(101).await.unwrap(), U256::from(2_000));
+
+    let (_, reorged_from) = reorged_tracker();
+    pool.invalidate_from(reorged_from.unwrap()).await;
+    asserter.push_success(&DCall::abi_encode_returns(&U256::from(3_000)));
+    assert_eq!(pool.get_tricrypto_d(101).await.unwrap(), U256::from(3_000));
+    assert_eq!(pool.get_tricrypto_d(100).await.unwrap(), U256::from(1_000));
+}
+
+#[tokio::test]
+async fn test_state_updater_drops_reorged_snapshots() {
+    let asserter = Asserter::new();
+    let provider = mocked(&asserter);
+    let updater = StateUpdater::new();
+    let snapshots = HashMap::from([(POOL, PoolSnapshot::UniswapV2(Default::default()))]);
+    asserter.push_success(&Vec::<Log>::new());
+    updater.update(provider.as_ref(), 100).await.unwrap();
+    updater.store_snapshots(100, &snapshots);
+    assert_eq!(updater.reusable_snapshots(100, [&POOL]).len(), 1);
+
+    updater.invalidate_from(100);
+    assert!(updater.is_dirty(&POOL));
+    assert!(updater.reusable_snapshots(100, [&POOL]).is_empty());
+}
@@ -0,0 +1,172 @@
+mod common;
+
+use alloy::transports::mock::Asserter;
+use alloy_primitives::{Address, Bytes, I256, U256, address};
+use alloy_provider::{Provider, ProviderBuilder};
+use arbrs::manager::token_manager::TokenManager;
+use arbrs::manager::uniswap_v2_pool_manager::UniswapV2PoolManager;
+use arbrs::pool::LiquidityPool;
+use arbrs::pool::reserve_drift::{
+    ReserveDrift, ReserveDriftConfig, SKIM_GAS_UNITS, SkimOpportunity,
+};
+use arbrs::pool::strategy::StandardV2Logic;
+use arbrs::pool::uniswap_v2::UniswapV2Pool;
+use common::{ether, token};
+use std::sync::Arc;
+
+type DynProvider = dyn Provider + Send + Sync;
+
+const WETH: Address = address!("C02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2");
+const OTHER: Address = Address::repeat_byte(0xee);
+const BLOCK: u64 = 1;
+const GAS_PRICE: u64 = 20_000_000_000;
+
+fn mocked(asserter: &Asserter) -> Arc<DynProvider> {
+    Arc::new(ProviderBuilder::new().connect_mocked_client(asserter.clone()))
+}
+
+fn push_words(asserter: &Asserter, words: &[U256]) {
+    asserter.push_success(&Bytes::from(
+        words
+            .iter()
+            .flat_map(|word| word.to_be_bytes::<32>())
+            .collect::<Vec<u8>>(),
+    ));
+}
+
+/// A WETH pair answering `getReserves()` with `reserves` and `balanceOf()` with
+/// `balances`. Each contract has its own mock, so the calls may come in any order.
+fn pair(
+    byte: u8,
+    reserves: (U256, U256),
+    balances: (U256, U256),
+) -> (
+    Arc<UniswapV2Pool<DynProvider, StandardV2Logic>>,
+    [Asserter; 3],
+) {
+    let asserters = [Asserter::new(), Asserter::new(), Asserter::new()];
+    push_words(&asserters[0], &[reserves.0, reserves.1, U256::ZERO]);
+    push_words(&asserters[1], &[balances.0]);
+    push_words(&asserters[2], &[balances.1]);
+    let pool = Arc::new(UniswapV2Pool::new(
+        Address::repeat_byte(byte),
+        token(WETH, mocked(&asserters[1])),
+        token(OTHER, mocked(&asserters[2])),
+        mocked(&asserters[0]),
+        StandardV2Logic,
+    ));
+    (pool, asserters)
+}
+
+#[tokio::test]
+async fn test_reserve_drift_is_balance_minus_reserve() {
+    let (pool, asserters) = pair(
+        0x01,
+        (U256::from(1_000), U256::from(2_000)),
+        (U256::from(1_100), U256::from(1_990)),
+    );
+    let (drift0, drift1) = pool.reserve_drift(BLOCK).await.unwrap();
+    assert_eq!(drift0, I256::try_from(100).unwrap());
+    assert_eq!(drift1, I256::try_from(-10).unwrap());
+    assert!(
+        asserters
+            .iter()
+            .all(|asserter| asserter.read_q().is_empty())
+    );
+}
+
+#[test]
+fn test_relative_drift_and_skim_value() {
+    // WETH is token1; 20 tokens over reserves at 2000 per WETH are worth 0.01 ETH.
+    let drift = ReserveDrift {
+        pool: Address::repeat_byte(0x01),
+        block: BLOCK,
+        token0: OTHER,
+        token1: WETH,
+        reserve0: ether(2_000),
+        reserve1: ether(1),
+        drift0: I256::from_raw(ether(20)),
+        drift1: I256::try_from(-5).unwrap(),
+    };
+    assert_eq!(drift.relative_bps(), 100);
+    assert!(drift.exceeds(99));
+    assert!(!drift.exceeds(100));
+    // Balances below reserves can't be skimmed.
+    assert_eq!(drift.skim_amounts(), (ether(20), U256::ZERO));
+    assert_eq!(drift.skim_value_wei(), Some(ether(1) / U256::from(100)));
+
+    // 60k gas at 20 gwei is 0.0012 ETH, below the value; at 200 gwei it's 0.012 ETH.
+    let gas_cost_wei = U256::from(SKIM_GAS_UNITS) * U256::from(GAS_PRICE);
+    let skim = drift.skim_opportunity(gas_cost_wei).unwrap();
+    assert_eq!(skim.gas_cost_wei, gas_cost_wei);
+    assert!(
+        drift
+            .skim_opportunity(gas_cost_wei * U256::from(10))
+            .is_none()
+    );
+
+    let no_weth = ReserveDrift {
+        token1: Address::repeat_byte(0xdd),
+        ..drift.clone()
+    };
+    assert_eq!(no_weth.skim_value_wei(), None);
+    let empty = ReserveDrift {
+        reserve1: U256::ZERO,
+        drift1: I256::ONE,
+        ..drift
+    };
+    assert_eq!(empty.relative_bps(), u64::MAX);
+}
+
+#[tokio::test]
+async fn test_sweep_flags_drifting_pools_and_reports_skims() {
+    let weth_reserve = ether(100);
+    let other_reserve = ether(200_000);
+    // Half an ETH over reserves (50 bps), and 0.001 ETH, below the 0.0012 ETH skim gas.
+    let (drifting, _drifting_mocks) = pair(
+        0x01,
+        (weth_reserve, other_reserve),
+        (weth_reserve + ether(1) / U256::from(2), other_reserve),
+    );
+    let (dusty, _dusty_mocks) = pair(
+        0x02,
+        (weth_reserve, other_reserve),
+        (weth_reserve + ether(1) / U256::from(1_000), other_reserve),
+    );
+    let provider = mocked(&Asserter::new());
+    let manager = UniswapV2PoolManager::new_static(
+        Arc::new(TokenManager::in_memory(provider.clone(), 1)),
+        provider,
+        [
+            drifting as Arc<dyn LiquidityPool<DynProvider>>,
+            dusty as Arc<dyn LiquidityPool<DynProvider>>,
+        ],
+    );
+
+    let config = ReserveDriftConfig {
+        threshold_bps: 40,
+        pools_per_sweep: 2,
+        ..Default::default()
+    };
+    let skims = manager
+        .sweep_reserve_drift(BLOCK, &config, U256::from(GAS_PRICE))
+        .await;
+    assert_eq!(
+        skims,
+        [SkimOpportunity {
+            pool: Address::repeat_byte(0x01),
+            block: BLOCK,
+            amount0: ether(1) / U256::from(2),
+            amount1: U256::ZERO,
+            value_wei: ether(1) / U256::from(2),
+            gas_cost_wei: U256::from(SKIM_GAS_UNITS) * U256::from(GAS_PRICE),
+        }]
+    );
+    assert_eq!(manager.drift_flagged_pools(), [Address::repeat_byte(0x01)]);
+    let recorded = manager.reserve_drift(Address::repeat_byte(0x02)).unwrap();
+    assert_eq!(
+        recorded.drift0,
+        I256::from_raw(ether(1) / U256::from(1_000))
+    );
+    assert_eq!(recorded.relative_bps(), 0);
+}
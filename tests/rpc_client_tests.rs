@@ -0,0 +1,210 @@
+mod common;
+
+use alloy::transports::mock::Asserter;
+use alloy::transports::{TransportErrorKind, TransportResult};
+use alloy_json_rpc::ErrorPayload;
+use alloy_network::Ethereum;
+use alloy_primitives::{Address, Bytes, TxKind, U64, U256};
+use alloy_provider::{
+    Caller, EthCall, EthCallManyParams, EthCallParams, Provider, ProviderBuilder, ProviderCall,
+    RootProvider,
+};
+use alloy_rpc_types::TransactionRequest;
+use alloy_sol_types::SolValue;
+use arbrs::ArbRsError;
+use arbrs::core::rpc_client::RpcClient;
+use arbrs::pool::strategy::StandardV2Logic;
+use arbrs::pool::uniswap_v2::UniswapV2Pool;
+use arbrs::pool::{LiquidityPool, PoolSnapshot};
+use common::token;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+type DynProvider = dyn Provider + Send + Sync;
+
+const SLOW_POOL: Address = Address::repeat_byte(0x01);
+const FAST_POOL: Address = Address::repeat_byte(0x02);
+const TIMEOUT: Duration = Duration::from_millis(200);
+
+/// A node that takes 10 seconds to answer any `eth_call` to `SLOW_POOL`.
+struct SlowProvider {
+    root: RootProvider,
+}
+
+impl Provider for SlowProvider {
+    fn root(&self) -> &RootProvider {
+        &self.root
+    }
+
+    fn call(&self, tx: TransactionRequest) -> EthCall<Ethereum, Bytes> {
+        EthCall::call(
+            SlowCaller {
+                root: self.root.clone(),
+            },
+            tx,
+        )
+    }
+}
+
+struct SlowCaller {
+    root: RootProvider,
+}
+
+impl Caller<Ethereum, Bytes> for SlowCaller {
+    fn call(
+        &self,
+        params: EthCallParams<Ethereum>,
+    ) -> TransportResult<ProviderCall<EthCallParams<Ethereum>, Bytes>> {
+        if params.data().to == Some(TxKind::Call(SLOW_POOL)) {
+            return Ok(ProviderCall::BoxedFuture(Box::pin(async {
+                tokio::time::sleep(Duration::from_secs(10)).await;
+                Err(TransportErrorKind::custom_str("answered too late"))
+            })));
+        }
+        Caller::<Ethereum, Bytes>::call(&self.root.weak_client(), params)
+    }
+
+    fn estimate_gas(
+        &self,
+        params: EthCallParams<Ethereum>,
+    ) -> TransportResult<ProviderCall<EthCallParams<Ethereum>, Bytes>> {
+        Caller::<Ethereum, Bytes>::estimate_gas(&self.root.weak_client(), params)
+    }
+
+    fn call_many(
+        &self,
+        params: EthCallManyParams<'_>,
+    ) -> TransportResult<ProviderCall<EthCallManyParams<'static>, Bytes>> {
+        Caller::<Ethereum, Bytes>::call_many(&self.root.weak_client(), params)
+    }
+}
+
+fn v2_pool(
+    address: Address,
+    provider: Arc<DynProvider>,
+) -> UniswapV2Pool<DynProvider, StandardV2Logic> {
+    let token = |byte: u8| token(Address::repeat_byte(byte), provider.clone());
+    UniswapV2Pool::new(
+        address,
+        token(0xaa),
+        token(0xbb),
+        provider.clone(),
+        StandardV2Logic,
+    )
+}
+
+fn reserves(reserve0: u64, reserve1: u64) -> Bytes {
+    (U256::from(reserve0), U256::from(reserve1), U256::ZERO)
+        .abi_encode()
+        .into()
+}
+
+/// The pool's snapshot, and how long after `started` it came back.
+async fn timed_snapshot(
+    pool: &UniswapV2Pool<DynProvider, StandardV2Logic>,
+    started: Instant,
+) -> (Result<PoolSnapshot, ArbRsError>, Duration) {
+    let result = pool.get_snapshot(Some(1)).await;
+    (result, started.elapsed())
+}
+
+#[tokio::test]
+async fn test_slow_pool_does_not_block_the_others() {
+    let asserter = Asserter::new();
+    let slow = SlowProvider {
+        root: ProviderBuilder::new()
+            .connect_mocked_client(asserter.clone())
+            .root()
+            .clone(),
+    };
+    let client = RpcClient::new(Arc::new(slow))
+        .with_timeout(TIMEOUT)
+        .with_max_retries(1)
+        .with_backoff(Duration::from_millis(10));
+    let metrics = client.metrics();
+    let provider: Arc<DynProvider> = Arc::new(client);
+    let slow_pool = v2_pool(SLOW_POOL, provider.clone());
+    let fast_pool = v2_pool(FAST_POOL, provider);
+    asserter.push_success(&reserves(1_000, 2_000));
+
+    let started = Instant::now();
+    let ((slow, slow_elapsed), (fast, fast_elapsed)) = tokio::join!(
+        timed_snapshot(&slow_pool, started),
+        timed_snapshot(&fast_pool, started)
+    );
+
+    let Ok(PoolSnapshot::UniswapV2(state)) = fast else {
+        panic!("expected the fast pool's reserves, got {fast:?}");
+    };
+    assert_eq!(state.reserve1, U256::from(2_000));
+    assert!(fast_elapsed < TIMEOUT, "took {fast_elapsed:?}");
+
+    // Two attempts of `TIMEOUT` each, with a backoff between them.
+    assert_eq!(
+        slow.unwrap_err(),
+        ArbRsError::RpcTimeout {
+            method: "eth_call".to_string(),
+            timeout: TIMEOUT,
+        }
+    );
+    assert!(
+        slow_elapsed < Duration::from_secs(1),
+        "took {slow_elapsed:?}"
+    );
+
+    let counters = metrics.snapshot();
+    assert_eq!(counters.calls, 2);
+    assert_eq!(counters.timeouts, 2);
+    assert_eq!(counters.retries, 1);
+    assert_eq!(counters.failures, 1);
+}
+
+#[tokio::test]
+async fn test_rate_limited_calls_are_retried() {
+    let asserter = Asserter::new();
+    let client = RpcClient::new(Arc::new(
+        ProviderBuilder::new().connect_mocked_client(asserter.clone()),
+    ))
+    .with_backoff(Duration::from_millis(1));
+    asserter.push_failure(ErrorPayload {
+        code: 429,
+        message: "Too Many Requests".into(),
+        data: None,
+    });
+    asserter.push_success(&U64::from(7));
+
+    assert_eq!(client.get_block_number().await.unwrap(), 7);
+    let counters = client.metrics().snapshot();
+    assert_eq!((counters.rate_limited, counters.retries), (1, 1));
+    assert_eq!(counters.failures, 0);
+
+    // Once out of retries, the error says why.
+    for _ in 0..3 {
+        asserter.push_failure(ErrorPayload {
+            code: 429,
+            message: "Too Many Requests".into(),
+            data: None,
+        });
+    }
+    let error: ArbRsError = client.get_block_number().await.unwrap_err().into();
+    assert!(matches!(error, ArbRsError::RpcRateLimited(_)), "{error:?}");
+    assert!(error.is_retryable());
+}
+
+#[tokio::test]
+async fn test_reverts_are_not_retried() {
+    let asserter = Asserter::new();
+    let client = RpcClient::new(Arc::new(
+        ProviderBuilder::new().connect_mocked_client(asserter.clone()),
+    ));
+    let metrics = client.metrics();
+    let pool = v2_pool(FAST_POOL, Arc::new(client));
+    asserter.push_failure_msg("execution reverted");
+    asserter.push_success(&reserves(1, 1));
+
+    let error = pool.get_snapshot(Some(1)).await.unwrap_err();
+    assert!(matches!(error, ArbRsError::ProviderError(_)), "{error:?}");
+    assert!(!error.is_retryable());
+    assert_eq!(metrics.snapshot().retries, 0);
+    assert_eq!(asserter.read_q().len(), 1, "the revert wasn't retried");
+}
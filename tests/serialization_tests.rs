@@ -0,0 +1,384 @@
+mod common;
+
+use alloy_primitives::{Address, U256};
+use alloy_provider::{Provider, ProviderBuilder};
+use arbrs::arbitrage::amount::TokenAmount;
+use arbrs::arbitrage::cycle::ArbitrageCycle;
+use arbrs::arbitrage::serializable::{SerializableSolution, SerializableSwapAction};
+use arbrs::arbitrage::types::{
+    ArbitragePath, ArbitrageSolution, HopMetrics, InputBound, ScenarioResult, SwapAction, SwapKind,
+    TokenRef,
+};
+use arbrs::arbitrage::usd::UsdValues;
+use arbrs::arbitrage::verification::VerifiedSolution;
+use arbrs::balancer::pool::BalancerPoolSnapshot;
+use arbrs::core::token::Token;
+use arbrs::curve::types::CurvePoolSnapshot;
+use arbrs::manager::token_manager::TokenManager;
+use arbrs::pool::serializable::SerializablePoolSnapshot;
+use arbrs::pool::strategy::StandardV2Logic;
+use arbrs::pool::uniswap_v2::{UniswapV2Pool, UniswapV2PoolState};
+use arbrs::pool::uniswap_v3::{TickInfo, UniswapV3PoolSnapshot};
+use arbrs::pool::{DexKind, LiquidityPool, PoolSnapshot};
+use common::named_token;
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+// The provider is lazy and never called.
+const FORK_RPC_URL: &str = "http://127.0.0.1:8545";
+type DynProvider = dyn Provider + Send + Sync;
+
+fn provider() -> Arc<DynProvider> {
+    Arc::new(ProviderBuilder::new().connect_http(FORK_RPC_URL.parse().unwrap()))
+}
+
+fn v3_snapshot() -> UniswapV3PoolSnapshot {
+    UniswapV3PoolSnapshot {
+        sqrt_price_x96: U256::MAX,
+        tick: -887_220,
+        liquidity: u128::MAX,
+        tick_bitmap: BTreeMap::from([(-3, U256::from(5)), (2, U256::MAX)]),
+        tick_data: BTreeMap::from([
+            (
+                -60,
+                TickInfo {
+                    liquidity_gross: u128::MAX,
+                    liquidity_net: i128::MIN,
+                },
+            ),
+            (
+                60,
+                TickInfo {
+                    liquidity_gross: 10,
+                    liquidity_net: i128::MAX,
+                },
+            ),
+        ]),
+        fee_protocol: 0x44,
+    }
+}
+
+fn metapool_snapshot() -> CurvePoolSnapshot {
+    CurvePoolSnapshot {
+        balances: vec![U256::MAX, U256::from(2)],
+        a: U256::from(2_000),
+        fee: U256::from(4_000_000),
+        admin_fee: Some(U256::from(5_000_000_000u64)),
+        base_pool_virtual_price: Some(U256::from(10).pow(U256::from(18))),
+        rates: vec![U256::from(10).pow(U256::from(30))],
+        admin_balances: Some(vec![U256::ZERO, U256::from(7)]),
+        tricrypto_price_scale: Some(vec![]),
+        base_pool_snapshot: Some(Box::new(CurvePoolSnapshot {
+            balances: vec![U256::from(1), U256::from(2), U256::from(3)],
+            a: U256::from(200_000),
+            fee: U256::from(1_000_000),
+            ..Default::default()
+        })),
+        ..Default::default()
+    }
+}
+
+fn round_trip(snapshot: &PoolSnapshot) -> PoolSnapshot {
+    let json = serde_json::to_string(&SerializablePoolSnapshot::from(snapshot)).unwrap();
+    serde_json::from_str::<SerializablePoolSnapshot>(&json)
+        .unwrap()
+        .into()
+}
+
+/// A two-pool cycle with amounts that don't fit in a u64 or an f64 mantissa.
+fn synthetic_solution(
+    provider: Arc<DynProvider>,
+) -> (ArbitrageSolution<DynProvider>, Arc<Token<DynProvider>>) {
+    let a = named_token(Address::repeat_byte(0x0a), "AAA", 18, provider.clone());
+    let b = named_token(Address::repeat_byte(0x0b), "BBB", 6, provider.clone());
+    let pool = |byte: u8| -> Arc<dyn LiquidityPool<DynProvider>> {
+        Arc::new(UniswapV2Pool::new(
+            Address::repeat_byte(byte),
+            a.clone(),
+            b.clone(),
+            provider.clone(),
+            StandardV2Logic,
+        ))
+    };
+    let (p1, p2) = (pool(0x01), pool(0x02));
+
+    let cycle = ArbitrageCycle::new(ArbitragePath {
+        pools: vec![p1.clone(), p2.clone()],
+        path: vec![a.clone(), b.clone(), a.clone()],
+        profit_token: a.clone(),
+    });
+    let optimal_input = U256::from(123_456_789_012_345_678_901_234u128);
+    let solution = ArbitrageSolution {
+        cycle_id: cycle.cycle_id(),
+        path: Arc::new(cycle),
+        optimal_input: TokenAmount::new(a.clone(), optimal_input),
+        gross_profit: TokenAmount::new(a.clone(), U256::from(9_007_199_254_740_993u64)),
+        net_profit: TokenAmount::new(a.clone(), U256::from(9_000_000_000_000_001u64)),
+        net_profit_weth: U256::from(9_000_000_000_000_001u64),
+        flashloan_fee: U256::from(7_000_000_000_000u64),
+        gas_cost: U256::from(199_254_740_992u64),
+        divergent_pools: vec![p2.address()],
+        scenario_results: vec![ScenarioResult {
+            label: "+100%".to_string(),
+            gas_cost: U256::from(398_509_481_984u64),
+            net_profit: U256::from(8_999_800_745_259_009u64),
+            passes: false,
+        }],
+        bound_by: InputBound::Liquidity,
+        persistence_blocks: 4,
+        dexes_involved: vec![DexKind::UniswapV2],
+        swap_actions: vec![
+            SwapAction {
+                pool_address: p1.address(),
+                token_in: TokenRef::from(a.as_ref()),
+                token_out: TokenRef::from(b.as_ref()),
+                amount_in: optimal_input,
+                min_amount_out: U256::from(250_000_000_123u64),
+                kind: SwapKind::Swap,
+            },
+            SwapAction {
+                pool_address: p2.address(),
+                token_in: TokenRef::from(b.as_ref()),
+                token_out: TokenRef::from(a.as_ref()),
+                amount_in: U256::from(250_000_000_123u64),
+                min_amount_out: U256::MAX,
+                kind: SwapKind::Swap,
+            },
+        ],
+        hop_metrics: vec![
+            Some(HopMetrics {
+                pre_trade_price: 2.5e-7,
+                post_trade_price: 2.4e-7,
+                price_impact_bps: 400.0,
+            }),
+            None,
+        ],
+        approve_actions: vec![],
+        usd: Some(UsdValues {
+            net_profit: U256::from(1_800_000),
+            flashloan_fee: U256::from(1_400),
+            gas_cost: U256::from(5),
+        }),
+        verification: Some(VerifiedSolution {
+            input: optimal_input,
+            realized_output: U256::from(123_456_798_012_345_678_901_235u128),
+            gas_used: 184_000,
+        }),
+    };
+    (solution, a)
+}
+
+#[test]
+fn test_snapshots_round_trip() {
+    let snapshots = [
+        PoolSnapshot::UniswapV2(UniswapV2PoolState {
+            reserve0: U256::MAX,
+            reserve1: U256::from(42),
+            block_number: 19_000_000,
+        }),
+        PoolSnapshot::UniswapV3(v3_snapshot()),
+        PoolSnapshot::Curve(metapool_snapshot()),
+        PoolSnapshot::Balancer(BalancerPoolSnapshot {
+            balances: vec![U256::from(3), U256::MAX],
+            is_paused: true,
+            block_number: Some(19_000_000),
+            swap_fee: Some(U256::from(4)),
+            amplification: None,
+            scaling_factors: Some(vec![U256::from(10).pow(U256::from(12)), U256::from(1)]),
+        }),
+        PoolSnapshot::WrappedNative,
+    ];
+
+    for snapshot in &snapshots {
+        let parsed = round_trip(snapshot);
+        assert_eq!(
+            SerializablePoolSnapshot::from(&parsed),
+            SerializablePoolSnapshot::from(snapshot)
+        );
+    }
+
+    let PoolSnapshot::UniswapV3(parsed) = round_trip(&snapshots[1]) else {
+        panic!("expected a V3 snapshot");
+    };
+    assert_eq!(parsed.liquidity, u128::MAX);
+    assert_eq!(parsed.tick_data[&-60].liquidity_net, i128::MIN);
+    assert_eq!(parsed.tick_data[&60].liquidity_net, i128::MAX);
+    assert_eq!(parsed.tick_bitmap, v3_snapshot().tick_bitmap);
+
+    let PoolSnapshot::Curve(parsed) = round_trip(&snapshots[2]) else {
+        panic!("expected a Curve snapshot");
+    };
+    assert_eq!(parsed, metapool_snapshot());
+}
+
+#[test]
+fn test_snapshot_json_schema_is_stable() {
+    let v3 = serde_json::to_value(SerializablePoolSnapshot::from(&PoolSnapshot::UniswapV3(
+        v3_snapshot(),
+    )))
+    .unwrap();
+    assert_eq!(
+        v3,
+        json!({
+            "type": "uniswap_v3",
+            "sqrt_price_x96": U256::MAX.to_string(),
+            "tick": -887220,
+            "liquidity": u128::MAX.to_string(),
+            "fee_protocol": 68,
+            "tick_bitmap": [
+                { "word": -3, "bitmap": "5" },
+                { "word": 2, "bitmap": U256::MAX.to_string() },
+            ],
+            "ticks": [
+                {
+                    "tick": -60,
+                    "liquidity_gross": u128::MAX.to_string(),
+                    "liquidity_net": i128::MIN.to_string(),
+                },
+                {
+                    "tick": 60,
+                    "liquidity_gross": "10",
+                    "liquidity_net": i128::MAX.to_string(),
+                },
+            ],
+        })
+    );
+
+    let base = serde_json::to_value(SerializablePoolSnapshot::from(&PoolSnapshot::Curve(
+        CurvePoolSnapshot {
+            balances: vec![U256::from(1)],
+            a: U256::from(100),
+            fee: U256::from(4_000_000),
+            admin_fee: Some(U256::from(5)),
+            ..Default::default()
+        },
+    )))
+    .unwrap();
+    assert_eq!(
+        base,
+        json!({
+            "type": "curve",
+            "balances": ["1"],
+            "a": "100",
+            "fee": "4000000",
+            "admin_fee": "5",
+            "block_timestamp": 0,
+            "base_pool_virtual_price": null,
+            "base_pool_lp_total_supply": null,
+            "rates": [],
+            "admin_balances": null,
+            "tricrypto_d": null,
+            "tricrypto_gamma": null,
+            "tricrypto_price_scale": null,
+            "mid_fee": null,
+            "out_fee": null,
+            "fee_gamma": null,
+            "scaled_redemption_price": null,
+            "oracle_updated_at": null,
+            "base_pool_snapshot": null,
+        })
+    );
+
+    let wrapped =
+        serde_json::to_value(SerializablePoolSnapshot::from(&PoolSnapshot::WrappedNative)).unwrap();
+    assert_eq!(wrapped, json!({ "type": "wrapped_native" }));
+}
+
+#[test]
+fn test_swap_action_json_schema_is_stable() {
+    let action = SwapAction {
+        pool_address: Address::repeat_byte(0x01),
+        token_in: TokenRef {
+            address: Address::repeat_byte(0x0a),
+            symbol: "AAA".to_string(),
+            decimals: 18,
+        },
+        token_out: TokenRef {
+            address: Address::repeat_byte(0x0b),
+            symbol: "BBB".to_string(),
+            decimals: 6,
+        },
+        amount_in: U256::from(123_456_789_012_345_678_901_234u128),
+        min_amount_out: U256::MAX,
+        kind: SwapKind::SwapUnderlying,
+    };
+    let value = serde_json::to_value(SerializableSwapAction::from(&action)).unwrap();
+    assert_eq!(
+        value,
+        json!({
+            "pool": Address::repeat_byte(0x01),
+            "token_in": {
+                "address": Address::repeat_byte(0x0a),
+                "symbol": "AAA",
+                "decimals": 18,
+            },
+            "token_out": {
+                "address": Address::repeat_byte(0x0b),
+                "symbol": "BBB",
+                "decimals": 6,
+            },
+            "amount_in": "123456789012345678901234",
+            "min_amount_out": U256::MAX.to_string(),
+            "kind": "SwapUnderlying",
+        })
+    );
+
+    let parsed: SwapAction = serde_json::from_value::<SerializableSwapAction>(value)
+        .unwrap()
+        .into();
+    assert_eq!(parsed, action);
+}
+
+#[tokio::test]
+async fn test_solution_round_trip() {
+    let provider = provider();
+    let (solution, profit_token) = synthetic_solution(provider.clone());
+    let dto = SerializableSolution::from(&solution);
+
+    assert_eq!(
+        dto.pools,
+        vec![Address::repeat_byte(0x01), Address::repeat_byte(0x02)]
+    );
+    assert_eq!(
+        dto.path
+            .iter()
+            .map(|token| token.symbol.as_str())
+            .collect::<Vec<_>>(),
+        ["AAA", "BBB", "AAA"]
+    );
+
+    let json = serde_json::to_string(&dto).unwrap();
+    let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+    assert_eq!(value["optimal_input"], "123456789012345678901234");
+    assert_eq!(
+        value["swap_actions"][1]["min_amount_out"],
+        U256::MAX.to_string()
+    );
+    assert_eq!(
+        value["verification"]["realized_output"],
+        "123456798012345678901235"
+    );
+    assert_eq!(value["hop_metrics"][1], serde_json::Value::Null);
+
+    let parsed: SerializableSolution = serde_json::from_str(&json).unwrap();
+    assert_eq!(parsed, dto);
+
+    // The profit token is resolved from the cache, so the lazy provider is never called.
+    let token_manager = TokenManager::in_memory(provider, 1);
+    token_manager.insert_token(profit_token.clone());
+    let rebuilt = parsed
+        .into_solution(solution.path.clone(), &token_manager)
+        .await
+        .unwrap();
+
+    assert!(Arc::ptr_eq(&rebuilt.net_profit.token, &profit_token));
+    assert_eq!(rebuilt.optimal_input.raw, solution.optimal_input.raw);
+    assert_eq!(rebuilt.swap_actions, solution.swap_actions);
+    assert_eq!(rebuilt.hop_metrics, solution.hop_metrics);
+    assert_eq!(
+        rebuilt.path.get_involved_pools(),
+        solution.path.get_involved_pools()
+    );
+    assert_eq!(SerializableSolution::from(&rebuilt), dto);
+}
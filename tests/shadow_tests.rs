@@ -0,0 +1,175 @@
+#![cfg(feature = "db")]
+
+mod common;
+
+use alloy::transports::mock::Asserter;
+use alloy_primitives::{Address, I256, U256, address};
+use alloy_provider::{Provider, ProviderBuilder};
+use arbrs::arbitrage::cache::ArbitrageCache;
+use arbrs::arbitrage::cycle::ArbitrageCycle;
+use arbrs::arbitrage::engine::ArbitrageEngine;
+use arbrs::arbitrage::shadow::{ShadowMode, ShadowRecord};
+use arbrs::arbitrage::types::ArbitragePath;
+use arbrs::db::DbManager;
+use arbrs::manager::token_manager::TokenManager;
+use arbrs::pool::strategy::StandardV2Logic;
+use arbrs::pool::uniswap_v2::{UniswapV2Pool, UniswapV2PoolState};
+use arbrs::pool::{LiquidityPool, PoolSnapshot};
+use common::{ether, token};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+type DynProvider = dyn Provider + Send + Sync;
+
+const WETH: Address = address!("C02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2");
+const DAY: u64 = 20_000;
+const PROFIT_TOKEN: Address = WETH;
+const FIRST_POOL: Address = Address::repeat_byte(0x01);
+const SECOND_POOL: Address = Address::repeat_byte(0x02);
+
+fn reserves(reserve0: U256, reserve1: U256) -> PoolSnapshot {
+    PoolSnapshot::UniswapV2(UniswapV2PoolState {
+        reserve0,
+        reserve1,
+        block_number: 1,
+    })
+}
+
+/// Uniswap V2 output with the 0.3% fee.
+fn v2_out(amount_in: U256, reserve_in: U256, reserve_out: U256) -> U256 {
+    let amount_in_with_fee = amount_in * U256::from(997);
+    amount_in_with_fee * reserve_out / (reserve_in * U256::from(1000) + amount_in_with_fee)
+}
+
+/// The second pool's profit token reserve in each block. It loses 2 bps from block 1 to 2,
+/// within the 5 bps slippage allowance, and 1% from block 2 to 3, which reverts.
+fn second_pool_reserve0(block: u64) -> U256 {
+    match block {
+        1 => ether(1_000),
+        2 => ether(1_000) * U256::from(9_998) / U256::from(10_000),
+        _ => ether(1_000) * U256::from(9_898) / U256::from(10_000),
+    }
+}
+
+async fn engine() -> ArbitrageEngine<DynProvider> {
+    let provider: Arc<DynProvider> =
+        Arc::new(ProviderBuilder::new().connect_mocked_client(Asserter::new()));
+    let (profit_token, other) = (
+        token(WETH, provider.clone()),
+        token(Address::repeat_byte(0xee), provider.clone()),
+    );
+    let pools: Vec<Arc<dyn LiquidityPool<DynProvider>>> = [FIRST_POOL, SECOND_POOL]
+        .into_iter()
+        .map(|address| {
+            Arc::new(UniswapV2Pool::new(
+                address,
+                profit_token.clone(),
+                other.clone(),
+                provider.clone(),
+                StandardV2Logic,
+            )) as Arc<dyn LiquidityPool<DynProvider>>
+        })
+        .collect();
+
+    let cache = Arc::new(ArbitrageCache::new());
+    cache
+        .add_path(Arc::new(ArbitrageCycle::new(ArbitragePath {
+            pools,
+            path: vec![profit_token.clone(), other, profit_token.clone()],
+            profit_token,
+        })))
+        .await;
+    ArbitrageEngine::new(
+        cache,
+        Arc::new(TokenManager::in_memory(provider.clone(), 1)),
+        provider,
+    )
+    .with_min_net_profit(U256::ZERO)
+}
+
+#[tokio::test]
+async fn test_shadow_records_decaying_opportunity() {
+    let db_manager = DbManager::new("sqlite::memory:").await.unwrap();
+    let engine = engine().await;
+    let shadow_mode = ShadowMode::new();
+
+    let mut resolved: Vec<ShadowRecord> = Vec::new();
+    let mut inputs = Vec::new();
+    for block in 1..=3 {
+        let overrides = HashMap::from([
+            (FIRST_POOL, reserves(ether(1_000), ether(2_400_000))),
+            (
+                SECOND_POOL,
+                reserves(second_pool_reserve0(block), ether(2_000_000)),
+            ),
+        ]);
+        let solutions = engine
+            .find_opportunities_with_overrides(Some(block), overrides)
+            .await;
+        assert_eq!(solutions.len(), 1, "block {block}");
+        inputs.push(solutions[0].optimal_input.raw);
+
+        let records = shadow_mode
+            .observe_and_save(
+                &db_manager,
+                block,
+                DAY * 86_400 + block * 12,
+                &solutions,
+                &engine.last_snapshots(),
+            )
+            .await
+            .unwrap();
+        // Each block resolves the previous block's choice.
+        assert_eq!(records.len(), usize::from(block > 1));
+        resolved.extend(records);
+    }
+
+    let quote = |input: U256, block: u64| {
+        let other_out = v2_out(input, ether(1_000), ether(2_400_000));
+        v2_out(other_out, ether(2_000_000), second_pool_reserve0(block))
+    };
+    let (decayed, reverted) = (&resolved[0], &resolved[1]);
+    assert_eq!((decayed.block, decayed.realized_block), (1, 2));
+    assert_eq!(decayed.input, inputs[0]);
+    assert_eq!(decayed.expected_out, quote(inputs[0], 1));
+    assert_eq!(decayed.realized_out, Some(quote(inputs[0], 2)));
+    assert!(decayed.realized_profit() < decayed.expected_profit());
+    assert_eq!(
+        decayed.expected_profit() - decayed.realized_profit(),
+        I256::from_raw(quote(inputs[0], 1) - quote(inputs[0], 2))
+    );
+
+    assert_eq!((reverted.block, reverted.realized_block), (2, 3));
+    assert_eq!(reverted.expected_out, quote(inputs[1], 2));
+    assert_eq!(reverted.realized_out, None);
+    assert_eq!(
+        reverted.realized_profit(),
+        -I256::from_raw(reverted.gas_cost)
+    );
+
+    assert_eq!(
+        db_manager.load_shadow_records(1..=3).await.unwrap(),
+        resolved
+    );
+    assert_eq!(
+        db_manager.load_shadow_records(2..=3).await.unwrap(),
+        std::slice::from_ref(reverted)
+    );
+
+    let summary = db_manager.shadow_pnl_summary(1..=3).await.unwrap();
+    assert_eq!(summary.len(), 1);
+    let row = &summary[0];
+    assert_eq!(
+        (row.day, row.path_type.as_str(), row.profit_token),
+        (DAY, "uniswap_v2+uniswap_v2", PROFIT_TOKEN)
+    );
+    assert_eq!((row.trades, row.reverts), (2, 1));
+    assert_eq!(
+        row.expected_profit,
+        decayed.expected_profit() + reverted.expected_profit()
+    );
+    assert_eq!(
+        row.realized_profit,
+        decayed.realized_profit() + reverted.realized_profit()
+    );
+}
@@ -0,0 +1,182 @@
+mod common;
+
+use alloy::transports::mock::Asserter;
+use alloy_primitives::{Address, Bytes, U256, address};
+use alloy_provider::{Provider, ProviderBuilder};
+use alloy_rpc_types::Log;
+use arbrs::arbitrage::cache::ArbitrageCache;
+use arbrs::arbitrage::cycle::ArbitrageCycle;
+use arbrs::arbitrage::engine::{ArbitrageEngine, EngineConfig};
+use arbrs::arbitrage::snapshot_store::SnapshotStore;
+use arbrs::arbitrage::types::ArbitragePath;
+use arbrs::manager::token_manager::TokenManager;
+use arbrs::pool::state_updater::StateUpdater;
+use arbrs::pool::strategy::StandardV2Logic;
+use arbrs::pool::uniswap_v2::{UniswapV2Pool, UniswapV2PoolState};
+use arbrs::pool::{LiquidityPool, PoolSnapshot};
+use common::token;
+use std::collections::HashMap;
+use std::num::NonZeroUsize;
+use std::sync::Arc;
+
+type DynProvider = dyn Provider + Send + Sync;
+
+const WETH: Address = address!("C02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2");
+const POOL_A: Address = Address::repeat_byte(0x01);
+const POOL_B: Address = Address::repeat_byte(0x02);
+
+fn v2_snapshot(reserve0: u64, reserve1: u64, block_number: u64) -> PoolSnapshot {
+    PoolSnapshot::UniswapV2(UniswapV2PoolState {
+        reserve0: U256::from(reserve0),
+        reserve1: U256::from(reserve1),
+        block_number,
+    })
+}
+
+/// The reserves and block of a stored V2 snapshot.
+fn v2_state(snapshot: Option<PoolSnapshot>) -> Option<(U256, U256, u64)> {
+    match snapshot? {
+        PoolSnapshot::UniswapV2(state) => {
+            Some((state.reserve0, state.reserve1, state.block_number))
+        }
+        _ => None,
+    }
+}
+
+/// Queues the `getReserves()` result of one snapshot.
+fn push_reserves(asserter: &Asserter) {
+    let words = [U256::from(1_000_000), U256::from(2_000_000), U256::ZERO];
+    asserter.push_success(&Bytes::from(
+        words
+            .iter()
+            .flat_map(|word| word.to_be_bytes::<32>())
+            .collect::<Vec<u8>>(),
+    ));
+}
+
+/// An engine over one WETH cycle through pools A and B, answering from `asserter`.
+async fn engine(asserter: &Asserter) -> (ArbitrageEngine<DynProvider>, Arc<DynProvider>) {
+    let provider: Arc<DynProvider> =
+        Arc::new(ProviderBuilder::new().connect_mocked_client(asserter.clone()));
+    let (weth, other) = (
+        token(WETH, provider.clone()),
+        token(Address::repeat_byte(0xee), provider.clone()),
+    );
+    let pools: Vec<Arc<dyn LiquidityPool<DynProvider>>> = [POOL_A, POOL_B]
+        .into_iter()
+        .map(|address| {
+            Arc::new(UniswapV2Pool::new(
+                address,
+                weth.clone(),
+                other.clone(),
+                provider.clone(),
+                StandardV2Logic,
+            )) as Arc<dyn LiquidityPool<DynProvider>>
+        })
+        .collect();
+    let cache = Arc::new(ArbitrageCache::new());
+    cache
+        .add_path(Arc::new(ArbitrageCycle::new(ArbitragePath {
+            pools,
+            path: vec![weth.clone(), other, weth.clone()],
+            profit_token: weth,
+        })))
+        .await;
+    let engine = ArbitrageEngine::new(
+        cache,
+        Arc::new(TokenManager::in_memory(provider.clone(), 1)),
+        provider.clone(),
+    )
+    .with_config(EngineConfig {
+        flashloan_sources: HashMap::new(),
+        ..Default::default()
+    });
+    (engine, provider)
+}
+
+#[tokio::test]
+async fn test_quiet_block_is_evaluated_without_snapshot_calls() {
+    let asserter = Asserter::new();
+    let (engine, provider) = engine(&asserter).await;
+    let updater = Arc::new(StateUpdater::new());
+    let engine = engine.with_state_updater(updater.clone());
+
+    asserter.push_success(&Vec::<Log>::new());
+    updater.update(provider.as_ref(), 10).await.unwrap();
+    push_reserves(&asserter);
+    push_reserves(&asserter);
+    asserter.push_success(&U256::from(1));
+    engine.find_opportunities(Some(10)).await;
+    assert!(asserter.read_q().is_empty());
+
+    // Block 11 changed neither pool: only its logs and the gas price are fetched.
+    asserter.push_success(&Vec::<Log>::new());
+    updater.update(provider.as_ref(), 11).await.unwrap();
+    asserter.push_success(&U256::from(1));
+    engine.find_opportunities(Some(11)).await;
+    assert!(asserter.read_q().is_empty());
+
+    let stats = engine.snapshot_store.stats();
+    assert_eq!(engine.last_stats().failed_snapshots, 0);
+    assert_eq!((stats.aliased, stats.entries), (2, 4));
+    assert_eq!(
+        v2_state(engine.snapshot_store.get(POOL_A, 11)),
+        v2_state(engine.snapshot_store.get(POOL_A, 10))
+    );
+}
+
+#[tokio::test]
+async fn test_reevaluating_a_block_reads_the_store() {
+    let asserter = Asserter::new();
+    let (engine, _) = engine(&asserter).await;
+
+    push_reserves(&asserter);
+    push_reserves(&asserter);
+    asserter.push_success(&U256::from(1));
+    engine.find_opportunities(Some(10)).await;
+    asserter.push_success(&U256::from(1));
+    engine.find_opportunities(Some(10)).await;
+    assert!(asserter.read_q().is_empty());
+    let stats = engine.snapshot_store.stats();
+    assert_eq!((stats.hits, stats.misses, stats.entries), (2, 2, 2));
+
+    // A reorg replacing block 10 drops its snapshots.
+    engine.invalidate_from(10).await;
+    assert_eq!(engine.snapshot_store.stats().entries, 0);
+    assert!(engine.snapshot_store.get(POOL_A, 10).is_none());
+}
+
+#[test]
+fn test_unchanged_snapshots_are_shared_with_the_next_block() {
+    let store = SnapshotStore::default();
+    store.insert(POOL_A, 10, v2_snapshot(100, 200, 10));
+    store.insert(POOL_A, 11, v2_snapshot(100, 200, 11));
+    assert_eq!(store.stats().aliased, 1);
+    assert_eq!(
+        v2_state(store.get(POOL_A, 11)),
+        v2_state(Some(v2_snapshot(100, 200, 10)))
+    );
+
+    store.insert(POOL_A, 12, v2_snapshot(100, 300, 12));
+    // Two blocks apart nothing is known about the block in between.
+    store.insert(POOL_A, 14, v2_snapshot(100, 300, 14));
+    assert_eq!(store.stats().aliased, 1);
+    assert_eq!(
+        v2_state(store.get(POOL_A, 14)),
+        v2_state(Some(v2_snapshot(100, 300, 14)))
+    );
+}
+
+#[test]
+fn test_least_recently_used_snapshots_are_evicted() {
+    let store = SnapshotStore::new(NonZeroUsize::new(2).unwrap());
+    store.insert(POOL_A, 10, v2_snapshot(100, 200, 10));
+    store.insert(POOL_B, 10, v2_snapshot(300, 400, 10));
+    assert!(store.get(POOL_A, 10).is_some());
+    store.insert(POOL_A, 11, v2_snapshot(500, 600, 11));
+
+    assert!(store.get(POOL_B, 10).is_none());
+    assert!(store.get(POOL_A, 10).is_some());
+    let stats = store.stats();
+    assert_eq!((stats.hits, stats.misses, stats.entries), (2, 1, 2));
+}
@@ -0,0 +1,97 @@
+use alloy_primitives::{Address, B256, U256};
+use alloy_provider::{Provider, ProviderBuilder};
+use alloy_rpc_types::{BlockId, TransactionRequest};
+use alloy_sol_types::{SolCall, sol};
+use arbrs::TokenLike;
+use arbrs::dex::{DexDetails, DexVariant};
+use arbrs::manager::token_manager::TokenManager;
+use arbrs::manager::uniswap_v2_pool_manager::UniswapV2PoolManager;
+use std::sync::Arc;
+
+const FORK_RPC_URL: &str = "http://127.0.0.1:8545";
+type DynProvider = dyn Provider + Send + Sync;
+
+sol! {
+    function getAmountOut(uint256 amountIn, address tokenIn) external view returns (uint256);
+}
+
+fn env_u32(name: &str, default: u32) -> u32 {
+    std::env::var(name)
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(default)
+}
+
+/// Quotes every pair of `ARBRS_SOLIDLY_PAIRS`, a comma-separated list of Solidly-style
+/// pairs deployed on the fork, in both directions and against the pair's own
+/// `getAmountOut`. Stable and volatile pairs charge `ARBRS_SOLIDLY_STABLE_FEE_BPS` and
+/// `ARBRS_SOLIDLY_VOLATILE_FEE_BPS`, Velodrome's 5 and 30 bps unless set. Skipped when
+/// `ARBRS_SOLIDLY_PAIRS` is unset.
+#[tokio::test]
+async fn test_solidly_quotes_match_get_amount_out() {
+    let Ok(pairs) = std::env::var("ARBRS_SOLIDLY_PAIRS") else {
+        eprintln!("ARBRS_SOLIDLY_PAIRS not set, skipping");
+        return;
+    };
+    let pairs: Vec<Address> = pairs
+        .split(',')
+        .map(|pair| pair.trim().parse().unwrap())
+        .collect();
+    let provider: Arc<DynProvider> =
+        Arc::new(ProviderBuilder::new().connect_http(FORK_RPC_URL.parse().unwrap()));
+    let token_manager = Arc::new(TokenManager::in_memory(provider.clone(), 1));
+    let factory = Address::repeat_byte(0xfa);
+    let manager = UniswapV2PoolManager::new(token_manager, provider.clone(), factory, 0)
+        .with_factory(
+            factory,
+            DexDetails::new(
+                DexVariant::SolidlyVolatile,
+                env_u32("ARBRS_SOLIDLY_VOLATILE_FEE_BPS", 30),
+                10_000,
+                B256::ZERO,
+            )
+            .with_stable_fee(env_u32("ARBRS_SOLIDLY_STABLE_FEE_BPS", 5), 10_000),
+        );
+    let block = provider.get_block_number().await.unwrap();
+
+    for pair in pairs {
+        let pool = manager
+            .add_pool_by_address(pair, DexVariant::SolidlyStable)
+            .await
+            .unwrap();
+        let snapshot = pool.get_snapshot(Some(block)).await.unwrap();
+        let tokens = pool.get_all_tokens();
+        for (token_in, token_out) in [(&tokens[0], &tokens[1]), (&tokens[1], &tokens[0])] {
+            for whole in [1u64, 1_000, 100_000] {
+                let amount_in =
+                    U256::from(whole) * U256::from(10).pow(U256::from(token_in.decimals()));
+                let Ok(amount_out) =
+                    pool.calculate_tokens_out(token_in, token_out, amount_in, &snapshot)
+                else {
+                    continue;
+                };
+                let request = TransactionRequest::default().to(pair).input(
+                    getAmountOutCall {
+                        amountIn: amount_in,
+                        tokenIn: token_in.address(),
+                    }
+                    .abi_encode()
+                    .into(),
+                );
+                let on_chain = getAmountOutCall::abi_decode_returns(
+                    &provider
+                        .call(request)
+                        .block(BlockId::number(block))
+                        .await
+                        .unwrap(),
+                )
+                .unwrap();
+                assert!(
+                    amount_out.abs_diff(on_chain) <= U256::from(1),
+                    "{pair}: {amount_in} of {} quoted {amount_out}, the pair says {on_chain}",
+                    token_in.address()
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,373 @@
+mod common;
+
+use alloy::transports::mock::Asserter;
+use alloy_primitives::{Address, B256, LogData, U256, address};
+use alloy_provider::{Provider, ProviderBuilder};
+use alloy_rpc_types::Log;
+use alloy_sol_types::{SolCall, sol};
+use arbrs::core::token::TokenLike;
+use arbrs::dex::{DexDetails, DexVariant};
+use arbrs::manager::token_manager::TokenManager;
+use arbrs::manager::uniswap_v2_pool_manager::UniswapV2PoolManager;
+use arbrs::pool::strategy::{
+    SolidlyStableStrategy, SolidlyVolatileStrategy, StandardV2Logic, V2CalculationStrategy,
+};
+use arbrs::pool::uniswap_v2::{UniswapV2Pool, UniswapV2PoolState};
+use arbrs::pool::{LiquidityPool, PoolSnapshot};
+use common::{named_token, token};
+use proptest::prelude::*;
+use std::sync::Arc;
+
+sol! {
+    event PairCreated(address indexed token0, address indexed token1, bool stable, address pair, uint256);
+
+    function stable() external view returns (bool);
+    function token0() external view returns (address);
+    function token1() external view returns (address);
+}
+
+type DynProvider = dyn Provider + Send + Sync;
+
+const USDC: Address = address!("A0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48");
+const DAI: Address = address!("6B175474E89094C44Da98b954EedeAC495271d0F");
+const FACTORY: Address = Address::repeat_byte(0xfa);
+
+const ONE: U256 = U256::from_limbs([1_000_000_000_000_000_000, 0, 0, 0]);
+
+fn units(amount: u64, decimals: u8) -> U256 {
+    U256::from(amount) * U256::from(10).pow(U256::from(decimals))
+}
+
+/// Velodrome V2's `Pool`, for a swap of token0 into token1.
+struct ReferencePool {
+    decimals0: U256,
+    decimals1: U256,
+}
+
+impl ReferencePool {
+    fn new(decimals0: u8, decimals1: u8) -> Self {
+        Self {
+            decimals0: U256::from(10).pow(U256::from(decimals0)),
+            decimals1: U256::from(10).pow(U256::from(decimals1)),
+        }
+    }
+
+    fn k(&self, x: U256, y: U256) -> U256 {
+        let x = x * ONE / self.decimals0;
+        let y = y * ONE / self.decimals1;
+        let a = x * y / ONE;
+        let b = x * x / ONE + y * y / ONE;
+        a * b / ONE
+    }
+
+    fn f(x0: U256, y: U256) -> U256 {
+        let a = x0 * y / ONE;
+        let b = x0 * x0 / ONE + y * y / ONE;
+        a * b / ONE
+    }
+
+    fn d(x0: U256, y: U256) -> U256 {
+        U256::from(3) * x0 * (y * y / ONE) / ONE + (x0 * x0 / ONE) * x0 / ONE
+    }
+
+    fn get_y(&self, x0: U256, xy: U256, mut y: U256) -> U256 {
+        for _ in 0..255 {
+            let k = Self::f(x0, y);
+            if k < xy {
+                let mut dy = (xy - k) * ONE / Self::d(x0, y);
+                if dy.is_zero() {
+                    if k == xy {
+                        return y;
+                    }
+                    if self.k(x0, y + U256::from(1)) > xy {
+                        return y + U256::from(1);
+                    }
+                    dy = U256::from(1);
+                }
+                y += dy;
+            } else {
+                let mut dy = (k - xy) * ONE / Self::d(x0, y);
+                if dy.is_zero() {
+                    if k == xy || Self::f(x0, y - U256::from(1)) < xy {
+                        return y;
+                    }
+                    dy = U256::from(1);
+                }
+                y -= dy;
+            }
+        }
+        panic!("!y");
+    }
+
+    /// `getAmountOut` of a stable pool charging `fee` out of 10000.
+    fn amount_out(&self, amount_in: U256, reserve0: U256, reserve1: U256, fee: u64) -> U256 {
+        let amount_in = amount_in - amount_in * U256::from(fee) / U256::from(10_000);
+        let xy = self.k(reserve0, reserve1);
+        let reserve_a = reserve0 * ONE / self.decimals0;
+        let reserve_b = reserve1 * ONE / self.decimals1;
+        let amount_in = amount_in * ONE / self.decimals0;
+        let y = reserve_b - self.get_y(amount_in + reserve_a, xy, reserve_b);
+        y * self.decimals1 / ONE
+    }
+}
+
+/// Balanced within 4x, as stable pools are, and small enough for the pool's math.
+fn stable_case() -> impl Strategy<Value = (u64, u64, u64)> {
+    (1_000u64..1_000_000_000).prop_flat_map(|reserve_in| {
+        (
+            Just(reserve_in),
+            reserve_in / 4..reserve_in * 4,
+            1u64..reserve_in / 10,
+        )
+    })
+}
+
+proptest! {
+    #[test]
+    fn prop_stable_quotes_match_the_pool(
+        (reserve_in, reserve_out, amount) in stable_case(),
+        decimals in prop_oneof![Just((18u8, 18u8)), Just((6, 18)), Just((18, 6))],
+    ) {
+        let (decimals_in, decimals_out) = decimals;
+        let strategy = SolidlyStableStrategy::new(5, 10_000).unwrap();
+        let (reserve_in, reserve_out, amount_in) = (
+            units(reserve_in, decimals_in),
+            units(reserve_out, decimals_out),
+            units(amount, decimals_in),
+        );
+        let amount_out = strategy
+            .calculate_tokens_out_with_decimals(
+                reserve_in,
+                reserve_out,
+                amount_in,
+                decimals_in,
+                decimals_out,
+            )
+            .unwrap();
+        let expected = ReferencePool::new(decimals_in, decimals_out)
+            .amount_out(amount_in, reserve_in, reserve_out, 5);
+        // The pool's exit check renormalizes by both decimals, so mixed pairs may differ
+        // by a unit.
+        if decimals_in == decimals_out {
+            prop_assert_eq!(amount_out, expected);
+        } else {
+            prop_assert!(amount_out.abs_diff(expected) <= U256::from(1));
+        }
+
+        // The smallest input giving at least that output.
+        let target = amount_out.max(U256::from(1));
+        let amount_in = strategy
+            .calculate_tokens_in_from_tokens_out_with_decimals(
+                reserve_in,
+                reserve_out,
+                target,
+                decimals_in,
+                decimals_out,
+            )
+            .unwrap();
+        let quote = |amount_in: U256| {
+            strategy
+                .calculate_tokens_out_with_decimals(
+                    reserve_in,
+                    reserve_out,
+                    amount_in,
+                    decimals_in,
+                    decimals_out,
+                )
+                .unwrap_or_default()
+        };
+        prop_assert!(quote(amount_in) >= target);
+        prop_assert!(amount_in == U256::from(1) || quote(amount_in - U256::from(1)) < target);
+    }
+
+    #[test]
+    fn prop_volatile_exact_output_is_the_smallest_input(
+        reserve_in in 1_000u128..(1 << 112),
+        reserve_out in 1_000u128..(1 << 112),
+        amount in 1u128..1_000_000_000_000_000_000_000,
+    ) {
+        prop_assume!(amount < reserve_out);
+        let strategy = SolidlyVolatileStrategy::new(30, 10_000).unwrap();
+        let (reserve_in, reserve_out, amount_out) =
+            (U256::from(reserve_in), U256::from(reserve_out), U256::from(amount));
+        let amount_in = strategy
+            .calculate_tokens_in_from_tokens_out(reserve_in, reserve_out, amount_out)
+            .unwrap();
+        let quote = |amount_in: U256| {
+            strategy
+                .calculate_tokens_out(reserve_in, reserve_out, amount_in)
+                .unwrap_or_default()
+        };
+        prop_assert!(quote(amount_in) >= amount_out);
+        prop_assert!(quote(amount_in - U256::from(1)) < amount_out);
+    }
+}
+
+#[test]
+fn test_volatile_quotes_take_the_fee_off_the_input() {
+    let strategy = SolidlyVolatileStrategy::new(30, 10_000).unwrap();
+    let (reserve_in, reserve_out) = (units(1_000, 18), units(2_000_000, 18));
+    let amount_in = units(3, 18) + U256::from(1);
+    // The fee is rounded down, so the odd wei is swapped.
+    let after_fee = amount_in - amount_in * U256::from(30) / U256::from(10_000);
+    assert_eq!(
+        strategy
+            .calculate_tokens_out(reserve_in, reserve_out, amount_in)
+            .unwrap(),
+        after_fee * reserve_out / (reserve_in + after_fee)
+    );
+    assert_eq!(strategy.get_fee_bps(), 30);
+    assert!(SolidlyVolatileStrategy::new(10_000, 10_000).is_err());
+    assert!(SolidlyStableStrategy::new(1, 0).is_err());
+}
+
+#[test]
+fn test_stable_pairs_quote_near_the_peg() {
+    let stable = SolidlyStableStrategy::new(5, 10_000).unwrap();
+    let (reserve_usdc, reserve_dai) = (units(1_000_000, 6), units(1_000_000, 18));
+    let amount_out = stable
+        .calculate_tokens_out_with_decimals(reserve_usdc, reserve_dai, units(10_000, 6), 6, 18)
+        .unwrap();
+    // Within 1 DAI of the input net of the 0.05% fee, far closer than constant product.
+    assert!(amount_out < units(9_995, 18) && amount_out > units(9_994, 18));
+    let constant_product = StandardV2Logic
+        .calculate_tokens_out(reserve_usdc, reserve_dai, units(10_000, 6))
+        .unwrap();
+    assert!(constant_product < units(9_900, 18));
+
+    let price = stable.spot_price(reserve_usdc, reserve_dai, 6, 18);
+    assert!((price / 1e12 - 1.0).abs() < 1e-9);
+    let price = stable.spot_price(units(1_000_000, 18), units(1_200_000, 18), 18, 18);
+    assert!(price > 1.0 && price < 1.2);
+}
+
+/// A manager discovering from a Solidly factory whose stable pairs charge 5 bps and
+/// volatile ones 30, and that already knows USDC and DAI.
+fn manager(asserter: &Asserter) -> UniswapV2PoolManager<DynProvider> {
+    let provider: Arc<DynProvider> =
+        Arc::new(ProviderBuilder::new().connect_mocked_client(asserter.clone()));
+    let token_manager = Arc::new(TokenManager::in_memory(provider.clone(), 1));
+    token_manager.insert_token(named_token(USDC, "TKN", 6, provider.clone()));
+    token_manager.insert_token(token(DAI, provider.clone()));
+    UniswapV2PoolManager::new(token_manager, provider, FACTORY, 0).with_factory(
+        FACTORY,
+        DexDetails::new(DexVariant::SolidlyVolatile, 30, 10_000, B256::ZERO)
+            .with_stable_fee(5, 10_000),
+    )
+}
+
+fn pair_created(stable: bool, pair: Address) -> Log {
+    let event = PairCreated {
+        token0: DAI,
+        token1: USDC,
+        stable,
+        pair,
+        _4: U256::from(1),
+    };
+    Log {
+        inner: alloy_primitives::Log {
+            address: FACTORY,
+            data: LogData::from(&event),
+        },
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn test_solidly_discovery_builds_stable_and_volatile_pairs() {
+    let asserter = Asserter::new();
+    let mut manager = manager(&asserter);
+    let (stable_pair, volatile_pair) = (Address::repeat_byte(0x01), Address::repeat_byte(0x02));
+    asserter.push_success(&vec![
+        pair_created(true, stable_pair),
+        pair_created(false, volatile_pair),
+    ]);
+
+    let pools = manager.discover_pools_in_range(10).await.unwrap();
+    assert_eq!(pools.len(), 2);
+    let pool = |address: Address| manager.get_pool_by_address(address).unwrap();
+    let stable = pool(stable_pair);
+    let stable = stable
+        .as_any()
+        .downcast_ref::<UniswapV2Pool<DynProvider, SolidlyStableStrategy>>()
+        .unwrap();
+    assert_eq!(
+        *stable.strategy(),
+        SolidlyStableStrategy::new(5, 10_000).unwrap()
+    );
+    let volatile = pool(volatile_pair);
+    let volatile = volatile
+        .as_any()
+        .downcast_ref::<UniswapV2Pool<DynProvider, SolidlyVolatileStrategy>>()
+        .unwrap();
+    assert_eq!(
+        *volatile.strategy(),
+        SolidlyVolatileStrategy::new(30, 10_000).unwrap()
+    );
+
+    // Quotes normalize by each token's decimals.
+    let snapshot = PoolSnapshot::UniswapV2(UniswapV2PoolState {
+        reserve0: units(1_000_000, 18),
+        reserve1: units(1_000_000, 6),
+        block_number: 1,
+    });
+    let tokens = stable.get_all_tokens();
+    assert_eq!(tokens[0].address(), DAI);
+    let amount_out = stable
+        .calculate_tokens_out(&tokens[1], &tokens[0], units(10_000, 6), &snapshot)
+        .unwrap();
+    assert!(amount_out > units(9_994, 18));
+}
+
+#[tokio::test]
+async fn test_added_solidly_pairs_are_asked_whether_they_are_stable() {
+    let asserter = Asserter::new();
+    let manager = manager(&asserter);
+    let pair = Address::repeat_byte(0x03);
+    asserter.push_success(&token0Call::abi_encode_returns(&USDC));
+    asserter.push_success(&token1Call::abi_encode_returns(&DAI));
+    asserter.push_success(&stableCall::abi_encode_returns(&true));
+
+    // The variant given doesn't matter, the pair says it's stable.
+    let pool = manager
+        .add_pool_by_address(pair, DexVariant::SolidlyVolatile)
+        .await
+        .unwrap();
+    assert!(asserter.read_q().is_empty());
+    assert!(
+        pool.as_any()
+            .downcast_ref::<UniswapV2Pool<DynProvider, SolidlyStableStrategy>>()
+            .is_some()
+    );
+}
+
+#[cfg(feature = "db")]
+#[tokio::test]
+async fn test_discovered_solidly_pairs_are_stored_with_their_kind() {
+    use arbrs::db::DbManager;
+    use arbrs::dex::PoolKind;
+
+    let asserter = Asserter::new();
+    let db = Arc::new(DbManager::new("sqlite::memory:").await.unwrap());
+    let mut manager = manager(&asserter).with_db_manager(db.clone());
+    asserter.push_success(&vec![
+        pair_created(true, Address::repeat_byte(0x01)),
+        pair_created(false, Address::repeat_byte(0x02)),
+    ]);
+    manager.discover_pools_in_range(10).await.unwrap();
+
+    let mut records = db.load_all_pools().await.unwrap();
+    records.sort_by_key(|record| record.address);
+    let stored: Vec<_> = records
+        .iter()
+        .map(|record| (record.dex.clone(), record.fee))
+        .collect();
+    assert_eq!(
+        stored,
+        [
+            (PoolKind::SolidlyStable, Some(500)),
+            (PoolKind::SolidlyVolatile, Some(3_000)),
+        ]
+    );
+    assert_eq!("Solidly Stable".parse(), Ok(PoolKind::SolidlyStable));
+}
@@ -0,0 +1,102 @@
+mod common;
+
+use alloy::transports::mock::Asserter;
+use alloy_primitives::{Address, Bytes, U64, U256};
+use alloy_provider::{Provider, ProviderBuilder};
+use arbrs::ArbRsError;
+use arbrs::pool::LiquidityPool;
+use arbrs::pool::strategy::StandardV2Logic;
+use arbrs::pool::uniswap_v2::{UniswapV2Pool, UniswapV2PoolState};
+use common::token;
+use std::sync::Arc;
+
+type DynProvider = dyn Provider + Send + Sync;
+
+const POOL: Address = Address::repeat_byte(0x01);
+const N: u64 = 1_000;
+
+fn state(reserve0: u64, reserve1: u64, block_number: u64) -> UniswapV2PoolState {
+    UniswapV2PoolState {
+        reserve0: U256::from(reserve0),
+        reserve1: U256::from(reserve1),
+        block_number,
+    }
+}
+
+/// Queues the `getReserves()` result of one state fetch.
+fn push_reserves(asserter: &Asserter, reserve0: u64, reserve1: u64) {
+    let words = [U256::from(reserve0), U256::from(reserve1), U256::ZERO];
+    asserter.push_success(&Bytes::from(
+        words
+            .iter()
+            .flat_map(|word| word.to_be_bytes::<32>())
+            .collect::<Vec<u8>>(),
+    ));
+}
+
+#[tokio::test]
+async fn test_v2_rewind_with_allow_rewind() {
+    let asserter = Asserter::new();
+    let provider: Arc<DynProvider> =
+        Arc::new(ProviderBuilder::new().connect_mocked_client(asserter.clone()));
+    let pool = UniswapV2Pool::new(
+        POOL,
+        token(Address::repeat_byte(0x0a), provider.clone()),
+        token(Address::repeat_byte(0x0b), provider.clone()),
+        provider,
+        StandardV2Logic,
+    );
+
+    push_reserves(&asserter, 100, 200);
+    pool.update_state_at_block(N - 150, false).await.unwrap();
+    push_reserves(&asserter, 120, 180);
+    pool.update_state_at_block(N, false).await.unwrap();
+
+    // Without allow_rewind, nothing is fetched and the pool is left as it was.
+    assert_eq!(
+        pool.update_state_at_block(N - 100, false).await,
+        Err(ArbRsError::LateUpdateError {
+            pool: POOL,
+            attempted_block: N - 100,
+            latest_block: N,
+        })
+    );
+    assert_eq!(pool.get_cached_reserves().await, state(120, 180, N));
+
+    push_reserves(&asserter, 110, 190);
+    pool.update_state_at_block(N - 100, true).await.unwrap();
+    assert_eq!(pool.get_cached_reserves().await, state(110, 190, N - 100));
+    // States after the rewind target are dropped, earlier ones kept.
+    let cached = pool.cached_states().await;
+    assert_eq!(
+        cached.into_iter().collect::<Vec<_>>(),
+        [
+            (N - 150, state(100, 200, N - 150)),
+            (N - 100, state(110, 190, N - 100)),
+        ]
+    );
+
+    push_reserves(&asserter, 111, 189);
+    pool.update_state_at_block(N - 99, false).await.unwrap();
+    assert_eq!(pool.get_cached_reserves().await, state(111, 189, N - 99));
+    assert_eq!(
+        pool.cached_states()
+            .await
+            .keys()
+            .copied()
+            .collect::<Vec<_>>(),
+        [N - 150, N - 100, N - 99]
+    );
+
+    // The live update path stays strict: the chain head is behind the pool.
+    asserter.push_success(&U64::from(N - 200));
+    assert_eq!(
+        pool.update_state().await,
+        Err(ArbRsError::LateUpdateError {
+            pool: POOL,
+            attempted_block: N - 200,
+            latest_block: N - 99,
+        })
+    );
+    assert!(asserter.read_q().is_empty());
+}
@@ -0,0 +1,301 @@
+mod common;
+
+use alloy::transports::mock::Asserter;
+use alloy_primitives::{Address, Bytes, U64, U256};
+use alloy_provider::{Provider, ProviderBuilder};
+use alloy_sol_types::{SolCall, sol};
+use arbrs::balancer::pool::BalancerPool;
+use arbrs::core::messaging::{Publisher, PublisherMessage, Subscriber, SubscriberList};
+use arbrs::core::token::Token;
+use arbrs::curve::pool::CurveStableswapPool;
+use arbrs::curve::pool_attributes::SwapStrategyType;
+use arbrs::manager::token_manager::TokenManager;
+use arbrs::pool::strategy::StandardV2Logic;
+use arbrs::pool::uniswap_v2::UniswapV2Pool;
+use arbrs::pool::uniswap_v3::UniswapV3Pool;
+use arbrs::pool::{LiquidityPool, StateUpdate};
+use async_trait::async_trait;
+use common::{attributes, token};
+use std::sync::{Arc, Mutex, Weak};
+
+type DynProvider = dyn Provider + Send + Sync;
+
+const POOL: Address = Address::repeat_byte(0x01);
+const BLOCK: u64 = 1_000;
+
+// Return encodings of the Balancer calls.
+sol! {
+    function getPoolTokens(bytes32 poolId) external view returns (address[] tokens, uint256[] balances, uint256 lastChangeBlock);
+    function getPausedState() external view returns (bool paused, uint256 pauseWindowEndTime, uint256 bufferPeriodEndTime);
+    function inRecoveryMode() external view returns (bool);
+    function getSwapFeePercentage() external view returns (uint256);
+}
+
+/// Keeps every message it's notified of.
+#[derive(Default)]
+struct Recorder {
+    id: usize,
+    messages: Mutex<Vec<PublisherMessage>>,
+}
+
+#[async_trait]
+impl Subscriber<DynProvider> for Recorder {
+    fn id(&self) -> usize {
+        self.id
+    }
+
+    async fn notify(&self, message: PublisherMessage) {
+        self.messages.lock().unwrap().push(message);
+    }
+}
+
+fn mocked(asserter: &Asserter) -> Arc<DynProvider> {
+    Arc::new(ProviderBuilder::new().connect_mocked_client(asserter.clone()))
+}
+
+fn tokens(provider: &Arc<DynProvider>) -> [Arc<Token<DynProvider>>; 2] {
+    [0x0a, 0x0b].map(|byte| token(Address::repeat_byte(byte), provider.clone()))
+}
+
+fn push_words(asserter: &Asserter, words: &[U256]) {
+    asserter.push_success(&Bytes::from(
+        words
+            .iter()
+            .flat_map(|word| word.to_be_bytes::<32>())
+            .collect::<Vec<u8>>(),
+    ));
+}
+
+/// Runs `update_state` three times: twice against the same state, then against a changed
+/// one, queueing each fetch with `push_state(block, changed)` first. Returns what a
+/// subscriber was notified of, once per update that changed something.
+async fn assert_updates<L>(
+    pool: &L,
+    asserter: &Asserter,
+    push_state: impl Fn(u64, bool),
+) -> Vec<PublisherMessage>
+where
+    L: LiquidityPool<DynProvider> + Publisher<DynProvider>,
+{
+    let recorder = Arc::new(Recorder::default());
+    let subscriber: Arc<dyn Subscriber<DynProvider>> = recorder.clone();
+    pool.subscribe(Arc::downgrade(&subscriber)).await;
+
+    push_state(BLOCK, false);
+    assert_eq!(
+        pool.update_state().await,
+        Ok(StateUpdate::Updated { block: BLOCK })
+    );
+    push_state(BLOCK + 1, false);
+    let unchanged = pool.update_state().await;
+    assert_eq!(unchanged, Ok(StateUpdate::Unchanged));
+    assert!(!unchanged.unwrap().changed());
+    assert_eq!(recorder.messages.lock().unwrap().len(), 1);
+    push_state(BLOCK + 2, true);
+    let updated = pool.update_state().await;
+    assert_eq!(updated, Ok(StateUpdate::Updated { block: BLOCK + 2 }));
+    assert!(updated.unwrap().changed());
+    assert!(asserter.read_q().is_empty());
+
+    let messages = recorder.messages.lock().unwrap().clone();
+    assert_eq!(messages.len(), 2);
+    messages
+}
+
+#[tokio::test]
+async fn test_v2_update_reports_changes() {
+    let asserter = Asserter::new();
+    let provider = mocked(&asserter);
+    let [token0, token1] = tokens(&provider);
+    let pool = UniswapV2Pool::new(POOL, token0, token1, provider, StandardV2Logic);
+
+    let messages = assert_updates(&pool, &asserter, |block, changed| {
+        asserter.push_success(&U64::from(block));
+        let reserve0 = if changed { 1_100 } else { 1_000 };
+        push_words(
+            &asserter,
+            &[U256::from(reserve0), U256::from(2_000), U256::ZERO],
+        );
+    })
+    .await;
+    assert!(matches!(
+        &messages[1],
+        PublisherMessage::PoolStateUpdate(state) if state.reserve0 == U256::from(1_100)
+    ));
+}
+
+#[tokio::test]
+async fn test_v3_update_reports_changes() {
+    let asserter = Asserter::new();
+    let provider = mocked(&asserter);
+    let [token0, token1] = tokens(&provider);
+    let pool = UniswapV3Pool::new(POOL, token0, token1, 3_000, 60, provider, None);
+
+    let messages = assert_updates(&pool, &asserter, |block, changed| {
+        asserter.push_success(&U64::from(block));
+        let sqrt_price_x96 = U256::from(1u128 << 96);
+        // `slot0()`, then `liquidity()`.
+        push_words(
+            &asserter,
+            &[
+                sqrt_price_x96,
+                U256::ZERO,
+                U256::ZERO,
+                U256::ZERO,
+                U256::ZERO,
+                U256::ZERO,
+                U256::ONE,
+            ],
+        );
+        let liquidity = if changed { 2_000_000 } else { 1_000_000 };
+        push_words(&asserter, &[U256::from(liquidity)]);
+    })
+    .await;
+    assert!(matches!(
+        messages[..],
+        [
+            PublisherMessage::UniswapV3StateUpdate {
+                pool: POOL,
+                block_number: BLOCK,
+                liquidity: 1_000_000,
+                ..
+            },
+            PublisherMessage::UniswapV3StateUpdate {
+                block_number,
+                sqrt_price_x96,
+                tick: 0,
+                liquidity: 2_000_000,
+                ..
+            },
+        ] if block_number == BLOCK + 2 && sqrt_price_x96 == U256::from(1u128 << 96)
+    ));
+}
+
+#[tokio::test]
+async fn test_curve_update_reports_changes() {
+    let asserter = Asserter::new();
+    let provider = mocked(&asserter);
+    let tokens = tokens(&provider);
+    let attributes = attributes(2, SwapStrategyType::Default);
+    let pool = CurveStableswapPool::from_parts(
+        POOL,
+        tokens[0].clone(),
+        tokens.to_vec(),
+        provider.clone(),
+        Arc::new(TokenManager::in_memory(provider, 1)),
+        attributes,
+    );
+
+    let messages = assert_updates(&pool, &asserter, |block, changed| {
+        asserter.push_success(&U64::from(block));
+        // `A()`, `fee()`, the `balances(int128)` probe, made by the first update only, and
+        // both balances all answer the same word, so the order they are issued in doesn't
+        // matter.
+        let word = U256::from(if changed { 200 } else { 100 });
+        let calls = if block == BLOCK { 5 } else { 4 };
+        for _ in 0..calls {
+            push_words(&asserter, &[word]);
+        }
+    })
+    .await;
+    assert_eq!(*pool.balances.read().await, [U256::from(200); 2]);
+    let PublisherMessage::CurveStateUpdate {
+        pool: address,
+        block_number,
+        balances,
+        a,
+        fee,
+    } = &messages[1]
+    else {
+        panic!("not a Curve update: {:?}", messages[1]);
+    };
+    assert_eq!((*address, *block_number), (POOL, BLOCK + 2));
+    assert_eq!(balances, &[U256::from(200); 2]);
+    assert_eq!((*a, *fee), (U256::from(200), U256::from(200)));
+}
+
+#[tokio::test]
+async fn test_balancer_update_reports_changes() {
+    let asserter = Asserter::new();
+    let provider = mocked(&asserter);
+    let tokens = tokens(&provider);
+    let half = U256::from(500_000_000_000_000_000u64);
+    let pool = BalancerPool::from_parts(
+        POOL,
+        provider,
+        tokens.to_vec(),
+        vec![half, half],
+        U256::from(3_000_000_000_000_000u64),
+        Address::repeat_byte(0xba),
+        [0x11; 32],
+    );
+
+    let push_paused = |paused: bool| {
+        asserter.push_success(&Bytes::from(getPausedStateCall::abi_encode_returns(
+            &getPausedStateReturn {
+                paused,
+                pauseWindowEndTime: U256::ZERO,
+                bufferPeriodEndTime: U256::ZERO,
+            },
+        )));
+    };
+    let messages = assert_updates(&pool, &asserter, |block, changed| {
+        asserter.push_success(&U64::from(block));
+        // The vault's pause state, then the pool's tokens, pause state, recovery mode and fee.
+        push_paused(false);
+        let balance = if changed { 2_000 } else { 1_000 };
+        asserter.push_success(&Bytes::from(getPoolTokensCall::abi_encode_returns(
+            &getPoolTokensReturn {
+                tokens: vec![Address::repeat_byte(0x0a), Address::repeat_byte(0x0b)],
+                balances: vec![U256::from(balance); 2],
+                lastChangeBlock: U256::ZERO,
+            },
+        )));
+        push_paused(false);
+        asserter.push_success(&Bytes::from(inRecoveryModeCall::abi_encode_returns(&false)));
+        asserter.push_success(&Bytes::from(getSwapFeePercentageCall::abi_encode_returns(
+            &U256::from(3_000_000_000_000_000u64),
+        )));
+    })
+    .await;
+    let PublisherMessage::BalancerStateUpdate {
+        block_number,
+        balances,
+        swap_fee,
+        is_paused,
+        ..
+    } = &messages[1]
+    else {
+        panic!("not a Balancer update: {:?}", messages[1]);
+    };
+    assert_eq!(*block_number, BLOCK + 2);
+    assert_eq!(balances, &[U256::from(2_000); 2]);
+    assert_eq!(*swap_fee, U256::from(3_000_000_000_000_000u64));
+    assert!(!is_paused);
+}
+
+#[tokio::test]
+async fn test_dropped_subscribers_are_pruned() {
+    let subscribers = SubscriberList::<DynProvider>::default();
+    let kept: Arc<dyn Subscriber<DynProvider>> = Arc::new(Recorder {
+        id: 1,
+        ..Default::default()
+    });
+    let dropped: Arc<dyn Subscriber<DynProvider>> = Arc::new(Recorder {
+        id: 2,
+        ..Default::default()
+    });
+    subscribers.subscribe(Arc::downgrade(&kept)).await;
+    subscribers.subscribe(Arc::downgrade(&dropped)).await;
+    let weak_dropped: Weak<dyn Subscriber<DynProvider>> = Arc::downgrade(&dropped);
+    drop(dropped);
+    assert!(weak_dropped.upgrade().is_none());
+
+    subscribers
+        .notify(PublisherMessage::PoolStateUpdate(Default::default()))
+        .await;
+    assert_eq!(subscribers.len().await, 1);
+
+    subscribers.unsubscribe(1).await;
+    assert!(subscribers.is_empty().await);
+}
@@ -0,0 +1,268 @@
+mod common;
+
+use alloy::transports::mock::Asserter;
+use alloy_primitives::{Address, B256, Bytes, LogData, U256, address};
+use alloy_provider::{Provider, ProviderBuilder};
+use alloy_rpc_types::Log;
+use alloy_sol_types::sol;
+use arbrs::arbitrage::cache::ArbitrageCache;
+use arbrs::arbitrage::cycle::ArbitrageCycle;
+use arbrs::arbitrage::engine::{ArbitrageEngine, EngineConfig};
+use arbrs::arbitrage::types::ArbitragePath;
+use arbrs::curve::types::CurvePoolSnapshot;
+use arbrs::manager::token_manager::TokenManager;
+use arbrs::pool::state_updater::StateUpdater;
+use arbrs::pool::strategy::StandardV2Logic;
+use arbrs::pool::uniswap_v2::UniswapV2Pool;
+use arbrs::pool::uniswap_v4::{UNISWAP_V4_POOL_MANAGER, v4_pool_address};
+use arbrs::pool::{LiquidityPool, PoolSnapshot};
+use common::token;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+sol! {
+    interface IUniswapV2Pair {
+        event Sync(uint112 reserve0, uint112 reserve1);
+    }
+    event PoolBalanceChanged(bytes32 indexed poolId, address indexed liquidityProvider, address[] tokens, int256[] deltas, uint256[] protocolFeeAmounts);
+    event Approval(address indexed owner, address indexed spender, uint256 value);
+    interface ICurvePool {
+        event RampA(uint256 old_A, uint256 new_A, uint256 initial_time, uint256 future_time);
+        event StopRampA(uint256 A, uint256 t);
+    }
+    interface ICurvePool3 {
+        event AddLiquidity(address indexed provider, uint256[3] token_amounts, uint256[3] fees, uint256 invariant, uint256 token_supply);
+    }
+    interface IPoolManager {
+        event Swap(bytes32 indexed id, address indexed sender, int128 amount0, int128 amount1, uint160 sqrtPriceX96, uint128 liquidity, int24 tick, uint24 fee);
+        event ModifyLiquidity(bytes32 indexed id, address indexed sender, int24 tickLower, int24 tickUpper, int256 liquidityDelta, bytes32 salt);
+    }
+}
+
+type DynProvider = dyn Provider + Send + Sync;
+
+const WETH: Address = address!("C02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2");
+const VAULT: Address = address!("BA12222222228d8Ba445958a75a0704d566BF2C8");
+const POOL_A: Address = Address::repeat_byte(0x01);
+const POOL_B: Address = Address::repeat_byte(0x02);
+
+fn log(address: Address, data: LogData) -> Log {
+    Log {
+        inner: alloy_primitives::Log { address, data },
+        ..Default::default()
+    }
+}
+
+fn sync_log(pool: Address) -> Log {
+    log(
+        pool,
+        LogData::from(&IUniswapV2Pair::Sync {
+            reserve0: Default::default(),
+            reserve1: Default::default(),
+        }),
+    )
+}
+
+/// Queues the `getReserves()` result of one snapshot.
+fn push_reserves(asserter: &Asserter) {
+    let words = [U256::from(1_000_000), U256::from(2_000_000), U256::ZERO];
+    asserter.push_success(&Bytes::from(
+        words
+            .iter()
+            .flat_map(|word| word.to_be_bytes::<32>())
+            .collect::<Vec<u8>>(),
+    ));
+}
+
+#[test]
+fn test_state_changing_logs_mark_their_pool_dirty() {
+    let updater = StateUpdater::new();
+    assert!(updater.record_log(&sync_log(POOL_A)));
+
+    let mut pool_id = [0u8; 32];
+    pool_id[..20].copy_from_slice(POOL_B.as_slice());
+    let balance_change = PoolBalanceChanged {
+        poolId: B256::from(pool_id),
+        liquidityProvider: Address::repeat_byte(0xaa),
+        tokens: vec![],
+        deltas: vec![],
+        protocolFeeAmounts: vec![],
+    };
+    assert!(updater.record_log(&log(VAULT, LogData::from(&balance_change))));
+
+    let approval = Approval {
+        owner: Address::repeat_byte(0xaa),
+        spender: Address::repeat_byte(0xbb),
+        value: U256::from(1),
+    };
+    let unrelated = Address::repeat_byte(0x03);
+    assert!(!updater.record_log(&log(unrelated, LogData::from(&approval))));
+
+    assert!(updater.is_dirty(&POOL_A));
+    assert!(updater.is_dirty(&POOL_B));
+    assert!(!updater.is_dirty(&unrelated));
+    assert!(!updater.is_dirty(&VAULT));
+}
+
+#[test]
+fn test_v4_pool_manager_logs_mark_the_pool_of_their_id_dirty() {
+    let updater = StateUpdater::new();
+    let (swapped, modified) = (B256::repeat_byte(0x11), B256::repeat_byte(0x22));
+
+    let swap = IPoolManager::Swap {
+        id: swapped,
+        sender: Address::repeat_byte(0xaa),
+        amount0: Default::default(),
+        amount1: Default::default(),
+        sqrtPriceX96: Default::default(),
+        liquidity: 0,
+        tick: Default::default(),
+        fee: Default::default(),
+    };
+    assert!(updater.record_log(&log(UNISWAP_V4_POOL_MANAGER, LogData::from(&swap))));
+    let modify = IPoolManager::ModifyLiquidity {
+        id: modified,
+        sender: Address::repeat_byte(0xaa),
+        tickLower: Default::default(),
+        tickUpper: Default::default(),
+        liquidityDelta: Default::default(),
+        salt: B256::ZERO,
+    };
+    assert!(updater.record_log(&log(UNISWAP_V4_POOL_MANAGER, LogData::from(&modify))));
+
+    assert!(updater.is_dirty(&v4_pool_address(swapped)));
+    assert!(updater.is_dirty(&v4_pool_address(modified)));
+    assert!(!updater.is_dirty(&UNISWAP_V4_POOL_MANAGER));
+}
+
+#[test]
+fn test_curve_pools_stay_dirty_while_their_a_ramps() {
+    let updater = StateUpdater::new();
+    let add_liquidity = ICurvePool3::AddLiquidity {
+        provider: Address::repeat_byte(0xaa),
+        token_amounts: [U256::from(1); 3],
+        fees: [U256::ZERO; 3],
+        invariant: U256::from(3),
+        token_supply: U256::from(3),
+    };
+    assert!(updater.record_log(&log(POOL_A, LogData::from(&add_liquidity))));
+    let ramp = ICurvePool::RampA {
+        old_A: U256::from(100),
+        new_A: U256::from(200),
+        initial_time: U256::from(1_000),
+        future_time: U256::from(2_000),
+    };
+    assert!(updater.record_log(&log(POOL_B, LogData::from(&ramp))));
+    assert!(updater.is_dirty(&POOL_A));
+
+    let curve_snapshot = |block_timestamp| {
+        PoolSnapshot::Curve(CurvePoolSnapshot {
+            block_timestamp,
+            ..Default::default()
+        })
+    };
+    let snapshots = HashMap::from([
+        (POOL_A, curve_snapshot(1_500)),
+        (POOL_B, curve_snapshot(1_500)),
+    ]);
+    updater.store_snapshots(100, &snapshots);
+    assert!(!updater.is_dirty(&POOL_A));
+    assert!(
+        updater.is_dirty(&POOL_B),
+        "mid-ramp A changes without an event"
+    );
+
+    updater.store_snapshots(101, &HashMap::from([(POOL_B, curve_snapshot(2_000))]));
+    assert!(!updater.is_dirty(&POOL_B), "the ramp has ended");
+
+    let stop = ICurvePool::StopRampA {
+        A: U256::from(150),
+        t: U256::from(3_000),
+    };
+    updater.mark_ramping(POOL_A, 5_000);
+    assert!(updater.is_dirty(&POOL_A));
+    assert!(updater.record_log(&log(POOL_A, LogData::from(&stop))));
+    updater.store_snapshots(102, &HashMap::new());
+    assert!(!updater.is_dirty(&POOL_A));
+}
+
+#[tokio::test]
+async fn test_engine_only_resnapshots_dirty_pools() {
+    let asserter = Asserter::new();
+    let provider: Arc<DynProvider> =
+        Arc::new(ProviderBuilder::new().connect_mocked_client(asserter.clone()));
+    let (weth, other) = (
+        token(WETH, provider.clone()),
+        token(Address::repeat_byte(0xee), provider.clone()),
+    );
+    let pools: Vec<Arc<dyn LiquidityPool<DynProvider>>> = [POOL_A, POOL_B]
+        .into_iter()
+        .map(|address| {
+            Arc::new(UniswapV2Pool::new(
+                address,
+                weth.clone(),
+                other.clone(),
+                provider.clone(),
+                StandardV2Logic,
+            )) as Arc<dyn LiquidityPool<DynProvider>>
+        })
+        .collect();
+    let cache = Arc::new(ArbitrageCache::new());
+    cache
+        .add_path(Arc::new(ArbitrageCycle::new(ArbitragePath {
+            pools,
+            path: vec![weth.clone(), other, weth.clone()],
+            profit_token: weth,
+        })))
+        .await;
+
+    let updater = Arc::new(StateUpdater::new());
+    let engine = ArbitrageEngine::new(
+        cache,
+        Arc::new(TokenManager::in_memory(provider.clone(), 1)),
+        provider.clone(),
+    )
+    .with_config(EngineConfig {
+        flashloan_sources: HashMap::new(),
+        ..Default::default()
+    })
+    .with_state_updater(updater.clone());
+
+    // Block 10: nothing is cached yet, so both pools are fetched.
+    asserter.push_success(&Vec::<Log>::new());
+    updater.update(provider.as_ref(), 10).await.unwrap();
+    push_reserves(&asserter);
+    push_reserves(&asserter);
+    asserter.push_success(&U256::from(1));
+    engine.find_opportunities(Some(10)).await;
+    let stats = engine.last_stats();
+    assert_eq!((stats.failed_snapshots, stats.reused_snapshots), (0, 0));
+
+    // Block 11: only pool A synced, so pool B's snapshot is reused.
+    asserter.push_success(&vec![sync_log(POOL_A)]);
+    updater.update(provider.as_ref(), 11).await.unwrap();
+    assert!(updater.is_dirty(&POOL_A) && !updater.is_dirty(&POOL_B));
+    push_reserves(&asserter);
+    asserter.push_success(&U256::from(1));
+    engine.find_opportunities(Some(11)).await;
+    let stats = engine.last_stats();
+    assert_eq!((stats.failed_snapshots, stats.reused_snapshots), (0, 1));
+
+    // A reorg back to block 11 invalidates everything without fetching logs.
+    updater.update(provider.as_ref(), 11).await.unwrap();
+    assert!(updater.is_dirty(&POOL_B));
+    engine.invalidate_from(11).await;
+    push_reserves(&asserter);
+    push_reserves(&asserter);
+    asserter.push_success(&U256::from(1));
+    engine.find_opportunities(Some(11)).await;
+    let stats = engine.last_stats();
+    assert_eq!((stats.failed_snapshots, stats.reused_snapshots), (0, 0));
+
+    // Block 12 without the updater having seen its logs can't trust the cache.
+    push_reserves(&asserter);
+    push_reserves(&asserter);
+    asserter.push_success(&U256::from(1));
+    engine.find_opportunities(Some(12)).await;
+    assert_eq!(engine.last_stats().reused_snapshots, 0);
+}
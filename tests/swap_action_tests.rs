@@ -0,0 +1,114 @@
+mod common;
+
+use alloy::transports::mock::Asserter;
+use alloy_primitives::{Address, U256, address};
+use alloy_provider::{Provider, ProviderBuilder};
+use arbrs::arbitrage::cache::ArbitrageCache;
+use arbrs::arbitrage::cycle::ArbitrageCycle;
+use arbrs::arbitrage::engine::ArbitrageEngine;
+use arbrs::arbitrage::types::{ArbitragePath, SwapAction, TokenRef};
+use arbrs::manager::token_manager::TokenManager;
+use arbrs::pool::strategy::StandardV2Logic;
+use arbrs::pool::uniswap_v2::{UniswapV2Pool, UniswapV2PoolState};
+use arbrs::pool::{LiquidityPool, PoolSnapshot};
+use common::named_token;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+type DynProvider = dyn Provider + Send + Sync;
+
+const WETH: Address = address!("C02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2");
+const OTHER: Address = Address::repeat_byte(0xee);
+const BLOCK: u64 = 1;
+
+fn reserves(reserve0: u64, reserve1: u64) -> PoolSnapshot {
+    let ether = U256::from(10).pow(U256::from(18));
+    PoolSnapshot::UniswapV2(UniswapV2PoolState {
+        reserve0: U256::from(reserve0) * ether,
+        reserve1: U256::from(reserve1) * ether,
+        block_number: BLOCK,
+    })
+}
+
+fn assert_send<T: Send + 'static>(_value: &T) {}
+
+#[tokio::test]
+async fn test_swap_actions_cross_threads_without_tokens() {
+    // Pools and gas price calls fail, so the snapshots come from the overrides.
+    let provider: Arc<DynProvider> =
+        Arc::new(ProviderBuilder::new().connect_mocked_client(Asserter::new()));
+    let (weth, other) = (
+        named_token(WETH, "WETH", 18, provider.clone()),
+        named_token(OTHER, "OTHER", 18, provider.clone()),
+    );
+    let pools: Vec<Arc<dyn LiquidityPool<DynProvider>>> = [0x01, 0x02]
+        .into_iter()
+        .map(|byte| {
+            Arc::new(UniswapV2Pool::new(
+                Address::repeat_byte(byte),
+                weth.clone(),
+                other.clone(),
+                provider.clone(),
+                StandardV2Logic,
+            )) as Arc<dyn LiquidityPool<DynProvider>>
+        })
+        .collect();
+    let cache = Arc::new(ArbitrageCache::new());
+    cache
+        .add_path(Arc::new(ArbitrageCycle::new(ArbitragePath {
+            pools,
+            path: vec![weth.clone(), other.clone(), weth.clone()],
+            profit_token: weth.clone(),
+        })))
+        .await;
+
+    let engine = ArbitrageEngine::new(
+        cache,
+        Arc::new(TokenManager::in_memory(provider.clone(), 1)),
+        provider,
+    )
+    .with_min_net_profit(U256::ZERO);
+    let overrides = HashMap::from([
+        (Address::repeat_byte(0x01), reserves(1_000, 2_400_000)),
+        (Address::repeat_byte(0x02), reserves(1_000, 2_000_000)),
+    ]);
+    let mut solutions = engine
+        .find_opportunities_with_overrides(Some(BLOCK), overrides)
+        .await;
+    assert_eq!(solutions.len(), 1);
+    let solution = solutions.remove(0);
+
+    let weth_ref = TokenRef::from(weth.as_ref());
+    let other_ref = TokenRef::from(other.as_ref());
+    assert_eq!(
+        weth_ref,
+        TokenRef {
+            address: WETH,
+            symbol: "WETH".to_string(),
+            decimals: 18,
+        }
+    );
+
+    let actions = solution.swap_actions.clone();
+    assert_send(&actions);
+    let hops = std::thread::spawn(move || {
+        actions
+            .into_iter()
+            .map(|action: SwapAction| (action.token_in, action.token_out, action.amount_in))
+            .collect::<Vec<_>>()
+    })
+    .join()
+    .unwrap();
+
+    assert_eq!(
+        hops,
+        vec![
+            (weth_ref.clone(), other_ref.clone(), solution.optimal_input.raw),
+            (other_ref, weth_ref, solution.swap_actions[1].amount_in),
+        ]
+    );
+    assert_eq!(
+        solution.swap_actions[0].pool_address,
+        Address::repeat_byte(0x01)
+    );
+}
@@ -0,0 +1,112 @@
+mod common;
+
+use alloy::transports::mock::Asserter;
+use alloy_primitives::{Address, U256, address};
+use alloy_provider::{Provider, ProviderBuilder};
+use arbrs::arbitrage::amount::{TokenAmount, format_units, parse_units};
+use arbrs::core::token::Token;
+use common::named_token;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+type DynProvider = dyn Provider + Send + Sync;
+
+const WETH: Address = address!("C02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2");
+const USDC: Address = address!("A0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48");
+const WBTC: Address = address!("2260FAC5E5542a773Aa44fBCfeDf7C193bc2C599");
+
+fn token(address: Address, symbol: &str, decimals: u8) -> Arc<Token<DynProvider>> {
+    let provider: Arc<DynProvider> =
+        Arc::new(ProviderBuilder::new().connect_mocked_client(Asserter::new()));
+    named_token(address, symbol, decimals, provider)
+}
+
+/// Price of 1 ETH in each token, scaled by 1e18: 2000 USDC and 0.05 WBTC.
+fn conversion_rates() -> HashMap<Address, U256> {
+    let scaled = |amount: &str| parse_units(amount, 18).unwrap();
+    HashMap::from([
+        (WETH, scaled("1")),
+        (USDC, scaled("2000")),
+        (WBTC, scaled("0.05")),
+    ])
+}
+
+#[test]
+fn test_formats_with_the_token_decimals() {
+    let usdc = TokenAmount::new(token(USDC, "USDC", 6), U256::from(1_999_999));
+    assert_eq!(usdc.to_string(), "1.999999 USDC");
+    assert_eq!(format!("{usdc:.2}"), "1.99 USDC");
+    assert_eq!(format!("{usdc:.8}"), "1.99999900 USDC");
+    assert_eq!(format!("{usdc:.0}"), "1 USDC");
+
+    let wbtc = TokenAmount::new(token(WBTC, "WBTC", 8), U256::from(5_000_000));
+    assert_eq!(wbtc.to_string(), "0.05 WBTC");
+    assert_eq!(wbtc.to_f64(), 0.05);
+
+    // Past the first limb, which an `as_limbs()[0] / 1e18` display would wrap around.
+    let weth = TokenAmount::new(token(WETH, "WETH", 18), U256::from(20_000_000_000_000_000_000u128));
+    assert_eq!(weth.to_string(), "20 WETH");
+    assert_eq!(format!("{weth:.4}"), "20.0000 WETH");
+}
+
+#[test]
+fn test_parse_units_round_trips() {
+    for (amount, decimals, raw) in [
+        ("0.05", 18, 50_000_000_000_000_000u128),
+        ("1.5", 6, 1_500_000),
+        ("21", 8, 2_100_000_000),
+        (".25", 6, 250_000),
+        ("7", 0, 7),
+    ] {
+        assert_eq!(parse_units(amount, decimals), Some(U256::from(raw)), "{amount}");
+        assert_eq!(
+            parse_units(&format_units(U256::from(raw), decimals), decimals),
+            Some(U256::from(raw))
+        );
+    }
+    assert_eq!(parse_units("0.0000001", 6), None);
+    assert_eq!(parse_units("1.2.3", 18), None);
+    assert_eq!(parse_units("-1", 18), None);
+    assert_eq!(parse_units("", 18), None);
+}
+
+#[test]
+fn test_weth_threshold_applies_to_any_decimals() {
+    let rates = conversion_rates();
+    let threshold_wei = parse_units("0.05", 18).unwrap();
+
+    // 0.05 WETH is worth 100 USDC and 0.0025 WBTC.
+    for (token, exact) in [
+        (token(WETH, "WETH", 18), "0.05 WETH"),
+        (token(USDC, "USDC", 6), "100 USDC"),
+        (token(WBTC, "WBTC", 8), "0.0025 WBTC"),
+    ] {
+        let threshold = TokenAmount::from_weth(token.clone(), threshold_wei, &rates).unwrap();
+        assert_eq!(threshold.to_string(), exact);
+        assert_eq!(threshold.to_weth(&rates), Some(threshold_wei));
+        assert!(threshold.is_at_least_weth(threshold_wei, &rates));
+
+        let below = TokenAmount::new(token, threshold.raw - U256::from(1));
+        assert!(!below.is_at_least_weth(threshold_wei, &rates));
+        assert!(below < threshold);
+    }
+}
+
+#[test]
+fn test_amounts_of_different_tokens_compare_by_weth_value() {
+    let rates = conversion_rates();
+    let usdc = TokenAmount::parse(token(USDC, "USDC", 6), "150").unwrap();
+    let wbtc = TokenAmount::parse(token(WBTC, "WBTC", 8), "0.004").unwrap();
+
+    // 150 USDC is 0.075 ETH, 0.004 WBTC is 0.08 ETH.
+    assert_eq!(usdc.partial_cmp(&wbtc), None);
+    assert_ne!(usdc, wbtc);
+    assert_eq!(usdc.cmp_weth(&wbtc, &rates), Some(Ordering::Less));
+    assert_eq!(wbtc.cmp_weth(&usdc, &rates), Some(Ordering::Greater));
+
+    let unpriced = TokenAmount::new(token(Address::repeat_byte(0xee), "TKN", 18), U256::from(1));
+    assert_eq!(unpriced.to_weth(&rates), None);
+    assert_eq!(usdc.cmp_weth(&unpriced, &rates), None);
+    assert!(!unpriced.is_at_least_weth(U256::ZERO, &rates));
+}
@@ -0,0 +1,199 @@
+mod common;
+
+use alloy::transports::mock::Asserter;
+use alloy_primitives::{Address, Bytes, U64, U256, address};
+use alloy_provider::{Provider, ProviderBuilder};
+use alloy_sol_types::SolValue;
+use arbrs::arbitrage::cycle::ArbitrageCycle;
+use arbrs::arbitrage::types::{Arbitrage, ArbitragePath};
+use arbrs::core::token::Token;
+use arbrs::core::token_behavior::TokenBehavior;
+use arbrs::manager::token_manager::TokenManager;
+use arbrs::pool::strategy::StandardV2Logic;
+use arbrs::pool::uniswap_v2::{UniswapV2Pool, UniswapV2PoolState};
+use arbrs::pool::{LiquidityPool, PoolSnapshot};
+use arbrs::{ArbRsError, TokenLike};
+use common::{ether, token};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+type DynProvider = dyn Provider + Send + Sync;
+
+const WETH: Address = address!("C02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2");
+const TAXED: Address = Address::repeat_byte(0x7a);
+const POOL: Address = Address::repeat_byte(0x01);
+
+/// Queues the holder's balance and the probe's `(succeeded, before, after)` result.
+fn push_transfer(asserter: &Asserter, balance: U256, succeeded: bool, received: U256) {
+    asserter.push_success(&U64::from(100));
+    asserter.push_success(&Bytes::from(balance.abi_encode()));
+    asserter.push_success(&Bytes::from(
+        (succeeded, U256::from(5), U256::from(5) + received).abi_encode_params(),
+    ));
+}
+
+#[tokio::test]
+async fn test_simulated_transfer_measures_the_tax() {
+    let asserter = Asserter::new();
+    let provider: Arc<DynProvider> =
+        Arc::new(ProviderBuilder::new().connect_mocked_client(asserter.clone()));
+    let token_manager = TokenManager::in_memory(provider.clone(), 1);
+    let taxed = token(TAXED, provider.clone());
+
+    // 1% of the balance is sent, and 98% of it arrives.
+    push_transfer(&asserter, ether(1_000), true, ether(98) / U256::from(10));
+    let behavior = token_manager
+        .analyze_token_behavior(&taxed, POOL)
+        .await
+        .unwrap();
+    assert_eq!(behavior.transfer_tax_bps, 200);
+    assert_eq!(taxed.transfer_tax_bps(), 200);
+    assert_eq!(token_manager.token_behavior(TAXED), Some(behavior));
+    assert_eq!(taxed.received_amount(ether(1)), ether(98) / U256::from(100));
+
+    // A transfer that moves nothing can't be measured.
+    let other = token(Address::repeat_byte(0x7b), provider.clone());
+    push_transfer(&asserter, ether(1_000), false, U256::ZERO);
+    let result = token_manager.analyze_token_behavior(&other, POOL).await;
+    assert!(matches!(result, Err(ArbRsError::TokenStandardError(..))));
+    assert_eq!(token_manager.token_behavior(other.address()), None);
+}
+
+#[tokio::test]
+async fn test_pool_tokens_are_only_analysed_when_enabled() {
+    let asserter = Asserter::new();
+    let provider: Arc<DynProvider> =
+        Arc::new(ProviderBuilder::new().connect_mocked_client(asserter.clone()));
+    let tokens = [
+        token(WETH, provider.clone()),
+        token(TAXED, provider.clone()),
+    ];
+
+    TokenManager::in_memory(provider.clone(), 1)
+        .analyze_pool_tokens(POOL, &tokens)
+        .await;
+    assert!(asserter.read_q().is_empty());
+
+    let token_manager = TokenManager::in_memory(provider.clone(), 1).with_transfer_tax_detection();
+    push_transfer(&asserter, ether(1_000), true, ether(10));
+    push_transfer(&asserter, ether(1_000), true, ether(9));
+    token_manager.analyze_pool_tokens(POOL, &tokens).await;
+    assert_eq!(
+        token_manager.token_behavior(WETH),
+        Some(TokenBehavior::default())
+    );
+    assert_eq!(
+        token_manager
+            .token_behavior(TAXED)
+            .unwrap()
+            .transfer_tax_bps,
+        1_000
+    );
+
+    // Analysed tokens aren't simulated again.
+    token_manager.analyze_pool_tokens(POOL, &tokens).await;
+    assert!(asserter.read_q().is_empty());
+}
+
+#[test]
+fn test_transfer_tax_removes_the_phantom_profit() {
+    let provider: Arc<DynProvider> =
+        Arc::new(ProviderBuilder::new().connect_mocked_client(Asserter::new()));
+    let (weth, taxed) = (
+        token(WETH, provider.clone()),
+        token(TAXED, provider.clone()),
+    );
+    let pool = |byte: u8| {
+        Arc::new(UniswapV2Pool::new(
+            Address::repeat_byte(byte),
+            weth.clone(),
+            taxed.clone(),
+            provider.clone(),
+            StandardV2Logic,
+        )) as Arc<dyn LiquidityPool<DynProvider>>
+    };
+    let cycle = ArbitrageCycle::new(ArbitragePath {
+        pools: vec![pool(0x01), pool(0x02)],
+        path: vec![weth.clone(), taxed.clone(), weth.clone()],
+        profit_token: weth.clone(),
+    });
+    // The second pool prices the token 1.5% higher.
+    let state = |reserve0: u64, reserve1: u64| {
+        PoolSnapshot::UniswapV2(UniswapV2PoolState {
+            reserve0: ether(reserve0),
+            reserve1: ether(reserve1),
+            block_number: 1,
+        })
+    };
+    let snapshots = HashMap::from([
+        (Address::repeat_byte(0x01), state(1_000, 1_000_000)),
+        (Address::repeat_byte(0x02), state(1_015, 1_000_000)),
+    ]);
+    let amount_in = ether(1);
+
+    let untaxed = cycle.calculate_out_amount(amount_in, &snapshots).unwrap();
+    assert!(untaxed > amount_in);
+    assert!(cycle.check_viability(&snapshots).unwrap());
+
+    // A 2% tax is taken from the token on its way from the first pool to the second.
+    let Token::Erc20(data) = taxed.as_ref() else {
+        unreachable!()
+    };
+    data.set_transfer_tax_bps(200);
+    let taxed_out = cycle.calculate_out_amount(amount_in, &snapshots).unwrap();
+    assert!(taxed_out < amount_in);
+    // Walking back from the output adds the tax back on.
+    let implied_input = cycle.calculate_in_amount(taxed_out, &snapshots).unwrap();
+    assert!(implied_input <= amount_in);
+    assert_eq!(
+        cycle
+            .calculate_out_amount(implied_input, &snapshots)
+            .unwrap(),
+        taxed_out
+    );
+    assert!(!cycle.check_viability(&snapshots).unwrap());
+
+    let (first, second) = (&cycle.path.pools[0], &cycle.path.pools[1]);
+    let bought = first
+        .calculate_tokens_out(&weth, &taxed, amount_in, &snapshots[&first.address()])
+        .unwrap();
+    let received = bought * U256::from(98) / U256::from(100);
+    assert_eq!(
+        taxed_out,
+        second
+            .calculate_tokens_out(&taxed, &weth, received, &snapshots[&second.address()])
+            .unwrap()
+    );
+}
+
+#[cfg(feature = "db")]
+#[tokio::test]
+async fn test_transfer_tax_is_loaded_from_the_database() {
+    use arbrs::db::DbManager;
+
+    let provider: Arc<DynProvider> =
+        Arc::new(ProviderBuilder::new().connect_mocked_client(Asserter::new()));
+    let db = Arc::new(DbManager::new("sqlite::memory:").await.unwrap());
+    db.save_token(&token(TAXED, provider.clone()))
+        .await
+        .unwrap();
+    assert_eq!(
+        db.get_token_by_address(TAXED)
+            .await
+            .unwrap()
+            .unwrap()
+            .transfer_tax_bps,
+        None
+    );
+    db.save_token_transfer_tax(TAXED, 200).await.unwrap();
+
+    let token_manager = TokenManager::new(provider, 1, db);
+    let taxed = token_manager.get_token(TAXED).await.unwrap();
+    assert_eq!(taxed.transfer_tax_bps(), 200);
+    assert_eq!(
+        token_manager.token_behavior(TAXED),
+        Some(TokenBehavior {
+            transfer_tax_bps: 200
+        })
+    );
+}
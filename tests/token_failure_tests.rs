@@ -0,0 +1,189 @@
+#![cfg(feature = "db")]
+
+mod common;
+
+use alloy::transports::mock::Asserter;
+use alloy_primitives::{Address, LogData, U64, U160, address, aliases::I24, aliases::U24};
+use alloy_provider::{Provider, ProviderBuilder};
+use alloy_rpc_types::Log;
+use alloy_sol_types::sol;
+use arbrs::ArbRsError;
+use arbrs::db::DbManager;
+use arbrs::manager::token_manager::{DEFAULT_TOKEN_RETRY_BLOCKS, TokenManager};
+use arbrs::manager::uniswap_v4_pool_manager::UniswapV4PoolManager;
+use arbrs::pool::uniswap_v4::{PoolKey, UNISWAP_V4_POOL_MANAGER, v4_pool_address};
+use common::named_token;
+use std::sync::Arc;
+
+const WETH_ADDRESS: Address = address!("C02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2");
+// A contract whose `decimals()`, `symbol()` and `name()` all revert.
+const BROKEN: Address = Address::repeat_byte(0xbb);
+type DynProvider = dyn Provider + Send + Sync;
+
+sol! {
+    event Initialize(bytes32 indexed id, address indexed currency0, address indexed currency1, uint24 fee, int24 tickSpacing, address hooks, uint160 sqrtPriceX96, int24 tick);
+}
+
+struct Fixture {
+    asserter: Asserter,
+    provider: Arc<DynProvider>,
+    db_manager: Arc<DbManager>,
+}
+
+async fn setup() -> Fixture {
+    let asserter = Asserter::new();
+    let provider: Arc<DynProvider> =
+        Arc::new(ProviderBuilder::new().connect_mocked_client(asserter.clone()));
+    let db_manager = Arc::new(DbManager::new("sqlite::memory:").await.unwrap());
+    let weth = named_token(WETH_ADDRESS, "WETH", 18, provider.clone());
+    db_manager.save_token(&weth).await.unwrap();
+    Fixture {
+        asserter,
+        provider,
+        db_manager,
+    }
+}
+
+/// A manager as a new run would create it, refusing tokens without `decimals()`.
+fn token_manager(fixture: &Fixture) -> Arc<TokenManager<DynProvider>> {
+    Arc::new(
+        TokenManager::new(fixture.provider.clone(), 1, fixture.db_manager.clone())
+            .with_default_decimals(None),
+    )
+}
+
+/// Answers the metadata calls a probe of `BROKEN` makes.
+fn push_reverts(asserter: &Asserter) {
+    for _ in 0..3 {
+        asserter.push_failure_msg("execution reverted");
+    }
+}
+
+#[tokio::test]
+async fn test_failed_token_is_only_probed_by_the_first_run() {
+    let fixture = setup().await;
+    let first_run = token_manager(&fixture);
+    push_reverts(&fixture.asserter);
+    fixture.asserter.push_success(&U64::from(100));
+    assert!(matches!(
+        first_run.get_token(BROKEN).await,
+        Err(ArbRsError::TokenStandardError(address, _)) if address == BROKEN
+    ));
+    assert!(fixture.asserter.read_q().is_empty());
+    let failure = first_run.token_failure(BROKEN).await.unwrap();
+    assert_eq!(failure.error_class, "token");
+    assert_eq!(failure.retry_after_block, 100 + DEFAULT_TOKEN_RETRY_BLOCKS);
+
+    // Neither this run nor the next calls the token again; a call would find the mock
+    // empty and fail otherwise.
+    assert_eq!(
+        first_run.get_token(BROKEN).await.unwrap_err(),
+        ArbRsError::TokenUnusable(BROKEN)
+    );
+    let second_run = token_manager(&fixture);
+    assert_eq!(
+        second_run.get_token(BROKEN).await.unwrap_err(),
+        ArbRsError::TokenUnusable(BROKEN)
+    );
+    let tokens = second_run.get_tokens([BROKEN, WETH_ADDRESS]).await;
+    assert_eq!(tokens[&BROKEN], Err(ArbRsError::TokenUnusable(BROKEN)));
+    assert!(tokens[&WETH_ADDRESS].is_ok());
+}
+
+#[tokio::test]
+async fn test_failed_token_is_probed_again_after_its_retry_block_or_invalidation() {
+    let fixture = setup().await;
+    let token_manager = Arc::new(
+        TokenManager::new(fixture.provider.clone(), 1, fixture.db_manager.clone())
+            .with_default_decimals(None)
+            .with_failure_retry_blocks(10),
+    );
+    token_manager.observe_block(100);
+    push_reverts(&fixture.asserter);
+    assert!(token_manager.get_token(BROKEN).await.is_err());
+
+    token_manager.observe_block(109);
+    assert_eq!(
+        token_manager.get_token(BROKEN).await.unwrap_err(),
+        ArbRsError::TokenUnusable(BROKEN)
+    );
+
+    // Due a retry, which fails again and pushes the retry block on.
+    token_manager.observe_block(110);
+    push_reverts(&fixture.asserter);
+    assert!(matches!(
+        token_manager.get_token(BROKEN).await,
+        Err(ArbRsError::TokenStandardError(..))
+    ));
+    assert!(fixture.asserter.read_q().is_empty());
+    assert_eq!(
+        token_manager
+            .token_failure(BROKEN)
+            .await
+            .unwrap()
+            .retry_after_block,
+        120
+    );
+
+    token_manager.invalidate(BROKEN).await;
+    assert_eq!(token_manager.token_failure(BROKEN).await, None);
+    assert_eq!(
+        fixture.db_manager.load_token_failure(BROKEN).await.unwrap(),
+        None
+    );
+    push_reverts(&fixture.asserter);
+    assert!(matches!(
+        token_manager.get_token(BROKEN).await,
+        Err(ArbRsError::TokenStandardError(..))
+    ));
+}
+
+#[tokio::test]
+async fn test_discovery_skips_pools_of_unusable_tokens() {
+    let fixture = setup().await;
+    let token_manager = token_manager(&fixture);
+    push_reverts(&fixture.asserter);
+    fixture.asserter.push_success(&U64::from(100));
+    assert!(token_manager.get_token(BROKEN).await.is_err());
+
+    let key = PoolKey {
+        currency0: BROKEN,
+        currency1: WETH_ADDRESS,
+        fee: 3_000,
+        tick_spacing: 60,
+        hooks: Address::ZERO,
+    };
+    let event = Initialize {
+        id: key.pool_id(),
+        currency0: key.currency0,
+        currency1: key.currency1,
+        fee: U24::from(key.fee),
+        tickSpacing: I24::try_from(key.tick_spacing).unwrap(),
+        hooks: key.hooks,
+        sqrtPriceX96: U160::from(1) << 96,
+        tick: I24::ZERO,
+    };
+    fixture.asserter.push_success(&vec![Log {
+        inner: alloy_primitives::Log {
+            address: UNISWAP_V4_POOL_MANAGER,
+            data: LogData::from(&event),
+        },
+        ..Default::default()
+    }]);
+    let mut manager = UniswapV4PoolManager::new(token_manager, fixture.provider.clone(), 0);
+    assert!(
+        manager
+            .discover_pools_in_range(10)
+            .await
+            .unwrap()
+            .is_empty()
+    );
+    assert!(fixture.asserter.read_q().is_empty());
+    assert_eq!(
+        manager.skipped_pools(),
+        vec![(
+            v4_pool_address(key.pool_id()),
+            ArbRsError::TokenUnusable(BROKEN).to_string()
+        )]
+    );
+}
@@ -0,0 +1,173 @@
+mod common;
+
+use alloy::transports::mock::Asserter;
+use alloy_primitives::Address;
+use alloy_provider::{Provider, ProviderBuilder};
+use arbrs::ArbRsError;
+use arbrs::arbitrage::cache::ArbitrageCache;
+use arbrs::arbitrage::finder::MinLiquidityFilter;
+use arbrs::arbitrage::types::{Arbitrage, PathKey};
+use arbrs::core::token::WETH_ADDRESS;
+use arbrs::core::token_policy::{TokenPolicy, TokenPolicyMode};
+use arbrs::manager::token_manager::TokenManager;
+use arbrs::manager::uniswap_v2_pool_manager::UniswapV2PoolManager;
+use arbrs::pool::LiquidityPool;
+use arbrs::pool::strategy::StandardV2Logic;
+use arbrs::pool::uniswap_v2::UniswapV2Pool;
+use common::token;
+use std::collections::HashSet;
+use std::sync::Arc;
+
+type DynProvider = dyn Provider + Send + Sync;
+
+const USDC: Address = Address::repeat_byte(0x0a);
+const SCAM: Address = Address::repeat_byte(0x0b);
+
+/// A token manager knowing WETH, USDC and SCAM, so nothing is fetched, under `policy`.
+fn token_manager(
+    provider: &Arc<DynProvider>,
+    policy: Arc<TokenPolicy>,
+) -> Arc<TokenManager<DynProvider>> {
+    let token_manager = TokenManager::in_memory(provider.clone(), 1).with_token_policy(policy);
+    for address in [WETH_ADDRESS, USDC, SCAM] {
+        token_manager.insert_token(token(address, provider.clone()));
+    }
+    Arc::new(token_manager)
+}
+
+/// Two WETH pairs of `other` at addresses starting with `first_byte`, making one cycle.
+fn pair_pools(
+    provider: &Arc<DynProvider>,
+    other: Address,
+    first_byte: u8,
+) -> Vec<Arc<dyn LiquidityPool<DynProvider>>> {
+    (0..2)
+        .map(|i| {
+            Arc::new(UniswapV2Pool::new(
+                Address::repeat_byte(first_byte + i),
+                token(WETH_ADDRESS, provider.clone()),
+                token(other, provider.clone()),
+                provider.clone(),
+                StandardV2Logic,
+            )) as Arc<dyn LiquidityPool<DynProvider>>
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn test_pool_holding_a_denied_token_is_not_registered() {
+    let asserter = Asserter::new();
+    let provider: Arc<DynProvider> =
+        Arc::new(ProviderBuilder::new().connect_mocked_client(asserter.clone()));
+    let policy = Arc::new(TokenPolicy::new().with_denied([SCAM]));
+    let manager = UniswapV2PoolManager::new(
+        token_manager(&provider, policy),
+        provider.clone(),
+        Address::ZERO,
+        0,
+    );
+
+    let scam_pool = Address::repeat_byte(0x21);
+    let error = manager
+        .build_v2_pool_with_fee(scam_pool, SCAM, WETH_ADDRESS, 3, 1000)
+        .await
+        .unwrap_err();
+    assert_eq!(
+        error,
+        ArbRsError::TokenNotAllowed {
+            pool: scam_pool,
+            token: SCAM
+        }
+    );
+    assert_eq!(error.class(), "policy");
+    assert!(manager.get_pool_by_address(scam_pool).is_none());
+
+    let usdc_pool = Address::repeat_byte(0x11);
+    manager
+        .build_v2_pool_with_fee(usdc_pool, USDC, WETH_ADDRESS, 3, 1000)
+        .await
+        .unwrap();
+    assert!(manager.get_pool_by_address(usdc_pool).is_some());
+    // Nothing was read from the chain for either pool.
+    assert!(asserter.read_q().is_empty());
+}
+
+#[tokio::test]
+async fn test_switching_to_a_whitelist_prunes_paths_through_other_tokens() {
+    let provider: Arc<DynProvider> =
+        Arc::new(ProviderBuilder::new().connect_mocked_client(Asserter::new()));
+    let policy = Arc::new(TokenPolicy::new());
+    let token_manager = token_manager(&provider, policy.clone());
+    let mut pools = pair_pools(&provider, USDC, 0x11);
+    pools.extend(pair_pools(&provider, SCAM, 0x21));
+
+    let cache = ArbitrageCache::new();
+    let filter = MinLiquidityFilter::default();
+    let report = cache
+        .rebuild_with_filter(pools.clone(), &token_manager, 2, &filter)
+        .await;
+    let scam_pools = [Address::repeat_byte(0x21), Address::repeat_byte(0x22)];
+    let (scam_paths, usdc_paths): (Vec<_>, Vec<_>) = report.paths.iter().partition(|path| {
+        path.get_involved_pools()
+            .iter()
+            .any(|pool| scam_pools.contains(pool))
+    });
+    assert!(!scam_paths.is_empty() && !usdc_paths.is_empty());
+    let key_set = |paths: &[&Arc<dyn Arbitrage<DynProvider>>]| -> HashSet<PathKey> {
+        paths.iter().map(|path| path.path_key()).collect()
+    };
+
+    // Restricted to WETH and USDC, the SCAM pools and the paths through them are refused.
+    policy.allow([WETH_ADDRESS, USDC]);
+    policy.set_mode(TokenPolicyMode::Whitelist);
+    assert_eq!(policy.refused_pools(&pools), scam_pools);
+    let dropped: HashSet<PathKey> = cache.prune_refused(&policy).await.into_iter().collect();
+    assert_eq!(dropped, key_set(&scam_paths));
+    let cached: Vec<_> = cache.paths.read().await.clone();
+    assert_eq!(
+        cached
+            .iter()
+            .map(|path| path.path_key())
+            .collect::<HashSet<_>>(),
+        key_set(&usdc_paths)
+    );
+    assert!(cache.prune_refused(&policy).await.is_empty());
+
+    // Path finding leaves them out from then on.
+    let report = cache
+        .rebuild_with_filter(pools, &token_manager, 2, &filter)
+        .await;
+    assert_eq!(
+        report
+            .paths
+            .iter()
+            .map(|path| path.path_key())
+            .collect::<HashSet<_>>(),
+        key_set(&usdc_paths)
+    );
+}
+
+#[cfg(feature = "db")]
+#[tokio::test]
+async fn test_policy_is_persisted() {
+    let db_manager = arbrs::db::DbManager::new("sqlite::memory:").await.unwrap();
+    let empty = db_manager.load_token_policy().await.unwrap();
+    assert_eq!(empty.mode(), TokenPolicyMode::Open);
+    assert!(empty.allowed().is_empty() && empty.denied().is_empty());
+
+    let policy = TokenPolicy::whitelist([WETH_ADDRESS, USDC]).with_denied([SCAM]);
+    db_manager.save_token_policy(&policy).await.unwrap();
+    let loaded = db_manager.load_token_policy().await.unwrap();
+    assert_eq!(loaded.mode(), TokenPolicyMode::Whitelist);
+    assert_eq!(loaded.allowed(), policy.allowed());
+    assert_eq!(loaded.denied(), [SCAM]);
+
+    // Saving again replaces the stored lists.
+    policy.forget(USDC);
+    policy.set_mode(TokenPolicyMode::Open);
+    db_manager.save_token_policy(&policy).await.unwrap();
+    let loaded = db_manager.load_token_policy().await.unwrap();
+    assert_eq!(loaded.mode(), TokenPolicyMode::Open);
+    assert_eq!(loaded.allowed(), [WETH_ADDRESS]);
+    assert!(!loaded.allows(SCAM) && loaded.allows(USDC));
+}
@@ -1,3 +1,5 @@
+#![cfg(feature = "db")]
+
 use alloy_primitives::{Address, U256, address};
 use alloy_provider::{Provider, ProviderBuilder};
 use arbrs::core::token::TokenLike;
@@ -82,6 +84,38 @@ async fn test_non_compliant_token_fallback() {
     assert_eq!(mkr_token.decimals(), 18);
 }
 
+#[tokio::test]
+async fn test_batched_tokens_include_bytes32_symbols() {
+    let manager = setup_manager().await;
+    let mkr_address = address!("9f8F72aA9304c8B593d555F12eF6589cC3A579A2");
+    let tokens = manager
+        .get_token_list(&[WETH_ADDRESS, mkr_address, WBTC_ADDRESS])
+        .await
+        .unwrap();
+
+    let symbols: Vec<&str> = tokens.iter().map(|token| token.symbol()).collect();
+    assert_eq!(symbols, ["WETH", "MKR", "WBTC"]);
+    assert_eq!(tokens[1].decimals(), 18);
+    assert_eq!(tokens[2].decimals(), 8);
+    // Served from the registry afterwards.
+    let mkr_token = manager.get_token(mkr_address).await.unwrap();
+    assert!(Arc::ptr_eq(&mkr_token, &tokens[1]));
+}
+
+#[tokio::test]
+async fn test_token_without_metadata_gets_defaults() {
+    // The router implements none of `decimals()`, `symbol()` and `name()`.
+    let manager = setup_manager().await;
+    let token = manager.get_token(ROUTER_ADDRESS).await.unwrap();
+    assert!(token.symbol().starts_with("UNKNOWN@"));
+    assert_eq!(token.decimals(), 18);
+
+    let strict = setup_manager().await.with_default_decimals(None);
+    let tokens = strict.get_tokens([ROUTER_ADDRESS, WETH_ADDRESS]).await;
+    assert!(tokens[&ROUTER_ADDRESS].is_err());
+    assert_eq!(tokens[&WETH_ADDRESS].as_ref().unwrap().symbol(), "WETH");
+}
+
 #[tokio::test]
 async fn test_native_ether_placeholder() {
     let manager = setup_manager().await;
@@ -0,0 +1,221 @@
+mod common;
+
+use alloy::transports::mock::Asserter;
+use alloy_primitives::{Address, Bytes, U256};
+use alloy_provider::{Provider, ProviderBuilder};
+use arbrs::TokenLike;
+use arbrs::curve::pool::CurveStableswapPool;
+use arbrs::curve::pool_attributes::{ParameterFetcherType, PoolAttributes, SwapStrategyType};
+use arbrs::curve::types::CurvePoolSnapshot;
+use arbrs::manager::token_manager::TokenManager;
+use arbrs::pool::{LiquidityPool, PoolSnapshot};
+use common::{attributes, named_token, token};
+use std::sync::Arc;
+
+type DynProvider = dyn Provider + Send + Sync;
+
+fn pow10(exp: u64) -> U256 {
+    U256::from(10).pow(U256::from(exp))
+}
+
+/// tricrypto2's coins, USDT, WBTC and WETH, holding $30M each at $40k per bitcoin and $2k
+/// per ether.
+fn tricrypto() -> (CurveStableswapPool<DynProvider>, PoolSnapshot) {
+    tricrypto_with_decimals([6, 8, 18])
+}
+
+/// The same pool with coins of the given decimals.
+fn tricrypto_with_decimals(decimals: [u8; 3]) -> (CurveStableswapPool<DynProvider>, PoolSnapshot) {
+    let provider: Arc<DynProvider> =
+        Arc::new(ProviderBuilder::new().connect_mocked_client(Asserter::new()));
+    let tokens: Vec<_> = [0x0a, 0x0b, 0x0c]
+        .into_iter()
+        .zip(decimals)
+        .map(|(byte, decimals)| {
+            named_token(
+                Address::repeat_byte(byte),
+                "TKN",
+                decimals,
+                provider.clone(),
+            )
+        })
+        .collect();
+    let attributes = PoolAttributes {
+        precision_multipliers: decimals.iter().map(|d| pow10(18 - *d as u64)).collect(),
+        fee_gamma: Some(U256::from(10_000_000_000_000_000u128)),
+        mid_fee: Some(U256::from(4_000_000)),
+        out_fee: Some(U256::from(40_000_000)),
+        parameter_fetcher: ParameterFetcherType::Crypto,
+        ..attributes(3, SwapStrategyType::Tricrypto)
+    };
+    let pool = CurveStableswapPool::from_parts(
+        Address::repeat_byte(0x01),
+        tokens[0].clone(),
+        tokens,
+        provider.clone(),
+        Arc::new(TokenManager::in_memory(provider, 1)),
+        attributes,
+    );
+    let snapshot = CurvePoolSnapshot {
+        balances: vec![
+            U256::from(30_000_000) * pow10(decimals[0] as u64),
+            U256::from(750) * pow10(decimals[1] as u64),
+            U256::from(15_000) * pow10(decimals[2] as u64),
+        ],
+        a: U256::from(1_707_629),
+        fee: U256::from(4_000_000),
+        tricrypto_d: Some(U256::from(90_000_000) * pow10(18)),
+        tricrypto_gamma: Some(U256::from(11_809_167_828_997u64)),
+        tricrypto_price_scale: Some(vec![
+            U256::from(40_000) * pow10(18),
+            U256::from(2_000) * pow10(18),
+        ]),
+        ..Default::default()
+    };
+    (pool, PoolSnapshot::Curve(snapshot))
+}
+
+/// A two-coin crypto pool of WETH and CRV at 2000 CRV per ether, with $20M on each side, read
+/// through `asserter`.
+fn two_coin(asserter: &Asserter) -> (CurveStableswapPool<DynProvider>, PoolSnapshot) {
+    let provider: Arc<DynProvider> =
+        Arc::new(ProviderBuilder::new().connect_mocked_client(asserter.clone()));
+    let tokens: Vec<_> = [0x0a, 0x0b]
+        .into_iter()
+        .map(|byte| token(Address::repeat_byte(byte), provider.clone()))
+        .collect();
+    let attributes = PoolAttributes {
+        parameter_fetcher: ParameterFetcherType::Crypto,
+        ..attributes(2, SwapStrategyType::CryptoSwap)
+    };
+    let pool = CurveStableswapPool::from_parts(
+        Address::repeat_byte(0x02),
+        tokens[0].clone(),
+        tokens,
+        provider.clone(),
+        Arc::new(TokenManager::in_memory(provider, 1)),
+        attributes,
+    );
+    let snapshot = CurvePoolSnapshot {
+        balances: vec![
+            U256::from(10_000) * pow10(18),
+            U256::from(20_000_000) * pow10(18),
+        ],
+        a: U256::from(400_000),
+        fee: U256::from(26_000_000),
+        tricrypto_d: Some(U256::from(20_000) * pow10(18)),
+        tricrypto_gamma: Some(U256::from(145_000_000_000_000u64)),
+        // CRV priced in ether, the first coin.
+        tricrypto_price_scale: Some(vec![pow10(18) / U256::from(2_000)]),
+        mid_fee: Some(U256::from(26_000_000)),
+        out_fee: Some(U256::from(45_000_000)),
+        fee_gamma: Some(U256::from(230_000_000_000_000u64)),
+        ..Default::default()
+    };
+    (pool, PoolSnapshot::Curve(snapshot))
+}
+
+#[test]
+fn test_two_coin_crypto_pool_quotes_both_ways() {
+    let (pool, snapshot) = two_coin(&Asserter::new());
+    let (weth, crv) = (&pool.tokens[0], &pool.tokens[1]);
+    let crv_out = pool
+        .calculate_tokens_out(weth, crv, pow10(18), &snapshot)
+        .unwrap();
+    // Just under 2000 CRV, less the fee of 0.26% or more.
+    assert!(crv_out < U256::from(1_995) * pow10(18), "{crv_out}");
+    assert!(crv_out > U256::from(1_990) * pow10(18), "{crv_out}");
+    let weth_out = pool
+        .calculate_tokens_out(crv, weth, crv_out, &snapshot)
+        .unwrap();
+    assert!(weth_out < pow10(18));
+
+    let amount_in = pool
+        .calculate_tokens_in(weth, crv, crv_out, &snapshot)
+        .unwrap();
+    assert!(amount_in <= pow10(18));
+    let out = |dx| pool.calculate_tokens_out(weth, crv, dx, &snapshot).unwrap();
+    assert!(out(amount_in) >= crv_out);
+    assert!(out(amount_in - U256::ONE) < crv_out);
+}
+
+#[tokio::test]
+async fn test_two_coin_price_scale_falls_back_to_the_unindexed_getter() {
+    let asserter = Asserter::new();
+    let (pool, _) = two_coin(&asserter);
+    asserter.push_failure_msg("execution reverted");
+    asserter.push_success(&Bytes::from(pow10(15).to_be_bytes::<32>()));
+    assert_eq!(
+        pool.get_tricrypto_price_scale(1).await.unwrap(),
+        vec![pow10(15)]
+    );
+    // Cached for the block.
+    assert_eq!(
+        pool.get_tricrypto_price_scale(1).await.unwrap(),
+        vec![pow10(15)]
+    );
+    assert!(asserter.read_q().is_empty());
+}
+
+#[test]
+fn test_tricrypto_tokens_in_is_the_smallest_input_covering_the_output() {
+    let (pool, snapshot) = tricrypto();
+    for (i, j) in [(0, 1), (0, 2), (1, 0), (1, 2), (2, 0), (2, 1)] {
+        let (token_in, token_out) = (&pool.tokens[i], &pool.tokens[j]);
+        for units in [1u64, 100] {
+            let amount_out = U256::from(units) * pow10(token_out.decimals() as u64);
+            let amount_in = pool
+                .calculate_tokens_in(token_in, token_out, amount_out, &snapshot)
+                .unwrap();
+            let out = |dx| {
+                pool.calculate_tokens_out(token_in, token_out, dx, &snapshot)
+                    .unwrap()
+            };
+            assert!(
+                out(amount_in) >= amount_out,
+                "{i}->{j}: {amount_in} falls short"
+            );
+            assert!(
+                out(amount_in - U256::ONE) < amount_out,
+                "{i}->{j}: {amount_in} overpays"
+            );
+        }
+    }
+}
+
+#[test]
+fn test_tricrypto_tokens_in_rejects_outputs_beyond_the_pool() {
+    let (pool, snapshot) = tricrypto();
+    let (usdt, wbtc) = (&pool.tokens[0], &pool.tokens[1]);
+    assert_eq!(
+        pool.calculate_tokens_in(usdt, wbtc, U256::ZERO, &snapshot),
+        Ok(U256::ZERO)
+    );
+    let PoolSnapshot::Curve(curve_snapshot) = &snapshot else {
+        unreachable!()
+    };
+    assert!(
+        pool.calculate_tokens_in(usdt, wbtc, curve_snapshot.balances[1], &snapshot)
+            .is_err()
+    );
+}
+
+#[test]
+fn test_tricrypto_precisions_follow_coin_decimals() {
+    // An 18-decimal dollar leg must price like tricrypto2's 6-decimal USDT.
+    let (pool, snapshot) = tricrypto();
+    let (pool_18, snapshot_18) = tricrypto_with_decimals([18, 8, 18]);
+    let out = pool
+        .calculate_tokens_out(&pool.tokens[0], &pool.tokens[2], pow10(6 + 4), &snapshot)
+        .unwrap();
+    let out_18 = pool_18
+        .calculate_tokens_out(
+            &pool_18.tokens[0],
+            &pool_18.tokens[2],
+            pow10(18 + 4),
+            &snapshot_18,
+        )
+        .unwrap();
+    assert!(out > U256::ZERO);
+    assert_eq!(out, out_18);
+}
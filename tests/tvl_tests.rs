@@ -0,0 +1,231 @@
+mod common;
+
+use alloy::transports::mock::Asserter;
+use alloy_primitives::{Address, U256};
+use alloy_provider::{Provider, ProviderBuilder};
+use arbrs::arbitrage::tvl::TvlEstimator;
+use arbrs::balancer::pool::{BalancerPool, BalancerPoolSnapshot};
+use arbrs::chain::ChainConfig;
+use arbrs::core::token::{Token, TokenLike, USDC_ADDRESS, WETH_ADDRESS};
+use arbrs::curve::pool::CurveStableswapPool;
+use arbrs::curve::pool_attributes::{CalculationStrategy, PoolAttributes, SwapStrategyType};
+use arbrs::curve::pool_overrides::DVariant;
+use arbrs::curve::types::CurvePoolSnapshot;
+use arbrs::manager::token_manager::TokenManager;
+use arbrs::pool::strategy::StandardV2Logic;
+use arbrs::pool::uniswap_v2::{UniswapV2Pool, UniswapV2PoolState};
+use arbrs::pool::uniswap_v3::{UniswapV3Pool, UniswapV3PoolSnapshot};
+use arbrs::pool::{LiquidityPool, PoolSnapshot};
+use common::{attributes, ether, named_token, token};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+type DynProvider = dyn Provider + Send + Sync;
+
+const TOKEN_A: Address = Address::repeat_byte(0x0a);
+const TOKEN_B: Address = Address::repeat_byte(0x0b);
+
+fn usdc(amount: u64) -> U256 {
+    U256::from(amount) * U256::from(1_000_000)
+}
+
+fn provider() -> Arc<DynProvider> {
+    Arc::new(ProviderBuilder::new().connect_mocked_client(Asserter::new()))
+}
+
+fn v2_pool(
+    address: Address,
+    token0: &Arc<Token<DynProvider>>,
+    token1: &Arc<Token<DynProvider>>,
+    provider: &Arc<DynProvider>,
+) -> Arc<dyn LiquidityPool<DynProvider>> {
+    Arc::new(UniswapV2Pool::new(
+        address,
+        token0.clone(),
+        token1.clone(),
+        provider.clone(),
+        StandardV2Logic,
+    ))
+}
+
+fn v2_snapshot(reserve0: U256, reserve1: U256) -> PoolSnapshot {
+    PoolSnapshot::UniswapV2(UniswapV2PoolState {
+        reserve0,
+        reserve1,
+        block_number: 1,
+    })
+}
+
+/// The summary as addresses, to compare without the tokens' other fields.
+fn addresses(summary: &[(Arc<Token<DynProvider>>, U256)]) -> Vec<(Address, U256)> {
+    summary
+        .iter()
+        .map(|(token, reserve)| (token.address(), *reserve))
+        .collect()
+}
+
+fn curve_attributes() -> PoolAttributes {
+    PoolAttributes {
+        strategy: CalculationStrategy::Legacy,
+        d_variant: DVariant::Legacy,
+        raw_coin_addresses: vec![WETH_ADDRESS, TOKEN_A, TOKEN_B],
+        ..attributes(3, SwapStrategyType::Default)
+    }
+}
+
+#[test]
+fn test_reserves_summary_of_each_pool_kind() {
+    let provider = provider();
+    let weth = token(WETH_ADDRESS, provider.clone());
+    let a = token(TOKEN_A, provider.clone());
+    let b = named_token(TOKEN_B, "TKN", 6, provider.clone());
+
+    let v2 = v2_pool(Address::repeat_byte(0x01), &weth, &b, &provider);
+    let v2_state = v2_snapshot(ether(10), usdc(20_000));
+    assert_eq!(
+        addresses(&v2.reserves_summary(&v2_state)),
+        [(WETH_ADDRESS, ether(10)), (TOKEN_B, usdc(20_000))]
+    );
+
+    // At a price of 4, L = 100 stands for 50 of token0 and 200 of token1.
+    let v3 = UniswapV3Pool::new(
+        Address::repeat_byte(0x02),
+        weth.clone(),
+        a.clone(),
+        3_000,
+        60,
+        provider.clone(),
+        None,
+    );
+    let v3_state = PoolSnapshot::UniswapV3(UniswapV3PoolSnapshot {
+        sqrt_price_x96: U256::from(2) << 96,
+        liquidity: 100,
+        ..Default::default()
+    });
+    assert_eq!(
+        addresses(&v3.reserves_summary(&v3_state)),
+        [(WETH_ADDRESS, U256::from(50)), (TOKEN_A, U256::from(200))]
+    );
+
+    let curve = CurveStableswapPool::from_parts(
+        Address::repeat_byte(0x03),
+        a.clone(),
+        vec![weth.clone(), a.clone(), b.clone()],
+        provider.clone(),
+        Arc::new(TokenManager::in_memory(provider.clone(), 1)),
+        curve_attributes(),
+    );
+    let curve_state = PoolSnapshot::Curve(CurvePoolSnapshot {
+        balances: vec![ether(1), ether(2_000), usdc(3_000)],
+        ..Default::default()
+    });
+    assert_eq!(
+        addresses(&curve.reserves_summary(&curve_state)),
+        [
+            (WETH_ADDRESS, ether(1)),
+            (TOKEN_A, ether(2_000)),
+            (TOKEN_B, usdc(3_000))
+        ]
+    );
+
+    let balancer = BalancerPool::from_parts(
+        Address::repeat_byte(0x04),
+        provider.clone(),
+        vec![weth.clone(), a.clone()],
+        vec![ether(8) / U256::from(10), ether(2) / U256::from(10)],
+        U256::from(3_000_000_000_000_000u64),
+        Address::repeat_byte(0xba),
+        [0x11; 32],
+    );
+    let balancer_state = PoolSnapshot::Balancer(BalancerPoolSnapshot {
+        balances: vec![ether(40), ether(20_000)],
+        ..Default::default()
+    });
+    assert_eq!(
+        addresses(&balancer.reserves_summary(&balancer_state)),
+        [(WETH_ADDRESS, ether(40)), (TOKEN_A, ether(20_000))]
+    );
+
+    // Another kind of pool's snapshot says nothing about the pool.
+    assert!(v2.reserves_summary(&v3_state).is_empty());
+    assert!(v3.reserves_summary(&curve_state).is_empty());
+    assert!(curve.reserves_summary(&balancer_state).is_empty());
+    assert!(balancer.reserves_summary(&v2_state).is_empty());
+}
+
+#[test]
+fn test_estimate_counts_tokens_without_a_rate_as_zero() {
+    let provider = provider();
+    let weth = token(WETH_ADDRESS, provider.clone());
+    let usdc_token = named_token(USDC_ADDRESS, "TKN", 6, provider.clone());
+    let unknown = token(TOKEN_A, provider.clone());
+    // 1 ETH buys 2,000 USDC.
+    let estimator = TvlEstimator::new(HashMap::from([(USDC_ADDRESS, ether(2_000))]));
+
+    let pool = v2_pool(Address::repeat_byte(0x01), &weth, &usdc_token, &provider);
+    let snapshot = v2_snapshot(ether(10), usdc(20_000));
+    assert_eq!(estimator.pool_tvl(pool.as_ref(), &snapshot), ether(20));
+
+    let pool = v2_pool(Address::repeat_byte(0x02), &weth, &unknown, &provider);
+    assert_eq!(
+        estimator.pool_tvl(pool.as_ref(), &v2_snapshot(ether(10), ether(1_000_000))),
+        ether(10)
+    );
+    assert_eq!(estimator.to_weth(&unknown, ether(1)), None);
+}
+
+#[test]
+fn test_rates_from_snapshots_fall_back_to_usdc() {
+    let provider = provider();
+    let weth = token(WETH_ADDRESS, provider.clone());
+    let usdc_token = named_token(USDC_ADDRESS, "TKN", 6, provider.clone());
+    let a = token(TOKEN_A, provider.clone());
+    let orphan = token(TOKEN_B, provider.clone());
+
+    // TOKEN_A trades only against USDC, one for one; a dust WETH/USDC pool at another
+    // price is passed over for the deep one.
+    let pools: HashMap<Address, Arc<dyn LiquidityPool<DynProvider>>> = [
+        v2_pool(Address::repeat_byte(0x01), &weth, &usdc_token, &provider),
+        v2_pool(Address::repeat_byte(0x02), &weth, &usdc_token, &provider),
+        v2_pool(Address::repeat_byte(0x03), &usdc_token, &a, &provider),
+    ]
+    .into_iter()
+    .map(|pool| (pool.address(), pool))
+    .collect();
+    let snapshots = HashMap::from([
+        (
+            Address::repeat_byte(0x01),
+            v2_snapshot(ether(10_000), usdc(20_000_000)),
+        ),
+        (Address::repeat_byte(0x02), v2_snapshot(ether(1), usdc(100))),
+        (
+            Address::repeat_byte(0x03),
+            v2_snapshot(usdc(10_000_000), ether(10_000_000)),
+        ),
+    ]);
+    let estimator = TvlEstimator::from_snapshots(
+        &ChainConfig::mainnet(),
+        &[usdc_token.clone(), a.clone(), orphan.clone()],
+        &pools,
+        &snapshots,
+    );
+
+    // Both within fees of 2,000 per ETH.
+    for token in [USDC_ADDRESS, TOKEN_A] {
+        let rate = estimator.rate(token).unwrap();
+        assert!(rate < ether(2_000) && rate > ether(1_985), "{rate}");
+    }
+    assert_eq!(estimator.rate(TOKEN_B), None);
+    assert_eq!(estimator.rate(WETH_ADDRESS), Some(ether(1)));
+
+    // The USDC/TOKEN_A pool holds about 10,000 ETH of value; the orphan adds nothing.
+    let tvl = estimator.pool_tvl(
+        pools[&Address::repeat_byte(0x03)].as_ref(),
+        &snapshots[&Address::repeat_byte(0x03)],
+    );
+    assert!(tvl > ether(10_000) && tvl < ether(10_100), "{tvl}");
+    assert_eq!(
+        estimator.estimate(&[(orphan, ether(1_000)), (weth, ether(1))]),
+        ether(1)
+    );
+}
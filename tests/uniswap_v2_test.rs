@@ -1,12 +1,15 @@
+#![cfg(feature = "db")]
+
 use alloy_primitives::{Address, B256, U256, address, b256};
 use alloy_provider::{Provider, ProviderBuilder};
+use alloy_rpc_types::Filter;
 use arbrs::{
     TokenLike,
     db::DbManager,
     dex::DexVariant,
     manager::{token_manager::TokenManager, uniswap_v2_pool_manager::UniswapV2PoolManager},
     pool::{
-        LiquidityPool,
+        LiquidityPool, PoolSnapshot,
         strategy::StandardV2Logic,
         uniswap_v2::{UniswapV2Pool, UniswapV2PoolState},
     },
@@ -157,3 +160,75 @@ async fn test_pool_manager_creation() {
     assert_eq!(pool.address(), SUSHISWAP_WETH_USDC_POOL);
     assert_eq!(pool_manager.get_all_pools().len(), 1);
 }
+
+#[tokio::test]
+async fn test_v2_sync_logs_replay_to_onchain_reserves() {
+    let (provider, _, token_manager) = setup().await;
+    let weth = token_manager.get_token(WETH_ADDRESS).await.unwrap();
+    let usdc = token_manager.get_token(USDC_ADDRESS).await.unwrap();
+    let pool_address = address!("B4e16d0168e52d35CaCD2c6185b44281Ec28C9Dc");
+    let pool = UniswapV2Pool::new(
+        pool_address,
+        usdc.clone(),
+        weth.clone(),
+        provider.clone(),
+        StandardV2Logic,
+    );
+
+    let filter = Filter::new()
+        .address(pool_address)
+        .event("Sync(uint112,uint112)")
+        .from_block(18_999_990)
+        .to_block(19_000_000);
+    let logs = provider.get_logs(&filter).await.unwrap();
+    assert!(!logs.is_empty());
+    for log in &logs {
+        pool.apply_sync_log(log).await.unwrap();
+    }
+
+    let replayed = pool.get_cached_reserves().await;
+    // Read by a pool that never saw the logs, so its state cache can't hold the replay.
+    let fresh = UniswapV2Pool::new(pool_address, usdc, weth, provider, StandardV2Logic);
+    let onchain = fresh
+        ._fetch_state_at_block(replayed.block_number)
+        .await
+        .unwrap();
+    assert_eq!(replayed, onchain);
+}
+
+#[tokio::test]
+async fn test_v2_historical_snapshots_are_cached() {
+    let (provider, _, token_manager) = setup().await;
+    let weth = token_manager.get_token(WETH_ADDRESS).await.unwrap();
+    let usdc = token_manager.get_token(USDC_ADDRESS).await.unwrap();
+    let pool_address = address!("B4e16d0168e52d35CaCD2c6185b44281Ec28C9Dc");
+    let pool = UniswapV2Pool::new(pool_address, usdc, weth, provider, StandardV2Logic);
+
+    let snapshot_at = |block: u64| {
+        let pool = &pool;
+        async move {
+            match pool.get_snapshot(Some(block)).await.unwrap() {
+                PoolSnapshot::UniswapV2(state) => state,
+                _ => panic!("expected a V2 snapshot"),
+            }
+        }
+    };
+    let earlier = snapshot_at(18_900_000).await;
+    let later = snapshot_at(19_000_000).await;
+    assert_ne!(
+        (earlier.reserve0, earlier.reserve1),
+        (later.reserve0, later.reserve1)
+    );
+    assert_eq!(
+        pool.cached_states().await.keys().copied().collect::<Vec<_>>(),
+        [18_900_000, 19_000_000]
+    );
+    assert_eq!(snapshot_at(18_900_000).await, earlier);
+
+    let range = pool
+        .get_snapshot_range(18_999_998, 19_000_000)
+        .await
+        .unwrap();
+    assert_eq!(range.len(), 3);
+    assert_eq!(range[&19_000_000], later);
+}
@@ -1,3 +1,7 @@
+#![cfg(feature = "db")]
+
+mod common;
+
 use alloy::sol;
 use alloy_primitives::aliases::U24;
 use alloy_primitives::{Address, I256, U256, address};
@@ -5,9 +9,16 @@ use alloy_provider::{Provider, ProviderBuilder};
 use alloy_rpc_types::TransactionRequest;
 use alloy_sol_types::SolCall;
 use arbrs::TokenLike;
+use arbrs::arbitrage::cycle::ArbitrageCycle;
+use arbrs::arbitrage::optimizer;
+use arbrs::arbitrage::types::{Arbitrage, ArbitragePath};
+use arbrs::core::token::Token;
 use arbrs::db::DbManager;
+use arbrs::errors::ArbRsError;
+use arbrs::pool::quoter::Quoter;
+use arbrs::pool::tick_lens::UNISWAP_V3_TICK_LENS;
 use arbrs::pool::uniswap_v3::UniswapV3Pool;
-use arbrs::pool::uniswap_v3::{TickInfo, UniswapV3PoolState};
+use arbrs::pool::uniswap_v3::{TickInfo, UniswapV3PoolSnapshot, UniswapV3PoolState};
 use arbrs::pool::{LiquidityPool, PoolSnapshot};
 use arbrs::{
     TokenManager,
@@ -15,11 +26,13 @@ use arbrs::{
         sqrt_price_math::{self, MAX_U160},
         swap_math::{self},
         tick::Tick,
+        tick_math,
         utils::sqrt,
     },
 };
+use common::token;
 use ruint::aliases::U160;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashMap};
 use std::str::FromStr;
 use std::sync::Arc;
 
@@ -29,6 +42,7 @@ const WBTC_WETH_V3_POOL_ADDRESS: Address = address!("CBCdF9626bC03E24f779434178A
 const WETH_ADDRESS: Address = address!("C02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2");
 const WBTC_ADDRESS: Address = address!("2260FAC5E5542a773Aa44fBCfeDf7C193bc2C599");
 const QUOTER_ADDRESS: Address = address!("b27308f9F90D607463bb33eA1BeBb41C27CE5AB6");
+const QUOTER_V2_ADDRESS: Address = address!("61fFE014bA17989E743c5F6cB21bF9697530B21e");
 const TEST_BLOCK: u64 = 19000000;
 type DynProvider = dyn Provider + Send + Sync;
 
@@ -42,6 +56,20 @@ sol! {
             uint160 sqrtPriceLimitX96
         ) external returns (uint256 amountOut);
     }
+
+    interface IQuoterV2 {
+        struct QuoteExactOutputSingleParams {
+            address tokenIn;
+            address tokenOut;
+            uint256 amount;
+            uint24 fee;
+            uint160 sqrtPriceLimitX96;
+        }
+
+        function quoteExactOutputSingle(QuoteExactOutputSingleParams memory params)
+            external
+            returns (uint256 amountIn, uint160 sqrtPriceX96After, uint32 initializedTicksCrossed, uint256 gasEstimate);
+    }
 }
 
 async fn setup() -> (
@@ -352,7 +380,7 @@ async fn test_v3_exchange_rate_from_sqrt_price() {
         None,
     );
 
-    pool.update_state_at_block(TEST_BLOCK).await.unwrap();
+    pool.update_state_at_block(TEST_BLOCK, true).await.unwrap();
 
     let price = pool.absolute_price(&wbtc, &weth).await.unwrap();
 
@@ -468,3 +496,614 @@ async fn test_v3_simulations() {
     assert_eq!(sim_result.amount0_delta, I256::from_raw(amount_in_wbtc));
     assert_eq!(sim_result.amount1_delta, -I256::from_raw(expected_weth_out));
 }
+
+#[test]
+fn test_v3_exact_output_quote_covers_the_output() {
+    let provider: Arc<DynProvider> =
+        Arc::new(ProviderBuilder::new().connect_http(FORK_RPC_URL.parse().unwrap()));
+    let [token0, token1] = [Address::repeat_byte(0x0a), Address::repeat_byte(0x0b)]
+        .map(|address| token(address, provider.clone()));
+    let pool = UniswapV3Pool::new(
+        Address::repeat_byte(0x01),
+        token0.clone(),
+        token1.clone(),
+        3000,
+        60,
+        provider,
+        None,
+    );
+    // No initialized ticks, so one range of liquidity at a price of 1.
+    let snapshot = PoolSnapshot::UniswapV3(UniswapV3PoolSnapshot {
+        sqrt_price_x96: U256::from(1) << 96,
+        tick: 0,
+        liquidity: 10u128.pow(24),
+        tick_bitmap: BTreeMap::new(),
+        tick_data: BTreeMap::new(),
+        fee_protocol: 0,
+    });
+
+    for (token_in, token_out) in [(&token0, &token1), (&token1, &token0)] {
+        let amount_out = e18(1);
+        let amount_in = pool
+            .calculate_tokens_in(token_in, token_out, amount_out, &snapshot)
+            .unwrap();
+        // Just over the output plus the 0.3% fee.
+        assert!(
+            amount_in > amount_out && amount_in < amount_out * U256::from(101) / U256::from(100)
+        );
+        let quoted_out = pool
+            .calculate_tokens_out(token_in, token_out, amount_in, &snapshot)
+            .unwrap();
+        assert!(quoted_out >= amount_out);
+    }
+}
+
+#[test]
+fn test_v3_protocol_fee_is_split_off_the_lp_fee() {
+    let provider: Arc<DynProvider> =
+        Arc::new(ProviderBuilder::new().connect_http(FORK_RPC_URL.parse().unwrap()));
+    let [token0, token1] = [Address::repeat_byte(0x0a), Address::repeat_byte(0x0b)]
+        .map(|address| token(address, provider.clone()));
+    let pool = UniswapV3Pool::new(
+        Address::repeat_byte(0x01),
+        token0.clone(),
+        token1.clone(),
+        3000,
+        60,
+        provider,
+        None,
+    );
+    let snapshot = UniswapV3PoolSnapshot {
+        sqrt_price_x96: U256::from(1) << 96,
+        tick: 0,
+        liquidity: 10u128.pow(24),
+        tick_bitmap: BTreeMap::new(),
+        tick_data: BTreeMap::new(),
+        fee_protocol: 0,
+    };
+    // 1/4 of the fee of swaps selling token0, 1/5 of those selling token1.
+    let with_protocol_fee = UniswapV3PoolSnapshot {
+        fee_protocol: 4 | (5 << 4),
+        ..snapshot.clone()
+    };
+
+    for (zero_for_one, denominator) in [(true, 4u64), (false, 5)] {
+        let (token_in, token_out) = if zero_for_one {
+            (&token0, &token1)
+        } else {
+            (&token1, &token0)
+        };
+        let without = pool
+            .simulate_exact_input_swap(token_in, token_out, e18(1), &snapshot)
+            .unwrap();
+        let with = pool
+            .simulate_exact_input_swap(token_in, token_out, e18(1), &with_protocol_fee)
+            .unwrap();
+        assert_eq!(without.protocol_fee, U256::ZERO);
+        // 0.3% of the input, rounded up.
+        assert_eq!(without.lp_fee, U256::from(3_000_000_000_000_000u64));
+        // One step, so the protocol takes exactly its share of that step's fee.
+        assert_eq!(with.protocol_fee, without.lp_fee / U256::from(denominator));
+        assert_eq!(with.lp_fee + with.protocol_fee, without.lp_fee);
+        // The fee is paid either way, only who earns it changes.
+        assert_eq!(with.amount0_delta, without.amount0_delta);
+        assert_eq!(with.amount1_delta, without.amount1_delta);
+        assert_eq!(
+            with.final_state.sqrt_price_x96,
+            without.final_state.sqrt_price_x96
+        );
+        assert_eq!(
+            pool.calculate_tokens_out(
+                token_in,
+                token_out,
+                e18(1),
+                &PoolSnapshot::UniswapV3(with_protocol_fee.clone())
+            )
+            .unwrap(),
+            (-if zero_for_one {
+                without.amount1_delta
+            } else {
+                without.amount0_delta
+            })
+            .into_raw()
+        );
+    }
+}
+
+#[test]
+fn test_v3_exact_output_stops_at_the_price_limit() {
+    let provider: Arc<DynProvider> =
+        Arc::new(ProviderBuilder::new().connect_http(FORK_RPC_URL.parse().unwrap()));
+    let [token0, token1] = [Address::repeat_byte(0x0a), Address::repeat_byte(0x0b)]
+        .map(|address| token(address, provider.clone()));
+    let pool = UniswapV3Pool::new(
+        Address::repeat_byte(0x01),
+        token0.clone(),
+        token1.clone(),
+        3000,
+        60,
+        provider,
+        None,
+    );
+    let snapshot = PoolSnapshot::UniswapV3(UniswapV3PoolSnapshot {
+        sqrt_price_x96: U256::from(1) << 96,
+        tick: 0,
+        liquidity: 10u128.pow(24),
+        tick_bitmap: BTreeMap::new(),
+        tick_data: BTreeMap::new(),
+        fee_protocol: 0,
+    });
+    // Selling token0 moves the price down, to at most tick -10: about 500 token1 out.
+    let limit = tick_math::get_sqrt_ratio_at_tick(-10).unwrap();
+
+    let within_limit = pool
+        .calculate_tokens_in_with_price_limit(&token0, &token1, e18(1), &snapshot, Some(limit))
+        .unwrap();
+    assert_eq!(
+        within_limit,
+        pool.calculate_tokens_in(&token0, &token1, e18(1), &snapshot)
+            .unwrap()
+    );
+
+    let err = pool
+        .calculate_tokens_in_with_price_limit(&token0, &token1, e18(1_000), &snapshot, Some(limit))
+        .unwrap_err();
+    let ArbRsError::PartialFill { requested, filled } = err else {
+        panic!("Expected a partial fill, got {err:?}");
+    };
+    assert_eq!(requested, e18(1_000));
+    assert!(filled > e18(499) && filled < e18(500));
+    // Without the limit the range has plenty of liquidity.
+    assert!(
+        pool.calculate_tokens_in(&token0, &token1, e18(1_000), &snapshot)
+            .is_ok()
+    );
+
+    // A limit on the wrong side of the current price can't be swapped towards.
+    for (token_in, token_out) in [(&token0, &token1), (&token1, &token0)] {
+        let wrong_side = if token_in == &token0 {
+            tick_math::get_sqrt_ratio_at_tick(10).unwrap()
+        } else {
+            limit
+        };
+        let error = pool
+            .calculate_tokens_in_with_price_limit(
+                token_in,
+                token_out,
+                e18(1),
+                &snapshot,
+                Some(wrong_side),
+            )
+            .unwrap_err();
+        assert!(matches!(error.root(), ArbRsError::CalculationError(_)));
+        assert_eq!(error.pool_context().unwrap().address, pool.address());
+    }
+}
+
+/// A 0.3% pool at `address` over two 18-decimal tokens, built without a reachable node.
+fn offline_pool(
+    address: Address,
+) -> (
+    UniswapV3Pool<DynProvider>,
+    Arc<Token<DynProvider>>,
+    Arc<Token<DynProvider>>,
+) {
+    let provider: Arc<DynProvider> =
+        Arc::new(ProviderBuilder::new().connect_http(FORK_RPC_URL.parse().unwrap()));
+    let [token0, token1] = [Address::repeat_byte(0x0a), Address::repeat_byte(0x0b)]
+        .map(|address| token(address, provider.clone()));
+    let pool = UniswapV3Pool::new(
+        address,
+        token0.clone(),
+        token1.clone(),
+        3000,
+        60,
+        provider,
+        None,
+    );
+    (pool, token0, token1)
+}
+
+/// A snapshot at tick 0 without liquidity in range, holding one position of `liquidity`
+/// from `tick_lower` to `tick_upper`.
+fn snapshot_with_position(
+    tick_lower: i32,
+    tick_upper: i32,
+    liquidity: u128,
+) -> UniswapV3PoolSnapshot {
+    let mut tick_bitmap: BTreeMap<i16, U256> = BTreeMap::new();
+    let mut tick_data = BTreeMap::new();
+    for (tick, liquidity_net) in [
+        (tick_lower, liquidity as i128),
+        (tick_upper, -(liquidity as i128)),
+    ] {
+        let compressed = tick / 60;
+        *tick_bitmap.entry((compressed >> 8) as i16).or_default() |=
+            U256::from(1) << (compressed & 0xff) as usize;
+        tick_data.insert(
+            tick,
+            TickInfo {
+                liquidity_gross: liquidity,
+                liquidity_net,
+            },
+        );
+    }
+    UniswapV3PoolSnapshot {
+        sqrt_price_x96: U256::from(1) << 96,
+        tick: 0,
+        liquidity: 0,
+        tick_bitmap,
+        tick_data,
+        fee_protocol: 0,
+    }
+}
+
+#[test]
+fn test_v3_pool_without_liquidity_fails_fast() {
+    let (pool, token0, token1) = offline_pool(Address::repeat_byte(0x01));
+    let empty = PoolSnapshot::UniswapV3(UniswapV3PoolSnapshot {
+        sqrt_price_x96: U256::from(1) << 96,
+        tick: 0,
+        liquidity: 0,
+        tick_bitmap: BTreeMap::new(),
+        tick_data: BTreeMap::new(),
+        fee_protocol: 0,
+    });
+    let quoter = pool.to_quoter(&empty).unwrap();
+    for (token_in, token_out) in [(&token0, &token1), (&token1, &token0)] {
+        for error in [
+            pool.calculate_tokens_out(token_in, token_out, e18(1), &empty)
+                .unwrap_err(),
+            pool.calculate_tokens_in(token_in, token_out, e18(1), &empty)
+                .unwrap_err(),
+            quoter
+                .calculate_out(token_in.address(), token_out.address(), e18(1))
+                .unwrap_err(),
+        ] {
+            assert!(
+                matches!(error, ArbRsError::NoLiquidity { pool: address } if address == pool.address()),
+                "Expected no liquidity, got {error:?}"
+            );
+        }
+    }
+
+    // Liquidity above the current price can only be reached by buying token0.
+    let above = snapshot_with_position(600, 1200, 10u128.pow(24));
+    assert!(pool.check_liquidity(false, &above).is_ok());
+    let bought = pool
+        .calculate_tokens_out(
+            &token1,
+            &token0,
+            e18(1),
+            &PoolSnapshot::UniswapV3(above.clone()),
+        )
+        .unwrap();
+    assert!(bought > e18(9) / U256::from(10) && bought < e18(1));
+    assert!(matches!(
+        pool.calculate_tokens_out(
+            &token0,
+            &token1,
+            e18(1),
+            &PoolSnapshot::UniswapV3(above.clone())
+        ),
+        Err(ArbRsError::NoLiquidity { .. })
+    ));
+    assert!(matches!(
+        pool.check_liquidity(true, &above),
+        Err(ArbRsError::NoLiquidity { .. })
+    ));
+}
+
+#[test]
+fn test_v3_swap_searches_at_most_max_swap_words() {
+    let (pool, token0, token1) = offline_pool(Address::repeat_byte(0x01));
+    // Liquidity five words above the current price, tick spacing 60.
+    let tick_lower = (5 * 256 + 10) * 60;
+    let far = PoolSnapshot::UniswapV3(snapshot_with_position(
+        tick_lower,
+        tick_lower + 6_000,
+        10u128.pow(24),
+    ));
+    let uncapped = pool
+        .calculate_tokens_out(&token1, &token0, e18(1), &far)
+        .unwrap();
+
+    let (pool, ..) = offline_pool(Address::repeat_byte(0x01));
+    let pool = pool.with_max_swap_words(6);
+    assert_eq!(
+        pool.calculate_tokens_out(&token1, &token0, e18(1), &far)
+            .unwrap(),
+        uncapped
+    );
+    let (pool, ..) = offline_pool(Address::repeat_byte(0x01));
+    let pool = pool.with_max_swap_words(5);
+    let error = pool
+        .calculate_tokens_out(&token1, &token0, e18(1), &far)
+        .unwrap_err();
+    assert!(matches!(error, ArbRsError::NoLiquidity { .. }), "{error:?}");
+    assert!(matches!(
+        pool.to_quoter(&far)
+            .unwrap()
+            .calculate_out(token1.address(), token0.address(), e18(1)),
+        Err(ArbRsError::NoLiquidity { .. })
+    ));
+
+    // With liquidity in range, the swap ends at the last word it may search.
+    let PoolSnapshot::UniswapV3(mut in_range) = far.clone() else {
+        unreachable!()
+    };
+    in_range.liquidity = 10u128.pow(18);
+    let in_range = PoolSnapshot::UniswapV3(in_range);
+    let error = pool
+        .calculate_tokens_out(&token1, &token0, e18(1_000), &in_range)
+        .unwrap_err();
+    let ArbRsError::PartialFill { requested, filled } = error.root() else {
+        panic!("Expected a partial fill, got {error:?}");
+    };
+    assert_eq!(*requested, e18(1_000));
+    assert!(filled < requested);
+}
+
+#[test]
+fn test_v3_liquidity_search_floors_negative_ticks() {
+    let (pool, token0, token1) = offline_pool(Address::repeat_byte(0x01));
+    // Tick -30 lies between spacings -60 and 0, below the only position.
+    let above = PoolSnapshot::UniswapV3(UniswapV3PoolSnapshot {
+        sqrt_price_x96: tick_math::get_sqrt_ratio_at_tick(-30).unwrap(),
+        tick: -30,
+        ..snapshot_with_position(0, 600, 10u128.pow(24))
+    });
+    let PoolSnapshot::UniswapV3(snapshot) = &above else {
+        unreachable!()
+    };
+    assert!(pool.check_liquidity(false, snapshot).is_ok());
+    assert!(matches!(
+        pool.check_liquidity(true, snapshot),
+        Err(ArbRsError::NoLiquidity { .. })
+    ));
+    assert!(matches!(
+        pool.calculate_tokens_out(&token0, &token1, e18(1), &above),
+        Err(ArbRsError::NoLiquidity { .. })
+    ));
+    assert!(
+        pool.calculate_tokens_out(&token1, &token0, e18(1), &above)
+            .unwrap()
+            > U256::ZERO
+    );
+}
+
+#[test]
+fn test_v3_empty_pool_makes_path_not_viable() {
+    let (pool, token0, token1) = offline_pool(Address::repeat_byte(0x01));
+    let (other, ..) = offline_pool(Address::repeat_byte(0x02));
+    let pools: Vec<Arc<dyn LiquidityPool<DynProvider>>> = vec![Arc::new(pool), Arc::new(other)];
+    let cycle = ArbitrageCycle::new(ArbitragePath {
+        pools: pools.clone(),
+        path: vec![token0.clone(), token1.clone(), token0.clone()],
+        profit_token: token0,
+    });
+
+    // token1 buys twice as much token0 in the second pool.
+    let cheap = encode_price_sqrt(1, 2);
+    let second = |liquidity: u128| UniswapV3PoolSnapshot {
+        sqrt_price_x96: cheap,
+        tick: tick_math::get_tick_at_sqrt_ratio(cheap).unwrap(),
+        liquidity,
+        tick_bitmap: BTreeMap::new(),
+        tick_data: BTreeMap::new(),
+        fee_protocol: 0,
+    };
+    let snapshots = |liquidity: u128| {
+        HashMap::from([
+            (
+                pools[0].address(),
+                PoolSnapshot::UniswapV3(UniswapV3PoolSnapshot {
+                    liquidity: 10u128.pow(24),
+                    ..snapshot_with_position(-600, 600, 0)
+                }),
+            ),
+            (
+                pools[1].address(),
+                PoolSnapshot::UniswapV3(second(liquidity)),
+            ),
+        ])
+    };
+    assert!(cycle.check_viability(&snapshots(10u128.pow(24))).unwrap());
+    assert!(!cycle.check_viability(&snapshots(0)).unwrap());
+}
+
+#[tokio::test]
+async fn test_v3_exact_output_matches_quoter_v2() {
+    let (provider, _db, token_manager) = setup().await;
+    let weth = token_manager.get_token(WETH_ADDRESS).await.unwrap();
+    let wbtc = token_manager.get_token(WBTC_ADDRESS).await.unwrap();
+    let pool = UniswapV3Pool::new(
+        WBTC_WETH_V3_POOL_ADDRESS,
+        wbtc.clone(),
+        weth.clone(),
+        3000,
+        60,
+        provider.clone(),
+        None,
+    );
+    let snapshot = pool.get_snapshot(Some(TEST_BLOCK)).await.unwrap();
+    let PoolSnapshot::UniswapV3(v3_snapshot) = &snapshot else {
+        panic!("Wrong snapshot type");
+    };
+
+    let quote = |token_in: Address, token_out: Address, amount: U256, limit: U256| {
+        let call = IQuoterV2::quoteExactOutputSingleCall {
+            params: IQuoterV2::QuoteExactOutputSingleParams {
+                tokenIn: token_in,
+                tokenOut: token_out,
+                amount,
+                fee: U24::from(3000),
+                sqrtPriceLimitX96: U160::from(limit),
+            },
+        };
+        let request = TransactionRequest::default()
+            .to(QUOTER_V2_ADDRESS)
+            .input(call.abi_encode().into());
+        let provider = provider.clone();
+        async move {
+            let result_bytes = provider
+                .call(request)
+                .block(TEST_BLOCK.into())
+                .await
+                .unwrap();
+            IQuoterV2::quoteExactOutputSingleCall::abi_decode_returns(&result_bytes).unwrap()
+        }
+    };
+
+    // 1 WETH out for WBTC, and 0.1 WBTC out for WETH, without a limit.
+    for (token_in, token_out, amount_out) in [
+        (&wbtc, &weth, e18(1)),
+        (&weth, &wbtc, U256::from(10_000_000)),
+    ] {
+        let local = pool
+            .calculate_tokens_in(token_in, token_out, amount_out, &snapshot)
+            .unwrap();
+        let onchain = quote(
+            token_in.address(),
+            token_out.address(),
+            amount_out,
+            U256::ZERO,
+        )
+        .await;
+        assert_eq!(local, onchain.amountIn);
+    }
+
+    // With a limit just below the current price, the quoter silently quotes only the part
+    // of 1000 WETH the limit lets through, while the local quote reports it as a partial fill.
+    let limit = v3_snapshot.sqrt_price_x96 - v3_snapshot.sqrt_price_x96 / U256::from(10_000);
+    let truncated = quote(wbtc.address(), weth.address(), e18(1_000), limit).await;
+    assert_eq!(U256::from(truncated.sqrtPriceX96After), limit);
+    let err = pool
+        .calculate_tokens_in_with_price_limit(&wbtc, &weth, e18(1_000), &snapshot, Some(limit))
+        .unwrap_err();
+    let ArbRsError::PartialFill { requested, filled } = err else {
+        panic!("Expected a partial fill, got {err:?}");
+    };
+    assert_eq!(requested, e18(1_000));
+    assert!(filled < requested);
+
+    // Asking for just the achievable output costs what the quoter charged for the truncated
+    // swap.
+    let local = pool
+        .calculate_tokens_in_with_price_limit(&wbtc, &weth, filled, &snapshot, Some(limit))
+        .unwrap();
+    assert_eq!(local, truncated.amountIn);
+}
+
+#[tokio::test]
+async fn test_v3_partial_fill_is_reported() {
+    let (provider, _db, token_manager) = setup().await;
+    let weth = token_manager.get_token(WETH_ADDRESS).await.unwrap();
+    let wbtc = token_manager.get_token(WBTC_ADDRESS).await.unwrap();
+    let pool = Arc::new(UniswapV3Pool::new(
+        WBTC_WETH_V3_POOL_ADDRESS,
+        wbtc.clone(),
+        weth.clone(),
+        3000,
+        60,
+        provider.clone(),
+        None,
+    ));
+
+    let snapshot = pool.get_snapshot(Some(TEST_BLOCK)).await.unwrap();
+    let v3_snapshot = match &snapshot {
+        PoolSnapshot::UniswapV3(s) => s.clone(),
+        _ => panic!("Wrong snapshot type"),
+    };
+
+    let absurd_amount_in = U256::from(10).pow(U256::from(40));
+    let sim_result = pool
+        .simulate_exact_input_swap(&weth, &wbtc, absurd_amount_in, &v3_snapshot)
+        .unwrap();
+    assert!(!sim_result.fully_filled);
+    // WETH is token1, so its delta is what the pool took before reaching the price limit.
+    let consumed = sim_result.amount1_delta.into_raw();
+    assert!(consumed < absurd_amount_in);
+
+    let err = pool
+        .calculate_tokens_out(&weth, &wbtc, absurd_amount_in, &snapshot)
+        .unwrap_err();
+    assert_eq!(
+        err,
+        ArbRsError::PartialFill {
+            requested: absurd_amount_in,
+            filled: consumed,
+        }
+    );
+
+    // Asking for more WBTC than the pool holds fills only what it could pay out.
+    let absurd_amount_out = U256::from(10).pow(U256::from(20));
+    let sim_result = pool
+        .simulate_exact_output_swap(&weth, &wbtc, absurd_amount_out, &v3_snapshot)
+        .unwrap();
+    assert!(!sim_result.fully_filled);
+    let paid_out = (-sim_result.amount0_delta).into_raw();
+    let err = pool
+        .calculate_tokens_in(&weth, &wbtc, absurd_amount_out, &snapshot)
+        .unwrap_err();
+    assert_eq!(
+        err,
+        ArbRsError::PartialFill {
+            requested: absurd_amount_out,
+            filled: paid_out,
+        }
+    );
+
+    let sim_result = pool
+        .simulate_exact_input_swap(&weth, &wbtc, e18(1), &v3_snapshot)
+        .unwrap();
+    assert!(sim_result.fully_filled);
+
+    // A WETH -> WBTC -> WETH round trip through the same pool; the search must stay
+    // within the range the pool can actually fill.
+    let path = ArbitrageCycle::new(ArbitragePath {
+        pools: vec![pool.clone(), pool.clone()],
+        path: vec![weth.clone(), wbtc.clone(), weth.clone()],
+        profit_token: weth.clone(),
+    });
+    let snapshots = HashMap::from([(WBTC_WETH_V3_POOL_ADDRESS, snapshot)]);
+
+    let optimal_input = optimizer::find_optimal_input(
+        &path.quote_path(&snapshots).unwrap(),
+        e18(1) / U256::from(10),
+        absurd_amount_in,
+        &optimizer::OptimizerConfig::default(),
+    )
+    .unwrap()
+    .input;
+    assert!(optimal_input < absurd_amount_in);
+    assert!(path.calculate_out_amount(optimal_input, &snapshots).is_ok());
+}
+
+#[tokio::test]
+async fn test_tick_lens_reads_the_same_liquidity_map() {
+    let (provider, _db, token_manager) = setup().await;
+    let weth = token_manager.get_token(WETH_ADDRESS).await.unwrap();
+    let wbtc = token_manager.get_token(WBTC_ADDRESS).await.unwrap();
+    let pool = || {
+        UniswapV3Pool::new(
+            WBTC_WETH_V3_POOL_ADDRESS,
+            wbtc.clone(),
+            weth.clone(),
+            3000,
+            60,
+            provider.clone(),
+            None,
+        )
+    };
+    let per_tick = pool();
+    let lens = pool().with_tick_lens(UNISWAP_V3_TICK_LENS);
+
+    per_tick.refresh_liquidity_map(TEST_BLOCK).await.unwrap();
+    lens.refresh_liquidity_map(TEST_BLOCK).await.unwrap();
+    let (_, per_tick_map) = per_tick.liquidity_map().await;
+    let (_, lens_map) = lens.liquidity_map().await;
+    assert!(!lens_map.tick_data.is_empty());
+    assert_eq!(lens_map.tick_bitmap, per_tick_map.tick_bitmap);
+    assert_eq!(lens_map.tick_data, per_tick_map.tick_data);
+}
@@ -0,0 +1,428 @@
+mod common;
+
+use alloy::transports::mock::Asserter;
+use alloy_primitives::{Address, B256, U160, U256, address, aliases::I24, aliases::U24};
+use alloy_provider::{Provider, ProviderBuilder};
+use alloy_rpc_types::TransactionRequest;
+use alloy_sol_types::{SolCall, sol};
+use arbrs::ArbRsError;
+use arbrs::core::token::TokenLike;
+use arbrs::dex::PoolKind;
+use arbrs::manager::token_manager::TokenManager;
+use arbrs::pool::uniswap_v3::{UniswapV3Pool, UniswapV3PoolSnapshot};
+use arbrs::pool::uniswap_v4::{
+    PoolKey, UNISWAP_V4_POOL_MANAGER, UNISWAP_V4_STATE_VIEW, UniswapV4Pool, UniswapV4PoolSnapshot,
+    swap_fee, v4_pool_address,
+};
+use arbrs::pool::{DexKind, LiquidityPool, PoolSnapshot};
+use common::{ether, named_token, token};
+use std::str::FromStr;
+use std::sync::Arc;
+
+type DynProvider = dyn Provider + Send + Sync;
+
+const FORK_RPC_URL: &str = "http://127.0.0.1:8545";
+const V4_QUOTER: Address = address!("52f0e24d1c21c8a0cb1e5a5dd6198556bd9e1203");
+const WETH_ADDRESS: Address = address!("C02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2");
+const USDC_ADDRESS: Address = address!("A0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48");
+const TEST_BLOCK: u64 = 22_000_000;
+
+sol! {
+    struct QuoterPoolKey {
+        address currency0;
+        address currency1;
+        uint24 fee;
+        int24 tickSpacing;
+        address hooks;
+    }
+
+    struct QuoteExactSingleParams {
+        QuoterPoolKey poolKey;
+        bool zeroForOne;
+        uint128 exactAmount;
+        bytes hookData;
+    }
+
+    function quoteExactInputSingle(QuoteExactSingleParams memory params) external returns (uint256 amountOut, uint256 gasEstimate);
+}
+
+fn provider() -> Arc<DynProvider> {
+    Arc::new(ProviderBuilder::new().connect_mocked_client(Asserter::new()))
+}
+
+fn key(fee: u32, tick_spacing: i32) -> PoolKey {
+    PoolKey {
+        currency0: Address::repeat_byte(0x0a),
+        currency1: Address::repeat_byte(0x0b),
+        fee,
+        tick_spacing,
+        hooks: Address::ZERO,
+    }
+}
+
+fn v3_state() -> UniswapV3PoolSnapshot {
+    UniswapV3PoolSnapshot {
+        sqrt_price_x96: U256::ONE << 96,
+        tick: 0,
+        liquidity: 10u128.pow(24),
+        ..Default::default()
+    }
+}
+
+fn v4_snapshot(protocol_fee: u32, lp_fee: u32) -> PoolSnapshot {
+    PoolSnapshot::UniswapV4(UniswapV4PoolSnapshot {
+        state: v3_state(),
+        protocol_fee,
+        lp_fee,
+    })
+}
+
+#[test]
+fn test_swap_fee_adds_the_protocol_fee_to_the_lp_fee() {
+    assert_eq!(swap_fee(0, 3_000, true), 3_000);
+    // 0.1% selling currency0 and 0.05% selling currency1.
+    let protocol_fee = (500 << 12) | 1_000;
+    assert_eq!(swap_fee(protocol_fee, 3_000, true), 1_000 + 3_000 - 3);
+    assert_eq!(swap_fee(protocol_fee, 3_000, false), 500 + 3_000 - 1);
+    assert_eq!(swap_fee(protocol_fee, 0, false), 500);
+}
+
+#[test]
+fn test_pool_id_hashes_the_key() {
+    let key = key(3_000, 60);
+    assert_ne!(key.pool_id(), PoolKey { fee: 500, ..key }.pool_id());
+    assert_eq!(
+        v4_pool_address(key.pool_id()),
+        Address::from_word(key.pool_id())
+    );
+    assert!(!key.has_hooks() && !key.is_native());
+    assert!(
+        PoolKey {
+            currency0: Address::ZERO,
+            ..key
+        }
+        .is_native()
+    );
+}
+
+#[test]
+fn test_quotes_match_v3_without_a_protocol_fee() {
+    let provider = provider();
+    let (token0, token1) = (
+        token(Address::repeat_byte(0x0a), provider.clone()),
+        token(Address::repeat_byte(0x0b), provider.clone()),
+    );
+    let v3_pool = UniswapV3Pool::new(
+        Address::repeat_byte(0x01),
+        token0.clone(),
+        token1.clone(),
+        3_000,
+        60,
+        provider.clone(),
+        None,
+    );
+    let v4_pool = UniswapV4Pool::new(
+        key(3_000, 60),
+        UNISWAP_V4_POOL_MANAGER,
+        UNISWAP_V4_STATE_VIEW,
+        token0.clone(),
+        token1.clone(),
+        provider,
+    );
+    assert_eq!(v4_pool.dex_kind(), Some(DexKind::UniswapV4));
+    assert_eq!(v4_pool.address(), v4_pool_address(key(3_000, 60).pool_id()));
+
+    let v3_snapshot = PoolSnapshot::UniswapV3(v3_state());
+    let v4_snapshot = v4_snapshot(0, 3_000);
+    for (token_in, token_out) in [(&token0, &token1), (&token1, &token0)] {
+        for amount in [U256::from(1_000), ether(1), ether(10_000)] {
+            assert_eq!(
+                v4_pool
+                    .calculate_tokens_out(token_in, token_out, amount, &v4_snapshot)
+                    .unwrap(),
+                v3_pool
+                    .calculate_tokens_out(token_in, token_out, amount, &v3_snapshot)
+                    .unwrap()
+            );
+            assert_eq!(
+                v4_pool
+                    .calculate_tokens_in(token_in, token_out, amount, &v4_snapshot)
+                    .unwrap(),
+                v3_pool
+                    .calculate_tokens_in(token_in, token_out, amount, &v3_snapshot)
+                    .unwrap()
+            );
+        }
+    }
+}
+
+#[test]
+fn test_protocol_fee_lowers_the_output_in_its_direction() {
+    let provider = provider();
+    let (token0, token1) = (
+        token(Address::repeat_byte(0x0a), provider.clone()),
+        token(Address::repeat_byte(0x0b), provider.clone()),
+    );
+    let pool = UniswapV4Pool::new(
+        key(3_000, 60),
+        UNISWAP_V4_POOL_MANAGER,
+        UNISWAP_V4_STATE_VIEW,
+        token0.clone(),
+        token1.clone(),
+        provider,
+    );
+    let out = |snapshot: &PoolSnapshot, zero_for_one: bool| {
+        let (token_in, token_out) = if zero_for_one {
+            (&token0, &token1)
+        } else {
+            (&token1, &token0)
+        };
+        pool.calculate_tokens_out(token_in, token_out, ether(1), snapshot)
+            .unwrap()
+    };
+
+    // A protocol fee on swaps selling currency0 only.
+    let without = v4_snapshot(0, 3_000);
+    let with = v4_snapshot(1_000, 3_000);
+    assert!(out(&with, true) < out(&without, true));
+    assert_eq!(out(&with, false), out(&without, false));
+
+    // A fee of the whole input is refused, as the pool manager does.
+    let error = pool
+        .calculate_tokens_out(&token0, &token1, ether(1), &v4_snapshot(0, 1_000_000))
+        .unwrap_err();
+    assert!(matches!(error.root(), ArbRsError::CalculationError(_)));
+}
+
+#[test]
+fn test_pool_kind_parses() {
+    assert_eq!(
+        PoolKind::from_str("uniswap v4").unwrap(),
+        PoolKind::UniswapV4
+    );
+    assert_eq!(PoolKind::UniswapV4.dex_kind(), Some(DexKind::UniswapV4));
+}
+
+#[cfg(feature = "db")]
+mod discovery {
+    use super::*;
+    use alloy_primitives::{Bytes, LogData, U64};
+    use alloy_rpc_types::Log;
+    use arbrs::db::DbManager;
+    use arbrs::manager::uniswap_v4_pool_manager::UniswapV4PoolManager;
+
+    sol! {
+        event Initialize(bytes32 indexed id, address indexed currency0, address indexed currency1, uint24 fee, int24 tickSpacing, address hooks, uint160 sqrtPriceX96, int24 tick);
+        function getTickBitmap(bytes32 poolId, int16 tick) external view returns (uint256 tickBitmap);
+    }
+
+    const HOOKS: Address = Address::repeat_byte(0x44);
+
+    struct Fixture {
+        asserter: Asserter,
+        provider: Arc<DynProvider>,
+        token_manager: Arc<TokenManager<DynProvider>>,
+        db_manager: Arc<DbManager>,
+    }
+
+    async fn setup() -> Fixture {
+        let asserter = Asserter::new();
+        let provider: Arc<DynProvider> =
+            Arc::new(ProviderBuilder::new().connect_mocked_client(asserter.clone()));
+        let db_manager = Arc::new(DbManager::new("sqlite::memory:").await.unwrap());
+        // Tokens load from the database, so building a pool makes no token calls.
+        for (address, symbol, decimals) in [(USDC_ADDRESS, "USDC", 6), (WETH_ADDRESS, "WETH", 18)] {
+            let token = named_token(address, symbol, decimals, provider.clone());
+            db_manager.save_token(&token).await.unwrap();
+        }
+        let token_manager = Arc::new(TokenManager::new(provider.clone(), 1, db_manager.clone()));
+        Fixture {
+            asserter,
+            provider,
+            token_manager,
+            db_manager,
+        }
+    }
+
+    fn manager(fixture: &Fixture) -> UniswapV4PoolManager<DynProvider> {
+        UniswapV4PoolManager::new(fixture.token_manager.clone(), fixture.provider.clone(), 0)
+            .with_db_manager(fixture.db_manager.clone())
+    }
+
+    fn initialize_log(key: &PoolKey) -> Log {
+        let event = Initialize {
+            id: key.pool_id(),
+            currency0: key.currency0,
+            currency1: key.currency1,
+            fee: U24::from(key.fee),
+            tickSpacing: I24::try_from(key.tick_spacing).unwrap(),
+            hooks: key.hooks,
+            sqrtPriceX96: U160::from(1) << 96,
+            tick: I24::ZERO,
+        };
+        Log {
+            inner: alloy_primitives::Log {
+                address: UNISWAP_V4_POOL_MANAGER,
+                data: LogData::from(&event),
+            },
+            ..Default::default()
+        }
+    }
+
+    /// Answers the liquidity map read of a freshly built pool with a tick spacing of 200,
+    /// whose usable ticks spread over words -18 to 17, all empty.
+    fn push_empty_map(asserter: &Asserter) {
+        asserter.push_success(&U64::from(100));
+        for _ in 0..36 {
+            asserter.push_success(&Bytes::from(getTickBitmapCall::abi_encode_returns(
+                &U256::ZERO,
+            )));
+        }
+    }
+
+    fn ether_usdc() -> PoolKey {
+        PoolKey {
+            currency0: Address::ZERO,
+            currency1: USDC_ADDRESS,
+            fee: 500,
+            tick_spacing: 200,
+            hooks: Address::ZERO,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_discovery_skips_hooked_pools_and_lists_ether_as_weth() {
+        let fixture = setup().await;
+        let mut manager = manager(&fixture);
+        let hooked = PoolKey {
+            currency0: USDC_ADDRESS,
+            currency1: WETH_ADDRESS,
+            hooks: HOOKS,
+            ..ether_usdc()
+        };
+        fixture.asserter.push_success(&vec![
+            initialize_log(&hooked),
+            initialize_log(&ether_usdc()),
+        ]);
+        push_empty_map(&fixture.asserter);
+
+        let pools = manager.discover_pools_in_range(10).await.unwrap();
+        assert!(fixture.asserter.read_q().is_empty());
+        assert_eq!(pools.len(), 1);
+        let pool = &pools[0];
+        assert_eq!(pool.address(), v4_pool_address(ether_usdc().pool_id()));
+        let tokens: Vec<Address> = pool.get_all_tokens().iter().map(|t| t.address()).collect();
+        assert_eq!(tokens, vec![WETH_ADDRESS, USDC_ADDRESS]);
+        assert!(pool.is_native(WETH_ADDRESS));
+        assert!(!pool.is_native(USDC_ADDRESS));
+
+        assert_eq!(
+            manager.skipped_pools(),
+            vec![(v4_pool_address(hooked.pool_id()), format!("hooks {HOOKS}"))]
+        );
+        assert_eq!(manager.get_all_pools().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_stored_pool_is_hydrated_from_its_key() {
+        let fixture = setup().await;
+        let mut manager = manager(&fixture);
+        fixture
+            .asserter
+            .push_success(&vec![initialize_log(&ether_usdc())]);
+        push_empty_map(&fixture.asserter);
+        manager.discover_pools_in_range(10).await.unwrap();
+
+        let records = fixture.db_manager.load_all_pools().await.unwrap();
+        assert_eq!(records.len(), 1);
+        let record = &records[0];
+        assert_eq!(record.dex, PoolKind::UniswapV4);
+        assert_eq!(record.v4_pool_key, Some(ether_usdc()));
+        assert_eq!(record.v4_pool_manager, Some(UNISWAP_V4_POOL_MANAGER));
+        assert_eq!((record.fee, record.tick_spacing), (Some(500), Some(200)));
+
+        let fresh = self::manager(&fixture);
+        push_empty_map(&fixture.asserter);
+        let pool = fresh.build_pool_from_record(record).await.unwrap();
+        assert!(fixture.asserter.read_q().is_empty());
+        let v4_pool = pool
+            .as_any()
+            .downcast_ref::<UniswapV4Pool<DynProvider>>()
+            .unwrap();
+        assert_eq!(v4_pool.key(), &ether_usdc());
+        assert_eq!(v4_pool.liquidity_map().await.0, Some(100));
+
+        // A key that doesn't hash to the stored address is refused before any call.
+        let tampered = arbrs::db::PoolRecord {
+            v4_pool_key: Some(PoolKey {
+                fee: 3_000,
+                ..ether_usdc()
+            }),
+            ..record.clone()
+        };
+        assert!(matches!(
+            self::manager(&fixture).build_pool_from_record(&tampered).await,
+            Err(ArbRsError::InvalidPool(address, _)) if address == record.address
+        ));
+    }
+}
+
+#[tokio::test]
+async fn test_quote_matches_v4_quoter() {
+    let provider: Arc<DynProvider> =
+        Arc::new(ProviderBuilder::new().connect_http(FORK_RPC_URL.parse().unwrap()));
+    let token_manager = Arc::new(TokenManager::in_memory(provider.clone(), 1));
+    let weth = token_manager.get_token(WETH_ADDRESS).await.unwrap();
+    let usdc = token_manager.get_token(USDC_ADDRESS).await.unwrap();
+    let key = PoolKey {
+        currency0: Address::ZERO,
+        currency1: USDC_ADDRESS,
+        fee: 500,
+        tick_spacing: 10,
+        hooks: Address::ZERO,
+    };
+    let pool = UniswapV4Pool::new(
+        key,
+        UNISWAP_V4_POOL_MANAGER,
+        UNISWAP_V4_STATE_VIEW,
+        weth.clone(),
+        usdc.clone(),
+        provider.clone(),
+    );
+    assert_ne!(pool.pool_id(), B256::ZERO);
+    pool.refresh_liquidity_map(TEST_BLOCK).await.unwrap();
+    let snapshot = pool.get_snapshot(Some(TEST_BLOCK)).await.unwrap();
+
+    for (token_in, token_out, amount_in) in [
+        (&weth, &usdc, ether(10)),
+        (&usdc, &weth, U256::from(20_000_000_000u64)),
+    ] {
+        let local = pool
+            .calculate_tokens_out(token_in, token_out, amount_in, &snapshot)
+            .unwrap();
+        let call = quoteExactInputSingleCall {
+            params: QuoteExactSingleParams {
+                poolKey: QuoterPoolKey {
+                    currency0: key.currency0,
+                    currency1: key.currency1,
+                    fee: U24::from(key.fee),
+                    tickSpacing: I24::try_from(key.tick_spacing).unwrap(),
+                    hooks: key.hooks,
+                },
+                zeroForOne: token_in.address() == WETH_ADDRESS,
+                exactAmount: amount_in.to(),
+                hookData: Default::default(),
+            },
+        };
+        let request = TransactionRequest::default()
+            .to(V4_QUOTER)
+            .input(call.abi_encode().into());
+        let result = provider
+            .call(request)
+            .block(TEST_BLOCK.into())
+            .await
+            .unwrap();
+        let onchain = quoteExactInputSingleCall::abi_decode_returns(&result).unwrap();
+        assert_eq!(local, onchain.amountOut);
+    }
+}
@@ -0,0 +1,149 @@
+mod common;
+
+use alloy::transports::mock::Asserter;
+use alloy_primitives::{Address, Bytes, U256, address};
+use alloy_provider::{Provider, ProviderBuilder};
+use arbrs::arbitrage::cache::ArbitrageCache;
+use arbrs::arbitrage::cycle::ArbitrageCycle;
+use arbrs::arbitrage::engine::ArbitrageEngine;
+use arbrs::arbitrage::export::SolutionExport;
+use arbrs::arbitrage::types::{ArbitragePath, ArbitrageSolution};
+use arbrs::arbitrage::usd::{
+    CHAINLINK_ETH_USD, ChainlinkUsdPriceFeed, UsdPriceFeed, format_usd, wei_to_usd,
+};
+use arbrs::manager::token_manager::TokenManager;
+use arbrs::pool::strategy::StandardV2Logic;
+use arbrs::pool::uniswap_v2::{UniswapV2Pool, UniswapV2PoolState};
+use arbrs::pool::{LiquidityPool, PoolSnapshot};
+use common::token;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+type DynProvider = dyn Provider + Send + Sync;
+
+const WETH: Address = address!("C02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2");
+const BLOCK: u64 = 1;
+// $2000.00 with Chainlink's 8 decimals.
+const ETH_USD_ANSWER: u64 = 200_000_000_000;
+
+fn mocked(asserter: &Asserter) -> Arc<DynProvider> {
+    Arc::new(ProviderBuilder::new().connect_mocked_client(asserter.clone()))
+}
+
+/// Queues the `latestRoundData()` result of one call.
+fn push_round(asserter: &Asserter, answer: u64) {
+    let words = [1, answer, 0, 0, 1].map(U256::from);
+    asserter.push_success(&Bytes::from(
+        words
+            .iter()
+            .flat_map(|word| word.to_be_bytes::<32>())
+            .collect::<Vec<u8>>(),
+    ));
+}
+
+fn reserves(reserve0: u64, reserve1: u64) -> PoolSnapshot {
+    let ether = U256::from(10).pow(U256::from(18));
+    PoolSnapshot::UniswapV2(UniswapV2PoolState {
+        reserve0: U256::from(reserve0) * ether,
+        reserve1: U256::from(reserve1) * ether,
+        block_number: BLOCK,
+    })
+}
+
+/// Evaluates a mispriced two pool WETH cycle, valuing it with `feed`.
+async fn solve(feed: Arc<dyn UsdPriceFeed>) -> Vec<ArbitrageSolution<DynProvider>> {
+    // Pools and gas price calls fail, so the snapshots come from the overrides.
+    let provider = mocked(&Asserter::new());
+    let (weth, other) = (
+        token(WETH, provider.clone()),
+        token(Address::repeat_byte(0xee), provider.clone()),
+    );
+    let pools: Vec<Arc<dyn LiquidityPool<DynProvider>>> = [0x01, 0x02]
+        .into_iter()
+        .map(|byte| {
+            Arc::new(UniswapV2Pool::new(
+                Address::repeat_byte(byte),
+                weth.clone(),
+                other.clone(),
+                provider.clone(),
+                StandardV2Logic,
+            )) as Arc<dyn LiquidityPool<DynProvider>>
+        })
+        .collect();
+    let cache = Arc::new(ArbitrageCache::new());
+    cache
+        .add_path(Arc::new(ArbitrageCycle::new(ArbitragePath {
+            pools,
+            path: vec![weth.clone(), other, weth.clone()],
+            profit_token: weth,
+        })))
+        .await;
+
+    let engine = ArbitrageEngine::new(
+        cache,
+        Arc::new(TokenManager::in_memory(provider.clone(), 1)),
+        provider,
+    )
+    .with_min_net_profit(U256::ZERO)
+    .with_usd_price_feed(feed);
+    let overrides = HashMap::from([
+        (Address::repeat_byte(0x01), reserves(1_000, 2_400_000)),
+        (Address::repeat_byte(0x02), reserves(1_000, 2_000_000)),
+    ]);
+    engine
+        .find_opportunities_with_overrides(Some(BLOCK), overrides)
+        .await
+}
+
+#[test]
+fn test_wei_to_usd_cents() {
+    let price = U256::from(ETH_USD_ANSWER);
+    let cents = wei_to_usd(U256::from(1_234_567_890_000_000_000u64), price);
+    assert_eq!(cents, U256::from(246_913));
+    assert_eq!(format_usd(cents), "2469.13");
+    assert_eq!(format_usd(U256::from(5)), "0.05");
+    // Rounds down below a cent.
+    assert_eq!(
+        wei_to_usd(U256::from(4_999_999_999_999u64), price),
+        U256::ZERO
+    );
+}
+
+#[tokio::test]
+async fn test_chainlink_price_values_solution_in_usd() {
+    let asserter = Asserter::new();
+    push_round(&asserter, ETH_USD_ANSWER);
+    let feed = ChainlinkUsdPriceFeed::new(mocked(&asserter), CHAINLINK_ETH_USD);
+
+    let solutions = solve(Arc::new(feed)).await;
+    assert!(asserter.read_q().is_empty());
+    assert_eq!(solutions.len(), 1);
+    let solution = &solutions[0];
+    let usd = solution.usd.unwrap();
+
+    // The profit token is WETH, so cents are wei * 2000 * 100 / 1e18, rounded down.
+    let to_cents = |wei: U256| wei * U256::from(200_000) / U256::from(10).pow(U256::from(18));
+    assert_eq!(usd.net_profit, to_cents(solution.net_profit.raw));
+    assert_eq!(usd.gas_cost, to_cents(solution.gas_cost));
+    assert_eq!(usd.flashloan_fee, to_cents(solution.flashloan_fee));
+    assert!(usd.net_profit > U256::ZERO);
+    // Two V2 swaps and the overhead, 340k gas, at the 20 gwei fallback gas price is
+    // 0.0068 ETH.
+    assert_eq!(usd.gas_cost, U256::from(1_360));
+
+    let export = SolutionExport::from_solution(solution).usd.unwrap();
+    assert_eq!(export.net_profit, usd.net_profit);
+}
+
+#[tokio::test]
+async fn test_feed_error_leaves_usd_unset() {
+    // Nothing is queued, so the aggregator call fails.
+    let asserter = Asserter::new();
+    let feed = ChainlinkUsdPriceFeed::new(mocked(&asserter), CHAINLINK_ETH_USD);
+
+    let solutions = solve(Arc::new(feed)).await;
+    assert_eq!(solutions.len(), 1);
+    assert!(solutions[0].net_profit.raw > U256::ZERO);
+    assert_eq!(solutions[0].usd, None);
+    assert_eq!(SolutionExport::from_solution(&solutions[0]).usd, None);
+}
@@ -0,0 +1,191 @@
+#![cfg(feature = "db")]
+
+mod common;
+
+use alloy::transports::mock::Asserter;
+use alloy_primitives::{Address, B256, LogData, U256, address};
+use alloy_provider::{Provider, ProviderBuilder};
+use alloy_rpc_types::Log;
+use alloy_sol_types::sol;
+use arbrs::db::DbManager;
+use arbrs::dex::{
+    DexDetails, DexVariant, FEE_PIPS_DENOMINATOR, PoolKind, UNISWAP_V2_FACTORY,
+    build_mainnet_dex_registry,
+};
+use arbrs::manager::token_manager::TokenManager;
+use arbrs::manager::uniswap_v2_pool_manager::UniswapV2PoolManager;
+use arbrs::pool::PoolSnapshot;
+use arbrs::pool::strategy::{
+    ConfigurableFeeStrategy, PancakeV2Logic, StandardV2Logic, V2CalculationStrategy,
+};
+use arbrs::pool::uniswap_v2::{UniswapV2Pool, UniswapV2PoolState};
+use common::{ether, token};
+use std::sync::Arc;
+
+sol! {
+    event PairCreated(address indexed token0, address indexed token1, address pair, uint256);
+}
+
+type DynProvider = dyn Provider + Send + Sync;
+
+const WETH: Address = address!("C02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2");
+const USDC: Address = address!("A0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48");
+const STABLE_FACTORY: Address = Address::repeat_byte(0xfa);
+const PAIR: Address = Address::repeat_byte(0x01);
+
+/// A manager over `provider` that already knows WETH and USDC, with a 1 bps factory.
+fn manager(provider: Arc<DynProvider>, factory: Address) -> UniswapV2PoolManager<DynProvider> {
+    let token_manager = Arc::new(TokenManager::in_memory(provider.clone(), 1));
+    token_manager.insert_token(token(WETH, provider.clone()));
+    token_manager.insert_token(token(USDC, provider.clone()));
+    UniswapV2PoolManager::new(token_manager, provider, factory, 0).with_factory(
+        STABLE_FACTORY,
+        DexDetails::new(DexVariant::Custom, 1, 10_000, B256::repeat_byte(0x11)),
+    )
+}
+
+#[test]
+fn test_configurable_fee_matches_the_fixed_strategies() {
+    let (reserve_in, reserve_out, amount_in) = (ether(1_000), ether(2_000_000), ether(3));
+    for (configurable, fixed) in [
+        (
+            ConfigurableFeeStrategy::new(30, 10_000).unwrap(),
+            &StandardV2Logic as &dyn V2CalculationStrategy,
+        ),
+        (
+            ConfigurableFeeStrategy::new(2_500, FEE_PIPS_DENOMINATOR).unwrap(),
+            &PancakeV2Logic,
+        ),
+    ] {
+        assert_eq!(configurable.get_fee_bps(), fixed.get_fee_bps());
+        assert_eq!(
+            configurable
+                .calculate_tokens_out(reserve_in, reserve_out, amount_in)
+                .unwrap(),
+            fixed
+                .calculate_tokens_out(reserve_in, reserve_out, amount_in)
+                .unwrap()
+        );
+    }
+
+    // 1 bps: 9999 of every 10000 input units are swapped.
+    let stable = ConfigurableFeeStrategy::new(1, 10_000).unwrap();
+    let amount_out = stable
+        .calculate_tokens_out(ether(1_000_000), ether(1_000_000), ether(10_000))
+        .unwrap();
+    let with_fee = ether(9_999);
+    assert_eq!(
+        amount_out,
+        with_fee * ether(1_000_000) / (ether(1_000_000) + with_fee)
+    );
+    assert!(ConfigurableFeeStrategy::new(10_000, 10_000).is_err());
+    assert!(ConfigurableFeeStrategy::new(1, 0).is_err());
+}
+
+#[test]
+fn test_registry_knows_each_fork_fee() {
+    let registry = build_mainnet_dex_registry();
+    assert_eq!(
+        registry[&UNISWAP_V2_FACTORY].pair_address(UNISWAP_V2_FACTORY, WETH, USDC),
+        address!("B4e16d0168e52d35CaCD2c6185b44281Ec28C9Dc")
+    );
+    let fees: Vec<u32> = [
+        DexVariant::UniswapV2,
+        DexVariant::SushiSwap,
+        DexVariant::PancakeSwapV2,
+    ]
+    .iter()
+    .map(|dex| {
+        registry
+            .values()
+            .find(|details| details.dex_type == *dex)
+            .unwrap()
+            .fee_pips()
+    })
+    .collect();
+    assert_eq!(fees, [3_000, 3_000, 2_500]);
+}
+
+#[tokio::test]
+async fn test_pools_are_quoted_with_their_factory_fee() {
+    let provider: Arc<DynProvider> =
+        Arc::new(ProviderBuilder::new().connect_mocked_client(Asserter::new()));
+    let manager = manager(provider, UNISWAP_V2_FACTORY);
+
+    let pancake = manager
+        .build_v2_pool(PAIR, WETH, USDC, DexVariant::PancakeSwapV2)
+        .await
+        .unwrap();
+    assert!(
+        pancake
+            .as_any()
+            .downcast_ref::<UniswapV2Pool<DynProvider, PancakeV2Logic>>()
+            .is_some()
+    );
+
+    let stable = manager
+        .build_v2_pool_for_factory(Address::repeat_byte(0x02), WETH, USDC, STABLE_FACTORY)
+        .await
+        .unwrap();
+    let snapshot = PoolSnapshot::UniswapV2(UniswapV2PoolState {
+        reserve0: ether(1_000_000),
+        reserve1: ether(1_000_000),
+        block_number: 1,
+    });
+    let tokens = stable.get_all_tokens();
+    let amount_out = stable
+        .calculate_tokens_out(&tokens[0], &tokens[1], ether(10_000), &snapshot)
+        .unwrap();
+    let expected = ConfigurableFeeStrategy::new(1, 10_000)
+        .unwrap()
+        .calculate_tokens_out(ether(1_000_000), ether(1_000_000), ether(10_000))
+        .unwrap();
+    assert_eq!(amount_out, expected);
+
+    assert!(
+        manager
+            .build_v2_pool_for_factory(Address::repeat_byte(0x03), WETH, USDC, PAIR)
+            .await
+            .is_err()
+    );
+}
+
+#[tokio::test]
+async fn test_discovered_pools_are_stored_with_their_fee() {
+    let asserter = Asserter::new();
+    let provider: Arc<DynProvider> =
+        Arc::new(ProviderBuilder::new().connect_mocked_client(asserter.clone()));
+    let db = Arc::new(DbManager::new("sqlite::memory:").await.unwrap());
+    let mut manager = manager(provider, STABLE_FACTORY).with_db_manager(db.clone());
+
+    let event = PairCreated {
+        token0: USDC,
+        token1: WETH,
+        pair: PAIR,
+        _3: U256::from(1),
+    };
+    let log = Log {
+        inner: alloy_primitives::Log {
+            address: STABLE_FACTORY,
+            data: LogData::from(&event),
+        },
+        ..Default::default()
+    };
+    asserter.push_success(&vec![log]);
+
+    let pools = manager.discover_pools_in_range(10).await.unwrap();
+    assert_eq!(pools.len(), 1);
+    assert!(
+        pools[0]
+            .as_any()
+            .downcast_ref::<UniswapV2Pool<DynProvider, ConfigurableFeeStrategy>>()
+            .is_some()
+    );
+
+    let records = db.load_all_pools().await.unwrap();
+    assert_eq!(records.len(), 1);
+    assert_eq!(records[0].address, PAIR);
+    assert_eq!(records[0].dex, PoolKind::UniswapV2);
+    assert_eq!(records[0].fee, Some(100));
+    assert_eq!(records[0].tokens, [USDC, WETH]);
+}
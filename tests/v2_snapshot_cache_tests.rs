@@ -0,0 +1,142 @@
+mod common;
+
+use alloy::transports::mock::Asserter;
+use alloy_primitives::{Address, Bytes, U64, U256, aliases::U112};
+use alloy_provider::{Provider, ProviderBuilder};
+use alloy_sol_types::{SolCall, sol};
+use arbrs::ArbRsError;
+use arbrs::pool::strategy::StandardV2Logic;
+use arbrs::pool::uniswap_v2::{UniswapV2Pool, UniswapV2PoolState};
+use arbrs::pool::{LiquidityPool, PoolSnapshot};
+use common::token;
+use std::sync::Arc;
+
+type DynProvider = dyn Provider + Send + Sync;
+type Pool = UniswapV2Pool<DynProvider, StandardV2Logic>;
+
+const POOL: Address = Address::repeat_byte(0x01);
+
+sol! {
+    function getReserves() external view returns (uint112 reserve0, uint112 reserve1, uint32 blockTimestampLast);
+}
+
+fn pool(asserter: &Asserter) -> Pool {
+    let provider: Arc<DynProvider> =
+        Arc::new(ProviderBuilder::new().connect_mocked_client(asserter.clone()));
+    UniswapV2Pool::new(
+        POOL,
+        token(Address::repeat_byte(0x0a), provider.clone()),
+        token(Address::repeat_byte(0x0b), provider.clone()),
+        provider,
+        StandardV2Logic,
+    )
+}
+
+/// Queues the result of one state fetch.
+fn push_reserves(asserter: &Asserter, reserve0: u64, reserve1: u64) {
+    asserter.push_success(&getReservesCall::abi_encode_returns(&getReservesReturn {
+        reserve0: U112::from(reserve0),
+        reserve1: U112::from(reserve1),
+        blockTimestampLast: 0,
+    }));
+}
+
+async fn reserves_at(pool: &Pool, block: u64) -> Result<UniswapV2PoolState, ArbRsError> {
+    match pool.get_snapshot(Some(block)).await? {
+        PoolSnapshot::UniswapV2(state) => Ok(state),
+        _ => panic!("expected a V2 snapshot"),
+    }
+}
+
+#[tokio::test]
+async fn test_snapshot_at_block_reads_through_the_cache() {
+    let asserter = Asserter::new();
+    let pool = pool(&asserter);
+
+    push_reserves(&asserter, 100, 200);
+    push_reserves(&asserter, 110, 190);
+    let first = reserves_at(&pool, 1_000).await.unwrap();
+    let second = reserves_at(&pool, 1_001).await.unwrap();
+    assert_eq!(
+        (first.reserve0, first.block_number),
+        (U256::from(100), 1_000)
+    );
+    assert_eq!(
+        (second.reserve0, second.block_number),
+        (U256::from(110), 1_001)
+    );
+    assert!(asserter.read_q().is_empty());
+
+    // Nothing is queued, so any call would fail.
+    assert_eq!(reserves_at(&pool, 1_000).await.unwrap(), first);
+    assert_eq!(reserves_at(&pool, 1_001).await.unwrap(), second);
+    assert_eq!(pool.cached_states().await.len(), 2);
+}
+
+#[tokio::test]
+async fn test_snapshot_range_only_fetches_missing_blocks() {
+    let asserter = Asserter::new();
+    let pool = pool(&asserter);
+
+    push_reserves(&asserter, 100, 200);
+    reserves_at(&pool, 1_001).await.unwrap();
+
+    // The head, then the two blocks not cached, which are all the same here.
+    asserter.push_success(&U64::from(1_002));
+    push_reserves(&asserter, 120, 180);
+    push_reserves(&asserter, 120, 180);
+    let range = pool.get_snapshot_range(1_000, 1_002).await.unwrap();
+    assert!(asserter.read_q().is_empty());
+    assert_eq!(
+        range.keys().copied().collect::<Vec<_>>(),
+        [1_000, 1_001, 1_002]
+    );
+    assert_eq!(range[&1_001].reserve0, U256::from(100));
+    assert_eq!(range[&1_002].reserve0, U256::from(120));
+    assert_eq!(range[&1_002].block_number, 1_002);
+
+    // Now fully cached.
+    assert_eq!(pool.get_snapshot_range(1_000, 1_002).await.unwrap(), range);
+}
+
+#[tokio::test]
+async fn test_snapshot_before_deployment_is_an_error() {
+    let asserter = Asserter::new();
+    let pool = pool(&asserter);
+
+    asserter.push_success(&Bytes::new());
+    assert_eq!(
+        reserves_at(&pool, 10).await,
+        Err(ArbRsError::PoolNotDeployed {
+            pool: POOL,
+            block: 10,
+        })
+    );
+    assert!(pool.cached_states().await.is_empty());
+}
+
+#[tokio::test]
+async fn test_snapshot_past_the_head_is_an_error() {
+    let asserter = Asserter::new();
+    let pool = pool(&asserter);
+
+    asserter.push_failure_msg("header not found");
+    asserter.push_success(&U64::from(1_000));
+    assert_eq!(
+        reserves_at(&pool, 1_005).await,
+        Err(ArbRsError::BlockNotMined {
+            requested: 1_005,
+            latest: 1_000,
+        })
+    );
+
+    asserter.push_success(&U64::from(1_000));
+    assert_eq!(
+        pool.get_snapshot_range(999, 1_001).await,
+        Err(ArbRsError::BlockNotMined {
+            requested: 1_001,
+            latest: 1_000,
+        })
+    );
+    assert!(asserter.read_q().is_empty());
+}
@@ -0,0 +1,160 @@
+use alloy_primitives::U256;
+use arbrs::ArbRsError;
+use arbrs::pool::strategy::{PancakeV2Logic, StandardV2Logic, V2CalculationStrategy};
+use proptest::prelude::*;
+
+// Reserves fit in a uint112, as in the pair contract.
+const MAX_RESERVE: u128 = (1 << 112) - 1;
+const MIN_RESERVE: u128 = 1_000;
+
+/// `UniswapV2Library.getAmountOut`, with the fee kept as `fee.0 / fee.1`.
+fn reference_amount_out(
+    amount_in: U256,
+    reserve_in: U256,
+    reserve_out: U256,
+    fee: (u64, u64),
+) -> U256 {
+    let amount_in_with_fee = amount_in * U256::from(fee.0);
+    let numerator = amount_in_with_fee * reserve_out;
+    let denominator = reserve_in * U256::from(fee.1) + amount_in_with_fee;
+    numerator / denominator
+}
+
+/// `UniswapV2Library.getAmountIn`.
+fn reference_amount_in(
+    amount_out: U256,
+    reserve_in: U256,
+    reserve_out: U256,
+    fee: (u64, u64),
+) -> U256 {
+    let numerator = reserve_in * amount_out * U256::from(fee.1);
+    let denominator = (reserve_out - amount_out) * U256::from(fee.0);
+    numerator / denominator + U256::from(1)
+}
+
+/// The pair's `swap` check: fee-adjusted balances must not decrease `k`.
+fn k_holds(
+    reserve_in: U256,
+    reserve_out: U256,
+    amount_in: U256,
+    amount_out: U256,
+    fee: (u64, u64),
+) -> bool {
+    let fee_paid = U256::from(fee.1 - fee.0);
+    let scale = U256::from(fee.1);
+    let balance_in_adjusted = (reserve_in + amount_in) * scale - amount_in * fee_paid;
+    let balance_out_adjusted = (reserve_out - amount_out) * scale;
+    balance_in_adjusted * balance_out_adjusted >= reserve_in * reserve_out * scale * scale
+}
+
+fn check_strategy(
+    strategy: &dyn V2CalculationStrategy,
+    fee: (u64, u64),
+    reserve_in: u128,
+    reserve_out: u128,
+    amount: u128,
+) -> Result<(), TestCaseError> {
+    let (reserve_in, reserve_out, amount) = (
+        U256::from(reserve_in),
+        U256::from(reserve_out),
+        U256::from(amount),
+    );
+
+    let amount_out = strategy
+        .calculate_tokens_out(reserve_in, reserve_out, amount)
+        .unwrap();
+    prop_assert_eq!(
+        amount_out,
+        reference_amount_out(amount, reserve_in, reserve_out, fee)
+    );
+    prop_assert!(k_holds(reserve_in, reserve_out, amount, amount_out, fee));
+
+    if amount < reserve_out {
+        let amount_in = strategy
+            .calculate_tokens_in_from_tokens_out(reserve_in, reserve_out, amount)
+            .unwrap();
+        prop_assert_eq!(
+            amount_in,
+            reference_amount_in(amount, reserve_in, reserve_out, fee)
+        );
+        prop_assert!(k_holds(reserve_in, reserve_out, amount_in, amount, fee));
+    }
+    Ok(())
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(2_000))]
+
+    #[test]
+    fn standard_matches_library(
+        reserve_in in MIN_RESERVE..=MAX_RESERVE,
+        reserve_out in MIN_RESERVE..=MAX_RESERVE,
+        amount in 1u128..=MAX_RESERVE,
+    ) {
+        check_strategy(&StandardV2Logic, (997, 1000), reserve_in, reserve_out, amount)?;
+    }
+
+    #[test]
+    fn standard_matches_library_for_small_trades(
+        reserve_in in MIN_RESERVE..=1_000_000_000_000u128,
+        reserve_out in MIN_RESERVE..=1_000_000_000_000u128,
+        amount in 1u128..=1_000_000u128,
+    ) {
+        check_strategy(&StandardV2Logic, (997, 1000), reserve_in, reserve_out, amount)?;
+    }
+
+    #[test]
+    fn pancake_matches_library(
+        reserve_in in MIN_RESERVE..=MAX_RESERVE,
+        reserve_out in MIN_RESERVE..=MAX_RESERVE,
+        amount in 1u128..=MAX_RESERVE,
+    ) {
+        check_strategy(&PancakeV2Logic, (9975, 10000), reserve_in, reserve_out, amount)?;
+    }
+}
+
+#[test]
+fn test_tokens_in_rounds_once() {
+    // Flooring the reserve ratio before applying the fee gives 602, one short of the
+    // library's 603.
+    let amount_in = StandardV2Logic
+        .calculate_tokens_in_from_tokens_out(
+            U256::from(1_000_000),
+            U256::from(10_000),
+            U256::from(6),
+        )
+        .unwrap();
+    assert_eq!(amount_in, U256::from(603));
+}
+
+#[test]
+fn test_edge_cases_return_typed_errors() {
+    let reserve = U256::from(1_000_000);
+    let strategy = StandardV2Logic;
+
+    assert_eq!(
+        strategy.calculate_tokens_out(reserve, reserve, U256::ZERO),
+        Err(ArbRsError::InsufficientInputAmount)
+    );
+    assert_eq!(
+        strategy.calculate_tokens_out(U256::ZERO, reserve, U256::from(1)),
+        Err(ArbRsError::InsufficientLiquidity)
+    );
+    assert_eq!(
+        strategy.calculate_tokens_in_from_tokens_out(reserve, reserve, U256::ZERO),
+        Err(ArbRsError::InsufficientOutputAmount)
+    );
+    assert_eq!(
+        strategy.calculate_tokens_in_from_tokens_out(reserve, reserve, reserve),
+        Err(ArbRsError::InsufficientLiquidity)
+    );
+    assert_eq!(
+        strategy.calculate_tokens_in_from_tokens_out(reserve, reserve, U256::MAX),
+        Err(ArbRsError::InsufficientLiquidity)
+    );
+    // Overflowing inputs error instead of panicking.
+    assert!(matches!(
+        strategy.calculate_tokens_out(U256::MAX, U256::MAX, U256::MAX),
+        Err(ArbRsError::CalculationError(_))
+    ));
+}
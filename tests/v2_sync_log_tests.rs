@@ -0,0 +1,131 @@
+mod common;
+
+use alloy::transports::mock::Asserter;
+use alloy_primitives::{Address, LogData, U256, aliases::U112};
+use alloy_provider::{Provider, ProviderBuilder};
+use alloy_rpc_types::Log;
+use alloy_sol_types::sol;
+use arbrs::ArbRsError;
+use arbrs::core::messaging::{Publisher, PublisherMessage, Subscriber};
+use arbrs::pool::strategy::StandardV2Logic;
+use arbrs::pool::uniswap_v2::{UniswapV2Pool, UniswapV2PoolState};
+use async_trait::async_trait;
+use common::token;
+use std::sync::{Arc, Mutex};
+
+sol! {
+    interface IUniswapV2Pair {
+        event Sync(uint112 reserve0, uint112 reserve1);
+    }
+}
+
+type DynProvider = dyn Provider + Send + Sync;
+
+const POOL: Address = Address::repeat_byte(0x01);
+
+#[derive(Default)]
+struct Recorder {
+    states: Mutex<Vec<UniswapV2PoolState>>,
+}
+
+#[async_trait]
+impl Subscriber<DynProvider> for Recorder {
+    fn id(&self) -> usize {
+        1
+    }
+
+    async fn notify(&self, message: PublisherMessage) {
+        if let PublisherMessage::PoolStateUpdate(state) = message {
+            self.states.lock().unwrap().push(state);
+        }
+    }
+}
+
+fn pool() -> UniswapV2Pool<DynProvider, StandardV2Logic> {
+    let provider: Arc<DynProvider> =
+        Arc::new(ProviderBuilder::new().connect_mocked_client(Asserter::new()));
+    UniswapV2Pool::new(
+        POOL,
+        token(Address::repeat_byte(0x0a), provider.clone()),
+        token(Address::repeat_byte(0x0b), provider.clone()),
+        provider,
+        StandardV2Logic,
+    )
+}
+
+fn sync_log(address: Address, reserve0: u64, reserve1: u64, block_number: u64) -> Log {
+    let event = IUniswapV2Pair::Sync {
+        reserve0: U112::from(reserve0),
+        reserve1: U112::from(reserve1),
+    };
+    Log {
+        inner: alloy_primitives::Log {
+            address,
+            data: LogData::from(&event),
+        },
+        block_number: Some(block_number),
+        ..Default::default()
+    }
+}
+
+fn state(reserve0: u64, reserve1: u64, block_number: u64) -> UniswapV2PoolState {
+    UniswapV2PoolState {
+        reserve0: U256::from(reserve0),
+        reserve1: U256::from(reserve1),
+        block_number,
+    }
+}
+
+#[tokio::test]
+async fn test_sync_log_sets_reserves_without_a_call() {
+    let pool = pool();
+    let recorder = Arc::new(Recorder::default());
+    let subscriber: Arc<dyn Subscriber<DynProvider>> = recorder.clone();
+    pool.subscribe(Arc::downgrade(&subscriber)).await;
+
+    pool.apply_sync_log(&sync_log(POOL, 100, 200, 10))
+        .await
+        .unwrap();
+    // A later sync in the same block supersedes the first.
+    pool.apply_sync_log(&sync_log(POOL, 110, 190, 10))
+        .await
+        .unwrap();
+
+    assert_eq!(pool.get_cached_reserves().await, state(110, 190, 10));
+    let cached = pool.cached_states().await;
+    assert_eq!(cached.len(), 1);
+    assert_eq!(cached[&10], state(110, 190, 10));
+
+    assert_eq!(
+        *recorder.states.lock().unwrap(),
+        [state(100, 200, 10), state(110, 190, 10)]
+    );
+}
+
+#[tokio::test]
+async fn test_older_sync_log_is_ignored() {
+    let pool = pool();
+    pool.apply_sync_log(&sync_log(POOL, 100, 200, 10))
+        .await
+        .unwrap();
+    pool.apply_sync_log(&sync_log(POOL, 50, 400, 9))
+        .await
+        .unwrap();
+
+    assert_eq!(pool.get_cached_reserves().await, state(100, 200, 10));
+    assert!(!pool.cached_states().await.contains_key(&9));
+}
+
+#[tokio::test]
+async fn test_sync_log_from_another_pool_is_rejected() {
+    let pool = pool();
+    let result = pool
+        .apply_sync_log(&sync_log(Address::repeat_byte(0x02), 100, 200, 10))
+        .await;
+
+    assert!(matches!(result, Err(ArbRsError::CalculationError(_))));
+    assert_eq!(
+        pool.get_cached_reserves().await,
+        UniswapV2PoolState::default()
+    );
+}
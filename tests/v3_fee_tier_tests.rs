@@ -0,0 +1,301 @@
+#![cfg(feature = "db")]
+
+mod common;
+
+use alloy::transports::mock::Asserter;
+use alloy_primitives::{Address, Bytes, LogData, address, aliases::I24, aliases::U24};
+use alloy_provider::{Provider, ProviderBuilder};
+use alloy_rpc_types::Log;
+use alloy_sol_types::{SolCall, sol};
+use arbrs::ArbRsError;
+use arbrs::core::token::Token;
+use arbrs::db::DbManager;
+use arbrs::dex::PoolKind;
+use arbrs::manager::token_manager::TokenManager;
+use arbrs::manager::uniswap_v3_pool_manager::{
+    PANCAKE_V3_FACTORY, UNISWAP_V3_FACTORY, UniswapV3PoolManager, V3FactoryConfig,
+};
+use arbrs::pool::LiquidityPool;
+use arbrs::pool::uniswap_v3::UniswapV3Pool;
+use common::named_token;
+use std::sync::Arc;
+
+const DB_URL: &str = "sqlite::memory:";
+const WETH_ADDRESS: Address = address!("C02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2");
+const USDC_ADDRESS: Address = address!("A0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48");
+const USDC_WETH_005_POOL: Address = address!("88e6A0c2dDD26FEEb64F039a2c41296FcB3f5640");
+const USDC_WETH_030_POOL: Address = address!("8ad599c3A0ff1De082011EFDDc58f1908eb6e6D8");
+const PANCAKE_POOL: Address = address!("0000000000000000000000000000000000000abc");
+type DynProvider = dyn Provider + Send + Sync;
+
+// Return encodings for the calls the mock answers.
+sol! {
+    function fee() external view returns (uint24);
+    function tickSpacing() external view returns (int24);
+    event PoolCreated(address indexed token0, address indexed token1, uint24 indexed fee, int24 tickSpacing, address pool);
+}
+
+struct Fixture {
+    asserter: Asserter,
+    provider: Arc<DynProvider>,
+    token_manager: Arc<TokenManager<DynProvider>>,
+    db_manager: Arc<DbManager>,
+    tokens: [Arc<Token<DynProvider>>; 2],
+}
+
+async fn setup() -> Fixture {
+    let asserter = Asserter::new();
+    let provider: Arc<DynProvider> =
+        Arc::new(ProviderBuilder::new().connect_mocked_client(asserter.clone()));
+    let db_manager = Arc::new(DbManager::new(DB_URL).await.unwrap());
+    let tokens = [
+        named_token(USDC_ADDRESS, "USDC", 6, provider.clone()),
+        named_token(WETH_ADDRESS, "WETH", 18, provider.clone()),
+    ];
+    // Tokens load from the database, so building a pool makes no token calls.
+    for token in &tokens {
+        db_manager.save_token(token).await.unwrap();
+    }
+    let token_manager = Arc::new(TokenManager::new(provider.clone(), 1, db_manager.clone()));
+
+    Fixture {
+        asserter,
+        provider,
+        token_manager,
+        db_manager,
+        tokens,
+    }
+}
+
+fn manager(fixture: &Fixture, factory: Address) -> UniswapV3PoolManager<DynProvider> {
+    UniswapV3PoolManager::new(
+        fixture.token_manager.clone(),
+        fixture.provider.clone(),
+        1,
+        0,
+        factory,
+    )
+    .with_db_manager(fixture.db_manager.clone())
+}
+
+fn push_fee_tier(asserter: &Asserter, fee: u32, tick_spacing: i32) {
+    asserter.push_success(&Bytes::from(feeCall::abi_encode_returns(&U24::from(fee))));
+    asserter.push_success(&Bytes::from(tickSpacingCall::abi_encode_returns(
+        &I24::try_from(tick_spacing).unwrap(),
+    )));
+}
+
+fn as_v3(pool: &Arc<dyn LiquidityPool<DynProvider>>) -> &UniswapV3Pool<DynProvider> {
+    pool.as_any()
+        .downcast_ref::<UniswapV3Pool<DynProvider>>()
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_corrupted_record_is_corrected_from_chain() {
+    let fixture = setup().await;
+    fixture
+        .db_manager
+        .save_pool(
+            USDC_WETH_030_POOL,
+            &PoolKind::UniswapV3,
+            &fixture.tokens,
+            Some(3_000),
+            Some(10),
+        )
+        .await
+        .unwrap();
+
+    push_fee_tier(&fixture.asserter, 3_000, 60);
+    let pool = manager(&fixture, UNISWAP_V3_FACTORY)
+        .build_pool(USDC_WETH_030_POOL, USDC_ADDRESS, WETH_ADDRESS, 3_000, 10)
+        .await
+        .unwrap();
+    assert!(fixture.asserter.read_q().is_empty());
+
+    let pool = as_v3(&pool);
+    assert_eq!((pool.fee(), pool.tick_spacing()), (3_000, 60));
+    assert!(!pool.is_non_standard_tier());
+
+    let records = fixture.db_manager.load_all_pools().await.unwrap();
+    assert_eq!(records.len(), 1);
+    assert_eq!(records[0].fee, Some(3_000));
+    assert_eq!(records[0].tick_spacing, Some(60));
+}
+
+#[tokio::test]
+async fn test_standard_tier_checks_pool_address() {
+    let fixture = setup().await;
+    let manager = manager(&fixture, UNISWAP_V3_FACTORY);
+
+    // A consistent record needs no calls.
+    let pool = manager
+        .build_pool(USDC_WETH_005_POOL, WETH_ADDRESS, USDC_ADDRESS, 500, 10)
+        .await
+        .unwrap();
+    assert_eq!(as_v3(&pool).tick_spacing(), 10);
+
+    // The 0.05% pool's address doesn't derive from the 0.3% tier.
+    let result = manager
+        .build_pool(USDC_WETH_030_POOL, USDC_ADDRESS, WETH_ADDRESS, 500, 10)
+        .await;
+    assert!(matches!(
+        result,
+        Err(ArbRsError::AddressMismatch(USDC_WETH_030_POOL, _))
+    ));
+}
+
+fn pancake_pool(fee: u32) -> Address {
+    V3FactoryConfig::pancakeswap()
+        .pool_address(USDC_ADDRESS, WETH_ADDRESS, fee)
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_pancake_tier_is_not_mangled() {
+    let fixture = setup().await;
+
+    let pool = manager(&fixture, PANCAKE_V3_FACTORY)
+        .build_pool(pancake_pool(2_500), USDC_ADDRESS, WETH_ADDRESS, 2_500, 50)
+        .await
+        .unwrap();
+    let pool = as_v3(&pool);
+    assert_eq!((pool.fee(), pool.tick_spacing()), (2_500, 50));
+    assert!(!pool.is_non_standard_tier());
+    assert!(fixture.asserter.read_q().is_empty());
+}
+
+#[tokio::test]
+async fn test_unknown_tiers_are_flagged() {
+    let fixture = setup().await;
+
+    // An unknown factory's pairs are taken as given.
+    let pool = manager(&fixture, Address::repeat_byte(0xfa))
+        .build_pool(PANCAKE_POOL, USDC_ADDRESS, WETH_ADDRESS, 2_500, 50)
+        .await
+        .unwrap();
+    assert!(as_v3(&pool).is_non_standard_tier());
+
+    // A known factory confirms an unlisted pair on-chain, then skips the address check.
+    push_fee_tier(&fixture.asserter, 2_500, 50);
+    let pool = manager(&fixture, UNISWAP_V3_FACTORY)
+        .build_pool(PANCAKE_POOL, USDC_ADDRESS, WETH_ADDRESS, 2_500, 50)
+        .await
+        .unwrap();
+    let pool = as_v3(&pool);
+    assert_eq!((pool.fee(), pool.tick_spacing()), (2_500, 50));
+    assert!(pool.is_non_standard_tier());
+}
+
+#[test]
+fn test_known_pancake_pools_derive_from_the_deployer() {
+    // Pancake V3 deploys from the same deployer and init code on every chain, so its BSC
+    // USDT/WBNB pools check the derivation.
+    let usdt = address!("55d398326f99059fF775485246999027B3197955");
+    let wbnb = address!("bb4CdB9CBd36B01bD1cBaEBF2De08d9173bc095c");
+    let pancake = V3FactoryConfig::pancakeswap();
+    assert_eq!(
+        pancake.pool_address(usdt, wbnb, 100),
+        Some(address!("172fcD41E0913e95784454622d1c3724f546f849"))
+    );
+    assert_eq!(
+        pancake.pool_address(wbnb, usdt, 500),
+        Some(address!("36696169C63e42cd08ce11f5deeBbCeBae652050"))
+    );
+
+    assert_eq!(
+        V3FactoryConfig::uniswap().pool_address(USDC_ADDRESS, WETH_ADDRESS, 500),
+        Some(USDC_WETH_005_POOL)
+    );
+    assert_eq!(
+        V3FactoryConfig::new(Address::repeat_byte(0xfa), Default::default()).pool_address(
+            USDC_ADDRESS,
+            WETH_ADDRESS,
+            500
+        ),
+        None
+    );
+}
+
+#[tokio::test]
+async fn test_one_manager_hydrates_both_deployments() {
+    let fixture = setup().await;
+    let manager = manager(&fixture, UNISWAP_V3_FACTORY);
+    assert_eq!(
+        manager.factory_for_kind(&PoolKind::UniswapV3),
+        Some(UNISWAP_V3_FACTORY)
+    );
+    let pancake = manager
+        .factory_for_kind(&PoolKind::PancakeSwapV3)
+        .unwrap();
+    assert_eq!(pancake, PANCAKE_V3_FACTORY);
+
+    // Pancake's 0.05% pool doesn't derive from Uniswap's deployment.
+    let result = manager
+        .build_pool(pancake_pool(500), USDC_ADDRESS, WETH_ADDRESS, 500, 10)
+        .await;
+    assert!(matches!(result, Err(ArbRsError::AddressMismatch(..))));
+
+    for (fee, tick_spacing) in [(500, 10), (2_500, 50)] {
+        let pool = manager
+            .build_factory_pool(
+                pancake,
+                pancake_pool(fee),
+                USDC_ADDRESS,
+                WETH_ADDRESS,
+                fee,
+                tick_spacing,
+            )
+            .await
+            .unwrap();
+        assert_eq!(as_v3(&pool).fee(), fee);
+    }
+    assert!(fixture.asserter.read_q().is_empty());
+}
+
+fn pool_created_log(pool: Address, fee: u32, tick_spacing: i32) -> Log {
+    let event = PoolCreated {
+        token0: USDC_ADDRESS,
+        token1: WETH_ADDRESS,
+        fee: U24::from(fee),
+        tickSpacing: I24::try_from(tick_spacing).unwrap(),
+        pool,
+    };
+    Log {
+        inner: alloy_primitives::Log {
+            address: PANCAKE_V3_FACTORY,
+            data: LogData::from(&event),
+        },
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn test_discovered_pools_are_stored_with_their_dex() {
+    let fixture = setup().await;
+    let mut manager = UniswapV3PoolManager::from_config(
+        fixture.token_manager.clone(),
+        fixture.provider.clone(),
+        1,
+        0,
+        V3FactoryConfig::pancakeswap(),
+    )
+    .with_db_manager(fixture.db_manager.clone());
+
+    // The second event names an address the deployer can't have created.
+    fixture.asserter.push_success(&vec![
+        pool_created_log(pancake_pool(2_500), 2_500, 50),
+        pool_created_log(PANCAKE_POOL, 500, 10),
+    ]);
+    let pools = manager.discover_pools_in_range(10).await.unwrap();
+    assert_eq!(pools.len(), 1);
+    assert_eq!(pools[0].address(), pancake_pool(2_500));
+
+    let records = fixture.db_manager.load_all_pools().await.unwrap();
+    assert_eq!(records.len(), 1);
+    assert_eq!(records[0].dex, PoolKind::PancakeSwapV3);
+    assert_eq!(
+        (records[0].fee, records[0].tick_spacing),
+        (Some(2_500), Some(50))
+    );
+}
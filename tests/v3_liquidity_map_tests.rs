@@ -0,0 +1,390 @@
+#![cfg(feature = "db")]
+
+mod common;
+
+use alloy::transports::mock::Asserter;
+use alloy_primitives::{Address, Bytes, LogData, U64, U256, address, aliases::I24};
+use alloy_provider::{Provider, ProviderBuilder};
+use alloy_rpc_types::Log;
+use alloy_sol_types::{SolCall, sol};
+use arbrs::ArbRsError;
+use arbrs::db::DbManager;
+use arbrs::manager::log_scan::LogScanConfig;
+use arbrs::manager::token_manager::TokenManager;
+use arbrs::manager::uniswap_v3_pool_manager::{FeeTierTable, UniswapV3PoolManager};
+use arbrs::pool::uniswap_v3::{TickInfo, UniswapV3Pool};
+use arbrs::pool::uniswap_v3_snapshot::{LiquidityMap, LiquidityMapBuilder};
+use common::token;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+sol! {
+    event Mint(address sender, address indexed owner, int24 indexed tickLower, int24 indexed tickUpper, uint128 amount, uint256 amount0, uint256 amount1);
+    event Burn(address indexed owner, int24 indexed tickLower, int24 indexed tickUpper, uint128 amount, uint256 amount0, uint256 amount1);
+    function slot0() external view returns (uint160 sqrtPriceX96, int24 tick, uint16 observationIndex, uint16 observationCardinality, uint16 observationCardinalityNext, uint8 feeProtocol, bool unlocked);
+    function liquidity() external view returns (uint128);
+    function tickBitmap(int16 wordPosition) external view returns (uint256);
+    function ticks(int24 tick) external view returns (uint128 liquidityGross, int128 liquidityNet, uint256 feeGrowthOutside0X128, uint256 feeGrowthOutside1X128, int56 tickCumulativeOutside, uint160 secondsPerLiquidityOutsideX128, uint32 secondsOutside, bool initialized);
+
+    struct PopulatedTick {
+        int24 tick;
+        int128 liquidityNet;
+        uint128 liquidityGross;
+    }
+    function getPopulatedTicksInWord(address pool, int16 tickBitmapIndex) external view returns (PopulatedTick[] populatedTicks);
+}
+
+type DynProvider = dyn Provider + Send + Sync;
+
+const POOL: Address = Address::repeat_byte(0x01);
+const FACTORY: Address = Address::repeat_byte(0xfa);
+const FORK_RPC_URL: &str = "http://127.0.0.1:8545";
+const WBTC_WETH_3000: Address = address!("CBCdF9626bC03E24f779434178A73a0B4bad62eD");
+
+fn pool(provider: Arc<DynProvider>, tick_spacing: i32) -> UniswapV3Pool<DynProvider> {
+    UniswapV3Pool::new(
+        POOL,
+        token(Address::repeat_byte(0x0a), provider.clone()),
+        token(Address::repeat_byte(0x0b), provider.clone()),
+        3_000,
+        tick_spacing,
+        provider,
+        None,
+    )
+}
+
+fn tick(liquidity_gross: u128, liquidity_net: i128) -> TickInfo {
+    TickInfo {
+        liquidity_gross,
+        liquidity_net,
+    }
+}
+
+/// Ticks 0 and 60 in word 0 and tick 76800 in word 5, at a tick spacing of 60.
+fn stored_map() -> LiquidityMap {
+    LiquidityMap {
+        tick_bitmap: BTreeMap::from([(0, U256::from(0b11)), (5, U256::from(1))]),
+        tick_data: BTreeMap::from([
+            (0, tick(100, 100)),
+            (60, tick(100, -100)),
+            (76_800, tick(7, 7)),
+        ]),
+    }
+}
+
+fn mint_log(tick_lower: i32, tick_upper: i32) -> Log {
+    let event = Mint {
+        sender: Address::ZERO,
+        owner: Address::ZERO,
+        tickLower: I24::try_from(tick_lower).unwrap(),
+        tickUpper: I24::try_from(tick_upper).unwrap(),
+        amount: 1,
+        amount0: U256::ZERO,
+        amount1: U256::ZERO,
+    };
+    Log {
+        inner: alloy_primitives::Log {
+            address: POOL,
+            data: LogData::from(&event),
+        },
+        ..Default::default()
+    }
+}
+
+/// A `Mint` of `amount`, or a `Burn` if it's negative, in block `block_number`.
+fn liquidity_log(tick_lower: i32, tick_upper: i32, amount: i128, block_number: u64) -> Log {
+    let (tick_lower, tick_upper) = (
+        I24::try_from(tick_lower).unwrap(),
+        I24::try_from(tick_upper).unwrap(),
+    );
+    let data = if amount >= 0 {
+        LogData::from(&Mint {
+            sender: Address::ZERO,
+            owner: Address::ZERO,
+            tickLower: tick_lower,
+            tickUpper: tick_upper,
+            amount: amount as u128,
+            amount0: U256::ZERO,
+            amount1: U256::ZERO,
+        })
+    } else {
+        LogData::from(&Burn {
+            owner: Address::ZERO,
+            tickLower: tick_lower,
+            tickUpper: tick_upper,
+            amount: amount.unsigned_abs(),
+            amount0: U256::ZERO,
+            amount1: U256::ZERO,
+        })
+    };
+    Log {
+        inner: alloy_primitives::Log {
+            address: POOL,
+            data,
+        },
+        block_number: Some(block_number),
+        ..Default::default()
+    }
+}
+
+/// Answers the builder's cross-check: the pool's tick, then its `liquidity()`.
+fn push_pool_state(asserter: &Asserter, tick: i32, liquidity: u128) {
+    asserter.push_success(&Bytes::from(slot0Call::abi_encode_returns(&slot0Return {
+        sqrtPriceX96: Default::default(),
+        tick: I24::try_from(tick).unwrap(),
+        observationIndex: 0,
+        observationCardinality: 0,
+        observationCardinalityNext: 0,
+        feeProtocol: 0,
+        unlocked: true,
+    })));
+    asserter.push_success(&Bytes::from(liquidityCall::abi_encode_returns(&liquidity)));
+}
+
+/// Two positions minted and the first burnt again, with the logs out of order.
+fn push_backfill_logs(asserter: &Asserter) {
+    asserter.push_success(&vec![
+        liquidity_log(0, 60, -100, 3),
+        liquidity_log(0, 60, 100, 1),
+        liquidity_log(-60, 120, 50, 2),
+    ]);
+}
+
+#[tokio::test]
+async fn test_builder_folds_mints_and_burns_into_a_map() {
+    let asserter = Asserter::new();
+    let provider: Arc<DynProvider> =
+        Arc::new(ProviderBuilder::new().connect_mocked_client(asserter.clone()));
+    let builder = LiquidityMapBuilder::new(provider, POOL, 60)
+        .with_log_scan(LogScanConfig::default().with_chunk_size(100));
+
+    push_backfill_logs(&asserter);
+    push_pool_state(&asserter, 10, 50);
+    let map = builder.build(1, 3).await.unwrap();
+    assert!(asserter.read_q().is_empty());
+
+    // Ticks 0 and 60 were cleared by the burn; -60 sits in word -1 and 120 in word 0.
+    assert_eq!(
+        map.tick_data,
+        BTreeMap::from([(-60, tick(50, 50)), (120, tick(50, -50))])
+    );
+    assert_eq!(
+        map.tick_bitmap,
+        BTreeMap::from([(-1, U256::ONE << 255), (0, U256::from(0b100))])
+    );
+    assert_eq!(map.liquidity_at(10), 50);
+    assert_eq!(map.liquidity_at(120), 0);
+}
+
+#[tokio::test]
+async fn test_builder_reports_a_map_disagreeing_with_the_pool() {
+    let asserter = Asserter::new();
+    let provider: Arc<DynProvider> =
+        Arc::new(ProviderBuilder::new().connect_mocked_client(asserter.clone()));
+    // Without the position minted before the range, the map is short of it.
+    let builder = LiquidityMapBuilder::new(provider, POOL, 60);
+
+    push_backfill_logs(&asserter);
+    push_pool_state(&asserter, 10, 80);
+    let result = builder.build(1, 3).await;
+    assert_eq!(
+        result,
+        Err(ArbRsError::LiquidityMismatch {
+            pool: POOL,
+            block: 3,
+            computed: 50,
+            onchain: 80,
+        })
+    );
+}
+
+#[tokio::test]
+async fn test_builder_extends_a_map_on_fork() {
+    let provider: Arc<DynProvider> =
+        Arc::new(ProviderBuilder::new().connect_http(FORK_RPC_URL.parse().unwrap()));
+    let end_block = provider.get_block_number().await.unwrap();
+    let start_block = end_block - 50_000;
+
+    // The map at the start is read from the pool, then brought to the end from its logs.
+    let pool = UniswapV3Pool::new(
+        WBTC_WETH_3000,
+        token(Address::repeat_byte(0x0a), provider.clone()),
+        token(Address::repeat_byte(0x0b), provider.clone()),
+        3_000,
+        60,
+        provider.clone(),
+        None,
+    );
+    pool.refresh_liquidity_map(start_block).await.unwrap();
+    let (_, start_map) = pool.liquidity_map().await;
+
+    let map = LiquidityMapBuilder::new(provider, WBTC_WETH_3000, 60)
+        .with_initial_map(start_map)
+        .build(start_block + 1, end_block)
+        .await
+        .unwrap();
+    assert!(!map.tick_data.is_empty());
+}
+
+#[tokio::test]
+async fn test_liquidity_map_round_trips_through_the_database() {
+    let db = DbManager::new("sqlite::memory:").await.unwrap();
+    assert!(db.load_liquidity_map(POOL).await.unwrap().is_none());
+
+    let mut map = stored_map();
+    map.tick_data.insert(-120, tick(u128::MAX, i128::MIN));
+    map.tick_bitmap.insert(-1, U256::MAX);
+    db.save_liquidity_map(POOL, 60, 100, &map).await.unwrap();
+    assert_eq!(db.load_liquidity_map(POOL).await.unwrap(), Some((100, map)));
+
+    // Saving again replaces the whole map.
+    db.save_liquidity_map(POOL, 60, 200, &stored_map())
+        .await
+        .unwrap();
+    assert_eq!(
+        db.load_liquidity_map(POOL).await.unwrap(),
+        Some((200, stored_map()))
+    );
+}
+
+#[tokio::test]
+async fn test_refresh_reads_only_words_touched_since_the_map_block() {
+    let asserter = Asserter::new();
+    let provider: Arc<DynProvider> =
+        Arc::new(ProviderBuilder::new().connect_mocked_client(asserter.clone()));
+    let pool = pool(provider, 60);
+    pool.set_liquidity_map(stored_map(), 100).await;
+
+    // A mint between ticks 0 and 60 touches word 0 only, which now has tick 60 alone.
+    asserter.push_success(&vec![mint_log(0, 60)]);
+    asserter.push_success(&Bytes::from(tickBitmapCall::abi_encode_returns(
+        &U256::from(0b10),
+    )));
+    asserter.push_success(&Bytes::from(ticksCall::abi_encode_returns(&ticksReturn {
+        liquidityGross: 500,
+        liquidityNet: -500,
+        feeGrowthOutside0X128: U256::ZERO,
+        feeGrowthOutside1X128: U256::ZERO,
+        tickCumulativeOutside: Default::default(),
+        secondsPerLiquidityOutsideX128: Default::default(),
+        secondsOutside: 0,
+        initialized: true,
+    })));
+
+    assert_eq!(pool.refresh_liquidity_map(105).await.unwrap(), 1);
+    let (map_block, map) = pool.liquidity_map().await;
+    assert_eq!(map_block, Some(105));
+    assert_eq!(
+        map.tick_bitmap,
+        BTreeMap::from([(0, U256::from(0b10)), (5, U256::from(1))])
+    );
+    assert_eq!(
+        map.tick_data,
+        BTreeMap::from([(60, tick(500, -500)), (76_800, tick(7, 7))])
+    );
+
+    // Nothing to do at or before the map's block.
+    assert_eq!(pool.refresh_liquidity_map(105).await.unwrap(), 0);
+}
+
+#[tokio::test]
+async fn test_tick_lens_reads_a_word_in_one_call() {
+    let asserter = Asserter::new();
+    let provider: Arc<DynProvider> =
+        Arc::new(ProviderBuilder::new().connect_mocked_client(asserter.clone()));
+    let pool = pool(provider, 60).with_tick_lens(Address::repeat_byte(0x1e));
+    pool.set_liquidity_map(stored_map(), 100).await;
+
+    // Word 0 now has ticks 0 and 60, which the lens lists highest first.
+    asserter.push_success(&vec![mint_log(0, 60)]);
+    asserter.push_success(&Bytes::from(tickBitmapCall::abi_encode_returns(
+        &U256::from(0b11),
+    )));
+    let populated = |tick: i32, liquidity: u128, liquidity_net: i128| PopulatedTick {
+        tick: I24::try_from(tick).unwrap(),
+        liquidityNet: liquidity_net,
+        liquidityGross: liquidity,
+    };
+    asserter.push_success(&Bytes::from(
+        getPopulatedTicksInWordCall::abi_encode_returns(&vec![
+            populated(60, 300, -300),
+            populated(0, 300, 300),
+        ]),
+    ));
+
+    assert_eq!(pool.refresh_liquidity_map(105).await.unwrap(), 1);
+    assert!(asserter.read_q().is_empty());
+    let (_, map) = pool.liquidity_map().await;
+    assert_eq!(
+        map.tick_data,
+        BTreeMap::from([
+            (0, tick(300, 300)),
+            (60, tick(300, -300)),
+            (76_800, tick(7, 7))
+        ])
+    );
+}
+
+#[tokio::test]
+async fn test_map_never_read_is_read_in_full() {
+    let asserter = Asserter::new();
+    let provider: Arc<DynProvider> =
+        Arc::new(ProviderBuilder::new().connect_mocked_client(asserter.clone()));
+    // A tick spacing of 200 spreads the usable ticks over words -18 to 17.
+    let pool = pool(provider, 200);
+    for _ in 0..36 {
+        asserter.push_success(&Bytes::from(tickBitmapCall::abi_encode_returns(
+            &U256::ZERO,
+        )));
+    }
+
+    assert_eq!(pool.refresh_liquidity_map(100).await.unwrap(), 36);
+    let (map_block, map) = pool.liquidity_map().await;
+    assert_eq!(map_block, Some(100));
+    assert!(map.tick_bitmap.is_empty() && map.tick_data.is_empty());
+}
+
+#[tokio::test]
+async fn test_built_pool_is_hydrated_from_the_stored_map() {
+    let asserter = Asserter::new();
+    let provider: Arc<DynProvider> =
+        Arc::new(ProviderBuilder::new().connect_mocked_client(asserter.clone()));
+    let db = Arc::new(DbManager::new("sqlite::memory:").await.unwrap());
+    db.save_liquidity_map(POOL, 60, 100, &stored_map())
+        .await
+        .unwrap();
+
+    let token_manager = Arc::new(TokenManager::in_memory(provider.clone(), 1));
+    token_manager.insert_token(token(Address::repeat_byte(0x0a), provider.clone()));
+    token_manager.insert_token(token(Address::repeat_byte(0x0b), provider.clone()));
+    let manager = UniswapV3PoolManager::new(token_manager, provider, 1, 0, FACTORY)
+        .with_fee_tiers(FACTORY, FeeTierTable::new([(3_000, 60)]))
+        .with_db_manager(db.clone());
+
+    // The chain is still at the map's block, so no tick is read.
+    asserter.push_success(&U64::from(100));
+    let pool = manager
+        .build_pool(
+            POOL,
+            Address::repeat_byte(0x0a),
+            Address::repeat_byte(0x0b),
+            3_000,
+            60,
+        )
+        .await
+        .unwrap();
+    let v3_pool = pool
+        .as_any()
+        .downcast_ref::<UniswapV3Pool<DynProvider>>()
+        .unwrap();
+    assert_eq!(v3_pool.liquidity_map().await, (Some(100), stored_map()));
+
+    db.save_liquidity_map(POOL, 60, 100, &LiquidityMap::default())
+        .await
+        .unwrap();
+    assert_eq!(manager.save_liquidity_maps(100, 5).await, 1);
+    assert_eq!(
+        db.load_liquidity_map(POOL).await.unwrap(),
+        Some((100, stored_map()))
+    );
+}
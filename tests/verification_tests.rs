@@ -0,0 +1,270 @@
+mod common;
+
+use alloy::transports::mock::Asserter;
+use alloy_primitives::{Address, Bytes, U64, U256, address};
+use alloy_provider::{Provider, ProviderBuilder};
+use alloy_rpc_types::simulate::{SimCallResult, SimulatedBlock};
+use arbrs::ArbRsError;
+use arbrs::arbitrage::engine::ArbitrageEngine;
+use arbrs::arbitrage::types::ArbitrageSolution;
+use arbrs::arbitrage::verification::VerificationPolicy;
+use arbrs::dex::DexVariant;
+use arbrs::manager::token_manager::TokenManager;
+use arbrs::manager::uniswap_v2_pool_manager::UniswapV2PoolManager;
+use arbrs::pool::strategy::StandardV2Logic;
+use arbrs::pool::uniswap_v2::{UniswapV2Pool, UniswapV2PoolState};
+use arbrs::pool::{LiquidityPool, PoolSnapshot};
+use common::{ether, named_token, token};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+const FORK_RPC_URL: &str = "http://127.0.0.1:8545";
+const FORK_BLOCK: u64 = 19_000_000;
+const WETH: Address = address!("C02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2");
+const UNISWAP_V2_WETH_USDC: Address = address!("B4e16d0168e52d35CaCD2c6185b44281Ec28C9Dc");
+const SUSHISWAP_WETH_USDC: Address = address!("397FF1542f962076d0BFE58ea045ffa2d3473aee");
+/// What the balance slot probe reads back when the first candidate, Solidity slot 0, holds
+/// the balance.
+const FIRST_SLOT_MARKER: U256 = U256::from_limbs([0, 0, 0, 1 << 32]);
+type DynProvider = dyn Provider + Send + Sync;
+
+fn reserves(weth: u64, other: u64) -> PoolSnapshot {
+    PoolSnapshot::UniswapV2(UniswapV2PoolState {
+        reserve0: ether(weth),
+        reserve1: ether(other),
+        block_number: 1,
+    })
+}
+
+fn word(value: U256) -> Bytes {
+    Bytes::from(value.to_be_bytes::<32>())
+}
+
+/// A quote-only engine over two WETH pairs of the same token, plus the pairs.
+async fn engine(
+    asserter: &Asserter,
+) -> (
+    ArbitrageEngine<DynProvider>,
+    Vec<Arc<dyn LiquidityPool<DynProvider>>>,
+) {
+    let provider: Arc<DynProvider> =
+        Arc::new(ProviderBuilder::new().connect_mocked_client(asserter.clone()));
+    let (weth, other) = (
+        named_token(WETH, "WETH", 18, provider.clone()),
+        token(Address::repeat_byte(0xee), provider.clone()),
+    );
+    let pools: Vec<Arc<dyn LiquidityPool<DynProvider>>> = [0x01, 0x02]
+        .into_iter()
+        .map(|byte| {
+            Arc::new(UniswapV2Pool::new(
+                Address::repeat_byte(byte),
+                weth.clone(),
+                other.clone(),
+                provider.clone(),
+                StandardV2Logic,
+            )) as Arc<dyn LiquidityPool<DynProvider>>
+        })
+        .collect();
+    asserter.push_success(&U64::from(1));
+    let engine = ArbitrageEngine::quote_only(provider, pools.clone())
+        .await
+        .unwrap();
+    (engine, pools)
+}
+
+/// Stale reserves where pair 0x01 pays 20% more for WETH than it really does.
+fn stale_reserves() -> HashMap<Address, PoolSnapshot> {
+    HashMap::from([
+        (Address::repeat_byte(0x01), reserves(1_000, 2_400_000)),
+        (Address::repeat_byte(0x02), reserves(1_000, 2_000_000)),
+    ])
+}
+
+/// Fails the calls an evaluation makes before verifying: both snapshots, which the stale
+/// reserves stand in for, the gas price and the flashloan liquidity.
+fn fail_evaluation_calls(asserter: &Asserter) {
+    for _ in 0..4 {
+        asserter.push_failure_msg("not mocked");
+    }
+}
+
+/// Queues both pairs' actual reserves, equal so that no arbitrage exists.
+fn push_actual_reserves(asserter: &Asserter) {
+    for _ in 0..2 {
+        asserter.push_success(&Bytes::from(
+            [ether(1_000), ether(2_000_000), U256::ZERO]
+                .iter()
+                .flat_map(|word| word.to_be_bytes::<32>())
+                .collect::<Vec<u8>>(),
+        ));
+    }
+}
+
+/// Queues the reads a verification makes ahead of its simulation: the balance slot probe,
+/// then the pairs' actual reserves.
+fn push_verification_reads(asserter: &Asserter) {
+    asserter.push_success(&word(FIRST_SLOT_MARKER));
+    push_actual_reserves(asserter);
+}
+
+/// Queues an `eth_simulateV1` result: each hop's transfer and swap with the given statuses,
+/// then the WETH balance left.
+fn push_simulation(asserter: &Asserter, statuses: [bool; 4], balance: U256) {
+    let mut calls: Vec<SimCallResult> = statuses
+        .into_iter()
+        .map(|status| SimCallResult {
+            return_data: Bytes::new(),
+            logs: Vec::new(),
+            gas_used: 60_000,
+            status,
+            error: None,
+        })
+        .collect();
+    calls.push(SimCallResult {
+        return_data: word(balance),
+        logs: Vec::new(),
+        gas_used: 30_000,
+        status: true,
+        error: None,
+    });
+    let blocks: Vec<SimulatedBlock> = vec![SimulatedBlock {
+        inner: Default::default(),
+        calls,
+    }];
+    asserter.push_success(&blocks);
+}
+
+/// What `solution`'s input really returns through pairs with equal reserves.
+fn actual_output(solution: &ArbitrageSolution<DynProvider>) -> U256 {
+    let snapshots = HashMap::from([
+        (Address::repeat_byte(0x01), reserves(1_000, 2_000_000)),
+        (Address::repeat_byte(0x02), reserves(1_000, 2_000_000)),
+    ]);
+    solution
+        .path
+        .calculate_out_amount(solution.optimal_input.raw, &snapshots)
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_stale_snapshot_solution_is_dropped() {
+    let asserter = Asserter::new();
+    let (engine, _) = engine(&asserter).await;
+    let engine = engine.with_verification(VerificationPolicy::Drop);
+
+    // Found on the stale reserves, a round trip through two equal pairs only pays fees.
+    let probe = engine
+        .find_opportunities_with_overrides(None, stale_reserves())
+        .await;
+    assert_eq!(probe.len(), 1);
+    let realized = actual_output(&probe[0]);
+    assert!(realized < probe[0].optimal_input.raw);
+
+    fail_evaluation_calls(&asserter);
+    push_verification_reads(&asserter);
+    push_simulation(&asserter, [true; 4], realized);
+    let solutions = engine
+        .find_opportunities_with_overrides(Some(1), stale_reserves())
+        .await;
+    assert!(solutions.is_empty());
+    assert!(asserter.read_q().is_empty());
+    let stats = engine.last_stats();
+    assert_eq!((stats.verification_rejects, stats.solutions), (1, 0));
+}
+
+#[tokio::test]
+async fn test_unverified_solution_is_downgraded_with_its_replay() {
+    let asserter = Asserter::new();
+    let (engine, _) = engine(&asserter).await;
+    let engine = engine.with_verification(VerificationPolicy::Downgrade);
+
+    let probe = engine
+        .find_opportunities_with_overrides(None, stale_reserves())
+        .await;
+    let realized = actual_output(&probe[0]);
+
+    fail_evaluation_calls(&asserter);
+    push_verification_reads(&asserter);
+    push_simulation(&asserter, [true; 4], realized);
+    let solutions = engine
+        .find_opportunities_with_overrides(Some(1), stale_reserves())
+        .await;
+    assert_eq!(solutions.len(), 1);
+    assert!(!solutions[0].is_verified());
+    let verified = solutions[0].verification.unwrap();
+    assert_eq!(verified.input, solutions[0].optimal_input.raw);
+    assert_eq!(verified.realized_output, realized);
+    assert_eq!(verified.gas_used, 240_000);
+    assert_eq!(engine.last_stats().verification_rejects, 1);
+}
+
+#[tokio::test]
+async fn test_reverted_hop_fails_verification() {
+    let asserter = Asserter::new();
+    let (engine, _) = engine(&asserter).await;
+    let solutions = engine
+        .find_opportunities_with_overrides(None, stale_reserves())
+        .await;
+
+    push_verification_reads(&asserter);
+    push_simulation(&asserter, [true, true, true, false], U256::ZERO);
+    let result = engine.verify_solution(&solutions[0], 1).await;
+    assert!(
+        matches!(&result, Err(ArbRsError::ContractError(message)) if message.starts_with("Hop 1")),
+        "{result:?}"
+    );
+
+    // The balance slot found and the pools' reserves at the block are kept, so the second
+    // replay goes straight to the simulation.
+    push_simulation(&asserter, [true; 4], solutions[0].optimal_input.raw);
+    let verified = engine.verify_solution(&solutions[0], 1).await.unwrap();
+    assert_eq!(verified.realized_profit(), U256::ZERO);
+    assert!(asserter.read_q().is_empty());
+}
+
+#[tokio::test]
+async fn test_stale_snapshot_solution_fails_replay_on_fork() {
+    let provider: Arc<DynProvider> =
+        Arc::new(ProviderBuilder::new().connect_http(FORK_RPC_URL.parse().unwrap()));
+    let token_manager = Arc::new(TokenManager::in_memory(provider.clone(), 1));
+    let v2_manager = UniswapV2PoolManager::new_static(token_manager, provider.clone(), []);
+    v2_manager
+        .add_pool_by_address(UNISWAP_V2_WETH_USDC, DexVariant::UniswapV2)
+        .await
+        .unwrap();
+    v2_manager
+        .add_pool_by_address(SUSHISWAP_WETH_USDC, DexVariant::SushiSwap)
+        .await
+        .unwrap();
+    let pools = v2_manager.get_all_pools();
+    let sushiswap = pools
+        .iter()
+        .find(|pool| pool.address() == SUSHISWAP_WETH_USDC)
+        .unwrap();
+
+    // SushiSwap is made to look like it pays 10% more USDC for WETH than it does.
+    let PoolSnapshot::UniswapV2(mut stale) =
+        sushiswap.get_snapshot(Some(FORK_BLOCK)).await.unwrap()
+    else {
+        panic!("not a V2 snapshot");
+    };
+    stale.reserve0 = stale.reserve0 * U256::from(11) / U256::from(10);
+    let overrides = HashMap::from([(SUSHISWAP_WETH_USDC, PoolSnapshot::UniswapV2(stale))]);
+
+    let engine = ArbitrageEngine::quote_only(provider, pools)
+        .await
+        .unwrap()
+        .with_verification(VerificationPolicy::PassThrough);
+    let solutions = engine
+        .find_opportunities_with_overrides(Some(FORK_BLOCK), overrides)
+        .await;
+    assert!(!solutions.is_empty());
+    for solution in &solutions {
+        // The replay runs against the real reserves and loses money.
+        let verified = solution.verification.expect("the replay went through");
+        assert!(verified.realized_output < verified.input);
+        assert!(verified.gas_used > 0);
+        assert!(!solution.is_verified());
+    }
+    assert_eq!(engine.last_stats().verification_rejects, solutions.len());
+}
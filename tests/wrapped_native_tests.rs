@@ -0,0 +1,273 @@
+mod common;
+
+use alloy::transports::mock::Asserter;
+use alloy_primitives::{Address, U64, U256};
+use alloy_provider::{Provider, ProviderBuilder};
+use arbrs::arbitrage::cycle::ArbitrageCycle;
+use arbrs::arbitrage::engine::ArbitrageEngine;
+use arbrs::arbitrage::finder::{MinLiquidityFilter, find_multi_hop_cycles_in_pools};
+use arbrs::arbitrage::types::{Arbitrage, SwapKind};
+use arbrs::core::token::{NATIVE_ETH_ADDRESS, NativeTokenData, Token, WETH_ADDRESS};
+use arbrs::curve::constants::FEE_DENOMINATOR;
+use arbrs::curve::pool::CurveStableswapPool;
+use arbrs::curve::pool_attributes::{
+    CalculationStrategy, PoolAttributes, PoolVariant, SwapStrategyType,
+};
+use arbrs::curve::types::CurvePoolSnapshot;
+use arbrs::manager::token_manager::TokenManager;
+use arbrs::pool::strategy::StandardV2Logic;
+use arbrs::pool::uniswap_v2::{UniswapV2Pool, UniswapV2PoolState};
+use arbrs::pool::{LiquidityPool, PoolSnapshot};
+use arbrs::{ArbRsError, TokenLike};
+use common::{ether, named_token, token};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+type DynProvider = dyn Provider + Send + Sync;
+
+const CURVE_POOL: Address = Address::repeat_byte(0x01);
+const V2_POOL: Address = Address::repeat_byte(0x02);
+const STETH: Address = Address::repeat_byte(0x5e);
+
+/// Attributes of a plain stableswap pool of `n_coins` coins worth an ether each.
+fn attributes(n_coins: usize) -> PoolAttributes {
+    PoolAttributes {
+        pool_variant: PoolVariant::Eth,
+        strategy: CalculationStrategy::Legacy,
+        ..common::attributes(n_coins, SwapStrategyType::Default)
+    }
+}
+
+/// A stableswap pool whose `coins` returned `raw_coins`, native ether listed as WETH.
+fn pool_with_coins(
+    provider: &Arc<DynProvider>,
+    raw_coins: &[Address],
+) -> CurveStableswapPool<DynProvider> {
+    let mut attributes = attributes(raw_coins.len());
+    attributes.is_native = raw_coins
+        .iter()
+        .map(|coin| *coin == NATIVE_ETH_ADDRESS)
+        .collect();
+    attributes.raw_coin_addresses = raw_coins.to_vec();
+    let tokens = raw_coins
+        .iter()
+        .map(|coin| match *coin {
+            NATIVE_ETH_ADDRESS => named_token(WETH_ADDRESS, "WETH", 18, provider.clone()),
+            coin => token(coin, provider.clone()),
+        })
+        .collect::<Vec<_>>();
+    CurveStableswapPool::from_parts(
+        CURVE_POOL,
+        named_token(Address::repeat_byte(0x1b), "LP", 18, provider.clone()),
+        tokens,
+        provider.clone(),
+        Arc::new(TokenManager::in_memory(provider.clone(), 1)),
+        attributes,
+    )
+}
+
+/// An ETH/stETH stableswap pool like Curve's, taking ether for its first coin when
+/// `native`, and a WETH/stETH pair paying 10% more for stETH.
+fn pools(provider: &Arc<DynProvider>, native: bool) -> Vec<Arc<dyn LiquidityPool<DynProvider>>> {
+    let (weth, steth) = (
+        named_token(WETH_ADDRESS, "WETH", 18, provider.clone()),
+        named_token(STETH, "stETH", 18, provider.clone()),
+    );
+    let mut attributes = attributes(2);
+    attributes.is_native = vec![native, false];
+    let curve = CurveStableswapPool::from_parts(
+        CURVE_POOL,
+        steth.clone(),
+        vec![weth.clone(), steth.clone()],
+        provider.clone(),
+        Arc::new(TokenManager::in_memory(provider.clone(), 1)),
+        attributes,
+    );
+    let v2 = UniswapV2Pool::new(V2_POOL, weth, steth, provider.clone(), StandardV2Logic);
+    vec![Arc::new(curve), Arc::new(v2)]
+}
+
+fn overrides() -> HashMap<Address, PoolSnapshot> {
+    HashMap::from([
+        (
+            CURVE_POOL,
+            PoolSnapshot::Curve(CurvePoolSnapshot {
+                balances: vec![ether(10_000); 2],
+                a: U256::from(100),
+                fee: U256::from(4_000_000),
+                admin_fee: Some(FEE_DENOMINATOR / U256::from(2)),
+                rates: vec![ether(1); 2],
+                ..Default::default()
+            }),
+        ),
+        (
+            V2_POOL,
+            PoolSnapshot::UniswapV2(UniswapV2PoolState {
+                reserve0: ether(1_100),
+                reserve1: ether(1_000),
+                block_number: 1,
+            }),
+        ),
+    ])
+}
+
+#[tokio::test]
+async fn test_finder_unwraps_ahead_of_a_pool_taking_ether() {
+    let provider: Arc<DynProvider> =
+        Arc::new(ProviderBuilder::new().connect_mocked_client(Asserter::new()));
+    let token_manager = TokenManager::in_memory(provider.clone(), 1);
+    let native_pools = pools(&provider, true);
+    for token in native_pools.iter().flat_map(|pool| pool.get_all_tokens()) {
+        token_manager.insert_token(token);
+    }
+    let report = find_multi_hop_cycles_in_pools(
+        native_pools,
+        &token_manager,
+        3,
+        &MinLiquidityFilter::default(),
+    )
+    .await;
+
+    let cycle = report
+        .paths
+        .iter()
+        .filter_map(|path| path.as_any().downcast_ref::<ArbitrageCycle<DynProvider>>())
+        .find(|cycle| {
+            cycle.get_involved_pools().contains(&V2_POOL)
+                && cycle.get_involved_pools().contains(&CURVE_POOL)
+        })
+        .unwrap();
+    // The pool at WETH's address is WETH itself, unwrapping.
+    assert_eq!(
+        cycle.get_involved_pools(),
+        vec![WETH_ADDRESS, CURVE_POOL, V2_POOL]
+    );
+    let tokens: Vec<Address> = cycle
+        .path
+        .path
+        .iter()
+        .map(|token| token.address())
+        .collect();
+    assert_eq!(
+        tokens,
+        vec![WETH_ADDRESS, NATIVE_ETH_ADDRESS, STETH, WETH_ADDRESS]
+    );
+
+    // Pools that all trade WETH get no extra hop.
+    let report = find_multi_hop_cycles_in_pools(
+        pools(&provider, false),
+        &token_manager,
+        3,
+        &MinLiquidityFilter::default(),
+    )
+    .await;
+    assert!(
+        report
+            .paths
+            .iter()
+            .all(|path| !path.get_involved_pools().contains(&WETH_ADDRESS))
+    );
+}
+
+#[tokio::test]
+async fn test_wrap_hops_leave_the_profit_unchanged() {
+    let mut solutions = Vec::new();
+    for native in [true, false] {
+        let asserter = Asserter::new();
+        let provider: Arc<DynProvider> =
+            Arc::new(ProviderBuilder::new().connect_mocked_client(asserter.clone()));
+        asserter.push_success(&U64::from(1));
+        let engine = ArbitrageEngine::quote_only(provider.clone(), pools(&provider, native))
+            .await
+            .unwrap();
+        // The snapshots, which the overrides stand in for, the gas price and the flashloan
+        // liquidity.
+        for _ in 0..16 {
+            asserter.push_failure_msg("not mocked");
+        }
+        let mut found = engine
+            .find_opportunities_with_overrides(Some(1), overrides())
+            .await;
+        assert_eq!(found.len(), 1);
+        solutions.push(found.remove(0));
+    }
+    let (wrapped, plain) = (&solutions[0], &solutions[1]);
+
+    let kinds: Vec<SwapKind> = wrapped.swap_actions.iter().map(|swap| swap.kind).collect();
+    assert_eq!(
+        kinds,
+        vec![SwapKind::Unwrap, SwapKind::Swap, SwapKind::Swap]
+    );
+    assert_eq!(wrapped.swap_actions[1].token_in.address, NATIVE_ETH_ADDRESS);
+    assert!(
+        plain
+            .swap_actions
+            .iter()
+            .all(|swap| swap.kind == SwapKind::Swap)
+    );
+
+    assert_eq!(wrapped.optimal_input, plain.optimal_input);
+    assert_eq!(wrapped.gross_profit, plain.gross_profit);
+    // Only the gas of the unwrap sets them apart.
+    assert!(wrapped.gas_cost > plain.gas_cost);
+}
+
+#[test]
+fn test_weth_swaps_as_the_native_coin_the_pool_lists() {
+    let provider: Arc<DynProvider> =
+        Arc::new(ProviderBuilder::new().connect_mocked_client(Asserter::new()));
+    let pool = pool_with_coins(&provider, &[STETH, NATIVE_ETH_ADDRESS]);
+    let weth = named_token(WETH_ADDRESS, "WETH", 18, provider.clone());
+    let ether_token = Token::Native(Arc::new(NativeTokenData::new(
+        1,
+        NATIVE_ETH_ADDRESS,
+        provider.clone(),
+    )));
+
+    assert_eq!(pool.swap_index(&weth).unwrap(), Some(1));
+    assert_eq!(pool.swap_index(&ether_token).unwrap(), Some(1));
+    assert!(pool.is_native(WETH_ADDRESS));
+}
+
+#[test]
+fn test_pool_listing_ether_and_weth_needs_a_pick() {
+    let provider: Arc<DynProvider> =
+        Arc::new(ProviderBuilder::new().connect_mocked_client(Asserter::new()));
+    let coins = [NATIVE_ETH_ADDRESS, WETH_ADDRESS, STETH];
+    let (weth, steth) = (
+        named_token(WETH_ADDRESS, "WETH", 18, provider.clone()),
+        named_token(STETH, "stETH", 18, provider.clone()),
+    );
+    let snapshot = PoolSnapshot::Curve(CurvePoolSnapshot {
+        balances: vec![ether(10_000); 3],
+        a: U256::from(100),
+        fee: U256::from(4_000_000),
+        rates: vec![ether(1); 3],
+        ..Default::default()
+    });
+
+    let pool = pool_with_coins(&provider, &coins);
+    assert!(matches!(
+        pool.swap_index(&weth),
+        Err(ArbRsError::InvalidPool(CURVE_POOL, _))
+    ));
+    assert!(matches!(
+        pool.calculate_tokens_out(&weth, &steth, ether(1), &snapshot),
+        Err(ArbRsError::InvalidPool(..))
+    ));
+    assert_eq!(pool.coin_index(&weth), None);
+    assert!(!pool.is_native(WETH_ADDRESS));
+
+    let as_weth = pool_with_coins(&provider, &coins).with_weth_coin(1);
+    assert_eq!(as_weth.swap_index(&weth).unwrap(), Some(1));
+    assert!(!as_weth.is_native(WETH_ADDRESS));
+    assert!(
+        as_weth
+            .calculate_tokens_out(&weth, &steth, ether(1), &snapshot)
+            .is_ok()
+    );
+
+    let as_ether = pool_with_coins(&provider, &coins).with_weth_coin(0);
+    assert_eq!(as_ether.swap_index(&weth).unwrap(), Some(0));
+    assert!(as_ether.is_native(WETH_ADDRESS));
+}